@@ -88,7 +88,8 @@ fn bench_vectored_write_single_flush(c: &mut Criterion) {
                     IoSlice::new(&ip),
                     IoSlice::new(&port),
                 ];
-                writer.write_vectored(&slices).unwrap();
+                let written = writer.write_vectored(&slices).unwrap();
+                black_box(written);
                 writer.flush().unwrap();
             }
 
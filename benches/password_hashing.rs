@@ -0,0 +1,40 @@
+/// Benchmark: Userpass Password Verification
+///
+/// Measures the cost of one argon2id verification, which every userpass login pays,
+/// at the default `[auth.password_hashing]` parameters and at the cheapest allowed ones.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rustsocks::auth::{hash_params, hash_password, PasswordHash};
+use rustsocks::config::PasswordHashSettings;
+
+fn bench_verify(c: &mut Criterion, name: &str, settings: &PasswordHashSettings) {
+    let params = hash_params(settings).unwrap();
+    let hash = PasswordHash::parse(&hash_password("secret123", &params)).unwrap();
+
+    c.bench_function(name, |b| {
+        b.iter(|| black_box(hash.verify(black_box(b"secret123"))));
+    });
+}
+
+fn bench_verify_default(c: &mut Criterion) {
+    bench_verify(
+        c,
+        "argon2id_verify_default",
+        &PasswordHashSettings::default(),
+    );
+}
+
+fn bench_verify_minimal(c: &mut Criterion) {
+    let settings = PasswordHashSettings {
+        memory_kib: 8,
+        iterations: 1,
+        parallelism: 1,
+    };
+    bench_verify(c, "argon2id_verify_minimal", &settings);
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = bench_verify_default, bench_verify_minimal
+}
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rustsocks::server::{RelayBufferPool, DEFAULT_RELAY_BUFFER_BYTES};
+
+/// The two buffers a tunnel relays through, as allocated per session before pooling
+fn bench_fresh_buffers(c: &mut Criterion) {
+    c.bench_function("relay_buffers_fresh", |b| {
+        b.iter(|| {
+            let upload = vec![0u8; DEFAULT_RELAY_BUFFER_BYTES].into_boxed_slice();
+            let download = vec![0u8; DEFAULT_RELAY_BUFFER_BYTES].into_boxed_slice();
+            black_box((upload, download));
+        });
+    });
+}
+
+fn bench_pooled_buffers(c: &mut Criterion) {
+    let pool = RelayBufferPool::default();
+
+    c.bench_function("relay_buffers_pooled", |b| {
+        b.iter(|| {
+            let upload = pool.checkout();
+            let download = pool.checkout();
+            black_box((&upload[..], &download[..]));
+        });
+    });
+}
+
+criterion_group!(benches, bench_fresh_buffers, bench_pooled_buffers);
+criterion_main!(benches);
@@ -6,9 +6,14 @@ fn build_session() -> Session {
     let conn = ConnectionInfo {
         source_ip: IpAddr::from([127, 0, 0, 1]),
         source_port: 50000,
-        dest_ip: "example.com".to_string(),
+        dest_ip: "example.com".into(),
         dest_port: 443,
         protocol: Protocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
     };
 
     Session::new("bench-user", conn, "allow", Some("bench-rule".to_string()))
@@ -0,0 +1,99 @@
+/// Benchmark: Session Store Write Amplification
+///
+/// Flushes batches the way the batch writer does (`SessionStore::save_batch`) into a
+/// SQLite file with the current schema, and into one with the indexes of migration 015
+/// reverted. The difference is the write cost of the history filter indexes.
+///
+/// Run with: cargo bench --bench session_store_writes --features database
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rustsocks::session::types::{ConnectionInfo, Protocol, Session, SessionStatus};
+use rustsocks::session::SessionStore;
+use std::net::IpAddr;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+/// Rows written before measuring, so every index has some depth
+const PREFILL_ROWS: usize = 20_000;
+/// Default `sessions.batch_size`
+const BATCH_SIZE: usize = 100;
+
+/// Index set before migration 015 (whose end_time index 030 turned into
+/// idx_sessions_end_start)
+const REVERT_015: &[&str] = &[
+    "DROP INDEX IF EXISTS idx_sessions_dest_start",
+    "DROP INDEX IF EXISTS idx_sessions_end_start",
+    "CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user)",
+    "CREATE INDEX IF NOT EXISTS idx_sessions_status ON sessions(status)",
+    "CREATE INDEX IF NOT EXISTS idx_sessions_dest_ip ON sessions(dest_ip)",
+    "CREATE INDEX IF NOT EXISTS idx_sessions_start_time_asc ON sessions(start_time ASC)",
+];
+
+fn closed_session(n: usize) -> Session {
+    let conn = ConnectionInfo {
+        source_ip: IpAddr::from([10, 0, (n / 256 % 256) as u8, (n % 256) as u8]),
+        source_port: 40000 + (n % 20000) as u16,
+        dest_ip: format!("10.1.{}.{}", n / 7 % 256, n % 13).into(),
+        dest_port: 443,
+        protocol: Protocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+
+    let mut session = Session::new(
+        format!("user{}", n % 500),
+        conn,
+        "allow",
+        Some("bench-rule".to_string()),
+    );
+    session.bytes_sent = 4096;
+    session.bytes_received = 65536;
+    session.close(Some("finished".to_string()), SessionStatus::Closed);
+    session
+}
+
+async fn prepared_store(dir: &TempDir, revert_015: bool) -> SessionStore {
+    let url = format!("sqlite://{}", dir.path().join("sessions.db").display());
+    let store = SessionStore::connect(&url).await.expect("session store");
+
+    if revert_015 {
+        for statement in REVERT_015 {
+            sqlx::query(statement)
+                .execute(store.pool())
+                .await
+                .expect("revert migration 015");
+        }
+    }
+
+    for chunk in (0..PREFILL_ROWS).collect::<Vec<_>>().chunks(1000) {
+        let batch = chunk.iter().map(|n| closed_session(*n)).collect();
+        store.save_batch(batch).await.expect("prefill");
+    }
+    store
+}
+
+fn bench_batch_flush(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("tokio runtime");
+    let mut group = c.benchmark_group("session_store_batch_flush");
+
+    for (name, revert_015) in [("before_015", true), ("with_015", false)] {
+        let dir = TempDir::new().expect("temp dir");
+        let store = runtime.block_on(prepared_store(&dir, revert_015));
+
+        group.bench_with_input(BenchmarkId::new(name, BATCH_SIZE), &store, |b, store| {
+            let mut next = PREFILL_ROWS;
+            b.to_async(&runtime).iter(|| {
+                let batch = (next..next + BATCH_SIZE).map(closed_session).collect();
+                next += BATCH_SIZE;
+                async move { store.save_batch(batch).await.expect("batch flush") }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_batch_flush);
+criterion_main!(benches);
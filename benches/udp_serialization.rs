@@ -2,7 +2,16 @@
 ///
 /// Compares Vec::new() vs Vec::with_capacity() for UDP packet serialization.
 /// Pre-allocating capacity avoids multiple reallocations during packet construction.
+/// The relay itself wraps replies in place and strips client headers by slicing; the
+/// `udp_encapsulation` and `udp_decapsulation` groups compare that against the owned
+/// serialize/parse functions.
+use bytes::Bytes;
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rustsocks::protocol::{
+    encapsulate_udp_in_place, parse_udp_packet, serialize_udp_packet, udp_header_len, Address,
+    UdpHeader, UdpPacket, UDP_IP_HEADER_MAX,
+};
+use std::net::SocketAddr;
 
 fn bench_udp_serialize_no_capacity(c: &mut Criterion) {
     c.bench_function("udp_serialize_no_capacity", |b| {
@@ -149,12 +158,71 @@ fn bench_various_packet_sizes(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_udp_encapsulation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("udp_encapsulation");
+    let source: SocketAddr = "192.0.2.1:53".parse().unwrap();
+
+    for size in [64, 512, 1400].iter() {
+        let payload = Bytes::from(vec![0xAB; *size]);
+        group.bench_with_input(BenchmarkId::new("serialize", size), size, |b, _| {
+            b.iter(|| {
+                let packet = UdpPacket {
+                    header: UdpHeader {
+                        frag: 0,
+                        address: Address::IPv4([192, 0, 2, 1]),
+                        port: 53,
+                    },
+                    data: payload.clone(),
+                };
+                black_box(serialize_udp_packet(&packet));
+            });
+        });
+
+        let mut buf = vec![0xAB; UDP_IP_HEADER_MAX + 65_535];
+        group.bench_with_input(BenchmarkId::new("in_place", size), size, |b, &size| {
+            b.iter(|| {
+                black_box(encapsulate_udp_in_place(&mut buf, size, black_box(source)).len());
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_udp_decapsulation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("udp_decapsulation");
+
+    for size in [64, 512, 1400].iter() {
+        let datagram = Bytes::from(serialize_udp_packet(&UdpPacket {
+            header: UdpHeader {
+                frag: 0,
+                address: Address::IPv4([192, 0, 2, 1]),
+                port: 53,
+            },
+            data: Bytes::from(vec![0xAB; *size]),
+        }));
+        group.bench_with_input(BenchmarkId::new("parse", size), size, |b, _| {
+            b.iter(|| black_box(parse_udp_packet(datagram.clone()).unwrap().data.len()));
+        });
+        group.bench_with_input(BenchmarkId::new("header_len", size), size, |b, _| {
+            b.iter(|| {
+                let header_len = udp_header_len(black_box(&datagram)).unwrap();
+                black_box(datagram[header_len..].len())
+            });
+        });
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_udp_serialize_no_capacity,
     bench_udp_serialize_with_capacity,
     bench_udp_serialize_domain_no_capacity,
     bench_udp_serialize_domain_with_capacity,
-    bench_various_packet_sizes
+    bench_various_packet_sizes,
+    bench_udp_encapsulation,
+    bench_udp_decapsulation
 );
 criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Embeds what `GET /api/version` and the startup log report about the build: the git
+//! commit, the build time and the compiler version.
+//!
+//! Builds outside a git checkout (the Docker image copies `src/` only) take the commit
+//! from `RUSTSOCKS_GIT_HASH`, or report "unknown". `SOURCE_DATE_EPOCH` fixes the build
+//! time for reproducible builds.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=RUSTSOCKS_GIT_HASH");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // A new commit or checkout moves HEAD or the branch it points to
+    if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            for path in [
+                format!(".git/{}", reference),
+                ".git/packed-refs".to_string(),
+            ] {
+                if Path::new(&path).exists() {
+                    println!("cargo:rerun-if-changed={}", path);
+                }
+            }
+        }
+    }
+
+    let git_hash = std::env::var("RUSTSOCKS_GIT_HASH")
+        .ok()
+        .filter(|hash| !hash.trim().is_empty())
+        .or_else(|| command_output("git", &["rev-parse", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=RUSTSOCKS_GIT_HASH={}", git_hash.trim());
+
+    let build_timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0)
+        });
+    println!(
+        "cargo:rustc-env=RUSTSOCKS_BUILD_TIMESTAMP={}",
+        build_timestamp
+    );
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version =
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=RUSTSOCKS_RUSTC_VERSION={}", rustc_version);
+}
+
+/// Trimmed stdout of a command that succeeded
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|stdout| stdout.trim().to_string())
+}
@@ -25,6 +25,13 @@ const resolveAclBadge = (decision) => {
   return decision.toLowerCase() === 'allow' ? 'badge badge-success' : 'badge badge-danger'
 }
 
+const describeUpstream = (session) => {
+  if (!session.chained) return 'Direct'
+  const parent = session.upstream ? `Chained via ${session.upstream}` : 'Chained via parent proxy'
+  if (session.upstream_healthy !== false) return parent
+  return `${parent} (unhealthy${session.draining ? ', draining' : ''})`
+}
+
 function SessionDetailDrawer({ open, session, loading, error, onClose }) {
   useEffect(() => {
     if (!open) return
@@ -94,6 +101,12 @@ function SessionDetailDrawer({ open, session, loading, error, onClose }) {
                 value={<code>{session.dest_ip}:{session.dest_port}</code>}
               />
               <DetailRow label="Protocol" value={session.protocol?.toUpperCase()} />
+              <DetailRow
+                label="Command"
+                value={session.command?.replace('_', ' ').toUpperCase()}
+              />
+              <DetailRow label="SOCKS Version" value={session.socks_version} />
+              <DetailRow label="Upstream" value={describeUpstream(session)} />
               <DetailRow label="Start" value={formatDateTime(session.start_time)} />
               <DetailRow label="End" value={formatDateTime(session.end_time)} />
               <DetailRow label="Duration" value={formatDuration(session.duration_seconds)} />
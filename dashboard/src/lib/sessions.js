@@ -21,7 +21,8 @@ export const buildHistoryQuery = ({
 
   const filters = [
     ['user', cleanString(user)],
-    ['dest_ip', cleanString(destination)],
+    // Host or IP as requested; "*.example.com" matches every name below example.com
+    ['dest_host', cleanString(destination)],
     ['status', cleanString(status)]
   ]
 
@@ -10,14 +10,14 @@ describe('session helpers', () => {
   it('builds query string with filters and defaults', () => {
     const query = buildHistoryQuery({
       user: ' alice ',
-      destination: '10.0.0.1',
+      destination: '*.example.com',
       status: 'closed',
       hours: '24',
       page: 2,
       pageSize: 100
     })
 
-    expect(query).toBe('?page=2&page_size=100&user=alice&dest_ip=10.0.0.1&status=closed&hours=24')
+    expect(query).toBe('?page=2&page_size=100&user=alice&dest_host=*.example.com&status=closed&hours=24')
   })
 
   it('clamps page and page size to accepted ranges', () => {
@@ -417,12 +417,17 @@ function Configuration() {
                     onChange={(e) => updateField('sessions', 'storage', e.target.value)}
                   >
                     <option value="memory">memory</option>
-                    <option value="sqlite">sqlite</option>
+                    <option value="database">database</option>
+                    {['sqlite', 'mariadb', 'mysql'].includes(runtimeConfig.sessions.storage) && (
+                      <option value={runtimeConfig.sessions.storage}>
+                        {runtimeConfig.sessions.storage} (alias of database)
+                      </option>
+                    )}
                   </select>
                 </div>
-                {runtimeConfig.sessions.storage === 'sqlite' && (
+                {runtimeConfig.sessions.storage !== 'memory' && (
                   <div className="form-group">
-                    <label>SQLite URL</label>
+                    <label>Database URL</label>
                     <input
                       type="text"
                       value={runtimeConfig.sessions.database_url || ''}
@@ -565,7 +570,10 @@ function Configuration() {
                     onChange={(e) => updateField('metrics', 'storage', e.target.value)}
                   >
                     <option value="memory">memory</option>
-                    <option value="sqlite">sqlite</option>
+                    <option value="database">database</option>
+                    {runtimeConfig.metrics.storage === 'sqlite' && (
+                      <option value="sqlite">sqlite (alias of database)</option>
+                    )}
                   </select>
                 </div>
                 <div className="form-group">
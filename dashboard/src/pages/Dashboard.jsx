@@ -142,7 +142,8 @@ function Dashboard() {
 
   const fetchMetricsHistory = useCallback(async () => {
     try {
-      const response = await fetch(getApiUrl('/api/metrics/history'))
+      const params = new URLSearchParams({ minutes: String(timeRange), max_points: '720' })
+      const response = await fetch(getApiUrl(`/api/metrics/history?${params}`))
       if (!response.ok) throw new Error('Failed to fetch metrics history')
       const data = await response.json()
 
@@ -158,7 +159,7 @@ function Dashboard() {
     } catch (err) {
       console.warn('Failed to fetch metrics history:', err)
     }
-  }, [])
+  }, [timeRange])
 
   const fetchHealth = useCallback(async () => {
     try {
@@ -390,9 +391,9 @@ function Dashboard() {
               </div>
             </div>
             <div>
-              <div className="detail-label">Drops / Evicted</div>
+              <div className="detail-label">Drops / Evicted / Dead</div>
               <div className="detail-value">
-                {poolStats.dropped_full} drops / {poolStats.evicted} evicted
+                {poolStats.dropped_full} drops / {poolStats.evicted} evicted / {poolStats.validation_failures ?? 0} dead
               </div>
             </div>
             <div>
@@ -428,6 +429,9 @@ function Dashboard() {
                     <th style={{ cursor: 'pointer' }} onClick={() => handleSortClick('evicted', sortPoolDest, setSortPoolDest)}>
                       Evicted{getSortIndicator('evicted', sortPoolDest)}
                     </th>
+                    <th style={{ cursor: 'pointer' }} onClick={() => handleSortClick('validation_failures', sortPoolDest, setSortPoolDest)}>
+                      Dead{getSortIndicator('validation_failures', sortPoolDest)}
+                    </th>
                     <th style={{ cursor: 'pointer' }} onClick={() => handleSortClick('last_activity', sortPoolDest, setSortPoolDest)}>
                       Last Activity{getSortIndicator('last_activity', sortPoolDest)}
                     </th>
@@ -445,6 +449,7 @@ function Dashboard() {
                         <td>{dest.pool_misses}</td>
                         <td>{dest.drops}</td>
                         <td>{dest.evicted}</td>
+                        <td>{dest.validation_failures ?? 0}</td>
                         <td>{formatPoolTimestamp(lastEvent)}</td>
                       </tr>
                     )
@@ -29,7 +29,7 @@ const statusOptions = [
 
 const getInitialFilters = (params) => ({
   user: params.get('user') || '',
-  destination: params.get('dest_ip') || params.get('destination') || '',
+  destination: params.get('dest_host') || params.get('dest_ip') || params.get('destination') || '',
   status: params.get('status') || '',
   hours: params.get('hours') || '',
   page: Number(params.get('page')) || 1,
@@ -60,7 +60,7 @@ function Sessions() {
       if (!nextShowActive) {
         params.set('view', 'history')
         if (nextFilters.user) params.set('user', nextFilters.user)
-        if (nextFilters.destination) params.set('dest_ip', nextFilters.destination)
+        if (nextFilters.destination) params.set('dest_host', nextFilters.destination)
         if (nextFilters.status) params.set('status', nextFilters.status)
         if (nextFilters.hours) params.set('hours', nextFilters.hours)
         params.set('page', String(nextFilters.page))
@@ -252,7 +252,8 @@ function Sessions() {
         method: 'POST'
       })
 
-      if (!response.ok) {
+      // 409: the session ended on its own meanwhile; the refresh shows it as closed
+      if (!response.ok && response.status !== 409) {
         const errorData = await response.json().catch(() => ({}))
         throw new Error(errorData.error || 'Failed to terminate session')
       }
@@ -367,12 +368,12 @@ function Sessions() {
                 />
               </div>
               <div className="form-group">
-                <label>Destination address</label>
+                <label>Destination host</label>
                 <input
                   type="text"
                   value={filterDraft.destination}
                   onChange={(e) => handleDraftChange('destination', e.target.value)}
-                  placeholder="e.g. 192.168.0.10"
+                  placeholder="e.g. *.example.com or 192.168.0.10"
                 />
               </div>
               <div className="form-group">
@@ -0,0 +1,8 @@
+-- Record the TLS SNI observed on CONNECT-by-IP sessions
+-- Migration: 008_add_sni_host
+-- Created: 2026-10-16
+-- Purpose: keep the raw IP in dest_ip while exposing the logical (SNI) destination.
+
+ALTER TABLE sessions ADD COLUMN sni_host TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_sni_host ON sessions(sni_host);
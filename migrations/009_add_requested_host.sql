@@ -0,0 +1,10 @@
+-- Record the hostname a client asked for, with the source that supplied it
+-- Migration: 009_add_requested_host
+-- Created: 2026-10-16
+-- Purpose: report by domain even when the client dialled an IP
+--          (requested_host_source: socks_request, sni, resolve_extension, reverse_map).
+
+ALTER TABLE sessions ADD COLUMN requested_host TEXT;
+ALTER TABLE sessions ADD COLUMN requested_host_source TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_requested_host ON sessions(requested_host);
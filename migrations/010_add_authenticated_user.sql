@@ -0,0 +1,9 @@
+-- Record the principal that authenticated, separately from the accounting user
+-- Migration: 010_add_authenticated_user
+-- Created: 2026-10-16
+-- Purpose: keep the service account on sessions it opens on behalf of another user
+--          (auth.impersonation); `user` stays the effective identity.
+
+ALTER TABLE sessions ADD COLUMN authenticated_user TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_authenticated_user ON sessions(authenticated_user);
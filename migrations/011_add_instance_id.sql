@@ -0,0 +1,18 @@
+-- Record which server boot created each session and make session IDs explicitly unique
+-- Migration: 011_add_instance_id
+-- Created: 2026-10-16
+-- Purpose: session IDs are UUIDv7 from now on; instance_id (random per boot) lets
+--          multi-instance deployments tell sessions apart and lets the store refuse
+--          to overwrite a row that belongs to another session.
+
+ALTER TABLE sessions ADD COLUMN instance_id TEXT;
+
+-- Backfill: the boot that wrote older rows is unknown, so they get the nil UUID.
+-- Upserts only touch rows of the same instance, so new sessions never overwrite them.
+UPDATE sessions SET instance_id = '00000000-0000-0000-0000-000000000000'
+WHERE instance_id IS NULL;
+
+-- session_id is already the primary key in tables created by 001; the explicit unique
+-- index keeps the guarantee for tables that were created by hand without one.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_sessions_session_id_unique ON sessions(session_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_instance_id ON sessions(instance_id);
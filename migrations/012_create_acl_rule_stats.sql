@@ -0,0 +1,17 @@
+-- Persist ACL rule hit counters across restarts
+-- Migration: 012_create_acl_rule_stats
+-- Created: 2026-10-16
+-- Purpose: per-rule counters are flushed here periodically and on shutdown and
+--          seeded back at startup, so unused rules can be found over long horizons.
+--          rule_id is derived from the rule's scope and matching fields, so it
+--          survives reordering of the ACL file and changes when the rule is edited.
+
+CREATE TABLE IF NOT EXISTS acl_rule_stats (
+    rule_id TEXT PRIMARY KEY,
+    scope TEXT NOT NULL,
+    action TEXT NOT NULL,
+    hits INTEGER NOT NULL DEFAULT 0,
+    last_matched_at TEXT,
+    tracked_since TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Record keepalive activity on tunnels covered by [[server.tunnel_keepalive]]
+-- Migration: 013_add_keepalive_probes
+-- Created: 2026-10-16
+-- Purpose: show which long-idle sessions were kept open by keepalive probing.
+
+ALTER TABLE sessions ADD COLUMN keepalive_probes INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+-- Record how UDP associations matched their client's datagrams
+-- Migration: 014_add_udp_association
+-- Created: 2026-10-16
+-- Purpose: show the association mode, the client endpoint in use and how many
+-- datagrams from other sources were dropped (server.udp_association_mode).
+
+ALTER TABLE sessions ADD COLUMN udp_association_mode TEXT;
+ALTER TABLE sessions ADD COLUMN udp_client_endpoint TEXT;
+ALTER TABLE sessions ADD COLUMN udp_rejected_datagrams INTEGER NOT NULL DEFAULT 0;
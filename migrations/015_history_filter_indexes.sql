@@ -0,0 +1,28 @@
+-- Composite indexes for the session history filters
+-- Migration: 015_history_filter_indexes
+-- Created: 2026-10-16
+-- Purpose: every history filter is an equality on one column plus an optional
+--          start_time range, ordered by start_time. Give dest_ip the same
+--          (column, start_time) index that user and status already have (001/002),
+--          and index end_time for the retention delete.
+--
+-- Duration: building an index reads and sorts the whole table once, so on tables
+-- with tens of millions of rows expect minutes per index rather than seconds. The
+-- migration runs at startup before the proxy accepts connections; schedule the
+-- upgrade in a maintenance window on large deployments.
+
+CREATE INDEX IF NOT EXISTS idx_sessions_dest_start
+ON sessions(dest_ip, start_time DESC);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_end_time
+ON sessions(end_time);
+
+-- Single-column indexes that are now the leading prefix of a composite one, and the
+-- ascending duplicate of idx_sessions_start_time (SQLite walks an index both ways).
+-- Dropping them offsets the write cost of the two indexes above.
+DROP INDEX IF EXISTS idx_sessions_user;
+DROP INDEX IF EXISTS idx_sessions_status;
+DROP INDEX IF EXISTS idx_sessions_dest_ip;
+DROP INDEX IF EXISTS idx_sessions_start_time_asc;
+
+ANALYZE sessions;
@@ -0,0 +1,11 @@
+-- Record the client-supplied correlation ID of a session
+-- Migration: 016_add_correlation_id
+-- Created: 2026-10-16
+-- Purpose: attach the caller's workflow ID (auth.allow_correlation_suffix) to its
+--          sessions for cross-system tracing, and serve the history filter
+--          correlation_id= with the same (column, start_time) index as the others (015).
+
+ALTER TABLE sessions ADD COLUMN correlation_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_correlation_start
+ON sessions(correlation_id, start_time DESC);
@@ -0,0 +1,17 @@
+-- Lock table for server instances sharing one session database
+-- Migration: 017_create_store_instances
+-- Created: 2026-10-16
+-- Purpose: every instance registers its boot instance_id (see 011) here at startup
+--          and refreshes heartbeat_at while it runs. A second instance on the same
+--          SQLite file refuses to start while a live row exists, unless both run with
+--          sessions.allow_shared_database; rows whose heartbeat is older than
+--          sessions.instance_lock_stale_secs belong to dead instances and are taken over.
+
+CREATE TABLE IF NOT EXISTS store_instances (
+    instance_id TEXT PRIMARY KEY,
+    hostname TEXT NOT NULL,
+    pid INTEGER NOT NULL,
+    shared INTEGER NOT NULL DEFAULT 0,
+    started_at TEXT NOT NULL,
+    heartbeat_at TEXT NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Record the SOCKS command that opened a session
+-- Migration: 018_add_session_command
+-- Created: 2026-10-16
+-- Purpose: tell BIND sessions apart from CONNECT ones, which share the tcp protocol.
+--          NULL rows predate the column and read back as connect/udp_associate by protocol.
+
+ALTER TABLE sessions ADD COLUMN command TEXT;
@@ -0,0 +1,7 @@
+-- Record the SOCKS version each session was opened with
+-- Migration: 019_add_socks_version
+-- Created: 2026-10-16
+-- Purpose: count the SOCKS4/SOCKS4a clients that remain (server.enable_socks4).
+--          The version was not recorded before, so existing rows read as 5.
+
+ALTER TABLE sessions ADD COLUMN socks_version INTEGER NOT NULL DEFAULT 5;
@@ -0,0 +1,7 @@
+-- Record whether a session was chained through the parent proxy
+-- Migration: 020_add_chained
+-- Created: 2026-10-16
+-- Purpose: tell CONNECTs relayed via [server.upstream] apart from direct ones.
+--          Sessions from before chaining existed were all direct, so rows read as 0.
+
+ALTER TABLE sessions ADD COLUMN chained INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- Count UDP datagrams dropped by QoS
+-- Migration: 021_add_udp_throttled_datagrams
+-- Created: 2026-10-16
+-- Purpose: UDP relays drop datagrams the bandwidth limits have no tokens for instead
+--          of queueing them; record how many per session.
+
+ALTER TABLE sessions ADD COLUMN udp_throttled_datagrams INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,27 @@
+-- Keep downsampled metrics history
+-- Migration: 022_create_metrics_rollups
+-- Created: 2026-10-16
+-- Purpose: raw metrics_snapshots are kept for metrics.retention_hours; before they are
+--          deleted the cleanup task folds them into 1-minute (resolution_secs = 60) and
+--          15-minute (900) buckets kept for metrics.rollup_retention_days. Averages are
+--          sum / samples.
+
+CREATE TABLE IF NOT EXISTS metrics_rollups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    resolution_secs INTEGER NOT NULL,
+    bucket_start TEXT NOT NULL,
+    samples INTEGER NOT NULL,
+    active_sessions_min INTEGER NOT NULL,
+    active_sessions_max INTEGER NOT NULL,
+    active_sessions_sum INTEGER NOT NULL,
+    total_sessions_min INTEGER NOT NULL,
+    total_sessions_max INTEGER NOT NULL,
+    total_sessions_sum INTEGER NOT NULL,
+    bandwidth_min INTEGER NOT NULL,
+    bandwidth_max INTEGER NOT NULL,
+    bandwidth_sum INTEGER NOT NULL,
+    UNIQUE (resolution_secs, bucket_start)
+);
+
+-- Retention deletes span both resolutions
+CREATE INDEX IF NOT EXISTS idx_metrics_rollups_bucket_start ON metrics_rollups(bucket_start);
@@ -0,0 +1,13 @@
+-- Record the destination the client requested, as a searchable host
+-- Migration: 023_add_dest_host
+-- Created: 2026-10-16
+-- Purpose: serve the history filter dest_host= (exact or "*.example.com" suffix)
+--          on the lowercased domain or IP literal from the SOCKS request. Older
+--          rows take dest_ip, which held the same requested address.
+
+ALTER TABLE sessions ADD COLUMN dest_host TEXT;
+
+UPDATE sessions SET dest_host = LOWER(dest_ip) WHERE dest_host IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_dest_host_start
+ON sessions(dest_host, start_time DESC);
@@ -0,0 +1,7 @@
+-- Record the local address upstream traffic left from
+-- Migration: 024_add_egress_ip
+-- Created: 2026-10-16
+-- Purpose: audit which source address ([server.egress]) a session used. NULL for
+--          older rows and for sessions that never opened an upstream socket.
+
+ALTER TABLE sessions ADD COLUMN egress_ip TEXT;
@@ -0,0 +1,15 @@
+-- Groups of the user behind each session
+-- Migration: 025_create_session_groups
+-- Created: 2026-10-16
+-- Purpose: one row per (session, group) for the LDAP or system groups the ACL saw,
+--          so traffic can be broken down by department. Sessions without groups have
+--          no rows. Rows of sessions removed by the retention cleanup are removed with
+--          them.
+
+CREATE TABLE IF NOT EXISTS session_groups (
+    session_id TEXT NOT NULL,
+    group_name TEXT NOT NULL,
+    PRIMARY KEY (session_id, group_name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_groups_group ON session_groups(group_name);
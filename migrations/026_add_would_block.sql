@@ -0,0 +1,8 @@
+-- Record sessions the ACL let through only because it runs in monitor mode
+-- Migration: 026_add_would_block
+-- Created: 2026-10-16
+-- Purpose: acl.mode = "monitor" relays connections the rules would block and marks
+--          them, so the traffic a policy would affect can be counted before it is
+--          enforced. Sessions from before monitor mode existed read as 0.
+
+ALTER TABLE sessions ADD COLUMN would_block INTEGER NOT NULL DEFAULT 0;
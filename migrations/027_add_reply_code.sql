@@ -0,0 +1,9 @@
+-- Record the SOCKS reply and the failure category of each session
+-- Migration: 027_add_reply_code
+-- Created: 2026-10-16
+-- Purpose: failed requests (DNS errors, connect timeouts and refusals, ACL blocks)
+--          keep the reply the client got and why, for the failure statistics.
+--          Sessions from before this migration have neither and read as NULL.
+
+ALTER TABLE sessions ADD COLUMN reply_code INTEGER;
+ALTER TABLE sessions ADD COLUMN failure TEXT;
@@ -0,0 +1,11 @@
+-- Record how each session's destination was resolved and what the ACL matched on
+-- Migration: 028_add_destination_resolution
+-- Created: 2026-10-16
+-- Purpose: a name that resolves to a different address between the ACL check and
+--          the connect (DNS rebinding) shows up in the session record. resolved_ips
+--          is a comma-separated list; sessions from before this migration read as
+--          NULL.
+
+ALTER TABLE sessions ADD COLUMN resolved_ips TEXT;
+ALTER TABLE sessions ADD COLUMN connected_ip TEXT;
+ALTER TABLE sessions ADD COLUMN acl_matched_on TEXT;
@@ -0,0 +1,8 @@
+-- Record sessions whose upstream connection the proxy wrapped in TLS
+-- Migration: 029_add_upstream_tls
+-- Created: 2026-10-16
+-- Purpose: [server.upstream_tls] originates TLS to matching destinations on behalf of
+--          plain-TCP clients, so the session record shows which tunnels were
+--          encrypted past the proxy. Sessions from before this migration read as 0.
+
+ALTER TABLE sessions ADD COLUMN upstream_tls INTEGER NOT NULL DEFAULT 0;
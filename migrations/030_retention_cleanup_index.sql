@@ -0,0 +1,18 @@
+-- Index for the batched retention cleanup
+-- Migration: 030_retention_cleanup_index
+-- Created: 2026-10-16
+-- Purpose: the retention cleanup deletes expired sessions in bounded batches, oldest
+--          first: closed sessions ordered by end_time, sessions never closed
+--          (end_time IS NULL) ordered by start_time. One (end_time, start_time)
+--          index serves both selects in order, without sorting the expired rows.
+--          It replaces idx_sessions_end_time (015), its leading prefix.
+--
+-- Duration: building the index reads and sorts the whole table once; on tables with
+-- millions of rows expect the first startup after the upgrade to take minutes.
+
+CREATE INDEX IF NOT EXISTS idx_sessions_end_start
+ON sessions(end_time, start_time);
+
+DROP INDEX IF EXISTS idx_sessions_end_time;
+
+ANALYZE sessions;
@@ -0,0 +1,111 @@
+-- Session store schema for PostgreSQL
+-- Migration: postgres/001_create_schema
+-- Created: 2026-10-16
+-- Purpose: the schema the SQLite migrations in the parent directory arrive at after
+--          021, created in one step. Counters are BIGINT so they decode as the same
+--          64-bit integers as on SQLite; timestamps stay RFC3339 TEXT, which sorts
+--          chronologically and keeps the queries identical across databases.
+--          `user` is a reserved word here and is always quoted.
+--
+-- Later schema changes need a matching migration in this directory.
+
+CREATE TABLE IF NOT EXISTS sessions (
+    session_id TEXT PRIMARY KEY,
+    "user" TEXT NOT NULL,
+    start_time TEXT NOT NULL,
+    end_time TEXT,
+    duration_secs BIGINT,
+
+    source_ip TEXT NOT NULL,
+    source_port BIGINT NOT NULL,
+    dest_ip TEXT NOT NULL,
+    dest_port BIGINT NOT NULL,
+    protocol TEXT NOT NULL,
+
+    bytes_sent BIGINT DEFAULT 0,
+    bytes_received BIGINT DEFAULT 0,
+    packets_sent BIGINT DEFAULT 0,
+    packets_received BIGINT DEFAULT 0,
+
+    status TEXT NOT NULL,
+    close_reason TEXT,
+
+    acl_rule_matched TEXT,
+    acl_decision TEXT NOT NULL,
+
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+
+    sni_host TEXT,
+    requested_host TEXT,
+    requested_host_source TEXT,
+    authenticated_user TEXT,
+    instance_id TEXT,
+    keepalive_probes BIGINT NOT NULL DEFAULT 0,
+    udp_association_mode TEXT,
+    udp_client_endpoint TEXT,
+    udp_rejected_datagrams BIGINT NOT NULL DEFAULT 0,
+    correlation_id TEXT,
+    command TEXT,
+    socks_version BIGINT NOT NULL DEFAULT 5,
+    chained BIGINT NOT NULL DEFAULT 0,
+    udp_throttled_datagrams BIGINT NOT NULL DEFAULT 0
+);
+
+-- History filters: an equality on one column plus a start_time range (see 015)
+CREATE INDEX IF NOT EXISTS idx_sessions_start_time ON sessions(start_time DESC);
+CREATE INDEX IF NOT EXISTS idx_sessions_user_start ON sessions("user", start_time DESC);
+CREATE INDEX IF NOT EXISTS idx_sessions_status_start ON sessions(status, start_time DESC);
+CREATE INDEX IF NOT EXISTS idx_sessions_dest_start ON sessions(dest_ip, start_time DESC);
+CREATE INDEX IF NOT EXISTS idx_sessions_acl_decision_start
+ON sessions(acl_decision, start_time DESC);
+CREATE INDEX IF NOT EXISTS idx_sessions_user_status_start
+ON sessions("user", status, start_time DESC);
+CREATE INDEX IF NOT EXISTS idx_sessions_correlation_start
+ON sessions(correlation_id, start_time DESC);
+CREATE INDEX IF NOT EXISTS idx_sessions_dest_user ON sessions(dest_ip, "user");
+
+-- Sorting
+CREATE INDEX IF NOT EXISTS idx_sessions_bytes_sent ON sessions(bytes_sent);
+CREATE INDEX IF NOT EXISTS idx_sessions_bytes_received ON sessions(bytes_received);
+CREATE INDEX IF NOT EXISTS idx_sessions_protocol ON sessions(protocol);
+CREATE INDEX IF NOT EXISTS idx_sessions_acl_decision ON sessions(acl_decision);
+CREATE INDEX IF NOT EXISTS idx_sessions_duration_order ON sessions(duration_secs, session_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_source_ip ON sessions(source_ip, session_id);
+
+-- Lookups and retention
+CREATE INDEX IF NOT EXISTS idx_sessions_sni_host ON sessions(sni_host);
+CREATE INDEX IF NOT EXISTS idx_sessions_requested_host ON sessions(requested_host);
+CREATE INDEX IF NOT EXISTS idx_sessions_authenticated_user ON sessions(authenticated_user);
+CREATE INDEX IF NOT EXISTS idx_sessions_instance_id ON sessions(instance_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_end_time ON sessions(end_time);
+
+CREATE TABLE IF NOT EXISTS metrics_snapshots (
+    id BIGSERIAL PRIMARY KEY,
+    timestamp TEXT NOT NULL,
+    active_sessions BIGINT NOT NULL,
+    total_sessions BIGINT NOT NULL,
+    bandwidth BIGINT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_metrics_timestamp ON metrics_snapshots(timestamp);
+CREATE INDEX IF NOT EXISTS idx_metrics_created_at ON metrics_snapshots(created_at);
+
+CREATE TABLE IF NOT EXISTS acl_rule_stats (
+    rule_id TEXT PRIMARY KEY,
+    scope TEXT NOT NULL,
+    action TEXT NOT NULL,
+    hits BIGINT NOT NULL DEFAULT 0,
+    last_matched_at TEXT,
+    tracked_since TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS store_instances (
+    instance_id TEXT PRIMARY KEY,
+    hostname TEXT NOT NULL,
+    pid BIGINT NOT NULL,
+    shared BIGINT NOT NULL DEFAULT 0,
+    started_at TEXT NOT NULL,
+    heartbeat_at TEXT NOT NULL
+);
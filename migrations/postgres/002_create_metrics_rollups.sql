@@ -0,0 +1,24 @@
+-- Keep downsampled metrics history
+-- Migration: postgres/002_create_metrics_rollups
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 022: 1-minute and 15-minute buckets of
+--          metrics_snapshots, kept for metrics.rollup_retention_days.
+
+CREATE TABLE IF NOT EXISTS metrics_rollups (
+    id BIGSERIAL PRIMARY KEY,
+    resolution_secs BIGINT NOT NULL,
+    bucket_start TEXT NOT NULL,
+    samples BIGINT NOT NULL,
+    active_sessions_min BIGINT NOT NULL,
+    active_sessions_max BIGINT NOT NULL,
+    active_sessions_sum BIGINT NOT NULL,
+    total_sessions_min BIGINT NOT NULL,
+    total_sessions_max BIGINT NOT NULL,
+    total_sessions_sum BIGINT NOT NULL,
+    bandwidth_min BIGINT NOT NULL,
+    bandwidth_max BIGINT NOT NULL,
+    bandwidth_sum BIGINT NOT NULL,
+    UNIQUE (resolution_secs, bucket_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_metrics_rollups_bucket_start ON metrics_rollups(bucket_start);
@@ -0,0 +1,12 @@
+-- Record the destination the client requested, as a searchable host
+-- Migration: postgres/003_add_dest_host
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 023: the lowercased requested domain or IP
+--          literal behind the history filter dest_host=, backfilled from dest_ip.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS dest_host TEXT;
+
+UPDATE sessions SET dest_host = LOWER(dest_ip) WHERE dest_host IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_dest_host_start
+ON sessions(dest_host, start_time DESC);
@@ -0,0 +1,7 @@
+-- Record the local address upstream traffic left from
+-- Migration: postgres/004_add_egress_ip
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 024: the source address ([server.egress])
+--          a session's upstream socket was bound to.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS egress_ip TEXT;
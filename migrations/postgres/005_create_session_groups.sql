@@ -0,0 +1,13 @@
+-- Groups of the user behind each session
+-- Migration: postgres/005_create_session_groups
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 025: one row per (session, group) for the
+--          groups the ACL saw.
+
+CREATE TABLE IF NOT EXISTS session_groups (
+    session_id TEXT NOT NULL,
+    group_name TEXT NOT NULL,
+    PRIMARY KEY (session_id, group_name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_groups_group ON session_groups(group_name);
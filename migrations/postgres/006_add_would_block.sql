@@ -0,0 +1,7 @@
+-- Record sessions the ACL let through only because it runs in monitor mode
+-- Migration: postgres/006_add_would_block
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 026: sessions acl.mode = "monitor" relayed
+--          although the rules would have blocked them.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS would_block BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+-- Record the SOCKS reply and the failure category of each session
+-- Migration: postgres/007_add_reply_code
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 027: the reply a request was answered with and,
+--          for failed requests, why it failed.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS reply_code BIGINT;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS failure TEXT;
@@ -0,0 +1,9 @@
+-- Record how each session's destination was resolved and what the ACL matched on
+-- Migration: postgres/008_add_destination_resolution
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 028: the addresses a destination name resolved
+--          to, the one connected to and what the ACL decision was made on.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS resolved_ips TEXT;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS connected_ip TEXT;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS acl_matched_on TEXT;
@@ -0,0 +1,7 @@
+-- Record sessions whose upstream connection the proxy wrapped in TLS
+-- Migration: postgres/009_add_upstream_tls
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 029: sessions the proxy originated TLS for
+--          (server.upstream_tls).
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS upstream_tls BIGINT NOT NULL DEFAULT 0;
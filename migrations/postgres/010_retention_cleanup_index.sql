@@ -0,0 +1,12 @@
+-- Index for the batched retention cleanup
+-- Migration: postgres/010_retention_cleanup_index
+-- Created: 2026-10-16
+-- Purpose: matches SQLite migration 030: one (end_time, start_time) index serves the
+--          oldest-first retention batches, replacing idx_sessions_end_time.
+
+CREATE INDEX IF NOT EXISTS idx_sessions_end_start
+ON sessions(end_time, start_time);
+
+DROP INDEX IF EXISTS idx_sessions_end_time;
+
+ANALYZE sessions;
@@ -0,0 +1,244 @@
+//! Cache of ACL decisions (`[acl.cache]`).
+//!
+//! Decisions are keyed on the user, the ACL groups they belong to, the destination as
+//! requested (a domain stays a domain, it is never replaced by the address it resolves
+//! to), the port and the protocol. The client address is part of the key only when
+//! some rule restricts `sources`, the minute of the evaluation only when some rule has
+//! a `schedule`.
+//!
+//! A cache belongs to one compiled configuration: every reload starts from an empty
+//! one, so a cached decision never outlives the rules that produced it.
+
+use super::rule_stats::RuleCounter;
+use super::stats::AclStats;
+use super::types::{AclVerdict, Protocol};
+use crate::config::AclCacheSettings;
+use crate::protocol::Address;
+use dashmap::DashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What a cached decision was reached for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub user: String,
+    /// ACL groups of the user, lowercased and sorted
+    pub groups: Vec<String>,
+    pub destination: Address,
+    pub port: u16,
+    pub protocol: Protocol,
+    /// `None` unless the configuration has source-restricted rules
+    pub source: Option<IpAddr>,
+    /// Minutes since the epoch; `None` unless the configuration has scheduled rules
+    pub minute: Option<i64>,
+}
+
+#[derive(Debug)]
+struct CachedDecision {
+    verdict: AclVerdict,
+    /// Counter of the matched rule, so cached hits still show up in rule usage
+    counter: Option<Arc<RuleCounter>>,
+    expires_at: Instant,
+    /// Tick of the last lookup, for least-recently-used eviction
+    last_used: AtomicU64,
+}
+
+/// Bounded map of ACL decisions with a fixed time to live
+#[derive(Debug)]
+pub struct DecisionCache {
+    entries: DashMap<CacheKey, CachedDecision>,
+    ttl: Duration,
+    max_entries: usize,
+    tick: AtomicU64,
+    /// Held while evicting so concurrent inserts don't all scan the map
+    eviction: Mutex<()>,
+    stats: Arc<AclStats>,
+}
+
+impl DecisionCache {
+    pub fn new(ttl: Duration, max_entries: usize, stats: Arc<AclStats>) -> Self {
+        Self {
+            entries: DashMap::new(),
+            ttl,
+            max_entries,
+            tick: AtomicU64::new(0),
+            eviction: Mutex::new(()),
+            stats,
+        }
+    }
+
+    /// `None` when `settings.enabled` is off
+    pub fn from_settings(settings: &AclCacheSettings, stats: Arc<AclStats>) -> Option<Self> {
+        settings.enabled.then(|| {
+            Self::new(
+                Duration::from_secs(settings.ttl_secs),
+                settings.max_entries,
+                stats,
+            )
+        })
+    }
+
+    /// Cached verdict for `key`, counting the hit or miss and the matched rule's hit
+    pub fn get(&self, key: &CacheKey) -> Option<AclVerdict> {
+        let now = Instant::now();
+        let verdict = self.entries.get(key).and_then(|entry| {
+            if entry.expires_at <= now {
+                return None;
+            }
+            entry.last_used.store(self.next_tick(), Ordering::Relaxed);
+            if let Some(counter) = &entry.counter {
+                counter.record(&key.user);
+            }
+            Some(entry.verdict.clone())
+        });
+
+        match verdict {
+            Some(verdict) => {
+                self.stats.record_cache_hit();
+                Some(verdict)
+            }
+            None => {
+                self.stats.record_cache_miss();
+                None
+            }
+        }
+    }
+
+    /// Remember `verdict` for `key`, evicting the least recently used entries when full
+    pub fn insert(&self, key: CacheKey, verdict: AclVerdict, counter: Option<Arc<RuleCounter>>) {
+        if self.entries.len() >= self.max_entries {
+            self.evict();
+        }
+
+        self.entries.insert(
+            key,
+            CachedDecision {
+                verdict,
+                counter,
+                expires_at: Instant::now() + self.ttl,
+                last_used: AtomicU64::new(self.next_tick()),
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn next_tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Drop expired entries, then the least recently used tenth of the cache
+    fn evict(&self) {
+        // Another insert is already making room
+        let Ok(_evicting) = self.eviction.try_lock() else {
+            return;
+        };
+
+        let now = Instant::now();
+        self.entries.retain(|_, entry| entry.expires_at > now);
+        if self.entries.len() < self.max_entries {
+            return;
+        }
+
+        let mut by_age: Vec<(u64, CacheKey)> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.last_used.load(Ordering::Relaxed), entry.key().clone()))
+            .collect();
+        by_age.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+        let excess = self.entries.len() + 1 - self.max_entries;
+        let target = excess.max(self.max_entries / 10);
+        for (_, key) in by_age.into_iter().take(target) {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::types::{AclDecision, AclMatchedOn, RuleLogLevel};
+
+    fn key(destination: &str) -> CacheKey {
+        CacheKey {
+            user: "alice".to_string(),
+            groups: vec!["developers".to_string()],
+            destination: Address::Domain(destination.into()),
+            port: 443,
+            protocol: Protocol::Tcp,
+            source: None,
+            minute: None,
+        }
+    }
+
+    fn allow() -> AclVerdict {
+        AclVerdict {
+            decision: AclDecision::Allow,
+            matched_rule: Some("Allow web".to_string()),
+            log: RuleLogLevel::default(),
+            reply_code: None,
+            resolve: Default::default(),
+            matched_on: AclMatchedOn::Domain,
+        }
+    }
+
+    #[test]
+    fn hits_and_misses_are_counted() {
+        let stats = Arc::new(AclStats::new());
+        let cache = DecisionCache::new(Duration::from_secs(30), 10, stats.clone());
+
+        assert!(cache.get(&key("example.com")).is_none());
+        cache.insert(key("example.com"), allow(), None);
+        assert_eq!(cache.get(&key("example.com")), Some(allow()));
+        assert!(cache.get(&key("other.example.com")).is_none());
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.cache_hits, 1);
+        assert_eq!(snapshot.cache_misses, 2);
+    }
+
+    #[test]
+    fn expired_entries_are_not_returned() {
+        let cache = DecisionCache::new(Duration::from_millis(20), 10, Arc::new(AclStats::new()));
+        cache.insert(key("example.com"), allow(), None);
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(cache.get(&key("example.com")).is_none());
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted_first() {
+        let cache = DecisionCache::new(Duration::from_secs(30), 3, Arc::new(AclStats::new()));
+        cache.insert(key("a.example.com"), allow(), None);
+        cache.insert(key("b.example.com"), allow(), None);
+        cache.insert(key("c.example.com"), allow(), None);
+        assert!(cache.get(&key("a.example.com")).is_some());
+
+        cache.insert(key("d.example.com"), allow(), None);
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get(&key("b.example.com")).is_none());
+        assert!(cache.get(&key("a.example.com")).is_some());
+        assert!(cache.get(&key("d.example.com")).is_some());
+    }
+
+    #[test]
+    fn disabled_settings_build_no_cache() {
+        let stats = Arc::new(AclStats::new());
+        assert!(
+            DecisionCache::from_settings(&AclCacheSettings::default(), stats.clone()).is_none()
+        );
+        let enabled = AclCacheSettings {
+            enabled: true,
+            ..AclCacheSettings::default()
+        };
+        assert!(DecisionCache::from_settings(&enabled, stats).is_some());
+    }
+}
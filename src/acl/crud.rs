@@ -106,6 +106,7 @@ pub fn add_group_rule(
         // Create new group
         config.groups.push(GroupAcl {
             name: group_name.to_string(),
+            parents: vec![],
             rules: vec![rule.clone()],
         });
         info!(group = group_name, "Created new group and added rule");
@@ -192,15 +193,46 @@ pub fn delete_group_rule(
     Ok(deleted_rule)
 }
 
+/// Groups listing `group_name` among their parents
+pub fn groups_inheriting_from(config: &AclConfig, group_name: &str) -> Vec<String> {
+    config
+        .groups
+        .iter()
+        .filter(|g| g.parents.iter().any(|p| p.eq_ignore_ascii_case(group_name)))
+        .map(|g| g.name.clone())
+        .collect()
+}
+
 /// Delete an entire group
-pub fn delete_group(config: &mut AclConfig, group_name: &str) -> Result<GroupAcl, String> {
+///
+/// A group other groups inherit from is only deleted with `cascade`, which also removes
+/// it from their parents.
+pub fn delete_group(
+    config: &mut AclConfig,
+    group_name: &str,
+    cascade: bool,
+) -> Result<GroupAcl, String> {
     let index = config
         .groups
         .iter()
         .position(|g| g.name == group_name)
         .ok_or_else(|| format!("Group '{}' not found", group_name))?;
 
+    let children = groups_inheriting_from(config, group_name);
+    if !children.is_empty() && !cascade {
+        return Err(format!(
+            "Group '{}' is inherited by {}",
+            group_name,
+            children.join(", ")
+        ));
+    }
+
     let deleted_group = config.groups.remove(index);
+    for group in &mut config.groups {
+        group
+            .parents
+            .retain(|parent| !parent.eq_ignore_ascii_case(group_name));
+    }
 
     info!(
         group = group_name,
@@ -211,6 +243,36 @@ pub fn delete_group(config: &mut AclConfig, group_name: &str) -> Result<GroupAcl
     Ok(deleted_group)
 }
 
+/// Replace the groups `group_name` inherits from, returning the previous ones
+///
+/// Fails, leaving the group as it was, when a parent is not defined or the change
+/// would create an inheritance cycle.
+pub fn set_group_parents(
+    config: &mut AclConfig,
+    group_name: &str,
+    parents: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let index = config
+        .groups
+        .iter()
+        .position(|g| g.name == group_name)
+        .ok_or_else(|| format!("Group '{}' not found", group_name))?;
+
+    let previous = std::mem::replace(&mut config.groups[index].parents, parents);
+    if let Err(e) = config.group_ancestors() {
+        config.groups[index].parents = previous;
+        return Err(e);
+    }
+
+    info!(
+        group = group_name,
+        parents = ?config.groups[index].parents,
+        "Updated group parents"
+    );
+
+    Ok(previous)
+}
+
 /// Add a new rule to a user
 ///
 /// Creates the user entry if it doesn't exist.
@@ -328,6 +390,9 @@ pub struct RuleSearchCriteria {
     pub port: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub action: Option<String>,
+    /// Access-log verbosity (`default`, `silent`, `minimal`, `verbose`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -398,6 +463,13 @@ fn matches_criteria(rule: &AclRule, criteria: &RuleSearchCriteria) -> bool {
         }
     }
 
+    // Check log level if specified
+    if let Some(ref log) = criteria.log {
+        if !log.eq_ignore_ascii_case(rule.log.as_str()) {
+            return false;
+        }
+    }
+
     true
 }
 
@@ -513,7 +585,7 @@ pub fn remove_user_from_group(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::acl::types::{Action, Protocol};
+    use crate::acl::types::{Action, Protocol, RuleLogLevel};
 
     fn create_test_rule(dest: &str, port: &str) -> AclRule {
         AclRule {
@@ -521,8 +593,13 @@ mod tests {
             description: format!("Test rule for {}", dest),
             destinations: vec![dest.to_string()],
             ports: vec![port.to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Tcp],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         }
     }
 
@@ -600,6 +677,34 @@ mod tests {
         assert_eq!(config.groups[0].rules.len(), 0);
     }
 
+    #[test]
+    fn test_group_parents_and_delete() {
+        let mut config = AclConfig::default();
+        add_group_rule(
+            &mut config,
+            "engineering",
+            create_test_rule("git.corp", "22"),
+        )
+        .unwrap();
+        add_group_rule(&mut config, "backend", create_test_rule("db.corp", "5432")).unwrap();
+
+        set_group_parents(&mut config, "backend", vec!["engineering".to_string()]).unwrap();
+        let err =
+            set_group_parents(&mut config, "engineering", vec!["backend".to_string()]).unwrap_err();
+        assert!(
+            err.contains("engineering -> backend -> engineering"),
+            "{err}"
+        );
+        assert!(config.groups[0].parents.is_empty());
+        assert!(set_group_parents(&mut config, "backend", vec!["qa".to_string()]).is_err());
+
+        // Referenced by backend: only deleted with cascade, which drops the reference
+        assert!(delete_group(&mut config, "engineering", false).is_err());
+        delete_group(&mut config, "engineering", true).unwrap();
+        assert_eq!(config.groups.len(), 1);
+        assert!(config.groups[0].parents.is_empty());
+    }
+
     #[test]
     fn test_search_rules() {
         let mut config = AclConfig::default();
@@ -616,6 +721,7 @@ mod tests {
             destination: Some("prod.com".to_string()),
             port: None,
             action: None,
+            log: None,
         };
         let results = search_rules(&config, &criteria);
         assert_eq!(results.len(), 2);
@@ -625,6 +731,7 @@ mod tests {
             destination: None,
             port: None,
             action: Some("block".to_string()),
+            log: None,
         };
         let results = search_rules(&config, &criteria);
         assert_eq!(results.len(), 1);
@@ -0,0 +1,306 @@
+/// What replacing one ACL configuration with another changes
+///
+/// Used by the import dry-run (`POST /api/acl/import?dry_run=true`). Rules are
+/// matched within their user or group by [`rule_id`], the same identity the hit
+/// counters follow: a rule whose matching fields stay the same but whose description
+/// or log level differs is changed, while editing what a rule matches shows up as
+/// one rule removed and one added. Reordering rules changes nothing.
+use super::rule_stats::rule_id;
+use super::types::{AclConfig, AclRule, Action};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Summary of an import against the live configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AclConfigDiff {
+    /// New default policy; absent when it stays the same
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_policy: Option<Action>,
+    pub rules_added: usize,
+    pub rules_removed: usize,
+    pub rules_changed: usize,
+    /// Users and groups that change, ordered by scope; unchanged ones are left out
+    pub scopes: Vec<ScopeDiff>,
+}
+
+impl AclConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.default_policy.is_none() && self.scopes.is_empty()
+    }
+}
+
+/// Changes to one user or group
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScopeDiff {
+    /// `user:<name>` or `group:<name>`
+    pub scope: String,
+    pub change: ScopeChange,
+    pub rules_added: usize,
+    pub rules_removed: usize,
+    /// Same matching fields, different description or log level
+    pub rules_changed: usize,
+    /// Groups a user joins, or a group starts inheriting from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups_added: Vec<String>,
+    /// Groups a user leaves, or a group stops inheriting from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups_removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScopeChange {
+    /// The user or group is new
+    Added,
+    /// The user or group is dropped with all its rules
+    Removed,
+    /// Present in both with different rules or group memberships
+    Modified,
+}
+
+/// Rules and group memberships (a group's parents) of one user or group
+struct Scope<'a> {
+    rules: &'a [AclRule],
+    groups: &'a [String],
+}
+
+/// Changes `imported` makes to `current`
+pub fn diff(current: &AclConfig, imported: &AclConfig) -> AclConfigDiff {
+    let before = scopes(current);
+    let after = scopes(imported);
+
+    let mut names: Vec<&String> = before.keys().chain(after.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let mut result = AclConfigDiff {
+        default_policy: (current.global.default_policy != imported.global.default_policy)
+            .then(|| imported.global.default_policy.clone()),
+        ..AclConfigDiff::default()
+    };
+
+    for name in names {
+        let change = match (before.get(name), after.get(name)) {
+            (Some(_), Some(_)) => ScopeChange::Modified,
+            (None, Some(_)) => ScopeChange::Added,
+            (Some(_), None) => ScopeChange::Removed,
+            (None, None) => continue,
+        };
+        let old = before.get(name).map(|s| (s.rules, s.groups));
+        let new = after.get(name).map(|s| (s.rules, s.groups));
+        let (old_rules, old_groups) = old.unwrap_or_default();
+        let (new_rules, new_groups) = new.unwrap_or_default();
+
+        let (rules_added, rules_removed, rules_changed) = rule_changes(name, old_rules, new_rules);
+        let groups_added = missing_from(new_groups, old_groups);
+        let groups_removed = missing_from(old_groups, new_groups);
+
+        if change == ScopeChange::Modified
+            && rules_added + rules_removed + rules_changed == 0
+            && groups_added.is_empty()
+            && groups_removed.is_empty()
+        {
+            continue;
+        }
+
+        result.rules_added += rules_added;
+        result.rules_removed += rules_removed;
+        result.rules_changed += rules_changed;
+        result.scopes.push(ScopeDiff {
+            scope: name.clone(),
+            change,
+            rules_added,
+            rules_removed,
+            rules_changed,
+            groups_added,
+            groups_removed,
+        });
+    }
+
+    result
+}
+
+fn scopes(config: &AclConfig) -> BTreeMap<String, Scope<'_>> {
+    let users = config.users.iter().map(|user| {
+        (
+            format!("user:{}", user.username),
+            Scope {
+                rules: &user.rules,
+                groups: &user.groups,
+            },
+        )
+    });
+    let groups = config.groups.iter().map(|group| {
+        (
+            format!("group:{}", group.name),
+            Scope {
+                rules: &group.rules,
+                groups: &group.parents,
+            },
+        )
+    });
+    users.chain(groups).collect()
+}
+
+/// Rules added, removed and changed within `scope`
+fn rule_changes(scope: &str, old: &[AclRule], new: &[AclRule]) -> (usize, usize, usize) {
+    let mut unmatched: HashMap<String, Vec<&AclRule>> = HashMap::new();
+    for rule in old {
+        unmatched
+            .entry(rule_id(scope, rule))
+            .or_default()
+            .push(rule);
+    }
+
+    let (mut added, mut changed) = (0, 0);
+    for rule in new {
+        match unmatched
+            .get_mut(&rule_id(scope, rule))
+            .and_then(|rules| rules.pop())
+        {
+            Some(previous) => {
+                if previous.description != rule.description || previous.log != rule.log {
+                    changed += 1;
+                }
+            }
+            None => added += 1,
+        }
+    }
+    let removed = unmatched.values().map(Vec::len).sum();
+    (added, removed, changed)
+}
+
+/// Entries of `list` not in `other`
+fn missing_from(list: &[String], other: &[String]) -> Vec<String> {
+    list.iter()
+        .filter(|entry| !other.contains(entry))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::types::{GlobalAclConfig, GroupAcl, Protocol, RuleLogLevel, UserAcl};
+
+    fn rule(destination: &str, description: &str) -> AclRule {
+        AclRule {
+            action: Action::Allow,
+            description: description.to_string(),
+            destinations: vec![destination.to_string()],
+            ports: vec!["443".to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Tcp],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
+        }
+    }
+
+    fn config(users: Vec<UserAcl>, groups: Vec<GroupAcl>) -> AclConfig {
+        AclConfig {
+            global: GlobalAclConfig::default(),
+            users,
+            groups,
+        }
+    }
+
+    fn user(name: &str, groups: &[&str], rules: Vec<AclRule>) -> UserAcl {
+        UserAcl {
+            username: name.to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            rules,
+        }
+    }
+
+    fn group(name: &str, rules: Vec<AclRule>) -> GroupAcl {
+        GroupAcl {
+            name: name.to_string(),
+            parents: vec![],
+            rules,
+        }
+    }
+
+    #[test]
+    fn identical_configs_have_no_changes() {
+        let current = config(
+            vec![user("alice", &["dev"], vec![rule("a.example.com", "a")])],
+            vec![group("dev", vec![rule("b.example.com", "b")])],
+        );
+        assert!(diff(&current, &current.clone()).is_empty());
+    }
+
+    #[test]
+    fn rules_are_matched_by_their_matching_fields() {
+        let current = config(
+            vec![],
+            vec![group(
+                "dev",
+                vec![
+                    rule("keep.example.com", "keep"),
+                    rule("reword.example.com", "old wording"),
+                    rule("drop.example.com", "drop"),
+                ],
+            )],
+        );
+        let imported = config(
+            vec![],
+            vec![group(
+                "dev",
+                vec![
+                    rule("new.example.com", "new"),
+                    rule("reword.example.com", "new wording"),
+                    rule("keep.example.com", "keep"),
+                ],
+            )],
+        );
+
+        let diff = diff(&current, &imported);
+        assert_eq!(
+            (diff.rules_added, diff.rules_removed, diff.rules_changed),
+            (1, 1, 1)
+        );
+        assert_eq!(diff.scopes.len(), 1);
+        assert_eq!(diff.scopes[0].scope, "group:dev");
+        assert_eq!(diff.scopes[0].change, ScopeChange::Modified);
+    }
+
+    #[test]
+    fn scopes_and_memberships_are_reported() {
+        let current = config(
+            vec![user("alice", &["dev"], vec![])],
+            vec![group("dev", vec![rule("a.example.com", "a")])],
+        );
+        let mut imported = config(
+            vec![
+                user("alice", &["ops"], vec![]),
+                user("bob", &[], vec![rule("b.example.com", "b")]),
+            ],
+            vec![group("ops", vec![])],
+        );
+        imported.global.default_policy = Action::Allow;
+
+        let diff = diff(&current, &imported);
+        assert_eq!(diff.default_policy, Some(Action::Allow));
+        let changes: Vec<(&str, ScopeChange)> = diff
+            .scopes
+            .iter()
+            .map(|scope| (scope.scope.as_str(), scope.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("group:dev", ScopeChange::Removed),
+                ("group:ops", ScopeChange::Added),
+                ("user:alice", ScopeChange::Modified),
+                ("user:bob", ScopeChange::Added),
+            ]
+        );
+        let alice = &diff.scopes[2];
+        assert_eq!(alice.groups_added, vec!["ops"]);
+        assert_eq!(alice.groups_removed, vec!["dev"]);
+        assert_eq!((diff.rules_added, diff.rules_removed), (1, 1));
+    }
+}
@@ -0,0 +1,234 @@
+//! Rule destinations read from a file: `file:/etc/rustsocks/blocked-domains.txt`.
+//!
+//! The file holds one pattern per line in the syntax of inline destinations (domain,
+//! `*` wildcard, IP or CIDR); `#` starts a comment and blank lines are skipped. Lists
+//! are read when the rules are compiled, so every reload of the ACL reads them again,
+//! and with `acl.watch_domain_lists` a change to a list reloads the rules by itself.
+//!
+//! Exact domains go into a hash set, so a list of tens of thousands of names costs one
+//! lookup per evaluation; wildcards, IPs and CIDRs use the regular matchers.
+
+use super::matcher::CompiledDestinationMatcher;
+use super::types::AclConfig;
+use crate::protocol::Address;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Prefix of a destination that names a domain list file
+pub const DOMAIN_LIST_PREFIX: &str = "file:";
+
+/// The file a `file:` destination names; `None` for any other destination
+pub fn domain_list_path(destination: &str) -> Option<&Path> {
+    destination
+        .trim()
+        .strip_prefix(DOMAIN_LIST_PREFIX)
+        .map(|path| Path::new(path.trim()))
+}
+
+/// Every domain list file the rules of `config` reference
+pub fn referenced_domain_lists(config: &AclConfig) -> BTreeSet<PathBuf> {
+    config
+        .users
+        .iter()
+        .flat_map(|acl| acl.rules.iter())
+        .chain(config.groups.iter().flat_map(|acl| acl.rules.iter()))
+        .flat_map(|rule| rule.destinations.iter())
+        .filter_map(|destination| domain_list_path(destination))
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+/// The patterns of one domain list file
+#[derive(Debug)]
+pub struct DomainList {
+    path: PathBuf,
+    /// Exact domains, lowercase and without a trailing dot
+    exact: HashSet<String>,
+    /// Wildcards, IPs and CIDRs
+    patterns: Vec<CompiledDestinationMatcher>,
+}
+
+impl DomainList {
+    /// Read and parse the list at `path`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read domain list {}: {}", path.display(), e))?;
+        Self::parse(path, &content)
+    }
+
+    /// Parse the contents of the list at `path`; errors name the file and line
+    pub fn parse(path: &Path, content: &str) -> Result<Self, String> {
+        let mut exact = HashSet::new();
+        let mut patterns = Vec::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let entry = line.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let invalid = |reason: String| {
+                format!(
+                    "Domain list {} line {}: {}",
+                    path.display(),
+                    index + 1,
+                    reason
+                )
+            };
+
+            if entry.contains(char::is_whitespace) {
+                return Err(invalid(format!(
+                    "'{}' is not a single pattern (one pattern per line)",
+                    entry
+                )));
+            }
+            if entry == "*" {
+                return Err(invalid(
+                    "'*' would match every destination; use it in the rule instead".to_string(),
+                ));
+            }
+            if entry.starts_with(DOMAIN_LIST_PREFIX) {
+                return Err(invalid(
+                    "domain lists cannot include other lists".to_string(),
+                ));
+            }
+
+            if entry.contains('*')
+                || entry.parse::<std::net::IpAddr>().is_ok()
+                || entry.parse::<ipnet::IpNet>().is_ok()
+            {
+                patterns.push(CompiledDestinationMatcher::compile(entry).map_err(invalid)?);
+                continue;
+            }
+
+            let domain = entry.trim_end_matches('.');
+            if domain.is_empty()
+                || !domain
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+            {
+                return Err(invalid(format!("'{}' is not a valid domain", entry)));
+            }
+            exact.insert(domain.to_ascii_lowercase());
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            exact,
+            patterns,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of patterns in the list
+    pub fn len(&self) -> usize {
+        self.exact.len() + self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn matches(&self, addr: &Address) -> bool {
+        if let Address::Domain(domain) = addr {
+            let domain = domain.trim_end_matches('.');
+            let found = if domain.bytes().any(|b| b.is_ascii_uppercase()) {
+                self.exact.contains(&domain.to_ascii_lowercase())
+            } else {
+                self.exact.contains(domain)
+            };
+            if found {
+                return true;
+            }
+        }
+        self.patterns.iter().any(|pattern| pattern.matches(addr))
+    }
+}
+
+/// Lists loaded while compiling one configuration, so a file several rules reference
+/// is read once
+#[derive(Debug, Default)]
+pub struct DomainLists {
+    loaded: HashMap<PathBuf, Arc<DomainList>>,
+}
+
+impl DomainLists {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The list at `path`, reading it on first use
+    pub fn get(&mut self, path: &Path) -> Result<Arc<DomainList>, String> {
+        if let Some(list) = self.loaded.get(path) {
+            return Ok(list.clone());
+        }
+        let list = Arc::new(DomainList::load(path)?);
+        self.loaded.insert(path.to_path_buf(), list.clone());
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain(name: &str) -> Address {
+        Address::Domain(name.into())
+    }
+
+    #[test]
+    fn list_matches_exact_domains_wildcards_and_networks() {
+        let list = DomainList::parse(
+            Path::new("feed.txt"),
+            "# threat feed\n\
+             malware.example.com\n\
+             \n\
+             Phishing.Example.NET.   # trailing dot and case are ignored\n\
+             *.tracker.example.org\n\
+             203.0.113.0/24\n",
+        )
+        .unwrap();
+        assert_eq!(list.len(), 4);
+
+        assert!(list.matches(&domain("malware.example.com")));
+        assert!(list.matches(&domain("MALWARE.example.com")));
+        assert!(list.matches(&domain("phishing.example.net")));
+        assert!(list.matches(&domain("ads.tracker.example.org")));
+        assert!(list.matches(&Address::IPv4([203, 0, 113, 9])));
+        assert!(!list.matches(&domain("example.com")));
+        assert!(!list.matches(&domain("sub.malware.example.com")));
+        assert!(!list.matches(&Address::IPv4([198, 51, 100, 1])));
+    }
+
+    #[test]
+    fn errors_name_the_file_and_line() {
+        for (content, line) in [
+            ("good.example.com\nbad domain.com\n", 2),
+            ("# header\n\n*\n", 3),
+            ("a.example.com\nb.example.com\nexa$mple.com\n", 3),
+            ("file:/etc/other.txt\n", 1),
+        ] {
+            let err = DomainList::parse(Path::new("/etc/rustsocks/feed.txt"), content).unwrap_err();
+            assert!(
+                err.contains(&format!("/etc/rustsocks/feed.txt line {}", line)),
+                "{err}"
+            );
+        }
+
+        let err = DomainList::load(Path::new("/nonexistent/feed.txt")).unwrap_err();
+        assert!(err.contains("/nonexistent/feed.txt"), "{err}");
+    }
+
+    #[test]
+    fn file_destinations_name_their_path() {
+        assert_eq!(
+            domain_list_path("file:/etc/rustsocks/blocked.txt"),
+            Some(Path::new("/etc/rustsocks/blocked.txt"))
+        );
+        assert_eq!(domain_list_path("*.example.com"), None);
+    }
+}
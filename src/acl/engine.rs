@@ -1,13 +1,78 @@
-use super::matcher::CompiledAclRule;
-use super::types::{AclConfig, AclDecision, Action, GlobalAclConfig, Protocol};
+use super::cache::{CacheKey, DecisionCache};
+use super::domain_list::DomainLists;
+use super::lint::{self, LintFinding, LintSettings};
+use super::matcher::{CompiledAclRule, RuleMatch};
+use super::rule_stats::AclRuleStats;
+use super::schedule::{AclClock, CompiledSchedule, SystemClock};
+use super::stats::AclStats;
+use super::types::{
+    AclConfig, AclDecision, AclMatchedOn, AclMode, AclRule, AclVerdict, Action, BlockReplyCode,
+    GlobalAclConfig, Protocol, ResolveMode, RuleLogLevel,
+};
+use crate::config::AclCacheSettings;
 use crate::protocol::Address;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::sync::Mutex;
 use tracing::{info, warn};
 
 /// ACL Engine - evaluates ACL rules for connections
+///
+/// Evaluations work on a snapshot of the compiled configuration. A reload builds the
+/// replacement on a blocking thread and only takes the write lock to swap the pointer,
+/// so in-flight evaluations finish on the old snapshot and are never stalled by a build.
 pub struct AclEngine {
-    config: Arc<RwLock<CompiledAclConfig>>,
+    config: RwLock<Arc<CompiledAclConfig>>,
+    rule_stats: Arc<AclRuleStats>,
+    /// Serializes reloads; never taken by evaluation
+    reload_lock: Mutex<()>,
+    /// Checks every loaded configuration goes through, see [`super::lint`]
+    lint_settings: LintSettings,
+    /// Decision cache settings and where its hits and misses are counted; `None` when
+    /// decisions are not cached
+    cache_settings: Option<(AclCacheSettings, Arc<AclStats>)>,
+    /// Outcome of the latest reload from the ACL file
+    last_reload: RwLock<Option<AclReloadStatus>>,
+    /// `acl.mode = "monitor"`: decisions are reported but blocks are not carried out.
+    /// Kept across reloads; the connection handlers consult it, evaluation does not.
+    monitor: AtomicBool,
+    /// Time rule schedules are checked against
+    clock: Arc<dyn AclClock>,
+}
+
+/// What started a reload from the ACL file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclReloadTrigger {
+    /// The file changed while `acl.watch` was on
+    Watcher,
+    /// `POST /api/admin/reload-acl`
+    Api,
+    /// A `file:` domain list changed while `acl.watch_domain_lists` was on
+    DomainList,
+}
+
+impl AclReloadTrigger {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AclReloadTrigger::Watcher => "watcher",
+            AclReloadTrigger::Api => "api",
+            AclReloadTrigger::DomainList => "domain_list",
+        }
+    }
+}
+
+/// Outcome of one reload from the ACL file, see [`AclEngine::record_reload`]
+#[derive(Debug, Clone)]
+pub struct AclReloadStatus {
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub trigger: AclReloadTrigger,
+    pub success: bool,
+    /// Why the file was rejected; the previous rules stayed in effect
+    pub error: Option<String>,
 }
 
 /// Compiled ACL configuration for efficient evaluation
@@ -18,6 +83,76 @@ struct CompiledAclConfig {
     groups: std::collections::HashMap<String, CompiledGroupAcl>,
     // Lowercase index for O(1) case-insensitive group lookup (critical optimization for LDAP)
     groups_by_lowercase: std::collections::HashMap<String, CompiledGroupAcl>,
+    /// Whether any rule restricts `sources`, making the client address part of a decision
+    has_source_rules: bool,
+    /// Whether any rule has a `schedule`, making the time part of a decision
+    has_scheduled_rules: bool,
+    /// Decisions reached with this configuration; replaced empty on every reload
+    cache: Option<Arc<DecisionCache>>,
+    /// The configuration as loaded, for export
+    source: Arc<AclConfig>,
+}
+
+impl CompiledAclConfig {
+    fn rule_ids(&self) -> HashSet<String> {
+        self.users
+            .values()
+            .flat_map(|acl| acl.rules.iter())
+            .chain(self.groups.values().flat_map(|acl| acl.rules.iter()))
+            .map(|rule| rule.id.clone())
+            .collect()
+    }
+}
+
+/// Hit statistics of one configured rule
+#[derive(Debug, Clone)]
+pub struct RuleUsage {
+    pub rule_id: String,
+    /// `user:<name>` or `group:<name>`
+    pub scope: String,
+    pub description: String,
+    pub action: Action,
+    pub hits: u64,
+    pub last_matched_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub tracked_since: chrono::DateTime<chrono::Utc>,
+    /// Hits per user since startup or the last reset, most hits first
+    pub users: Vec<(String, u64)>,
+}
+
+/// Longest rule trace [`AclEngine::explain`] returns; the matching rule is always kept
+pub const MAX_TRACE_RULES: usize = 500;
+
+/// One rule evaluated by [`AclEngine::explain`]
+#[derive(Debug, Clone)]
+pub struct RuleTrace {
+    /// 1-based position in evaluation order
+    pub position: usize,
+    pub rule_id: String,
+    /// `user:<name>` or `group:<name>`
+    pub scope: String,
+    pub description: String,
+    pub action: Action,
+    pub priority: u32,
+    pub outcome: RuleMatch,
+}
+
+/// A decision together with the rules evaluated to reach it
+#[derive(Debug, Clone)]
+pub struct AclExplanation {
+    pub decision: AclDecision,
+    pub matched_rule: Option<String>,
+    /// Reply sent for a block decision; `None` for allow decisions
+    pub reply_code: Option<BlockReplyCode>,
+    /// Where the destination is resolved, see [`AclVerdict::resolve`]
+    pub resolve: ResolveMode,
+    /// Rules that apply to the user, whether evaluated or not
+    pub rules_total: usize,
+    /// Position of the rule evaluation stopped at; `None` when the default policy applied
+    pub stopped_at: Option<usize>,
+    /// Evaluated rules in order, capped at [`MAX_TRACE_RULES`]
+    pub trace: Vec<RuleTrace>,
+    /// Evaluated rules left out of `trace` by the cap
+    pub omitted: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -35,39 +170,210 @@ struct CompiledGroupAcl {
     name: String,
     // Use Arc to make cloning cheap (just atomic counter increment)
     rules: Vec<Arc<CompiledAclRule>>,
+    /// Lowercase names of the groups this one inherits from, with their distance
+    ancestors: Vec<(String, usize)>,
 }
 
 impl AclEngine {
     /// Create a new ACL engine from configuration
     pub fn new(config: AclConfig) -> Result<Self, String> {
-        let compiled = Self::compile_config(&config)?;
+        Self::with_lint_settings(config, LintSettings::default())
+    }
+
+    /// [`Self::new`], linting this and every reloaded configuration with `lint_settings`
+    ///
+    /// Findings are logged; error findings (unknown groups under
+    /// `strict_references`) fail the build.
+    pub fn with_lint_settings(
+        config: AclConfig,
+        lint_settings: LintSettings,
+    ) -> Result<Self, String> {
+        Self::check(&config, &lint_settings)?;
+        let rule_stats = Arc::new(AclRuleStats::new());
+        let compiled = Self::compile_config(&config, &rule_stats)?;
 
         Ok(Self {
-            config: Arc::new(RwLock::new(compiled)),
+            config: RwLock::new(Arc::new(compiled)),
+            rule_stats,
+            reload_lock: Mutex::new(()),
+            lint_settings,
+            cache_settings: None,
+            last_reload: RwLock::new(None),
+            monitor: AtomicBool::new(false),
+            clock: Arc::new(SystemClock),
         })
     }
 
+    /// Check rule schedules against `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn AclClock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Current time of the clock rule schedules are checked against
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Start in `mode` instead of [`AclMode::Enforce`]
+    pub fn with_mode(self, mode: AclMode) -> Self {
+        self.set_mode(mode);
+        self
+    }
+
+    /// Whether block decisions are carried out
+    pub fn mode(&self) -> AclMode {
+        if self.monitor.load(Ordering::Relaxed) {
+            AclMode::Monitor
+        } else {
+            AclMode::Enforce
+        }
+    }
+
+    /// Switch the mode at runtime, returning the previous one
+    ///
+    /// Only new decisions are affected: sessions relayed in monitor mode stay open
+    /// until a change to the watched ACL file re-checks them.
+    pub fn set_mode(&self, mode: AclMode) -> AclMode {
+        if self
+            .monitor
+            .swap(mode == AclMode::Monitor, Ordering::Relaxed)
+        {
+            AclMode::Monitor
+        } else {
+            AclMode::Enforce
+        }
+    }
+
+    /// Cache the decisions of connections as `settings` describe, counting cache hits
+    /// and misses in `stats`
+    ///
+    /// Every reload starts over with an empty cache. A no-op when the cache is disabled.
+    pub fn with_decision_cache(
+        mut self,
+        settings: &AclCacheSettings,
+        stats: Arc<AclStats>,
+    ) -> Self {
+        if !settings.enabled {
+            return self;
+        }
+
+        self.cache_settings = Some((settings.clone(), stats));
+        let cache = self.new_cache();
+        let config = self.config.get_mut().unwrap_or_else(|e| e.into_inner());
+        Arc::make_mut(config).cache = cache;
+        self
+    }
+
+    /// An empty decision cache for a freshly compiled configuration
+    fn new_cache(&self) -> Option<Arc<DecisionCache>> {
+        let (settings, stats) = self.cache_settings.as_ref()?;
+        DecisionCache::from_settings(settings, stats.clone()).map(Arc::new)
+    }
+
+    /// Lint findings for `config` under this engine's settings
+    pub fn lint(&self, config: &AclConfig) -> Vec<LintFinding> {
+        lint::lint(config, &self.lint_settings)
+    }
+
+    pub fn lint_settings(&self) -> &LintSettings {
+        &self.lint_settings
+    }
+
+    /// Remember the outcome of a reload from the ACL file, for `GET /api/acl/reload-status`
+    pub fn record_reload(&self, trigger: AclReloadTrigger, result: &Result<(), String>) {
+        let status = AclReloadStatus {
+            at: chrono::Utc::now(),
+            trigger,
+            success: result.is_ok(),
+            error: result.as_ref().err().cloned(),
+        };
+        *self.last_reload.write().unwrap_or_else(|e| e.into_inner()) = Some(status);
+    }
+
+    /// Outcome of the latest reload from the ACL file; `None` before the first one
+    pub fn last_reload(&self) -> Option<AclReloadStatus> {
+        self.last_reload
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Run every check a reload runs on `config` without applying it
+    ///
+    /// Returns the lint findings (warnings only) of a configuration that would load.
+    /// Compiles against throwaway counters, so the live rule statistics are untouched.
+    pub fn check_config(&self, config: &AclConfig) -> Result<Vec<LintFinding>, String> {
+        config.validate()?;
+        let findings = self.lint(config);
+        lint::reject_errors(&findings)?;
+        Self::compile_config(config, &AclRuleStats::new())?;
+        Ok(findings)
+    }
+
+    fn check(config: &AclConfig, lint_settings: &LintSettings) -> Result<(), String> {
+        let findings = lint::lint(config, lint_settings);
+        lint::log_findings(&findings);
+        lint::reject_errors(&findings)
+    }
+
+    /// The compiled configuration evaluations currently use
+    fn snapshot(&self) -> Arc<CompiledAclConfig> {
+        // The lock only ever guards a pointer swap, so a poisoned lock still holds a valid Arc
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Per-rule hit counters of the loaded configuration
+    pub fn rule_stats(&self) -> Arc<AclRuleStats> {
+        self.rule_stats.clone()
+    }
+
+    /// Compile the rules of one user or group, sorted for evaluation
+    fn compile_rules(
+        scope: &str,
+        rules: &[AclRule],
+        stats: &AclRuleStats,
+        lists: &mut DomainLists,
+    ) -> Result<Vec<Arc<CompiledAclRule>>, String> {
+        let mut compiled_rules: Vec<_> = rules
+            .iter()
+            .map(|r| CompiledAclRule::compile_tracked(r, scope, stats, lists).map(Arc::new))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Pre-sort rules during compilation (optimization: avoid per-evaluation sorting)
+        // BLOCK rules first, then by priority descending
+        compiled_rules.sort_by(|a, b| match (&a.action, &b.action) {
+            (Action::Block, Action::Allow) => std::cmp::Ordering::Less,
+            (Action::Allow, Action::Block) => std::cmp::Ordering::Greater,
+            _ => b.priority.cmp(&a.priority),
+        });
+
+        Ok(compiled_rules)
+    }
+
     /// Compile ACL configuration for efficient evaluation
-    fn compile_config(config: &AclConfig) -> Result<CompiledAclConfig, String> {
+    fn compile_config(
+        config: &AclConfig,
+        stats: &AclRuleStats,
+    ) -> Result<CompiledAclConfig, String> {
         let mut users = std::collections::HashMap::new();
         let mut groups = std::collections::HashMap::new();
         let mut groups_by_lowercase = std::collections::HashMap::new();
+        // Domain list files are read here, once each however many rules name them
+        let mut lists = DomainLists::new();
+        let mut ancestors = config.group_ancestors()?;
 
         // Compile user rules (wrap in Arc for cheap cloning)
         for user_acl in &config.users {
-            let mut compiled_rules: Vec<_> = user_acl
-                .rules
-                .iter()
-                .map(|r| CompiledAclRule::compile(r).map(Arc::new))
-                .collect::<Result<Vec<_>, _>>()?;
-
-            // Pre-sort rules during compilation (optimization: avoid per-evaluation sorting)
-            // BLOCK rules first, then by priority descending
-            compiled_rules.sort_by(|a, b| match (&a.action, &b.action) {
-                (Action::Block, Action::Allow) => std::cmp::Ordering::Less,
-                (Action::Allow, Action::Block) => std::cmp::Ordering::Greater,
-                _ => b.priority.cmp(&a.priority),
-            });
+            let compiled_rules = Self::compile_rules(
+                &format!("user:{}", user_acl.username),
+                &user_acl.rules,
+                stats,
+                &mut lists,
+            )?;
 
             users.insert(
                 user_acl.username.clone(),
@@ -81,23 +387,19 @@ impl AclEngine {
 
         // Compile group rules (wrap in Arc for cheap cloning)
         for group_acl in &config.groups {
-            let mut compiled_rules: Vec<_> = group_acl
-                .rules
-                .iter()
-                .map(|r| CompiledAclRule::compile(r).map(Arc::new))
-                .collect::<Result<Vec<_>, _>>()?;
-
-            // Pre-sort rules during compilation (optimization: avoid per-evaluation sorting)
-            // BLOCK rules first, then by priority descending
-            compiled_rules.sort_by(|a, b| match (&a.action, &b.action) {
-                (Action::Block, Action::Allow) => std::cmp::Ordering::Less,
-                (Action::Allow, Action::Block) => std::cmp::Ordering::Greater,
-                _ => b.priority.cmp(&a.priority),
-            });
+            let compiled_rules = Self::compile_rules(
+                &format!("group:{}", group_acl.name),
+                &group_acl.rules,
+                stats,
+                &mut lists,
+            )?;
 
             let compiled_group = CompiledGroupAcl {
                 name: group_acl.name.clone(),
                 rules: compiled_rules,
+                ancestors: ancestors
+                    .remove(&group_acl.name.to_ascii_lowercase())
+                    .unwrap_or_default(),
             };
 
             // Insert into both maps - regular and lowercase index
@@ -105,15 +407,34 @@ impl AclEngine {
             groups_by_lowercase.insert(group_acl.name.to_ascii_lowercase(), compiled_group);
         }
 
+        let has_source_rules = config
+            .users
+            .iter()
+            .flat_map(|acl| acl.rules.iter())
+            .chain(config.groups.iter().flat_map(|acl| acl.rules.iter()))
+            .any(|rule| !rule.sources.is_empty());
+        let has_scheduled_rules = config
+            .users
+            .iter()
+            .flat_map(|acl| acl.rules.iter())
+            .chain(config.groups.iter().flat_map(|acl| acl.rules.iter()))
+            .any(|rule| rule.schedule.is_some());
+
         Ok(CompiledAclConfig {
             global: config.global.clone(),
             users,
             groups,
             groups_by_lowercase,
+            has_source_rules,
+            has_scheduled_rules,
+            cache: None,
+            source: Arc::new(config.clone()),
         })
     }
 
     /// Evaluate ACL for a connection attempt (legacy method using static groups from config)
+    /// Does not count rule hits. `source` is the client address, matched against rules
+    /// with `sources`; `None` never matches such a rule.
     /// Returns (Decision, matched_rule_description)
     pub async fn evaluate(
         &self,
@@ -121,38 +442,126 @@ impl AclEngine {
         dest: &Address,
         port: u16,
         protocol: &Protocol,
+        source: Option<IpAddr>,
     ) -> (AclDecision, Option<String>) {
-        // OPTIMIZATION: Minimize RwLock hold time - clone only what we need and release lock immediately
+        let verdict = self.verdict(user, dest, port, protocol, source).await;
+        (verdict.decision, verdict.matched_rule)
+    }
+
+    /// [`Self::evaluate`] returning the matched rule's log level and reply code as well
+    pub async fn verdict(
+        &self,
+        user: &str,
+        dest: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+    ) -> AclVerdict {
+        self.verdict_at(user, dest, port, protocol, source, self.clock.now())
+            .await
+    }
+
+    /// [`Self::verdict`] with rule schedules checked at `at` instead of now, to preview
+    /// a decision at another time
+    pub async fn verdict_at(
+        &self,
+        user: &str,
+        dest: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+        at: DateTime<Utc>,
+    ) -> AclVerdict {
+        // Nothing borrowed from the snapshot outlives this block, so a reload can free it
         let (all_rules, default_policy) = {
-            let config = self.config.read().await;
+            let config = self.snapshot();
             let rules = self.collect_rules(&config, user);
             let policy = config.global.default_policy.clone();
             (rules, policy)
-            // Lock released here
         };
 
-        if all_rules.is_empty() {
-            return (
-                AclDecision::from(&default_policy),
-                Some("Default policy".to_string()),
-            );
-        }
-
         // Evaluate rules in priority order (BLOCK rules first)
         for rule in &all_rules {
-            if rule.matches(dest, port, protocol) {
-                return (
-                    AclDecision::from(&rule.action),
-                    Some(rule.description.clone()),
-                );
+            if rule.matches(dest, port, protocol, source, at) {
+                return rule_verdict(rule, dest);
             }
         }
 
         // No rule matched - apply default policy
-        (
-            AclDecision::from(&default_policy),
-            Some("Default policy".to_string()),
-        )
+        policy_verdict(&default_policy, "Default policy")
+    }
+
+    /// [`Self::evaluate`] with a trace of every rule evaluated and why it did or did not
+    /// match. Kept apart from `evaluate` so connections never pay for the bookkeeping.
+    pub async fn explain(
+        &self,
+        user: &str,
+        dest: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+    ) -> AclExplanation {
+        self.explain_at(user, dest, port, protocol, source, self.clock.now())
+            .await
+    }
+
+    /// [`Self::explain`] with rule schedules checked at `at` instead of now
+    pub async fn explain_at(
+        &self,
+        user: &str,
+        dest: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+        at: DateTime<Utc>,
+    ) -> AclExplanation {
+        let (all_rules, default_policy) = {
+            let config = self.snapshot();
+            let rules = self.collect_rules(&config, user);
+            let policy = config.global.default_policy.clone();
+            (rules, policy)
+        };
+
+        let default_verdict = policy_verdict(&default_policy, "Default policy");
+        let mut explanation = AclExplanation {
+            decision: default_verdict.decision,
+            matched_rule: default_verdict.matched_rule,
+            reply_code: default_verdict.reply_code,
+            resolve: default_verdict.resolve,
+            rules_total: all_rules.len(),
+            stopped_at: None,
+            trace: Vec::new(),
+            omitted: 0,
+        };
+
+        for (index, rule) in all_rules.iter().enumerate() {
+            let outcome = rule.explain(dest, port, protocol, source, at);
+            if explanation.trace.len() < MAX_TRACE_RULES || outcome.matched() {
+                explanation.trace.push(RuleTrace {
+                    position: index + 1,
+                    rule_id: rule.id.clone(),
+                    scope: rule.stats.scope().to_string(),
+                    description: rule.description.clone(),
+                    action: rule.action.clone(),
+                    priority: rule.priority,
+                    outcome,
+                });
+            } else {
+                explanation.omitted += 1;
+            }
+
+            if outcome.matched() {
+                let verdict = rule_verdict(rule, dest);
+                explanation.decision = verdict.decision;
+                explanation.matched_rule = verdict.matched_rule;
+                explanation.reply_code = verdict.reply_code;
+                explanation.resolve = verdict.resolve;
+                explanation.stopped_at = Some(index + 1);
+                break;
+            }
+        }
+
+        explanation
     }
 
     /// Evaluate ACL with dynamic groups from LDAP (via NSS/SSSD)
@@ -169,6 +578,9 @@ impl AclEngine {
     /// - ACL config defines: [[groups]] name = "developers"
     /// - This method uses ONLY "developers" rules, ignores all other groups
     ///
+    /// The matched rule's hit counter is incremented, so this is for real connections;
+    /// previews use [`Self::dry_run_with_groups`].
+    ///
     /// Returns (Decision, matched_rule_description)
     pub async fn evaluate_with_groups(
         &self,
@@ -177,38 +589,189 @@ impl AclEngine {
         dest: &Address,
         port: u16,
         protocol: &Protocol,
+        source: Option<IpAddr>,
     ) -> (AclDecision, Option<String>) {
-        // OPTIMIZATION: Minimize RwLock hold time - clone only what we need and release lock immediately
-        let (all_rules, default_policy) = {
-            let config = self.config.read().await;
+        let verdict = self
+            .evaluate_groups(user, user_groups, dest, port, protocol, source, true)
+            .await;
+        (verdict.decision, verdict.matched_rule)
+    }
+
+    /// [`Self::evaluate_with_groups`] returning the matched rule's log level as well
+    pub async fn verdict_with_groups(
+        &self,
+        user: &str,
+        user_groups: &[String],
+        dest: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+    ) -> AclVerdict {
+        self.evaluate_groups(user, user_groups, dest, port, protocol, source, true)
+            .await
+    }
+
+    /// Check the addresses a domain resolved to against the rules, for a name no rule
+    /// matched (its verdict came from the default policy).
+    ///
+    /// A block on any of the addresses wins, so a name cannot be pointed at an address
+    /// the ACL blocks; otherwise the first address an allow rule matches decides. The
+    /// verdict is `matched_on = ResolvedIp`. `None` when no rule matches any of them and
+    /// the name's verdict stands.
+    pub async fn verdict_for_resolved(
+        &self,
+        user: &str,
+        user_groups: &[String],
+        resolved: &[IpAddr],
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+    ) -> Option<AclVerdict> {
+        let mut allowed = None;
+        for ip in resolved {
+            let verdict = self
+                .evaluate_groups(
+                    user,
+                    user_groups,
+                    &Address::bound(*ip),
+                    port,
+                    protocol,
+                    source,
+                    true,
+                )
+                .await;
+            if verdict.matched_on == AclMatchedOn::DefaultPolicy {
+                continue;
+            }
+            let verdict = AclVerdict {
+                matched_on: AclMatchedOn::ResolvedIp,
+                ..verdict
+            };
+            if verdict.decision == AclDecision::Block {
+                return Some(verdict);
+            }
+            allowed.get_or_insert(verdict);
+        }
+        allowed
+    }
+
+    /// [`Self::evaluate_with_groups`] without counting a rule hit
+    pub async fn dry_run_with_groups(
+        &self,
+        user: &str,
+        user_groups: &[String],
+        dest: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+    ) -> (AclDecision, Option<String>) {
+        let verdict = self
+            .evaluate_groups(user, user_groups, dest, port, protocol, source, false)
+            .await;
+        (verdict.decision, verdict.matched_rule)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn evaluate_groups(
+        &self,
+        user: &str,
+        user_groups: &[String],
+        dest: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+        record_hit: bool,
+    ) -> AclVerdict {
+        let at = self.clock.now();
+        // Nothing borrowed from the snapshot outlives this block, so a reload can free it
+        let (all_rules, default_policy, cached) = {
+            let config = self.snapshot();
+
+            // Only connections go through the cache; previews always evaluate the rules
+            let cached = match &config.cache {
+                Some(cache) if record_hit => {
+                    let key = Self::cache_key(
+                        &config,
+                        user,
+                        user_groups,
+                        dest,
+                        port,
+                        protocol,
+                        source,
+                        at,
+                    );
+                    if let Some(verdict) = cache.get(&key) {
+                        return verdict;
+                    }
+                    Some((cache.clone(), key))
+                }
+                _ => None,
+            };
+
             let rules = self.collect_rules_from_groups(&config, user, user_groups);
             let policy = config.global.default_policy.clone();
-            (rules, policy)
-            // Lock released here
+            (rules, policy, cached)
         };
 
-        if all_rules.is_empty() {
-            return (
-                AclDecision::from(&default_policy),
-                Some("Default policy (no matching groups)".to_string()),
-            );
-        }
-
         // Evaluate rules in priority order (BLOCK rules first)
-        for rule in &all_rules {
-            if rule.matches(dest, port, protocol) {
-                return (
-                    AclDecision::from(&rule.action),
-                    Some(rule.description.clone()),
-                );
+        let matched = all_rules
+            .iter()
+            .find(|rule| rule.matches(dest, port, protocol, source, at));
+        let verdict = match matched {
+            Some(rule) => {
+                if record_hit {
+                    rule.stats.record(user);
+                }
+                rule_verdict(rule, dest)
+            }
+            None if all_rules.is_empty() => {
+                policy_verdict(&default_policy, "Default policy (no matching groups)")
             }
+            // No rule matched - apply default policy
+            None => policy_verdict(&default_policy, "Default policy"),
+        };
+
+        if let Some((cache, key)) = cached {
+            cache.insert(key, verdict.clone(), matched.map(|rule| rule.stats.clone()));
         }
+        verdict
+    }
 
-        // No rule matched - apply default policy
-        (
-            AclDecision::from(&default_policy),
-            Some("Default policy".to_string()),
-        )
+    /// Everything `evaluate_groups` depends on besides the configuration itself
+    ///
+    /// Destinations are keyed as requested, so a domain rule is cached per domain name
+    /// and never per resolved address. Schedules change at whole minutes, so with
+    /// scheduled rules the minute of `at` is part of the key.
+    #[allow(clippy::too_many_arguments)]
+    fn cache_key(
+        config: &CompiledAclConfig,
+        user: &str,
+        user_groups: &[String],
+        dest: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+        at: DateTime<Utc>,
+    ) -> CacheKey {
+        let mut groups: Vec<String> = user_groups
+            .iter()
+            .map(|group| group.to_ascii_lowercase())
+            .filter(|group| config.groups_by_lowercase.contains_key(group))
+            .collect();
+        groups.sort_unstable();
+        groups.dedup();
+
+        CacheKey {
+            user: user.to_string(),
+            groups,
+            destination: dest.clone(),
+            port,
+            protocol: protocol.clone(),
+            source: source.filter(|_| config.has_source_rules),
+            minute: config
+                .has_scheduled_rules
+                .then(|| at.timestamp().div_euclid(60)),
+        }
     }
 
     /// Collect all rules for a user (user rules + group rules)
@@ -220,27 +783,48 @@ impl AclEngine {
         // Get user's rules (already sorted during compilation)
         if let Some(user_acl) = config.users.get(user) {
             // Cheap clone - just Arc increment, no deep copy
-            all_rules.extend(user_acl.rules.clone());
+            all_rules.extend(user_acl.rules.iter().map(|rule| (0, rule.clone())));
 
-            // Add rules from user's groups (each group's rules are already sorted)
-            for group_name in &user_acl.groups {
-                if let Some(group_acl) = config.groups.get(group_name) {
-                    // Cheap clone - just Arc increment, no deep copy
-                    all_rules.extend(group_acl.rules.clone());
-                }
-            }
+            // Add rules from user's groups and the groups they inherit from
+            let groups = user_acl
+                .groups
+                .iter()
+                .filter(|group_name| config.groups.contains_key(*group_name))
+                .map(|group_name| group_name.to_ascii_lowercase());
+            Self::extend_with_groups(config, groups, &mut all_rules);
         }
 
-        // OPTIMIZATION: Re-sort combined rules to maintain global priority order
-        // This is needed because we're mixing user rules + group rules
-        // Pre-sorted data sorts faster (O(n) for already sorted data with adaptive sort)
-        all_rules.sort_unstable_by(|a, b| match (&a.action, &b.action) {
-            (Action::Block, Action::Allow) => std::cmp::Ordering::Less,
-            (Action::Allow, Action::Block) => std::cmp::Ordering::Greater,
-            _ => b.priority.cmp(&a.priority),
-        });
+        evaluation_order(all_rules)
+    }
+
+    /// Add the rules of `groups` (lowercase names of the user's groups) and of every
+    /// group they inherit from, tagged with the group's distance from the user: 1 for
+    /// the user's own groups, 2 for their parents and so on. A group reached along
+    /// several paths is added once, at its nearest distance.
+    fn extend_with_groups(
+        config: &CompiledAclConfig,
+        groups: impl IntoIterator<Item = String>,
+        all_rules: &mut Vec<(usize, Arc<CompiledAclRule>)>,
+    ) {
+        // Ordered by name, so groups at the same distance always sort the same way
+        let mut distances: BTreeMap<String, usize> = BTreeMap::new();
+        for group_name in groups {
+            let Some(group_acl) = config.groups_by_lowercase.get(&group_name) else {
+                continue;
+            };
+            for (ancestor, distance) in &group_acl.ancestors {
+                let nearest = distances.entry(ancestor.clone()).or_insert(distance + 1);
+                *nearest = (*nearest).min(distance + 1);
+            }
+            distances.insert(group_name, 1);
+        }
 
-        all_rules
+        for (group_name, distance) in distances {
+            if let Some(group_acl) = config.groups_by_lowercase.get(&group_name) {
+                // Cheap clone - just Arc increment, no deep copy
+                all_rules.extend(group_acl.rules.iter().map(|rule| (distance, rule.clone())));
+            }
+        }
     }
 
     /// Collect rules from LDAP groups (case-insensitive matching)
@@ -269,33 +853,14 @@ impl AclEngine {
         // Add per-user rules first (already sorted during compilation)
         if let Some(user_acl) = config.users.get(user) {
             // Cheap clone - just Arc increment, no deep copy
-            all_rules.extend(user_acl.rules.clone());
+            all_rules.extend(user_acl.rules.iter().map(|rule| (0, rule.clone())));
         }
 
         // OPTIMIZATION: Iterate through user's LDAP groups with O(1) lookup instead of O(n*m) nested loop
-        for ldap_group in user_groups {
-            let lowercase_group = ldap_group.to_ascii_lowercase();
-            if let Some(group_acl) = config.groups_by_lowercase.get(&lowercase_group) {
-                // Cheap clone - just Arc increment, no deep copy
-                // Rules are already sorted during compilation
-                all_rules.extend(group_acl.rules.clone());
-            }
-        }
+        let groups = user_groups.iter().map(|group| group.to_ascii_lowercase());
+        Self::extend_with_groups(config, groups, &mut all_rules);
 
-        // OPTIMIZATION: Re-sort combined rules to maintain global priority order
-        // This is needed because we're mixing user rules + multiple group rules
-        // Pre-sorting during compilation helps here (partially sorted data sorts faster)
-        // Use sort_unstable_by for better performance (no stable sort needed for ACL rules)
-        all_rules.sort_unstable_by(|a, b| {
-            match (&a.action, &b.action) {
-                (Action::Block, Action::Allow) => std::cmp::Ordering::Less,
-                (Action::Allow, Action::Block) => std::cmp::Ordering::Greater,
-                // Same action - sort by priority descending
-                _ => b.priority.cmp(&a.priority),
-            }
-        });
-
-        all_rules
+        evaluation_order(all_rules)
     }
 
     /// Get list of LDAP groups that matched ACL groups (for debugging)
@@ -319,32 +884,113 @@ impl AclEngine {
     }
 
     /// Hot reload ACL configuration
+    ///
+    /// Validation and compilation run on a blocking thread; evaluations keep using the
+    /// current snapshot until the new one is swapped in. A config that fails to build
+    /// leaves the current one in place.
     pub async fn reload(&self, new_config: AclConfig) -> Result<(), String> {
-        // Validate config
-        new_config.validate()?;
+        let _reload = self.reload_lock.lock().await;
+
+        let started = Instant::now();
+        let stats = self.rule_stats.clone();
+        let lint_settings = self.lint_settings.clone();
+        let built = tokio::task::spawn_blocking(move || {
+            new_config.validate()?;
+            Self::check(&new_config, &lint_settings)?;
+            let compiled = Self::compile_config(&new_config, &stats)?;
+            let rule_ids = compiled.rule_ids();
+            Ok::<_, String>((compiled, rule_ids))
+        })
+        .await
+        .map_err(|e| format!("ACL build task failed: {}", e))?;
+        let build_time = started.elapsed();
+
+        #[cfg(feature = "metrics")]
+        super::metrics::ACL_RELOAD_BUILD_DURATION.observe(build_time.as_secs_f64());
+
+        let (mut compiled, rule_ids) = match built {
+            Ok(built) => built,
+            Err(e) => {
+                // Drop counters the failed build registered for rules that never went live
+                self.rule_stats.retain(&self.snapshot().rule_ids());
+                return Err(e);
+            }
+        };
 
-        // Compile new config
-        let compiled = Self::compile_config(&new_config)?;
+        // Decisions cached under the previous rules go away with them
+        compiled.cache = self.new_cache();
 
-        // Atomic swap
-        let mut config = self.config.write().await;
-        *config = compiled;
+        let previous = {
+            let mut config = self.config.write().unwrap_or_else(|e| e.into_inner());
+            std::mem::replace(&mut *config, Arc::new(compiled))
+        };
+
+        // Pruning counters and freeing a large configuration stay off the runtime too
+        let stats = self.rule_stats.clone();
+        let _ = tokio::task::spawn_blocking(move || {
+            stats.retain(&rule_ids);
+            drop(previous);
+        })
+        .await;
 
-        info!("ACL configuration reloaded successfully");
+        info!(
+            build_ms = build_time.as_millis() as u64,
+            "ACL configuration reloaded successfully"
+        );
 
         Ok(())
     }
 
+    /// Hit statistics of every configured rule, ordered by scope and rule id
+    pub async fn rule_usage(&self) -> Vec<RuleUsage> {
+        let config = self.snapshot();
+        let scoped = config
+            .users
+            .iter()
+            .map(|(name, acl)| (format!("user:{}", name), &acl.rules))
+            .chain(
+                config
+                    .groups
+                    .iter()
+                    .map(|(name, acl)| (format!("group:{}", name), &acl.rules)),
+            );
+
+        let mut usage = Vec::new();
+        let mut seen = HashSet::new();
+        for (scope, rules) in scoped {
+            for rule in rules {
+                if !seen.insert(rule.id.clone()) {
+                    continue;
+                }
+                usage.push(RuleUsage {
+                    rule_id: rule.id.clone(),
+                    scope: scope.clone(),
+                    description: rule.description.clone(),
+                    action: rule.action.clone(),
+                    hits: rule.stats.hits(),
+                    last_matched_at: rule.stats.last_matched_at(),
+                    tracked_since: rule.stats.tracked_since(),
+                    users: rule.stats.user_hits(),
+                });
+            }
+        }
+        usage.sort_by(|a, b| a.scope.cmp(&b.scope).then(a.rule_id.cmp(&b.rule_id)));
+        usage
+    }
+
+    /// The configuration evaluations currently use, as it was loaded
+    pub fn current_config(&self) -> Arc<AclConfig> {
+        self.snapshot().source.clone()
+    }
+
     /// Get current config (for inspection)
     pub async fn get_user_count(&self) -> usize {
-        let config = self.config.read().await;
-        config.users.len()
+        self.snapshot().users.len()
     }
 
     /// Get current config (for inspection)
     pub async fn get_group_count(&self) -> usize {
-        let config = self.config.read().await;
-        config.groups.len()
+        self.snapshot().groups.len()
     }
 }
 
@@ -367,14 +1013,25 @@ impl AclConfig {
             }
         }
 
-        // Check that user groups exist
-        for user in &self.users {
-            for group_name in &user.groups {
-                if !self.groups.iter().any(|g| &g.name == group_name) {
-                    return Err(format!(
-                        "User '{}' references non-existent group '{}'",
-                        user.username, group_name
-                    ));
+        // Group references are checked by the loader, see `super::lint`; parents must
+        // exist and must not form a cycle
+        self.group_ancestors()?;
+
+        // Schedules are checked here so a bad range is reported before anything compiles
+        let scoped_rules = self
+            .users
+            .iter()
+            .map(|acl| (format!("user:{}", acl.username), &acl.rules))
+            .chain(
+                self.groups
+                    .iter()
+                    .map(|acl| (format!("group:{}", acl.name), &acl.rules)),
+            );
+        for (scope, rules) in scoped_rules {
+            for rule in rules {
+                if let Some(schedule) = &rule.schedule {
+                    CompiledSchedule::compile(schedule)
+                        .map_err(|e| format!("Rule '{}' of {}: {}", rule.description, scope, e))?;
                 }
             }
         }
@@ -393,6 +1050,147 @@ impl AclConfig {
 
         Ok(())
     }
+
+    /// Every group's inherited groups (`parents`, transitively) with their distance, 1
+    /// for a direct parent, in breadth-first order
+    ///
+    /// Keys and names are lowercase, matching groups the way evaluation does. Fails on a
+    /// parent that is not defined and on a cycle, naming the groups that form it.
+    pub fn group_ancestors(&self) -> Result<HashMap<String, Vec<(String, usize)>>, String> {
+        let mut parents: HashMap<String, (&str, Vec<String>)> = HashMap::new();
+        for group in &self.groups {
+            parents.insert(
+                group.name.to_ascii_lowercase(),
+                (
+                    group.name.as_str(),
+                    group
+                        .parents
+                        .iter()
+                        .map(|parent| parent.to_ascii_lowercase())
+                        .collect(),
+                ),
+            );
+        }
+        for group in &self.groups {
+            if let Some(parent) = group
+                .parents
+                .iter()
+                .find(|parent| !parents.contains_key(&parent.to_ascii_lowercase()))
+            {
+                return Err(format!(
+                    "Group '{}' inherits from non-existent group '{}'",
+                    group.name, parent
+                ));
+            }
+        }
+
+        // Depth-first from each group in file order, so the same file names the same cycle
+        let mut acyclic = HashSet::new();
+        for group in &self.groups {
+            find_inheritance_cycle(
+                &group.name.to_ascii_lowercase(),
+                &parents,
+                &mut Vec::new(),
+                &mut acyclic,
+            )?;
+        }
+
+        let mut ancestors = HashMap::new();
+        for name in parents.keys() {
+            let mut found: Vec<(String, usize)> = Vec::new();
+            let mut queue = std::collections::VecDeque::from([(name.as_str(), 0)]);
+            while let Some((group, distance)) = queue.pop_front() {
+                for parent in &parents[group].1 {
+                    if parent != name && !found.iter().any(|(seen, _)| seen == parent) {
+                        found.push((parent.clone(), distance + 1));
+                        queue.push_back((parent.as_str(), distance + 1));
+                    }
+                }
+            }
+            ancestors.insert(name.clone(), found);
+        }
+        Ok(ancestors)
+    }
+}
+
+/// Follow `parents` from `group`; `path` holds the groups being followed
+fn find_inheritance_cycle(
+    group: &str,
+    parents: &HashMap<String, (&str, Vec<String>)>,
+    path: &mut Vec<String>,
+    acyclic: &mut HashSet<String>,
+) -> Result<(), String> {
+    if acyclic.contains(group) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|seen| seen == group) {
+        let cycle: Vec<&str> = path[start..]
+            .iter()
+            .chain(std::iter::once(&path[start]))
+            .map(|name| parents[name].0)
+            .collect();
+        return Err(format!(
+            "ACL group inheritance cycle: {}",
+            cycle.join(" -> ")
+        ));
+    }
+
+    path.push(group.to_string());
+    for parent in &parents[group].1 {
+        find_inheritance_cycle(parent, parents, path, acyclic)?;
+    }
+    path.pop();
+    acyclic.insert(group.to_string());
+    Ok(())
+}
+
+/// Sort collected rules for evaluation and drop their distances
+///
+/// BLOCK rules come first, then higher priorities. At equal action and priority the
+/// user's own rules (distance 0) win over their groups' rules, which win over inherited
+/// ones; the sort is stable, so what is left keeps the collection order.
+fn evaluation_order(mut rules: Vec<(usize, Arc<CompiledAclRule>)>) -> Vec<Arc<CompiledAclRule>> {
+    rules.sort_by(|(a_distance, a), (b_distance, b)| {
+        let action = match (&a.action, &b.action) {
+            (Action::Block, Action::Allow) => std::cmp::Ordering::Less,
+            (Action::Allow, Action::Block) => std::cmp::Ordering::Greater,
+            _ => std::cmp::Ordering::Equal,
+        };
+        action
+            .then(b.priority.cmp(&a.priority))
+            .then(a_distance.cmp(b_distance))
+    });
+    rules.into_iter().map(|(_, rule)| rule).collect()
+}
+
+fn rule_verdict(rule: &CompiledAclRule, dest: &Address) -> AclVerdict {
+    let decision = AclDecision::from(&rule.action);
+    let reply_code = (decision == AclDecision::Block).then(|| rule.reply_code.unwrap_or_default());
+    AclVerdict {
+        decision,
+        matched_rule: Some(rule.description.clone()),
+        log: rule.log,
+        reply_code,
+        resolve: rule.resolve,
+        matched_on: if dest.literal_ip().is_some() {
+            AclMatchedOn::Ip
+        } else {
+            AclMatchedOn::Domain
+        },
+    }
+}
+
+fn policy_verdict(policy: &Action, description: &str) -> AclVerdict {
+    let decision = AclDecision::from(policy);
+    let reply_code = (decision == AclDecision::Block).then(BlockReplyCode::default);
+    AclVerdict {
+        decision,
+        matched_rule: Some(description.to_string()),
+        log: RuleLogLevel::Default,
+        reply_code,
+        resolve: ResolveMode::Local,
+        matched_on: AclMatchedOn::DefaultPolicy,
+    }
 }
 
 #[cfg(test)]
@@ -414,28 +1212,44 @@ mod tests {
                         description: "Allow HTTPS".to_string(),
                         destinations: vec!["0.0.0.0/0".to_string()],
                         ports: vec!["443".to_string()],
+                        sources: vec![],
                         protocols: vec![Protocol::Tcp],
                         priority: 100,
+                        log: RuleLogLevel::Default,
+                        reply_code: None,
+                        resolve: Default::default(),
+                        schedule: None,
                     },
                     AclRule {
                         action: Action::Block,
                         description: "Block admin panel".to_string(),
                         destinations: vec!["admin.example.com".to_string()],
                         ports: vec!["*".to_string()],
+                        sources: vec![],
                         protocols: vec![Protocol::Both],
                         priority: 1000,
+                        log: RuleLogLevel::Default,
+                        reply_code: None,
+                        resolve: Default::default(),
+                        schedule: None,
                     },
                 ],
             }],
             groups: vec![GroupAcl {
                 name: "developers".to_string(),
+                parents: vec![],
                 rules: vec![AclRule {
                     action: Action::Allow,
                     description: "Dev servers".to_string(),
                     destinations: vec!["*.dev.example.com".to_string()],
                     ports: vec!["*".to_string()],
+                    sources: vec![],
                     protocols: vec![Protocol::Both],
                     priority: 50,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
         }
@@ -449,9 +1263,10 @@ mod tests {
         let (decision, rule) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("admin.example.com".to_string()),
+                &Address::Domain("admin.example.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
 
@@ -470,6 +1285,7 @@ mod tests {
                 &Address::IPv4([93, 184, 216, 34]),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
 
@@ -477,6 +1293,43 @@ mod tests {
         assert_eq!(rule.unwrap(), "Allow HTTPS");
     }
 
+    #[tokio::test]
+    async fn test_domain_list_destination() {
+        let list = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(list.path(), "# feed\nmalware.example.com\n").unwrap();
+        let mut config = create_test_config();
+        config.users[0].rules[1].destinations = vec![format!("file:{}", list.path().display())];
+        let engine = AclEngine::new(config.clone()).unwrap();
+
+        let decision = |domain: &'static str| {
+            let engine = &engine;
+            async move {
+                engine
+                    .evaluate(
+                        "alice",
+                        &Address::Domain(domain.into()),
+                        443,
+                        &Protocol::Tcp,
+                        None,
+                    )
+                    .await
+                    .0
+            }
+        };
+        assert_eq!(decision("malware.example.com").await, AclDecision::Block);
+        assert_eq!(decision("api.dev.example.com").await, AclDecision::Allow);
+
+        // The list is read again on reload; a broken one keeps the previous rules
+        std::fs::write(list.path(), "api.dev.example.com\n").unwrap();
+        engine.reload(config.clone()).await.unwrap();
+        assert_eq!(decision("api.dev.example.com").await, AclDecision::Block);
+
+        std::fs::write(list.path(), "ok.example.com\nnot a domain\n").unwrap();
+        let err = engine.reload(config).await.unwrap_err();
+        assert!(err.contains(" line 2"), "{err}");
+        assert_eq!(decision("api.dev.example.com").await, AclDecision::Block);
+    }
+
     #[tokio::test]
     async fn test_group_inheritance() {
         let engine = AclEngine::new(create_test_config()).unwrap();
@@ -485,9 +1338,10 @@ mod tests {
         let (decision, rule) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("api.dev.example.com".to_string()),
+                &Address::Domain("api.dev.example.com".into()),
                 8080,
                 &Protocol::Tcp,
+                None,
             )
             .await;
 
@@ -506,6 +1360,7 @@ mod tests {
                 &Address::IPv4([93, 184, 216, 34]),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
 
@@ -513,6 +1368,209 @@ mod tests {
         assert_eq!(rule.unwrap(), "Default policy");
     }
 
+    #[tokio::test]
+    async fn test_explain_pinpoints_port_mismatch() {
+        let engine = AclEngine::new(create_test_config()).unwrap();
+
+        // "Allow HTTPS" matches everything but the port
+        let explanation = engine
+            .explain(
+                "alice",
+                &Address::IPv4([93, 184, 216, 34]),
+                8443,
+                &Protocol::Tcp,
+                None,
+            )
+            .await;
+
+        assert_eq!(explanation.decision, AclDecision::Block);
+        assert_eq!(explanation.matched_rule.as_deref(), Some("Default policy"));
+        assert_eq!(explanation.stopped_at, None);
+        assert_eq!(explanation.rules_total, 3);
+        assert_eq!(explanation.trace.len(), 3);
+        assert_eq!(explanation.omitted, 0);
+
+        let https = explanation
+            .trace
+            .iter()
+            .find(|rule| rule.description == "Allow HTTPS")
+            .unwrap();
+        assert_eq!(https.scope, "user:alice");
+        assert_eq!(
+            https.outcome,
+            RuleMatch {
+                protocol: true,
+                destination: true,
+                port: false,
+                source: true,
+                schedule: true,
+            }
+        );
+
+        let dev = explanation
+            .trace
+            .iter()
+            .find(|rule| rule.description == "Dev servers")
+            .unwrap();
+        assert_eq!(dev.scope, "group:developers");
+        assert!(!dev.outcome.destination);
+    }
+
+    #[tokio::test]
+    async fn test_explain_stops_at_matching_rule() {
+        let engine = AclEngine::new(create_test_config()).unwrap();
+        let dest = Address::Domain("admin.example.com".into());
+
+        let explanation = engine
+            .explain("alice", &dest, 443, &Protocol::Tcp, None)
+            .await;
+        let (decision, rule) = engine
+            .evaluate("alice", &dest, 443, &Protocol::Tcp, None)
+            .await;
+
+        assert_eq!(explanation.decision, decision);
+        assert_eq!(explanation.matched_rule, rule);
+        // BLOCK rules are evaluated first, so nothing after the first rule is traced
+        assert_eq!(explanation.stopped_at, Some(1));
+        assert_eq!(explanation.trace.len(), 1);
+        assert!(explanation.trace[0].outcome.matched());
+    }
+
+    #[tokio::test]
+    async fn test_explain_caps_trace() {
+        let mut config = create_test_config();
+        // Only the user's own rules, so positions follow the list below
+        config.users[0].groups.clear();
+        config.users[0].rules = (0..MAX_TRACE_RULES + 10)
+            .map(|i| AclRule {
+                action: Action::Allow,
+                description: format!("port {}", 1000 + i),
+                destinations: vec!["*".to_string()],
+                ports: vec![(1000 + i).to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                // Evaluated in the order they are listed
+                priority: (MAX_TRACE_RULES + 10 - i) as u32,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            })
+            .collect();
+        let engine = AclEngine::new(config).unwrap();
+
+        let port = (1000 + MAX_TRACE_RULES + 5) as u16;
+        let explanation = engine
+            .explain(
+                "alice",
+                &Address::IPv4([10, 0, 0, 1]),
+                port,
+                &Protocol::Tcp,
+                None,
+            )
+            .await;
+
+        assert_eq!(explanation.decision, AclDecision::Allow);
+        assert_eq!(explanation.stopped_at, Some(MAX_TRACE_RULES + 6));
+        assert_eq!(explanation.trace.len(), MAX_TRACE_RULES + 1);
+        assert_eq!(explanation.omitted, 5);
+        let last = explanation.trace.last().unwrap();
+        assert_eq!(last.position, MAX_TRACE_RULES + 6);
+        assert_eq!(last.description, format!("port {}", port));
+    }
+
+    #[tokio::test]
+    async fn test_group_rule_limited_to_sources() {
+        let mut config = create_test_config();
+        config.groups[0].rules[0].sources = vec!["10.1.0.0/16".to_string()];
+        let engine = AclEngine::new(config).unwrap();
+        let groups = vec!["developers".to_string()];
+        let dest = Address::Domain("api.dev.example.com".into());
+
+        let evaluate = |source: &str| {
+            engine.evaluate_with_groups(
+                "alice",
+                &groups,
+                &dest,
+                8080,
+                &Protocol::Tcp,
+                Some(source.parse().unwrap()),
+            )
+        };
+        let (office, _) = evaluate("10.1.4.2").await;
+        let (home, rule) = evaluate("198.51.100.7").await;
+
+        assert_eq!(office, AclDecision::Allow);
+        assert_eq!(home, AclDecision::Block);
+        assert_eq!(rule.as_deref(), Some("Default policy"));
+    }
+
+    #[tokio::test]
+    async fn test_resolved_addresses_checked_after_default_policy() {
+        let mut config = create_test_config();
+        config.global.default_policy = Action::Allow;
+        config.users[0].rules.push(AclRule {
+            action: Action::Block,
+            description: "Block internal".to_string(),
+            destinations: vec!["10.0.0.0/8".to_string()],
+            ports: vec!["*".to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Both],
+            priority: 900,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
+        });
+        let engine = AclEngine::new(config).unwrap();
+        let groups: Vec<String> = Vec::new();
+
+        let name = engine
+            .verdict_with_groups(
+                "alice",
+                &groups,
+                &Address::Domain("intranet.example.org".into()),
+                443,
+                &Protocol::Tcp,
+                None,
+            )
+            .await;
+        assert_eq!(name.matched_on, AclMatchedOn::DefaultPolicy);
+
+        // One blocked address is enough, whatever the others match
+        let resolved = [
+            "93.184.216.34".parse().unwrap(),
+            "10.1.2.3".parse().unwrap(),
+        ];
+        let verdict = engine
+            .verdict_for_resolved("alice", &groups, &resolved, 443, &Protocol::Tcp, None)
+            .await
+            .unwrap();
+        assert_eq!(verdict.decision, AclDecision::Block);
+        assert_eq!(verdict.matched_rule.as_deref(), Some("Block internal"));
+        assert_eq!(verdict.matched_on, AclMatchedOn::ResolvedIp);
+
+        // No rule for any of the addresses leaves the name's verdict standing
+        let resolved = ["93.184.216.34".parse().unwrap()];
+        assert!(engine
+            .verdict_for_resolved("alice", &groups, &resolved, 80, &Protocol::Tcp, None)
+            .await
+            .is_none());
+
+        // A rule on the name itself is recorded as a domain match
+        let admin = engine
+            .verdict_with_groups(
+                "alice",
+                &groups,
+                &Address::Domain("admin.example.com".into()),
+                443,
+                &Protocol::Tcp,
+                None,
+            )
+            .await;
+        assert_eq!(admin.matched_on, AclMatchedOn::Domain);
+    }
+
     #[tokio::test]
     async fn test_unknown_user_default_policy() {
         let engine = AclEngine::new(create_test_config()).unwrap();
@@ -524,6 +1582,7 @@ mod tests {
                 &Address::IPv4([93, 184, 216, 34]),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
 
@@ -540,9 +1599,10 @@ mod tests {
             let _ = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain("api.dev.example.com".to_string()),
+                    &Address::Domain("api.dev.example.com".into()),
                     443,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
         }
@@ -569,8 +1629,246 @@ mod tests {
         // Reset
         config = create_test_config();
 
-        // Non-existent group
+        // Non-existent group: a lint finding, an error only with strict references
         config.users[0].groups.push("non-existent".to_string());
-        assert!(config.validate().is_err());
+        assert!(config.validate().is_ok());
+        assert!(AclEngine::new(config.clone()).is_ok());
+        let strict = LintSettings {
+            strict_references: true,
+            ..LintSettings::default()
+        };
+        let err = AclEngine::with_lint_settings(config, strict)
+            .err()
+            .expect("strict references reject unknown groups");
+        assert!(err.contains("non-existent group 'non-existent'"), "{}", err);
+    }
+
+    fn inheritance_rule(action: Action, description: &str, destination: &str) -> AclRule {
+        AclRule {
+            action,
+            description: description.to_string(),
+            destinations: vec![destination.to_string()],
+            ports: vec!["*".to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Both],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
+        }
+    }
+
+    fn group(name: &str, parents: &[&str], rules: Vec<AclRule>) -> GroupAcl {
+        GroupAcl {
+            name: name.to_string(),
+            parents: parents.iter().map(|p| p.to_string()).collect(),
+            rules,
+        }
+    }
+
+    #[tokio::test]
+    async fn nested_groups_inherit_with_lower_precedence() {
+        let mut legacy = inheritance_rule(Action::Allow, "Backend legacy", "legacy.corp");
+        legacy.priority = 1000;
+        let config = AclConfig {
+            global: GlobalAclConfig {
+                default_policy: Action::Block,
+            },
+            users: vec![UserAcl {
+                username: "carol".to_string(),
+                groups: vec![],
+                rules: vec![inheritance_rule(Action::Allow, "Carol db", "db.corp")],
+            }],
+            groups: vec![
+                group(
+                    "engineering",
+                    &[],
+                    vec![
+                        inheritance_rule(Action::Allow, "Engineering git", "git.corp"),
+                        inheritance_rule(Action::Allow, "Engineering wiki", "wiki.corp"),
+                        inheritance_rule(Action::Block, "Engineering legacy", "legacy.corp"),
+                    ],
+                ),
+                group(
+                    "backend",
+                    &["Engineering"],
+                    vec![
+                        inheritance_rule(Action::Allow, "Backend git", "git.corp"),
+                        inheritance_rule(Action::Allow, "Backend db", "db.corp"),
+                        legacy,
+                    ],
+                ),
+                group("platform", &["backend"], vec![]),
+            ],
+        };
+        let engine = &AclEngine::new(config).unwrap();
+        let decide = move |user: &'static str, dest: &'static str| async move {
+            engine
+                .evaluate_with_groups(
+                    user,
+                    &["platform".to_string()],
+                    &Address::Domain(dest.into()),
+                    443,
+                    &Protocol::Tcp,
+                    None,
+                )
+                .await
+        };
+
+        // platform -> backend -> engineering
+        let (decision, rule) = decide("dave", "wiki.corp").await;
+        assert_eq!(decision, AclDecision::Allow);
+        assert_eq!(rule.as_deref(), Some("Engineering wiki"));
+        // Equal action and priority: the nearer group wins
+        let (_, rule) = decide("dave", "git.corp").await;
+        assert_eq!(rule.as_deref(), Some("Backend git"));
+        // ... and the user's own rules win over every group
+        let (_, rule) = decide("carol", "db.corp").await;
+        assert_eq!(rule.as_deref(), Some("Carol db"));
+        // An inherited block still beats a higher-priority allow
+        let (decision, rule) = decide("dave", "legacy.corp").await;
+        assert_eq!(decision, AclDecision::Block);
+        assert_eq!(rule.as_deref(), Some("Engineering legacy"));
+    }
+
+    #[test]
+    fn inheritance_cycles_and_unknown_parents_are_rejected() {
+        let config = AclConfig {
+            global: GlobalAclConfig::default(),
+            users: vec![],
+            groups: vec![
+                group("a", &["b"], vec![]),
+                group("b", &["c"], vec![]),
+                group("c", &["A"], vec![]),
+            ],
+        };
+        let err = config.validate().unwrap_err();
+        assert_eq!(err, "ACL group inheritance cycle: a -> b -> c -> a");
+        assert!(AclEngine::new(config).is_err());
+
+        let config = AclConfig {
+            global: GlobalAclConfig::default(),
+            users: vec![],
+            groups: vec![group("a", &["missing"], vec![])],
+        };
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("non-existent group 'missing'"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn strict_references_reject_reload() {
+        let strict = LintSettings {
+            strict_references: true,
+            ..LintSettings::default()
+        };
+        let engine = AclEngine::with_lint_settings(create_test_config(), strict).unwrap();
+
+        let mut typo = create_test_config();
+        typo.users[0].groups = vec!["develpers".to_string()];
+        assert!(engine.reload(typo).await.is_err());
+        assert_eq!(engine.get_group_count().await, 1);
+    }
+
+    /// Clock the test moves by hand
+    struct ManualClock(std::sync::Mutex<DateTime<Utc>>);
+
+    impl AclClock for ManualClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn business_hours() -> crate::acl::types::RuleSchedule {
+        crate::acl::types::RuleSchedule {
+            days: vec!["mon-fri".to_string()],
+            times: vec!["08:00-18:00".to_string()],
+            timezone: Some("UTC".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn scheduled_rules_only_match_inside_their_window() {
+        use chrono::TimeZone;
+
+        let mut build = inheritance_rule(Action::Allow, "Build servers", "build.corp");
+        build.schedule = Some(business_hours());
+        let config = AclConfig {
+            global: GlobalAclConfig {
+                default_policy: Action::Block,
+            },
+            users: vec![UserAcl {
+                username: "carol".to_string(),
+                groups: vec!["contractors".to_string()],
+                rules: vec![],
+            }],
+            groups: vec![group("contractors", &[], vec![build])],
+        };
+        // Monday 2026-10-12, 17:59 UTC
+        let clock = Arc::new(ManualClock(std::sync::Mutex::new(
+            Utc.with_ymd_and_hms(2026, 10, 12, 17, 59, 0).unwrap(),
+        )));
+        let engine = AclEngine::new(config)
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_decision_cache(
+                &AclCacheSettings {
+                    enabled: true,
+                    ttl_secs: 3600,
+                    max_entries: 100,
+                },
+                Arc::new(AclStats::new()),
+            );
+        let engine = &engine;
+        let groups = &["contractors".to_string()];
+        let build = &Address::Domain("build.corp".into());
+        let decide =
+            move || engine.verdict_with_groups("bob", groups, build, 443, &Protocol::Tcp, None);
+
+        assert_eq!(decide().await.decision, AclDecision::Allow);
+        // The cached allow does not outlive the minute it was reached in
+        *clock.0.lock().unwrap() = Utc.with_ymd_and_hms(2026, 10, 12, 18, 0, 0).unwrap();
+        assert_eq!(decide().await.decision, AclDecision::Block);
+        // Saturday
+        *clock.0.lock().unwrap() = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        assert_eq!(decide().await.decision, AclDecision::Block);
+
+        // Previews can ask about another time; the trace says why a rule was skipped
+        let monday_morning = Utc.with_ymd_and_hms(2026, 10, 19, 9, 0, 0).unwrap();
+        let verdict = engine
+            .verdict_at("carol", build, 443, &Protocol::Tcp, None, monday_morning)
+            .await;
+        assert_eq!(verdict.decision, AclDecision::Allow);
+        assert_eq!(
+            engine
+                .verdict("carol", build, 443, &Protocol::Tcp, None)
+                .await
+                .decision,
+            AclDecision::Block
+        );
+        let explanation = engine
+            .explain("carol", build, 443, &Protocol::Tcp, None)
+            .await;
+        assert_eq!(explanation.stopped_at, None);
+        assert!(explanation.trace[0].outcome.destination);
+        assert!(!explanation.trace[0].outcome.schedule);
+    }
+
+    #[test]
+    fn malformed_schedules_are_rejected() {
+        let mut rule = inheritance_rule(Action::Allow, "Late shift", "build.corp");
+        rule.schedule = Some(crate::acl::types::RuleSchedule {
+            times: vec!["18:00-25:00".to_string()],
+            ..business_hours()
+        });
+        let config = AclConfig {
+            global: GlobalAclConfig::default(),
+            users: vec![],
+            groups: vec![group("contractors", &[], vec![rule])],
+        };
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("'Late shift' of group:contractors"), "{}", err);
+        assert!(err.contains("18:00-25:00"), "{}", err);
+        assert!(AclEngine::new(config).is_err());
     }
 }
@@ -0,0 +1,878 @@
+//! Example ACL files written by `rustsocks acl example` and `GET /api/acl/example`.
+//!
+//! Each variant is built as an [`AclConfig`], validated, rendered with `toml` and then
+//! annotated, so the output always parses back to the config it was built from. The
+//! comments come from a local registry keyed by setting path; the tests fail when a
+//! rule field is added without a description or without a place in the kitchen-sink
+//! variant.
+
+use super::types::{
+    AclConfig, AclRule, Action, BlockReplyCode, GlobalAclConfig, GroupAcl, Protocol, ResolveMode,
+    RuleLogLevel, RuleSchedule, UserAcl,
+};
+use crate::session::{Session, SessionProtocol};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Bumped whenever the content of a variant changes
+pub const ACL_TEMPLATE_VERSION: u32 = 3;
+
+/// Comment lines are wrapped at this width.
+const COMMENT_WIDTH: usize = 88;
+
+/// Which example to generate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AclExampleVariant {
+    /// Default-block policy, one group and two rules
+    #[default]
+    Minimal,
+    /// Every setting, each with a comment
+    KitchenSink,
+    /// Allow rules for the traffic the server has seen
+    FromLearning,
+}
+
+impl AclExampleVariant {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AclExampleVariant::Minimal => "minimal",
+            AclExampleVariant::KitchenSink => "kitchen-sink",
+            AclExampleVariant::FromLearning => "from-learning",
+        }
+    }
+
+    fn summary(&self) -> &'static str {
+        match self {
+            AclExampleVariant::Minimal => {
+                "A safe starting point: everything is blocked unless a rule allows it. Add \
+                 users under [[users]] and share rules between them through [[groups]]."
+            }
+            AclExampleVariant::KitchenSink => {
+                "Every ACL setting, each described where it is first used. Meant as a \
+                 reference to copy from, not to be deployed as is."
+            }
+            AclExampleVariant::FromLearning => {
+                "One allow rule per user and destination seen in the traffic this server \
+                 allowed. Review the rules before loading them: anything not listed is \
+                 blocked."
+            }
+        }
+    }
+}
+
+impl fmt::Display for AclExampleVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AclExampleVariant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "minimal" => Ok(AclExampleVariant::Minimal),
+            "kitchen-sink" => Ok(AclExampleVariant::KitchenSink),
+            "from-learning" => Ok(AclExampleVariant::FromLearning),
+            other => Err(format!(
+                "Invalid ACL example variant '{}' (use: minimal, kitchen-sink, from-learning)",
+                other
+            )),
+        }
+    }
+}
+
+/// One connection the server allowed, as input for the from-learning variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedFlow {
+    pub user: String,
+    /// Requested hostname when known, the dialled address otherwise
+    pub destination: String,
+    pub port: u16,
+    pub protocol: Protocol,
+}
+
+impl ObservedFlow {
+    /// `None` for sessions the ACL did not allow.
+    pub fn from_session(session: &Session) -> Option<Self> {
+        if &*session.acl_decision != "allow" {
+            return None;
+        }
+        Some(Self {
+            user: session.user.to_string(),
+            destination: session.logical_destination().to_string(),
+            port: session.dest_port,
+            protocol: match session.protocol {
+                SessionProtocol::Tcp => Protocol::Tcp,
+                SessionProtocol::Udp => Protocol::Udp,
+            },
+        })
+    }
+}
+
+/// Setting descriptions; rule fields are shared by `users.rules` and `groups.rules`.
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    ("global", "Settings that apply to every user"),
+    (
+        "global.default_policy",
+        "Decision when no rule matches: \"block\" (allow-list, recommended) or \"allow\"",
+    ),
+    (
+        "users",
+        "Per-user rules, matched against the authenticated username (the anonymous user \
+         when authentication is off)",
+    ),
+    (
+        "users.username",
+        "Username as the proxy sees it after authentication",
+    ),
+    (
+        "users.groups",
+        "Groups whose rules this user inherits; each one must be defined under [[groups]]",
+    ),
+    (
+        "users.rules",
+        "Rules of this user, evaluated together with the rules of its groups: block rules \
+         before allow rules, each by descending priority. The first match decides.",
+    ),
+    (
+        "groups",
+        "Named rule sets shared by every user that lists the group",
+    ),
+    ("groups.name", "Name referenced from users.groups"),
+    (
+        "groups.parents",
+        "Groups whose rules this group inherits, and theirs in turn; cycles are rejected. \
+         At equal action and priority the group's own rules are evaluated first.",
+    ),
+    (
+        "groups.rules",
+        "Rules inherited by every member of the group",
+    ),
+    ("rules.action", "\"allow\" or \"block\""),
+    (
+        "rules.description",
+        "Free text shown in the access log, the dashboard and decision traces",
+    ),
+    (
+        "rules.destinations",
+        "IP addresses, CIDR ranges, domains and wildcard domains such as \"*.example.com\"; \
+         [\"*\"] matches everything and an empty list matches nothing",
+    ),
+    (
+        "rules.ports",
+        "Single ports, ranges (\"8000-9000\"), comma lists (\"80,443\") or \"*\"; an empty \
+         list matches nothing",
+    ),
+    (
+        "rules.sources",
+        "Client IP addresses and CIDR ranges the rule applies to; unset matches every \
+         client",
+    ),
+    (
+        "rules.protocols",
+        "\"tcp\", \"udp\" or \"both\" (\"*\" is accepted for \"both\"); an empty list matches \
+         nothing",
+    ),
+    (
+        "rules.priority",
+        "Order among rules with the same action, highest first (default 100)",
+    ),
+    (
+        "rules.log",
+        "Access-log verbosity for connections this rule allows: \"default\", \"silent\", \
+         \"minimal\" or \"verbose\". Blocked connections are always logged in full.",
+    ),
+    (
+        "rules.reply_code",
+        "SOCKS5 reply a block rule answers with: \"connection_not_allowed\" (default), \
+         \"general_failure\", \"network_unreachable\", \"host_unreachable\", \
+         \"connection_refused\" or \"ttl_expired\"",
+    ),
+    (
+        "rules.resolve",
+        "Where the domains of connections this allow rule matches are resolved: \"local\" \
+         (default) or \"remote\", handed unresolved to the parent proxy (server.upstream)",
+    ),
+    (
+        "rules.schedule",
+        "When the rule applies; outside its days and times the rule is skipped. Unset = \
+         always",
+    ),
+    (
+        "rules.schedule.days",
+        "Weekdays (\"mon\") or ranges (\"mon-fri\"); unset = every day",
+    ),
+    (
+        "rules.schedule.times",
+        "\"HH:MM-HH:MM\" ranges, end exclusive; one ending before it starts runs past \
+         midnight. Unset = all day",
+    ),
+    (
+        "rules.schedule.timezone",
+        "IANA time zone the days and times are read in; unset = the server's local time",
+    ),
+];
+
+/// Build and render the annotated example for `variant`.
+///
+/// `observed` is only used by [`AclExampleVariant::FromLearning`].
+pub fn generate_acl_example(
+    variant: AclExampleVariant,
+    observed: &[ObservedFlow],
+) -> Result<String, String> {
+    let config = example_config(variant, observed)?;
+    render(variant, &config)
+}
+
+/// The config a variant renders.
+pub fn example_config(
+    variant: AclExampleVariant,
+    observed: &[ObservedFlow],
+) -> Result<AclConfig, String> {
+    let config = match variant {
+        AclExampleVariant::Minimal => minimal(),
+        AclExampleVariant::KitchenSink => kitchen_sink(),
+        AclExampleVariant::FromLearning => learned(observed)?,
+    };
+    config.validate()?;
+    Ok(config)
+}
+
+fn rule(
+    action: Action,
+    description: &str,
+    destinations: &[&str],
+    ports: &[&str],
+    protocols: Vec<Protocol>,
+    priority: u32,
+) -> AclRule {
+    AclRule {
+        action,
+        description: description.to_string(),
+        destinations: destinations.iter().map(|d| d.to_string()).collect(),
+        ports: ports.iter().map(|p| p.to_string()).collect(),
+        sources: vec![],
+        protocols,
+        priority,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
+    }
+}
+
+fn minimal() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![UserAcl {
+            username: "alice".to_string(),
+            groups: vec!["staff".to_string()],
+            rules: Vec::new(),
+        }],
+        groups: vec![GroupAcl {
+            name: "staff".to_string(),
+            parents: vec![],
+            rules: vec![
+                rule(
+                    Action::Block,
+                    "No SSH through the proxy",
+                    &["*"],
+                    &["22"],
+                    vec![Protocol::Tcp],
+                    1000,
+                ),
+                rule(
+                    Action::Allow,
+                    "Web browsing",
+                    &["*"],
+                    &["80", "443"],
+                    vec![Protocol::Tcp],
+                    100,
+                ),
+            ],
+        }],
+    }
+}
+
+fn kitchen_sink() -> AclConfig {
+    let mut admin_panel = rule(
+        Action::Block,
+        "Block access to admin panel",
+        &["admin.company.com", "192.168.100.10"],
+        &["*"],
+        vec![Protocol::Both],
+        1000,
+    );
+    admin_panel.log = RuleLogLevel::Verbose;
+
+    let mut dns = rule(
+        Action::Allow,
+        "Internal DNS resolvers",
+        &["10.0.0.53", "2001:db8::53"],
+        &["53"],
+        vec![Protocol::Udp, Protocol::Tcp],
+        100,
+    );
+    dns.log = RuleLogLevel::Silent;
+
+    let mut dev = rule(
+        Action::Allow,
+        "Access to dev environments",
+        &[
+            "*.dev.company.com",
+            "api.*.company.com",
+            "10.1.0.0/16",
+            "2001:db8:1::/48",
+        ],
+        &["*"],
+        vec![Protocol::Both],
+        50,
+    );
+    dev.log = RuleLogLevel::Minimal;
+    // Only the parent proxy's DNS knows the dev zones
+    dev.resolve = ResolveMode::Remote;
+
+    let mut production = rule(
+        Action::Allow,
+        "Allow access to production servers from the office",
+        &["prod-*.company.com", "192.168.100.0/24"],
+        &["80,443,5432"],
+        vec![Protocol::Tcp],
+        200,
+    );
+    production.sources = vec!["10.20.0.0/16".to_string(), "2001:db8:20::/48".to_string()];
+
+    let mut ssh = rule(
+        Action::Allow,
+        "SSH access to all destinations during office hours",
+        &["*"],
+        &["22"],
+        vec![Protocol::Tcp],
+        50,
+    );
+    ssh.schedule = Some(RuleSchedule {
+        days: vec!["mon-fri".to_string()],
+        times: vec!["08:00-18:00".to_string()],
+        timezone: Some("Europe/Warsaw".to_string()),
+    });
+
+    let mut db_master = rule(
+        Action::Block,
+        "Block write operations",
+        &["db-master.company.com"],
+        &["*"],
+        vec![Protocol::Both],
+        1000,
+    );
+    // Looks like a dead host to clients instead of a policy denial
+    db_master.reply_code = Some(BlockReplyCode::HostUnreachable);
+
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![
+            UserAcl {
+                username: "alice".to_string(),
+                groups: vec!["developers".to_string(), "ssh-users".to_string()],
+                rules: vec![
+                    admin_panel,
+                    rule(
+                        Action::Allow,
+                        "Allow HTTPS to company network",
+                        &["10.0.0.0/8"],
+                        &["443", "8000-9000"],
+                        vec![Protocol::Tcp],
+                        100,
+                    ),
+                    production,
+                ],
+            },
+            UserAcl {
+                username: "bob".to_string(),
+                groups: vec!["readonly".to_string()],
+                rules: vec![
+                    rule(
+                        Action::Allow,
+                        "Read-only database access",
+                        &["db-replica.company.com"],
+                        &["5432"],
+                        vec![Protocol::Tcp],
+                        100,
+                    ),
+                    db_master,
+                ],
+            },
+        ],
+        groups: vec![
+            GroupAcl {
+                name: "developers".to_string(),
+                parents: vec!["ssh-users".to_string()],
+                rules: vec![dev, dns],
+            },
+            GroupAcl {
+                name: "ssh-users".to_string(),
+                parents: vec![],
+                rules: vec![ssh],
+            },
+            GroupAcl {
+                name: "readonly".to_string(),
+                parents: vec![],
+                rules: vec![rule(
+                    Action::Block,
+                    "Block SSH access to all destinations",
+                    &["*"],
+                    &["22"],
+                    vec![Protocol::Tcp],
+                    1000,
+                )],
+            },
+        ],
+    }
+}
+
+fn learned(observed: &[ObservedFlow]) -> Result<AclConfig, String> {
+    if observed.is_empty() {
+        return Err("No allowed traffic has been observed yet".to_string());
+    }
+
+    #[derive(Default)]
+    struct Seen {
+        ports: BTreeSet<u16>,
+        tcp: bool,
+        udp: bool,
+        connections: usize,
+    }
+
+    let mut by_user: BTreeMap<&str, BTreeMap<&str, Seen>> = BTreeMap::new();
+    for flow in observed {
+        let seen = by_user
+            .entry(flow.user.as_str())
+            .or_default()
+            .entry(flow.destination.as_str())
+            .or_default();
+        seen.ports.insert(flow.port);
+        match flow.protocol {
+            Protocol::Tcp => seen.tcp = true,
+            Protocol::Udp => seen.udp = true,
+            Protocol::Both => {
+                seen.tcp = true;
+                seen.udp = true;
+            }
+        }
+        seen.connections += 1;
+    }
+
+    let users = by_user
+        .into_iter()
+        .map(|(user, destinations)| UserAcl {
+            username: user.to_string(),
+            groups: Vec::new(),
+            rules: destinations
+                .into_iter()
+                .map(|(destination, seen)| AclRule {
+                    action: Action::Allow,
+                    description: format!(
+                        "Learned from {} connection(s) to {}",
+                        seen.connections, destination
+                    ),
+                    destinations: vec![destination.to_string()],
+                    ports: seen.ports.iter().map(|port| port.to_string()).collect(),
+                    sources: vec![],
+                    protocols: vec![match (seen.tcp, seen.udp) {
+                        (true, true) => Protocol::Both,
+                        (false, true) => Protocol::Udp,
+                        _ => Protocol::Tcp,
+                    }],
+                    priority: 100,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users,
+        groups: Vec::new(),
+    })
+}
+
+/// Render `config` and annotate the first use of every setting.
+fn render(variant: AclExampleVariant, config: &AclConfig) -> Result<String, String> {
+    let rendered = toml::to_string(config)
+        .map_err(|e| format!("Failed to render example ACL config: {}", e))?;
+
+    let mut out = preamble(variant);
+    let mut described: HashSet<&'static str> = HashSet::new();
+    let mut section = String::new();
+
+    for line in rendered.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(header) = table_header(line) {
+            section = header.to_string();
+            let pad = indent(&section);
+            out.push('\n');
+            push_description(&mut out, &mut described, &section, pad);
+            out.push_str(pad);
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+
+        let pad = indent(&section);
+        let key = line.split('=').next().unwrap_or_default().trim();
+        push_description(&mut out, &mut described, &join(&section, key), pad);
+        out.push_str(pad);
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+fn preamble(variant: AclExampleVariant) -> String {
+    let mut out = String::from("# RustSocks ACL configuration\n#\n");
+    push_comment(
+        &mut out,
+        &format!(
+            "Variant \"{}\" of the example ACL, template v{} (RustSocks v{}). Regenerate \
+             with `rustsocks acl example --variant {}`.",
+            variant,
+            ACL_TEMPLATE_VERSION,
+            env!("CARGO_PKG_VERSION"),
+            variant
+        ),
+        "",
+    );
+    out.push_str("#\n");
+    push_comment(&mut out, variant.summary(), "");
+    out.push_str("#\n");
+    push_comment(
+        &mut out,
+        "Load the file with `acl.enabled = true` and `acl.config_file` pointing at it; \
+         changes are picked up without a restart when `acl.watch` is on.",
+        "",
+    );
+    out
+}
+
+/// `[a.b]` and `[[a.b]]` -> `a.b`
+fn table_header(line: &str) -> Option<&str> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    Some(
+        inner
+            .strip_prefix('[')
+            .and_then(|inner| inner.strip_suffix(']'))
+            .unwrap_or(inner),
+    )
+}
+
+/// Rule tables, and tables inside a rule, are indented under their user or group.
+fn indent(section: &str) -> &'static str {
+    if section.ends_with(".rules") || section.contains(".rules.") {
+        "  "
+    } else {
+        ""
+    }
+}
+
+fn join(section: &str, key: &str) -> String {
+    if section.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", section, key)
+    }
+}
+
+/// Description of a setting path; rule fields are looked up as `rules.<field>`.
+fn describe(path: &str) -> Option<(&'static str, &'static str)> {
+    let lookup = path
+        .strip_prefix("users.")
+        .or_else(|| path.strip_prefix("groups."))
+        .filter(|rest| rest.starts_with("rules."))
+        .unwrap_or(path);
+    DESCRIPTIONS
+        .iter()
+        .find(|(registered, _)| *registered == lookup)
+        .copied()
+}
+
+/// Describe `path` once per file.
+fn push_description(
+    out: &mut String,
+    described: &mut HashSet<&'static str>,
+    path: &str,
+    indent: &str,
+) {
+    if let Some((registered, description)) = describe(path) {
+        if described.insert(registered) {
+            push_comment(out, description, indent);
+        }
+    }
+}
+
+/// Append `text` as `# ` comment lines wrapped at [`COMMENT_WIDTH`].
+fn push_comment(out: &mut String, text: &str, indent: &str) {
+    let mut line = format!("{}#", indent);
+    let empty = line.len();
+    for word in text.split_whitespace() {
+        if line.len() > empty && line.len() + 1 + word.len() > COMMENT_WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line = format!("{}#", indent);
+        }
+        line.push(' ');
+        line.push_str(word);
+    }
+    out.push_str(&line);
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+
+    const VARIANTS: [AclExampleVariant; 3] = [
+        AclExampleVariant::Minimal,
+        AclExampleVariant::KitchenSink,
+        AclExampleVariant::FromLearning,
+    ];
+
+    fn observed() -> Vec<ObservedFlow> {
+        let flow = |user: &str, destination: &str, port, protocol| ObservedFlow {
+            user: user.to_string(),
+            destination: destination.to_string(),
+            port,
+            protocol,
+        };
+        vec![
+            flow("bob", "db.internal", 5432, Protocol::Tcp),
+            flow("alice", "example.com", 443, Protocol::Tcp),
+            flow("alice", "example.com", 80, Protocol::Tcp),
+            flow("alice", "10.0.0.53", 53, Protocol::Udp),
+            flow("alice", "10.0.0.53", 53, Protocol::Tcp),
+            flow("alice", "example.com", 443, Protocol::Tcp),
+        ]
+    }
+
+    /// Serde field names of `T`, taken from the derived `Deserialize` impl.
+    fn serde_fields<T: DeserializeOwned>() -> Vec<&'static str> {
+        struct FieldNames<'a>(&'a mut Vec<&'static str>);
+
+        impl<'de> Deserializer<'de> for FieldNames<'_> {
+            type Error = de::value::Error;
+
+            fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+                Err(de::Error::custom("not a struct"))
+            }
+
+            fn deserialize_struct<V: Visitor<'de>>(
+                self,
+                _name: &'static str,
+                fields: &'static [&'static str],
+                _visitor: V,
+            ) -> Result<V::Value, Self::Error> {
+                self.0.extend_from_slice(fields);
+                Err(de::Error::custom("field names collected"))
+            }
+
+            serde::forward_to_deserialize_any! {
+                bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+                byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+                enum identifier ignored_any
+            }
+        }
+
+        let mut fields = Vec::new();
+        let _ = T::deserialize(FieldNames(&mut fields));
+        assert!(
+            !fields.is_empty(),
+            "{} is not a struct",
+            std::any::type_name::<T>()
+        );
+        fields
+    }
+
+    /// Keys set on any of the tables of an array of tables.
+    fn keys<'a>(tables: impl IntoIterator<Item = &'a toml::Value>) -> BTreeSet<String> {
+        tables
+            .into_iter()
+            .filter_map(toml::Value::as_table)
+            .flat_map(|table| table.keys().cloned())
+            .collect()
+    }
+
+    fn array<'a>(value: &'a toml::Value, key: &str) -> &'a [toml::Value] {
+        value
+            .get(key)
+            .and_then(toml::Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn every_variant_round_trips() {
+        let observed = observed();
+        for variant in VARIANTS {
+            let example = generate_acl_example(variant, &observed).unwrap();
+            let parsed: AclConfig = toml::from_str(&example)
+                .unwrap_or_else(|e| panic!("{} does not parse: {}", variant, e));
+            assert!(parsed.validate().is_ok(), "{} does not validate", variant);
+
+            let built = example_config(variant, &observed).unwrap();
+            assert_eq!(
+                toml::Value::try_from(&parsed).unwrap(),
+                toml::Value::try_from(&built).unwrap(),
+                "{} differs after a round trip",
+                variant
+            );
+        }
+    }
+
+    #[test]
+    fn minimal_is_default_block_with_one_group_and_two_rules() {
+        let config = example_config(AclExampleVariant::Minimal, &[]).unwrap();
+        assert_eq!(config.global.default_policy, Action::Block);
+        assert_eq!(config.groups.len(), 1);
+        let rules: usize = config
+            .users
+            .iter()
+            .map(|user| user.rules.len())
+            .sum::<usize>()
+            + config
+                .groups
+                .iter()
+                .map(|group| group.rules.len())
+                .sum::<usize>();
+        assert_eq!(rules, 2);
+
+        let example = generate_acl_example(AclExampleVariant::Minimal, &[]).unwrap();
+        assert!(example.starts_with("# RustSocks ACL configuration\n"));
+        assert!(example.contains(&format!("template v{}", ACL_TEMPLATE_VERSION)));
+        assert!(example.contains("default_policy = \"block\"\n"));
+        assert!(example.contains("\n  [[groups.rules]]\n"));
+    }
+
+    #[test]
+    fn kitchen_sink_sets_every_serde_field() {
+        let example = generate_acl_example(AclExampleVariant::KitchenSink, &[]).unwrap();
+        let value: toml::Value = toml::from_str(&example).unwrap();
+
+        let users = array(&value, "users");
+        let groups = array(&value, "groups");
+        let rules: Vec<&toml::Value> = users
+            .iter()
+            .chain(groups)
+            .flat_map(|owner| array(owner, "rules"))
+            .collect();
+
+        let checks = [
+            ("AclConfig", keys([&value]), serde_fields::<AclConfig>()),
+            (
+                "GlobalAclConfig",
+                keys(value.get("global")),
+                serde_fields::<GlobalAclConfig>(),
+            ),
+            ("UserAcl", keys(users), serde_fields::<UserAcl>()),
+            ("GroupAcl", keys(groups), serde_fields::<GroupAcl>()),
+            ("AclRule", keys(rules), serde_fields::<AclRule>()),
+        ];
+        for (name, emitted, fields) in checks {
+            let missing: Vec<&str> = fields
+                .into_iter()
+                .filter(|field| !emitted.contains(*field))
+                .collect();
+            assert!(
+                missing.is_empty(),
+                "kitchen-sink never sets {} fields {:?}",
+                name,
+                missing
+            );
+        }
+    }
+
+    #[test]
+    fn every_emitted_setting_is_described() {
+        for variant in VARIANTS {
+            let example = generate_acl_example(variant, &observed()).unwrap();
+            let value: toml::Value = toml::from_str(&example).unwrap();
+
+            let mut paths = Vec::new();
+            for (key, child) in value.as_table().unwrap() {
+                paths.push(key.clone());
+                let tables: Vec<&toml::Value> = match child {
+                    toml::Value::Array(items) => items.iter().collect(),
+                    table => vec![table],
+                };
+                for field in keys(tables.iter().copied()) {
+                    paths.push(join(key, &field));
+                }
+                let rules = tables.iter().flat_map(|table| array(table, "rules"));
+                for field in keys(rules) {
+                    paths.push(format!("{}.rules.{}", key, field));
+                }
+            }
+
+            let undescribed: Vec<&String> = paths
+                .iter()
+                .filter(|path| describe(path).is_none())
+                .collect();
+            assert!(undescribed.is_empty(), "undescribed: {:?}", undescribed);
+        }
+    }
+
+    #[test]
+    fn settings_are_described_on_first_use() {
+        let example = generate_acl_example(AclExampleVariant::KitchenSink, &[]).unwrap();
+        assert!(example.contains("\n[global]\n# Decision when no rule matches"));
+        assert!(example.contains("  # \"allow\" or \"block\"\n  action = \"block\"\n"));
+        assert_eq!(example.matches("# \"allow\" or \"block\"").count(), 1);
+        assert!(example.contains("  log = \"verbose\"\n"));
+    }
+
+    #[test]
+    fn learned_rules_group_ports_and_protocols_per_destination() {
+        let config = example_config(AclExampleVariant::FromLearning, &observed()).unwrap();
+        assert_eq!(config.global.default_policy, Action::Block);
+        assert_eq!(config.users.len(), 2);
+
+        let alice = &config.users[0];
+        assert_eq!(alice.username, "alice");
+        assert_eq!(alice.rules.len(), 2);
+        assert_eq!(alice.rules[0].destinations, vec!["10.0.0.53"]);
+        assert_eq!(alice.rules[0].ports, vec!["53"]);
+        assert_eq!(alice.rules[0].protocols, vec![Protocol::Both]);
+        assert_eq!(alice.rules[1].destinations, vec!["example.com"]);
+        assert_eq!(alice.rules[1].ports, vec!["80", "443"]);
+        assert_eq!(alice.rules[1].protocols, vec![Protocol::Tcp]);
+        assert!(alice.rules[1].description.contains("3 connection(s)"));
+        assert!(alice.rules.iter().all(|rule| rule.action == Action::Allow));
+
+        assert!(example_config(AclExampleVariant::FromLearning, &[]).is_err());
+    }
+
+    #[test]
+    fn variant_names_parse() {
+        for variant in VARIANTS {
+            assert_eq!(variant.as_str().parse::<AclExampleVariant>(), Ok(variant));
+        }
+        assert_eq!(AclExampleVariant::default(), AclExampleVariant::Minimal);
+        assert!("everything".parse::<AclExampleVariant>().is_err());
+    }
+}
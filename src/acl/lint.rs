@@ -0,0 +1,830 @@
+//! Static checks over an ACL file
+//!
+//! Every check is a pure function of the [`AclConfig`]; nothing here looks at the
+//! compiled engine or at live traffic. Findings are reported by the loader log, by
+//! `rustsocks --check` and by `GET /api/acl/lint`. Only unknown group references under
+//! `acl.strict_references` are errors; everything else is advisory.
+
+use super::domain_list::domain_list_path;
+use super::types::{AclConfig, AclRule, PortMatcher, Protocol};
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use tracing::{error, warn};
+
+/// What a lint finding is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintKind {
+    /// A user lists a group that is not defined
+    UnknownGroup,
+    /// A group no user lists, no group inherits from and no system group pattern matches
+    OrphanGroup,
+    /// Two rules of one owner with the same action and matchers
+    DuplicateRule,
+    /// A rule that an earlier rule of the same owner and action always matches first
+    ShadowedRule,
+    /// A group without rules and parents, or a user without rules and groups
+    EmptyRules,
+    /// A rule on domain names while `server.dns.allow_domain_requests = false`
+    DeadDomainRule,
+}
+
+impl LintKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintKind::UnknownGroup => "unknown_group",
+            LintKind::OrphanGroup => "orphan_group",
+            LintKind::DuplicateRule => "duplicate_rule",
+            LintKind::ShadowedRule => "shadowed_rule",
+            LintKind::EmptyRules => "empty_rules",
+            LintKind::DeadDomainRule => "dead_domain_rule",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    Warning,
+    Error,
+}
+
+impl LintSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintSeverity::Warning => "warning",
+            LintSeverity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LintFinding {
+    pub kind: LintKind,
+    pub severity: LintSeverity,
+    /// `user:<name>` or `group:<name>`
+    pub scope: String,
+    /// Position of the rule in its owner's list, for rule findings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_index: Option<usize>,
+    pub message: String,
+}
+
+/// Settings the checks depend on (`acl.strict_references`, `acl.system_group_patterns`,
+/// `server.dns.allow_domain_requests`)
+#[derive(Debug, Clone, Default)]
+pub struct LintSettings {
+    /// Unknown group references are errors instead of warnings
+    pub strict_references: bool,
+    /// Group names (`*` wildcards, case-insensitive) resolved from the system group
+    /// database at connect time; matching ACL groups are not orphans
+    pub system_group_patterns: Vec<String>,
+    /// Clients must send IP addresses, so rules on domain names never match
+    pub domain_requests_rejected: bool,
+}
+
+impl From<&Config> for LintSettings {
+    fn from(config: &Config) -> Self {
+        Self {
+            strict_references: config.acl.strict_references,
+            system_group_patterns: config.acl.system_group_patterns.clone(),
+            domain_requests_rejected: !config.server.dns.allow_domain_requests,
+        }
+    }
+}
+
+/// Run every check, in the order of the ACL file
+pub fn lint(config: &AclConfig, settings: &LintSettings) -> Vec<LintFinding> {
+    let mut findings = unknown_group_references(config, settings.strict_references);
+    findings.extend(orphan_groups(config, &settings.system_group_patterns));
+    findings.extend(empty_rule_lists(config));
+    findings.extend(redundant_rules(config));
+    if settings.domain_requests_rejected {
+        findings.extend(dead_domain_rules(config));
+    }
+    findings
+}
+
+/// Fail with every error finding, if there is one
+pub fn reject_errors(findings: &[LintFinding]) -> Result<(), String> {
+    let errors: Vec<&str> = findings
+        .iter()
+        .filter(|f| f.severity == LintSeverity::Error)
+        .map(|f| f.message.as_str())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+pub fn log_findings(findings: &[LintFinding]) {
+    for finding in findings {
+        match finding.severity {
+            LintSeverity::Error => error!(
+                kind = finding.kind.as_str(),
+                scope = %finding.scope,
+                "ACL lint: {}",
+                finding.message
+            ),
+            LintSeverity::Warning => warn!(
+                kind = finding.kind.as_str(),
+                scope = %finding.scope,
+                "ACL lint: {}",
+                finding.message
+            ),
+        }
+    }
+}
+
+/// Users listing a group the file does not define
+pub fn unknown_group_references(config: &AclConfig, strict: bool) -> Vec<LintFinding> {
+    let severity = if strict {
+        LintSeverity::Error
+    } else {
+        LintSeverity::Warning
+    };
+
+    let mut findings = Vec::new();
+    for user in &config.users {
+        for group_name in &user.groups {
+            if config.groups.iter().any(|g| &g.name == group_name) {
+                continue;
+            }
+            let hint = config
+                .groups
+                .iter()
+                .find(|g| g.name.eq_ignore_ascii_case(group_name))
+                .map(|g| format!(" (did you mean '{}'?)", g.name))
+                .unwrap_or_default();
+            findings.push(LintFinding {
+                kind: LintKind::UnknownGroup,
+                severity,
+                scope: format!("user:{}", user.username),
+                rule_index: None,
+                message: format!(
+                    "User '{}' references non-existent group '{}'{}; the reference grants nothing",
+                    user.username, group_name, hint
+                ),
+            });
+        }
+    }
+    findings
+}
+
+/// Groups no user lists, no group inherits from and no system group pattern matches
+pub fn orphan_groups(config: &AclConfig, system_group_patterns: &[String]) -> Vec<LintFinding> {
+    let referenced: HashSet<&str> = config
+        .users
+        .iter()
+        .flat_map(|u| u.groups.iter().map(String::as_str))
+        .collect();
+    let inherited: HashSet<String> = config
+        .groups
+        .iter()
+        .flat_map(|g| g.parents.iter().map(|parent| parent.to_ascii_lowercase()))
+        .collect();
+
+    config
+        .groups
+        .iter()
+        .filter(|g| !referenced.contains(g.name.as_str()))
+        .filter(|g| !inherited.contains(&g.name.to_ascii_lowercase()))
+        .filter(|g| {
+            !system_group_patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern, &g.name))
+        })
+        .map(|g| LintFinding {
+            kind: LintKind::OrphanGroup,
+            severity: LintSeverity::Warning,
+            scope: format!("group:{}", g.name),
+            rule_index: None,
+            message: format!(
+                "Group '{}' is not listed by any user and matches no system group pattern; \
+                 its {} rule(s) never apply",
+                g.name,
+                g.rules.len()
+            ),
+        })
+        .collect()
+}
+
+/// Groups with neither rules nor parents, and users with neither rules nor groups
+pub fn empty_rule_lists(config: &AclConfig) -> Vec<LintFinding> {
+    let users = config
+        .users
+        .iter()
+        .filter(|u| u.rules.is_empty() && u.groups.is_empty())
+        .map(|u| LintFinding {
+            kind: LintKind::EmptyRules,
+            severity: LintSeverity::Warning,
+            scope: format!("user:{}", u.username),
+            rule_index: None,
+            message: format!(
+                "User '{}' has no rules and no groups; the default policy decides every connection",
+                u.username
+            ),
+        });
+    let groups = config
+        .groups
+        .iter()
+        .filter(|g| g.rules.is_empty() && g.parents.is_empty())
+        .map(|g| LintFinding {
+            kind: LintKind::EmptyRules,
+            severity: LintSeverity::Warning,
+            scope: format!("group:{}", g.name),
+            rule_index: None,
+            message: format!("Group '{}' has no rules", g.name),
+        });
+    users.chain(groups).collect()
+}
+
+/// Duplicate and shadowed rules within each user and group
+pub fn redundant_rules(config: &AclConfig) -> Vec<LintFinding> {
+    let owners = config
+        .users
+        .iter()
+        .map(|u| (format!("user:{}", u.username), &u.rules))
+        .chain(
+            config
+                .groups
+                .iter()
+                .map(|g| (format!("group:{}", g.name), &g.rules)),
+        );
+
+    let mut findings = Vec::new();
+    for (scope, rules) in owners {
+        for (later, rule) in rules.iter().enumerate() {
+            // Without destinations or ports a rule matches nothing to begin with
+            if rule.destinations.is_empty() || rule.ports.is_empty() {
+                continue;
+            }
+            let earlier = rules
+                .iter()
+                .enumerate()
+                .filter(|(i, other)| *i != later && evaluated_before(other, *i, rule, later));
+            for (index, other) in earlier {
+                let finding = if same_matchers(other, rule) {
+                    (LintKind::DuplicateRule, "duplicates")
+                } else if covers(other, rule) {
+                    (LintKind::ShadowedRule, "is shadowed by")
+                } else {
+                    continue;
+                };
+                findings.push(LintFinding {
+                    kind: finding.0,
+                    severity: LintSeverity::Warning,
+                    scope: scope.clone(),
+                    rule_index: Some(later),
+                    message: format!(
+                        "{} rule #{} '{}' {} rule #{} '{}' and never decides a connection",
+                        scope,
+                        later + 1,
+                        rule.description,
+                        finding.1,
+                        index + 1,
+                        other.description
+                    ),
+                });
+                break;
+            }
+        }
+    }
+    findings
+}
+
+/// Rules with a domain destination (a name, `*.domain` or a `file:` list), which
+/// `server.dns.allow_domain_requests = false` leaves without connections to match
+pub fn dead_domain_rules(config: &AclConfig) -> Vec<LintFinding> {
+    let owners = config
+        .users
+        .iter()
+        .map(|u| (format!("user:{}", u.username), &u.rules))
+        .chain(
+            config
+                .groups
+                .iter()
+                .map(|g| (format!("group:{}", g.name), &g.rules)),
+        );
+
+    let mut findings = Vec::new();
+    for (scope, rules) in owners {
+        for (index, rule) in rules.iter().enumerate() {
+            let Some(domain) = rule.destinations.iter().find(|d| is_domain_pattern(d)) else {
+                continue;
+            };
+            findings.push(LintFinding {
+                kind: LintKind::DeadDomainRule,
+                severity: LintSeverity::Warning,
+                scope: scope.clone(),
+                rule_index: Some(index),
+                message: format!(
+                    "{} rule #{} '{}' matches domain '{}', but server.dns.allow_domain_requests \
+                     = false rejects every request for a name",
+                    scope,
+                    index + 1,
+                    rule.description,
+                    domain
+                ),
+            });
+        }
+    }
+    findings
+}
+
+fn is_domain_pattern(destination: &str) -> bool {
+    let destination = destination.trim();
+    destination != "*"
+        && destination.parse::<IpAddr>().is_err()
+        && destination.parse::<ipnet::IpNet>().is_err()
+}
+
+/// Same action, and `a` comes first in the engine's order (priority, then file order)
+fn evaluated_before(a: &AclRule, a_index: usize, b: &AclRule, b_index: usize) -> bool {
+    a.action == b.action
+        && (a.priority > b.priority || (a.priority == b.priority && a_index < b_index))
+}
+
+fn same_matchers(a: &AclRule, b: &AclRule) -> bool {
+    fn normalized(values: &[String]) -> Vec<String> {
+        let mut values: Vec<String> = values.iter().map(|v| v.trim().to_lowercase()).collect();
+        values.sort();
+        values.dedup();
+        values
+    }
+
+    a.action == b.action
+        && normalized(&a.destinations) == normalized(&b.destinations)
+        && normalized(&a.ports) == normalized(&b.ports)
+        && normalized(&a.sources) == normalized(&b.sources)
+        && protocols_cover(&a.protocols, &b.protocols)
+        && protocols_cover(&b.protocols, &a.protocols)
+        && a.schedule == b.schedule
+}
+
+/// Every connection `b` matches is also matched by `a`.
+///
+/// Conservative: a matcher that cannot be parsed, or coverage that needs several of
+/// `a`'s matchers together, counts as not covered.
+fn covers(a: &AclRule, b: &AclRule) -> bool {
+    protocols_cover(&a.protocols, &b.protocols)
+        && b.destinations
+            .iter()
+            .all(|d| a.destinations.iter().any(|c| destination_covers(c, d)))
+        && b.ports
+            .iter()
+            .all(|p| a.ports.iter().any(|c| port_covers(c, p)))
+        && sources_cover(&a.sources, &b.sources)
+        // A rule that is not always in effect only covers rules with its schedule
+        && (a.schedule.is_none() || a.schedule == b.schedule)
+}
+
+/// No sources means any client; sources are IPs and CIDRs only
+fn sources_cover(a: &[String], b: &[String]) -> bool {
+    if a.is_empty() {
+        return true;
+    }
+    !b.is_empty() && b.iter().all(|s| a.iter().any(|c| destination_covers(c, s)))
+}
+
+fn protocols_cover(a: &[Protocol], b: &[Protocol]) -> bool {
+    let has = |list: &[Protocol], p: Protocol| list.iter().any(|x| *x == p || *x == Protocol::Both);
+    b.iter().all(|p| match p {
+        Protocol::Both => has(a, Protocol::Tcp) && has(a, Protocol::Udp),
+        other => has(a, other.clone()),
+    })
+}
+
+fn destination_covers(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim(), b.trim());
+    if a == "*" {
+        return true;
+    }
+    if b == "*" {
+        return false;
+    }
+    // The contents of a domain list are not known here; only the same file covers it
+    if domain_list_path(a).is_some() || domain_list_path(b).is_some() {
+        return a == b;
+    }
+
+    let a_net = a
+        .parse::<ipnet::IpNet>()
+        .ok()
+        .or_else(|| a.parse::<IpAddr>().ok().map(ipnet::IpNet::from));
+    let b_net = b
+        .parse::<ipnet::IpNet>()
+        .ok()
+        .or_else(|| b.parse::<IpAddr>().ok().map(ipnet::IpNet::from));
+    match (a_net, b_net) {
+        (Some(a), Some(b)) => return a.contains(&b),
+        (None, None) => {}
+        _ => return false,
+    }
+
+    // Domains: `*` stands for exactly one label, as in the matcher
+    let a_labels: Vec<&str> = a.split('.').collect();
+    let b_labels: Vec<&str> = b.split('.').collect();
+    a_labels.len() == b_labels.len()
+        && a_labels
+            .iter()
+            .zip(&b_labels)
+            .all(|(x, y)| *x == "*" || x.eq_ignore_ascii_case(y))
+}
+
+fn port_covers(a: &str, b: &str) -> bool {
+    let (Ok(a), Ok(b)) = (
+        PortMatcher::from_str(a.trim()),
+        PortMatcher::from_str(b.trim()),
+    ) else {
+        return false;
+    };
+    let a = port_ranges(&a);
+    port_ranges(&b)
+        .iter()
+        .all(|(start, end)| a.iter().any(|(s, e)| s <= start && end <= e))
+}
+
+fn port_ranges(matcher: &PortMatcher) -> Vec<(u16, u16)> {
+    match matcher {
+        PortMatcher::Any => vec![(0, u16::MAX)],
+        PortMatcher::Single(p) => vec![(*p, *p)],
+        PortMatcher::Range { start, end } => vec![(*start, *end)],
+        PortMatcher::Multiple(ports) => ports.iter().map(|p| (*p, *p)).collect(),
+    }
+}
+
+/// Case-insensitive match with `*` standing for any run of characters
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || name.len() < first.len() + last.len() || !name.ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::types::{Action, GlobalAclConfig, GroupAcl, RuleLogLevel, UserAcl};
+
+    fn rule(action: Action, description: &str, destination: &str, port: &str) -> AclRule {
+        AclRule {
+            action,
+            description: description.to_string(),
+            destinations: vec![destination.to_string()],
+            ports: vec![port.to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Tcp],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
+        }
+    }
+
+    fn config(users: Vec<UserAcl>, groups: Vec<GroupAcl>) -> AclConfig {
+        AclConfig {
+            global: GlobalAclConfig::default(),
+            users,
+            groups,
+        }
+    }
+
+    fn user(name: &str, groups: &[&str], rules: Vec<AclRule>) -> UserAcl {
+        UserAcl {
+            username: name.to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            rules,
+        }
+    }
+
+    fn group(name: &str, rules: Vec<AclRule>) -> GroupAcl {
+        GroupAcl {
+            name: name.to_string(),
+            parents: vec![],
+            rules,
+        }
+    }
+
+    fn kinds(findings: &[LintFinding]) -> Vec<LintKind> {
+        findings.iter().map(|f| f.kind).collect()
+    }
+
+    #[test]
+    fn clean_config_has_no_findings() {
+        let config = config(
+            vec![user(
+                "alice",
+                &["developers"],
+                vec![rule(Action::Allow, "HTTPS", "0.0.0.0/0", "443")],
+            )],
+            vec![group(
+                "developers",
+                vec![rule(Action::Allow, "Dev", "*.dev.example.com", "*")],
+            )],
+        );
+
+        assert!(lint(&config, &LintSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn unknown_group_suggests_case_match() {
+        let config = config(
+            vec![user("alice", &["Developers", "qa"], vec![])],
+            vec![group(
+                "developers",
+                vec![rule(Action::Allow, "Dev", "*", "*")],
+            )],
+        );
+
+        let findings = unknown_group_references(&config, false);
+        assert_eq!(findings.len(), 2);
+        assert!(findings
+            .iter()
+            .all(|f| f.severity == LintSeverity::Warning && f.scope == "user:alice"));
+        assert!(findings[0].message.contains("(did you mean 'developers'?)"));
+        assert!(!findings[1].message.contains("did you mean"));
+        assert!(reject_errors(&findings).is_ok());
+    }
+
+    #[test]
+    fn strict_references_are_errors() {
+        let config = config(vec![user("alice", &["qa"], vec![])], vec![]);
+        let settings = LintSettings {
+            strict_references: true,
+            ..LintSettings::default()
+        };
+
+        let findings = lint(&config, &settings);
+        assert_eq!(findings[0].kind, LintKind::UnknownGroup);
+        assert_eq!(findings[0].severity, LintSeverity::Error);
+        let err = reject_errors(&findings).unwrap_err();
+        assert!(err.contains("non-existent group 'qa'"));
+    }
+
+    #[test]
+    fn orphan_groups_respect_system_patterns() {
+        let config = config(
+            vec![user("alice", &["developers"], vec![])],
+            vec![
+                group("developers", vec![rule(Action::Allow, "Dev", "*", "*")]),
+                group("contractors", vec![rule(Action::Allow, "Web", "*", "443")]),
+                group("LDAP-Ops", vec![rule(Action::Allow, "Ops", "*", "22")]),
+            ],
+        );
+
+        let findings = orphan_groups(&config, &[]);
+        assert_eq!(
+            findings
+                .iter()
+                .map(|f| f.scope.as_str())
+                .collect::<Vec<_>>(),
+            vec!["group:contractors", "group:LDAP-Ops"]
+        );
+
+        let findings = orphan_groups(&config, &["ldap-*".to_string()]);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].scope, "group:contractors");
+    }
+
+    #[test]
+    fn inherited_groups_are_not_orphans() {
+        let mut backend = group("backend", vec![]);
+        backend.parents = vec!["Engineering".to_string()];
+        let config = config(
+            vec![user("alice", &["backend"], vec![])],
+            vec![
+                backend,
+                group("engineering", vec![rule(Action::Allow, "Git", "*", "22")]),
+            ],
+        );
+
+        assert!(orphan_groups(&config, &[]).is_empty());
+        // A group that only inherits is not empty either
+        assert!(empty_rule_lists(&config).is_empty());
+    }
+
+    #[test]
+    fn empty_rule_lists_are_reported() {
+        let config = config(
+            vec![
+                user("alice", &[], vec![]),
+                user("bob", &["developers"], vec![]),
+            ],
+            vec![group("developers", vec![])],
+        );
+
+        let findings = empty_rule_lists(&config);
+        assert_eq!(kinds(&findings), vec![LintKind::EmptyRules; 2]);
+        assert_eq!(findings[0].scope, "user:alice");
+        assert_eq!(findings[1].scope, "group:developers");
+    }
+
+    #[test]
+    fn duplicate_rule_is_reported_once() {
+        let config = config(
+            vec![user(
+                "alice",
+                &[],
+                vec![
+                    rule(Action::Allow, "HTTPS", "example.com", "443"),
+                    rule(Action::Allow, "HTTPS again", "EXAMPLE.com", "443"),
+                ],
+            )],
+            vec![],
+        );
+
+        let findings = redundant_rules(&config);
+        assert_eq!(kinds(&findings), vec![LintKind::DuplicateRule]);
+        assert_eq!(findings[0].rule_index, Some(1));
+        assert!(findings[0]
+            .message
+            .contains("rule #2 'HTTPS again' duplicates rule #1"));
+    }
+
+    #[test]
+    fn shadowed_rules_are_reported() {
+        let config = config(
+            vec![],
+            vec![group(
+                "developers",
+                vec![
+                    rule(Action::Allow, "Any dev host", "*.dev.example.com", "*"),
+                    rule(Action::Allow, "Api", "api.dev.example.com", "443"),
+                    rule(Action::Block, "Internal", "10.0.0.0/8", "1-1024"),
+                    rule(Action::Block, "Subnet", "10.1.0.0/16", "22,80"),
+                    rule(Action::Block, "Host", "10.2.3.4", "8080"),
+                ],
+            )],
+        );
+
+        let findings = redundant_rules(&config);
+        assert_eq!(
+            findings.iter().map(|f| f.rule_index).collect::<Vec<_>>(),
+            vec![Some(1), Some(3)]
+        );
+        assert!(findings.iter().all(|f| f.kind == LintKind::ShadowedRule));
+    }
+
+    #[test]
+    fn scheduled_rules_only_shadow_rules_with_the_same_schedule() {
+        let mut office_hours = rule(Action::Allow, "Office hours", "*.dev.example.com", "*");
+        office_hours.schedule = Some(crate::acl::types::RuleSchedule {
+            days: vec!["mon-fri".to_string()],
+            times: vec!["08:00-18:00".to_string()],
+            timezone: None,
+        });
+        let mut api_office_hours = rule(Action::Allow, "Api", "api.dev.example.com", "443");
+        api_office_hours.schedule = office_hours.schedule.clone();
+        let config = config(
+            vec![],
+            vec![group(
+                "contractors",
+                vec![
+                    office_hours,
+                    rule(Action::Allow, "Api always", "api.dev.example.com", "443"),
+                    api_office_hours,
+                ],
+            )],
+        );
+
+        let findings = redundant_rules(&config);
+        assert_eq!(
+            findings.iter().map(|f| f.rule_index).collect::<Vec<_>>(),
+            vec![Some(2)]
+        );
+    }
+
+    #[test]
+    fn higher_priority_and_other_actions_are_not_shadowed() {
+        let mut first = rule(Action::Allow, "Any", "*", "*");
+        first.priority = 10;
+        let config = config(
+            vec![user(
+                "alice",
+                &[],
+                vec![
+                    first,
+                    rule(Action::Allow, "HTTPS", "example.com", "443"),
+                    rule(Action::Block, "Blocked", "example.com", "443"),
+                    rule(Action::Allow, "Nothing", "example.com", "443"),
+                ],
+            )],
+            vec![],
+        );
+
+        // Rule #2 outranks the wildcard; only #4 repeats it
+        let findings = redundant_rules(&config);
+        assert_eq!(kinds(&findings), vec![LintKind::DuplicateRule]);
+        assert_eq!(findings[0].rule_index, Some(3));
+    }
+
+    #[test]
+    fn udp_rule_is_not_shadowed_by_tcp_rule() {
+        let mut udp = rule(Action::Allow, "DNS", "10.0.0.53", "53");
+        udp.protocols = vec![Protocol::Udp];
+        let config = config(
+            vec![user(
+                "alice",
+                &[],
+                vec![rule(Action::Allow, "Internal", "10.0.0.0/8", "*"), udp],
+            )],
+            vec![],
+        );
+
+        assert!(redundant_rules(&config).is_empty());
+    }
+
+    #[test]
+    fn source_restricted_rule_shadows_only_narrower_sources() {
+        let mut office = rule(Action::Allow, "Office", "*", "*");
+        office.sources = vec!["10.1.0.0/16".to_string()];
+        let mut desk = rule(Action::Allow, "Desk", "example.com", "443");
+        desk.sources = vec!["10.1.2.3".to_string()];
+        let config = config(
+            vec![user(
+                "alice",
+                &[],
+                vec![
+                    office,
+                    desk,
+                    rule(Action::Allow, "Anywhere", "example.com", "443"),
+                ],
+            )],
+            vec![],
+        );
+
+        // A rule limited to some clients never covers one that applies to every client
+        let findings = redundant_rules(&config);
+        assert_eq!(kinds(&findings), vec![LintKind::ShadowedRule]);
+        assert_eq!(findings[0].rule_index, Some(1));
+    }
+
+    #[test]
+    fn domain_rules_are_dead_without_domain_requests() {
+        let config = config(
+            vec![user(
+                "alice",
+                &[],
+                vec![
+                    rule(Action::Allow, "Internal", "10.0.0.0/8", "*"),
+                    rule(Action::Allow, "Any", "*", "443"),
+                    rule(Action::Block, "Ads", "*.ads.example.com", "*"),
+                ],
+            )],
+            vec![group(
+                "developers",
+                vec![rule(Action::Allow, "Git", "git.example.com", "22")],
+            )],
+        );
+        let settings = LintSettings {
+            domain_requests_rejected: true,
+            ..LintSettings::default()
+        };
+
+        assert!(
+            !kinds(&lint(&config, &LintSettings::default())).contains(&LintKind::DeadDomainRule)
+        );
+        let findings: Vec<LintFinding> = lint(&config, &settings)
+            .into_iter()
+            .filter(|f| f.kind == LintKind::DeadDomainRule)
+            .collect();
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].scope, "user:alice");
+        assert_eq!(findings[0].rule_index, Some(2));
+        assert_eq!(findings[1].scope, "group:developers");
+        assert!(findings.iter().all(|f| f.severity == LintSeverity::Warning));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_matches("ldap-*", "LDAP-ops"));
+        assert!(glob_matches("*-admins", "net-admins"));
+        assert!(glob_matches("a*b*c", "aXXbYYc"));
+        assert!(glob_matches("*", "anything"));
+        assert!(glob_matches("exact", "EXACT"));
+        assert!(!glob_matches("exact", "exactly"));
+        assert!(!glob_matches("ab*ba", "aba"));
+        assert!(!glob_matches("a*b*c", "acb"));
+    }
+}
@@ -1,3 +1,4 @@
+use super::example::{generate_acl_example, AclExampleVariant};
 use super::types::AclConfig;
 use std::path::Path;
 use tracing::info;
@@ -8,11 +9,7 @@ pub async fn load_acl_config<P: AsRef<Path>>(path: P) -> Result<AclConfig, Strin
         .await
         .map_err(|e| format!("Failed to read ACL config file: {}", e))?;
 
-    let config: AclConfig =
-        toml::from_str(&content).map_err(|e| format!("Failed to parse ACL config: {}", e))?;
-
-    // Validate
-    config.validate()?;
+    let config = parse_acl_config(&content)?;
 
     info!(
         users = config.users.len(),
@@ -28,11 +25,7 @@ pub fn load_acl_config_sync<P: AsRef<Path>>(path: P) -> Result<AclConfig, String
     let content = std::fs::read_to_string(path.as_ref())
         .map_err(|e| format!("Failed to read ACL config file: {}", e))?;
 
-    let config: AclConfig =
-        toml::from_str(&content).map_err(|e| format!("Failed to parse ACL config: {}", e))?;
-
-    // Validate
-    config.validate()?;
+    let config = parse_acl_config(&content)?;
 
     info!(
         users = config.users.len(),
@@ -43,114 +36,22 @@ pub fn load_acl_config_sync<P: AsRef<Path>>(path: P) -> Result<AclConfig, String
     Ok(config)
 }
 
-/// Create example ACL configuration file
-pub fn create_example_acl_config<P: AsRef<Path>>(path: P) -> Result<(), String> {
-    let example = r#"# RustSocks ACL Configuration
-
-[global]
-default_policy = "block"  # Options: "allow", "block"
-
-# Per-user ACL rules
-[[users]]
-username = "alice"
-groups = ["developers", "ssh-users"]
-
-  # BLOCK rules have highest priority
-  [[users.rules]]
-  action = "block"
-  description = "Block access to admin panel"
-  destinations = ["admin.company.com", "192.168.100.10"]
-  ports = ["*"]  # Match all ports
-  protocols = ["both"]
-  priority = 1000
-
-  [[users.rules]]
-  action = "allow"
-  description = "Allow HTTPS to company network"
-  destinations = ["10.0.0.0/8"]
-  ports = ["443", "8000-9000"]
-  protocols = ["tcp"]
-  priority = 100
-
-  # Example: Allow everything with ["*"]
-  # [[users.rules]]
-  # action = "allow"
-  # description = "Allow all traffic"
-  # destinations = ["*"]  # All IPs and domains
-  # ports = ["*"]  # All ports
-  # protocols = ["*"]  # TCP and UDP (same as ["both"])
-  # priority = 50
-
-  [[users.rules]]
-  action = "allow"
-  description = "Allow access to production servers"
-  destinations = ["prod-*.company.com", "192.168.100.0/24"]
-  ports = ["443", "5432"]
-  protocols = ["tcp"]
-  priority = 100
-
-[[users]]
-username = "bob"
-groups = ["readonly"]
-
-  [[users.rules]]
-  action = "allow"
-  description = "Read-only database access"
-  destinations = ["db-replica.company.com"]
-  ports = ["5432"]
-  protocols = ["tcp"]
-  priority = 100
-
-  [[users.rules]]
-  action = "block"
-  description = "Block write operations"
-  destinations = ["db-master.company.com"]
-  ports = ["*"]  # Match all ports
-  protocols = ["both"]
-  priority = 1000
-
-# Group rules (inherited by all users in group)
-[[groups]]
-name = "developers"
-
-  [[groups.rules]]
-  action = "allow"
-  description = "Access to dev environments"
-  destinations = ["*.dev.company.com", "10.1.0.0/16"]
-  ports = ["*"]  # Match all ports
-  protocols = ["both"]
-  priority = 50
-
-[[groups]]
-name = "ssh-users"
-
-  [[groups.rules]]
-  action = "allow"
-  description = "SSH access to all destinations"
-  # IMPORTANT: Use ["*"] to match all
-  # Examples:
-  #   destinations = ["*"]  - matches all IPs and domains
-  #   destinations = []  - matches NOTHING (rule never applies!)
-  #   ports = ["*"]  - matches all ports
-  #   ports = []  - matches NOTHING
-  #   protocols = ["*"]  - matches TCP and UDP (same as ["both"])
-  #   protocols = []  - matches NOTHING
-  destinations = ["*"]
-  ports = ["22"]
-  protocols = ["tcp"]
-  priority = 50
-
-[[groups]]
-name = "readonly"
-
-  [[groups.rules]]
-  action = "block"
-  description = "Block SSH access to all destinations"
-  destinations = ["*"]  # Match all
-  ports = ["22"]
-  protocols = ["tcp"]
-  priority = 500
-"#;
+/// Parse and validate the contents of an ACL file.
+///
+/// Parse errors carry the line and column, with the offending line quoted.
+pub fn parse_acl_config(content: &str) -> Result<AclConfig, String> {
+    let config: AclConfig =
+        toml::from_str(content).map_err(|e| format!("Failed to parse ACL config: {}", e))?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Write the `variant` example ACL file; see [`super::example`]
+pub fn create_example_acl_config<P: AsRef<Path>>(
+    path: P,
+    variant: AclExampleVariant,
+) -> Result<(), String> {
+    let example = generate_acl_example(variant, &[])?;
 
     std::fs::write(path.as_ref(), example)
         .map_err(|e| format!("Failed to write example ACL config: {}", e))?;
@@ -169,7 +70,7 @@ mod tests {
         let path = temp_file.path();
 
         // Create example
-        create_example_acl_config(path).unwrap();
+        create_example_acl_config(path, AclExampleVariant::KitchenSink).unwrap();
 
         // Load it
         let config = load_acl_config_sync(path).unwrap();
@@ -181,4 +82,10 @@ mod tests {
         assert_eq!(config.users[0].groups.len(), 2);
         assert_eq!(config.users[0].rules.len(), 3);
     }
+
+    #[test]
+    fn parse_errors_point_at_the_line() {
+        let err = parse_acl_config("[global]\ndefault_policy = \"allow\"\n[[users]\n").unwrap_err();
+        assert!(err.contains("line 3"), "{err}");
+    }
 }
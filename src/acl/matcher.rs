@@ -1,7 +1,14 @@
-use super::types::{AclRule, Action, PortMatcher, Protocol};
+use super::domain_list::{domain_list_path, DomainList, DomainLists};
+use super::rule_stats::{rule_id, AclRuleStats, RuleCounter};
+use super::schedule::CompiledSchedule;
+use super::types::{
+    AclRule, Action, BlockReplyCode, PortMatcher, Protocol, ResolveMode, RuleLogLevel,
+};
 use crate::protocol::Address;
+use chrono::{DateTime, Utc};
 use regex::Regex;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
 
 /// Parsed matchers (compiled from strings for efficiency)
 #[derive(Debug, Clone)]
@@ -16,6 +23,8 @@ enum DestinationMatcherType {
     Cidr(ipnet::IpNet),
     Domain(String),
     WildcardDomain(WildcardPattern),
+    /// `file:` destination, see [`super::domain_list`]
+    List(Arc<DomainList>),
 }
 
 #[derive(Debug, Clone)]
@@ -50,6 +59,41 @@ impl CompiledDestinationMatcher {
         })
     }
 
+    /// Compile an ACL rule destination, which may also name a domain list file
+    /// (`file:/path`) that is taken from `lists`
+    pub fn compile_destination(s: &str, lists: &mut DomainLists) -> Result<Self, String> {
+        match domain_list_path(s) {
+            Some(path) => Ok(Self {
+                matcher: DestinationMatcherType::List(lists.get(path)?),
+            }),
+            None => Self::compile(s),
+        }
+    }
+
+    /// Compile a client source matcher: an IP address or CIDR, never a domain
+    pub fn compile_source(s: &str) -> Result<Self, String> {
+        let compiled = Self::compile(s)?;
+        match compiled.matcher {
+            DestinationMatcherType::Ip(_) | DestinationMatcherType::Cidr(_) => Ok(compiled),
+            _ => Err(format!(
+                "Invalid source '{}': expected an IP address or CIDR",
+                s
+            )),
+        }
+    }
+
+    /// Check if a client address matches this matcher
+    ///
+    /// IPv4-mapped IPv6 addresses (dual-stack listeners) match their IPv4 form.
+    #[inline]
+    pub fn matches_ip(&self, ip: IpAddr) -> bool {
+        let addr = match ip.to_canonical() {
+            IpAddr::V4(v4) => Address::IPv4(v4.octets()),
+            IpAddr::V6(v6) => Address::IPv6(v6.octets()),
+        };
+        self.matches(&addr)
+    }
+
     /// Check if address matches this matcher
     #[inline(always)]
     pub fn matches(&self, addr: &Address) -> bool {
@@ -59,6 +103,7 @@ impl CompiledDestinationMatcher {
             DestinationMatcherType::Cidr(cidr) => Self::match_cidr(cidr, addr),
             DestinationMatcherType::Domain(domain) => Self::match_domain(domain, addr),
             DestinationMatcherType::WildcardDomain(pattern) => Self::match_wildcard(pattern, addr),
+            DestinationMatcherType::List(list) => list.matches(addr),
         }
     }
 
@@ -73,29 +118,16 @@ impl CompiledDestinationMatcher {
                 let addr_ip = Ipv6Addr::from(*octets);
                 matcher_ip == &addr_ip
             }
-            (_, Address::Domain(domain)) => {
-                if let Ok(parsed) = domain.parse::<IpAddr>() {
-                    matcher_ip_eq(ip, &parsed)
-                } else {
-                    false
-                }
-            }
+            (_, Address::Domain(_)) => addr
+                .literal_ip()
+                .is_some_and(|parsed| matcher_ip_eq(ip, &parsed)),
             _ => false,
         }
     }
 
     #[inline]
     fn match_cidr(cidr: &ipnet::IpNet, addr: &Address) -> bool {
-        let ip = match addr {
-            Address::IPv4(octets) => IpAddr::V4(Ipv4Addr::from(*octets)),
-            Address::IPv6(octets) => IpAddr::V6(Ipv6Addr::from(*octets)),
-            Address::Domain(domain) => match domain.parse::<IpAddr>() {
-                Ok(parsed) => parsed,
-                Err(_) => return false,
-            },
-        };
-
-        cidr.contains(&ip)
+        addr.literal_ip().is_some_and(|ip| cidr.contains(&ip))
     }
 
     #[inline]
@@ -196,6 +228,25 @@ impl CompiledPortMatcher {
     }
 }
 
+/// Per-component outcome of matching one rule, see [`CompiledAclRule::explain`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleMatch {
+    pub protocol: bool,
+    pub destination: bool,
+    pub port: bool,
+    /// Always true for rules without `sources`
+    pub source: bool,
+    /// Always true for rules without `schedule`
+    pub schedule: bool,
+}
+
+impl RuleMatch {
+    /// Whether the rule matched as a whole
+    pub fn matched(&self) -> bool {
+        self.protocol && self.destination && self.port && self.source && self.schedule
+    }
+}
+
 /// Compiled ACL rule with pre-compiled matchers
 #[derive(Debug, Clone)]
 pub struct CompiledAclRule {
@@ -203,17 +254,46 @@ pub struct CompiledAclRule {
     pub description: String,
     pub destinations: Vec<CompiledDestinationMatcher>,
     pub ports: Vec<CompiledPortMatcher>,
+    /// Client source matchers; empty = any client
+    pub sources: Vec<CompiledDestinationMatcher>,
     pub protocols: Vec<Protocol>,
     pub priority: u32,
+    /// Stable identity, see [`rule_id`]
+    pub id: String,
+    /// Hit counter; shared with the engine's [`AclRuleStats`] for tracked rules
+    pub stats: Arc<RuleCounter>,
+    pub log: RuleLogLevel,
+    pub reply_code: Option<BlockReplyCode>,
+    pub resolve: ResolveMode,
+    /// When the rule applies; `None` = always
+    pub schedule: Option<CompiledSchedule>,
 }
 
 impl CompiledAclRule {
+    /// Compile a rule of `scope` whose hits are counted in `stats`, reading domain
+    /// lists through `lists`
+    pub fn compile_tracked(
+        rule: &AclRule,
+        scope: &str,
+        stats: &AclRuleStats,
+        lists: &mut DomainLists,
+    ) -> Result<Self, String> {
+        let mut compiled = Self::compile_with_lists(rule, lists)?;
+        compiled.id = rule_id(scope, rule);
+        compiled.stats = stats.counter(&compiled.id, scope, &rule.action);
+        Ok(compiled)
+    }
+
     /// Compile an ACL rule for efficient matching
     pub fn compile(rule: &AclRule) -> Result<Self, String> {
+        Self::compile_with_lists(rule, &mut DomainLists::new())
+    }
+
+    fn compile_with_lists(rule: &AclRule, lists: &mut DomainLists) -> Result<Self, String> {
         let destinations: Result<Vec<_>, _> = rule
             .destinations
             .iter()
-            .map(|s| CompiledDestinationMatcher::compile(s))
+            .map(|s| CompiledDestinationMatcher::compile_destination(s, lists))
             .collect();
 
         let ports: Result<Vec<_>, _> = rule
@@ -222,23 +302,70 @@ impl CompiledAclRule {
             .map(|s| CompiledPortMatcher::compile(s))
             .collect();
 
+        let sources: Result<Vec<_>, _> = rule
+            .sources
+            .iter()
+            .map(|s| CompiledDestinationMatcher::compile_source(s))
+            .collect();
+
+        if rule.reply_code.is_some() && rule.action != Action::Block {
+            return Err(format!(
+                "Rule '{}' sets reply_code, which only applies to block rules",
+                rule.description
+            ));
+        }
+        if !rule.resolve.is_default() && rule.action != Action::Allow {
+            return Err(format!(
+                "Rule '{}' sets resolve, which only applies to allow rules",
+                rule.description
+            ));
+        }
+
+        let schedule = rule
+            .schedule
+            .as_ref()
+            .map(CompiledSchedule::compile)
+            .transpose()
+            .map_err(|e| format!("Rule '{}': {}", rule.description, e))?;
+
         Ok(Self {
             action: rule.action.clone(),
             description: rule.description.clone(),
             destinations: destinations?,
             ports: ports?,
+            sources: sources?,
             protocols: rule.protocols.clone(),
             priority: rule.priority,
+            id: rule_id("", rule),
+            stats: Arc::new(RuleCounter::new("", &rule.action)),
+            log: rule.log,
+            reply_code: rule.reply_code,
+            resolve: rule.resolve,
+            schedule,
         })
     }
 
     /// Check if this rule matches the given connection parameters
-    pub fn matches(&self, addr: &Address, port: u16, protocol: &Protocol) -> bool {
+    ///
+    /// `source` is the client address; a rule with `sources` never matches without one.
+    /// `at` is the evaluation time, checked against the rule's schedule.
+    pub fn matches(
+        &self,
+        addr: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+        at: DateTime<Utc>,
+    ) -> bool {
         // Check protocol
         if !self.protocols.iter().any(|p| p.matches(protocol)) {
             return false;
         }
 
+        if !self.source_matches(source) {
+            return false;
+        }
+
         // Check destination
         // Empty list = match nothing
         // Use ["*"] to match all destinations
@@ -249,7 +376,42 @@ impl CompiledAclRule {
         // Use ["*"] to match all ports
         let port_match = self.ports.iter().any(|p| p.matches(port));
 
-        dest_match && port_match
+        dest_match && port_match && self.schedule_matches(at)
+    }
+
+    /// [`Self::matches`] with every component evaluated, for explaining a decision
+    pub fn explain(
+        &self,
+        addr: &Address,
+        port: u16,
+        protocol: &Protocol,
+        source: Option<IpAddr>,
+        at: DateTime<Utc>,
+    ) -> RuleMatch {
+        RuleMatch {
+            protocol: self.protocols.iter().any(|p| p.matches(protocol)),
+            destination: self.destinations.iter().any(|d| d.matches(addr)),
+            port: self.ports.iter().any(|p| p.matches(port)),
+            source: self.source_matches(source),
+            schedule: self.schedule_matches(at),
+        }
+    }
+
+    /// Empty list = any client, including an unknown one
+    #[inline]
+    fn source_matches(&self, source: Option<IpAddr>) -> bool {
+        if self.sources.is_empty() {
+            return true;
+        }
+        source.is_some_and(|ip| self.sources.iter().any(|s| s.matches_ip(ip)))
+    }
+
+    /// No schedule = always
+    #[inline]
+    fn schedule_matches(&self, at: DateTime<Utc>) -> bool {
+        self.schedule
+            .as_ref()
+            .is_none_or(|schedule| schedule.contains(at))
     }
 }
 
@@ -263,7 +425,7 @@ mod tests {
 
         assert!(matcher.matches(&Address::IPv4([192, 168, 1, 1])));
         assert!(!matcher.matches(&Address::IPv4([192, 168, 1, 2])));
-        assert!(!matcher.matches(&Address::Domain("example.com".to_string())));
+        assert!(!matcher.matches(&Address::Domain("example.com".into())));
     }
 
     #[test]
@@ -284,9 +446,9 @@ mod tests {
     fn test_domain_matching() {
         let matcher = CompiledDestinationMatcher::compile("example.com").unwrap();
 
-        assert!(matcher.matches(&Address::Domain("example.com".to_string())));
-        assert!(matcher.matches(&Address::Domain("EXAMPLE.COM".to_string())));
-        assert!(!matcher.matches(&Address::Domain("test.example.com".to_string())));
+        assert!(matcher.matches(&Address::Domain("example.com".into())));
+        assert!(matcher.matches(&Address::Domain("EXAMPLE.COM".into())));
+        assert!(!matcher.matches(&Address::Domain("test.example.com".into())));
         assert!(!matcher.matches(&Address::IPv4([192, 168, 1, 1])));
     }
 
@@ -294,16 +456,16 @@ mod tests {
     fn test_wildcard_domain_matching() {
         let matcher = CompiledDestinationMatcher::compile("*.example.com").unwrap();
 
-        assert!(matcher.matches(&Address::Domain("api.example.com".to_string())));
-        assert!(matcher.matches(&Address::Domain("www.example.com".to_string())));
-        assert!(!matcher.matches(&Address::Domain("example.com".to_string())));
-        assert!(!matcher.matches(&Address::Domain("api.test.example.com".to_string())));
+        assert!(matcher.matches(&Address::Domain("api.example.com".into())));
+        assert!(matcher.matches(&Address::Domain("www.example.com".into())));
+        assert!(!matcher.matches(&Address::Domain("example.com".into())));
+        assert!(!matcher.matches(&Address::Domain("api.test.example.com".into())));
 
         // Test api.*.com pattern
         let matcher2 = CompiledDestinationMatcher::compile("api.*.com").unwrap();
-        assert!(matcher2.matches(&Address::Domain("api.example.com".to_string())));
-        assert!(matcher2.matches(&Address::Domain("api.test.com".to_string())));
-        assert!(!matcher2.matches(&Address::Domain("api.example.org".to_string())));
+        assert!(matcher2.matches(&Address::Domain("api.example.com".into())));
+        assert!(matcher2.matches(&Address::Domain("api.test.com".into())));
+        assert!(!matcher2.matches(&Address::Domain("api.example.org".into())));
     }
 
     #[test]
@@ -341,23 +503,103 @@ mod tests {
             description: "Allow HTTPS".to_string(),
             destinations: vec!["10.0.0.0/8".to_string()],
             ports: vec!["443".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Tcp],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let compiled = CompiledAclRule::compile(&rule).unwrap();
 
         // Should match: TCP to 10.x.x.x:443
-        assert!(compiled.matches(&Address::IPv4([10, 0, 0, 1]), 443, &Protocol::Tcp));
+        assert!(compiled.matches(
+            &Address::IPv4([10, 0, 0, 1]),
+            443,
+            &Protocol::Tcp,
+            None,
+            Utc::now()
+        ));
 
         // Should not match: wrong port
-        assert!(!compiled.matches(&Address::IPv4([10, 0, 0, 1]), 80, &Protocol::Tcp));
+        assert!(!compiled.matches(
+            &Address::IPv4([10, 0, 0, 1]),
+            80,
+            &Protocol::Tcp,
+            None,
+            Utc::now()
+        ));
 
         // Should not match: wrong IP range
-        assert!(!compiled.matches(&Address::IPv4([11, 0, 0, 1]), 443, &Protocol::Tcp));
+        assert!(!compiled.matches(
+            &Address::IPv4([11, 0, 0, 1]),
+            443,
+            &Protocol::Tcp,
+            None,
+            Utc::now()
+        ));
 
         // Should not match: wrong protocol
-        assert!(!compiled.matches(&Address::IPv4([10, 0, 0, 1]), 443, &Protocol::Udp));
+        assert!(!compiled.matches(
+            &Address::IPv4([10, 0, 0, 1]),
+            443,
+            &Protocol::Udp,
+            None,
+            Utc::now()
+        ));
+    }
+
+    #[test]
+    fn test_rule_source_matching() {
+        let rule = AclRule {
+            action: Action::Allow,
+            description: "Office only".to_string(),
+            destinations: vec!["*".to_string()],
+            ports: vec!["*".to_string()],
+            sources: vec!["10.1.0.0/16".to_string(), "2001:db8::/32".to_string()],
+            protocols: vec![Protocol::Tcp],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
+        };
+        let compiled = CompiledAclRule::compile(&rule).unwrap();
+        let dest = Address::Domain("example.com".into());
+        let matches = |source: Option<&str>| {
+            compiled.matches(
+                &dest,
+                443,
+                &Protocol::Tcp,
+                source.map(|s| s.parse().unwrap()),
+                Utc::now(),
+            )
+        };
+
+        assert!(matches(Some("10.1.2.3")));
+        assert!(matches(Some("2001:db8::1")));
+        // Dual-stack listeners report IPv4 clients as mapped addresses
+        assert!(matches(Some("::ffff:10.1.2.3")));
+        assert!(!matches(Some("10.2.0.1")));
+        assert!(!matches(None));
+        assert!(
+            !compiled
+                .explain(
+                    &dest,
+                    443,
+                    &Protocol::Tcp,
+                    Some("192.0.2.1".parse().unwrap()),
+                    Utc::now(),
+                )
+                .source
+        );
+
+        // Sources are client addresses, never names
+        let mut named = rule.clone();
+        named.sources = vec!["office.example.com".to_string()];
+        assert!(CompiledAclRule::compile(&named).is_err());
     }
 
     #[test]
@@ -381,11 +623,24 @@ mod tests {
     #[test]
     fn test_domain_string_as_ip_matches_cidr_and_ip() {
         let cidr_matcher = CompiledDestinationMatcher::compile("192.168.0.0/16").unwrap();
-        assert!(cidr_matcher.matches(&Address::Domain("192.168.55.220".to_string())));
-        assert!(!cidr_matcher.matches(&Address::Domain("10.0.0.5".to_string())));
+        assert!(cidr_matcher.matches(&Address::Domain("192.168.55.220".into())));
+        assert!(!cidr_matcher.matches(&Address::Domain("10.0.0.5".into())));
 
         let ip_matcher = CompiledDestinationMatcher::compile("10.0.0.1").unwrap();
-        assert!(ip_matcher.matches(&Address::Domain("10.0.0.1".to_string())));
-        assert!(!ip_matcher.matches(&Address::Domain("10.0.0.2".to_string())));
+        assert!(ip_matcher.matches(&Address::Domain("10.0.0.1".into())));
+        assert!(!ip_matcher.matches(&Address::Domain("10.0.0.2".into())));
+    }
+
+    #[test]
+    fn test_bracketed_and_zoned_ipv6_match_as_ip() {
+        let cidr_matcher = CompiledDestinationMatcher::compile("fe80::/10").unwrap();
+        assert!(cidr_matcher.matches(&Address::Domain("fe80::1%eth0".into())));
+        assert!(cidr_matcher.matches(&Address::Domain("[fe80::1%25eth0]".into())));
+        // Even a zone naming no local interface cannot hide the IP from the rule
+        assert!(cidr_matcher.matches(&Address::Domain("fe80::1%no-such-if0".into())));
+
+        let ip_matcher = CompiledDestinationMatcher::compile("2001:db8::1").unwrap();
+        assert!(ip_matcher.matches(&Address::Domain("[2001:db8::1]".into())));
+        assert!(!ip_matcher.matches(&Address::Domain("[2001:db8::1".into())));
     }
 }
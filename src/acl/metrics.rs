@@ -0,0 +1,13 @@
+use lazy_static::lazy_static;
+use prometheus::{register_histogram, Histogram, HistogramOpts};
+
+lazy_static! {
+    pub static ref ACL_RELOAD_BUILD_DURATION: Histogram = register_histogram!(HistogramOpts::new(
+        "rustsocks_acl_reload_build_seconds",
+        "Time spent validating and compiling a reloaded ACL configuration"
+    )
+    .buckets(vec![
+        0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0
+    ]))
+    .expect("register rustsocks_acl_reload_build_seconds histogram");
+}
@@ -1,16 +1,41 @@
+pub mod cache;
 pub mod crud;
+pub mod diff;
+pub mod domain_list;
 pub mod engine;
+pub mod example;
+pub mod lint;
 pub mod loader;
 pub mod matcher;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod persistence;
+pub mod rule_stats;
+pub mod schedule;
 pub mod stats;
 pub mod types;
 pub mod watcher;
 
+pub use cache::DecisionCache;
 pub use crud::{RuleIdentifier, RuleSearchCriteria, RuleSearchResult};
-pub use engine::AclEngine;
-pub use loader::{create_example_acl_config, load_acl_config, load_acl_config_sync};
+pub use diff::{AclConfigDiff, ScopeChange, ScopeDiff};
+pub use domain_list::{referenced_domain_lists, DomainList, DomainLists};
+pub use engine::{
+    AclEngine, AclExplanation, AclReloadStatus, AclReloadTrigger, RuleTrace, RuleUsage,
+    MAX_TRACE_RULES,
+};
+pub use example::{generate_acl_example, AclExampleVariant, ObservedFlow, ACL_TEMPLATE_VERSION};
+pub use lint::{LintFinding, LintKind, LintSettings, LintSeverity};
+pub use loader::{
+    create_example_acl_config, load_acl_config, load_acl_config_sync, parse_acl_config,
+};
+pub use matcher::RuleMatch;
 pub use persistence::{load_config, save_config};
+pub use rule_stats::{AclRuleStats, RuleStatsPersistence, RuleStatsRecord};
+pub use schedule::{AclClock, CompiledSchedule, SystemClock};
 pub use stats::{AclStats, AclStatsSnapshot};
-pub use types::{AclConfig, AclDecision, Action, Protocol};
+pub use types::{
+    AclConfig, AclDecision, AclMatchedOn, AclMode, AclVerdict, Action, BlockReplyCode, Protocol,
+    ResolveMode, RuleLogLevel, RuleSchedule,
+};
 pub use watcher::AclWatcher;
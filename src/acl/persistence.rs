@@ -266,11 +266,13 @@ mod tests {
 
         // Create invalid config (will be validated on save)
         let mut config = create_test_config();
-        config.users.push(crate::acl::types::UserAcl {
+        let alice = crate::acl::types::UserAcl {
             username: "alice".to_string(),
-            groups: vec!["non-existent-group".to_string()],
+            groups: vec![],
             rules: vec![],
-        });
+        };
+        config.users.push(alice.clone());
+        config.users.push(alice);
 
         // Save should fail validation
         assert!(save_config(&config, &config_path).await.is_err());
@@ -0,0 +1,518 @@
+/// Per-rule hit counters that survive reloads and restarts
+///
+/// Every compiled rule shares a [`RuleCounter`] with the engine's [`AclRuleStats`],
+/// keyed by [`rule_id`]: a hash of the rule's scope and matching fields. Reordering
+/// the ACL file keeps a rule's counter; editing what the rule matches starts a new one.
+///
+/// Counters are written periodically and on shutdown to the session database
+/// (`acl_rule_stats` table) or, without one, to an optional JSON sidecar next to the
+/// ACL file, and seeded back at startup. The breakdown by user is kept in memory only.
+/// Only [`AclRuleStats::reset`] sets counters back to zero.
+use super::types::{AclRule, Action};
+use chrono::{DateTime, TimeZone, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+/// Stable identifier of `rule` within `scope` (`user:<name>` or `group:<name>`).
+///
+/// Covers everything that decides what the rule matches and does; the description
+/// is left out so rewording it keeps the counter.
+pub fn rule_id(scope: &str, rule: &AclRule) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(scope.as_bytes());
+    hasher.update([0]);
+    hasher.update(format!("{:?}", rule.action).as_bytes());
+    for destination in &rule.destinations {
+        hasher.update([0x1f]);
+        hasher.update(destination.as_bytes());
+    }
+    hasher.update([0]);
+    for port in &rule.ports {
+        hasher.update([0x1f]);
+        hasher.update(port.as_bytes());
+    }
+    hasher.update([0]);
+    for protocol in &rule.protocols {
+        hasher.update([0x1f]);
+        hasher.update(format!("{:?}", protocol).as_bytes());
+    }
+    hasher.update([0]);
+    hasher.update(rule.priority.to_be_bytes());
+    // Only hashed when set, so rules written before `sources` existed keep their ids
+    if !rule.sources.is_empty() {
+        for source in &rule.sources {
+            hasher.update([0x1f]);
+            hasher.update(source.as_bytes());
+        }
+        hasher.update([0]);
+    }
+    if let Some(reply_code) = rule.reply_code {
+        hasher.update(reply_code.as_str().as_bytes());
+        hasher.update([0]);
+    }
+    if !rule.resolve.is_default() {
+        hasher.update(b"resolve=");
+        hasher.update(rule.resolve.as_str().as_bytes());
+        hasher.update([0]);
+    }
+    if let Some(schedule) = &rule.schedule {
+        hasher.update(b"schedule=");
+        for day in &schedule.days {
+            hasher.update([0x1f]);
+            hasher.update(day.as_bytes());
+        }
+        hasher.update([0]);
+        for range in &schedule.times {
+            hasher.update([0x1f]);
+            hasher.update(range.as_bytes());
+        }
+        hasher.update([0]);
+        hasher.update(schedule.timezone.as_deref().unwrap_or("").as_bytes());
+        hasher.update([0]);
+    }
+
+    hasher.finalize()[..8]
+        .iter()
+        .fold(String::with_capacity(16), |mut id, byte| {
+            let _ = write!(id, "{:02x}", byte);
+            id
+        })
+}
+
+/// Hit counter of one rule.
+#[derive(Debug)]
+pub struct RuleCounter {
+    scope: String,
+    action: Action,
+    hits: AtomicU64,
+    /// Unix milliseconds of the last match, 0 if never matched
+    last_matched_ms: AtomicI64,
+    /// Unix milliseconds at which counting started for this rule
+    tracked_since_ms: AtomicI64,
+    /// Hits per user the rule was evaluated for
+    users: DashMap<String, u64>,
+}
+
+impl RuleCounter {
+    pub(crate) fn new(scope: &str, action: &Action) -> Self {
+        Self {
+            scope: scope.to_string(),
+            action: action.clone(),
+            hits: AtomicU64::new(0),
+            last_matched_ms: AtomicI64::new(0),
+            tracked_since_ms: AtomicI64::new(Utc::now().timestamp_millis()),
+            users: DashMap::new(),
+        }
+    }
+
+    /// Count one match for `user`.
+    #[inline]
+    pub fn record(&self, user: &str) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        self.last_matched_ms
+            .fetch_max(Utc::now().timestamp_millis(), Ordering::Relaxed);
+        match self.users.get_mut(user) {
+            Some(mut hits) => *hits += 1,
+            None => *self.users.entry(user.to_string()).or_insert(0) += 1,
+        }
+    }
+
+    /// Start counting again from zero, now
+    fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.last_matched_ms.store(0, Ordering::Relaxed);
+        self.tracked_since_ms
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+        self.users.clear();
+    }
+
+    /// `user:<name>` or `group:<name>`; empty for untracked rules
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn last_matched_at(&self) -> Option<DateTime<Utc>> {
+        match self.last_matched_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Utc.timestamp_millis_opt(ms).single(),
+        }
+    }
+
+    pub fn tracked_since(&self) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(self.tracked_since_ms.load(Ordering::Relaxed))
+            .single()
+            .unwrap_or_else(Utc::now)
+    }
+
+    /// Hits per user since this process started or the last reset, most hits first
+    pub fn user_hits(&self) -> Vec<(String, u64)> {
+        let mut users: Vec<(String, u64)> = self
+            .users
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect();
+        users.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        users
+    }
+}
+
+/// Persisted form of a [`RuleCounter`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleStatsRecord {
+    pub rule_id: String,
+    pub scope: String,
+    pub action: Action,
+    pub hits: u64,
+    pub last_matched_at: Option<DateTime<Utc>>,
+    pub tracked_since: DateTime<Utc>,
+}
+
+/// Registry of rule counters, shared by every configuration the engine compiles.
+#[derive(Debug, Default)]
+pub struct AclRuleStats {
+    counters: DashMap<String, Arc<RuleCounter>>,
+}
+
+impl AclRuleStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counter for `id`, created on first use.
+    pub(crate) fn counter(&self, id: &str, scope: &str, action: &Action) -> Arc<RuleCounter> {
+        if let Some(counter) = self.counters.get(id) {
+            return counter.clone();
+        }
+        self.counters
+            .entry(id.to_string())
+            .or_insert_with(|| Arc::new(RuleCounter::new(scope, action)))
+            .clone()
+    }
+
+    /// Drop counters of rules that are no longer configured.
+    pub(crate) fn retain(&self, ids: &HashSet<String>) {
+        self.counters.retain(|id, _| ids.contains(id));
+    }
+
+    /// Set every counter back to zero and restart its tracking window.
+    ///
+    /// Reloads never do this; the persisted counters are replaced on the next flush.
+    /// Returns the number of counters reset.
+    pub fn reset(&self) -> usize {
+        let mut reset = 0;
+        for counter in self.counters.iter() {
+            counter.reset();
+            reset += 1;
+        }
+        reset
+    }
+
+    /// Look up the counter of a configured rule.
+    pub fn get(&self, id: &str) -> Option<Arc<RuleCounter>> {
+        self.counters.get(id).map(|counter| counter.clone())
+    }
+
+    /// Resume counters from persisted records.
+    ///
+    /// Records of rules that are not in the current ACL (removed or edited since they
+    /// were written) are ignored. Returns the number of counters seeded.
+    pub fn seed(&self, records: &[RuleStatsRecord]) -> usize {
+        let mut seeded = 0;
+        for record in records {
+            let Some(counter) = self.counters.get(&record.rule_id) else {
+                continue;
+            };
+            counter.hits.fetch_add(record.hits, Ordering::Relaxed);
+            if let Some(last) = record.last_matched_at {
+                counter
+                    .last_matched_ms
+                    .fetch_max(last.timestamp_millis(), Ordering::Relaxed);
+            }
+            counter
+                .tracked_since_ms
+                .fetch_min(record.tracked_since.timestamp_millis(), Ordering::Relaxed);
+            seeded += 1;
+        }
+        seeded
+    }
+
+    /// Current value of every counter, ordered by rule id.
+    pub fn snapshot(&self) -> Vec<RuleStatsRecord> {
+        let mut records: Vec<_> = self
+            .counters
+            .iter()
+            .map(|entry| {
+                let counter = entry.value();
+                RuleStatsRecord {
+                    rule_id: entry.key().clone(),
+                    scope: counter.scope.clone(),
+                    action: counter.action.clone(),
+                    hits: counter.hits(),
+                    last_matched_at: counter.last_matched_at(),
+                    tracked_since: counter.tracked_since(),
+                }
+            })
+            .collect();
+        records.sort_by(|a, b| a.rule_id.cmp(&b.rule_id));
+        records
+    }
+}
+
+/// Where rule counters are persisted.
+#[derive(Clone)]
+pub enum RuleStatsPersistence {
+    /// JSON file next to the ACL file, see [`sidecar_path`]
+    Sidecar(PathBuf),
+    /// `acl_rule_stats` table of the session database
+    #[cfg(feature = "database")]
+    Database(Arc<crate::session::SessionStore>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SidecarFile {
+    rules: Vec<RuleStatsRecord>,
+}
+
+impl RuleStatsPersistence {
+    /// Read persisted records; a missing sidecar yields none.
+    pub async fn load(&self) -> Result<Vec<RuleStatsRecord>, String> {
+        match self {
+            Self::Sidecar(path) => match fs::read_to_string(path).await {
+                Ok(content) => serde_json::from_str::<SidecarFile>(&content)
+                    .map(|file| file.rules)
+                    .map_err(|e| format!("Invalid rule stats file {}: {}", path.display(), e)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+                Err(e) => Err(format!(
+                    "Failed to read rule stats file {}: {}",
+                    path.display(),
+                    e
+                )),
+            },
+            #[cfg(feature = "database")]
+            Self::Database(store) => store
+                .load_acl_rule_stats()
+                .await
+                .map_err(|e| format!("Failed to load ACL rule stats: {}", e)),
+        }
+    }
+
+    /// Write `records`, replacing what was persisted for the same rules.
+    pub async fn save(&self, records: &[RuleStatsRecord]) -> Result<(), String> {
+        match self {
+            Self::Sidecar(path) => {
+                let content = serde_json::to_string_pretty(&SidecarFile {
+                    rules: records.to_vec(),
+                })
+                .map_err(|e| format!("Failed to serialize rule stats: {}", e))?;
+
+                // Write next to the target and rename so readers never see a partial file
+                let mut temp_path = path.clone().into_os_string();
+                temp_path.push(".tmp");
+                fs::write(&temp_path, content)
+                    .await
+                    .map_err(|e| format!("Failed to write rule stats file: {}", e))?;
+                fs::rename(&temp_path, path)
+                    .await
+                    .map_err(|e| format!("Failed to replace rule stats file: {}", e))
+            }
+            #[cfg(feature = "database")]
+            Self::Database(store) => store
+                .save_acl_rule_stats(records)
+                .await
+                .map_err(|e| format!("Failed to save ACL rule stats: {}", e)),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Sidecar(path) => path.display().to_string(),
+            #[cfg(feature = "database")]
+            Self::Database(_) => "database".to_string(),
+        }
+    }
+}
+
+/// Sidecar file for the ACL file at `acl_path`: `<acl file>.stats.json`.
+pub fn sidecar_path(acl_path: &Path) -> PathBuf {
+    let mut path = acl_path.as_os_str().to_owned();
+    path.push(".stats.json");
+    PathBuf::from(path)
+}
+
+/// Seed `stats` from `persistence`, logging instead of failing.
+pub async fn restore(stats: &AclRuleStats, persistence: &RuleStatsPersistence) {
+    match persistence.load().await {
+        Ok(records) => {
+            let seeded = stats.seed(&records);
+            info!(
+                target = %persistence.describe(),
+                seeded,
+                persisted = records.len(),
+                "ACL rule statistics restored"
+            );
+        }
+        Err(e) => warn!(error = %e, "Failed to restore ACL rule statistics"),
+    }
+}
+
+/// Write the current counters to `persistence`.
+pub async fn flush(stats: &AclRuleStats, persistence: &RuleStatsPersistence) {
+    let records = stats.snapshot();
+    match persistence.save(&records).await {
+        Ok(()) => debug!(rules = records.len(), "Flushed ACL rule statistics"),
+        Err(e) => warn!(error = %e, "Failed to flush ACL rule statistics"),
+    }
+}
+
+/// Flush counters every `period` until the task is aborted.
+pub fn spawn_flusher(
+    stats: Arc<AclRuleStats>,
+    persistence: RuleStatsPersistence,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick completes immediately; there is nothing new to write yet
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            flush(&stats, &persistence).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::types::{Protocol, RuleLogLevel};
+
+    fn rule(destination: &str) -> AclRule {
+        AclRule {
+            action: Action::Allow,
+            description: "Allow".to_string(),
+            destinations: vec![destination.to_string()],
+            ports: vec!["443".to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Tcp],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
+        }
+    }
+
+    #[test]
+    fn rule_ids_follow_matching_fields_and_scope() {
+        let base = rule("example.com");
+        let id = rule_id("group:dev", &base);
+        assert_eq!(id.len(), 16);
+        assert_eq!(id, rule_id("group:dev", &base.clone()));
+
+        let mut reworded = base.clone();
+        reworded.description = "Something else".to_string();
+        assert_eq!(id, rule_id("group:dev", &reworded));
+
+        assert_ne!(id, rule_id("group:ops", &base));
+        assert_ne!(id, rule_id("group:dev", &rule("example.org")));
+        let mut reprioritized = base.clone();
+        reprioritized.priority = 200;
+        assert_ne!(id, rule_id("group:dev", &reprioritized));
+    }
+
+    #[test]
+    fn seeding_skips_unknown_rules_and_keeps_oldest_tracking_start() {
+        let stats = AclRuleStats::new();
+        let counter = stats.counter("a", "user:alice", &Action::Allow);
+        counter.record("alice");
+
+        let since = Utc::now() - chrono::Duration::days(30);
+        let last = Utc::now() - chrono::Duration::days(2);
+        let records = vec![
+            RuleStatsRecord {
+                rule_id: "a".to_string(),
+                scope: "user:alice".to_string(),
+                action: Action::Allow,
+                hits: 41,
+                last_matched_at: Some(last),
+                tracked_since: since,
+            },
+            RuleStatsRecord {
+                rule_id: "gone".to_string(),
+                scope: "user:alice".to_string(),
+                action: Action::Block,
+                hits: 7,
+                last_matched_at: None,
+                tracked_since: since,
+            },
+        ];
+        assert_eq!(stats.seed(&records), 1);
+
+        assert_eq!(counter.hits(), 42);
+        assert!(counter.last_matched_at().unwrap() > last);
+        assert_eq!(
+            counter.tracked_since().timestamp_millis(),
+            since.timestamp_millis()
+        );
+        assert!(stats.get("gone").is_none());
+    }
+
+    #[test]
+    fn hits_are_broken_out_by_user_until_reset() {
+        let stats = AclRuleStats::new();
+        let counter = stats.counter("a", "group:dev", &Action::Allow);
+        let started = counter.tracked_since();
+        counter.record("bob");
+        counter.record("alice");
+        counter.record("alice");
+
+        assert_eq!(counter.hits(), 3);
+        assert_eq!(
+            counter.user_hits(),
+            vec![("alice".to_string(), 2), ("bob".to_string(), 1)]
+        );
+
+        assert_eq!(stats.reset(), 1);
+        assert_eq!(counter.hits(), 0);
+        assert_eq!(counter.last_matched_at(), None);
+        assert!(counter.user_hits().is_empty());
+        assert!(counter.tracked_since() >= started);
+    }
+
+    #[tokio::test]
+    async fn sidecar_round_trips_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let persistence = RuleStatsPersistence::Sidecar(sidecar_path(&dir.path().join("acl.toml")));
+        assert!(persistence.load().await.unwrap().is_empty());
+
+        let stats = AclRuleStats::new();
+        stats
+            .counter("a", "group:dev", &Action::Block)
+            .record("alice");
+        stats.counter("b", "group:dev", &Action::Allow);
+        flush(&stats, &persistence).await;
+
+        let loaded = persistence.load().await.unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].rule_id, "a");
+        assert_eq!(loaded[0].hits, 1);
+        assert_eq!(loaded[1].last_matched_at, None);
+        assert!(dir.path().join("acl.toml.stats.json").exists());
+    }
+}
@@ -0,0 +1,254 @@
+//! Rules that only apply at certain times: `schedule` on a rule.
+//!
+//! A schedule lists weekdays (`"mon"`, `"mon-fri"`) and time ranges (`"08:00-18:00"`,
+//! end exclusive) read in the rule's `timezone`, or the server's local time without
+//! one. A rule outside its schedule does not match, so evaluation moves on to the next
+//! rule. A range that ends before it starts runs past midnight and belongs to the day
+//! it starts on: `days = ["fri"]` with `"22:00-06:00"` covers Friday night until
+//! Saturday 06:00.
+//!
+//! Schedules are checked when a connection is evaluated; a connection that is already
+//! open is not closed when its rule's window ends.
+
+use super::types::RuleSchedule;
+use chrono::{DateTime, Datelike, Local, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// Where evaluation timestamps come from; the engine reads it once per evaluation
+pub trait AclClock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl AclClock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Zone {
+    Local,
+    Named(Tz),
+}
+
+/// Parsed [`RuleSchedule`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledSchedule {
+    /// Indexed by days from Monday
+    days: [bool; 7],
+    /// Minutes since midnight, start inclusive and end exclusive; start > end runs
+    /// past midnight
+    times: Vec<(u16, u16)>,
+    zone: Zone,
+}
+
+impl CompiledSchedule {
+    pub fn compile(schedule: &RuleSchedule) -> Result<Self, String> {
+        let mut days = [schedule.days.is_empty(); 7];
+        for entry in &schedule.days {
+            let (first, last) = match entry.split_once('-') {
+                Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+                None => {
+                    let day = parse_day(entry)?;
+                    (day, day)
+                }
+            };
+            // "fri-mon" wraps around the weekend
+            let mut day = first;
+            loop {
+                days[day.num_days_from_monday() as usize] = true;
+                if day == last {
+                    break;
+                }
+                day = day.succ();
+            }
+        }
+
+        let times = if schedule.times.is_empty() {
+            vec![(0, MINUTES_PER_DAY)]
+        } else {
+            schedule
+                .times
+                .iter()
+                .map(|range| parse_range(range))
+                .collect::<Result<_, _>>()?
+        };
+
+        let zone = match schedule.timezone.as_deref() {
+            None => Zone::Local,
+            Some(name) => Zone::Named(name.trim().parse::<Tz>().map_err(|_| {
+                format!(
+                    "Unknown time zone '{}' (use an IANA name such as Europe/Warsaw)",
+                    name
+                )
+            })?),
+        };
+
+        Ok(Self { days, times, zone })
+    }
+
+    /// Whether the schedule covers the instant `at`
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        let (weekday, minute) = match self.zone {
+            Zone::Local => day_and_minute(&at.with_timezone(&Local)),
+            Zone::Named(tz) => day_and_minute(&at.with_timezone(&tz)),
+        };
+        let today = self.days[weekday.num_days_from_monday() as usize];
+        let yesterday = self.days[weekday.pred().num_days_from_monday() as usize];
+
+        self.times.iter().any(|&(start, end)| {
+            if start < end {
+                today && (start..end).contains(&minute)
+            } else {
+                (today && minute >= start) || (yesterday && minute < end)
+            }
+        })
+    }
+}
+
+fn day_and_minute<T: Datelike + Timelike>(local: &T) -> (Weekday, u16) {
+    (local.weekday(), (local.hour() * 60 + local.minute()) as u16)
+}
+
+fn parse_day(s: &str) -> Result<Weekday, String> {
+    s.trim().parse::<Weekday>().map_err(|_| {
+        format!(
+            "Invalid day '{}' in schedule (use mon, tue, wed, thu, fri, sat, sun or a range such as mon-fri)",
+            s.trim()
+        )
+    })
+}
+
+/// "HH:MM-HH:MM" as minutes since midnight; "24:00" is only allowed as the end
+fn parse_range(range: &str) -> Result<(u16, u16), String> {
+    let invalid = |reason: &str| {
+        format!(
+            "Invalid time range '{}' in schedule: {} (use HH:MM-HH:MM, e.g. 08:00-18:00)",
+            range, reason
+        )
+    };
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| invalid("missing '-'"))?;
+    let start = parse_time(start).map_err(|e| invalid(&e))?;
+    let end = parse_time(end).map_err(|e| invalid(&e))?;
+    if start == MINUTES_PER_DAY {
+        return Err(invalid("a range cannot start at 24:00"));
+    }
+    if start == end {
+        return Err(invalid("the range is empty"));
+    }
+    Ok((start, end))
+}
+
+fn parse_time(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let (hours, minutes) = s
+        .split_once(':')
+        .filter(|(h, m)| {
+            (1..=2).contains(&h.len())
+                && m.len() == 2
+                && h.bytes().chain(m.bytes()).all(|b| b.is_ascii_digit())
+        })
+        .ok_or_else(|| format!("'{}' is not HH:MM", s))?;
+    let hours: u16 = hours.parse().map_err(|_| format!("'{}' is not HH:MM", s))?;
+    let minutes: u16 = minutes
+        .parse()
+        .map_err(|_| format!("'{}' is not HH:MM", s))?;
+    if minutes > 59 || hours > 24 || (hours == 24 && minutes != 0) {
+        return Err(format!("'{}' is not a time of day", s));
+    }
+    Ok(hours * 60 + minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn schedule(days: &[&str], times: &[&str], timezone: Option<&str>) -> RuleSchedule {
+        RuleSchedule {
+            days: days.iter().map(|d| d.to_string()).collect(),
+            times: times.iter().map(|t| t.to_string()).collect(),
+            timezone: timezone.map(str::to_string),
+        }
+    }
+
+    /// 2026-10-12 is a Monday
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn business_hours_cover_weekdays_only() {
+        let hours =
+            CompiledSchedule::compile(&schedule(&["mon-fri"], &["08:00-18:00"], Some("UTC")))
+                .unwrap();
+
+        assert!(hours.contains(utc(12, 8, 0)));
+        assert!(hours.contains(utc(16, 17, 59)));
+        assert!(!hours.contains(utc(16, 18, 0)), "the end is exclusive");
+        assert!(!hours.contains(utc(12, 7, 59)));
+        assert!(!hours.contains(utc(17, 12, 0)), "Saturday");
+    }
+
+    #[test]
+    fn overnight_ranges_belong_to_the_day_they_start() {
+        let nights =
+            CompiledSchedule::compile(&schedule(&["fri"], &["22:00-06:00"], Some("UTC"))).unwrap();
+
+        assert!(nights.contains(utc(16, 23, 0)));
+        assert!(nights.contains(utc(17, 5, 59)), "Saturday morning");
+        assert!(!nights.contains(utc(17, 6, 0)));
+        assert!(!nights.contains(utc(16, 5, 0)), "Thursday night");
+        assert!(!nights.contains(utc(17, 23, 0)));
+
+        let weekend = CompiledSchedule::compile(&schedule(&["fri-mon"], &[], Some("UTC"))).unwrap();
+        assert!(weekend.contains(utc(18, 12, 0)));
+        assert!(weekend.contains(utc(12, 0, 0)));
+        assert!(!weekend.contains(utc(14, 12, 0)));
+    }
+
+    #[test]
+    fn times_are_read_in_the_schedule_time_zone() {
+        // Warsaw is UTC+2 until the last Sunday of October
+        let warsaw =
+            CompiledSchedule::compile(&schedule(&["mon"], &["08:00-09:00"], Some("Europe/Warsaw")))
+                .unwrap();
+        assert!(warsaw.contains(utc(12, 6, 30)));
+        assert!(!warsaw.contains(utc(12, 8, 30)));
+
+        let all_day = CompiledSchedule::compile(&schedule(&[], &["00:00-24:00"], None)).unwrap();
+        assert!(all_day.contains(Utc::now()));
+    }
+
+    #[test]
+    fn malformed_schedules_are_rejected() {
+        for times in [
+            "25:00-26:00",
+            "08:00-25:00",
+            "12:60-13:00",
+            "8-18",
+            "08:00",
+            "09:00-09:00",
+            "24:00-06:00",
+            "24:30-01:00",
+        ] {
+            let err = CompiledSchedule::compile(&schedule(&[], &[times], None)).unwrap_err();
+            assert!(err.contains(times), "{}: {}", times, err);
+        }
+        assert!(CompiledSchedule::compile(&schedule(&["someday"], &[], None)).is_err());
+        assert!(CompiledSchedule::compile(&schedule(&[], &[], Some("Mars/Olympus"))).is_err());
+        assert!(
+            CompiledSchedule::compile(&schedule(&["Monday", "wed-THU"], &["6:30-7:00"], None))
+                .is_ok()
+        );
+    }
+}
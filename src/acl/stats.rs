@@ -1,3 +1,4 @@
+use super::types::ResolveMode;
 use dashmap::DashMap;
 use std::borrow::Cow;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -7,6 +8,10 @@ use std::sync::atomic::{AtomicU64, Ordering};
 pub struct AclStats {
     total_allowed: AtomicU64,
     total_blocked: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    resolved_local: AtomicU64,
+    resolved_remote: AtomicU64,
     per_user: DashMap<String, UserAclStats>,
 }
 
@@ -22,6 +27,10 @@ impl AclStats {
         Self {
             total_allowed: AtomicU64::new(0),
             total_blocked: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            resolved_local: AtomicU64::new(0),
+            resolved_remote: AtomicU64::new(0),
             per_user: DashMap::new(),
         }
     }
@@ -52,11 +61,35 @@ impl AclStats {
             });
     }
 
-    /// Snapshot overall counters (allowed, blocked).
+    /// Record a decision served from the decision cache.
+    pub fn record_cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a lookup the decision cache could not answer.
+    pub fn record_cache_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record where a CONNECT to a domain name was resolved.
+    pub fn record_resolution(&self, mode: ResolveMode) {
+        match mode {
+            ResolveMode::Local => &self.resolved_local,
+            ResolveMode::Remote => &self.resolved_remote,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Snapshot overall counters (allowed, blocked, decision cache hits and misses,
+    /// resolution modes).
     pub fn snapshot(&self) -> AclStatsSnapshot {
         AclStatsSnapshot {
             allowed: self.total_allowed.load(Ordering::Relaxed),
             blocked: self.total_blocked.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            resolved_local: self.resolved_local.load(Ordering::Relaxed),
+            resolved_remote: self.resolved_remote.load(Ordering::Relaxed),
         }
     }
 
@@ -65,6 +98,7 @@ impl AclStats {
         self.per_user.get(user).map(|stats| AclStatsSnapshot {
             allowed: stats.allowed,
             blocked: stats.blocked,
+            ..AclStatsSnapshot::default()
         })
     }
 }
@@ -80,6 +114,14 @@ impl Default for AclStats {
 pub struct AclStatsSnapshot {
     pub allowed: u64,
     pub blocked: u64,
+    /// Decisions served from the decision cache; always 0 per user
+    pub cache_hits: u64,
+    /// Decision cache lookups that had to evaluate the rules; always 0 per user
+    pub cache_misses: u64,
+    /// Domain CONNECTs resolved by this server; always 0 per user
+    pub resolved_local: u64,
+    /// Domain CONNECTs handed unresolved to the upstream proxy; always 0 per user
+    pub resolved_remote: u64,
 }
 
 #[cfg(test)]
@@ -109,4 +151,17 @@ mod tests {
 
         assert!(stats.user_snapshot("charlie").is_none());
     }
+
+    #[test]
+    fn record_resolution_modes() {
+        let stats = AclStats::new();
+
+        stats.record_resolution(ResolveMode::Local);
+        stats.record_resolution(ResolveMode::Remote);
+        stats.record_resolution(ResolveMode::Remote);
+
+        let totals = stats.snapshot();
+        assert_eq!(totals.resolved_local, 1);
+        assert_eq!(totals.resolved_remote, 2);
+    }
 }
@@ -1,3 +1,4 @@
+use crate::protocol::ReplyCode;
 use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
@@ -11,7 +12,7 @@ pub enum Action {
 }
 
 /// Protocol filter
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Protocol {
     Tcp,
@@ -159,6 +160,10 @@ pub struct AclRule {
     #[serde(default)]
     pub ports: Vec<String>,
 
+    /// Client source matchers (IP, CIDR); empty = any client
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+
     /// Protocol filter (tcp, udp, both)
     #[serde(default = "default_protocols")]
     pub protocols: Vec<Protocol>,
@@ -166,6 +171,222 @@ pub struct AclRule {
     /// Priority (higher = evaluated first)
     #[serde(default = "default_priority")]
     pub priority: u32,
+
+    /// Access-log verbosity for connections this rule allows
+    #[serde(default, skip_serializing_if = "RuleLogLevel::is_default")]
+    pub log: RuleLogLevel,
+
+    /// SOCKS reply sent when this block rule matches; unset = connection_not_allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_code: Option<BlockReplyCode>,
+
+    /// Where a domain destination this allow rule matches is resolved
+    #[serde(default, skip_serializing_if = "ResolveMode::is_default")]
+    pub resolve: ResolveMode,
+
+    /// When the rule applies; unset = always
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<RuleSchedule>,
+}
+
+/// Days and times of day a rule applies (`schedule` on a rule), see
+/// [`super::schedule::CompiledSchedule`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RuleSchedule {
+    /// Weekdays such as "mon" or ranges such as "mon-fri"; empty = every day
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<String>,
+
+    /// "HH:MM-HH:MM" ranges, end exclusive; one ending before it starts runs past
+    /// midnight. Empty = all day
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub times: Vec<String>,
+
+    /// IANA time zone the days and times are read in, e.g. "Europe/Warsaw"; unset =
+    /// the server's local time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+/// Where the domain of an allowed connection is resolved (`resolve` on a rule)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolveMode {
+    /// This proxy resolves the name and connects to the addresses it gets
+    #[default]
+    Local,
+    /// The name is passed on unresolved to the parent proxy (`server.upstream`), whose
+    /// DNS answers for it; without a parent it still has to be resolved here
+    Remote,
+}
+
+impl ResolveMode {
+    pub fn is_default(&self) -> bool {
+        *self == ResolveMode::Local
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResolveMode::Local => "local",
+            ResolveMode::Remote => "remote",
+        }
+    }
+}
+
+impl std::str::FromStr for ResolveMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(ResolveMode::Local),
+            "remote" => Ok(ResolveMode::Remote),
+            other => Err(format!(
+                "Invalid resolve mode '{}' (use: local, remote)",
+                other
+            )),
+        }
+    }
+}
+
+/// Whether block decisions are carried out (`acl.mode`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AclMode {
+    /// Blocked connections are refused
+    #[default]
+    Enforce,
+    /// Blocked connections are logged and counted as blocks but relayed anyway, with
+    /// their sessions marked `would_block`
+    Monitor,
+}
+
+impl AclMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AclMode::Enforce => "enforce",
+            AclMode::Monitor => "monitor",
+        }
+    }
+}
+
+impl std::str::FromStr for AclMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "enforce" => Ok(AclMode::Enforce),
+            "monitor" => Ok(AclMode::Monitor),
+            other => Err(format!(
+                "Invalid ACL mode '{}' (use: enforce, monitor)",
+                other
+            )),
+        }
+    }
+}
+
+/// SOCKS5 reply a block rule answers with (`reply_code` on a rule)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockReplyCode {
+    /// 0x01
+    GeneralFailure,
+    /// 0x02, what blocked connections get unless the rule says otherwise
+    #[default]
+    ConnectionNotAllowed,
+    /// 0x03
+    NetworkUnreachable,
+    /// 0x04
+    HostUnreachable,
+    /// 0x05
+    ConnectionRefused,
+    /// 0x06
+    TtlExpired,
+}
+
+impl BlockReplyCode {
+    pub fn as_str(&self) -> &'static str {
+        ReplyCode::from(*self).as_str()
+    }
+}
+
+impl From<BlockReplyCode> for ReplyCode {
+    fn from(code: BlockReplyCode) -> Self {
+        match code {
+            BlockReplyCode::GeneralFailure => ReplyCode::GeneralFailure,
+            BlockReplyCode::ConnectionNotAllowed => ReplyCode::ConnectionNotAllowed,
+            BlockReplyCode::NetworkUnreachable => ReplyCode::NetworkUnreachable,
+            BlockReplyCode::HostUnreachable => ReplyCode::HostUnreachable,
+            BlockReplyCode::ConnectionRefused => ReplyCode::ConnectionRefused,
+            BlockReplyCode::TtlExpired => ReplyCode::TtlExpired,
+        }
+    }
+}
+
+impl std::str::FromStr for BlockReplyCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "general_failure" => Ok(BlockReplyCode::GeneralFailure),
+            "connection_not_allowed" => Ok(BlockReplyCode::ConnectionNotAllowed),
+            "network_unreachable" => Ok(BlockReplyCode::NetworkUnreachable),
+            "host_unreachable" => Ok(BlockReplyCode::HostUnreachable),
+            "connection_refused" => Ok(BlockReplyCode::ConnectionRefused),
+            "ttl_expired" => Ok(BlockReplyCode::TtlExpired),
+            other => Err(format!(
+                "Invalid reply code '{}' (use: general_failure, connection_not_allowed, \
+                 network_unreachable, host_unreachable, connection_refused, ttl_expired)",
+                other
+            )),
+        }
+    }
+}
+
+/// Access-log verbosity of a rule; blocked connections are always logged in full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLogLevel {
+    /// One access-log record per connection
+    #[default]
+    Default,
+    /// No access-log record; only counters are updated
+    Silent,
+    /// One aggregated record per user and destination per flush interval
+    Minimal,
+    /// Full record plus negotiation attributes
+    Verbose,
+}
+
+impl RuleLogLevel {
+    pub fn is_default(&self) -> bool {
+        *self == RuleLogLevel::Default
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleLogLevel::Default => "default",
+            RuleLogLevel::Silent => "silent",
+            RuleLogLevel::Minimal => "minimal",
+            RuleLogLevel::Verbose => "verbose",
+        }
+    }
+}
+
+impl std::str::FromStr for RuleLogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(RuleLogLevel::Default),
+            "silent" => Ok(RuleLogLevel::Silent),
+            "minimal" => Ok(RuleLogLevel::Minimal),
+            "verbose" => Ok(RuleLogLevel::Verbose),
+            other => Err(format!(
+                "Invalid log level '{}' (use: default, silent, minimal, verbose)",
+                other
+            )),
+        }
+    }
 }
 
 fn default_protocols() -> Vec<Protocol> {
@@ -194,6 +415,11 @@ pub struct UserAcl {
 pub struct GroupAcl {
     pub name: String,
 
+    /// Groups whose rules this group inherits, transitively. A member's own groups take
+    /// precedence over the groups they inherit from, see [`super::engine`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<String>,
+
     #[serde(default)]
     pub rules: Vec<AclRule>,
 }
@@ -247,6 +473,67 @@ impl From<&Action> for AclDecision {
     }
 }
 
+/// What a decision was matched on, recorded on the session for DNS rebinding audits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AclMatchedOn {
+    /// A rule matched the domain name the client asked for
+    Domain,
+    /// A rule matched the IP address the client asked for
+    Ip,
+    /// No rule matched the name, and a rule matched an address it resolved to
+    ResolvedIp,
+    /// No rule matched
+    DefaultPolicy,
+}
+
+impl AclMatchedOn {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AclMatchedOn::Domain => "domain",
+            AclMatchedOn::Ip => "ip",
+            AclMatchedOn::ResolvedIp => "resolved_ip",
+            AclMatchedOn::DefaultPolicy => "default_policy",
+        }
+    }
+}
+
+impl std::str::FromStr for AclMatchedOn {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "domain" => Ok(AclMatchedOn::Domain),
+            "ip" => Ok(AclMatchedOn::Ip),
+            "resolved_ip" => Ok(AclMatchedOn::ResolvedIp),
+            "default_policy" => Ok(AclMatchedOn::DefaultPolicy),
+            other => Err(format!("Invalid ACL match target: {}", other)),
+        }
+    }
+}
+
+/// Decision plus what the access log needs to know about the matched rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclVerdict {
+    pub decision: AclDecision,
+    /// Description of the matched rule, or which default policy applied
+    pub matched_rule: Option<String>,
+    /// Log level of the matched rule; `Default` when a default policy applied
+    pub log: RuleLogLevel,
+    /// Reply sent for a block decision; `None` for allow decisions
+    pub reply_code: Option<BlockReplyCode>,
+    /// Where the destination is resolved; `Local` unless an allow rule says otherwise
+    pub resolve: ResolveMode,
+    pub matched_on: AclMatchedOn,
+}
+
+impl AclVerdict {
+    /// SOCKS reply for a blocked connection
+    pub fn block_reply(&self) -> ReplyCode {
+        self.reply_code.unwrap_or_default().into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
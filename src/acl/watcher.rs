@@ -1,44 +1,38 @@
-use super::engine::AclEngine;
-use super::loader::load_acl_config_sync;
+use super::domain_list::referenced_domain_lists;
+use super::engine::{AclEngine, AclReloadTrigger};
+use super::loader::parse_acl_config;
+use super::types::AclConfig;
 use crate::session::SessionManager;
-use notify::{
-    Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Result as NotifyResult, Watcher,
-};
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::{Duration, Instant, SystemTime};
-use tokio::sync::{mpsc, Mutex};
-use tokio::task::JoinHandle;
-use tokio::time::interval;
+use crate::utils::file_watch::{FileWatch, RELOAD_DEBOUNCE};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 use tracing::{error, info, warn};
 
+/// Quiet time after the last change before the ACL file is read, so the several events
+/// of one save (write to a temp file, rename over the original) cause a single reload
+pub const ACL_RELOAD_DEBOUNCE: Duration = RELOAD_DEBOUNCE;
+
 /// ACL Hot Reload Watcher
 /// Watches ACL configuration file and automatically reloads on changes
+///
+/// Changes are coalesced until the file has been quiet for [`ACL_RELOAD_DEBOUNCE`]
+/// (see [`FileWatch`]), then the file is parsed and validated into a staging
+/// configuration; the engine only swaps to it when every check passes. A file that is
+/// empty or changes while it is read is left for the next round. The outcome of each
+/// reload is kept on the engine ([`AclEngine::last_reload`]).
+///
+/// With [`AclWatcher::with_domain_lists`] the `file:` domain lists the rules reference
+/// are watched too; a change to one recompiles the current rules, which reads the lists
+/// again. The set of watched lists follows the rules after each reload.
 pub struct AclWatcher {
     config_path: PathBuf,
     engine: Arc<AclEngine>,
-    watcher: Option<RecommendedWatcher>,
-    poll_handle: Option<JoinHandle<()>>,
-    last_fingerprint: Arc<Mutex<Option<FileFingerprint>>>,
+    watch: Option<FileWatch>,
     session_manager: Option<Arc<SessionManager>>,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct FileFingerprint {
-    modified: Option<SystemTime>,
-    len: u64,
-}
-
-impl FileFingerprint {
-    fn capture(path: &Path) -> Result<Self, String> {
-        let metadata = std::fs::metadata(path)
-            .map_err(|e| format!("Failed to access ACL config metadata: {}", e))?;
-
-        let modified = metadata.modified().ok();
-        let len = metadata.len();
-
-        Ok(Self { modified, len })
-    }
+    watch_domain_lists: bool,
+    domain_lists: Option<Arc<DomainListWatches>>,
 }
 
 impl AclWatcher {
@@ -51,114 +45,74 @@ impl AclWatcher {
         Self {
             config_path,
             engine,
-            watcher: None,
-            poll_handle: None,
-            last_fingerprint: Arc::new(Mutex::new(None)),
+            watch: None,
             session_manager,
+            watch_domain_lists: false,
+            domain_lists: None,
         }
     }
 
+    /// Also watch the domain lists the rules reference (`acl.watch_domain_lists`)
+    pub fn with_domain_lists(mut self, enabled: bool) -> Self {
+        self.watch_domain_lists = enabled;
+        self
+    }
+
     /// Start watching the ACL config file for changes
     pub async fn start(&mut self) -> Result<(), String> {
-        let (tx, mut rx) = mpsc::channel(100);
-        let config_path = self.config_path.clone();
         let engine = self.engine.clone();
-        let fingerprint_state = self.last_fingerprint.clone();
         let session_manager = self.session_manager.clone();
+        let config_path = self.config_path.clone();
 
-        // Setup file watcher
-        let mut watcher = RecommendedWatcher::new(
-            move |res: NotifyResult<Event>| {
-                if let Ok(event) = res {
-                    // Filter for modification events
-                    if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
-                        let _ = tx.blocking_send(event);
+        let domain_lists = self.watch_domain_lists.then(|| {
+            let watches = Arc::new(DomainListWatches {
+                engine: engine.clone(),
+                session_manager: session_manager.clone(),
+                watches: Mutex::new(HashMap::new()),
+            });
+            watches.sync();
+            watches
+        });
+        let lists = domain_lists.as_ref().map(Arc::downgrade);
+
+        let watch = FileWatch::start(self.config_path.clone(), "ACL config", move |content| {
+            let engine = engine.clone();
+            let session_manager = session_manager.clone();
+            let config_path = config_path.clone();
+            let lists = lists.clone();
+            async move {
+                let result = match parse_acl_config(&content) {
+                    Ok(config) => Self::handle_reload_event(config, &engine, session_manager).await,
+                    Err(e) => {
+                        error!(
+                            path = ?config_path,
+                            "Rejected ACL config, keeping current configuration: {}", e
+                        );
+                        Err(e)
                     }
+                };
+                engine.record_reload(AclReloadTrigger::Watcher, &result);
+                if let Some(lists) = lists.as_ref().and_then(Weak::upgrade) {
+                    lists.sync();
                 }
-            },
-            Config::default()
-                .with_poll_interval(Duration::from_secs(1))
-                .with_compare_contents(true), // Only trigger on actual content changes
-        )
-        .map_err(|e| format!("Failed to create file watcher: {}", e))?;
-
-        // Watch the config file
-        watcher
-            .watch(&config_path, RecursiveMode::NonRecursive)
-            .map_err(|e| format!("Failed to watch config file: {}", e))?;
-
-        self.watcher = Some(watcher);
-
-        // Capture the initial fingerprint so we don't reload immediately
-        if let Ok(initial_fp) = FileFingerprint::capture(&config_path) {
-            let mut state = fingerprint_state.lock().await;
-            *state = Some(initial_fp);
-        }
-
-        info!(
-            path = ?config_path,
-            "ACL hot reload watcher started"
-        );
-
-        // Spawn background task to handle reload events
-        let fingerprint_state_clone = fingerprint_state.clone();
-        let session_manager_clone = session_manager.clone();
-        tokio::spawn(async move {
-            while let Some(_event) = rx.recv().await {
-                info!("ACL config file changed, checking for reload...");
-                Self::maybe_reload(
-                    &config_path,
-                    &engine,
-                    &fingerprint_state_clone,
-                    session_manager_clone.clone(),
-                )
-                .await;
-            }
-        });
-
-        // Spawn polling fallback for environments where filesystem events are unreliable
-        let poll_path = self.config_path.clone();
-        let poll_engine = self.engine.clone();
-        let poll_state = self.last_fingerprint.clone();
-        let poll_manager = session_manager.clone();
-        let poll_handle = tokio::spawn(async move {
-            let mut ticker = interval(Duration::from_secs(1));
-            loop {
-                ticker.tick().await;
-                Self::maybe_reload(&poll_path, &poll_engine, &poll_state, poll_manager.clone())
-                    .await;
             }
-        });
-
-        self.poll_handle = Some(poll_handle);
+        })?;
+        self.watch = Some(watch);
+        self.domain_lists = domain_lists;
 
+        info!(path = ?self.config_path, "ACL hot reload watcher started");
         Ok(())
     }
 
-    /// Handle a reload event (with validation and rollback)
+    /// Swap the engine to `new_config`, which it compiles and checks first
     async fn handle_reload_event(
-        config_path: &Path,
+        new_config: AclConfig,
         engine: &Arc<AclEngine>,
         session_manager: Option<Arc<SessionManager>>,
-    ) -> bool {
+    ) -> Result<(), String> {
         let start_time = Instant::now();
 
-        // Step 1: Load new config
-        let new_config = match load_acl_config_sync(config_path) {
-            Ok(config) => config,
-            Err(e) => {
-                error!(
-                    error = %e,
-                    "Failed to load new ACL config, keeping current configuration"
-                );
-                return false;
-            }
-        };
-
-        // Step 2: Validate new config (already done in load_acl_config_sync)
-        // The validation happens in AclConfig::validate() which is called by loader
-
-        // Step 3: Try to compile and reload
+        // The engine validates, lints and compiles the configuration before the swap
         match engine.reload(new_config).await {
             Ok(()) => {
                 let elapsed = start_time.elapsed();
@@ -181,7 +135,7 @@ impl AclWatcher {
                         manager.enforce_acl(engine).await;
                     });
                 }
-                true
+                Ok(())
             }
             Err(e) => {
                 error!(
@@ -190,70 +144,79 @@ impl AclWatcher {
                 );
                 // The current config remains unchanged due to the failed reload
                 // This is our "rollback" - we simply don't swap if validation/compilation fails
-                false
+                Err(e)
             }
         }
     }
 
-    /// Check if the file fingerprint changed and reload if needed
-    async fn maybe_reload(
-        config_path: &Path,
-        engine: &Arc<AclEngine>,
-        state: &Arc<Mutex<Option<FileFingerprint>>>,
-        session_manager: Option<Arc<SessionManager>>,
-    ) {
-        let current_fp = match FileFingerprint::capture(config_path) {
-            Ok(fp) => fp,
-            Err(e) => {
-                warn!(
-                    path = ?config_path,
-                    error = %e,
-                    "Failed to stat ACL config while watching"
-                );
-                return;
-            }
-        };
-
-        let should_reload = {
-            let state_lock = state.lock().await;
-            state_lock.as_ref() != Some(&current_fp)
-        };
-
-        if !should_reload {
-            return;
-        }
-
-        Self::handle_reload_event(config_path, engine, session_manager).await;
-
-        let mut state_lock = state.lock().await;
-        *state_lock = Some(current_fp);
-    }
-
     /// Stop watching
     pub fn stop(&mut self) {
-        if let Some(handle) = self.poll_handle.take() {
-            handle.abort();
-        }
-        self.watcher = None;
+        self.watch = None;
+        self.domain_lists = None;
         info!("ACL hot reload watcher stopped");
     }
 }
 
-impl Drop for AclWatcher {
-    fn drop(&mut self) {
-        // Silently stop the watcher (don't log, as stop() may have been called explicitly)
-        if let Some(handle) = self.poll_handle.take() {
-            handle.abort();
+/// Watches on the domain list files the current rules reference
+struct DomainListWatches {
+    engine: Arc<AclEngine>,
+    session_manager: Option<Arc<SessionManager>>,
+    watches: Mutex<HashMap<PathBuf, FileWatch>>,
+}
+
+impl DomainListWatches {
+    /// Watch the lists the engine's current rules reference, and only those
+    fn sync(self: &Arc<Self>) {
+        let wanted = referenced_domain_lists(&self.engine.current_config());
+        let mut watches = self
+            .watches
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        watches.retain(|path, _| wanted.contains(path));
+
+        for path in wanted {
+            if watches.contains_key(&path) {
+                continue;
+            }
+            let this = Arc::downgrade(self);
+            let list_path = path.clone();
+            let watch = FileWatch::start(path.clone(), "domain list", move |_content| {
+                let this = this.clone();
+                let list_path = list_path.clone();
+                async move {
+                    let Some(this) = this.upgrade() else {
+                        return;
+                    };
+                    info!(path = ?list_path, "Domain list changed, recompiling ACL rules");
+                    let config = (*this.engine.current_config()).clone();
+                    let result = AclWatcher::handle_reload_event(
+                        config,
+                        &this.engine,
+                        this.session_manager.clone(),
+                    )
+                    .await;
+                    this.engine
+                        .record_reload(AclReloadTrigger::DomainList, &result);
+                    this.sync();
+                }
+            });
+            match watch {
+                Ok(watch) => {
+                    watches.insert(path, watch);
+                }
+                Err(e) => warn!(path = ?path, "Cannot watch domain list: {}", e),
+            }
         }
-        self.watcher = None;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::acl::types::{AclConfig, AclRule, Action, GlobalAclConfig, Protocol, UserAcl};
-    use crate::acl::AclEngine;
+    use crate::acl::types::{
+        AclConfig, AclRule, Action, GlobalAclConfig, Protocol, RuleLogLevel, UserAcl,
+    };
+    use crate::acl::{AclEngine, AclReloadStatus};
     use std::fs;
     use tempfile::NamedTempFile;
     use tokio::time::{sleep, Duration};
@@ -271,8 +234,13 @@ mod tests {
                     description: "Allow HTTPS".to_string(),
                     destinations: vec!["0.0.0.0/0".to_string()],
                     ports: vec!["443".to_string()],
+                    sources: vec![],
                     protocols: vec![Protocol::Tcp],
                     priority: 100,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
             groups: vec![],
@@ -292,8 +260,13 @@ mod tests {
                     description: "Block port 80".to_string(),
                     destinations: vec!["0.0.0.0/0".to_string()],
                     ports: vec!["80".to_string()],
+                    sources: vec![],
                     protocols: vec![Protocol::Tcp],
                     priority: 100,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
             groups: vec![],
@@ -355,6 +328,77 @@ mod tests {
         );
     }
 
+    /// Wait until the watcher has recorded a reload after `since`
+    async fn next_reload(
+        engine: &AclEngine,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> AclReloadStatus {
+        for _ in 0..50 {
+            if let Some(status) = engine.last_reload().filter(|s| s.at > since) {
+                return status;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        panic!("no ACL reload recorded");
+    }
+
+    #[tokio::test]
+    async fn test_rapid_writes_reload_once() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_path_buf();
+        let initial_config = create_test_config();
+        fs::write(&path, toml::to_string_pretty(&initial_config).unwrap()).unwrap();
+
+        let engine = Arc::new(AclEngine::new(initial_config).unwrap());
+        let mut watcher = AclWatcher::new(path.clone(), engine.clone(), None);
+        watcher.start().await.unwrap();
+        let started = chrono::Utc::now();
+
+        // An editor truncating the file and writing it in pieces
+        let modified = toml::to_string_pretty(&create_modified_config()).unwrap();
+        let (head, tail) = modified.split_at(modified.len() / 2);
+        fs::write(&path, "").unwrap();
+        sleep(Duration::from_millis(50)).await;
+        fs::write(&path, head).unwrap();
+        sleep(Duration::from_millis(50)).await;
+        fs::write(&path, format!("{}{}", head, tail)).unwrap();
+
+        let status = next_reload(&engine, started).await;
+        assert!(status.success, "{:?}", status.error);
+        assert_eq!(status.trigger, AclReloadTrigger::Watcher);
+        assert_eq!(engine.current_config().global.default_policy, Action::Allow);
+
+        // Nothing left to reload
+        sleep(Duration::from_millis(1500)).await;
+        assert_eq!(engine.last_reload().unwrap().at, status.at);
+
+        watcher.stop();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_file_keeps_previous_rules() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_path_buf();
+        let initial_config = create_test_config();
+        fs::write(&path, toml::to_string_pretty(&initial_config).unwrap()).unwrap();
+
+        let engine = Arc::new(AclEngine::new(initial_config).unwrap());
+        let mut watcher = AclWatcher::new(path.clone(), engine.clone(), None);
+        watcher.start().await.unwrap();
+        let started = chrono::Utc::now();
+
+        fs::write(&path, "[global]\ndefault_policy = \"allow\"\n[[users]\n").unwrap();
+
+        let status = next_reload(&engine, started).await;
+        assert!(!status.success);
+        let error = status.error.unwrap();
+        assert!(error.contains("line 3"), "{error}");
+        assert_eq!(engine.current_config().global.default_policy, Action::Block);
+        assert_eq!(engine.get_user_count().await, 1);
+
+        watcher.stop();
+    }
+
     #[tokio::test]
     #[ignore] // This test requires actual file system watching, might be slow
     async fn test_file_watcher_integration() {
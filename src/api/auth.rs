@@ -1,8 +1,9 @@
 use axum::{
     body::Body,
-    extract::State,
-    http::{header, HeaderMap, StatusCode},
-    response::IntoResponse,
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
     Json,
 };
 use dashmap::DashMap;
@@ -16,7 +17,9 @@ use tracing::{debug, info, warn};
 use hmac::{Hmac, Mac};
 type HmacSha256 = Hmac<Sha256>;
 
-use crate::config::DashboardAuthSettings;
+use crate::api::types::ErrorResponse;
+use crate::auth::PasswordHash;
+use crate::config::{ApiAuthSettings, DashboardAuthSettings};
 
 const SESSION_COOKIE_NAME: &str = "rustsocks_session";
 
@@ -86,10 +89,15 @@ impl AuthState {
     }
 
     pub fn verify_credentials(&self, username: &str, password: &str) -> bool {
-        self.settings
-            .users
-            .iter()
-            .any(|user| user.username == username && user.password == password)
+        self.settings.users.iter().any(|user| {
+            user.username == username
+                && match &user.password_hash {
+                    Some(hash) => PasswordHash::parse(hash)
+                        .map(|hash| hash.verify(password.as_bytes()))
+                        .unwrap_or(false),
+                    None => *user.password == password,
+                }
+        })
     }
 }
 
@@ -170,7 +178,6 @@ pub async fn login_handler(
     Json(req): Json<LoginRequest>,
 ) -> impl IntoResponse {
     use axum::body::Body;
-    use axum::response::Response;
 
     debug!("Login attempt for user: {}", req.username);
 
@@ -472,3 +479,184 @@ pub fn extract_session_from_headers(headers: &HeaderMap) -> Option<String> {
     }
     None
 }
+
+/// What a request needs under `[sessions.api_auth]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiAccess {
+    /// Login, the public status page, API docs and dashboard files
+    Public,
+    Read,
+    Admin,
+}
+
+/// POST endpoints that only evaluate what they are sent
+const READ_ONLY_POSTS: [&str; 3] = ["/api/acl/test", "/api/acl/search", "/api/admission/test"];
+
+/// Dashboard login endpoints, reachable before there is a session or a key
+const LOGIN_PATHS: [&str; 5] = [
+    "/api/auth/login",
+    "/api/auth/logout",
+    "/api/auth/check",
+    "/api/auth/altcha-config",
+    "/api/auth/altcha-challenge",
+];
+
+/// Access level for `method` on `path` (relative to `sessions.base_path`)
+pub fn required_access(method: &Method, path: &str) -> ApiAccess {
+    let path = match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+
+    // Health probes come from kubelets and load balancers, which cannot send a key
+    if LOGIN_PATHS.contains(&path)
+        || matches!(
+            path,
+            "/status"
+                | "/status.json"
+                | "/swagger-ui"
+                | "/openapi.json"
+                | "/health"
+                | "/health/live"
+                | "/health/ready"
+        )
+    {
+        return ApiAccess::Public;
+    }
+    if !path.starts_with("/api/") && path != "/metrics" {
+        // Dashboard files; dashboard_auth gates those
+        return ApiAccess::Public;
+    }
+
+    let reads =
+        *method == Method::GET || *method == Method::HEAD || READ_ONLY_POSTS.contains(&path);
+    if path.starts_with("/api/admin/") || !reads {
+        ApiAccess::Admin
+    } else {
+        ApiAccess::Read
+    }
+}
+
+/// Who an authenticated API request came from (`key <name>` or `dashboard user <name>`),
+/// added to the request's extensions by [`api_key_auth`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiCaller(pub String);
+
+/// State of [`api_key_auth`]
+pub struct ApiAuthState {
+    pub settings: ApiAuthSettings,
+    /// A dashboard login counts as an admin key
+    pub dashboard: Arc<AuthState>,
+}
+
+impl ApiAuthState {
+    /// Access granted by the request's key or dashboard login, and who it belongs to
+    fn granted(&self, headers: &HeaderMap) -> Option<(ApiAccess, String)> {
+        if let Some(token) = extract_api_key(headers) {
+            return self
+                .settings
+                .keys
+                .iter()
+                .find(|key| constant_time_eq(key.token.as_bytes(), token.as_bytes()))
+                .map(|key| {
+                    let access = if key.is_admin() {
+                        ApiAccess::Admin
+                    } else {
+                        ApiAccess::Read
+                    };
+                    let name = key.name.clone().unwrap_or_else(|| "unnamed".to_string());
+                    (access, format!("key {}", name))
+                });
+        }
+
+        if self.dashboard.settings.enabled {
+            let session = extract_session_from_headers(headers)?;
+            let username = self.dashboard.validate_session(&session)?;
+            return Some((ApiAccess::Admin, format!("dashboard user {}", username)));
+        }
+        None
+    }
+}
+
+/// Middleware enforcing `[sessions.api_auth]`: 401 without a valid key, 403 when the
+/// key's role is too low for the endpoint
+pub async fn api_key_auth(
+    State(state): State<Arc<ApiAuthState>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    let required = required_access(request.method(), request.uri().path());
+    if !state.settings.enabled || required == ApiAccess::Public {
+        return next.run(request).await;
+    }
+
+    match state.granted(request.headers()) {
+        Some((granted, who)) if granted >= required => {
+            request.extensions_mut().insert(ApiCaller(who));
+            next.run(request).await
+        }
+        Some((_, who)) => {
+            warn!(
+                who = %who,
+                method = %request.method(),
+                path = request.uri().path(),
+                "API request needs an admin key"
+            );
+            api_auth_error(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "This endpoint requires an admin API key",
+            )
+        }
+        None => {
+            debug!(
+                method = %request.method(),
+                path = request.uri().path(),
+                "API request without a valid key"
+            );
+            api_auth_error(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "A valid API key is required (Authorization: Bearer <token> or X-API-Key)",
+            )
+        }
+    }
+}
+
+fn api_auth_error(status: StatusCode, error: &str, message: &str) -> Response<Body> {
+    let mut response = (
+        status,
+        Json(ErrorResponse::new(error, message, status.as_u16())),
+    )
+        .into_response();
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static("Bearer"),
+        );
+    }
+    response
+}
+
+/// Token from `Authorization: Bearer` or `X-API-Key`
+fn extract_api_key(headers: &HeaderMap) -> Option<&str> {
+    if let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(token.trim());
+    }
+    headers
+        .get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+}
+
+/// Compare without returning early on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,314 @@
+//! Response compression, conditional GETs and body formats for the stats API.
+//!
+//! The dashboard polls the session list and the metrics history every few seconds.
+//! With thousands of sessions those are megabytes of JSON, so responses are compressed
+//! (gzip or deflate, as negotiated from `Accept-Encoding`) and the heavier GET endpoints
+//! carry a weak ETag: a poll whose `If-None-Match` still matches gets an empty 304.
+//! Agents that poll every second can ask those endpoints for MessagePack instead of
+//! JSON ([`BodyFormat`]).
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tower_http::compression::predicate::{And, DefaultPredicate, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+
+use crate::config::ApiCompressionSettings;
+
+/// Compression for the whole API router; `None` when disabled.
+///
+/// Server-sent events, gRPC, images and bodies below `min_size_bytes` stay uncompressed.
+pub fn compression_layer(
+    settings: &ApiCompressionSettings,
+) -> Option<CompressionLayer<And<DefaultPredicate, SizeAbove>>> {
+    if !settings.enabled {
+        return None;
+    }
+    let predicate = DefaultPredicate::new().and(SizeAbove::new(settings.min_size_bytes));
+    Some(CompressionLayer::new().compress_when(predicate))
+}
+
+/// Full request paths that are never compressed (`exclude_paths` below `base_prefix`)
+#[derive(Debug, Clone, Default)]
+pub struct CompressionExclusions {
+    paths: Arc<[String]>,
+}
+
+impl CompressionExclusions {
+    pub fn new(settings: &ApiCompressionSettings, base_prefix: &str) -> Self {
+        Self {
+            paths: settings
+                .exclude_paths
+                .iter()
+                .map(|path| format!("{}{}", base_prefix, path))
+                .collect(),
+        }
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.paths.iter().any(|excluded| excluded == path)
+    }
+}
+
+/// Middleware placed outside the compression layer: requests to excluded paths lose
+/// their `Accept-Encoding`, so the response goes out as is.
+pub async fn skip_compression(
+    State(exclusions): State<CompressionExclusions>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    if exclusions.contains(request.uri().path()) {
+        request.headers_mut().remove(header::ACCEPT_ENCODING);
+    }
+    next.run(request).await
+}
+
+/// Middleware for GET endpoints: tag 200 responses with a weak ETag over the body and
+/// answer a matching `If-None-Match` with 304 Not Modified.
+///
+/// The tag is computed before compression and is weak, so it stays valid for every
+/// encoding of the same JSON.
+pub async fn conditional_get(request: Request<Body>, next: Next) -> Response<Body> {
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    // The tagged handlers build their JSON in memory; this only takes it back
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let etag = etag_for(&bytes);
+
+    if if_none_match
+        .as_ref()
+        .is_some_and(|value| etag_matches(value, &etag))
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, etag);
+        if let Some(cache_control) = parts.headers.remove(header::CACHE_CONTROL) {
+            headers.insert(header::CACHE_CONTROL, cache_control);
+        }
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    parts.headers.insert(header::ETAG, etag);
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Media type of MessagePack bodies
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// Body format of a response, negotiated from the request's `Accept`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BodyFormat {
+    #[default]
+    Json,
+    /// The same structures as the JSON, encoded with rmp-serde as maps with field names
+    MsgPack,
+}
+
+impl BodyFormat {
+    /// MessagePack when `Accept` lists `application/msgpack` (or the older
+    /// `application/x-msgpack`) without `q=0`; JSON for anything else
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        let Some(accept) = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return BodyFormat::Json;
+        };
+        let msgpack = accept.split(',').any(|range| {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or_default().trim();
+            let refused = parts.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            !refused
+                && (media_type.eq_ignore_ascii_case(MSGPACK_CONTENT_TYPE)
+                    || media_type.eq_ignore_ascii_case("application/x-msgpack"))
+        });
+        if msgpack {
+            BodyFormat::MsgPack
+        } else {
+            BodyFormat::Json
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            BodyFormat::Json => "application/json",
+            BodyFormat::MsgPack => MSGPACK_CONTENT_TYPE,
+        }
+    }
+
+    /// Encode `value` in this format
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            BodyFormat::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            BodyFormat::MsgPack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// `value` as the body of a `status` response, marked as varying with `Accept`
+    pub fn respond<T: Serialize>(self, status: StatusCode, value: &T) -> Response {
+        match self.encode(value) {
+            Ok(body) => (
+                status,
+                [
+                    (header::CONTENT_TYPE, self.content_type()),
+                    (header::VARY, "accept"),
+                ],
+                body,
+            )
+                .into_response(),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "error": format!("failed to encode response: {}", e)
+                })),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// `W/"<first 128 bits of SHA-256, hex>"`
+fn etag_for(body: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    HeaderValue::from_str(&format!("W/\"{}\"", hex)).expect("hex digest is a valid header")
+}
+
+/// Weak comparison against every tag in an `If-None-Match` list
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(candidates) = if_none_match.to_str() else {
+        return false;
+    };
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    candidates
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let etag = etag_for(b"{\"sessions\":[]}");
+        let tag = etag.to_str().unwrap().to_string();
+        assert!(tag.starts_with("W/\""));
+
+        let strong = tag.trim_start_matches("W/").to_string();
+        for value in [
+            tag.clone(),
+            strong,
+            format!("\"other\", {}", tag),
+            "*".to_string(),
+        ] {
+            assert!(
+                etag_matches(&HeaderValue::from_str(&value).unwrap(), &etag),
+                "{value}"
+            );
+        }
+        assert!(!etag_matches(
+            &HeaderValue::from_static("W/\"other\""),
+            &etag
+        ));
+        assert_ne!(etag_for(b"{\"sessions\":[1]}"), etag);
+    }
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn msgpack_only_when_asked_for() {
+        for value in [
+            "application/msgpack",
+            "application/x-msgpack",
+            "application/json;q=0.5, Application/MsgPack",
+        ] {
+            assert_eq!(
+                BodyFormat::negotiate(&accept(value)),
+                BodyFormat::MsgPack,
+                "{value}"
+            );
+        }
+        for value in [
+            "application/json",
+            "*/*",
+            "application/cbor",
+            "application/msgpack;q=0",
+        ] {
+            assert_eq!(
+                BodyFormat::negotiate(&accept(value)),
+                BodyFormat::Json,
+                "{value}"
+            );
+        }
+        assert_eq!(BodyFormat::negotiate(&HeaderMap::new()), BodyFormat::Json);
+    }
+
+    #[test]
+    fn both_formats_carry_the_same_data() {
+        #[derive(Serialize)]
+        struct Sample {
+            user: String,
+            bytes_sent: u64,
+            rate: f64,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            note: Option<String>,
+            tags: Vec<String>,
+        }
+        let sample = vec![
+            Sample {
+                user: "alice".to_string(),
+                bytes_sent: u64::MAX,
+                rate: 1.5,
+                note: None,
+                tags: vec!["vip".to_string()],
+            },
+            Sample {
+                user: "bob".to_string(),
+                bytes_sent: 0,
+                rate: 0.0,
+                note: Some("idle".to_string()),
+                tags: Vec::new(),
+            },
+        ];
+
+        let json = BodyFormat::Json.encode(&sample).unwrap();
+        let msgpack = BodyFormat::MsgPack.encode(&sample).unwrap();
+        assert!(msgpack.len() < json.len());
+        let from_json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let from_msgpack: serde_json::Value = rmp_serde::from_slice(&msgpack).unwrap();
+        assert_eq!(from_json, from_msgpack);
+    }
+}
@@ -3,14 +3,21 @@
 /// These handlers provide REST API endpoints for managing ACL rules dynamically,
 /// including adding, updating, and deleting rules for groups and users.
 use crate::acl::crud::{self, RuleIdentifier, RuleSearchCriteria};
+use crate::acl::matcher::CompiledDestinationMatcher;
 use crate::acl::persistence;
-use crate::acl::types::{AclRule, Action, Protocol};
+use crate::acl::schedule::CompiledSchedule;
+use crate::acl::types::{
+    AclConfig, AclMode, AclRule, Action, BlockReplyCode, Protocol, ResolveMode, RuleLogLevel,
+};
+use crate::api::auth::ApiCaller;
 use crate::api::handlers::sessions::ApiState;
 use crate::api::types::*;
+use crate::session::admission::ACCESS_LOG_TARGET;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    Json,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
 };
 use tracing::{error, info};
 
@@ -50,13 +57,49 @@ fn request_to_rule(req: &AddRuleRequest) -> Result<AclRule, String> {
         return Err("Ports cannot be empty".to_string());
     }
 
+    // Validate sources
+    for source in &req.sources {
+        CompiledDestinationMatcher::compile_source(source)?;
+    }
+
+    let log = match req.log.as_deref() {
+        Some(level) => level.parse::<RuleLogLevel>()?,
+        None => RuleLogLevel::Default,
+    };
+
+    let reply_code = req
+        .reply_code
+        .as_deref()
+        .map(str::parse::<BlockReplyCode>)
+        .transpose()?;
+    if reply_code.is_some() && action != Action::Block {
+        return Err("reply_code only applies to block rules".to_string());
+    }
+
+    let resolve = match req.resolve.as_deref() {
+        Some(mode) => mode.parse::<ResolveMode>()?,
+        None => ResolveMode::Local,
+    };
+    if !resolve.is_default() && action != Action::Allow {
+        return Err("resolve only applies to allow rules".to_string());
+    }
+
+    if let Some(schedule) = &req.schedule {
+        CompiledSchedule::compile(schedule)?;
+    }
+
     Ok(AclRule {
         action,
         description: req.description.clone(),
         destinations: req.destinations.clone(),
         ports: req.ports.clone(),
+        sources: req.sources.clone(),
         protocols,
         priority: req.priority,
+        log,
+        reply_code,
+        resolve,
+        schedule: req.schedule.clone(),
     })
 }
 
@@ -80,8 +123,11 @@ async fn save_and_reload(
         .as_ref()
         .ok_or_else(|| "ACL config path not set".to_string())?;
 
-    // Validate config
+    // Validate config; strict group references are checked before the file is touched
     config.validate()?;
+    if let Some(ref acl_engine) = state.acl_engine {
+        crate::acl::lint::reject_errors(&acl_engine.lint(&config))?;
+    }
 
     // Save to file (atomic)
     persistence::save_config(&config, config_path).await?;
@@ -116,6 +162,7 @@ pub async fn list_groups(State(state): State<ApiState>) -> (StatusCode, Json<Gro
         .iter()
         .map(|g| GroupSummary {
             name: g.name.clone(),
+            parents: g.parents.clone(),
             rule_count: g.rules.len(),
         })
         .collect();
@@ -136,6 +183,7 @@ pub async fn get_group_detail(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(GroupDetailResponse {
                     name: group_name,
+                    parents: vec![],
                     rules: vec![],
                 }),
             );
@@ -149,6 +197,7 @@ pub async fn get_group_detail(
             StatusCode::OK,
             Json(GroupDetailResponse {
                 name: g.name.clone(),
+                parents: g.parents.clone(),
                 rules: g.rules.clone(),
             }),
         ),
@@ -156,6 +205,7 @@ pub async fn get_group_detail(
             StatusCode::NOT_FOUND,
             Json(GroupDetailResponse {
                 name: group_name,
+                parents: vec![],
                 rules: vec![],
             }),
         ),
@@ -494,8 +544,20 @@ pub async fn create_group(
     // Add empty group
     config.groups.push(crate::acl::types::GroupAcl {
         name: request.name.clone(),
+        parents: request.parents.clone(),
         rules: vec![],
     });
+    if let Err(e) = config.group_ancestors() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(RuleOperationResponse {
+                success: false,
+                message: e,
+                rule: None,
+                old_rule: None,
+            }),
+        );
+    }
 
     // Save and reload
     if let Err(e) = save_and_reload(&state, config).await {
@@ -523,10 +585,96 @@ pub async fn create_group(
     )
 }
 
+/// PUT /api/acl/groups/{groupname}/parents - Replace the groups a group inherits from
+pub async fn set_group_parents(
+    State(state): State<ApiState>,
+    Path(group_name): Path<String>,
+    Json(request): Json<SetGroupParentsRequest>,
+) -> (StatusCode, Json<RuleOperationResponse>) {
+    if state.acl_engine.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(RuleOperationResponse {
+                success: false,
+                message: "ACL is not enabled".to_string(),
+                rule: None,
+                old_rule: None,
+            }),
+        );
+    }
+
+    let mut config = match load_current_config(&state).await {
+        Ok(c) => c,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(RuleOperationResponse {
+                    success: false,
+                    message: format!("Failed to load config: {}", e),
+                    rule: None,
+                    old_rule: None,
+                }),
+            );
+        }
+    };
+
+    if !config.groups.iter().any(|g| g.name == group_name) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(RuleOperationResponse {
+                success: false,
+                message: format!("Group '{}' not found", group_name),
+                rule: None,
+                old_rule: None,
+            }),
+        );
+    }
+
+    // Unknown parents and cycles leave the file untouched
+    if let Err(e) = crud::set_group_parents(&mut config, &group_name, request.parents.clone()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(RuleOperationResponse {
+                success: false,
+                message: e,
+                rule: None,
+                old_rule: None,
+            }),
+        );
+    }
+
+    if let Err(e) = save_and_reload(&state, config).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(RuleOperationResponse {
+                success: false,
+                message: format!("Failed to save config: {}", e),
+                rule: None,
+                old_rule: None,
+            }),
+        );
+    }
+
+    info!(group = group_name, parents = ?request.parents, "Set group parents via API");
+
+    (
+        StatusCode::OK,
+        Json(RuleOperationResponse {
+            success: true,
+            message: format!("Parents of group '{}' updated", group_name),
+            rule: None,
+            old_rule: None,
+        }),
+    )
+}
+
 /// DELETE /api/acl/groups/{groupname} - Delete entire group
+///
+/// Refused with 409 while other groups inherit from it, unless `?cascade=true`.
 pub async fn delete_group(
     State(state): State<ApiState>,
     Path(group_name): Path<String>,
+    Query(query): Query<DeleteGroupQuery>,
 ) -> (StatusCode, Json<DeleteGroupResponse>) {
     if state.acl_engine.is_none() {
         return (
@@ -553,8 +701,25 @@ pub async fn delete_group(
         }
     };
 
+    let children = crud::groups_inheriting_from(&config, &group_name);
+    if !children.is_empty() && !query.cascade && config.groups.iter().any(|g| g.name == group_name)
+    {
+        return (
+            StatusCode::CONFLICT,
+            Json(DeleteGroupResponse {
+                success: false,
+                message: format!(
+                    "Group '{}' is inherited by {}; delete with ?cascade=true to remove it from their parents",
+                    group_name,
+                    children.join(", ")
+                ),
+                deleted_group: None,
+            }),
+        );
+    }
+
     // Delete group
-    let deleted_group = match crud::delete_group(&mut config, &group_name) {
+    let deleted_group = match crud::delete_group(&mut config, &group_name, query.cascade) {
         Ok(g) => g,
         Err(e) => {
             return (
@@ -580,7 +745,11 @@ pub async fn delete_group(
         );
     }
 
-    info!(group = group_name, "Deleted group via API");
+    info!(
+        group = group_name,
+        cascade = query.cascade,
+        "Deleted group via API"
+    );
 
     (
         StatusCode::OK,
@@ -942,6 +1111,7 @@ pub async fn delete_user_rule(
 pub async fn get_global_settings(
     State(state): State<ApiState>,
 ) -> (StatusCode, Json<GlobalSettingsResponse>) {
+    let mode = acl_mode(&state).as_str().to_string();
     let config = match load_current_config(&state).await {
         Ok(c) => c,
         Err(e) => {
@@ -950,104 +1120,157 @@ pub async fn get_global_settings(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(GlobalSettingsResponse {
                     default_policy: "unknown".to_string(),
+                    mode,
                 }),
             );
         }
     };
 
-    let policy_str = match config.global.default_policy {
-        Action::Allow => "allow",
-        Action::Block => "block",
-    };
-
     (
         StatusCode::OK,
         Json(GlobalSettingsResponse {
-            default_policy: policy_str.to_string(),
+            default_policy: policy_name(&config.global.default_policy).to_string(),
+            mode,
         }),
     )
 }
 
+fn policy_name(policy: &Action) -> &'static str {
+    match policy {
+        Action::Allow => "allow",
+        Action::Block => "block",
+    }
+}
+
+/// `acl.mode` as the engine currently applies it
+fn acl_mode(state: &ApiState) -> AclMode {
+    state
+        .acl_engine
+        .as_ref()
+        .map(|engine| engine.mode())
+        .unwrap_or_default()
+}
+
 /// PUT /api/acl/global - Update global settings
+///
+/// `default_policy` is written to the ACL file. `mode` switches between enforcing and
+/// monitoring at runtime only; `acl.mode` in the configuration applies again after a
+/// restart.
 pub async fn update_global_settings(
     State(state): State<ApiState>,
+    caller: Option<Extension<ApiCaller>>,
     Json(request): Json<UpdateGlobalSettingsRequest>,
 ) -> (StatusCode, Json<UpdateGlobalSettingsResponse>) {
-    if state.acl_engine.is_none() {
-        return (
-            StatusCode::BAD_REQUEST,
+    let failure = |status: StatusCode, message: String| {
+        (
+            status,
             Json(UpdateGlobalSettingsResponse {
                 success: false,
-                message: "ACL is not enabled".to_string(),
+                message,
                 old_policy: "unknown".to_string(),
                 new_policy: "unknown".to_string(),
+                old_mode: "unknown".to_string(),
+                new_mode: "unknown".to_string(),
             }),
+        )
+    };
+
+    let Some(engine) = state.acl_engine.clone() else {
+        return failure(StatusCode::BAD_REQUEST, "ACL is not enabled".to_string());
+    };
+    if request.default_policy.is_none() && request.mode.is_none() {
+        return failure(
+            StatusCode::BAD_REQUEST,
+            "Nothing to update (set default_policy and/or mode)".to_string(),
         );
     }
 
-    let new_policy = match request.default_policy.to_lowercase().as_str() {
-        "allow" => Action::Allow,
-        "block" => Action::Block,
-        _ => {
-            return (
+    let new_mode = match request.mode.as_deref().map(str::parse::<AclMode>) {
+        None => None,
+        Some(Ok(mode)) => Some(mode),
+        Some(Err(_)) => {
+            return failure(
                 StatusCode::BAD_REQUEST,
-                Json(UpdateGlobalSettingsResponse {
-                    success: false,
-                    message: "Invalid policy. Must be 'allow' or 'block'".to_string(),
-                    old_policy: "unknown".to_string(),
-                    new_policy: request.default_policy,
-                }),
+                "Invalid mode. Must be 'enforce' or 'monitor'".to_string(),
             );
         }
     };
-
-    let mut config = match load_current_config(&state).await {
-        Ok(c) => c,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(UpdateGlobalSettingsResponse {
-                    success: false,
-                    message: format!("Failed to load config: {}", e),
-                    old_policy: "unknown".to_string(),
-                    new_policy: "unknown".to_string(),
-                }),
+    let new_policy = match request.default_policy.as_deref().map(str::to_lowercase) {
+        None => None,
+        Some(policy) if policy == "allow" => Some(Action::Allow),
+        Some(policy) if policy == "block" => Some(Action::Block),
+        Some(_) => {
+            return failure(
+                StatusCode::BAD_REQUEST,
+                "Invalid policy. Must be 'allow' or 'block'".to_string(),
             );
         }
     };
 
-    let old_policy_str = match config.global.default_policy {
-        Action::Allow => "allow",
-        Action::Block => "block",
-    };
+    let old_policy = policy_name(&engine.current_config().global.default_policy);
+    if let Some(new_policy) = &new_policy {
+        let mut config = match load_current_config(&state).await {
+            Ok(c) => c,
+            Err(e) => {
+                return failure(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to load config: {}", e),
+                );
+            }
+        };
+        config.global.default_policy = new_policy.clone();
 
-    config.global.default_policy = new_policy;
+        if let Err(e) = save_and_reload(&state, config).await {
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save config: {}", e),
+            );
+        }
 
-    if let Err(e) = save_and_reload(&state, config).await {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(UpdateGlobalSettingsResponse {
-                success: false,
-                message: format!("Failed to save config: {}", e),
-                old_policy: old_policy_str.to_string(),
-                new_policy: request.default_policy,
-            }),
+        // Recorded with the access log, next to the decisions it changes
+        info!(
+            target: ACCESS_LOG_TARGET,
+            stage = "acl_policy",
+            outcome = policy_name(new_policy),
+            old_policy,
+            by = caller.as_ref().map(|Extension(caller)| caller.0.as_str()).unwrap_or("-"),
+            "Global ACL policy changed via API"
         );
     }
 
-    info!(
-        old_policy = old_policy_str,
-        new_policy = request.default_policy,
-        "Updated global ACL policy via API"
-    );
+    let old_mode = match new_mode {
+        Some(mode) => {
+            let old_mode = engine.set_mode(mode);
+            if old_mode != mode {
+                // Recorded with the access log, next to the decisions it changes
+                info!(
+                    target: ACCESS_LOG_TARGET,
+                    stage = "acl_mode",
+                    outcome = mode.as_str(),
+                    old_mode = old_mode.as_str(),
+                    by = caller.as_ref().map(|Extension(caller)| caller.0.as_str()).unwrap_or("-"),
+                    "ACL mode changed via API"
+                );
+            }
+            old_mode
+        }
+        None => engine.mode(),
+    };
 
+    let message = match (new_policy, new_mode) {
+        (Some(_), Some(_)) => "Global policy and ACL mode updated",
+        (Some(_), None) => "Global policy updated",
+        _ => "ACL mode updated",
+    };
     (
         StatusCode::OK,
         Json(UpdateGlobalSettingsResponse {
             success: true,
-            message: "Global policy updated".to_string(),
-            old_policy: old_policy_str.to_string(),
-            new_policy: request.default_policy,
+            message: message.to_string(),
+            old_policy: old_policy.to_string(),
+            new_policy: policy_name(&engine.current_config().global.default_policy).to_string(),
+            old_mode: old_mode.as_str().to_string(),
+            new_mode: engine.mode().as_str().to_string(),
         }),
     )
 }
@@ -1075,6 +1298,7 @@ pub async fn search_rules(
         destination: request.destination,
         port: request.port,
         action: request.action,
+        log: request.log,
     };
 
     let results = crud::search_rules(&config, &criteria);
@@ -1376,3 +1600,153 @@ pub async fn remove_user_from_group(
         }),
     )
 }
+
+// ============================================================================
+// Import & Export
+// ============================================================================
+
+/// Whether an import body is TOML; anything without a TOML content type is read as JSON
+fn is_toml_body(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    [
+        "application/toml",
+        "text/toml",
+        "application/x-toml",
+        "text/x-toml",
+    ]
+    .iter()
+    .any(|toml| mime.eq_ignore_ascii_case(toml))
+}
+
+/// POST /api/acl/import - Replace the whole ACL configuration
+///
+/// The body is a complete ACL config as JSON, or as TOML with a TOML content type.
+/// It goes through the checks a reload runs (validation, lint errors, rule
+/// compilation). With `dry_run=true` only the verdict and a diff against the live
+/// configuration come back; otherwise the ACL file is replaced atomically and the
+/// engine reloaded.
+pub async fn import_acl_config(
+    State(state): State<ApiState>,
+    Query(query): Query<AclImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> (StatusCode, Json<AclImportResponse>) {
+    let dry_run = query.dry_run;
+    let rejected = |status: StatusCode, message: String| {
+        (
+            status,
+            Json(AclImportResponse {
+                success: false,
+                dry_run,
+                applied: false,
+                message,
+                findings: Vec::new(),
+                diff: None,
+            }),
+        )
+    };
+
+    let Some(acl_engine) = state.acl_engine.clone() else {
+        return rejected(StatusCode::BAD_REQUEST, "ACL is not enabled".to_string());
+    };
+
+    let parsed = if is_toml_body(&headers) {
+        toml::from_str::<AclConfig>(&body).map_err(|e| format!("Invalid TOML ACL config: {}", e))
+    } else {
+        serde_json::from_str::<AclConfig>(&body)
+            .map_err(|e| format!("Invalid JSON ACL config: {}", e))
+    };
+    let config = match parsed {
+        Ok(config) => config,
+        Err(e) => return rejected(StatusCode::BAD_REQUEST, e),
+    };
+
+    // Compiling a large policy stays off the runtime, as on reload
+    let checked = {
+        let acl_engine = acl_engine.clone();
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || acl_engine.check_config(&config)).await
+    };
+    let findings = match checked {
+        Ok(Ok(findings)) => findings,
+        Ok(Err(e)) => {
+            return rejected(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("ACL config rejected: {}", e),
+            )
+        }
+        Err(e) => {
+            return rejected(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("ACL check task failed: {}", e),
+            )
+        }
+    };
+
+    let diff = crate::acl::diff::diff(&acl_engine.current_config(), &config);
+
+    if dry_run {
+        return (
+            StatusCode::OK,
+            Json(AclImportResponse {
+                success: true,
+                dry_run,
+                applied: false,
+                message: "ACL config is valid; nothing was applied".to_string(),
+                findings,
+                diff: Some(diff),
+            }),
+        );
+    }
+
+    if let Err(e) = save_and_reload(&state, config).await {
+        error!("Failed to import ACL config: {}", e);
+        return rejected(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to apply ACL config: {}", e),
+        );
+    }
+
+    info!(
+        rules_added = diff.rules_added,
+        rules_removed = diff.rules_removed,
+        rules_changed = diff.rules_changed,
+        "Imported ACL config via API"
+    );
+
+    (
+        StatusCode::OK,
+        Json(AclImportResponse {
+            success: true,
+            dry_run,
+            applied: true,
+            message: "ACL config imported".to_string(),
+            findings,
+            diff: Some(diff),
+        }),
+    )
+}
+
+/// GET /api/acl/export - Live ACL configuration as TOML
+pub async fn export_acl_config(
+    State(state): State<ApiState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let Some(ref acl_engine) = state.acl_engine else {
+        return Err((StatusCode::BAD_REQUEST, "ACL is not enabled".to_string()));
+    };
+
+    let content = toml::to_string_pretty(&*acl_engine.current_config()).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to serialize ACL config: {}", e),
+        )
+    })?;
+
+    Ok(([(header::CONTENT_TYPE, "application/toml")], content))
+}
@@ -0,0 +1,304 @@
+use crate::acl::{AclDecision, Protocol};
+use crate::api::handlers::sessions::ApiState;
+use crate::api::types::{
+    AdmissionGateResult, AdmissionRejectionsQuery, AdmissionTestRequest, AdmissionTestResponse,
+};
+use crate::protocol::{Address, ReplyCode};
+use crate::server::{SpecialNameDecision, SpecialNamesPolicy};
+use crate::session::AdmissionRejection;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures::Stream;
+use serde_json::json;
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+
+const STATUS_PASS: &str = "pass";
+const STATUS_FAIL: &str = "fail";
+const STATUS_NOT_CONFIGURED: &str = "not_configured";
+
+/// Gates other proxies run at admission time that RustSocks does not implement, listed in
+/// every verdict so a clean result is not read as covering them.
+const NOT_EVALUATED_GATES: [&str; 5] = [
+    "maintenance_mode",
+    "allowed_commands",
+    "rate_limiter",
+    "quota",
+    "fan_out",
+];
+
+/// POST /api/admission/test - Dry-run the full admission pipeline for a connection
+///
+/// Gates are evaluated in the same order as the SOCKS handler (connection limits,
+/// special-use names, ACL). Every gate is reported even after the first failure so
+/// operators see the whole picture; nothing is counted, reserved or recorded.
+///
+/// Groups are taken from the request as-is. The handler gets them from authentication
+/// (PAM/LDAP), which a dry run cannot perform, so callers must pass the groups they
+/// expect the user to have.
+pub async fn test_admission(
+    State(state): State<ApiState>,
+    Json(request): Json<AdmissionTestRequest>,
+) -> (StatusCode, Json<AdmissionTestResponse>) {
+    let command = request.command.to_lowercase();
+    let is_udp_associate = match command.as_str() {
+        "connect" | "bind" => false,
+        "udp_associate" => true,
+        _ => {
+            return invalid_request(
+                request,
+                "Invalid command (use: connect, bind, or udp_associate)",
+            )
+        }
+    };
+
+    let protocol_name = match request.protocol.as_deref().map(str::to_lowercase) {
+        Some(protocol) => protocol,
+        None if is_udp_associate => "udp".to_string(),
+        None => "tcp".to_string(),
+    };
+    let protocol = match protocol_name.as_str() {
+        "tcp" => Protocol::Tcp,
+        "udp" => Protocol::Udp,
+        _ => return invalid_request(request, "Invalid protocol (use: tcp or udp)"),
+    };
+
+    let source = match request
+        .source
+        .as_deref()
+        .map(str::parse::<std::net::IpAddr>)
+    {
+        None => None,
+        Some(Ok(ip)) => Some(ip),
+        Some(Err(_)) => return invalid_request(request, "Invalid source (use an IP address)"),
+    };
+
+    let address = match request.destination.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(ipv4)) => Address::IPv4(ipv4.octets()),
+        Ok(std::net::IpAddr::V6(ipv6)) => Address::IPv6(ipv6.octets()),
+        Err(_) => Address::Domain(request.destination.clone().into()),
+    };
+
+    let config = &state.config_snapshot;
+    let mut gates = Vec::with_capacity(3);
+    let mut failure: Option<(String, ReplyCode)> = None;
+
+    // Gate 1: connection limits (QoS), as changed by any config reload
+    let limits = match &state.config_reloader {
+        Some(reloader) => reloader.connection_limits(),
+        None => config.qos.connection_limits.clone(),
+    };
+    let limits_details = json!({
+        "user_connections": state.qos_engine.get_user_connections(&request.user),
+        "max_connections_per_user": limits.max_connections_per_user,
+        "total_connections": state.qos_engine.get_total_connections(),
+        "max_connections_global": limits.max_connections_global,
+    });
+    let gate = if !state.qos_engine.is_enabled() {
+        gate_result("connection_limits", STATUS_NOT_CONFIGURED, None, None)
+    } else {
+        match state
+            .qos_engine
+            .check_connection_limit(&request.user, &limits)
+        {
+            Ok(()) => gate_result("connection_limits", STATUS_PASS, None, Some(limits_details)),
+            Err(e) => {
+                record_failure(
+                    &mut failure,
+                    "connection_limits",
+                    ReplyCode::ConnectionNotAllowed,
+                );
+                gate_result(
+                    "connection_limits",
+                    STATUS_FAIL,
+                    Some(e.to_string()),
+                    Some(limits_details),
+                )
+            }
+        }
+    };
+    gates.push(gate);
+
+    // Gate 2: special-use names (UDP ASSOCIATE checks them per datagram, not here)
+    let gate = if is_udp_associate {
+        gate_result(
+            "special_names",
+            STATUS_NOT_CONFIGURED,
+            Some("Checked per datagram by the UDP relay".to_string()),
+            None,
+        )
+    } else {
+        let policy = SpecialNamesPolicy::from(&config.resolver.special_names);
+        match policy.check(&address) {
+            SpecialNameDecision::Allow => gate_result("special_names", STATUS_PASS, None, None),
+            SpecialNameDecision::Block(category) => {
+                record_failure(&mut failure, "special_names", category.reply_code());
+                gate_result(
+                    "special_names",
+                    STATUS_FAIL,
+                    Some(format!("special-name:{}", category)),
+                    Some(json!({ "category": category.as_str() })),
+                )
+            }
+        }
+    };
+    gates.push(gate);
+
+    // Gate 3: ACL
+    let gate = match state.acl_engine {
+        None => gate_result("acl", STATUS_NOT_CONFIGURED, None, None),
+        Some(ref engine) => {
+            let (decision, matched_rule) = engine
+                .dry_run_with_groups(
+                    &request.user,
+                    &request.groups,
+                    &address,
+                    request.port,
+                    &protocol,
+                    source,
+                )
+                .await;
+            match decision {
+                AclDecision::Allow => gate_result("acl", STATUS_PASS, matched_rule, None),
+                AclDecision::Block => {
+                    record_failure(&mut failure, "acl", ReplyCode::ConnectionNotAllowed);
+                    gate_result("acl", STATUS_FAIL, matched_rule, None)
+                }
+            }
+        }
+    };
+    gates.push(gate);
+
+    let (failed_gate, reply_code) = match failure {
+        Some((gate, code)) => (Some(gate), code),
+        None => (None, ReplyCode::Succeeded),
+    };
+
+    let response = AdmissionTestResponse {
+        user: request.user,
+        groups: request.groups,
+        destination: request.destination,
+        port: request.port,
+        protocol: protocol_name,
+        command,
+        admitted: failed_gate.is_none(),
+        failed_gate,
+        reply_code: reply_code as u8,
+        reply: reply_code.to_string(),
+        gates,
+        not_evaluated: not_evaluated_gates(),
+    };
+
+    (StatusCode::OK, Json(response))
+}
+
+/// GET /api/admission/rejections - Recent connection-limit rejections, newest first
+pub async fn get_admission_rejections(
+    State(state): State<ApiState>,
+    Query(query): Query<AdmissionRejectionsQuery>,
+) -> (StatusCode, Json<Vec<AdmissionRejection>>) {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let rejections = state
+        .session_manager
+        .admission()
+        .recent(query.user.as_deref(), limit);
+
+    (StatusCode::OK, Json(rejections))
+}
+
+/// GET /api/admission/rejections/stream - Live feed of rejections as server-sent events
+///
+/// Each rejection is sent as an `admission_rejection` event carrying the same JSON as the
+/// list endpoint. A subscriber that falls behind gets a `lagged` event with the number
+/// of rejections it missed; those are still in the recent-rejections list.
+pub async fn stream_admission_rejections(
+    State(state): State<ApiState>,
+    Query(query): Query<AdmissionRejectionsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.session_manager.admission().subscribe();
+    let user = query.user;
+
+    let stream = futures::stream::unfold(receiver, move |mut receiver| {
+        let user = user.clone();
+        async move {
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(rejection) if user.as_deref().is_some_and(|user| rejection.user != user) => {
+                        continue
+                    }
+                    Ok(rejection) => Event::default()
+                        .event("admission_rejection")
+                        .json_data(&rejection)
+                        .unwrap_or_else(|_| Event::default().comment("unserializable")),
+                    Err(RecvError::Lagged(missed)) => {
+                        Event::default().event("lagged").data(missed.to_string())
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((Ok(event), receiver));
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Only the first failing gate decides the reply, mirroring the handler's early return.
+fn record_failure(failure: &mut Option<(String, ReplyCode)>, gate: &str, code: ReplyCode) {
+    if failure.is_none() {
+        *failure = Some((gate.to_string(), code));
+    }
+}
+
+fn gate_result(
+    gate: &str,
+    status: &str,
+    reason: Option<String>,
+    details: Option<serde_json::Value>,
+) -> AdmissionGateResult {
+    AdmissionGateResult {
+        gate: gate.to_string(),
+        status: status.to_string(),
+        reason,
+        details,
+    }
+}
+
+fn invalid_request(
+    request: AdmissionTestRequest,
+    reason: &str,
+) -> (StatusCode, Json<AdmissionTestResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(AdmissionTestResponse {
+            user: request.user,
+            groups: request.groups,
+            destination: request.destination,
+            port: request.port,
+            protocol: request.protocol.unwrap_or_default(),
+            command: request.command,
+            admitted: false,
+            failed_gate: Some("request".to_string()),
+            reply_code: ReplyCode::GeneralFailure as u8,
+            reply: ReplyCode::GeneralFailure.to_string(),
+            gates: vec![gate_result(
+                "request",
+                STATUS_FAIL,
+                Some(reason.to_string()),
+                None,
+            )],
+            not_evaluated: not_evaluated_gates(),
+        }),
+    )
+}
+
+fn not_evaluated_gates() -> Vec<String> {
+    NOT_EVALUATED_GATES
+        .iter()
+        .map(|gate| gate.to_string())
+        .collect()
+}
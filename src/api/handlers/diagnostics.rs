@@ -5,7 +5,20 @@ use tokio::net::TcpStream;
 use tokio::time::{timeout, Instant};
 
 use crate::api::handlers::sessions::ApiState;
-use crate::api::types::{ConnectivityTestRequest, ConnectivityTestResponse};
+use crate::api::types::{ConnectivityTestRequest, ConnectivityTestResponse, ProbesResponse};
+
+/// GET /api/diagnostics/probes - status and recent results of `[[diagnostics.probes]]`
+pub async fn get_probes(
+    State(state): State<ApiState>,
+) -> axum::response::Result<Json<ProbesResponse>> {
+    let Some(monitor) = state.probe_monitor.as_ref() else {
+        return Err((StatusCode::NOT_FOUND, "Connectivity probes are not running").into());
+    };
+    Ok(Json(ProbesResponse {
+        user: monitor.user().to_string(),
+        probes: monitor.statuses(),
+    }))
+}
 
 /// POST /api/diagnostics/connectivity - test TCP connectivity to a destination
 pub async fn test_tcp_connectivity(
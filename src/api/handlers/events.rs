@@ -0,0 +1,127 @@
+use crate::api::handlers::sessions::ApiState;
+use crate::api::types::SessionEventSubscription;
+use crate::session::{DestHostPattern, SessionEventFilter, SessionManager};
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+
+/// How long a client has to send its subscription after the upgrade
+const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// GET /api/ws - Live session events over a WebSocket
+///
+/// The client's first message is a JSON [`SessionEventSubscription`] (`{}` for every
+/// event); the server confirms it with a `subscribed` message and then sends one JSON
+/// message per session event: `session_started`, `session_closed`, `traffic_update`,
+/// `acl_blocked`, `qos_throttled` and `admission_rejected`. A client that falls behind gets a `lagged`
+/// message with the number of events it missed; the proxy never waits for it.
+pub async fn session_events_ws(State(state): State<ApiState>, ws: WebSocketUpgrade) -> Response {
+    let session_manager = state.session_manager.clone();
+    ws.on_upgrade(move |socket| stream_session_events(socket, session_manager))
+}
+
+async fn stream_session_events(mut socket: WebSocket, session_manager: Arc<SessionManager>) {
+    let (subscription, filter) = match read_subscription(&mut socket).await {
+        Ok(subscribed) => subscribed,
+        Err(Some(reason)) => {
+            let _ = send_json(&mut socket, &json!({"type": "error", "message": reason})).await;
+            let _ = socket
+                .send(Message::Close(Some(CloseFrame {
+                    code: close_code::POLICY,
+                    reason: "invalid subscription".into(),
+                })))
+                .await;
+            return;
+        }
+        Err(None) => return,
+    };
+
+    // Subscribed before the confirmation, so nothing after it is missed
+    let mut receiver = session_manager.events().subscribe();
+    let confirmation = json!({
+        "type": "subscribed",
+        "user": subscription.user,
+        "destination": subscription.destination,
+    });
+    if send_json(&mut socket, &confirmation).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            event = receiver.recv() => {
+                let sent = match event {
+                    Ok(event) if !filter.matches(&event) => continue,
+                    Ok(event) => send_json(&mut socket, &event).await,
+                    Err(RecvError::Lagged(missed)) => {
+                        send_json(&mut socket, &json!({"type": "lagged", "missed": missed})).await
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if sent.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                // Pings are answered by the WebSocket layer; anything else is ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    debug!("Session event subscriber disconnected");
+}
+
+/// Wait for the client's subscription. `Err(None)` when the client went away first.
+async fn read_subscription(
+    socket: &mut WebSocket,
+) -> Result<(SessionEventSubscription, SessionEventFilter), Option<String>> {
+    let text = tokio::time::timeout(SUBSCRIBE_TIMEOUT, async {
+        loop {
+            match socket.recv().await {
+                Some(Ok(Message::Text(text))) => return Ok(text),
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => continue,
+                Some(Ok(Message::Binary(_))) => {
+                    return Err(Some("subscription must be a JSON text message".to_string()))
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Err(None),
+            }
+        }
+    })
+    .await
+    .map_err(|_| {
+        Some(format!(
+            "no subscription within {} seconds",
+            SUBSCRIBE_TIMEOUT.as_secs()
+        ))
+    })??;
+
+    let subscription: SessionEventSubscription = serde_json::from_str(text.as_str())
+        .map_err(|e| Some(format!("invalid subscription: {}", e)))?;
+    let destination = subscription
+        .destination
+        .as_deref()
+        .map(DestHostPattern::parse)
+        .transpose()
+        .map_err(Some)?;
+    let filter = SessionEventFilter {
+        user: subscription.user.clone(),
+        destination,
+    };
+    Ok((subscription, filter))
+}
+
+async fn send_json<T: Serialize>(socket: &mut WebSocket, value: &T) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(value).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text.into())).await
+}
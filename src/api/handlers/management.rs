@@ -1,7 +1,24 @@
+use crate::acl::{
+    generate_acl_example, AclExampleVariant, AclReloadTrigger, LintSeverity, ObservedFlow,
+    ACL_TEMPLATE_VERSION,
+};
 use crate::api::handlers::sessions::ApiState;
-use crate::api::types::{AclTestRequest, AclTestResponse, HealthResponse};
+use crate::api::types::{
+    AclExampleQuery, AclExampleResponse, AclLintResponse, AclReloadStatusEntry,
+    AclReloadStatusResponse, AclRuleStatsEntry, AclRuleStatsResetResponse, AclRuleStatsResponse,
+    AclRuleUserHits, AclTestExplanation, AclTestRequest, AclTestResponse, AclTraceRule,
+    AddressCacheInvalidateRequest, AddressCacheInvalidateResponse, ComponentState, ComponentStatus,
+    DnsFlushResponse, HealthResponse, OverloadModeRequest, ReadinessResponse, UnusedAclRule,
+    UnusedAclRulesQuery, UnusedAclRulesResponse, UnusedRuleStatus, VersionResponse,
+};
 use crate::config::Config;
-use axum::{extract::State, http::StatusCode, Json};
+use crate::server::OverloadStatus;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::ffi::OsString;
 use std::process::{self, Command};
@@ -10,15 +27,178 @@ use tokio::fs;
 use tokio::time::{sleep, Duration};
 use tracing::{info, warn};
 
-/// GET /health - Health check endpoint
-pub async fn health_check(State(state): State<ApiState>) -> (StatusCode, Json<HealthResponse>) {
-    let response = HealthResponse {
+/// How long the readiness check waits for the session store to answer
+#[cfg(feature = "database")]
+const READINESS_STORE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// GET /health and GET /health/live - Liveness: the process is up and serving the API
+///
+/// Always 200 while the API answers, graceful shutdown included: a failing liveness
+/// probe would get the process killed mid-drain. Draining is reported by
+/// [`readiness_check`]; here only `remaining_sessions` shows it.
+pub async fn health_check(State(state): State<ApiState>) -> Json<HealthResponse> {
+    let draining = state.session_manager.is_shutting_down();
+    Json(HealthResponse {
         status: "healthy".to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         uptime_seconds: state.start_time.elapsed().as_secs(),
+        instance_id: crate::session::instance_id().to_string(),
+        overload: state.overload.as_ref().map(|shedder| shedder.status()),
+        remaining_sessions: draining.then(|| state.session_manager.active_session_count()),
+        listening: state
+            .bound_addresses
+            .as_ref()
+            .map(|bound| bound.get().iter().map(ToString::to_string).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// GET /api/version - Build and runtime information, for telling apart what runs where
+pub async fn get_version(State(state): State<ApiState>) -> Json<VersionResponse> {
+    use crate::utils::build_info;
+
+    Json(VersionResponse {
+        version: build_info::VERSION.to_string(),
+        git_hash: build_info::GIT_HASH.to_string(),
+        build_timestamp: build_info::build_time(),
+        rustc_version: build_info::RUSTC_VERSION.to_string(),
+        features: build_info::enabled_features()
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        uptime_seconds: state.start_time.elapsed().as_secs(),
+        active_sessions: state.session_manager.active_session_count(),
+        config_path: state
+            .config_path
+            .as_ref()
+            .map(|path| path.display().to_string()),
+        subsystems: build_info::Subsystems::from_config(&state.config_snapshot),
+    })
+}
+
+/// GET /health/ready - Readiness: everything a SOCKS client depends on is up
+///
+/// Checks that the SOCKS listener is bound, the ACL is loaded (when enabled), the
+/// session store answers (when sessions are stored in a database) and the QoS engine
+/// exists (when enabled). Answers 503 when any of them is down or while draining.
+pub async fn readiness_check(
+    State(state): State<ApiState>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let config = &state.config_snapshot;
+    let mut components = std::collections::BTreeMap::new();
+
+    let listener = match state.bound_addresses.as_ref().map(|bound| bound.get()) {
+        Some(addresses) if !addresses.is_empty() => component(
+            ComponentState::Up,
+            Some(
+                addresses
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+        ),
+        Some(_) => component(ComponentState::Down, Some("not bound yet".to_string())),
+        None => component(
+            ComponentState::Down,
+            Some("no SOCKS listener in this process".to_string()),
+        ),
     };
+    components.insert("socks_listener".to_string(), listener);
 
-    (StatusCode::OK, Json(response))
+    let acl = if !config.acl.enabled {
+        component(ComponentState::Disabled, None)
+    } else if state.acl_engine.is_some() {
+        component(ComponentState::Up, None)
+    } else {
+        component(ComponentState::Down, Some("ACL not loaded".to_string()))
+    };
+    components.insert("acl".to_string(), acl);
+
+    let session_store = if !config.sessions.enabled || !config.sessions.uses_database() {
+        component(ComponentState::Disabled, None)
+    } else {
+        session_store_status(&state).await
+    };
+    components.insert("session_store".to_string(), session_store);
+
+    let qos = if !config.qos.enabled {
+        component(ComponentState::Disabled, None)
+    } else if matches!(*state.qos_engine, crate::qos::QosEngine::None) {
+        component(ComponentState::Down, Some("not initialized".to_string()))
+    } else {
+        component(ComponentState::Up, None)
+    };
+    components.insert("qos".to_string(), qos);
+
+    let draining = state.session_manager.is_shutting_down();
+    let ready = components
+        .values()
+        .all(|c| c.status != ComponentState::Down);
+    let (code, status) = match (draining, ready) {
+        (true, _) => (StatusCode::SERVICE_UNAVAILABLE, "draining"),
+        (false, true) => (StatusCode::OK, "ready"),
+        (false, false) => (StatusCode::SERVICE_UNAVAILABLE, "not_ready"),
+    };
+    (
+        code,
+        Json(ReadinessResponse {
+            status: status.to_string(),
+            components,
+        }),
+    )
+}
+
+fn component(status: ComponentState, detail: Option<String>) -> ComponentStatus {
+    ComponentStatus { status, detail }
+}
+
+#[cfg(feature = "database")]
+async fn session_store_status(state: &ApiState) -> ComponentStatus {
+    let Some(store) = state.session_store.as_ref() else {
+        return component(ComponentState::Down, Some("not connected".to_string()));
+    };
+    match tokio::time::timeout(READINESS_STORE_TIMEOUT, store.ping()).await {
+        Ok(Ok(())) => component(ComponentState::Up, None),
+        Ok(Err(e)) => component(ComponentState::Down, Some(e.to_string())),
+        Err(_) => component(
+            ComponentState::Down,
+            Some(format!(
+                "no answer within {}s",
+                READINESS_STORE_TIMEOUT.as_secs()
+            )),
+        ),
+    }
+}
+
+#[cfg(not(feature = "database"))]
+async fn session_store_status(_state: &ApiState) -> ComponentStatus {
+    component(
+        ComponentState::Down,
+        Some("built without database support".to_string()),
+    )
+}
+
+/// GET /api/admin/overload - Current load shedding state
+pub async fn get_overload_status(
+    State(state): State<ApiState>,
+) -> axum::response::Result<Json<OverloadStatus>> {
+    let Some(ref shedder) = state.overload else {
+        return Err((StatusCode::BAD_REQUEST, "server.overload is not enabled").into());
+    };
+    Ok(Json(shedder.status()))
+}
+
+/// PUT /api/admin/overload - Force shedding on or off, or return to automatic
+pub async fn set_overload_mode(
+    State(state): State<ApiState>,
+    Json(request): Json<OverloadModeRequest>,
+) -> axum::response::Result<Json<OverloadStatus>> {
+    let Some(ref shedder) = state.overload else {
+        return Err((StatusCode::BAD_REQUEST, "server.overload is not enabled").into());
+    };
+    shedder.set_mode(request.mode);
+    Ok(Json(shedder.status()))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -27,6 +207,67 @@ pub struct ReloadResponse {
     pub message: String,
 }
 
+/// POST /api/auth/address-cache/invalidate - Drop cached pam.address verdicts
+///
+/// Removes the entry for `ip` when given, otherwise flushes the whole cache.
+pub async fn invalidate_address_cache(
+    State(state): State<ApiState>,
+    request: Option<Json<AddressCacheInvalidateRequest>>,
+) -> axum::response::Result<(StatusCode, Json<AddressCacheInvalidateResponse>)> {
+    let Some(ref gate) = state.address_gate else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "pam.address authentication is not enabled",
+        )
+            .into());
+    };
+
+    let ip = match request.and_then(|Json(request)| request.ip) {
+        Some(ip) => Some(ip.trim().parse::<std::net::IpAddr>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid IP address: {}", ip),
+            )
+        })?),
+        None => None,
+    };
+
+    let removed = gate.invalidate(ip);
+    info!(ip = ?ip, removed, "pam.address cache invalidated via API");
+
+    Ok((
+        StatusCode::OK,
+        Json(AddressCacheInvalidateResponse {
+            removed,
+            stats: gate.stats(),
+        }),
+    ))
+}
+
+/// POST /api/admin/flush-dns - Drop every cached destination lookup
+pub async fn flush_dns_cache(
+    State(state): State<ApiState>,
+) -> axum::response::Result<(StatusCode, Json<DnsFlushResponse>)> {
+    let Some(ref cache) = state.dns_cache else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "DNS cache is not enabled (server.dns.cache_ttl_secs = 0)",
+        )
+            .into());
+    };
+
+    let flushed = cache.flush();
+    info!(flushed, "DNS cache flushed via API");
+
+    Ok((
+        StatusCode::OK,
+        Json(DnsFlushResponse {
+            flushed,
+            stats: cache.stats(),
+        }),
+    ))
+}
+
 /// POST /api/admin/reload-acl - Reload ACL configuration
 pub async fn reload_acl(State(state): State<ApiState>) -> (StatusCode, Json<ReloadResponse>) {
     // Check if ACL is enabled
@@ -54,6 +295,7 @@ pub async fn reload_acl(State(state): State<ApiState>) -> (StatusCode, Json<Relo
     let new_config = match crate::acl::load_acl_config(config_path).await {
         Ok(config) => config,
         Err(e) => {
+            acl_engine.record_reload(AclReloadTrigger::Api, &Err(e.clone()));
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ReloadResponse {
@@ -65,7 +307,9 @@ pub async fn reload_acl(State(state): State<ApiState>) -> (StatusCode, Json<Relo
     };
 
     // Reload ACL engine
-    match acl_engine.reload(new_config).await {
+    let result = acl_engine.reload(new_config).await;
+    acl_engine.record_reload(AclReloadTrigger::Api, &result);
+    match result {
         Ok(()) => (
             StatusCode::OK,
             Json(ReloadResponse {
@@ -83,6 +327,73 @@ pub async fn reload_acl(State(state): State<ApiState>) -> (StatusCode, Json<Relo
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ConfigReloadResponse {
+    pub success: bool,
+    pub message: String,
+    /// Changed settings now in effect
+    pub applied: Vec<String>,
+    /// Changed settings that need a restart to take effect
+    pub requires_restart: Vec<String>,
+}
+
+impl ConfigReloadResponse {
+    fn failed(message: String) -> Self {
+        Self {
+            success: false,
+            message,
+            applied: Vec::new(),
+            requires_restart: Vec::new(),
+        }
+    }
+}
+
+/// POST /api/admin/reload-config - Re-read the config file and apply what can
+/// change without a restart (same as SIGHUP)
+pub async fn reload_config(
+    State(state): State<ApiState>,
+) -> (StatusCode, Json<ConfigReloadResponse>) {
+    let Some(ref reloader) = state.config_reloader else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ConfigReloadResponse::failed(
+                "Config reload is not available".to_string(),
+            )),
+        );
+    };
+    if reloader.path().is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ConfigReloadResponse::failed(
+                "Server was started without a config file path; nothing to reload".to_string(),
+            )),
+        );
+    }
+
+    match reloader.reload().await {
+        Ok(report) => (
+            StatusCode::OK,
+            Json(ConfigReloadResponse {
+                success: true,
+                message: if report.requires_restart.is_empty() {
+                    "Configuration reloaded".to_string()
+                } else {
+                    "Configuration reloaded; some changes require a restart".to_string()
+                },
+                applied: report.applied,
+                requires_restart: report.requires_restart,
+            }),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ConfigReloadResponse::failed(format!(
+                "Failed to reload configuration: {}",
+                e
+            ))),
+        ),
+    }
+}
+
 #[derive(Serialize)]
 pub struct ConfigFileResponse {
     pub path: Option<String>,
@@ -212,6 +523,8 @@ pub async fn update_config_file(
 
 #[derive(Serialize)]
 pub struct RuntimeConfigResponse {
+    /// Random per boot; matches `instance_id` on sessions created by this process
+    pub instance_id: String,
     pub path: Option<String>,
     pub editable: bool,
     pub server: ServerRuntimeConfig,
@@ -285,6 +598,7 @@ pub async fn get_runtime_config(
     State(state): State<ApiState>,
 ) -> (StatusCode, Json<RuntimeConfigResponse>) {
     let response = RuntimeConfigResponse {
+        instance_id: crate::session::instance_id().to_string(),
         path: state.config_path.as_ref().map(|p| p.display().to_string()),
         editable: state.config_path.is_some(),
         server: map_server_config(&state.config_snapshot),
@@ -517,6 +831,205 @@ pub async fn get_acl_rules(State(state): State<ApiState>) -> (StatusCode, Json<A
     (StatusCode::OK, Json(response))
 }
 
+const DEFAULT_UNUSED_RULE_DAYS: u32 = 90;
+
+/// GET /api/acl/example - Annotated example ACL file
+///
+/// The from-learning variant is built from the allowed sessions the server still
+/// tracks (active, and closed ones within session retention).
+pub async fn get_acl_example(
+    State(state): State<ApiState>,
+    Query(query): Query<AclExampleQuery>,
+) -> Result<Json<AclExampleResponse>, (StatusCode, String)> {
+    let observed: Vec<ObservedFlow> = if query.variant == AclExampleVariant::FromLearning {
+        state
+            .session_manager
+            .get_all_sessions()
+            .await
+            .iter()
+            .filter_map(ObservedFlow::from_session)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if query.variant == AclExampleVariant::FromLearning && observed.is_empty() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "No allowed traffic has been observed yet".to_string(),
+        ));
+    }
+
+    let content = generate_acl_example(query.variant, &observed)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(AclExampleResponse {
+        variant: query.variant.to_string(),
+        template_version: ACL_TEMPLATE_VERSION,
+        content,
+    }))
+}
+
+/// GET /api/acl/lint - Orphaned groups, unknown group references and redundant rules
+/// in the ACL file on disk
+pub async fn get_acl_lint(
+    State(state): State<ApiState>,
+) -> Result<Json<AclLintResponse>, (StatusCode, String)> {
+    let Some(ref acl_engine) = state.acl_engine else {
+        return Err((StatusCode::BAD_REQUEST, "ACL is not enabled".to_string()));
+    };
+    let Some(ref config_path) = state.acl_config_path else {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ACL config path not set".to_string(),
+        ));
+    };
+
+    let config = crate::acl::load_config(config_path)
+        .await
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let findings = acl_engine.lint(&config);
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == LintSeverity::Error)
+        .count();
+
+    Ok(Json(AclLintResponse {
+        strict_references: acl_engine.lint_settings().strict_references,
+        errors,
+        warnings: findings.len() - errors,
+        findings,
+    }))
+}
+
+/// GET /api/acl/reload-status - Outcome of the latest reload from the ACL file
+pub async fn get_acl_reload_status(
+    State(state): State<ApiState>,
+) -> Result<Json<AclReloadStatusResponse>, (StatusCode, String)> {
+    let Some(ref acl_engine) = state.acl_engine else {
+        return Err((StatusCode::BAD_REQUEST, "ACL is not enabled".to_string()));
+    };
+
+    let watching = state.config_snapshot.acl.watch;
+    let last_reload = acl_engine.last_reload().map(|status| AclReloadStatusEntry {
+        timestamp: status.at.to_rfc3339(),
+        trigger: status.trigger.as_str().to_string(),
+        success: status.success,
+        error: status.error,
+    });
+
+    Ok(Json(AclReloadStatusResponse {
+        watching,
+        last_reload,
+    }))
+}
+
+/// GET /api/acl/rules/unused - Rules without matches in the last `days` days
+pub async fn get_unused_acl_rules(
+    State(state): State<ApiState>,
+    Query(query): Query<UnusedAclRulesQuery>,
+) -> Result<Json<UnusedAclRulesResponse>, (StatusCode, String)> {
+    let Some(ref acl_engine) = state.acl_engine else {
+        return Err((StatusCode::BAD_REQUEST, "ACL is not enabled".to_string()));
+    };
+
+    let days = query.days.unwrap_or(DEFAULT_UNUSED_RULE_DAYS);
+    if days == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "days must be greater than 0".to_string(),
+        ));
+    }
+    let since = Utc::now() - chrono::Duration::days(i64::from(days));
+
+    let rules = acl_engine
+        .rule_usage()
+        .await
+        .into_iter()
+        .filter_map(|usage| {
+            let status = unused_rule_status(usage.last_matched_at, usage.tracked_since, since)?;
+            Some(UnusedAclRule {
+                rule_id: usage.rule_id,
+                scope: usage.scope,
+                description: usage.description,
+                action: match usage.action {
+                    crate::acl::Action::Allow => "allow".to_string(),
+                    crate::acl::Action::Block => "block".to_string(),
+                },
+                status,
+                hits: usage.hits,
+                last_matched_at: usage.last_matched_at,
+                tracked_since: usage.tracked_since,
+            })
+        })
+        .collect();
+
+    Ok(Json(UnusedAclRulesResponse { days, since, rules }))
+}
+
+/// GET /api/acl/rules/stats - Hit counters of every configured rule, most hits first
+pub async fn get_acl_rule_stats(
+    State(state): State<ApiState>,
+) -> Result<Json<AclRuleStatsResponse>, (StatusCode, String)> {
+    let Some(ref acl_engine) = state.acl_engine else {
+        return Err((StatusCode::BAD_REQUEST, "ACL is not enabled".to_string()));
+    };
+
+    let mut rules: Vec<AclRuleStatsEntry> = acl_engine
+        .rule_usage()
+        .await
+        .into_iter()
+        .map(|usage| AclRuleStatsEntry {
+            rule_id: usage.rule_id,
+            scope: usage.scope,
+            description: usage.description,
+            action: match usage.action {
+                crate::acl::Action::Allow => "allow".to_string(),
+                crate::acl::Action::Block => "block".to_string(),
+            },
+            hits: usage.hits,
+            last_matched_at: usage.last_matched_at,
+            tracked_since: usage.tracked_since,
+            users: usage
+                .users
+                .into_iter()
+                .map(|(user, hits)| AclRuleUserHits { user, hits })
+                .collect(),
+        })
+        .collect();
+    // Stable, so rules with equal hits stay in scope and rule id order
+    rules.sort_by_key(|rule| std::cmp::Reverse(rule.hits));
+
+    Ok(Json(AclRuleStatsResponse { rules }))
+}
+
+/// POST /api/acl/rules/stats/reset - Set every rule hit counter back to zero
+pub async fn reset_acl_rule_stats(
+    State(state): State<ApiState>,
+) -> Result<Json<AclRuleStatsResetResponse>, (StatusCode, String)> {
+    let Some(ref acl_engine) = state.acl_engine else {
+        return Err((StatusCode::BAD_REQUEST, "ACL is not enabled".to_string()));
+    };
+
+    let reset = acl_engine.rule_stats().reset();
+    info!(reset, "ACL rule statistics reset via API");
+
+    Ok(Json(AclRuleStatsResetResponse { reset }))
+}
+
+/// Classify a rule against the window starting at `since`; `None` if it matched within it.
+fn unused_rule_status(
+    last_matched_at: Option<DateTime<Utc>>,
+    tracked_since: DateTime<Utc>,
+    since: DateTime<Utc>,
+) -> Option<UnusedRuleStatus> {
+    match last_matched_at {
+        Some(last) if last >= since => None,
+        Some(_) => Some(UnusedRuleStatus::Stale),
+        None if tracked_since <= since => Some(UnusedRuleStatus::NeverMatched),
+        None => Some(UnusedRuleStatus::NoData),
+    }
+}
+
 /// POST /api/acl/test - Test ACL decision for a connection
 pub async fn test_acl_decision(
     State(state): State<ApiState>,
@@ -530,8 +1043,13 @@ pub async fn test_acl_decision(
                 destination: request.destination,
                 port: request.port,
                 protocol: request.protocol,
+                source: request.source,
+                at: request.at,
                 decision: "error".to_string(),
                 matched_rule: Some("ACL is not enabled".to_string()),
+                reply_code: None,
+                resolve: None,
+                explanation: None,
             }),
         );
     };
@@ -549,8 +1067,41 @@ pub async fn test_acl_decision(
                     destination: request.destination,
                     port: request.port,
                     protocol: request.protocol,
+                    source: request.source,
+                    at: request.at,
                     decision: "error".to_string(),
                     matched_rule: Some("Invalid protocol (use: tcp, udp, or both)".to_string()),
+                    reply_code: None,
+                    resolve: None,
+                    explanation: None,
+                }),
+            );
+        }
+    };
+
+    // Parse source as the client IP address
+    let source = match request
+        .source
+        .as_deref()
+        .map(str::parse::<std::net::IpAddr>)
+    {
+        None => None,
+        Some(Ok(ip)) => Some(ip),
+        Some(Err(_)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(AclTestResponse {
+                    user: request.user,
+                    destination: request.destination,
+                    port: request.port,
+                    protocol: request.protocol,
+                    source: request.source,
+                    at: request.at,
+                    decision: "error".to_string(),
+                    matched_rule: Some("Invalid source (use an IP address)".to_string()),
+                    reply_code: None,
+                    resolve: None,
+                    explanation: None,
                 }),
             );
         }
@@ -562,13 +1113,34 @@ pub async fn test_acl_decision(
             std::net::IpAddr::V4(ipv4) => crate::protocol::Address::IPv4(ipv4.octets()),
             std::net::IpAddr::V6(ipv6) => crate::protocol::Address::IPv6(ipv6.octets()),
         },
-        Err(_) => crate::protocol::Address::Domain(request.destination.clone()),
+        Err(_) => crate::protocol::Address::Domain(request.destination.clone().into()),
     };
 
-    // Evaluate ACL
-    let (decision, matched_rule) = acl_engine
-        .evaluate(&request.user, &address, request.port, &protocol)
-        .await;
+    // Evaluate ACL; schedules are checked at the requested time, or now
+    let at = request.at.unwrap_or_else(|| acl_engine.now());
+    let (decision, matched_rule, reply_code, resolve, explanation) = if request.explain {
+        let explanation = acl_engine
+            .explain_at(&request.user, &address, request.port, &protocol, source, at)
+            .await;
+        (
+            explanation.decision.clone(),
+            explanation.matched_rule.clone(),
+            explanation.reply_code,
+            explanation.resolve,
+            Some(explanation_to_response(explanation)),
+        )
+    } else {
+        let verdict = acl_engine
+            .verdict_at(&request.user, &address, request.port, &protocol, source, at)
+            .await;
+        (
+            verdict.decision,
+            verdict.matched_rule,
+            verdict.reply_code,
+            verdict.resolve,
+            None,
+        )
+    };
 
     // Convert decision to string
     let decision_str = match decision {
@@ -581,13 +1153,48 @@ pub async fn test_acl_decision(
         destination: request.destination,
         port: request.port,
         protocol: request.protocol,
+        source: request.source,
+        at: request.at,
         decision: decision_str.to_string(),
         matched_rule,
+        reply_code: reply_code.map(|code| code.as_str().to_string()),
+        resolve: (decision == crate::acl::AclDecision::Allow).then(|| resolve.as_str().to_string()),
+        explanation,
     };
 
     (StatusCode::OK, Json(response))
 }
 
+fn explanation_to_response(explanation: crate::acl::AclExplanation) -> AclTestExplanation {
+    AclTestExplanation {
+        rules_total: explanation.rules_total,
+        stopped_at: explanation.stopped_at,
+        rules: explanation
+            .trace
+            .into_iter()
+            .map(|rule| AclTraceRule {
+                position: rule.position,
+                rule_id: rule.rule_id,
+                scope: rule.scope,
+                description: rule.description,
+                action: match rule.action {
+                    crate::acl::Action::Allow => "allow".to_string(),
+                    crate::acl::Action::Block => "block".to_string(),
+                },
+                priority: rule.priority,
+                protocol_matched: rule.outcome.protocol,
+                destination_matched: rule.outcome.destination,
+                port_matched: rule.outcome.port,
+                source_matched: rule.outcome.source,
+                schedule_matched: rule.outcome.schedule,
+                matched: rule.outcome.matched(),
+            })
+            .collect(),
+        truncated: explanation.omitted > 0,
+        omitted_rules: explanation.omitted,
+    }
+}
+
 /// GET /metrics - Prometheus metrics endpoint
 pub async fn get_metrics(State(state): State<ApiState>) -> (StatusCode, String) {
     let sessions = state.session_manager.get_all_sessions().await;
@@ -1,15 +1,25 @@
 pub mod acl_management;
+pub mod admission;
 pub mod diagnostics;
+pub mod events;
 pub mod management;
 pub mod pool;
+pub mod qos;
 pub mod sessions;
+pub mod status;
 pub mod system_resources;
 pub mod telemetry;
+pub mod upstream;
 
 pub use acl_management::*;
+pub use admission::*;
 pub use diagnostics::*;
+pub use events::*;
 pub use management::*;
 pub use pool::*;
+pub use qos::*;
 pub use sessions::*;
+pub use status::*;
 pub use system_resources::*;
 pub use telemetry::*;
+pub use upstream::*;
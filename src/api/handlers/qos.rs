@@ -0,0 +1,114 @@
+use crate::api::auth::ApiCaller;
+use crate::api::handlers::sessions::ApiState;
+use crate::api::types::{
+    QosAllocationsQuery, QosAllocationsResponse, QosConfigResponse, QosHtbConfigResponse,
+    QosLimitsResponse, QosPerIpResponse, QosUserAllocationResponse, QosUserLimitRequest,
+    QosUserLimitResponse,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use tracing::info;
+
+/// GET /api/qos/allocations - Per-user bandwidth allocations with readable rates, and
+/// the heaviest source IPs when per-IP shaping is on
+pub async fn get_qos_allocations(
+    State(state): State<ApiState>,
+    Query(query): Query<QosAllocationsQuery>,
+) -> Json<QosAllocationsResponse> {
+    let mut users: Vec<QosUserAllocationResponse> = state
+        .qos_engine
+        .get_user_allocations()
+        .await
+        .into_iter()
+        .map(QosUserAllocationResponse::from)
+        .collect();
+    users.sort_by(|a, b| a.user.cmp(&b.user));
+
+    let per_ip = match state.qos_engine.per_ip_config() {
+        Some(config) => {
+            let limit = query.ip_limit.unwrap_or(50).clamp(1, 1000);
+            let allocations = state.qos_engine.get_ip_allocations().await;
+            Some(QosPerIpResponse::new(config, allocations, limit))
+        }
+        None => None,
+    };
+
+    Json(QosAllocationsResponse {
+        enabled: state.qos_engine.is_enabled(),
+        limits: state
+            .qos_engine
+            .htb_config()
+            .map(|config| QosLimitsResponse::from(&config)),
+        users,
+        per_ip,
+    })
+}
+
+/// GET /api/qos/config - HTB parameters in effect and the per-user maximums set at runtime
+pub async fn get_qos_config(State(state): State<ApiState>) -> Json<QosConfigResponse> {
+    Json(QosConfigResponse {
+        enabled: state.qos_engine.is_enabled(),
+        htb: state
+            .qos_engine
+            .htb_config()
+            .map(|config| QosHtbConfigResponse::from(&config)),
+        user_limits: state
+            .qos_engine
+            .user_max_overrides()
+            .into_iter()
+            .map(|(user, max)| QosUserLimitResponse {
+                user,
+                max: max.into(),
+            })
+            .collect(),
+    })
+}
+
+/// PUT /api/qos/users/{user}/limit - Override a user's maximum bandwidth until restart
+/// or DELETE
+pub async fn set_qos_user_limit(
+    State(state): State<ApiState>,
+    Path(user): Path<String>,
+    caller: Option<Extension<ApiCaller>>,
+    Json(request): Json<QosUserLimitRequest>,
+) -> axum::response::Result<Json<QosUserLimitResponse>> {
+    if !state.qos_engine.is_enabled() {
+        return Err((StatusCode::BAD_REQUEST, "QoS is not enabled").into());
+    }
+    state
+        .qos_engine
+        .set_user_max_bandwidth(&user, request.max_bandwidth)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    info!(
+        user = %user,
+        max_bytes_per_sec = request.max_bandwidth,
+        by = caller.as_ref().map(|Extension(caller)| caller.0.as_str()).unwrap_or("-"),
+        "User bandwidth limit overridden via API"
+    );
+    Ok(Json(QosUserLimitResponse {
+        user,
+        max: request.max_bandwidth.into(),
+    }))
+}
+
+/// DELETE /api/qos/users/{user}/limit - Return a user to the configured maximum
+pub async fn delete_qos_user_limit(
+    State(state): State<ApiState>,
+    Path(user): Path<String>,
+    caller: Option<Extension<ApiCaller>>,
+) -> StatusCode {
+    if !state.qos_engine.clear_user_max_bandwidth(&user).await {
+        return StatusCode::NOT_FOUND;
+    }
+    info!(
+        user = %user,
+        by = caller.as_ref().map(|Extension(caller)| caller.0.as_str()).unwrap_or("-"),
+        "User bandwidth limit override removed via API"
+    );
+    StatusCode::NO_CONTENT
+}
@@ -1,25 +1,41 @@
+use crate::api::auth::ApiCaller;
+use crate::api::encoding::BodyFormat;
 use crate::api::types::{
-    DestinationStat, PagedResponse, SessionQueryParams, SessionResponse, SessionStatsResponse,
-    UserStat,
+    ActiveSessionsQuery, DestinationStat, DestinationStatsQuery, DestinationStatsResponse,
+    FailureStatsQuery, FailureStatsResponse, GroupStat, MetricsHistoryQuery, PagedResponse,
+    SessionQueryParams, SessionResponse, SessionStatsQuery, SessionStatsResponse,
+    SessionWriterStatsResponse, TerminateUserSessionsRequest, TerminateUserSessionsResponse,
+    UserBanResponse, UserStat, WouldBlockStats, UNGROUPED,
 };
 use crate::config::Config;
+use crate::session::admission::ACCESS_LOG_TARGET;
 #[cfg(feature = "database")]
 use crate::session::SessionFilter;
-use crate::session::{Session, SessionManager, SessionStatus};
-use crate::telemetry::TelemetryHistory;
+use crate::session::{
+    AclDecisionStats, DestHostPattern, HostSource, MetricsCursor, MetricsHistory,
+    MetricsResolution, MetricsSnapshot, MinMaxDecimator, Session, SessionManager, SessionStatus,
+    TerminateOutcome,
+};
+use crate::telemetry::{TelemetryHistory, TelemetrySeverity};
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    body::Body,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, Extensions, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use chrono::{Duration as ChronoDuration, Utc};
+use bytes::Bytes;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use uuid::Uuid;
 
 #[cfg(feature = "database")]
-use tracing::{error, warn};
+use tracing::error;
+use tracing::{info, warn};
 
 /// API state containing shared resources
 #[derive(Clone)]
@@ -28,6 +44,7 @@ pub struct ApiState {
     pub acl_engine: Option<Arc<crate::acl::AclEngine>>,
     pub acl_config_path: Option<String>,
     pub connection_pool: Arc<crate::server::pool::ConnectionPool>,
+    pub qos_engine: Arc<crate::qos::QosEngine>,
     pub start_time: std::time::Instant,
     #[cfg(feature = "database")]
     pub session_store: Option<Arc<crate::session::SessionStore>>,
@@ -36,25 +53,64 @@ pub struct ApiState {
     pub config_path: Option<PathBuf>,
     pub config_snapshot: Arc<Config>,
     pub original_args: Arc<Vec<std::ffi::OsString>>,
+    pub address_gate: Option<Arc<crate::auth::AddressGate>>,
+    pub overload: Option<Arc<crate::server::LoadShedder>>,
+    pub resource_guard: Option<Arc<crate::server::ResourceGuard>>,
+    /// Main config reload; `None` in setups without a running server
+    pub config_reloader: Option<Arc<crate::server::ConfigReloader>>,
+    /// `server.dns` cache; `None` when disabled
+    pub dns_cache: Option<Arc<crate::server::DnsCache>>,
+    /// SOCKS listener addresses; `None` in setups without a running server
+    pub bound_addresses: Option<Arc<crate::server::BoundAddresses>>,
+    /// Temporary login bans; `None` in setups without a running server
+    pub user_bans: Option<Arc<crate::auth::UserBans>>,
+    /// `[[diagnostics.probes]]`; `None` in setups without a running server
+    pub probe_monitor: Option<Arc<crate::server::ProbeMonitor>>,
+    /// `[server.upstream]` parent proxy; `None` when chaining is off
+    pub upstream_proxy: Option<Arc<crate::server::UpstreamProxy>>,
 }
 
 /// GET /api/sessions/active - Get active sessions
+///
+/// `?sort=rate` lists the busiest sessions first by their current transfer rate, and
+/// `?limit=N` keeps the first N. Any other `sort` is a 400. MessagePack on request
+/// (see [`BodyFormat`]).
 pub async fn get_active_sessions(
     State(state): State<ApiState>,
-) -> (StatusCode, Json<Vec<SessionResponse>>) {
+    headers: HeaderMap,
+    Query(query): Query<ActiveSessionsQuery>,
+) -> axum::response::Result<Response> {
+    let by_rate = match query.sort.as_deref() {
+        None => false,
+        Some(sort) if sort.eq_ignore_ascii_case("rate") => true,
+        Some(_) => return Err((StatusCode::BAD_REQUEST, "Invalid sort (supported: rate)").into()),
+    };
+
     let sessions = state.session_manager.get_active_sessions().await;
-    let responses = sessions
-        .iter()
-        .map(|s| session_to_response(s.clone()))
+    let mut responses: Vec<SessionResponse> = sessions
+        .into_iter()
+        .map(|session| active_session_to_response(&state, session))
         .collect();
-    (StatusCode::OK, Json(responses))
+    if by_rate {
+        responses.sort_by_key(|s| {
+            std::cmp::Reverse(s.rate_bps_sent.saturating_add(s.rate_bps_received))
+        });
+    }
+    if let Some(limit) = query.limit {
+        responses.truncate(limit);
+    }
+    Ok(BodyFormat::negotiate(&headers).respond(StatusCode::OK, &responses))
 }
 
 /// GET /api/sessions/history - Get session history with filtering
+///
+/// MessagePack on request (see [`BodyFormat`]).
 pub async fn get_session_history(
     State(state): State<ApiState>,
+    headers: HeaderMap,
     Query(params): Query<SessionQueryParams>,
-) -> (StatusCode, Json<PagedResponse<SessionResponse>>) {
+) -> Response {
+    let format = BodyFormat::negotiate(&headers);
     let page_size = params.page_size.clamp(1, 1000);
     let page_size_usize = page_size as usize;
     let page = params.page.max(1);
@@ -69,23 +125,48 @@ pub async fn get_session_history(
         }
     }
 
+    let mut host_filter = RequestedHostFilter {
+        host: params.requested_host.clone(),
+        source: None,
+    };
+    if let Some(ref source_str) = params.requested_host_source {
+        match HostSource::from_str(source_str) {
+            Ok(source) => host_filter.source = Some(source),
+            Err(_) => invalid_status = true,
+        }
+    }
+
+    let mut dest_filter = DestFilter {
+        ip: params.dest_ip.clone(),
+        host: None,
+    };
+    if let Some(ref pattern) = params.dest_host {
+        match DestHostPattern::parse(pattern) {
+            Ok(pattern) => dest_filter.host = Some(pattern),
+            Err(_) => invalid_status = true,
+        }
+    }
+
     if invalid_status {
-        let response = PagedResponse {
+        let response: PagedResponse<SessionResponse> = PagedResponse {
             data: Vec::new(),
             total: 0,
             page,
             page_size,
             total_pages: 0,
         };
-        return (StatusCode::OK, Json(response));
+        return format.respond(StatusCode::OK, &response);
     }
 
     let cutoff = params
         .hours
         .map(|hours| Utc::now() - ChronoDuration::hours(hours as i64));
 
-    let user_filter = params.user.clone();
-    let dest_filter = params.dest_ip.clone();
+    let user_filter = UserFilter {
+        user: params.user.clone(),
+        authenticated_user: params.authenticated_user.clone(),
+        correlation_id: params.correlation_id.clone(),
+    };
     let sort_by = params.sort_by.clone();
     let sort_dir = params.sort_dir.clone();
 
@@ -96,6 +177,7 @@ pub async fn get_session_history(
             &state.session_manager,
             &user_filter,
             &dest_filter,
+            &host_filter,
             &status_filter,
             cutoff.as_ref(),
             offset,
@@ -107,7 +189,7 @@ pub async fn get_session_history(
         )
         .await
         {
-            Ok(response) => return (StatusCode::OK, Json(response)),
+            Ok(response) => return format.respond(StatusCode::OK, &response),
             Err(e) => {
                 error!(
                     error = %e,
@@ -121,6 +203,7 @@ pub async fn get_session_history(
         &state.session_manager,
         &user_filter,
         &dest_filter,
+        &host_filter,
         &status_filter,
         cutoff.as_ref(),
         page,
@@ -132,7 +215,7 @@ pub async fn get_session_history(
     )
     .await;
 
-    (StatusCode::OK, Json(response))
+    format.respond(StatusCode::OK, &response)
 }
 
 #[cfg(feature = "database")]
@@ -141,8 +224,9 @@ pub async fn get_session_history(
 async fn fetch_history_from_store(
     store: &crate::session::SessionStore,
     manager: &SessionManager,
-    user_filter: &Option<String>,
-    dest_filter: &Option<String>,
+    user_filter: &UserFilter,
+    dest_filter: &DestFilter,
+    host_filter: &RequestedHostFilter,
     status_filter: &Option<SessionStatus>,
     cutoff: Option<&chrono::DateTime<Utc>>,
     offset: usize,
@@ -154,7 +238,14 @@ async fn fetch_history_from_store(
 ) -> Result<PagedResponse<SessionResponse>, sqlx::Error> {
     let mut in_memory = manager.get_closed_sessions().await;
     in_memory.retain(|session| {
-        matches_history_filters(session, user_filter, dest_filter, status_filter, cutoff)
+        matches_history_filters(
+            session,
+            user_filter,
+            dest_filter,
+            host_filter,
+            status_filter,
+            cutoff,
+        )
     });
 
     let extra_ids: Vec<_> = in_memory.iter().map(|s| s.session_id).collect();
@@ -177,6 +268,7 @@ async fn fetch_history_from_store(
             "user" => a.user.cmp(&b.user),
             "source_ip" => a.source_ip.to_string().cmp(&b.source_ip.to_string()),
             "dest_ip" => a.dest_ip.cmp(&b.dest_ip),
+            "dest_host" => a.dest_host.cmp(&b.dest_host),
             "protocol" => a.protocol.as_str().cmp(b.protocol.as_str()),
             "status" => a.status.as_str().cmp(b.status.as_str()),
             "acl_decision" => a.acl_decision.cmp(&b.acl_decision),
@@ -205,8 +297,13 @@ async fn fetch_history_from_store(
     let db_limit = page_size.saturating_sub(extra_page_len);
 
     let filter = SessionFilter {
-        user: user_filter.clone(),
-        dest_ip: dest_filter.clone(),
+        user: user_filter.user.clone(),
+        authenticated_user: user_filter.authenticated_user.clone(),
+        correlation_id: user_filter.correlation_id.clone(),
+        dest_ip: dest_filter.ip.clone(),
+        dest_host: dest_filter.host.clone(),
+        requested_host: host_filter.host.clone(),
+        requested_host_source: host_filter.source,
         status: status_filter.clone(),
         limit: Some(db_limit as u64),
         offset: Some(db_offset as u64),
@@ -252,8 +349,9 @@ async fn fetch_history_from_store(
 #[allow(clippy::too_many_arguments)]
 async fn build_memory_history_response(
     manager: &SessionManager,
-    user_filter: &Option<String>,
-    dest_filter: &Option<String>,
+    user_filter: &UserFilter,
+    dest_filter: &DestFilter,
+    host_filter: &RequestedHostFilter,
     status_filter: &Option<SessionStatus>,
     cutoff: Option<&chrono::DateTime<Utc>>,
     page: u32,
@@ -265,7 +363,14 @@ async fn build_memory_history_response(
 ) -> PagedResponse<SessionResponse> {
     let mut sessions = manager.get_closed_sessions().await;
     sessions.retain(|session| {
-        matches_history_filters(session, user_filter, dest_filter, status_filter, cutoff)
+        matches_history_filters(
+            session,
+            user_filter,
+            dest_filter,
+            host_filter,
+            status_filter,
+            cutoff,
+        )
     });
 
     // Apply sorting
@@ -280,6 +385,7 @@ async fn build_memory_history_response(
             "user" => a.user.cmp(&b.user),
             "source_ip" => a.source_ip.to_string().cmp(&b.source_ip.to_string()),
             "dest_ip" => a.dest_ip.cmp(&b.dest_ip),
+            "dest_host" => a.dest_host.cmp(&b.dest_host),
             "protocol" => a.protocol.as_str().cmp(b.protocol.as_str()),
             "status" => a.status.as_str().cmp(b.status.as_str()),
             "acl_decision" => a.acl_decision.cmp(&b.acl_decision),
@@ -321,25 +427,76 @@ async fn build_memory_history_response(
     }
 }
 
+/// `user` (effective identity), `authenticated_user` and `correlation_id` filters of the
+/// history endpoint
+struct UserFilter {
+    user: Option<String>,
+    authenticated_user: Option<String>,
+    correlation_id: Option<String>,
+}
+
+/// `dest_ip` and `dest_host` filters of the history endpoint
+struct DestFilter {
+    ip: Option<String>,
+    host: Option<DestHostPattern>,
+}
+
+/// `requested_host` filters of the history endpoint
+struct RequestedHostFilter {
+    host: Option<String>,
+    source: Option<HostSource>,
+}
+
 fn matches_history_filters(
     session: &Session,
-    user_filter: &Option<String>,
-    dest_filter: &Option<String>,
+    user_filter: &UserFilter,
+    dest_filter: &DestFilter,
+    host_filter: &RequestedHostFilter,
     status_filter: &Option<SessionStatus>,
     cutoff: Option<&chrono::DateTime<Utc>>,
 ) -> bool {
-    if let Some(user) = user_filter.as_ref() {
+    if let Some(user) = user_filter.user.as_ref() {
         if session.user.as_ref() != user {
             return false;
         }
     }
 
-    if let Some(dest) = dest_filter.as_ref() {
+    if let Some(principal) = user_filter.authenticated_user.as_ref() {
+        if session.authenticated_user.as_deref() != Some(principal.as_str()) {
+            return false;
+        }
+    }
+
+    if let Some(correlation_id) = user_filter.correlation_id.as_ref() {
+        if session.correlation_id.as_deref() != Some(correlation_id.as_str()) {
+            return false;
+        }
+    }
+
+    if let Some(dest) = dest_filter.ip.as_ref() {
         if session.dest_ip.as_ref() != dest {
             return false;
         }
     }
 
+    if let Some(pattern) = dest_filter.host.as_ref() {
+        if !pattern.matches(&session.dest_host) {
+            return false;
+        }
+    }
+
+    if let Some(host) = host_filter.host.as_ref() {
+        if session.requested_host.as_deref() != Some(host.as_str()) {
+            return false;
+        }
+    }
+
+    if let Some(source) = host_filter.source {
+        if session.requested_host_source != Some(source) {
+            return false;
+        }
+    }
+
     if let Some(status) = status_filter.as_ref() {
         if &session.status != status {
             return false;
@@ -361,26 +518,31 @@ pub async fn get_session_detail(
     State(state): State<ApiState>,
     Path(id): Path<String>,
 ) -> axum::response::Result<(StatusCode, Json<SessionResponse>)> {
+    // Accepts v4 IDs from before the switch to UUIDv7, in any of the textual UUID forms
+    let Ok(uuid) = Uuid::parse_str(id.trim()) else {
+        return Err((StatusCode::BAD_REQUEST, "Invalid session id").into());
+    };
+
     let sessions = state.session_manager.get_all_sessions().await;
 
-    if let Some(session) = sessions.iter().find(|s| s.session_id.to_string() == id) {
-        Ok((StatusCode::OK, Json(session_to_response(session.clone()))))
+    if let Some(session) = sessions.iter().find(|s| s.session_id == uuid) {
+        let response = if session.status == SessionStatus::Active {
+            active_session_to_response(&state, session.clone())
+        } else {
+            session_to_response(session.clone())
+        };
+        Ok((StatusCode::OK, Json(response)))
     } else {
         #[cfg(feature = "database")]
         {
             if let Some(store) = state.session_store.as_ref() {
-                match Uuid::parse_str(&id) {
-                    Ok(uuid) => match store.get_session(&uuid).await {
-                        Ok(Some(session)) => {
-                            return Ok((StatusCode::OK, Json(session_to_response(session))));
-                        }
-                        Ok(None) => {}
-                        Err(e) => {
-                            warn!(session_id = %id, error = %e, "Failed to load session from store")
-                        }
-                    },
-                    Err(_) => {
-                        return Err((StatusCode::BAD_REQUEST, "Invalid session id").into());
+                match store.get_session(&uuid).await {
+                    Ok(Some(session)) => {
+                        return Ok((StatusCode::OK, Json(session_to_response(session))));
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        warn!(session_id = %id, error = %e, "Failed to load session from store")
                     }
                 }
             }
@@ -390,11 +552,47 @@ pub async fn get_session_detail(
     }
 }
 
+/// How `/api/sessions/stats` keys its destination table
+#[derive(Clone, Copy)]
+enum DestinationKey {
+    Dialled,
+    Sni,
+    RequestedHost,
+}
+
 /// GET /api/sessions/stats - Get aggregated session statistics
+///
+/// `?group_by=sni` keys destinations by the SNI name of classified sessions and
+/// `requested_host` by the hostname the client asked for (whatever its source);
+/// `destination` (the default) keeps the dialled address. `group` keeps the dialled
+/// address and adds `groups`, the breakdown by the user's groups. Anything else is a 400.
 pub async fn get_session_stats(
     State(state): State<ApiState>,
-) -> (StatusCode, Json<SessionStatsResponse>) {
+    Query(query): Query<SessionStatsQuery>,
+) -> axum::response::Result<(StatusCode, Json<SessionStatsResponse>)> {
+    let mut by_group = false;
+    let group_by = match query.group_by.as_deref() {
+        None => DestinationKey::Dialled,
+        Some(group_by) if group_by.eq_ignore_ascii_case("destination") => DestinationKey::Dialled,
+        Some(group_by) if group_by.eq_ignore_ascii_case("sni") => DestinationKey::Sni,
+        Some(group_by) if group_by.eq_ignore_ascii_case("requested_host") => {
+            DestinationKey::RequestedHost
+        }
+        Some(group_by) if group_by.eq_ignore_ascii_case("group") => {
+            by_group = true;
+            DestinationKey::Dialled
+        }
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Invalid group_by (supported: destination, sni, requested_host, group)",
+            )
+                .into());
+        }
+    };
+
     let all_sessions = state.session_manager.get_all_sessions().await;
+    let eviction = state.session_manager.eviction().await;
 
     let active_sessions = all_sessions
         .iter()
@@ -412,6 +610,16 @@ pub async fn get_session_stats(
     let total_bytes_sent: u64 = all_sessions.iter().map(|s| s.bytes_sent).sum();
     let total_bytes_received: u64 = all_sessions.iter().map(|s| s.bytes_received).sum();
 
+    let mut would_block = WouldBlockStats::default();
+    for session in all_sessions.iter().filter(|s| s.would_block) {
+        would_block.session_count += 1;
+        if session.status == SessionStatus::Active {
+            would_block.active_sessions += 1;
+        }
+        would_block.bytes_sent += session.bytes_sent;
+        would_block.bytes_received += session.bytes_received;
+    }
+
     // Calculate top users (by session count)
     let mut user_stats: std::collections::HashMap<String, (u64, u64, u64)> =
         std::collections::HashMap::new();
@@ -433,14 +641,19 @@ pub async fn get_session_stats(
             bytes_received: received,
         })
         .collect();
-    top_users.sort_by(|a, b| b.session_count.cmp(&a.session_count));
+    top_users.sort_by_key(|entry| std::cmp::Reverse(entry.session_count));
     top_users.truncate(10);
 
     // Calculate top destinations
     let mut dest_stats: std::collections::HashMap<String, (u64, u64, u64)> =
         std::collections::HashMap::new();
     for session in &all_sessions {
-        let key = format!("{}:{}", session.dest_ip, session.dest_port);
+        let destination = match group_by {
+            DestinationKey::Dialled => &session.dest_ip,
+            DestinationKey::Sni => session.sni_host.as_ref().unwrap_or(&session.dest_ip),
+            DestinationKey::RequestedHost => session.logical_destination(),
+        };
+        let key = format!("{}:{}", destination, session.dest_port);
         let entry = dest_stats.entry(key).or_insert((0, 0, 0));
         entry.0 += 1;
         entry.1 += session.bytes_sent;
@@ -456,7 +669,7 @@ pub async fn get_session_stats(
             bytes_received: received,
         })
         .collect();
-    top_destinations.sort_by(|a, b| b.session_count.cmp(&a.session_count));
+    top_destinations.sort_by_key(|entry| std::cmp::Reverse(entry.session_count));
     top_destinations.truncate(10);
 
     let response = SessionStatsResponse {
@@ -468,9 +681,219 @@ pub async fn get_session_stats(
         total_bytes_received,
         top_users,
         top_destinations,
+        groups: by_group.then(|| group_stats(&all_sessions)),
+        would_block,
+        admission_rejections: state.session_manager.admission().stats(),
+        truncated: eviction.evicted > 0,
+        evicted_sessions: eviction.evicted,
     };
 
-    (StatusCode::OK, Json(response))
+    Ok((StatusCode::OK, Json(response)))
+}
+
+/// Aggregates per group of the sessions' users, busiest group first. A session counts
+/// once towards each of its groups; sessions without any go to [`UNGROUPED`].
+fn group_stats(sessions: &[Session]) -> Vec<GroupStat> {
+    #[derive(Default)]
+    struct Totals<'a> {
+        sessions: u64,
+        bytes_sent: u64,
+        bytes_received: u64,
+        users: HashSet<&'a str>,
+        allowed: u64,
+        blocked: u64,
+    }
+
+    let mut totals: HashMap<&str, Totals<'_>> = HashMap::new();
+    for session in sessions {
+        let ungrouped = session.groups.is_empty().then_some(UNGROUPED);
+        for group in session.groups.iter().map(|group| &**group).chain(ungrouped) {
+            let entry = totals.entry(group).or_default();
+            entry.sessions += 1;
+            entry.bytes_sent += session.bytes_sent;
+            entry.bytes_received += session.bytes_received;
+            entry.users.insert(session.user.as_ref());
+            if session.acl_decision.eq_ignore_ascii_case("allow") {
+                entry.allowed += 1;
+            } else if session.acl_decision.eq_ignore_ascii_case("block") {
+                entry.blocked += 1;
+            }
+        }
+    }
+
+    let mut stats: Vec<GroupStat> = totals
+        .into_iter()
+        .map(|(group, totals)| GroupStat {
+            group: group.to_string(),
+            session_count: totals.sessions,
+            bytes_sent: totals.bytes_sent,
+            bytes_received: totals.bytes_received,
+            unique_users: totals.users.len() as u64,
+            acl: AclDecisionStats {
+                allowed: totals.allowed,
+                blocked: totals.blocked,
+            },
+        })
+        .collect();
+    stats.sort_by(|a, b| {
+        b.session_count
+            .cmp(&a.session_count)
+            .then_with(|| a.group.cmp(&b.group))
+    });
+    stats
+}
+
+const DEFAULT_DESTINATION_WINDOW_HOURS: u64 = 24;
+const MAX_DESTINATION_WINDOW_HOURS: u64 = 24 * 366;
+const DEFAULT_DESTINATION_BUCKET_MINUTES: u32 = 60;
+const DEFAULT_TOP_DESTINATIONS: usize = 10;
+const MAX_TOP_DESTINATIONS: usize = 100;
+/// Buckets per destination; with `MAX_TOP_DESTINATIONS` this bounds the response rows
+const MAX_DESTINATION_BUCKETS: u64 = 1_000;
+
+/// GET /api/stats/destinations - Per-destination traffic over time
+///
+/// Aggregated from the session store, so sessions still waiting in the batch writer
+/// are not counted yet.
+pub async fn get_destination_stats(
+    State(state): State<ApiState>,
+    Query(query): Query<DestinationStatsQuery>,
+) -> axum::response::Result<Json<DestinationStatsResponse>> {
+    let window_hours = query
+        .window_hours
+        .unwrap_or(DEFAULT_DESTINATION_WINDOW_HOURS);
+    let bucket_minutes = query
+        .bucket_minutes
+        .unwrap_or(DEFAULT_DESTINATION_BUCKET_MINUTES);
+    let top = query.top.unwrap_or(DEFAULT_TOP_DESTINATIONS);
+
+    if window_hours == 0 || bucket_minutes == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "window_hours and bucket_minutes must be at least 1",
+        )
+            .into());
+    }
+    if window_hours > MAX_DESTINATION_WINDOW_HOURS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "window_hours cannot exceed {}",
+                MAX_DESTINATION_WINDOW_HOURS
+            ),
+        )
+            .into());
+    }
+    if window_hours.saturating_mul(60) / u64::from(bucket_minutes) > MAX_DESTINATION_BUCKETS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "window_hours / bucket_minutes spans more than {} buckets; use wider buckets",
+                MAX_DESTINATION_BUCKETS
+            ),
+        )
+            .into());
+    }
+    if top == 0 || top > MAX_TOP_DESTINATIONS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("top must be between 1 and {}", MAX_TOP_DESTINATIONS),
+        )
+            .into());
+    }
+
+    #[cfg(feature = "database")]
+    if let Some(store) = state.session_store.as_ref() {
+        let since = Utc::now() - ChronoDuration::hours(window_hours as i64);
+        let buckets = store
+            .destination_stats(&since, bucket_minutes.saturating_mul(60), top)
+            .await
+            .map_err(|e| {
+                error!(error = %e, "Failed to aggregate destination statistics");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to query the session store",
+                )
+            })?;
+        return Ok(Json(DestinationStatsResponse {
+            window_hours,
+            bucket_minutes,
+            buckets,
+        }));
+    }
+
+    #[cfg(not(feature = "database"))]
+    let _ = &state;
+    Err((
+        StatusCode::BAD_REQUEST,
+        "Destination statistics need a persistent session store (sessions.storage)",
+    )
+        .into())
+}
+
+/// GET /api/sessions/writer-status - Queue and flush state of the session batch writer
+///
+/// Sessions waiting for the store, the queue limit and what overflowing it has cost so
+/// far, for alerting before sessions are lost.
+pub async fn get_session_writer_status(
+    State(state): State<ApiState>,
+) -> axum::response::Result<Json<SessionWriterStatsResponse>> {
+    #[cfg(feature = "database")]
+    if let Some(stats) = state.session_manager.batch_writer_stats().await {
+        return Ok(Json(stats.into()));
+    }
+
+    #[cfg(not(feature = "database"))]
+    let _ = &state;
+    Err((
+        StatusCode::NOT_FOUND,
+        "The session batch writer needs a persistent session store (sessions.storage)",
+    )
+        .into())
+}
+
+const DEFAULT_FAILURE_WINDOW_MINUTES: u64 = 60;
+const MAX_FAILURE_WINDOW_MINUTES: u64 = 24 * 60;
+
+/// GET /api/stats/failures - Failed requests by category and destination
+///
+/// Served from the in-memory failure log, which also holds logins that failed before a
+/// session existed; it keeps the latest `FAILURE_LOG_CAPACITY` failures, so a busy
+/// window may reach back further than `oldest_kept`.
+pub async fn get_failure_stats(
+    State(state): State<ApiState>,
+    Query(query): Query<FailureStatsQuery>,
+) -> axum::response::Result<Json<FailureStatsResponse>> {
+    let window_minutes = query
+        .window_minutes
+        .unwrap_or(DEFAULT_FAILURE_WINDOW_MINUTES);
+    let top = query.top.unwrap_or(DEFAULT_TOP_DESTINATIONS);
+
+    if window_minutes == 0 || window_minutes > MAX_FAILURE_WINDOW_MINUTES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "window_minutes must be between 1 and {}",
+                MAX_FAILURE_WINDOW_MINUTES
+            ),
+        )
+            .into());
+    }
+    if top == 0 || top > MAX_TOP_DESTINATIONS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("top must be between 1 and {}", MAX_TOP_DESTINATIONS),
+        )
+            .into());
+    }
+
+    let failures = state.session_manager.failures();
+    let since = Utc::now() - ChronoDuration::minutes(window_minutes as i64);
+    Ok(Json(FailureStatsResponse {
+        window_minutes,
+        summary: failures.summary(since, top),
+        totals_since_start: failures.totals(),
+    }))
 }
 
 /// GET /api/users/{user}/sessions - Get sessions for specific user
@@ -484,10 +907,15 @@ pub async fn get_user_sessions(
         if let Some(store) = state.session_store.as_ref() {
             let filter = SessionFilter {
                 user: Some(user.clone()),
+                authenticated_user: None,
+                correlation_id: None,
                 status: None,
                 start_after: None,
                 start_before: None,
                 dest_ip: None,
+                dest_host: None,
+                requested_host: None,
+                requested_host_source: None,
                 min_duration_secs: None,
                 min_bytes: None,
                 limit: Some(1000), // Limit to 1000 most recent sessions
@@ -544,42 +972,313 @@ pub async fn get_user_sessions(
     (StatusCode::OK, Json(user_sessions))
 }
 
-/// GET /api/metrics/history - Get historical metrics snapshots
+/// Look-back window when a history request gives no start.
+const DEFAULT_HISTORY_MINUTES: u64 = 120;
+/// Samples returned when the client does not ask for a specific resolution.
+const DEFAULT_HISTORY_POINTS: usize = 1440;
+const MIN_HISTORY_POINTS: usize = 4;
+const MAX_HISTORY_POINTS: usize = 10_000;
+
+/// Response header naming the tier a history response was read from.
+const RESOLUTION_HEADER: &str = "x-metrics-resolution";
+
+/// GET /api/metrics/history - Stream historical metrics snapshots
+///
+/// Samples are read from storage in chunks, decimated to `max_points` and
+/// serialized incrementally as a JSON array or NDJSON (`Accept:
+/// application/x-ndjson` or `format=ndjson`), so memory stays bounded however
+/// wide the range is. `resolution` picks raw samples or 1-minute or 15-minute
+/// rollups; left out, the coarsest tier that still gives `max_points` distinct
+/// points is used, and rollups stand in where raw samples have aged out. Raw
+/// ranges wider than `metrics.history_max_range_hours` are refused with 413.
+///
+/// `Accept: application/msgpack` (without `format`) returns the same array as
+/// MessagePack; that body is built whole, which `max_points` keeps small.
 pub async fn get_metrics_history(
     State(state): State<ApiState>,
-) -> (StatusCode, Json<Vec<crate::session::MetricsSnapshot>>) {
-    // Try to load from database first (persistent)
-    #[cfg(feature = "database")]
-    if let Some(store) = state.session_store.as_ref() {
-        // Get last 2 hours of metrics (1440 snapshots @ 5s intervals)
-        match store.query_metrics(None, Some(1440)).await {
-            Ok(mut snapshots) => {
-                // Reverse to get chronological order (query returns DESC)
-                snapshots.reverse();
-                return (StatusCode::OK, Json(snapshots));
-            }
+    headers: HeaderMap,
+    Query(params): Query<MetricsHistoryQuery>,
+) -> Response {
+    let ndjson = match params.format.as_deref() {
+        Some("ndjson") => true,
+        Some("json") => false,
+        Some(other) => {
+            return history_error(
+                StatusCode::BAD_REQUEST,
+                &format!("unknown format '{}', expected json or ndjson", other),
+            );
+        }
+        None => headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| {
+                accept.contains("application/x-ndjson") || accept.contains("application/ndjson")
+            }),
+    };
+    let msgpack = params.format.is_none()
+        && !ndjson
+        && BodyFormat::negotiate(&headers) == BodyFormat::MsgPack;
+    let requested = match params.resolution.as_deref() {
+        None | Some("auto") => None,
+        Some(value) => match value.parse::<MetricsResolution>() {
+            Ok(resolution) => Some(resolution),
+            Err(message) => return history_error(StatusCode::BAD_REQUEST, &message),
+        },
+    };
+    let end = params.end.unwrap_or_else(Utc::now);
+    let start = match params.start {
+        Some(start) => start,
+        None => {
+            let minutes = params.minutes.unwrap_or(DEFAULT_HISTORY_MINUTES);
+            let minutes = i64::try_from(minutes).unwrap_or(i64::MAX);
+            end - ChronoDuration::try_minutes(minutes).unwrap_or(ChronoDuration::MAX)
+        }
+    };
+    if start > end {
+        return history_error(StatusCode::BAD_REQUEST, "start must not be after end");
+    }
+
+    let max_points = params
+        .max_points
+        .unwrap_or(DEFAULT_HISTORY_POINTS)
+        .clamp(MIN_HISTORY_POINTS, MAX_HISTORY_POINTS);
+
+    let metrics = &state.config_snapshot.metrics;
+    let max_range_hours = metrics.history_max_range_hours;
+    let max_range = i64::try_from(max_range_hours)
+        .ok()
+        .and_then(ChronoDuration::try_hours)
+        .unwrap_or(ChronoDuration::MAX);
+    // Oldest raw sample still kept; retention 0 never deletes any
+    let raw_since = i64::try_from(metrics.retention_hours)
+        .ok()
+        .filter(|hours| *hours > 0)
+        .and_then(ChronoDuration::try_hours)
+        .and_then(|retention| Utc::now().checked_sub_signed(retention))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let resolution = requested.unwrap_or_else(|| {
+        MetricsResolution::for_range(
+            end - start,
+            max_points,
+            start >= raw_since && end - start <= max_range,
+            history_keeps_rollups(&state),
+        )
+    });
+    if resolution == MetricsResolution::Raw && end - start > max_range {
+        #[cfg(feature = "metrics")]
+        crate::session::metrics::METRICS_HISTORY_REJECTED.inc();
+        return history_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!(
+                "requested range exceeds metrics.history_max_range_hours ({}h)",
+                max_range_hours
+            ),
+        );
+    }
+
+    let (cursor, first_chunk) = open_metrics_cursor(&state, resolution, start, end).await;
+    let stream = HistoryStream {
+        cursor,
+        decimator: MinMaxDecimator::new(start, end, max_points),
+        prefetched: first_chunk,
+        ndjson,
+        written: 0,
+        started: std::time::Instant::now(),
+    };
+
+    if msgpack {
+        let mut response = match stream.collect().await {
+            Ok(points) => BodyFormat::MsgPack.respond(StatusCode::OK, &points),
             Err(e) => {
-                warn!(
-                    error = %e,
-                    "Failed to load metrics from database, falling back to in-memory"
+                warn!(error = %e, "Metrics history read failed");
+                return history_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "failed to read metrics history",
                 );
             }
-        }
+        };
+        response.headers_mut().insert(
+            HeaderName::from_static(RESOLUTION_HEADER),
+            HeaderValue::from_static(resolution.as_str()),
+        );
+        return response;
     }
 
-    // Fallback to in-memory history
-    if let Some(history) = state.metrics_history.as_ref() {
-        let snapshots = history.get_snapshots().await;
-        (StatusCode::OK, Json(snapshots))
+    let content_type = if ndjson {
+        "application/x-ndjson"
     } else {
-        (StatusCode::OK, Json(Vec::new()))
+        "application/json"
+    };
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type),
+            (
+                HeaderName::from_static(RESOLUTION_HEADER),
+                resolution.as_str(),
+            ),
+        ],
+        Body::from_stream(stream.into_stream()),
+    )
+        .into_response()
+}
+
+fn history_error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+/// Whether the history source answering requests keeps rollups
+fn history_keeps_rollups(state: &ApiState) -> bool {
+    #[cfg(feature = "database")]
+    if state.config_snapshot.metrics.uses_database() && state.session_store.is_some() {
+        return state.config_snapshot.metrics.rollup_retention_days > 0;
+    }
+    state
+        .metrics_history
+        .as_ref()
+        .is_some_and(|history| history.keeps_rollups())
+}
+
+/// Pick the history source and read its first chunk, falling back to memory if
+/// the persistent store fails before anything has been sent.
+async fn open_metrics_cursor(
+    state: &ApiState,
+    resolution: MetricsResolution,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> (MetricsCursor, Option<Vec<MetricsSnapshot>>) {
+    #[cfg(feature = "database")]
+    if state.config_snapshot.metrics.uses_database() {
+        if let Some(store) = state.session_store.as_ref() {
+            let opened = match MetricsCursor::store_at(store.clone(), resolution, start, end).await
+            {
+                Ok(mut cursor) => cursor.next_chunk().await.map(|chunk| (cursor, chunk)),
+                Err(e) => Err(e),
+            };
+            match opened {
+                Ok((cursor, chunk)) => return (cursor, Some(chunk)),
+                Err(e) => {
+                    warn!(
+                        error = %e,
+                        "Failed to load metrics from database, falling back to in-memory"
+                    );
+                }
+            }
+        }
+    }
+
+    let history = state
+        .metrics_history
+        .clone()
+        .unwrap_or_else(|| Arc::new(MetricsHistory::new(0, 0)));
+    (
+        MetricsCursor::memory_at(history, resolution, start, end),
+        None,
+    )
+}
+
+/// Streaming state for one metrics history response.
+struct HistoryStream {
+    cursor: MetricsCursor,
+    decimator: MinMaxDecimator,
+    prefetched: Option<Vec<MetricsSnapshot>>,
+    ndjson: bool,
+    written: usize,
+    started: std::time::Instant,
+}
+
+impl HistoryStream {
+    fn into_stream(self) -> impl futures::Stream<Item = std::io::Result<Bytes>> + Send {
+        futures::stream::unfold(Some(self), |state| async move {
+            let mut state = state?;
+            let mut points = Vec::new();
+            loop {
+                let chunk = match state.prefetched.take() {
+                    Some(chunk) => chunk,
+                    None => match state.cursor.next_chunk().await {
+                        Ok(chunk) => chunk,
+                        Err(e) => {
+                            warn!(error = %e, "Metrics history stream aborted");
+                            return Some((Err(e), None));
+                        }
+                    },
+                };
+
+                if chunk.is_empty() {
+                    state.decimator.finish(&mut points);
+                    let mut bytes = state.encode(&points);
+                    if !state.ndjson {
+                        if state.written == 0 {
+                            bytes.push(b'[');
+                        }
+                        bytes.push(b']');
+                    }
+                    return Some((Ok(Bytes::from(bytes)), None));
+                }
+
+                for sample in chunk {
+                    state.decimator.push(sample, &mut points);
+                }
+                if !points.is_empty() {
+                    let bytes = state.encode(&points);
+                    return Some((Ok(Bytes::from(bytes)), Some(state)));
+                }
+            }
+        })
+    }
+
+    /// Every point at once, for formats that are not streamed
+    async fn collect(mut self) -> std::io::Result<Vec<MetricsSnapshot>> {
+        let mut points = Vec::new();
+        loop {
+            let chunk = match self.prefetched.take() {
+                Some(chunk) => chunk,
+                None => self.cursor.next_chunk().await?,
+            };
+            if chunk.is_empty() {
+                self.decimator.finish(&mut points);
+                return Ok(points);
+            }
+            for sample in chunk {
+                self.decimator.push(sample, &mut points);
+            }
+        }
+    }
+
+    fn encode(&mut self, points: &[MetricsSnapshot]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(points.len() * 96);
+        for point in points {
+            if !self.ndjson {
+                bytes.push(if self.written == 0 { b'[' } else { b',' });
+            }
+            // MetricsSnapshot has no map keys or non-finite floats, so this cannot fail
+            let _ = serde_json::to_writer(&mut bytes, point);
+            if self.ndjson {
+                bytes.push(b'\n');
+            }
+            self.written += 1;
+        }
+        bytes
+    }
+}
+
+impl Drop for HistoryStream {
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics")]
+        crate::session::metrics::METRICS_HISTORY_REQUEST_DURATION
+            .observe(self.started.elapsed().as_secs_f64());
     }
 }
 
 /// POST /api/sessions/:id/terminate - Terminate an active session
+///
+/// Cancels the session's relay, which closes the client and upstream sockets, and
+/// records who asked in the close reason: the API key or dashboard user when API keys
+/// are enforced, and the address the request came from.
 pub async fn terminate_session(
     State(state): State<ApiState>,
     Path(session_id): Path<String>,
+    extensions: Extensions,
 ) -> (StatusCode, Json<serde_json::Value>) {
     // Parse session ID
     let session_uuid = match Uuid::from_str(&session_id) {
@@ -594,53 +1293,295 @@ pub async fn terminate_session(
         }
     };
 
-    // Check if session exists and is active
-    let session_exists = state
+    let reason = termination_reason(&extensions);
+    let outcome = state
         .session_manager
-        .get_active_sessions()
-        .await
-        .iter()
-        .any(|s| s.session_id == session_uuid);
+        .terminate_session(&session_uuid, reason.clone(), SessionStatus::Closed)
+        .await;
 
-    if !session_exists {
-        return (
+    match outcome {
+        TerminateOutcome::Terminated => {
+            info!(session = %session_uuid, reason = %reason, "Session terminated via API");
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "status": "terminated",
+                    "message": "Session terminated successfully",
+                    "close_reason": reason
+                })),
+            )
+        }
+        TerminateOutcome::AlreadyClosed => (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": "Session already closed",
+                "status": "already_closed"
+            })),
+        ),
+        TerminateOutcome::NotFound => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({
-                "error": "Session not found or not active"
+                "error": "Session not found",
+                "status": "not_found"
             })),
-        );
+        ),
     }
+}
 
-    // Terminate the session
-    state
+/// Longest ban `ban_minutes` may ask for (one year)
+const MAX_BAN_MINUTES: u64 = 365 * 24 * 60;
+
+/// POST /api/users/{user}/terminate-sessions - Close all of a user's active sessions
+///
+/// With `ban_minutes`, the user's logins are refused for that long as well. The ban is
+/// set before the sessions are closed, so a client that reconnects at once is refused.
+pub async fn terminate_user_sessions(
+    State(state): State<ApiState>,
+    Path(user): Path<String>,
+    extensions: Extensions,
+    request: Option<Json<TerminateUserSessionsRequest>>,
+) -> axum::response::Result<Json<TerminateUserSessionsResponse>> {
+    let ban_minutes = request.and_then(|Json(request)| request.ban_minutes);
+    let by = api_caller(&extensions);
+
+    let ban = match ban_minutes {
+        None => None,
+        Some(0) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "ban_minutes must be greater than 0",
+            )
+                .into())
+        }
+        Some(minutes) if minutes > MAX_BAN_MINUTES => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("ban_minutes must be at most {}", MAX_BAN_MINUTES),
+            )
+                .into())
+        }
+        Some(minutes) => {
+            let Some(bans) = state.user_bans.as_ref() else {
+                return Err((
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "User bans are not available",
+                )
+                    .into());
+            };
+            let ban = bans.ban(&user, ChronoDuration::minutes(minutes as i64), by);
+            info!(
+                target: ACCESS_LOG_TARGET,
+                stage = "user_ban",
+                outcome = "banned",
+                user = %user,
+                ban_minutes = minutes,
+                expires_at = %ban.expires_at.to_rfc3339(),
+                by,
+                "User banned via API"
+            );
+            record_admin_event(
+                &state,
+                format!("User {} banned for {} minutes", user, minutes),
+                serde_json::json!({
+                    "user": user,
+                    "ban_minutes": minutes,
+                    "expires_at": ban.expires_at,
+                    "by": by,
+                }),
+            )
+            .await;
+            Some(ban)
+        }
+    };
+
+    let reason = termination_reason(&extensions);
+    let terminated = state
         .session_manager
-        .terminate_session(&session_uuid, "Terminated by admin", SessionStatus::Closed)
+        .terminate_user_sessions(&user, &reason)
         .await;
+    info!(
+        target: ACCESS_LOG_TARGET,
+        stage = "user_terminate",
+        outcome = "terminated",
+        user = %user,
+        sessions = terminated.len(),
+        by,
+        "User sessions terminated via API"
+    );
+    record_admin_event(
+        &state,
+        format!("{} sessions of user {} terminated", terminated.len(), user),
+        serde_json::json!({
+            "user": user,
+            "sessions": terminated.len(),
+            "by": by,
+        }),
+    )
+    .await;
 
-    (
-        StatusCode::OK,
-        Json(serde_json::json!({
-            "success": true,
-            "message": "Session terminated successfully"
-        })),
+    Ok(Json(TerminateUserSessionsResponse {
+        user,
+        terminated: terminated.iter().map(Uuid::to_string).collect(),
+        close_reason: reason,
+        ban: ban.map(UserBanResponse::from),
+    }))
+}
+
+/// GET /api/users/banned - Active user bans, soonest to expire first
+pub async fn get_banned_users(State(state): State<ApiState>) -> Json<Vec<UserBanResponse>> {
+    let bans = state
+        .user_bans
+        .as_ref()
+        .map(|bans| bans.list())
+        .unwrap_or_default();
+    Json(bans.into_iter().map(UserBanResponse::from).collect())
+}
+
+/// DELETE /api/users/banned/{user} - Lift a user's ban before it runs out
+pub async fn delete_user_ban(
+    State(state): State<ApiState>,
+    Path(user): Path<String>,
+    extensions: Extensions,
+) -> StatusCode {
+    let lifted = state.user_bans.as_ref().and_then(|bans| bans.lift(&user));
+    if lifted.is_none() {
+        return StatusCode::NOT_FOUND;
+    }
+
+    let by = api_caller(&extensions);
+    info!(
+        target: ACCESS_LOG_TARGET,
+        stage = "user_ban",
+        outcome = "lifted",
+        user = %user,
+        by,
+        "User ban lifted via API"
+    );
+    record_admin_event(
+        &state,
+        format!("Ban of user {} lifted", user),
+        serde_json::json!({ "user": user, "by": by }),
     )
+    .await;
+    StatusCode::NO_CONTENT
+}
+
+/// Name of the API key or dashboard user making the request, "-" when unknown
+fn api_caller(extensions: &Extensions) -> &str {
+    extensions
+        .get::<ApiCaller>()
+        .map(|caller| caller.0.as_str())
+        .unwrap_or("-")
+}
+
+/// Keep an admin action in the telemetry history, when enabled
+async fn record_admin_event(state: &ApiState, message: String, details: serde_json::Value) {
+    if let Some(history) = state.telemetry_history.as_ref() {
+        history
+            .record_event(TelemetrySeverity::Info, "admin", message, Some(details))
+            .await;
+    }
+}
+
+/// Close reason naming who requested a termination, e.g.
+/// `Terminated by admin (key ops) from 10.0.0.5`
+fn termination_reason(extensions: &Extensions) -> String {
+    let caller = extensions
+        .get::<ApiCaller>()
+        .map(|caller| caller.0.as_str());
+    let peer = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+
+    let mut reason = String::from("Terminated by admin");
+    if let Some(caller) = caller {
+        reason.push_str(&format!(" ({})", caller));
+    }
+    if let Some(peer) = peer {
+        reason.push_str(&format!(" from {}", peer));
+    }
+    reason
+}
+
+/// [`session_to_response`] plus the parent proxy an active session is tunnelled through,
+/// its health, and whether the session is draining
+fn active_session_to_response(
+    state: &ApiState,
+    session: crate::session::Session,
+) -> SessionResponse {
+    let draining = state.session_manager.is_draining(&session.session_id);
+    let parent = state.upstream_proxy.as_deref().filter(|_| session.chained);
+    let mut response = session_to_response(session);
+    response.upstream = parent.map(|parent| parent.endpoint());
+    response.upstream_healthy = parent.map(|parent| parent.is_healthy());
+    response.draining = draining;
+    response
 }
 
 /// Helper function to convert internal Session to API SessionResponse
 fn session_to_response(session: crate::session::Session) -> SessionResponse {
+    let rates = if session.status == SessionStatus::Active {
+        session.transfer_rate.rates(std::time::Instant::now())
+    } else {
+        Default::default()
+    };
     SessionResponse {
         id: session.session_id.to_string(),
+        instance_id: session.instance_id.to_string(),
         user: session.user.to_string(),
+        authenticated_user: session.authenticated_user.as_ref().map(|s| s.to_string()),
+        correlation_id: session.correlation_id.as_ref().map(|s| s.to_string()),
+        groups: session
+            .groups
+            .iter()
+            .map(|group| group.to_string())
+            .collect(),
         source_ip: session.source_ip.to_string(),
         source_port: session.source_port,
         dest_ip: session.dest_ip.to_string(),
+        dest_host: session.dest_host.to_string(),
         dest_port: session.dest_port,
+        sni_host: session.sni_host.as_ref().map(|s| s.to_string()),
+        requested_host: session.requested_host.as_ref().map(|s| s.to_string()),
+        requested_host_source: session
+            .requested_host_source
+            .map(|s| s.as_str().to_string()),
         protocol: session.protocol.as_str().to_string(),
+        command: session.command.as_str().to_string(),
+        socks_version: session.socks_version,
+        chained: session.chained,
+        upstream: None,
+        upstream_healthy: None,
+        draining: false,
+        upstream_tls: session.upstream_tls,
+        would_block: session.would_block,
+        egress_ip: session.egress_ip.map(|ip| ip.to_string()),
+        resolved_ips: session
+            .resolved_ips
+            .iter()
+            .map(|ip| ip.to_string())
+            .collect(),
+        connected_ip: session.connected_ip.map(|ip| ip.to_string()),
+        acl_matched_on: session.acl_matched_on.map(|on| on.as_str().to_string()),
+        reply_code: session.reply_code,
+        failure: session.failure.map(|f| f.as_str().to_string()),
         status: session.status.as_str().to_string(),
         acl_decision: session.acl_decision.to_string(),
         acl_rule: session.acl_rule_matched.as_ref().map(|s| s.to_string()),
         bytes_sent: session.bytes_sent,
         bytes_received: session.bytes_received,
+        rate_bps_sent: rates.sent_bps,
+        rate_bps_received: rates.received_bps,
+        rate_bps_sent_60s: rates.sent_bps_60s,
+        rate_bps_received_60s: rates.received_bps_60s,
+        keepalive_probes: session.keepalive_probes,
+        udp_association_mode: session
+            .udp_association_mode
+            .map(|mode| mode.as_str().to_string()),
+        udp_client_endpoint: session.udp_client_endpoint.map(|addr| addr.to_string()),
+        udp_rejected_datagrams: session.udp_rejected_datagrams,
+        udp_throttled_datagrams: session.udp_throttled_datagrams,
         start_time: session.start_time.to_rfc3339(),
         end_time: session.end_time.map(|t| t.to_rfc3339()),
         duration_seconds: session.duration_secs,
@@ -0,0 +1,178 @@
+//! Public status page (`sessions.public_status_enabled`).
+//!
+//! `/status` and `/status.json` are served without authentication, so the response is
+//! assembled from aggregate counters into [`PublicStatus`] and nothing else: no session
+//! is ever read, which keeps usernames and destinations out by construction.
+
+use crate::api::handlers::sessions::ApiState;
+use crate::api::types::{PublicState, PublicStatus};
+use crate::qos::format_rate;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+
+/// Seconds between reloads of the HTML page
+const REFRESH_SECS: u32 = 30;
+
+/// Upper bounds (inclusive) of the active session buckets
+const SESSION_BUCKETS: [(usize, &str); 7] = [
+    (0, "0"),
+    (10, "1-10"),
+    (100, "11-100"),
+    (500, "101-500"),
+    (1_000, "501-1k"),
+    (5_000, "1k-5k"),
+    (10_000, "5k-10k"),
+];
+
+/// GET /status.json - Coarse, unauthenticated service status
+pub async fn get_public_status(State(state): State<ApiState>) -> Response {
+    match public_status(&state).await {
+        Some(status) => Json(status).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// GET /status - HTML rendering of [`get_public_status`] that refreshes itself
+pub async fn get_public_status_page(State(state): State<ApiState>) -> Response {
+    match public_status(&state).await {
+        Some(status) => Html(render_page(&status)).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Current status, or `None` when the page is disabled.
+async fn public_status(state: &ApiState) -> Option<PublicStatus> {
+    let settings = &state.config_snapshot.sessions;
+    if !settings.public_status_enabled {
+        return None;
+    }
+
+    let maintenance_message = settings
+        .maintenance_message
+        .as_deref()
+        .map(str::trim)
+        .filter(|message| !message.is_empty())
+        .map(str::to_string);
+    let state_value = if maintenance_message.is_some() {
+        PublicState::Maintenance
+    } else if state
+        .overload
+        .as_ref()
+        .is_some_and(|shedder| shedder.is_shedding())
+    {
+        PublicState::Degraded
+    } else {
+        PublicState::Up
+    };
+
+    let throughput_bytes_per_sec = match &state.metrics_history {
+        Some(history) => history.recent_throughput().await.map(round_significant),
+        None => None,
+    };
+
+    Some(PublicStatus {
+        state: state_value,
+        uptime_minutes: state.start_time.elapsed().as_secs() / 60,
+        active_sessions: session_bucket(state.session_manager.active_session_count()).to_string(),
+        throughput_bytes_per_sec,
+        throughput_human: throughput_bytes_per_sec.map(format_rate),
+        maintenance_message,
+    })
+}
+
+fn session_bucket(count: usize) -> &'static str {
+    SESSION_BUCKETS
+        .iter()
+        .find(|(upper, _)| count <= *upper)
+        .map_or("10k+", |(_, label)| label)
+}
+
+/// Round to two significant digits so the figure cannot be used to count sessions.
+fn round_significant(value: f64) -> u64 {
+    if !value.is_finite() || value < 1.0 {
+        return 0;
+    }
+    let scale = 10f64.powi((value.log10().floor() as i32 - 1).max(0));
+    ((value / scale).round() * scale) as u64
+}
+
+fn format_uptime(minutes: u64) -> String {
+    match (minutes / (24 * 60), minutes / 60 % 24, minutes % 60) {
+        (0, 0, m) => format!("{}m", m),
+        (0, h, m) => format!("{}h {}m", h, m),
+        (d, h, _) => format!("{}d {}h", d, h),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_page(status: &PublicStatus) -> String {
+    let (label, color) = match status.state {
+        PublicState::Up => ("Operational", "#2e7d32"),
+        PublicState::Degraded => ("Degraded performance", "#ef6c00"),
+        PublicState::Maintenance => ("Maintenance", "#1565c0"),
+    };
+    let message = status
+        .maintenance_message
+        .as_deref()
+        .map(|message| format!(r#"<p class="message">{}</p>"#, escape_html(message)))
+        .unwrap_or_default();
+    let throughput = status
+        .throughput_human
+        .clone()
+        .unwrap_or_else(|| "n/a".to_string());
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+    <meta http-equiv="refresh" content="{refresh}" />
+    <title>RustSocks status</title>
+    <style>
+        body {{ font-family: system-ui, sans-serif; max-width: 32rem; margin: 3rem auto; padding: 0 1rem; color: #222; }}
+        .state {{ padding: 1rem; border-radius: 6px; color: #fff; background: {color}; font-size: 1.25rem; }}
+        .message {{ padding: 0.75rem 1rem; border-left: 4px solid {color}; background: #f5f5f5; }}
+        dl {{ display: grid; grid-template-columns: auto 1fr; gap: 0.5rem 1.5rem; }}
+        dt {{ color: #666; }}
+        footer {{ color: #999; font-size: 0.85rem; margin-top: 2rem; }}
+    </style>
+</head>
+<body>
+    <div class="state">{label}</div>
+    {message}
+    <dl>
+        <dt>Uptime</dt><dd>{uptime}</dd>
+        <dt>Active sessions</dt><dd>{sessions}</dd>
+        <dt>Throughput</dt><dd>{throughput}</dd>
+    </dl>
+    <footer>Refreshes every {refresh} seconds</footer>
+</body>
+</html>"#,
+        refresh = REFRESH_SECS,
+        color = color,
+        label = label,
+        message = message,
+        uptime = format_uptime(status.uptime_minutes),
+        sessions = escape_html(&status.active_sessions),
+        throughput = throughput,
+    )
+}
@@ -1,9 +1,13 @@
+use crate::api::handlers::sessions::ApiState;
 use crate::api::types::SystemResourcesResponse;
-use axum::{http::StatusCode, Json};
+use crate::server::guardrails::{fd_limit, open_fd_count};
+use axum::{extract::State, http::StatusCode, Json};
 use sysinfo::{CpuRefreshKind, MemoryRefreshKind, ProcessRefreshKind, RefreshKind, System};
 
 /// GET /api/system/resources - Get system and process resource usage
-pub async fn get_system_resources() -> (StatusCode, Json<SystemResourcesResponse>) {
+pub async fn get_system_resources(
+    State(state): State<ApiState>,
+) -> (StatusCode, Json<SystemResourcesResponse>) {
     // Create system instance with refresh settings
     let mut sys = System::new_with_specifics(
         RefreshKind::nothing()
@@ -42,6 +46,15 @@ pub async fn get_system_resources() -> (StatusCode, Json<SystemResourcesResponse
     // Get load average (Unix-like systems only)
     let load_average_1m = System::load_average().one;
 
+    #[cfg(feature = "database")]
+    let session_writer = state
+        .session_manager
+        .batch_writer_stats()
+        .await
+        .map(Into::into);
+    #[cfg(not(feature = "database"))]
+    let session_writer = None;
+
     let response = SystemResourcesResponse {
         system_cpu_percent,
         system_ram_percent,
@@ -54,6 +67,10 @@ pub async fn get_system_resources() -> (StatusCode, Json<SystemResourcesResponse
         } else {
             None
         },
+        session_writer,
+        process_open_fds: open_fd_count(),
+        process_fd_limit: fd_limit(),
+        guardrails: state.resource_guard.as_ref().map(|guard| guard.status()),
     };
 
     (StatusCode::OK, Json(response))
@@ -66,7 +66,7 @@ pub async fn get_telemetry_events(
         events.retain(|event| event.category.eq_ignore_ascii_case(&normalized));
     }
 
-    events.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    events.sort_by_key(|event| std::cmp::Reverse(event.timestamp));
     let limit = params.limit.unwrap_or(100).clamp(1, 500);
     events.truncate(limit);
 
@@ -0,0 +1,13 @@
+use crate::api::handlers::sessions::ApiState;
+use crate::server::UpstreamStatus;
+use axum::{extract::State, http::StatusCode, Json};
+
+/// GET /api/upstream - health of the `[server.upstream]` parent proxy and its sessions
+pub async fn get_upstream_status(
+    State(state): State<ApiState>,
+) -> axum::response::Result<Json<UpstreamStatus>> {
+    let Some(proxy) = state.upstream_proxy.as_ref() else {
+        return Err((StatusCode::NOT_FOUND, "No upstream proxy configured").into());
+    };
+    Ok(Json(proxy.status(&state.session_manager).await))
+}
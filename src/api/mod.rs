@@ -1,4 +1,5 @@
 pub mod auth;
+pub mod encoding;
 pub mod handlers;
 pub mod server;
 pub mod types;
@@ -17,26 +17,41 @@ use tower_http::services::{ServeDir, ServeFile};
 use tracing::{error, info, warn};
 
 use crate::api::auth::{
-    altcha_challenge_handler, altcha_config_handler, check_auth_handler,
-    extract_session_from_headers, login_handler, logout_handler, AuthState,
+    altcha_challenge_handler, altcha_config_handler, api_key_auth, check_auth_handler,
+    extract_session_from_headers, login_handler, logout_handler, ApiAuthState, AuthState,
+};
+use crate::api::encoding::{
+    compression_layer, conditional_get, skip_compression, CompressionExclusions,
 };
 use crate::api::handlers::sessions::ApiState;
 use crate::api::handlers::{
     acl_management::{
         add_group_rule, add_user_rule, add_user_to_group, create_group, create_user, delete_group,
-        delete_group_rule, delete_user, delete_user_rule, get_global_settings, get_group_detail,
-        get_user_detail, list_groups, list_users, remove_user_from_group, search_rules,
-        update_global_settings, update_group_rule, update_user_rule,
+        delete_group_rule, delete_user, delete_user_rule, export_acl_config, get_global_settings,
+        get_group_detail, get_user_detail, import_acl_config, list_groups, list_users,
+        remove_user_from_group, search_rules, set_group_parents, update_global_settings,
+        update_group_rule, update_user_rule,
     },
-    get_pool_stats, get_system_resources,
+    admission::{get_admission_rejections, stream_admission_rejections, test_admission},
+    delete_qos_user_limit,
+    events::session_events_ws,
+    get_pool_stats, get_probes, get_qos_allocations, get_qos_config, get_system_resources,
+    get_upstream_status,
     management::{
-        get_acl_rules, get_config_file, get_metrics, get_runtime_config, health_check, reload_acl,
-        test_acl_decision, update_config_file, update_runtime_config,
+        flush_dns_cache, get_acl_example, get_acl_lint, get_acl_reload_status, get_acl_rule_stats,
+        get_acl_rules, get_config_file, get_metrics, get_overload_status, get_runtime_config,
+        get_unused_acl_rules, get_version, health_check, invalidate_address_cache, readiness_check,
+        reload_acl, reload_config, reset_acl_rule_stats, set_overload_mode, test_acl_decision,
+        update_config_file, update_runtime_config,
     },
     sessions::{
-        get_active_sessions, get_metrics_history, get_session_detail, get_session_history,
-        get_session_stats, get_user_sessions, terminate_session,
+        delete_user_ban, get_active_sessions, get_banned_users, get_destination_stats,
+        get_failure_stats, get_metrics_history, get_session_detail, get_session_history,
+        get_session_stats, get_session_writer_status, get_user_sessions, terminate_session,
+        terminate_user_sessions,
     },
+    set_qos_user_limit,
+    status::{get_public_status, get_public_status_page},
     telemetry::get_telemetry_events,
     test_tcp_connectivity,
 };
@@ -101,7 +116,7 @@ fn get_openapi_spec(base_path: String) -> serde_json::Value {
         "info": {
             "title": "RustSocks API",
             "version": env!("CARGO_PKG_VERSION"),
-            "description": "Complete REST API for RustSocks SOCKS5 proxy server with session tracking, ACL management, and metrics",
+            "description": "Complete REST API for RustSocks SOCKS5 proxy server with session tracking, ACL management, and metrics. With sessions.api_auth enabled every endpoint except the dashboard login, /status, the /health probes and the API docs needs an API key or a dashboard login: \"read\" keys reach GET endpoints and the read-only POSTs (/api/acl/test, /api/acl/search, /api/admission/test); every other change and /api/admin/* need an \"admin\" key. A missing or unknown key gets 401, a read key on an admin endpoint 403",
             "contact": {
                 "name": "RustSocks"
             }
@@ -150,189 +165,307 @@ fn get_openapi_spec(base_path: String) -> serde_json::Value {
                 "description": "Troubleshooting and connectivity checks"
             }
         ],
-        "paths": {
-            "/health": {
-                "get": {
-                    "summary": "Health check",
-                    "description": "Check if API server is healthy and operational",
-                    "tags": ["Health"],
-                    "operationId": "healthCheck",
-                    "responses": {
-                        "200": {
-                            "description": "Server is healthy",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "status": {"type": "string", "example": "healthy"},
-                                            "version": {"type": "string", "example": "0.1.0"}
-                                        }
-                                    }
+        "security": [
+            {"bearerAuth": []},
+            {"apiKeyHeader": []}
+        ],
+        "paths": openapi_paths(),
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "A sessions.api_auth.keys token"
+                },
+                "apiKeyHeader": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "X-API-Key",
+                    "description": "A sessions.api_auth.keys token"
+                }
+            },
+            "schemas": {
+                "RateValue": {
+                    "type": "object",
+                    "properties": {
+                        "bytes_per_sec": {"type": "integer"},
+                        "rate_human": {"type": "string", "example": "100 Mbps (12.5 MB/s)"}
+                    }
+                },
+                "QosUserLimit": {
+                    "type": "object",
+                    "properties": {
+                        "user": {"type": "string"},
+                        "max": {"$ref": "#/components/schemas/RateValue"}
+                    }
+                },
+                "UserBan": {
+                    "type": "object",
+                    "properties": {
+                        "user": {"type": "string"},
+                        "banned_at": {"type": "string", "format": "date-time"},
+                        "expires_at": {"type": "string", "format": "date-time"},
+                        "banned_by": {"type": "string"}
+                    }
+                },
+                "ReadinessResponse": {
+                    "type": "object",
+                    "properties": {
+                        "status": {"type": "string", "enum": ["ready", "not_ready", "draining"]},
+                        "components": {
+                            "type": "object",
+                            "description": "socks_listener, acl, session_store and qos",
+                            "additionalProperties": {
+                                "type": "object",
+                                "properties": {
+                                    "status": {"type": "string", "enum": ["up", "down", "disabled"]},
+                                    "detail": {"type": "string", "example": "0.0.0.0:1080"}
                                 }
                             }
                         }
                     }
-                }
-            },
-            "/metrics": {
-                "get": {
-                    "summary": "Prometheus metrics",
-                    "description": "Get metrics in Prometheus text format",
-                    "tags": ["Metrics"],
-                    "operationId": "getMetrics",
-                    "responses": {
-                        "200": {
-                            "description": "Prometheus metrics",
-                            "content": {
-                                "text/plain": {
-                                    "schema": {"type": "string"}
+                },
+                "FailureStatsResponse": {
+                    "type": "object",
+                    "properties": {
+                        "window_minutes": {"type": "integer"},
+                        "since": {"type": "string", "format": "date-time"},
+                        "oldest_kept": {"type": "string", "format": "date-time", "nullable": true, "description": "Oldest failure still in memory; older failures in the window were dropped"},
+                        "total": {"type": "integer"},
+                        "by_category": {
+                            "type": "object",
+                            "description": "Failures per category in the window, zeros included",
+                            "additionalProperties": {"type": "integer"},
+                            "example": {"dns_error": 3, "connect_timeout": 12, "connect_refused": 1, "connect_error": 0, "upstream_tls": 0, "acl_block": 40, "auth_fail": 2}
+                        },
+                        "top_destinations": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "destination": {"type": "string", "example": "example.com:443"},
+                                    "failures": {"type": "integer"},
+                                    "by_category": {"type": "object", "additionalProperties": {"type": "integer"}}
                                 }
                             }
+                        },
+                        "totals_since_start": {
+                            "type": "object",
+                            "description": "Failures per category since the server started",
+                            "additionalProperties": {"type": "integer"}
                         }
                     }
+                },
+                "AdmissionRejection": {
+                    "type": "object",
+                    "properties": {
+                        "timestamp": {"type": "string", "format": "date-time"},
+                        "stage": {"type": "string", "enum": ["admission"]},
+                        "limit_type": {"type": "string", "enum": ["global_connections", "user_connections"]},
+                        "user": {"type": "string"},
+                        "source_ip": {"type": "string"},
+                        "source_port": {"type": "integer"},
+                        "current": {"type": "integer", "description": "Connections counted against the limit when the check ran"},
+                        "limit": {"type": "integer"},
+                        "destination": {"type": "string", "nullable": true, "description": "host:port when already known (SOCKS4); null for SOCKS5"},
+                        "correlation_id": {"type": "string", "description": "Client-supplied correlation ID; omitted when none"}
+                    }
+                },
+                "AdmissionRejectionStats": {
+                    "type": "object",
+                    "properties": {
+                        "total": {"type": "integer"},
+                        "global_connections": {"type": "integer"},
+                        "user_connections": {"type": "integer"}
+                    }
+                },
+                "OverloadStatus": {
+                    "type": "object",
+                    "properties": {
+                        "mode": {"type": "string", "enum": ["auto", "force_on", "force_off"]},
+                        "shedding": {"type": "boolean"},
+                        "signal": {"type": "number", "description": "Last sampled signal value"},
+                        "reject_ratio": {"type": "number", "example": 0.1},
+                        "rejected_connections": {"type": "integer"},
+                        "engagements": {"type": "integer", "description": "How many times automatic shedding has engaged"}
+                    }
+                },
+                "AclRule": {
+                    "type": "object",
+                    "properties": {
+                        "action": {"type": "string", "enum": ["allow", "block"]},
+                        "description": {"type": "string"},
+                        "destinations": {"type": "array", "items": {"type": "string"}, "example": ["*.example.com", "10.0.0.0/8"]},
+                        "ports": {"type": "array", "items": {"type": "string"}, "example": ["22", "80", "443", "8000-9000"]},
+                        "sources": {"type": "array", "items": {"type": "string"}, "example": ["10.20.0.0/16"], "description": "Client IPs and CIDRs the rule is limited to; omitted = any client"},
+                        "protocols": {"type": "array", "items": {"type": "string", "enum": ["tcp", "udp", "both"]}},
+                        "priority": {"type": "integer", "example": 100},
+                        "log": {"type": "string", "enum": ["default", "silent", "minimal", "verbose"], "default": "default", "description": "Access-log verbosity for allowed connections; blocked connections always log in full"},
+                        "reply_code": {"type": "string", "enum": ["general_failure", "connection_not_allowed", "network_unreachable", "host_unreachable", "connection_refused", "ttl_expired"], "description": "SOCKS reply of a block rule; omitted = connection_not_allowed"},
+                        "resolve": {"type": "string", "enum": ["local", "remote"], "default": "local", "description": "Allow rules only; remote hands matched domains unresolved to the upstream proxy"},
+                        "schedule": {"$ref": "#/components/schemas/RuleSchedule"}
+                    }
+                },
+                "RuleSchedule": {
+                    "type": "object",
+                    "description": "When the rule applies; outside it the rule does not match. Omitted = always",
+                    "properties": {
+                        "days": {"type": "array", "items": {"type": "string"}, "example": ["mon-fri"], "description": "Weekdays or ranges; omitted = every day"},
+                        "times": {"type": "array", "items": {"type": "string"}, "example": ["08:00-18:00"], "description": "HH:MM-HH:MM, end exclusive; a range ending before it starts runs past midnight. Omitted = all day"},
+                        "timezone": {"type": "string", "example": "Europe/Warsaw", "description": "IANA time zone; omitted = server local time"}
+                    }
+                },
+                "AddRuleRequest": {
+                    "type": "object",
+                    "properties": {
+                        "action": {"type": "string", "enum": ["allow", "block"]},
+                        "description": {"type": "string"},
+                        "destinations": {"type": "array", "items": {"type": "string"}},
+                        "ports": {"type": "array", "items": {"type": "string"}},
+                        "sources": {"type": "array", "items": {"type": "string"}, "default": []},
+                        "protocols": {"type": "array", "items": {"type": "string", "enum": ["tcp", "udp", "both"]}},
+                        "priority": {"type": "integer"},
+                        "log": {"type": "string", "enum": ["default", "silent", "minimal", "verbose"], "default": "default"},
+                        "reply_code": {"type": "string", "enum": ["general_failure", "connection_not_allowed", "network_unreachable", "host_unreachable", "connection_refused", "ttl_expired"], "description": "Block rules only"},
+                        "resolve": {"type": "string", "enum": ["local", "remote"], "default": "local", "description": "Allow rules only"},
+                        "schedule": {"$ref": "#/components/schemas/RuleSchedule"}
+                    },
+                    "required": ["action", "description", "destinations", "ports", "protocols", "priority"]
+                },
+                "RuleIdentifier": {
+                    "type": "object",
+                    "description": "Identifies a rule by destination + optional port (NOT by index!)",
+                    "properties": {
+                        "destinations": {"type": "array", "items": {"type": "string"}},
+                        "ports": {"type": "array", "items": {"type": "string"}}
+                    },
+                    "required": ["destinations"]
+                },
+                "RuleOperationResponse": {
+                    "type": "object",
+                    "properties": {
+                        "success": {"type": "boolean"},
+                        "message": {"type": "string"},
+                        "rule": {"$ref": "#/components/schemas/AclRule"},
+                        "old_rule": {"$ref": "#/components/schemas/AclRule"}
+                    }
                 }
-            },
-            "/api/diagnostics/connectivity": {
-                "post": {
-                    "summary": "Test TCP connectivity",
-                    "description": "Attempt a TCP connection to the specified IP address and port",
-                    "tags": ["Diagnostics"],
-                    "operationId": "testTcpConnectivity",
-                    "requestBody": {
-                        "required": true,
+            }
+        }
+    })
+}
+
+/// Every documented path, each its own `json!` value: one literal holding all of them
+/// exceeds the macro recursion limit
+fn openapi_paths() -> serde_json::Map<String, serde_json::Value> {
+    let mut paths = serde_json::Map::new();
+    paths.insert(
+        "/health".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Health check",
+                "description": "Check if API server is healthy and operational. Answers 200 as long as the API does, including while shutdown waits for sessions to finish (server.shutdown_grace_period_secs), so a liveness probe does not kill the process mid-drain; remaining_sessions then counts the sessions still open. /health/ready reports the drain",
+                "tags": ["Health"],
+                "operationId": "healthCheck",
+                "responses": {
+                    "200": {
+                        "description": "Server is healthy",
                         "content": {
                             "application/json": {
                                 "schema": {
                                     "type": "object",
                                     "properties": {
-                                        "address": {"type": "string", "example": "8.8.8.8"},
-                                        "port": {"type": "integer", "format": "int32", "example": 53},
-                                        "timeout_ms": {"type": "integer", "format": "int64", "example": 3000}
-                                    },
-                                    "required": ["address", "port"]
-                                }
-                            }
-                        }
-                    },
-                    "responses": {
-                        "200": {
-                            "description": "Connectivity test result",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "address": {"type": "string"},
-                                            "port": {"type": "integer", "format": "int32"},
-                                            "success": {"type": "boolean"},
-                                            "latency_ms": {"type": "integer", "format": "int64", "nullable": true},
-                                            "message": {"type": "string"},
-                                            "error": {"type": "string", "nullable": true}
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        "400": {
-                            "description": "Invalid request payload",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "address": {"type": "string"},
-                                            "port": {"type": "integer", "format": "int32"},
-                                            "success": {"type": "boolean"},
-                                            "latency_ms": {"type": "integer", "format": "int64", "nullable": true},
-                                            "message": {"type": "string"},
-                                            "error": {"type": "string", "nullable": true}
-                                        }
+                                        "status": {"type": "string", "enum": ["healthy"]},
+                                        "version": {"type": "string", "example": "0.1.0"},
+                                        "uptime_seconds": {"type": "integer"},
+                                        "instance_id": {"type": "string", "format": "uuid", "description": "Random per boot"},
+                                        "overload": {"$ref": "#/components/schemas/OverloadStatus"},
+                                        "remaining_sessions": {"type": "integer", "description": "Sessions still open; only while draining"},
+                                        "listening": {"type": "array", "items": {"type": "string"}, "example": ["0.0.0.0:1080", "[::]:1080"], "description": "Addresses the SOCKS listeners are bound to"}
                                     }
                                 }
                             }
                         }
                     }
                 }
-            },
-            "/api/sessions/active": {
-                "get": {
-                    "summary": "Get active sessions",
-                    "description": "List all currently active SOCKS5 sessions",
-                    "tags": ["Sessions"],
-                    "operationId": "getActiveSessions",
-                    "responses": {
-                        "200": {
-                            "description": "List of active sessions",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "array",
-                                        "items": {"type": "object"}
-                                    }
-                                }
+            }
+        }),
+    );
+    paths.insert(
+        "/health/live".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Liveness check",
+                "description": "The process is up and serving the API; same response as /health, which stays as an alias",
+                "tags": ["Health"],
+                "operationId": "livenessCheck",
+                "responses": {
+                    "200": {"description": "Server is alive, draining included"}
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/health/ready".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Readiness check",
+                "description": "Whether the node can take SOCKS traffic: the SOCKS listener is bound, the ACL is loaded (acl.enabled), the session store answers SELECT 1 (database session storage) and the QoS engine is initialized (qos.enabled). Components that are not configured report \"disabled\" and do not count",
+                "tags": ["Health"],
+                "operationId": "readinessCheck",
+                "responses": {
+                    "200": {
+                        "description": "Every required component is up",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/ReadinessResponse"}
                             }
                         }
-                    }
-                }
-            },
-            "/api/sessions/history": {
-                "get": {
-                    "summary": "Get session history",
-                    "description": "Get historical session data with optional filtering by user, time, or destination",
-                    "tags": ["Sessions"],
-                    "operationId": "getSessionHistory",
-                    "parameters": [
-                        {
-                            "name": "user",
-                            "in": "query",
-                            "schema": {"type": "string"},
-                            "description": "Filter by username"
-                        },
-                        {
-                            "name": "hours",
-                            "in": "query",
-                            "schema": {"type": "integer"},
-                            "description": "Filter by time range in hours"
-                        },
-                        {
-                            "name": "dest_ip",
-                            "in": "query",
-                            "schema": {"type": "string"},
-                            "description": "Filter by destination IP"
-                        }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "Session history data",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"type": "object"}
-                                }
+                    },
+                    "503": {
+                        "description": "A required component is down (status \"not_ready\") or the server is draining (status \"draining\"); same body",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/ReadinessResponse"}
                             }
                         }
                     }
                 }
-            },
-            "/api/sessions/stats": {
-                "get": {
-                    "summary": "Get session statistics",
-                    "description": "Get aggregated session statistics for monitoring and analytics",
-                    "tags": ["Sessions"],
-                    "operationId": "getSessionStats",
-                    "responses": {
-                        "200": {
-                            "description": "Aggregated session statistics",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "active_sessions": {"type": "integer"},
-                                            "total_sessions": {"type": "integer"},
-                                            "total_bytes": {"type": "integer"},
-                                            "top_users": {"type": "array"},
-                                            "top_destinations": {"type": "array"}
+            }
+        }),
+    );
+    paths.insert(
+        "/api/version".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Build and runtime information",
+                "description": "Version, git commit, build time, compiler and cargo features of the running binary, with its uptime, active sessions, config file and the optional subsystems the configuration enables. The build part is also logged once at startup",
+                "tags": ["Health"],
+                "operationId": "getVersion",
+                "responses": {
+                    "200": {
+                        "description": "Build and runtime information",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "version": {"type": "string", "example": "0.9.0"},
+                                        "git_hash": {"type": "string", "description": "Commit the binary was built from; \"unknown\" outside a git checkout"},
+                                        "build_timestamp": {"type": "string", "format": "date-time", "nullable": true},
+                                        "rustc_version": {"type": "string", "example": "rustc 1.90.0 (1159e78c4 2025-09-14)"},
+                                        "features": {"type": "array", "items": {"type": "string"}, "example": ["database", "metrics", "fast-allocator"]},
+                                        "uptime_seconds": {"type": "integer"},
+                                        "active_sessions": {"type": "integer"},
+                                        "config_path": {"type": "string", "nullable": true, "example": "/etc/rustsocks/rustsocks.toml"},
+                                        "subsystems": {
+                                            "type": "object",
+                                            "properties": {
+                                                "acl": {"type": "boolean"},
+                                                "qos": {"type": "boolean"},
+                                                "tls": {"type": "boolean", "description": "TLS on the SOCKS listener"},
+                                                "session_storage": {"type": "string", "example": "sqlite", "description": "memory, the database URL scheme (sqlite, mysql, postgres) or disabled"}
+                                            }
                                         }
                                     }
                                 }
@@ -340,612 +473,2323 @@ fn get_openapi_spec(base_path: String) -> serde_json::Value {
                         }
                     }
                 }
-            },
-            "/api/sessions/{id}": {
-                "get": {
-                    "summary": "Get session detail",
-                    "description": "Get detailed information about a specific session",
-                    "tags": ["Sessions"],
-                    "operationId": "getSessionDetail",
-                    "parameters": [
-                        {
-                            "name": "id",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Session ID"
-                        }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "Session details",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"type": "object"}
+            }
+        }),
+    );
+    paths.insert(
+        "/status.json".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Public status",
+                "description": "Coarse service status without usernames, destinations or exact counts. Served without authentication when sessions.public_status_enabled is set; /status renders the same data as an auto-refreshing HTML page",
+                "tags": ["Health"],
+                "operationId": "getPublicStatus",
+                "security": [],
+                "responses": {
+                    "200": {
+                        "description": "Current status",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "state": {"type": "string", "enum": ["up", "degraded", "maintenance"]},
+                                        "uptime_minutes": {"type": "integer"},
+                                        "active_sessions": {"type": "string", "example": "1k-5k"},
+                                        "throughput_bytes_per_sec": {"type": "integer", "nullable": true, "description": "Rounded to two significant digits"},
+                                        "throughput_human": {"type": "string", "nullable": true, "example": "100 Mbps (12.5 MB/s)"},
+                                        "maintenance_message": {"type": "string", "nullable": true}
+                                    }
                                 }
                             }
-                        },
-                        "404": {
-                            "description": "Session not found"
                         }
+                    },
+                    "404": {
+                        "description": "Public status page is disabled"
                     }
                 }
-            },
-            "/api/users/{user}/sessions": {
-                "get": {
-                    "summary": "Get user sessions",
-                    "description": "Get all sessions for a specific user",
-                    "tags": ["Sessions"],
-                    "operationId": "getUserSessions",
-                    "parameters": [
-                        {
-                            "name": "user",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Username"
-                        }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "User's sessions",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"type": "array"}
-                                }
+            }
+        }),
+    );
+    paths.insert(
+        "/metrics".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Prometheus metrics",
+                "description": "Get metrics in Prometheus text format",
+                "tags": ["Metrics"],
+                "operationId": "getMetrics",
+                "responses": {
+                    "200": {
+                        "description": "Prometheus metrics",
+                        "content": {
+                            "text/plain": {
+                                "schema": {"type": "string"}
                             }
                         }
                     }
                 }
-            },
-            "/api/acl/rules": {
-                "get": {
-                    "summary": "Get ACL rules",
-                    "description": "Get current Access Control List rules configuration",
-                    "tags": ["ACL"],
-                    "operationId": "getAclRules",
-                    "responses": {
-                        "200": {
-                            "description": "ACL rules summary",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "user_count": {"type": "integer"},
-                                            "group_count": {"type": "integer"},
-                                            "message": {"type": "string"}
+            }
+        }),
+    );
+    paths.insert(
+        "/api/qos/allocations".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "QoS bandwidth allocations",
+                "description": "Configured HTB limits and the current allocation of every user. With qos.per_ip enabled, also the per-IP limit and the source IPs that relayed the most bytes, cut to ip_limit. Each rate carries the raw `bytes_per_sec` and `rate_human` in bits and bytes",
+                "tags": ["Metrics"],
+                "operationId": "getQosAllocations",
+                "parameters": [
+                    {"name": "ip_limit", "in": "query", "schema": {"type": "integer", "default": 50, "minimum": 1, "maximum": 1000}, "description": "Source IPs listed, heaviest first"}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Allocations, sorted by user",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "enabled": {"type": "boolean"},
+                                        "limits": {
+                                            "type": "object",
+                                            "nullable": true,
+                                            "properties": {
+                                                "global": {"$ref": "#/components/schemas/RateValue"},
+                                                "guaranteed_per_user": {"$ref": "#/components/schemas/RateValue"},
+                                                "max_per_user": {"$ref": "#/components/schemas/RateValue"},
+                                                "burst_size_bytes": {"type": "integer"}
+                                            }
+                                        },
+                                        "users": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "user": {"type": "string"},
+                                                    "allocated": {"$ref": "#/components/schemas/RateValue"},
+                                                    "guaranteed": {"$ref": "#/components/schemas/RateValue"},
+                                                    "borrowed": {"$ref": "#/components/schemas/RateValue"},
+                                                    "max": {"$ref": "#/components/schemas/RateValue"},
+                                                    "max_overridden": {"type": "boolean", "description": "max was set through PUT /api/qos/users/{user}/limit"},
+                                                    "current_demand": {"$ref": "#/components/schemas/RateValue"},
+                                                    "is_active": {"type": "boolean"},
+                                                    "is_throttled": {"type": "boolean", "description": "A transfer is waiting for the user's tokens right now"},
+                                                    "active_connections": {"type": "integer"}
+                                                }
+                                            }
+                                        },
+                                        "per_ip": {
+                                            "type": "object",
+                                            "description": "Absent unless qos.per_ip is enabled",
+                                            "properties": {
+                                                "max_per_ip": {"$ref": "#/components/schemas/RateValue"},
+                                                "burst_bytes": {"type": "integer"},
+                                                "exempt": {"type": "array", "items": {"type": "string"}},
+                                                "tracked_ips": {"type": "integer", "description": "Source IPs with a bucket; idle ones are evicted"},
+                                                "truncated": {"type": "boolean", "description": "ips holds only the ip_limit heaviest addresses"},
+                                                "ips": {
+                                                    "type": "array",
+                                                    "items": {
+                                                        "type": "object",
+                                                        "properties": {
+                                                            "ip": {"type": "string"},
+                                                            "total_bytes": {"type": "integer"},
+                                                            "available_bytes": {"type": "integer"},
+                                                            "is_active": {"type": "boolean"}
+                                                        }
+                                                    }
+                                                }
+                                            }
                                         }
                                     }
                                 }
                             }
-                        },
-                        "400": {
-                            "description": "ACL is not enabled"
                         }
                     }
                 }
-            },
-            "/api/acl/test": {
-                "post": {
-                    "summary": "Test ACL decision",
-                    "description": "Test if a connection would be allowed or blocked by ACL rules",
-                    "tags": ["ACL"],
-                    "operationId": "testAclDecision",
-                    "requestBody": {
-                        "required": true,
+            }
+        }),
+    );
+    paths.insert(
+        "/api/qos/config".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Effective QoS configuration",
+                "description": "HTB parameters in effect, including rates changed by a config reload, and the per-user maximums set at runtime",
+                "tags": ["Metrics"],
+                "operationId": "getQosConfig",
+                "responses": {
+                    "200": {
+                        "description": "QoS configuration",
                         "content": {
                             "application/json": {
                                 "schema": {
                                     "type": "object",
                                     "properties": {
-                                        "user": {"type": "string", "example": "alice"},
-                                        "destination": {"type": "string", "example": "192.168.1.1"},
-                                        "port": {"type": "integer", "example": 443},
-                                        "protocol": {"type": "string", "enum": ["tcp", "udp", "both"], "example": "tcp"}
-                                    },
-                                    "required": ["user", "destination", "port", "protocol"]
-                                }
-                            }
-                        }
-                    },
-                    "responses": {
-                        "200": {
-                            "description": "ACL decision result",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "decision": {"type": "string", "enum": ["allow", "block"]},
-                                            "matched_rule": {"type": "string"}
+                                        "enabled": {"type": "boolean"},
+                                        "htb": {
+                                            "type": "object",
+                                            "nullable": true,
+                                            "properties": {
+                                                "global": {"$ref": "#/components/schemas/RateValue"},
+                                                "guaranteed_per_user": {"$ref": "#/components/schemas/RateValue"},
+                                                "max_per_user": {"$ref": "#/components/schemas/RateValue"},
+                                                "burst_size_bytes": {"type": "integer"},
+                                                "refill_interval_ms": {"type": "integer"},
+                                                "fair_sharing_enabled": {"type": "boolean"},
+                                                "rebalance_interval_ms": {"type": "integer"},
+                                                "idle_timeout_secs": {"type": "integer"}
+                                            }
+                                        },
+                                        "user_limits": {
+                                            "type": "array",
+                                            "items": {"$ref": "#/components/schemas/QosUserLimit"}
                                         }
                                     }
                                 }
                             }
-                        },
-                        "400": {
-                            "description": "Invalid parameters or ACL not enabled"
                         }
                     }
                 }
-            },
-            "/api/admin/reload-acl": {
-                "post": {
-                    "summary": "Reload ACL configuration",
-                    "description": "Reload ACL rules from configuration file without restarting server",
-                    "tags": ["Admin"],
-                    "operationId": "reloadAcl",
-                    "responses": {
-                        "200": {
-                            "description": "ACL reloaded successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": {"type": "boolean"},
-                                            "message": {"type": "string"}
-                                        }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/qos/users/{user}/limit".to_string(),
+        serde_json::json!({
+            "put": {
+                "summary": "Override a user's maximum bandwidth",
+                "description": "Replaces qos.htb.max_bandwidth_bytes_per_sec for one user until the server restarts or the override is deleted; config reloads keep it. A maximum below the guaranteed rate lowers that too. Applies to the user's running transfers at once",
+                "tags": ["Admin"],
+                "operationId": "setQosUserLimit",
+                "parameters": [
+                    {"name": "user", "in": "path", "required": true, "schema": {"type": "string"}}
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "required": ["max_bandwidth"],
+                                "properties": {
+                                    "max_bandwidth": {
+                                        "oneOf": [{"type": "integer", "minimum": 1}, {"type": "string"}],
+                                        "description": "Bytes per second, or a rate with a unit such as \"50mbps\" or \"6.25MB/s\""
                                     }
                                 }
                             }
-                        },
-                        "400": {
-                            "description": "ACL is not enabled"
-                        },
-                        "500": {
-                            "description": "Failed to reload ACL configuration"
                         }
                     }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Override in effect",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/QosUserLimit"}
+                            }
+                        }
+                    },
+                    "400": {"description": "QoS is not enabled, or the rate is 0"},
+                    "422": {"description": "max_bandwidth is not a valid rate"}
                 }
             },
-            "/api/admin/config-file": {
-                "get": {
-                    "summary": "Get RustSocks configuration file",
-                    "description": "Return the current configuration file content and metadata",
-                    "tags": ["Admin"],
-                    "operationId": "getConfigFile",
-                    "responses": {
-                        "200": {
-                            "description": "Configuration file contents",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
+            "delete": {
+                "summary": "Remove a user's bandwidth override",
+                "description": "Return the user to qos.htb.max_bandwidth_bytes_per_sec",
+                "tags": ["Admin"],
+                "operationId": "deleteQosUserLimit",
+                "parameters": [
+                    {"name": "user", "in": "path", "required": true, "schema": {"type": "string"}}
+                ],
+                "responses": {
+                    "204": {"description": "Override removed"},
+                    "404": {"description": "No override set for this user"}
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/metrics/history".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get metrics history",
+                "description": "Stream metrics snapshots for a time range, oldest first, decimated to max_points by keeping the per-bucket minimum and maximum of active sessions and bandwidth. Ranges are read from raw samples or from 1-minute or 15-minute rollups; the X-Metrics-Resolution response header names the tier used. Accept: application/msgpack returns the same array as MessagePack",
+                "tags": ["Metrics"],
+                "operationId": "getMetricsHistory",
+                "parameters": [
+                    {"name": "start", "in": "query", "schema": {"type": "string", "format": "date-time"}, "description": "Range start (default: minutes before end)"},
+                    {"name": "end", "in": "query", "schema": {"type": "string", "format": "date-time"}, "description": "Range end (default: now)"},
+                    {"name": "minutes", "in": "query", "schema": {"type": "integer", "default": 120}, "description": "Look-back window when start is absent"},
+                    {"name": "max_points", "in": "query", "schema": {"type": "integer", "default": 1440, "minimum": 4, "maximum": 10000}, "description": "Upper bound on returned samples"},
+                    {"name": "format", "in": "query", "schema": {"type": "string", "enum": ["json", "ndjson"]}, "description": "Response encoding; overrides the Accept header"},
+                    {"name": "resolution", "in": "query", "schema": {"type": "string", "enum": ["auto", "raw", "1m", "15m"], "default": "auto"}, "description": "History tier; auto picks the coarsest tier still finer than one point and uses rollups where raw samples have aged out"}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Metrics snapshots",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "items": {
                                         "type": "object",
                                         "properties": {
-                                            "path": {"type": "string", "nullable": true},
-                                            "content": {"type": "string"},
-                                            "editable": {"type": "boolean"}
+                                            "timestamp": {"type": "string", "format": "date-time"},
+                                            "active_sessions": {"type": "integer"},
+                                            "total_sessions": {"type": "integer"},
+                                            "bandwidth": {"type": "integer"},
+                                            "rollup": {
+                                                "type": "object",
+                                                "description": "Present on rollup points, whose series then hold the bucket averages; each series has min, max, avg and sum",
+                                                "properties": {
+                                                    "samples": {"type": "integer"},
+                                                    "active_sessions": {"type": "object"},
+                                                    "total_sessions": {"type": "object"},
+                                                    "bandwidth": {"type": "object"}
+                                                }
+                                            }
                                         }
                                     }
                                 }
+                            },
+                            "application/x-ndjson": {
+                                "schema": {"type": "string"}
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "start after end, unknown format or unknown resolution"
+                    },
+                    "413": {
+                        "description": "Raw range wider than metrics.history_max_range_hours"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/diagnostics/connectivity".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Test TCP connectivity",
+                "description": "Attempt a TCP connection to the specified IP address and port",
+                "tags": ["Diagnostics"],
+                "operationId": "testTcpConnectivity",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "address": {"type": "string", "example": "8.8.8.8"},
+                                    "port": {"type": "integer", "format": "int32", "example": 53},
+                                    "timeout_ms": {"type": "integer", "format": "int64", "example": 3000}
+                                },
+                                "required": ["address", "port"]
                             }
-                        },
-                        "400": {
-                            "description": "Editing not available"
                         }
                     }
                 },
-                "put": {
-                    "summary": "Update configuration file",
-                    "description": "Validate and persist a new configuration file and optionally restart RustSocks",
-                    "tags": ["Admin"],
-                    "operationId": "updateConfigFile",
-                    "requestBody": {
-                        "required": true,
+                "responses": {
+                    "200": {
+                        "description": "Connectivity test result",
                         "content": {
                             "application/json": {
                                 "schema": {
                                     "type": "object",
                                     "properties": {
-                                        "content": {"type": "string"},
-                                        "restart": {"type": "boolean", "default": true}
-                                    },
-                                    "required": ["content"]
+                                        "address": {"type": "string"},
+                                        "port": {"type": "integer", "format": "int32"},
+                                        "success": {"type": "boolean"},
+                                        "latency_ms": {"type": "integer", "format": "int64", "nullable": true},
+                                        "message": {"type": "string"},
+                                        "error": {"type": "string", "nullable": true}
+                                    }
                                 }
                             }
                         }
                     },
-                    "responses": {
-                        "200": {
-                            "description": "Configuration saved",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": {"type": "boolean"},
-                                            "message": {"type": "string"},
-                                            "restarting": {"type": "boolean"}
-                                        }
+                    "400": {
+                        "description": "Invalid request payload",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "address": {"type": "string"},
+                                        "port": {"type": "integer", "format": "int32"},
+                                        "success": {"type": "boolean"},
+                                        "latency_ms": {"type": "integer", "format": "int64", "nullable": true},
+                                        "message": {"type": "string"},
+                                        "error": {"type": "string", "nullable": true}
                                     }
                                 }
                             }
-                        },
-                        "400": {
-                            "description": "Invalid configuration or editing disabled"
-                        },
-                        "500": {
-                            "description": "Failed to persist configuration"
                         }
                     }
                 }
-            },
-            "/api/admin/runtime-config": {
-                "get": {
-                    "summary": "Get structured runtime configuration",
-                    "description": "Return the most important RustSocks settings grouped by module",
-                    "tags": ["Admin"],
-                    "operationId": "getRuntimeConfig",
-                    "responses": {
-                        "200": {
-                            "description": "Runtime configuration",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "path": {"type": "string", "nullable": true},
-                                            "editable": {"type": "boolean"},
-                                            "server": {"type": "object"},
-                                            "pool": {"type": "object"},
-                                            "sessions": {"type": "object"},
-                                            "metrics": {"type": "object"},
-                                            "telemetry": {"type": "object"}
+            }
+        }),
+    );
+    paths.insert(
+        "/api/diagnostics/probes".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Connectivity probes",
+                "description": "Status and recent results of the [[diagnostics.probes]] destinations, which are connected to as diagnostics.probe_user through the same ACL, resolve and connect path as a CONNECT. history is newest first and holds up to diagnostics.probe_history results; up is null until the first check finishes",
+                "tags": ["Diagnostics"],
+                "operationId": "getProbes",
+                "responses": {
+                    "200": {
+                        "description": "Probe status",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "user": {"type": "string"},
+                                        "probes": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "name": {"type": "string"},
+                                                    "host": {"type": "string"},
+                                                    "port": {"type": "integer", "format": "int32"},
+                                                    "interval_secs": {"type": "integer", "format": "int64"},
+                                                    "up": {"type": "boolean", "nullable": true},
+                                                    "consecutive_failures": {"type": "integer", "format": "int64"},
+                                                    "checks_total": {"type": "integer", "format": "int64"},
+                                                    "failures_total": {"type": "integer", "format": "int64"},
+                                                    "history": {
+                                                        "type": "array",
+                                                        "items": {
+                                                            "type": "object",
+                                                            "properties": {
+                                                                "timestamp": {"type": "string", "format": "date-time"},
+                                                                "success": {"type": "boolean"},
+                                                                "latency_ms": {"type": "integer", "format": "int64"},
+                                                                "category": {"type": "string", "nullable": true, "enum": ["dns_error", "connect_timeout", "connect_refused", "connect_error", "upstream_tls", "acl_block"]},
+                                                                "error": {"type": "string", "nullable": true},
+                                                                "acl_rule": {"type": "string", "nullable": true},
+                                                                "connected_addr": {"type": "string", "nullable": true},
+                                                                "chained": {"type": "boolean"}
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                            }
                                         }
                                     }
                                 }
                             }
                         }
+                    },
+                    "404": {
+                        "description": "No running SOCKS server to probe through"
                     }
-                },
-                "put": {
-                    "summary": "Update structured runtime configuration",
-                    "description": "Validate and persist important RustSocks settings. Optionally restarts the server.",
-                    "tags": ["Admin"],
-                    "operationId": "updateRuntimeConfig",
-                    "requestBody": {
-                        "required": true,
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/upstream".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Upstream proxy status",
+                "description": "Health of the [server.upstream] parent proxy as of its latest health checks, the active sessions chained through it, and how many of them are pending drain after it turned unhealthy (server.upstream.on_unhealthy = drain)",
+                "tags": ["Health"],
+                "operationId": "getUpstreamStatus",
+                "responses": {
+                    "200": {
+                        "description": "Parent proxy status",
                         "content": {
                             "application/json": {
                                 "schema": {
                                     "type": "object",
                                     "properties": {
-                                        "server": {"type": "object"},
-                                        "pool": {"type": "object"},
-                                        "sessions": {"type": "object"},
-                                        "metrics": {"type": "object"},
-                                        "telemetry": {"type": "object"},
-                                        "restart": {"type": "boolean"}
-                                    },
-                                    "required": ["server", "pool", "sessions", "metrics", "telemetry"]
+                                        "endpoint": {"type": "string"},
+                                        "healthy": {"type": "boolean"},
+                                        "on_unhealthy": {"type": "string", "enum": ["keep", "drain", "migrate"]},
+                                        "health_check_interval_secs": {"type": "integer", "format": "int64"},
+                                        "consecutive_failures": {"type": "integer", "format": "int32"},
+                                        "checks_total": {"type": "integer", "format": "int64"},
+                                        "failures_total": {"type": "integer", "format": "int64"},
+                                        "last_check": {"type": "string", "format": "date-time", "nullable": true},
+                                        "last_error": {"type": "string", "nullable": true},
+                                        "active_sessions": {"type": "integer", "format": "int64"},
+                                        "pending_drain": {"type": "integer", "format": "int64"}
+                                    }
                                 }
                             }
                         }
                     },
-                    "responses": {
-                        "200": {
-                            "description": "Configuration updated",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": {"type": "boolean"},
-                                            "message": {"type": "string"},
-                                            "restarting": {"type": "boolean"}
+                    "404": {
+                        "description": "No upstream proxy configured"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/sessions/active".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get active sessions",
+                "description": "List all currently active SOCKS5 sessions with their transfer rates (rate_bps_sent/rate_bps_received over 5 seconds, *_60s over 60 seconds, in bytes per second). Sessions chained through server.upstream also carry upstream (the parent's host:port), upstream_healthy, and draining while they wait to be closed off an unhealthy parent. Accept: application/msgpack returns the same data as MessagePack",
+                "tags": ["Sessions"],
+                "operationId": "getActiveSessions",
+                "parameters": [
+                    {
+                        "name": "sort",
+                        "in": "query",
+                        "schema": {"type": "string", "enum": ["rate"]},
+                        "description": "rate: busiest sessions first by their 5-second transfer rate"
+                    },
+                    {
+                        "name": "limit",
+                        "in": "query",
+                        "schema": {"type": "integer", "minimum": 0},
+                        "description": "Return at most this many sessions"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "List of active sessions",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "items": {"type": "object"}
+                                }
+                            }
+                        }
+                    },
+                    "400": {"description": "Unsupported sort"}
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/sessions/history".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get session history",
+                "description": "Get historical session data with optional filtering by user, time, or destination. Accept: application/msgpack returns the same data as MessagePack",
+                "tags": ["Sessions"],
+                "operationId": "getSessionHistory",
+                "parameters": [
+                    {
+                        "name": "user",
+                        "in": "query",
+                        "schema": {"type": "string"},
+                        "description": "Filter by username (effective identity)"
+                    },
+                    {
+                        "name": "authenticated_user",
+                        "in": "query",
+                        "schema": {"type": "string"},
+                        "description": "Filter by the principal that authenticated (differs from user under auth.impersonation)"
+                    },
+                    {
+                        "name": "correlation_id",
+                        "in": "query",
+                        "schema": {"type": "string"},
+                        "description": "Filter by the client-supplied correlation ID (auth.allow_correlation_suffix)"
+                    },
+                    {
+                        "name": "hours",
+                        "in": "query",
+                        "schema": {"type": "integer"},
+                        "description": "Filter by time range in hours"
+                    },
+                    {
+                        "name": "dest_ip",
+                        "in": "query",
+                        "schema": {"type": "string"},
+                        "description": "Filter by destination IP"
+                    },
+                    {
+                        "name": "dest_host",
+                        "in": "query",
+                        "schema": {"type": "string"},
+                        "example": "*.example.com",
+                        "description": "Filter by the destination the client requested (domain or IP literal, case-insensitive): an exact host, or *.example.com for every name below example.com. An invalid pattern returns an empty page"
+                    },
+                    {
+                        "name": "requested_host",
+                        "in": "query",
+                        "schema": {"type": "string"},
+                        "description": "Filter by the hostname the client asked for"
+                    },
+                    {
+                        "name": "requested_host_source",
+                        "in": "query",
+                        "schema": {"type": "string", "enum": ["socks_request", "sni", "resolve_extension", "reverse_map"]},
+                        "description": "Filter by where requested_host came from"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Session history data",
+                        "content": {
+                            "application/json": {
+                                "schema": {"type": "object"}
+                            }
+                        }
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/sessions/stats".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get session statistics",
+                "description": "Get aggregated session statistics for monitoring and analytics",
+                "tags": ["Sessions"],
+                "operationId": "getSessionStats",
+                "parameters": [
+                    {
+                        "name": "group_by",
+                        "in": "query",
+                        "schema": {"type": "string", "enum": ["destination", "sni", "requested_host", "group"]},
+                        "description": "Destination grouping: destination (dialled address, default), sni (SNI name when observed) or requested_host (hostname the client asked for, from any source). group keeps the dialled address and adds the per-group breakdown"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Aggregated session statistics",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "active_sessions": {"type": "integer"},
+                                        "total_sessions": {"type": "integer"},
+                                        "total_bytes": {"type": "integer"},
+                                        "top_users": {"type": "array"},
+                                        "top_destinations": {"type": "array"},
+                                        "groups": {
+                                            "type": "array",
+                                            "description": "With group_by=group: per group of the users, busiest first. A session counts towards each of its user's groups; sessions without groups are under \"ungrouped\"",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "group": {"type": "string"},
+                                                    "session_count": {"type": "integer"},
+                                                    "bytes_sent": {"type": "integer"},
+                                                    "bytes_received": {"type": "integer"},
+                                                    "unique_users": {"type": "integer"},
+                                                    "acl": {
+                                                        "type": "object",
+                                                        "properties": {
+                                                            "allowed": {"type": "integer"},
+                                                            "blocked": {"type": "integer"}
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        },
+                                        "would_block": {
+                                            "type": "object",
+                                            "description": "Sessions relayed only because acl.mode is monitor: what enforcing the ACL would refuse",
+                                            "properties": {
+                                                "session_count": {"type": "integer"},
+                                                "active_sessions": {"type": "integer"},
+                                                "bytes_sent": {"type": "integer"},
+                                                "bytes_received": {"type": "integer"}
+                                            }
+                                        },
+                                        "admission_rejections": {"$ref": "#/components/schemas/AdmissionRejectionStats"},
+                                        "truncated": {"type": "boolean", "description": "Finished sessions were evicted from memory (sessions.memory_max_sessions); the counts cover only the retained ones"},
+                                        "evicted_sessions": {"type": "integer"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Unsupported group_by value"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/stats/destinations".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get per-destination traffic over time",
+                "description": "Connections and bytes per destination (dest_ip:dest_port), bucketed by session start time and aggregated from the session store. Only the top destinations by bytes over the whole window are included.",
+                "tags": ["Sessions"],
+                "operationId": "getDestinationStats",
+                "parameters": [
+                    {
+                        "name": "window_hours",
+                        "in": "query",
+                        "schema": {"type": "integer", "default": 24, "minimum": 1, "maximum": 8784},
+                        "description": "How far back to look"
+                    },
+                    {
+                        "name": "bucket_minutes",
+                        "in": "query",
+                        "schema": {"type": "integer", "default": 60, "minimum": 1},
+                        "description": "Bucket width; the window may span at most 1000 buckets"
+                    },
+                    {
+                        "name": "top",
+                        "in": "query",
+                        "schema": {"type": "integer", "default": 10, "minimum": 1, "maximum": 100},
+                        "description": "Destinations to include, by bytes over the window"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Destination time series",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "window_hours": {"type": "integer"},
+                                        "bucket_minutes": {"type": "integer"},
+                                        "buckets": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "bucket_start": {"type": "string", "format": "date-time"},
+                                                    "dest": {"type": "string", "example": "example.com:443"},
+                                                    "connections": {"type": "integer"},
+                                                    "bytes_sent": {"type": "integer"},
+                                                    "bytes_received": {"type": "integer"}
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Invalid parameters, or no persistent session store"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/stats/failures".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get failed requests by category and destination",
+                "description": "Requests that ended without a tunnel over the window, grouped by failure category (dns_error, connect_timeout, connect_refused, connect_error, upstream_tls, acl_block, auth_fail) and by destination. Failed logins are counted although they never become sessions. Served from an in-memory log of the latest 10000 failures.",
+                "tags": ["Sessions"],
+                "operationId": "getFailureStats",
+                "parameters": [
+                    {
+                        "name": "window_minutes",
+                        "in": "query",
+                        "schema": {"type": "integer", "default": 60, "minimum": 1, "maximum": 1440},
+                        "description": "How far back to look"
+                    },
+                    {
+                        "name": "top",
+                        "in": "query",
+                        "schema": {"type": "integer", "default": 10, "minimum": 1, "maximum": 100},
+                        "description": "Failing destinations to include, most failures first"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Failure counts",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/FailureStatsResponse"}
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Invalid parameters"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/sessions/writer-status".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get session batch writer status",
+                "description": "Sessions waiting to be written to the persistent store, the queue limit (sessions.batch_queue_max) and what the overflow policy (sessions.batch_overflow_policy) did since startup. A growing dropped_sessions means sessions are missing from the store.",
+                "tags": ["Sessions"],
+                "operationId": "getSessionWriterStatus",
+                "responses": {
+                    "200": {
+                        "description": "Batch writer state",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "queue_depth": {"type": "integer"},
+                                        "queue_max": {"type": "integer"},
+                                        "overflow_policy": {"type": "string", "enum": ["block", "drop_oldest", "drop_newest"]},
+                                        "dropped_sessions": {"type": "integer"},
+                                        "blocked_enqueues": {"type": "integer"},
+                                        "effective_batch_size": {"type": "integer"},
+                                        "effective_interval_ms": {"type": "integer"},
+                                        "flush_count": {"type": "integer"},
+                                        "p95_flush_ms": {"type": "number", "nullable": true}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "404": {
+                        "description": "No persistent session store"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/sessions/{id}".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get session detail",
+                "description": "Get detailed information about a specific session",
+                "tags": ["Sessions"],
+                "operationId": "getSessionDetail",
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Session ID (UUIDv7; v4 IDs of older sessions are still accepted, in hyphenated, simple, braced or URN form)"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Session details",
+                        "content": {
+                            "application/json": {
+                                "schema": {"type": "object"}
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Invalid session ID"
+                    },
+                    "404": {
+                        "description": "Session not found"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/sessions/{id}/terminate".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Terminate session",
+                "description": "Cancel an active session's relay, closing the client and upstream sockets. The close reason records the API key or dashboard user (when API keys are enforced) and the address that asked.",
+                "tags": ["Sessions"],
+                "operationId": "terminateSession",
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Session ID"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Session terminated (`status: terminated`, with the recorded `close_reason`)"
+                    },
+                    "400": {
+                        "description": "Invalid session ID"
+                    },
+                    "404": {
+                        "description": "No such session (`status: not_found`)"
+                    },
+                    "409": {
+                        "description": "Session had already ended (`status: already_closed`)"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/users/{user}/sessions".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get user sessions",
+                "description": "Get all sessions for a specific user",
+                "tags": ["Sessions"],
+                "operationId": "getUserSessions",
+                "parameters": [
+                    {
+                        "name": "user",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Username"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "User's sessions",
+                        "content": {
+                            "application/json": {
+                                "schema": {"type": "array"}
+                            }
+                        }
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/users/{user}/terminate-sessions".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Terminate all of a user's sessions",
+                "description": "Close every active session whose user or authenticating principal is the given user. With ban_minutes, the user's logins are also refused for that long; bans are kept in memory, survive ACL and config reloads, and end with the process",
+                "tags": ["Sessions"],
+                "operationId": "terminateUserSessions",
+                "parameters": [
+                    {"name": "user", "in": "path", "required": true, "schema": {"type": "string"}}
+                ],
+                "requestBody": {
+                    "required": false,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "ban_minutes": {"type": "integer", "minimum": 1, "maximum": 525600}
+                                }
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Sessions closed",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "user": {"type": "string"},
+                                        "terminated": {"type": "array", "items": {"type": "string"}},
+                                        "close_reason": {"type": "string"},
+                                        "ban": {"$ref": "#/components/schemas/UserBan"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {"description": "ban_minutes is 0 or longer than a year"},
+                    "503": {"description": "Bans are not available in this setup"}
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/users/banned".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "List banned users",
+                "description": "Active bans set through terminate-sessions, soonest to expire first",
+                "tags": ["Sessions"],
+                "operationId": "getBannedUsers",
+                "responses": {
+                    "200": {
+                        "description": "Active bans",
+                        "content": {
+                            "application/json": {
+                                "schema": {"type": "array", "items": {"$ref": "#/components/schemas/UserBan"}}
+                            }
+                        }
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/users/banned/{user}".to_string(),
+        serde_json::json!({
+            "delete": {
+                "summary": "Lift a user ban",
+                "tags": ["Sessions"],
+                "operationId": "deleteUserBan",
+                "parameters": [
+                    {"name": "user", "in": "path", "required": true, "schema": {"type": "string"}}
+                ],
+                "responses": {
+                    "204": {"description": "Ban lifted"},
+                    "404": {"description": "The user is not banned"}
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/rules".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get ACL rules",
+                "description": "Get current Access Control List rules configuration",
+                "tags": ["ACL"],
+                "operationId": "getAclRules",
+                "responses": {
+                    "200": {
+                        "description": "ACL rules summary",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "user_count": {"type": "integer"},
+                                        "group_count": {"type": "integer"},
+                                        "message": {"type": "string"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/rules/unused".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "List unused ACL rules",
+                "description": "Rules without a match in the last `days` days. Hit counters persist across restarts; a rule edited or added within the window reports `no_data` instead of `never_matched`",
+                "tags": ["ACL"],
+                "operationId": "getUnusedAclRules",
+                "parameters": [
+                    {
+                        "name": "days",
+                        "in": "query",
+                        "required": false,
+                        "schema": {"type": "integer", "default": 90, "minimum": 1}
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Unused rules",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "days": {"type": "integer"},
+                                        "since": {"type": "string", "format": "date-time"},
+                                        "rules": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "rule_id": {"type": "string"},
+                                                    "scope": {"type": "string", "example": "group:developers"},
+                                                    "description": {"type": "string"},
+                                                    "action": {"type": "string", "enum": ["allow", "block"]},
+                                                    "status": {"type": "string", "enum": ["never_matched", "stale", "no_data"]},
+                                                    "hits": {"type": "integer"},
+                                                    "last_matched_at": {"type": "string", "format": "date-time", "nullable": true},
+                                                    "tracked_since": {"type": "string", "format": "date-time"}
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled or days is 0"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/rules/stats".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "ACL rule hit counters",
+                "description": "Every configured rule with its hits, last match and hits per user, most hits first. Rules that never matched are listed last, so cleanup candidates are easy to spot. Counters survive reloads and restarts; the per-user breakdown starts over with the process. Only POST /api/acl/rules/stats/reset sets them back to zero",
+                "tags": ["ACL"],
+                "operationId": "getAclRuleStats",
+                "responses": {
+                    "200": {
+                        "description": "Rule hit counters",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "rules": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "rule_id": {"type": "string"},
+                                                    "scope": {"type": "string", "example": "group:developers"},
+                                                    "description": {"type": "string"},
+                                                    "action": {"type": "string", "enum": ["allow", "block"]},
+                                                    "hits": {"type": "integer"},
+                                                    "last_matched_at": {"type": "string", "format": "date-time", "nullable": true},
+                                                    "tracked_since": {"type": "string", "format": "date-time"},
+                                                    "users": {
+                                                        "type": "array",
+                                                        "items": {
+                                                            "type": "object",
+                                                            "properties": {
+                                                                "user": {"type": "string"},
+                                                                "hits": {"type": "integer"}
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/rules/stats/reset".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Reset ACL rule hit counters",
+                "description": "Set the hits, last match and per-user hits of every rule back to zero and restart their tracking window. The persisted counters are replaced on the next flush",
+                "tags": ["ACL"],
+                "operationId": "resetAclRuleStats",
+                "responses": {
+                    "200": {
+                        "description": "Counters reset",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "reset": {"type": "integer", "description": "Rule counters reset"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/reload-status".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Latest ACL reload",
+                "description": "Outcome of the latest reload from the ACL file, triggered by the file watcher or by POST /api/admin/reload-acl. A rejected file leaves the previous rules in effect; the error names the offending line for parse errors",
+                "tags": ["ACL"],
+                "operationId": "getAclReloadStatus",
+                "responses": {
+                    "200": {
+                        "description": "Reload status; last_reload is null until the first reload",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "watching": {"type": "boolean", "description": "acl.watch"},
+                                        "last_reload": {
+                                            "type": "object",
+                                            "nullable": true,
+                                            "properties": {
+                                                "timestamp": {"type": "string", "format": "date-time"},
+                                                "trigger": {"type": "string", "enum": ["watcher", "api"]},
+                                                "success": {"type": "boolean"},
+                                                "error": {"type": "string", "nullable": true}
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/lint".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Lint the ACL file",
+                "description": "Static checks over the ACL file on disk: user references to undefined groups (errors under `acl.strict_references`), groups no user lists and no `acl.system_group_patterns` entry matches, duplicate rules, rules shadowed by an earlier broader rule of the same action, and empty rule lists",
+                "tags": ["ACL"],
+                "operationId": "getAclLint",
+                "responses": {
+                    "200": {
+                        "description": "Lint findings; an empty list means the file is clean",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "strict_references": {"type": "boolean"},
+                                        "errors": {"type": "integer"},
+                                        "warnings": {"type": "integer"},
+                                        "findings": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "kind": {"type": "string", "enum": ["unknown_group", "orphan_group", "duplicate_rule", "shadowed_rule", "empty_rules", "dead_domain_rule"]},
+                                                    "severity": {"type": "string", "enum": ["warning", "error"]},
+                                                    "scope": {"type": "string", "example": "group:developers"},
+                                                    "rule_index": {"type": "integer", "description": "Zero-based position of the rule in its owner's list; rule findings only"},
+                                                    "message": {"type": "string"}
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled"
+                    },
+                    "422": {
+                        "description": "The ACL file does not parse or validate"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/example".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Generate an example ACL file",
+                "description": "Annotated TOML built from the ACL types, so it always parses. `from-learning` emits one allow rule per user and destination seen in allowed sessions",
+                "tags": ["ACL"],
+                "operationId": "getAclExample",
+                "parameters": [
+                    {
+                        "name": "variant",
+                        "in": "query",
+                        "required": false,
+                        "schema": {"type": "string", "enum": ["minimal", "kitchen-sink", "from-learning"], "default": "minimal"}
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Example ACL file",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "variant": {"type": "string"},
+                                        "template_version": {"type": "integer"},
+                                        "content": {"type": "string"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Unknown variant"
+                    },
+                    "404": {
+                        "description": "from-learning requested before any allowed traffic was observed"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/import".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Replace the whole ACL configuration",
+                "description": "Takes a complete ACL config as JSON, or as TOML when the content type is application/toml. It goes through the same checks as a reload (validation, lint errors, rule compilation). With `dry_run=true` only the verdict and a diff against the live configuration are returned; otherwise the ACL file is replaced atomically and the engine reloaded. Rules are matched by their matching fields, so an edited rule counts as one removed and one added; a new description or log level counts as changed",
+                "tags": ["ACL"],
+                "operationId": "importAclConfig",
+                "parameters": [
+                    {
+                        "name": "dry_run",
+                        "in": "query",
+                        "required": false,
+                        "schema": {"type": "boolean", "default": false}
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {"schema": {"type": "object", "description": "ACL config: global, users, groups"}},
+                        "application/toml": {"schema": {"type": "string"}}
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Configuration accepted (and applied unless dry_run)",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "success": {"type": "boolean"},
+                                        "dry_run": {"type": "boolean"},
+                                        "applied": {"type": "boolean"},
+                                        "message": {"type": "string"},
+                                        "findings": {"type": "array", "items": {"type": "object"}, "description": "Lint warnings, as in GET /api/acl/lint"},
+                                        "diff": {
+                                            "type": "object",
+                                            "properties": {
+                                                "default_policy": {"type": "string", "enum": ["allow", "block"], "description": "Present when the default policy changes"},
+                                                "rules_added": {"type": "integer"},
+                                                "rules_removed": {"type": "integer"},
+                                                "rules_changed": {"type": "integer"},
+                                                "scopes": {
+                                                    "type": "array",
+                                                    "items": {
+                                                        "type": "object",
+                                                        "properties": {
+                                                            "scope": {"type": "string", "example": "group:developers"},
+                                                            "change": {"type": "string", "enum": ["added", "removed", "modified"]},
+                                                            "rules_added": {"type": "integer"},
+                                                            "rules_removed": {"type": "integer"},
+                                                            "rules_changed": {"type": "integer"},
+                                                            "groups_added": {"type": "array", "items": {"type": "string"}},
+                                                            "groups_removed": {"type": "array", "items": {"type": "string"}}
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled or the body does not parse"
+                    },
+                    "422": {
+                        "description": "The configuration fails validation, lint errors or rule compilation"
+                    },
+                    "500": {
+                        "description": "Writing the ACL file or reloading failed"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/export".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Export the live ACL configuration",
+                "description": "The configuration the engine currently evaluates, as TOML; it can be sent back unchanged to POST /api/acl/import",
+                "tags": ["ACL"],
+                "operationId": "exportAclConfig",
+                "responses": {
+                    "200": {
+                        "description": "ACL file content",
+                        "content": {
+                            "application/toml": {"schema": {"type": "string"}}
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/test".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Test ACL decision",
+                "description": "Test if a connection would be allowed or blocked by ACL rules",
+                "tags": ["ACL"],
+                "operationId": "testAclDecision",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "user": {"type": "string", "example": "alice"},
+                                    "destination": {"type": "string", "example": "192.168.1.1"},
+                                    "port": {"type": "integer", "example": 443},
+                                    "protocol": {"type": "string", "enum": ["tcp", "udp", "both"], "example": "tcp"},
+                                    "source": {"type": "string", "example": "10.20.1.5", "description": "Client IP matched against rule sources; rules with sources never match when omitted"},
+                                    "explain": {"type": "boolean", "default": false, "description": "Include the trace of every rule evaluated"},
+                                    "at": {"type": "string", "format": "date-time", "example": "2026-10-19T07:30:00Z", "description": "Check rule schedules at this time instead of now"}
+                                },
+                                "required": ["user", "destination", "port", "protocol"]
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "ACL decision result",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "decision": {"type": "string", "enum": ["allow", "block"]},
+                                        "matched_rule": {"type": "string"},
+                                        "at": {"type": "string", "format": "date-time", "description": "Echoed when the request gave one"},
+                                        "reply_code": {"type": "string", "description": "SOCKS reply a blocked connection gets; absent when allowed"},
+                                        "resolve": {"type": "string", "enum": ["local", "remote"], "description": "Where an allowed domain is resolved; absent when blocked"},
+                                        "explanation": {
+                                            "type": "object",
+                                            "description": "Only with explain; rules in evaluation order, capped for users with many rules",
+                                            "properties": {
+                                                "rules_total": {"type": "integer"},
+                                                "stopped_at": {"type": "integer", "nullable": true},
+                                                "truncated": {"type": "boolean"},
+                                                "omitted_rules": {"type": "integer"},
+                                                "rules": {
+                                                    "type": "array",
+                                                    "items": {
+                                                        "type": "object",
+                                                        "properties": {
+                                                            "position": {"type": "integer"},
+                                                            "rule_id": {"type": "string"},
+                                                            "scope": {"type": "string", "example": "group:developers"},
+                                                            "description": {"type": "string"},
+                                                            "action": {"type": "string", "enum": ["allow", "block"]},
+                                                            "priority": {"type": "integer"},
+                                                            "protocol_matched": {"type": "boolean"},
+                                                            "destination_matched": {"type": "boolean"},
+                                                            "port_matched": {"type": "boolean"},
+                                                            "source_matched": {"type": "boolean"},
+                                                            "schedule_matched": {"type": "boolean", "description": "Always true for rules without a schedule"},
+                                                            "matched": {"type": "boolean"}
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Invalid parameters or ACL not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admission/test".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Dry-run connection admission",
+                "description": "Evaluate the admission gates RustSocks implements (connection limits, special-use names, ACL) for a hypothetical connection without counting, reserving or recording anything. Groups are taken from the request rather than resolved through authentication. Maintenance mode, command allowlists, rate limiting, quotas and fan-out limits do not exist in RustSocks and are listed under not_evaluated.",
+                "tags": ["ACL"],
+                "operationId": "testAdmission",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "user": {"type": "string", "example": "alice"},
+                                    "groups": {"type": "array", "items": {"type": "string"}, "example": ["developers"], "description": "Groups the ACL gate evaluates; caller-supplied, not looked up from PAM/LDAP"},
+                                    "destination": {"type": "string", "example": "example.com"},
+                                    "port": {"type": "integer", "example": 443},
+                                    "protocol": {"type": "string", "enum": ["tcp", "udp"], "description": "Defaults to udp for udp_associate, tcp otherwise"},
+                                    "command": {"type": "string", "enum": ["connect", "bind", "udp_associate"], "default": "connect"},
+                                    "source": {"type": "string", "example": "10.20.1.5", "description": "Client IP matched against ACL rule sources"}
+                                },
+                                "required": ["user", "destination", "port"]
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Admission verdict",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "admitted": {"type": "boolean"},
+                                        "groups": {"type": "array", "items": {"type": "string"}, "description": "Caller-supplied groups used by the ACL gate"},
+                                        "failed_gate": {"type": "string", "example": "acl"},
+                                        "reply_code": {"type": "integer", "example": 2},
+                                        "reply": {"type": "string", "example": "connection_not_allowed"},
+                                        "gates": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "gate": {"type": "string", "enum": ["connection_limits", "special_names", "acl"]},
+                                                    "status": {"type": "string", "enum": ["pass", "fail", "not_configured"]},
+                                                    "reason": {"type": "string"},
+                                                    "details": {"type": "object"}
+                                                }
+                                            }
+                                        },
+                                        "not_evaluated": {
+                                            "type": "array",
+                                            "items": {"type": "string"},
+                                            "description": "Gates RustSocks does not implement",
+                                            "example": ["maintenance_mode", "allowed_commands", "rate_limiter", "quota", "fan_out"]
                                         }
                                     }
                                 }
                             }
-                        },
-                        "400": {"description": "Invalid configuration"},
-                        "500": {"description": "Failed to persist configuration"}
+                        }
+                    },
+                    "400": {
+                        "description": "Invalid command or protocol"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admission/rejections".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Recent connection-limit rejections",
+                "description": "Clients refused by qos.connection_limits, newest first, from a bounded in-memory buffer of the last 1000 rejections. The same records are written to the access log (target rustsocks::access, stage=admission) and, when telemetry is enabled, as admission telemetry events.",
+                "tags": ["Sessions"],
+                "operationId": "getAdmissionRejections",
+                "parameters": [
+                    {"name": "user", "in": "query", "schema": {"type": "string"}, "description": "Only rejections for this user"},
+                    {"name": "limit", "in": "query", "schema": {"type": "integer", "default": 100, "maximum": 1000}}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Rejections",
+                        "content": {
+                            "application/json": {
+                                "schema": {"type": "array", "items": {"$ref": "#/components/schemas/AdmissionRejection"}}
+                            }
+                        }
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admission/rejections/stream".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Live feed of connection-limit rejections",
+                "description": "Server-sent events: one admission_rejection event (AdmissionRejection JSON) per rejection, or a lagged event with the number of missed rejections when the client falls behind",
+                "tags": ["Sessions"],
+                "operationId": "streamAdmissionRejections",
+                "parameters": [
+                    {"name": "user", "in": "query", "schema": {"type": "string"}, "description": "Only rejections for this user"}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Event stream",
+                        "content": {"text/event-stream": {"schema": {"type": "string"}}}
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/ws".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Live session events (WebSocket)",
+                "description": "WebSocket upgrade. The client first sends a subscription, {\"user\": ..., \"destination\": ...} with either field optional ({} for everything; destination accepts *.example.com), and gets a subscribed message back. Then every matching session event arrives as a JSON text message whose type is session_started, session_closed, traffic_update (at most once per session per sessions.live_traffic_interval_secs), acl_blocked, qos_throttled or admission_rejected (a client refused by a connection limit; session_id is nil). A client that falls behind gets {\"type\": \"lagged\", \"missed\": n}; an invalid subscription gets an error message and the socket is closed.",
+                "tags": ["Sessions"],
+                "operationId": "sessionEventsWebSocket",
+                "responses": {
+                    "101": {"description": "Switching to the WebSocket protocol"},
+                    "400": {"description": "Not a WebSocket upgrade request"}
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admin/overload".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get overload shedding state",
+                "description": "Current shed mode, last sampled signal, reject ratio and rejection counters",
+                "tags": ["Admin"],
+                "operationId": "getOverloadStatus",
+                "responses": {
+                    "200": {
+                        "description": "Shedding state",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/OverloadStatus"}
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "server.overload is not enabled"
                     }
                 }
             },
-            "/api/acl/groups": {
-                "get": {
-                    "summary": "List all ACL groups",
-                    "description": "Get a list of all configured ACL groups with rule counts",
-                    "tags": ["ACL-Groups"],
-                    "operationId": "listGroups",
-                    "responses": {
-                        "200": {
-                            "description": "List of ACL groups",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "groups": {
-                                                "type": "array",
-                                                "items": {
-                                                    "type": "object",
-                                                    "properties": {
-                                                        "name": {"type": "string", "example": "developers"},
-                                                        "rule_count": {"type": "integer", "example": 5}
-                                                    }
+            "put": {
+                "summary": "Override overload shedding",
+                "description": "force_on sheds at max_reject_ratio, force_off never sheds, auto follows the sampled signal",
+                "tags": ["Admin"],
+                "operationId": "setOverloadMode",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "required": ["mode"],
+                                "properties": {
+                                    "mode": {"type": "string", "enum": ["auto", "force_on", "force_off"]}
+                                }
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Shedding state after the change",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/OverloadStatus"}
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "server.overload is not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/auth/address-cache/invalidate".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Invalidate pam.address cache",
+                "description": "Drop cached pam.address verdicts for one client IP, or for every client when no IP is given",
+                "tags": ["Admin"],
+                "operationId": "invalidateAddressCache",
+                "requestBody": {
+                    "required": false,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "ip": {"type": "string", "example": "10.0.0.5"}
+                                }
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Entries removed and current gate counters",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "removed": {"type": "integer"},
+                                        "stats": {
+                                            "type": "object",
+                                            "properties": {
+                                                "cache_hits": {"type": "integer"},
+                                                "pam_invocations": {"type": "integer"},
+                                                "average_pam_latency_ms": {"type": "number"},
+                                                "allow_list_hits": {"type": "integer"},
+                                                "deny_list_hits": {"type": "integer"},
+                                                "cached_entries": {"type": "integer"}
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "pam.address is not enabled or the IP is invalid"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admin/reload-acl".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Reload ACL configuration",
+                "description": "Reload ACL rules from configuration file without restarting server",
+                "tags": ["Admin"],
+                "operationId": "reloadAcl",
+                "responses": {
+                    "200": {
+                        "description": "ACL reloaded successfully",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "success": {"type": "boolean"},
+                                        "message": {"type": "string"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "ACL is not enabled"
+                    },
+                    "500": {
+                        "description": "Failed to reload ACL configuration"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admin/flush-dns".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Flush the DNS cache",
+                "description": "Drop every cached destination lookup (server.dns), including cached NXDOMAIN answers; the next CONNECT to each name resolves again.",
+                "tags": ["Admin"],
+                "operationId": "flushDnsCache",
+                "responses": {
+                    "200": {
+                        "description": "Cache flushed",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "flushed": {"type": "integer", "description": "Entries dropped"},
+                                        "stats": {
+                                            "type": "object",
+                                            "properties": {
+                                                "entries": {"type": "integer"},
+                                                "hits": {"type": "integer"},
+                                                "misses": {"type": "integer"}
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "DNS cache is not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admin/reload-config".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Reload the main configuration",
+                "description": "Re-read the config file (same as SIGHUP). auth.users, auth.password_hashing, the qos.htb bandwidth rates, qos.connection_limits, the sessions batch_* settings and logging.level are applied immediately; any other changed setting is listed in requires_restart and keeps its running value. An invalid file changes nothing.",
+                "tags": ["Admin"],
+                "operationId": "reloadConfig",
+                "responses": {
+                    "200": {
+                        "description": "Configuration reloaded",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "success": {"type": "boolean"},
+                                        "message": {"type": "string"},
+                                        "applied": {"type": "array", "items": {"type": "string"}, "description": "Changed settings now in effect, as dotted paths"},
+                                        "requires_restart": {"type": "array", "items": {"type": "string"}, "description": "Changed settings that need a restart"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Server was started without a config file"
+                    },
+                    "500": {
+                        "description": "The config file is invalid; nothing was changed"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admin/config-file".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get RustSocks configuration file",
+                "description": "Return the current configuration file content and metadata",
+                "tags": ["Admin"],
+                "operationId": "getConfigFile",
+                "responses": {
+                    "200": {
+                        "description": "Configuration file contents",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "path": {"type": "string", "nullable": true},
+                                        "content": {"type": "string"},
+                                        "editable": {"type": "boolean"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Editing not available"
+                    }
+                }
+            },
+            "put": {
+                "summary": "Update configuration file",
+                "description": "Validate and persist a new configuration file and optionally restart RustSocks",
+                "tags": ["Admin"],
+                "operationId": "updateConfigFile",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "content": {"type": "string"},
+                                    "restart": {"type": "boolean", "default": true}
+                                },
+                                "required": ["content"]
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Configuration saved",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "success": {"type": "boolean"},
+                                        "message": {"type": "string"},
+                                        "restarting": {"type": "boolean"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Invalid configuration or editing disabled"
+                    },
+                    "500": {
+                        "description": "Failed to persist configuration"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/admin/runtime-config".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get structured runtime configuration",
+                "description": "Return the most important RustSocks settings grouped by module",
+                "tags": ["Admin"],
+                "operationId": "getRuntimeConfig",
+                "responses": {
+                    "200": {
+                        "description": "Runtime configuration",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "path": {"type": "string", "nullable": true},
+                                        "editable": {"type": "boolean"},
+                                        "server": {"type": "object"},
+                                        "pool": {"type": "object"},
+                                        "sessions": {"type": "object"},
+                                        "metrics": {"type": "object"},
+                                        "telemetry": {"type": "object"}
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            "put": {
+                "summary": "Update structured runtime configuration",
+                "description": "Validate and persist important RustSocks settings. Optionally restarts the server.",
+                "tags": ["Admin"],
+                "operationId": "updateRuntimeConfig",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "server": {"type": "object"},
+                                    "pool": {"type": "object"},
+                                    "sessions": {"type": "object"},
+                                    "metrics": {"type": "object"},
+                                    "telemetry": {"type": "object"},
+                                    "restart": {"type": "boolean"}
+                                },
+                                "required": ["server", "pool", "sessions", "metrics", "telemetry"]
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Configuration updated",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "success": {"type": "boolean"},
+                                        "message": {"type": "string"},
+                                        "restarting": {"type": "boolean"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {"description": "Invalid configuration"},
+                    "500": {"description": "Failed to persist configuration"}
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/groups".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "List all ACL groups",
+                "description": "Get a list of all configured ACL groups with the groups they inherit from and their rule counts",
+                "tags": ["ACL-Groups"],
+                "operationId": "listGroups",
+                "responses": {
+                    "200": {
+                        "description": "List of ACL groups",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "groups": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "name": {"type": "string", "example": "developers"},
+                                                    "parents": {"type": "array", "items": {"type": "string"}, "example": ["engineering"]},
+                                                    "rule_count": {"type": "integer", "example": 5}
                                                 }
                                             }
                                         }
-                                    },
-                                    "example": {
-                                        "groups": [
-                                            {"name": "developers", "rule_count": 3},
-                                            {"name": "admins", "rule_count": 5}
-                                        ]
+                                    }
+                                },
+                                "example": {
+                                    "groups": [
+                                        {"name": "developers", "parents": ["engineering"], "rule_count": 3},
+                                        {"name": "engineering", "parents": [], "rule_count": 5}
+                                    ]
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            "post": {
+                "summary": "Create new ACL group",
+                "description": "Create a new ACL group without rules, optionally inheriting the rules of existing groups",
+                "tags": ["ACL-Groups"],
+                "operationId": "createGroup",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "name": {"type": "string", "example": "backend"},
+                                    "parents": {"type": "array", "items": {"type": "string"}, "example": ["engineering"], "description": "Groups whose rules the group inherits, transitively"}
+                                },
+                                "required": ["name"]
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Group created successfully",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "success": {"type": "boolean"},
+                                        "message": {"type": "string"}
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "400": {
+                        "description": "Group already exists, a parent does not exist or would form an inheritance cycle, or ACL not enabled"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/groups/{groupname}".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get ACL group details",
+                "description": "Get detailed information about an ACL group including all rules",
+                "tags": ["ACL-Groups"],
+                "operationId": "getGroupDetail",
+                "parameters": [
+                    {
+                        "name": "groupname",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Group name",
+                        "example": "developers"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Group details with rules",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "name": {"type": "string"},
+                                        "parents": {"type": "array", "items": {"type": "string"}},
+                                        "rules": {
+                                            "type": "array",
+                                            "items": {"$ref": "#/components/schemas/AclRule"}
+                                        }
                                     }
                                 }
                             }
                         }
+                    },
+                    "404": {
+                        "description": "Group not found"
+                    }
+                }
+            },
+            "delete": {
+                "summary": "Delete ACL group",
+                "description": "Delete an entire ACL group and all its rules. A group other groups inherit from is only deleted with cascade=true, which also removes it from their parents.",
+                "tags": ["ACL-Groups"],
+                "operationId": "deleteGroup",
+                "parameters": [
+                    {
+                        "name": "groupname",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Group name"
+                    },
+                    {
+                        "name": "cascade",
+                        "in": "query",
+                        "required": false,
+                        "schema": {"type": "boolean", "default": false},
+                        "description": "Delete the group even though other groups inherit from it"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Group deleted successfully"
+                    },
+                    "404": {
+                        "description": "Group not found"
+                    },
+                    "409": {
+                        "description": "Other groups inherit from the group and cascade is not set"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/groups/{groupname}/parents".to_string(),
+        serde_json::json!({
+            "put": {
+                "summary": "Set group parents",
+                "description": "Replace the groups whose rules the group inherits. At equal priority a group's own rules are evaluated before inherited ones; BLOCK rules still come first.",
+                "tags": ["ACL-Groups"],
+                "operationId": "setGroupParents",
+                "parameters": [
+                    {
+                        "name": "groupname",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Group name",
+                        "example": "backend"
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "parents": {"type": "array", "items": {"type": "string"}, "example": ["engineering"]}
+                                },
+                                "required": ["parents"]
+                            }
+                        }
                     }
                 },
-                "post": {
-                    "summary": "Create new ACL group",
-                    "description": "Create a new empty ACL group",
-                    "tags": ["ACL-Groups"],
-                    "operationId": "createGroup",
-                    "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": {
-                                    "type": "object",
-                                    "properties": {
-                                        "name": {"type": "string", "example": "admins"}
-                                    },
-                                    "required": ["name"]
-                                }
-                            }
-                        }
+                "responses": {
+                    "200": {
+                        "description": "Parents updated"
                     },
-                    "responses": {
-                        "200": {
-                            "description": "Group created successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": {"type": "boolean"},
-                                            "message": {"type": "string"}
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        "400": {
-                            "description": "Group already exists or ACL not enabled"
-                        }
+                    "400": {
+                        "description": "A parent does not exist or the change would form an inheritance cycle, or ACL not enabled"
+                    },
+                    "404": {
+                        "description": "Group not found"
                     }
                 }
-            },
-            "/api/acl/groups/{groupname}": {
-                "get": {
-                    "summary": "Get ACL group details",
-                    "description": "Get detailed information about an ACL group including all rules",
-                    "tags": ["ACL-Groups"],
-                    "operationId": "getGroupDetail",
-                    "parameters": [
-                        {
-                            "name": "groupname",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Group name",
-                            "example": "developers"
-                        }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "Group details with rules",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "name": {"type": "string"},
-                                            "rules": {
-                                                "type": "array",
-                                                "items": {"$ref": "#/components/schemas/AclRule"}
-                                            }
-                                        }
-                                    }
-                                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/groups/{groupname}/rules".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Add rule to group",
+                "description": "Add a new ACL rule to a group. Rules are identified by destination + port combination.",
+                "tags": ["ACL-Groups"],
+                "operationId": "addGroupRule",
+                "parameters": [
+                    {
+                        "name": "groupname",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Group name"
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/AddRuleRequest"},
+                            "example": {
+                                "action": "allow",
+                                "description": "SSH access to production",
+                                "destinations": ["*.prod.company.com"],
+                                "ports": ["22"],
+                                "protocols": ["tcp"],
+                                "priority": 200
                             }
-                        },
-                        "404": {
-                            "description": "Group not found"
                         }
                     }
                 },
-                "delete": {
-                    "summary": "Delete ACL group",
-                    "description": "Delete an entire ACL group and all its rules",
-                    "tags": ["ACL-Groups"],
-                    "operationId": "deleteGroup",
-                    "parameters": [
-                        {
-                            "name": "groupname",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Group name"
-                        }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "Group deleted successfully"
-                        },
-                        "404": {
-                            "description": "Group not found"
+                "responses": {
+                    "200": {
+                        "description": "Rule added successfully",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/RuleOperationResponse"}
+                            }
                         }
+                    },
+                    "400": {
+                        "description": "Invalid rule or duplicate rule"
                     }
                 }
             },
-            "/api/acl/groups/{groupname}/rules": {
-                "post": {
-                    "summary": "Add rule to group",
-                    "description": "Add a new ACL rule to a group. Rules are identified by destination + port combination.",
-                    "tags": ["ACL-Groups"],
-                    "operationId": "addGroupRule",
-                    "parameters": [
-                        {
-                            "name": "groupname",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Group name"
-                        }
-                    ],
-                    "requestBody": {
+            "put": {
+                "summary": "Update group rule",
+                "description": "Update an existing rule by identifying it with destination + port",
+                "tags": ["ACL-Groups"],
+                "operationId": "updateGroupRule",
+                "parameters": [
+                    {
+                        "name": "groupname",
+                        "in": "path",
                         "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": {"$ref": "#/components/schemas/AddRuleRequest"},
-                                "example": {
-                                    "action": "allow",
-                                    "description": "SSH access to production",
+                        "schema": {"type": "string"},
+                        "description": "Group name"
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "match": {"$ref": "#/components/schemas/RuleIdentifier"},
+                                    "update": {"$ref": "#/components/schemas/AddRuleRequest"}
+                                },
+                                "required": ["match", "update"]
+                            },
+                            "example": {
+                                "match": {
+                                    "destinations": ["*.prod.company.com"],
+                                    "ports": ["22"]
+                                },
+                                "update": {
+                                    "action": "block",
+                                    "description": "SSH blocked (new security policy)",
                                     "destinations": ["*.prod.company.com"],
                                     "ports": ["22"],
                                     "protocols": ["tcp"],
-                                    "priority": 200
-                                }
-                            }
-                        }
-                    },
-                    "responses": {
-                        "200": {
-                            "description": "Rule added successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"$ref": "#/components/schemas/RuleOperationResponse"}
+                                    "priority": 1000
                                 }
                             }
-                        },
-                        "400": {
-                            "description": "Invalid rule or duplicate rule"
                         }
                     }
                 },
-                "put": {
-                    "summary": "Update group rule",
-                    "description": "Update an existing rule by identifying it with destination + port",
-                    "tags": ["ACL-Groups"],
-                    "operationId": "updateGroupRule",
-                    "parameters": [
-                        {
-                            "name": "groupname",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Group name"
-                        }
-                    ],
-                    "requestBody": {
-                        "required": true,
+                "responses": {
+                    "200": {
+                        "description": "Rule updated successfully",
                         "content": {
                             "application/json": {
-                                "schema": {
-                                    "type": "object",
-                                    "properties": {
-                                        "match": {"$ref": "#/components/schemas/RuleIdentifier"},
-                                        "update": {"$ref": "#/components/schemas/AddRuleRequest"}
-                                    },
-                                    "required": ["match", "update"]
-                                },
-                                "example": {
-                                    "match": {
-                                        "destinations": ["*.prod.company.com"],
-                                        "ports": ["22"]
-                                    },
-                                    "update": {
-                                        "action": "block",
-                                        "description": "SSH blocked (new security policy)",
-                                        "destinations": ["*.prod.company.com"],
-                                        "ports": ["22"],
-                                        "protocols": ["tcp"],
-                                        "priority": 1000
-                                    }
-                                }
+                                "schema": {"$ref": "#/components/schemas/RuleOperationResponse"}
                             }
                         }
                     },
-                    "responses": {
-                        "200": {
-                            "description": "Rule updated successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"$ref": "#/components/schemas/RuleOperationResponse"}
-                                }
-                            }
-                        },
-                        "404": {
-                            "description": "Rule not found"
-                        }
+                    "404": {
+                        "description": "Rule not found"
                     }
-                },
-                "delete": {
-                    "summary": "Delete group rule",
-                    "description": "Delete a rule by identifying it with destination + port",
-                    "tags": ["ACL-Groups"],
-                    "operationId": "deleteGroupRule",
-                    "parameters": [
-                        {
-                            "name": "groupname",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Group name"
-                        }
-                    ],
-                    "requestBody": {
+                }
+            },
+            "delete": {
+                "summary": "Delete group rule",
+                "description": "Delete a rule by identifying it with destination + port",
+                "tags": ["ACL-Groups"],
+                "operationId": "deleteGroupRule",
+                "parameters": [
+                    {
+                        "name": "groupname",
+                        "in": "path",
                         "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": {"$ref": "#/components/schemas/RuleIdentifier"},
-                                "example": {
-                                    "destinations": ["*.prod.company.com"],
-                                    "ports": ["22"]
-                                }
+                        "schema": {"type": "string"},
+                        "description": "Group name"
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/RuleIdentifier"},
+                            "example": {
+                                "destinations": ["*.prod.company.com"],
+                                "ports": ["22"]
                             }
                         }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Rule deleted successfully"
                     },
-                    "responses": {
-                        "200": {
-                            "description": "Rule deleted successfully"
-                        },
-                        "404": {
-                            "description": "Rule not found"
-                        }
+                    "404": {
+                        "description": "Rule not found"
                     }
                 }
-            },
-            "/api/acl/users": {
-                "get": {
-                    "summary": "List all ACL users",
-                    "description": "Get a list of all users with ACL rules configured",
-                    "tags": ["ACL-Users"],
-                    "operationId": "listUsers",
-                    "responses": {
-                        "200": {
-                            "description": "List of ACL users",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "users": {
-                                                "type": "array",
-                                                "items": {
-                                                    "type": "object",
-                                                    "properties": {
-                                                        "username": {"type": "string"},
-                                                        "groups": {"type": "array", "items": {"type": "string"}},
-                                                        "rule_count": {"type": "integer"}
-                                                    }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/users".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "List all ACL users",
+                "description": "Get a list of all users with ACL rules configured",
+                "tags": ["ACL-Users"],
+                "operationId": "listUsers",
+                "responses": {
+                    "200": {
+                        "description": "List of ACL users",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "users": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "username": {"type": "string"},
+                                                    "groups": {"type": "array", "items": {"type": "string"}},
+                                                    "rule_count": {"type": "integer"}
                                                 }
                                             }
                                         }
@@ -955,247 +2799,263 @@ fn get_openapi_spec(base_path: String) -> serde_json::Value {
                         }
                     }
                 }
-            },
-            "/api/acl/users/{username}": {
-                "get": {
-                    "summary": "Get user ACL details",
-                    "description": "Get detailed ACL information for a specific user",
-                    "tags": ["ACL-Users"],
-                    "operationId": "getUserDetail",
-                    "parameters": [
-                        {
-                            "name": "username",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Username"
-                        }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "User ACL details"
-                        },
-                        "404": {
-                            "description": "User not found"
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/users/{username}".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get user ACL details",
+                "description": "Get detailed ACL information for a specific user",
+                "tags": ["ACL-Users"],
+                "operationId": "getUserDetail",
+                "parameters": [
+                    {
+                        "name": "username",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Username"
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "User ACL details"
+                    },
+                    "404": {
+                        "description": "User not found"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/users/{username}/rules".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Add rule to user",
+                "description": "Add a per-user ACL rule override (higher priority than group rules)",
+                "tags": ["ACL-Users"],
+                "operationId": "addUserRule",
+                "parameters": [
+                    {
+                        "name": "username",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Username"
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/AddRuleRequest"},
+                            "example": {
+                                "action": "block",
+                                "description": "Alice blocked from admin panel",
+                                "destinations": ["admin.company.com"],
+                                "ports": ["*"],
+                                "protocols": ["tcp"],
+                                "priority": 2000
+                            }
                         }
                     }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Rule added successfully"
+                    }
                 }
             },
-            "/api/acl/users/{username}/rules": {
-                "post": {
-                    "summary": "Add rule to user",
-                    "description": "Add a per-user ACL rule override (higher priority than group rules)",
-                    "tags": ["ACL-Users"],
-                    "operationId": "addUserRule",
-                    "parameters": [
-                        {
-                            "name": "username",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Username"
-                        }
-                    ],
-                    "requestBody": {
+            "put": {
+                "summary": "Update user rule",
+                "description": "Update an existing per-user rule",
+                "tags": ["ACL-Users"],
+                "operationId": "updateUserRule",
+                "parameters": [
+                    {
+                        "name": "username",
+                        "in": "path",
                         "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": {"$ref": "#/components/schemas/AddRuleRequest"},
-                                "example": {
-                                    "action": "block",
-                                    "description": "Alice blocked from admin panel",
-                                    "destinations": ["admin.company.com"],
-                                    "ports": ["*"],
-                                    "protocols": ["tcp"],
-                                    "priority": 2000
+                        "schema": {"type": "string"},
+                        "description": "Username"
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "match": {"$ref": "#/components/schemas/RuleIdentifier"},
+                                    "update": {"$ref": "#/components/schemas/AddRuleRequest"}
                                 }
                             }
                         }
+                    }
+                },
+                "responses": {
+                    "200": {
+                        "description": "Rule updated successfully"
                     },
-                    "responses": {
-                        "200": {
-                            "description": "Rule added successfully"
+                    "404": {
+                        "description": "Rule not found"
+                    }
+                }
+            },
+            "delete": {
+                "summary": "Delete user rule",
+                "description": "Delete a per-user ACL rule",
+                "tags": ["ACL-Users"],
+                "operationId": "deleteUserRule",
+                "parameters": [
+                    {
+                        "name": "username",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string"},
+                        "description": "Username"
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/RuleIdentifier"}
                         }
                     }
                 },
-                "put": {
-                    "summary": "Update user rule",
-                    "description": "Update an existing per-user rule",
-                    "tags": ["ACL-Users"],
-                    "operationId": "updateUserRule",
-                    "parameters": [
-                        {
-                            "name": "username",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Username"
-                        }
-                    ],
-                    "requestBody": {
-                        "required": true,
+                "responses": {
+                    "200": {
+                        "description": "Rule deleted successfully"
+                    },
+                    "404": {
+                        "description": "Rule not found"
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/global".to_string(),
+        serde_json::json!({
+            "get": {
+                "summary": "Get global ACL settings",
+                "description": "Get global ACL configuration (default policy, enforce or monitor mode)",
+                "tags": ["ACL-Global"],
+                "operationId": "getGlobalSettings",
+                "responses": {
+                    "200": {
+                        "description": "Global ACL settings",
                         "content": {
                             "application/json": {
                                 "schema": {
                                     "type": "object",
                                     "properties": {
-                                        "match": {"$ref": "#/components/schemas/RuleIdentifier"},
-                                        "update": {"$ref": "#/components/schemas/AddRuleRequest"}
+                                        "default_policy": {"type": "string", "enum": ["allow", "block"], "example": "block"},
+                                        "mode": {"type": "string", "enum": ["enforce", "monitor"], "example": "enforce"}
                                     }
                                 }
                             }
                         }
-                    },
-                    "responses": {
-                        "200": {
-                            "description": "Rule updated successfully"
-                        },
-                        "404": {
-                            "description": "Rule not found"
-                        }
-                    }
-                },
-                "delete": {
-                    "summary": "Delete user rule",
-                    "description": "Delete a per-user ACL rule",
-                    "tags": ["ACL-Users"],
-                    "operationId": "deleteUserRule",
-                    "parameters": [
-                        {
-                            "name": "username",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string"},
-                            "description": "Username"
-                        }
-                    ],
-                    "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": {"$ref": "#/components/schemas/RuleIdentifier"}
-                            }
-                        }
-                    },
-                    "responses": {
-                        "200": {
-                            "description": "Rule deleted successfully"
-                        },
-                        "404": {
-                            "description": "Rule not found"
-                        }
                     }
                 }
             },
-            "/api/acl/global": {
-                "get": {
-                    "summary": "Get global ACL settings",
-                    "description": "Get global ACL configuration (default policy)",
-                    "tags": ["ACL-Global"],
-                    "operationId": "getGlobalSettings",
-                    "responses": {
-                        "200": {
-                            "description": "Global ACL settings",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "default_policy": {"type": "string", "enum": ["allow", "block"], "example": "block"}
-                                        }
-                                    }
+            "put": {
+                "summary": "Update global ACL settings",
+                "description": "Update the default policy (saved to the ACL file) and/or the mode. In monitor mode blocked connections are logged and counted but relayed, with their sessions marked would_block. A mode change lasts until restart and is written to the access log.",
+                "tags": ["ACL-Global"],
+                "operationId": "updateGlobalSettings",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "default_policy": {"type": "string", "enum": ["allow", "block"]},
+                                    "mode": {"type": "string", "enum": ["enforce", "monitor"]}
                                 }
+                            },
+                            "example": {
+                                "default_policy": "allow"
                             }
                         }
                     }
                 },
-                "put": {
-                    "summary": "Update global ACL settings",
-                    "description": "Update global ACL configuration (default policy)",
-                    "tags": ["ACL-Global"],
-                    "operationId": "updateGlobalSettings",
-                    "requestBody": {
-                        "required": true,
+                "responses": {
+                    "200": {
+                        "description": "Global settings updated successfully",
                         "content": {
                             "application/json": {
                                 "schema": {
                                     "type": "object",
                                     "properties": {
-                                        "default_policy": {"type": "string", "enum": ["allow", "block"]}
-                                    },
-                                    "required": ["default_policy"]
-                                },
-                                "example": {
-                                    "default_policy": "allow"
+                                        "success": {"type": "boolean"},
+                                        "message": {"type": "string"},
+                                        "old_policy": {"type": "string"},
+                                        "new_policy": {"type": "string"},
+                                        "old_mode": {"type": "string"},
+                                        "new_mode": {"type": "string"}
+                                    }
                                 }
                             }
                         }
-                    },
-                    "responses": {
-                        "200": {
-                            "description": "Global settings updated successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": {"type": "boolean"},
-                                            "message": {"type": "string"},
-                                            "old_policy": {"type": "string"},
-                                            "new_policy": {"type": "string"}
-                                        }
-                                    }
+                    }
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/api/acl/search".to_string(),
+        serde_json::json!({
+            "post": {
+                "summary": "Search ACL rules",
+                "description": "Search for ACL rules across all groups and users using various criteria",
+                "tags": ["ACL-Global"],
+                "operationId": "searchRules",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "destination": {"type": "string", "example": "prod.company.com"},
+                                    "port": {"type": "integer", "example": 22},
+                                    "action": {"type": "string", "enum": ["allow", "block"]},
+                                    "log": {"type": "string", "enum": ["default", "silent", "minimal", "verbose"]}
                                 }
+                            },
+                            "example": {
+                                "destination": "prod.company.com"
                             }
                         }
                     }
-                }
-            },
-            "/api/acl/search": {
-                "post": {
-                    "summary": "Search ACL rules",
-                    "description": "Search for ACL rules across all groups and users using various criteria",
-                    "tags": ["ACL-Global"],
-                    "operationId": "searchRules",
-                    "requestBody": {
-                        "required": true,
+                },
+                "responses": {
+                    "200": {
+                        "description": "Search results",
                         "content": {
                             "application/json": {
                                 "schema": {
                                     "type": "object",
                                     "properties": {
-                                        "destination": {"type": "string", "example": "prod.company.com"},
-                                        "port": {"type": "integer", "example": 22},
-                                        "action": {"type": "string", "enum": ["allow", "block"]}
-                                    }
-                                },
-                                "example": {
-                                    "destination": "prod.company.com"
-                                }
-                            }
-                        }
-                    },
-                    "responses": {
-                        "200": {
-                            "description": "Search results",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "matches": {
-                                                "type": "array",
-                                                "items": {
-                                                    "type": "object",
-                                                    "properties": {
-                                                        "rule_type": {"type": "string", "enum": ["group", "user"]},
-                                                        "owner": {"type": "string"},
-                                                        "rule": {"$ref": "#/components/schemas/AclRule"}
-                                                    }
+                                        "matches": {
+                                            "type": "array",
+                                            "items": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "rule_type": {"type": "string", "enum": ["group", "user"]},
+                                                    "owner": {"type": "string"},
+                                                    "rule": {"$ref": "#/components/schemas/AclRule"}
                                                 }
-                                            },
-                                            "count": {"type": "integer"}
-                                        }
+                                            }
+                                        },
+                                        "count": {"type": "integer"}
                                     }
                                 }
                             }
@@ -1203,53 +3063,9 @@ fn get_openapi_spec(base_path: String) -> serde_json::Value {
                     }
                 }
             }
-        },
-        "components": {
-            "schemas": {
-                "AclRule": {
-                    "type": "object",
-                    "properties": {
-                        "action": {"type": "string", "enum": ["allow", "block"]},
-                        "description": {"type": "string"},
-                        "destinations": {"type": "array", "items": {"type": "string"}, "example": ["*.example.com", "10.0.0.0/8"]},
-                        "ports": {"type": "array", "items": {"type": "string"}, "example": ["22", "80", "443", "8000-9000"]},
-                        "protocols": {"type": "array", "items": {"type": "string", "enum": ["tcp", "udp", "both"]}},
-                        "priority": {"type": "integer", "example": 100}
-                    }
-                },
-                "AddRuleRequest": {
-                    "type": "object",
-                    "properties": {
-                        "action": {"type": "string", "enum": ["allow", "block"]},
-                        "description": {"type": "string"},
-                        "destinations": {"type": "array", "items": {"type": "string"}},
-                        "ports": {"type": "array", "items": {"type": "string"}},
-                        "protocols": {"type": "array", "items": {"type": "string", "enum": ["tcp", "udp", "both"]}},
-                        "priority": {"type": "integer"}
-                    },
-                    "required": ["action", "description", "destinations", "ports", "protocols", "priority"]
-                },
-                "RuleIdentifier": {
-                    "type": "object",
-                    "description": "Identifies a rule by destination + optional port (NOT by index!)",
-                    "properties": {
-                        "destinations": {"type": "array", "items": {"type": "string"}},
-                        "ports": {"type": "array", "items": {"type": "string"}}
-                    },
-                    "required": ["destinations"]
-                },
-                "RuleOperationResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": {"type": "boolean"},
-                        "message": {"type": "string"},
-                        "rule": {"$ref": "#/components/schemas/AclRule"},
-                        "old_rule": {"$ref": "#/components/schemas/AclRule"}
-                    }
-                }
-            }
-        }
-    })
+        }),
+    );
+    paths
 }
 
 /// Start the REST API server with Swagger documentation
@@ -1260,11 +3076,21 @@ pub async fn start_api_server(
     acl_engine: Option<Arc<crate::acl::AclEngine>>,
     acl_config_path: Option<String>,
     connection_pool: Arc<ConnectionPool>,
+    qos_engine: Arc<crate::qos::QosEngine>,
     metrics_history: Option<Arc<crate::session::MetricsHistory>>,
     telemetry_history: Option<Arc<TelemetryHistory>>,
     server_config: Arc<Config>,
     config_path: Option<PathBuf>,
     original_args: Arc<Vec<std::ffi::OsString>>,
+    address_gate: Option<Arc<crate::auth::AddressGate>>,
+    overload: Option<Arc<crate::server::LoadShedder>>,
+    resource_guard: Option<Arc<crate::server::ResourceGuard>>,
+    config_reloader: Option<Arc<crate::server::ConfigReloader>>,
+    dns_cache: Option<Arc<crate::server::DnsCache>>,
+    bound_addresses: Option<Arc<crate::server::BoundAddresses>>,
+    user_bans: Option<Arc<crate::auth::UserBans>>,
+    probe_monitor: Option<Arc<crate::server::ProbeMonitor>>,
+    upstream_proxy: Option<Arc<crate::server::UpstreamProxy>>,
 ) -> Result<JoinHandle<()>> {
     if !config.enable_api {
         info!("API server disabled");
@@ -1306,9 +3132,19 @@ pub async fn start_api_server(
         metrics_history,
         telemetry_history,
         connection_pool,
+        qos_engine,
         config_path,
         config_snapshot: server_config,
         original_args,
+        address_gate,
+        overload,
+        resource_guard,
+        config_reloader,
+        dns_cache,
+        bound_addresses,
+        user_bans,
+        probe_monitor,
+        upstream_proxy,
     };
 
     // Build router with all endpoints
@@ -1353,33 +3189,103 @@ pub async fn start_api_server(
         .route("/api/auth/altcha-challenge", get(altcha_challenge_handler))
         .with_state(auth_state.clone());
 
+    // Polled endpoints answer an unchanged body with 304
+    let etag = middleware::from_fn(conditional_get);
+
     // Add API routes
     app = app
         .merge(auth_router)
         // Health and metrics
         .route("/health", get(health_check))
+        .route("/health/live", get(health_check))
+        .route("/health/ready", get(readiness_check))
+        // Unauthenticated by design; 404 unless sessions.public_status_enabled
+        .route("/status", get(get_public_status_page))
+        .route("/status.json", get(get_public_status))
         .route("/metrics", get(get_metrics))
         .route("/api/pool/stats", get(get_pool_stats))
+        .route("/api/qos/allocations", get(get_qos_allocations))
+        .route("/api/qos/config", get(get_qos_config))
+        .route(
+            "/api/qos/users/{user}/limit",
+            put(set_qos_user_limit).delete(delete_qos_user_limit),
+        )
         .route("/api/system/resources", get(get_system_resources))
+        .route("/api/version", get(get_version))
         // Session endpoints
-        .route("/api/sessions/active", get(get_active_sessions))
-        .route("/api/sessions/history", get(get_session_history))
-        .route("/api/sessions/stats", get(get_session_stats))
+        .route(
+            "/api/sessions/active",
+            get(get_active_sessions).layer(etag.clone()),
+        )
+        .route(
+            "/api/sessions/history",
+            get(get_session_history).layer(etag.clone()),
+        )
+        .route(
+            "/api/sessions/stats",
+            get(get_session_stats).layer(etag.clone()),
+        )
+        .route(
+            "/api/stats/destinations",
+            get(get_destination_stats).layer(etag.clone()),
+        )
+        .route("/api/stats/failures", get(get_failure_stats))
+        .route(
+            "/api/sessions/writer-status",
+            get(get_session_writer_status),
+        )
+        .route("/api/ws", get(session_events_ws))
         .route("/api/sessions/{id}", get(get_session_detail))
         .route("/api/sessions/{id}/terminate", post(terminate_session))
         .route("/api/users/{user}/sessions", get(get_user_sessions))
+        .route(
+            "/api/users/{user}/terminate-sessions",
+            post(terminate_user_sessions),
+        )
+        .route("/api/users/banned", get(get_banned_users))
+        .route(
+            "/api/users/banned/{user}",
+            axum::routing::delete(delete_user_ban),
+        )
         .route("/api/telemetry/events", get(get_telemetry_events))
-        .route("/api/metrics/history", get(get_metrics_history))
+        .route(
+            "/api/metrics/history",
+            get(get_metrics_history).layer(etag.clone()),
+        )
         // Diagnostics endpoints
         .route("/api/diagnostics/connectivity", post(test_tcp_connectivity))
+        .route("/api/diagnostics/probes", get(get_probes))
+        .route("/api/upstream", get(get_upstream_status))
         // Management endpoints
         .route("/api/admin/reload-acl", post(reload_acl))
+        .route("/api/admin/reload-config", post(reload_config))
+        .route("/api/admin/flush-dns", post(flush_dns_cache))
+        .route(
+            "/api/auth/address-cache/invalidate",
+            post(invalidate_address_cache),
+        )
+        .route("/api/admin/overload", get(get_overload_status))
+        .route("/api/admin/overload", put(set_overload_mode))
         .route("/api/admin/runtime-config", get(get_runtime_config))
         .route("/api/admin/runtime-config", put(update_runtime_config))
         .route("/api/admin/config-file", get(get_config_file))
         .route("/api/admin/config-file", put(update_config_file))
         .route("/api/acl/rules", get(get_acl_rules))
+        .route("/api/acl/rules/unused", get(get_unused_acl_rules))
+        .route("/api/acl/rules/stats", get(get_acl_rule_stats))
+        .route("/api/acl/rules/stats/reset", post(reset_acl_rule_stats))
+        .route("/api/acl/lint", get(get_acl_lint))
+        .route("/api/acl/reload-status", get(get_acl_reload_status))
+        .route("/api/acl/example", get(get_acl_example))
+        .route("/api/acl/import", post(import_acl_config))
+        .route("/api/acl/export", get(export_acl_config))
         .route("/api/acl/test", post(test_acl_decision))
+        .route("/api/admission/test", post(test_admission))
+        .route("/api/admission/rejections", get(get_admission_rejections))
+        .route(
+            "/api/admission/rejections/stream",
+            get(stream_admission_rejections),
+        )
         // ACL Management endpoints - Groups
         .route("/api/acl/groups", get(list_groups))
         .route("/api/acl/groups", post(create_group))
@@ -1388,6 +3294,10 @@ pub async fn start_api_server(
             "/api/acl/groups/{groupname}",
             axum::routing::delete(delete_group),
         )
+        .route(
+            "/api/acl/groups/{groupname}/parents",
+            axum::routing::put(set_group_parents),
+        )
         .route("/api/acl/groups/{groupname}/rules", post(add_group_rule))
         .route(
             "/api/acl/groups/{groupname}/rules",
@@ -1495,6 +3405,20 @@ pub async fn start_api_server(
         }
     }
 
+    if config.api_auth.enabled {
+        info!(
+            key_count = config.api_auth.keys.len(),
+            "API key authentication enabled"
+        );
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::new(ApiAuthState {
+                settings: config.api_auth.clone(),
+                dashboard: auth_state.clone(),
+            }),
+            api_key_auth,
+        ));
+    }
+
     let app = if base_path == "/" {
         app
     } else {
@@ -1509,11 +3433,43 @@ pub async fn start_api_server(
             .nest(&base_path, app)
     };
 
+    let app = match compression_layer(&config.api_compression) {
+        Some(compression) => app.layer(compression).layer(middleware::from_fn_with_state(
+            CompressionExclusions::new(&config.api_compression, base_prefix),
+            skip_compression,
+        )),
+        None => app,
+    };
+
     // Layer with state and body limit
     let app = app
         .layer(DefaultBodyLimit::max(1024 * 1024)) // 1MB max body
         .with_state(state);
 
+    // The same router serves either listener
+    #[cfg(unix)]
+    if let Some(path) = config.unix_socket.as_deref() {
+        let listener = bind_unix_socket(path, config.unix_socket_mode).await?;
+        info!(
+            "API server listening on unix:{} (mode {:o}), base path '{}'",
+            path.display(),
+            config.unix_socket_mode,
+            if base_prefix.is_empty() {
+                "/"
+            } else {
+                base_prefix
+            }
+        );
+
+        let handle = tokio::spawn(async move {
+            // No peer address to record on a Unix socket
+            if let Err(err) = axum::serve(listener, app.into_make_service()).await {
+                error!("API server error: {}", err);
+            }
+        });
+        return Ok(handle);
+    }
+
     // Bind and listen
     let addr: SocketAddr = format!("{}:{}", config.bind_address, config.bind_port)
         .parse()
@@ -1539,7 +3495,11 @@ pub async fn start_api_server(
     }
 
     let handle = tokio::spawn(async move {
-        let server = axum::serve(listener, app);
+        // Peer addresses end up in the close reason of sessions terminated via the API
+        let server = axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        );
         if let Err(err) = server.await {
             error!("API server error: {}", err);
         }
@@ -1548,6 +3508,38 @@ pub async fn start_api_server(
     Ok(handle)
 }
 
+/// Bind the API's Unix domain socket with `mode` permissions. A socket file left by a
+/// previous run is removed when nothing answers on it any more; a live one is an error.
+#[cfg(unix)]
+async fn bind_unix_socket(path: &Path, mode: u32) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if tokio::net::UnixStream::connect(path).await.is_ok() {
+                return Err(RustSocksError::Config(format!(
+                    "API socket {} is in use by another process",
+                    path.display()
+                )));
+            }
+            warn!("Removing stale API socket {}", path.display());
+            std::fs::remove_file(path)?;
+        }
+        Ok(_) => {
+            return Err(RustSocksError::Config(format!(
+                "API socket path {} exists and is not a socket",
+                path.display()
+            )));
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
 /// Middleware that gates dashboard content with session-based auth when enabled
 async fn dashboard_session_auth(
     State(auth_state): State<Arc<AuthState>>,
@@ -2,8 +2,11 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
-use crate::config::DashboardAuthSettings;
+use crate::acl::{AclConfigDiff, AclExampleVariant, LintFinding};
+use crate::config::{ApiAuthSettings, ApiCompressionSettings, DashboardAuthSettings};
+use crate::qos::{format_rate, HtbConfig, IpAllocation, PerIpConfig, UserAllocation};
 use crate::server::pool::PoolStats;
+use crate::session::{AclDecisionStats, AdmissionRejectionStats, FailureCategory, FailureSummary};
 
 /// API health check response
 #[derive(Debug, Serialize, Deserialize)]
@@ -11,23 +14,170 @@ pub struct HealthResponse {
     pub status: String,
     pub version: String,
     pub uptime_seconds: u64,
+    /// Random per boot; also recorded on every session as `instance_id`
+    pub instance_id: String,
+    /// Present when `server.overload` shedding is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overload: Option<crate::server::OverloadStatus>,
+    /// Sessions still open; present only while draining for shutdown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining_sessions: Option<usize>,
+    /// Addresses the SOCKS listeners are bound to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub listening: Vec<String>,
+}
+
+/// Build and runtime information, `GET /api/version`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionResponse {
+    pub version: String,
+    /// Commit the binary was built from; "unknown" outside a git checkout
+    pub git_hash: String,
+    pub build_timestamp: Option<DateTime<Utc>>,
+    pub rustc_version: String,
+    /// Cargo features compiled in
+    pub features: Vec<String>,
+    pub uptime_seconds: u64,
+    pub active_sessions: usize,
+    pub config_path: Option<String>,
+    pub subsystems: crate::utils::build_info::Subsystems,
+}
+
+/// State of one component checked by `GET /health/ready`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentState {
+    Up,
+    Down,
+    /// Not configured, so not required for readiness
+    Disabled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentStatus {
+    pub status: ComponentState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Readiness check response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadinessResponse {
+    /// "ready", "not_ready", or "draining" while shutdown waits for sessions
+    pub status: String,
+    /// socks_listener, acl, session_store and qos
+    pub components: std::collections::BTreeMap<String, ComponentStatus>,
 }
 
 /// Session detail in API response
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SessionResponse {
     pub id: String,
+    /// Server boot that created the session
+    pub instance_id: String,
+    /// Effective identity used for ACL, QoS and statistics
     pub user: String,
+    /// Principal that authenticated; differs from `user` under impersonation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authenticated_user: Option<String>,
+    /// Client-supplied correlation ID (`auth.allow_correlation_suffix`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Groups of the user as the ACL saw them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
     pub source_ip: String,
     pub source_port: u16,
     pub dest_ip: String,
+    /// Requested domain or IP literal, lowercased; what `dest_host=` searches
+    #[serde(default)]
+    pub dest_host: String,
     pub dest_port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sni_host: Option<String>,
+    /// Hostname the client asked for, even when it dialled an IP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_host: Option<String>,
+    /// socks_request, sni, resolve_extension or reverse_map
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_host_source: Option<String>,
     pub protocol: String,
+    /// `connect`, `bind` or `udp_associate`
+    #[serde(default)]
+    pub command: String,
+    /// 5, or 4 for SOCKS4/SOCKS4a clients
+    #[serde(default = "default_socks_version")]
+    pub socks_version: u8,
+    /// Tunnelled through the parent proxy (`server.upstream`)
+    #[serde(default)]
+    pub chained: bool,
+    /// `host:port` of the parent an active chained session is tunnelled through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    /// Whether that parent passed its latest health checks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_healthy: Option<bool>,
+    /// Waiting to be closed off an unhealthy parent (`server.upstream.on_unhealthy`)
+    #[serde(default)]
+    pub draining: bool,
+    /// Upstream connection wrapped in TLS by the proxy (`server.upstream_tls`)
+    #[serde(default)]
+    pub upstream_tls: bool,
+    /// Relayed although the ACL blocks it (`acl.mode = "monitor"`)
+    #[serde(default)]
+    pub would_block: bool,
+    /// Local address upstream traffic left from (`server.egress`)
+    #[serde(default)]
+    pub egress_ip: Option<String>,
+    /// Addresses the requested domain resolved to; empty for IP literals and chained
+    /// sessions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolved_ips: Vec<String>,
+    /// Destination address the upstream connection went to
+    #[serde(default)]
+    pub connected_ip: Option<String>,
+    /// What the ACL decision was made on: `domain`, `ip`, `resolved_ip` or
+    /// `default_policy`
+    #[serde(default)]
+    pub acl_matched_on: Option<String>,
+    /// SOCKS5 reply code the request was answered with
+    #[serde(default)]
+    pub reply_code: Option<u8>,
+    /// Why the request failed (`dns_error`, `connect_timeout`, `acl_block`, ...)
+    #[serde(default)]
+    pub failure: Option<String>,
     pub status: String,
     pub acl_decision: String,
     pub acl_rule: Option<String>,
     pub bytes_sent: u64,
     pub bytes_received: u64,
+    /// Bytes per second sent over the last 5 seconds; 0 once the session has ended
+    #[serde(default)]
+    pub rate_bps_sent: u64,
+    /// Bytes per second received over the last 5 seconds
+    #[serde(default)]
+    pub rate_bps_received: u64,
+    /// Bytes per second sent over the last 60 seconds
+    #[serde(default)]
+    pub rate_bps_sent_60s: u64,
+    /// Bytes per second received over the last 60 seconds
+    #[serde(default)]
+    pub rate_bps_received_60s: u64,
+    /// Keepalive probes armed while the tunnel was idle
+    #[serde(default)]
+    pub keepalive_probes: u64,
+    /// UDP ASSOCIATE: `strict`, `ip-only` or `learned`
+    #[serde(default)]
+    pub udp_association_mode: Option<String>,
+    /// UDP ASSOCIATE: client endpoint datagrams are accepted from, once known
+    #[serde(default)]
+    pub udp_client_endpoint: Option<String>,
+    /// UDP ASSOCIATE: datagrams dropped for coming from an unknown source
+    #[serde(default)]
+    pub udp_rejected_datagrams: u64,
+    /// UDP ASSOCIATE: datagrams dropped by the QoS bandwidth limits
+    #[serde(default)]
+    pub udp_throttled_datagrams: u64,
     pub start_time: String,
     pub end_time: Option<String>,
     pub duration_seconds: Option<u64>,
@@ -44,6 +194,18 @@ pub struct SessionStatsResponse {
     pub total_bytes_received: u64,
     pub top_users: Vec<UserStat>,
     pub top_destinations: Vec<DestinationStat>,
+    /// Per-group breakdown, with `group_by=group` only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<GroupStat>>,
+    /// Sessions relayed although the ACL blocks them (`acl.mode = "monitor"`)
+    #[serde(default)]
+    pub would_block: WouldBlockStats,
+    /// Connections refused by connection limits since startup
+    pub admission_rejections: AdmissionRejectionStats,
+    /// Finished sessions were evicted from memory (`sessions.memory_max_sessions`), so
+    /// the counts cover only the retained ones
+    pub truncated: bool,
+    pub evicted_sessions: u64,
 }
 
 /// Per-user statistics
@@ -55,6 +217,16 @@ pub struct UserStat {
     pub bytes_received: u64,
 }
 
+/// Traffic of the sessions monitor mode let through, i.e. what enforcing the ACL
+/// would have refused
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WouldBlockStats {
+    pub session_count: u64,
+    pub active_sessions: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
 /// Per-destination statistics
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DestinationStat {
@@ -64,6 +236,51 @@ pub struct DestinationStat {
     pub bytes_received: u64,
 }
 
+/// Per-group statistics. A session counts towards every group of its user, so the
+/// groups can add up to more than the total; sessions without groups are under
+/// [`UNGROUPED`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupStat {
+    pub group: String,
+    pub session_count: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub unique_users: u64,
+    pub acl: AclDecisionStats,
+}
+
+/// [`GroupStat::group`] of sessions whose user has no groups
+pub const UNGROUPED: &str = "ungrouped";
+
+/// Overall state on the public status page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublicState {
+    Up,
+    /// New connections are being shed under overload
+    Degraded,
+    /// `sessions.maintenance_message` is set
+    Maintenance,
+}
+
+/// Response of the unauthenticated GET /status.json.
+///
+/// Built field by field from aggregate counters, never from sessions, so it cannot
+/// carry usernames, destinations or exact counts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicStatus {
+    pub state: PublicState,
+    /// Whole minutes since the API server started
+    pub uptime_minutes: u64,
+    /// Active session count bucket, e.g. "1k-5k"
+    pub active_sessions: String,
+    /// Bytes per second, rounded to two significant digits; absent without metrics history
+    pub throughput_bytes_per_sec: Option<u64>,
+    /// `throughput_bytes_per_sec` in bits and bytes, e.g. "100 Mbps (12.5 MB/s)"
+    pub throughput_human: Option<String>,
+    pub maintenance_message: Option<String>,
+}
+
 /// ACL decision test response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AclTestResponse {
@@ -71,8 +288,205 @@ pub struct AclTestResponse {
     pub destination: String,
     pub port: u16,
     pub protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Time rule schedules were checked at, when the request gave one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<DateTime<Utc>>,
     pub decision: String,
     pub matched_rule: Option<String>,
+    /// SOCKS reply a blocked connection gets, e.g. connection_not_allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_code: Option<String>,
+    /// Where an allowed domain is resolved: local or remote
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolve: Option<String>,
+    /// Present when the request asked for `explain`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<AclTestExplanation>,
+}
+
+/// Evaluation trace of POST /api/acl/test with `explain`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclTestExplanation {
+    /// Rules that apply to the user, whether evaluated or not
+    pub rules_total: usize,
+    /// Position of the rule evaluation stopped at; null when the default policy applied
+    pub stopped_at: Option<usize>,
+    /// Evaluated rules in order
+    pub rules: Vec<AclTraceRule>,
+    /// Whether evaluated rules were left out of `rules` to cap its length
+    pub truncated: bool,
+    pub omitted_rules: usize,
+}
+
+/// One evaluated rule and which of its components matched
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclTraceRule {
+    /// 1-based position in evaluation order
+    pub position: usize,
+    pub rule_id: String,
+    /// `user:<name>` or `group:<name>`
+    pub scope: String,
+    pub description: String,
+    pub action: String,
+    pub priority: u32,
+    pub protocol_matched: bool,
+    pub destination_matched: bool,
+    pub port_matched: bool,
+    pub source_matched: bool,
+    /// Always true for rules without a schedule
+    pub schedule_matched: bool,
+    pub matched: bool,
+}
+
+/// Query parameters for GET /api/acl/example
+#[derive(Debug, Default, Deserialize)]
+pub struct AclExampleQuery {
+    /// minimal (default), kitchen-sink or from-learning
+    #[serde(default)]
+    pub variant: AclExampleVariant,
+}
+
+/// Response for GET /api/acl/example
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclExampleResponse {
+    pub variant: String,
+    pub template_version: u32,
+    /// Annotated TOML, ready to save as the ACL file
+    pub content: String,
+}
+
+/// Query parameters for GET /api/acl/rules/unused
+#[derive(Debug, Default, Deserialize)]
+pub struct UnusedAclRulesQuery {
+    /// Look-back window in days (default 90)
+    #[serde(default)]
+    pub days: Option<u32>,
+}
+
+/// Why a rule is reported as unused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnusedRuleStatus {
+    /// Tracked for the whole window and never matched
+    NeverMatched,
+    /// Matched before, but not within the window
+    Stale,
+    /// Not matched, but tracked for less than the window (new or edited rule)
+    NoData,
+}
+
+/// ACL rule without matches in the requested window
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnusedAclRule {
+    pub rule_id: String,
+    /// `user:<name>` or `group:<name>`
+    pub scope: String,
+    pub description: String,
+    pub action: String,
+    pub status: UnusedRuleStatus,
+    pub hits: u64,
+    pub last_matched_at: Option<DateTime<Utc>>,
+    pub tracked_since: DateTime<Utc>,
+}
+
+/// Response for GET /api/acl/rules/unused
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnusedAclRulesResponse {
+    pub days: u32,
+    pub since: DateTime<Utc>,
+    pub rules: Vec<UnusedAclRule>,
+}
+
+/// Hits of one ACL rule for one user
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclRuleUserHits {
+    pub user: String,
+    pub hits: u64,
+}
+
+/// Hit counters of one configured ACL rule
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclRuleStatsEntry {
+    pub rule_id: String,
+    /// `user:<name>` or `group:<name>`
+    pub scope: String,
+    pub description: String,
+    pub action: String,
+    pub hits: u64,
+    pub last_matched_at: Option<DateTime<Utc>>,
+    pub tracked_since: DateTime<Utc>,
+    /// Hits per user since startup or the last reset, most hits first
+    pub users: Vec<AclRuleUserHits>,
+}
+
+/// Response for GET /api/acl/rules/stats
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclRuleStatsResponse {
+    /// Every configured rule, most hits first; rules that never matched come last
+    pub rules: Vec<AclRuleStatsEntry>,
+}
+
+/// Response for POST /api/acl/rules/stats/reset
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclRuleStatsResetResponse {
+    /// Rule counters set back to zero
+    pub reset: usize,
+}
+
+/// Query parameters for POST /api/acl/import
+#[derive(Debug, Default, Deserialize)]
+pub struct AclImportQuery {
+    /// Validate and diff without applying anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Response for POST /api/acl/import
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclImportResponse {
+    pub success: bool,
+    pub dry_run: bool,
+    /// Whether the configuration is now live; always false for a dry run
+    pub applied: bool,
+    pub message: String,
+    /// Lint warnings of an accepted configuration
+    #[serde(default)]
+    pub findings: Vec<LintFinding>,
+    /// Changes against the live configuration; absent when validation failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<AclConfigDiff>,
+}
+
+/// Response for GET /api/acl/lint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclLintResponse {
+    /// `acl.strict_references`; unknown group references are errors when set
+    pub strict_references: bool,
+    pub errors: usize,
+    pub warnings: usize,
+    pub findings: Vec<LintFinding>,
+}
+
+/// Response for GET /api/acl/reload-status
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclReloadStatusResponse {
+    /// `acl.watch`: the file is reloaded when it changes
+    pub watching: bool,
+    /// Latest reload from the ACL file; `None` before the first one
+    pub last_reload: Option<AclReloadStatusEntry>,
+}
+
+/// One reload from the ACL file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclReloadStatusEntry {
+    pub timestamp: String,
+    /// `watcher` (the file changed) or `api` (`POST /api/admin/reload-acl`)
+    pub trigger: String,
+    pub success: bool,
+    /// Why the file was rejected; the previous rules stayed in effect
+    pub error: Option<String>,
 }
 
 /// ACL rule info for API response
@@ -103,9 +517,21 @@ pub struct SessionQueryParams {
     #[serde(default)]
     pub user: Option<String>,
     #[serde(default)]
+    pub authenticated_user: Option<String>,
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    #[serde(default)]
     pub hours: Option<u32>,
     #[serde(default)]
     pub dest_ip: Option<String>,
+    /// Exact host, or `*.example.com` for every name below example.com
+    #[serde(default)]
+    pub dest_host: Option<String>,
+    #[serde(default)]
+    pub requested_host: Option<String>,
+    /// socks_request, sni, resolve_extension or reverse_map
+    #[serde(default)]
+    pub requested_host_source: Option<String>,
     #[serde(default)]
     pub status: Option<String>,
     #[serde(default = "default_page")]
@@ -118,6 +544,157 @@ pub struct SessionQueryParams {
     pub sort_dir: Option<String>,
 }
 
+/// Query parameters for GET /api/sessions/active
+#[derive(Debug, Default, Deserialize)]
+pub struct ActiveSessionsQuery {
+    /// `rate`: busiest first by the 5-second transfer rate, both directions
+    #[serde(default)]
+    pub sort: Option<String>,
+    /// Return at most this many sessions (after sorting)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Body of POST /api/users/{user}/terminate-sessions; may be omitted
+#[derive(Debug, Default, Deserialize)]
+pub struct TerminateUserSessionsRequest {
+    /// Also refuse the user's logins for this many minutes (in memory, until restart)
+    #[serde(default)]
+    pub ban_minutes: Option<u64>,
+}
+
+/// Response for POST /api/users/{user}/terminate-sessions
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TerminateUserSessionsResponse {
+    pub user: String,
+    /// IDs of the sessions closed
+    pub terminated: Vec<String>,
+    pub close_reason: String,
+    /// Present when `ban_minutes` was given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ban: Option<UserBanResponse>,
+}
+
+/// An active user ban
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserBanResponse {
+    pub user: String,
+    pub banned_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub banned_by: String,
+}
+
+impl From<crate::auth::UserBan> for UserBanResponse {
+    fn from(ban: crate::auth::UserBan) -> Self {
+        Self {
+            user: ban.user,
+            banned_at: ban.banned_at,
+            expires_at: ban.expires_at,
+            banned_by: ban.banned_by,
+        }
+    }
+}
+
+/// Query parameters for aggregated session statistics
+#[derive(Debug, Default, Deserialize)]
+pub struct SessionStatsQuery {
+    /// `destination` (dialled address, default), `sni` (SNI name when observed) or
+    /// `requested_host` (hostname the client asked for, from any source) key the
+    /// destination table; `group` adds the per-group breakdown instead
+    #[serde(default)]
+    pub group_by: Option<String>,
+}
+
+/// Query parameters for GET /api/stats/destinations
+#[derive(Debug, Default, Deserialize)]
+pub struct DestinationStatsQuery {
+    /// How far back to look (default 24)
+    #[serde(default)]
+    pub window_hours: Option<u64>,
+    /// Width of each time bucket (default 60)
+    #[serde(default)]
+    pub bucket_minutes: Option<u32>,
+    /// Destinations to include, by bytes over the whole window (default 10, max 100)
+    #[serde(default)]
+    pub top: Option<usize>,
+}
+
+/// Per-destination traffic, bucketed by session start time
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DestinationStatsResponse {
+    pub window_hours: u64,
+    pub bucket_minutes: u32,
+    /// Ordered by `bucket_start`, then destination; empty buckets are omitted
+    pub buckets: Vec<crate::session::DestinationBucket>,
+}
+
+/// Query parameters for GET /api/stats/failures
+#[derive(Debug, Default, Deserialize)]
+pub struct FailureStatsQuery {
+    /// How far back to look (default 60, max 1440)
+    #[serde(default)]
+    pub window_minutes: Option<u64>,
+    /// Failing destinations to include (default 10, max 100)
+    #[serde(default)]
+    pub top: Option<usize>,
+}
+
+/// Failed requests over a window, from the in-memory failure log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailureStatsResponse {
+    pub window_minutes: u64,
+    #[serde(flatten)]
+    pub summary: FailureSummary,
+    /// Failures per category since startup, including those outside the window
+    pub totals_since_start: std::collections::BTreeMap<FailureCategory, u64>,
+}
+
+/// First message a client sends on GET /api/ws; `{}` subscribes to everything
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SessionEventSubscription {
+    /// Only events of this (effective) user
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Only events for this destination host, or `*.example.com` for every name below
+    /// example.com
+    #[serde(default)]
+    pub destination: Option<String>,
+}
+
+/// Query parameters for GET /api/admission/rejections and its live stream
+#[derive(Debug, Default, Deserialize)]
+pub struct AdmissionRejectionsQuery {
+    /// Only rejections for this (effective) user
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Maximum entries returned (default 100, capped at 1000; ignored by the stream)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Query parameters for GET /api/metrics/history
+#[derive(Debug, Default, Deserialize)]
+pub struct MetricsHistoryQuery {
+    /// Range start (RFC 3339); defaults to `minutes` before `end`
+    #[serde(default)]
+    pub start: Option<DateTime<Utc>>,
+    /// Range end (RFC 3339); defaults to now
+    #[serde(default)]
+    pub end: Option<DateTime<Utc>>,
+    /// Look-back window when `start` is absent (default 120)
+    #[serde(default)]
+    pub minutes: Option<u64>,
+    /// Upper bound on returned samples; wider ranges are decimated per time bucket
+    #[serde(default)]
+    pub max_points: Option<usize>,
+    /// `raw`, `1m`, `15m` or `auto` (default): the history tier samples are read from
+    #[serde(default)]
+    pub resolution: Option<String>,
+    /// `json` (array) or `ndjson`; overrides the Accept header
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
 fn default_page() -> u32 {
     1
 }
@@ -126,6 +703,10 @@ fn default_page_size() -> u32 {
     50
 }
 
+fn default_socks_version() -> u8 {
+    5
+}
+
 /// ACL test request
 #[derive(Debug, Deserialize)]
 pub struct AclTestRequest {
@@ -133,6 +714,101 @@ pub struct AclTestRequest {
     pub destination: String,
     pub port: u16,
     pub protocol: String,
+    /// Client IP address matched against rule `sources`; rules with sources never
+    /// match when omitted
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Return the trace of every rule evaluated
+    #[serde(default)]
+    pub explain: bool,
+    /// Check rule schedules at this time instead of now, to preview a decision
+    #[serde(default)]
+    pub at: Option<DateTime<Utc>>,
+}
+
+/// pam.address cache invalidation request; omit `ip` to flush every entry
+#[derive(Debug, Default, Deserialize)]
+pub struct AddressCacheInvalidateRequest {
+    #[serde(default)]
+    pub ip: Option<String>,
+}
+
+/// pam.address cache invalidation result
+#[derive(Debug, Serialize)]
+pub struct AddressCacheInvalidateResponse {
+    pub removed: usize,
+    pub stats: crate::auth::AddressGateStats,
+}
+
+/// DNS cache flush result
+#[derive(Debug, Serialize)]
+pub struct DnsFlushResponse {
+    pub flushed: usize,
+    /// Counters since startup; `entries` is 0 after the flush
+    pub stats: crate::server::DnsCacheStats,
+}
+
+/// Overload shedding override request
+#[derive(Debug, Deserialize)]
+pub struct OverloadModeRequest {
+    pub mode: crate::server::ShedMode,
+}
+
+/// Admission dry-run request
+#[derive(Debug, Deserialize)]
+pub struct AdmissionTestRequest {
+    pub user: String,
+    /// Groups evaluated by the ACL gate; supplied by the caller, not resolved
+    #[serde(default)]
+    pub groups: Vec<String>,
+    pub destination: String,
+    pub port: u16,
+    /// "tcp" or "udp"; derived from the command when omitted
+    #[serde(default)]
+    pub protocol: Option<String>,
+    /// "connect", "bind" or "udp_associate"
+    #[serde(default = "default_admission_command")]
+    pub command: String,
+    /// Client IP address for ACL rules with `sources`
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+fn default_admission_command() -> String {
+    "connect".to_string()
+}
+
+/// Result of a single admission gate
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdmissionGateResult {
+    pub gate: String,
+    /// "pass", "fail" or "not_configured"
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Admission dry-run verdict
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdmissionTestResponse {
+    pub user: String,
+    /// Caller-supplied groups the ACL gate was evaluated with
+    pub groups: Vec<String>,
+    pub destination: String,
+    pub port: u16,
+    pub protocol: String,
+    pub command: String,
+    pub admitted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_gate: Option<String>,
+    /// SOCKS5 reply code the client would receive for the request itself
+    pub reply_code: u8,
+    pub reply: String,
+    pub gates: Vec<AdmissionGateResult>,
+    /// Gates with no RustSocks implementation, hence never part of the verdict
+    pub not_evaluated: Vec<String>,
 }
 
 /// Connectivity test request payload
@@ -155,6 +831,14 @@ pub struct ConnectivityTestResponse {
     pub error: Option<String>,
 }
 
+/// Connectivity probes response (`GET /api/diagnostics/probes`)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProbesResponse {
+    /// `diagnostics.probe_user`
+    pub user: String,
+    pub probes: Vec<crate::server::ProbeStatus>,
+}
+
 /// API error response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
@@ -199,7 +883,13 @@ pub struct ApiConfig {
     pub swagger_enabled: bool,
     pub dashboard_enabled: bool,
     pub dashboard_auth: DashboardAuthSettings,
+    pub api_auth: ApiAuthSettings,
+    pub api_compression: ApiCompressionSettings,
     pub base_path: String,
+    /// Serve on this Unix domain socket instead of `bind_address:bind_port`
+    pub unix_socket: Option<std::path::PathBuf>,
+    /// Permission bits of `unix_socket`
+    pub unix_socket_mode: u32,
 }
 
 impl Default for ApiConfig {
@@ -212,7 +902,11 @@ impl Default for ApiConfig {
             swagger_enabled: true,
             dashboard_enabled: false,
             dashboard_auth: DashboardAuthSettings::default(),
+            api_auth: ApiAuthSettings::default(),
+            api_compression: ApiCompressionSettings::default(),
             base_path: "/".to_string(),
+            unix_socket: None,
+            unix_socket_mode: 0o660,
         }
     }
 }
@@ -230,6 +924,21 @@ pub struct AddRuleRequest {
     pub ports: Vec<String>,
     pub protocols: Vec<String>,
     pub priority: u32,
+    /// Client IPs and CIDRs the rule is limited to; empty = any client
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Access-log verbosity: default, silent, minimal or verbose
+    #[serde(default)]
+    pub log: Option<String>,
+    /// SOCKS reply for block rules, e.g. host_unreachable; unset = connection_not_allowed
+    #[serde(default)]
+    pub reply_code: Option<String>,
+    /// Where allowed domains are resolved: local (default) or remote
+    #[serde(default)]
+    pub resolve: Option<String>,
+    /// Days and times the rule applies; unset = always
+    #[serde(default)]
+    pub schedule: Option<crate::acl::types::RuleSchedule>,
 }
 
 /// Request to update an existing ACL rule
@@ -273,6 +982,7 @@ pub struct RuleOperationResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GroupSummary {
     pub name: String,
+    pub parents: Vec<String>,
     pub rule_count: usize,
 }
 
@@ -286,6 +996,8 @@ pub struct GroupListResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GroupDetailResponse {
     pub name: String,
+    /// Groups whose rules this one inherits
+    pub parents: Vec<String>,
     pub rules: Vec<crate::acl::types::AclRule>,
 }
 
@@ -315,12 +1027,18 @@ pub struct UserDetailResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GlobalSettingsResponse {
     pub default_policy: String,
+    /// `enforce` or `monitor`, as currently applied
+    pub mode: String,
 }
 
-/// Request to update global settings
+/// Request to update global settings; at least one field must be set
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateGlobalSettingsRequest {
-    pub default_policy: String,
+    #[serde(default)]
+    pub default_policy: Option<String>,
+    /// `enforce` or `monitor`; lasts until restart
+    #[serde(default)]
+    pub mode: Option<String>,
 }
 
 /// Response for global settings update
@@ -330,6 +1048,8 @@ pub struct UpdateGlobalSettingsResponse {
     pub message: String,
     pub old_policy: String,
     pub new_policy: String,
+    pub old_mode: String,
+    pub new_mode: String,
 }
 
 /// Request to search for rules
@@ -341,6 +1061,8 @@ pub struct RuleSearchRequest {
     pub port: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub action: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
 }
 
 /// Single search result
@@ -362,6 +1084,23 @@ pub struct RuleSearchResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateGroupRequest {
     pub name: String,
+    /// Groups the new group inherits rules from
+    #[serde(default)]
+    pub parents: Vec<String>,
+}
+
+/// Request for PUT /api/acl/groups/{groupname}/parents
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetGroupParentsRequest {
+    pub parents: Vec<String>,
+}
+
+/// Query parameters for DELETE /api/acl/groups/{groupname}
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteGroupQuery {
+    /// Also delete a group others inherit from, removing it from their parents
+    #[serde(default)]
+    pub cascade: bool,
 }
 
 /// Response for delete group operation
@@ -416,6 +1155,8 @@ pub struct PoolStatsResponse {
     pub dropped_full: u64,
     pub expired: u64,
     pub evicted: u64,
+    /// Idle connections dropped because the upstream had closed them
+    pub validation_failures: u64,
     pub pending_creates: u64,
     pub hit_rate: f64,
     pub config: PoolConfigResponse,
@@ -443,6 +1184,7 @@ pub struct PoolDestinationResponse {
     pub drops: u64,
     pub evicted: u64,
     pub expired: u64,
+    pub validation_failures: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_activity: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -465,6 +1207,7 @@ impl From<PoolStats> for PoolStatsResponse {
                 drops: dest.drops,
                 evicted: dest.evicted,
                 expired: dest.expired,
+                validation_failures: dest.validation_failures,
                 last_activity: format_system_time(dest.last_activity),
                 last_miss: format_system_time(dest.last_miss),
             })
@@ -489,6 +1232,7 @@ impl From<PoolStats> for PoolStatsResponse {
             dropped_full: stats.dropped_full,
             expired: stats.expired,
             evicted: stats.evicted,
+            validation_failures: stats.validation_failures,
             pending_creates: stats.pending_creates,
             hit_rate,
             config: PoolConfigResponse {
@@ -507,6 +1251,206 @@ fn format_system_time(time: Option<SystemTime>) -> Option<String> {
     time.map(|ts| DateTime::<Utc>::from(ts).to_rfc3339())
 }
 
+// ============================================================================
+// QoS API Types
+// ============================================================================
+
+/// A rate as raw bytes per second and in readable form
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateValue {
+    pub bytes_per_sec: u64,
+    /// Bits and bytes, e.g. "100 Mbps (12.5 MB/s)"
+    pub rate_human: String,
+}
+
+impl From<u64> for RateValue {
+    fn from(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            rate_human: format_rate(bytes_per_sec),
+        }
+    }
+}
+
+/// Query parameters for GET /api/qos/allocations
+#[derive(Debug, Default, Deserialize)]
+pub struct QosAllocationsQuery {
+    /// Source IPs listed, heaviest first (default 50, capped at 1000)
+    #[serde(default)]
+    pub ip_limit: Option<usize>,
+}
+
+/// Response for GET /api/qos/allocations
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosAllocationsResponse {
+    pub enabled: bool,
+    /// Configured HTB limits; absent when QoS is disabled
+    pub limits: Option<QosLimitsResponse>,
+    pub users: Vec<QosUserAllocationResponse>,
+    /// Per-source-IP shaping; absent unless `qos.per_ip` is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_ip: Option<QosPerIpResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosPerIpResponse {
+    pub max_per_ip: RateValue,
+    pub burst_bytes: u64,
+    pub exempt: Vec<String>,
+    /// Source IPs with a bucket right now (idle ones are evicted)
+    pub tracked_ips: usize,
+    /// `ips` holds only the `ip_limit` heaviest addresses
+    pub truncated: bool,
+    pub ips: Vec<QosIpAllocationResponse>,
+}
+
+impl QosPerIpResponse {
+    /// `allocations` heaviest first, cut to `limit`
+    pub fn new(config: &PerIpConfig, mut allocations: Vec<IpAllocation>, limit: usize) -> Self {
+        let tracked_ips = allocations.len();
+        allocations.truncate(limit);
+        Self {
+            max_per_ip: config.max_bytes_per_sec.into(),
+            burst_bytes: config.burst,
+            exempt: config.exempt.clone(),
+            tracked_ips,
+            truncated: tracked_ips > allocations.len(),
+            ips: allocations
+                .into_iter()
+                .map(QosIpAllocationResponse::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosIpAllocationResponse {
+    pub ip: String,
+    /// Bytes relayed since the bucket was created
+    pub total_bytes: u64,
+    /// Tokens left in the bucket
+    pub available_bytes: u64,
+    pub is_active: bool,
+}
+
+impl From<IpAllocation> for QosIpAllocationResponse {
+    fn from(allocation: IpAllocation) -> Self {
+        Self {
+            ip: allocation.ip.to_string(),
+            total_bytes: allocation.total_bytes,
+            available_bytes: allocation.available_bytes,
+            is_active: allocation.is_active,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosLimitsResponse {
+    pub global: RateValue,
+    pub guaranteed_per_user: RateValue,
+    pub max_per_user: RateValue,
+    pub burst_size_bytes: u64,
+}
+
+impl From<&HtbConfig> for QosLimitsResponse {
+    fn from(config: &HtbConfig) -> Self {
+        Self {
+            global: config.global_bandwidth_bytes_per_sec.into(),
+            guaranteed_per_user: config.guaranteed_bandwidth_bytes_per_sec.into(),
+            max_per_user: config.max_bandwidth_bytes_per_sec.into(),
+            burst_size_bytes: config.burst_size_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosUserAllocationResponse {
+    pub user: String,
+    pub allocated: RateValue,
+    pub guaranteed: RateValue,
+    /// Part of `allocated` above the guarantee
+    pub borrowed: RateValue,
+    pub max: RateValue,
+    /// `max` was set through PUT /api/qos/users/{user}/limit
+    pub max_overridden: bool,
+    /// Estimated from recent usage
+    pub current_demand: RateValue,
+    pub is_active: bool,
+    /// A transfer is waiting for the user's tokens right now
+    pub is_throttled: bool,
+    pub active_connections: usize,
+}
+
+impl From<UserAllocation> for QosUserAllocationResponse {
+    fn from(allocation: UserAllocation) -> Self {
+        Self {
+            user: allocation.user,
+            allocated: allocation.allocated_bandwidth.into(),
+            guaranteed: allocation.guaranteed_bandwidth.into(),
+            borrowed: allocation.borrowed_bandwidth.into(),
+            max: allocation.max_bandwidth.into(),
+            max_overridden: allocation.max_overridden,
+            current_demand: allocation.current_demand.into(),
+            is_active: allocation.is_active,
+            is_throttled: allocation.is_throttled,
+            active_connections: allocation.active_connections,
+        }
+    }
+}
+
+/// Response for GET /api/qos/config
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosConfigResponse {
+    pub enabled: bool,
+    /// HTB parameters in effect, including rates changed by a config reload; absent
+    /// when QoS is disabled
+    pub htb: Option<QosHtbConfigResponse>,
+    /// Per-user maximums set at runtime
+    pub user_limits: Vec<QosUserLimitResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosHtbConfigResponse {
+    pub global: RateValue,
+    pub guaranteed_per_user: RateValue,
+    pub max_per_user: RateValue,
+    pub burst_size_bytes: u64,
+    pub refill_interval_ms: u64,
+    pub fair_sharing_enabled: bool,
+    pub rebalance_interval_ms: u64,
+    pub idle_timeout_secs: u64,
+}
+
+impl From<&HtbConfig> for QosHtbConfigResponse {
+    fn from(config: &HtbConfig) -> Self {
+        Self {
+            global: config.global_bandwidth_bytes_per_sec.into(),
+            guaranteed_per_user: config.guaranteed_bandwidth_bytes_per_sec.into(),
+            max_per_user: config.max_bandwidth_bytes_per_sec.into(),
+            burst_size_bytes: config.burst_size_bytes,
+            refill_interval_ms: config.refill_interval_ms,
+            fair_sharing_enabled: config.fair_sharing_enabled,
+            rebalance_interval_ms: config.rebalance_interval_ms,
+            idle_timeout_secs: config.idle_timeout_secs,
+        }
+    }
+}
+
+/// Request for PUT /api/qos/users/{user}/limit
+#[derive(Debug, Deserialize)]
+pub struct QosUserLimitRequest {
+    /// Bytes per second or a rate with a unit, e.g. "50mbps"
+    #[serde(with = "crate::qos::rate::rate_serde")]
+    pub max_bandwidth: u64,
+}
+
+/// A per-user maximum set at runtime
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosUserLimitResponse {
+    pub user: String,
+    pub max: RateValue,
+}
+
 // ============================================================================
 // System Resources API Types
 // ============================================================================
@@ -529,4 +1473,60 @@ pub struct SystemResourcesResponse {
     /// System load average (1 minute)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub load_average_1m: Option<f64>,
+    /// Adaptive session batch writer state (only when a persistent store is attached)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_writer: Option<SessionWriterStatsResponse>,
+    /// File descriptors held by the process (Linux, macOS and the BSDs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process_open_fds: Option<u64>,
+    /// Effective RLIMIT_NOFILE; absent when unlimited or unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process_fd_limit: Option<u64>,
+    /// Present when `server.guardrails` is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guardrails: Option<crate::server::ResourceGuardStatus>,
+}
+
+/// Adaptive session batch writer state
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionWriterStatsResponse {
+    pub adaptive: bool,
+    pub effective_batch_size: usize,
+    pub effective_interval_ms: u64,
+    pub min_batch_size: usize,
+    pub max_batch_size: usize,
+    pub target_flush_ms: u64,
+    pub queue_depth: usize,
+    /// `sessions.batch_queue_max`
+    pub queue_max: usize,
+    pub overflow_policy: crate::session::BatchOverflowPolicy,
+    /// Sessions discarded on a full queue since startup; lost to the session store
+    pub dropped_sessions: u64,
+    /// Enqueues that waited for a flush on a full queue since startup
+    pub blocked_enqueues: u64,
+    pub flush_count: u64,
+    pub last_flush_ms: Option<f64>,
+    pub p95_flush_ms: Option<f64>,
+}
+
+#[cfg(feature = "database")]
+impl From<crate::session::BatchWriterStats> for SessionWriterStatsResponse {
+    fn from(stats: crate::session::BatchWriterStats) -> Self {
+        Self {
+            adaptive: stats.adaptive,
+            effective_batch_size: stats.effective_batch_size,
+            effective_interval_ms: stats.effective_interval_ms,
+            min_batch_size: stats.min_batch_size,
+            max_batch_size: stats.max_batch_size,
+            target_flush_ms: stats.target_flush_ms,
+            queue_depth: stats.queue_depth,
+            queue_max: stats.queue_max,
+            overflow_policy: stats.overflow_policy,
+            dropped_sessions: stats.dropped_sessions,
+            blocked_enqueues: stats.blocked_enqueues,
+            flush_count: stats.flush_count,
+            last_flush_ms: stats.last_flush_ms,
+            p95_flush_ms: stats.p95_flush_ms,
+        }
+    }
 }
@@ -0,0 +1,220 @@
+//! Client gate for `pam.address`.
+//!
+//! Invoking PAM for every TCP connection is expensive with network-backed stacks
+//! (pam_ldap, sssd), so the gate sits in front of it:
+//!
+//! 1. `auth.client_deny` CIDRs are rejected without calling PAM
+//! 2. `auth.client_allow` CIDRs are accepted without calling PAM
+//! 3. Everything else goes through PAM, and the verdict is cached per client IP for
+//!    `auth.pam.address_cache_secs` (accepted) or `auth.pam.negative_cache_secs` (rejected)
+//!
+//! With none of those settings configured every connection still reaches PAM.
+
+#[cfg(feature = "metrics")]
+use super::metrics::{ADDRESS_CACHE_HITS, PAM_ADDRESS_INVOCATIONS, PAM_ADDRESS_LATENCY};
+use crate::config::AuthConfig;
+use crate::utils::error::{Result, RustSocksError};
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use ipnet::IpNet;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Why an address check did not pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressAuthError {
+    /// The backend rejected the client; the verdict may be cached
+    Denied(String),
+    /// The backend could not decide (PAM/LDAP unavailable); never cached
+    Unavailable(String),
+}
+
+/// Backend consulted for clients not covered by the allow/deny lists.
+pub trait AddressAuthenticator: Send + Sync + 'static {
+    fn authenticate<'a>(
+        &'a self,
+        client_ip: IpAddr,
+    ) -> BoxFuture<'a, std::result::Result<(), AddressAuthError>>;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CachedVerdict {
+    allowed: bool,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct GateCounters {
+    cache_hits: AtomicU64,
+    backend_calls: AtomicU64,
+    backend_latency_micros: AtomicU64,
+    allow_list_hits: AtomicU64,
+    deny_list_hits: AtomicU64,
+}
+
+/// Counters exposed by the invalidation endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressGateStats {
+    pub cache_hits: u64,
+    pub pam_invocations: u64,
+    pub average_pam_latency_ms: f64,
+    pub allow_list_hits: u64,
+    pub deny_list_hits: u64,
+    pub cached_entries: usize,
+}
+
+pub struct AddressGate {
+    backend: Arc<dyn AddressAuthenticator>,
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+    positive_ttl: Duration,
+    negative_ttl: Duration,
+    cache: DashMap<IpAddr, CachedVerdict>,
+    counters: GateCounters,
+}
+
+impl AddressGate {
+    pub fn new(config: &AuthConfig, backend: Arc<dyn AddressAuthenticator>) -> Result<Self> {
+        Ok(Self {
+            backend,
+            allow: parse_networks("auth.client_allow", &config.client_allow)?,
+            deny: parse_networks("auth.client_deny", &config.client_deny)?,
+            positive_ttl: Duration::from_secs(config.pam.address_cache_secs),
+            negative_ttl: Duration::from_secs(config.pam.negative_cache_secs),
+            cache: DashMap::new(),
+            counters: GateCounters::default(),
+        })
+    }
+
+    /// Decide whether `client_ip` may proceed.
+    pub async fn check(&self, client_ip: IpAddr) -> Result<()> {
+        if self.deny.iter().any(|net| net.contains(&client_ip)) {
+            self.counters.deny_list_hits.fetch_add(1, Ordering::Relaxed);
+            return Err(RustSocksError::AuthFailed(format!(
+                "Client {} is listed in auth.client_deny",
+                client_ip
+            )));
+        }
+        if self.allow.iter().any(|net| net.contains(&client_ip)) {
+            self.counters
+                .allow_list_hits
+                .fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        if let Some(verdict) = self.cached(client_ip) {
+            self.counters.cache_hits.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            ADDRESS_CACHE_HITS.inc();
+            return if verdict {
+                Ok(())
+            } else {
+                Err(RustSocksError::AuthFailed(format!(
+                    "PAM address authentication failed for {} (cached)",
+                    client_ip
+                )))
+            };
+        }
+
+        let started = Instant::now();
+        let outcome = self.backend.authenticate(client_ip).await;
+        let elapsed = started.elapsed();
+        self.counters.backend_calls.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .backend_latency_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            PAM_ADDRESS_INVOCATIONS.inc();
+            PAM_ADDRESS_LATENCY.observe(elapsed.as_secs_f64());
+        }
+
+        match outcome {
+            Ok(()) => {
+                self.remember(client_ip, true, self.positive_ttl);
+                Ok(())
+            }
+            Err(AddressAuthError::Denied(msg)) => {
+                self.remember(client_ip, false, self.negative_ttl);
+                Err(RustSocksError::AuthFailed(msg))
+            }
+            Err(AddressAuthError::Unavailable(msg)) => Err(RustSocksError::AuthFailed(msg)),
+        }
+    }
+
+    /// Drop cached verdicts for one client, or for everyone when `client_ip` is `None`.
+    /// Returns the number of entries removed.
+    pub fn invalidate(&self, client_ip: Option<IpAddr>) -> usize {
+        match client_ip {
+            Some(ip) => usize::from(self.cache.remove(&ip).is_some()),
+            None => {
+                let removed = self.cache.len();
+                self.cache.clear();
+                removed
+            }
+        }
+    }
+
+    pub fn stats(&self) -> AddressGateStats {
+        let pam_invocations = self.counters.backend_calls.load(Ordering::Relaxed);
+        let latency_micros = self.counters.backend_latency_micros.load(Ordering::Relaxed);
+        let average_pam_latency_ms = if pam_invocations == 0 {
+            0.0
+        } else {
+            latency_micros as f64 / pam_invocations as f64 / 1000.0
+        };
+
+        AddressGateStats {
+            cache_hits: self.counters.cache_hits.load(Ordering::Relaxed),
+            pam_invocations,
+            average_pam_latency_ms,
+            allow_list_hits: self.counters.allow_list_hits.load(Ordering::Relaxed),
+            deny_list_hits: self.counters.deny_list_hits.load(Ordering::Relaxed),
+            cached_entries: self.cache.len(),
+        }
+    }
+
+    fn cached(&self, client_ip: IpAddr) -> Option<bool> {
+        let entry = self.cache.get(&client_ip)?;
+        if entry.expires_at > Instant::now() {
+            return Some(entry.allowed);
+        }
+        drop(entry);
+        self.cache.remove_if(&client_ip, |_, verdict| {
+            verdict.expires_at <= Instant::now()
+        });
+        None
+    }
+
+    fn remember(&self, client_ip: IpAddr, allowed: bool, ttl: Duration) {
+        if ttl.is_zero() {
+            return;
+        }
+        debug!(client_ip = %client_ip, allowed, ttl_secs = ttl.as_secs(), "Caching PAM address verdict");
+        self.cache.insert(
+            client_ip,
+            CachedVerdict {
+                allowed,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+    }
+}
+
+/// Parse a list of CIDRs or bare IP addresses.
+pub(crate) fn parse_networks(field: &str, entries: &[String]) -> Result<Vec<IpNet>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.trim();
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| RustSocksError::Config(format!("Invalid {} entry: {}", field, entry)))
+        })
+        .collect()
+}
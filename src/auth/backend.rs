@@ -0,0 +1,74 @@
+//! Authenticators behind the SOCKS methods that check a client without a method-specific
+//! exchange: `none`, `pam.address`, and the username/password (RFC 1929) backends
+//! `userpass`, `pam.username` and `ldap`.
+//!
+//! [`AuthManager`](super::AuthManager) reads the credentials off the wire, strips
+//! correlation and impersonation suffixes, and hands the rest to the backend. What the
+//! backend returns decides the session user; answering the client and looking up
+//! groups the backend did not supply stay with the manager.
+
+use crate::protocol::AuthMethod;
+use crate::utils::error::Result;
+use futures::future::BoxFuture;
+use std::net::IpAddr;
+use zeroize::Zeroizing;
+
+/// What a backend gets to decide on.
+pub struct AuthRequest {
+    pub client_ip: IpAddr,
+    /// Login for username/password methods; `None` for no-auth methods
+    pub credentials: Option<LoginCredentials>,
+}
+
+/// Username and password sent by the client, wiped from memory on drop.
+pub struct LoginCredentials {
+    /// Principal to verify, without correlation or impersonation suffixes
+    pub username: String,
+    pub password: Zeroizing<String>,
+}
+
+/// Successful authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthOutcome {
+    /// The client may go on without naming a user (no-auth methods)
+    Anonymous,
+    /// The credentials of `username` were verified
+    User {
+        username: String,
+        /// Groups from the backend itself; `None` looks them up from the system
+        groups: Option<Vec<String>>,
+    },
+}
+
+/// A way of checking clients, selected by name in `auth.client_method`,
+/// `auth.socks_method` or `auth.socks_method_preference`.
+///
+/// A rejected client is an [`AuthFailed`](crate::utils::error::RustSocksError::AuthFailed)
+/// error, and so is a backend that could not be reached in time: the handshake must
+/// fail rather than wait.
+pub trait Authenticator: Send + Sync + 'static {
+    /// Configuration name, e.g. `pam.username`
+    fn name(&self) -> &'static str;
+
+    /// SOCKS5 method byte this backend answers
+    fn method(&self) -> AuthMethod;
+
+    fn authenticate<'a>(&'a self, request: AuthRequest) -> BoxFuture<'a, Result<AuthOutcome>>;
+}
+
+/// `none`: every client is accepted.
+pub struct NoAuthenticator;
+
+impl Authenticator for NoAuthenticator {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn method(&self) -> AuthMethod {
+        AuthMethod::NoAuth
+    }
+
+    fn authenticate<'a>(&'a self, _request: AuthRequest) -> BoxFuture<'a, Result<AuthOutcome>> {
+        Box::pin(async { Ok(AuthOutcome::Anonymous) })
+    }
+}
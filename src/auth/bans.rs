@@ -0,0 +1,99 @@
+//! Temporary bans of users, set through `POST /api/users/{user}/terminate-sessions`.
+//!
+//! A banned user's login is refused after the credentials check, before the client is
+//! told it succeeded. Bans live in memory only: they outlast ACL and config reloads but
+//! not a restart, and an expired ban is dropped the next time it is looked at.
+
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+
+/// One active ban
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserBan {
+    pub user: String,
+    pub banned_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// API caller that set the ban, "-" when unknown
+    pub banned_by: String,
+}
+
+#[derive(Debug, Default)]
+pub struct UserBans {
+    bans: DashMap<String, UserBan>,
+}
+
+impl UserBans {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ban `user` for `duration` from now, replacing any ban they already have
+    pub fn ban(&self, user: &str, duration: Duration, banned_by: &str) -> UserBan {
+        let banned_at = Utc::now();
+        let ban = UserBan {
+            user: user.to_string(),
+            banned_at,
+            expires_at: banned_at + duration,
+            banned_by: banned_by.to_string(),
+        };
+        self.bans.insert(user.to_string(), ban.clone());
+        ban
+    }
+
+    /// Remove the ban on `user`; `None` when they had none that was still active
+    pub fn lift(&self, user: &str) -> Option<UserBan> {
+        self.bans
+            .remove(user)
+            .map(|(_, ban)| ban)
+            .filter(|ban| ban.expires_at > Utc::now())
+    }
+
+    /// The active ban on `user`, if any
+    pub fn get(&self, user: &str) -> Option<UserBan> {
+        let now = Utc::now();
+        let ban = self.bans.get(user).map(|ban| ban.clone())?;
+        if ban.expires_at > now {
+            return Some(ban);
+        }
+        self.bans.remove_if(user, |_, ban| ban.expires_at <= now);
+        None
+    }
+
+    /// Active bans, soonest to expire first
+    pub fn list(&self) -> Vec<UserBan> {
+        let now = Utc::now();
+        self.bans.retain(|_, ban| ban.expires_at > now);
+        let mut bans: Vec<UserBan> = self.bans.iter().map(|ban| ban.value().clone()).collect();
+        bans.sort_by(|a, b| a.expires_at.cmp(&b.expires_at).then(a.user.cmp(&b.user)));
+        bans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bans_expire_and_can_be_lifted() {
+        let bans = UserBans::new();
+        bans.ban("alice", Duration::minutes(30), "ops");
+        bans.ban("bob", Duration::minutes(5), "-");
+        assert_eq!(bans.get("alice").unwrap().banned_by, "ops");
+        assert!(bans.get("carol").is_none());
+
+        let listed: Vec<String> = bans.list().into_iter().map(|ban| ban.user).collect();
+        assert_eq!(listed, ["bob", "alice"]);
+
+        assert!(bans.lift("bob").is_some());
+        assert!(bans.lift("bob").is_none());
+        assert!(bans.get("bob").is_none());
+
+        // A ban that has run out is gone, whichever way it is looked at
+        bans.ban("alice", Duration::zero(), "ops");
+        assert!(bans.get("alice").is_none());
+        assert!(bans.list().is_empty());
+        bans.ban("alice", Duration::zero(), "ops");
+        assert!(bans.lift("alice").is_none());
+    }
+}
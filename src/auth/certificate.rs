@@ -0,0 +1,330 @@
+//! Client certificate identities (`auth.client_method = "tls.cert"`).
+//!
+//! The TLS acceptor has already verified the chain against `server.tls.client_ca_path`;
+//! this only reads the name the session runs as from the leaf certificate. It walks
+//! just enough DER to reach the subject and the subjectAltName extension.
+//! `rustsocks check` reads the expiry date the same way.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_TELETEX_STRING: u8 = 0x14;
+const TAG_IA5_STRING: u8 = 0x16;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_BMP_STRING: u8 = 0x1e;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+/// `[0] EXPLICIT Version` of TBSCertificate
+const TAG_VERSION: u8 = 0xa0;
+/// `[3] EXPLICIT Extensions` of TBSCertificate
+const TAG_EXTENSIONS: u8 = 0xa3;
+/// GeneralName choices, `[n] IMPLICIT IA5String`
+const TAG_SAN_EMAIL: u8 = 0x81;
+const TAG_SAN_DNS: u8 = 0x82;
+const TAG_SAN_URI: u8 = 0x86;
+
+/// id-at-commonName, 2.5.4.3
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+/// id-ce-subjectAltName, 2.5.29.17
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Certificate field used as the username (`auth.tls_cert_identity`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertIdentityField {
+    /// Subject common name
+    CommonName,
+    /// First DNS name in subjectAltName
+    SanDns,
+    /// First email address (rfc822Name) in subjectAltName
+    SanEmail,
+    /// First URI in subjectAltName
+    SanUri,
+}
+
+impl CertIdentityField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CertIdentityField::CommonName => "cn",
+            CertIdentityField::SanDns => "san.dns",
+            CertIdentityField::SanEmail => "san.email",
+            CertIdentityField::SanUri => "san.uri",
+        }
+    }
+
+    fn san_tag(&self) -> Option<u8> {
+        match self {
+            CertIdentityField::CommonName => None,
+            CertIdentityField::SanDns => Some(TAG_SAN_DNS),
+            CertIdentityField::SanEmail => Some(TAG_SAN_EMAIL),
+            CertIdentityField::SanUri => Some(TAG_SAN_URI),
+        }
+    }
+}
+
+impl FromStr for CertIdentityField {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "cn" => Ok(CertIdentityField::CommonName),
+            "san.dns" => Ok(CertIdentityField::SanDns),
+            "san.email" => Ok(CertIdentityField::SanEmail),
+            "san.uri" => Ok(CertIdentityField::SanUri),
+            other => Err(format!(
+                "unsupported certificate identity field '{}' (expected cn, san.dns, \
+                 san.email or san.uri)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CertIdentityField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Read `field` from a DER-encoded X.509 certificate.
+///
+/// `None` when the certificate does not carry the field, the value is empty after
+/// trimming, or the DER is malformed.
+pub fn certificate_identity(der: &[u8], field: CertIdentityField) -> Option<String> {
+    let tbs = tbs_certificate(der)?;
+    let value = match field.san_tag() {
+        None => common_name(tbs.subject)?,
+        Some(tag) => subject_alt_name(tbs.extensions?, tag)?,
+    };
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// End of the validity period (notAfter) of a DER-encoded X.509 certificate.
+///
+/// `None` when the DER is malformed.
+pub fn certificate_not_after(der: &[u8]) -> Option<DateTime<Utc>> {
+    let tbs = tbs_certificate(der)?;
+    let (_not_before_tag, _not_before, rest) = read_tlv(tbs.validity)?;
+    let (tag, content, _) = read_tlv(rest)?;
+    decode_time(tag, content)
+}
+
+/// The parts of TBSCertificate the identity and expiry are read from
+struct TbsCertificate<'a> {
+    validity: &'a [u8],
+    subject: &'a [u8],
+    extensions: Option<&'a [u8]>,
+}
+
+fn tbs_certificate(der: &[u8]) -> Option<TbsCertificate<'_>> {
+    let (certificate, _) = expect(der, TAG_SEQUENCE)?;
+    let (tbs, _) = expect(certificate, TAG_SEQUENCE)?;
+
+    let mut rest = tbs;
+    if rest.first() == Some(&TAG_VERSION) {
+        rest = read_tlv(rest)?.2;
+    }
+    let (_serial, rest) = expect(rest, TAG_INTEGER)?;
+    let (_signature, rest) = expect(rest, TAG_SEQUENCE)?;
+    let (_issuer, rest) = expect(rest, TAG_SEQUENCE)?;
+    let (validity, rest) = expect(rest, TAG_SEQUENCE)?;
+    let (subject, mut rest) = expect(rest, TAG_SEQUENCE)?;
+    let (_public_key, after_key) = expect(rest, TAG_SEQUENCE)?;
+    rest = after_key;
+
+    // issuerUniqueID [1] and subjectUniqueID [2] may sit before the extensions
+    let mut extensions = None;
+    while !rest.is_empty() {
+        let (tag, content, next) = read_tlv(rest)?;
+        if tag == TAG_EXTENSIONS {
+            extensions = Some(expect(content, TAG_SEQUENCE)?.0);
+        }
+        rest = next;
+    }
+
+    Some(TbsCertificate {
+        validity,
+        subject,
+        extensions,
+    })
+}
+
+/// First commonName of a Name (SEQUENCE OF SET OF AttributeTypeAndValue)
+fn common_name(name: &[u8]) -> Option<String> {
+    let mut rdns = name;
+    while !rdns.is_empty() {
+        let (rdn, next) = expect(rdns, TAG_SET)?;
+        let mut attributes = rdn;
+        while !attributes.is_empty() {
+            let (attribute, next_attribute) = expect(attributes, TAG_SEQUENCE)?;
+            let (oid, value) = expect(attribute, TAG_OID)?;
+            if oid == OID_COMMON_NAME {
+                let (tag, content, _) = read_tlv(value)?;
+                return decode_string(tag, content);
+            }
+            attributes = next_attribute;
+        }
+        rdns = next;
+    }
+    None
+}
+
+/// First GeneralName with `tag` in the subjectAltName extension
+fn subject_alt_name(extensions: &[u8], tag: u8) -> Option<String> {
+    let mut rest = extensions;
+    while !rest.is_empty() {
+        let (extension, next) = expect(rest, TAG_SEQUENCE)?;
+        let (oid, mut fields) = expect(extension, TAG_OID)?;
+        if oid == OID_SUBJECT_ALT_NAME {
+            if fields.first() == Some(&TAG_BOOLEAN) {
+                fields = read_tlv(fields)?.2;
+            }
+            let (value, _) = expect(fields, TAG_OCTET_STRING)?;
+            let (names, _) = expect(value, TAG_SEQUENCE)?;
+
+            let mut names = names;
+            while !names.is_empty() {
+                let (name_tag, content, next_name) = read_tlv(names)?;
+                if name_tag == tag {
+                    return decode_string(TAG_IA5_STRING, content);
+                }
+                names = next_name;
+            }
+            return None;
+        }
+        rest = next;
+    }
+    None
+}
+
+fn decode_string(tag: u8, content: &[u8]) -> Option<String> {
+    match tag {
+        TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING | TAG_TELETEX_STRING => {
+            std::str::from_utf8(content).ok().map(str::to_string)
+        }
+        TAG_BMP_STRING if content.len().is_multiple_of(2) => {
+            let units: Vec<u16> = content
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16(&units).ok()
+        }
+        _ => None,
+    }
+}
+
+/// UTCTime (`YYMMDDHHMMSSZ`, years 1950-2049) or GeneralizedTime (`YYYYMMDDHHMMSSZ`),
+/// the two encodings RFC 5280 allows for certificate validity.
+fn decode_time(tag: u8, content: &[u8]) -> Option<DateTime<Utc>> {
+    let text = std::str::from_utf8(content).ok()?;
+    let full = match tag {
+        TAG_UTC_TIME => {
+            let year: u32 = text.get(..2)?.parse().ok()?;
+            format!("{}{}", if year >= 50 { "19" } else { "20" }, text)
+        }
+        TAG_GENERALIZED_TIME => text.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&full, "%Y%m%d%H%M%SZ")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Read one TLV with the given tag; returns its content and what follows it.
+fn expect(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (found, content, rest) = read_tlv(input)?;
+    (found == tag).then_some((content, rest))
+}
+
+/// Read one DER TLV (single-byte tags, definite lengths up to four bytes).
+fn read_tlv(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let len = if first < 0x80 {
+        usize::from(first)
+    } else {
+        let octets = usize::from(first & 0x7f);
+        if octets == 0 || octets > 4 || rest.len() < octets {
+            return None;
+        }
+        let (len_bytes, after) = rest.split_at(octets);
+        rest = after;
+        len_bytes
+            .iter()
+            .fold(0usize, |len, &byte| (len << 8) | usize::from(byte))
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (content, rest) = rest.split_at(len);
+    Some((tag, content, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_tlv_handles_long_lengths_and_truncation() {
+        let mut long = vec![TAG_OCTET_STRING, 0x81, 200];
+        long.extend_from_slice(&[0; 200]);
+        let (tag, content, rest) = read_tlv(&long).unwrap();
+        assert_eq!((tag, content.len(), rest.len()), (TAG_OCTET_STRING, 200, 0));
+
+        assert!(read_tlv(&[TAG_OCTET_STRING, 0x05, 1, 2]).is_none());
+        assert!(read_tlv(&[TAG_OCTET_STRING, 0x80]).is_none());
+    }
+
+    #[test]
+    fn identity_fields_parse() {
+        assert_eq!("cn".parse(), Ok(CertIdentityField::CommonName));
+        assert_eq!("san.email".parse(), Ok(CertIdentityField::SanEmail));
+        assert!("subject".parse::<CertIdentityField>().is_err());
+    }
+
+    #[test]
+    fn garbage_has_no_identity() {
+        assert_eq!(
+            certificate_identity(b"not a certificate", CertIdentityField::CommonName),
+            None
+        );
+        assert_eq!(certificate_not_after(b"not a certificate"), None);
+    }
+
+    #[test]
+    fn not_after_reads_both_time_encodings() {
+        use chrono::TimeZone;
+
+        // rcgen writes UTCTime before 2050 and GeneralizedTime from then on
+        for (year, month, day) in [(2031, 5, 17), (2051, 1, 2)] {
+            let mut params = rcgen::CertificateParams::new(vec!["localhost".into()]).unwrap();
+            params.not_after = rcgen::date_time_ymd(year, month, day);
+            let key = rcgen::KeyPair::generate().unwrap();
+            let cert = params.self_signed(&key).unwrap();
+            assert_eq!(
+                certificate_not_after(cert.der()),
+                Some(
+                    Utc.with_ymd_and_hms(year, month.into(), day.into(), 0, 0, 0)
+                        .unwrap()
+                )
+            );
+        }
+
+        assert_eq!(
+            decode_time(TAG_UTC_TIME, b"491231235959Z"),
+            Some(Utc.with_ymd_and_hms(2049, 12, 31, 23, 59, 59).unwrap())
+        );
+        assert_eq!(
+            decode_time(TAG_UTC_TIME, b"500101000000Z"),
+            Some(Utc.with_ymd_and_hms(1950, 1, 1, 0, 0, 0).unwrap())
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Client-supplied correlation IDs (`auth.allow_correlation_suffix`).
+//!
+//! SOCKS has no request headers, so the ID rides on the login name: a userpass or
+//! pam.username login of `user<separator>id` authenticates `user` and records `id` on
+//! the session. The suffix never reaches the credential check, ACL or QoS.
+
+use crate::config::AuthConfig;
+use crate::utils::error::{Result, RustSocksError};
+
+/// Longest accepted correlation ID, in bytes.
+pub const MAX_CORRELATION_ID_LEN: usize = 64;
+
+pub(crate) struct CorrelationSuffix {
+    separator: String,
+}
+
+impl CorrelationSuffix {
+    /// `None` when `auth.allow_correlation_suffix` is off.
+    pub(crate) fn new(config: &AuthConfig) -> Result<Option<Self>> {
+        if !config.allow_correlation_suffix {
+            return Ok(None);
+        }
+        if config.correlation_separator.is_empty() {
+            return Err(RustSocksError::Config(
+                "auth.correlation_separator cannot be empty".to_string(),
+            ));
+        }
+
+        Ok(Some(Self {
+            separator: config.correlation_separator.clone(),
+        }))
+    }
+
+    /// Split a login name at the last separator into the name to authenticate and
+    /// the raw suffix.
+    pub(crate) fn split<'a>(&self, login: &'a str) -> (&'a str, Option<&'a str>) {
+        match login.rsplit_once(self.separator.as_str()) {
+            Some((name, id)) => (name, Some(id)),
+            None => (login, None),
+        }
+    }
+}
+
+/// Check a correlation ID: 1 to [`MAX_CORRELATION_ID_LEN`] ASCII letters, digits,
+/// `-`, `_`, `.` or `:`. The error never echoes the ID itself.
+pub fn validate_correlation_id(id: &str) -> std::result::Result<(), String> {
+    if id.is_empty() {
+        return Err("correlation ID is empty".to_string());
+    }
+    if id.len() > MAX_CORRELATION_ID_LEN {
+        return Err(format!(
+            "correlation ID is {} bytes, longer than {}",
+            id.len(),
+            MAX_CORRELATION_ID_LEN
+        ));
+    }
+    if let Some(c) = id
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')))
+    {
+        return Err(format!(
+            "correlation ID contains an invalid character {:?}",
+            c
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suffix(separator: &str) -> CorrelationSuffix {
+        let config = AuthConfig {
+            allow_correlation_suffix: true,
+            correlation_separator: separator.to_string(),
+            ..AuthConfig::default()
+        };
+        CorrelationSuffix::new(&config).unwrap().expect("enabled")
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(CorrelationSuffix::new(&AuthConfig::default())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn splits_at_last_separator() {
+        let hash = suffix("#");
+        assert_eq!(hash.split("alice#wf-12345"), ("alice", Some("wf-12345")));
+        assert_eq!(hash.split("a#b#wf-1"), ("a#b", Some("wf-1")));
+        assert_eq!(hash.split("alice"), ("alice", None));
+        assert_eq!(hash.split("alice#"), ("alice", Some("")));
+        assert_eq!(suffix("++").split("alice++wf"), ("alice", Some("wf")));
+    }
+
+    #[test]
+    fn validates_charset_and_length() {
+        assert!(validate_correlation_id("wf-12345").is_ok());
+        assert!(validate_correlation_id("run_7.step:3").is_ok());
+        assert!(validate_correlation_id(&"a".repeat(MAX_CORRELATION_ID_LEN)).is_ok());
+
+        assert!(validate_correlation_id("").is_err());
+        assert!(validate_correlation_id(&"a".repeat(MAX_CORRELATION_ID_LEN + 1)).is_err());
+        assert!(validate_correlation_id("wf 1").is_err());
+        assert!(validate_correlation_id("wf\n1").is_err());
+        assert!(validate_correlation_id("wf-é").is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Service accounts acting on behalf of other users (`auth.impersonation`).
+//!
+//! A login name of `principal<separator>target` authenticates `principal` with the
+//! supplied password and, if the principal is allowed to impersonate and the target
+//! matches the allowed pattern, attributes the session to `target`.
+
+use crate::config::ImpersonationSettings;
+use crate::utils::error::{Result, RustSocksError};
+use regex::Regex;
+use std::collections::HashSet;
+
+pub(crate) struct Impersonation {
+    principals: HashSet<String>,
+    target_pattern: Regex,
+    separator: String,
+}
+
+impl Impersonation {
+    /// `None` when no principal is allowed to impersonate.
+    pub(crate) fn new(settings: &ImpersonationSettings) -> Result<Option<Self>> {
+        if settings.allowed_principals.is_empty() {
+            return Ok(None);
+        }
+        if settings.separator.is_empty() {
+            return Err(RustSocksError::Config(
+                "auth.impersonation.separator cannot be empty".to_string(),
+            ));
+        }
+
+        Ok(Some(Self {
+            principals: settings.allowed_principals.iter().cloned().collect(),
+            target_pattern: glob_to_regex(&settings.allowed_target_pattern)?,
+            separator: settings.separator.clone(),
+        }))
+    }
+
+    /// Split a login name into the principal to authenticate and the requested target.
+    pub(crate) fn split<'a>(&self, login: &'a str) -> (&'a str, Option<&'a str>) {
+        match login.split_once(self.separator.as_str()) {
+            Some((principal, target)) => (principal, Some(target)),
+            None => (login, None),
+        }
+    }
+
+    /// Check that an authenticated `principal` may act as `target`.
+    pub(crate) fn authorize(&self, principal: &str, target: &str) -> Result<()> {
+        if !self.principals.contains(principal) {
+            return Err(RustSocksError::ImpersonationDenied(format!(
+                "{} is not allowed to impersonate",
+                principal
+            )));
+        }
+        if target.is_empty() || !self.target_pattern.is_match(target) {
+            return Err(RustSocksError::ImpersonationDenied(format!(
+                "{} may not act as {:?}",
+                principal, target
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn glob_to_regex(pattern: &str) -> Result<Regex> {
+    let body = pattern
+        .split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(".*");
+    Regex::new(&format!("^{}$", body)).map_err(|e| {
+        RustSocksError::Config(format!(
+            "Invalid auth.impersonation.allowed_target_pattern: {}",
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(pattern: &str) -> ImpersonationSettings {
+        ImpersonationSettings {
+            allowed_principals: vec!["svc".to_string()],
+            allowed_target_pattern: pattern.to_string(),
+            separator: ":".to_string(),
+        }
+    }
+
+    #[test]
+    fn disabled_without_principals() {
+        assert!(Impersonation::new(&ImpersonationSettings::default())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn splits_on_first_separator() {
+        let imp = Impersonation::new(&settings("*")).unwrap().unwrap();
+        assert_eq!(imp.split("svc:alice"), ("svc", Some("alice")));
+        assert_eq!(imp.split("svc:a:b"), ("svc", Some("a:b")));
+        assert_eq!(imp.split("alice"), ("alice", None));
+    }
+
+    #[test]
+    fn target_pattern_is_anchored() {
+        let imp = Impersonation::new(&settings("app-*")).unwrap().unwrap();
+        assert!(imp.authorize("svc", "app-alice").is_ok());
+        assert!(imp.authorize("svc", "alice").is_err());
+        assert!(imp.authorize("svc", "xapp-alice").is_err());
+        assert!(imp.authorize("svc", "").is_err());
+        assert!(matches!(
+            imp.authorize("bob", "app-alice"),
+            Err(RustSocksError::ImpersonationDenied(_))
+        ));
+    }
+
+    #[test]
+    fn pattern_metacharacters_are_literal() {
+        let imp = Impersonation::new(&settings("a.b")).unwrap().unwrap();
+        assert!(imp.authorize("svc", "a.b").is_ok());
+        assert!(imp.authorize("svc", "axb").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! `ldap`: the password is checked by a simple bind as the user.
+//!
+//! The bind DN comes from `auth.ldap.bind_dn_template`. With a `group_search_base`
+//! the user's groups are searched for on the same connection, still bound as the
+//! user; otherwise they are looked up from the system like for the other backends.
+//! Connecting, binding and the search share one `timeout_ms`: a directory that is
+//! down or slow fails the login instead of holding the handshake.
+
+use super::backend::{AuthOutcome, AuthRequest, Authenticator, LoginCredentials};
+use crate::config::LdapSettings;
+use crate::protocol::AuthMethod;
+use crate::utils::error::{Result, RustSocksError};
+use futures::future::BoxFuture;
+use ldap3::{
+    dn_escape, ldap_escape, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry,
+};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Result code of a bind with a wrong DN or password (RFC 4511)
+const INVALID_CREDENTIALS: u32 = 49;
+
+pub(super) struct LdapAuthenticator {
+    settings: LdapSettings,
+    timeout: Duration,
+}
+
+impl LdapAuthenticator {
+    pub(super) fn new(settings: &LdapSettings) -> Result<Self> {
+        settings.validate()?;
+        Ok(Self {
+            settings: settings.clone(),
+            timeout: Duration::from_millis(settings.timeout_ms),
+        })
+    }
+
+    fn bind_dn(&self, username: &str) -> String {
+        self.settings
+            .bind_dn_template
+            .replace("{username}", &dn_escape(username))
+    }
+
+    fn group_filter(&self, dn: &str, username: &str) -> String {
+        self.settings
+            .group_filter
+            .replace("{dn}", &ldap_escape(dn))
+            .replace("{username}", &ldap_escape(username))
+    }
+
+    /// Bind as the user and collect their groups when a search base is set
+    async fn login(
+        &self,
+        dn: &str,
+        login: &LoginCredentials,
+    ) -> std::result::Result<Option<Vec<String>>, LdapError> {
+        let conn_settings = LdapConnSettings::new()
+            .set_conn_timeout(self.timeout)
+            .set_starttls(self.settings.starttls);
+        let (conn, mut ldap) =
+            LdapConnAsync::with_settings(conn_settings, &self.settings.url).await?;
+        ldap3::drive!(conn);
+        ldap.with_timeout(self.timeout);
+
+        ldap.simple_bind(dn, &login.password).await?.success()?;
+
+        let groups = match &self.settings.group_search_base {
+            Some(base) => {
+                let filter = self.group_filter(dn, &login.username);
+                let attribute = self.settings.group_attribute.as_str();
+                let (entries, _) = ldap
+                    .search(base, Scope::Subtree, &filter, vec![attribute])
+                    .await?
+                    .success()?;
+                let groups = entries
+                    .into_iter()
+                    .flat_map(|entry| {
+                        SearchEntry::construct(entry)
+                            .attrs
+                            .remove(attribute)
+                            .unwrap_or_default()
+                    })
+                    .collect::<Vec<_>>();
+                debug!(
+                    user = %login.username,
+                    group_count = groups.len(),
+                    "Retrieved user groups from LDAP"
+                );
+                Some(groups)
+            }
+            None => None,
+        };
+
+        let _ = ldap.unbind().await;
+        Ok(groups)
+    }
+}
+
+impl Authenticator for LdapAuthenticator {
+    fn name(&self) -> &'static str {
+        "ldap"
+    }
+
+    fn method(&self) -> AuthMethod {
+        AuthMethod::UserPass
+    }
+
+    fn authenticate<'a>(&'a self, request: AuthRequest) -> BoxFuture<'a, Result<AuthOutcome>> {
+        Box::pin(async move {
+            let login = request.credentials.ok_or_else(|| {
+                RustSocksError::AuthFailed("ldap needs a username and password".to_string())
+            })?;
+            // An empty password would be an unauthenticated bind, which servers accept
+            if login.username.is_empty() || login.password.is_empty() {
+                return Err(RustSocksError::AuthFailed(format!(
+                    "Empty LDAP credentials for user: {}",
+                    login.username
+                )));
+            }
+
+            let dn = self.bind_dn(&login.username);
+            let groups = match tokio::time::timeout(self.timeout, self.login(&dn, &login)).await {
+                Ok(Ok(groups)) => groups,
+                Ok(Err(LdapError::LdapResult { result })) if result.rc == INVALID_CREDENTIALS => {
+                    return Err(RustSocksError::AuthFailed(format!(
+                        "Invalid credentials for user: {}",
+                        login.username
+                    )));
+                }
+                Ok(Err(e)) => {
+                    warn!(url = %self.settings.url, dn = %dn, error = %e, "LDAP login failed");
+                    return Err(RustSocksError::AuthFailed(format!(
+                        "LDAP login failed: {}",
+                        e
+                    )));
+                }
+                Err(_) => {
+                    warn!(
+                        url = %self.settings.url,
+                        timeout_ms = self.settings.timeout_ms,
+                        "LDAP server did not answer in time"
+                    );
+                    return Err(RustSocksError::AuthFailed(format!(
+                        "LDAP server did not answer within {} ms",
+                        self.settings.timeout_ms
+                    )));
+                }
+            };
+
+            Ok(AuthOutcome::User {
+                username: login.username,
+                groups,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use zeroize::Zeroizing;
+
+    fn settings(url: &str) -> LdapSettings {
+        LdapSettings {
+            url: url.to_string(),
+            bind_dn_template: "uid={username},ou=people,dc=example,dc=com".to_string(),
+            timeout_ms: 300,
+            ..LdapSettings::default()
+        }
+    }
+
+    fn request(username: &str, password: &str) -> AuthRequest {
+        AuthRequest {
+            client_ip: "127.0.0.1".parse().unwrap(),
+            credentials: Some(LoginCredentials {
+                username: username.to_string(),
+                password: Zeroizing::new(password.to_string()),
+            }),
+        }
+    }
+
+    #[test]
+    fn login_names_are_escaped() {
+        let auth = LdapAuthenticator::new(&settings("ldap://127.0.0.1:389")).unwrap();
+        assert_eq!(
+            auth.bind_dn("alice,ou=admins"),
+            "uid=alice\\2cou\\3dadmins,ou=people,dc=example,dc=com"
+        );
+        assert_eq!(
+            auth.group_filter("uid=alice,dc=example", "al*ce"),
+            "(member=uid=alice,dc=example)"
+        );
+
+        let mut by_name = settings("ldap://127.0.0.1:389");
+        by_name.group_filter = "(memberUid={username})".to_string();
+        let auth = LdapAuthenticator::new(&by_name).unwrap();
+        assert_eq!(auth.group_filter("", "al*ce)"), "(memberUid=al\\2ace\\29)");
+    }
+
+    #[tokio::test]
+    async fn silent_server_fails_the_login_at_the_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        // Accept and never answer
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let auth = LdapAuthenticator::new(&settings(&url)).unwrap();
+        let started = std::time::Instant::now();
+        let err = auth
+            .authenticate(request("alice", "secret"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RustSocksError::AuthFailed(_)), "{err:?}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_and_empty_password_fail() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let auth = LdapAuthenticator::new(&settings(&format!("ldap://127.0.0.1:{port}"))).unwrap();
+
+        let err = auth
+            .authenticate(request("alice", "secret"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RustSocksError::AuthFailed(_)), "{err:?}");
+        let err = auth.authenticate(request("alice", "")).await.unwrap_err();
+        assert!(matches!(err, RustSocksError::AuthFailed(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,23 @@
+use lazy_static::lazy_static;
+use prometheus::{register_histogram, register_int_counter, Histogram, HistogramOpts, IntCounter};
+
+lazy_static! {
+    pub static ref ADDRESS_CACHE_HITS: IntCounter = register_int_counter!(
+        "rustsocks_pam_address_cache_hits_total",
+        "pam.address checks answered from the per-client verdict cache"
+    )
+    .expect("register rustsocks_pam_address_cache_hits_total counter");
+    pub static ref PAM_ADDRESS_INVOCATIONS: IntCounter = register_int_counter!(
+        "rustsocks_pam_address_invocations_total",
+        "pam.address checks that invoked the PAM stack"
+    )
+    .expect("register rustsocks_pam_address_invocations_total counter");
+    pub static ref PAM_ADDRESS_LATENCY: Histogram = register_histogram!(HistogramOpts::new(
+        "rustsocks_pam_address_latency_seconds",
+        "Time spent in the PAM stack per pam.address check"
+    )
+    .buckets(vec![
+        0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5
+    ]))
+    .expect("register rustsocks_pam_address_latency_seconds histogram");
+}
@@ -1,242 +1,494 @@
+mod address_gate;
+mod backend;
+mod bans;
+mod certificate;
+mod correlation;
 mod groups;
 #[cfg(feature = "gssapi")]
 mod gssapi;
+mod impersonation;
+#[cfg(feature = "ldap")]
+mod ldap;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod pam;
+mod password;
+mod users_file;
 
+pub(crate) use self::address_gate::parse_networks;
+pub use self::address_gate::{
+    AddressAuthError, AddressAuthenticator, AddressGate, AddressGateStats,
+};
+pub use self::backend::{
+    AuthOutcome, AuthRequest, Authenticator, LoginCredentials, NoAuthenticator,
+};
+pub use self::bans::{UserBan, UserBans};
+pub use self::certificate::{certificate_identity, certificate_not_after, CertIdentityField};
+use self::correlation::CorrelationSuffix;
+pub use self::correlation::{validate_correlation_id, MAX_CORRELATION_ID_LEN};
 #[cfg(feature = "gssapi")]
 use self::gssapi::{GssApiAuthError, GssApiAuthenticator};
+use self::impersonation::Impersonation;
+#[cfg(feature = "ldap")]
+use self::ldap::LdapAuthenticator;
 use self::pam::{PamAuthError, PamAuthenticator, PamMethod};
-use crate::config::AuthConfig;
+pub use self::password::{hash_params, hash_password, PasswordHash, HASH_PREFIX};
+pub use self::users_file::{load_users_file, parse_users_file, UsersFileWatcher};
+use crate::config::{AuthConfig, PasswordHashSettings, User};
 use crate::protocol::{parse_userpass_auth, send_auth_response, AuthMethod};
 use crate::utils::error::{Result, RustSocksError};
+use crate::utils::log_sampling;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use futures::future::BoxFuture;
 pub use groups::get_user_groups;
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{debug, info, warn};
+use zeroize::{Zeroize, Zeroizing};
 
 pub struct AuthManager {
     client_backend: AuthBackend,
-    socks_backend: AuthBackend,
+    /// SOCKS backends, most preferred first; at most one per method byte
+    socks_backends: Vec<AuthBackend>,
+    /// The userpass backend, kept to swap its users on reload
+    userpass: Option<UserPassAuthenticator>,
+    /// pam.address gate shared by both stages
+    address_gate: Option<Arc<AddressGate>>,
+    impersonation: Option<Impersonation>,
+    correlation: Option<CorrelationSuffix>,
+    /// Users refused at login until their ban runs out
+    bans: Arc<UserBans>,
+}
+
+/// Outcome of a successful SOCKS-level authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    /// Principal whose credentials were verified
+    pub authenticated_user: String,
+    /// Identity used for ACL, QoS, quotas and statistics
+    pub user: String,
+    /// Groups of `user`
+    pub groups: Vec<String>,
+    /// Validated correlation ID stripped from the login name
+    pub correlation_id: Option<String>,
+}
+
+impl Identity {
+    /// Whether the principal is acting on behalf of another user
+    pub fn is_impersonated(&self) -> bool {
+        self.authenticated_user != self.user
+    }
 }
 
 enum AuthBackend {
-    None,
-    UserPass(UserPassAuthenticator),
-    PamAddress(PamAuthenticator),
-    PamUsername(PamAuthenticator),
+    /// Decides from the client address or the username/password login alone
+    Checked(Arc<dyn Authenticator>),
+    /// Exchanges its own tokens with the client
     #[cfg(feature = "gssapi")]
     Gssapi(GssApiAuthenticator),
+    /// Client stage only: the name comes from the verified TLS client certificate
+    TlsCert(CertIdentityField),
+}
+
+impl AuthBackend {
+    /// SOCKS5 method byte this backend answers
+    fn method(&self) -> AuthMethod {
+        match self {
+            AuthBackend::Checked(backend) => backend.method(),
+            #[cfg(feature = "gssapi")]
+            AuthBackend::Gssapi(_) => AuthMethod::Gssapi,
+            AuthBackend::TlsCert(_) => AuthMethod::NoAuth,
+        }
+    }
+}
+
+/// Backends both auth stages share, created by whichever stage needs them first
+#[derive(Default)]
+struct SharedBackends {
+    address_gate: Option<Arc<AddressGate>>,
+    userpass: Option<UserPassAuthenticator>,
 }
 
+/// Holds only password hashes; see [`password`] for the format.
+#[derive(Clone)]
 struct UserPassAuthenticator {
-    users: HashMap<String, String>,
+    /// Swapped as a whole when `auth.users` is reloaded
+    credentials: Arc<RwLock<Arc<Credentials>>>,
+}
+
+struct Credentials {
+    users: HashMap<String, PasswordHash>,
+    /// Verified against for unknown users so they cost as much as a wrong password
+    decoy: PasswordHash,
 }
 
+/// `pam.username`: the login is checked by the PAM username service.
+struct PamUsernameAuthenticator(PamAuthenticator);
+
 impl AuthManager {
     pub fn new(config: &AuthConfig) -> Result<Self> {
-        let client_backend = Self::build_backend(&config.client_method, config)?;
-        let socks_backend = Self::build_backend(&config.socks_method, config)?;
+        Self::build(config, SharedBackends::default())
+    }
+
+    /// Build an auth manager whose pam.address checks go through `backend` instead of PAM.
+    pub fn with_address_authenticator(
+        config: &AuthConfig,
+        backend: Arc<dyn AddressAuthenticator>,
+    ) -> Result<Self> {
+        let shared = SharedBackends {
+            address_gate: Some(Arc::new(AddressGate::new(config, backend)?)),
+            userpass: None,
+        };
+        Self::build(config, shared)
+    }
+
+    fn build(config: &AuthConfig, mut shared: SharedBackends) -> Result<Self> {
+        let client_backend = Self::build_backend(&config.client_method, config, &mut shared)?;
+        let socks_backends = Self::build_socks_backends(config, &mut shared)?;
+        let uses_gate = std::iter::once(&client_backend)
+            .chain(&socks_backends)
+            .any(|backend| {
+                matches!(backend, AuthBackend::Checked(backend) if backend.name() == "pam.address")
+            });
 
         Ok(Self {
             client_backend,
-            socks_backend,
+            socks_backends,
+            userpass: shared.userpass,
+            address_gate: shared.address_gate.filter(|_| uses_gate),
+            impersonation: Impersonation::new(&config.impersonation)?,
+            correlation: CorrelationSuffix::new(config)?,
+            bans: Arc::new(UserBans::new()),
         })
     }
 
-    fn build_backend(method: &str, config: &AuthConfig) -> Result<AuthBackend> {
-        match method {
-            "none" => Ok(AuthBackend::None),
+    /// Replace the userpass users and hashing cost from a reloaded config, reading
+    /// `auth.users_file` again when it is set. Logins already in progress finish
+    /// against the old list. Returns `false` when the SOCKS stage does not use userpass.
+    ///
+    /// Hashes every plaintext password, so call it off the async runtime.
+    pub fn reload_users(&self, config: &AuthConfig) -> Result<bool> {
+        if self.userpass.is_none() {
+            return Ok(false);
+        }
+        let users = configured_users(config)?;
+        self.replace_users(&users, &config.password_hashing)?;
+        info!(users = users.len(), "Userpass users reloaded");
+        Ok(true)
+    }
+
+    /// Swap the userpass users for `users` as a whole; on error the current users
+    /// stay. Returns `false` when the SOCKS stage does not use userpass.
+    ///
+    /// Hashes every plaintext password, so call it off the async runtime.
+    pub fn replace_users(&self, users: &[User], settings: &PasswordHashSettings) -> Result<bool> {
+        let Some(auth) = &self.userpass else {
+            return Ok(false);
+        };
+        auth.replace(users, settings)?;
+        Ok(true)
+    }
+
+    /// Shared pam.address gate, when either auth stage uses pam.address
+    pub fn address_gate(&self) -> Option<Arc<AddressGate>> {
+        self.address_gate.clone()
+    }
+
+    /// Temporary user bans consulted by every login that names a user
+    pub fn user_bans(&self) -> Arc<UserBans> {
+        self.bans.clone()
+    }
+
+    fn build_socks_backends(
+        config: &AuthConfig,
+        shared: &mut SharedBackends,
+    ) -> Result<Vec<AuthBackend>> {
+        let mut backends: Vec<AuthBackend> = Vec::new();
+        for method in config.socks_methods() {
+            let backend = Self::build_backend(method, config, shared)?;
+            if matches!(backend, AuthBackend::TlsCert(_)) {
+                return Err(RustSocksError::Config(format!(
+                    "{} is only supported as auth.client_method",
+                    method
+                )));
+            }
+            if backends
+                .iter()
+                .any(|existing| existing.method() == backend.method())
+            {
+                return Err(RustSocksError::Config(format!(
+                    "auth.socks_method_preference: {} answers the same SOCKS method as an \
+                     earlier entry",
+                    method
+                )));
+            }
+            backends.push(backend);
+        }
+        Ok(backends)
+    }
+
+    fn build_backend(
+        method: &str,
+        config: &AuthConfig,
+        shared: &mut SharedBackends,
+    ) -> Result<AuthBackend> {
+        let backend: Arc<dyn Authenticator> = match method {
+            "none" => Arc::new(NoAuthenticator),
             "userpass" => {
-                let mut users = HashMap::new();
-                for user in &config.users {
-                    users.insert(user.username.clone(), user.password.clone());
-                }
-                Ok(AuthBackend::UserPass(UserPassAuthenticator { users }))
+                let auth = match &shared.userpass {
+                    Some(auth) => auth.clone(),
+                    None => UserPassAuthenticator::new(
+                        &configured_users(config)?,
+                        &config.password_hashing,
+                    )?,
+                };
+                shared.userpass = Some(auth.clone());
+                Arc::new(auth)
             }
             "pam.address" => {
+                // Client and SOCKS stages share one gate so they share one cache
+                if let Some(gate) = &shared.address_gate {
+                    return Ok(AuthBackend::Checked(gate.clone()));
+                }
                 let authenticator = PamAuthenticator::new(PamMethod::Address, &config.pam)
                     .map_err(map_pam_config_error)?;
-                Ok(AuthBackend::PamAddress(authenticator))
+                let gate = Arc::new(AddressGate::new(config, Arc::new(authenticator))?);
+                shared.address_gate = Some(gate.clone());
+                gate
             }
             "pam.username" => {
                 let authenticator = PamAuthenticator::new(PamMethod::Username, &config.pam)
                     .map_err(map_pam_config_error)?;
-                Ok(AuthBackend::PamUsername(authenticator))
+                Arc::new(PamUsernameAuthenticator(authenticator))
+            }
+            #[cfg(feature = "ldap")]
+            "ldap" => Arc::new(LdapAuthenticator::new(&config.ldap)?),
+            #[cfg(not(feature = "ldap"))]
+            "ldap" => {
+                return Err(RustSocksError::Config(
+                    "ldap authentication requires building with the `ldap` feature".to_string(),
+                ))
             }
             #[cfg(feature = "gssapi")]
             "gssapi" => {
                 let authenticator =
                     GssApiAuthenticator::new(&config.gssapi).map_err(map_gssapi_config_error)?;
-                Ok(AuthBackend::Gssapi(authenticator))
+                return Ok(AuthBackend::Gssapi(authenticator));
             }
-            other => Err(RustSocksError::Config(format!(
-                "Unsupported authentication method: {}",
-                other
-            ))),
-        }
+            "tls.cert" => {
+                return config
+                    .tls_cert_identity
+                    .parse()
+                    .map(AuthBackend::TlsCert)
+                    .map_err(|e| RustSocksError::Config(format!("auth.tls_cert_identity: {}", e)))
+            }
+            other => {
+                return Err(RustSocksError::Config(format!(
+                    "Unsupported authentication method: {}",
+                    other
+                )))
+            }
+        };
+        Ok(AuthBackend::Checked(backend))
     }
 
-    /// Method advertised during SOCKS5 negotiation
+    /// Most preferred SOCKS5 method
     pub fn get_method(&self) -> AuthMethod {
-        match self.socks_backend {
-            AuthBackend::None | AuthBackend::PamAddress(_) => AuthMethod::NoAuth,
-            AuthBackend::UserPass(_) | AuthBackend::PamUsername(_) => AuthMethod::UserPass,
-            #[cfg(feature = "gssapi")]
-            AuthBackend::Gssapi(_) => AuthMethod::Gssapi,
-        }
+        self.socks_backends
+            .first()
+            .map(AuthBackend::method)
+            .unwrap_or(AuthMethod::NoAcceptable)
     }
 
     /// Check if a specific auth method is supported
     pub fn supports(&self, method: AuthMethod) -> bool {
-        let server_method = self.get_method();
-        if server_method == method {
-            return true;
-        }
+        self.socks_backends
+            .iter()
+            .any(|backend| backend.method() == method)
+    }
 
-        matches!(method, AuthMethod::NoAuth)
-            && matches!(
-                self.socks_backend,
-                AuthBackend::None | AuthBackend::PamAddress(_)
-            )
+    /// Method to answer a client greeting with: the most preferred server method the
+    /// client offered, whatever order the client listed them in. `NoAcceptable` (0xFF)
+    /// only when the two sets do not overlap.
+    pub fn select_method(&self, offered: &[AuthMethod]) -> AuthMethod {
+        self.socks_backends
+            .iter()
+            .map(AuthBackend::method)
+            .find(|method| offered.contains(method))
+            .unwrap_or(AuthMethod::NoAcceptable)
     }
 
     /// Perform client-level authentication (before SOCKS negotiation)
     pub async fn authenticate_client(&self, client_ip: IpAddr) -> Result<()> {
         match &self.client_backend {
-            AuthBackend::None => Ok(()),
-            AuthBackend::PamAddress(pam) => pam
-                .authenticate_address(client_ip)
+            AuthBackend::Checked(backend) if backend.method() == AuthMethod::NoAuth => backend
+                .authenticate(AuthRequest {
+                    client_ip,
+                    credentials: None,
+                })
                 .await
-                .map_err(map_pam_runtime_error),
-            #[cfg(feature = "gssapi")]
-            AuthBackend::UserPass(_) | AuthBackend::PamUsername(_) | AuthBackend::Gssapi(_) => {
-                Err(RustSocksError::Config(
-                    "Invalid client auth configuration: only none or pam.address are supported"
-                        .to_string(),
-                ))
-            }
-            #[cfg(not(feature = "gssapi"))]
-            AuthBackend::UserPass(_) | AuthBackend::PamUsername(_) => Err(RustSocksError::Config(
+                .map(|_| ()),
+            AuthBackend::TlsCert(_) => Err(RustSocksError::AuthFailed(
+                "tls.cert client authentication needs a TLS client certificate".to_string(),
+            )),
+            _ => Err(RustSocksError::Config(
                 "Invalid client auth configuration: only none or pam.address are supported"
                     .to_string(),
             )),
         }
     }
 
+    /// Whether the client stage takes the session user from the TLS client
+    /// certificate (`auth.client_method = "tls.cert"`)
+    pub fn uses_client_certificates(&self) -> bool {
+        matches!(self.client_backend, AuthBackend::TlsCert(_))
+    }
+
+    /// Client-level authentication from a verified TLS client certificate.
+    ///
+    /// `certificate` is the DER of the leaf certificate the TLS acceptor verified.
+    /// The configured field becomes the user, and its groups are looked up exactly
+    /// as for a userpass login. Fails when the backend is not `tls.cert`, no
+    /// certificate was presented, or the certificate lacks the field.
+    pub fn authenticate_certificate(&self, certificate: Option<&[u8]>) -> Result<Identity> {
+        let AuthBackend::TlsCert(field) = &self.client_backend else {
+            return Err(RustSocksError::Config(
+                "Client certificate authentication requires auth.client_method = \"tls.cert\""
+                    .to_string(),
+            ));
+        };
+        let certificate = certificate.ok_or_else(|| {
+            RustSocksError::AuthFailed("No TLS client certificate presented".to_string())
+        })?;
+        let user = certificate_identity(certificate, *field).ok_or_else(|| {
+            warn!(field = %field, "TLS client certificate has no usable identity");
+            RustSocksError::AuthFailed(format!("TLS client certificate has no {} field", field))
+        })?;
+
+        let groups = get_user_groups(&user).unwrap_or_else(|e| {
+            warn!(
+                user = %user,
+                error = %e,
+                "Failed to retrieve user groups from system, using empty list"
+            );
+            Vec::new()
+        });
+        self.check_bans(&user, &user)?;
+        log_sampling::note_user(&user);
+        info!(user = %user, field = %field, "TLS client certificate authentication successful");
+
+        Ok(Identity {
+            authenticated_user: user.clone(),
+            user,
+            groups,
+            correlation_id: None,
+        })
+    }
+
     /// Perform SOCKS-level authentication
     ///
     /// Returns:
     /// - `Ok(None)` for no-auth methods
-    /// - `Ok(Some((username, groups)))` for authenticated users with their LDAP groups
+    /// - `Ok(Some(identity))` for authenticated users with their groups, from the
+    ///   backend when it supplies them and from the system otherwise
+    ///
+    /// With `auth.impersonation`, a username/password login of `principal:target` verifies
+    /// `principal` and returns `target` as the effective user; a principal that is
+    /// not allowed to do so fails with [`RustSocksError::ImpersonationDenied`].
+    ///
+    /// With `auth.allow_correlation_suffix`, a trailing `#id` is stripped first and
+    /// returned as [`Identity::correlation_id`]; an invalid ID is logged and dropped
+    /// without failing the login.
     pub async fn authenticate<S>(
         &self,
         stream: &mut S,
         method: AuthMethod,
         client_ip: IpAddr,
-    ) -> Result<Option<(String, Vec<String>)>>
+    ) -> Result<Option<Identity>>
     where
         S: AsyncRead + AsyncWrite + Unpin + Send,
     {
-        match (&self.socks_backend, method) {
-            (AuthBackend::None, AuthMethod::NoAuth) => {
-                debug!("No authentication required");
+        let backend = self
+            .socks_backends
+            .iter()
+            .find(|backend| backend.method() == method);
+        match (backend, method) {
+            (Some(AuthBackend::Checked(backend)), AuthMethod::NoAuth) => {
+                backend
+                    .authenticate(AuthRequest {
+                        client_ip,
+                        credentials: None,
+                    })
+                    .await?;
+                debug!(backend = backend.name(), "No-auth method accepted client");
                 Ok(None)
             }
-            (AuthBackend::PamAddress(pam), AuthMethod::NoAuth) => {
-                pam.authenticate_address(client_ip)
-                    .await
-                    .map_err(map_pam_runtime_error)?;
-                debug!("PAM address authentication successful");
-                Ok(None)
-            }
-            (AuthBackend::UserPass(auth), AuthMethod::UserPass) => {
-                debug!("Performing username/password authentication");
-
-                let (username, password) = parse_userpass_auth(stream).await?;
-                let is_valid = auth.authenticate(&username, &password);
-                send_auth_response(stream, is_valid).await?;
-
-                if is_valid {
-                    info!(user = %username, "User/pass authentication successful");
-
-                    // Retrieve user groups from system (LDAP via NSS/SSSD)
-                    let groups = get_user_groups(&username).unwrap_or_else(|e| {
-                        warn!(
-                            user = %username,
-                            error = %e,
-                            "Failed to retrieve user groups from system, using empty list"
-                        );
-                        Vec::new()
-                    });
-
-                    debug!(
-                        user = %username,
-                        group_count = groups.len(),
-                        groups = ?groups,
-                        "Retrieved user groups from system"
-                    );
-
-                    Ok(Some((username, groups)))
-                } else {
-                    warn!(user = %username, "User/pass authentication failed");
-                    Err(RustSocksError::AuthFailed(format!(
-                        "Invalid credentials for user: {}",
-                        username
-                    )))
-                }
-            }
-            (AuthBackend::PamUsername(pam), AuthMethod::UserPass) => {
-                debug!("Performing PAM username authentication");
-                let (username, password) = parse_userpass_auth(stream).await?;
-
-                match pam
-                    .authenticate_username(client_ip, &username, &password)
-                    .await
-                {
-                    Ok(()) => {
-                        send_auth_response(stream, true).await?;
-                        info!(user = %username, "PAM authentication successful");
-
-                        // Retrieve user groups from system (LDAP via NSS/SSSD)
-                        let groups = get_user_groups(&username).unwrap_or_else(|e| {
-                            warn!(
-                                user = %username,
-                                error = %e,
-                                "Failed to retrieve user groups from system, using empty list"
-                            );
-                            Vec::new()
-                        });
+            (Some(AuthBackend::Checked(backend)), AuthMethod::UserPass) => {
+                debug!(
+                    backend = backend.name(),
+                    "Performing username/password authentication"
+                );
 
-                        info!(
-                            user = %username,
-                            group_count = groups.len(),
-                            groups = ?groups,
-                            "PAM authentication successful with LDAP groups"
-                        );
+                let (login, password) = parse_userpass_auth(stream).await?;
+                let (login, correlation_id) = self.split_correlation(&login);
+                let (username, target) = self.split_login(login);
+                let request = AuthRequest {
+                    client_ip,
+                    credentials: Some(LoginCredentials {
+                        username: username.to_string(),
+                        password,
+                    }),
+                };
 
-                        Ok(Some((username, groups)))
+                match backend.authenticate(request).await {
+                    Ok(AuthOutcome::User {
+                        username: principal,
+                        groups,
+                    }) => {
+                        log_sampling::note_user(&principal);
+                        info!(user = %principal, backend = backend.name(), "Authentication successful");
+                        self.complete_login(stream, &principal, target, correlation_id, groups)
+                            .await
+                            .map(Some)
+                    }
+                    Ok(AuthOutcome::Anonymous) => {
+                        send_auth_response(stream, false).await?;
+                        warn!(user = %username, backend = backend.name(), "Backend did not name a user");
+                        Err(RustSocksError::AuthFailed(format!(
+                            "{} did not verify user: {}",
+                            backend.name(),
+                            username
+                        )))
                     }
                     Err(e) => {
                         send_auth_response(stream, false).await?;
-                        warn!(user = %username, error = ?e, "PAM authentication failed");
-                        Err(map_pam_runtime_error(e))
+                        warn!(user = %username, backend = backend.name(), error = %e, "Authentication failed");
+                        Err(e)
                     }
                 }
             }
             #[cfg(feature = "gssapi")]
-            (AuthBackend::Gssapi(gssapi), AuthMethod::Gssapi) => {
+            (Some(AuthBackend::Gssapi(gssapi)), AuthMethod::Gssapi) => {
                 debug!("Performing GSS-API authentication");
 
                 match gssapi.authenticate(stream).await {
                     Ok((username, groups)) => {
+                        self.check_bans(&username, &username)?;
+                        log_sampling::note_user(&username);
                         info!(
                             user = %username,
                             group_count = groups.len(),
                             groups = ?groups,
                             "GSS-API authentication successful with groups"
                         );
-                        Ok(Some((username, groups)))
+                        Ok(Some(Identity {
+                            authenticated_user: username.clone(),
+                            user: username,
+                            groups,
+                            correlation_id: None,
+                        }))
                     }
                     Err(e) => {
                         warn!(error = ?e, "GSS-API authentication failed");
@@ -246,8 +498,11 @@ impl AuthManager {
             }
             _ => {
                 warn!(
-                    "Authentication method mismatch: expected {:?}, got {:?}",
-                    self.get_method(),
+                    "Authentication method mismatch: server accepts {:?}, got {:?}",
+                    self.socks_backends
+                        .iter()
+                        .map(AuthBackend::method)
+                        .collect::<Vec<_>>(),
                     method
                 );
                 Err(RustSocksError::AuthFailed(
@@ -258,15 +513,287 @@ impl AuthManager {
     }
 }
 
+impl AuthManager {
+    /// Strip a correlation suffix. An invalid ID is dropped; the login itself goes on
+    /// with the stripped name.
+    fn split_correlation<'a>(&self, login: &'a str) -> (&'a str, Option<String>) {
+        let Some(correlation) = &self.correlation else {
+            return (login, None);
+        };
+        match correlation.split(login) {
+            (name, Some(id)) => match validate_correlation_id(id) {
+                Ok(()) => (name, Some(id.to_string())),
+                Err(e) => {
+                    warn!(user = %name, error = %e, "Ignoring invalid correlation ID");
+                    (name, None)
+                }
+            },
+            (name, None) => (name, None),
+        }
+    }
+
+    /// Refuse a login whose principal or effective user is banned
+    fn check_bans(&self, principal: &str, user: &str) -> Result<()> {
+        for name in [principal, user] {
+            if let Some(ban) = self.bans.get(name) {
+                warn!(
+                    user = %name,
+                    expires_at = %ban.expires_at.to_rfc3339(),
+                    "Login refused: user is banned"
+                );
+                return Err(RustSocksError::AuthFailed(format!(
+                    "User {} is banned until {}",
+                    name,
+                    ban.expires_at.to_rfc3339()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn split_login<'a>(&self, login: &'a str) -> (&'a str, Option<&'a str>) {
+        match &self.impersonation {
+            Some(impersonation) => impersonation.split(login),
+            None => (login, None),
+        }
+    }
+
+    /// Resolve the effective user for a verified principal, answer the client and
+    /// look up the effective user's groups. `backend_groups` are the principal's, so
+    /// they are only used when the principal is not acting on behalf of someone else.
+    async fn complete_login<S>(
+        &self,
+        stream: &mut S,
+        principal: &str,
+        target: Option<&str>,
+        correlation_id: Option<String>,
+        backend_groups: Option<Vec<String>>,
+    ) -> Result<Identity>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let user = match (target, &self.impersonation) {
+            (Some(target), Some(impersonation)) => {
+                if let Err(e) = impersonation.authorize(principal, target) {
+                    send_auth_response(stream, false).await?;
+                    warn!(principal = %principal, target = %target, error = %e, "Impersonation denied");
+                    return Err(e);
+                }
+                log_sampling::note_user(target);
+                info!(principal = %principal, user = %target, "Acting on behalf of user");
+                target
+            }
+            _ => principal,
+        };
+        if let Err(e) = self.check_bans(principal, user) {
+            send_auth_response(stream, false).await?;
+            return Err(e);
+        }
+        send_auth_response(stream, true).await?;
+
+        let groups = match backend_groups.filter(|_| user == principal) {
+            Some(groups) => groups,
+            None => {
+                // Retrieve user groups from system (LDAP via NSS/SSSD)
+                let groups = get_user_groups(user).unwrap_or_else(|e| {
+                    warn!(
+                        user = %user,
+                        error = %e,
+                        "Failed to retrieve user groups from system, using empty list"
+                    );
+                    Vec::new()
+                });
+                debug!(
+                    user = %user,
+                    group_count = groups.len(),
+                    groups = ?groups,
+                    "Retrieved user groups from system"
+                );
+                groups
+            }
+        };
+
+        Ok(Identity {
+            authenticated_user: principal.to_string(),
+            user: user.to_string(),
+            groups,
+            correlation_id,
+        })
+    }
+}
+
 impl UserPassAuthenticator {
-    fn authenticate(&self, username: &str, password: &str) -> bool {
-        self.users
-            .get(username)
-            .map(|stored_password| stored_password == password)
+    fn new(users: &[User], settings: &PasswordHashSettings) -> Result<Self> {
+        Ok(Self {
+            credentials: Arc::new(RwLock::new(Arc::new(Credentials::new(users, settings)?))),
+        })
+    }
+
+    /// Swap in a new user list; nothing changes when a password cannot be hashed
+    fn replace(&self, users: &[User], settings: &PasswordHashSettings) -> Result<()> {
+        let credentials = Arc::new(Credentials::new(users, settings)?);
+        *self.credentials.write().unwrap_or_else(|e| e.into_inner()) = credentials;
+        Ok(())
+    }
+
+    /// Check `password` for `username`, then wipe it.
+    fn verify(&self, username: &str, password: &mut Zeroizing<String>) -> bool {
+        let credentials = self
+            .credentials
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let verified = match credentials.users.get(username) {
+            Some(hash) => hash.verify(password.as_bytes()),
+            None => {
+                credentials.decoy.verify(password.as_bytes());
+                false
+            }
+        };
+        password.zeroize();
+        verified
+    }
+
+    /// [`Self::verify`] on the blocking pool; an argon2 derivation takes milliseconds.
+    async fn check(&self, username: &str, mut password: Zeroizing<String>) -> bool {
+        let auth = self.clone();
+        let username = username.to_string();
+        tokio::task::spawn_blocking(move || auth.verify(&username, &mut password))
+            .await
             .unwrap_or(false)
     }
 }
 
+impl Authenticator for UserPassAuthenticator {
+    fn name(&self) -> &'static str {
+        "userpass"
+    }
+
+    fn method(&self) -> AuthMethod {
+        AuthMethod::UserPass
+    }
+
+    fn authenticate<'a>(&'a self, request: AuthRequest) -> BoxFuture<'a, Result<AuthOutcome>> {
+        Box::pin(async move {
+            let login = require_credentials(request)?;
+            if !self.check(&login.username, login.password).await {
+                return Err(RustSocksError::AuthFailed(format!(
+                    "Invalid credentials for user: {}",
+                    login.username
+                )));
+            }
+            Ok(AuthOutcome::User {
+                username: login.username,
+                groups: None,
+            })
+        })
+    }
+}
+
+impl Authenticator for AddressGate {
+    fn name(&self) -> &'static str {
+        "pam.address"
+    }
+
+    fn method(&self) -> AuthMethod {
+        AuthMethod::NoAuth
+    }
+
+    fn authenticate<'a>(&'a self, request: AuthRequest) -> BoxFuture<'a, Result<AuthOutcome>> {
+        Box::pin(async move {
+            self.check(request.client_ip).await?;
+            Ok(AuthOutcome::Anonymous)
+        })
+    }
+}
+
+impl Authenticator for PamUsernameAuthenticator {
+    fn name(&self) -> &'static str {
+        "pam.username"
+    }
+
+    fn method(&self) -> AuthMethod {
+        AuthMethod::UserPass
+    }
+
+    fn authenticate<'a>(&'a self, request: AuthRequest) -> BoxFuture<'a, Result<AuthOutcome>> {
+        Box::pin(async move {
+            let client_ip = request.client_ip;
+            let login = require_credentials(request)?;
+            self.0
+                .authenticate_username(client_ip, &login.username, &login.password)
+                .await
+                .map_err(map_pam_runtime_error)?;
+            Ok(AuthOutcome::User {
+                username: login.username,
+                groups: None,
+            })
+        })
+    }
+}
+
+/// Userpass users from `auth.users_file` when set, `auth.users` otherwise
+fn configured_users(config: &AuthConfig) -> Result<Vec<User>> {
+    match &config.users_file {
+        Some(path) => load_users_file(path),
+        None => Ok(config.users.clone()),
+    }
+}
+
+/// Username and password of a request to a username/password backend
+fn require_credentials(request: AuthRequest) -> Result<LoginCredentials> {
+    request.credentials.ok_or_else(|| {
+        RustSocksError::AuthFailed("Username/password method called without a login".to_string())
+    })
+}
+
+impl Credentials {
+    fn new(users: &[User], settings: &PasswordHashSettings) -> Result<Self> {
+        let params = hash_params(settings)?;
+
+        let mut hashes = HashMap::with_capacity(users.len());
+        for user in users {
+            let hash = password::user_hash(user, &params).map_err(|e| {
+                RustSocksError::Config(format!("auth.users '{}': {}", user.username, e))
+            })?;
+            if user.has_plaintext_password() {
+                warn!(
+                    user = %user.username,
+                    "auth.users: plaintext passwords are deprecated; replace password with \
+                     the password_hash printed by `rustsocks hash-password`"
+                );
+            }
+            hashes.insert(user.username.clone(), hash);
+        }
+
+        let mut decoy = Zeroizing::new([0u8; 16]);
+        OsRng.fill_bytes(decoy.as_mut());
+
+        Ok(Self {
+            users: hashes,
+            decoy: PasswordHash::new(decoy.as_ref(), &params),
+        })
+    }
+}
+
+impl AddressAuthenticator for PamAuthenticator {
+    fn authenticate<'a>(
+        &'a self,
+        client_ip: IpAddr,
+    ) -> BoxFuture<'a, std::result::Result<(), AddressAuthError>> {
+        Box::pin(async move {
+            self.authenticate_address(client_ip)
+                .await
+                .map_err(|err| match err {
+                    PamAuthError::AuthFailed(msg) => AddressAuthError::Denied(msg),
+                    PamAuthError::Config(msg)
+                    | PamAuthError::System(msg)
+                    | PamAuthError::NotSupported(msg) => AddressAuthError::Unavailable(msg),
+                })
+        })
+    }
+}
+
 fn map_pam_config_error(err: PamAuthError) -> RustSocksError {
     match err {
         PamAuthError::Config(msg) | PamAuthError::System(msg) => RustSocksError::Config(msg),
@@ -312,12 +839,23 @@ mod tests {
         AuthConfig {
             client_method: "none".to_string(),
             socks_method: "userpass".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![User {
                 username: "alice".to_string(),
-                password: "secret123".to_string(),
+                password: "secret123".to_string().into(),
+                password_hash: None,
             }],
+            users_file: None,
             pam: PamSettings::default(),
             gssapi: crate::config::GssApiSettings::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         }
     }
 
@@ -336,4 +874,300 @@ mod tests {
         let auth_manager = AuthManager::new(&config).unwrap();
         assert_eq!(auth_manager.get_method(), AuthMethod::UserPass);
     }
+
+    #[test]
+    fn select_method_follows_the_server_preference() {
+        let mut config = userpass_config();
+        config.socks_method_preference = vec!["userpass".to_string(), "none".to_string()];
+        let auth_manager = AuthManager::new(&config).unwrap();
+
+        // The client's order does not matter, only what it offered
+        for offered in [
+            vec![AuthMethod::NoAuth, AuthMethod::UserPass],
+            vec![AuthMethod::UserPass, AuthMethod::NoAuth],
+            vec![AuthMethod::Gssapi, AuthMethod::UserPass],
+        ] {
+            assert_eq!(auth_manager.select_method(&offered), AuthMethod::UserPass);
+        }
+        assert_eq!(
+            auth_manager.select_method(&[AuthMethod::Gssapi, AuthMethod::NoAuth]),
+            AuthMethod::NoAuth
+        );
+        assert_eq!(
+            auth_manager.select_method(&[AuthMethod::Gssapi, AuthMethod::NoAcceptable]),
+            AuthMethod::NoAcceptable
+        );
+        assert_eq!(auth_manager.get_method(), AuthMethod::UserPass);
+        assert!(auth_manager.supports(AuthMethod::NoAuth));
+    }
+
+    #[test]
+    fn select_method_without_a_preference_uses_socks_method() {
+        let auth_manager = AuthManager::new(&userpass_config()).unwrap();
+        assert_eq!(
+            auth_manager.select_method(&[AuthMethod::NoAuth, AuthMethod::UserPass]),
+            AuthMethod::UserPass
+        );
+        assert_eq!(
+            auth_manager.select_method(&[AuthMethod::NoAuth]),
+            AuthMethod::NoAcceptable
+        );
+        assert!(!auth_manager.supports(AuthMethod::NoAuth));
+    }
+
+    #[test]
+    fn preference_rejects_two_backends_for_one_method() {
+        let mut config = userpass_config();
+        config.socks_method_preference = vec![
+            "none".to_string(),
+            "userpass".to_string(),
+            "none".to_string(),
+        ];
+        assert!(AuthManager::new(&config).is_err());
+    }
+
+    fn fast_hashing() -> PasswordHashSettings {
+        PasswordHashSettings {
+            memory_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn userpass_verifies_plaintext_and_prehashed_entries() {
+        let settings = fast_hashing();
+        let stored = hash_password("hunter2", &hash_params(&settings).unwrap());
+        let users = vec![
+            User {
+                username: "alice".to_string(),
+                password: "secret123".to_string().into(),
+                password_hash: None,
+            },
+            User {
+                username: "bob".to_string(),
+                password: stored.into(),
+                password_hash: None,
+            },
+        ];
+        let auth = UserPassAuthenticator::new(&users, &settings).unwrap();
+
+        let check = |user: &str, password: &str| {
+            auth.verify(user, &mut Zeroizing::new(password.to_string()))
+        };
+        assert!(check("alice", "secret123"));
+        assert!(!check("alice", "secret124"));
+        assert!(!check("alice", "hunter2"));
+        assert!(check("bob", "hunter2"));
+        assert!(!check("bob", "Hunter2"));
+        assert!(!check("carol", "secret123"));
+    }
+
+    #[test]
+    fn userpass_verifies_password_hash_entries() {
+        let settings = fast_hashing();
+        let stored = hash_password("hunter2", &hash_params(&settings).unwrap());
+        let mut users = vec![User {
+            username: "bob".to_string(),
+            password: Default::default(),
+            password_hash: Some(stored),
+        }];
+        let auth = UserPassAuthenticator::new(&users, &settings).unwrap();
+        assert!(auth.verify("bob", &mut Zeroizing::new("hunter2".to_string())));
+        assert!(!auth.verify("bob", &mut Zeroizing::new("hunter3".to_string())));
+
+        // Both fields, neither field, and a hash that is really a password
+        users[0].password = "hunter2".to_string().into();
+        assert!(UserPassAuthenticator::new(&users, &settings).is_err());
+        users[0].password = Default::default();
+        users[0].password_hash = None;
+        assert!(UserPassAuthenticator::new(&users, &settings).is_err());
+        users[0].password_hash = Some("hunter2".to_string());
+        assert!(UserPassAuthenticator::new(&users, &settings).is_err());
+    }
+
+    #[test]
+    fn userpass_rejects_unsupported_hashes() {
+        let users = vec![User {
+            username: "alice".to_string(),
+            password: "$2b$12$abcdefghijklmnopqrstuv".to_string().into(),
+            password_hash: None,
+        }];
+        assert!(UserPassAuthenticator::new(&users, &fast_hashing()).is_err());
+    }
+
+    #[test]
+    fn reload_users_swaps_the_credentials() {
+        let mut config = userpass_config();
+        config.password_hashing = fast_hashing();
+        let auth_manager = AuthManager::new(&config).unwrap();
+        let auth = auth_manager.userpass.as_ref().expect("userpass backend");
+        let check = |user: &str, password: &str| {
+            auth.verify(user, &mut Zeroizing::new(password.to_string()))
+        };
+
+        config.users = vec![User {
+            username: "bob".to_string(),
+            password: "hunter2".to_string().into(),
+            password_hash: None,
+        }];
+        assert!(auth_manager.reload_users(&config).unwrap());
+        assert!(check("bob", "hunter2"));
+        assert!(!check("alice", "secret123"));
+
+        // A list that fails to hash leaves the current one in place
+        config.users[0].password = "$2b$12$abcdefghijklmnopqrstuv".to_string().into();
+        assert!(auth_manager.reload_users(&config).is_err());
+        assert!(check("bob", "hunter2"));
+
+        let none = AuthManager::new(&AuthConfig::default()).unwrap();
+        assert!(!none.reload_users(&config).unwrap());
+    }
+
+    #[test]
+    fn users_file_replaces_inline_users() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            "[[users]]\nusername = \"bob\"\npassword = \"hunter2\"\n",
+        )
+        .unwrap();
+
+        let mut config = userpass_config();
+        config.users.clear();
+        config.users_file = Some(file.path().display().to_string());
+        config.password_hashing = fast_hashing();
+        let auth_manager = AuthManager::new(&config).unwrap();
+        let auth = auth_manager.userpass.as_ref().expect("userpass backend");
+        let check = |user: &str, password: &str| {
+            auth.verify(user, &mut Zeroizing::new(password.to_string()))
+        };
+        assert!(check("bob", "hunter2"));
+
+        std::fs::write(
+            file.path(),
+            "[[users]]\nusername = \"carol\"\npassword = \"s3cret\"\n",
+        )
+        .unwrap();
+        assert!(auth_manager.reload_users(&config).unwrap());
+        assert!(check("carol", "s3cret"));
+        assert!(!check("bob", "hunter2"));
+
+        // An invalid file leaves the current users in place
+        std::fs::write(file.path(), "[[users]]\nusername = \"dave\"\n").unwrap();
+        assert!(auth_manager.reload_users(&config).is_err());
+        assert!(check("carol", "s3cret"));
+
+        config.users_file = Some("/nonexistent/users.toml".to_string());
+        assert!(AuthManager::new(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn wire_password_is_zeroed_after_authentication() {
+        let mut config = userpass_config();
+        config.password_hashing = fast_hashing();
+        let auth_manager = AuthManager::new(&config).unwrap();
+        let auth = auth_manager.userpass.as_ref().expect("userpass backend");
+
+        let (mut client, mut server) = tokio::io::duplex(64);
+        tokio::io::AsyncWriteExt::write_all(&mut client, b"\x01\x05alice\x09secret123")
+            .await
+            .unwrap();
+        let (_, mut password) = parse_userpass_auth(&mut server).await.unwrap();
+        let capacity = password.capacity();
+        let buffer = password.as_ptr();
+
+        assert!(auth.verify("alice", &mut password));
+
+        assert!(password.is_empty());
+        assert_eq!(password.capacity(), capacity);
+        // SAFETY: the allocation is still owned by `password`; zeroize wrote every byte
+        let bytes = unsafe { std::slice::from_raw_parts(buffer, capacity) };
+        assert!(bytes.iter().all(|&b| b == 0));
+    }
+
+    #[tokio::test]
+    async fn userpass_answers_through_the_backend_trait() {
+        let mut config = userpass_config();
+        config.password_hashing = fast_hashing();
+        let auth_manager = AuthManager::new(&config).unwrap();
+        let AuthBackend::Checked(backend) = &auth_manager.socks_backends[0] else {
+            panic!("expected a checked backend");
+        };
+        assert_eq!(backend.name(), "userpass");
+        assert_eq!(backend.method(), AuthMethod::UserPass);
+
+        let request = |password: &str| AuthRequest {
+            client_ip: "127.0.0.1".parse().unwrap(),
+            credentials: Some(LoginCredentials {
+                username: "alice".to_string(),
+                password: Zeroizing::new(password.to_string()),
+            }),
+        };
+        assert_eq!(
+            backend.authenticate(request("secret123")).await.unwrap(),
+            AuthOutcome::User {
+                username: "alice".to_string(),
+                groups: None,
+            }
+        );
+        assert!(matches!(
+            backend.authenticate(request("wrong")).await,
+            Err(RustSocksError::AuthFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn backend_groups_belong_to_the_principal_only() {
+        let mut config = userpass_config();
+        config.impersonation.allowed_principals = vec!["svc".to_string()];
+        let auth_manager = AuthManager::new(&config).unwrap();
+        let groups = || Some(vec!["ops".to_string()]);
+
+        let (_client, mut server) = tokio::io::duplex(64);
+        let identity = auth_manager
+            .complete_login(&mut server, "alice", None, None, groups())
+            .await
+            .unwrap();
+        assert_eq!(identity.groups, vec!["ops"]);
+
+        let identity = auth_manager
+            .complete_login(&mut server, "svc", Some("bob"), None, groups())
+            .await
+            .unwrap();
+        assert_eq!(identity.user, "bob");
+        assert!(!identity.groups.contains(&"ops".to_string()));
+    }
+
+    #[tokio::test]
+    async fn banned_users_are_refused_after_their_credentials_check() {
+        let mut config = userpass_config();
+        config.impersonation.allowed_principals = vec!["svc".to_string()];
+        let auth_manager = AuthManager::new(&config).unwrap();
+        auth_manager
+            .user_bans()
+            .ban("bob", chrono::Duration::minutes(10), "ops");
+
+        let (mut client, mut server) = tokio::io::duplex(64);
+        for (principal, target) in [("bob", None), ("svc", Some("bob"))] {
+            assert!(matches!(
+                auth_manager
+                    .complete_login(&mut server, principal, target, None, None)
+                    .await,
+                Err(RustSocksError::AuthFailed(_))
+            ));
+            let mut reply = [0u8; 2];
+            tokio::io::AsyncReadExt::read_exact(&mut client, &mut reply)
+                .await
+                .unwrap();
+            assert_eq!(reply, [0x01, 0x01]);
+        }
+
+        assert!(auth_manager.user_bans().lift("bob").is_some());
+        let identity = auth_manager
+            .complete_login(&mut server, "bob", None, None, Some(Vec::new()))
+            .await
+            .unwrap();
+        assert_eq!(identity.user, "bob");
+    }
 }
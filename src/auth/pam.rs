@@ -201,6 +201,8 @@ mod unix {
                 default_ruser: "pamtest".to_string(),
                 verbose: false,
                 verify_service: false,
+                address_cache_secs: 0,
+                negative_cache_secs: 0,
             }
         }
 
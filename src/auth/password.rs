@@ -0,0 +1,222 @@
+//! Password hashes for the userpass backend.
+//!
+//! Credentials are kept as argon2id hashes in PHC notation,
+//! `$argon2id$v=19$m=<KiB>,t=<passes>,p=<lanes>$<salt>$<hash>`, so plaintext
+//! passwords from the config do not outlive [`super::AuthManager`] construction.
+//! Verification re-derives with the parameters stored in the hash and compares the
+//! output in constant time.
+
+use crate::config::{PasswordHashSettings, User};
+use crate::utils::error::{Result, RustSocksError};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use std::fmt;
+
+/// Prefix of hashes produced by [`PasswordHash`].
+pub const HASH_PREFIX: &str = "$argon2id$";
+
+/// Prefixes of hash formats this build cannot verify; rejected instead of being
+/// mistaken for plaintext passwords.
+const UNSUPPORTED_PREFIXES: [&str; 7] = [
+    "$argon2i$",
+    "$argon2d$",
+    "$2a$",
+    "$2b$",
+    "$2y$",
+    "$scrypt$",
+    "$pbkdf2",
+];
+
+/// A validated argon2id PHC string.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PasswordHash {
+    encoded: String,
+}
+
+impl PasswordHash {
+    /// Hash `password` with a fresh random salt.
+    pub fn new(password: &[u8], params: &Params) -> Self {
+        let salt = SaltString::generate(&mut OsRng);
+        let encoded = hasher(params.clone())
+            .hash_password(password, &salt)
+            .expect("argon2 parameters are validated before hashing")
+            .to_string();
+        Self { encoded }
+    }
+
+    /// Parse a `$argon2id$...` string.
+    pub fn parse(encoded: &str) -> std::result::Result<Self, String> {
+        if !encoded.starts_with(HASH_PREFIX) {
+            return Err(format!("password hash must start with {}", HASH_PREFIX));
+        }
+        let phc = argon2::PasswordHash::new(encoded)
+            .map_err(|e| format!("invalid password hash: {}", e))?;
+        Params::try_from(&phc).map_err(|e| format!("invalid password hash parameters: {}", e))?;
+        if phc.salt.is_none() || phc.hash.is_none() {
+            return Err("password hash must include a salt and a hash".to_string());
+        }
+        Ok(Self {
+            encoded: encoded.to_string(),
+        })
+    }
+
+    /// Parse a configured `password_hash`, naming the format when it is one this
+    /// build cannot verify.
+    pub fn parse_configured(encoded: &str) -> std::result::Result<Self, String> {
+        if let Some(prefix) = unsupported_prefix(encoded) {
+            return Err(format!(
+                "{}... hashes are not supported; use {}...",
+                prefix, HASH_PREFIX
+            ));
+        }
+        Self::parse(encoded)
+    }
+
+    /// Constant-time check of `password` against the stored hash.
+    pub fn verify(&self, password: &[u8]) -> bool {
+        match argon2::PasswordHash::new(&self.encoded) {
+            Ok(phc) => Argon2::default().verify_password(password, &phc).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Cost parameters stored in the hash.
+    pub fn params(&self) -> Params {
+        argon2::PasswordHash::new(&self.encoded)
+            .ok()
+            .and_then(|phc| Params::try_from(&phc).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.encoded)
+    }
+}
+
+impl fmt::Debug for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PasswordHash").finish_non_exhaustive()
+    }
+}
+
+fn hasher(params: Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+/// Build argon2id parameters from `[auth.password_hashing]`.
+pub fn hash_params(settings: &PasswordHashSettings) -> Result<Params> {
+    Params::new(
+        settings.memory_kib,
+        settings.iterations,
+        settings.parallelism,
+        None,
+    )
+    .map_err(|e| RustSocksError::Config(format!("Invalid auth.password_hashing: {}", e)))
+}
+
+/// Hash `password` for use in `[[auth.users]]`.
+pub fn hash_password(password: &str, params: &Params) -> String {
+    PasswordHash::new(password.as_bytes(), params).to_string()
+}
+
+/// Turn a configured password into a hash: pre-hashed entries are parsed, anything
+/// else is treated as plaintext and hashed with `params`.
+pub(crate) fn hash_configured(
+    password: &str,
+    params: &Params,
+) -> std::result::Result<PasswordHash, String> {
+    if password.starts_with(HASH_PREFIX) || unsupported_prefix(password).is_some() {
+        return PasswordHash::parse_configured(password);
+    }
+    Ok(PasswordHash::new(password.as_bytes(), params))
+}
+
+/// Hash of a `[[auth.users]]` entry: its `password_hash`, or else its `password`
+/// through [`hash_configured`].
+pub(crate) fn user_hash(user: &User, params: &Params) -> std::result::Result<PasswordHash, String> {
+    match &user.password_hash {
+        Some(_) if !user.password.is_empty() => {
+            Err("sets both password and password_hash".to_string())
+        }
+        Some(hash) => PasswordHash::parse_configured(hash),
+        None if user.password.is_empty() => Err("needs a password or a password_hash".to_string()),
+        None => hash_configured(&user.password, params),
+    }
+}
+
+fn unsupported_prefix(encoded: &str) -> Option<&'static str> {
+    UNSUPPORTED_PREFIXES
+        .iter()
+        .find(|prefix| encoded.starts_with(**prefix))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal cost keeps the tests fast
+    fn params() -> Params {
+        Params::new(Params::MIN_M_COST, 1, 1, None).unwrap()
+    }
+
+    #[test]
+    fn hashes_round_trip_and_verify() {
+        let hash = PasswordHash::new(b"secret", &params());
+        assert!(hash.verify(b"secret"));
+        assert!(!hash.verify(b"Secret"));
+        assert!(!hash.verify(b""));
+
+        let encoded = hash.to_string();
+        assert!(encoded.starts_with("$argon2id$v=19$m=8,t=1,p=1$"));
+        let parsed = PasswordHash::parse(&encoded).unwrap();
+        assert_eq!(parsed, hash);
+        assert!(parsed.verify(b"secret"));
+
+        // Salts are random, so the same password hashes differently
+        assert_ne!(hash_password("secret", &params()), encoded);
+    }
+
+    #[test]
+    fn configured_passwords_keep_their_own_parameters() {
+        let stored = hash_password("secret", &Params::new(16, 2, 1, None).unwrap());
+        let hash = hash_configured(&stored, &params()).unwrap();
+        assert_eq!(hash.params().m_cost(), 16);
+        assert_eq!(hash.params().t_cost(), 2);
+        assert!(hash.verify(b"secret"));
+        assert!(!hash.verify(b"wrong"));
+
+        let plain = hash_configured("secret", &params()).unwrap();
+        assert_eq!(plain.params().m_cost(), Params::MIN_M_COST);
+        assert!(plain.verify(b"secret"));
+        assert!(!plain.verify(b"wrong"));
+    }
+
+    #[test]
+    fn malformed_and_foreign_hashes_are_rejected() {
+        for bad in [
+            "$argon2id$v=19$m=8,t=1,p=1$c2FsdHNhbHQ",
+            "$argon2id$v=19$m=8,t=0,p=1$c2FsdHNhbHQ$aGFzaGhhc2hoYXNoaGFzaA",
+            "$argon2id$v=19$rounds=10$c2FsdHNhbHQ$aGFzaGhhc2hoYXNoaGFzaA",
+            "$argon2id$garbage",
+        ] {
+            assert!(hash_configured(bad, &params()).is_err(), "{}", bad);
+        }
+        let err = hash_configured("$2b$12$abcdefghijklmnopqrstuv", &params()).unwrap_err();
+        assert!(err.contains("not supported"));
+    }
+
+    #[test]
+    fn settings_are_validated() {
+        let mut settings = PasswordHashSettings::default();
+        assert!(hash_params(&settings).is_ok());
+        settings.iterations = 0;
+        assert!(hash_params(&settings).is_err());
+        settings.iterations = 1;
+        settings.memory_kib = 1;
+        assert!(hash_params(&settings).is_err());
+    }
+}
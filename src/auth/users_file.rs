@@ -0,0 +1,162 @@
+//! `auth.users_file`: userpass accounts kept in a file of their own.
+//!
+//! The file holds `[[users]]` tables in the `[[auth.users]]` format, hashes included. It
+//! is read when the server starts and watched afterwards: a change that parses and
+//! validates replaces the whole user list at once, anything else is logged and the
+//! current users stay. Removing a user refuses their next login; sessions already open
+//! are left alone.
+
+use super::AuthManager;
+use crate::config::{PasswordHashSettings, User};
+use crate::utils::error::{Result, RustSocksError};
+use crate::utils::file_watch::FileWatch;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{error, info};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UsersFile {
+    #[serde(default)]
+    users: Vec<User>,
+}
+
+/// Parse and validate the contents of a users file
+pub fn parse_users_file(content: &str) -> Result<Vec<User>> {
+    let file: UsersFile = toml::from_str(content)
+        .map_err(|e| RustSocksError::Config(format!("Failed to parse users file: {}", e)))?;
+    if file.users.is_empty() {
+        return Err(RustSocksError::Config(
+            "users file must define at least one [[users]] entry".to_string(),
+        ));
+    }
+
+    let mut seen = HashSet::with_capacity(file.users.len());
+    for user in &file.users {
+        user.validate("users file")?;
+        if !seen.insert(user.username.as_str()) {
+            return Err(RustSocksError::Config(format!(
+                "users file lists user '{}' more than once",
+                user.username
+            )));
+        }
+    }
+    Ok(file.users)
+}
+
+/// Read and validate the users file at `path`
+pub fn load_users_file<P: AsRef<Path>>(path: P) -> Result<Vec<User>> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        RustSocksError::Config(format!(
+            "Failed to read users file {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    parse_users_file(&content)
+}
+
+/// Reloads the userpass users when `auth.users_file` changes (see [`FileWatch`])
+pub struct UsersFileWatcher {
+    path: PathBuf,
+    auth_manager: Arc<AuthManager>,
+    password_hashing: PasswordHashSettings,
+    watch: Option<FileWatch>,
+}
+
+impl UsersFileWatcher {
+    pub fn new(
+        path: PathBuf,
+        auth_manager: Arc<AuthManager>,
+        password_hashing: PasswordHashSettings,
+    ) -> Self {
+        Self {
+            path,
+            auth_manager,
+            password_hashing,
+            watch: None,
+        }
+    }
+
+    /// Start watching the users file for changes
+    pub fn start(&mut self) -> std::result::Result<(), String> {
+        let auth_manager = self.auth_manager.clone();
+        let password_hashing = self.password_hashing.clone();
+        let path = self.path.clone();
+
+        let watch = FileWatch::start(self.path.clone(), "users file", move |content| {
+            let auth_manager = auth_manager.clone();
+            let password_hashing = password_hashing.clone();
+            let path = path.clone();
+            async move {
+                // Plaintext passwords are hashed here, so swap off the async runtime
+                let result = tokio::task::spawn_blocking(move || {
+                    let users = parse_users_file(&content)?;
+                    auth_manager.replace_users(&users, &password_hashing)?;
+                    Ok::<_, RustSocksError>(users.len())
+                })
+                .await
+                .unwrap_or_else(|e| {
+                    Err(RustSocksError::Config(format!(
+                        "users file reload failed: {}",
+                        e
+                    )))
+                });
+
+                match result {
+                    Ok(users) => info!(path = ?path, users, "Users file reloaded"),
+                    Err(e) => error!(
+                        path = ?path,
+                        "Rejected users file, keeping current users: {}", e
+                    ),
+                }
+            }
+        })?;
+        self.watch = Some(watch);
+        Ok(())
+    }
+
+    /// Stop watching
+    pub fn stop(&mut self) {
+        self.watch = None;
+        info!("Users file watcher stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_file_accepts_the_auth_users_format() {
+        let hash =
+            crate::auth::hash_password("secret123", &argon2::Params::new(8, 1, 1, None).unwrap());
+        let users = parse_users_file(&format!(
+            "[[users]]\nusername = \"alice\"\npassword_hash = \"{}\"\n\n\
+             [[users]]\nusername = \"bob\"\npassword = \"secret123\"\n",
+            hash
+        ))
+        .unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].username, "alice");
+        assert!(users[1].has_plaintext_password());
+    }
+
+    #[test]
+    fn invalid_users_files_are_rejected() {
+        for content in [
+            "[[users]\nusername = \"alice\"",
+            "# nobody\n",
+            "[[users]]\nusername = \"alice\"\n",
+            "[[users]]\nusername = \"alice\"\npassword_hash = \"secret\"\n",
+            "[[users]]\nusername = \"alice\"\npassword = \"a\"\n\n\
+             [[users]]\nusername = \"alice\"\npassword = \"b\"\n",
+            "[[user]]\nusername = \"alice\"\npassword = \"a\"\n",
+        ] {
+            assert!(parse_users_file(content).is_err(), "{content}");
+        }
+    }
+}
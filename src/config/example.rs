@@ -0,0 +1,323 @@
+//! Example configuration written by `--generate-config`.
+//!
+//! The file is rendered from [`Config::default`] and annotated from the
+//! [`fields`](super::fields) registry, so every setting appears with its real default
+//! and description. Settings that only matter with an optional Cargo feature are left
+//! out of builds without it, with a comment saying so; they still parse to their
+//! defaults, which keeps the generated file equivalent to an empty configuration.
+
+use super::fields::{describe, feature_enabled, FieldDoc, FEATURES, FIELDS};
+use super::Config;
+use crate::utils::error::{Result, RustSocksError};
+use std::collections::HashSet;
+
+/// Comment lines are wrapped at this width.
+const COMMENT_WIDTH: usize = 88;
+
+/// Render the annotated example configuration.
+pub(crate) fn generate() -> Result<String> {
+    let rendered = toml::to_string_pretty(&Config::default())
+        .map_err(|e| RustSocksError::Config(format!("Failed to render example config: {}", e)))?;
+
+    let mut out = preamble();
+    let mut introduced: HashSet<String> = HashSet::new();
+    let mut section = String::new();
+    let mut present: HashSet<&str> = HashSet::new();
+    // Table left out entirely because its feature is not compiled in
+    let mut omitted_table: Option<String> = None;
+
+    let mut lines = rendered.lines().peekable();
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(header) = table_header(line) {
+            if omitted_table.is_none() {
+                finish_table(&mut out, &section, &present);
+            }
+            present.clear();
+            section = header.to_string();
+
+            if let Some(table) = &omitted_table {
+                if section.starts_with(&format!("{}.", table)) {
+                    continue;
+                }
+            }
+            omitted_table = None;
+
+            if let Some(doc) = describe(&section).filter(|doc| !doc.is_compiled_in()) {
+                out.push_str(&format!(
+                    "\n# [{}] omitted: the \"{}\" feature is not compiled in\n",
+                    section,
+                    doc.feature.unwrap_or_default()
+                ));
+                omitted_table = Some(section.clone());
+                continue;
+            }
+
+            // Tables holding only subtables are not rendered on their own
+            let mut parent = String::new();
+            for segment in section.split('.') {
+                if !parent.is_empty() {
+                    parent.push('.');
+                }
+                parent.push_str(segment);
+                if introduced.insert(parent.clone()) {
+                    out.push('\n');
+                    push_description(&mut out, &parent);
+                    out.push_str(&format!("[{}]\n", parent));
+                }
+            }
+            continue;
+        }
+
+        // Multi-line arrays continue on indented lines and close with `]`
+        let mut entry = vec![line];
+        while let Some(&next) = lines.peek() {
+            if next.starts_with(char::is_whitespace) || next.starts_with(']') {
+                entry.push(next);
+                lines.next();
+            } else {
+                break;
+            }
+        }
+
+        if omitted_table.is_some() {
+            continue;
+        }
+
+        let key = line.split('=').next().unwrap_or_default().trim();
+        present.insert(key);
+        let path = join(&section, key);
+        match describe(&path) {
+            Some(doc) if !doc.is_compiled_in() => {}
+            Some(doc) if doc.computed => {
+                push_description(&mut out, &path);
+                out.push_str(&format!("# {} = {}\n", key, doc.example.unwrap_or("\"\"")));
+            }
+            _ => {
+                push_description(&mut out, &path);
+                for entry_line in entry {
+                    out.push_str(entry_line);
+                    out.push('\n');
+                }
+            }
+        }
+    }
+    if omitted_table.is_none() {
+        finish_table(&mut out, &section, &present);
+    }
+
+    Ok(out)
+}
+
+fn preamble() -> String {
+    let (compiled, missing): (Vec<&str>, Vec<&str>) = FEATURES
+        .iter()
+        .copied()
+        .partition(|feature| feature_enabled(feature));
+
+    let mut out = String::new();
+    out.push_str("# RustSocks configuration\n#\n");
+    push_comment(
+        &mut out,
+        &format!(
+            "Generated by `rustsocks --generate-config` (v{}). Every setting is listed \
+             with its default value; settings that are unset by default are shown \
+             commented out.",
+            env!("CARGO_PKG_VERSION")
+        ),
+    );
+    out.push_str("#\n");
+    push_comment(
+        &mut out,
+        &format!("Features compiled in: {}", list(&compiled)),
+    );
+    push_comment(
+        &mut out,
+        &format!("Features not compiled in: {}", list(&missing)),
+    );
+    out
+}
+
+/// Close a table: commented examples for its unset settings, then a note listing the
+/// settings left out because their feature is not compiled in.
+fn finish_table(out: &mut String, section: &str, present: &HashSet<&str>) {
+    let fields: Vec<&FieldDoc> = FIELDS
+        .iter()
+        .filter(|field| field.parent() == section && !section.is_empty())
+        .collect();
+
+    for field in &fields {
+        if present.contains(field.key()) || field.computed || !field.is_compiled_in() {
+            continue;
+        }
+        if let Some(example) = field.example {
+            push_description(out, field.path);
+            out.push_str(&format!("# {} = {}\n", field.key(), example));
+        }
+    }
+
+    for feature in FEATURES {
+        let omitted: Vec<&str> = fields
+            .iter()
+            .filter(|field| field.feature == Some(*feature) && !field.is_compiled_in())
+            .filter(|field| present.contains(field.key()) || field.example.is_some())
+            .map(|field| field.key())
+            .collect();
+        if !omitted.is_empty() {
+            push_comment(
+                out,
+                &format!(
+                    "Omitted, the \"{}\" feature is not compiled in: {}",
+                    feature,
+                    omitted.join(", ")
+                ),
+            );
+        }
+    }
+}
+
+/// `[a.b]` -> `a.b`; arrays of tables are not table headers.
+fn table_header(line: &str) -> Option<&str> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    (!inner.starts_with('[')).then_some(inner)
+}
+
+fn join(section: &str, key: &str) -> String {
+    if section.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", section, key)
+    }
+}
+
+fn list(features: &[&str]) -> String {
+    if features.is_empty() {
+        "none".to_string()
+    } else {
+        features.join(", ")
+    }
+}
+
+fn push_description(out: &mut String, path: &str) {
+    if let Some(doc) = describe(path) {
+        push_comment(out, doc.description);
+    }
+}
+
+/// Append `text` as `# ` comment lines wrapped at [`COMMENT_WIDTH`].
+fn push_comment(out: &mut String, text: &str) {
+    let mut line = String::from("#");
+    for word in text.split_whitespace() {
+        if line.len() > 1 && line.len() + 1 + word.len() > COMMENT_WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line = String::from("#");
+        }
+        line.push(' ');
+        line.push_str(word);
+    }
+    out.push_str(&line);
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every table and setting path of a serialized value.
+    fn paths(value: &toml::Value, prefix: &str, out: &mut Vec<String>) {
+        if let toml::Value::Table(table) = value {
+            for (key, child) in table {
+                let path = join(prefix, key);
+                paths(child, &path, out);
+                out.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn generated_example_parses_back_to_defaults() {
+        let example = generate().unwrap();
+        let parsed: Config = toml::from_str(&example).unwrap();
+        assert!(parsed.validate().is_ok());
+
+        // The dashboard session secret is generated afresh every time it is left unset
+        let mut expected = Config::default();
+        expected.sessions.dashboard_auth.session_secret =
+            parsed.sessions.dashboard_auth.session_secret.clone();
+        assert_eq!(
+            toml::Value::try_from(&parsed).unwrap(),
+            toml::Value::try_from(&expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn every_setting_is_described() {
+        let mut all = Vec::new();
+        paths(
+            &toml::Value::try_from(Config::default()).unwrap(),
+            "",
+            &mut all,
+        );
+        let undescribed: Vec<&String> =
+            all.iter().filter(|path| describe(path).is_none()).collect();
+        assert!(
+            undescribed.is_empty(),
+            "undescribed settings: {:?}",
+            undescribed
+        );
+
+        let mut registered = HashSet::new();
+        for field in FIELDS {
+            assert!(
+                registered.insert(field.path),
+                "duplicate entry {}",
+                field.path
+            );
+            assert!(
+                field
+                    .feature
+                    .into_iter()
+                    .all(|feature| FEATURES.contains(&feature)),
+                "unknown feature on {}",
+                field.path
+            );
+        }
+    }
+
+    #[test]
+    fn settings_carry_their_description_and_default() {
+        let example = generate().unwrap();
+        assert!(example.contains("# Port the SOCKS listener binds to\nbind_port = 1080\n"));
+        assert!(example.contains("# min_protocol_version = \"TLS13\"\n"));
+        assert!(example.contains("[resolver]\n\n# Special-use names"));
+        assert!(!example.contains("rustsocks-secret-"));
+    }
+
+    #[test]
+    fn features_not_compiled_in_are_noted() {
+        let example = generate().unwrap();
+
+        assert_eq!(
+            example.contains("\n[auth.gssapi]\n"),
+            cfg!(feature = "gssapi")
+        );
+        assert_eq!(
+            example.contains("# [auth.gssapi] omitted: the \"gssapi\" feature is not compiled in"),
+            !cfg!(feature = "gssapi")
+        );
+
+        assert_eq!(
+            example.contains("\nbatch_size = 100\n"),
+            cfg!(feature = "database")
+        );
+        assert_eq!(
+            example
+                .contains("# Omitted, the \"database\" feature is not compiled in: database_url,"),
+            !cfg!(feature = "database")
+        );
+    }
+}
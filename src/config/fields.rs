@@ -0,0 +1,1105 @@
+//! Descriptions of every configuration setting, keyed by dotted TOML path.
+//!
+//! The registry is the single place a setting is explained to operators: the example
+//! configuration written by `--generate-config` is built from it and from
+//! [`Config::default`](super::Config), so adding a field without describing it here is
+//! caught by the config tests rather than by a stale template.
+
+/// Documentation of one table or setting.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldDoc {
+    /// Dotted path, e.g. `server.tls.enabled`
+    pub path: &'static str,
+    pub description: &'static str,
+    /// Cargo feature the setting only matters with
+    pub feature: Option<&'static str>,
+    /// Value shown commented out when the default leaves the setting unset
+    pub example: Option<&'static str>,
+    /// The default is computed at startup, so the example shows a placeholder instead
+    pub computed: bool,
+}
+
+impl FieldDoc {
+    const fn new(path: &'static str, description: &'static str) -> Self {
+        Self {
+            path,
+            description,
+            feature: None,
+            example: None,
+            computed: false,
+        }
+    }
+
+    const fn feature(mut self, feature: &'static str) -> Self {
+        self.feature = Some(feature);
+        self
+    }
+
+    const fn example(mut self, example: &'static str) -> Self {
+        self.example = Some(example);
+        self
+    }
+
+    const fn computed(mut self) -> Self {
+        self.computed = true;
+        self
+    }
+
+    /// Last path segment, i.e. the TOML key.
+    pub fn key(&self) -> &'static str {
+        self.path.rsplit('.').next().unwrap_or(self.path)
+    }
+
+    /// Path of the table holding this entry (empty for top-level tables).
+    pub fn parent(&self) -> &'static str {
+        self.path.rsplit_once('.').map_or("", |(parent, _)| parent)
+    }
+
+    /// Whether the setting has an effect in this build.
+    pub fn is_compiled_in(&self) -> bool {
+        self.feature.into_iter().all(feature_enabled)
+    }
+}
+
+/// Whether the optional Cargo feature `name` was compiled in.
+pub fn feature_enabled(name: &str) -> bool {
+    match name {
+        "database" => cfg!(feature = "database"),
+        "metrics" => cfg!(feature = "metrics"),
+        "gssapi" => cfg!(feature = "gssapi"),
+        "ldap" => cfg!(feature = "ldap"),
+        "fast-allocator" => cfg!(feature = "fast-allocator"),
+        _ => false,
+    }
+}
+
+/// Optional Cargo features, in the order they are listed in `Cargo.toml`.
+pub const FEATURES: &[&str] = &["metrics", "database", "fast-allocator", "gssapi", "ldap"];
+
+/// Look up the documentation of a table or setting.
+pub fn describe(path: &str) -> Option<&'static FieldDoc> {
+    FIELDS.iter().find(|field| field.path == path)
+}
+
+/// Every table and setting of [`Config`](super::Config).
+pub const FIELDS: &[FieldDoc] = &[
+    // [server]
+    FieldDoc::new("server", "SOCKS listener"),
+    FieldDoc::new("server.bind_address", "Address the SOCKS listener binds to"),
+    FieldDoc::new(
+        "server.bind_addresses",
+        "Addresses to listen on, e.g. [\"0.0.0.0\", \"::\"] for dual-stack; replaces \
+         bind_address when not empty",
+    ),
+    FieldDoc::new("server.bind_port", "Port the SOCKS listener binds to"),
+    FieldDoc::new(
+        "server.reuse_port",
+        "Set SO_REUSEPORT so a new process can bind the port while the old one drains \
+         (Unix only)",
+    ),
+    FieldDoc::new(
+        "server.max_connections",
+        "Concurrent client connections accepted before new ones are refused",
+    ),
+    FieldDoc::new(
+        "server.handshake_timeout_secs",
+        "Seconds a client has from accept to finish the TLS handshake, method negotiation, \
+         authentication and SOCKS request; a client still negotiating then is closed and \
+         counted in rustsocks_handshake_timeouts_total by the phase it stalled in",
+    ),
+    FieldDoc::new(
+        "server.max_concurrent_handshakes",
+        "Connections allowed to be in the handshake at once, apart from established \
+         sessions; further clients are closed on accept so slow or idle handshakes cannot \
+         crowd out relays (0 = no limit)",
+    ),
+    FieldDoc::new(
+        "server.udp_association_mode",
+        "Which datagram sources a UDP ASSOCIATE relays: \"strict\" only the address and port \
+         stated in the request (the control connection's address when none is stated), \
+         \"ip-only\" any port from the client's address until the first datagram fixes it, \
+         \"learned\" the first datagram from the control connection's address. Use \
+         \"ip-only\" for clients that announce 0.0.0.0:0 and send from another port",
+    ),
+    FieldDoc::new(
+        "server.bind_accept_timeout_secs",
+        "Seconds a BIND waits for the expected peer to connect before the client gets a \
+         failure reply and the listening socket is closed",
+    ),
+    FieldDoc::new(
+        "server.idle_timeout_secs",
+        "Seconds a CONNECT or BIND tunnel may relay nothing in either direction before both \
+         sockets are shut down and the session closes with reason \"idle timeout\"; 0 keeps \
+         idle tunnels open",
+    ),
+    FieldDoc::new(
+        "server.relay_buffer_bytes",
+        "Bytes in each of the two buffers a CONNECT or BIND tunnel relays through (1024 to \
+         1048576); buffers come from a pool shared by all sessions and go back to it when the \
+         session closes",
+    ),
+    FieldDoc::new(
+        "server.shutdown_grace_period_secs",
+        "Seconds shutdown waits for active sessions to finish after the listener stops \
+         accepting; sessions still open then are closed with reason \"server shutdown\". \
+         /health reports \"draining\" meanwhile",
+    ),
+    FieldDoc::new(
+        "server.enable_socks4",
+        "Serve SOCKS4/SOCKS4a clients on the same listener (requires the no-auth method); the \
+         USERID field becomes the session user",
+    ),
+    FieldDoc::new(
+        "server.tunnel_keepalive",
+        "Keepalive for idle tunnels to specific destinations, as [[server.tunnel_keepalive]] \
+         tables with destinations, interval_secs, mode (\"tcp\" or \"probe\") and \
+         user_timeout_secs; the first matching entry wins",
+    ),
+    FieldDoc::new(
+        "server.upstream_socket_options",
+        "Socket options for upstream connections to specific destinations, as \
+         [[server.upstream_socket_options]] tables with destinations, ports, \
+         tcp_md5_password (Linux only), and dscp or ip_tos; the first matching entry wins \
+         and matching tunnels bypass the connection pool",
+    ),
+    FieldDoc::new(
+        "server.upstream",
+        "Parent SOCKS5 proxy that CONNECTs to matching destinations are chained through",
+    ),
+    FieldDoc::new(
+        "server.upstream.enabled",
+        "Chain matching CONNECTs through the parent proxy",
+    ),
+    FieldDoc::new(
+        "server.upstream.address",
+        "Host name or IP address of the parent proxy",
+    ),
+    FieldDoc::new("server.upstream.port", "Port of the parent proxy"),
+    FieldDoc::new(
+        "server.upstream.username",
+        "RFC 1929 username for the parent proxy; set together with password",
+    ),
+    FieldDoc::new(
+        "server.upstream.password",
+        "RFC 1929 password for the parent proxy",
+    ),
+    FieldDoc::new(
+        "server.upstream.destinations",
+        "Destination patterns as in ACL rules (IPs, CIDRs, domains, *.domain, *) routed via \
+         the parent, which resolves them; other destinations connect directly",
+    ),
+    FieldDoc::new(
+        "server.upstream.health_check_interval_secs",
+        "Seconds between SOCKS5 greetings sent to the parent to check it is healthy \
+         (0 = no checks)",
+    ),
+    FieldDoc::new(
+        "server.upstream.unhealthy_threshold",
+        "Consecutive failed health checks before the parent counts as unhealthy",
+    ),
+    FieldDoc::new(
+        "server.upstream.on_unhealthy",
+        "When the parent turns unhealthy: \"keep\" leaves chained sessions alone, \"drain\" \
+         closes them after drain_grace_secs with close reason \"upstream unhealthy\", \
+         \"migrate\" drops idle pooled connections to the parent",
+    ),
+    FieldDoc::new(
+        "server.upstream.drain_grace_secs",
+        "Seconds chained sessions keep relaying after the parent turns unhealthy before \
+         on_unhealthy = \"drain\" closes them",
+    ),
+    FieldDoc::new(
+        "server.upstream_tls",
+        "TLS the proxy originates on upstream connections to matching destinations",
+    ),
+    FieldDoc::new(
+        "server.upstream_tls.enabled",
+        "Wrap upstream connections to matching destinations in TLS",
+    ),
+    FieldDoc::new(
+        "server.upstream_tls.destinations",
+        "Destination patterns as in ACL rules (IPs, CIDRs, domains, *.domain, *) reached \
+         over TLS; other destinations keep plain TCP",
+    ),
+    FieldDoc::new(
+        "server.upstream_tls.ports",
+        "Port patterns as in ACL rules (\"443\", \"8000-8100\", \"*\") reached over TLS",
+    ),
+    FieldDoc::new(
+        "server.upstream_tls.ca_path",
+        "PEM bundle of the CAs destination certificates must chain to",
+    ),
+    FieldDoc::new(
+        "server.upstream_tls.client_certificate_path",
+        "PEM client certificate presented to destinations; set together with client_key_path",
+    ),
+    FieldDoc::new(
+        "server.upstream_tls.client_key_path",
+        "PKCS#8 or RSA private key of the client certificate",
+    ),
+    FieldDoc::new(
+        "server.upstream_tls.server_name",
+        "Name sent as SNI and verified against the certificate; defaults to the requested \
+         host, or the IP for IP destinations",
+    ),
+    FieldDoc::new(
+        "server.egress",
+        "Source addresses for upstream connections and UDP relays by user or group",
+    ),
+    FieldDoc::new(
+        "server.egress.rules",
+        "[[server.egress.rules]] tables with users, groups (ACL groups) and addresses \
+         (local IPs, at most one per family); the first rule naming the user or one of \
+         their groups wins, other users keep the default source address",
+    ),
+    FieldDoc::new("server.tls", "TLS on the SOCKS listener"),
+    FieldDoc::new("server.tls.enabled", "Require clients to connect over TLS"),
+    FieldDoc::new("server.tls.certificate_path", "PEM certificate chain")
+        .example("\"config/server.crt\""),
+    FieldDoc::new("server.tls.private_key_path", "PEM private key")
+        .example("\"config/server.key\""),
+    FieldDoc::new(
+        "server.tls.key_password",
+        "Password of an encrypted private key",
+    )
+    .example("\"change-me\""),
+    FieldDoc::new(
+        "server.tls.require_client_auth",
+        "Require client certificates signed by client_ca_path",
+    ),
+    FieldDoc::new(
+        "server.tls.client_ca_path",
+        "PEM bundle trusted for client certificates",
+    )
+    .example("\"config/ca.crt\""),
+    FieldDoc::new(
+        "server.tls.alpn_protocols",
+        "ALPN protocols offered to clients (empty = no ALPN)",
+    ),
+    FieldDoc::new(
+        "server.tls.min_protocol_version",
+        "Oldest TLS version accepted: \"TLS12\" or \"TLS13\" (unset = TLS12)",
+    )
+    .example("\"TLS13\""),
+    FieldDoc::new(
+        "server.pool",
+        "Reuse of upstream connections between tunnels",
+    ),
+    FieldDoc::new(
+        "server.pool.enabled",
+        "Keep upstream connections open after a tunnel closes",
+    ),
+    FieldDoc::new(
+        "server.pool.max_idle_per_dest",
+        "Idle connections kept per destination (max_per_destination is an alias)",
+    ),
+    FieldDoc::new(
+        "server.pool.max_total_idle",
+        "Idle connections kept across all destinations",
+    ),
+    FieldDoc::new(
+        "server.pool.idle_timeout_secs",
+        "Close pooled connections idle for longer than this (idle_ttl_secs is an alias)",
+    ),
+    FieldDoc::new(
+        "server.pool.connect_timeout_ms",
+        "Timeout for opening upstream connections",
+    ),
+    FieldDoc::new(
+        "server.connect_timeout_secs",
+        "Limit on each upstream connection attempt, so an unreachable address fails over \
+         to the next one in time; unset uses server.pool.connect_timeout_ms",
+    )
+    .example("5"),
+    FieldDoc::new(
+        "server.connect_retries",
+        "Retries of an upstream connect that was refused or timed out, e.g. while the \
+         destination restarts (0 = fail at once); all attempts end within 10 seconds",
+    ),
+    FieldDoc::new(
+        "server.connect_retry_backoff_ms",
+        "Wait before the first connect retry, doubled for each further one",
+    ),
+    FieldDoc::new(
+        "server.dns",
+        "Cache of destination lookups and the order their addresses are tried in",
+    ),
+    FieldDoc::new(
+        "server.dns.cache_ttl_secs",
+        "Reuse resolved addresses for this long, whatever the record TTL (0 = no cache)",
+    ),
+    FieldDoc::new(
+        "server.dns.negative_ttl_secs",
+        "Remember names that do not exist for this long (0 = not at all)",
+    ),
+    FieldDoc::new(
+        "server.dns.cache_max_entries",
+        "Names kept; the ones closest to expiry are dropped first",
+    ),
+    FieldDoc::new(
+        "server.dns.strategy",
+        "Order in which a destination's addresses are connected: \"prefer_ipv6\" (all IPv6, \
+         then IPv4), \"prefer_ipv4\", \"ipv4_only\" and \"ipv6_only\" (the other family is \
+         never tried), or \"happy_eyeballs\" (families alternate and attempts overlap, RFC 8305)",
+    ),
+    FieldDoc::new(
+        "server.dns.happy_eyeballs_delay_ms",
+        "With \"happy_eyeballs\", milliseconds an attempt runs alone before the next address \
+         is tried alongside it (at least 10)",
+    ),
+    FieldDoc::new(
+        "server.dns.allow_domain_requests",
+        "Accept CONNECT and UDP ASSOCIATE requests for domain names; false answers them with \
+         reply 0x08 before any resolution or ACL check, so clients must resolve names \
+         themselves. ACL rules on domains are reported as dead",
+    ),
+    FieldDoc::new(
+        "server.rate_limit",
+        "Per-client-IP limit on new connections, applied before TLS or SOCKS",
+    ),
+    FieldDoc::new(
+        "server.rate_limit.connections_per_ip_per_minute",
+        "New connections one client IP may open in any 60-second window; more are closed \
+         right after accept (0 = no limit)",
+    ),
+    FieldDoc::new(
+        "server.rate_limit.log_interval_secs",
+        "Log dropped connections at most once per interval (0 = no warnings)",
+    ),
+    FieldDoc::new(
+        "server.rate_limit.warn_per_client",
+        "Instead of log_interval_secs, warn once per client IP per 60-second window, on \
+         its first dropped connection",
+    ),
+    FieldDoc::new(
+        "server.overload",
+        "Shed new connections while overloaded; established sessions are never throttled",
+    ),
+    FieldDoc::new("server.overload.enabled", "Enable load shedding"),
+    FieldDoc::new(
+        "server.overload.signal",
+        "\"cpu\" (system CPU %) or \"runtime_queue\" (queued runtime tasks)",
+    ),
+    FieldDoc::new(
+        "server.overload.engage_threshold",
+        "Start shedding at this signal value",
+    ),
+    FieldDoc::new(
+        "server.overload.release_threshold",
+        "Stop shedding only once the signal falls below this",
+    ),
+    FieldDoc::new(
+        "server.overload.full_threshold",
+        "Signal at which max_reject_ratio applies",
+    ),
+    FieldDoc::new(
+        "server.overload.min_reject_ratio",
+        "Fraction of new connections rejected at engage_threshold",
+    ),
+    FieldDoc::new(
+        "server.overload.max_reject_ratio",
+        "Fraction of new connections rejected at full_threshold",
+    ),
+    FieldDoc::new(
+        "server.overload.sample_interval_ms",
+        "How often the signal is sampled",
+    ),
+    FieldDoc::new(
+        "server.guardrails",
+        "File descriptor and memory watermarks: new connections are rejected at the soft \
+         watermark, idle pooled connections and UDP associations are closed at the hard one",
+    ),
+    FieldDoc::new("server.guardrails.enabled", "Enable resource guardrails"),
+    FieldDoc::new(
+        "server.guardrails.fd_soft_limit",
+        "Open descriptors at which new connections are rejected; defaults to 80% of \
+         RLIMIT_NOFILE",
+    )
+    .example("50000"),
+    FieldDoc::new(
+        "server.guardrails.fd_hard_limit",
+        "Open descriptors at which idle connections are reclaimed; defaults to 90% of \
+         RLIMIT_NOFILE",
+    )
+    .example("58000"),
+    FieldDoc::new(
+        "server.guardrails.fd_reserve",
+        "Descriptors kept free for the API and session store; both watermarks stay at \
+         least this far below RLIMIT_NOFILE",
+    ),
+    FieldDoc::new(
+        "server.guardrails.memory_soft_limit_mb",
+        "Resident memory at which new connections are rejected",
+    )
+    .example("2048"),
+    FieldDoc::new(
+        "server.guardrails.memory_hard_limit_mb",
+        "Resident memory at which idle connections are reclaimed",
+    )
+    .example("3072"),
+    FieldDoc::new(
+        "server.guardrails.udp_idle_secs",
+        "UDP associations without traffic for this long are closed at the hard watermark",
+    ),
+    FieldDoc::new(
+        "server.guardrails.sample_interval_ms",
+        "How often descriptor count and memory are sampled",
+    ),
+    FieldDoc::new("server.udp", "Datagram handling of UDP ASSOCIATE relays"),
+    FieldDoc::new(
+        "server.udp.enable_fragments",
+        "Reassemble datagrams sent in fragments (RFC 1928 FRAG field); when off they are \
+         dropped and counted in rustsocks_udp_dropped_datagrams_total{reason=\"fragmented\"}",
+    ),
+    FieldDoc::new(
+        "server.udp.max_reassembly_queues",
+        "Fragmented datagrams reassembled at once per association; a fragment starting \
+         another one beyond this is dropped",
+    ),
+    FieldDoc::new(
+        "server.udp.reassembly_timeout_ms",
+        "Time for all fragments of a datagram to arrive after the first before the \
+         partial datagram is dropped",
+    ),
+    FieldDoc::new(
+        "server.udp.max_datagram_size",
+        "Largest client datagram (SOCKS header included), reassembled datagram or \
+         destination reply relayed; larger ones are dropped. Also sizes each association's \
+         receive buffer",
+    ),
+    FieldDoc::new(
+        "server.udp.association_idle_timeout_secs",
+        "Close a UDP association, its TCP control connection included, after this long \
+         without a datagram from the client or one of its destinations",
+    ),
+    FieldDoc::new(
+        "server.tcp",
+        "TCP options for accepted client sockets and upstream sockets",
+    ),
+    FieldDoc::new(
+        "server.tcp.nodelay",
+        "TCP_NODELAY: send small writes at once instead of coalescing them (Nagle)",
+    ),
+    FieldDoc::new(
+        "server.tcp.keepalive_secs",
+        "SO_KEEPALIVE idle time and probe interval on both sockets, up to 32767; 0 leaves \
+         keepalive off. server.tcp_keepalive and [[server.tunnel_keepalive]] take precedence \
+         on upstream sockets",
+    ),
+    FieldDoc::new(
+        "server.tcp.send_buffer_bytes",
+        "SO_SNDBUF, 4096 to 67108864; 0 keeps the kernel default and its autotuning",
+    ),
+    FieldDoc::new(
+        "server.tcp.recv_buffer_bytes",
+        "SO_RCVBUF, 4096 to 67108864; 0 keeps the kernel default and its autotuning. Set \
+         before upstream connects so it can size the TCP window",
+    ),
+    FieldDoc::new(
+        "server.tcp_keepalive",
+        "Kernel TCP keepalive on upstream sockets",
+    ),
+    FieldDoc::new("server.tcp_keepalive.enabled", "Enable SO_KEEPALIVE"),
+    FieldDoc::new(
+        "server.tcp_keepalive.idle_secs",
+        "Idle time before the first probe",
+    ),
+    FieldDoc::new(
+        "server.tcp_keepalive.interval_secs",
+        "Time between unanswered probes",
+    ),
+    FieldDoc::new(
+        "server.tcp_keepalive.retries",
+        "Unanswered probes before the connection is dropped",
+    ),
+    // [auth]
+    FieldDoc::new("auth", "Client authentication"),
+    FieldDoc::new(
+        "auth.client_method",
+        "Connection-level check: \"none\", \"pam.address\" or \"tls.cert\" (the verified \
+         TLS client certificate names the user; needs server.tls.require_client_auth)",
+    ),
+    FieldDoc::new(
+        "auth.socks_method",
+        "SOCKS method: \"none\", \"userpass\", \"pam.address\", \"pam.username\", \"ldap\" \
+         or \"gssapi\"",
+    ),
+    FieldDoc::new(
+        "auth.socks_method_preference",
+        "SOCKS methods to accept, most preferred first, e.g. [\"userpass\", \"none\"]; the \
+         server picks the first one the client offered and replaces socks_method when set. \
+         At most one no-auth and one username/password entry",
+    ),
+    FieldDoc::new(
+        "auth.users",
+        "userpass accounts, as [[auth.users]] tables with username and either password_hash \
+         (\"$argon2id$...\", from `rustsocks hash-password`) or a deprecated plaintext \
+         password, hashed at load",
+    ),
+    FieldDoc::new(
+        "auth.users_file",
+        "TOML file of [[users]] tables in the auth.users format, used instead of \
+         auth.users; reloaded when it changes, and an invalid file keeps the current users",
+    )
+    .example("\"/etc/rustsocks/users.toml\""),
+    FieldDoc::new(
+        "auth.client_allow",
+        "pam.address short-circuit allow list (CIDRs or addresses)",
+    ),
+    FieldDoc::new(
+        "auth.client_deny",
+        "pam.address short-circuit deny list, checked before client_allow",
+    ),
+    FieldDoc::new("auth.pam", "PAM backends (Linux /etc/pam.d/<service>)"),
+    FieldDoc::new(
+        "auth.pam.username_service",
+        "PAM service used by pam.username",
+    ),
+    FieldDoc::new(
+        "auth.pam.address_service",
+        "PAM service used by pam.address",
+    ),
+    FieldDoc::new(
+        "auth.pam.default_user",
+        "User passed to PAM by pam.address when the client sends none",
+    ),
+    FieldDoc::new(
+        "auth.pam.default_ruser",
+        "Remote user passed to PAM by pam.address",
+    ),
+    FieldDoc::new("auth.pam.verbose", "Verbose PAM logging"),
+    FieldDoc::new(
+        "auth.pam.verify_service",
+        "Warn at startup when the PAM service file is missing",
+    ),
+    FieldDoc::new(
+        "auth.pam.address_cache_secs",
+        "Cache successful pam.address verdicts per client IP (0 = ask PAM every connection)",
+    ),
+    FieldDoc::new(
+        "auth.pam.negative_cache_secs",
+        "Cache rejected pam.address verdicts per client IP (0 = ask PAM every connection)",
+    ),
+    FieldDoc::new(
+        "auth.gssapi",
+        "Kerberos authentication (socks_method = \"gssapi\")",
+    )
+    .feature("gssapi"),
+    FieldDoc::new(
+        "auth.gssapi.service_name",
+        "Service principal name, e.g. socks for socks/host@REALM",
+    )
+    .feature("gssapi"),
+    FieldDoc::new(
+        "auth.gssapi.keytab_path",
+        "Keytab holding the service key (unset = KRB5_KTNAME or the system keytab)",
+    )
+    .feature("gssapi")
+    .example("\"/etc/krb5.keytab\""),
+    FieldDoc::new(
+        "auth.gssapi.protection_level",
+        "\"integrity\", \"confidentiality\" or \"selective\"",
+    )
+    .feature("gssapi"),
+    FieldDoc::new("auth.gssapi.verbose", "Verbose GSS-API logging").feature("gssapi"),
+    FieldDoc::new(
+        "auth.ldap",
+        "LDAP bind authentication (socks_method = \"ldap\")",
+    )
+    .feature("ldap"),
+    FieldDoc::new(
+        "auth.ldap.url",
+        "Directory server, ldap://host:389 or ldaps://host:636",
+    )
+    .feature("ldap"),
+    FieldDoc::new(
+        "auth.ldap.bind_dn_template",
+        "DN to bind as, e.g. \"uid={username},ou=people,dc=example,dc=com\"",
+    )
+    .feature("ldap"),
+    FieldDoc::new(
+        "auth.ldap.starttls",
+        "Upgrade the ldap:// connection with StartTLS before binding",
+    )
+    .feature("ldap"),
+    FieldDoc::new(
+        "auth.ldap.timeout_ms",
+        "Limit on connecting, binding and the group search together; past it the login fails",
+    )
+    .feature("ldap"),
+    FieldDoc::new(
+        "auth.ldap.group_search_base",
+        "Search base for the user's groups (unset = groups from the system)",
+    )
+    .feature("ldap")
+    .example("\"ou=groups,dc=example,dc=com\""),
+    FieldDoc::new(
+        "auth.ldap.group_filter",
+        "Group search filter; {dn} is the bound DN, {username} the login name",
+    )
+    .feature("ldap"),
+    FieldDoc::new(
+        "auth.ldap.group_attribute",
+        "Group entry attribute used as the group name",
+    )
+    .feature("ldap"),
+    FieldDoc::new(
+        "auth.impersonation",
+        "Service accounts that may log in as \"principal<separator>target\" \
+         (userpass/pam.username/ldap); the session is accounted to the target",
+    ),
+    FieldDoc::new(
+        "auth.impersonation.allowed_principals",
+        "Principals allowed to impersonate (empty = disabled)",
+    ),
+    FieldDoc::new(
+        "auth.impersonation.allowed_target_pattern",
+        "Target users they may act as (\"*\" matches any run of characters)",
+    ),
+    FieldDoc::new(
+        "auth.impersonation.separator",
+        "Separates principal and target in the login name",
+    ),
+    FieldDoc::new(
+        "auth.allow_correlation_suffix",
+        "Accept \"user<correlation_separator><id>\" logins (userpass/pam.username/ldap); \
+         the ID is stripped before authentication and recorded on the session",
+    ),
+    FieldDoc::new(
+        "auth.correlation_separator",
+        "Separates the login name from the correlation ID",
+    ),
+    FieldDoc::new(
+        "auth.tls_cert_identity",
+        "Client certificate field used as the username with tls.cert: \"cn\", \"san.dns\", \
+         \"san.email\" or \"san.uri\"",
+    ),
+    FieldDoc::new(
+        "auth.password_hashing",
+        "argon2id cost for hashing plaintext passwords; every login pays one derivation",
+    ),
+    FieldDoc::new(
+        "auth.password_hashing.memory_kib",
+        "Memory per derivation in KiB",
+    ),
+    FieldDoc::new(
+        "auth.password_hashing.iterations",
+        "Passes over memory (pre-hashed entries keep their own parameters)",
+    ),
+    FieldDoc::new("auth.password_hashing.parallelism", "Lanes"),
+    // [logging]
+    FieldDoc::new("logging", "Log output"),
+    FieldDoc::new(
+        "logging.level",
+        "\"trace\", \"debug\", \"info\", \"warn\" or \"error\"",
+    ),
+    FieldDoc::new("logging.format", "\"pretty\" or \"json\""),
+    FieldDoc::new(
+        "logging.sampling",
+        "Sampling of per-connection info events; warnings and errors are always kept",
+    ),
+    FieldDoc::new(
+        "logging.sampling.connection_events_ratio",
+        "Share of connections logged, 0.0 to 1.0 (1.0 = all)",
+    ),
+    FieldDoc::new(
+        "logging.sampling.always_log_users",
+        "Users always logged in full, from their login on",
+    ),
+    // [acl]
+    FieldDoc::new("acl", "Access control lists"),
+    FieldDoc::new("acl.enabled", "Evaluate ACL rules for every request"),
+    FieldDoc::new("acl.config_file", "ACL rules file (required when enabled)")
+        .example("\"config/acl.toml\""),
+    FieldDoc::new("acl.watch", "Reload the rules file when it changes"),
+    FieldDoc::new(
+        "acl.watch_domain_lists",
+        "Reload the rules when a file: domain list they reference changes (needs acl.watch)",
+    ),
+    FieldDoc::new(
+        "acl.anonymous_user",
+        "User the ACL is evaluated for when the client did not authenticate",
+    ),
+    FieldDoc::new(
+        "acl.mode",
+        "\"enforce\", or \"monitor\" to log blocks and relay the connections anyway",
+    ),
+    FieldDoc::new(
+        "acl.classify_by_sni",
+        "Classify TLS connections made to IP literals by their SNI \
+         (stats, logging, second ACL stage)",
+    ),
+    FieldDoc::new(
+        "acl.sni_peek_timeout_ms",
+        "How long to wait for a ClientHello",
+    ),
+    FieldDoc::new(
+        "acl.sni_ports",
+        "Destination ports expected to start with a ClientHello",
+    ),
+    FieldDoc::new(
+        "acl.sni_fail_mode",
+        "\"block\" or \"allow\" when no readable ClientHello arrives in time",
+    ),
+    FieldDoc::new(
+        "acl.rule_stats_flush_interval_secs",
+        "How often per-rule hit counters are persisted",
+    ),
+    FieldDoc::new(
+        "acl.rule_stats_sidecar",
+        "Persist rule counters to <config_file>.stats.json when there is no session database",
+    ),
+    FieldDoc::new(
+        "acl.minimal_log_interval_secs",
+        "Rules with log = \"minimal\" write one aggregated record per user and destination \
+         this often",
+    ),
+    FieldDoc::new(
+        "acl.strict_references",
+        "Fail loading the ACL file when a user lists an undefined group (default: warn)",
+    ),
+    FieldDoc::new(
+        "acl.system_group_patterns",
+        "Group names (* wildcards) resolved from the system/LDAP group database; \
+         matching ACL groups are not reported as orphans",
+    ),
+    FieldDoc::new(
+        "acl.cache",
+        "Reuse ACL decisions per user, destination, port and protocol; dropped on every ACL \
+         reload",
+    ),
+    FieldDoc::new("acl.cache.enabled", "Cache ACL decisions"),
+    FieldDoc::new(
+        "acl.cache.ttl_secs",
+        "Seconds a cached decision is reused before the rules are evaluated again",
+    ),
+    FieldDoc::new(
+        "acl.cache.max_entries",
+        "Cached decisions kept before the least recently used are evicted",
+    ),
+    // [sessions]
+    FieldDoc::new("sessions", "Session tracking and the management API"),
+    FieldDoc::new("sessions.enabled", "Track sessions"),
+    FieldDoc::new(
+        "sessions.storage",
+        "\"memory\" or \"database\" (\"sqlite\", \"mariadb\" and \"mysql\" are aliases)",
+    ),
+    FieldDoc::new(
+        "sessions.database_url",
+        "Session database for storage = \"database\": sqlite:, mysql://, mariadb:// or postgres://",
+    )
+    .feature("database")
+    .example("\"sqlite://var/lib/rustsocks/sessions.db\""),
+    FieldDoc::new(
+        "sessions.batch_size",
+        "Starting batch size (adaptive sizing moves within min/max)",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.batch_interval_ms",
+        "Longest time a session waits before being persisted",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.batch_adaptive",
+        "Grow or shrink batches based on observed flush latency",
+    )
+    .feature("database"),
+    FieldDoc::new("sessions.batch_min_size", "Smallest adaptive batch").feature("database"),
+    FieldDoc::new("sessions.batch_max_size", "Largest adaptive batch").feature("database"),
+    FieldDoc::new(
+        "sessions.batch_target_flush_ms",
+        "Keep p95 flush latency under this target",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.batch_queue_max",
+        "Sessions waiting for the store before batch_overflow_policy applies; at least \
+         batch_max_size",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.batch_overflow_policy",
+        "Full queue: \"block\" (closing sessions wait for the next flush), \"drop_oldest\" \
+         or \"drop_newest\" (the session is never stored)",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.retention_days",
+        "Delete closed sessions older than this",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.cleanup_interval_hours",
+        "How often old sessions are deleted",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.cleanup_batch_size",
+        "Old sessions deleted per transaction (1-30000)",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.allow_shared_database",
+        "Let several instances share one SQLite session file",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.instance_lock_stale_secs",
+        "Heartbeat age after which another instance's store lock is taken over",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "sessions.traffic_update_packet_interval",
+        "Packets relayed between traffic counter updates",
+    ),
+    FieldDoc::new(
+        "sessions.live_traffic_interval_secs",
+        "Minimum seconds between traffic_update (and qos_throttled) events of one session \
+         on /api/ws",
+    ),
+    FieldDoc::new(
+        "sessions.stats_window_hours",
+        "Window of the session statistics endpoints",
+    ),
+    FieldDoc::new(
+        "sessions.requested_host_ttl_secs",
+        "Remember client hostname lookups for CONNECT-by-IP (0 = off)",
+    ),
+    FieldDoc::new(
+        "sessions.memory_max_sessions",
+        "Closed and rejected sessions kept in memory each, oldest evicted first (0 = unbounded)",
+    ),
+    FieldDoc::new("sessions.stats_api_enabled", "Serve the management API"),
+    FieldDoc::new(
+        "sessions.stats_api_bind_address",
+        "Address the management API binds to",
+    ),
+    FieldDoc::new(
+        "sessions.stats_api_port",
+        "Port the management API binds to",
+    ),
+    FieldDoc::new(
+        "sessions.stats_api_bind",
+        "\"unix:<path>\" serves the management API on a Unix domain socket instead of \
+         stats_api_bind_address:stats_api_port; a stale socket file is removed at startup",
+    )
+    .example("\"unix:/run/rustsocks/api.sock\""),
+    FieldDoc::new(
+        "sessions.api_socket_mode",
+        "Octal permissions of the stats_api_bind socket file",
+    ),
+    FieldDoc::new("sessions.swagger_enabled", "Serve Swagger UI for the API"),
+    FieldDoc::new("sessions.dashboard_enabled", "Serve the web dashboard"),
+    FieldDoc::new(
+        "sessions.base_path",
+        "URL prefix of the API and dashboard when served behind a reverse proxy",
+    ),
+    FieldDoc::new(
+        "sessions.public_status_enabled",
+        "Unauthenticated status page at /status (coarse state, load and throughput only)",
+    ),
+    FieldDoc::new(
+        "sessions.maintenance_message",
+        "Shown on the status page, which then reports the \"maintenance\" state",
+    )
+    .example("\"Planned upgrade 22:00-23:00 UTC\""),
+    FieldDoc::new("sessions.dashboard_auth", "Login for the dashboard and API"),
+    FieldDoc::new("sessions.dashboard_auth.enabled", "Require a login"),
+    FieldDoc::new(
+        "sessions.dashboard_auth.users",
+        "Dashboard accounts, as [[sessions.dashboard_auth.users]] tables with username and \
+         either password_hash or password",
+    ),
+    FieldDoc::new(
+        "sessions.dashboard_auth.altcha_enabled",
+        "Require an ALTCHA proof of work on the login form",
+    ),
+    FieldDoc::new(
+        "sessions.dashboard_auth.altcha_challenge_url",
+        "External ALTCHA challenge endpoint (unset = served by the API)",
+    )
+    .example("\"https://altcha.example.com/challenge\""),
+    FieldDoc::new(
+        "sessions.dashboard_auth.session_secret",
+        "Secret signing login sessions (unset = generated at startup, logging everyone \
+         out on restart)",
+    )
+    .example("\"<long random string>\"")
+    .computed(),
+    FieldDoc::new(
+        "sessions.dashboard_auth.session_duration_hours",
+        "Lifetime of a login",
+    ),
+    FieldDoc::new("sessions.api_auth", "API keys for the stats API"),
+    FieldDoc::new(
+        "sessions.api_auth.enabled",
+        "Require an API key (or a dashboard login) for API requests",
+    ),
+    FieldDoc::new(
+        "sessions.api_auth.keys",
+        "Keys, as [[sessions.api_auth.keys]] tables with token (sent as \"Authorization: \
+         Bearer <token>\" or \"X-API-Key: <token>\"), role (\"read\" or \"admin\") and an \
+         optional name for logs",
+    ),
+    FieldDoc::new(
+        "sessions.api_compression",
+        "gzip/deflate compression of API responses",
+    ),
+    FieldDoc::new(
+        "sessions.api_compression.enabled",
+        "Compress responses for clients that send Accept-Encoding",
+    ),
+    FieldDoc::new(
+        "sessions.api_compression.min_size_bytes",
+        "Smaller bodies are sent uncompressed",
+    ),
+    FieldDoc::new(
+        "sessions.api_compression.exclude_paths",
+        "Paths below base_path that are never compressed",
+    ),
+    // [metrics]
+    FieldDoc::new("metrics", "Dashboard metrics history"),
+    FieldDoc::new("metrics.enabled", "Collect metrics history"),
+    FieldDoc::new(
+        "metrics.storage",
+        "\"memory\" or \"database\" (uses sessions.database_url; \"sqlite\" is an alias)",
+    ),
+    FieldDoc::new("metrics.retention_hours", "Keep raw samples for this long"),
+    FieldDoc::new(
+        "metrics.rollup_retention_days",
+        "Keep 1-minute and 15-minute rollups for this long (0 disables rollups)",
+    ),
+    FieldDoc::new(
+        "metrics.cleanup_interval_hours",
+        "How often metrics are rolled up and old ones deleted from the database",
+    )
+    .feature("database"),
+    FieldDoc::new(
+        "metrics.collection_interval_secs",
+        "How often a sample is taken",
+    ),
+    FieldDoc::new(
+        "metrics.history_max_range_hours",
+        "Widest range one /api/metrics/history request may scan at raw resolution",
+    ),
+    // [telemetry]
+    FieldDoc::new(
+        "telemetry",
+        "Operational event history shown by the dashboard",
+    ),
+    FieldDoc::new("telemetry.enabled", "Record telemetry events"),
+    FieldDoc::new("telemetry.max_events", "Events kept in memory"),
+    FieldDoc::new("telemetry.retention_hours", "Drop events older than this"),
+    // [qos]
+    FieldDoc::new("qos", "Quality of service / rate limiting"),
+    FieldDoc::new(
+        "qos.enabled",
+        "Enable bandwidth shaping and connection limits",
+    ),
+    FieldDoc::new(
+        "qos.algorithm",
+        "\"htb\" (Hierarchical Token Bucket with fair sharing)",
+    ),
+    FieldDoc::new(
+        "qos.htb",
+        "Hierarchical Token Bucket. Rates take a unit (\"100mbps\" and \"1gbit\" are bits, \
+         \"12.5MBps\" and \"64KiB/s\" are bytes per second); a plain number is bytes per \
+         second",
+    ),
+    FieldDoc::new(
+        "qos.htb.global_bandwidth_bytes_per_sec",
+        "Global bandwidth limit",
+    ),
+    FieldDoc::new(
+        "qos.htb.guaranteed_bandwidth_bytes_per_sec",
+        "Per-user guaranteed minimum bandwidth",
+    ),
+    FieldDoc::new(
+        "qos.htb.max_bandwidth_bytes_per_sec",
+        "Per-user maximum bandwidth when borrowing",
+    ),
+    FieldDoc::new(
+        "qos.htb.burst_size_bytes",
+        "How much can be transferred instantly (\"1MiB\", \"64kB\" or bytes)",
+    ),
+    FieldDoc::new("qos.htb.refill_interval_ms", "Token bucket refill interval"),
+    FieldDoc::new(
+        "qos.htb.fair_sharing_enabled",
+        "Lend unused bandwidth to active users",
+    ),
+    FieldDoc::new(
+        "qos.htb.rebalance_interval_ms",
+        "How often fair shares are recalculated",
+    ),
+    FieldDoc::new(
+        "qos.htb.idle_timeout_secs",
+        "A user is considered idle after this period without traffic",
+    ),
+    FieldDoc::new("qos.connection_limits", "Connection limits"),
+    FieldDoc::new(
+        "qos.connection_limits.max_connections_per_user",
+        "Concurrent connections per user",
+    ),
+    FieldDoc::new(
+        "qos.connection_limits.max_connections_global",
+        "Concurrent connections across all users",
+    ),
+    FieldDoc::new(
+        "qos.per_ip",
+        "Per-source-IP shaping on top of the user and global limits, for clients that \
+         share one account",
+    ),
+    FieldDoc::new("qos.per_ip.enabled", "Shape every source IP separately"),
+    FieldDoc::new(
+        "qos.per_ip.max_bytes_per_sec",
+        "Bandwidth limit per source IP (\"10mbit\", \"2MBps\" or bytes per second)",
+    ),
+    FieldDoc::new(
+        "qos.per_ip.burst",
+        "How much one source IP can transfer instantly",
+    ),
+    FieldDoc::new(
+        "qos.per_ip.exempt",
+        "Source networks (CIDRs or addresses) not shaped per IP",
+    ),
+    // [resolver]
+    FieldDoc::new("resolver", "Destination name resolution"),
+    FieldDoc::new(
+        "resolver.special_names",
+        "Special-use names (RFC 6761), checked before any DNS query for CONNECT and every \
+         UDP datagram; .onion and .i2p are always rejected",
+    ),
+    FieldDoc::new(
+        "resolver.special_names.localhost",
+        "\"block\" or \"allow\" (also covers loopback IP literals)",
+    ),
+    FieldDoc::new(
+        "resolver.special_names.local",
+        "\"block\" or \"resolve\" (mDNS lookups can stall for seconds)",
+    ),
+    // [diagnostics]
+    FieldDoc::new(
+        "diagnostics",
+        "Connectivity probes to upstream destinations, shown at /api/diagnostics/probes",
+    ),
+    FieldDoc::new(
+        "diagnostics.probe_user",
+        "User the probes connect as: ACL rules, egress addresses and the parent proxy apply \
+         as for a client logged in with this name",
+    ),
+    FieldDoc::new(
+        "diagnostics.probe_groups",
+        "Groups of probe_user for ACL group rules",
+    ),
+    FieldDoc::new(
+        "diagnostics.probe_history",
+        "Most recent results kept per probe (1 to 10000)",
+    ),
+    FieldDoc::new(
+        "diagnostics.probes",
+        "[[diagnostics.probes]] tables with host, port, interval_secs (default 30) and an \
+         optional name (default host:port); each is connected to through the proxy's own \
+         resolve and connect path and reported in rustsocks_probe_up{probe}",
+    ),
+];
@@ -5,6 +5,10 @@ use std::fs;
 use std::io::Write;
 use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+mod example;
+pub mod fields;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
@@ -22,36 +26,413 @@ pub struct Config {
     pub telemetry: TelemetrySettings,
     #[serde(default)]
     pub qos: crate::qos::QosConfig,
+    #[serde(default)]
+    pub resolver: ResolverSettings,
+    #[serde(default)]
+    pub diagnostics: DiagnosticsSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     #[serde(default = "default_bind_address")]
     pub bind_address: String,
+    /// Listen on each of these addresses; when empty, `bind_address` alone is used
+    #[serde(default)]
+    pub bind_addresses: Vec<String>,
     #[serde(default = "default_bind_port")]
     pub bind_port: u16,
+    /// Set SO_REUSEPORT on the SOCKS listeners so a new process can bind the same port
+    /// while the old one drains (Unix only; ignored elsewhere)
+    #[serde(default)]
+    pub reuse_port: bool,
     #[serde(default = "default_max_connections")]
     pub max_connections: usize,
+    /// Close clients that have not finished the TLS and SOCKS handshake after this long
+    #[serde(default = "default_handshake_timeout_secs")]
+    pub handshake_timeout_secs: u64,
+    /// Handshakes allowed in progress at once; further clients are closed on accept (0 = no limit)
+    #[serde(default = "default_max_concurrent_handshakes")]
+    pub max_concurrent_handshakes: usize,
+    /// Which datagram sources a UDP ASSOCIATE accepts: "strict", "ip-only" or "learned"
+    #[serde(default = "default_udp_association_mode")]
+    pub udp_association_mode: String,
+    /// How long a BIND waits for its inbound connection before failing
+    #[serde(default = "default_bind_accept_timeout_secs")]
+    pub bind_accept_timeout_secs: u64,
+    /// Close relayed TCP sessions after this long without traffic in either direction (0 = never)
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+    /// Size of each of the two buffers a TCP tunnel relays through
+    #[serde(default = "default_relay_buffer_bytes")]
+    pub relay_buffer_bytes: usize,
+    /// Limit on each upstream connection attempt; unset keeps `pool.connect_timeout_ms`
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// Retries of an upstream connect that was refused or timed out (0 = fail at once)
+    #[serde(default)]
+    pub connect_retries: u32,
+    /// Wait before the first retry, doubled for each further one
+    #[serde(default = "default_connect_retry_backoff_ms")]
+    pub connect_retry_backoff_ms: u64,
+    /// How long shutdown waits for active sessions before closing them (0 = close at once)
+    #[serde(default = "default_shutdown_grace_period_secs")]
+    pub shutdown_grace_period_secs: u64,
+    /// Accept SOCKS4/SOCKS4a clients on the same listener
+    #[serde(default)]
+    pub enable_socks4: bool,
     #[serde(default)]
     pub tls: TlsSettings,
     #[serde(default)]
     pub pool: PoolSettings,
+    #[serde(default)]
+    pub dns: DnsSettings,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    #[serde(default)]
+    pub overload: OverloadSettings,
+    #[serde(default)]
+    pub guardrails: GuardrailSettings,
+    #[serde(default)]
+    pub udp: UdpSettings,
+    #[serde(default)]
+    pub tcp: TcpSocketSettings,
+    #[serde(default)]
+    pub tcp_keepalive: TcpKeepaliveSettings,
+    /// Keepalive overrides for tunnels to matching destinations (first match wins)
+    #[serde(default)]
+    pub tunnel_keepalive: Vec<TunnelKeepaliveSettings>,
+    /// Socket options for upstream connections to matching destinations (first match wins)
+    #[serde(default)]
+    pub upstream_socket_options: Vec<UpstreamSocketOptionSettings>,
+    /// Parent SOCKS5 proxy for CONNECTs to matching destinations
+    #[serde(default)]
+    pub upstream: UpstreamProxySettings,
+    /// TLS the proxy originates to matching destinations
+    #[serde(default)]
+    pub upstream_tls: UpstreamTlsSettings,
+    /// Source addresses for upstream traffic of matching users or groups
+    #[serde(default)]
+    pub egress: EgressSettings,
+}
+
+/// TCP options for accepted client sockets and upstream sockets (`[server.tcp]`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TcpSocketSettings {
+    /// TCP_NODELAY: send small writes at once instead of coalescing them
+    #[serde(default = "default_tcp_nodelay")]
+    pub nodelay: bool,
+    /// SO_KEEPALIVE idle time and probe interval; 0 leaves keepalive off
+    #[serde(default)]
+    pub keepalive_secs: u64,
+    /// SO_SNDBUF; 0 keeps the kernel default and its autotuning
+    #[serde(default = "default_tcp_buffer_bytes")]
+    pub send_buffer_bytes: u32,
+    /// SO_RCVBUF; 0 keeps the kernel default and its autotuning
+    #[serde(default = "default_tcp_buffer_bytes")]
+    pub recv_buffer_bytes: u32,
+}
+
+/// Longest keepalive idle time the kernels accept (TCP_KEEPIDLE on Linux)
+pub const MAX_TCP_KEEPALIVE_SECS: u64 = 32_767;
+/// Smallest `server.relay_buffer_bytes`
+pub const MIN_RELAY_BUFFER_BYTES: usize = 1024;
+/// Largest `server.relay_buffer_bytes`
+pub const MAX_RELAY_BUFFER_BYTES: usize = 1024 * 1024;
+/// Smallest non-zero socket buffer `[server.tcp]` accepts
+pub const MIN_TCP_BUFFER_BYTES: u32 = 4 * 1024;
+/// Largest socket buffer `[server.tcp]` accepts
+pub const MAX_TCP_BUFFER_BYTES: u32 = 64 * 1024 * 1024;
+
+/// Socket-level TCP keepalive (SO_KEEPALIVE) on upstream connections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TcpKeepaliveSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Idle time before the first probe
+    #[serde(default = "default_tcp_keepalive_idle_secs")]
+    pub idle_secs: u64,
+    /// Time between unanswered probes
+    #[serde(default = "default_tcp_keepalive_interval_secs")]
+    pub interval_secs: u64,
+    /// Unanswered probes before the connection is dropped
+    #[serde(default = "default_tcp_keepalive_retries")]
+    pub retries: u32,
+}
+
+/// Keepalive for idle tunnels to specific destinations (`[[server.tunnel_keepalive]]`).
+///
+/// Both modes rely on kernel keepalive probes, which carry no payload: nothing is ever
+/// written into the relayed stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelKeepaliveSettings {
+    /// Destination patterns as in ACL rules: IPs, CIDRs, domains and `*.domain` wildcards
+    pub destinations: Vec<String>,
+    pub interval_secs: u64,
+    /// "tcp" (socket keepalive override) or "probe" (probe once both directions are idle)
+    #[serde(default = "default_tunnel_keepalive_mode")]
+    pub mode: String,
+    /// TCP_USER_TIMEOUT applied while probing (Linux only); unset keeps the system default
+    #[serde(default)]
+    pub user_timeout_secs: Option<u64>,
+}
+
+/// Socket options for upstream connections to specific destinations
+/// (`[[server.upstream_socket_options]]`).
+///
+/// Options are set before the connection is opened, so matching tunnels never use
+/// pooled upstream sockets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamSocketOptionSettings {
+    /// Destination patterns as in ACL rules: IPs, CIDRs, domains and `*.domain` wildcards
+    pub destinations: Vec<String>,
+    /// Port patterns as in ACL rules: "179", "8000-8100" or "*"
+    #[serde(default = "default_upstream_socket_option_ports")]
+    pub ports: Vec<String>,
+    /// RFC 2385 TCP MD5 signature key (Linux only, at most 80 bytes)
+    #[serde(default)]
+    pub tcp_md5_password: Option<Zeroizing<String>>,
+    /// Reserved for TCP-AO (RFC 5925); rejected until it is supported
+    #[serde(default)]
+    pub tcp_ao_password: Option<Zeroizing<String>>,
+    /// DSCP code point (0-63), written to the upper six bits of IP_TOS / IPV6_TCLASS
+    #[serde(default)]
+    pub dscp: Option<u8>,
+    /// Raw IP_TOS / IPV6_TCLASS byte; mutually exclusive with `dscp`
+    #[serde(default)]
+    pub ip_tos: Option<u8>,
+}
+
+/// Source addresses of upstream connections and UDP relays by user or group
+/// (`[server.egress]`).
+///
+/// Users matching no rule keep the address the kernel picks. Every address must be
+/// assigned to this host; the server refuses to start otherwise.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EgressSettings {
+    /// First rule naming the user or one of their ACL groups wins
+    #[serde(default)]
+    pub rules: Vec<EgressRuleSettings>,
+}
+
+/// One `[[server.egress.rules]]` entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EgressRuleSettings {
+    /// Usernames, matched exactly
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// ACL groups, matched case-insensitively
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Local IPs to bind, at most one IPv4 and one IPv6
+    pub addresses: Vec<String>,
+}
+
+/// Parent SOCKS5 proxy that CONNECTs to matching destinations are chained through
+/// (`[server.upstream]`).
+///
+/// Chained destinations are not resolved locally: the name is handed to the parent,
+/// which does the DNS lookup. Everything else connects directly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamProxySettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Host name or IP of the parent proxy
+    #[serde(default)]
+    pub address: String,
+    #[serde(default = "default_upstream_proxy_port")]
+    pub port: u16,
+    /// RFC 1929 username for the parent; set together with `password`
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<Zeroizing<String>>,
+    /// Destination patterns as in ACL rules routed via the parent; the rest connect directly
+    #[serde(default = "default_upstream_proxy_destinations")]
+    pub destinations: Vec<String>,
+    /// Seconds between health checks of the parent (0 = no checks, always healthy)
+    #[serde(default = "default_upstream_health_check_interval_secs")]
+    pub health_check_interval_secs: u64,
+    /// Consecutive failed checks before the parent counts as unhealthy
+    #[serde(default = "default_upstream_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+    /// What happens to traffic through the parent once it is unhealthy: "keep",
+    /// "drain" or "migrate"
+    #[serde(default = "default_upstream_on_unhealthy")]
+    pub on_unhealthy: String,
+    /// With `on_unhealthy = "drain"`, how long chained sessions keep relaying before
+    /// they are closed
+    #[serde(default = "default_upstream_drain_grace_secs")]
+    pub drain_grace_secs: u64,
+}
+
+/// TLS originated by the proxy on upstream connections to matching destinations
+/// (`[server.upstream_tls]`).
+///
+/// The client speaks plain TCP to the proxy; the upstream connection is wrapped in TLS
+/// before the relay starts, verified against `ca_path` and, when a client certificate is
+/// set, authenticated with it. Other destinations keep plain TCP.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamTlsSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Destination patterns as in ACL rules: IPs, CIDRs, domains and `*.domain` wildcards
+    #[serde(default)]
+    pub destinations: Vec<String>,
+    /// Port patterns as in ACL rules: "443", "8000-8100" or "*"
+    #[serde(default = "default_upstream_tls_ports")]
+    pub ports: Vec<String>,
+    /// PEM bundle of the CAs destination certificates must chain to
+    #[serde(default)]
+    pub ca_path: Option<String>,
+    /// PEM client certificate presented to destinations; set together with `client_key_path`
+    #[serde(default)]
+    pub client_certificate_path: Option<String>,
+    /// PKCS#8 or RSA private key of the client certificate
+    #[serde(default)]
+    pub client_key_path: Option<String>,
+    /// Name sent as SNI and verified against the certificate instead of the requested host
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+/// Load shedding of new connections while the proxy is overloaded.
+///
+/// Shedding engages when the signal reaches `engage_threshold` and stays on until it
+/// falls below `release_threshold`. While engaged, the rejected fraction of new
+/// connections ramps from `min_reject_ratio` at `engage_threshold` to
+/// `max_reject_ratio` at `full_threshold`. Established sessions are never throttled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverloadSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// "cpu" (system CPU %) or "runtime_queue" (tasks waiting in the runtime's global queue)
+    #[serde(default = "default_overload_signal")]
+    pub signal: String,
+    #[serde(default = "default_overload_engage_threshold")]
+    pub engage_threshold: f64,
+    #[serde(default = "default_overload_release_threshold")]
+    pub release_threshold: f64,
+    #[serde(default = "default_overload_full_threshold")]
+    pub full_threshold: f64,
+    #[serde(default = "default_overload_min_reject_ratio")]
+    pub min_reject_ratio: f64,
+    #[serde(default = "default_overload_max_reject_ratio")]
+    pub max_reject_ratio: f64,
+    #[serde(default = "default_overload_sample_interval_ms")]
+    pub sample_interval_ms: u64,
+}
+
+/// File descriptor and memory watermarks of the whole process.
+///
+/// At a soft watermark new connections are rejected right after accept, like load
+/// shedding. At a hard watermark idle pooled connections and idle UDP associations are
+/// closed as well. Unset fd watermarks default to a share of RLIMIT_NOFILE, and both
+/// are kept at least `fd_reserve` below it so the API and session store can still open
+/// descriptors. Memory watermarks apply only when set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardrailSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Open descriptors at which new connections are rejected (default 80% of the limit)
+    #[serde(default)]
+    pub fd_soft_limit: Option<u64>,
+    /// Open descriptors at which idle connections are reclaimed (default 90% of the limit)
+    #[serde(default)]
+    pub fd_hard_limit: Option<u64>,
+    /// Descriptors never handed to SOCKS clients
+    #[serde(default = "default_guardrails_fd_reserve")]
+    pub fd_reserve: u64,
+    #[serde(default)]
+    pub memory_soft_limit_mb: Option<u64>,
+    #[serde(default)]
+    pub memory_hard_limit_mb: Option<u64>,
+    /// UDP associations without traffic for this long are reclaimed at the hard watermark
+    #[serde(default = "default_guardrails_udp_idle_secs")]
+    pub udp_idle_secs: u64,
+    #[serde(default = "default_guardrails_sample_interval_ms")]
+    pub sample_interval_ms: u64,
+}
+
+/// Datagram handling of UDP ASSOCIATE relays (`[server.udp]`).
+///
+/// RFC 1928 leaves fragmentation optional. Without `enable_fragments`, datagrams with a
+/// non-zero FRAG field are dropped and counted; with it, each association reassembles a
+/// few of them at a time and drops those whose fragments do not all arrive in time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UdpSettings {
+    #[serde(default)]
+    pub enable_fragments: bool,
+    /// Fragmented datagrams one association reassembles at once
+    #[serde(default = "default_udp_max_reassembly_queues")]
+    pub max_reassembly_queues: usize,
+    /// Time from the first fragment of a datagram until its last must have arrived
+    #[serde(default = "default_udp_reassembly_timeout_ms")]
+    pub reassembly_timeout_ms: u64,
+    /// Largest client datagram (SOCKS header included) or destination reply relayed;
+    /// also the size of each association's receive buffer
+    #[serde(default = "default_udp_max_datagram_size")]
+    pub max_datagram_size: usize,
+    /// An association without datagrams from its client or destinations for this long
+    /// is closed with its control connection
+    #[serde(default = "default_udp_association_idle_timeout_secs")]
+    pub association_idle_timeout_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PoolSettings {
     #[serde(default)]
     pub enabled: bool,
-    #[serde(default = "default_pool_max_idle_per_dest")]
+    #[serde(
+        default = "default_pool_max_idle_per_dest",
+        alias = "max_per_destination"
+    )]
     pub max_idle_per_dest: usize,
     #[serde(default = "default_pool_max_total_idle")]
     pub max_total_idle: usize,
-    #[serde(default = "default_pool_idle_timeout_secs")]
+    #[serde(default = "default_pool_idle_timeout_secs", alias = "idle_ttl_secs")]
     pub idle_timeout_secs: u64,
     #[serde(default = "default_pool_connect_timeout_ms")]
     pub connect_timeout_ms: u64,
 }
 
+/// In-process cache of destination lookups (`[server.dns]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsSettings {
+    /// How long resolved addresses are reused (0 = no cache)
+    #[serde(default)]
+    pub cache_ttl_secs: u64,
+    /// How long a name that does not exist is remembered (0 = not at all)
+    #[serde(default = "default_dns_negative_ttl_secs")]
+    pub negative_ttl_secs: u64,
+    #[serde(default = "default_dns_cache_max_entries")]
+    pub cache_max_entries: usize,
+    /// Address family order for upstream connects: "prefer_ipv6", "prefer_ipv4",
+    /// "ipv4_only", "ipv6_only" or "happy_eyeballs"
+    #[serde(default = "default_dns_strategy")]
+    pub strategy: String,
+    /// With "happy_eyeballs", how long an attempt runs before the next address joins it
+    #[serde(default = "default_dns_happy_eyeballs_delay_ms")]
+    pub happy_eyeballs_delay_ms: u64,
+    /// Whether clients may send domain names; when false, CONNECT and UDP ASSOCIATE
+    /// requests for a name get reply 0x08 before anything is resolved
+    #[serde(default = "default_dns_allow_domain_requests")]
+    pub allow_domain_requests: bool,
+}
+
+/// Per-client-IP limit on new connections, checked right after accept (`[server.rate_limit]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitSettings {
+    /// New connections one client IP may open in any 60-second window (0 = no limit)
+    #[serde(default)]
+    pub connections_per_ip_per_minute: u32,
+    /// At most one warning per this many seconds for dropped connections (0 = no warnings)
+    #[serde(default = "default_rate_limit_log_interval_secs")]
+    pub log_interval_secs: u64,
+    /// Warn on the first dropped connection of each client IP per window instead
+    #[serde(default)]
+    pub warn_per_client: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TlsSettings {
     #[serde(default = "default_tls_enabled")]
@@ -75,21 +456,116 @@ pub struct TlsSettings {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthConfig {
     #[serde(default = "default_client_method")]
-    pub client_method: String, // "none", "pam.address"
+    pub client_method: String, // "none", "pam.address", "tls.cert"
     #[serde(default = "default_socks_method", alias = "method")]
-    pub socks_method: String, // "none", "userpass", "pam.address", "pam.username", "gssapi"
+    pub socks_method: String, // "none", "userpass", "pam.address", "pam.username", "ldap", "gssapi"
+    /// SOCKS methods to accept, most preferred first; replaces `socks_method` when set.
+    /// The server picks the first entry the client also offered.
+    #[serde(default)]
+    pub socks_method_preference: Vec<String>,
     #[serde(default)]
     pub users: Vec<User>,
+    /// TOML file of `[[users]]` tables used instead of `users`; watched and reloaded
+    /// when it changes
+    #[serde(default)]
+    pub users_file: Option<String>,
     #[serde(default)]
     pub pam: PamSettings,
     #[serde(default)]
     pub gssapi: GssApiSettings,
+    #[serde(default)]
+    pub ldap: LdapSettings,
+    /// Client CIDRs accepted without consulting PAM (pam.address only)
+    #[serde(default)]
+    pub client_allow: Vec<String>,
+    /// Client CIDRs rejected without consulting PAM (pam.address only)
+    #[serde(default)]
+    pub client_deny: Vec<String>,
+    #[serde(default)]
+    pub impersonation: ImpersonationSettings,
+    #[serde(default)]
+    pub password_hashing: PasswordHashSettings,
+    /// Accept `user<correlation_separator><id>` logins (userpass/pam.username); the
+    /// suffix is stripped before authentication and recorded on the session
+    #[serde(default)]
+    pub allow_correlation_suffix: bool,
+    #[serde(default = "default_correlation_separator")]
+    pub correlation_separator: String,
+    /// Client certificate field used as the username with `client_method = "tls.cert"`:
+    /// "cn", "san.dns", "san.email" or "san.uri"
+    #[serde(default = "default_tls_cert_identity")]
+    pub tls_cert_identity: String,
 }
 
+/// A login for `[[auth.users]]` or `[[sessions.dashboard_auth.users]]`; exactly one
+/// of `password` and `password_hash` is set.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub username: String,
-    pub password: String,
+    /// Deprecated plaintext password (an `$argon2id$...` hash is also accepted here);
+    /// empty when unset, wiped from memory on drop
+    #[serde(default, skip_serializing_if = "is_unset")]
+    pub password: Zeroizing<String>,
+    /// `$argon2id$...` hash, e.g. from `rustsocks hash-password`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+}
+
+impl User {
+    /// Whether the password is given as plaintext rather than as a hash
+    pub fn has_plaintext_password(&self) -> bool {
+        self.password_hash.is_none() && !self.password.starts_with(crate::auth::HASH_PREFIX)
+    }
+
+    /// Exactly one of `password` and `password_hash`, and a hash that parses
+    pub(crate) fn validate(&self, section: &str) -> Result<()> {
+        if self.username.trim().is_empty() {
+            return Err(RustSocksError::Config(format!(
+                "{} user username cannot be empty",
+                section
+            )));
+        }
+        let has_password = !self.password.trim().is_empty();
+        match (&self.password_hash, has_password) {
+            (Some(_), true) => Err(RustSocksError::Config(format!(
+                "{} user '{}' sets both password and password_hash",
+                section, self.username
+            ))),
+            (None, false) => Err(RustSocksError::Config(format!(
+                "{} user '{}' needs a password or a password_hash",
+                section, self.username
+            ))),
+            (Some(hash), false) => crate::auth::PasswordHash::parse_configured(hash)
+                .map(|_| ())
+                .map_err(|e| {
+                    RustSocksError::Config(format!("{} user '{}': {}", section, self.username, e))
+                }),
+            (None, true) => Ok(()),
+        }
+    }
+}
+
+fn is_unset(password: &Zeroizing<String>) -> bool {
+    password.is_empty()
+}
+
+/// argon2id cost used by the userpass backend to hash plaintext `[[auth.users]]`
+/// passwords at load.
+///
+/// Every login costs one derivation with these parameters, so raising them trades
+/// authentication latency for resistance to offline guessing. The defaults are the
+/// OWASP minimum for argon2id (19 MiB, 2 passes, 1 lane).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordHashSettings {
+    /// Memory per derivation in KiB
+    #[serde(default = "default_password_hash_memory_kib")]
+    pub memory_kib: u32,
+    /// Passes over memory (pre-hashed entries keep their own parameters)
+    #[serde(default = "default_password_hash_iterations")]
+    pub iterations: u32,
+    /// Lanes
+    #[serde(default = "default_password_hash_parallelism")]
+    pub parallelism: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,6 +582,28 @@ pub struct PamSettings {
     pub verbose: bool,
     #[serde(default)]
     pub verify_service: bool,
+    /// Seconds to cache a successful pam.address verdict per client IP (0 = disabled)
+    #[serde(default)]
+    pub address_cache_secs: u64,
+    /// Seconds to cache a rejected pam.address verdict per client IP (0 = disabled)
+    #[serde(default)]
+    pub negative_cache_secs: u64,
+}
+
+/// Service accounts that may act on behalf of other users.
+///
+/// A userpass/pam.username/ldap login of `principal<separator>target` authenticates
+/// `principal` and attributes the session to `target`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpersonationSettings {
+    /// Principals allowed to impersonate; empty disables the feature
+    #[serde(default)]
+    pub allowed_principals: Vec<String>,
+    /// Target users they may act as (`*` matches any run of characters)
+    #[serde(default = "default_impersonation_target_pattern")]
+    pub allowed_target_pattern: String,
+    #[serde(default = "default_impersonation_separator")]
+    pub separator: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,12 +618,61 @@ pub struct GssApiSettings {
     pub verbose: bool,
 }
 
+/// `ldap`: a simple bind as the user checks the password, with the `ldap` feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LdapSettings {
+    /// `ldap://host:389` or `ldaps://host:636`
+    #[serde(default)]
+    pub url: String,
+    /// DN to bind as; `{username}` is replaced by the escaped login name
+    #[serde(default)]
+    pub bind_dn_template: String,
+    /// Upgrade an `ldap://` connection with StartTLS before binding
+    #[serde(default)]
+    pub starttls: bool,
+    /// Limit on connecting, binding and the group search together
+    #[serde(default = "default_ldap_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Where to search for the user's groups; unset takes them from the system
+    #[serde(default)]
+    pub group_search_base: Option<String>,
+    /// Group filter; `{dn}` is the bound DN and `{username}` the login name
+    #[serde(default = "default_ldap_group_filter")]
+    pub group_filter: String,
+    /// Attribute of a group entry used as the group name
+    #[serde(default = "default_ldap_group_attribute")]
+    pub group_attribute: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
     #[serde(default = "default_log_level")]
     pub level: String,
     #[serde(default = "default_log_format")]
     pub format: String, // "json" or "pretty"
+    #[serde(default)]
+    pub sampling: LogSamplingSettings,
+}
+
+/// Sampling of the info-level events every client connection writes
+/// (`[logging.sampling]`); warnings, errors and the access log are always kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogSamplingSettings {
+    /// Share of connections whose events are logged, 0.0 to 1.0 (1.0 = all)
+    #[serde(default = "default_log_connection_events_ratio")]
+    pub connection_events_ratio: f64,
+    /// Users whose connections are always logged in full once they logged in
+    #[serde(default)]
+    pub always_log_users: Vec<String>,
+}
+
+impl Default for LogSamplingSettings {
+    fn default() -> Self {
+        Self {
+            connection_events_ratio: default_log_connection_events_ratio(),
+            always_log_users: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -136,8 +683,59 @@ pub struct AclSettings {
     pub config_file: Option<String>,
     #[serde(default = "default_acl_watch")]
     pub watch: bool,
+    /// Also reload the rules when a `file:` domain list they reference changes
+    #[serde(default)]
+    pub watch_domain_lists: bool,
     #[serde(default = "default_acl_anonymous_user")]
     pub anonymous_user: String,
+    /// "enforce" refuses blocked connections; "monitor" logs and counts them as blocks
+    /// but relays them, marking their sessions `would_block`
+    #[serde(default = "default_acl_mode")]
+    pub mode: String,
+    /// Use the TLS SNI of CONNECT-by-IP sessions as their logical destination
+    #[serde(default = "default_acl_classify_by_sni")]
+    pub classify_by_sni: bool,
+    #[serde(default = "default_acl_sni_peek_timeout_ms")]
+    pub sni_peek_timeout_ms: u64,
+    /// Destination ports peeked for a ClientHello when classify_by_sni is enabled
+    #[serde(default = "default_acl_sni_ports")]
+    pub sni_ports: Vec<u16>,
+    #[serde(default = "default_acl_sni_fail_mode")]
+    pub sni_fail_mode: String, // "block" or "allow"
+    /// How often per-rule hit counters are persisted
+    #[serde(default = "default_acl_rule_stats_flush_interval_secs")]
+    pub rule_stats_flush_interval_secs: u64,
+    /// Persist rule counters to `<config_file>.stats.json` when there is no session database
+    #[serde(default)]
+    pub rule_stats_sidecar: bool,
+    /// How often connections of `log = "minimal"` rules are written as aggregated records
+    #[serde(default = "default_acl_minimal_log_interval_secs")]
+    pub minimal_log_interval_secs: u64,
+    /// Refuse to load an ACL file whose users reference undefined groups
+    #[serde(default)]
+    pub strict_references: bool,
+    /// Group names (`*` wildcards) that come from the system group database; ACL
+    /// groups matching one are not reported as orphans
+    #[serde(default)]
+    pub system_group_patterns: Vec<String>,
+    #[serde(default)]
+    pub cache: AclCacheSettings,
+}
+
+/// Cache of ACL decisions per user, destination, port and protocol (`[acl.cache]`).
+///
+/// The whole cache is dropped whenever the ACL configuration is reloaded, from the
+/// file watcher or the management API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AclCacheSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How long a cached decision is reused
+    #[serde(default = "default_acl_cache_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Decisions kept before the least recently used are evicted
+    #[serde(default = "default_acl_cache_max_entries")]
+    pub max_entries: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -152,28 +750,80 @@ pub struct SessionSettings {
     pub batch_size: usize,
     #[serde(default = "default_session_batch_interval_ms")]
     pub batch_interval_ms: u64,
+    #[serde(default = "default_session_batch_adaptive")]
+    pub batch_adaptive: bool,
+    #[serde(default = "default_session_batch_min_size")]
+    pub batch_min_size: usize,
+    #[serde(default = "default_session_batch_max_size")]
+    pub batch_max_size: usize,
+    #[serde(default = "default_session_batch_target_flush_ms")]
+    pub batch_target_flush_ms: u64,
+    /// Sessions waiting for the store before `batch_overflow_policy` applies
+    #[serde(default = "default_session_batch_queue_max")]
+    pub batch_queue_max: usize,
+    /// "block", "drop_oldest" or "drop_newest"
+    #[serde(default = "default_session_batch_overflow_policy")]
+    pub batch_overflow_policy: String,
     #[serde(default = "default_session_retention_days")]
     pub retention_days: u64,
     #[serde(default = "default_session_cleanup_interval_hours")]
     pub cleanup_interval_hours: u64,
+    /// Expired sessions the retention cleanup deletes per transaction
+    #[serde(default = "default_session_cleanup_batch_size")]
+    pub cleanup_batch_size: usize,
+    /// Let several instances write one SQLite file: startup close and cleanup only
+    /// touch this instance's rows. Without it a second live instance refuses to start.
+    #[serde(default)]
+    pub allow_shared_database: bool,
+    /// Heartbeat age after which another instance's store lock is taken over
+    #[serde(default = "default_session_instance_lock_stale_secs")]
+    pub instance_lock_stale_secs: u64,
     #[serde(default = "default_session_traffic_update_packet_interval")]
     pub traffic_update_packet_interval: u64,
+    /// Traffic and throttle events on `/api/ws` come at most once per session per this
+    /// many seconds
+    #[serde(default = "default_session_live_traffic_interval_secs")]
+    pub live_traffic_interval_secs: u64,
     #[serde(default = "default_stats_window_hours")]
     pub stats_window_hours: u64,
+    /// How long a client's hostname resolution is remembered for CONNECT-by-IP (0 = off)
+    #[serde(default = "default_requested_host_ttl_secs")]
+    pub requested_host_ttl_secs: u64,
+    /// Closed (and, separately, rejected) sessions kept in memory, oldest evicted first
+    /// (0 = unbounded). Active sessions are never evicted.
+    #[serde(default = "default_session_memory_max_sessions")]
+    pub memory_max_sessions: usize,
     #[serde(default = "default_stats_api_enabled")]
     pub stats_api_enabled: bool,
     #[serde(default = "default_stats_api_bind_address")]
     pub stats_api_bind_address: String,
     #[serde(default = "default_stats_api_port")]
     pub stats_api_port: u16,
+    /// `unix:<path>` serves the API on a Unix domain socket instead of
+    /// `stats_api_bind_address:stats_api_port`
+    #[serde(default)]
+    pub stats_api_bind: Option<String>,
+    /// Octal permissions of the API socket file
+    #[serde(default = "default_api_socket_mode")]
+    pub api_socket_mode: String,
     #[serde(default = "default_swagger_enabled")]
     pub swagger_enabled: bool,
     #[serde(default = "default_dashboard_enabled")]
     pub dashboard_enabled: bool,
     #[serde(default)]
     pub dashboard_auth: DashboardAuthSettings,
+    #[serde(default)]
+    pub api_auth: ApiAuthSettings,
+    #[serde(default)]
+    pub api_compression: ApiCompressionSettings,
     #[serde(default = "default_base_path")]
     pub base_path: String,
+    /// Serve the unauthenticated coarse status page at /status and /status.json
+    #[serde(default)]
+    pub public_status_enabled: bool,
+    /// Shown on the status page, which then reports the "maintenance" state
+    #[serde(default)]
+    pub maintenance_message: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -192,18 +842,84 @@ pub struct DashboardAuthSettings {
     pub session_duration_hours: u64,
 }
 
+/// Static API keys for the stats API (`[sessions.api_auth]`)
+///
+/// When enabled, every API request needs a key (`Authorization: Bearer <token>` or
+/// `X-API-Key: <token>`) or a dashboard login. "read" keys reach the GET endpoints;
+/// changes (ACL edits, reloads, session termination) and `/api/admin/*` need "admin".
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApiAuthSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// At least 16 characters; wiped from memory on drop
+    pub token: Zeroizing<String>,
+    /// "read" or "admin"
+    #[serde(default = "default_api_key_role")]
+    pub role: String,
+    /// Shown in logs instead of the token
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl ApiKey {
+    pub fn is_admin(&self) -> bool {
+        self.role == "admin"
+    }
+}
+
+/// Compression of stats API responses (`[sessions.api_compression]`)
+///
+/// gzip or deflate, whichever the client's `Accept-Encoding` prefers. Event streams,
+/// images and bodies under `min_size_bytes` are sent as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiCompressionSettings {
+    #[serde(default = "default_api_compression_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_api_compression_min_size_bytes")]
+    pub min_size_bytes: u16,
+    /// Paths (below `base_path`) always sent uncompressed, e.g. for scrapers that
+    /// mishandle `Content-Encoding`
+    #[serde(default = "default_api_compression_exclude_paths")]
+    pub exclude_paths: Vec<String>,
+}
+
+impl Default for ApiCompressionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: default_api_compression_enabled(),
+            min_size_bytes: default_api_compression_min_size_bytes(),
+            exclude_paths: default_api_compression_exclude_paths(),
+        }
+    }
+}
+
+/// Shortest accepted `sessions.api_auth.keys` token
+pub const MIN_API_KEY_LEN: usize = 16;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricsSettings {
     #[serde(default = "default_metrics_enabled")]
     pub enabled: bool,
     #[serde(default = "default_metrics_storage")]
-    pub storage: String, // "memory" or "sqlite"
+    pub storage: String, // "memory" or "database" ("sqlite" is an alias)
     #[serde(default = "default_metrics_retention_hours")]
     pub retention_hours: u64,
+    /// How long 1-minute and 15-minute rollups outlive the raw samples; 0 keeps none.
+    #[serde(default = "default_metrics_rollup_retention_days")]
+    pub rollup_retention_days: u64,
     #[serde(default = "default_metrics_cleanup_interval_hours")]
     pub cleanup_interval_hours: u64,
     #[serde(default = "default_metrics_collection_interval_secs")]
     pub collection_interval_secs: u64,
+    /// Widest time range a single /api/metrics/history request may scan.
+    #[serde(default = "default_metrics_history_max_range_hours")]
+    pub history_max_range_hours: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -216,6 +932,70 @@ pub struct TelemetrySettings {
     pub retention_hours: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ResolverSettings {
+    #[serde(default)]
+    pub special_names: SpecialNamesSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecialNamesSettings {
+    #[serde(default = "default_special_names_localhost")]
+    pub localhost: String, // "block" or "allow"
+    #[serde(default = "default_special_names_local")]
+    pub local: String, // "block" or "resolve"
+}
+
+/// Proactive upstream connectivity probes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsSettings {
+    /// User the probes connect as; ACL rules, egress and the parent proxy apply as for
+    /// a client logged in with this name
+    #[serde(default = "default_probe_user")]
+    pub probe_user: String,
+    /// Groups of `probe_user` for ACL group rules
+    #[serde(default)]
+    pub probe_groups: Vec<String>,
+    /// Results kept per probe for `GET /api/diagnostics/probes`
+    #[serde(default = "default_probe_history")]
+    pub probe_history: usize,
+    #[serde(default)]
+    pub probes: Vec<ProbeTarget>,
+}
+
+/// One `[[diagnostics.probes]]` destination
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeTarget {
+    /// Label in the API and the `probe` metric label; defaults to `host:port`
+    #[serde(default)]
+    pub name: Option<String>,
+    pub host: String,
+    pub port: u16,
+    #[serde(default = "default_probe_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl ProbeTarget {
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None if self.host.contains(':') => format!("[{}]:{}", self.host, self.port),
+            None => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+impl Default for DiagnosticsSettings {
+    fn default() -> Self {
+        Self {
+            probe_user: default_probe_user(),
+            probe_groups: Vec::new(),
+            probe_history: default_probe_history(),
+            probes: Vec::new(),
+        }
+    }
+}
+
 impl Default for TelemetrySettings {
     fn default() -> Self {
         Self {
@@ -239,6 +1019,34 @@ fn default_max_connections() -> usize {
     1000
 }
 
+fn default_handshake_timeout_secs() -> u64 {
+    crate::server::DEFAULT_HANDSHAKE_TIMEOUT.as_secs()
+}
+
+fn default_max_concurrent_handshakes() -> usize {
+    1000
+}
+
+fn default_udp_association_mode() -> String {
+    "strict".to_string()
+}
+
+fn default_bind_accept_timeout_secs() -> u64 {
+    crate::server::DEFAULT_BIND_ACCEPT_TIMEOUT.as_secs()
+}
+
+fn default_connect_retry_backoff_ms() -> u64 {
+    crate::server::DEFAULT_CONNECT_RETRY_BACKOFF.as_millis() as u64
+}
+
+fn default_shutdown_grace_period_secs() -> u64 {
+    30
+}
+
+fn default_relay_buffer_bytes() -> usize {
+    crate::server::DEFAULT_RELAY_BUFFER_BYTES
+}
+
 fn default_tls_enabled() -> bool {
     false
 }
@@ -271,6 +1079,34 @@ fn default_pam_default_ruser() -> String {
     "rhostusr".to_string()
 }
 
+fn default_password_hash_memory_kib() -> u32 {
+    19 * 1024
+}
+
+fn default_password_hash_iterations() -> u32 {
+    2
+}
+
+fn default_password_hash_parallelism() -> u32 {
+    1
+}
+
+fn default_impersonation_target_pattern() -> String {
+    "*".to_string()
+}
+
+fn default_impersonation_separator() -> String {
+    ":".to_string()
+}
+
+fn default_correlation_separator() -> String {
+    "#".to_string()
+}
+
+fn default_tls_cert_identity() -> String {
+    "cn".to_string()
+}
+
 fn default_gssapi_service_name() -> String {
     "socks".to_string()
 }
@@ -279,6 +1115,18 @@ fn default_gssapi_protection_level() -> String {
     "integrity".to_string()
 }
 
+fn default_ldap_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_ldap_group_filter() -> String {
+    "(member={dn})".to_string()
+}
+
+fn default_ldap_group_attribute() -> String {
+    "cn".to_string()
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -287,6 +1135,10 @@ fn default_log_format() -> String {
     "pretty".to_string()
 }
 
+fn default_log_connection_events_ratio() -> f64 {
+    1.0
+}
+
 fn default_acl_enabled() -> bool {
     false
 }
@@ -299,58 +1151,163 @@ fn default_acl_anonymous_user() -> String {
     "anonymous".to_string()
 }
 
-fn default_sessions_enabled() -> bool {
+fn default_acl_mode() -> String {
+    "enforce".to_string()
+}
+
+fn default_acl_classify_by_sni() -> bool {
     false
 }
 
-fn default_session_storage() -> String {
-    "memory".to_string()
+fn default_acl_sni_peek_timeout_ms() -> u64 {
+    250
 }
 
-fn default_session_batch_size() -> usize {
-    100
+fn default_acl_sni_ports() -> Vec<u16> {
+    vec![443]
 }
 
-fn default_session_batch_interval_ms() -> u64 {
-    1000
+fn default_acl_sni_fail_mode() -> String {
+    "block".to_string()
 }
 
-fn default_session_retention_days() -> u64 {
-    90
+fn default_acl_rule_stats_flush_interval_secs() -> u64 {
+    300
 }
 
-fn default_session_cleanup_interval_hours() -> u64 {
-    24
+fn default_acl_minimal_log_interval_secs() -> u64 {
+    60
 }
 
-fn default_session_traffic_update_packet_interval() -> u64 {
-    10
+fn default_acl_cache_ttl_secs() -> u64 {
+    30
 }
 
-fn default_stats_window_hours() -> u64 {
-    24
+fn default_acl_cache_max_entries() -> usize {
+    10_000
 }
 
-fn default_stats_api_enabled() -> bool {
+fn default_sessions_enabled() -> bool {
     false
 }
 
-fn default_stats_api_bind_address() -> String {
-    "127.0.0.1".to_string()
+fn default_session_storage() -> String {
+    "memory".to_string()
 }
 
-fn default_stats_api_port() -> u16 {
-    9090
+fn default_session_batch_size() -> usize {
+    100
 }
 
-fn default_swagger_enabled() -> bool {
-    true
+fn default_session_batch_interval_ms() -> u64 {
+    1000
+}
+
+fn default_session_batch_adaptive() -> bool {
+    true
+}
+
+fn default_session_batch_min_size() -> usize {
+    10
+}
+
+fn default_session_batch_max_size() -> usize {
+    1000
+}
+
+fn default_session_batch_target_flush_ms() -> u64 {
+    50
+}
+
+fn default_session_batch_queue_max() -> usize {
+    100_000
+}
+
+fn default_session_batch_overflow_policy() -> String {
+    "block".to_string()
+}
+
+fn default_session_retention_days() -> u64 {
+    90
+}
+
+fn default_session_cleanup_interval_hours() -> u64 {
+    24
+}
+
+fn default_session_cleanup_batch_size() -> usize {
+    5000
+}
+
+/// The session IDs of one cleanup batch are bound to a single statement, and SQLite
+/// takes at most 32766 parameters
+const MAX_SESSION_CLEANUP_BATCH_SIZE: usize = 30_000;
+
+/// `sessions.database_url` prefixes the session store can connect to
+const SESSION_DATABASE_SCHEMES: &[&str] = &[
+    "sqlite:",
+    "mysql://",
+    "mariadb://",
+    "postgres://",
+    "postgresql://",
+];
+
+/// Lower bound of `sessions.instance_lock_stale_secs`, so heartbeats stay at least a
+/// couple of seconds apart
+pub const MIN_INSTANCE_LOCK_STALE_SECS: u64 = 10;
+
+fn default_session_instance_lock_stale_secs() -> u64 {
+    60
+}
+
+fn default_session_traffic_update_packet_interval() -> u64 {
+    10
+}
+
+fn default_session_live_traffic_interval_secs() -> u64 {
+    1
+}
+
+fn default_stats_window_hours() -> u64 {
+    24
+}
+
+fn default_requested_host_ttl_secs() -> u64 {
+    300
+}
+
+fn default_session_memory_max_sessions() -> usize {
+    100_000
+}
+
+fn default_stats_api_enabled() -> bool {
+    false
+}
+
+fn default_stats_api_bind_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_stats_api_port() -> u16 {
+    9090
+}
+
+fn default_api_socket_mode() -> String {
+    "0660".to_string()
+}
+
+fn default_swagger_enabled() -> bool {
+    true
 }
 
 fn default_dashboard_enabled() -> bool {
     false
 }
 
+fn default_api_key_role() -> String {
+    "read".to_string()
+}
+
 fn default_dashboard_auth_enabled() -> bool {
     false
 }
@@ -372,6 +1329,18 @@ fn default_session_duration_hours() -> u64 {
     24
 }
 
+fn default_api_compression_enabled() -> bool {
+    true
+}
+
+fn default_api_compression_min_size_bytes() -> u16 {
+    1024
+}
+
+fn default_api_compression_exclude_paths() -> Vec<String> {
+    vec!["/metrics".to_string()]
+}
+
 fn default_base_path() -> String {
     "/".to_string()
 }
@@ -388,6 +1357,10 @@ fn default_metrics_retention_hours() -> u64 {
     24
 }
 
+fn default_metrics_rollup_retention_days() -> u64 {
+    30
+}
+
 fn default_metrics_cleanup_interval_hours() -> u64 {
     6
 }
@@ -396,6 +1369,10 @@ fn default_metrics_collection_interval_secs() -> u64 {
     5
 }
 
+fn default_metrics_history_max_range_hours() -> u64 {
+    24
+}
+
 fn default_telemetry_enabled() -> bool {
     true
 }
@@ -408,6 +1385,27 @@ fn default_telemetry_retention_hours() -> u64 {
     6
 }
 
+fn default_probe_user() -> String {
+    "probe".to_string()
+}
+
+fn default_probe_history() -> usize {
+    60
+}
+
+fn default_probe_interval_secs() -> u64 {
+    30
+}
+
+fn default_special_names_localhost() -> String {
+    // Loopback is the classic SSRF target: opt in explicitly to reach services on the proxy host
+    "block".to_string()
+}
+
+fn default_special_names_local() -> String {
+    "block".to_string()
+}
+
 fn default_pool_max_idle_per_dest() -> usize {
     4
 }
@@ -424,6 +1422,144 @@ fn default_pool_connect_timeout_ms() -> u64 {
     5000
 }
 
+fn default_dns_negative_ttl_secs() -> u64 {
+    5
+}
+
+fn default_dns_strategy() -> String {
+    crate::server::resolver::DnsStrategy::default()
+        .as_str()
+        .to_string()
+}
+
+fn default_dns_happy_eyeballs_delay_ms() -> u64 {
+    crate::server::resolver::DEFAULT_HAPPY_EYEBALLS_DELAY_MS
+}
+
+fn default_dns_allow_domain_requests() -> bool {
+    true
+}
+
+fn default_dns_cache_max_entries() -> usize {
+    10_000
+}
+
+fn default_rate_limit_log_interval_secs() -> u64 {
+    10
+}
+
+fn default_overload_signal() -> String {
+    "cpu".to_string()
+}
+
+fn default_overload_engage_threshold() -> f64 {
+    90.0
+}
+
+fn default_overload_release_threshold() -> f64 {
+    75.0
+}
+
+fn default_overload_full_threshold() -> f64 {
+    99.0
+}
+
+fn default_overload_min_reject_ratio() -> f64 {
+    0.1
+}
+
+fn default_overload_max_reject_ratio() -> f64 {
+    0.9
+}
+
+fn default_guardrails_fd_reserve() -> u64 {
+    64
+}
+
+fn default_guardrails_udp_idle_secs() -> u64 {
+    30
+}
+
+fn default_guardrails_sample_interval_ms() -> u64 {
+    500
+}
+
+fn default_udp_max_reassembly_queues() -> usize {
+    4
+}
+
+fn default_udp_reassembly_timeout_ms() -> u64 {
+    5_000 // RFC 1928: no less than 5 seconds
+}
+
+fn default_udp_max_datagram_size() -> usize {
+    65_507 // Largest UDP payload over IPv4
+}
+
+fn default_udp_association_idle_timeout_secs() -> u64 {
+    120
+}
+
+fn default_overload_sample_interval_ms() -> u64 {
+    1000
+}
+
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+fn default_tcp_buffer_bytes() -> u32 {
+    256 * 1024
+}
+
+fn default_tcp_keepalive_idle_secs() -> u64 {
+    300
+}
+
+fn default_tcp_keepalive_interval_secs() -> u64 {
+    30
+}
+
+fn default_tcp_keepalive_retries() -> u32 {
+    4
+}
+
+fn default_upstream_socket_option_ports() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_upstream_proxy_port() -> u16 {
+    1080
+}
+
+fn default_upstream_proxy_destinations() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_upstream_health_check_interval_secs() -> u64 {
+    10
+}
+
+fn default_upstream_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_upstream_on_unhealthy() -> String {
+    "keep".to_string()
+}
+
+fn default_upstream_drain_grace_secs() -> u64 {
+    30
+}
+
+fn default_upstream_tls_ports() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_tunnel_keepalive_mode() -> String {
+    "tcp".to_string()
+}
+
 fn normalize_base_path(raw: &str) -> String {
     let trimmed = raw.trim();
     if trimmed.is_empty() || trimmed == "/" {
@@ -450,10 +1586,172 @@ impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             bind_address: default_bind_address(),
+            bind_addresses: Vec::new(),
             bind_port: default_bind_port(),
+            reuse_port: false,
             max_connections: default_max_connections(),
+            handshake_timeout_secs: default_handshake_timeout_secs(),
+            max_concurrent_handshakes: default_max_concurrent_handshakes(),
+            udp_association_mode: default_udp_association_mode(),
+            bind_accept_timeout_secs: default_bind_accept_timeout_secs(),
+            idle_timeout_secs: 0,
+            relay_buffer_bytes: default_relay_buffer_bytes(),
+            connect_timeout_secs: None,
+            connect_retries: 0,
+            connect_retry_backoff_ms: default_connect_retry_backoff_ms(),
+            shutdown_grace_period_secs: default_shutdown_grace_period_secs(),
+            enable_socks4: false,
             tls: TlsSettings::default(),
             pool: PoolSettings::default(),
+            dns: DnsSettings::default(),
+            rate_limit: RateLimitSettings::default(),
+            overload: OverloadSettings::default(),
+            guardrails: GuardrailSettings::default(),
+            udp: UdpSettings::default(),
+            tcp: TcpSocketSettings::default(),
+            tcp_keepalive: TcpKeepaliveSettings::default(),
+            tunnel_keepalive: Vec::new(),
+            upstream_socket_options: Vec::new(),
+            upstream: UpstreamProxySettings::default(),
+            upstream_tls: UpstreamTlsSettings::default(),
+            egress: EgressSettings::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Socket addresses the SOCKS listeners bind: every `bind_addresses` entry, or
+    /// `bind_address` when that list is empty, each on `bind_port`.
+    ///
+    /// Entries that are not IP literals are skipped; [`Config::validate`] rejects them.
+    pub fn listen_addresses(&self) -> Vec<SocketAddr> {
+        let addresses: Vec<&String> = if self.bind_addresses.is_empty() {
+            vec![&self.bind_address]
+        } else {
+            self.bind_addresses.iter().collect()
+        };
+        addresses
+            .into_iter()
+            .filter_map(|address| address.parse::<IpAddr>().ok())
+            .map(|ip| SocketAddr::new(ip, self.bind_port))
+            .collect()
+    }
+}
+
+impl Default for UpstreamProxySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::new(),
+            port: default_upstream_proxy_port(),
+            username: None,
+            password: None,
+            destinations: default_upstream_proxy_destinations(),
+            health_check_interval_secs: default_upstream_health_check_interval_secs(),
+            unhealthy_threshold: default_upstream_unhealthy_threshold(),
+            on_unhealthy: default_upstream_on_unhealthy(),
+            drain_grace_secs: default_upstream_drain_grace_secs(),
+        }
+    }
+}
+
+impl Default for UpstreamTlsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            destinations: Vec::new(),
+            ports: default_upstream_tls_ports(),
+            ca_path: None,
+            client_certificate_path: None,
+            client_key_path: None,
+            server_name: None,
+        }
+    }
+}
+
+impl Default for TcpSocketSettings {
+    fn default() -> Self {
+        Self {
+            nodelay: default_tcp_nodelay(),
+            keepalive_secs: 0,
+            send_buffer_bytes: default_tcp_buffer_bytes(),
+            recv_buffer_bytes: default_tcp_buffer_bytes(),
+        }
+    }
+}
+
+impl Default for TcpKeepaliveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_secs: default_tcp_keepalive_idle_secs(),
+            interval_secs: default_tcp_keepalive_interval_secs(),
+            retries: default_tcp_keepalive_retries(),
+        }
+    }
+}
+
+impl Default for OverloadSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            signal: default_overload_signal(),
+            engage_threshold: default_overload_engage_threshold(),
+            release_threshold: default_overload_release_threshold(),
+            full_threshold: default_overload_full_threshold(),
+            min_reject_ratio: default_overload_min_reject_ratio(),
+            max_reject_ratio: default_overload_max_reject_ratio(),
+            sample_interval_ms: default_overload_sample_interval_ms(),
+        }
+    }
+}
+
+impl Default for GuardrailSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fd_soft_limit: None,
+            fd_hard_limit: None,
+            fd_reserve: default_guardrails_fd_reserve(),
+            memory_soft_limit_mb: None,
+            memory_hard_limit_mb: None,
+            udp_idle_secs: default_guardrails_udp_idle_secs(),
+            sample_interval_ms: default_guardrails_sample_interval_ms(),
+        }
+    }
+}
+
+impl Default for UdpSettings {
+    fn default() -> Self {
+        Self {
+            enable_fragments: false,
+            max_reassembly_queues: default_udp_max_reassembly_queues(),
+            reassembly_timeout_ms: default_udp_reassembly_timeout_ms(),
+            max_datagram_size: default_udp_max_datagram_size(),
+            association_idle_timeout_secs: default_udp_association_idle_timeout_secs(),
+        }
+    }
+}
+
+impl Default for DnsSettings {
+    fn default() -> Self {
+        Self {
+            cache_ttl_secs: 0,
+            negative_ttl_secs: default_dns_negative_ttl_secs(),
+            cache_max_entries: default_dns_cache_max_entries(),
+            strategy: default_dns_strategy(),
+            happy_eyeballs_delay_ms: default_dns_happy_eyeballs_delay_ms(),
+            allow_domain_requests: default_dns_allow_domain_requests(),
+        }
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            connections_per_ip_per_minute: 0,
+            log_interval_secs: default_rate_limit_log_interval_secs(),
+            warn_per_client: false,
         }
     }
 }
@@ -502,9 +1800,39 @@ impl Default for AuthConfig {
         Self {
             client_method: default_client_method(),
             socks_method: default_socks_method(),
+            socks_method_preference: Vec::new(),
             users: Vec::new(),
+            users_file: None,
             pam: PamSettings::default(),
             gssapi: GssApiSettings::default(),
+            ldap: LdapSettings::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: ImpersonationSettings::default(),
+            password_hashing: PasswordHashSettings::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: default_correlation_separator(),
+            tls_cert_identity: default_tls_cert_identity(),
+        }
+    }
+}
+
+impl Default for PasswordHashSettings {
+    fn default() -> Self {
+        Self {
+            memory_kib: default_password_hash_memory_kib(),
+            iterations: default_password_hash_iterations(),
+            parallelism: default_password_hash_parallelism(),
+        }
+    }
+}
+
+impl Default for ImpersonationSettings {
+    fn default() -> Self {
+        Self {
+            allowed_principals: Vec::new(),
+            allowed_target_pattern: default_impersonation_target_pattern(),
+            separator: default_impersonation_separator(),
         }
     }
 }
@@ -518,6 +1846,8 @@ impl Default for PamSettings {
             default_ruser: default_pam_default_ruser(),
             verbose: false,
             verify_service: false,
+            address_cache_secs: 0,
+            negative_cache_secs: 0,
         }
     }
 }
@@ -533,11 +1863,26 @@ impl Default for GssApiSettings {
     }
 }
 
+impl Default for LdapSettings {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            bind_dn_template: String::new(),
+            starttls: false,
+            timeout_ms: default_ldap_timeout_ms(),
+            group_search_base: None,
+            group_filter: default_ldap_group_filter(),
+            group_attribute: default_ldap_group_attribute(),
+        }
+    }
+}
+
 impl Default for LoggingConfig {
     fn default() -> Self {
         Self {
             level: default_log_level(),
             format: default_log_format(),
+            sampling: LogSamplingSettings::default(),
         }
     }
 }
@@ -548,7 +1893,29 @@ impl Default for AclSettings {
             enabled: default_acl_enabled(),
             config_file: None,
             watch: default_acl_watch(),
+            watch_domain_lists: false,
             anonymous_user: default_acl_anonymous_user(),
+            mode: default_acl_mode(),
+            classify_by_sni: default_acl_classify_by_sni(),
+            sni_peek_timeout_ms: default_acl_sni_peek_timeout_ms(),
+            sni_ports: default_acl_sni_ports(),
+            sni_fail_mode: default_acl_sni_fail_mode(),
+            rule_stats_flush_interval_secs: default_acl_rule_stats_flush_interval_secs(),
+            rule_stats_sidecar: false,
+            minimal_log_interval_secs: default_acl_minimal_log_interval_secs(),
+            strict_references: false,
+            system_group_patterns: Vec::new(),
+            cache: AclCacheSettings::default(),
+        }
+    }
+}
+
+impl Default for AclCacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_acl_cache_ttl_secs(),
+            max_entries: default_acl_cache_max_entries(),
         }
     }
 }
@@ -561,17 +1928,35 @@ impl Default for SessionSettings {
             database_url: None,
             batch_size: default_session_batch_size(),
             batch_interval_ms: default_session_batch_interval_ms(),
+            batch_adaptive: default_session_batch_adaptive(),
+            batch_min_size: default_session_batch_min_size(),
+            batch_max_size: default_session_batch_max_size(),
+            batch_target_flush_ms: default_session_batch_target_flush_ms(),
+            batch_queue_max: default_session_batch_queue_max(),
+            batch_overflow_policy: default_session_batch_overflow_policy(),
             retention_days: default_session_retention_days(),
             cleanup_interval_hours: default_session_cleanup_interval_hours(),
+            cleanup_batch_size: default_session_cleanup_batch_size(),
+            allow_shared_database: false,
+            instance_lock_stale_secs: default_session_instance_lock_stale_secs(),
             traffic_update_packet_interval: default_session_traffic_update_packet_interval(),
+            live_traffic_interval_secs: default_session_live_traffic_interval_secs(),
             stats_window_hours: default_stats_window_hours(),
+            requested_host_ttl_secs: default_requested_host_ttl_secs(),
+            memory_max_sessions: default_session_memory_max_sessions(),
             stats_api_enabled: default_stats_api_enabled(),
             stats_api_bind_address: default_stats_api_bind_address(),
             stats_api_port: default_stats_api_port(),
+            stats_api_bind: None,
+            api_socket_mode: default_api_socket_mode(),
             swagger_enabled: default_swagger_enabled(),
             dashboard_enabled: default_dashboard_enabled(),
             dashboard_auth: DashboardAuthSettings::default(),
+            api_auth: ApiAuthSettings::default(),
+            api_compression: ApiCompressionSettings::default(),
             base_path: default_base_path(),
+            public_status_enabled: false,
+            maintenance_message: None,
         }
     }
 }
@@ -589,14 +1974,25 @@ impl Default for DashboardAuthSettings {
     }
 }
 
-impl Default for MetricsSettings {
+impl Default for SpecialNamesSettings {
     fn default() -> Self {
         Self {
-            enabled: default_metrics_enabled(),
-            storage: default_metrics_storage(),
+            localhost: default_special_names_localhost(),
+            local: default_special_names_local(),
+        }
+    }
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: default_metrics_enabled(),
+            storage: default_metrics_storage(),
             retention_hours: default_metrics_retention_hours(),
+            rollup_retention_days: default_metrics_rollup_retention_days(),
             cleanup_interval_hours: default_metrics_cleanup_interval_hours(),
             collection_interval_secs: default_metrics_collection_interval_secs(),
+            history_max_range_hours: default_metrics_history_max_range_hours(),
         }
     }
 }
@@ -702,30 +2098,69 @@ impl Config {
     /// Validate configuration
     fn validate(&self) -> Result<()> {
         // Validate authentication configuration
-        if !matches!(self.auth.client_method.as_str(), "none" | "pam.address") {
+        if !matches!(
+            self.auth.client_method.as_str(),
+            "none" | "pam.address" | "tls.cert"
+        ) {
             return Err(RustSocksError::Config(format!(
-                "Invalid client auth method: {}. Supported: none, pam.address",
+                "Invalid client auth method: {}. Supported: none, pam.address, tls.cert",
                 self.auth.client_method
             )));
         }
 
+        if self.auth.client_method == "tls.cert" {
+            if !(self.server.tls.enabled && self.server.tls.require_client_auth) {
+                return Err(RustSocksError::Config(
+                    "auth.client_method = \"tls.cert\" requires server.tls.enabled and \
+                     server.tls.require_client_auth"
+                        .to_string(),
+                ));
+            }
+            self.auth
+                .tls_cert_identity
+                .parse::<crate::auth::CertIdentityField>()
+                .map_err(|e| RustSocksError::Config(format!("auth.tls_cert_identity: {}", e)))?;
+        }
+
         if !matches!(
             self.auth.socks_method.as_str(),
-            "none" | "userpass" | "pam.address" | "pam.username"
+            "none" | "userpass" | "pam.address" | "pam.username" | "ldap"
         ) {
             return Err(RustSocksError::Config(format!(
-                "Invalid SOCKS auth method: {}. Supported: none, userpass, pam.address, pam.username",
+                "Invalid SOCKS auth method: {}. Supported: none, userpass, pam.address, \
+                 pam.username, ldap",
                 self.auth.socks_method
             )));
         }
 
+        // Each entry answers one SOCKS method byte, so two backends cannot share one
+        let mut wire_methods = Vec::new();
+        for method in &self.auth.socks_method_preference {
+            let wire = match method.as_str() {
+                "none" | "pam.address" => "no-auth (0x00)",
+                "userpass" | "pam.username" | "ldap" => "username/password (0x02)",
+                _ => {
+                    return Err(RustSocksError::Config(format!(
+                        "Invalid auth.socks_method_preference entry: {}. Supported: none, \
+                         userpass, pam.address, pam.username, ldap",
+                        method
+                    )));
+                }
+            };
+            if wire_methods.contains(&wire) {
+                return Err(RustSocksError::Config(format!(
+                    "auth.socks_method_preference lists more than one {} method",
+                    wire
+                )));
+            }
+            wire_methods.push(wire);
+        }
+
         #[cfg(not(unix))]
         {
             if self.auth.client_method == "pam.address"
-                || matches!(
-                    self.auth.socks_method.as_str(),
-                    "pam.address" | "pam.username"
-                )
+                || self.auth.uses_socks_method("pam.address")
+                || self.auth.uses_socks_method("pam.username")
             {
                 return Err(RustSocksError::Config(
                     "PAM authentication is only supported on Unix-like systems".to_string(),
@@ -733,13 +2168,31 @@ impl Config {
             }
         }
 
-        if self.auth.socks_method == "userpass" && self.auth.users.is_empty() {
+        if let Some(users_file) = &self.auth.users_file {
+            if users_file.trim().is_empty() {
+                return Err(RustSocksError::Config(
+                    "auth.users_file cannot be empty".to_string(),
+                ));
+            }
+            if !self.auth.users.is_empty() {
+                return Err(RustSocksError::Config(
+                    "auth.users and auth.users_file cannot both be set".to_string(),
+                ));
+            }
+        }
+        if self.auth.uses_socks_method("userpass")
+            && self.auth.users.is_empty()
+            && self.auth.users_file.is_none()
+        {
             return Err(RustSocksError::Config(
                 "userpass auth requires at least one user".to_string(),
             ));
         }
+        for user in &self.auth.users {
+            user.validate("auth.users")?;
+        }
 
-        if self.auth.socks_method == "pam.username"
+        if self.auth.uses_socks_method("pam.username")
             && self.auth.pam.username_service.trim().is_empty()
         {
             return Err(RustSocksError::Config(
@@ -748,7 +2201,11 @@ impl Config {
             ));
         }
 
-        if (self.auth.socks_method == "pam.address" || self.auth.client_method == "pam.address")
+        if self.auth.uses_socks_method("ldap") {
+            self.auth.ldap.validate()?;
+        }
+
+        if (self.auth.uses_socks_method("pam.address") || self.auth.client_method == "pam.address")
             && self.auth.pam.address_service.trim().is_empty()
         {
             return Err(RustSocksError::Config(
@@ -757,6 +2214,449 @@ impl Config {
             ));
         }
 
+        crate::auth::parse_networks("auth.client_allow", &self.auth.client_allow)?;
+        crate::auth::parse_networks("auth.client_deny", &self.auth.client_deny)?;
+
+        if !self.auth.impersonation.allowed_principals.is_empty() {
+            if self.auth.impersonation.separator.is_empty() {
+                return Err(RustSocksError::Config(
+                    "auth.impersonation.separator cannot be empty".to_string(),
+                ));
+            }
+            if !self.auth.uses_login_method() {
+                return Err(RustSocksError::Config(
+                    "auth.impersonation requires socks_method userpass, pam.username or ldap"
+                        .to_string(),
+                ));
+            }
+        }
+
+        if self.auth.allow_correlation_suffix {
+            if self.auth.correlation_separator.is_empty() {
+                return Err(RustSocksError::Config(
+                    "auth.correlation_separator cannot be empty".to_string(),
+                ));
+            }
+            if !self.auth.impersonation.allowed_principals.is_empty()
+                && self.auth.correlation_separator == self.auth.impersonation.separator
+            {
+                return Err(RustSocksError::Config(
+                    "auth.correlation_separator must differ from auth.impersonation.separator"
+                        .to_string(),
+                ));
+            }
+            if !self.auth.uses_login_method() {
+                return Err(RustSocksError::Config(
+                    "auth.allow_correlation_suffix requires socks_method userpass, pam.username \
+                     or ldap"
+                        .to_string(),
+                ));
+            }
+        }
+
+        crate::auth::hash_params(&self.auth.password_hashing)?;
+
+        if self.server.overload.enabled {
+            let overload = &self.server.overload;
+            if !matches!(overload.signal.as_str(), "cpu" | "runtime_queue") {
+                return Err(RustSocksError::Config(format!(
+                    "Invalid server.overload.signal: {}. Supported: cpu, runtime_queue",
+                    overload.signal
+                )));
+            }
+            if !(overload.release_threshold < overload.engage_threshold
+                && overload.engage_threshold < overload.full_threshold)
+            {
+                return Err(RustSocksError::Config(
+                    "server.overload thresholds must satisfy release < engage < full".to_string(),
+                ));
+            }
+            if !(0.0..=1.0).contains(&overload.min_reject_ratio)
+                || !(0.0..=1.0).contains(&overload.max_reject_ratio)
+                || overload.min_reject_ratio > overload.max_reject_ratio
+            {
+                return Err(RustSocksError::Config(
+                    "server.overload reject ratios must satisfy 0 <= min <= max <= 1".to_string(),
+                ));
+            }
+            if overload.sample_interval_ms == 0 {
+                return Err(RustSocksError::Config(
+                    "server.overload.sample_interval_ms must be greater than 0".to_string(),
+                ));
+            }
+        }
+
+        if self.server.guardrails.enabled {
+            let guardrails = &self.server.guardrails;
+            let watermarks = [
+                ("fd", guardrails.fd_soft_limit, guardrails.fd_hard_limit),
+                (
+                    "memory",
+                    guardrails.memory_soft_limit_mb,
+                    guardrails.memory_hard_limit_mb,
+                ),
+            ];
+            for (kind, soft, hard) in watermarks {
+                if soft == Some(0) || hard == Some(0) {
+                    return Err(RustSocksError::Config(format!(
+                        "server.guardrails {} limits must be greater than 0",
+                        kind
+                    )));
+                }
+                if let (Some(soft), Some(hard)) = (soft, hard) {
+                    if soft > hard {
+                        return Err(RustSocksError::Config(format!(
+                            "server.guardrails {}_soft_limit must not exceed the hard limit",
+                            kind
+                        )));
+                    }
+                }
+            }
+            if guardrails.udp_idle_secs == 0 || guardrails.sample_interval_ms == 0 {
+                return Err(RustSocksError::Config(
+                    "server.guardrails udp_idle_secs and sample_interval_ms must be greater than 0"
+                        .to_string(),
+                ));
+            }
+        }
+
+        let tcp = &self.server.tcp;
+        if tcp.keepalive_secs > MAX_TCP_KEEPALIVE_SECS {
+            return Err(RustSocksError::Config(format!(
+                "server.tcp.keepalive_secs must be at most {} (0 disables keepalive)",
+                MAX_TCP_KEEPALIVE_SECS
+            )));
+        }
+        for (field, bytes) in [
+            ("send_buffer_bytes", tcp.send_buffer_bytes),
+            ("recv_buffer_bytes", tcp.recv_buffer_bytes),
+        ] {
+            if bytes != 0 && !(MIN_TCP_BUFFER_BYTES..=MAX_TCP_BUFFER_BYTES).contains(&bytes) {
+                return Err(RustSocksError::Config(format!(
+                    "server.tcp.{} must be 0 (kernel default) or between {} and {}",
+                    field, MIN_TCP_BUFFER_BYTES, MAX_TCP_BUFFER_BYTES
+                )));
+            }
+        }
+
+        if self.server.tcp_keepalive.enabled {
+            let keepalive = &self.server.tcp_keepalive;
+            if keepalive.idle_secs == 0 || keepalive.interval_secs == 0 || keepalive.retries == 0 {
+                return Err(RustSocksError::Config(
+                    "server.tcp_keepalive idle_secs, interval_secs and retries must be greater than 0"
+                        .to_string(),
+                ));
+            }
+        }
+
+        self.server
+            .udp_association_mode
+            .parse::<crate::session::UdpAssociationMode>()
+            .map_err(|_| {
+                RustSocksError::Config(format!(
+                    "Invalid server.udp_association_mode: {}. Supported: strict, ip-only, learned",
+                    self.server.udp_association_mode
+                ))
+            })?;
+
+        let udp = &self.server.udp;
+        if !(10..=65_535).contains(&udp.max_datagram_size) {
+            return Err(RustSocksError::Config(
+                "server.udp.max_datagram_size must be between 10 and 65535".to_string(),
+            ));
+        }
+        if udp.enable_fragments
+            && (udp.max_reassembly_queues == 0 || udp.reassembly_timeout_ms == 0)
+        {
+            return Err(RustSocksError::Config(
+                "server.udp max_reassembly_queues and reassembly_timeout_ms must be greater than 0"
+                    .to_string(),
+            ));
+        }
+        if udp.association_idle_timeout_secs == 0 {
+            return Err(RustSocksError::Config(
+                "server.udp.association_idle_timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.server.bind_accept_timeout_secs == 0 {
+            return Err(RustSocksError::Config(
+                "server.bind_accept_timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+        if !(MIN_RELAY_BUFFER_BYTES..=MAX_RELAY_BUFFER_BYTES)
+            .contains(&self.server.relay_buffer_bytes)
+        {
+            return Err(RustSocksError::Config(format!(
+                "server.relay_buffer_bytes must be between {} and {}",
+                MIN_RELAY_BUFFER_BYTES, MAX_RELAY_BUFFER_BYTES
+            )));
+        }
+        if self.server.handshake_timeout_secs == 0 {
+            return Err(RustSocksError::Config(
+                "server.handshake_timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        crate::server::resolver::AddressSelection::from_settings(&self.server.dns)?;
+        if self.server.dns.happy_eyeballs_delay_ms < 10 {
+            return Err(RustSocksError::Config(
+                "server.dns.happy_eyeballs_delay_ms must be at least 10 (RFC 8305)".to_string(),
+            ));
+        }
+        if self.server.connect_timeout_secs == Some(0) {
+            return Err(RustSocksError::Config(
+                "server.connect_timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.server.connect_retries > 0
+            && u128::from(self.server.connect_retry_backoff_ms)
+                >= crate::server::CONNECT_RETRY_DEADLINE.as_millis()
+        {
+            return Err(RustSocksError::Config(format!(
+                "server.connect_retry_backoff_ms must be below the {} s retry deadline",
+                crate::server::CONNECT_RETRY_DEADLINE.as_secs()
+            )));
+        }
+
+        if self.server.dns.cache_ttl_secs > 0 && self.server.dns.cache_max_entries == 0 {
+            return Err(RustSocksError::Config(
+                "server.dns.cache_max_entries must be greater than 0 when the cache is enabled"
+                    .to_string(),
+            ));
+        }
+
+        for (index, tunnel) in self.server.tunnel_keepalive.iter().enumerate() {
+            if tunnel.destinations.is_empty() {
+                return Err(RustSocksError::Config(format!(
+                    "server.tunnel_keepalive[{}] needs at least one destination",
+                    index
+                )));
+            }
+            for destination in &tunnel.destinations {
+                crate::acl::matcher::CompiledDestinationMatcher::compile(destination).map_err(
+                    |e| {
+                        RustSocksError::Config(format!(
+                            "Invalid server.tunnel_keepalive[{}] destination '{}': {}",
+                            index, destination, e
+                        ))
+                    },
+                )?;
+            }
+            if tunnel.interval_secs == 0 {
+                return Err(RustSocksError::Config(format!(
+                    "server.tunnel_keepalive[{}].interval_secs must be greater than 0",
+                    index
+                )));
+            }
+            if !matches!(tunnel.mode.as_str(), "tcp" | "probe") {
+                return Err(RustSocksError::Config(format!(
+                    "Invalid server.tunnel_keepalive[{}].mode: {}. Supported: tcp, probe",
+                    index, tunnel.mode
+                )));
+            }
+            if tunnel.user_timeout_secs == Some(0) {
+                return Err(RustSocksError::Config(format!(
+                    "server.tunnel_keepalive[{}].user_timeout_secs must be greater than 0",
+                    index
+                )));
+            }
+        }
+
+        for (index, entry) in self.server.upstream_socket_options.iter().enumerate() {
+            if entry.destinations.is_empty() {
+                return Err(RustSocksError::Config(format!(
+                    "server.upstream_socket_options[{}] needs at least one destination",
+                    index
+                )));
+            }
+            for destination in &entry.destinations {
+                crate::acl::matcher::CompiledDestinationMatcher::compile(destination).map_err(
+                    |e| {
+                        RustSocksError::Config(format!(
+                            "Invalid server.upstream_socket_options[{}] destination '{}': {}",
+                            index, destination, e
+                        ))
+                    },
+                )?;
+            }
+            for port in &entry.ports {
+                crate::acl::matcher::CompiledPortMatcher::compile(port).map_err(|e| {
+                    RustSocksError::Config(format!(
+                        "Invalid server.upstream_socket_options[{}] port '{}': {}",
+                        index, port, e
+                    ))
+                })?;
+            }
+            if entry.tcp_md5_password.is_none() && entry.dscp.is_none() && entry.ip_tos.is_none() {
+                return Err(RustSocksError::Config(format!(
+                    "server.upstream_socket_options[{}] sets no option \
+                     (tcp_md5_password, dscp or ip_tos)",
+                    index
+                )));
+            }
+            if let Some(password) = &entry.tcp_md5_password {
+                if !cfg!(target_os = "linux") {
+                    return Err(RustSocksError::Config(format!(
+                        "server.upstream_socket_options[{}].tcp_md5_password requires Linux \
+                         (TCP_MD5SIG is not available on this platform)",
+                        index
+                    )));
+                }
+                if password.is_empty()
+                    || password.len() > crate::server::socket_options::TCP_MD5_MAX_KEY_LEN
+                {
+                    return Err(RustSocksError::Config(format!(
+                        "server.upstream_socket_options[{}].tcp_md5_password must be 1-{} bytes",
+                        index,
+                        crate::server::socket_options::TCP_MD5_MAX_KEY_LEN
+                    )));
+                }
+            }
+            if entry.tcp_ao_password.is_some() {
+                return Err(RustSocksError::Config(format!(
+                    "server.upstream_socket_options[{}].tcp_ao_password: TCP-AO is not \
+                     supported yet; use tcp_md5_password",
+                    index
+                )));
+            }
+            if entry.dscp.is_some() && entry.ip_tos.is_some() {
+                return Err(RustSocksError::Config(format!(
+                    "server.upstream_socket_options[{}] sets both dscp and ip_tos",
+                    index
+                )));
+            }
+            if entry.dscp.is_some_and(|dscp| dscp > 63) {
+                return Err(RustSocksError::Config(format!(
+                    "server.upstream_socket_options[{}].dscp must be between 0 and 63",
+                    index
+                )));
+            }
+        }
+
+        crate::server::EgressMap::parse(&self.server.egress)?;
+
+        let upstream = &self.server.upstream;
+        if upstream.enabled {
+            if upstream.address.trim().is_empty() {
+                return Err(RustSocksError::Config(
+                    "server.upstream.address is required when server.upstream.enabled is true"
+                        .to_string(),
+                ));
+            }
+            if upstream.port == 0 {
+                return Err(RustSocksError::Config(
+                    "server.upstream.port must be greater than 0".to_string(),
+                ));
+            }
+            match (&upstream.username, &upstream.password) {
+                (Some(username), Some(password)) => {
+                    // RFC 1929 carries both fields with a one-byte length
+                    if username.is_empty() || username.len() > 255 {
+                        return Err(RustSocksError::Config(
+                            "server.upstream.username must be 1-255 bytes".to_string(),
+                        ));
+                    }
+                    if password.is_empty() || password.len() > 255 {
+                        return Err(RustSocksError::Config(
+                            "server.upstream.password must be 1-255 bytes".to_string(),
+                        ));
+                    }
+                }
+                (None, None) => {}
+                _ => {
+                    return Err(RustSocksError::Config(
+                        "server.upstream.username and server.upstream.password must be set together"
+                            .to_string(),
+                    ));
+                }
+            }
+            if upstream.destinations.is_empty() {
+                return Err(RustSocksError::Config(
+                    "server.upstream.destinations needs at least one pattern".to_string(),
+                ));
+            }
+            for destination in &upstream.destinations {
+                crate::acl::matcher::CompiledDestinationMatcher::compile(destination).map_err(
+                    |e| {
+                        RustSocksError::Config(format!(
+                            "Invalid server.upstream destination '{}': {}",
+                            destination, e
+                        ))
+                    },
+                )?;
+            }
+            if upstream.unhealthy_threshold == 0 {
+                return Err(RustSocksError::Config(
+                    "server.upstream.unhealthy_threshold must be greater than 0".to_string(),
+                ));
+            }
+            upstream
+                .on_unhealthy
+                .parse::<crate::server::upstream_proxy::UnhealthyPolicy>()
+                .map_err(|_| {
+                    RustSocksError::Config(format!(
+                        "Invalid server.upstream.on_unhealthy: {}. Supported: keep, drain, migrate",
+                        upstream.on_unhealthy
+                    ))
+                })?;
+        }
+
+        let upstream_tls = &self.server.upstream_tls;
+        if upstream_tls.enabled {
+            if upstream_tls.destinations.is_empty() {
+                return Err(RustSocksError::Config(
+                    "server.upstream_tls.destinations needs at least one pattern".to_string(),
+                ));
+            }
+            for destination in &upstream_tls.destinations {
+                crate::acl::matcher::CompiledDestinationMatcher::compile(destination).map_err(
+                    |e| {
+                        RustSocksError::Config(format!(
+                            "Invalid server.upstream_tls destination '{}': {}",
+                            destination, e
+                        ))
+                    },
+                )?;
+            }
+            for port in &upstream_tls.ports {
+                crate::acl::matcher::CompiledPortMatcher::compile(port).map_err(|e| {
+                    RustSocksError::Config(format!(
+                        "Invalid server.upstream_tls port '{}': {}",
+                        port, e
+                    ))
+                })?;
+            }
+            if upstream_tls
+                .ca_path
+                .as_deref()
+                .is_none_or(|path| path.trim().is_empty())
+            {
+                return Err(RustSocksError::Config(
+                    "server.upstream_tls.ca_path is required when server.upstream_tls.enabled \
+                     is true"
+                        .to_string(),
+                ));
+            }
+            if upstream_tls.client_certificate_path.is_some()
+                != upstream_tls.client_key_path.is_some()
+            {
+                return Err(RustSocksError::Config(
+                    "server.upstream_tls.client_certificate_path and \
+                     server.upstream_tls.client_key_path must be set together"
+                        .to_string(),
+                ));
+            }
+            if let Some(name) = &upstream_tls.server_name {
+                rustls::pki_types::ServerName::try_from(name.as_str()).map_err(|_| {
+                    RustSocksError::Config(format!(
+                        "Invalid server.upstream_tls.server_name '{}'",
+                        name
+                    ))
+                })?;
+            }
+        }
+
         if self.server.tls.enabled {
             let cert_path = self.server.tls.certificate_path.as_ref().ok_or_else(|| {
                 RustSocksError::Config(
@@ -817,42 +2717,160 @@ impl Config {
             }
         }
 
-        let db_backed_storage = matches!(
-            self.sessions.storage.as_str(),
-            "sqlite" | "mariadb" | "mysql"
-        );
-
-        if !matches!(
-            self.sessions.storage.as_str(),
-            "memory" | "sqlite" | "mariadb" | "mysql"
-        ) {
-            return Err(RustSocksError::Config(format!(
-                "Invalid session storage: {}. Supported: memory, sqlite, mariadb",
-                self.sessions.storage
-            )));
-        }
-
-        if self.sessions.enabled && db_backed_storage && self.sessions.database_url.is_none() {
+        if self.acl.watch_domain_lists && !self.acl.watch {
             return Err(RustSocksError::Config(
-                "sessions.database_url is required when session tracking uses sqlite or MariaDB storage"
-                    .to_string(),
+                "acl.watch_domain_lists requires acl.watch to be enabled".to_string(),
             ));
         }
 
-        if self.sessions.cleanup_interval_hours == 0 {
+        if self.acl.classify_by_sni && self.acl.sni_peek_timeout_ms == 0 {
             return Err(RustSocksError::Config(
-                "sessions.cleanup_interval_hours must be greater than 0".to_string(),
+                "acl.sni_peek_timeout_ms must be greater than 0 when classify_by_sni is enabled"
+                    .to_string(),
             ));
         }
 
-        if self.sessions.traffic_update_packet_interval == 0 {
+        if self.acl.classify_by_sni && self.acl.sni_ports.is_empty() {
             return Err(RustSocksError::Config(
-                "sessions.traffic_update_packet_interval must be greater than 0".to_string(),
+                "acl.sni_ports cannot be empty when classify_by_sni is enabled".to_string(),
             ));
         }
 
-        if self.sessions.stats_window_hours == 0 {
-            return Err(RustSocksError::Config(
+        self.acl.mode.parse::<crate::acl::AclMode>().map_err(|_| {
+            RustSocksError::Config(format!(
+                "Invalid acl.mode: {}. Supported: enforce, monitor",
+                self.acl.mode
+            ))
+        })?;
+
+        if !matches!(self.acl.sni_fail_mode.as_str(), "block" | "allow") {
+            return Err(RustSocksError::Config(format!(
+                "Invalid acl.sni_fail_mode: {}. Supported: block, allow",
+                self.acl.sni_fail_mode
+            )));
+        }
+
+        if self.acl.rule_stats_flush_interval_secs == 0 {
+            return Err(RustSocksError::Config(
+                "acl.rule_stats_flush_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.acl.minimal_log_interval_secs == 0 {
+            return Err(RustSocksError::Config(
+                "acl.minimal_log_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.acl.cache.enabled {
+            if self.acl.cache.ttl_secs == 0 {
+                return Err(RustSocksError::Config(
+                    "acl.cache.ttl_secs must be greater than 0 when the cache is enabled"
+                        .to_string(),
+                ));
+            }
+            if self.acl.cache.max_entries == 0 {
+                return Err(RustSocksError::Config(
+                    "acl.cache.max_entries must be greater than 0 when the cache is enabled"
+                        .to_string(),
+                ));
+            }
+        }
+
+        if self.sessions.storage != "memory" && !self.sessions.uses_database() {
+            return Err(RustSocksError::Config(format!(
+                "Invalid session storage: {}. Supported: memory, database (sqlite, mariadb \
+                 and mysql are accepted as aliases)",
+                self.sessions.storage
+            )));
+        }
+
+        if self.sessions.enabled && self.sessions.uses_database() {
+            let Some(url) = self.sessions.database_url.as_deref() else {
+                return Err(RustSocksError::Config(
+                    "sessions.database_url is required when session tracking uses database storage"
+                        .to_string(),
+                ));
+            };
+            let scheme = url.trim().to_ascii_lowercase();
+            if !SESSION_DATABASE_SCHEMES
+                .iter()
+                .any(|prefix| scheme.starts_with(prefix))
+            {
+                return Err(RustSocksError::Config(format!(
+                    "sessions.database_url '{}' has an unsupported scheme. Supported: sqlite:, \
+                     mysql://, mariadb://, postgres://, postgresql://",
+                    url
+                )));
+            }
+        }
+
+        if self.sessions.batch_min_size == 0
+            || self.sessions.batch_min_size > self.sessions.batch_max_size
+        {
+            return Err(RustSocksError::Config(format!(
+                "sessions.batch_min_size ({}) must be between 1 and sessions.batch_max_size ({})",
+                self.sessions.batch_min_size, self.sessions.batch_max_size
+            )));
+        }
+
+        if self.sessions.batch_target_flush_ms == 0 {
+            return Err(RustSocksError::Config(
+                "sessions.batch_target_flush_ms must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.sessions.batch_queue_max < self.sessions.batch_max_size {
+            return Err(RustSocksError::Config(format!(
+                "sessions.batch_queue_max ({}) must be at least sessions.batch_max_size ({})",
+                self.sessions.batch_queue_max, self.sessions.batch_max_size
+            )));
+        }
+
+        self.sessions
+            .batch_overflow_policy
+            .parse::<crate::session::BatchOverflowPolicy>()
+            .map_err(|_| {
+                RustSocksError::Config(format!(
+                    "Invalid sessions.batch_overflow_policy: {}. Supported: block, drop_oldest, drop_newest",
+                    self.sessions.batch_overflow_policy
+                ))
+            })?;
+
+        if self.sessions.cleanup_interval_hours == 0 {
+            return Err(RustSocksError::Config(
+                "sessions.cleanup_interval_hours must be greater than 0".to_string(),
+            ));
+        }
+
+        if !(1..=MAX_SESSION_CLEANUP_BATCH_SIZE).contains(&self.sessions.cleanup_batch_size) {
+            return Err(RustSocksError::Config(format!(
+                "sessions.cleanup_batch_size must be between 1 and {}",
+                MAX_SESSION_CLEANUP_BATCH_SIZE
+            )));
+        }
+
+        if self.sessions.instance_lock_stale_secs < MIN_INSTANCE_LOCK_STALE_SECS {
+            return Err(RustSocksError::Config(format!(
+                "sessions.instance_lock_stale_secs must be at least {}",
+                MIN_INSTANCE_LOCK_STALE_SECS
+            )));
+        }
+
+        if self.sessions.traffic_update_packet_interval == 0 {
+            return Err(RustSocksError::Config(
+                "sessions.traffic_update_packet_interval must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.sessions.live_traffic_interval_secs == 0 {
+            return Err(RustSocksError::Config(
+                "sessions.live_traffic_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.sessions.stats_window_hours == 0 {
+            return Err(RustSocksError::Config(
                 "sessions.stats_window_hours must be greater than 0".to_string(),
             ));
         }
@@ -876,7 +2894,51 @@ impl Config {
             )));
         }
 
-        if self.sessions.stats_api_enabled {
+        let mut bind_ips = std::collections::HashSet::new();
+        for address in &self.server.bind_addresses {
+            let ip = address.parse::<IpAddr>().map_err(|_| {
+                RustSocksError::Config(format!(
+                    "Invalid server.bind_addresses entry '{}': expected IPv4 or IPv6 literal",
+                    address
+                ))
+            })?;
+            if !bind_ips.insert(ip) {
+                return Err(RustSocksError::Config(format!(
+                    "server.bind_addresses lists '{}' more than once",
+                    address
+                )));
+            }
+        }
+
+        if self.sessions.api_socket_mode_bits().is_none() {
+            return Err(RustSocksError::Config(format!(
+                "Invalid sessions.api_socket_mode: {}. Expected octal permissions such as \"0660\"",
+                self.sessions.api_socket_mode
+            )));
+        }
+
+        if let Some(bind) = self.sessions.stats_api_bind.as_deref() {
+            match self.sessions.stats_api_unix_socket() {
+                Some(path) if !path.as_os_str().is_empty() => {
+                    if cfg!(not(unix)) {
+                        return Err(RustSocksError::Config(
+                            "sessions.stats_api_bind: Unix domain sockets are not supported on \
+                             this platform"
+                                .to_string(),
+                        ));
+                    }
+                }
+                _ => {
+                    return Err(RustSocksError::Config(format!(
+                        "Invalid sessions.stats_api_bind: {}. Expected \"unix:<path>\"; TCP uses \
+                         stats_api_bind_address and stats_api_port",
+                        bind
+                    )));
+                }
+            }
+        }
+
+        if self.sessions.stats_api_enabled && self.sessions.stats_api_bind.is_none() {
             if self.sessions.stats_api_bind_address.trim().is_empty() {
                 return Err(RustSocksError::Config(
                     "sessions.stats_api_bind_address cannot be empty when stats API is enabled"
@@ -913,23 +2975,71 @@ impl Config {
             }
 
             for user in &self.sessions.dashboard_auth.users {
-                if user.username.trim().is_empty() {
-                    return Err(RustSocksError::Config(
-                        "sessions.dashboard_auth user username cannot be empty".to_string(),
-                    ));
-                }
-                if user.password.trim().is_empty() {
-                    return Err(RustSocksError::Config(
-                        "sessions.dashboard_auth user password cannot be empty".to_string(),
-                    ));
-                }
+                user.validate("sessions.dashboard_auth")?;
+            }
+        }
+
+        if self.sessions.api_auth.enabled && self.sessions.api_auth.keys.is_empty() {
+            return Err(RustSocksError::Config(
+                "sessions.api_auth requires at least one key when enabled".to_string(),
+            ));
+        }
+        let mut tokens = std::collections::HashSet::new();
+        for (index, key) in self.sessions.api_auth.keys.iter().enumerate() {
+            if key.token.chars().count() < MIN_API_KEY_LEN {
+                return Err(RustSocksError::Config(format!(
+                    "sessions.api_auth.keys[{}]: token must be at least {} characters",
+                    index, MIN_API_KEY_LEN
+                )));
+            }
+            if !matches!(key.role.as_str(), "read" | "admin") {
+                return Err(RustSocksError::Config(format!(
+                    "sessions.api_auth.keys[{}]: invalid role '{}'. Supported: read, admin",
+                    index, key.role
+                )));
+            }
+            if !tokens.insert(key.token.as_str()) {
+                return Err(RustSocksError::Config(format!(
+                    "sessions.api_auth.keys[{}]: duplicate token",
+                    index
+                )));
+            }
+        }
+
+        for path in &self.sessions.api_compression.exclude_paths {
+            if !path.starts_with('/') {
+                return Err(RustSocksError::Config(format!(
+                    "sessions.api_compression.exclude_paths: '{}' must start with '/'",
+                    path
+                )));
             }
         }
 
+        let sampling = &self.logging.sampling;
+        if !(0.0..=1.0).contains(&sampling.connection_events_ratio) {
+            return Err(RustSocksError::Config(format!(
+                "logging.sampling.connection_events_ratio must be between 0.0 and 1.0, got {}",
+                sampling.connection_events_ratio
+            )));
+        }
+        if sampling
+            .always_log_users
+            .iter()
+            .any(|user| user.trim().is_empty())
+        {
+            return Err(RustSocksError::Config(
+                "logging.sampling.always_log_users cannot contain empty names".to_string(),
+            ));
+        }
+
         // Validate metrics configuration
-        if !matches!(self.metrics.storage.as_str(), "memory" | "sqlite") {
+        if !matches!(
+            self.metrics.storage.as_str(),
+            "memory" | "database" | "sqlite"
+        ) {
             return Err(RustSocksError::Config(format!(
-                "Invalid metrics storage: {}. Supported: memory, sqlite",
+                "Invalid metrics storage: {}. Supported: memory, database (sqlite is accepted \
+                 as an alias)",
                 self.metrics.storage
             )));
         }
@@ -946,124 +3056,100 @@ impl Config {
             ));
         }
 
+        if self.metrics.history_max_range_hours == 0 {
+            return Err(RustSocksError::Config(
+                "metrics.history_max_range_hours must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.metrics.rollup_retention_days > 0
+            && self.metrics.rollup_retention_days.saturating_mul(24) < self.metrics.retention_hours
+        {
+            return Err(RustSocksError::Config(format!(
+                "metrics.rollup_retention_days ({}) must cover metrics.retention_hours ({})",
+                self.metrics.rollup_retention_days, self.metrics.retention_hours
+            )));
+        }
+
+        if !matches!(
+            self.resolver.special_names.localhost.as_str(),
+            "block" | "allow"
+        ) {
+            return Err(RustSocksError::Config(format!(
+                "Invalid resolver.special_names.localhost: {}. Supported: block, allow",
+                self.resolver.special_names.localhost
+            )));
+        }
+
+        if !matches!(
+            self.resolver.special_names.local.as_str(),
+            "block" | "resolve"
+        ) {
+            return Err(RustSocksError::Config(format!(
+                "Invalid resolver.special_names.local: {}. Supported: block, resolve",
+                self.resolver.special_names.local
+            )));
+        }
+
+        let diagnostics = &self.diagnostics;
+        if !diagnostics.probes.is_empty() {
+            if diagnostics.probe_user.trim().is_empty() {
+                return Err(RustSocksError::Config(
+                    "diagnostics.probe_user must not be empty".to_string(),
+                ));
+            }
+            if diagnostics.probe_history == 0 || diagnostics.probe_history > 10_000 {
+                return Err(RustSocksError::Config(
+                    "diagnostics.probe_history must be between 1 and 10000".to_string(),
+                ));
+            }
+        }
+        let mut probe_names = std::collections::HashSet::new();
+        for probe in &diagnostics.probes {
+            let name = probe.display_name();
+            if probe.host.trim().is_empty() || probe.port == 0 {
+                return Err(RustSocksError::Config(format!(
+                    "diagnostics.probes entry {} needs a host and a port greater than 0",
+                    name
+                )));
+            }
+            if probe.interval_secs == 0 {
+                return Err(RustSocksError::Config(format!(
+                    "diagnostics.probes entry {}: interval_secs must be greater than 0",
+                    name
+                )));
+            }
+            if !probe_names.insert(name.clone()) {
+                return Err(RustSocksError::Config(format!(
+                    "diagnostics.probes: duplicate probe name {}",
+                    name
+                )));
+            }
+        }
+
+        if self.qos.per_ip.enabled {
+            if self.qos.per_ip.max_bytes_per_sec == 0 || self.qos.per_ip.burst == 0 {
+                return Err(RustSocksError::Config(
+                    "qos.per_ip.max_bytes_per_sec and qos.per_ip.burst must be greater than 0"
+                        .to_string(),
+                ));
+            }
+            crate::auth::parse_networks("qos.per_ip.exempt", &self.qos.per_ip.exempt)?;
+        }
+
+        // Valid, but likely a bits/bytes mixup: warn instead of refusing to start
+        if self.qos.enabled {
+            for warning in self.qos.htb.warnings() {
+                tracing::warn!("{}", warning);
+            }
+        }
+
         Ok(())
     }
 
     /// Create example configuration file
     pub fn create_example<P: AsRef<Path>>(path: P) -> Result<()> {
-        let example = r#"[server]
-bind_address = "127.0.0.1"
-bind_port = 1080
-max_connections = 1000
-
-[server.tls]
-enabled = false
-certificate_path = "config/server.crt"
-private_key_path = "config/server.key"
-require_client_auth = false
-# client_ca_path = "config/ca.crt"
-# alpn_protocols = ["socks"]
-# min_protocol_version = "TLS13"
-
-[auth]
-client_method = "none"       # Options: "none", "pam.address"
-socks_method = "none"        # Options: "none", "userpass", "pam.address", "pam.username"
-
-# For userpass authentication, add users:
-# [[auth.users]]
-# username = "alice"
-# password = "secret123"
-
-[auth.pam]
-# PAM service names (Linux /etc/pam.d/<service>)
-username_service = "rustsocks"
-address_service = "rustsocks-client"
-
-# Default identity for pam.address when username is not provided
-default_user = "rhostusr"
-default_ruser = "rhostusr"
-
-# Set to true to enable verbose PAM logging and service validation
-verbose = false
-verify_service = false
-
-[logging]
-level = "info"  # Options: "trace", "debug", "info", "warn", "error"
-format = "pretty"  # Options: "pretty", "json"
-
-[acl]
-enabled = false
-config_file = "config/acl.toml"
-watch = false
-anonymous_user = "anonymous"
-
-[sessions]
-enabled = false
-storage = "memory"  # Options: "memory", "sqlite"
-# database_url = "sqlite://var/lib/rustsocks/sessions.db"
-batch_size = 100
-batch_interval_ms = 1000
-retention_days = 90
-cleanup_interval_hours = 24
-traffic_update_packet_interval = 10
-stats_window_hours = 24
-stats_api_enabled = false
-stats_api_bind_address = "127.0.0.1"
-stats_api_port = 9090
-swagger_enabled = true
-dashboard_enabled = false
-
-[sessions.dashboard_auth]
-enabled = false
-# [[sessions.dashboard_auth.users]]
-# username = "admin"
-# password = "strong-secret"
-
-base_path = "/"
-
-[metrics]
-enabled = true              # Enable metrics collection
-storage = "memory"          # Options: "memory", "sqlite" (uses sessions.database_url)
-retention_hours = 24        # Keep metrics for 24 hours
-cleanup_interval_hours = 6  # Cleanup old metrics every 6 hours
-collection_interval_secs = 5  # Collect metrics every 5 seconds
-
-[qos]
-enabled = false  # Enable QoS (Quality of Service) / Rate Limiting
-algorithm = "htb"  # Options: "htb" (Hierarchical Token Bucket with fair sharing)
-
-[qos.htb]
-# Global bandwidth limit (1 Gbps = 125 MB/s = 125000000 bytes/sec)
-global_bandwidth_bytes_per_sec = 125000000
-
-# Per-user guaranteed minimum bandwidth (1 Mbps = 131072 bytes/sec)
-guaranteed_bandwidth_bytes_per_sec = 131072
-
-# Per-user maximum bandwidth when borrowing (100 Mbps = 12500000 bytes/sec)
-max_bandwidth_bytes_per_sec = 12500000
-
-# Burst size (how much can be transferred instantly)
-burst_size_bytes = 1048576  # 1 MB
-
-# Token bucket refill interval (milliseconds)
-refill_interval_ms = 50
-
-# Fair sharing - dynamically allocate unused bandwidth to active users
-fair_sharing_enabled = true
-
-# How often to recalculate fair shares (milliseconds)
-rebalance_interval_ms = 100
-
-# User inactivity timeout (seconds) - user considered idle after this period
-idle_timeout_secs = 5
-
-[qos.connection_limits]
-# Maximum connections per user
-max_connections_per_user = 20
-
-# Maximum total connections (global)
-max_connections_global = 10000
-"#;
+        let example = example::generate()?;
 
         std::fs::write(path.as_ref(), example).map_err(|e| {
             RustSocksError::Config(format!("Failed to write example config: {}", e))
@@ -1073,10 +3159,123 @@ max_connections_global = 10000
     }
 }
 
+impl AuthConfig {
+    /// SOCKS methods in effect, most preferred first: `socks_method_preference`, or
+    /// `socks_method` alone when no preference is set
+    pub fn socks_methods(&self) -> Vec<&str> {
+        if self.socks_method_preference.is_empty() {
+            vec![self.socks_method.as_str()]
+        } else {
+            self.socks_method_preference
+                .iter()
+                .map(String::as_str)
+                .collect()
+        }
+    }
+
+    pub(crate) fn uses_socks_method(&self, method: &str) -> bool {
+        self.socks_methods().contains(&method)
+    }
+
+    /// Whether a username/password (RFC 1929) backend is in effect
+    fn uses_login_method(&self) -> bool {
+        ["userpass", "pam.username", "ldap"]
+            .iter()
+            .any(|method| self.uses_socks_method(method))
+    }
+}
+
+impl LdapSettings {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if !cfg!(feature = "ldap") {
+            return Err(RustSocksError::Config(
+                "ldap authentication requires building with the `ldap` feature".to_string(),
+            ));
+        }
+        if !self.url.starts_with("ldap://") && !self.url.starts_with("ldaps://") {
+            return Err(RustSocksError::Config(format!(
+                "auth.ldap.url must start with ldap:// or ldaps:// (got '{}')",
+                self.url
+            )));
+        }
+        if self.starttls && self.url.starts_with("ldaps://") {
+            return Err(RustSocksError::Config(
+                "auth.ldap.starttls only applies to ldap:// URLs".to_string(),
+            ));
+        }
+        if !self.bind_dn_template.contains("{username}") {
+            return Err(RustSocksError::Config(
+                "auth.ldap.bind_dn_template must contain {username}".to_string(),
+            ));
+        }
+        if self.timeout_ms == 0 {
+            return Err(RustSocksError::Config(
+                "auth.ldap.timeout_ms must be greater than 0".to_string(),
+            ));
+        }
+        if self.group_search_base.is_some() {
+            if self.group_filter.trim().is_empty() {
+                return Err(RustSocksError::Config(
+                    "auth.ldap.group_filter cannot be empty when group_search_base is set"
+                        .to_string(),
+                ));
+            }
+            if self.group_attribute.trim().is_empty() {
+                return Err(RustSocksError::Config(
+                    "auth.ldap.group_attribute cannot be empty when group_search_base is set"
+                        .to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl SessionSettings {
+    /// Session history goes to the SQL store at `database_url`. `sqlite`, `mariadb`
+    /// and `mysql` predate the generic `database` kind and still mean the same; the
+    /// URL scheme picks the backend.
+    pub fn uses_database(&self) -> bool {
+        matches!(
+            self.storage.as_str(),
+            "database" | "sqlite" | "mariadb" | "mysql"
+        )
+    }
+
+    /// How often the store lock heartbeat is refreshed: a quarter of the stale
+    /// threshold, so a few missed beats never look like a dead instance
+    pub fn instance_heartbeat_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs((self.instance_lock_stale_secs / 4).max(1))
+    }
+
     pub fn normalized_base_path(&self) -> String {
         normalize_base_path(&self.base_path)
     }
+
+    /// Socket path of `stats_api_bind = "unix:<path>"`; `None` serves the API over TCP
+    pub fn stats_api_unix_socket(&self) -> Option<PathBuf> {
+        self.stats_api_bind
+            .as_deref()?
+            .strip_prefix("unix:")
+            .map(PathBuf::from)
+    }
+
+    /// `api_socket_mode` as permission bits; `None` when it is not octal up to 0777
+    pub fn api_socket_mode_bits(&self) -> Option<u32> {
+        let mode = self.api_socket_mode.trim();
+        let digits = mode.strip_prefix("0o").unwrap_or(mode);
+        u32::from_str_radix(digits, 8)
+            .ok()
+            .filter(|bits| *bits <= 0o777)
+    }
+}
+
+impl MetricsSettings {
+    /// Snapshots are persisted in the session store (`database`, or its older alias
+    /// `sqlite`)
+    pub fn uses_database(&self) -> bool {
+        matches!(self.storage.as_str(), "database" | "sqlite")
+    }
 }
 
 #[cfg(test)]
@@ -1093,8 +3292,17 @@ mod tests {
         assert_eq!(config.sessions.storage, "memory");
         assert_eq!(config.sessions.batch_size, 100);
         assert_eq!(config.sessions.batch_interval_ms, 1000);
+        assert!(config.sessions.batch_adaptive);
+        assert_eq!(config.sessions.batch_min_size, 10);
+        assert_eq!(config.sessions.batch_max_size, 1000);
+        assert_eq!(config.sessions.batch_target_flush_ms, 50);
+        assert_eq!(config.sessions.batch_queue_max, 100_000);
+        assert_eq!(config.sessions.batch_overflow_policy, "block");
         assert_eq!(config.sessions.retention_days, 90);
         assert_eq!(config.sessions.cleanup_interval_hours, 24);
+        assert!(config.server.dns.allow_domain_requests);
+        assert!(!config.server.upstream_tls.enabled);
+        assert_eq!(config.server.upstream_tls.ports, vec!["*".to_string()]);
         assert_eq!(config.sessions.storage, "memory");
         assert_eq!(config.sessions.batch_size, 100);
         assert_eq!(config.sessions.traffic_update_packet_interval, 10);
@@ -1102,12 +3310,19 @@ mod tests {
         assert!(!config.sessions.stats_api_enabled);
         assert_eq!(config.sessions.stats_api_bind_address, "127.0.0.1");
         assert_eq!(config.sessions.stats_api_port, 9090);
+        assert_eq!(config.sessions.stats_api_bind, None);
+        assert_eq!(config.sessions.api_socket_mode_bits(), Some(0o660));
         assert!(config.sessions.swagger_enabled);
         assert!(!config.sessions.dashboard_enabled);
         assert!(!config.sessions.dashboard_auth.enabled);
         assert!(config.sessions.dashboard_auth.users.is_empty());
         assert_eq!(config.sessions.base_path, "/");
         assert_eq!(config.sessions.normalized_base_path(), "/");
+        assert_eq!(config.resolver.special_names.localhost, "block");
+        assert_eq!(config.resolver.special_names.local, "block");
+        assert_eq!(config.diagnostics.probe_user, "probe");
+        assert_eq!(config.diagnostics.probe_history, 60);
+        assert!(config.diagnostics.probes.is_empty());
     }
 
     #[test]
@@ -1121,10 +3336,53 @@ mod tests {
 
         config.auth.users.push(User {
             username: "test".to_string(),
-            password: "pass".to_string(),
+            password: "pass".to_string().into(),
+            password_hash: None,
         });
         assert!(config.validate().is_ok());
 
+        // Exactly one of password and password_hash, and a hash that parses
+        config.auth.users[0].password_hash = Some(crate::auth::hash_password(
+            "pass",
+            &argon2::Params::new(8, 1, 1, None).unwrap(),
+        ));
+        assert!(config.validate().is_err());
+        config.auth.users[0].password = Default::default();
+        assert!(config.validate().is_ok());
+        config.auth.users[0].password_hash = None;
+        assert!(config.validate().is_err());
+        config.auth.users[0].password_hash = Some("$2b$12$abcdefghijklmnopqrstuv".to_string());
+        assert!(config.validate().is_err());
+        config.auth.users[0].password_hash = Some("pass".to_string());
+        assert!(config.validate().is_err());
+
+        // A users file replaces the inline list
+        config.auth.users_file = Some("/etc/rustsocks/users.toml".to_string());
+        assert!(config.validate().is_err());
+        config.auth.users.clear();
+        assert!(config.validate().is_ok());
+        config.auth.users_file = Some(" ".to_string());
+        assert!(config.validate().is_err());
+
+        // tls.cert needs verified client certificates
+        let mut config = Config::default();
+        config.auth.client_method = "tls.cert".to_string();
+        assert!(config.validate().is_err());
+        config.server.tls.enabled = true;
+        config.server.tls.certificate_path = Some("server.crt".to_string());
+        config.server.tls.private_key_path = Some("server.key".to_string());
+        assert!(config.validate().is_err());
+        config.server.tls.require_client_auth = true;
+        config.server.tls.client_ca_path = Some("clients-ca.crt".to_string());
+        assert!(config.validate().is_ok());
+        config.auth.tls_cert_identity = "san.email".to_string();
+        assert!(config.validate().is_ok());
+        config.auth.tls_cert_identity = "subject".to_string();
+        assert!(config.validate().is_err());
+        config.auth.tls_cert_identity = "cn".to_string();
+        config.auth.socks_method = "tls.cert".to_string();
+        assert!(config.validate().is_err());
+
         // ACL enabled without file should fail
         let mut config = Config::default();
         config.acl.enabled = true;
@@ -1134,6 +3392,421 @@ mod tests {
         config.acl.config_file = Some("config/acl.toml".to_string());
         assert!(config.validate().is_ok());
 
+        // Domain lists are only watched alongside the ACL file
+        config.acl.watch = false;
+        config.acl.watch_domain_lists = true;
+        assert!(config.validate().is_err());
+        config.acl.watch = true;
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.metrics.history_max_range_hours = 0;
+        assert!(config.validate().is_err());
+
+        // Rollups are off or outlive the raw samples
+        let mut config = Config::default();
+        config.metrics.retention_hours = 72;
+        config.metrics.rollup_retention_days = 2;
+        assert!(config.validate().is_err());
+        config.metrics.rollup_retention_days = 3;
+        assert!(config.validate().is_ok());
+        config.metrics.rollup_retention_days = 0;
+        assert!(config.validate().is_ok());
+
+        // SNI classification needs ports and a known fail mode
+        let mut config = Config::default();
+        config.acl.classify_by_sni = true;
+        assert!(config.validate().is_ok());
+        config.acl.sni_ports.clear();
+        assert!(config.validate().is_err());
+        config.acl.sni_ports = vec![443, 8443];
+        config.acl.sni_fail_mode = "open".to_string();
+        assert!(config.validate().is_err());
+        config.acl.sni_fail_mode = "allow".to_string();
+        assert!(config.validate().is_ok());
+        config.acl.mode = "shadow".to_string();
+        assert!(config.validate().is_err());
+        config.acl.mode = "monitor".to_string();
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.acl.rule_stats_flush_interval_secs = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.acl.minimal_log_interval_secs = 0;
+        assert!(config.validate().is_err());
+
+        // The decision cache needs a TTL and room for entries, but only when enabled
+        let mut config = Config::default();
+        config.acl.cache.ttl_secs = 0;
+        config.acl.cache.max_entries = 0;
+        assert!(config.validate().is_ok());
+        config.acl.cache.enabled = true;
+        assert!(config.validate().is_err());
+        config.acl.cache.ttl_secs = 30;
+        assert!(config.validate().is_err());
+        config.acl.cache.max_entries = 1000;
+        assert!(config.validate().is_ok());
+
+        // Tunnel keepalive entries need destinations, a positive interval and a known mode
+        let mut config = Config::default();
+        config.server.tunnel_keepalive = vec![TunnelKeepaliveSettings {
+            destinations: vec!["*.legacy.example.com".to_string()],
+            interval_secs: 30,
+            mode: "probe".to_string(),
+            user_timeout_secs: Some(90),
+        }];
+        assert!(config.validate().is_ok());
+        config.server.tunnel_keepalive[0].mode = "bytes".to_string();
+        assert!(config.validate().is_err());
+        config.server.tunnel_keepalive[0].mode = "tcp".to_string();
+        config.server.tunnel_keepalive[0].interval_secs = 0;
+        assert!(config.validate().is_err());
+        config.server.tunnel_keepalive[0].interval_secs = 30;
+        config.server.tunnel_keepalive[0].destinations.clear();
+        assert!(config.validate().is_err());
+
+        // Upstream socket options need destinations, an option and a valid DSCP
+        let mut config = Config::default();
+        config.server.upstream_socket_options = vec![UpstreamSocketOptionSettings {
+            destinations: vec!["192.0.2.0/24".to_string()],
+            ports: vec!["179".to_string()],
+            tcp_md5_password: None,
+            tcp_ao_password: None,
+            dscp: Some(46),
+            ip_tos: None,
+        }];
+        assert!(config.validate().is_ok());
+        config.server.upstream_socket_options[0].dscp = Some(64);
+        assert!(config.validate().is_err());
+        config.server.upstream_socket_options[0].dscp = Some(46);
+        config.server.upstream_socket_options[0].ip_tos = Some(0x10);
+        assert!(config.validate().is_err());
+        config.server.upstream_socket_options[0].dscp = None;
+        config.server.upstream_socket_options[0].ports = vec!["99999".to_string()];
+        assert!(config.validate().is_err());
+        config.server.upstream_socket_options[0].ports = vec!["*".to_string()];
+        config.server.upstream_socket_options[0].tcp_ao_password =
+            Some("secret".to_string().into());
+        assert!(config.validate().is_err());
+        config.server.upstream_socket_options[0].tcp_ao_password = None;
+        config.server.upstream_socket_options[0].tcp_md5_password =
+            Some("secret".to_string().into());
+        assert_eq!(config.validate().is_ok(), cfg!(target_os = "linux"));
+        config.server.upstream_socket_options[0].tcp_md5_password = Some("x".repeat(81).into());
+        assert!(config.validate().is_err());
+        config.server.upstream_socket_options[0].tcp_md5_password = None;
+        config.server.upstream_socket_options[0].ip_tos = None;
+        assert!(config.validate().is_err());
+
+        // Egress rules need a user or group and one address per family
+        let mut config = Config::default();
+        config.server.egress.rules = vec![EgressRuleSettings {
+            users: vec!["alice".to_string()],
+            groups: Vec::new(),
+            addresses: vec!["192.0.2.10".to_string(), "2001:db8::10".to_string()],
+        }];
+        assert!(config.validate().is_ok());
+        config.server.egress.rules[0].addresses[1] = "192.0.2.11".to_string();
+        assert!(config.validate().is_err());
+        config.server.egress.rules[0].addresses = vec!["egress.example.com".to_string()];
+        assert!(config.validate().is_err());
+        config.server.egress.rules[0].addresses = vec!["192.0.2.10".to_string()];
+        config.server.egress.rules[0].users.clear();
+        assert!(config.validate().is_err());
+
+        // The parent proxy needs an address, paired credentials and valid destinations
+        let mut config = Config::default();
+        config.server.upstream.enabled = true;
+        assert!(config.validate().is_err());
+        config.server.upstream.address = "socks.corp.example".to_string();
+        assert!(config.validate().is_ok());
+        config.server.upstream.username = Some("gateway".to_string());
+        assert!(config.validate().is_err());
+        config.server.upstream.password = Some("secret".to_string().into());
+        assert!(config.validate().is_ok());
+        config.server.upstream.password = Some("x".repeat(256).into());
+        assert!(config.validate().is_err());
+        config.server.upstream.password = Some("secret".to_string().into());
+        config.server.upstream.on_unhealthy = "failover".to_string();
+        assert!(config.validate().is_err());
+        config.server.upstream.on_unhealthy = "drain".to_string();
+        assert!(config.validate().is_ok());
+        config.server.upstream.unhealthy_threshold = 0;
+        assert!(config.validate().is_err());
+        config.server.upstream.unhealthy_threshold = 3;
+        config.server.upstream.destinations.clear();
+        assert!(config.validate().is_err());
+
+        // Upstream TLS needs destinations, a CA and a paired client certificate
+        let mut config = Config::default();
+        config.server.upstream_tls.enabled = true;
+        config.server.upstream_tls.destinations = vec!["*.internal.example".to_string()];
+        assert!(config.validate().is_err());
+        config.server.upstream_tls.ca_path = Some("internal-ca.pem".to_string());
+        assert!(config.validate().is_ok());
+        config.server.upstream_tls.client_certificate_path = Some("proxy.crt".to_string());
+        assert!(config.validate().is_err());
+        config.server.upstream_tls.client_key_path = Some("proxy.key".to_string());
+        assert!(config.validate().is_ok());
+        config.server.upstream_tls.ports = vec!["99999".to_string()];
+        assert!(config.validate().is_err());
+        config.server.upstream_tls.ports = vec!["443".to_string()];
+        config.server.upstream_tls.server_name = Some("not a name".to_string());
+        assert!(config.validate().is_err());
+        config.server.upstream_tls.server_name = None;
+        config.server.upstream_tls.destinations.clear();
+        assert!(config.validate().is_err());
+
+        // UDP association modes
+        let mut config = Config::default();
+        for mode in ["strict", "ip-only", "learned"] {
+            config.server.udp_association_mode = mode.to_string();
+            assert!(config.validate().is_ok());
+        }
+        config.server.udp_association_mode = "any".to_string();
+        assert!(config.validate().is_err());
+
+        // DNS strategy and connect timeout
+        let mut config = Config::default();
+        for strategy in [
+            "prefer_ipv4",
+            "prefer_ipv6",
+            "ipv4_only",
+            "ipv6_only",
+            "happy_eyeballs",
+        ] {
+            config.server.dns.strategy = strategy.to_string();
+            assert!(config.validate().is_ok());
+        }
+        config.server.dns.strategy = "ipv4_first".to_string();
+        assert!(config.validate().is_err());
+        config.server.dns.strategy = "happy_eyeballs".to_string();
+        config.server.dns.happy_eyeballs_delay_ms = 5;
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.server.connect_timeout_secs = Some(0);
+        assert!(config.validate().is_err());
+        config.server.connect_timeout_secs = Some(5);
+        assert!(config.validate().is_ok());
+        config.server.connect_retries = 3;
+        config.server.connect_retry_backoff_ms = 10_000;
+        assert!(config.validate().is_err());
+        config.server.connect_retry_backoff_ms = 250;
+        assert!(config.validate().is_ok());
+
+        // BIND accept timeout
+        let mut config = Config::default();
+        assert_eq!(config.server.bind_accept_timeout_secs, 300);
+        config.server.bind_accept_timeout_secs = 0;
+        assert!(config.validate().is_err());
+
+        // Relay buffer size
+        let mut config = Config::default();
+        assert_eq!(config.server.relay_buffer_bytes, 32 * 1024);
+        config.server.relay_buffer_bytes = 512;
+        assert!(config.validate().is_err());
+        config.server.relay_buffer_bytes = 2 * 1024 * 1024;
+        assert!(config.validate().is_err());
+        config.server.relay_buffer_bytes = 64 * 1024;
+        assert!(config.validate().is_ok());
+
+        // Handshake timeout; the concurrency limit may be off
+        let mut config = Config::default();
+        assert_eq!(config.server.handshake_timeout_secs, 10);
+        config.server.max_concurrent_handshakes = 0;
+        assert!(config.validate().is_ok());
+        config.server.handshake_timeout_secs = 0;
+        assert!(config.validate().is_err());
+
+        // UDP datagram size and reassembly limits
+        let mut config = Config::default();
+        assert!(!config.server.udp.enable_fragments);
+        config.server.udp.max_datagram_size = 9;
+        assert!(config.validate().is_err());
+        config.server.udp.max_datagram_size = 1500;
+        config.server.udp.max_reassembly_queues = 0;
+        assert!(config.validate().is_ok()); // Fragments off
+        config.server.udp.enable_fragments = true;
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.server.udp.association_idle_timeout_secs = 0;
+        assert!(config.validate().is_err());
+
+        // [[diagnostics.probes]]: host, port and interval set, names unique
+        let probe = |name: Option<&str>, host: &str, port: u16| ProbeTarget {
+            name: name.map(str::to_string),
+            host: host.to_string(),
+            port,
+            interval_secs: 30,
+        };
+        let mut config = Config::default();
+        config.diagnostics.probes = vec![probe(None, "example.com", 443), probe(None, "::1", 22)];
+        assert!(config.validate().is_ok());
+        assert_eq!(config.diagnostics.probes[1].display_name(), "[::1]:22");
+        config
+            .diagnostics
+            .probes
+            .push(probe(Some("example.com:443"), "example.org", 443));
+        assert!(config.validate().is_err());
+        config.diagnostics.probes[2] = probe(None, "example.org", 0);
+        assert!(config.validate().is_err());
+        config.diagnostics.probes[2] = probe(None, "example.org", 443);
+        config.diagnostics.probes[2].interval_secs = 0;
+        assert!(config.validate().is_err());
+        config.diagnostics.probes[2].interval_secs = 10;
+        config.diagnostics.probe_history = 0;
+        assert!(config.validate().is_err());
+
+        // [server.tcp]: keepalive within the kernel limit, buffers 0 or in range
+        let mut config = Config::default();
+        config.server.tcp.keepalive_secs = MAX_TCP_KEEPALIVE_SECS;
+        config.server.tcp.send_buffer_bytes = 0;
+        assert!(config.validate().is_ok());
+        config.server.tcp.keepalive_secs = MAX_TCP_KEEPALIVE_SECS + 1;
+        assert!(config.validate().is_err());
+        config.server.tcp.keepalive_secs = 60;
+        config.server.tcp.recv_buffer_bytes = MIN_TCP_BUFFER_BYTES - 1;
+        assert!(config.validate().is_err());
+        config.server.tcp.recv_buffer_bytes = MAX_TCP_BUFFER_BYTES + 1;
+        assert!(config.validate().is_err());
+
+        // DNS cache needs room for at least one name
+        let mut config = Config::default();
+        config.server.dns.cache_max_entries = 0;
+        assert!(config.validate().is_ok()); // Cache off
+        config.server.dns.cache_ttl_secs = 60;
+        assert!(config.validate().is_err());
+
+        // Listener addresses: bind_addresses replaces bind_address when set
+        let mut config = Config::default();
+        assert_eq!(
+            config.server.listen_addresses(),
+            vec!["127.0.0.1:1080".parse::<SocketAddr>().unwrap()]
+        );
+        config.server.bind_addresses = vec!["0.0.0.0".to_string(), "::".to_string()];
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.server.listen_addresses(),
+            vec![
+                "0.0.0.0:1080".parse::<SocketAddr>().unwrap(),
+                "[::]:1080".parse::<SocketAddr>().unwrap(),
+            ]
+        );
+        config.server.bind_addresses.push("localhost".to_string());
+        assert!(config.validate().is_err());
+        config.server.bind_addresses = vec!["::".to_string(), "::".to_string()];
+        assert!(config.validate().is_err());
+
+        // Client allow/deny lists must be CIDRs or bare addresses
+        let mut config = Config::default();
+        config.auth.client_deny = vec!["10.0.0.0/33".to_string()];
+        assert!(config.validate().is_err());
+        config.auth.client_deny = vec!["10.0.0.0/8".to_string(), "::1".to_string()];
+        config.auth.client_allow = vec!["192.168.0.0/16".to_string()];
+        assert!(config.validate().is_ok());
+
+        // The preference list takes known methods, one per SOCKS method byte
+        let mut config = Config::default();
+        config.auth.socks_method_preference = vec!["userpass".to_string(), "none".to_string()];
+        assert!(config.validate().is_err()); // userpass listed, no users
+        config.auth.users.push(User {
+            username: "alice".to_string(),
+            password: "pass".to_string().into(),
+            password_hash: None,
+        });
+        assert!(config.validate().is_ok());
+        assert_eq!(config.auth.socks_methods(), vec!["userpass", "none"]);
+        config
+            .auth
+            .socks_method_preference
+            .push("pam.address".to_string());
+        assert!(config.validate().is_err());
+        config.auth.socks_method_preference = vec!["none".to_string(), "kerberos".to_string()];
+        assert!(config.validate().is_err());
+
+        // Impersonation needs a method that carries a login name
+        let mut config = Config::default();
+        config.auth.impersonation.allowed_principals = vec!["svc".to_string()];
+        assert!(config.validate().is_err());
+        config.auth.socks_method = "userpass".to_string();
+        config.auth.users.push(User {
+            username: "svc".to_string(),
+            password: "pass".to_string().into(),
+            password_hash: None,
+        });
+        assert!(config.validate().is_ok());
+        config.auth.impersonation.separator.clear();
+        assert!(config.validate().is_err());
+
+        // Correlation suffixes also need a login name, and a separator of their own
+        let mut config = Config::default();
+        config.auth.allow_correlation_suffix = true;
+        assert!(config.validate().is_err());
+        config.auth.socks_method = "userpass".to_string();
+        config.auth.users.push(User {
+            username: "alice".to_string(),
+            password: "pass".to_string().into(),
+            password_hash: None,
+        });
+        assert!(config.validate().is_ok());
+        config.auth.impersonation.allowed_principals = vec!["alice".to_string()];
+        config.auth.impersonation.separator = "#".to_string();
+        assert!(config.validate().is_err());
+        config.auth.impersonation.separator = ":".to_string();
+        assert!(config.validate().is_ok());
+        config.auth.correlation_separator.clear();
+        assert!(config.validate().is_err());
+
+        // LDAP needs the feature, a directory URL and a DN template naming the user
+        let mut config = Config::default();
+        config.auth.socks_method = "ldap".to_string();
+        config.auth.ldap.url = "ldap://ldap.example.com".to_string();
+        config.auth.ldap.bind_dn_template = "uid={username},dc=example,dc=com".to_string();
+        assert_eq!(config.validate().is_ok(), cfg!(feature = "ldap"));
+        if cfg!(feature = "ldap") {
+            config.auth.impersonation.allowed_principals = vec!["svc".to_string()];
+            assert!(config.validate().is_ok());
+            config.auth.ldap.starttls = true;
+            config.auth.ldap.url = "ldaps://ldap.example.com".to_string();
+            assert!(config.validate().is_err());
+            config.auth.ldap.url = "http://ldap.example.com".to_string();
+            assert!(config.validate().is_err());
+            config.auth.ldap.url = "ldap://ldap.example.com".to_string();
+            config.auth.ldap.bind_dn_template = "uid=alice,dc=example,dc=com".to_string();
+            assert!(config.validate().is_err());
+            config.auth.ldap.bind_dn_template = "uid={username},dc=example,dc=com".to_string();
+            config.auth.ldap.timeout_ms = 0;
+            assert!(config.validate().is_err());
+        }
+
+        // Overload shedding needs ordered thresholds and ratios
+        let mut config = Config::default();
+        config.server.overload.enabled = true;
+        assert!(config.validate().is_ok());
+        config.server.overload.release_threshold = 95.0;
+        assert!(config.validate().is_err());
+        config.server.overload.release_threshold = 75.0;
+        config.server.overload.max_reject_ratio = 1.5;
+        assert!(config.validate().is_err());
+        config.server.overload.max_reject_ratio = 0.9;
+        config.server.overload.signal = "memory".to_string();
+        assert!(config.validate().is_err());
+
+        // Guardrail soft watermarks may not exceed the hard ones
+        let mut config = Config::default();
+        config.server.guardrails.enabled = true;
+        assert!(config.validate().is_ok());
+        config.server.guardrails.fd_soft_limit = Some(900);
+        config.server.guardrails.fd_hard_limit = Some(800);
+        assert!(config.validate().is_err());
+        config.server.guardrails.fd_hard_limit = Some(1000);
+        config.server.guardrails.memory_hard_limit_mb = Some(0);
+        assert!(config.validate().is_err());
+        config.server.guardrails.memory_hard_limit_mb = Some(2048);
+        assert!(config.validate().is_ok());
+
         // Invalid session storage
         let mut config = Config::default();
         config.sessions.storage = "invalid".to_string();
@@ -1148,15 +3821,77 @@ mod tests {
         config.sessions.database_url = Some("sqlite::memory:".to_string());
         assert!(config.validate().is_ok());
 
+        // Generic database storage; the URL scheme picks the backend
+        config.sessions.storage = "database".to_string();
+        config.sessions.database_url =
+            Some("postgres://rustsocks@db.example.com/rustsocks".to_string());
+        assert!(config.validate().is_ok());
+        config.sessions.database_url = Some("oracle://db.example.com/rustsocks".to_string());
+        assert!(config.validate().is_err());
+        config.sessions.database_url = Some("sqlite::memory:".to_string());
+
         config.sessions.cleanup_interval_hours = 0;
         assert!(config.validate().is_err());
 
         config.sessions.cleanup_interval_hours = 12;
         assert!(config.validate().is_ok());
 
+        config.sessions.cleanup_batch_size = 0;
+        assert!(config.validate().is_err());
+        config.sessions.cleanup_batch_size = MAX_SESSION_CLEANUP_BATCH_SIZE + 1;
+        assert!(config.validate().is_err());
+        config.sessions.cleanup_batch_size = 5000;
+        assert!(config.validate().is_ok());
+
+        config.sessions.instance_lock_stale_secs = MIN_INSTANCE_LOCK_STALE_SECS - 1;
+        assert!(config.validate().is_err());
+
+        config.sessions.instance_lock_stale_secs = MIN_INSTANCE_LOCK_STALE_SECS;
+        assert!(config.validate().is_ok());
+
+        config.sessions.live_traffic_interval_secs = 0;
+        assert!(config.validate().is_err());
+
+        config.sessions.live_traffic_interval_secs = 1;
+        assert!(config.validate().is_ok());
+
         config.sessions.stats_window_hours = 0;
         assert!(config.validate().is_err());
 
+        // Zero batch settings loaded before adaptive batching; the writer clamps them
+        let mut config = Config::default();
+        config.sessions.batch_size = 0;
+        config.sessions.batch_interval_ms = 0;
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        for policy in ["block", "drop_oldest", "drop_newest"] {
+            config.sessions.batch_overflow_policy = policy.to_string();
+            assert!(config.validate().is_ok());
+        }
+        config.sessions.batch_overflow_policy = "drop".to_string();
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.sessions.batch_queue_max = config.sessions.batch_max_size - 1;
+        assert!(config.validate().is_err());
+
+        // API on a Unix domain socket
+        let mut config = Config::default();
+        config.sessions.stats_api_enabled = true;
+        config.sessions.stats_api_bind = Some("unix:/run/rustsocks/api.sock".to_string());
+        assert_eq!(config.validate().is_ok(), cfg!(unix));
+        config.sessions.stats_api_bind = Some("127.0.0.1:9090".to_string());
+        assert!(config.validate().is_err());
+        config.sessions.stats_api_bind = Some("unix:".to_string());
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.sessions.api_socket_mode = "0o600".to_string();
+        assert!(config.validate().is_ok());
+        for mode in ["0668", "1777", "rw-rw----"] {
+            config.sessions.api_socket_mode = mode.to_string();
+            assert!(config.validate().is_err(), "{}", mode);
+        }
+
         let mut config = Config::default();
         config.sessions.base_path = "".to_string();
         assert!(config.validate().is_err());
@@ -1168,6 +3903,42 @@ mod tests {
         assert!(config.validate().is_ok());
         assert_eq!(config.sessions.normalized_base_path(), "/rustsocks");
 
+        config.sessions.api_compression.exclude_paths = vec!["metrics".to_string()];
+        assert!(config.validate().is_err());
+        config.sessions.api_compression.exclude_paths = vec!["/metrics".to_string()];
+        assert!(config.validate().is_ok());
+
+        config.logging.sampling.connection_events_ratio = 1.5;
+        assert!(config.validate().is_err());
+        config.logging.sampling.connection_events_ratio = 0.01;
+        config.logging.sampling.always_log_users = vec![" ".to_string()];
+        assert!(config.validate().is_err());
+        config.logging.sampling.always_log_users = vec!["alice".to_string()];
+        assert!(config.validate().is_ok());
+
+        {
+            let mut config = Config::default();
+            config.sessions.api_auth.enabled = true;
+            assert!(config.validate().is_err()); // No keys
+
+            let key = |token: &str, role: &str| ApiKey {
+                token: token.to_string().into(),
+                role: role.to_string(),
+                name: None,
+            };
+            config.sessions.api_auth.keys = vec![key("0123456789abcdef", "read")];
+            assert!(config.validate().is_ok());
+            config.sessions.api_auth.keys = vec![key("short", "read")];
+            assert!(config.validate().is_err());
+            config.sessions.api_auth.keys = vec![key("0123456789abcdef", "write")];
+            assert!(config.validate().is_err());
+            config.sessions.api_auth.keys = vec![
+                key("0123456789abcdef", "read"),
+                key("0123456789abcdef", "admin"),
+            ];
+            assert!(config.validate().is_err());
+        }
+
         {
             let mut config = Config::default();
             config.sessions.dashboard_auth.enabled = true;
@@ -1175,7 +3946,8 @@ mod tests {
 
             config.sessions.dashboard_auth.users.push(User {
                 username: "admin".to_string(),
-                password: "secret".to_string(),
+                password: "secret".to_string().into(),
+                password_hash: None,
             });
             assert!(config.validate().is_ok());
         }
@@ -1185,7 +3957,8 @@ mod tests {
             config.sessions.dashboard_auth.enabled = true;
             config.sessions.dashboard_auth.users.push(User {
                 username: "".to_string(),
-                password: "secret".to_string(),
+                password: "secret".to_string().into(),
+                password_hash: None,
             });
             assert!(config.validate().is_err());
         }
@@ -1195,9 +3968,23 @@ mod tests {
             config.sessions.dashboard_auth.enabled = true;
             config.sessions.dashboard_auth.users.push(User {
                 username: "admin".to_string(),
-                password: "".to_string(),
+                password: "".to_string().into(),
+                password_hash: None,
             });
             assert!(config.validate().is_err());
         }
+
+        {
+            let mut config = Config::default();
+            config.resolver.special_names.localhost = "resolve".to_string();
+            assert!(config.validate().is_err());
+
+            config.resolver.special_names.localhost = "block".to_string();
+            config.resolver.special_names.local = "allow".to_string();
+            assert!(config.validate().is_err());
+
+            config.resolver.special_names.local = "resolve".to_string();
+            assert!(config.validate().is_ok());
+        }
     }
 }
@@ -3,14 +3,20 @@
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use rustsocks::acl::lint::{lint, reject_errors};
+use rustsocks::acl::{generate_acl_example, load_acl_config_sync, AclExampleVariant, LintSettings};
+use rustsocks::auth::{hash_params, hash_password};
 use rustsocks::config::Config;
 use rustsocks::server::SocksServer;
+use rustsocks::utils::log_sampling::SamplingLayer;
 use rustsocks::Result;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::{error, info};
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+use zeroize::Zeroizing;
 
 #[derive(Parser, Debug)]
 #[command(name = "RustSocks")]
@@ -18,7 +24,7 @@ use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 #[command(version)]
 struct Args {
     /// Configuration file path
-    #[arg(short, long, value_name = "FILE")]
+    #[arg(short, long, value_name = "FILE", global = true)]
     config: Option<PathBuf>,
 
     /// Bind address (overrides config)
@@ -33,9 +39,60 @@ struct Args {
     #[arg(long, value_name = "FILE")]
     generate_config: Option<PathBuf>,
 
+    /// Validate the configuration and the ACL file, print ACL lint findings and exit
+    #[arg(long)]
+    check: bool,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(long, default_value = "info")]
     log_level: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// ACL file tools
+    Acl {
+        #[command(subcommand)]
+        command: AclCommand,
+    },
+    /// Prompt for a password and print its hash for `password_hash`
+    ///
+    /// Uses the `auth.password_hashing` cost of --config when given.
+    HashPassword,
+    /// Self-test the configuration bundle of --config without starting listeners
+    ///
+    /// Loads the ACL file, the users file, TLS certificates and keys, connects to the
+    /// session database and looks for the PAM services, as far as each is enabled.
+    /// Exits with 1 when a check fails; warnings such as a certificate expiring within
+    /// 14 days do not fail the run.
+    Check {
+        /// Report format (text, json)
+        #[arg(long, value_enum, default_value = "text")]
+        output: CheckOutput,
+    },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CheckOutput {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum AclCommand {
+    /// Print an annotated example ACL file
+    Example {
+        /// Template variant (minimal, kitchen-sink, from-learning)
+        #[arg(long, default_value = "minimal")]
+        variant: AclExampleVariant,
+
+        /// Write the example to FILE instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
@@ -56,8 +113,21 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    match args.command {
+        Some(Command::Acl { command }) => return run_acl_command(command),
+        Some(Command::HashPassword) => return run_hash_password(config_path.as_deref()),
+        Some(Command::Check { output }) => {
+            return run_self_test(config_path.as_deref(), output).await
+        }
+        None => {}
+    }
+
+    if args.check {
+        return run_check(config_path.as_deref());
+    }
+
     // Initialize logging
-    init_logging(&args.log_level)?;
+    let log_filter = init_logging(&args.log_level)?;
 
     info!("RustSocks v{} starting", env!("CARGO_PKG_VERSION"));
     if let Ok(cwd) = std::env::current_dir() {
@@ -84,9 +154,21 @@ async fn main() -> Result<()> {
         config.server.bind_port = port;
     }
 
+    rustsocks::utils::build_info::log_startup(&config, config_path.as_deref());
+
     // Create and run server
     let server = SocksServer::new(config, config_path, Arc::new(original_args)).await?;
 
+    // A reload with a changed logging.level swaps the filter in place
+    server.config_reloader().set_log_level_hook(Box::new(
+        move |level: &str| -> std::result::Result<(), String> {
+            let filter = EnvFilter::try_new(level).map_err(|e| e.to_string())?;
+            log_filter.reload(filter).map_err(|e| e.to_string())?;
+            info!("Log level changed to {}", level);
+            Ok(())
+        },
+    ));
+
     info!("Server initialized, starting listener...");
 
     // Handle Ctrl+C for graceful shutdown
@@ -106,23 +188,193 @@ async fn main() -> Result<()> {
             }
         }
         _ = shutdown => {
-            info!("Server shutdown complete");
+            // Dropping run() closed the listener; shutdown() drains what is still open
+            info!("Stopped accepting connections");
         }
     }
 
     server.shutdown().await;
+    info!("Server shutdown complete");
+
+    Ok(())
+}
+
+fn run_acl_command(command: AclCommand) -> Result<()> {
+    match command {
+        AclCommand::Example { variant, output } => {
+            // Learned rules come from the session tracker of a running server
+            if variant == AclExampleVariant::FromLearning {
+                return Err(rustsocks::RustSocksError::Config(
+                    "The from-learning variant needs the traffic of a running server: \
+                     use GET /api/acl/example?variant=from-learning"
+                        .to_string(),
+                ));
+            }
+
+            let example =
+                generate_acl_example(variant, &[]).map_err(rustsocks::RustSocksError::Config)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, example)?;
+                    println!("Example ACL file ({}) written to {:?}", variant, path);
+                }
+                None => print!("{}", example),
+            }
+            Ok(())
+        }
+    }
+}
 
+/// `hash-password`: reads the password without echo, twice when on a terminal
+fn run_hash_password(config_path: Option<&Path>) -> Result<()> {
+    let settings = match config_path {
+        Some(path) => Config::from_file(path)?.auth.password_hashing,
+        None => Default::default(),
+    };
+    let params = hash_params(&settings)?;
+
+    let password = read_password("Password: ")?;
+    if password.is_empty() {
+        return Err(rustsocks::RustSocksError::Config(
+            "Password cannot be empty".to_string(),
+        ));
+    }
+    if std::io::stdin().is_terminal() && *read_password("Confirm password: ")? != *password {
+        return Err(rustsocks::RustSocksError::Config(
+            "Passwords do not match".to_string(),
+        ));
+    }
+
+    println!("{}", hash_password(&password, &params));
     Ok(())
 }
 
-fn init_logging(level: &str) -> Result<()> {
+/// One line from stdin, with terminal echo turned off while it is typed
+fn read_password(prompt: &str) -> Result<Zeroizing<String>> {
+    let stdin = std::io::stdin();
+    let interactive = stdin.is_terminal();
+    if interactive {
+        eprint!("{}", prompt);
+        std::io::stderr().flush()?;
+    }
+
+    let echo_off = interactive.then(EchoOff::new).flatten();
+    let mut line = Zeroizing::new(String::new());
+    stdin.read_line(&mut line)?;
+    drop(echo_off);
+    if interactive {
+        eprintln!();
+    }
+
+    let len = line.trim_end_matches(['\r', '\n']).len();
+    line.truncate(len);
+    Ok(line)
+}
+
+/// Terminal echo disabled until dropped
+#[cfg(unix)]
+struct EchoOff(libc::termios);
+
+#[cfg(unix)]
+impl EchoOff {
+    fn new() -> Option<Self> {
+        // SAFETY: termios is plain data that tcgetattr fills in
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return None;
+        }
+        let mut silent = saved;
+        silent.c_lflag &= !libc::ECHO;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &silent) } != 0 {
+            return None;
+        }
+        Some(Self(saved))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0);
+        }
+    }
+}
+
+/// Echo cannot be turned off here; the password is read as typed
+#[cfg(not(unix))]
+struct EchoOff;
+
+#[cfg(not(unix))]
+impl EchoOff {
+    fn new() -> Option<Self> {
+        None
+    }
+}
+
+/// `--check`: fails on an invalid configuration or ACL file, and on lint errors
+fn run_check(config_path: Option<&Path>) -> Result<()> {
+    let config = match config_path {
+        Some(path) => Config::from_file(path)?,
+        None => Config::default(),
+    };
+    println!("Configuration OK");
+
+    if !config.acl.enabled {
+        println!("ACL disabled, no ACL file to check");
+        return Ok(());
+    }
+    let acl_path = config
+        .acl
+        .config_file
+        .as_deref()
+        .expect("validated: config_file must be provided when ACL is enabled");
+    let acl = load_acl_config_sync(acl_path).map_err(rustsocks::RustSocksError::Config)?;
+
+    let findings = lint(&acl, &LintSettings::from(&config));
+    for finding in &findings {
+        println!(
+            "{} [{}] {}: {}",
+            finding.severity.as_str(),
+            finding.kind.as_str(),
+            finding.scope,
+            finding.message
+        );
+    }
+    reject_errors(&findings).map_err(rustsocks::RustSocksError::Config)?;
+
+    println!("ACL file {} OK, {} finding(s)", acl_path, findings.len());
+    Ok(())
+}
+
+async fn run_self_test(config_path: Option<&Path>, output: CheckOutput) -> Result<()> {
+    let report = rustsocks::server::run_self_test(config_path).await;
+    match output {
+        CheckOutput::Text => print!("{}", report.render_text()),
+        CheckOutput::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("self-test report serializes")
+        ),
+    }
+    std::io::stdout().flush()?;
+
+    if !report.passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Returns the handle a config reload uses to change the level
+fn init_logging(level: &str) -> Result<reload::Handle<EnvFilter, Registry>> {
     let env_filter = EnvFilter::try_new(level)
         .map_err(|e| rustsocks::RustSocksError::Config(format!("Invalid log level: {}", e)))?;
+    let (env_filter, handle) = reload::Layer::new(env_filter);
 
     tracing_subscriber::registry()
         .with(env_filter)
+        .with(SamplingLayer)
         .with(fmt::layer())
         .init();
 
-    Ok(())
+    Ok(handle)
 }
@@ -2,8 +2,10 @@ use super::types::*;
 use crate::utils::error::{Result, RustSocksError};
 use bytes::Bytes;
 use smallvec::SmallVec;
+use std::net::SocketAddr;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tracing::{debug, trace};
+use zeroize::Zeroizing;
 
 /// Parse client greeting (method selection) for SOCKS5.
 /// The caller must provide the already-read version byte.
@@ -26,11 +28,13 @@ where
         ));
     }
 
-    // Read methods - use SmallVec for stack allocation (SOCKS5 typically has 1-3 methods)
-    let mut methods_buf = SmallVec::<[u8; 8]>::from_elem(0, nmethods as usize);
-    stream.read_exact(&mut methods_buf).await?;
+    // Read methods into a fixed stack buffer (nmethods is a u8, so 255 is the upper bound)
+    let mut methods_buf = [0u8; 255];
+    let methods_buf = &mut methods_buf[..nmethods as usize];
+    stream.read_exact(methods_buf).await?;
 
-    let methods: Vec<AuthMethod> = methods_buf.into_iter().map(AuthMethod::from).collect();
+    let methods: SmallVec<[AuthMethod; 8]> =
+        methods_buf.iter().copied().map(AuthMethod::from).collect();
 
     trace!("Parsed client greeting: {} methods", methods.len());
 
@@ -52,13 +56,32 @@ where
     Ok(())
 }
 
-/// Parse username/password authentication (RFC 1929)
-pub async fn parse_userpass_auth<S>(stream: &mut S) -> Result<(String, String)>
+/// Parse a username/password sub-negotiation request (RFC 1929).
+///
+/// ULEN and PLEN must be 1-255; both fields are read into one 255-byte stack buffer,
+/// so a declared length never allocates. A malformed or truncated request is answered
+/// with a failure status before the error is returned. Reads are not bounded here:
+/// callers run this inside the handshake deadline.
+pub async fn parse_userpass_auth<S>(stream: &mut S) -> Result<(String, Zeroizing<String>)>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    match read_userpass_auth(stream).await {
+        Ok(login) => Ok(login),
+        Err(e) => {
+            // Best effort: a client that only closed its sending side still reads this
+            let _ = send_auth_response(stream, false).await;
+            Err(e)
+        }
+    }
+}
+
+async fn read_userpass_auth<S>(stream: &mut S) -> Result<(String, Zeroizing<String>)>
+where
+    S: AsyncRead + Unpin,
 {
     // Read version
-    let version = stream.read_u8().await?;
+    let version = stream.read_u8().await.map_err(userpass_read_error)?;
 
     if version != 0x01 {
         return Err(RustSocksError::Protocol(format!(
@@ -67,18 +90,18 @@ where
         )));
     }
 
-    // Read username - use SmallVec for stack allocation (most usernames < 64 bytes)
-    let username_len = stream.read_u8().await? as usize;
-    let mut username_buf = SmallVec::<[u8; 64]>::from_elem(0, username_len);
-    stream.read_exact(&mut username_buf).await?;
-    let username = String::from_utf8(username_buf.to_vec())
+    // Both fields are length-prefixed by a u8, so one stack buffer fits either of them.
+    // It briefly holds the password, so it is wiped when it goes out of scope.
+    let mut scratch = Zeroizing::new([0u8; 255]);
+
+    let username = read_userpass_field(stream, &mut scratch, "username").await?;
+    let username = std::str::from_utf8(username)
+        .map(str::to_owned)
         .map_err(|_| RustSocksError::Protocol("Invalid username encoding".to_string()))?;
 
-    // Read password - use SmallVec for stack allocation (most passwords < 64 bytes)
-    let password_len = stream.read_u8().await? as usize;
-    let mut password_buf = SmallVec::<[u8; 64]>::from_elem(0, password_len);
-    stream.read_exact(&mut password_buf).await?;
-    let password = String::from_utf8(password_buf.to_vec())
+    let password = read_userpass_field(stream, &mut scratch, "password").await?;
+    let password = std::str::from_utf8(password)
+        .map(|password| Zeroizing::new(password.to_owned()))
         .map_err(|_| RustSocksError::Protocol("Invalid password encoding".to_string()))?;
 
     trace!("Parsed userpass auth for user: {}", username);
@@ -86,6 +109,37 @@ where
     Ok((username, password))
 }
 
+/// One length-prefixed field of the sub-negotiation; empty fields are malformed
+async fn read_userpass_field<'a, S>(
+    stream: &mut S,
+    scratch: &'a mut [u8; 255],
+    field: &str,
+) -> Result<&'a [u8]>
+where
+    S: AsyncRead + Unpin,
+{
+    let len = stream.read_u8().await.map_err(userpass_read_error)? as usize;
+    if len == 0 {
+        return Err(RustSocksError::Protocol(format!(
+            "Empty {} in userpass sub-negotiation",
+            field
+        )));
+    }
+    stream
+        .read_exact(&mut scratch[..len])
+        .await
+        .map_err(userpass_read_error)?;
+    Ok(&scratch[..len])
+}
+
+fn userpass_read_error(e: std::io::Error) -> RustSocksError {
+    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+        RustSocksError::Protocol("Truncated userpass sub-negotiation".to_string())
+    } else {
+        e.into()
+    }
+}
+
 /// Send authentication response
 #[inline(always)]
 pub async fn send_auth_response<S>(stream: &mut S, success: bool) -> Result<()>
@@ -145,13 +199,15 @@ where
             Address::IPv4(addr)
         }
         0x03 => {
-            // Domain name - use SmallVec for stack allocation (most domains < 128 bytes)
+            // Domain name - read into a stack buffer; `DomainName` copies it inline, and only
+            // names longer than the inline buffer allocate (one shared `Arc<str>`)
             let domain_len = stream.read_u8().await? as usize;
-            let mut domain_buf = SmallVec::<[u8; 128]>::from_elem(0, domain_len);
-            stream.read_exact(&mut domain_buf).await?;
-            let domain = String::from_utf8(domain_buf.to_vec())
+            let mut domain_buf = [0u8; 255];
+            let domain_buf = &mut domain_buf[..domain_len];
+            stream.read_exact(domain_buf).await?;
+            let domain = std::str::from_utf8(domain_buf)
                 .map_err(|_| RustSocksError::Protocol("Invalid domain encoding".to_string()))?;
-            Address::Domain(domain)
+            Address::Domain(domain.into())
         }
         0x04 => {
             // IPv6
@@ -169,9 +225,7 @@ where
 
     debug!(
         "Parsed SOCKS5 request: command={:?}, address={}, port={}",
-        command,
-        address.to_string(),
-        port
+        command, address, port
     );
 
     Ok(Socks5Request {
@@ -230,9 +284,7 @@ where
 
     debug!(
         "Sent SOCKS5 response: reply={:?}, bind_addr={}, bind_port={}",
-        reply,
-        bind_addr.to_string(),
-        bind_port
+        reply, bind_addr, bind_port
     );
 
     Ok(())
@@ -266,17 +318,14 @@ where
                     "SOCKS4a domain name missing".to_string(),
                 ));
             }
-            Address::Domain(domain)
+            Address::Domain(domain.into())
         } else {
             Address::IPv4(ip_octets)
         };
 
     debug!(
         "Parsed SOCKS4 request: command={:?}, address={}, port={}, user_id={:?}",
-        command,
-        address.to_string(),
-        port,
-        user_id
+        command, address, port, user_id
     );
 
     Ok(Socks4Request {
@@ -297,11 +346,17 @@ pub async fn send_socks4_response<S>(
 where
     S: AsyncRead + AsyncWrite + Unpin + Send,
 {
-    let mut buf = Vec::with_capacity(8);
-    buf.push(0x00);
-    buf.push(reply as u8);
-    buf.extend_from_slice(&bind_port.to_be_bytes());
-    buf.extend_from_slice(&bind_addr);
+    let port = bind_port.to_be_bytes();
+    let buf = [
+        0x00,
+        reply as u8,
+        port[0],
+        port[1],
+        bind_addr[0],
+        bind_addr[1],
+        bind_addr[2],
+        bind_addr[3],
+    ];
 
     stream.write_all(&buf).await?;
     stream.flush().await?;
@@ -336,13 +391,76 @@ where
         bytes.push(byte);
     }
 
-    String::from_utf8(bytes.to_vec())
+    std::str::from_utf8(&bytes)
+        .map(str::to_owned)
         .map_err(|_| RustSocksError::Protocol("Invalid string encoding".to_string()))
 }
 
+/// Longest SOCKS5 UDP header that carries an IP address:
+/// RSV(2) + FRAG(1) + ATYP(1) + IPv6(16) + PORT(2). Relay buffers keep this much
+/// headroom in front of received payloads so replies can be wrapped in place.
+pub const UDP_IP_HEADER_MAX: usize = 22;
+
 /// Parse UDP packet from raw bytes
 /// Format: RSV(2) + FRAG(1) + ATYP(1) + DST.ADDR(var) + DST.PORT(2) + DATA
 pub fn parse_udp_packet(buf: Bytes) -> Result<UdpPacket> {
+    let (header, header_len) = parse_udp_header(&buf)?;
+    Ok(UdpPacket {
+        header,
+        data: buf.slice(header_len..),
+    })
+}
+
+/// Length of the SOCKS5 UDP header (RSV through DST.PORT) at the start of `datagram`.
+///
+/// This is the FRAG=0 fast path: lengths and the address type are checked without
+/// decoding the address, so nothing is allocated. Fragmented datagrams are dropped.
+#[inline]
+pub fn udp_header_len(datagram: &[u8]) -> Result<usize> {
+    let header_len = udp_fragment_header_len(datagram)?;
+    if datagram[2] != 0 {
+        return Err(fragmented_udp_packet(datagram[2]));
+    }
+    Ok(header_len)
+}
+
+/// Like [`udp_header_len`], for a datagram with any FRAG value.
+#[inline]
+pub fn udp_fragment_header_len(datagram: &[u8]) -> Result<usize> {
+    if datagram.len() < 10 {
+        return Err(RustSocksError::Protocol("UDP packet too short".to_string()));
+    }
+
+    let header_len = match datagram[3] {
+        0x01 => 10,
+        0x04 => UDP_IP_HEADER_MAX,
+        0x03 => 7 + datagram[4] as usize,
+        other => return Err(RustSocksError::UnsupportedAddressType(other)),
+    };
+    if datagram.len() < header_len {
+        return Err(RustSocksError::Protocol(
+            "Malformed UDP packet (truncated header)".to_string(),
+        ));
+    }
+    Ok(header_len)
+}
+
+#[cold]
+fn fragmented_udp_packet(frag: u8) -> RustSocksError {
+    // RFC 1928: "If an implementation does not support fragmentation, it MUST drop
+    // any datagram whose FRAG field is other than X'00'."
+    trace!(
+        "Dropping UDP packet with FRAG={} (fragmentation not supported)",
+        frag
+    );
+    RustSocksError::Protocol(
+        "UDP fragmentation not supported - packet dropped per RFC 1928".to_string(),
+    )
+}
+
+/// Parse the SOCKS5 UDP header at the start of `buf`.
+/// Returns the header and its length; the payload follows it.
+pub fn parse_udp_header(buf: &[u8]) -> Result<(UdpHeader, usize)> {
     if buf.len() < 10 {
         return Err(RustSocksError::Protocol("UDP packet too short".to_string()));
     }
@@ -369,16 +487,8 @@ pub fn parse_udp_packet(buf: Bytes) -> Result<UdpPacket> {
     let frag = buf[pos];
     pos += 1;
 
-    // RFC 1928: "If an implementation does not support fragmentation, it MUST drop
-    // any datagram whose FRAG field is other than X'00'."
     if frag != 0 {
-        trace!(
-            "Dropping UDP packet with FRAG={} (fragmentation not supported)",
-            frag
-        );
-        return Err(RustSocksError::Protocol(
-            "UDP fragmentation not supported - packet dropped per RFC 1928".to_string(),
-        ));
+        return Err(fragmented_udp_packet(frag));
     }
 
     // Address type
@@ -417,11 +527,11 @@ pub fn parse_udp_packet(buf: Bytes) -> Result<UdpPacket> {
                     "Invalid domain in UDP packet".to_string(),
                 ));
             }
-            let domain = String::from_utf8(buf[pos..pos + domain_len].to_vec()).map_err(|_| {
+            let domain = std::str::from_utf8(&buf[pos..pos + domain_len]).map_err(|_| {
                 RustSocksError::Protocol("Invalid domain encoding in UDP packet".to_string())
             })?;
             pos += domain_len;
-            Address::Domain(domain)
+            Address::Domain(domain.into())
         }
         0x04 => {
             // IPv6
@@ -449,22 +559,38 @@ pub fn parse_udp_packet(buf: Bytes) -> Result<UdpPacket> {
     let port = u16::from_be_bytes([buf[pos], buf[pos + 1]]);
     pos += 2;
 
-    // Data (rest of packet)
-    if pos > len {
-        return Err(RustSocksError::Protocol(
-            "Malformed UDP packet (missing payload)".to_string(),
-        ));
-    }
-    let data = buf.slice(pos..);
-
-    Ok(UdpPacket {
-        header: UdpHeader {
+    Ok((
+        UdpHeader {
             frag,
             address,
             port,
         },
-        data,
-    })
+        pos,
+    ))
+}
+
+/// Wrap a received payload in a SOCKS5 UDP header for `source` without moving it.
+///
+/// `buf` holds [`UDP_IP_HEADER_MAX`] bytes of headroom followed by `payload_len` bytes
+/// of payload. The header is written at the end of the headroom and the returned slice
+/// is the complete datagram, byte-for-byte what [`serialize_udp_packet`] would produce.
+#[inline]
+pub fn encapsulate_udp_in_place(buf: &mut [u8], payload_len: usize, source: SocketAddr) -> &[u8] {
+    let start = match source {
+        SocketAddr::V4(addr) => {
+            let start = UDP_IP_HEADER_MAX - 10;
+            buf[start..start + 4].copy_from_slice(&[0x00, 0x00, 0x00, 0x01]);
+            buf[start + 4..start + 8].copy_from_slice(&addr.ip().octets());
+            start
+        }
+        SocketAddr::V6(addr) => {
+            buf[..4].copy_from_slice(&[0x00, 0x00, 0x00, 0x04]);
+            buf[4..20].copy_from_slice(&addr.ip().octets());
+            0
+        }
+    };
+    buf[UDP_IP_HEADER_MAX - 2..UDP_IP_HEADER_MAX].copy_from_slice(&source.port().to_be_bytes());
+    &buf[start..UDP_IP_HEADER_MAX + payload_len]
 }
 
 /// Serialize UDP packet to bytes
@@ -618,7 +744,13 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_socks5_client_greeting, AuthMethod, SOCKS_VERSION};
+    use super::{
+        encapsulate_udp_in_place, parse_socks5_client_greeting, parse_udp_header,
+        serialize_udp_packet, udp_fragment_header_len, udp_header_len, Address, AuthMethod,
+        UdpHeader, UdpPacket, SOCKS_VERSION, UDP_IP_HEADER_MAX,
+    };
+    use bytes::Bytes;
+    use std::net::SocketAddr;
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::net::{TcpListener, TcpStream};
 
@@ -642,8 +774,74 @@ mod tests {
 
         let greeting = server.await.unwrap();
         assert_eq!(
-            greeting.methods,
-            vec![AuthMethod::NoAuth, AuthMethod::UserPass]
+            greeting.methods.as_slice(),
+            &[AuthMethod::NoAuth, AuthMethod::UserPass]
+        );
+    }
+
+    #[test]
+    fn test_udp_in_place_encapsulation_matches_serializer() {
+        let payload = b"payload bytes";
+        for source in ["192.0.2.7:5353", "[2001:db8::1]:443"] {
+            let source: SocketAddr = source.parse().unwrap();
+            let mut buf = vec![0xEE; UDP_IP_HEADER_MAX + payload.len()];
+            buf[UDP_IP_HEADER_MAX..].copy_from_slice(payload);
+
+            let expected = serialize_udp_packet(&UdpPacket {
+                header: UdpHeader {
+                    frag: 0,
+                    address: match source {
+                        SocketAddr::V4(addr) => Address::IPv4(addr.ip().octets()),
+                        SocketAddr::V6(addr) => Address::IPv6(addr.ip().octets()),
+                    },
+                    port: source.port(),
+                },
+                data: Bytes::from_static(payload),
+            });
+            let datagram = encapsulate_udp_in_place(&mut buf, payload.len(), source);
+            assert_eq!(datagram, expected.as_slice(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_udp_header_len_matches_full_parse() {
+        let headers = [
+            UdpHeader {
+                frag: 0,
+                address: Address::IPv4([10, 0, 0, 1]),
+                port: 53,
+            },
+            UdpHeader {
+                frag: 0,
+                address: Address::IPv6([
+                    0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+                ]),
+                port: 443,
+            },
+            UdpHeader {
+                frag: 0,
+                address: Address::Domain("example.com".into()),
+                port: 8080,
+            },
+        ];
+        for header in headers {
+            let datagram = serialize_udp_packet(&UdpPacket {
+                header,
+                data: Bytes::from_static(b"data"),
+            });
+            let (_, parsed_len) = parse_udp_header(&datagram).unwrap();
+            assert_eq!(udp_header_len(&datagram).unwrap(), parsed_len);
+            assert_eq!(&datagram[parsed_len..], b"data");
+        }
+
+        // Fragments, unknown address types and truncated headers never reach the payload
+        assert!(udp_header_len(&[0, 0, 1, 0x01, 10, 0, 0, 1, 0, 53]).is_err());
+        assert_eq!(
+            udp_fragment_header_len(&[0, 0, 0x81, 0x01, 10, 0, 0, 1, 0, 53, 7]).unwrap(),
+            10
         );
+        assert!(udp_header_len(&[0, 0, 0, 0x02, 10, 0, 0, 1, 0, 53]).is_err());
+        assert!(udp_header_len(&[0, 0, 0, 0x03, 20, b'a', b'b', b'c', 0, 53]).is_err());
+        assert!(udp_header_len(&[0, 0, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
     }
 }
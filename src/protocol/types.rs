@@ -1,6 +1,11 @@
 use bytes::Bytes;
+use smallvec::SmallVec;
 use std::fmt;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::ops::Deref;
+use std::sync::Arc;
 
 /// SOCKS protocol versions
 pub const SOCKS_VERSION: u8 = 0x05;
@@ -27,6 +32,17 @@ impl From<u8> for AuthMethod {
     }
 }
 
+impl AuthMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthMethod::NoAuth => "none",
+            AuthMethod::Gssapi => "gssapi",
+            AuthMethod::UserPass => "userpass",
+            AuthMethod::NoAcceptable => "no_acceptable",
+        }
+    }
+}
+
 /// SOCKS5 commands
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -51,12 +67,265 @@ impl TryFrom<u8> for Command {
     }
 }
 
+impl Command {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Command::Connect => "connect",
+            Command::Bind => "bind",
+            Command::UdpAssociate => "udp_associate",
+        }
+    }
+}
+
 /// Address types
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Address {
     IPv4([u8; 4]),
     IPv6([u8; 16]),
-    Domain(String),
+    Domain(DomainName),
+}
+
+/// Longest domain name kept inline in [`DomainName`].
+const INLINE_DOMAIN_LEN: usize = 30;
+
+/// Domain name from a SOCKS request, stored inline when short.
+///
+/// Most requested names fit in the inline buffer, so parsing a domain request does not
+/// allocate; longer names (SOCKS5 allows up to 255 bytes) share one `Arc<str>`, which
+/// also makes clones cheap.
+#[derive(Clone)]
+pub struct DomainName(DomainRepr);
+
+#[derive(Clone)]
+enum DomainRepr {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_DOMAIN_LEN],
+    },
+    Heap(Arc<str>),
+}
+
+impl DomainName {
+    pub fn new(name: &str) -> Self {
+        if name.len() <= INLINE_DOMAIN_LEN {
+            let mut bytes = [0u8; INLINE_DOMAIN_LEN];
+            bytes[..name.len()].copy_from_slice(name.as_bytes());
+            Self(DomainRepr::Inline {
+                len: name.len() as u8,
+                bytes,
+            })
+        } else {
+            Self(DomainRepr::Heap(Arc::from(name)))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // SAFETY: inline bytes are only ever copied from a `&str` in `new`
+            DomainRepr::Inline { len, bytes } => unsafe {
+                std::str::from_utf8_unchecked(&bytes[..*len as usize])
+            },
+            DomainRepr::Heap(name) => name,
+        }
+    }
+
+    /// Whether the name is stored inline (no heap allocation).
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, DomainRepr::Inline { .. })
+    }
+}
+
+impl Deref for DomainName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for DomainName {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for DomainName {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for DomainName {
+    fn from(name: String) -> Self {
+        if name.len() <= INLINE_DOMAIN_LEN {
+            Self::new(&name)
+        } else {
+            Self(DomainRepr::Heap(Arc::from(name)))
+        }
+    }
+}
+
+impl From<&String> for DomainName {
+    fn from(name: &String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl PartialEq for DomainName {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for DomainName {}
+
+impl PartialEq<str> for DomainName {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for DomainName {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for DomainName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl fmt::Debug for DomainName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for DomainName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Address {
+    /// Render the address as a shared string with exactly one allocation.
+    pub fn to_arc_str(&self) -> Arc<str> {
+        let ip = match self {
+            Address::Domain(domain) => return Arc::from(domain.as_str()),
+            Address::IPv4(octets) => IpAddr::V4(Ipv4Addr::from(*octets)),
+            Address::IPv6(octets) => IpAddr::V6(Ipv6Addr::from(*octets)),
+        };
+
+        // Longest textual IPv6 address is 45 bytes
+        let mut buf = [0u8; 46];
+        let mut cursor = std::io::Cursor::new(&mut buf[..]);
+        write!(cursor, "{}", ip).expect("IP address fits the buffer");
+        let len = cursor.position() as usize;
+        Arc::from(std::str::from_utf8(&buf[..len]).expect("IP addresses format as ASCII"))
+    }
+
+    /// BND.ADDR of a reply about a socket bound to `ip`. Dual-stack sockets report IPv4
+    /// addresses as IPv4-mapped IPv6; those are sent as IPv4.
+    pub fn bound(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(v4) => Address::IPv4(v4.octets()),
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => Address::IPv4(v4.octets()),
+                None => Address::IPv6(v6.octets()),
+            },
+        }
+    }
+
+    /// IP of the destination, also when a client sent an IP literal as a domain name
+    pub fn literal_ip(&self) -> Option<IpAddr> {
+        match self {
+            Address::IPv4(octets) => Some(IpAddr::V4(Ipv4Addr::from(*octets))),
+            Address::IPv6(octets) => Some(IpAddr::V6(Ipv6Addr::from(*octets))),
+            Address::Domain(domain) => IpLiteral::parse(domain).map(|literal| literal.ip),
+        }
+    }
+
+    /// Socket address of an IP destination, domain-form literals included, so it can be
+    /// connected to without a lookup. `None` for names, and for a zone naming an
+    /// interface this host does not have.
+    pub fn literal_socket_addr(&self, port: u16) -> Option<SocketAddr> {
+        match self {
+            Address::Domain(domain) => IpLiteral::parse(domain)?.socket_addr(port),
+            _ => self.literal_ip().map(|ip| SocketAddr::new(ip, port)),
+        }
+    }
+}
+
+/// IP literal written where a domain name goes.
+///
+/// Accepts plain addresses, bracketed IPv6 (`[2001:db8::1]`) and IPv6 with a zone
+/// (`fe80::1%eth0`, or `[fe80::1%25eth0]` as escaped in URIs). Brackets around IPv4, a
+/// zone on IPv4 and an empty zone are not literals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpLiteral<'a> {
+    pub ip: IpAddr,
+    /// Interface name or numeric scope id after `%`
+    pub zone: Option<&'a str>,
+}
+
+impl<'a> IpLiteral<'a> {
+    pub fn parse(text: &'a str) -> Option<Self> {
+        let (text, bracketed) = match text.strip_prefix('[') {
+            Some(rest) => (rest.strip_suffix(']')?, true),
+            None => (text, false),
+        };
+        let (ip, zone) = match text.split_once('%') {
+            Some((ip, zone)) if bracketed => (
+                ip,
+                Some(
+                    zone.strip_prefix("25")
+                        .filter(|z| !z.is_empty())
+                        .unwrap_or(zone),
+                ),
+            ),
+            Some((ip, zone)) => (ip, Some(zone)),
+            None => (text, None),
+        };
+
+        let ip: IpAddr = ip.parse().ok()?;
+        match (ip, zone) {
+            (IpAddr::V4(_), _) if bracketed => None,
+            (IpAddr::V4(_), Some(_)) => None,
+            (_, Some("")) => None,
+            _ => Some(Self { ip, zone }),
+        }
+    }
+
+    /// Scope id of the zone: 0 without one, the number itself for a numeric zone,
+    /// otherwise the index of the named interface
+    pub fn scope_id(&self) -> Option<u32> {
+        match self.zone {
+            None => Some(0),
+            Some(zone) => zone.parse().ok().or_else(|| interface_index(zone)),
+        }
+    }
+
+    pub fn socket_addr(&self, port: u16) -> Option<SocketAddr> {
+        match self.ip {
+            IpAddr::V4(_) => Some(SocketAddr::new(self.ip, port)),
+            IpAddr::V6(v6) => Some(SocketAddrV6::new(v6, port, 0, self.scope_id()?).into()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn interface_index(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: `name` is a NUL-terminated string that outlives the call
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    (index != 0).then_some(index)
+}
+
+#[cfg(not(unix))]
+fn interface_index(_name: &str) -> Option<u32> {
+    None
 }
 
 impl fmt::Display for Address {
@@ -84,10 +353,33 @@ pub enum ReplyCode {
     AddressTypeNotSupported = 0x08,
 }
 
+impl ReplyCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplyCode::Succeeded => "succeeded",
+            ReplyCode::GeneralFailure => "general_failure",
+            ReplyCode::ConnectionNotAllowed => "connection_not_allowed",
+            ReplyCode::NetworkUnreachable => "network_unreachable",
+            ReplyCode::HostUnreachable => "host_unreachable",
+            ReplyCode::ConnectionRefused => "connection_refused",
+            ReplyCode::TtlExpired => "ttl_expired",
+            ReplyCode::CommandNotSupported => "command_not_supported",
+            ReplyCode::AddressTypeNotSupported => "address_type_not_supported",
+        }
+    }
+}
+
+impl fmt::Display for ReplyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Client greeting message
 #[derive(Debug)]
 pub struct ClientGreeting {
-    pub methods: Vec<AuthMethod>,
+    /// Offered methods, kept inline (clients rarely offer more than a handful)
+    pub methods: SmallVec<[AuthMethod; 8]>,
 }
 
 /// Server choice message
@@ -175,9 +467,119 @@ mod tests {
         let ipv4 = Address::IPv4([192, 168, 1, 1]);
         assert_eq!(ipv4.to_string(), "192.168.1.1");
 
-        let domain = Address::Domain("example.com".to_string());
+        let domain = Address::Domain("example.com".into());
         assert_eq!(domain.to_string(), "example.com");
     }
+
+    #[test]
+    fn test_address_to_arc_str() {
+        let ipv6 = Address::IPv6(Ipv6Addr::new(0xffff, 1, 2, 3, 4, 5, 6, 7).octets());
+        assert_eq!(ipv6.to_arc_str().as_ref(), ipv6.to_string());
+        assert_eq!(
+            Address::IPv4([10, 0, 0, 1]).to_arc_str().as_ref(),
+            "10.0.0.1"
+        );
+        assert_eq!(
+            Address::Domain("example.com".into()).to_arc_str().as_ref(),
+            "example.com"
+        );
+    }
+
+    #[test]
+    fn test_bound_address_family() {
+        assert_eq!(
+            Address::bound("192.0.2.7".parse().unwrap()),
+            Address::IPv4([192, 0, 2, 7])
+        );
+        let v6: Ipv6Addr = "2001:db8::7".parse().unwrap();
+        assert_eq!(Address::bound(IpAddr::V6(v6)), Address::IPv6(v6.octets()));
+        // A dual-stack socket's view of an IPv4 connection
+        assert_eq!(
+            Address::bound("::ffff:192.0.2.7".parse().unwrap()),
+            Address::IPv4([192, 0, 2, 7])
+        );
+    }
+
+    #[test]
+    fn test_domain_name_storage() {
+        let short = DomainName::from("api.example.com");
+        assert!(short.is_inline());
+        assert_eq!(short, "api.example.com");
+
+        let long_name = "a".repeat(200) + ".example.com";
+        let long = DomainName::from(long_name.clone());
+        assert!(!long.is_inline());
+        assert_eq!(long.as_str(), long_name);
+        assert_eq!(long.clone(), long);
+
+        let boundary = "x".repeat(INLINE_DOMAIN_LEN);
+        assert!(DomainName::from(boundary.as_str()).is_inline());
+        assert!(!DomainName::from(boundary + "y").is_inline());
+    }
+
+    #[test]
+    fn bracketed_ipv6_is_a_literal() {
+        let address = Address::Domain("[2001:db8::1]".into());
+        let ip: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(address.literal_ip(), Some(ip));
+        assert_eq!(
+            address.literal_socket_addr(443),
+            Some(SocketAddr::new(ip, 443))
+        );
+        assert_eq!(
+            Address::Domain("10.0.0.1".into()).literal_ip(),
+            Some(IpAddr::from([10, 0, 0, 1]))
+        );
+        assert_eq!(Address::Domain("example.com".into()).literal_ip(), None);
+    }
+
+    #[test]
+    fn zone_maps_to_scope_id() {
+        let numeric = Address::Domain("fe80::1%3".into());
+        let Some(SocketAddr::V6(addr)) = numeric.literal_socket_addr(80) else {
+            panic!("zoned literal not converted");
+        };
+        assert_eq!(*addr.ip(), "fe80::1".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(addr.scope_id(), 3);
+
+        // URI form escapes the `%`
+        let literal = IpLiteral::parse("[fe80::1%25eth0]").unwrap();
+        assert_eq!(literal.zone, Some("eth0"));
+        assert_eq!(IpLiteral::parse("[fe80::1%7]").unwrap().scope_id(), Some(7));
+
+        #[cfg(target_os = "linux")]
+        {
+            let Some(SocketAddr::V6(addr)) =
+                Address::Domain("fe80::1%lo".into()).literal_socket_addr(80)
+            else {
+                panic!("interface zone not converted");
+            };
+            assert_ne!(addr.scope_id(), 0);
+        }
+
+        // An unknown interface still names an IP, but cannot be connected to as such
+        let unknown = Address::Domain("fe80::1%no-such-if0".into());
+        assert!(unknown.literal_ip().is_some());
+        assert_eq!(unknown.literal_socket_addr(80), None);
+    }
+
+    #[test]
+    fn malformed_literals_are_names() {
+        for text in [
+            "[2001:db8::1",
+            "2001:db8::1]",
+            "[10.0.0.1]",
+            "10.0.0.1%eth0",
+            "fe80::1%",
+            "[fe80::1%]",
+            "[::1]:443",
+            "[]",
+            "[example.com]",
+        ] {
+            assert_eq!(IpLiteral::parse(text), None, "{text}");
+            assert_eq!(Address::Domain(text.into()).literal_ip(), None, "{text}");
+        }
+    }
 }
 /// SOCKS protocol negotiated with the client
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -186,6 +588,16 @@ pub enum SocksProtocol {
     V5,
 }
 
+impl SocksProtocol {
+    /// Version byte the client opened with (4 also covers SOCKS4a)
+    pub fn version(&self) -> u8 {
+        match self {
+            SocksProtocol::V4 => SOCKS4_VERSION,
+            SocksProtocol::V5 => SOCKS_VERSION,
+        }
+    }
+}
+
 /// GSS-API message types (RFC 1961)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -1,10 +1,12 @@
 use super::metrics::QosMetrics;
 use super::token_bucket::TokenBucket;
-use super::types::{HtbConfig, UserAllocation};
+use super::types::{HtbConfig, IpAllocation, PerIpConfig, UserAllocation};
 use crate::utils::error::{Result, RustSocksError};
 use dashmap::DashMap;
+use ipnet::IpNet;
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock, Weak};
 use std::time::{Duration, Instant};
 use tokio::task::JoinHandle;
 use tokio::time::interval;
@@ -30,6 +32,9 @@ struct UserBucket {
 
     /// Total bytes transferred (for statistics)
     total_bytes: AtomicU64,
+
+    /// Transfers waiting for this user's tokens right now
+    waiting: AtomicUsize,
 }
 
 impl UserBucket {
@@ -41,6 +46,7 @@ impl UserBucket {
             last_activity: Arc::new(tokio::sync::Mutex::new(Instant::now())),
             active_connections: AtomicUsize::new(0),
             total_bytes: AtomicU64::new(0),
+            waiting: AtomicUsize::new(0),
         }
     }
 
@@ -76,14 +82,88 @@ impl UserBucket {
     fn connection_count(&self) -> usize {
         self.active_connections.load(Ordering::Relaxed)
     }
+
+    /// Set the refill rates of both buckets
+    async fn set_rates(&self, guaranteed_rate: u64, max_rate: u64) {
+        self.guaranteed_bucket
+            .set_refill_rate(guaranteed_rate)
+            .await;
+        self.max_bucket.set_refill_rate(max_rate).await;
+    }
+}
+
+/// Counts a transfer as waiting on a user bucket until dropped
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaitingGuard<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::Relaxed);
+        Self(waiting)
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Per-source-IP bucket (`qos.per_ip`)
+#[derive(Debug)]
+struct IpBucket {
+    bucket: TokenBucket,
+
+    /// Last activity timestamp
+    last_activity: Arc<tokio::sync::Mutex<Instant>>,
+
+    /// Total bytes transferred (for statistics)
+    total_bytes: AtomicU64,
+}
+
+impl IpBucket {
+    fn new(rate: u64, burst_size: u64) -> Self {
+        Self {
+            bucket: TokenBucket::new(burst_size, rate),
+            last_activity: Arc::new(tokio::sync::Mutex::new(Instant::now())),
+            total_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Check if the address had traffic within the idle timeout
+    async fn is_active(&self, idle_timeout: Duration) -> bool {
+        let last_activity = self.last_activity.lock().await;
+        last_activity.elapsed() < idle_timeout
+    }
+
+    /// Update activity timestamp
+    async fn update_activity(&self) {
+        let mut last_activity = self.last_activity.lock().await;
+        *last_activity = Instant::now();
+    }
+}
+
+/// `qos.per_ip` with the exemptions parsed
+#[derive(Debug)]
+struct PerIpShaping {
+    config: PerIpConfig,
+    exempt: Vec<IpNet>,
+}
+
+impl PerIpShaping {
+    fn is_exempt(&self, ip: IpAddr) -> bool {
+        self.exempt.iter().any(|net| net.contains(&ip))
+    }
 }
 
 type UserKey = Arc<str>;
 
 /// Hierarchical Token Bucket QoS Engine
-#[derive(Clone)]
+///
+/// Shared as `Arc<HtbQos>`; the background tasks only hold a weak reference and are
+/// stopped when the last owner is dropped.
 pub struct HtbQos {
-    config: HtbConfig,
+    /// Limits in effect; the rates can change on a config reload
+    config: Arc<RwLock<HtbConfig>>,
 
     /// Global bandwidth bucket
     global_bucket: Arc<TokenBucket>,
@@ -91,11 +171,23 @@ pub struct HtbQos {
     /// Per-user buckets keyed by shared Arc<str> to avoid cloning per request
     user_buckets: Arc<DashMap<UserKey, Arc<UserBucket>>>,
 
+    /// Per-user maximum rates set at runtime, in place of `max_bandwidth_bytes_per_sec`
+    max_overrides: Arc<DashMap<UserKey, u64>>,
+
     /// Total active connections
     total_connections: Arc<AtomicUsize>,
 
+    /// Per-source-IP limit; `None` unless `qos.per_ip.enabled`
+    per_ip: Option<Arc<PerIpShaping>>,
+
+    /// Per-source-IP buckets, dropped after `idle_timeout_secs` without traffic
+    ip_buckets: Arc<DashMap<IpAddr, Arc<IpBucket>>>,
+
     /// Rebalancing task handle
-    rebalance_handle: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+    rebalance_handle: Mutex<Option<JoinHandle<()>>>,
+
+    /// Idle IP bucket eviction task handle
+    eviction_handle: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl HtbQos {
@@ -107,66 +199,184 @@ impl HtbQos {
         ));
 
         Self {
-            config,
+            config: Arc::new(RwLock::new(config)),
             global_bucket,
             user_buckets: Arc::new(DashMap::new()),
+            max_overrides: Arc::new(DashMap::new()),
             total_connections: Arc::new(AtomicUsize::new(0)),
-            rebalance_handle: Arc::new(tokio::sync::Mutex::new(None)),
+            per_ip: None,
+            ip_buckets: Arc::new(DashMap::new()),
+            rebalance_handle: Mutex::new(None),
+            eviction_handle: Mutex::new(None),
+        }
+    }
+
+    /// Also shape every source IP when `per_ip.enabled`
+    pub fn with_per_ip(mut self, per_ip: PerIpConfig) -> Result<Self> {
+        if per_ip.enabled {
+            let exempt = crate::auth::parse_networks("qos.per_ip.exempt", &per_ip.exempt)?;
+            self.per_ip = Some(Arc::new(PerIpShaping {
+                config: per_ip,
+                exempt,
+            }));
         }
+        Ok(self)
     }
 
-    /// Start the rebalancing task
-    pub async fn start(&self) {
-        if !self.config.fair_sharing_enabled {
+    /// Start the rebalancing task, and the idle IP bucket eviction when per-IP
+    /// shaping is on
+    pub fn start(self: &Arc<Self>) {
+        if self.per_ip.is_some() {
+            let mut handle_guard = self
+                .eviction_handle
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            if handle_guard.is_none() {
+                *handle_guard = Some(tokio::spawn(Self::eviction_task(Arc::downgrade(self))));
+                debug!("HTB idle IP bucket eviction task started");
+            }
+        }
+
+        if !self.config().fair_sharing_enabled {
             debug!("Fair sharing disabled, skipping rebalancing task");
             return;
         }
 
-        let htb = self.clone();
-        let mut handle_guard = self.rebalance_handle.lock().await;
-
+        let mut handle_guard = self
+            .rebalance_handle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
         if handle_guard.is_none() {
-            let handle = tokio::spawn(async move {
-                htb.rebalancing_task().await;
-            });
-            *handle_guard = Some(handle);
+            *handle_guard = Some(tokio::spawn(Self::rebalancing_task(Arc::downgrade(self))));
             debug!("HTB rebalancing task started");
         }
     }
 
-    /// Stop the rebalancing task
-    pub async fn stop(&self) {
-        let mut handle_guard = self.rebalance_handle.lock().await;
-        if let Some(handle) = handle_guard.take() {
+    /// Stop the background tasks
+    pub fn stop(&self) {
+        if let Some(handle) = self
+            .rebalance_handle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+        {
             handle.abort();
             debug!("HTB rebalancing task stopped");
         }
+
+        if let Some(handle) = self
+            .eviction_handle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+        {
+            handle.abort();
+        }
     }
 
     /// Allocate bandwidth for a user to transfer bytes
     ///
     /// This is the main entry point called from proxy loop
     pub async fn allocate_bandwidth(&self, user: &str, bytes: u64) -> Result<()> {
-        self.allocate_bandwidth_impl(user, || self.get_or_create_user_bucket_str(user), bytes)
-            .await
+        self.allocate_bandwidth_impl(
+            user,
+            || self.get_or_create_user_bucket_str(user),
+            None,
+            bytes,
+        )
+        .await
+        .map(|_| ())
     }
 
     pub async fn allocate_bandwidth_arc(&self, user: &Arc<str>, bytes: u64) -> Result<()> {
         let label = user.as_ref();
-        self.allocate_bandwidth_impl(label, || self.get_or_create_user_bucket_arc(user), bytes)
-            .await
+        self.allocate_bandwidth_impl(
+            label,
+            || self.get_or_create_user_bucket_arc(user),
+            None,
+            bytes,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Allocate bandwidth for bytes a user relays for the client at `source_ip`; the
+    /// transfer also takes from that address's bucket when per-IP shaping is on.
+    ///
+    /// Returns the first limit the transfer had to wait for (`"global"`, `"ip"` or
+    /// `"user"`), or `None` when the tokens were there.
+    pub async fn allocate_bandwidth_from(
+        &self,
+        user: &Arc<str>,
+        source_ip: IpAddr,
+        bytes: u64,
+    ) -> Result<Option<&'static str>> {
+        self.allocate_bandwidth_impl(
+            user.as_ref(),
+            || self.get_or_create_user_bucket_arc(user),
+            Some(source_ip),
+            bytes,
+        )
+        .await
+    }
+
+    /// [`Self::allocate_bandwidth_from`] without waiting, for traffic that is dropped
+    /// rather than delayed (UDP datagrams).
+    ///
+    /// On `Err` nothing is consumed and the error names the limit that was exhausted:
+    /// `"global"`, `"ip"` or `"user"`.
+    pub async fn try_allocate_bandwidth_from(
+        &self,
+        user: &Arc<str>,
+        source_ip: IpAddr,
+        bytes: u64,
+    ) -> std::result::Result<(), &'static str> {
+        if self.global_bucket.try_consume(bytes).is_err() {
+            return Err("global");
+        }
+
+        let ip_bucket = self.get_or_create_ip_bucket(source_ip);
+        if let Some(ip_bucket) = &ip_bucket {
+            ip_bucket.update_activity().await;
+            if ip_bucket.bucket.try_consume(bytes).is_err() {
+                self.global_bucket.refund(bytes);
+                return Err("ip");
+            }
+        }
+
+        let user_bucket = self.get_or_create_user_bucket_arc(user);
+        user_bucket.update_activity().await;
+        if user_bucket.guaranteed_bucket.try_consume(bytes).is_err()
+            && user_bucket.max_bucket.try_consume(bytes).is_err()
+        {
+            self.global_bucket.refund(bytes);
+            if let Some(ip_bucket) = &ip_bucket {
+                ip_bucket.bucket.refund(bytes);
+            }
+            return Err("user");
+        }
+
+        if let Some(ip_bucket) = &ip_bucket {
+            ip_bucket.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+        user_bucket.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+        Ok(())
     }
 
     async fn allocate_bandwidth_impl<F>(
         &self,
         user_label: &str,
         bucket_factory: F,
+        source_ip: Option<IpAddr>,
         bytes: u64,
-    ) -> Result<()>
+    ) -> Result<Option<&'static str>>
     where
         F: FnOnce() -> Arc<UserBucket>,
     {
+        let mut throttled = None;
         if self.global_bucket.try_consume(bytes).is_err() {
+            QosMetrics::record_throttle("global");
+            throttled = Some("global");
             let wait_start = Instant::now();
             self.global_bucket
                 .consume(bytes)
@@ -175,6 +385,28 @@ impl HtbQos {
             QosMetrics::observe_wait(wait_start.elapsed().as_secs_f64());
         }
 
+        if let Some(ip_bucket) = source_ip.and_then(|ip| self.get_or_create_ip_bucket(ip)) {
+            ip_bucket.update_activity().await;
+            ip_bucket.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+
+            if ip_bucket.bucket.try_consume(bytes).is_err() {
+                QosMetrics::record_throttle("ip");
+                throttled = throttled.or(Some("ip"));
+                trace!(
+                    source_ip = ?source_ip,
+                    bytes = bytes,
+                    "Waiting for per-IP tokens"
+                );
+                let wait_start = Instant::now();
+                ip_bucket
+                    .bucket
+                    .consume(bytes)
+                    .await
+                    .map_err(RustSocksError::Io)?;
+                QosMetrics::observe_wait(wait_start.elapsed().as_secs_f64());
+            }
+        }
+
         let user_bucket = bucket_factory();
 
         user_bucket.update_activity().await;
@@ -186,7 +418,7 @@ impl HtbQos {
                 bytes = bytes,
                 "Consumed from guaranteed bucket"
             );
-            return Ok(());
+            return Ok(throttled);
         }
 
         if user_bucket.max_bucket.try_consume(bytes).is_ok() {
@@ -195,7 +427,7 @@ impl HtbQos {
                 bytes = bytes,
                 "Consumed from borrowed bucket"
             );
-            return Ok(());
+            return Ok(throttled);
         }
 
         trace!(
@@ -204,6 +436,8 @@ impl HtbQos {
             "Waiting for tokens"
         );
 
+        QosMetrics::record_throttle("user");
+        let _waiting = WaitingGuard::new(&user_bucket.waiting);
         let wait_start = Instant::now();
         user_bucket
             .max_bucket
@@ -212,7 +446,7 @@ impl HtbQos {
             .map_err(RustSocksError::Io)?;
         QosMetrics::observe_wait(wait_start.elapsed().as_secs_f64());
 
-        Ok(())
+        Ok(throttled.or(Some("user")))
     }
 
     /// Increment user connection count
@@ -271,6 +505,53 @@ impl HtbQos {
             .unwrap_or(0)
     }
 
+    /// Limits in effect, including rates changed by a config reload
+    pub fn config(&self) -> HtbConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Apply the bandwidth rates of a reloaded config to the global bucket and
+    /// every existing user bucket. Burst size, intervals and fair sharing keep
+    /// the values the engine was started with.
+    pub async fn update_rates(&self, config: &HtbConfig) {
+        {
+            let mut current = self.config.write().unwrap_or_else(|e| e.into_inner());
+            current.global_bandwidth_bytes_per_sec = config.global_bandwidth_bytes_per_sec;
+            current.guaranteed_bandwidth_bytes_per_sec = config.guaranteed_bandwidth_bytes_per_sec;
+            current.max_bandwidth_bytes_per_sec = config.max_bandwidth_bytes_per_sec;
+        }
+
+        self.global_bucket
+            .set_refill_rate(config.global_bandwidth_bytes_per_sec)
+            .await;
+
+        let buckets: Vec<(Arc<str>, Arc<UserBucket>)> = self
+            .user_buckets
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        for (user, bucket) in buckets {
+            // The rebalancer lowers the maximum again when fair sharing is on
+            let (guaranteed, max) = self.user_rates(&user, config);
+            bucket.set_rates(guaranteed, max).await;
+        }
+
+        debug!(
+            global = config.global_bandwidth_bytes_per_sec,
+            guaranteed_per_user = config.guaranteed_bandwidth_bytes_per_sec,
+            max_per_user = config.max_bandwidth_bytes_per_sec,
+            "HTB rates updated"
+        );
+    }
+
+    /// Per-IP limit in effect; `None` when per-IP shaping is off
+    pub fn per_ip_config(&self) -> Option<&PerIpConfig> {
+        self.per_ip.as_ref().map(|per_ip| &per_ip.config)
+    }
+
     /// Get total connection count
     pub fn get_total_connections(&self) -> usize {
         self.total_connections.load(Ordering::Relaxed)
@@ -279,22 +560,31 @@ impl HtbQos {
     /// Get current user allocations (for monitoring/API)
     pub async fn get_user_allocations(&self) -> Vec<UserAllocation> {
         let mut allocations = Vec::new();
-        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
-
-        for entry in self.user_buckets.iter() {
-            let user_key = entry.key().clone();
-            let bucket = entry.value();
-
+        let config = self.config();
+        let idle_timeout = Duration::from_secs(config.idle_timeout_secs);
+        let buckets: Vec<(Arc<str>, Arc<UserBucket>)> = self
+            .user_buckets
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+
+        for (user_key, bucket) in buckets {
             let is_active = bucket.is_active(idle_timeout).await;
             let current_demand = bucket.current_demand.load(Ordering::Relaxed);
+            let allocated_bandwidth = bucket.max_bucket.refill_rate();
+            let guaranteed_bandwidth = bucket.guaranteed_bucket.refill_rate();
+            let max_override = self.max_overrides.get(&user_key).map(|rate| *rate);
 
             allocations.push(UserAllocation {
                 user: user_key.to_string(),
-                allocated_bandwidth: bucket.max_bucket.refill_rate(),
-                guaranteed_bandwidth: bucket.guaranteed_bucket.refill_rate(),
-                max_bandwidth: self.config.max_bandwidth_bytes_per_sec,
+                allocated_bandwidth,
+                guaranteed_bandwidth,
+                borrowed_bandwidth: allocated_bandwidth.saturating_sub(guaranteed_bandwidth),
+                max_bandwidth: max_override.unwrap_or(config.max_bandwidth_bytes_per_sec),
+                max_overridden: max_override.is_some(),
                 current_demand,
                 is_active,
+                is_throttled: bucket.waiting.load(Ordering::Relaxed) > 0,
                 active_connections: bucket.connection_count(),
             });
         }
@@ -302,21 +592,184 @@ impl HtbQos {
         allocations
     }
 
+    /// Replace the configured per-user maximum for `user` until restart or
+    /// [`Self::clear_user_max_bandwidth`]. A maximum below the guaranteed rate lowers
+    /// that too. Survives config reloads.
+    pub async fn set_user_max_bandwidth(&self, user: &str, max_bytes_per_sec: u64) -> Result<()> {
+        if max_bytes_per_sec == 0 {
+            return Err(RustSocksError::Config(
+                "User bandwidth limit must be greater than 0".to_string(),
+            ));
+        }
+        self.max_overrides
+            .insert(Arc::from(user), max_bytes_per_sec);
+        self.apply_user_rates(user).await;
+        debug!(
+            user,
+            max_per_user = max_bytes_per_sec,
+            "User bandwidth limit overridden"
+        );
+        Ok(())
+    }
+
+    /// Return `user` to the configured maximum; `false` when no override was set
+    pub async fn clear_user_max_bandwidth(&self, user: &str) -> bool {
+        if self.max_overrides.remove(user).is_none() {
+            return false;
+        }
+        self.apply_user_rates(user).await;
+        debug!(user, "User bandwidth limit override removed");
+        true
+    }
+
+    /// Runtime overrides of the per-user maximum, by user
+    pub fn user_max_overrides(&self) -> Vec<(String, u64)> {
+        let mut overrides: Vec<(String, u64)> = self
+            .max_overrides
+            .iter()
+            .map(|entry| (entry.key().to_string(), *entry.value()))
+            .collect();
+        overrides.sort();
+        overrides
+    }
+
+    /// Guaranteed and maximum rate of `user`, taking a runtime override into account
+    fn user_rates(&self, user: &str, config: &HtbConfig) -> (u64, u64) {
+        let max = self
+            .max_overrides
+            .get(user)
+            .map(|rate| *rate)
+            .unwrap_or(config.max_bandwidth_bytes_per_sec);
+        (config.guaranteed_bandwidth_bytes_per_sec.min(max), max)
+    }
+
+    /// Bring an existing bucket of `user` to its current rates
+    async fn apply_user_rates(&self, user: &str) {
+        let Some(bucket) = self
+            .user_buckets
+            .get(user)
+            .map(|entry| entry.value().clone())
+        else {
+            return;
+        };
+        let (guaranteed, max) = self.user_rates(user, &self.config());
+        bucket.set_rates(guaranteed, max).await;
+    }
+
+    /// Get current per-IP usage, heaviest first (for monitoring/API)
+    pub async fn get_ip_allocations(&self) -> Vec<IpAllocation> {
+        let Some(per_ip) = &self.per_ip else {
+            return Vec::new();
+        };
+        let idle_timeout = Duration::from_secs(self.config().idle_timeout_secs);
+        let buckets: Vec<(IpAddr, Arc<IpBucket>)> = self
+            .ip_buckets
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+
+        let mut allocations = Vec::with_capacity(buckets.len());
+        for (ip, bucket) in buckets {
+            allocations.push(IpAllocation {
+                ip,
+                max_bandwidth: per_ip.config.max_bytes_per_sec,
+                total_bytes: bucket.total_bytes.load(Ordering::Relaxed),
+                available_bytes: bucket.bucket.available_tokens(),
+                is_active: bucket.is_active(idle_timeout).await,
+            });
+        }
+
+        allocations.sort_by(|a, b| b.total_bytes.cmp(&a.total_bytes).then(a.ip.cmp(&b.ip)));
+        allocations
+    }
+
+    /// Bucket for `ip`; `None` when per-IP shaping is off or the address is exempt
+    fn get_or_create_ip_bucket(&self, ip: IpAddr) -> Option<Arc<IpBucket>> {
+        let per_ip = self.per_ip.as_ref()?;
+        if let Some(bucket) = self.ip_buckets.get(&ip) {
+            return Some(bucket.clone());
+        }
+        if per_ip.is_exempt(ip) {
+            return None;
+        }
+
+        Some(
+            self.ip_buckets
+                .entry(ip)
+                .or_insert_with(|| {
+                    Arc::new(IpBucket::new(
+                        per_ip.config.max_bytes_per_sec,
+                        per_ip.config.burst,
+                    ))
+                })
+                .clone(),
+        )
+    }
+
+    /// Periodically drop IP buckets that saw no traffic for `idle_timeout_secs`, so
+    /// memory stays bounded by the addresses active recently
+    async fn eviction_task(htb: Weak<Self>) {
+        let Some(idle_timeout) = htb
+            .upgrade()
+            .map(|htb| Duration::from_secs(htb.config().idle_timeout_secs.max(1)))
+        else {
+            return;
+        };
+        let mut ticker = interval(idle_timeout);
+
+        loop {
+            ticker.tick().await;
+            let Some(htb) = htb.upgrade() else {
+                return;
+            };
+
+            let evicted = htb.evict_idle_ip_buckets(idle_timeout).await;
+            if evicted > 0 {
+                debug!(
+                    evicted,
+                    remaining = htb.ip_buckets.len(),
+                    "Evicted idle per-IP buckets"
+                );
+            }
+        }
+    }
+
+    /// Drop the IP buckets idle for `idle_timeout`; returns how many went
+    async fn evict_idle_ip_buckets(&self, idle_timeout: Duration) -> usize {
+        let buckets: Vec<(IpAddr, Arc<IpBucket>)> = self
+            .ip_buckets
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+
+        let mut idle = Vec::new();
+        for (ip, bucket) in buckets {
+            if !bucket.is_active(idle_timeout).await {
+                idle.push(ip);
+            }
+        }
+
+        // A bucket some transfer is holding right now stays
+        idle.into_iter()
+            .filter(|ip| {
+                self.ip_buckets
+                    .remove_if(ip, |_, bucket| Arc::strong_count(bucket) == 1)
+                    .is_some()
+            })
+            .count()
+    }
+
     fn get_or_create_user_bucket_arc(&self, user: &Arc<str>) -> Arc<UserBucket> {
         if let Some(bucket) = self.user_buckets.get(user.as_ref()) {
             return bucket.clone();
         }
 
+        let config = self.config();
+        let (guaranteed, max) = self.user_rates(user, &config);
         let key = Arc::clone(user);
         self.user_buckets
             .entry(key)
-            .or_insert_with(|| {
-                Arc::new(UserBucket::new(
-                    self.config.guaranteed_bandwidth_bytes_per_sec,
-                    self.config.max_bandwidth_bytes_per_sec,
-                    self.config.burst_size_bytes,
-                ))
-            })
+            .or_insert_with(|| Arc::new(UserBucket::new(guaranteed, max, config.burst_size_bytes)))
             .clone()
     }
 
@@ -325,28 +778,30 @@ impl HtbQos {
             return bucket.clone();
         }
 
+        let config = self.config();
+        let (guaranteed, max) = self.user_rates(user, &config);
         let key: Arc<str> = Arc::from(user);
         self.user_buckets
             .entry(key)
-            .or_insert_with(|| {
-                Arc::new(UserBucket::new(
-                    self.config.guaranteed_bandwidth_bytes_per_sec,
-                    self.config.max_bandwidth_bytes_per_sec,
-                    self.config.burst_size_bytes,
-                ))
-            })
+            .or_insert_with(|| Arc::new(UserBucket::new(guaranteed, max, config.burst_size_bytes)))
             .clone()
     }
 
     /// Periodic rebalancing task - recalculate fair shares
-    async fn rebalancing_task(&self) {
-        let mut ticker = interval(Duration::from_millis(self.config.rebalance_interval_ms));
-        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+    async fn rebalancing_task(htb: Weak<Self>) {
+        let Some(config) = htb.upgrade().map(|htb| htb.config()) else {
+            return;
+        };
+        let mut ticker = interval(Duration::from_millis(config.rebalance_interval_ms));
+        let idle_timeout = Duration::from_secs(config.idle_timeout_secs);
 
         loop {
             ticker.tick().await;
+            let Some(htb) = htb.upgrade() else {
+                return;
+            };
 
-            if let Err(e) = self.rebalance_bandwidth(idle_timeout).await {
+            if let Err(e) = htb.rebalance_bandwidth(idle_timeout).await {
                 warn!("Rebalancing error: {}", e);
             }
         }
@@ -399,14 +854,18 @@ impl HtbQos {
         &self,
         active_users: &[(Arc<str>, Arc<UserBucket>, u64)],
     ) -> Vec<(Arc<str>, Arc<UserBucket>, u64)> {
+        let config = self.config();
         let mut allocations = Vec::new();
-        let mut remaining = self.config.global_bandwidth_bytes_per_sec;
+        let mut remaining = config.global_bandwidth_bytes_per_sec;
 
         // Phase 1: Allocate guaranteed bandwidth to all active users
-        for (user, bucket, _demand) in active_users {
-            let guaranteed = self.config.guaranteed_bandwidth_bytes_per_sec;
-            allocations.push((user.clone(), bucket.clone(), guaranteed));
-            remaining = remaining.saturating_sub(guaranteed);
+        let rates: Vec<(u64, u64)> = active_users
+            .iter()
+            .map(|(user, _, _)| self.user_rates(user, &config))
+            .collect();
+        for ((user, bucket, _demand), (guaranteed, _)) in active_users.iter().zip(&rates) {
+            allocations.push((user.clone(), bucket.clone(), *guaranteed));
+            remaining = remaining.saturating_sub(*guaranteed);
         }
 
         if remaining == 0 || active_users.is_empty() {
@@ -418,7 +877,7 @@ impl HtbQos {
 
         if total_demand > 0 {
             for (idx, (_user, _bucket, demand)) in active_users.iter().enumerate() {
-                let guaranteed = self.config.guaranteed_bandwidth_bytes_per_sec;
+                let (guaranteed, max) = rates[idx];
 
                 // Calculate proportional share
                 let share = if total_demand > remaining {
@@ -429,9 +888,8 @@ impl HtbQos {
                     *demand
                 };
 
-                // Cap at max_bandwidth
-                let capped_share =
-                    std::cmp::min(share, self.config.max_bandwidth_bytes_per_sec - guaranteed);
+                // Cap at the user's maximum
+                let capped_share = std::cmp::min(share, max.saturating_sub(guaranteed));
 
                 // Update allocation
                 allocations[idx].2 = guaranteed + capped_share;
@@ -440,12 +898,8 @@ impl HtbQos {
             // No demand info, split equally
             let equal_share = remaining / active_users.len() as u64;
 
-            for (idx, _) in active_users.iter().enumerate() {
-                let guaranteed = self.config.guaranteed_bandwidth_bytes_per_sec;
-                let capped_share = std::cmp::min(
-                    equal_share,
-                    self.config.max_bandwidth_bytes_per_sec - guaranteed,
-                );
+            for (idx, (guaranteed, max)) in rates.iter().enumerate() {
+                let capped_share = std::cmp::min(equal_share, max.saturating_sub(*guaranteed));
                 allocations[idx].2 = guaranteed + capped_share;
             }
         }
@@ -456,6 +910,7 @@ impl HtbQos {
     /// Estimate user's bandwidth demand from recent activity
     async fn estimate_user_demand(&self, bucket: &UserBucket) -> u64 {
         // Simple estimation: if bucket is being depleted, user has high demand
+        let config = self.config();
         let guaranteed_available = bucket.guaranteed_bucket.available_tokens();
         let max_available = bucket.max_bucket.available_tokens();
 
@@ -463,11 +918,18 @@ impl HtbQos {
         if guaranteed_available < bucket.guaranteed_bucket.capacity() / 4
             || max_available < bucket.max_bucket.capacity() / 4
         {
-            return self.config.max_bandwidth_bytes_per_sec;
+            return config.max_bandwidth_bytes_per_sec;
         }
 
         // Otherwise assume they want guaranteed
-        self.config.guaranteed_bandwidth_bytes_per_sec
+        config.guaranteed_bandwidth_bytes_per_sec
+    }
+}
+
+impl Drop for HtbQos {
+    fn drop(&mut self) {
+        // The last owner is gone; the tasks would stop at their next tick anyway
+        self.stop();
     }
 }
 
@@ -629,4 +1091,131 @@ mod tests {
             bob_rate
         );
     }
+
+    #[tokio::test]
+    async fn user_max_override_applies_until_cleared() {
+        let config = HtbConfig {
+            global_bandwidth_bytes_per_sec: 1_000_000,
+            guaranteed_bandwidth_bytes_per_sec: 100_000,
+            max_bandwidth_bytes_per_sec: 500_000,
+            ..Default::default()
+        };
+        let htb = HtbQos::new(config.clone());
+        let alice = htb.get_or_create_user_bucket_str("alice");
+
+        assert!(htb.set_user_max_bandwidth("alice", 0).await.is_err());
+        htb.set_user_max_bandwidth("alice", 50_000).await.unwrap();
+        htb.set_user_max_bandwidth("bob", 800_000).await.unwrap();
+        // A maximum below the guarantee lowers the guarantee too
+        assert_eq!(alice.max_bucket.refill_rate(), 50_000);
+        assert_eq!(alice.guaranteed_bucket.refill_rate(), 50_000);
+        // Users without a bucket yet get theirs at the override
+        let bob = htb.get_or_create_user_bucket_str("bob");
+        assert_eq!(bob.max_bucket.refill_rate(), 800_000);
+
+        // Fair sharing and reloads keep to the override
+        let active = vec![
+            (Arc::<str>::from("alice"), alice.clone(), 500_000),
+            (Arc::<str>::from("bob"), bob.clone(), 500_000),
+        ];
+        let shares = htb.calculate_fair_shares(&active);
+        assert_eq!(shares[0].2, 50_000);
+        assert!(shares[1].2 > 500_000, "bob may go above the configured max");
+        htb.update_rates(&config).await;
+        assert_eq!(alice.max_bucket.refill_rate(), 50_000);
+
+        let allocations = htb.get_user_allocations().await;
+        let reported = allocations.iter().find(|a| a.user == "alice").unwrap();
+        assert!(reported.max_overridden);
+        assert_eq!(reported.max_bandwidth, 50_000);
+        assert_eq!(
+            htb.user_max_overrides(),
+            vec![("alice".to_string(), 50_000), ("bob".to_string(), 800_000)]
+        );
+
+        assert!(htb.clear_user_max_bandwidth("alice").await);
+        assert!(!htb.clear_user_max_bandwidth("alice").await);
+        assert_eq!(alice.max_bucket.refill_rate(), 500_000);
+        assert_eq!(alice.guaranteed_bucket.refill_rate(), 100_000);
+    }
+
+    #[tokio::test]
+    async fn waiting_transfers_mark_the_user_throttled() {
+        let config = HtbConfig {
+            guaranteed_bandwidth_bytes_per_sec: 10_000,
+            max_bandwidth_bytes_per_sec: 20_000,
+            burst_size_bytes: 10_000,
+            ..Default::default()
+        };
+        let htb = Arc::new(HtbQos::new(config));
+        htb.allocate_bandwidth("alice", 10_000).await.unwrap();
+        htb.allocate_bandwidth("alice", 10_000).await.unwrap();
+
+        let waiting = {
+            let htb = htb.clone();
+            tokio::spawn(async move { htb.allocate_bandwidth("alice", 5_000).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let allocation = &htb.get_user_allocations().await[0];
+        assert!(allocation.is_throttled);
+        assert_eq!(allocation.borrowed_bandwidth, 10_000);
+
+        waiting.await.unwrap().unwrap();
+        assert!(!htb.get_user_allocations().await[0].is_throttled);
+    }
+
+    #[tokio::test]
+    async fn idle_ip_buckets_are_evicted() {
+        let per_ip = PerIpConfig {
+            enabled: true,
+            exempt: vec!["10.0.0.0/8".to_string()],
+            ..Default::default()
+        };
+        let htb = HtbQos::new(HtbConfig::default())
+            .with_per_ip(per_ip)
+            .unwrap();
+        let user: Arc<str> = Arc::from("guest");
+        let idle: IpAddr = "192.168.1.10".parse().unwrap();
+        let busy: IpAddr = "192.168.1.11".parse().unwrap();
+        let exempt: IpAddr = "10.0.0.1".parse().unwrap();
+
+        for ip in [idle, busy, exempt] {
+            htb.allocate_bandwidth_from(&user, ip, 1_000).await.unwrap();
+        }
+        assert_eq!(htb.ip_buckets.len(), 2, "exempt addresses get no bucket");
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        htb.allocate_bandwidth_from(&user, busy, 1_000)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            htb.evict_idle_ip_buckets(Duration::from_millis(50)).await,
+            1
+        );
+        assert!(!htb.ip_buckets.contains_key(&idle));
+        assert!(htb.ip_buckets.contains_key(&busy));
+
+        // An evicted address starts over with a fresh bucket
+        htb.allocate_bandwidth_from(&user, idle, 1_000)
+            .await
+            .unwrap();
+        let allocations = htb.get_ip_allocations().await;
+        assert_eq!(allocations[0].ip, busy);
+        assert_eq!(allocations[0].total_bytes, 2_000);
+        assert_eq!(allocations[1].total_bytes, 1_000);
+    }
+
+    #[tokio::test]
+    async fn per_ip_shaping_is_off_by_default() {
+        let htb = HtbQos::new(HtbConfig::default())
+            .with_per_ip(PerIpConfig::default())
+            .unwrap();
+        let user: Arc<str> = Arc::from("guest");
+        htb.allocate_bandwidth_from(&user, "192.168.1.10".parse().unwrap(), 1_000)
+            .await
+            .unwrap();
+        assert!(htb.ip_buckets.is_empty());
+        assert!(htb.per_ip_config().is_none());
+    }
 }
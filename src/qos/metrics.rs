@@ -16,6 +16,18 @@ lazy_static! {
         &["user", "direction"]
     )
     .expect("register rustsocks_qos_bandwidth_allocated_bytes_total counter vec");
+    pub static ref THROTTLED: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_qos_throttled_total",
+        "Transfers that had to wait for tokens, by the limit that held them back (global, user or ip)",
+        &["dimension"]
+    )
+    .expect("register rustsocks_qos_throttled_total counter vec");
+    pub static ref DROPPED_DATAGRAMS: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_qos_dropped_datagrams_total",
+        "UDP datagrams dropped for lack of tokens, by the limit that was exhausted (global, user or ip)",
+        &["dimension"]
+    )
+    .expect("register rustsocks_qos_dropped_datagrams_total counter vec");
     pub static ref ALLOCATION_WAIT: Histogram = register_histogram!(
         "rustsocks_qos_allocation_wait_seconds",
         "Observed wait time while throttling traffic for QoS allocations"
@@ -44,6 +56,18 @@ impl QosMetrics {
             .inc_by(bytes);
     }
 
+    /// A transfer found the `dimension` bucket empty and waits for a refill
+    #[inline]
+    pub fn record_throttle(dimension: &str) {
+        THROTTLED.with_label_values(&[dimension]).inc();
+    }
+
+    /// A UDP datagram was dropped because the `dimension` bucket was empty
+    #[inline]
+    pub fn record_drop(dimension: &str) {
+        DROPPED_DATAGRAMS.with_label_values(&[dimension]).inc();
+    }
+
     #[inline]
     pub fn observe_wait(duration_secs: f64) {
         ALLOCATION_WAIT.observe(duration_secs);
@@ -54,5 +78,7 @@ impl QosMetrics {
 pub fn init() {
     lazy_static::initialize(&ACTIVE_QOS_USERS);
     lazy_static::initialize(&BANDWIDTH_ALLOCATED);
+    lazy_static::initialize(&THROTTLED);
+    lazy_static::initialize(&DROPPED_DATAGRAMS);
     lazy_static::initialize(&ALLOCATION_WAIT);
 }
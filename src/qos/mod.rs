@@ -1,13 +1,19 @@
 mod htb;
 mod metrics;
+pub mod rate;
 mod token_bucket;
 mod types;
 
 pub use htb::HtbQos;
 pub use metrics::QosMetrics;
-pub use types::{ConnectionLimits, HtbConfig, QosConfig, UserAllocation};
+pub use rate::{format_rate, parse_rate, parse_size};
+pub use types::{
+    ConnectionLimitExceeded, ConnectionLimits, HtbConfig, IpAllocation, LimitType, PerIpConfig,
+    QosConfig, SharedConnectionLimits, UserAllocation,
+};
 
 use crate::utils::error::{Result, RustSocksError};
+use std::net::IpAddr;
 use std::sync::Arc;
 use tracing::info;
 
@@ -38,13 +44,14 @@ impl QosEngine {
                     guaranteed_per_user = config.htb.guaranteed_bandwidth_bytes_per_sec,
                     max_per_user = config.htb.max_bandwidth_bytes_per_sec,
                     fair_sharing = config.htb.fair_sharing_enabled,
+                    per_ip = config.per_ip.enabled,
                     "Initializing HTB QoS engine"
                 );
 
-                let htb = HtbQos::new(config.htb);
-                htb.start().await;
+                let htb = Arc::new(HtbQos::new(config.htb).with_per_ip(config.per_ip)?);
+                htb.start();
 
-                Ok(Self::Htb(Arc::new(htb)))
+                Ok(Self::Htb(htb))
             }
             other => Err(RustSocksError::Config(format!(
                 "Unknown QoS algorithm: {}",
@@ -68,29 +75,77 @@ impl QosEngine {
         }
     }
 
-    /// Check connection limit and increment if allowed
-    pub fn check_and_inc_connection(&self, user: &str, limits: &ConnectionLimits) -> Result<usize> {
+    /// Allocate bandwidth for bytes relayed for a user's client at `source_ip`;
+    /// `Some` names the limit the transfer waited for (see
+    /// [`HtbQos::allocate_bandwidth_from`])
+    pub async fn allocate_bandwidth_from(
+        &self,
+        user: &Arc<str>,
+        source_ip: IpAddr,
+        bytes: u64,
+    ) -> Result<Option<&'static str>> {
         match self {
-            Self::None => Ok(0),
+            Self::None => Ok(None),
+            Self::Htb(htb) => htb.allocate_bandwidth_from(user, source_ip, bytes).await,
+        }
+    }
+
+    /// Take tokens for a datagram without waiting; `Err` names the exhausted limit
+    /// (see [`HtbQos::try_allocate_bandwidth_from`]) and the datagram should be dropped.
+    pub async fn try_allocate_bandwidth_from(
+        &self,
+        user: &Arc<str>,
+        source_ip: IpAddr,
+        bytes: u64,
+    ) -> std::result::Result<(), &'static str> {
+        match self {
+            Self::None => Ok(()),
+            Self::Htb(htb) => {
+                htb.try_allocate_bandwidth_from(user, source_ip, bytes)
+                    .await
+            }
+        }
+    }
+
+    /// Check connection limit without reserving a slot
+    pub fn check_connection_limit(&self, user: &str, limits: &ConnectionLimits) -> Result<()> {
+        match self {
+            Self::None => Ok(()),
             Self::Htb(htb) => {
                 // Check global limit
                 let global_count = htb.get_total_connections();
                 if global_count >= limits.max_connections_global {
-                    return Err(RustSocksError::Config(format!(
-                        "Global connection limit reached: {}/{}",
-                        global_count, limits.max_connections_global
-                    )));
+                    return Err(RustSocksError::ConnectionLimit(ConnectionLimitExceeded {
+                        limit_type: LimitType::GlobalConnections,
+                        user: user.to_string(),
+                        current: global_count,
+                        limit: limits.max_connections_global,
+                    }));
                 }
 
                 // Check per-user limit
                 let user_count = htb.get_user_connections(user);
                 if user_count >= limits.max_connections_per_user {
-                    return Err(RustSocksError::Config(format!(
-                        "User connection limit reached for '{}': {}/{}",
-                        user, user_count, limits.max_connections_per_user
-                    )));
+                    return Err(RustSocksError::ConnectionLimit(ConnectionLimitExceeded {
+                        limit_type: LimitType::UserConnections,
+                        user: user.to_string(),
+                        current: user_count,
+                        limit: limits.max_connections_per_user,
+                    }));
                 }
 
+                Ok(())
+            }
+        }
+    }
+
+    /// Check connection limit and increment if allowed
+    pub fn check_and_inc_connection(&self, user: &str, limits: &ConnectionLimits) -> Result<usize> {
+        match self {
+            Self::None => Ok(0),
+            Self::Htb(htb) => {
+                self.check_connection_limit(user, limits)?;
+
                 // Increment
                 let count = htb.inc_user_connections(user)?;
                 if count == 1 {
@@ -111,18 +166,22 @@ impl QosEngine {
             Self::Htb(htb) => {
                 let global_count = htb.get_total_connections();
                 if global_count >= limits.max_connections_global {
-                    return Err(RustSocksError::Config(format!(
-                        "Global connection limit reached: {}/{}",
-                        global_count, limits.max_connections_global
-                    )));
+                    return Err(RustSocksError::ConnectionLimit(ConnectionLimitExceeded {
+                        limit_type: LimitType::GlobalConnections,
+                        user: user.to_string(),
+                        current: global_count,
+                        limit: limits.max_connections_global,
+                    }));
                 }
 
                 let user_count = htb.get_user_connections_arc(user);
                 if user_count >= limits.max_connections_per_user {
-                    return Err(RustSocksError::Config(format!(
-                        "User connection limit reached for '{}': {}/{}",
-                        user, user_count, limits.max_connections_per_user
-                    )));
+                    return Err(RustSocksError::ConnectionLimit(ConnectionLimitExceeded {
+                        limit_type: LimitType::UserConnections,
+                        user: user.to_string(),
+                        current: user_count,
+                        limit: limits.max_connections_per_user,
+                    }));
                 }
 
                 let count = htb.inc_user_connections_arc(user)?;
@@ -134,6 +193,26 @@ impl QosEngine {
         }
     }
 
+    /// Check connection limits and reserve a slot for `user`, released when the
+    /// returned [`ConnectionSlot`] is dropped. Connection handlers use this rather
+    /// than pairing `check_and_inc_connection` with `dec_user_connection` by hand, so
+    /// no early return can leave the count raised.
+    pub fn acquire_connection(
+        &self,
+        user: &Arc<str>,
+        limits: &ConnectionLimits,
+    ) -> Result<ConnectionSlot> {
+        self.check_and_inc_connection_arc(user, limits)?;
+        let htb = match self {
+            Self::None => None,
+            Self::Htb(htb) => Some(Arc::clone(htb)),
+        };
+        Ok(ConnectionSlot {
+            htb,
+            user: Arc::clone(user),
+        })
+    }
+
     /// Decrement user connection count
     pub fn dec_user_connection(&self, user: &str) {
         match self {
@@ -183,20 +262,82 @@ impl QosEngine {
         }
     }
 
+    /// Current per-source-IP usage, heaviest first; empty unless per-IP shaping is on
+    pub async fn get_ip_allocations(&self) -> Vec<IpAllocation> {
+        match self {
+            Self::None => Vec::new(),
+            Self::Htb(htb) => htb.get_ip_allocations().await,
+        }
+    }
+
+    /// Per-IP limit in effect; `None` when QoS or per-IP shaping is off
+    pub fn per_ip_config(&self) -> Option<&PerIpConfig> {
+        match self {
+            Self::None => None,
+            Self::Htb(htb) => htb.per_ip_config(),
+        }
+    }
+
+    /// HTB limits in effect; `None` when QoS is disabled
+    pub fn htb_config(&self) -> Option<HtbConfig> {
+        match self {
+            Self::None => None,
+            Self::Htb(htb) => Some(htb.config()),
+        }
+    }
+
+    /// Override the per-user maximum of `user` until restart or
+    /// [`Self::clear_user_max_bandwidth`]
+    pub async fn set_user_max_bandwidth(&self, user: &str, max_bytes_per_sec: u64) -> Result<()> {
+        match self {
+            Self::None => Err(RustSocksError::Config("QoS is not enabled".to_string())),
+            Self::Htb(htb) => htb.set_user_max_bandwidth(user, max_bytes_per_sec).await,
+        }
+    }
+
+    /// Drop the override of `user`; `false` when there was none
+    pub async fn clear_user_max_bandwidth(&self, user: &str) -> bool {
+        match self {
+            Self::None => false,
+            Self::Htb(htb) => htb.clear_user_max_bandwidth(user).await,
+        }
+    }
+
+    /// Per-user maximum rates overridden at runtime, by user
+    pub fn user_max_overrides(&self) -> Vec<(String, u64)> {
+        match self {
+            Self::None => Vec::new(),
+            Self::Htb(htb) => htb.user_max_overrides(),
+        }
+    }
+
+    /// Apply reloaded HTB bandwidth rates; no-op when QoS is disabled
+    pub async fn update_rates(&self, config: &HtbConfig) {
+        if let Self::Htb(htb) = self {
+            htb.update_rates(config).await;
+        }
+    }
+
     /// Check if QoS is enabled
     pub fn is_enabled(&self) -> bool {
         !matches!(self, Self::None)
     }
 }
 
-impl Drop for QosEngine {
+/// A connection counted against its user's and the global limits, from
+/// [`QosEngine::acquire_connection`]; dropping it releases the slot
+#[must_use = "the connection slot is released as soon as it is dropped"]
+pub struct ConnectionSlot {
+    htb: Option<Arc<HtbQos>>,
+    user: Arc<str>,
+}
+
+impl Drop for ConnectionSlot {
     fn drop(&mut self) {
-        // Stop rebalancing task on drop
-        if let Self::Htb(htb) = self {
-            let htb = htb.clone();
-            tokio::spawn(async move {
-                htb.stop().await;
-            });
+        if let Some(htb) = &self.htb {
+            if htb.dec_user_connections_arc(&self.user) == 0 {
+                metrics::QosMetrics::user_deactivated();
+            }
         }
     }
 }
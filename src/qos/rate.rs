@@ -0,0 +1,456 @@
+//! Bandwidth and size values with units.
+//!
+//! QoS settings take plain integers (bytes per second, or bytes for sizes) or strings
+//! with a unit. Decimal prefixes are powers of 1000 and `Ki`/`Mi`/`Gi`/`Ti` powers of
+//! 1024; prefixes and spelled-out units are case-insensitive. In the short forms the
+//! case of `b` carries the meaning, as usual for network rates: `Mbps` is megabits,
+//! `MBps` and `MB/s` are megabytes. Anything that could be read either way (`100m`,
+//! `100mb`, `100MBPS`) is rejected instead of guessed.
+
+use serde::de::{self, Visitor};
+use std::fmt;
+
+/// Decimal and binary prefixes, largest first
+const PREFIXES: [(&str, u64); 8] = [
+    ("T", 1_000_000_000_000),
+    ("G", 1_000_000_000),
+    ("M", 1_000_000),
+    ("k", 1_000),
+    ("Ti", 1 << 40),
+    ("Gi", 1 << 30),
+    ("Mi", 1 << 20),
+    ("Ki", 1 << 10),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quantity {
+    /// Bytes per second
+    Rate,
+    /// Bytes
+    Size,
+}
+
+/// Parse a rate into bytes per second: `"100mbps"`, `"12.5MBps"`, `"1gbit"`, `"64KiB/s"`
+/// or a plain integer of bytes per second.
+pub fn parse_rate(input: &str) -> Result<u64, String> {
+    parse(input, Quantity::Rate)
+}
+
+/// Parse a size into bytes: `"1MiB"`, `"64kB"`, `"512 bytes"` or a plain integer.
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    parse(input, Quantity::Size)
+}
+
+fn parse(input: &str, quantity: Quantity) -> Result<u64, String> {
+    let trimmed = input.trim();
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '_'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number = number.replace('_', "");
+    let unit = unit.trim_start();
+
+    if number.is_empty() {
+        return Err(format!("'{}' does not start with a number", input));
+    }
+    if unit.is_empty() {
+        return number.parse::<u64>().map_err(|_| {
+            format!(
+                "'{}' has no unit, so it must be a whole number of {}",
+                input,
+                match quantity {
+                    Quantity::Rate => "bytes per second",
+                    Quantity::Size => "bytes",
+                }
+            )
+        });
+    }
+
+    let value = parse_decimal(&number).ok_or_else(|| format!("'{}' is not a number", number))?;
+    let (multiplier, unit_name) = split_prefix(unit);
+    let bits = match quantity {
+        Quantity::Rate => rate_unit(unit_name, input)?,
+        Quantity::Size => size_unit(unit_name, input)?,
+    };
+
+    let bytes = value * multiplier as f64 / if bits { 8.0 } else { 1.0 };
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("'{}' is too large", input));
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Digits with at most one decimal point between them; no signs or exponents.
+fn parse_decimal(number: &str) -> Option<f64> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, "0"));
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    if !digits(whole) || !digits(fraction) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+fn split_prefix(unit: &str) -> (u64, &str) {
+    let mut best = (1, unit);
+    for (prefix, multiplier) in PREFIXES {
+        let Some(head) = unit.get(..prefix.len()) else {
+            continue;
+        };
+        // Prefixes never swallow the whole unit: "100m" is a bare prefix, not a unit
+        if head.eq_ignore_ascii_case(prefix) && unit.len() > prefix.len() {
+            let rest = &unit[prefix.len()..];
+            if best.1.len() > rest.len() {
+                best = (multiplier, rest);
+            }
+        }
+    }
+    best
+}
+
+/// `true` for bits per second, `false` for bytes per second.
+fn rate_unit(unit: &str, input: &str) -> Result<bool, String> {
+    const BITS: [&str; 4] = ["bit", "bits", "bit/s", "bits/s"];
+    const BYTES: [&str; 2] = ["byte/s", "bytes/s"];
+
+    if BITS.iter().any(|name| unit.eq_ignore_ascii_case(name))
+        || (unit.starts_with('b')
+            && (unit[1..].eq_ignore_ascii_case("ps") || unit[1..].eq_ignore_ascii_case("/s")))
+    {
+        return Ok(true);
+    }
+    if BYTES.iter().any(|name| unit.eq_ignore_ascii_case(name))
+        || (unit.starts_with('B') && (&unit[1..] == "ps" || unit[1..].eq_ignore_ascii_case("/s")))
+    {
+        return Ok(false);
+    }
+
+    if is_prefix_only(unit) || matches!(unit, "b" | "B") || unit.eq_ignore_ascii_case("bps") {
+        return Err(format!(
+            "'{}' is ambiguous: write bits as \"mbps\" or \"mbit\" and bytes as \"MBps\" \
+             or \"MB/s\"",
+            input
+        ));
+    }
+    Err(format!(
+        "'{}' has an unknown unit '{}' (use bps, bit, Bps, B/s or bytes/s with an optional \
+         k, M, G, T, Ki, Mi, Gi or Ti prefix)",
+        input, unit
+    ))
+}
+
+/// Sizes are always in bytes, so this only returns `false`.
+fn size_unit(unit: &str, input: &str) -> Result<bool, String> {
+    if unit == "B" || unit.eq_ignore_ascii_case("byte") || unit.eq_ignore_ascii_case("bytes") {
+        return Ok(false);
+    }
+    if is_prefix_only(unit) || unit == "b" {
+        return Err(format!(
+            "'{}' is ambiguous: sizes are in bytes, write \"kB\", \"MB\", \"KiB\" or \"MiB\"",
+            input
+        ));
+    }
+    Err(format!(
+        "'{}' has an unknown unit '{}' (use B or bytes with an optional k, M, G, T, Ki, Mi, \
+         Gi or Ti prefix)",
+        input, unit
+    ))
+}
+
+fn is_prefix_only(unit: &str) -> bool {
+    PREFIXES
+        .iter()
+        .any(|(prefix, _)| unit.eq_ignore_ascii_case(prefix))
+}
+
+/// Display form of a rate in both bits and bytes, e.g. `"100 Mbps (12.5 MB/s)"`.
+pub fn format_rate(bytes_per_sec: u64) -> String {
+    let bits = scaled(bytes_per_sec as f64 * 8.0);
+    let bytes = scaled(bytes_per_sec as f64);
+    format!("{} {}bps ({} {}B/s)", bits.0, bits.1, bytes.0, bytes.1)
+}
+
+/// Value below 1000 with its decimal prefix, up to two decimals.
+fn scaled(mut value: f64) -> (String, &'static str) {
+    const SCALES: [&str; 5] = ["", "k", "M", "G", "T"];
+    let mut prefix = 0;
+    while value >= 1000.0 && prefix < SCALES.len() - 1 {
+        value /= 1000.0;
+        prefix += 1;
+    }
+    let formatted = format!("{:.2}", value);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    (formatted.to_string(), SCALES[prefix])
+}
+
+/// Shortest exact unit form of a value for configuration files; `None` when only the
+/// plain integer is exact.
+fn config_form(value: u64, quantity: Quantity) -> Option<String> {
+    if value == 0 {
+        return None;
+    }
+    let mut candidates: Vec<(u128, String)> = Vec::new();
+    for (prefix, multiplier) in PREFIXES {
+        let multiplier = u128::from(multiplier);
+        let value = u128::from(value);
+        match quantity {
+            Quantity::Rate => {
+                if (value * 8) % multiplier == 0 {
+                    candidates.push(((value * 8) / multiplier, format!("{}bit", prefix)));
+                }
+                if value % multiplier == 0 {
+                    candidates.push((value / multiplier, format!("{}B/s", prefix)));
+                }
+            }
+            Quantity::Size => {
+                if value % multiplier == 0 {
+                    candidates.push((value / multiplier, format!("{}B", prefix)));
+                }
+            }
+        }
+    }
+    // Smallest number wins; on a tie the earlier (decimal, then bits) form
+    candidates
+        .into_iter()
+        .enumerate()
+        .min_by_key(|(order, (count, _))| (*count, *order))
+        .map(|(_, (count, unit))| format!("{}{}", count, unit))
+}
+
+/// Serde adapter for rates: integers or unit strings in, unit strings out.
+pub mod rate_serde {
+    use super::{config_form, Quantity, QuantityVisitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match config_form(*value, Quantity::Rate) {
+            Some(form) => serializer.serialize_str(&form),
+            None => serializer.serialize_u64(*value),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(QuantityVisitor(Quantity::Rate))
+    }
+}
+
+/// Serde adapter for sizes: integers or unit strings in, unit strings out.
+pub mod size_serde {
+    use super::{config_form, Quantity, QuantityVisitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match config_form(*value, Quantity::Size) {
+            Some(form) => serializer.serialize_str(&form),
+            None => serializer.serialize_u64(*value),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(QuantityVisitor(Quantity::Size))
+    }
+}
+
+struct QuantityVisitor(Quantity);
+
+impl Visitor<'_> for QuantityVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Quantity::Rate => f.write_str("bytes per second or a rate such as \"100mbps\""),
+            Quantity::Size => f.write_str("bytes or a size such as \"1MiB\""),
+        }
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<u64, E> {
+        Ok(value)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<u64, E> {
+        u64::try_from(value).map_err(|_| E::custom(format!("{} is negative", value)))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<u64, E> {
+        parse(value, self.0).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_integers_are_bytes() {
+        assert_eq!(parse_rate("125000000"), Ok(125_000_000));
+        assert_eq!(parse_rate(" 125_000_000 "), Ok(125_000_000));
+        assert_eq!(parse_size("1048576"), Ok(1_048_576));
+        assert!(parse_rate("12.5").is_err());
+    }
+
+    #[test]
+    fn bit_suffixes() {
+        for unit in ["bps", "b/s", "bit", "bits", "bit/s", "bits/s", " bps"] {
+            assert_eq!(parse_rate(&format!("8{}", unit)), Ok(1), "8{}", unit);
+        }
+        assert_eq!(parse_rate("100kbps"), Ok(12_500));
+        assert_eq!(parse_rate("100mbps"), Ok(12_500_000));
+        assert_eq!(parse_rate("1gbit"), Ok(125_000_000));
+        assert_eq!(parse_rate("2Tbit/s"), Ok(250_000_000_000));
+        assert_eq!(parse_rate("12Mb/s"), Ok(1_500_000));
+        assert_eq!(parse_rate("1Kibit"), Ok(128));
+        assert_eq!(parse_rate("1Mibit"), Ok(131_072));
+        assert_eq!(parse_rate("1Gibps"), Ok(134_217_728));
+        assert_eq!(parse_rate("1Tibit"), Ok(137_438_953_472));
+    }
+
+    #[test]
+    fn byte_suffixes() {
+        for unit in ["Bps", "B/s", "byte/s", "bytes/s", " B/s"] {
+            assert_eq!(parse_rate(&format!("5{}", unit)), Ok(5), "5{}", unit);
+        }
+        assert_eq!(parse_rate("64kB/s"), Ok(64_000));
+        assert_eq!(parse_rate("64KiB/s"), Ok(65_536));
+        assert_eq!(parse_rate("10MBps"), Ok(10_000_000));
+        assert_eq!(parse_rate("1GiB/s"), Ok(1 << 30));
+        assert_eq!(parse_rate("1 TB/s"), Ok(1_000_000_000_000));
+
+        for unit in ["B", "byte", "bytes", " bytes"] {
+            assert_eq!(parse_size(&format!("5{}", unit)), Ok(5), "5{}", unit);
+        }
+        assert_eq!(parse_size("1MiB"), Ok(1_048_576));
+        assert_eq!(parse_size("100kB"), Ok(100_000));
+        assert_eq!(parse_size("2GB"), Ok(2_000_000_000));
+    }
+
+    #[test]
+    fn prefixes_and_words_ignore_case() {
+        for input in ["1gbit", "1Gbit", "1GBIT", "1GBit", "1gbps", "1Gbps"] {
+            assert_eq!(parse_rate(input), Ok(125_000_000), "{}", input);
+        }
+        for input in ["1mibit", "1MIBIT", "1MiBit"] {
+            assert_eq!(parse_rate(input), Ok(131_072), "{}", input);
+        }
+        for input in ["12mBps", "12MBps", "12mB/s", "12MB/S", "12 MBYTES/S"] {
+            assert_eq!(parse_rate(input), Ok(12_000_000), "{}", input);
+        }
+        assert_eq!(parse_size("1miB"), Ok(1_048_576));
+        assert_eq!(parse_size("1KIB"), Ok(1_024));
+        assert_eq!(parse_size("3 BYTES"), Ok(3));
+    }
+
+    #[test]
+    fn fractional_values() {
+        assert_eq!(parse_rate("12.5MBps"), Ok(12_500_000));
+        assert_eq!(parse_rate("1.5gbit"), Ok(187_500_000));
+        assert_eq!(parse_rate("0.5 Mbps"), Ok(62_500));
+        assert_eq!(parse_rate("2.5KiB/s"), Ok(2_560));
+        assert_eq!(parse_size("1.5MiB"), Ok(1_572_864));
+    }
+
+    #[test]
+    fn ambiguous_and_malformed_values_are_rejected() {
+        for input in [
+            "100m",
+            "100M",
+            "100k",
+            "1Gi",
+            "100mb",
+            "100Mb",
+            "100MB",
+            "100B",
+            "100MBPS",
+            "100BPS",
+            "100 mbytes",
+        ] {
+            let err = parse_rate(input).unwrap_err();
+            assert!(err.contains(input), "{}: {}", input, err);
+        }
+        assert!(parse_rate("100m").unwrap_err().contains("ambiguous"));
+        assert!(parse_rate("100MBPS").unwrap_err().contains("ambiguous"));
+
+        for input in [
+            "",
+            "mbps",
+            "-5mbps",
+            "+5mbps",
+            "1e6",
+            "1e6bps",
+            ".5mbps",
+            "5.mbps",
+            "1.2.3mbps",
+            "100 furlongs",
+            "100 mbps extra",
+            "99999999999999999999Tbit",
+        ] {
+            assert!(parse_rate(input).is_err(), "accepted {:?}", input);
+        }
+
+        for input in ["1M", "1k", "1b", "1mib", "1mbit", "1MB/s", "1Mbps"] {
+            assert!(parse_size(input).is_err(), "accepted size {:?}", input);
+        }
+    }
+
+    #[test]
+    fn display_shows_bits_and_bytes() {
+        assert_eq!(format_rate(12_500_000), "100 Mbps (12.5 MB/s)");
+        assert_eq!(format_rate(125_000_000), "1 Gbps (125 MB/s)");
+        assert_eq!(format_rate(131_072), "1.05 Mbps (131.07 kB/s)");
+        assert_eq!(format_rate(100), "800 bps (100 B/s)");
+        assert_eq!(format_rate(0), "0 bps (0 B/s)");
+    }
+
+    #[test]
+    fn config_form_round_trips() {
+        assert_eq!(
+            config_form(125_000_000, Quantity::Rate).as_deref(),
+            Some("1Gbit")
+        );
+        assert_eq!(
+            config_form(12_500_000, Quantity::Rate).as_deref(),
+            Some("100Mbit")
+        );
+        assert_eq!(
+            config_form(131_072, Quantity::Rate).as_deref(),
+            Some("1Mibit")
+        );
+        assert_eq!(config_form(1_000, Quantity::Rate).as_deref(), Some("1kB/s"));
+        assert_eq!(
+            config_form(1_048_576, Quantity::Size).as_deref(),
+            Some("1MiB")
+        );
+        assert_eq!(
+            config_form(100_000, Quantity::Size).as_deref(),
+            Some("100kB")
+        );
+        assert_eq!(config_form(123_457, Quantity::Rate), None);
+        assert_eq!(config_form(0, Quantity::Rate), None);
+
+        for value in [
+            1,
+            7,
+            1_000,
+            65_536,
+            131_072,
+            12_500_000,
+            125_000_000,
+            123_457,
+        ] {
+            let form = config_form(value, Quantity::Rate).unwrap_or_else(|| value.to_string());
+            assert_eq!(parse_rate(&form), Ok(value), "{}", form);
+            let form = config_form(value, Quantity::Size).unwrap_or_else(|| value.to_string());
+            assert_eq!(parse_size(&form), Ok(value), "{}", form);
+        }
+    }
+}
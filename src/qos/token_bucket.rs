@@ -95,6 +95,11 @@ impl TokenBucket {
         }
     }
 
+    /// Return tokens taken for a transfer that did not happen (never above capacity)
+    pub fn refund(&self, amount: u64) {
+        self.add_tokens(amount);
+    }
+
     /// Refill tokens based on elapsed time (synchronous)
     fn refill_sync(&self) {
         // Note: This uses a mutex for the timestamp, but only briefly
@@ -1,4 +1,7 @@
+use super::rate::{format_rate, rate_serde, size_serde};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
 
 /// QoS configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -17,6 +20,10 @@ pub struct QosConfig {
     /// Connection limits
     #[serde(default)]
     pub connection_limits: ConnectionLimits,
+
+    /// Per-source-IP shaping, on top of the user and global limits
+    #[serde(default)]
+    pub per_ip: PerIpConfig,
 }
 
 fn default_algorithm() -> String {
@@ -30,31 +37,35 @@ impl Default for QosConfig {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         }
     }
 }
 
 /// Hierarchical Token Bucket configuration
+///
+/// Rates accept bytes per second or a string with a unit ("100mbps", "12.5MBps",
+/// "1gbit"); the burst size accepts bytes or a size ("1MiB"). See [`super::rate`].
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HtbConfig {
     /// Global bandwidth limit in bytes per second
-    /// Example: 125_000_000 = 1 Gbps
-    #[serde(default = "default_global_bandwidth")]
+    /// Example: 125_000_000 = "1gbit"
+    #[serde(default = "default_global_bandwidth", with = "rate_serde")]
     pub global_bandwidth_bytes_per_sec: u64,
 
     /// Guaranteed minimum bandwidth per user in bytes per second
-    /// Example: 131_072 = 1 Mbps
-    #[serde(default = "default_guaranteed_bandwidth")]
+    /// Example: 131_072 = "1mibit" (1 Mbps is 125_000)
+    #[serde(default = "default_guaranteed_bandwidth", with = "rate_serde")]
     pub guaranteed_bandwidth_bytes_per_sec: u64,
 
     /// Maximum bandwidth per user in bytes per second (when borrowing)
-    /// Example: 12_500_000 = 100 Mbps
-    #[serde(default = "default_max_bandwidth")]
+    /// Example: 12_500_000 = "100mbit"
+    #[serde(default = "default_max_bandwidth", with = "rate_serde")]
     pub max_bandwidth_bytes_per_sec: u64,
 
     /// Burst size in bytes (how much can be consumed instantly)
-    /// Example: 1_048_576 = 1 MB
-    #[serde(default = "default_burst_size")]
+    /// Example: 1_048_576 = "1MiB"
+    #[serde(default = "default_burst_size", with = "size_serde")]
     pub burst_size_bytes: u64,
 
     /// How often to refill token buckets (milliseconds)
@@ -79,7 +90,7 @@ fn default_global_bandwidth() -> u64 {
 }
 
 fn default_guaranteed_bandwidth() -> u64 {
-    131_072 // 1 Mbps
+    131_072 // 1 Mibit/s
 }
 
 fn default_max_bandwidth() -> u64 {
@@ -106,6 +117,31 @@ fn default_idle_timeout() -> u64 {
     5 // 5 seconds
 }
 
+/// Guaranteed rates below this look like a value meant in bits or kilobits
+const SUSPICIOUS_GUARANTEED_RATE: u64 = 10_000;
+
+/// ...but only next to a global limit above this
+const SUSPICIOUS_GLOBAL_RATE: u64 = 100_000_000;
+
+impl HtbConfig {
+    /// Settings that are valid but probably not what was meant.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.guaranteed_bandwidth_bytes_per_sec < SUSPICIOUS_GUARANTEED_RATE
+            && self.global_bandwidth_bytes_per_sec > SUSPICIOUS_GLOBAL_RATE
+        {
+            warnings.push(format!(
+                "qos.htb.guaranteed_bandwidth_bytes_per_sec is {} while the global limit is \
+                 {}; plain numbers are bytes per second, so this may be a value in bits. \
+                 Write it with a unit, e.g. \"1mbps\" or \"125kB/s\"",
+                format_rate(self.guaranteed_bandwidth_bytes_per_sec),
+                format_rate(self.global_bandwidth_bytes_per_sec)
+            ));
+        }
+        warnings
+    }
+}
+
 impl Default for HtbConfig {
     fn default() -> Self {
         Self {
@@ -121,6 +157,44 @@ impl Default for HtbConfig {
     }
 }
 
+/// Per-source-IP bandwidth limit (`[qos.per_ip]`)
+///
+/// Shapes each client address independently of who it authenticated as, so devices
+/// behind one shared account cannot starve each other. A transfer takes tokens from
+/// its IP bucket as well as from the user and global ones; the strictest one governs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PerIpConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Bandwidth limit per source IP in bytes per second
+    #[serde(default = "default_per_ip_bandwidth", with = "rate_serde")]
+    pub max_bytes_per_sec: u64,
+
+    /// Burst size per source IP in bytes
+    #[serde(default = "default_burst_size", with = "size_serde")]
+    pub burst: u64,
+
+    /// Source networks (CIDRs or addresses) that are not shaped per IP
+    #[serde(default)]
+    pub exempt: Vec<String>,
+}
+
+fn default_per_ip_bandwidth() -> u64 {
+    1_250_000 // 10 Mbps
+}
+
+impl Default for PerIpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes_per_sec: default_per_ip_bandwidth(),
+            burst: default_burst_size(),
+            exempt: Vec::new(),
+        }
+    }
+}
+
 /// Connection limit configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ConnectionLimits {
@@ -150,6 +224,78 @@ impl Default for ConnectionLimits {
     }
 }
 
+/// Connection limits shared by the accept path and the config reloader, so a
+/// reload takes effect for the next connection without a restart
+#[derive(Debug, Clone, Default)]
+pub struct SharedConnectionLimits(Arc<RwLock<ConnectionLimits>>);
+
+impl SharedConnectionLimits {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self(Arc::new(RwLock::new(limits)))
+    }
+
+    /// Limits in effect now
+    pub fn get(&self) -> ConnectionLimits {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the limits; established connections keep their slot
+    pub fn set(&self, limits: ConnectionLimits) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = limits;
+    }
+}
+
+impl From<ConnectionLimits> for SharedConnectionLimits {
+    fn from(limits: ConnectionLimits) -> Self {
+        Self::new(limits)
+    }
+}
+
+/// Which connection limit turned a client away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitType {
+    /// `max_connections_global`
+    GlobalConnections,
+    /// `max_connections_per_user`
+    UserConnections,
+}
+
+impl LimitType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitType::GlobalConnections => "global_connections",
+            LimitType::UserConnections => "user_connections",
+        }
+    }
+}
+
+/// A connection refused by [`ConnectionLimits`], with the counts at the time of the check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionLimitExceeded {
+    pub limit_type: LimitType,
+    pub user: String,
+    pub current: usize,
+    pub limit: usize,
+}
+
+impl std::fmt::Display for ConnectionLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.limit_type {
+            LimitType::GlobalConnections => write!(
+                f,
+                "Global connection limit reached: {}/{}",
+                self.current, self.limit
+            ),
+            LimitType::UserConnections => write!(
+                f,
+                "User connection limit reached for '{}': {}/{}",
+                self.user, self.current, self.limit
+            ),
+        }
+    }
+}
+
 /// User bandwidth allocation info
 #[derive(Debug, Clone)]
 pub struct UserAllocation {
@@ -162,15 +308,42 @@ pub struct UserAllocation {
     /// Guaranteed bandwidth (bytes/sec)
     pub guaranteed_bandwidth: u64,
 
+    /// Share of the allocation above the guarantee, borrowed from the global limit (bytes/sec)
+    pub borrowed_bandwidth: u64,
+
     /// Maximum possible bandwidth (bytes/sec)
     pub max_bandwidth: u64,
 
+    /// The maximum was set at runtime rather than by the config
+    pub max_overridden: bool,
+
     /// Current demand (estimated from recent usage)
     pub current_demand: u64,
 
     /// Is user currently active?
     pub is_active: bool,
 
+    /// A transfer is waiting for the user's tokens right now
+    pub is_throttled: bool,
+
     /// Active connections count
     pub active_connections: usize,
 }
+
+/// Bandwidth use of one shaped source IP
+#[derive(Debug, Clone)]
+pub struct IpAllocation {
+    pub ip: IpAddr,
+
+    /// Per-IP limit (bytes/sec)
+    pub max_bandwidth: u64,
+
+    /// Bytes transferred since the bucket was created
+    pub total_bytes: u64,
+
+    /// Tokens left in the bucket
+    pub available_bytes: u64,
+
+    /// Traffic within the idle timeout
+    pub is_active: bool,
+}
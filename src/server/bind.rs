@@ -1,28 +1,101 @@
-use crate::protocol::{Address, ReplyCode};
+use crate::protocol::{Address, ReplyCode, SocksProtocol};
 use crate::qos::QosEngine;
 use crate::server::handler::IoStream;
 use crate::server::pool::{ConnectionPool, ReuseHint};
 use crate::server::proxy::{proxy_data, TrafficUpdateConfig};
-use crate::session::{ConnectionInfo, SessionManager, SessionProtocol, SessionStatus};
+use crate::server::resolver::{literal_target, DestinationResolver};
+use crate::session::{
+    ConnectionInfo, SessionCommand, SessionManager, SessionProtocol, SessionStatus,
+};
 use crate::utils::error::{Result, RustSocksError};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::time::timeout;
 use tracing::{debug, info, warn};
 
-/// BIND waiting timeout (typically 5 minutes per RFC 1928)
-const BIND_ACCEPT_TIMEOUT: Duration = Duration::from_secs(300);
+/// Default wait for the inbound connection (`server.bind_accept_timeout_secs`)
+pub const DEFAULT_BIND_ACCEPT_TIMEOUT: Duration = Duration::from_secs(300);
 
 /// Context for BIND command handling
 pub struct BindContext {
     pub user: Arc<str>,
+    /// Principal that passed SOCKS authentication, when any
+    pub authenticated_user: Option<Arc<str>>,
+    /// Client-supplied correlation ID, when any
+    pub correlation_id: Option<Arc<str>>,
     pub client_addr: SocketAddr,
-    pub acl_decision: String,
+    pub acl_decision: &'static str,
     pub acl_rule: Option<String>,
     pub qos_engine: QosEngine,
     pub connection_pool: Arc<ConnectionPool>,
+    /// Resolves a domain-type DST.ADDR into the addresses the peer may come from
+    pub resolver: Arc<dyn DestinationResolver>,
+    /// How long to wait for the inbound connection before replying with a failure
+    pub accept_timeout: Duration,
+    /// Traffic update interval and idle timeout for the relay
+    pub traffic_config: TrafficUpdateConfig,
+    /// Groups of the user, recorded on the session
+    pub groups: Vec<Arc<str>>,
+    /// SOCKS version the client negotiated, recorded on the session
+    pub protocol: SocksProtocol,
+    /// Relayed although the ACL blocks it (`acl.mode = "monitor"`)
+    pub would_block: bool,
+}
+
+/// Source addresses accepted for the inbound connection.
+///
+/// RFC 1928 has the client name the host expected to connect in DST.ADDR; an
+/// unspecified address (0.0.0.0 or ::) accepts any peer. Only the address is
+/// compared, since the peer's source port is rarely known in advance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ExpectedPeer {
+    Any,
+    Hosts(Vec<IpAddr>),
+}
+
+impl ExpectedPeer {
+    async fn from_request(
+        address: &Address,
+        port: u16,
+        resolver: &dyn DestinationResolver,
+    ) -> Result<Self> {
+        let addrs = match literal_target(address, port) {
+            Some(target) if target.ip().is_unspecified() => return Ok(ExpectedPeer::Any),
+            Some(target) => vec![target],
+            None => resolver.resolve(address, port).await?,
+        };
+        Ok(ExpectedPeer::Hosts(
+            addrs.iter().map(|addr| addr.ip().to_canonical()).collect(),
+        ))
+    }
+
+    fn admits(&self, peer: &SocketAddr) -> bool {
+        match self {
+            ExpectedPeer::Any => true,
+            ExpectedPeer::Hosts(hosts) => hosts.contains(&peer.ip().to_canonical()),
+        }
+    }
+}
+
+/// Accept connections until one comes from the expected peer; others are dropped.
+async fn accept_expected(
+    listener: &TcpListener,
+    expected: &ExpectedPeer,
+    client_addr: SocketAddr,
+) -> std::io::Result<(TcpStream, SocketAddr)> {
+    loop {
+        let (stream, peer_addr) = listener.accept().await?;
+        if expected.admits(&peer_addr) {
+            return Ok((stream, peer_addr));
+        }
+        warn!(
+            peer = %peer_addr,
+            client = %client_addr,
+            "BIND: dropped inbound connection from unexpected peer"
+        );
+    }
 }
 
 /// Handle BIND command
@@ -38,10 +111,35 @@ where
     S: IoStream,
 {
     let client_addr = bind_ctx.client_addr;
-    let dest_string = dest_addr.to_string();
+    let dest_string = dest_addr.to_arc_str();
+
+    let expected =
+        match ExpectedPeer::from_request(dest_addr, dest_port, bind_ctx.resolver.as_ref()).await {
+            Ok(expected) => expected,
+            Err(e) => {
+                warn!(
+                    "BIND: cannot resolve expected peer {}:{}: {}",
+                    dest_string, dest_port, e
+                );
+                send_bind_response(&mut client_stream, ReplyCode::HostUnreachable, client_addr)
+                    .await?;
+                return Err(e);
+            }
+        };
 
-    // Bind TCP listener on ephemeral port (0 = random)
-    let bind_listener = TcpListener::bind("0.0.0.0:0").await?;
+    // Listen on the expected peer's address family, on an ephemeral port
+    let listen_addr = match &expected {
+        ExpectedPeer::Hosts(hosts) if hosts.iter().all(IpAddr::is_ipv6) => "[::]:0",
+        _ => "0.0.0.0:0",
+    };
+    let bind_listener = match TcpListener::bind(listen_addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("BIND: cannot open listening socket: {}", e);
+            send_bind_response(&mut client_stream, ReplyCode::GeneralFailure, client_addr).await?;
+            return Err(RustSocksError::Io(e));
+        }
+    };
     let bind_addr = bind_listener.local_addr()?;
 
     info!(
@@ -56,23 +154,51 @@ where
     let connection_info = ConnectionInfo {
         source_ip: client_addr.ip(),
         source_port: client_addr.port(),
-        dest_ip: dest_string.clone(),
+        dest_ip: dest_string,
         dest_port,
         protocol: SessionProtocol::Tcp,
+        authenticated_user: bind_ctx.authenticated_user.clone(),
+        correlation_id: bind_ctx.correlation_id.clone(),
+        socks_version: bind_ctx.protocol.version(),
+        chained: false,
+        groups: bind_ctx.groups.clone(),
     };
 
     let (session_id, cancel_token) = session_manager
         .new_session_with_control(
-            bind_ctx.user.as_ref(),
+            Arc::clone(&bind_ctx.user),
             connection_info,
-            bind_ctx.acl_decision.clone(),
-            bind_ctx.acl_rule.clone(),
+            bind_ctx.acl_decision,
+            bind_ctx.acl_rule,
             None,
         )
         .await;
+    session_manager
+        .set_command(&session_id, SessionCommand::Bind)
+        .await;
+    if bind_ctx.would_block {
+        session_manager.mark_would_block(&session_id).await;
+    }
 
-    // Wait for incoming connection with timeout
-    let incoming_result = timeout(BIND_ACCEPT_TIMEOUT, bind_listener.accept()).await;
+    // Wait for the expected peer, then stop listening
+    let accept = accept_expected(&bind_listener, &expected, client_addr);
+    let incoming_result = tokio::select! {
+        result = timeout(bind_ctx.accept_timeout, accept) => result,
+        _ = cancel_token.cancelled() => {
+            info!("BIND: session {} terminated while waiting for peer", session_id);
+            send_bind_response(&mut client_stream, ReplyCode::GeneralFailure, client_addr)
+                .await?;
+            session_manager
+                .close_session(
+                    &session_id,
+                    Some("BIND terminated while waiting for connection".to_string()),
+                    SessionStatus::Closed,
+                )
+                .await;
+            return Ok(());
+        }
+    };
+    drop(bind_listener);
 
     match incoming_result {
         Ok(Ok((incoming_stream, peer_addr))) => {
@@ -91,9 +217,10 @@ where
                 session_manager.clone(),
                 session_id,
                 cancel_token,
-                TrafficUpdateConfig::default(),
+                bind_ctx.traffic_config,
                 bind_ctx.qos_engine.clone(),
                 Arc::clone(&bind_ctx.user),
+                client_addr.ip(),
             )
             .await
             {
@@ -137,6 +264,19 @@ where
                         .await;
                     info!("BIND session closed by client {}", client_addr);
                 }
+                Err(RustSocksError::IdleTimeout) => {
+                    bind_ctx
+                        .connection_pool
+                        .release(peer_addr, ReuseHint::Refresh)
+                        .await;
+                    session_manager
+                        .close_session(
+                            &session_id,
+                            Some("idle timeout".to_string()),
+                            SessionStatus::Closed,
+                        )
+                        .await;
+                }
                 Err(e) => {
                     let reason = format!("BIND proxy error: {}", e);
                     bind_ctx
@@ -165,7 +305,7 @@ where
         Err(_) => {
             warn!(
                 "BIND: timeout waiting for incoming connection ({}s)",
-                BIND_ACCEPT_TIMEOUT.as_secs()
+                bind_ctx.accept_timeout.as_secs()
             );
             send_bind_response(&mut client_stream, ReplyCode::GeneralFailure, client_addr).await?;
             session_manager
@@ -229,12 +369,37 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::resolver::SystemResolver;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[test]
     fn test_bind_accept_timeout_is_reasonable() {
         // Verify timeout is at least 5 minutes as per RFC 1928
-        assert!(BIND_ACCEPT_TIMEOUT.as_secs() >= 300);
+        assert!(DEFAULT_BIND_ACCEPT_TIMEOUT.as_secs() >= 300);
         // But not more than 10 minutes
-        assert!(BIND_ACCEPT_TIMEOUT.as_secs() <= 600);
+        assert!(DEFAULT_BIND_ACCEPT_TIMEOUT.as_secs() <= 600);
+    }
+
+    #[tokio::test]
+    async fn expected_peer_matches_address_only() {
+        let any = ExpectedPeer::from_request(&Address::IPv4([0, 0, 0, 0]), 0, &SystemResolver)
+            .await
+            .unwrap();
+        assert_eq!(any, ExpectedPeer::Any);
+        let any_v6 = ExpectedPeer::from_request(&Address::IPv6([0; 16]), 21, &SystemResolver)
+            .await
+            .unwrap();
+        assert_eq!(any_v6, ExpectedPeer::Any);
+
+        let expected =
+            ExpectedPeer::from_request(&Address::IPv4([192, 0, 2, 10]), 20, &SystemResolver)
+                .await
+                .unwrap();
+        assert!(expected.admits(&SocketAddr::new(Ipv4Addr::new(192, 0, 2, 10).into(), 40000)));
+        assert!(!expected.admits(&SocketAddr::new(Ipv4Addr::new(192, 0, 2, 11).into(), 20)));
+        // IPv4-mapped sources from a dual-stack listener still match
+        let mapped = Ipv4Addr::new(192, 0, 2, 10).to_ipv6_mapped();
+        assert!(expected.admits(&SocketAddr::new(mapped.into(), 20)));
+        assert!(!expected.admits(&SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 20)));
     }
 }
@@ -0,0 +1,192 @@
+//! Relay buffers shared across sessions (`server.relay_buffer_bytes`).
+//!
+//! Every CONNECT or BIND tunnel relays through two buffers, one per direction. Instead of
+//! allocating them for each session, the relay checks them out of [`RelayBufferPool`] and
+//! they go back when the relay ends. Only `[..bytes_read]` of a buffer is ever written
+//! out, so bytes left over from an earlier session never leave the proxy.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Size of each relay buffer unless `server.relay_buffer_bytes` says otherwise
+pub const DEFAULT_RELAY_BUFFER_BYTES: usize = 32 * 1024;
+
+/// Idle buffers kept for reuse; returns beyond this are freed
+pub const MAX_IDLE_BUFFERS: usize = 1024;
+
+/// Counters of a [`RelayBufferPool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelayBufferStats {
+    /// Bytes per buffer handed out now
+    pub buffer_bytes: usize,
+    /// Buffers waiting for the next checkout
+    pub idle: usize,
+    /// Checkouts that had to allocate
+    pub allocated: u64,
+    /// Checkouts served from an idle buffer
+    pub reused: u64,
+}
+
+#[derive(Debug)]
+pub struct RelayBufferPool {
+    buffer_bytes: AtomicUsize,
+    idle: Mutex<Vec<Box<[u8]>>>,
+    allocated: AtomicU64,
+    reused: AtomicU64,
+}
+
+impl RelayBufferPool {
+    pub fn new(buffer_bytes: usize) -> Self {
+        Self {
+            buffer_bytes: AtomicUsize::new(buffer_bytes),
+            idle: Mutex::new(Vec::new()),
+            allocated: AtomicU64::new(0),
+            reused: AtomicU64::new(0),
+        }
+    }
+
+    /// The pool the proxy relay uses
+    pub fn global() -> &'static RelayBufferPool {
+        static POOL: OnceLock<RelayBufferPool> = OnceLock::new();
+        POOL.get_or_init(|| RelayBufferPool::new(DEFAULT_RELAY_BUFFER_BYTES))
+    }
+
+    /// Hand out buffers of `bytes` from now on. Idle buffers of another size are freed,
+    /// and ones still checked out are freed when they come back.
+    pub fn set_buffer_bytes(&self, bytes: usize) {
+        if self.buffer_bytes.swap(bytes, Ordering::Relaxed) != bytes {
+            self.idle_buffers().retain(|buffer| buffer.len() == bytes);
+        }
+    }
+
+    pub fn buffer_bytes(&self) -> usize {
+        self.buffer_bytes.load(Ordering::Relaxed)
+    }
+
+    /// A buffer of [`Self::buffer_bytes`], returned to the pool when dropped
+    pub fn checkout(&self) -> RelayBuffer<'_> {
+        let bytes = self.buffer_bytes();
+        let reused = self.idle_buffers().pop();
+        let buffer = match reused {
+            Some(buffer) if buffer.len() == bytes => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                buffer
+            }
+            _ => {
+                self.allocated.fetch_add(1, Ordering::Relaxed);
+                vec![0u8; bytes].into_boxed_slice()
+            }
+        };
+        RelayBuffer {
+            pool: self,
+            buffer: Some(buffer),
+        }
+    }
+
+    pub fn stats(&self) -> RelayBufferStats {
+        RelayBufferStats {
+            buffer_bytes: self.buffer_bytes(),
+            idle: self.idle_buffers().len(),
+            allocated: self.allocated.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+        }
+    }
+
+    fn give_back(&self, buffer: Box<[u8]>) {
+        if buffer.len() != self.buffer_bytes() {
+            return;
+        }
+        let mut idle = self.idle_buffers();
+        if idle.len() < MAX_IDLE_BUFFERS {
+            idle.push(buffer);
+        }
+    }
+
+    fn idle_buffers(&self) -> std::sync::MutexGuard<'_, Vec<Box<[u8]>>> {
+        // A panic while holding the lock cannot leave the list inconsistent
+        self.idle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for RelayBufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_RELAY_BUFFER_BYTES)
+    }
+}
+
+/// A buffer checked out of a [`RelayBufferPool`]
+pub struct RelayBuffer<'a> {
+    pool: &'a RelayBufferPool,
+    buffer: Option<Box<[u8]>>,
+}
+
+impl Deref for RelayBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.buffer.as_deref().expect("buffer present until drop")
+    }
+}
+
+impl DerefMut for RelayBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.buffer
+            .as_deref_mut()
+            .expect("buffer present until drop")
+    }
+}
+
+impl Drop for RelayBuffer<'_> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.give_back(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returned_buffers_are_reused() {
+        let pool = RelayBufferPool::new(4096);
+        {
+            let mut upload = pool.checkout();
+            let _download = pool.checkout();
+            assert_eq!(upload.len(), 4096);
+            upload[0] = 0xff;
+        }
+        let _again = pool.checkout();
+        let stats = pool.stats();
+        assert_eq!(stats.allocated, 2);
+        assert_eq!(stats.reused, 1);
+        assert_eq!(stats.idle, 1);
+    }
+
+    #[test]
+    fn size_change_drops_buffers_of_the_old_size() {
+        let pool = RelayBufferPool::new(4096);
+        let held = pool.checkout();
+        drop(pool.checkout());
+        assert_eq!(pool.stats().idle, 1);
+
+        pool.set_buffer_bytes(8192);
+        assert_eq!(pool.stats().idle, 0);
+        drop(held);
+        assert_eq!(pool.stats().idle, 0);
+        assert_eq!(pool.checkout().len(), 8192);
+        assert_eq!(pool.stats().idle, 1);
+    }
+
+    #[test]
+    fn idle_buffers_are_capped() {
+        let pool = RelayBufferPool::new(1024);
+        let held: Vec<_> = (0..MAX_IDLE_BUFFERS + 8).map(|_| pool.checkout()).collect();
+        drop(held);
+        assert_eq!(pool.stats().idle, MAX_IDLE_BUFFERS);
+    }
+}
@@ -0,0 +1,312 @@
+//! Re-reading the main configuration file while the server runs.
+//!
+//! A reload (SIGHUP or `POST /api/admin/reload-config`) parses and validates the
+//! file, then applies the settings that can change in place: `auth.users` and
+//! `auth.password_hashing`, the HTB bandwidth rates, `qos.connection_limits`, the
+//! `[sessions]` batch parameters, `logging.level` and `[logging.sampling]`. Every
+//! other changed setting is reported as requiring a restart and keeps its running
+//! value. A file that fails to parse or validate leaves the running configuration
+//! untouched.
+
+use crate::auth::AuthManager;
+use crate::config::Config;
+use crate::qos::{ConnectionLimits, QosEngine, SharedConnectionLimits};
+#[cfg(feature = "database")]
+use crate::session::BatchConfig;
+use crate::session::SessionManager;
+use crate::utils::error::{Result, RustSocksError};
+use crate::utils::log_sampling;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, RwLock};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+/// Applies a new log filter directive; installed by the binary that owns the
+/// tracing subscriber.
+pub type LogLevelHook = Box<dyn Fn(&str) -> std::result::Result<(), String> + Send + Sync>;
+
+/// Outcome of a successful reload, as dotted config paths
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConfigReloadReport {
+    /// Changed settings now in effect
+    pub applied: Vec<String>,
+    /// Changed settings that keep their running value until a restart
+    pub requires_restart: Vec<String>,
+}
+
+pub struct ConfigReloader {
+    path: Option<PathBuf>,
+    running: RwLock<Arc<Config>>,
+    auth_manager: Arc<AuthManager>,
+    qos_engine: QosEngine,
+    connection_limits: SharedConnectionLimits,
+    #[cfg_attr(not(feature = "database"), allow(dead_code))]
+    session_manager: Arc<SessionManager>,
+    log_level_hook: OnceLock<LogLevelHook>,
+    /// Serializes reloads so two signals cannot interleave their updates
+    reload_lock: Mutex<()>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        config: Arc<Config>,
+        path: Option<PathBuf>,
+        auth_manager: Arc<AuthManager>,
+        qos_engine: QosEngine,
+        connection_limits: SharedConnectionLimits,
+        session_manager: Arc<SessionManager>,
+    ) -> Self {
+        Self {
+            path,
+            running: RwLock::new(config),
+            auth_manager,
+            qos_engine,
+            connection_limits,
+            session_manager,
+            log_level_hook: OnceLock::new(),
+            reload_lock: Mutex::new(()),
+        }
+    }
+
+    /// File re-read on reload; `None` when the server started without `--config`
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    /// Configuration in effect, with the hot settings of the last reload applied
+    pub fn running(&self) -> Arc<Config> {
+        self.running
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Connection limits in effect
+    pub fn connection_limits(&self) -> ConnectionLimits {
+        self.connection_limits.get()
+    }
+
+    /// Install the hook that changes the log filter; later calls are ignored
+    pub fn set_log_level_hook(&self, hook: LogLevelHook) {
+        let _ = self.log_level_hook.set(hook);
+    }
+
+    /// Re-read the config file and apply what can change without a restart.
+    ///
+    /// On error nothing was applied and the running configuration stays as it was.
+    pub async fn reload(&self) -> Result<ConfigReloadReport> {
+        let _guard = self.reload_lock.lock().await;
+        let result = self.reload_locked().await;
+        if let Err(e) = &result {
+            error!(
+                path = ?self.path,
+                error = %e,
+                "Configuration reload failed, keeping the running configuration"
+            );
+        }
+        result
+    }
+
+    async fn reload_locked(&self) -> Result<ConfigReloadReport> {
+        let Some(path) = &self.path else {
+            return Err(RustSocksError::Config(
+                "Server was started without a config file; nothing to reload".to_string(),
+            ));
+        };
+        let new = Config::from_file(path)?;
+        let running = self.running();
+
+        // The hot settings must also hold up next to the running cold ones
+        let next = with_hot_settings(&running, &new);
+        next.validate_effective()?;
+        let applied = changed_paths(&running, &next)?;
+        let requires_restart = changed_paths(&next, &new)?;
+        let changed = |prefix: &str| applied.iter().any(|path| path.starts_with(prefix));
+
+        // Fallible steps first, so a failure leaves everything as it was
+        if changed("logging.level") {
+            EnvFilter::try_new(&next.logging.level).map_err(|e| {
+                RustSocksError::Config(format!(
+                    "Invalid logging.level '{}': {}",
+                    next.logging.level, e
+                ))
+            })?;
+        }
+        if changed("auth.") {
+            let auth_manager = self.auth_manager.clone();
+            let auth = next.auth.clone();
+            tokio::task::spawn_blocking(move || auth_manager.reload_users(&auth))
+                .await
+                .map_err(|e| {
+                    RustSocksError::Config(format!("auth.users reload failed: {}", e))
+                })??;
+        }
+
+        if changed("qos.htb.") {
+            self.qos_engine.update_rates(&next.qos.htb).await;
+        }
+        if changed("qos.connection_limits.") {
+            self.connection_limits
+                .set(next.qos.connection_limits.clone());
+        }
+        #[cfg(feature = "database")]
+        if changed("sessions.") {
+            self.session_manager
+                .reconfigure_batch_writer(BatchConfig::from_session_settings(&next.sessions));
+        }
+        if changed("logging.sampling.") {
+            log_sampling::configure(&next.logging.sampling);
+        }
+        if changed("logging.level") {
+            match self.log_level_hook.get() {
+                Some(hook) => {
+                    if let Err(e) = hook(&next.logging.level) {
+                        warn!(error = %e, "Failed to apply the reloaded log level");
+                    }
+                }
+                None => warn!("No log level hook installed, logging.level not applied"),
+            }
+        }
+
+        *self.running.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(next);
+
+        info!(
+            path = %path.display(),
+            applied = ?applied,
+            "Configuration reloaded"
+        );
+        if !requires_restart.is_empty() {
+            warn!(
+                settings = ?requires_restart,
+                "Changed settings take effect only after a restart"
+            );
+        }
+
+        Ok(ConfigReloadReport {
+            applied,
+            requires_restart,
+        })
+    }
+}
+
+/// `running` with the hot-reloadable settings taken from `new`
+fn with_hot_settings(running: &Config, new: &Config) -> Config {
+    let mut next = running.clone();
+
+    next.auth.users = new.auth.users.clone();
+    next.auth.password_hashing = new.auth.password_hashing.clone();
+
+    next.qos.htb.global_bandwidth_bytes_per_sec = new.qos.htb.global_bandwidth_bytes_per_sec;
+    next.qos.htb.guaranteed_bandwidth_bytes_per_sec =
+        new.qos.htb.guaranteed_bandwidth_bytes_per_sec;
+    next.qos.htb.max_bandwidth_bytes_per_sec = new.qos.htb.max_bandwidth_bytes_per_sec;
+    next.qos.connection_limits = new.qos.connection_limits.clone();
+
+    next.sessions.batch_size = new.sessions.batch_size;
+    next.sessions.batch_interval_ms = new.sessions.batch_interval_ms;
+    next.sessions.batch_adaptive = new.sessions.batch_adaptive;
+    next.sessions.batch_min_size = new.sessions.batch_min_size;
+    next.sessions.batch_max_size = new.sessions.batch_max_size;
+    next.sessions.batch_target_flush_ms = new.sessions.batch_target_flush_ms;
+    next.sessions.batch_queue_max = new.sessions.batch_queue_max;
+    next.sessions.batch_overflow_policy = new.sessions.batch_overflow_policy.clone();
+
+    next.logging.level = new.logging.level.clone();
+    next.logging.sampling = new.logging.sampling.clone();
+
+    next
+}
+
+/// Dotted paths of the settings that differ; arrays compare as a whole
+fn changed_paths(old: &Config, new: &Config) -> Result<Vec<String>> {
+    let to_value = |config: &Config| {
+        serde_json::to_value(config)
+            .map_err(|e| RustSocksError::Config(format!("Failed to compare configs: {}", e)))
+    };
+    let mut paths = Vec::new();
+    diff_values("", &to_value(old)?, &to_value(new)?, &mut paths);
+    paths.sort();
+    Ok(paths)
+}
+
+fn diff_values(prefix: &str, old: &Value, new: &Value, paths: &mut Vec<String>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                match new.get(key) {
+                    Some(new_value) => diff_values(&join(key), old_value, new_value, paths),
+                    None => paths.push(join(key)),
+                }
+            }
+            for key in new.keys().filter(|key| !old.contains_key(*key)) {
+                paths.push(join(key));
+            }
+        }
+        _ if old != new => paths.push(prefix.to_string()),
+        _ => {}
+    }
+}
+
+/// Reload the configuration on every SIGHUP until the task is aborted
+#[cfg(unix)]
+pub fn spawn_sighup_listener(reloader: Arc<ConfigReloader>) -> Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    Ok(tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+            // Failures are logged by reload() and leave the server running as before
+            let _ = reloader.reload().await;
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hot_settings_are_applied_and_the_rest_needs_a_restart() {
+        let running = Config::default();
+        let mut new = Config::default();
+        new.qos.connection_limits.max_connections_per_user = 3;
+        new.sessions.batch_size = 7;
+        new.logging.level = "debug".to_string();
+        new.logging.sampling.connection_events_ratio = 0.01;
+        new.server.bind_port = running.server.bind_port + 1;
+        new.server.tls.certificate_path = Some("/etc/rustsocks/cert.pem".to_string());
+
+        let next = with_hot_settings(&running, &new);
+        assert_eq!(
+            changed_paths(&running, &next).unwrap(),
+            vec![
+                "logging.level",
+                "logging.sampling.connection_events_ratio",
+                "qos.connection_limits.max_connections_per_user",
+                "sessions.batch_size",
+            ]
+        );
+        assert_eq!(
+            changed_paths(&next, &new).unwrap(),
+            vec!["server.bind_port", "server.tls.certificate_path"]
+        );
+    }
+
+    #[test]
+    fn identical_configs_have_no_changes() {
+        let config = Config::default();
+        assert!(changed_paths(&config, &config.clone()).unwrap().is_empty());
+    }
+}
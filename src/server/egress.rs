@@ -0,0 +1,233 @@
+//! Source addresses for upstream traffic by user or group (`[server.egress]`).
+//!
+//! Hosts with several public addresses can send each user group out through its own,
+//! so firewalls further downstream can tell them apart. The first rule naming the user
+//! or one of their groups wins; everyone else keeps the address the kernel picks.
+//!
+//! A CONNECT with an egress address binds a fresh socket to it before connecting, so
+//! like per-destination socket options it never uses pooled connections. Destinations
+//! of an address family the rule has no address for are not tried: traffic never
+//! leaves from an address other than the configured one. A UDP ASSOCIATE relay binds to
+//! the rule's IPv4 address.
+
+use crate::config::EgressSettings;
+use crate::utils::error::{Result, RustSocksError};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+/// Source addresses of one rule, at most one per family.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EgressAddresses {
+    pub v4: Option<Ipv4Addr>,
+    pub v6: Option<Ipv6Addr>,
+}
+
+impl EgressAddresses {
+    /// Address to bind before connecting to `peer`; `None` when the family has none
+    pub fn for_peer(&self, peer: SocketAddr) -> Option<IpAddr> {
+        match peer {
+            SocketAddr::V4(_) => self.v4.map(IpAddr::V4),
+            SocketAddr::V6(_) => self.v6.map(IpAddr::V6),
+        }
+    }
+
+    /// Bind `socket` to the address for `peer`, before it connects
+    pub fn bind(&self, socket: &tokio::net::TcpSocket, peer: SocketAddr) -> io::Result<()> {
+        let Some(ip) = self.for_peer(peer) else {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("no egress address for {}", peer),
+            ));
+        };
+        socket.bind(SocketAddr::new(ip, 0)).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot bind egress address {}: {}", ip, e),
+            )
+        })
+    }
+}
+
+impl fmt::Display for EgressAddresses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.v4, self.v6) {
+            (Some(v4), Some(v6)) => write!(f, "{}, {}", v4, v6),
+            (Some(v4), None) => write!(f, "{}", v4),
+            (None, Some(v6)) => write!(f, "{}", v6),
+            (None, None) => f.write_str("-"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EgressRule {
+    users: Vec<String>,
+    groups: Vec<String>,
+    addresses: EgressAddresses,
+}
+
+/// Egress rules derived from `[server.egress]`.
+#[derive(Debug, Clone, Default)]
+pub struct EgressMap {
+    rules: Vec<EgressRule>,
+}
+
+impl EgressMap {
+    /// Build the rules, failing when an address is not assigned to this host.
+    pub fn from_settings(settings: &EgressSettings) -> Result<Self> {
+        let map = Self::parse(settings)?;
+        for rule in &map.rules {
+            let addresses = [
+                rule.addresses.v4.map(IpAddr::V4),
+                rule.addresses.v6.map(IpAddr::V6),
+            ];
+            for ip in addresses.into_iter().flatten() {
+                UdpSocket::bind((ip, 0)).map_err(|e| {
+                    RustSocksError::Config(format!(
+                        "server.egress address {} is not local to this host: {}",
+                        ip, e
+                    ))
+                })?;
+            }
+        }
+        Ok(map)
+    }
+
+    /// Build the rules without checking that the addresses are local
+    pub fn parse(settings: &EgressSettings) -> Result<Self> {
+        let mut rules = Vec::with_capacity(settings.rules.len());
+        for (index, entry) in settings.rules.iter().enumerate() {
+            if entry.users.is_empty() && entry.groups.is_empty() {
+                return Err(RustSocksError::Config(format!(
+                    "server.egress.rules[{}] must name at least one user or group",
+                    index
+                )));
+            }
+            if entry.addresses.is_empty() {
+                return Err(RustSocksError::Config(format!(
+                    "server.egress.rules[{}] needs at least one address",
+                    index
+                )));
+            }
+            let mut addresses = EgressAddresses::default();
+            for address in &entry.addresses {
+                let ip: IpAddr = address.parse().map_err(|_| {
+                    RustSocksError::Config(format!(
+                        "Invalid server.egress.rules[{}] address: {}",
+                        index, address
+                    ))
+                })?;
+                if ip.is_unspecified() || ip.is_multicast() {
+                    return Err(RustSocksError::Config(format!(
+                        "server.egress.rules[{}] address {} cannot be a source address",
+                        index, ip
+                    )));
+                }
+                let duplicate = match ip {
+                    IpAddr::V4(v4) => addresses.v4.replace(v4).is_some(),
+                    IpAddr::V6(v6) => addresses.v6.replace(v6).is_some(),
+                };
+                if duplicate {
+                    return Err(RustSocksError::Config(format!(
+                        "server.egress.rules[{}] has more than one address of the same family",
+                        index
+                    )));
+                }
+            }
+            rules.push(EgressRule {
+                users: entry.users.clone(),
+                groups: entry.groups.clone(),
+                addresses,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Source addresses for `user`, a member of `groups`: the first matching rule, if any.
+    pub fn select(&self, user: &str, groups: &[String]) -> Option<EgressAddresses> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.users.iter().any(|name| name == user)
+                    || rule
+                        .groups
+                        .iter()
+                        .any(|group| groups.iter().any(|g| g.eq_ignore_ascii_case(group)))
+            })
+            .map(|rule| rule.addresses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EgressRuleSettings;
+
+    fn rule(users: &[&str], groups: &[&str], addresses: &[&str]) -> EgressRuleSettings {
+        EgressRuleSettings {
+            users: users.iter().map(|s| s.to_string()).collect(),
+            groups: groups.iter().map(|s| s.to_string()).collect(),
+            addresses: addresses.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn first_rule_for_user_or_group_wins() {
+        let map = EgressMap::parse(&EgressSettings {
+            rules: vec![
+                rule(&["alice"], &[], &["192.0.2.10"]),
+                rule(&[], &["Finance"], &["192.0.2.11", "2001:db8::11"]),
+                rule(&["bob"], &[], &["192.0.2.12"]),
+            ],
+        })
+        .unwrap();
+
+        let alice = map.select("alice", &["finance".to_string()]).unwrap();
+        assert_eq!(alice.v4, Some(Ipv4Addr::new(192, 0, 2, 10)));
+        assert_eq!(alice.for_peer("[2001:db8::1]:443".parse().unwrap()), None);
+
+        let bob = map.select("bob", &["finance".to_string()]).unwrap();
+        assert_eq!(bob.to_string(), "192.0.2.11, 2001:db8::11");
+        assert_eq!(
+            bob.for_peer("[2001:db8::1]:443".parse().unwrap()),
+            Some("2001:db8::11".parse().unwrap())
+        );
+
+        assert!(map.select("carol", &["sales".to_string()]).is_none());
+        assert!(EgressMap::default().select("alice", &[]).is_none());
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for entry in [
+            rule(&[], &[], &["192.0.2.10"]),
+            rule(&["alice"], &[], &[]),
+            rule(&["alice"], &[], &["egress.example.com"]),
+            rule(&["alice"], &[], &["0.0.0.0"]),
+            rule(&["alice"], &[], &["192.0.2.10", "192.0.2.11"]),
+        ] {
+            let settings = EgressSettings { rules: vec![entry] };
+            assert!(EgressMap::parse(&settings).is_err());
+        }
+    }
+
+    #[test]
+    fn addresses_must_be_local() {
+        let local = EgressSettings {
+            rules: vec![rule(&["alice"], &[], &["127.0.0.1"])],
+        };
+        assert!(EgressMap::from_settings(&local).is_ok());
+
+        // TEST-NET-1 is never assigned to a test host
+        let remote = EgressSettings {
+            rules: vec![rule(&["alice"], &[], &["192.0.2.10"])],
+        };
+        let err = EgressMap::from_settings(&remote).unwrap_err().to_string();
+        assert!(err.contains("not local"), "{err}");
+    }
+}
@@ -0,0 +1,532 @@
+//! Whole-process resource guardrails (`server.guardrails`).
+//!
+//! A monitor task samples the open file descriptor count and resident memory and
+//! feeds them to [`ResourceGuard::observe`]. At a soft watermark the accept loop
+//! rejects new connections through [`ResourceGuard::admit`], the same way load
+//! shedding does. At a hard watermark the monitor also gives descriptors back by
+//! closing idle pooled connections and UDP associations that have gone quiet.
+//!
+//! Descriptor watermarks always stay `fd_reserve` below RLIMIT_NOFILE, so the API
+//! listener and the session store can still open descriptors when SOCKS clients have
+//! used up everything they are allowed.
+
+use crate::config::{GuardrailSettings, ServerConfig};
+use crate::server::pool::ConnectionPool;
+use crate::session::{SessionManager, SessionProtocol, SessionStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Descriptors one relayed connection holds: the client socket and the upstream one.
+pub const FDS_PER_CONNECTION: u64 = 2;
+
+/// Default watermarks as a share of RLIMIT_NOFILE, in percent.
+const DEFAULT_FD_SOFT_PERCENT: u64 = 80;
+const DEFAULT_FD_HARD_PERCENT: u64 = 90;
+
+/// Marks an atomic that has not been sampled (or cannot be measured).
+const UNKNOWN: u64 = u64::MAX;
+
+/// How close the process is to its limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardLevel {
+    Normal,
+    /// New connections are rejected
+    Soft,
+    /// New connections are rejected and idle connections reclaimed
+    Hard,
+}
+
+impl GuardLevel {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => GuardLevel::Soft,
+            2 => GuardLevel::Hard,
+            _ => GuardLevel::Normal,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            GuardLevel::Normal => 0,
+            GuardLevel::Soft => 1,
+            GuardLevel::Hard => 2,
+        }
+    }
+}
+
+/// Usage and watermarks reported by the system resources endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceGuardStatus {
+    pub level: GuardLevel,
+    pub open_fds: Option<u64>,
+    /// Effective RLIMIT_NOFILE; `None` when unlimited or unknown
+    pub fd_limit: Option<u64>,
+    pub fd_soft_watermark: Option<u64>,
+    pub fd_hard_watermark: Option<u64>,
+    pub fd_reserve: u64,
+    pub resident_memory_bytes: Option<u64>,
+    pub memory_soft_watermark_bytes: Option<u64>,
+    pub memory_hard_watermark_bytes: Option<u64>,
+    pub rejected_connections: u64,
+    pub reclaimed_pool_connections: u64,
+    pub reclaimed_udp_associations: u64,
+}
+
+pub struct ResourceGuard {
+    fd_limit: Option<u64>,
+    fd_soft: Option<u64>,
+    fd_hard: Option<u64>,
+    fd_reserve: u64,
+    memory_soft: Option<u64>,
+    memory_hard: Option<u64>,
+    udp_idle: Duration,
+    level: AtomicU8,
+    open_fds: AtomicU64,
+    resident_memory: AtomicU64,
+    /// Connections admitted since the last descriptor sample
+    admitted_since_sample: AtomicU64,
+    rejected: AtomicU64,
+    reclaimed_pool: AtomicU64,
+    reclaimed_udp: AtomicU64,
+}
+
+impl ResourceGuard {
+    /// Guard against the process's current RLIMIT_NOFILE.
+    pub fn new(settings: &GuardrailSettings) -> Self {
+        Self::with_fd_limit(settings, fd_limit())
+    }
+
+    /// Guard against an explicit descriptor limit (`None` for unlimited or unknown).
+    pub fn with_fd_limit(settings: &GuardrailSettings, fd_limit: Option<u64>) -> Self {
+        let default_share = |percent: u64| fd_limit.map(|limit| limit * percent / 100);
+        let ceiling = fd_limit.map(|limit| limit.saturating_sub(settings.fd_reserve));
+        let cap = |watermark: Option<u64>| match (watermark, ceiling) {
+            (Some(watermark), Some(ceiling)) => Some(watermark.min(ceiling)),
+            (watermark, _) => watermark,
+        };
+
+        let fd_hard = cap(settings
+            .fd_hard_limit
+            .or_else(|| default_share(DEFAULT_FD_HARD_PERCENT)));
+        let fd_soft = cap(settings
+            .fd_soft_limit
+            .or_else(|| default_share(DEFAULT_FD_SOFT_PERCENT)))
+        .map(|soft| fd_hard.map_or(soft, |hard| soft.min(hard)));
+        let megabytes = |mb: Option<u64>| mb.map(|mb| mb * 1024 * 1024);
+
+        let guard = Self {
+            fd_limit,
+            fd_soft,
+            fd_hard,
+            fd_reserve: settings.fd_reserve,
+            memory_soft: megabytes(settings.memory_soft_limit_mb),
+            memory_hard: megabytes(settings.memory_hard_limit_mb),
+            udp_idle: Duration::from_secs(settings.udp_idle_secs),
+            level: AtomicU8::new(GuardLevel::Normal.as_u8()),
+            open_fds: AtomicU64::new(UNKNOWN),
+            resident_memory: AtomicU64::new(UNKNOWN),
+            admitted_since_sample: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            reclaimed_pool: AtomicU64::new(0),
+            reclaimed_udp: AtomicU64::new(0),
+        };
+
+        #[cfg(feature = "metrics")]
+        crate::session::SessionMetrics::set_resource_watermarks(guard.fd_soft, guard.fd_hard);
+
+        guard
+    }
+
+    /// Record a new sample and update the guard level.
+    pub fn observe(&self, open_fds: Option<u64>, resident_memory: Option<u64>) -> GuardLevel {
+        self.open_fds
+            .store(open_fds.unwrap_or(UNKNOWN), Ordering::Relaxed);
+        self.resident_memory
+            .store(resident_memory.unwrap_or(UNKNOWN), Ordering::Relaxed);
+        self.admitted_since_sample.store(0, Ordering::Relaxed);
+
+        let reached = |value: Option<u64>, watermark: Option<u64>| matches!((value, watermark), (Some(value), Some(watermark)) if value >= watermark);
+        let level = if reached(open_fds, self.fd_hard) || reached(resident_memory, self.memory_hard)
+        {
+            GuardLevel::Hard
+        } else if reached(open_fds, self.fd_soft) || reached(resident_memory, self.memory_soft) {
+            GuardLevel::Soft
+        } else {
+            GuardLevel::Normal
+        };
+
+        let previous = GuardLevel::from_u8(self.level.swap(level.as_u8(), Ordering::Relaxed));
+        if level > previous {
+            warn!(
+                level = ?level,
+                open_fds = ?open_fds,
+                fd_limit = ?self.fd_limit,
+                resident_memory_bytes = ?resident_memory,
+                "Resource watermark reached, rejecting new connections"
+            );
+        } else if level < previous {
+            info!(
+                level = ?level,
+                open_fds = ?open_fds,
+                resident_memory_bytes = ?resident_memory,
+                "Resource usage back below watermark"
+            );
+        }
+
+        #[cfg(feature = "metrics")]
+        crate::session::SessionMetrics::set_resource_usage(
+            level.as_u8(),
+            open_fds,
+            resident_memory,
+        );
+
+        level
+    }
+
+    /// Decide whether a freshly accepted connection may proceed.
+    ///
+    /// Between samples every admitted connection is assumed to hold
+    /// [`FDS_PER_CONNECTION`] more descriptors, so a connection storm is cut off at the
+    /// soft watermark without waiting for the next sample.
+    pub fn admit(&self) -> bool {
+        let admitted = if self.level() >= GuardLevel::Soft {
+            false
+        } else {
+            match (self.open_fds(), self.fd_soft) {
+                (Some(open), Some(soft)) => {
+                    let pending = self.admitted_since_sample.fetch_add(1, Ordering::Relaxed);
+                    open + (pending + 1) * FDS_PER_CONNECTION <= soft
+                }
+                _ => true,
+            }
+        };
+
+        if !admitted {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            crate::session::SessionMetrics::record_resource_rejection();
+        }
+        admitted
+    }
+
+    pub fn level(&self) -> GuardLevel {
+        GuardLevel::from_u8(self.level.load(Ordering::Relaxed))
+    }
+
+    /// UDP associations quiet for this long are reclaimed at the hard watermark.
+    pub fn udp_idle(&self) -> Duration {
+        self.udp_idle
+    }
+
+    pub fn status(&self) -> ResourceGuardStatus {
+        ResourceGuardStatus {
+            level: self.level(),
+            open_fds: self.open_fds(),
+            fd_limit: self.fd_limit,
+            fd_soft_watermark: self.fd_soft,
+            fd_hard_watermark: self.fd_hard,
+            fd_reserve: self.fd_reserve,
+            resident_memory_bytes: known(self.resident_memory.load(Ordering::Relaxed)),
+            memory_soft_watermark_bytes: self.memory_soft,
+            memory_hard_watermark_bytes: self.memory_hard,
+            rejected_connections: self.rejected.load(Ordering::Relaxed),
+            reclaimed_pool_connections: self.reclaimed_pool.load(Ordering::Relaxed),
+            reclaimed_udp_associations: self.reclaimed_udp.load(Ordering::Relaxed),
+        }
+    }
+
+    fn open_fds(&self) -> Option<u64> {
+        known(self.open_fds.load(Ordering::Relaxed))
+    }
+
+    fn record_reclaimed(&self, pool: usize, udp: usize) {
+        self.reclaimed_pool
+            .fetch_add(pool as u64, Ordering::Relaxed);
+        self.reclaimed_udp.fetch_add(udp as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            crate::session::SessionMetrics::record_resource_reclaimed("pool", pool as u64);
+            crate::session::SessionMetrics::record_resource_reclaimed("udp", udp as u64);
+        }
+    }
+}
+
+fn known(value: u64) -> Option<u64> {
+    (value != UNKNOWN).then_some(value)
+}
+
+/// Sample usage every `interval`, feed it to the guard and reclaim idle connections
+/// while the hard watermark is reached.
+pub fn spawn_resource_monitor(
+    guard: Arc<ResourceGuard>,
+    connection_pool: Arc<ConnectionPool>,
+    session_manager: Arc<SessionManager>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut udp_activity = UdpActivity::default();
+
+        loop {
+            ticker.tick().await;
+            let level = guard.observe(open_fd_count(), resident_memory_bytes());
+
+            // Idle time is only tracked under pressure, so it counts from when the
+            // soft watermark was reached at the earliest
+            if level == GuardLevel::Normal {
+                udp_activity.clear();
+                continue;
+            }
+            let idle_udp = udp_activity
+                .refresh(&session_manager, guard.udp_idle())
+                .await;
+
+            if level == GuardLevel::Hard {
+                let pool = connection_pool.purge_idle();
+                for session_id in &idle_udp {
+                    session_manager
+                        .terminate_session(
+                            session_id,
+                            "Reclaimed: process at its resource hard watermark",
+                            SessionStatus::Closed,
+                        )
+                        .await;
+                }
+                if pool > 0 || !idle_udp.is_empty() {
+                    warn!(
+                        pool_connections = pool,
+                        udp_associations = idle_udp.len(),
+                        "Reclaimed idle connections at the resource hard watermark"
+                    );
+                }
+                guard.record_reclaimed(pool, idle_udp.len());
+            }
+        }
+    })
+}
+
+/// Packet totals of active UDP associations and when they last changed.
+#[derive(Default)]
+struct UdpActivity {
+    seen: HashMap<Uuid, (u64, Instant)>,
+}
+
+impl UdpActivity {
+    /// Update from the active sessions; returns the associations idle for `idle`.
+    async fn refresh(&mut self, session_manager: &SessionManager, idle: Duration) -> Vec<Uuid> {
+        let now = Instant::now();
+        let mut current = HashMap::new();
+        for session in session_manager.get_active_sessions().await {
+            if session.protocol != SessionProtocol::Udp {
+                continue;
+            }
+            let packets = session.packets_sent + session.packets_received;
+            let changed = match self.seen.get(&session.session_id) {
+                Some((seen, since)) if *seen == packets => *since,
+                _ => now,
+            };
+            current.insert(session.session_id, (packets, changed));
+        }
+        self.seen = current;
+
+        self.seen
+            .iter()
+            .filter(|(_, (_, since))| now.duration_since(*since) >= idle)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    fn clear(&mut self) {
+        self.seen.clear();
+    }
+}
+
+/// Effective RLIMIT_NOFILE; `None` when unlimited or unknown.
+pub fn fd_limit() -> Option<u64> {
+    #[cfg(unix)]
+    {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: getrlimit only writes to the struct it is given
+        let rc = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+        if rc == 0 && limit.rlim_cur != libc::RLIM_INFINITY {
+            // rlim_t is signed on some unixes
+            #[allow(clippy::unnecessary_cast)]
+            return Some(limit.rlim_cur as u64);
+        }
+        None
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Descriptors the process has open; `None` where they cannot be listed.
+///
+/// Reads `/proc/self/fd` on Linux and falls back to `/dev/fd` (macOS, the BSDs).
+pub fn open_fd_count() -> Option<u64> {
+    ["/proc/self/fd", "/dev/fd"].iter().find_map(|dir| {
+        let entries = std::fs::read_dir(dir).ok()?;
+        // The directory handle being read is one of the entries
+        Some((entries.count() as u64).saturating_sub(1))
+    })
+}
+
+/// Resident memory of the process in bytes.
+pub fn resident_memory_bytes() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    if let Ok(statm) = std::fs::read_to_string("/proc/self/statm") {
+        // SAFETY: sysconf has no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        let resident_pages = statm
+            .split_whitespace()
+            .nth(1)
+            .and_then(|pages| pages.parse::<u64>().ok());
+        if let Some(pages) = resident_pages.filter(|_| page_size > 0) {
+            return Some(pages * page_size as u64);
+        }
+    }
+
+    let pid = sysinfo::get_current_pid().ok()?;
+    let mut system = sysinfo::System::new();
+    system.refresh_processes_specifics(
+        sysinfo::ProcessesToUpdate::Some(&[pid]),
+        true,
+        sysinfo::ProcessRefreshKind::nothing().with_memory(),
+    );
+    system.process(pid).map(|process| process.memory())
+}
+
+/// Log the effective descriptor limit and warn when the configured connection and pool
+/// sizes cannot fit in it.
+pub fn log_fd_budget(server: &ServerConfig) {
+    let Some(limit) = fd_limit() else {
+        info!("RLIMIT_NOFILE: unlimited or unknown");
+        return;
+    };
+
+    let pool = if server.pool.enabled {
+        server.pool.max_total_idle as u64
+    } else {
+        0
+    };
+    let reserve = if server.guardrails.enabled {
+        server.guardrails.fd_reserve
+    } else {
+        0
+    };
+    let needed = server.max_connections as u64 * FDS_PER_CONNECTION + pool + reserve;
+
+    info!(
+        fd_limit = limit,
+        fds_needed = needed,
+        "Effective RLIMIT_NOFILE"
+    );
+    if needed > limit {
+        warn!(
+            "max_connections ({}) x {} descriptors + {} pooled + {} reserved = {} exceeds \
+             RLIMIT_NOFILE ({}); raise 'ulimit -n' or lower server.max_connections",
+            server.max_connections, FDS_PER_CONNECTION, pool, reserve, needed, limit
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> GuardrailSettings {
+        GuardrailSettings {
+            enabled: true,
+            ..GuardrailSettings::default()
+        }
+    }
+
+    #[test]
+    fn watermarks_default_to_a_share_of_the_limit() {
+        let guard = ResourceGuard::with_fd_limit(&settings(), Some(10_000));
+        let status = guard.status();
+        assert_eq!(status.fd_soft_watermark, Some(8_000));
+        assert_eq!(status.fd_hard_watermark, Some(9_000));
+
+        // Configured watermarks never eat into the reserve
+        let guard = ResourceGuard::with_fd_limit(
+            &GuardrailSettings {
+                fd_soft_limit: Some(1_000),
+                fd_hard_limit: Some(1_020),
+                fd_reserve: 64,
+                ..settings()
+            },
+            Some(1_024),
+        );
+        let status = guard.status();
+        assert_eq!(status.fd_soft_watermark, Some(960));
+        assert_eq!(status.fd_hard_watermark, Some(960));
+
+        let guard = ResourceGuard::with_fd_limit(&settings(), None);
+        assert_eq!(guard.status().fd_soft_watermark, None);
+        assert!(guard.admit());
+    }
+
+    #[test]
+    fn levels_follow_the_watermarks() {
+        let guard = ResourceGuard::with_fd_limit(
+            &GuardrailSettings {
+                fd_soft_limit: Some(100),
+                fd_hard_limit: Some(200),
+                memory_soft_limit_mb: Some(1),
+                ..settings()
+            },
+            Some(10_000),
+        );
+
+        assert_eq!(guard.observe(Some(50), Some(1024)), GuardLevel::Normal);
+        assert!(guard.admit());
+        assert_eq!(guard.observe(Some(100), Some(1024)), GuardLevel::Soft);
+        assert!(!guard.admit());
+        assert_eq!(guard.observe(Some(250), None), GuardLevel::Hard);
+        assert_eq!(guard.observe(Some(50), Some(2 << 20)), GuardLevel::Soft);
+        assert_eq!(guard.observe(Some(50), None), GuardLevel::Normal);
+        assert!(guard.admit());
+        assert_eq!(guard.status().rejected_connections, 1);
+    }
+
+    #[test]
+    fn admissions_between_samples_count_against_the_soft_watermark() {
+        let guard = ResourceGuard::with_fd_limit(
+            &GuardrailSettings {
+                fd_soft_limit: Some(100),
+                ..settings()
+            },
+            Some(10_000),
+        );
+
+        guard.observe(Some(90), None);
+        let admitted = (0..10).filter(|_| guard.admit()).count();
+        assert_eq!(admitted as u64, 10 / FDS_PER_CONNECTION);
+
+        // A fresh sample starts the estimate over
+        guard.observe(Some(90), None);
+        assert!(guard.admit());
+    }
+
+    #[test]
+    fn process_usage_is_measurable() {
+        if cfg!(target_os = "linux") {
+            // stdin, stdout and stderr at least
+            assert!(open_fd_count().unwrap() >= 3);
+            assert!(resident_memory_bytes().unwrap() > 0);
+        }
+        assert!(fd_limit().unwrap_or(u64::MAX) > 0);
+    }
+}
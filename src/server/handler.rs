@@ -1,77 +1,252 @@
-use crate::acl::{AclDecision, AclEngine, AclStats, Protocol};
-use crate::auth::AuthManager;
+use crate::acl::{
+    AclDecision, AclEngine, AclMatchedOn, AclMode, AclStats, AclVerdict, Protocol, ResolveMode,
+};
+use crate::auth::{AuthManager, Identity};
 use crate::protocol::*;
-use crate::qos::{ConnectionLimits, QosEngine};
+use crate::qos::{QosEngine, SharedConnectionLimits};
 use crate::server::bind::handle_bind as handle_bind_relay;
+use crate::server::egress::{EgressAddresses, EgressMap};
+use crate::server::handshake::{Handshake, HandshakeLimits, HandshakePhase};
+use crate::server::host_hints::HostHints;
+use crate::server::keepalive::{ActivityStream, KeepaliveMode, TunnelKeepalive, TunnelProbe};
 use crate::server::pool::{ConnectionPool, ReuseHint};
-use crate::server::proxy::{proxy_data, TrafficUpdateConfig};
-use crate::server::resolver::resolve_address;
-use crate::server::udp::handle_udp_associate as handle_udp_relay;
-use crate::session::{ConnectionInfo, SessionManager, SessionProtocol, SessionStatus};
+use crate::server::probes::{ProbeAttempt, ProbeError};
+use crate::server::proxy::{proxy_data, proxy_stream, TrafficUpdateConfig};
+use crate::server::resolver::{literal_target, AddressSelection, DestinationResolver};
+use crate::server::retry::ConnectRetry;
+use crate::server::sni::{peek_sni, SniFailMode, SniParse, SniRouting};
+use crate::server::socket_options::{SocketOptionPlan, UpstreamSocketOptions};
+use crate::server::special_names::{SpecialNameCategory, SpecialNameDecision, SpecialNamesPolicy};
+use crate::server::udp::{
+    handle_udp_associate as handle_udp_relay, ClientEndpoint, UdpDatagramLimits, UdpDestinations,
+    UdpShaping,
+};
+use crate::server::upstream_proxy::UpstreamProxy;
+use crate::server::upstream_tls::{self, UpstreamStream, UpstreamTls};
+use crate::session::{
+    AclAccess, AdmissionRejection, ConnectionInfo, FailureCategory, FailureRecord, HostSource,
+    SessionManager, SessionProtocol, SessionResolution, SessionStatus, UdpAssociationMode,
+};
 use crate::utils::error::{Result, RustSocksError};
-use std::net::IpAddr;
+use crate::utils::log_sampling;
+use smallvec::{smallvec, SmallVec};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, BufReader};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
 use tokio::sync::broadcast;
 use tracing::{debug, info, instrument, warn};
 
-/// Optimize TCP socket for low-latency proxying
-/// - Disables Nagle's algorithm (TCP_NODELAY) for lower latency
-/// - Increases send/recv buffers for better throughput
-fn optimize_tcp_socket(stream: &TcpStream) -> Result<()> {
-    // Disable Nagle's algorithm - improves latency for small packets
-    // This is the single most impactful TCP optimization for proxy workloads
-    stream.set_nodelay(true)?;
-
-    // Increase TCP buffer sizes for better throughput (especially on high-latency links)
-    // These are hints; OS may adjust based on available memory
-    let sock_ref = socket2::SockRef::from(stream);
-    // 256 KB buffers provide good balance between memory usage and throughput
-    let _ = sock_ref.set_recv_buffer_size(262144); // 256 KB
-    let _ = sock_ref.set_send_buffer_size(262144); // 256 KB
-
-    Ok(())
-}
-
 /// Context for handling client connections
 pub struct ClientHandlerContext {
     pub auth_manager: Arc<AuthManager>,
     pub acl_engine: Option<Arc<AclEngine>>,
     pub acl_stats: Arc<AclStats>,
-    pub anonymous_user: Arc<String>,
+    pub anonymous_user: Arc<str>,
     pub session_manager: Arc<SessionManager>,
     pub traffic_config: TrafficUpdateConfig,
     pub qos_engine: QosEngine,
-    pub connection_limits: ConnectionLimits,
+    /// Replaced in place by a config reload
+    pub connection_limits: SharedConnectionLimits,
     pub connection_pool: Arc<ConnectionPool>,
+    pub special_names: SpecialNamesPolicy,
+    pub sni_routing: SniRouting,
+    pub resolver: Arc<dyn DestinationResolver>,
+    /// Recent per-client resolutions used to name CONNECT-by-IP sessions
+    pub host_hints: Option<Arc<HostHints>>,
+    /// Keepalive applied to upstream sockets (`server.tcp_keepalive`, `server.tunnel_keepalive`)
+    pub tunnel_keepalive: Arc<TunnelKeepalive>,
+    /// Per-destination options set on upstream sockets (`server.upstream_socket_options`)
+    pub upstream_socket_options: Arc<UpstreamSocketOptions>,
+    /// Source addresses of upstream traffic by user or group (`server.egress`)
+    pub egress: Arc<EgressMap>,
+    /// Parent SOCKS5 proxy for matching CONNECT destinations (`server.upstream`)
+    pub upstream_proxy: Option<Arc<UpstreamProxy>>,
+    /// TLS originated to matching CONNECT destinations (`server.upstream_tls`)
+    pub upstream_tls: Option<Arc<UpstreamTls>>,
+    /// Which source UDP associations accept client datagrams from (`server.udp_association_mode`)
+    pub udp_association: UdpAssociationMode,
+    /// Datagram size limit and fragment reassembly of UDP associations (`server.udp`)
+    pub udp_datagrams: UdpDatagramLimits,
+    /// How long a BIND waits for its inbound connection (`server.bind_accept_timeout_secs`)
+    pub bind_accept_timeout: std::time::Duration,
+    /// Whether clients opening with version byte 4 are served (`server.enable_socks4`)
+    pub enable_socks4: bool,
+    /// Order and family of upstream addresses tried (`server.dns.strategy`)
+    pub address_selection: AddressSelection,
+    /// Retries of refused or timed-out upstream connects (`server.connect_retries`)
+    pub connect_retry: ConnectRetry,
+    /// Deadline and concurrency limit of handshakes (`server.handshake_timeout_secs`,
+    /// `server.max_concurrent_handshakes`)
+    pub handshake: HandshakeLimits,
+    /// Whether requests may name their destination by domain
+    /// (`server.dns.allow_domain_requests`)
+    pub allow_domain_requests: bool,
 }
 
 pub trait IoStream: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
 impl<T> IoStream for T where T: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
 
+/// Handle a newly accepted client.
+///
+/// Fails at once when `server.max_concurrent_handshakes` handshakes are in progress.
+pub async fn handle_client<S>(
+    client_stream: S,
+    ctx: Arc<ClientHandlerContext>,
+    client_addr: std::net::SocketAddr,
+) -> Result<()>
+where
+    S: IoStream,
+{
+    let handshake = begin_handshake(&ctx)?;
+    serve_accepted_client(client_stream, ctx, client_addr, handshake).await
+}
+
+/// Handle a client that completed a TLS handshake.
+///
+/// With `auth.client_method = "tls.cert"` the verified client certificate names the
+/// session user; otherwise this is [`handle_client`].
+pub async fn handle_tls_client<S>(
+    client_stream: tokio_rustls::server::TlsStream<S>,
+    ctx: Arc<ClientHandlerContext>,
+    client_addr: std::net::SocketAddr,
+) -> Result<()>
+where
+    S: IoStream,
+{
+    let handshake = begin_handshake(&ctx)?;
+    serve_accepted_tls_client(client_stream, ctx, client_addr, handshake).await
+}
+
+fn begin_handshake(ctx: &ClientHandlerContext) -> Result<Handshake> {
+    ctx.handshake.begin().ok_or_else(|| {
+        #[cfg(feature = "metrics")]
+        crate::session::SessionMetrics::record_handshake_rejected();
+        RustSocksError::Protocol("Too many handshakes in progress".to_string())
+    })
+}
+
+/// [`handle_client`] for a connection whose handshake the accept loop already started
 #[instrument(
     level = "debug",
-    skip(client_stream, ctx),
+    skip(client_stream, ctx, handshake),
     fields(client = %client_addr)
 )]
-pub async fn handle_client<S>(
-    mut client_stream: S,
+pub(crate) async fn serve_accepted_client<S>(
+    client_stream: S,
     ctx: Arc<ClientHandlerContext>,
     client_addr: std::net::SocketAddr,
+    handshake: Handshake,
 ) -> Result<()>
 where
     S: IoStream,
 {
-    ctx.auth_manager
-        .authenticate_client(client_addr.ip())
+    handshake
+        .phase(
+            HandshakePhase::Auth,
+            ctx.auth_manager.authenticate_client(client_addr.ip()),
+        )
         .await?;
 
-    let version = client_stream.read_u8().await?;
+    serve_client(client_stream, ctx, client_addr, None, handshake).await
+}
+
+/// [`handle_tls_client`] for a connection whose handshake the accept loop already started
+#[instrument(
+    level = "debug",
+    skip(client_stream, ctx, handshake),
+    fields(client = %client_addr)
+)]
+pub(crate) async fn serve_accepted_tls_client<S>(
+    client_stream: tokio_rustls::server::TlsStream<S>,
+    ctx: Arc<ClientHandlerContext>,
+    client_addr: std::net::SocketAddr,
+    handshake: Handshake,
+) -> Result<()>
+where
+    S: IoStream,
+{
+    if !ctx.auth_manager.uses_client_certificates() {
+        return serve_accepted_client(client_stream, ctx, client_addr, handshake).await;
+    }
+
+    let certificate = client_stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|chain| chain.first());
+    let identity = match ctx
+        .auth_manager
+        .authenticate_certificate(certificate.map(|der| der.as_ref()))
+    {
+        Ok(identity) => identity,
+        Err(e) => {
+            warn!(client = %client_addr, error = %e, "TLS client certificate rejected");
+            return Err(e);
+        }
+    };
+
+    serve_client(client_stream, ctx, client_addr, Some(identity), handshake).await
+}
+
+/// SOCKS negotiation after client-level authentication. `client_identity` is the
+/// user established before SOCKS (a TLS client certificate), if any.
+async fn serve_client<S>(
+    mut client_stream: S,
+    ctx: Arc<ClientHandlerContext>,
+    client_addr: std::net::SocketAddr,
+    client_identity: Option<Identity>,
+    handshake: Handshake,
+) -> Result<()>
+where
+    S: IoStream,
+{
+    let version = handshake
+        .phase(HandshakePhase::Negotiation, client_stream.read_u8())
+        .await?;
 
     match version {
-        SOCKS_VERSION => handle_socks5(client_stream, ctx, client_addr, version).await,
-        SOCKS4_VERSION => handle_socks4(client_stream, ctx, client_addr).await,
+        SOCKS_VERSION => {
+            handle_socks5(
+                client_stream,
+                ctx,
+                client_addr,
+                version,
+                client_identity,
+                handshake,
+            )
+            .await
+        }
+        SOCKS4_VERSION if ctx.enable_socks4 => {
+            handle_socks4(client_stream, ctx, client_addr, client_identity, handshake).await
+        }
+        SOCKS4_VERSION => {
+            // Read the request first so the client sees the reply rather than a reset
+            let request = handshake
+                .phase(
+                    HandshakePhase::Request,
+                    parse_socks4_request(&mut client_stream),
+                )
+                .await?;
+            warn!(
+                client = %client_addr,
+                dest = %request.address,
+                port = request.port,
+                "SOCKS4 request rejected: server.enable_socks4 is off"
+            );
+            send_socks_response(
+                &mut client_stream,
+                SocksProtocol::V4,
+                ReplyCode::CommandNotSupported,
+                Address::IPv4([0, 0, 0, 0]),
+                0,
+            )
+            .await?;
+            Err(RustSocksError::Protocol(
+                "SOCKS4 is disabled (server.enable_socks4)".to_string(),
+            ))
+        }
         _ => Err(RustSocksError::Protocol(format!(
             "Unsupported SOCKS version: 0x{:02x}",
             version
@@ -79,6 +254,62 @@ where
     }
 }
 
+/// Reason recorded for requests refused by `server.dns.allow_domain_requests = false`
+const DOMAIN_REQUESTS_DISABLED: &str = "domain-requests-disabled";
+
+/// With `server.dns.allow_domain_requests = false`, whether the request names its
+/// destination by domain and must be refused. IP literals sent as names need no lookup
+/// and pass.
+fn domain_request_refused(
+    ctx: &ClientHandlerContext,
+    user: &str,
+    address: &Address,
+    port: u16,
+    command: Command,
+) -> bool {
+    if ctx.allow_domain_requests || address.literal_ip().is_some() {
+        return false;
+    }
+    warn!(
+        user = %user,
+        dest = %address,
+        port,
+        command = command.as_str(),
+        "Domain request refused (server.dns.allow_domain_requests = false)"
+    );
+
+    #[cfg(feature = "metrics")]
+    crate::session::SessionMetrics::record_domain_request_rejected(command.as_str());
+
+    true
+}
+
+/// Check the destination against the special-use name policy before anything is resolved.
+fn special_name_block(
+    ctx: &ClientHandlerContext,
+    user: &str,
+    address: &Address,
+    port: u16,
+) -> Option<SpecialNameCategory> {
+    match ctx.special_names.check(address) {
+        SpecialNameDecision::Allow => None,
+        SpecialNameDecision::Block(category) => {
+            warn!(
+                user = %user,
+                dest = %address,
+                port,
+                category = category.as_str(),
+                "Special-use destination name blocked"
+            );
+
+            #[cfg(feature = "metrics")]
+            crate::session::SessionMetrics::record_special_name_block(category.as_str());
+
+            Some(category)
+        }
+    }
+}
+
 async fn send_socks_response<S>(
     stream: &mut S,
     protocol: SocksProtocol,
@@ -115,14 +346,16 @@ where
 
 #[instrument(
     level = "debug",
-    skip(client_stream, ctx),
-    fields(client = %client_addr, version)
+    skip(client_stream, ctx, handshake),
+    fields(client = %client_addr, version, correlation_id = tracing::field::Empty)
 )]
 async fn handle_socks5<S>(
     client_stream: S,
     ctx: Arc<ClientHandlerContext>,
     client_addr: std::net::SocketAddr,
     version: u8,
+    client_identity: Option<Identity>,
+    handshake: Handshake,
 ) -> Result<()>
 where
     S: IoStream,
@@ -133,88 +366,148 @@ where
     let mut buffered_stream = BufReader::with_capacity(4096, client_stream);
 
     // Step 1: Method selection
-    let greeting = parse_socks5_client_greeting(&mut buffered_stream, version).await?;
+    let greeting = handshake
+        .phase(
+            HandshakePhase::Negotiation,
+            parse_socks5_client_greeting(&mut buffered_stream, version),
+        )
+        .await?;
 
     debug!("Client offered methods: {:?}", greeting.methods);
 
-    // Select auth method
-    let server_method = if greeting.methods.contains(&ctx.auth_manager.get_method()) {
-        ctx.auth_manager.get_method()
-    } else if greeting.methods.contains(&AuthMethod::NoAuth)
-        && ctx.auth_manager.supports(AuthMethod::NoAuth)
-    {
-        AuthMethod::NoAuth
-    } else {
+    // Select auth method: the most preferred server method the client offered
+    let server_method = ctx.auth_manager.select_method(&greeting.methods);
+    if server_method == AuthMethod::NoAcceptable {
         // Use get_mut() to access underlying stream for write operations
-        send_server_choice(buffered_stream.get_mut(), AuthMethod::NoAcceptable).await?;
+        handshake
+            .phase(
+                HandshakePhase::Negotiation,
+                send_server_choice(buffered_stream.get_mut(), AuthMethod::NoAcceptable),
+            )
+            .await?;
+        ctx.session_manager
+            .failures()
+            .record(FailureRecord::auth_failure(client_addr.ip(), None));
         return Err(RustSocksError::AuthFailed(
             "No acceptable auth method".to_string(),
         ));
-    };
-
-    send_server_choice(buffered_stream.get_mut(), server_method).await?;
+    }
 
-    // Step 2: Authentication (reads buffered, writes through get_mut())
-    let auth_result = ctx
-        .auth_manager
-        .authenticate(&mut buffered_stream, server_method, client_addr.ip())
+    handshake
+        .phase(
+            HandshakePhase::Negotiation,
+            send_server_choice(buffered_stream.get_mut(), server_method),
+        )
         .await?;
 
-    // Extract username and groups from authentication result
-    let (user, user_groups) = match auth_result {
-        Some((username, groups)) => {
+    // Step 2: Authentication (reads buffered, writes through get_mut())
+    let auth_result = match handshake
+        .phase(
+            HandshakePhase::Auth,
+            ctx.auth_manager
+                .authenticate(&mut buffered_stream, server_method, client_addr.ip()),
+        )
+        .await
+    {
+        Err(e @ RustSocksError::HandshakeTimeout(_)) if server_method == AuthMethod::UserPass => {
+            // A client stalled mid sub-negotiation still gets the RFC 1929 failure status
+            let _ = tokio::time::timeout(
+                AUTH_FAILURE_WRITE_TIMEOUT,
+                send_auth_response(buffered_stream.get_mut(), false),
+            )
+            .await;
+            return Err(e);
+        }
+        Err(e @ RustSocksError::AuthFailed(_)) => {
+            ctx.session_manager
+                .failures()
+                .record(FailureRecord::auth_failure(client_addr.ip(), None));
+            return Err(e);
+        }
+        result => result?,
+    }
+    // A SOCKS-level login takes precedence over the client certificate
+    .or(client_identity);
+
+    // One shared allocation for the username; session, QoS and ACL all borrow or clone the Arc.
+    // `acl_user` is the effective identity; the authenticated principal only differs from it
+    // when a service account acts on behalf of another user.
+    let (acl_user, authenticated_user, user_groups, correlation_id) = match auth_result {
+        Some(identity) => {
             info!(
-                user = %username,
-                group_count = groups.len(),
+                user = %identity.user,
+                authenticated_user = %identity.authenticated_user,
+                group_count = identity.groups.len(),
                 "User authenticated with groups from LDAP"
             );
-            (Some(username), groups)
+            let impersonated = identity.is_impersonated();
+            let user: Arc<str> = Arc::from(identity.user);
+            let principal = if impersonated {
+                Arc::from(identity.authenticated_user)
+            } else {
+                Arc::clone(&user)
+            };
+            let correlation_id: Option<Arc<str>> = identity.correlation_id.map(Arc::from);
+            (user, Some(principal), identity.groups, correlation_id)
         }
         None => {
             debug!("No authentication (anonymous user)");
-            (None, Vec::new())
+            (Arc::clone(&ctx.anonymous_user), None, Vec::new(), None)
         }
     };
+    let session_groups = group_names(&user_groups);
+    if let Some(id) = correlation_id.as_deref() {
+        tracing::Span::current().record("correlation_id", id);
+    }
 
-    let acl_user: Arc<str> = user
-        .map(|username| Arc::from(username.into_boxed_str()))
-        .unwrap_or_else(|| Arc::from(ctx.anonymous_user.as_str()));
-
-    // Step 2b: Check connection limits (QoS)
-    if let Err(e) = ctx
+    // Step 2b: Check connection limits (QoS); the slot is released on every exit path
+    let _connection_slot = match ctx
         .qos_engine
-        .check_and_inc_connection_arc(&acl_user, &ctx.connection_limits)
+        .acquire_connection(&acl_user, &ctx.connection_limits.get())
     {
-        warn!(
-            user = %acl_user.as_ref(),
-            error = %e,
-            "Connection limit exceeded"
-        );
-        send_socks_response(
-            buffered_stream.get_mut(),
-            SocksProtocol::V5,
-            ReplyCode::ConnectionNotAllowed,
-            Address::IPv4([0, 0, 0, 0]),
-            0,
-        )
-        .await?;
-        return Err(e);
-    }
-
-    // Ensure connection is decremented on drop
-    let _connection_guard = ConnectionGuard {
-        qos_engine: ctx.qos_engine.clone(),
-        user: Arc::clone(&acl_user),
+        Ok(slot) => slot,
+        Err(e) => {
+            warn!(
+                user = %acl_user.as_ref(),
+                error = %e,
+                "Connection limit exceeded"
+            );
+            if let RustSocksError::ConnectionLimit(exceeded) = &e {
+                // Limits are checked before the request is read, so no destination yet
+                let mut rejection = AdmissionRejection::new(exceeded, client_addr, None);
+                rejection.correlation_id = correlation_id.as_deref().map(str::to_string);
+                ctx.session_manager
+                    .record_admission_rejection(rejection)
+                    .await;
+            }
+            send_socks_response(
+                buffered_stream.get_mut(),
+                SocksProtocol::V5,
+                ReplyCode::ConnectionNotAllowed,
+                Address::IPv4([0, 0, 0, 0]),
+                0,
+            )
+            .await?;
+            return Err(e);
+        }
     };
 
     // Step 3: SOCKS5 request (buffered read for final handshake message)
-    let request = parse_socks5_request(&mut buffered_stream).await?;
+    let request = handshake
+        .phase(
+            HandshakePhase::Request,
+            parse_socks5_request(&mut buffered_stream),
+        )
+        .await?;
 
-    let dest_string = request.address.to_string();
     info!(
         user = %acl_user.as_ref(),
-        "SOCKS5 request: command={:?}, dest={}:{}",
-        request.command, dest_string, request.port
+        authenticated_user = authenticated_user.as_deref().unwrap_or("-"),
+        correlation_id = correlation_id.as_deref().unwrap_or("-"),
+        command = ?request.command,
+        dest = %request.address,
+        port = request.port,
+        "SOCKS5 request"
     );
 
     let session_protocol = match request.command {
@@ -222,55 +515,211 @@ where
         _ => SessionProtocol::Tcp,
     };
 
+    // Step 3a: Names are refused outright when clients must resolve them themselves
+    if domain_request_refused(
+        &ctx,
+        acl_user.as_ref(),
+        &request.address,
+        request.port,
+        request.command,
+    ) {
+        ctx.session_manager.access_log().record_rejected_request(
+            &AclAccess {
+                user: acl_user.as_ref(),
+                authenticated_user: authenticated_user.as_deref(),
+                correlation_id: correlation_id.as_deref(),
+                client_addr,
+                destination: &request.address,
+                port: request.port,
+                protocol: if session_protocol == SessionProtocol::Udp {
+                    "udp"
+                } else {
+                    "tcp"
+                },
+                socks_version: 5,
+                command: request.command.as_str(),
+                auth_method: server_method.as_str(),
+                group_count: user_groups.len(),
+            },
+            DOMAIN_REQUESTS_DISABLED,
+        );
+        let conn_info = ConnectionInfo {
+            source_ip: client_addr.ip(),
+            source_port: client_addr.port(),
+            dest_ip: request.address.to_arc_str(),
+            dest_port: request.port,
+            protocol: session_protocol,
+            authenticated_user: authenticated_user.clone(),
+            correlation_id: correlation_id.clone(),
+            socks_version: SOCKS_VERSION,
+            chained: false,
+            groups: session_groups.clone(),
+        };
+        ctx.session_manager
+            .track_rejected_session(
+                acl_user.as_ref(),
+                conn_info,
+                Some(DOMAIN_REQUESTS_DISABLED.to_string()),
+                ReplyCode::AddressTypeNotSupported as u8,
+            )
+            .await;
+
+        send_socks_response(
+            buffered_stream.get_mut(),
+            SocksProtocol::V5,
+            ReplyCode::AddressTypeNotSupported,
+            Address::IPv4([0, 0, 0, 0]),
+            0,
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    // Step 3b: Special-use names (localhost, .local, .onion, ...) never reach the resolver.
+    // UDP ASSOCIATE carries the client's own address here; the relay checks each datagram.
+    if request.command != Command::UdpAssociate {
+        if let Some(category) =
+            special_name_block(&ctx, acl_user.as_ref(), &request.address, request.port)
+        {
+            let conn_info = ConnectionInfo {
+                source_ip: client_addr.ip(),
+                source_port: client_addr.port(),
+                dest_ip: request.address.to_arc_str(),
+                dest_port: request.port,
+                protocol: session_protocol,
+                authenticated_user: authenticated_user.clone(),
+                correlation_id: correlation_id.clone(),
+                socks_version: SOCKS_VERSION,
+                chained: false,
+                groups: session_groups.clone(),
+            };
+            ctx.session_manager
+                .track_rejected_session(
+                    acl_user.as_ref(),
+                    conn_info,
+                    Some(format!("special-name:{}", category)),
+                    category.reply_code() as u8,
+                )
+                .await;
+
+            send_socks_response(
+                buffered_stream.get_mut(),
+                SocksProtocol::V5,
+                category.reply_code(),
+                Address::IPv4([0, 0, 0, 0]),
+                0,
+            )
+            .await?;
+
+            return Ok(());
+        }
+    }
+
     let mut acl_rule_match: Option<String> = None;
-    let mut acl_decision = "allow".to_string();
+    let mut acl_matched_on: Option<AclMatchedOn> = None;
+    let mut acl_resolve = ResolveMode::Local;
+    let mut resolved_stage: Option<ResolvedAclStage> = None;
+    // Blocked, but relayed because the ACL runs in monitor mode
+    let mut would_block = false;
 
-    // Step 3b: ACL enforcement (if enabled)
+    // Step 3c: ACL enforcement (if enabled)
     if let Some(engine) = ctx.acl_engine.as_ref() {
         let protocol = match request.command {
             Command::UdpAssociate => Protocol::Udp,
             _ => Protocol::Tcp,
         };
 
-        // Use evaluate_with_groups() for dynamic LDAP group matching
-        let (decision, matched_rule) = engine
-            .evaluate_with_groups(
+        // Use verdict_with_groups() for dynamic LDAP group matching
+        let verdict = engine
+            .verdict_with_groups(
                 acl_user.as_ref(),
                 &user_groups,
                 &request.address,
                 request.port,
                 &protocol,
+                Some(client_addr.ip()),
             )
             .await;
+        ctx.session_manager.access_log().record_acl(
+            &verdict,
+            &AclAccess {
+                user: acl_user.as_ref(),
+                authenticated_user: authenticated_user.as_deref(),
+                correlation_id: correlation_id.as_deref(),
+                client_addr,
+                destination: &request.address,
+                port: request.port,
+                protocol: if protocol == Protocol::Udp {
+                    "udp"
+                } else {
+                    "tcp"
+                },
+                socks_version: 5,
+                command: request.command.as_str(),
+                auth_method: server_method.as_str(),
+                group_count: user_groups.len(),
+            },
+        );
+        let block_reply = verdict.block_reply();
+        let matched_rule = verdict.matched_rule;
+        acl_matched_on = Some(verdict.matched_on);
 
-        match decision {
+        match verdict.decision {
+            AclDecision::Block if engine.mode() == AclMode::Monitor => {
+                ctx.acl_stats.record_block(acl_user.as_ref());
+                warn!(
+                    user = %acl_user.as_ref(),
+                    dest = %request.address,
+                    port = request.port,
+                    rule = matched_rule.as_deref().unwrap_or("unknown rule"),
+                    "ACL would block connection (monitor mode)"
+                );
+                would_block = true;
+                acl_rule_match = matched_rule;
+            }
             AclDecision::Block => {
                 ctx.acl_stats.record_block(acl_user.as_ref());
                 let rule = matched_rule.as_deref().unwrap_or("unknown rule");
 
                 warn!(
                     user = %acl_user.as_ref(),
-                    dest = %dest_string,
+                    dest = %request.address,
                     port = request.port,
                     rule,
+                    reply = %block_reply,
                     "ACL blocked connection"
                 );
 
                 let conn_info = ConnectionInfo {
                     source_ip: client_addr.ip(),
                     source_port: client_addr.port(),
-                    dest_ip: dest_string.clone(),
+                    dest_ip: request.address.to_arc_str(),
                     dest_port: request.port,
                     protocol: session_protocol,
+                    authenticated_user: authenticated_user.clone(),
+                    correlation_id: correlation_id.clone(),
+                    socks_version: SOCKS_VERSION,
+                    chained: false,
+                    groups: session_groups.clone(),
                 };
                 ctx.session_manager
-                    .track_rejected_session(acl_user.as_ref(), conn_info, matched_rule.clone())
+                    .track_rejected_session_with(
+                        acl_user.as_ref(),
+                        conn_info,
+                        matched_rule,
+                        block_reply as u8,
+                        SessionResolution {
+                            acl_matched_on,
+                            ..Default::default()
+                        },
+                    )
                     .await;
 
                 send_socks_response(
                     buffered_stream.get_mut(),
                     SocksProtocol::V5,
-                    ReplyCode::ConnectionNotAllowed,
+                    block_reply,
                     Address::IPv4([0, 0, 0, 0]),
                     0,
                 )
@@ -279,30 +728,49 @@ where
                 return Ok(());
             }
             AclDecision::Allow => {
-                ctx.acl_stats.record_allow(acl_user.as_ref());
-                acl_rule_match = matched_rule.clone();
-                acl_decision = "allow".to_string();
+                if request.command == Command::Connect {
+                    resolved_stage = ResolvedAclStage::for_request(
+                        engine,
+                        &ctx.acl_stats,
+                        &request.address,
+                        verdict.matched_on,
+                        &acl_user,
+                        &user_groups,
+                        Some(client_addr.ip()),
+                    );
+                }
+                // The SNI and resolved-address stages record the final outcome of the
+                // connections they check
+                if !(request.command == Command::Connect
+                    && (SniStage::applies(&ctx, &request.address, request.port)
+                        || resolved_stage.is_some()))
+                {
+                    ctx.acl_stats.record_allow(acl_user.as_ref());
+                }
 
                 match matched_rule.as_deref() {
                     Some(rule) => debug!(
                         user = %acl_user.as_ref(),
-                        dest = %dest_string,
+                        dest = %request.address,
                         port = request.port,
                         rule,
                         "ACL allowed connection"
                     ),
                     None => debug!(
                         user = %acl_user.as_ref(),
-                        dest = %dest_string,
+                        dest = %request.address,
                         port = request.port,
                         "ACL allowed connection (default policy)"
                     ),
                 }
+                acl_rule_match = matched_rule;
+                acl_resolve = verdict.resolve;
             }
         }
     }
 
-    // Step 4: Handle command
+    // Step 4: Handle command; the handshake is over, so its slot goes to the next client
+    drop(handshake);
     // Unwrap BufReader to get raw stream for data transfer phase
     // BufReader was only needed for protocol parsing (3 reads) - now we proxy data directly
     let client_stream = buffered_stream.into_inner();
@@ -311,17 +779,43 @@ where
         Command::Connect => {
             let session_ctx = SessionContext {
                 user: Arc::clone(&acl_user),
+                authenticated_user: authenticated_user.clone(),
+                correlation_id: correlation_id.clone(),
                 client_addr,
-                acl_decision,
+                acl_decision: ACL_DECISION_ALLOW,
                 acl_rule: acl_rule_match,
+                acl_matched_on,
                 protocol: session_protocol,
                 qos_engine: ctx.qos_engine.clone(),
+                groups: session_groups,
+                would_block,
             };
             let connect_ctx = ConnectHandlerContext {
                 session_manager: ctx.session_manager.clone(),
                 traffic_config: ctx.traffic_config,
                 protocol: SocksProtocol::V5,
                 connection_pool: ctx.connection_pool.clone(),
+                resolver: ctx.resolver.clone(),
+                address_selection: ctx.address_selection,
+                connect_retry: ctx.connect_retry,
+                host_hints: ctx.host_hints.clone(),
+                tunnel_keepalive: ctx.tunnel_keepalive.clone(),
+                upstream_socket_options: ctx.upstream_socket_options.clone(),
+                upstream_proxy: ctx.upstream_proxy.clone(),
+                upstream_tls: ctx.upstream_tls.clone(),
+                egress: ctx.egress.select(&acl_user, &user_groups),
+                resolve: acl_resolve,
+                acl_stats: ctx.acl_stats.clone(),
+                resolved_stage,
+                sni_stage: SniStage::for_request(
+                    &ctx,
+                    &request.address,
+                    request.port,
+                    &acl_user,
+                    &user_groups,
+                    client_addr.ip(),
+                    would_block,
+                ),
             };
             handle_connect(
                 client_stream,
@@ -335,11 +829,19 @@ where
         Command::Bind => {
             let bind_ctx = crate::server::bind::BindContext {
                 user: Arc::clone(&acl_user),
+                authenticated_user: authenticated_user.clone(),
+                correlation_id: correlation_id.clone(),
                 client_addr,
-                acl_decision,
+                acl_decision: ACL_DECISION_ALLOW,
                 acl_rule: acl_rule_match,
                 qos_engine: ctx.qos_engine.clone(),
                 connection_pool: ctx.connection_pool.clone(),
+                resolver: ctx.resolver.clone(),
+                accept_timeout: ctx.bind_accept_timeout,
+                traffic_config: ctx.traffic_config,
+                groups: session_groups,
+                protocol: SocksProtocol::V5,
+                would_block,
             };
 
             handle_bind_relay(
@@ -354,18 +856,33 @@ where
         Command::UdpAssociate => {
             let session_ctx = SessionContext {
                 user: Arc::clone(&acl_user),
+                authenticated_user: authenticated_user.clone(),
+                correlation_id: correlation_id.clone(),
                 client_addr,
-                acl_decision,
+                acl_decision: ACL_DECISION_ALLOW,
                 acl_rule: acl_rule_match,
+                acl_matched_on,
                 protocol: session_protocol,
                 qos_engine: ctx.qos_engine.clone(),
+                groups: session_groups,
+                would_block,
+            };
+            let destinations = UdpDestinations {
+                special_names: ctx.special_names,
+                allow_domain_names: ctx.allow_domain_requests,
+                resolver: ctx.resolver.clone(),
+                address_selection: ctx.address_selection,
+                egress: ctx.egress.select(&acl_user, &user_groups),
             };
             handle_udp_associate(
                 client_stream,
                 &request.address,
                 request.port,
+                ctx.udp_association,
+                ctx.udp_datagrams,
                 ctx.session_manager.clone(),
                 session_ctx,
+                destinations,
             )
             .await?;
         }
@@ -376,13 +893,15 @@ where
 
 #[instrument(
     level = "debug",
-    skip(client_stream, ctx),
+    skip(client_stream, ctx, handshake),
     fields(client = %client_addr)
 )]
 async fn handle_socks4<S>(
     mut client_stream: S,
     ctx: Arc<ClientHandlerContext>,
     client_addr: std::net::SocketAddr,
+    client_identity: Option<Identity>,
+    handshake: Handshake,
 ) -> Result<()>
 where
     S: IoStream,
@@ -392,6 +911,12 @@ where
             client = %client_addr,
             "SOCKS4 request rejected: server requires authentication"
         );
+        ctx.session_manager
+            .failures()
+            .record(FailureRecord::auth_failure(
+                client_addr.ip(),
+                Some(ReplyCode::ConnectionNotAllowed as u8),
+            ));
         send_socks_response(
             &mut client_stream,
             SocksProtocol::V4,
@@ -406,87 +931,245 @@ where
     }
 
     // Perform no-auth path to allow future auth hooks (e.g., PAM address)
-    let auth_result = ctx
-        .auth_manager
-        .authenticate(&mut client_stream, AuthMethod::NoAuth, client_addr.ip())
-        .await?;
+    let auth_result = match handshake
+        .phase(
+            HandshakePhase::Auth,
+            ctx.auth_manager
+                .authenticate(&mut client_stream, AuthMethod::NoAuth, client_addr.ip()),
+        )
+        .await
+    {
+        Err(e @ RustSocksError::AuthFailed(_)) => {
+            ctx.session_manager
+                .failures()
+                .record(FailureRecord::auth_failure(client_addr.ip(), None));
+            return Err(e);
+        }
+        result => result?,
+    };
 
     // Extract groups if any (usually None for SOCKS4 no-auth)
-    let user_groups = match auth_result {
-        Some((_, groups)) => groups,
+    let user_groups = match auth_result.as_ref().or(client_identity.as_ref()) {
+        Some(identity) => identity.groups.clone(),
         None => Vec::new(),
     };
+    let session_groups = group_names(&user_groups);
 
-    let request = parse_socks4_request(&mut client_stream).await?;
+    let request = handshake
+        .phase(
+            HandshakePhase::Request,
+            parse_socks4_request(&mut client_stream),
+        )
+        .await?;
 
-    let dest_string = request.address.to_string();
     info!(
-        "SOCKS4 request: command={:?}, dest={}:{} user_id={:?}",
-        request.command, dest_string, request.port, request.user_id
+        command = ?request.command,
+        dest = %request.address,
+        port = request.port,
+        user_id = ?request.user_id,
+        "SOCKS4 request"
     );
 
-    let user = request.user_id.clone();
-    let acl_user: Arc<str> = user
-        .clone()
-        .filter(|s| !s.is_empty())
-        .map(|username| Arc::from(username.into_boxed_str()))
-        .unwrap_or_else(|| Arc::from(ctx.anonymous_user.as_str()));
-
-    if let Some(ref username) = user {
-        if !username.is_empty() {
-            info!("SOCKS4 user identifier received: {}", username);
-        }
-    }
+    // A client certificate is verified; the SOCKS4 user ID is not, so it yields
+    let (acl_user, authenticated_user): (Arc<str>, Option<Arc<str>>) =
+        match (client_identity, request.user_id.as_deref()) {
+            (Some(identity), _) => {
+                let user: Arc<str> = Arc::from(identity.user);
+                (Arc::clone(&user), Some(user))
+            }
+            (None, Some(username)) if !username.is_empty() => {
+                log_sampling::note_user(username);
+                info!(user = username, "SOCKS4 user identifier received");
+                (Arc::from(username), None)
+            }
+            _ => (Arc::clone(&ctx.anonymous_user), None),
+        };
 
-    if let Err(e) = ctx
+    let _connection_slot = match ctx
         .qos_engine
-        .check_and_inc_connection_arc(&acl_user, &ctx.connection_limits)
+        .acquire_connection(&acl_user, &ctx.connection_limits.get())
     {
-        warn!(
-            user = %acl_user.as_ref(),
-            error = %e,
-            "Connection limit exceeded (SOCKS4)"
+        Ok(slot) => slot,
+        Err(e) => {
+            warn!(
+                user = %acl_user.as_ref(),
+                error = %e,
+                "Connection limit exceeded (SOCKS4)"
+            );
+            if let RustSocksError::ConnectionLimit(exceeded) = &e {
+                ctx.session_manager
+                    .record_admission_rejection(AdmissionRejection::new(
+                        exceeded,
+                        client_addr,
+                        Some(format!("{}:{}", request.address, request.port)),
+                    ))
+                    .await;
+            }
+            send_socks_response(
+                &mut client_stream,
+                SocksProtocol::V4,
+                ReplyCode::ConnectionNotAllowed,
+                Address::IPv4([0, 0, 0, 0]),
+                0,
+            )
+            .await?;
+            return Err(e);
+        }
+    };
+
+    let session_protocol = SessionProtocol::Tcp;
+
+    // SOCKS4a names, like SOCKS5 ones
+    if domain_request_refused(
+        &ctx,
+        acl_user.as_ref(),
+        &request.address,
+        request.port,
+        request.command,
+    ) {
+        ctx.session_manager.access_log().record_rejected_request(
+            &AclAccess {
+                user: acl_user.as_ref(),
+                authenticated_user: authenticated_user.as_deref(),
+                correlation_id: None,
+                client_addr,
+                destination: &request.address,
+                port: request.port,
+                protocol: "tcp",
+                socks_version: 4,
+                command: request.command.as_str(),
+                auth_method: AuthMethod::NoAuth.as_str(),
+                group_count: user_groups.len(),
+            },
+            DOMAIN_REQUESTS_DISABLED,
         );
+        let conn_info = ConnectionInfo {
+            source_ip: client_addr.ip(),
+            source_port: client_addr.port(),
+            dest_ip: request.address.to_arc_str(),
+            dest_port: request.port,
+            protocol: session_protocol,
+            authenticated_user: authenticated_user.clone(),
+            correlation_id: None,
+            socks_version: SOCKS4_VERSION,
+            chained: false,
+            groups: session_groups.clone(),
+        };
+        ctx.session_manager
+            .track_rejected_session(
+                acl_user.as_ref(),
+                conn_info,
+                Some(DOMAIN_REQUESTS_DISABLED.to_string()),
+                ReplyCode::AddressTypeNotSupported as u8,
+            )
+            .await;
+
         send_socks_response(
             &mut client_stream,
             SocksProtocol::V4,
-            ReplyCode::ConnectionNotAllowed,
+            ReplyCode::AddressTypeNotSupported,
             Address::IPv4([0, 0, 0, 0]),
             0,
         )
         .await?;
-        return Err(e);
+
+        return Ok(());
     }
 
-    let _connection_guard = ConnectionGuard {
-        qos_engine: ctx.qos_engine.clone(),
-        user: Arc::clone(&acl_user),
-    };
+    if let Some(category) =
+        special_name_block(&ctx, acl_user.as_ref(), &request.address, request.port)
+    {
+        let conn_info = ConnectionInfo {
+            source_ip: client_addr.ip(),
+            source_port: client_addr.port(),
+            dest_ip: request.address.to_arc_str(),
+            dest_port: request.port,
+            protocol: session_protocol,
+            authenticated_user: authenticated_user.clone(),
+            correlation_id: None,
+            socks_version: SOCKS4_VERSION,
+            chained: false,
+            groups: session_groups.clone(),
+        };
+        ctx.session_manager
+            .track_rejected_session(
+                acl_user.as_ref(),
+                conn_info,
+                Some(format!("special-name:{}", category)),
+                category.reply_code() as u8,
+            )
+            .await;
+
+        send_socks_response(
+            &mut client_stream,
+            SocksProtocol::V4,
+            category.reply_code(),
+            Address::IPv4([0, 0, 0, 0]),
+            0,
+        )
+        .await?;
+
+        return Ok(());
+    }
 
-    let session_protocol = SessionProtocol::Tcp;
     let mut acl_rule_match: Option<String> = None;
-    let mut acl_decision = "allow".to_string();
+    let mut acl_matched_on: Option<AclMatchedOn> = None;
+    let mut acl_resolve = ResolveMode::Local;
+    let mut resolved_stage: Option<ResolvedAclStage> = None;
+    // Blocked, but relayed because the ACL runs in monitor mode
+    let mut would_block = false;
 
     if let Some(engine) = ctx.acl_engine.as_ref() {
-        // Use evaluate_with_groups() for dynamic LDAP group matching
-        let (decision, matched_rule) = engine
-            .evaluate_with_groups(
+        // Use verdict_with_groups() for dynamic LDAP group matching
+        let verdict = engine
+            .verdict_with_groups(
                 acl_user.as_ref(),
                 &user_groups,
                 &request.address,
                 request.port,
                 &Protocol::Tcp,
+                Some(client_addr.ip()),
             )
             .await;
+        ctx.session_manager.access_log().record_acl(
+            &verdict,
+            &AclAccess {
+                user: acl_user.as_ref(),
+                authenticated_user: authenticated_user.as_deref(),
+                correlation_id: None,
+                client_addr,
+                destination: &request.address,
+                port: request.port,
+                protocol: "tcp",
+                socks_version: 4,
+                command: request.command.as_str(),
+                auth_method: AuthMethod::NoAuth.as_str(),
+                group_count: user_groups.len(),
+            },
+        );
+        let matched_rule = verdict.matched_rule;
+        acl_matched_on = Some(verdict.matched_on);
 
-        match decision {
-            AclDecision::Block => {
+        match verdict.decision {
+            AclDecision::Block if engine.mode() == AclMode::Monitor => {
                 ctx.acl_stats.record_block(acl_user.as_ref());
-                let rule = matched_rule.as_deref().unwrap_or("unknown rule");
-
                 warn!(
                     user = %acl_user.as_ref(),
-                    dest = %dest_string,
+                    dest = %request.address,
+                    port = request.port,
+                    rule = matched_rule.as_deref().unwrap_or("unknown rule"),
+                    "ACL would block SOCKS4 connection (monitor mode)"
+                );
+                would_block = true;
+                acl_rule_match = matched_rule;
+            }
+            AclDecision::Block => {
+                ctx.acl_stats.record_block(acl_user.as_ref());
+                let rule = matched_rule.as_deref().unwrap_or("unknown rule");
+
+                warn!(
+                    user = %acl_user.as_ref(),
+                    dest = %request.address,
                     port = request.port,
                     rule,
                     "ACL blocked SOCKS4 connection"
@@ -495,12 +1178,26 @@ where
                 let conn_info = ConnectionInfo {
                     source_ip: client_addr.ip(),
                     source_port: client_addr.port(),
-                    dest_ip: dest_string.clone(),
+                    dest_ip: request.address.to_arc_str(),
                     dest_port: request.port,
                     protocol: session_protocol,
+                    authenticated_user: authenticated_user.clone(),
+                    correlation_id: None,
+                    socks_version: SOCKS4_VERSION,
+                    chained: false,
+                    groups: session_groups.clone(),
                 };
                 ctx.session_manager
-                    .track_rejected_session(acl_user.as_ref(), conn_info, matched_rule.clone())
+                    .track_rejected_session_with(
+                        acl_user.as_ref(),
+                        conn_info,
+                        matched_rule,
+                        ReplyCode::ConnectionNotAllowed as u8,
+                        SessionResolution {
+                            acl_matched_on,
+                            ..Default::default()
+                        },
+                    )
                     .await;
 
                 send_socks_response(
@@ -515,22 +1212,46 @@ where
                 return Ok(());
             }
             AclDecision::Allow => {
-                ctx.acl_stats.record_allow(acl_user.as_ref());
-                acl_rule_match = matched_rule.clone();
-                acl_decision = "allow".to_string();
+                if request.command == Command::Connect {
+                    resolved_stage = ResolvedAclStage::for_request(
+                        engine,
+                        &ctx.acl_stats,
+                        &request.address,
+                        verdict.matched_on,
+                        &acl_user,
+                        &user_groups,
+                        Some(client_addr.ip()),
+                    );
+                }
+                // The SNI and resolved-address stages record the final outcome of the
+                // connections they check
+                if !(request.command == Command::Connect
+                    && (SniStage::applies(&ctx, &request.address, request.port)
+                        || resolved_stage.is_some()))
+                {
+                    ctx.acl_stats.record_allow(acl_user.as_ref());
+                }
+                acl_rule_match = matched_rule;
+                acl_resolve = verdict.resolve;
             }
         }
     }
 
+    drop(handshake);
     match request.command {
         Command::Connect => {
             let session_ctx = SessionContext {
                 user: Arc::clone(&acl_user),
+                authenticated_user,
+                correlation_id: None,
                 client_addr,
-                acl_decision,
+                acl_decision: ACL_DECISION_ALLOW,
                 acl_rule: acl_rule_match,
+                acl_matched_on,
                 protocol: session_protocol,
                 qos_engine: ctx.qos_engine.clone(),
+                groups: session_groups,
+                would_block,
             };
 
             let connect_ctx = ConnectHandlerContext {
@@ -538,6 +1259,27 @@ where
                 traffic_config: ctx.traffic_config,
                 protocol: SocksProtocol::V4,
                 connection_pool: ctx.connection_pool.clone(),
+                resolver: ctx.resolver.clone(),
+                address_selection: ctx.address_selection,
+                connect_retry: ctx.connect_retry,
+                host_hints: ctx.host_hints.clone(),
+                tunnel_keepalive: ctx.tunnel_keepalive.clone(),
+                upstream_socket_options: ctx.upstream_socket_options.clone(),
+                upstream_proxy: ctx.upstream_proxy.clone(),
+                upstream_tls: ctx.upstream_tls.clone(),
+                egress: ctx.egress.select(&acl_user, &user_groups),
+                resolve: acl_resolve,
+                acl_stats: ctx.acl_stats.clone(),
+                resolved_stage,
+                sni_stage: SniStage::for_request(
+                    &ctx,
+                    &request.address,
+                    request.port,
+                    &acl_user,
+                    &user_groups,
+                    client_addr.ip(),
+                    would_block,
+                ),
             };
             handle_connect(
                 client_stream,
@@ -550,8 +1292,9 @@ where
         }
         Command::Bind => {
             warn!(
-                "SOCKS4 BIND not supported for dest {}:{}",
-                dest_string, request.port
+                dest = %request.address,
+                port = request.port,
+                "SOCKS4 BIND not supported"
             );
             send_socks_response(
                 &mut client_stream,
@@ -564,8 +1307,10 @@ where
         }
         _ => {
             warn!(
-                "Unsupported SOCKS4 command {:?} for dest {}:{}",
-                request.command, dest_string, request.port
+                command = ?request.command,
+                dest = %request.address,
+                port = request.port,
+                "Unsupported SOCKS4 command"
             );
             send_socks_response(
                 &mut client_stream,
@@ -581,13 +1326,35 @@ where
     Ok(())
 }
 
+/// ACL decision recorded for sessions that made it past admission.
+const ACL_DECISION_ALLOW: &str = "allow";
+
+/// How long the failure status of a timed-out login may take to write
+const AUTH_FAILURE_WRITE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
 struct SessionContext {
     user: Arc<str>,
+    authenticated_user: Option<Arc<str>>,
+    correlation_id: Option<Arc<str>>,
     client_addr: std::net::SocketAddr,
-    acl_decision: String,
+    acl_decision: &'static str,
     acl_rule: Option<String>,
+    /// See [`crate::session::Session::acl_matched_on`]
+    acl_matched_on: Option<AclMatchedOn>,
     protocol: SessionProtocol,
     qos_engine: QosEngine,
+    /// Recorded on the session (see [`crate::session::Session::groups`])
+    groups: Vec<Arc<str>>,
+    /// See [`crate::session::Session::would_block`]
+    would_block: bool,
+}
+
+/// The user's groups as the session records them
+fn group_names(groups: &[String]) -> Vec<Arc<str>> {
+    groups
+        .iter()
+        .map(|group| Arc::from(group.as_str()))
+        .collect()
 }
 
 struct ConnectHandlerContext {
@@ -595,6 +1362,220 @@ struct ConnectHandlerContext {
     traffic_config: TrafficUpdateConfig,
     protocol: SocksProtocol,
     connection_pool: Arc<ConnectionPool>,
+    resolver: Arc<dyn DestinationResolver>,
+    address_selection: AddressSelection,
+    connect_retry: ConnectRetry,
+    host_hints: Option<Arc<HostHints>>,
+    tunnel_keepalive: Arc<TunnelKeepalive>,
+    upstream_socket_options: Arc<UpstreamSocketOptions>,
+    upstream_proxy: Option<Arc<UpstreamProxy>>,
+    upstream_tls: Option<Arc<UpstreamTls>>,
+    /// Source addresses of the user's egress rule; direct connects only
+    egress: Option<EgressAddresses>,
+    /// Where the matched rule wants the destination name resolved
+    resolve: ResolveMode,
+    acl_stats: Arc<AclStats>,
+    resolved_stage: Option<ResolvedAclStage>,
+    sni_stage: Option<SniStage>,
+}
+
+impl ConnectHandlerContext {
+    /// Parent proxy the CONNECT is chained through, if any. A `resolve = "remote"` rule
+    /// hands names to the parent even outside its destinations.
+    fn parent_for(&self, dest_addr: &Address) -> Option<&UpstreamProxy> {
+        let remote = dest_addr.literal_ip().is_none() && self.resolve == ResolveMode::Remote;
+        self.upstream_proxy
+            .as_deref()
+            .filter(|parent| remote || parent.routes(dest_addr))
+    }
+
+    /// Client TLS the upstream connection to `dest_addr:dest_port` is wrapped in, if any
+    fn tls_for(&self, dest_addr: &Address, dest_port: u16) -> Option<&UpstreamTls> {
+        self.upstream_tls
+            .as_deref()
+            .filter(|tls| tls.applies(dest_addr, dest_port))
+    }
+}
+
+/// Where a tunnel's upstream connection came from, and so where it goes back to.
+///
+/// Connections opened with per-destination socket options or an egress address bypass
+/// the pool entirely, as do tunnels chained through a parent proxy.
+struct UpstreamLease {
+    pool: Option<Arc<ConnectionPool>>,
+    addr: SocketAddr,
+}
+
+impl UpstreamLease {
+    async fn put(&self, stream: TcpStream, hint: ReuseHint) {
+        if let Some(pool) = &self.pool {
+            pool.put(self.addr, stream, hint).await;
+        }
+    }
+
+    async fn release(&self, hint: ReuseHint) {
+        if let Some(pool) = &self.pool {
+            pool.release(self.addr, hint).await;
+        }
+    }
+}
+
+/// Second classification stage for CONNECT-by-IP sessions (`acl.classify_by_sni`).
+///
+/// When the stage runs it owns the ACL allow/block accounting for the connection, so the
+/// IP stage does not record its allow. Connections that never reach the SNI decision
+/// (e.g. the upstream connect fails) record that deferred allow when the stage is dropped.
+struct SniStage {
+    peek_timeout: std::time::Duration,
+    fail_mode: SniFailMode,
+    acl_engine: Option<Arc<AclEngine>>,
+    acl_stats: Arc<AclStats>,
+    user: Arc<str>,
+    user_groups: Vec<String>,
+    /// Client address for rules with `sources`
+    client_ip: IpAddr,
+    recorded: AtomicBool,
+}
+
+impl SniStage {
+    /// Only IP-literal CONNECTs to `acl.sni_ports` are classified; named CONNECTs already
+    /// carry the host.
+    fn applies(ctx: &ClientHandlerContext, address: &Address, port: u16) -> bool {
+        ctx.sni_routing.applies_to_port(port) && !matches!(address, Address::Domain(_))
+    }
+
+    fn for_request(
+        ctx: &ClientHandlerContext,
+        address: &Address,
+        port: u16,
+        user: &Arc<str>,
+        user_groups: &[String],
+        client_ip: IpAddr,
+        would_block: bool,
+    ) -> Option<Self> {
+        if !Self::applies(ctx, address, port) {
+            return None;
+        }
+
+        Some(Self {
+            peek_timeout: ctx.sni_routing.peek_timeout,
+            fail_mode: ctx.sni_routing.fail_mode,
+            acl_engine: ctx.acl_engine.clone(),
+            acl_stats: ctx.acl_stats.clone(),
+            user: Arc::clone(user),
+            user_groups: user_groups.to_vec(),
+            client_ip,
+            // A block relayed in monitor mode was already counted by the first stage
+            recorded: AtomicBool::new(would_block),
+        })
+    }
+
+    /// Blocks are logged and the connection relayed (`acl.mode = "monitor"`)
+    fn monitoring(&self) -> bool {
+        self.acl_engine
+            .as_ref()
+            .is_some_and(|engine| engine.mode() == AclMode::Monitor)
+    }
+
+    fn record_allow(&self) {
+        if self.acl_engine.is_some() && !self.recorded.swap(true, Ordering::Relaxed) {
+            self.acl_stats.record_allow(self.user.as_ref());
+        }
+    }
+
+    fn record_block(&self) {
+        if self.acl_engine.is_some() && !self.recorded.swap(true, Ordering::Relaxed) {
+            self.acl_stats.record_block(self.user.as_ref());
+        }
+    }
+}
+
+impl Drop for SniStage {
+    fn drop(&mut self) {
+        self.record_allow();
+    }
+}
+
+/// Second ACL check for CONNECTs to a name only the default policy allowed.
+///
+/// The addresses the name resolves to are checked against the rules before connecting,
+/// so a name pointed at an address the ACL blocks cannot reach it (see
+/// [`AclEngine::verdict_for_resolved`]). Like [`SniStage`], the stage owns the ACL
+/// allow/block accounting of the connection; connections that never resolve the name
+/// here (chained, or the resolution fails) record the deferred allow when it is dropped.
+struct ResolvedAclStage {
+    acl_engine: Arc<AclEngine>,
+    acl_stats: Arc<AclStats>,
+    user: Arc<str>,
+    user_groups: Vec<String>,
+    /// Client address for rules with `sources`; `None` for probes, which have no client
+    client_ip: Option<IpAddr>,
+    recorded: AtomicBool,
+}
+
+impl ResolvedAclStage {
+    /// Only names allowed by the default policy are checked again: a rule on the name
+    /// itself is explicit, and a name the default policy blocks is never resolved.
+    fn for_request(
+        acl_engine: &Arc<AclEngine>,
+        acl_stats: &Arc<AclStats>,
+        address: &Address,
+        matched_on: AclMatchedOn,
+        user: &Arc<str>,
+        user_groups: &[String],
+        client_ip: Option<IpAddr>,
+    ) -> Option<Self> {
+        if matched_on != AclMatchedOn::DefaultPolicy || address.literal_ip().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            acl_engine: acl_engine.clone(),
+            acl_stats: acl_stats.clone(),
+            user: Arc::clone(user),
+            user_groups: user_groups.to_vec(),
+            client_ip,
+            recorded: AtomicBool::new(false),
+        })
+    }
+
+    /// Verdict of the rules on the resolved addresses; `None` leaves the default policy
+    /// standing.
+    async fn check(&self, resolved: &[IpAddr], port: u16) -> Option<AclVerdict> {
+        let verdict = self
+            .acl_engine
+            .verdict_for_resolved(
+                &self.user,
+                &self.user_groups,
+                resolved,
+                port,
+                &Protocol::Tcp,
+                self.client_ip,
+            )
+            .await;
+        if !self.recorded.swap(true, Ordering::Relaxed) {
+            match &verdict {
+                Some(verdict) if verdict.decision == AclDecision::Block => {
+                    self.acl_stats.record_block(self.user.as_ref())
+                }
+                _ => self.acl_stats.record_allow(self.user.as_ref()),
+            }
+        }
+        verdict
+    }
+
+    /// Blocks are logged and the connection relayed (`acl.mode = "monitor"`)
+    fn monitoring(&self) -> bool {
+        self.acl_engine.mode() == AclMode::Monitor
+    }
+}
+
+impl Drop for ResolvedAclStage {
+    fn drop(&mut self) {
+        if !self.recorded.swap(true, Ordering::Relaxed) {
+            self.acl_stats.record_allow(self.user.as_ref());
+        }
+    }
 }
 
 #[instrument(
@@ -612,130 +1593,284 @@ async fn handle_connect<S>(
 where
     S: IoStream,
 {
-    let dest_host = match dest_addr {
-        Address::IPv4(octets) => std::net::Ipv4Addr::from(*octets).to_string(),
-        Address::IPv6(octets) => std::net::Ipv6Addr::from(*octets).to_string(),
-        Address::Domain(domain) => domain.clone(),
+    // Session tracking (the destination string is the only per-session copy made here)
+    let dest_ip = dest_addr.to_arc_str();
+    let client_ip = session_ctx.client_addr.ip();
+
+    let literal = literal_target(dest_addr, dest_port);
+    let is_name = dest_addr.literal_ip().is_none();
+    let remote = is_name && connect_ctx.resolve == ResolveMode::Remote;
+    let parent = connect_ctx.parent_for(dest_addr);
+    let chained = parent.is_some();
+    if is_name {
+        if remote && !chained {
+            debug!(
+                dest = %dest_addr,
+                "No upstream proxy for remote resolution, resolving locally"
+            );
+        }
+        connect_ctx.acl_stats.record_resolution(if chained {
+            ResolveMode::Remote
+        } else {
+            ResolveMode::Local
+        });
+    }
+
+    let connection_info = ConnectionInfo {
+        source_ip: client_ip,
+        source_port: session_ctx.client_addr.port(),
+        dest_ip,
+        dest_port,
+        protocol: session_ctx.protocol,
+        authenticated_user: session_ctx.authenticated_user.clone(),
+        correlation_id: session_ctx.correlation_id.clone(),
+        socks_version: connect_ctx.protocol.version(),
+        chained,
+        groups: session_ctx.groups.clone(),
     };
 
-    let mut candidates = match resolve_address(dest_addr, dest_port).await {
-        Ok(list) => list,
-        Err(e) => {
-            warn!(
-                "Destination resolution failed for {}:{}: {}",
-                dest_host, dest_port, e
-            );
+    let connected = match parent {
+        Some(parent) => connect_via_parent(parent, dest_addr, dest_port, &connect_ctx).await,
+        None => connect_direct(dest_addr, dest_port, literal, &connect_ctx).await,
+    };
+    let UpstreamConnection {
+        stream: upstream_tcp,
+        lease: upstream_lease,
+        candidates,
+        resolved_ips,
+        acl: resolved_acl,
+    } = match connected {
+        Ok(connected) => connected,
+        Err(failure) => {
+            let resolution = SessionResolution {
+                resolved_ips: failure.resolved_ips,
+                connected_ip: None,
+                acl_matched_on: session_ctx.acl_matched_on,
+            };
+            let blocked = failure.acl.is_some();
+            match failure.acl {
+                Some(verdict) => {
+                    connect_ctx
+                        .session_manager
+                        .track_rejected_session_with(
+                            &session_ctx.user,
+                            connection_info,
+                            verdict.matched_rule,
+                            failure.reply as u8,
+                            SessionResolution {
+                                acl_matched_on: Some(verdict.matched_on),
+                                ..resolution
+                            },
+                        )
+                        .await;
+                }
+                None => {
+                    connect_ctx
+                        .session_manager
+                        .track_failed_session(
+                            &session_ctx.user,
+                            connection_info,
+                            session_ctx.acl_decision,
+                            session_ctx.acl_rule,
+                            failure.category,
+                            failure.reply as u8,
+                            format!("Connect failed: {}", failure.error),
+                            resolution,
+                        )
+                        .await;
+                }
+            }
             send_socks_response(
                 &mut client_stream,
                 connect_ctx.protocol,
-                ReplyCode::HostUnreachable,
+                failure.reply,
                 Address::IPv4([0, 0, 0, 0]),
                 0,
             )
             .await?;
-            return Err(e);
+            // An ACL block is an answer, not an error, as in the first ACL stage
+            return if blocked { Ok(()) } else { Err(failure.error) };
         }
     };
+    let upstream_addr = upstream_lease.addr;
 
-    if matches!(connect_ctx.protocol, SocksProtocol::V4) {
-        candidates.retain(|addr| matches!(addr.ip(), IpAddr::V4(_)));
-        if candidates.is_empty() {
-            warn!(
-                "SOCKS4 request {}:{} resolved to non-IPv4 addresses",
-                dest_host, dest_port
+    let keepalive_plan = match connect_ctx
+        .tunnel_keepalive
+        .configure(&upstream_tcp, dest_addr)
+    {
+        Ok(plan) => plan,
+        Err(e) => {
+            warn!("Failed to configure upstream keepalive: {}", e);
+            None
+        }
+    };
+
+    // Remember which name this client resolved so a later CONNECT to the IP can report it
+    let requested_hint = match (connect_ctx.host_hints.as_ref(), literal) {
+        (Some(hints), Some(target)) => hints.lookup(client_ip, target.ip()),
+        // Chained names were resolved by the parent, so there is nothing to remember
+        (Some(hints), None) if !chained => {
+            hints.record(
+                client_ip,
+                &connection_info.dest_ip,
+                candidates.iter().map(SocketAddr::ip),
+                HostSource::ReverseMap,
             );
-            send_socks_response(
-                &mut client_stream,
-                connect_ctx.protocol,
-                ReplyCode::HostUnreachable,
-                Address::IPv4([0, 0, 0, 0]),
-                0,
-            )
-            .await?;
-            return Err(RustSocksError::Protocol(
-                "SOCKS4 requires IPv4 destination".to_string(),
-            ));
+            None
         }
-    }
+        _ => None,
+    };
 
-    let mut last_err: Option<std::io::Error> = None;
-    let mut upstream_stream_opt = None;
+    // A rule on a resolved address takes over from the default policy
+    let (acl_rule, acl_matched_on, would_block) = match resolved_acl {
+        Some(verdict) => (
+            verdict.matched_rule,
+            Some(verdict.matched_on),
+            session_ctx.would_block || verdict.decision == AclDecision::Block,
+        ),
+        None => (
+            session_ctx.acl_rule,
+            session_ctx.acl_matched_on,
+            session_ctx.would_block,
+        ),
+    };
 
-    for target in candidates {
-        debug!("Attempting upstream connection to {}", target);
-        match connect_ctx.connection_pool.get(target).await {
+    // The handshake runs before the reply, so a destination that fails it is reported
+    // to the client instead of a tunnel that closes at once
+    let mut upstream_stream = match connect_ctx.tls_for(dest_addr, dest_port) {
+        None => UpstreamStream::Plain(upstream_tcp),
+        Some(tls) => match tls
+            .connect(
+                upstream_tcp,
+                dest_addr,
+                connect_ctx.connection_pool.connect_timeout(),
+            )
+            .await
+        {
             Ok(stream) => {
-                // Optimize TCP socket for low latency and high throughput
-                if let Err(e) = optimize_tcp_socket(&stream) {
-                    warn!("Failed to optimize upstream TCP socket: {}", e);
-                }
-                upstream_stream_opt = Some((stream, target));
-                break;
+                #[cfg(feature = "metrics")]
+                crate::session::SessionMetrics::record_upstream_tls_handshake("ok");
+                UpstreamStream::Tls(Box::new(stream))
             }
             Err(e) => {
-                last_err = Some(e);
-            }
-        }
-    }
-
-    let (upstream_stream, upstream_addr) = match upstream_stream_opt {
-        Some((stream, addr)) => (stream, addr),
-        None => {
-            if let Some(ref err) = last_err {
-                warn!("Failed to connect to {}:{}: {}", dest_host, dest_port, err);
+                let server_name = tls.server_name(dest_addr);
+                let outcome = upstream_tls::failure_outcome(&e);
+                warn!(
+                    dest = %dest_addr,
+                    port = dest_port,
+                    server_name = %server_name,
+                    outcome,
+                    error = %e,
+                    "Upstream TLS handshake failed"
+                );
+                #[cfg(feature = "metrics")]
+                crate::session::SessionMetrics::record_upstream_tls_handshake(outcome);
+                connect_ctx
+                    .session_manager
+                    .access_log()
+                    .record_upstream_tls_failure(
+                        &session_ctx.user,
+                        &connection_info,
+                        &server_name,
+                        outcome,
+                        &e.to_string(),
+                    );
+                connect_ctx
+                    .session_manager
+                    .track_failed_session(
+                        &session_ctx.user,
+                        connection_info,
+                        session_ctx.acl_decision,
+                        acl_rule,
+                        FailureCategory::UpstreamTls,
+                        ReplyCode::GeneralFailure as u8,
+                        format!("Upstream TLS handshake failed: {}", e),
+                        SessionResolution {
+                            resolved_ips,
+                            connected_ip: (!chained).then(|| upstream_addr.ip()),
+                            acl_matched_on,
+                        },
+                    )
+                    .await;
+                send_socks_response(
+                    &mut client_stream,
+                    connect_ctx.protocol,
+                    ReplyCode::GeneralFailure,
+                    Address::IPv4([0, 0, 0, 0]),
+                    0,
+                )
+                .await?;
+                return Err(RustSocksError::Io(e));
             }
-            send_socks_response(
-                &mut client_stream,
-                connect_ctx.protocol,
-                ReplyCode::HostUnreachable,
-                Address::IPv4([0, 0, 0, 0]),
-                0,
-            )
-            .await?;
-            return Err(RustSocksError::Io(last_err.unwrap_or_else(|| {
-                std::io::Error::other("no reachable upstream addresses")
-            })));
-        }
-    };
-
-    let peer_display = upstream_stream
-        .peer_addr()
-        .map(|addr| addr.to_string())
-        .unwrap_or_else(|_| format!("{}:{}", dest_host, dest_port));
-
-    // Session tracking
-    let connection_info = ConnectionInfo {
-        source_ip: session_ctx.client_addr.ip(),
-        source_port: session_ctx.client_addr.port(),
-        dest_ip: dest_host.clone(),
-        dest_port,
-        protocol: session_ctx.protocol,
+        },
     };
 
     let (session_id, cancel_token) = connect_ctx
         .session_manager
         .new_session_with_control(
-            session_ctx.user.as_ref(),
+            Arc::clone(&session_ctx.user),
             connection_info,
-            session_ctx.acl_decision.clone(),
-            session_ctx.acl_rule.clone(),
+            session_ctx.acl_decision,
+            acl_rule,
             None,
         )
         .await;
+    if would_block {
+        connect_ctx
+            .session_manager
+            .mark_would_block(&session_id)
+            .await;
+    }
+    if upstream_stream.is_tls() {
+        connect_ctx
+            .session_manager
+            .mark_upstream_tls(&session_id)
+            .await;
+    }
+    connect_ctx
+        .session_manager
+        .set_resolution(
+            &session_id,
+            SessionResolution {
+                resolved_ips,
+                // A chained connection's peer is the parent proxy
+                connected_ip: (!chained).then(|| upstream_addr.ip()),
+                acl_matched_on,
+            },
+        )
+        .await;
 
-    // Get local address for response
-    let local_addr = upstream_stream.local_addr()?;
-    let bind_addr = match local_addr {
-        std::net::SocketAddr::V4(addr) => Address::IPv4(addr.ip().octets()),
-        std::net::SocketAddr::V6(addr) => Address::IPv6(addr.ip().octets()),
+    if let Some((host, source)) = requested_hint {
+        connect_ctx
+            .session_manager
+            .set_requested_host(&session_id, &host, source)
+            .await;
+    }
+
+    // BND.ADDR/BND.PORT is the upstream socket's local address, which is also the
+    // address traffic leaves from; zeros only if the socket cannot report it
+    let (bind_addr, bind_port) = match upstream_stream.tcp().local_addr() {
+        Ok(local_addr) => {
+            connect_ctx
+                .session_manager
+                .set_egress_ip(&session_id, local_addr.ip())
+                .await;
+            (Address::bound(local_addr.ip()), local_addr.port())
+        }
+        Err(e) => {
+            warn!(
+                error = %e,
+                "Cannot read the upstream socket's local address, replying with 0.0.0.0:0"
+            );
+            (Address::IPv4([0, 0, 0, 0]), 0)
+        }
     };
-    let bind_port = local_addr.port();
 
     if matches!(connect_ctx.protocol, SocksProtocol::V4) && !matches!(&bind_addr, Address::IPv4(_))
     {
         warn!(
             "SOCKS4 client received non-IPv4 bind address {}:{}",
-            dest_host, dest_port
+            dest_addr, dest_port
         );
         send_socks_response(
             &mut client_stream,
@@ -758,32 +1893,109 @@ where
     )
     .await?;
 
-    info!("Connected to {}, proxying data", peer_display);
+    info!(
+        upstream = %upstream_addr,
+        chained,
+        tls = upstream_stream.is_tls(),
+        "Connected, proxying data"
+    );
 
-    // Proxy data between client and upstream
-    match proxy_data(
-        client_stream,
-        upstream_stream,
-        connect_ctx.session_manager.clone(),
-        session_id,
-        cancel_token,
-        connect_ctx.traffic_config,
-        session_ctx.qos_engine.clone(),
-        Arc::clone(&session_ctx.user),
-    )
-    .await
-    {
-        Ok(reusable_stream) => {
-            if let Some(reuse) = reusable_stream {
+    if let Some(stage) = connect_ctx.sni_stage.as_ref() {
+        match classify_by_sni(
+            &mut client_stream,
+            &mut upstream_stream,
+            stage,
+            &connect_ctx.session_manager,
+            &session_id,
+            &session_ctx.user,
+            dest_addr,
+            dest_port,
+        )
+        .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                // The reply already went out, so a late block can only drop the connection
+                upstream_lease.release(ReuseHint::Refresh).await;
+                return Ok(());
+            }
+            Err(e) => {
+                upstream_lease.release(ReuseHint::Refresh).await;
                 connect_ctx
-                    .connection_pool
-                    .put(upstream_addr, reuse.stream, reuse.hint)
+                    .session_manager
+                    .close_session(
+                        &session_id,
+                        Some(format!("SNI classification failed: {}", e)),
+                        SessionStatus::Failed,
+                    )
                     .await;
+                return Err(e);
+            }
+        }
+    }
+
+    // Probe tunnels watch relay activity so probes are only armed while fully idle
+    let probe = match keepalive_plan {
+        Some(plan) if plan.mode == KeepaliveMode::Probe => match TunnelProbe::start(
+            upstream_stream.tcp(),
+            plan,
+            &connect_ctx.tunnel_keepalive,
+            connect_ctx.session_manager.clone(),
+            session_id,
+        ) {
+            Ok(probe) => Some(probe),
+            Err(e) => {
+                warn!("Failed to start keepalive probing: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+    let client_stream =
+        ActivityStream::new(client_stream, probe.as_ref().map(TunnelProbe::activity));
+
+    // Proxy data between client and upstream; a TLS session never returns to the pool
+    let result = match upstream_stream {
+        UpstreamStream::Plain(upstream_stream) => {
+            proxy_data(
+                client_stream,
+                upstream_stream,
+                connect_ctx.session_manager.clone(),
+                session_id,
+                cancel_token,
+                connect_ctx.traffic_config,
+                session_ctx.qos_engine.clone(),
+                Arc::clone(&session_ctx.user),
+                session_ctx.client_addr.ip(),
+            )
+            .await
+        }
+        UpstreamStream::Tls(upstream_stream) => proxy_stream(
+            client_stream,
+            upstream_stream,
+            connect_ctx.session_manager.clone(),
+            session_id,
+            cancel_token,
+            connect_ctx.traffic_config,
+            session_ctx.qos_engine.clone(),
+            Arc::clone(&session_ctx.user),
+            session_ctx.client_addr.ip(),
+        )
+        .await
+        .map(|()| None),
+    };
+
+    // Restore the socket's keepalive settings before it can return to the pool
+    if let Some(probe) = probe {
+        probe.stop().await;
+    }
+
+    match result {
+        Ok(reusable_stream) => {
+            if let Some(reuse) = reusable_stream {
+                upstream_lease.put(reuse.stream, reuse.hint).await;
             } else {
-                connect_ctx
-                    .connection_pool
-                    .release(upstream_addr, ReuseHint::Refresh)
-                    .await;
+                upstream_lease.release(ReuseHint::Refresh).await;
             }
             connect_ctx
                 .session_manager
@@ -796,10 +2008,7 @@ where
             Ok(())
         }
         Err(RustSocksError::ConnectionClosed) => {
-            connect_ctx
-                .connection_pool
-                .release(upstream_addr, ReuseHint::Refresh)
-                .await;
+            upstream_lease.release(ReuseHint::Refresh).await;
             connect_ctx
                 .session_manager
                 .close_session(
@@ -811,12 +2020,21 @@ where
             debug!(session = %session_id, "Session closed by client");
             Ok(())
         }
-        Err(e) => {
-            let reason = format!("Proxy error: {}", e);
+        Err(RustSocksError::IdleTimeout) => {
+            upstream_lease.release(ReuseHint::Refresh).await;
             connect_ctx
-                .connection_pool
-                .release(upstream_addr, ReuseHint::Refresh)
+                .session_manager
+                .close_session(
+                    &session_id,
+                    Some("idle timeout".to_string()),
+                    SessionStatus::Closed,
+                )
                 .await;
+            Ok(())
+        }
+        Err(e) => {
+            let reason = format!("Proxy error: {}", e);
+            upstream_lease.release(ReuseHint::Refresh).await;
             connect_ctx
                 .session_manager
                 .close_session(&session_id, Some(reason), SessionStatus::Failed)
@@ -826,19 +2044,584 @@ where
     }
 }
 
-#[instrument(level = "debug", skip(client_stream, session_manager, session_ctx))]
+/// Upstream side of a CONNECT tunnel, before the reply goes out.
+struct UpstreamConnection {
+    stream: TcpStream,
+    lease: UpstreamLease,
+    /// Addresses the destination resolved to locally; empty when chained
+    candidates: SmallVec<[SocketAddr; 2]>,
+    /// Every address a name resolved to, before `server.dns.strategy` picked from them;
+    /// empty for IP literals and when chained
+    resolved_ips: Vec<IpAddr>,
+    /// Verdict of a rule on the resolved addresses ([`ResolvedAclStage`])
+    acl: Option<AclVerdict>,
+}
+
+/// Why the upstream side of a CONNECT could not be opened.
+struct ConnectFailure {
+    category: FailureCategory,
+    error: RustSocksError,
+    /// Reply the client gets
+    reply: ReplyCode,
+    /// See [`UpstreamConnection::resolved_ips`]
+    resolved_ips: Vec<IpAddr>,
+    /// The rule on a resolved address that blocked the connect
+    acl: Option<AclVerdict>,
+}
+
+impl ConnectFailure {
+    fn new(category: FailureCategory, error: RustSocksError) -> Self {
+        Self {
+            category,
+            error,
+            reply: ReplyCode::HostUnreachable,
+            resolved_ips: Vec::new(),
+            acl: None,
+        }
+    }
+
+    /// The destination gave no address to connect to
+    fn resolving(error: RustSocksError) -> Self {
+        Self::new(FailureCategory::DnsError, error)
+    }
+
+    fn connecting(error: RustSocksError) -> Self {
+        let category = match &error {
+            RustSocksError::Io(e) => FailureCategory::for_connect_error(e),
+            _ => FailureCategory::ConnectError,
+        };
+        Self::new(category, error)
+    }
+
+    /// A resolved address is blocked by `verdict`
+    fn blocked(verdict: AclVerdict, resolved_ips: Vec<IpAddr>) -> Self {
+        Self {
+            category: FailureCategory::AclBlock,
+            error: RustSocksError::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "destination address blocked by ACL",
+            )),
+            reply: verdict.block_reply(),
+            resolved_ips,
+            acl: Some(verdict),
+        }
+    }
+
+    fn with_resolved(mut self, resolved_ips: &[IpAddr]) -> Self {
+        self.resolved_ips = resolved_ips.to_vec();
+        self
+    }
+}
+
+/// Resolve the destination and connect to the first address that answers.
+///
+/// IP literals skip the resolver entirely; only domains pay for a lookup. The addresses
+/// of a name only the default policy allowed are checked against the ACL first
+/// ([`ResolvedAclStage`]). Addresses are tried in the order `server.dns.strategy` gives
+/// them. Failures are logged here and answered with the failure's reply by the caller.
+async fn connect_direct(
+    dest_addr: &Address,
+    dest_port: u16,
+    literal: Option<SocketAddr>,
+    connect_ctx: &ConnectHandlerContext,
+) -> std::result::Result<UpstreamConnection, ConnectFailure> {
+    let resolved = match literal {
+        Some(target) => Ok(smallvec![target]),
+        None => connect_ctx
+            .resolver
+            .resolve(dest_addr, dest_port)
+            .await
+            .map(SmallVec::from_vec),
+    };
+    let resolved: SmallVec<[SocketAddr; 2]> = match resolved {
+        Ok(list) => list,
+        Err(e) => {
+            warn!(
+                "Destination resolution failed for {}:{}: {}",
+                dest_addr, dest_port, e
+            );
+            return Err(ConnectFailure::resolving(e));
+        }
+    };
+    let resolved_ips: Vec<IpAddr> = match literal {
+        Some(_) => Vec::new(),
+        None => resolved.iter().map(SocketAddr::ip).collect(),
+    };
+
+    let mut acl = None;
+    if let Some(stage) = connect_ctx.resolved_stage.as_ref() {
+        if let Some(verdict) = stage.check(&resolved_ips, dest_port).await {
+            let rule = verdict.matched_rule.as_deref().unwrap_or("unknown rule");
+            if verdict.decision == AclDecision::Block {
+                if stage.monitoring() {
+                    warn!(
+                        dest = %dest_addr,
+                        port = dest_port,
+                        rule,
+                        "ACL would block resolved address (monitor mode)"
+                    );
+                } else {
+                    warn!(
+                        dest = %dest_addr,
+                        port = dest_port,
+                        rule,
+                        reply = %verdict.block_reply(),
+                        "ACL blocked resolved address"
+                    );
+                    return Err(ConnectFailure::blocked(verdict, resolved_ips));
+                }
+            } else {
+                debug!(
+                    dest = %dest_addr,
+                    port = dest_port,
+                    rule,
+                    "ACL allowed resolved address"
+                );
+            }
+            acl = Some(verdict);
+        }
+    }
+
+    let mut candidates = connect_ctx.address_selection.order(resolved);
+    if candidates.is_empty() {
+        warn!(
+            "No {} address for {}:{}",
+            connect_ctx.address_selection.strategy.as_str(),
+            dest_addr,
+            dest_port
+        );
+        return Err(
+            ConnectFailure::resolving(RustSocksError::Io(std::io::Error::new(
+                std::io::ErrorKind::AddrNotAvailable,
+                format!(
+                    "destination has no address allowed by server.dns.strategy = {}",
+                    connect_ctx.address_selection.strategy.as_str()
+                ),
+            )))
+            .with_resolved(&resolved_ips),
+        );
+    }
+
+    if matches!(connect_ctx.protocol, SocksProtocol::V4) {
+        candidates.retain(|addr| matches!(addr.ip(), IpAddr::V4(_)));
+        if candidates.is_empty() {
+            warn!(
+                "SOCKS4 request {}:{} resolved to non-IPv4 addresses",
+                dest_addr, dest_port
+            );
+            return Err(ConnectFailure::resolving(RustSocksError::Protocol(
+                "SOCKS4 requires IPv4 destination".to_string(),
+            ))
+            .with_resolved(&resolved_ips));
+        }
+    }
+
+    // Traffic of a user with an egress rule never leaves from another address
+    if let Some(egress) = connect_ctx.egress {
+        candidates.retain(|addr| egress.for_peer(*addr).is_some());
+        if candidates.is_empty() {
+            warn!(
+                "No egress address ({}) of the family of {}:{}",
+                egress, dest_addr, dest_port
+            );
+            return Err(
+                ConnectFailure::connecting(RustSocksError::Io(std::io::Error::new(
+                    std::io::ErrorKind::AddrNotAvailable,
+                    format!("server.egress has no address for {}", dest_addr),
+                )))
+                .with_resolved(&resolved_ips),
+            );
+        }
+    }
+
+    let socket_plan = connect_ctx
+        .upstream_socket_options
+        .plan_for(dest_addr, dest_port);
+    let socket_plan = match connect_ctx.egress {
+        Some(egress) => Some(SocketOptionPlan {
+            egress: Some(egress),
+            ..socket_plan.unwrap_or_default()
+        }),
+        None => socket_plan,
+    };
+    // A TLS session cannot be handed to the next tunnel, so it gets a socket of its own
+    let socket_plan = match socket_plan {
+        None if connect_ctx.tls_for(dest_addr, dest_port).is_some() => {
+            Some(SocketOptionPlan::default())
+        }
+        plan => plan,
+    };
+
+    let connect_timeout = connect_ctx.connection_pool.connect_timeout();
+    let tcp = connect_ctx.connection_pool.tcp_tuning();
+    let plan = socket_plan.as_ref();
+    let connected = connect_ctx
+        .connect_retry
+        .run(connect_timeout, || {
+            connect_ctx
+                .address_selection
+                .connect(&candidates, |target| async move {
+                    match plan {
+                        Some(plan) => plan.connect(target, tcp, connect_timeout).await,
+                        None => connect_ctx.connection_pool.get(target).await,
+                    }
+                })
+        })
+        .await;
+    let (stream, addr) = match connected {
+        Ok(connected) => connected,
+        Err(err) => {
+            warn!("Failed to connect to {}:{}: {}", dest_addr, dest_port, err);
+            return Err(
+                ConnectFailure::connecting(RustSocksError::Io(err)).with_resolved(&resolved_ips)
+            );
+        }
+    };
+
+    Ok(UpstreamConnection {
+        stream,
+        lease: UpstreamLease {
+            pool: socket_plan
+                .is_none()
+                .then(|| connect_ctx.connection_pool.clone()),
+            addr,
+        },
+        candidates,
+        resolved_ips,
+        acl,
+    })
+}
+
+/// Open the tunnel through the parent proxy, which resolves the destination itself.
+async fn connect_via_parent(
+    parent: &UpstreamProxy,
+    dest_addr: &Address,
+    dest_port: u16,
+    connect_ctx: &ConnectHandlerContext,
+) -> std::result::Result<UpstreamConnection, ConnectFailure> {
+    debug!(
+        parent = %parent.endpoint(),
+        "Chaining upstream connection to {}:{}", dest_addr, dest_port
+    );
+    let stream = match parent
+        .connect(
+            dest_addr,
+            dest_port,
+            connect_ctx.connection_pool.connect_timeout(),
+        )
+        .await
+    {
+        Ok(stream) => stream,
+        Err(e) => {
+            warn!(
+                parent = %parent.endpoint(),
+                "Upstream proxy failed to connect to {}:{}: {}", dest_addr, dest_port, e
+            );
+            return Err(ConnectFailure::connecting(e));
+        }
+    };
+    let addr = stream
+        .peer_addr()
+        .map_err(|e| ConnectFailure::connecting(RustSocksError::Io(e)))?;
+    // The parent proxy's own socket code opened this one, so the options come after
+    if let Err(e) = connect_ctx.connection_pool.tcp_tuning().apply(
+        &socket2::SockRef::from(&stream),
+        "upstream",
+        addr,
+    ) {
+        warn!("Failed to set TCP options on upstream socket: {}", e);
+    }
+
+    Ok(UpstreamConnection {
+        stream,
+        // The parent holds the tunnel for this one destination, so it is never pooled
+        lease: UpstreamLease { pool: None, addr },
+        candidates: SmallVec::new(),
+        resolved_ips: Vec::new(),
+        acl: None,
+    })
+}
+
+/// Open one upstream connection the way a CONNECT from `user` would, for
+/// `diagnostics.probes`.
+///
+/// The special-use names policy, both ACL stages, the parent proxy, the user's egress
+/// addresses and the address strategy all apply, but no session is recorded and the
+/// connection is closed (or returned to the pool) at once. Rules with `sources` never
+/// match a probe, which has no client address.
+pub(crate) async fn probe_connect(
+    ctx: &ClientHandlerContext,
+    user: &Arc<str>,
+    groups: &[String],
+    dest_addr: &Address,
+    dest_port: u16,
+) -> ProbeAttempt {
+    if let SpecialNameDecision::Block(category) = ctx.special_names.check(dest_addr) {
+        return ProbeAttempt::failed(
+            FailureCategory::AclBlock,
+            format!("special-use name ({})", category.as_str()),
+            Some(format!("special-name:{}", category)),
+        );
+    }
+
+    let mut acl_rule = None;
+    let mut resolve = ResolveMode::Local;
+    let mut resolved_stage = None;
+    if let Some(engine) = ctx.acl_engine.as_ref() {
+        let verdict = engine
+            .verdict_with_groups(user, groups, dest_addr, dest_port, &Protocol::Tcp, None)
+            .await;
+        acl_rule = verdict.matched_rule.clone();
+        match verdict.decision {
+            AclDecision::Block => {
+                ctx.acl_stats.record_block(user.as_ref());
+                if engine.mode() != AclMode::Monitor {
+                    return ProbeAttempt::failed(
+                        FailureCategory::AclBlock,
+                        format!("blocked by ACL (reply {})", verdict.block_reply()),
+                        acl_rule,
+                    );
+                }
+            }
+            AclDecision::Allow => {
+                resolved_stage = ResolvedAclStage::for_request(
+                    engine,
+                    &ctx.acl_stats,
+                    dest_addr,
+                    verdict.matched_on,
+                    user,
+                    groups,
+                    None,
+                );
+                if resolved_stage.is_none() {
+                    ctx.acl_stats.record_allow(user.as_ref());
+                }
+                resolve = verdict.resolve;
+            }
+        }
+    }
+
+    let connect_ctx = ConnectHandlerContext {
+        session_manager: ctx.session_manager.clone(),
+        traffic_config: ctx.traffic_config,
+        protocol: SocksProtocol::V5,
+        connection_pool: ctx.connection_pool.clone(),
+        resolver: ctx.resolver.clone(),
+        address_selection: ctx.address_selection,
+        connect_retry: ctx.connect_retry,
+        host_hints: None,
+        tunnel_keepalive: ctx.tunnel_keepalive.clone(),
+        upstream_socket_options: ctx.upstream_socket_options.clone(),
+        upstream_proxy: ctx.upstream_proxy.clone(),
+        upstream_tls: ctx.upstream_tls.clone(),
+        egress: ctx.egress.select(user, groups),
+        resolve,
+        acl_stats: ctx.acl_stats.clone(),
+        resolved_stage,
+        sni_stage: None,
+    };
+    let parent = connect_ctx.parent_for(dest_addr);
+    let chained = parent.is_some();
+    let connected = match parent {
+        Some(parent) => connect_via_parent(parent, dest_addr, dest_port, &connect_ctx).await,
+        None => {
+            let literal = literal_target(dest_addr, dest_port);
+            connect_direct(dest_addr, dest_port, literal, &connect_ctx).await
+        }
+    };
+
+    match connected {
+        Ok(connection) => {
+            if let Some(verdict) = connection.acl {
+                acl_rule = verdict.matched_rule;
+            }
+            let connected_addr = connection.lease.addr;
+            // A TLS destination is only up if its certificate checks out; its socket was
+            // opened outside the pool, so there is nothing to return
+            if let Some(tls) = connect_ctx.tls_for(dest_addr, dest_port) {
+                let handshake = tls
+                    .connect(
+                        connection.stream,
+                        dest_addr,
+                        connect_ctx.connection_pool.connect_timeout(),
+                    )
+                    .await;
+                return match handshake {
+                    Ok(_) => ProbeAttempt {
+                        outcome: Ok(connected_addr),
+                        acl_rule,
+                        chained,
+                    },
+                    Err(e) => ProbeAttempt {
+                        outcome: Err(ProbeError {
+                            category: FailureCategory::UpstreamTls,
+                            message: format!("TLS handshake failed: {}", e),
+                        }),
+                        acl_rule,
+                        chained,
+                    },
+                };
+            }
+            // Nothing was sent, so a pooled stream can serve the next client as-is
+            connection
+                .lease
+                .put(connection.stream, ReuseHint::Reuse)
+                .await;
+            ProbeAttempt {
+                outcome: Ok(connected_addr),
+                acl_rule,
+                chained,
+            }
+        }
+        Err(failure) => {
+            if let Some(verdict) = failure.acl {
+                acl_rule = verdict.matched_rule;
+            }
+            ProbeAttempt {
+                outcome: Err(ProbeError {
+                    category: failure.category,
+                    message: failure.error.to_string(),
+                }),
+                acl_rule,
+                chained,
+            }
+        }
+    }
+}
+
+/// Peek at the ClientHello, record the SNI and run the name-based ACL stage.
+///
+/// Returns `Ok(false)` when the SNI is blocked (the session is already closed), otherwise
+/// forwards the peeked bytes upstream and returns `Ok(true)`. The IP stage has already
+/// allowed the connection, so the stricter outcome always wins.
+#[allow(clippy::too_many_arguments)]
+async fn classify_by_sni<S, U>(
+    client_stream: &mut S,
+    upstream_stream: &mut U,
+    stage: &SniStage,
+    session_manager: &SessionManager,
+    session_id: &uuid::Uuid,
+    user: &str,
+    dest_addr: &Address,
+    dest_port: u16,
+) -> Result<bool>
+where
+    S: IoStream,
+    U: AsyncWrite + Unpin,
+{
+    let (peeked, outcome) = match peek_sni(client_stream, stage.peek_timeout).await {
+        Ok(peek) => peek,
+        Err(e) => return Err(e.into()),
+    };
+
+    let sni_host = match outcome {
+        SniParse::Found(name) => Some(name),
+        SniParse::Missing => None,
+        SniParse::Invalid | SniParse::Incomplete => {
+            if stage.fail_mode == SniFailMode::Block && stage.monitoring() {
+                stage.record_block();
+                warn!(
+                    user,
+                    dest = %dest_addr,
+                    port = dest_port,
+                    peeked = peeked.len(),
+                    "ACL would block connection without a readable ClientHello (monitor mode)"
+                );
+                session_manager.mark_would_block(session_id).await;
+            } else if stage.fail_mode == SniFailMode::Block && stage.acl_engine.is_some() {
+                stage.record_block();
+                warn!(
+                    user,
+                    dest = %dest_addr,
+                    port = dest_port,
+                    peeked = peeked.len(),
+                    "ACL blocked connection without a readable ClientHello"
+                );
+                session_manager
+                    .reject_active_session(session_id, None, "Rejected by ACL (no readable SNI)")
+                    .await;
+                return Ok(false);
+            }
+            None
+        }
+    };
+
+    if let Some(sni_host) = sni_host {
+        session_manager.set_sni_host(session_id, &sni_host).await;
+
+        if let Some(engine) = stage.acl_engine.as_ref() {
+            let sni_address = Address::Domain(sni_host.into());
+            let (decision, matched_rule) = engine
+                .evaluate_with_groups(
+                    user,
+                    &stage.user_groups,
+                    &sni_address,
+                    dest_port,
+                    &Protocol::Tcp,
+                    Some(stage.client_ip),
+                )
+                .await;
+
+            if decision == AclDecision::Block && stage.monitoring() {
+                stage.record_block();
+                warn!(
+                    user,
+                    dest = %dest_addr,
+                    sni = %sni_address,
+                    port = dest_port,
+                    rule = matched_rule.as_deref().unwrap_or("unknown rule"),
+                    "ACL would block connection by SNI (monitor mode)"
+                );
+                session_manager.mark_would_block(session_id).await;
+            } else if decision == AclDecision::Block {
+                stage.record_block();
+                warn!(
+                    user,
+                    dest = %dest_addr,
+                    sni = %sni_address,
+                    port = dest_port,
+                    rule = matched_rule.as_deref().unwrap_or("unknown rule"),
+                    "ACL blocked connection by SNI"
+                );
+                session_manager
+                    .reject_active_session(session_id, matched_rule, "Rejected by ACL (SNI)")
+                    .await;
+                return Ok(false);
+            } else {
+                debug!(user, sni = %sni_address, port = dest_port, "ACL allowed SNI");
+            }
+        }
+    }
+    stage.record_allow();
+
+    if !peeked.is_empty() {
+        upstream_stream.write_all(&peeked).await?;
+        session_manager
+            .update_traffic(session_id, peeked.len() as u64, 0, 1, 0)
+            .await;
+    }
+
+    Ok(true)
+}
+
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    level = "debug",
+    skip(client_stream, session_manager, session_ctx, destinations)
+)]
 async fn handle_udp_associate<S>(
     mut client_stream: S,
-    _dest_addr: &Address,
-    _dest_port: u16,
+    dest_addr: &Address,
+    dest_port: u16,
+    association_mode: UdpAssociationMode,
+    limits: UdpDatagramLimits,
     session_manager: Arc<SessionManager>,
     session_ctx: SessionContext,
+    destinations: UdpDestinations,
 ) -> Result<()>
 where
     S: IoStream,
 {
-    let dest_host = "0.0.0.0".to_string(); // UDP ASSOCIATE doesn't specify real destination yet
-
     // Create shutdown channel for UDP relay
     let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
 
@@ -846,31 +2629,68 @@ where
     let connection_info = ConnectionInfo {
         source_ip: session_ctx.client_addr.ip(),
         source_port: session_ctx.client_addr.port(),
-        dest_ip: dest_host.clone(),
+        // UDP ASSOCIATE doesn't specify real destination yet
+        dest_ip: Arc::from("0.0.0.0"),
         dest_port: 0,
         protocol: session_ctx.protocol,
+        authenticated_user: session_ctx.authenticated_user.clone(),
+        correlation_id: session_ctx.correlation_id.clone(),
+        socks_version: SOCKS_VERSION,
+        chained: false,
+        groups: session_ctx.groups.clone(),
     };
 
     let (session_id, cancel_token) = session_manager
         .new_session_with_control(
-            session_ctx.user.as_ref(),
+            Arc::clone(&session_ctx.user),
             connection_info,
-            session_ctx.acl_decision.clone(),
-            session_ctx.acl_rule.clone(),
+            session_ctx.acl_decision,
+            session_ctx.acl_rule,
             Some(shutdown_tx.clone()),
         )
         .await;
+    if session_ctx.would_block {
+        session_manager.mark_would_block(&session_id).await;
+    }
+    if session_ctx.acl_matched_on.is_some() {
+        session_manager
+            .set_resolution(
+                &session_id,
+                SessionResolution {
+                    acl_matched_on: session_ctx.acl_matched_on,
+                    ..Default::default()
+                },
+            )
+            .await;
+    }
 
-    // Start UDP relay
-    let udp_relay_addr = match handle_udp_relay(
+    // DST.ADDR/DST.PORT name where the client will send datagrams from, if it knows
+    let endpoint = ClientEndpoint::new(
+        association_mode,
         session_ctx.client_addr,
+        dest_addr,
+        dest_port,
+    );
+
+    let shaping = UdpShaping {
+        qos_engine: session_ctx.qos_engine.clone(),
+        user: Arc::clone(&session_ctx.user),
+        source_ip: session_ctx.client_addr.ip(),
+    };
+
+    // Start UDP relay
+    let (udp_relay_addr, mut relay) = match handle_udp_relay(
+        endpoint,
         session_manager.clone(),
         session_id,
         shutdown_rx,
+        destinations,
+        shaping,
+        limits,
     )
     .await
     {
-        Ok(addr) => addr,
+        Ok(started) => started,
         Err(e) => {
             warn!("Failed to start UDP relay: {}", e);
             send_socks5_response(
@@ -892,10 +2712,7 @@ where
     };
 
     // Send success response with UDP relay address
-    let bind_addr = match udp_relay_addr {
-        std::net::SocketAddr::V4(addr) => Address::IPv4(addr.ip().octets()),
-        std::net::SocketAddr::V6(addr) => Address::IPv6(addr.ip().octets()),
-    };
+    let bind_addr = Address::bound(udp_relay_addr.ip());
     let bind_port = udp_relay_addr.port();
 
     send_socks5_response(
@@ -911,44 +2728,43 @@ where
         udp_relay_addr, session_ctx.client_addr
     );
 
-    // Keep TCP connection alive - when it closes, UDP session ends
-    // Read from stream to detect disconnect
-    let mut buf = [0u8; 1];
-    tokio::select! {
-        result = tokio::io::AsyncReadExt::read(&mut client_stream, &mut buf) => {
-            match result {
-                Ok(0) | Err(_) => {
-                    debug!("TCP control connection closed, terminating UDP session");
-                    let _ = shutdown_tx.send(());
-                    session_manager
-                        .close_session(
-                            &session_id,
-                            Some("TCP control connection closed".to_string()),
-                            SessionStatus::Closed,
-                        )
-                        .await;
-                }
-                Ok(_) => {
-                    debug!("Unexpected data on TCP control connection");
+    // The association lives exactly as long as the TCP control connection (RFC 1928):
+    // closing it tears the relay down, and a relay that ends on its own (idle timeout,
+    // socket error) closes it. Anything the client sends on it is ignored.
+    let mut buf = [0u8; 64];
+    loop {
+        tokio::select! {
+            result = tokio::io::AsyncReadExt::read(&mut client_stream, &mut buf) => {
+                match result {
+                    Ok(0) | Err(_) => {
+                        debug!("TCP control connection closed, terminating UDP session");
+                        // Closed first so the relay's own shutdown close is a no-op
+                        session_manager
+                            .close_session(
+                                &session_id,
+                                Some("TCP control connection closed".to_string()),
+                                SessionStatus::Closed,
+                            )
+                            .await;
+                        let _ = shutdown_tx.send(());
+                        let _ = relay.await;
+                        break;
+                    }
+                    Ok(_) => {
+                        debug!("Ignoring data on TCP control connection");
+                    }
                 }
             }
-        }
-        _ = cancel_token.cancelled() => {
-            debug!("UDP session cancelled via ACL update");
+            _ = &mut relay => {
+                debug!("UDP relay ended, closing TCP control connection");
+                break;
+            }
+            _ = cancel_token.cancelled() => {
+                debug!("UDP session cancelled via ACL update");
+                break;
+            }
         }
     }
 
     Ok(())
 }
-
-/// RAII guard to ensure connection count is decremented on drop
-struct ConnectionGuard {
-    qos_engine: QosEngine,
-    user: Arc<str>,
-}
-
-impl Drop for ConnectionGuard {
-    fn drop(&mut self) {
-        self.qos_engine.dec_user_connection_arc(&self.user);
-    }
-}
@@ -0,0 +1,166 @@
+//! Bounds on the part of a connection before the relay starts (`server.handshake_timeout_secs`,
+//! `server.max_concurrent_handshakes`).
+//!
+//! A client that opens a connection and never finishes the SOCKS negotiation would
+//! otherwise hold its task forever. Every handshake gets one deadline for all its
+//! phases, and only a limited number of them run at once: connections past the limit
+//! are closed on accept, while established relays are not counted.
+
+use crate::config::ServerConfig;
+use crate::utils::error::{Result, RustSocksError};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Default of `server.handshake_timeout_secs`
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where in the handshake a client stalled; the label of
+/// `rustsocks_handshake_timeouts_total`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakePhase {
+    /// TLS handshake on a TLS listener
+    Tls,
+    /// Version byte, method selection and the server's choice
+    Negotiation,
+    /// Sub-negotiation of the selected method and address checks
+    Auth,
+    /// The SOCKS request
+    Request,
+}
+
+impl HandshakePhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HandshakePhase::Tls => "tls",
+            HandshakePhase::Negotiation => "negotiation",
+            HandshakePhase::Auth => "auth",
+            HandshakePhase::Request => "request",
+        }
+    }
+}
+
+/// Handshake limits shared by all connections of a server
+#[derive(Debug, Clone)]
+pub struct HandshakeLimits {
+    /// Time from accept until the relay starts
+    pub timeout: Duration,
+    /// Handshakes allowed in flight at once; `None` for no limit
+    pub slots: Option<Arc<Semaphore>>,
+}
+
+impl Default for HandshakeLimits {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            slots: None,
+        }
+    }
+}
+
+impl From<&ServerConfig> for HandshakeLimits {
+    fn from(server: &ServerConfig) -> Self {
+        Self {
+            timeout: Duration::from_secs(server.handshake_timeout_secs),
+            slots: (server.max_concurrent_handshakes > 0)
+                .then(|| Arc::new(Semaphore::new(server.max_concurrent_handshakes))),
+        }
+    }
+}
+
+impl HandshakeLimits {
+    /// Start a handshake, or `None` when the limit of concurrent handshakes is reached
+    pub fn begin(&self) -> Option<Handshake> {
+        let permit = match &self.slots {
+            Some(slots) => Some(slots.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+        Some(Handshake {
+            deadline: Instant::now() + self.timeout,
+            timeout: self.timeout,
+            _permit: permit,
+        })
+    }
+}
+
+/// One connection's handshake. Dropping it frees its slot, which the handler does
+/// once the relay starts or the connection is rejected.
+#[derive(Debug)]
+pub struct Handshake {
+    deadline: Instant,
+    timeout: Duration,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Handshake {
+    /// Run one step of `phase`, failing it when the handshake deadline passes first
+    pub async fn phase<T, E, F>(&self, phase: HandshakePhase, step: F) -> Result<T>
+    where
+        F: Future<Output = std::result::Result<T, E>>,
+        RustSocksError: From<E>,
+    {
+        match tokio::time::timeout_at(self.deadline, step).await {
+            Ok(result) => result.map_err(RustSocksError::from),
+            Err(_) => {
+                debug!(
+                    phase = phase.as_str(),
+                    "Handshake not completed within {:?}", self.timeout
+                );
+                #[cfg(feature = "metrics")]
+                crate::session::SessionMetrics::record_handshake_timeout(phase.as_str());
+                Err(RustSocksError::HandshakeTimeout(phase.as_str()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(timeout_ms: u64, slots: usize) -> HandshakeLimits {
+        HandshakeLimits {
+            timeout: Duration::from_millis(timeout_ms),
+            slots: Some(Arc::new(Semaphore::new(slots))),
+        }
+    }
+
+    #[test]
+    fn slots_are_freed_when_the_handshake_ends() {
+        let limits = limits(1000, 2);
+        let first = limits.begin().expect("first slot");
+        let _second = limits.begin().expect("second slot");
+        assert!(limits.begin().is_none());
+
+        drop(first);
+        assert!(limits.begin().is_some());
+        assert!(HandshakeLimits::default().begin().is_some());
+    }
+
+    #[tokio::test]
+    async fn phases_share_one_deadline() {
+        let handshake = limits(150, 1).begin().unwrap();
+        handshake
+            .phase(HandshakePhase::Negotiation, async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok::<_, RustSocksError>(())
+            })
+            .await
+            .expect("within the deadline");
+
+        let err = handshake
+            .phase(HandshakePhase::Auth, async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok::<_, RustSocksError>(())
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, RustSocksError::HandshakeTimeout("auth")),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Recent hostname -> IP resolutions per client.
+//!
+//! Clients that resolve a name first and then CONNECT to the IP lose the hostname in
+//! session records. The table remembers, per client, which name each IP was resolved
+//! from so the later CONNECT-by-IP can still report it as `requested_host`.
+
+use crate::session::HostSource;
+use dashmap::DashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Upper bound on remembered (client, IP) pairs; new hints are dropped beyond it.
+const MAX_HINTS: usize = 65_536;
+
+#[derive(Debug, Clone)]
+struct HostHint {
+    host: Arc<str>,
+    source: HostSource,
+    expires_at: Instant,
+}
+
+#[derive(Debug)]
+pub struct HostHints {
+    ttl: Duration,
+    entries: DashMap<(IpAddr, IpAddr), HostHint>,
+}
+
+impl HostHints {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: DashMap::new(),
+        }
+    }
+
+    /// Remember that `client` obtained `targets` for `host`.
+    ///
+    /// An unexpired hint from a more authoritative source is kept.
+    pub fn record(
+        &self,
+        client: IpAddr,
+        host: &Arc<str>,
+        targets: impl IntoIterator<Item = IpAddr>,
+        source: HostSource,
+    ) {
+        if self.entries.len() >= MAX_HINTS {
+            let now = Instant::now();
+            self.entries.retain(|_, hint| hint.expires_at > now);
+            if self.entries.len() >= MAX_HINTS {
+                return;
+            }
+        }
+
+        let now = Instant::now();
+        let expires_at = now + self.ttl;
+        for target in targets {
+            let hint = HostHint {
+                host: Arc::clone(host),
+                source,
+                expires_at,
+            };
+            self.entries
+                .entry((client, target))
+                .and_modify(|current| {
+                    if current.expires_at <= now || current.source.rank() <= source.rank() {
+                        *current = hint.clone();
+                    }
+                })
+                .or_insert_with(|| hint.clone());
+        }
+    }
+
+    /// Hostname `client` recently obtained `target` for, if any.
+    pub fn lookup(&self, client: IpAddr, target: IpAddr) -> Option<(Arc<str>, HostSource)> {
+        let hint = self.entries.get(&(client, target))?;
+        if hint.expires_at <= Instant::now() {
+            drop(hint);
+            self.entries.remove(&(client, target));
+            return None;
+        }
+        Some((Arc::clone(&hint.host), hint.source))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn hints_are_scoped_to_the_client() {
+        let hints = HostHints::new(Duration::from_secs(60));
+        let host: Arc<str> = Arc::from("example.com");
+        hints.record(
+            ip("10.0.0.1"),
+            &host,
+            [ip("93.184.216.34")],
+            HostSource::ReverseMap,
+        );
+
+        let (found, source) = hints.lookup(ip("10.0.0.1"), ip("93.184.216.34")).unwrap();
+        assert_eq!(&*found, "example.com");
+        assert_eq!(source, HostSource::ReverseMap);
+        assert!(hints.lookup(ip("10.0.0.2"), ip("93.184.216.34")).is_none());
+    }
+
+    #[test]
+    fn weaker_source_does_not_replace_stronger_hint() {
+        let hints = HostHints::new(Duration::from_secs(60));
+        let resolved: Arc<str> = Arc::from("resolved.example");
+        let mapped: Arc<str> = Arc::from("mapped.example");
+        let client = ip("10.0.0.1");
+        let target = ip("192.0.2.10");
+
+        hints.record(client, &resolved, [target], HostSource::ResolveExtension);
+        hints.record(client, &mapped, [target], HostSource::ReverseMap);
+        assert_eq!(
+            hints.lookup(client, target).unwrap(),
+            (resolved.clone(), HostSource::ResolveExtension)
+        );
+
+        hints.record(client, &mapped, [target], HostSource::ResolveExtension);
+        assert_eq!(&*hints.lookup(client, target).unwrap().0, "mapped.example");
+    }
+
+    #[test]
+    fn expired_hints_are_dropped() {
+        let hints = HostHints::new(Duration::ZERO);
+        let host: Arc<str> = Arc::from("example.com");
+        hints.record(
+            ip("10.0.0.1"),
+            &host,
+            [ip("192.0.2.1")],
+            HostSource::ReverseMap,
+        );
+
+        assert!(hints.lookup(ip("10.0.0.1"), ip("192.0.2.1")).is_none());
+        assert!(hints.is_empty());
+    }
+}
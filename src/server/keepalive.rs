@@ -0,0 +1,545 @@
+//! Keepalive for long-idle tunnels (`server.tcp_keepalive`, `[[server.tunnel_keepalive]]`).
+//!
+//! Everything here works through socket options: keepalive probes are generated by the
+//! kernel and carry no payload, so the relayed byte stream is never touched. That also
+//! bounds what keepalive can do:
+//!
+//! - Middleboxes that track idleness by payload, or drop bare ACKs, still expire the
+//!   flow; only a message of the tunnelled protocol itself could keep it open.
+//! - Only the proxy→destination leg is probed; the client leg is the client's business.
+//! - `keepalive_probes` on a session counts probes the proxy armed, not segments the
+//!   kernel confirmed on the wire.
+//! - TCP_USER_TIMEOUT is only applied on Linux and Android.
+
+use crate::acl::matcher::CompiledDestinationMatcher;
+use crate::config::ServerConfig;
+use crate::protocol::Address;
+use crate::session::SessionManager;
+use socket2::{SockRef, Socket, TcpKeepalive};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Idle time before the first probe once a `probe` tunnel is armed; the tunnel has
+/// already been idle for the whole interval by then.
+const ARMED_PROBE_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveMode {
+    /// Socket keepalive only
+    Tcp,
+    /// Arm a probe only once both directions have been idle for the interval
+    Probe,
+}
+
+/// Keepalive settings for one upstream socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepalivePlan {
+    pub mode: KeepaliveMode,
+    /// Idle time before the first probe (`tcp`) or before a probe is armed (`probe`)
+    pub idle: Duration,
+    /// Time between probes
+    pub interval: Duration,
+    /// Unanswered probes before the kernel drops the connection
+    pub retries: u32,
+    /// TCP_USER_TIMEOUT while a `probe` tunnel is armed
+    pub user_timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+struct KeepaliveRule {
+    destinations: Vec<CompiledDestinationMatcher>,
+    plan: KeepalivePlan,
+}
+
+/// Keepalive policy for upstream sockets, derived from `[server]`.
+#[derive(Debug, Clone, Default)]
+pub struct TunnelKeepalive {
+    global: Option<KeepalivePlan>,
+    rules: Vec<KeepaliveRule>,
+}
+
+impl From<&ServerConfig> for TunnelKeepalive {
+    fn from(settings: &ServerConfig) -> Self {
+        let tcp = &settings.tcp_keepalive;
+        let global = if tcp.enabled {
+            Some(KeepalivePlan {
+                mode: KeepaliveMode::Tcp,
+                idle: Duration::from_secs(tcp.idle_secs),
+                interval: Duration::from_secs(tcp.interval_secs),
+                retries: tcp.retries,
+                user_timeout: None,
+            })
+        } else {
+            // `server.tcp.keepalive_secs` already set on the socket, kept for tunnels
+            // that no rule matches instead of being switched off
+            (settings.tcp.keepalive_secs > 0).then(|| {
+                let interval = Duration::from_secs(settings.tcp.keepalive_secs);
+                KeepalivePlan {
+                    mode: KeepaliveMode::Tcp,
+                    idle: interval,
+                    interval,
+                    retries: tcp.retries,
+                    user_timeout: None,
+                }
+            })
+        };
+
+        let rules = settings
+            .tunnel_keepalive
+            .iter()
+            .map(|tunnel| {
+                let interval = Duration::from_secs(tunnel.interval_secs);
+                KeepaliveRule {
+                    // Patterns were checked by Config::validate
+                    destinations: tunnel
+                        .destinations
+                        .iter()
+                        .filter_map(|pattern| CompiledDestinationMatcher::compile(pattern).ok())
+                        .collect(),
+                    plan: KeepalivePlan {
+                        mode: match tunnel.mode.as_str() {
+                            "probe" => KeepaliveMode::Probe,
+                            _ => KeepaliveMode::Tcp,
+                        },
+                        idle: interval,
+                        interval,
+                        retries: tcp.retries,
+                        user_timeout: tunnel.user_timeout_secs.map(Duration::from_secs),
+                    },
+                }
+            })
+            .collect();
+
+        Self { global, rules }
+    }
+}
+
+impl TunnelKeepalive {
+    /// Whether neither global nor per-destination keepalive is configured.
+    pub fn is_disabled(&self) -> bool {
+        self.global.is_none() && self.rules.is_empty()
+    }
+
+    /// Plan for a tunnel to `destination`: the first matching entry, else the global one.
+    pub fn plan_for(&self, destination: &Address) -> Option<KeepalivePlan> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.destinations
+                    .iter()
+                    .any(|matcher| matcher.matches(destination))
+            })
+            .map(|rule| rule.plan)
+            .or(self.global)
+    }
+
+    /// Apply the plan for `destination` to a fresh or pooled upstream socket.
+    ///
+    /// `probe` tunnels start out with the global settings; [`TunnelProbe`] arms them.
+    pub fn configure(
+        &self,
+        stream: &TcpStream,
+        destination: &Address,
+    ) -> io::Result<Option<KeepalivePlan>> {
+        if self.is_disabled() {
+            return Ok(None);
+        }
+
+        let plan = self.plan_for(destination);
+        let socket = SockRef::from(stream);
+        match plan {
+            Some(plan) if plan.mode == KeepaliveMode::Tcp => apply(&socket, Some(&plan))?,
+            // Pooled sockets may still carry the options of an earlier tunnel
+            _ => apply(&socket, self.global.as_ref())?,
+        }
+        Ok(plan)
+    }
+}
+
+/// Set SO_KEEPALIVE and its timers from `plan`, or switch keepalive off.
+pub fn apply(socket: &Socket, plan: Option<&KeepalivePlan>) -> io::Result<()> {
+    match plan {
+        Some(plan) => socket.set_tcp_keepalive(&tcp_keepalive(plan.idle, plan)),
+        None => socket.set_keepalive(false),
+    }
+}
+
+fn tcp_keepalive(time: Duration, plan: &KeepalivePlan) -> TcpKeepalive {
+    let keepalive = TcpKeepalive::new()
+        .with_time(time)
+        .with_interval(plan.interval);
+    #[cfg(unix)]
+    let keepalive = keepalive.with_retries(plan.retries);
+    keepalive
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_user_timeout(socket: &Socket, timeout: Option<Duration>) -> io::Result<()> {
+    socket.set_tcp_user_timeout(timeout)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_user_timeout(_socket: &Socket, _timeout: Option<Duration>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Time of the last byte relayed in either direction.
+#[derive(Debug)]
+pub struct TunnelActivity {
+    origin: Instant,
+    last_ms: AtomicU64,
+}
+
+impl TunnelActivity {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            last_ms: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn touch(&self) {
+        self.last_ms
+            .store(self.origin.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.origin.elapsed().saturating_sub(last)
+    }
+}
+
+impl Default for TunnelActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Client stream that records relay activity; both directions of a tunnel pass through
+/// the client side, so one timestamp covers upload and download.
+pub struct ActivityStream<S> {
+    inner: S,
+    activity: Option<Arc<TunnelActivity>>,
+}
+
+impl<S> ActivityStream<S> {
+    pub fn new(inner: S, activity: Option<Arc<TunnelActivity>>) -> Self {
+        Self { inner, activity }
+    }
+
+    #[inline]
+    fn touch(&self) {
+        if let Some(activity) = &self.activity {
+            activity.touch();
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ActivityStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if matches!(result, Poll::Ready(Ok(()))) && buf.filled().len() > before {
+            this.touch();
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ActivityStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if matches!(result, Poll::Ready(Ok(n)) if n > 0) {
+            this.touch();
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Monitor of a `probe` tunnel.
+///
+/// Once both directions have been idle for the interval, keepalive is armed on the
+/// upstream socket so the kernel probes right away and then every interval, and
+/// TCP_USER_TIMEOUT is applied; every idle interval counts one probe on the session.
+/// The first relayed byte restores the global settings.
+pub struct TunnelProbe {
+    activity: Arc<TunnelActivity>,
+    stop: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+impl TunnelProbe {
+    pub fn start(
+        stream: &TcpStream,
+        plan: KeepalivePlan,
+        keepalive: &TunnelKeepalive,
+        session_manager: Arc<SessionManager>,
+        session_id: Uuid,
+    ) -> io::Result<Self> {
+        // A duplicate descriptor, so the relay keeps sole ownership of the stream
+        let socket = SockRef::from(stream).try_clone()?;
+        let activity = Arc::new(TunnelActivity::new());
+        let stop = CancellationToken::new();
+        let handle = tokio::spawn(monitor(
+            socket,
+            plan,
+            keepalive.global,
+            Arc::clone(&activity),
+            session_manager,
+            session_id,
+            stop.clone(),
+        ));
+
+        Ok(Self {
+            activity,
+            stop,
+            handle,
+        })
+    }
+
+    pub fn activity(&self) -> Arc<TunnelActivity> {
+        Arc::clone(&self.activity)
+    }
+
+    /// Stop monitoring and restore the global settings before the socket is reused.
+    pub async fn stop(self) {
+        self.stop.cancel();
+        let _ = self.handle.await;
+    }
+}
+
+async fn monitor(
+    socket: Socket,
+    plan: KeepalivePlan,
+    base: Option<KeepalivePlan>,
+    activity: Arc<TunnelActivity>,
+    session_manager: Arc<SessionManager>,
+    session_id: Uuid,
+    stop: CancellationToken,
+) {
+    let mut armed = false;
+    loop {
+        let idle = activity.idle_for();
+        let wait = if idle >= plan.idle {
+            if !armed {
+                if let Err(e) = arm(&socket, &plan) {
+                    warn!(session = %session_id, "Failed to arm keepalive probe: {}", e);
+                    break;
+                }
+                armed = true;
+                debug!(
+                    session = %session_id,
+                    idle_secs = idle.as_secs(),
+                    "Tunnel idle, keepalive probe armed"
+                );
+            }
+            session_manager.record_keepalive_probe(&session_id).await;
+            plan.interval
+        } else {
+            if armed {
+                disarm(&socket, base.as_ref());
+                armed = false;
+            }
+            plan.idle - idle
+        };
+
+        tokio::select! {
+            _ = stop.cancelled() => break,
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+
+    if armed {
+        disarm(&socket, base.as_ref());
+    }
+}
+
+fn arm(socket: &Socket, plan: &KeepalivePlan) -> io::Result<()> {
+    socket.set_tcp_keepalive(&tcp_keepalive(ARMED_PROBE_DELAY, plan))?;
+    if plan.user_timeout.is_some() {
+        set_user_timeout(socket, plan.user_timeout)?;
+    }
+    Ok(())
+}
+
+fn disarm(socket: &Socket, base: Option<&KeepalivePlan>) {
+    // The connection may already be gone; nothing to restore then
+    let _ = set_user_timeout(socket, None);
+    let _ = apply(socket, base);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{TcpKeepaliveSettings, TunnelKeepaliveSettings};
+    use tokio::net::TcpListener;
+
+    fn server_config() -> ServerConfig {
+        ServerConfig {
+            tcp_keepalive: TcpKeepaliveSettings {
+                enabled: true,
+                idle_secs: 600,
+                interval_secs: 60,
+                retries: 5,
+            },
+            tunnel_keepalive: vec![
+                TunnelKeepaliveSettings {
+                    destinations: vec!["*.legacy.example.com".to_string()],
+                    interval_secs: 20,
+                    mode: "tcp".to_string(),
+                    user_timeout_secs: None,
+                },
+                TunnelKeepaliveSettings {
+                    destinations: vec!["10.20.0.0/16".to_string()],
+                    interval_secs: 45,
+                    mode: "probe".to_string(),
+                    user_timeout_secs: Some(90),
+                },
+            ],
+            ..ServerConfig::default()
+        }
+    }
+
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn first_matching_entry_wins_over_global() {
+        let keepalive = TunnelKeepalive::from(&server_config());
+
+        let legacy = keepalive
+            .plan_for(&Address::Domain("app.legacy.example.com".into()))
+            .unwrap();
+        assert_eq!(legacy.mode, KeepaliveMode::Tcp);
+        assert_eq!(legacy.idle, Duration::from_secs(20));
+
+        let probed = keepalive.plan_for(&Address::IPv4([10, 20, 1, 2])).unwrap();
+        assert_eq!(probed.mode, KeepaliveMode::Probe);
+        assert_eq!(probed.user_timeout, Some(Duration::from_secs(90)));
+
+        let other = keepalive
+            .plan_for(&Address::Domain("example.org".into()))
+            .unwrap();
+        assert_eq!(other.idle, Duration::from_secs(600));
+        assert!(TunnelKeepalive::default().is_disabled());
+
+        // Without tcp_keepalive, server.tcp.keepalive_secs covers unmatched tunnels
+        let mut config = server_config();
+        config.tcp_keepalive.enabled = false;
+        config.tcp.keepalive_secs = 120;
+        let other = TunnelKeepalive::from(&config)
+            .plan_for(&Address::Domain("example.org".into()))
+            .unwrap();
+        assert_eq!(other.mode, KeepaliveMode::Tcp);
+        assert_eq!(other.interval, Duration::from_secs(120));
+    }
+
+    #[tokio::test]
+    async fn destination_settings_override_global_socket_options() {
+        let keepalive = TunnelKeepalive::from(&server_config());
+
+        let (matching, _server) = connected_pair().await;
+        keepalive
+            .configure(&matching, &Address::Domain("app.legacy.example.com".into()))
+            .unwrap();
+        let socket = SockRef::from(&matching);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(20));
+        assert_eq!(
+            socket.keepalive_interval().unwrap(),
+            Duration::from_secs(20)
+        );
+        assert_eq!(socket.keepalive_retries().unwrap(), 5);
+
+        let (other, _server) = connected_pair().await;
+        keepalive
+            .configure(&other, &Address::Domain("example.org".into()))
+            .unwrap();
+        let socket = SockRef::from(&other);
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(600));
+        assert_eq!(
+            socket.keepalive_interval().unwrap(),
+            Duration::from_secs(60)
+        );
+
+        // Probe tunnels keep the global settings until they are armed
+        let (probed, _server) = connected_pair().await;
+        keepalive
+            .configure(&probed, &Address::IPv4([10, 20, 1, 2]))
+            .unwrap();
+        assert_eq!(
+            SockRef::from(&probed).keepalive_time().unwrap(),
+            Duration::from_secs(600)
+        );
+    }
+
+    #[tokio::test]
+    async fn probe_arms_after_idle_and_restores_on_stop() {
+        let session_manager = Arc::new(SessionManager::new());
+        let (stream, _server) = connected_pair().await;
+        let plan = KeepalivePlan {
+            mode: KeepaliveMode::Probe,
+            idle: Duration::from_millis(100),
+            interval: Duration::from_secs(5),
+            retries: 3,
+            user_timeout: Some(Duration::from_secs(30)),
+        };
+
+        let probe = TunnelProbe::start(
+            &stream,
+            plan,
+            &TunnelKeepalive::default(),
+            session_manager,
+            Uuid::new_v4(),
+        )
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let socket = SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), ARMED_PROBE_DELAY);
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        assert_eq!(
+            socket.tcp_user_timeout().unwrap(),
+            Some(Duration::from_secs(30))
+        );
+
+        probe.stop().await;
+        assert!(!socket.keepalive().unwrap());
+    }
+}
@@ -1,43 +1,108 @@
-use crate::acl::{load_acl_config_sync, AclEngine, AclStats, AclWatcher};
+use crate::acl::rule_stats::{self, sidecar_path};
+use crate::acl::{
+    load_acl_config_sync, AclEngine, AclMode, AclStats, AclWatcher, RuleStatsPersistence,
+};
 use crate::api::start_api_server;
 use crate::api::types::ApiConfig;
-use crate::auth::AuthManager;
+use crate::auth::{AuthManager, UsersFileWatcher};
 use crate::config::{Config, TlsSettings};
-use crate::qos::QosEngine;
-use crate::server::handler::{handle_client, ClientHandlerContext};
+use crate::qos::{QosEngine, SharedConnectionLimits};
+use crate::server::config_reload::ConfigReloader;
+use crate::server::egress::EgressMap;
+use crate::server::guardrails::{self, spawn_resource_monitor, ResourceGuard};
+use crate::server::handler::{
+    serve_accepted_client, serve_accepted_tls_client, ClientHandlerContext,
+};
+use crate::server::handshake::{HandshakeLimits, HandshakePhase};
+use crate::server::host_hints::HostHints;
+use crate::server::keepalive::TunnelKeepalive;
+use crate::server::overload::{spawn_overload_monitor, LoadShedder, OverloadSignal};
 use crate::server::pool::ConnectionPool;
+use crate::server::probes::ProbeMonitor;
 use crate::server::proxy::TrafficUpdateConfig;
-use crate::session::{start_metrics_collector, MetricsHistory, SessionManager};
+use crate::server::rate_limit::ConnectionRateLimiter;
+use crate::server::resolver::{AddressSelection, CachingResolver, DnsCache, SystemResolver};
+use crate::server::retry::ConnectRetry;
+use crate::server::sni::SniRouting;
+use crate::server::socket_options::UpstreamSocketOptions;
+use crate::server::special_names::SpecialNamesPolicy;
+use crate::server::udp::UdpDatagramLimits;
+use crate::server::upstream_proxy::UpstreamProxy;
+use crate::server::upstream_tls::UpstreamTls;
 #[cfg(feature = "database")]
-use crate::session::{BatchConfig, SessionStore};
+use crate::session::{instance_id, BatchConfig, InstanceLock, SessionStore};
+use crate::session::{start_metrics_collector, MetricsHistory, SessionManager};
 use crate::telemetry::TelemetryHistory;
 use crate::utils::error::{Result, RustSocksError};
+use crate::utils::log_sampling;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use rustls::RootCertStore;
 use rustls_pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
 use std::ffi::OsString;
 use std::fs::File;
 use std::io::BufReader;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpSocket};
 use tokio::sync::Mutex;
-use tokio::task::JoinHandle;
+use tokio::task::{JoinHandle, JoinSet};
 use tokio_rustls::{rustls, TlsAcceptor};
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
+
+/// Pause after a failed accept, e.g. when the process is out of descriptors
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Pending-connection queue length for each SOCKS listener
+const LISTEN_BACKLOG: u32 = 1024;
+
+/// Addresses the SOCKS listeners are bound to, as reported by /health.
+///
+/// Empty until [`SocksServer::run`] has bound them.
+#[derive(Debug, Default)]
+pub struct BoundAddresses(RwLock<Vec<SocketAddr>>);
+
+impl BoundAddresses {
+    pub fn set(&self, addresses: Vec<SocketAddr>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = addresses;
+    }
+
+    pub fn get(&self) -> Vec<SocketAddr> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
 pub struct SocksServer {
     config: Arc<Config>,
     auth_manager: Arc<AuthManager>,
     acl_engine: Option<Arc<AclEngine>>,
     acl_stats: Arc<AclStats>,
-    anonymous_user: Arc<String>,
+    anonymous_user: Arc<str>,
     session_manager: Arc<SessionManager>,
     traffic_config: TrafficUpdateConfig,
     stats_handle: Option<JoinHandle<()>>,
     acl_watcher: Option<Mutex<AclWatcher>>,
+    users_file_watcher: Option<Mutex<UsersFileWatcher>>,
     qos_engine: QosEngine,
+    connection_limits: SharedConnectionLimits,
+    config_reloader: Arc<ConfigReloader>,
+    config_reload_listener: Option<JoinHandle<()>>,
     tls_acceptor: Option<TlsAcceptor>,
+    upstream_tls: Option<Arc<UpstreamTls>>,
+    upstream_proxy: Option<Arc<UpstreamProxy>>,
     connection_pool: Arc<ConnectionPool>,
+    dns_cache: Option<Arc<DnsCache>>,
+    bound_addresses: Arc<BoundAddresses>,
+    probe_monitor: Arc<ProbeMonitor>,
+    rate_limiter: Option<Arc<ConnectionRateLimiter>>,
+    overload: Option<Arc<LoadShedder>>,
+    overload_monitor: Option<JoinHandle<()>>,
+    resource_guard: Option<Arc<ResourceGuard>>,
+    resource_monitor: Option<JoinHandle<()>>,
+    rule_stats_persistence: Option<RuleStatsPersistence>,
+    rule_stats_flusher: Option<JoinHandle<()>>,
+    access_log_flusher: Option<JoinHandle<()>>,
 }
 
 /// Create a `TlsAcceptor` based on the server TLS settings.
@@ -106,7 +171,7 @@ pub fn create_tls_acceptor(tls: &TlsSettings) -> Result<TlsAcceptor> {
     Ok(TlsAcceptor::from(Arc::new(config)))
 }
 
-fn load_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+pub(crate) fn load_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>> {
     let file = File::open(path).map_err(|e| {
         RustSocksError::Config(format!(
             "Failed to open TLS certificate file '{}': {}",
@@ -132,7 +197,7 @@ fn load_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>> {
     Ok(certs)
 }
 
-fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+pub(crate) fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>> {
     let file = File::open(path).map_err(|e| {
         RustSocksError::Config(format!(
             "Failed to open TLS private key file '{}': {}",
@@ -213,11 +278,40 @@ impl SocksServer {
         config_path: Option<PathBuf>,
         original_args: Arc<Vec<OsString>>,
     ) -> Result<Self> {
+        log_sampling::configure(&config.logging.sampling);
+        if config.logging.sampling.connection_events_ratio < 1.0 {
+            info!(
+                ratio = config.logging.sampling.connection_events_ratio,
+                always_log_users = config.logging.sampling.always_log_users.len(),
+                "Sampling per-connection log events"
+            );
+        }
+
         let auth_manager = Arc::new(AuthManager::new(&config.auth)?);
 
+        let mut users_file_watcher = None;
+        if let Some(users_file) = config
+            .auth
+            .users_file
+            .as_ref()
+            .filter(|_| config.auth.uses_socks_method("userpass"))
+        {
+            let mut watcher = UsersFileWatcher::new(
+                PathBuf::from(users_file),
+                auth_manager.clone(),
+                config.auth.password_hashing.clone(),
+            );
+            watcher.start().map_err(|e| {
+                RustSocksError::Config(format!("Failed to start users file watcher: {}", e))
+            })?;
+            users_file_watcher = Some(Mutex::new(watcher));
+        }
+
+        let acl_stats = Arc::new(AclStats::default());
         let mut acl_engine: Option<Arc<AclEngine>> = None;
         let mut acl_watcher: Option<Mutex<AclWatcher>> = None;
         let mut watcher_setup: Option<(PathBuf, Arc<AclEngine>)> = None;
+        let mut acl_file: Option<PathBuf> = None;
 
         if config.acl.enabled {
             let config_path_str = config
@@ -230,10 +324,19 @@ impl SocksServer {
 
             let acl_config = load_acl_config_sync(&config_path).map_err(RustSocksError::Config)?;
 
-            let engine = match AclEngine::new(acl_config) {
+            let engine = match AclEngine::with_lint_settings(acl_config, (&config).into()) {
                 Ok(engine) => {
-                    info!("ACL engine initialized from {}", config_path.display());
-                    Arc::new(engine)
+                    let mode: AclMode = config.acl.mode.parse().unwrap_or_default();
+                    info!(
+                        mode = mode.as_str(),
+                        "ACL engine initialized from {}",
+                        config_path.display()
+                    );
+                    Arc::new(
+                        engine
+                            .with_decision_cache(&config.acl.cache, acl_stats.clone())
+                            .with_mode(mode),
+                    )
                 }
                 Err(e) => {
                     return Err(RustSocksError::Config(format!(
@@ -246,13 +349,14 @@ impl SocksServer {
             if config.acl.watch {
                 watcher_setup = Some((config_path.clone(), engine.clone()));
             }
+            acl_file = Some(config_path);
 
             acl_engine = Some(engine);
         } else {
             info!("ACL engine disabled");
         }
 
-        let anonymous_user = Arc::new(config.acl.anonymous_user.clone());
+        let anonymous_user: Arc<str> = Arc::from(config.acl.anonymous_user.as_str());
         let config = Arc::new(config);
         let config_path_clone = config_path.clone();
         let original_args_clone = original_args.clone();
@@ -262,17 +366,26 @@ impl SocksServer {
         } else {
             None
         };
+        let upstream_tls = UpstreamTls::from_settings(&config.server.upstream_tls)?.map(Arc::new);
+        if upstream_tls.is_some() {
+            info!(
+                destinations = config.server.upstream_tls.destinations.len(),
+                client_certificate = config.server.upstream_tls.client_certificate_path.is_some(),
+                "Originating TLS to matching upstream destinations"
+            );
+        }
+        let upstream_proxy = UpstreamProxy::from_settings(&config.server.upstream).map(Arc::new);
 
-        #[cfg_attr(not(feature = "database"), allow(unused_mut))]
         let mut session_manager_inner = SessionManager::new();
+        session_manager_inner.set_memory_max_sessions(config.sessions.memory_max_sessions);
+        session_manager_inner
+            .events()
+            .set_interval(Duration::from_secs(
+                config.sessions.live_traffic_interval_secs,
+            ));
 
         #[cfg(feature = "database")]
-        if config.sessions.enabled
-            && matches!(
-                config.sessions.storage.as_str(),
-                "sqlite" | "mariadb" | "mysql"
-            )
-        {
+        if config.sessions.enabled && config.sessions.uses_database() {
             let url = config
                 .sessions
                 .database_url
@@ -284,20 +397,33 @@ impl SocksServer {
 
             match SessionStore::connect(&url).await {
                 Ok(store) => {
-                    // Mark all active sessions as closed (they can't still be running after restart)
+                    let lock = InstanceLock {
+                        instance_id: instance_id(),
+                        shared: config.sessions.allow_shared_database,
+                        stale_after: Duration::from_secs(config.sessions.instance_lock_stale_secs),
+                    };
+                    if let Err(e) = store.acquire_instance_lock(lock).await {
+                        return Err(RustSocksError::Config(format!(
+                            "Failed to initialize session store: {}",
+                            e
+                        )));
+                    }
+
+                    // Mark active sessions as closed (they can't still be running after
+                    // restart); in shared mode only those of instances that are gone
                     if let Err(e) = store.close_all_active_sessions().await {
                         warn!(error = %e, "Failed to close stale active sessions on startup");
                     }
 
                     let arc_store = Arc::new(store);
-                    let batch_config = BatchConfig::from_settings(
-                        config.sessions.batch_size,
-                        config.sessions.batch_interval_ms,
-                    );
+                    let batch_config = BatchConfig::from_session_settings(&config.sessions);
                     session_manager_inner.set_store(arc_store.clone(), batch_config);
+                    arc_store
+                        .spawn_instance_heartbeat(config.sessions.instance_heartbeat_interval());
                     arc_store.spawn_cleanup(
                         config.sessions.retention_days,
                         config.sessions.cleanup_interval_hours,
+                        config.sessions.cleanup_batch_size,
                     );
                     info!("Session store initialized at {}", url);
                 }
@@ -312,12 +438,42 @@ impl SocksServer {
 
         let session_manager = Arc::new(session_manager_inner);
 
+        // Rule hit counters go to the session database when there is one, otherwise
+        // to the optional sidecar next to the ACL file
+        let rule_stats_persistence = acl_engine.as_ref().and_then(|_| {
+            #[cfg(feature = "database")]
+            if let Some(store) = session_manager.session_store() {
+                return Some(RuleStatsPersistence::Database(store));
+            }
+            acl_file
+                .as_deref()
+                .filter(|_| config.acl.rule_stats_sidecar)
+                .map(|path| RuleStatsPersistence::Sidecar(sidecar_path(path)))
+        });
+
+        let mut rule_stats_flusher = None;
+        if let (Some(engine), Some(persistence)) = (&acl_engine, &rule_stats_persistence) {
+            rule_stats::restore(&engine.rule_stats(), persistence).await;
+            rule_stats_flusher = Some(rule_stats::spawn_flusher(
+                engine.rule_stats(),
+                persistence.clone(),
+                Duration::from_secs(config.acl.rule_stats_flush_interval_secs),
+            ));
+        }
+
+        let access_log_flusher = acl_engine.as_ref().map(|_| {
+            session_manager
+                .access_log()
+                .spawn_flusher(Duration::from_secs(config.acl.minimal_log_interval_secs))
+        });
+
         if let Some((config_path, engine)) = watcher_setup {
             let mut watcher = AclWatcher::new(
                 config_path.clone(),
                 engine.clone(),
                 Some(session_manager.clone()),
-            );
+            )
+            .with_domain_lists(config.acl.watch_domain_lists);
             watcher.start().await.map_err(|e| {
                 RustSocksError::Config(format!("Failed to start ACL watcher: {}", e))
             })?;
@@ -331,11 +487,18 @@ impl SocksServer {
             acl_engine = Some(engine);
         }
 
+        crate::server::RelayBufferPool::global().set_buffer_bytes(config.server.relay_buffer_bytes);
+
         let traffic_config =
-            TrafficUpdateConfig::new(config.sessions.traffic_update_packet_interval);
+            TrafficUpdateConfig::new(config.sessions.traffic_update_packet_interval)
+                .with_idle_timeout(config.server.idle_timeout_secs);
 
         // Shared connection pool (used by proxy handlers and API telemetry)
-        let pool_config = crate::server::pool::PoolConfig::from(config.server.pool.clone());
+        let mut pool_config = crate::server::pool::PoolConfig::from(config.server.pool.clone());
+        if let Some(secs) = config.server.connect_timeout_secs {
+            // Every direct upstream connect goes through the pool, enabled or not
+            pool_config.connect_timeout_ms = secs.saturating_mul(1000);
+        }
         let telemetry_history = if config.telemetry.enabled {
             info!(
                 max_events = config.telemetry.max_events,
@@ -350,10 +513,25 @@ impl SocksServer {
             info!("Operational telemetry disabled");
             None
         };
-        let connection_pool = Arc::new(ConnectionPool::new_with_telemetry(
-            pool_config,
-            telemetry_history.clone(),
-        ));
+        let connection_pool = Arc::new(
+            ConnectionPool::new_with_telemetry(pool_config, telemetry_history.clone())
+                .with_tcp_tuning((&config.server.tcp).into()),
+        );
+        if let Some(telemetry) = telemetry_history.as_ref() {
+            session_manager.admission().set_telemetry(telemetry.clone());
+        }
+
+        let dns_cache = DnsCache::from_settings(&config.server.dns).map(Arc::new);
+        let bound_addresses = Arc::new(BoundAddresses::default());
+        let probe_monitor = Arc::new(ProbeMonitor::from_settings(&config.diagnostics));
+        if dns_cache.is_some() {
+            info!(
+                ttl_secs = config.server.dns.cache_ttl_secs,
+                negative_ttl_secs = config.server.dns.negative_ttl_secs,
+                max_entries = config.server.dns.cache_max_entries,
+                "DNS cache enabled"
+            );
+        }
         if config.server.pool.enabled {
             info!(
                 max_idle_per_dest = config.server.pool.max_idle_per_dest,
@@ -365,6 +543,73 @@ impl SocksServer {
             info!("Connection pool disabled");
         }
 
+        // Initialize QoS engine (shared with the API for admission dry-runs)
+        let qos_engine = QosEngine::from_config(config.qos.clone()).await?;
+        if qos_engine.is_enabled() {
+            info!("QoS engine initialized and started");
+        }
+
+        let connection_limits = SharedConnectionLimits::new(config.qos.connection_limits.clone());
+        let config_reloader = Arc::new(ConfigReloader::new(
+            config.clone(),
+            config_path.clone(),
+            auth_manager.clone(),
+            qos_engine.clone(),
+            connection_limits.clone(),
+            session_manager.clone(),
+        ));
+
+        let rate_limiter = ConnectionRateLimiter::new(&config.server.rate_limit).map(Arc::new);
+        if rate_limiter.is_some() {
+            info!(
+                connections_per_ip_per_minute =
+                    config.server.rate_limit.connections_per_ip_per_minute,
+                "Per-client-IP connection rate limit enabled"
+            );
+        }
+
+        let (overload, overload_monitor) = if config.server.overload.enabled {
+            let settings = &config.server.overload;
+            let shedder = Arc::new(LoadShedder::new(settings));
+            let monitor = spawn_overload_monitor(
+                shedder.clone(),
+                OverloadSignal::from(settings),
+                Duration::from_millis(settings.sample_interval_ms),
+            );
+            info!(
+                signal = %settings.signal,
+                engage_threshold = settings.engage_threshold,
+                release_threshold = settings.release_threshold,
+                "Overload shedding enabled"
+            );
+            (Some(shedder), Some(monitor))
+        } else {
+            (None, None)
+        };
+
+        guardrails::log_fd_budget(&config.server);
+        let (resource_guard, resource_monitor) = if config.server.guardrails.enabled {
+            let settings = &config.server.guardrails;
+            let guard = Arc::new(ResourceGuard::new(settings));
+            let monitor = spawn_resource_monitor(
+                guard.clone(),
+                connection_pool.clone(),
+                session_manager.clone(),
+                Duration::from_millis(settings.sample_interval_ms),
+            );
+            let status = guard.status();
+            info!(
+                fd_soft_watermark = ?status.fd_soft_watermark,
+                fd_hard_watermark = ?status.fd_hard_watermark,
+                memory_soft_watermark_bytes = ?status.memory_soft_watermark_bytes,
+                memory_hard_watermark_bytes = ?status.memory_hard_watermark_bytes,
+                "Resource guardrails enabled"
+            );
+            (Some(guard), Some(monitor))
+        } else {
+            (None, None)
+        };
+
         let mut stats_handle = None;
 
         if config.sessions.stats_api_enabled {
@@ -376,7 +621,12 @@ impl SocksServer {
                 swagger_enabled: config.sessions.swagger_enabled,
                 dashboard_enabled: config.sessions.dashboard_enabled,
                 dashboard_auth: config.sessions.dashboard_auth.clone(),
+                api_auth: config.sessions.api_auth.clone(),
+                api_compression: config.sessions.api_compression.clone(),
                 base_path: config.sessions.normalized_base_path(),
+                unix_socket: config.sessions.stats_api_unix_socket(),
+                // Validated with the rest of the config
+                unix_socket_mode: config.sessions.api_socket_mode_bits().unwrap_or(0o660),
             };
 
             let acl_config_path = if config.acl.enabled {
@@ -392,14 +642,19 @@ impl SocksServer {
                     as usize;
                 let max_age_hours = config.metrics.retention_hours as i64;
 
-                let history = Arc::new(MetricsHistory::new(max_snapshots, max_age_hours));
+                let mut history = MetricsHistory::new(max_snapshots, max_age_hours);
+                // A database keeps its own rollups, written by the cleanup task
+                if !config.metrics.uses_database() {
+                    history = history.with_rollups(config.metrics.rollup_retention_days);
+                }
+                let history = Arc::new(history);
                 let history_clone = history.clone();
                 let manager_clone = session_manager.clone();
                 let collection_interval = config.metrics.collection_interval_secs;
 
                 // Determine if we should persist to database
                 #[cfg(feature = "database")]
-                let use_database = config.metrics.storage == "sqlite"
+                let use_database = config.metrics.uses_database()
                     && session_manager.as_ref().session_store().is_some();
 
                 #[cfg(not(feature = "database"))]
@@ -435,6 +690,7 @@ impl SocksServer {
                     if let Some(store) = session_manager.as_ref().session_store() {
                         store.spawn_metrics_cleanup(
                             config.metrics.retention_hours,
+                            config.metrics.rollup_retention_days,
                             config.metrics.cleanup_interval_hours,
                         );
                     }
@@ -459,11 +715,21 @@ impl SocksServer {
                 acl_engine.clone(),
                 acl_config_path,
                 connection_pool.clone(),
+                Arc::new(qos_engine.clone()),
                 metrics_history,
                 telemetry_history.clone(),
                 config.clone(),
                 config_path_clone.clone(),
                 original_args_clone.clone(),
+                auth_manager.address_gate(),
+                overload.clone(),
+                resource_guard.clone(),
+                Some(config_reloader.clone()),
+                dns_cache.clone(),
+                Some(bound_addresses.clone()),
+                Some(auth_manager.user_bans()),
+                Some(probe_monitor.clone()),
+                upstream_proxy.clone(),
             )
             .await
             {
@@ -479,40 +745,88 @@ impl SocksServer {
             }
         }
 
-        // Initialize QoS engine
-        let qos_engine = QosEngine::from_config(config.qos.clone()).await?;
-        if qos_engine.is_enabled() {
-            info!("QoS engine initialized and started");
-        }
+        #[cfg(unix)]
+        let config_reload_listener = match config_reloader.path() {
+            Some(path) => {
+                let handle =
+                    crate::server::config_reload::spawn_sighup_listener(config_reloader.clone())?;
+                info!(path = %path.display(), "SIGHUP reloads the configuration file");
+                Some(handle)
+            }
+            None => None,
+        };
+        #[cfg(not(unix))]
+        let config_reload_listener = None;
 
         Ok(Self {
             config,
             auth_manager,
             acl_engine,
-            acl_stats: Arc::new(AclStats::default()),
+            acl_stats,
             anonymous_user,
             session_manager,
             traffic_config,
             stats_handle,
             acl_watcher,
+            users_file_watcher,
             qos_engine,
+            connection_limits,
+            config_reloader,
+            config_reload_listener,
             tls_acceptor,
+            upstream_tls,
+            upstream_proxy,
             connection_pool,
+            dns_cache,
+            bound_addresses,
+            probe_monitor,
+            rate_limiter,
+            overload,
+            overload_monitor,
+            resource_guard,
+            resource_monitor,
+            rule_stats_persistence,
+            rule_stats_flusher,
+            access_log_flusher,
         })
     }
 
+    /// Reloads the config file; also driven by SIGHUP and the admin API
+    pub fn config_reloader(&self) -> Arc<ConfigReloader> {
+        self.config_reloader.clone()
+    }
+
     pub async fn run(&self) -> Result<()> {
-        let bind_addr = format!(
-            "{}:{}",
-            self.config.server.bind_address, self.config.server.bind_port
+        let listeners = match inherited_listeners()? {
+            Some(listeners) => {
+                info!(
+                    count = listeners.len(),
+                    "Using SOCKS listeners inherited from the service manager (LISTEN_FDS); \
+                     server.bind_address(es) and bind_port are ignored"
+                );
+                for listener in &listeners {
+                    if let Ok(address) = listener.local_addr() {
+                        info!("RustSocks server listening on {} (inherited)", address);
+                    }
+                }
+                listeners
+            }
+            None => bind_listeners(
+                &self.config.server.listen_addresses(),
+                self.config.server.reuse_port,
+            )?,
+        };
+        self.bound_addresses.set(
+            listeners
+                .iter()
+                .filter_map(|listener| listener.local_addr().ok())
+                .collect(),
         );
 
-        let listener = TcpListener::bind(&bind_addr).await?;
-
-        info!("RustSocks server listening on {}", bind_addr);
         info!(
             "Authentication methods: client={}, socks={}",
-            self.config.auth.client_method, self.config.auth.socks_method
+            self.config.auth.client_method,
+            self.config.auth.socks_methods().join(",")
         );
         if self.acl_engine.is_some() {
             info!("ACL enforcement enabled");
@@ -523,6 +837,15 @@ impl SocksServer {
             info!("ACL enforcement disabled");
         }
 
+        // Refuse to start with an egress address this host does not have
+        let egress = EgressMap::from_settings(&self.config.server.egress)?;
+        if !egress.is_empty() {
+            info!(
+                rules = self.config.server.egress.rules.len(),
+                "Egress address selection enabled"
+            );
+        }
+
         let handler_ctx = Arc::new(ClientHandlerContext {
             auth_manager: self.auth_manager.clone(),
             acl_engine: self.acl_engine.clone(),
@@ -531,67 +854,152 @@ impl SocksServer {
             session_manager: self.session_manager.clone(),
             traffic_config: self.traffic_config,
             qos_engine: self.qos_engine.clone(),
-            connection_limits: self.config.qos.connection_limits.clone(),
+            connection_limits: self.connection_limits.clone(),
             connection_pool: self.connection_pool.clone(),
+            special_names: SpecialNamesPolicy::from(&self.config.resolver.special_names),
+            sni_routing: SniRouting::from(&self.config.acl),
+            resolver: match &self.dns_cache {
+                Some(cache) => Arc::new(CachingResolver::new(
+                    Arc::new(SystemResolver),
+                    cache.clone(),
+                )),
+                None => Arc::new(SystemResolver),
+            },
+            host_hints: (self.config.sessions.requested_host_ttl_secs > 0).then(|| {
+                Arc::new(HostHints::new(Duration::from_secs(
+                    self.config.sessions.requested_host_ttl_secs,
+                )))
+            }),
+            tunnel_keepalive: Arc::new(TunnelKeepalive::from(&self.config.server)),
+            upstream_socket_options: Arc::new(UpstreamSocketOptions::from(&self.config.server)),
+            egress: Arc::new(egress),
+            upstream_proxy: self.upstream_proxy.clone(),
+            upstream_tls: self.upstream_tls.clone(),
+            udp_association: self
+                .config
+                .server
+                .udp_association_mode
+                .parse()
+                .unwrap_or_default(),
+            udp_datagrams: UdpDatagramLimits::from(&self.config.server.udp),
+            bind_accept_timeout: Duration::from_secs(self.config.server.bind_accept_timeout_secs),
+            enable_socks4: self.config.server.enable_socks4,
+            address_selection: AddressSelection::from_settings(&self.config.server.dns)
+                .unwrap_or_default(),
+            connect_retry: ConnectRetry::from(&self.config.server),
+            handshake: HandshakeLimits::from(&self.config.server),
+            allow_domain_requests: self.config.server.dns.allow_domain_requests,
         });
 
-        let tls_acceptor = self.tls_acceptor.clone();
-
-        loop {
-            match listener.accept().await {
-                Ok((stream, addr)) => {
-                    info!("New connection from {}", addr);
-
-                    // Optimize client TCP socket for low latency and throughput
-                    if let Err(e) = stream.set_nodelay(true) {
-                        warn!("Failed to set TCP_NODELAY on client socket: {}", e);
-                    }
+        // Stopped with the accept loops when this future is dropped
+        let _probes = self.probe_monitor.spawn(handler_ctx.clone());
+        let _upstream_health = self.upstream_proxy.as_ref().map(|proxy| {
+            proxy.spawn_health_checks(self.session_manager.clone(), self.connection_pool.clone())
+        });
 
-                    // Increase buffer sizes for better throughput
-                    let sock_ref = socket2::SockRef::from(&stream);
-                    let _ = sock_ref.set_recv_buffer_size(262144); // 256 KB
-                    let _ = sock_ref.set_send_buffer_size(262144); // 256 KB
-
-                    let ctx = handler_ctx.clone();
-                    let tls_acceptor = tls_acceptor.clone();
-
-                    tokio::spawn(async move {
-                        let result = if let Some(acceptor) = tls_acceptor {
-                            match acceptor.accept(stream).await {
-                                Ok(tls_stream) => handle_client(tls_stream, ctx, addr).await,
-                                Err(e) => {
-                                    error!("TLS handshake failed for {}: {}", addr, e);
-                                    return;
-                                }
-                            }
-                        } else {
-                            handle_client(stream, ctx, addr).await
-                        };
+        // Dropping the set (with this future) aborts every accept loop
+        let mut accept_loops = JoinSet::new();
+        for listener in listeners {
+            accept_loops.spawn(accept_loop(
+                listener,
+                handler_ctx.clone(),
+                AcceptOptions {
+                    tls_acceptor: self.tls_acceptor.clone(),
+                    rate_limiter: self.rate_limiter.clone(),
+                    overload: self.overload.clone(),
+                    resource_guard: self.resource_guard.clone(),
+                },
+            ));
+        }
 
-                        if let Err(e) = result {
-                            error!("Client error from {}: {}", addr, e);
-                        }
-                    });
-                }
-                Err(e) => {
-                    error!("Failed to accept connection: {}", e);
-                }
-            }
+        match accept_loops.join_next().await {
+            Some(Ok(result)) => result,
+            Some(Err(e)) => Err(RustSocksError::Io(std::io::Error::other(e))),
+            None => Ok(()),
         }
     }
 
+    /// Stop background tasks and close the remaining sessions.
+    ///
+    /// Callers drop the [`Self::run`] future first, which closes the listener; active
+    /// sessions then get `server.shutdown_grace_period_secs` to finish before the rest
+    /// are closed with reason "server shutdown" and flushed to the session store.
     pub async fn shutdown(&self) {
+        self.drain_sessions().await;
+
         if let Some(watcher) = &self.acl_watcher {
             let mut watcher = watcher.lock().await;
             watcher.stop();
         }
 
+        if let Some(watcher) = &self.users_file_watcher {
+            let mut watcher = watcher.lock().await;
+            watcher.stop();
+        }
+
         if let Some(handle) = &self.stats_handle {
             handle.abort();
         }
 
-        #[cfg(feature = "database")]
+        if let Some(handle) = &self.config_reload_listener {
+            handle.abort();
+        }
+
+        if let Some(handle) = &self.overload_monitor {
+            handle.abort();
+        }
+
+        if let Some(handle) = &self.resource_monitor {
+            handle.abort();
+        }
+
+        if let Some(handle) = &self.rule_stats_flusher {
+            handle.abort();
+        }
+        if let (Some(engine), Some(persistence)) = (&self.acl_engine, &self.rule_stats_persistence)
+        {
+            rule_stats::flush(&engine.rule_stats(), persistence).await;
+        }
+
+        if let Some(handle) = &self.access_log_flusher {
+            handle.abort();
+        }
+        self.session_manager.access_log().flush_minimal();
+
         self.session_manager.shutdown().await;
+        #[cfg(feature = "database")]
+        if let Some(store) = self.session_manager.session_store() {
+            if let Err(e) = store.release_instance_lock().await {
+                warn!(error = %e, "Failed to release session store lock");
+            }
+        }
+    }
+
+    /// Wait up to `server.shutdown_grace_period_secs` for active sessions to end.
+    ///
+    /// /health reports "draining" from here on.
+    async fn drain_sessions(&self) {
+        self.session_manager.begin_shutdown();
+
+        let active = self.session_manager.active_session_count();
+        let grace = Duration::from_secs(self.config.server.shutdown_grace_period_secs);
+        if active == 0 || grace.is_zero() {
+            return;
+        }
+
+        info!(
+            active,
+            grace_secs = grace.as_secs(),
+            "Draining active sessions before shutdown"
+        );
+        if self.session_manager.wait_for_idle(grace).await {
+            info!("All sessions finished");
+        } else {
+            warn!(
+                remaining = self.session_manager.active_session_count(),
+                "Shutdown grace period elapsed, closing remaining sessions"
+            );
+        }
     }
 
     fn resolve_acl_path(path: &str) -> std::result::Result<PathBuf, RustSocksError> {
@@ -617,3 +1025,289 @@ impl SocksServer {
         })
     }
 }
+
+/// Bind a SOCKS listener on each of `addresses`.
+///
+/// An address that cannot be bound (e.g. IPv6 disabled on the host) is logged and
+/// skipped; binding fails only when none of them could be. When IPv4 and IPv6
+/// addresses are mixed, IPv6 sockets are made IPv6-only so `::` does not also claim
+/// the IPv4 port that `0.0.0.0` is about to bind. With `reuse_port` the sockets get
+/// SO_REUSEPORT where the platform has it, so another process (the next version
+/// during an upgrade) can bind the same port alongside them.
+pub fn bind_listeners(addresses: &[SocketAddr], reuse_port: bool) -> Result<Vec<TcpListener>> {
+    let v6_only =
+        addresses.iter().any(SocketAddr::is_ipv4) && addresses.iter().any(SocketAddr::is_ipv6);
+    let reuse_port = reuse_port && {
+        if !REUSE_PORT_SUPPORTED {
+            warn!("server.reuse_port is not supported on this platform, ignoring it");
+        }
+        REUSE_PORT_SUPPORTED
+    };
+
+    let mut listeners = Vec::with_capacity(addresses.len());
+    let mut last_error = None;
+    for &address in addresses {
+        match bind_listener(address, v6_only, reuse_port) {
+            Ok(listener) => {
+                info!(
+                    reuse_port,
+                    "RustSocks server listening on {}",
+                    listener.local_addr().unwrap_or(address)
+                );
+                listeners.push(listener);
+            }
+            Err(e) => {
+                error!(%address, error = %e, "Failed to bind SOCKS listener");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    if listeners.is_empty() {
+        return Err(match last_error {
+            Some(e) => RustSocksError::Io(e),
+            None => RustSocksError::Config("No SOCKS listen addresses configured".to_string()),
+        });
+    }
+    Ok(listeners)
+}
+
+/// Platforms where tokio can set SO_REUSEPORT
+const REUSE_PORT_SUPPORTED: bool = cfg!(all(
+    unix,
+    not(target_os = "solaris"),
+    not(target_os = "illumos")
+));
+
+fn bind_listener(
+    address: SocketAddr,
+    v6_only: bool,
+    reuse_port: bool,
+) -> std::io::Result<TcpListener> {
+    let socket = if address.is_ipv6() {
+        TcpSocket::new_v6()?
+    } else {
+        TcpSocket::new_v4()?
+    };
+    // Same as TcpListener::bind: allow restarting while old connections sit in TIME_WAIT
+    #[cfg(not(windows))]
+    socket.set_reuseaddr(true)?;
+    #[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+    if reuse_port {
+        socket.set_reuseport(true)?;
+    }
+    #[cfg(not(all(unix, not(target_os = "solaris"), not(target_os = "illumos"))))]
+    let _ = reuse_port;
+    if v6_only && address.is_ipv6() {
+        socket2::SockRef::from(&socket).set_only_v6(true)?;
+    }
+    socket.bind(address)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+/// First descriptor passed by socket activation (`SD_LISTEN_FDS_START`)
+#[cfg(unix)]
+const LISTEN_FDS_START: std::os::unix::io::RawFd = 3;
+
+/// Listening sockets passed by the service manager (systemd socket activation).
+///
+/// They are used when `LISTEN_PID` names this process; `LISTEN_FDS` of them start at
+/// descriptor 3 and replace the configured listen addresses. `None` when nothing was
+/// passed, and always on platforms without socket activation. The variables are left
+/// in place: a child process has another PID, so it ignores them, and the inherited
+/// sockets are marked close-on-exec so a restarted process cannot pick up stale ones.
+pub fn inherited_listeners() -> Result<Option<Vec<TcpListener>>> {
+    #[cfg(unix)]
+    {
+        let count = activation_fd_count(
+            std::env::var("LISTEN_PID").ok().as_deref(),
+            std::env::var("LISTEN_FDS").ok().as_deref(),
+            std::process::id(),
+        );
+        if count == 0 {
+            return Ok(None);
+        }
+        (LISTEN_FDS_START..LISTEN_FDS_START + count as std::os::unix::io::RawFd)
+            .map(inherited_listener)
+            .collect::<Result<Vec<_>>>()
+            .map(Some)
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(None)
+    }
+}
+
+/// Descriptors passed to process `pid`, from the `LISTEN_PID` and `LISTEN_FDS` values
+#[cfg(unix)]
+fn activation_fd_count(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    let for_us = listen_pid
+        .and_then(|value| value.trim().parse::<u32>().ok())
+        .is_some_and(|listen_pid| listen_pid == pid);
+    if !for_us {
+        return 0;
+    }
+    listen_fds
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn inherited_listener(fd: std::os::unix::io::RawFd) -> Result<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let invalid = |reason: &str| {
+        RustSocksError::Config(format!(
+            "Inherited descriptor {} (LISTEN_FDS) {}",
+            fd, reason
+        ))
+    };
+    // SAFETY: LISTEN_PID named this process, so the service manager passed it this
+    // descriptor and nothing else in the process owns it
+    let socket = unsafe { socket2::Socket::from_raw_fd(fd) };
+    if socket.r#type().map_err(|e| invalid(&e.to_string()))? != socket2::Type::STREAM {
+        return Err(invalid("is not a stream socket"));
+    }
+    if socket
+        .local_addr()
+        .ok()
+        .and_then(|address| address.as_socket())
+        .is_none()
+    {
+        return Err(invalid("is not a TCP socket"));
+    }
+    socket.set_cloexec(true)?;
+    socket.set_nonblocking(true)?;
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// TLS and admission checks of an [`accept_loop`]; the default serves plain SOCKS and
+/// admits every client
+#[derive(Clone, Default)]
+pub struct AcceptOptions {
+    /// Terminates TLS before the SOCKS handshake
+    pub tls_acceptor: Option<TlsAcceptor>,
+    /// Per-client-IP budget of new connections
+    pub rate_limiter: Option<Arc<ConnectionRateLimiter>>,
+    /// Sheds new connections while the process is overloaded
+    pub overload: Option<Arc<LoadShedder>>,
+    /// Rejects new connections at a descriptor or memory watermark
+    pub resource_guard: Option<Arc<ResourceGuard>>,
+}
+
+/// Accept clients from `listener` and serve each one on its own task.
+///
+/// A client over its `rate_limiter` budget, or any client while `overload` is
+/// shedding, is closed before TLS or any SOCKS bytes are processed; already-running
+/// sessions are unaffected. `resource_guard` rejects the same way once the process
+/// reaches a descriptor or memory watermark. Past those, a client is only taken on
+/// while fewer than `server.max_concurrent_handshakes` are negotiating, and its TLS and
+/// SOCKS handshake must finish within `server.handshake_timeout_secs`.
+pub async fn accept_loop(
+    listener: TcpListener,
+    handler_ctx: Arc<ClientHandlerContext>,
+    options: AcceptOptions,
+) -> Result<()> {
+    let AcceptOptions {
+        tls_acceptor,
+        rate_limiter,
+        overload,
+        resource_guard,
+    } = options;
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                if let Some(limiter) = &rate_limiter {
+                    if !limiter.admit(addr.ip()) {
+                        debug!("Rate limiting new connection from {}", addr);
+                        drop(stream);
+                        continue;
+                    }
+                }
+                if let Some(shedder) = &overload {
+                    if !shedder.admit() {
+                        debug!("Shedding new connection from {} (overloaded)", addr);
+                        drop(stream);
+                        continue;
+                    }
+                }
+                if let Some(guard) = &resource_guard {
+                    if !guard.admit() {
+                        debug!(
+                            "Rejecting new connection from {} (resource watermark reached)",
+                            addr
+                        );
+                        drop(stream);
+                        continue;
+                    }
+                }
+                let Some(handshake) = handler_ctx.handshake.begin() else {
+                    debug!(
+                        "Rejecting new connection from {} (too many handshakes in progress)",
+                        addr
+                    );
+                    #[cfg(feature = "metrics")]
+                    crate::session::SessionMetrics::record_handshake_rejected();
+                    drop(stream);
+                    continue;
+                };
+
+                if let Err(e) = handler_ctx.connection_pool.tcp_tuning().apply(
+                    &socket2::SockRef::from(&stream),
+                    "client",
+                    addr,
+                ) {
+                    warn!("Failed to set TCP options on client socket: {}", e);
+                }
+
+                let ctx = handler_ctx.clone();
+                let tls_acceptor = tls_acceptor.clone();
+
+                tokio::spawn(log_sampling::connection_scope(async move {
+                    info!("New connection from {}", addr);
+
+                    let result = if let Some(acceptor) = tls_acceptor {
+                        match handshake
+                            .phase(HandshakePhase::Tls, acceptor.accept(stream))
+                            .await
+                        {
+                            Ok(tls_stream) => {
+                                serve_accepted_tls_client(tls_stream, ctx, addr, handshake).await
+                            }
+                            Err(e) => {
+                                error!("TLS handshake failed for {}: {}", addr, e);
+                                return;
+                            }
+                        }
+                    } else {
+                        serve_accepted_client(stream, ctx, addr, handshake).await
+                    };
+
+                    if let Err(e) = result {
+                        error!("Client error from {}: {}", addr, e);
+                    }
+                }));
+            }
+            Err(e) => {
+                error!("Failed to accept connection: {}", e);
+                // EMFILE/ENFILE stay set until descriptors are released; don't spin on them
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn activation_fds_are_only_taken_by_the_named_process() {
+        assert_eq!(activation_fd_count(Some("42"), Some("2"), 42), 2);
+        // Inherited by a child from its activated parent
+        assert_eq!(activation_fd_count(Some("41"), Some("2"), 42), 0);
+        assert_eq!(activation_fd_count(None, Some("2"), 42), 0);
+        assert_eq!(activation_fd_count(Some("42"), None, 42), 0);
+        assert_eq!(activation_fd_count(Some("42"), Some("many"), 42), 0);
+    }
+}
@@ -1,16 +1,56 @@
 pub mod bind;
+pub mod buffer_pool;
+pub mod config_reload;
+pub mod egress;
+pub mod guardrails;
 pub mod handler;
+pub mod handshake;
+pub mod host_hints;
+pub mod keepalive;
 pub mod listener;
+pub mod overload;
 pub mod pool;
+pub mod probes;
 pub mod proxy;
+pub mod rate_limit;
 pub mod resolver;
+pub mod retry;
+pub mod self_test;
+pub mod sni;
+pub mod socket_options;
+pub mod special_names;
 pub mod stats;
 pub mod udp;
+pub mod udp_fragments;
+pub mod upstream_proxy;
+pub mod upstream_tls;
 
 pub use bind::*;
-pub use handler::{handle_client, ClientHandlerContext};
+pub use buffer_pool::{RelayBufferPool, RelayBufferStats, DEFAULT_RELAY_BUFFER_BYTES};
+pub use config_reload::{ConfigReloadReport, ConfigReloader, LogLevelHook};
+pub use egress::{EgressAddresses, EgressMap};
+pub use guardrails::{
+    spawn_resource_monitor, GuardLevel, ResourceGuard, ResourceGuardStatus, FDS_PER_CONNECTION,
+};
+pub use handler::{handle_client, handle_tls_client, ClientHandlerContext};
+pub use handshake::{Handshake, HandshakeLimits, HandshakePhase, DEFAULT_HANDSHAKE_TIMEOUT};
+pub use host_hints::HostHints;
+pub use keepalive::{KeepaliveMode, KeepalivePlan, TunnelKeepalive};
 pub use listener::*;
+pub use overload::{spawn_overload_monitor, LoadShedder, OverloadSignal, OverloadStatus, ShedMode};
 pub use pool::*;
+pub use probes::{ProbeMonitor, ProbeResult, ProbeStatus};
 pub use proxy::*;
+pub use rate_limit::ConnectionRateLimiter;
 pub use resolver::*;
+pub use retry::{ConnectRetry, CONNECT_RETRY_DEADLINE, DEFAULT_CONNECT_RETRY_BACKOFF};
+pub use self_test::{run_self_test, CheckStatus, SelfTestCheck, SelfTestReport};
+pub use sni::{parse_sni, SniFailMode, SniParse, SniRouting};
+pub use socket_options::{
+    SocketOptionPlan, TcpTuning, UpstreamSocketControl, UpstreamSocketOptions,
+};
+pub use special_names::{SpecialNameCategory, SpecialNameDecision, SpecialNamesPolicy};
 pub use udp::*;
+pub use udp_fragments::{FragmentLimits, Reassembler};
+pub use upstream_proxy::{UnhealthyPolicy, UpstreamProxy, UpstreamStatus};
+pub use upstream_tls::{UpstreamStream, UpstreamTls};
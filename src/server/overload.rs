@@ -0,0 +1,334 @@
+//! Load shedding of new connections under overload (`server.overload`).
+//!
+//! A monitor task samples one signal (system CPU or the runtime's global queue depth)
+//! and feeds it to [`LoadShedder::observe`]. The accept loop asks
+//! [`LoadShedder::admit`] before doing any work for a new connection, so a shed client
+//! costs one accept and one close. Established sessions are never touched: once a
+//! connection is admitted, its relay runs exactly as it would without shedding.
+//!
+//! Shedding engages at `engage_threshold` and is sticky until the signal drops below
+//! `release_threshold`, so a signal hovering around one value does not flap.
+
+use crate::config::OverloadSettings;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use sysinfo::{CpuRefreshKind, RefreshKind, System};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Reject ratios are kept in parts per million so they fit an atomic integer.
+const PPM: u64 = 1_000_000;
+
+/// Admin override of the automatic shed decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShedMode {
+    /// Follow the sampled signal
+    Auto,
+    /// Shed at `max_reject_ratio` regardless of the signal
+    ForceOn,
+    /// Never shed
+    ForceOff,
+}
+
+impl ShedMode {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => ShedMode::ForceOn,
+            2 => ShedMode::ForceOff,
+            _ => ShedMode::Auto,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            ShedMode::Auto => 0,
+            ShedMode::ForceOn => 1,
+            ShedMode::ForceOff => 2,
+        }
+    }
+}
+
+/// What the monitor samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverloadSignal {
+    /// System-wide CPU usage in percent
+    Cpu,
+    /// Tasks waiting in the Tokio runtime's global queue
+    RuntimeQueue,
+}
+
+impl From<&OverloadSettings> for OverloadSignal {
+    fn from(settings: &OverloadSettings) -> Self {
+        match settings.signal.as_str() {
+            "runtime_queue" => OverloadSignal::RuntimeQueue,
+            _ => OverloadSignal::Cpu,
+        }
+    }
+}
+
+/// Shed state reported by the health and overload endpoints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverloadStatus {
+    pub mode: ShedMode,
+    pub shedding: bool,
+    /// Last sampled signal value
+    pub signal: f64,
+    /// Fraction of new connections currently rejected
+    pub reject_ratio: f64,
+    pub rejected_connections: u64,
+    /// How many times automatic shedding has engaged
+    pub engagements: u64,
+}
+
+pub struct LoadShedder {
+    engage_threshold: f64,
+    release_threshold: f64,
+    full_threshold: f64,
+    min_reject_ratio: f64,
+    max_reject_ratio: f64,
+    mode: AtomicU8,
+    engaged: AtomicBool,
+    signal_bits: AtomicU64,
+    reject_ppm: AtomicU64,
+    /// Accumulated rejection credit; a connection is shed each time it crosses `PPM`
+    credit: AtomicU64,
+    rejected: AtomicU64,
+    engagements: AtomicU64,
+}
+
+impl LoadShedder {
+    pub fn new(settings: &OverloadSettings) -> Self {
+        Self {
+            engage_threshold: settings.engage_threshold,
+            release_threshold: settings.release_threshold,
+            full_threshold: settings.full_threshold,
+            min_reject_ratio: settings.min_reject_ratio,
+            max_reject_ratio: settings.max_reject_ratio,
+            mode: AtomicU8::new(ShedMode::Auto.as_u8()),
+            engaged: AtomicBool::new(false),
+            signal_bits: AtomicU64::new(0f64.to_bits()),
+            reject_ppm: AtomicU64::new(0),
+            credit: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            engagements: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a new signal sample and update the shed state.
+    pub fn observe(&self, value: f64) {
+        self.signal_bits.store(value.to_bits(), Ordering::Relaxed);
+
+        let was_engaged = self.engaged.load(Ordering::Relaxed);
+        let engaged = if was_engaged {
+            value >= self.release_threshold
+        } else {
+            value >= self.engage_threshold
+        };
+        if engaged != was_engaged {
+            self.engaged.store(engaged, Ordering::Relaxed);
+            if engaged {
+                self.engagements.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    signal = value,
+                    "Overload detected, shedding new connections"
+                );
+            } else {
+                info!(
+                    signal = value,
+                    "Overload cleared, accepting all new connections"
+                );
+            }
+        }
+
+        self.refresh_ratio();
+    }
+
+    /// Decide whether a freshly accepted connection may proceed.
+    ///
+    /// Rejections are spread evenly rather than drawn at random, so the configured
+    /// ratio holds over any run of connections.
+    pub fn admit(&self) -> bool {
+        let ratio = self.reject_ppm.load(Ordering::Relaxed);
+        if ratio == 0 {
+            return true;
+        }
+
+        let previous = self
+            .credit
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |credit| {
+                Some((credit + ratio) % PPM)
+            })
+            .expect("update closure always returns Some");
+        if previous + ratio < PPM {
+            return true;
+        }
+
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        crate::session::SessionMetrics::record_overload_rejection();
+        false
+    }
+
+    pub fn mode(&self) -> ShedMode {
+        ShedMode::from_u8(self.mode.load(Ordering::Relaxed))
+    }
+
+    /// Apply an admin override; takes effect for the next accepted connection.
+    pub fn set_mode(&self, mode: ShedMode) {
+        self.mode.store(mode.as_u8(), Ordering::Relaxed);
+        info!(mode = ?mode, "Overload shedding mode changed");
+        self.refresh_ratio();
+    }
+
+    pub fn is_shedding(&self) -> bool {
+        match self.mode() {
+            ShedMode::Auto => self.engaged.load(Ordering::Relaxed),
+            ShedMode::ForceOn => true,
+            ShedMode::ForceOff => false,
+        }
+    }
+
+    pub fn status(&self) -> OverloadStatus {
+        OverloadStatus {
+            mode: self.mode(),
+            shedding: self.is_shedding(),
+            signal: self.signal(),
+            reject_ratio: self.reject_ppm.load(Ordering::Relaxed) as f64 / PPM as f64,
+            rejected_connections: self.rejected.load(Ordering::Relaxed),
+            engagements: self.engagements.load(Ordering::Relaxed),
+        }
+    }
+
+    fn signal(&self) -> f64 {
+        f64::from_bits(self.signal_bits.load(Ordering::Relaxed))
+    }
+
+    fn refresh_ratio(&self) {
+        let shedding = self.is_shedding();
+        let ratio = match self.mode() {
+            ShedMode::ForceOn => self.max_reject_ratio,
+            _ if shedding => {
+                let severity = ((self.signal() - self.engage_threshold)
+                    / (self.full_threshold - self.engage_threshold))
+                    .clamp(0.0, 1.0);
+                self.min_reject_ratio + (self.max_reject_ratio - self.min_reject_ratio) * severity
+            }
+            _ => 0.0,
+        };
+        self.reject_ppm
+            .store((ratio * PPM as f64).round() as u64, Ordering::Relaxed);
+
+        #[cfg(feature = "metrics")]
+        crate::session::SessionMetrics::set_overload_state(shedding, self.signal());
+    }
+}
+
+/// Sample `signal` every `interval` and feed it to the shedder.
+pub fn spawn_overload_monitor(
+    shedder: Arc<LoadShedder>,
+    signal: OverloadSignal,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut system = match signal {
+            OverloadSignal::Cpu => Some(System::new_with_specifics(
+                RefreshKind::nothing().with_cpu(CpuRefreshKind::nothing().with_cpu_usage()),
+            )),
+            OverloadSignal::RuntimeQueue => None,
+        };
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            let value = match system.as_mut() {
+                Some(system) => {
+                    system.refresh_cpu_usage();
+                    system.global_cpu_usage() as f64
+                }
+                None => tokio::runtime::Handle::current()
+                    .metrics()
+                    .global_queue_depth() as f64,
+            };
+            shedder.observe(value);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shedder() -> LoadShedder {
+        LoadShedder::new(&OverloadSettings {
+            enabled: true,
+            engage_threshold: 80.0,
+            release_threshold: 60.0,
+            full_threshold: 100.0,
+            min_reject_ratio: 0.2,
+            max_reject_ratio: 0.6,
+            ..OverloadSettings::default()
+        })
+    }
+
+    fn rejected_out_of(shedder: &LoadShedder, attempts: usize) -> usize {
+        (0..attempts).filter(|_| !shedder.admit()).count()
+    }
+
+    #[test]
+    fn ratio_ramps_with_severity() {
+        let shedder = shedder();
+        shedder.observe(79.0);
+        assert_eq!(rejected_out_of(&shedder, 100), 0);
+
+        shedder.observe(80.0);
+        assert_eq!(rejected_out_of(&shedder, 100), 20);
+
+        shedder.observe(90.0);
+        assert_eq!(rejected_out_of(&shedder, 100), 40);
+
+        shedder.observe(150.0);
+        assert_eq!(rejected_out_of(&shedder, 100), 60);
+        assert_eq!(shedder.status().rejected_connections, 120);
+    }
+
+    #[test]
+    fn hysteresis_keeps_shedding_until_release() {
+        let shedder = shedder();
+        shedder.observe(85.0);
+        assert!(shedder.is_shedding());
+
+        // Below engage but above release: still shedding at the minimum ratio
+        shedder.observe(70.0);
+        assert!(shedder.is_shedding());
+        assert_eq!(shedder.status().reject_ratio, 0.2);
+
+        shedder.observe(59.0);
+        assert!(!shedder.is_shedding());
+        assert_eq!(rejected_out_of(&shedder, 100), 0);
+
+        // Re-engaging needs the engage threshold again
+        shedder.observe(70.0);
+        assert!(!shedder.is_shedding());
+        shedder.observe(80.0);
+        assert_eq!(shedder.status().engagements, 2);
+    }
+
+    #[test]
+    fn overrides_win_over_the_signal() {
+        let shedder = shedder();
+        shedder.set_mode(ShedMode::ForceOn);
+        assert!(shedder.is_shedding());
+        assert_eq!(rejected_out_of(&shedder, 100), 60);
+
+        shedder.observe(100.0);
+        shedder.set_mode(ShedMode::ForceOff);
+        assert!(!shedder.is_shedding());
+        assert_eq!(rejected_out_of(&shedder, 100), 0);
+
+        shedder.set_mode(ShedMode::Auto);
+        assert!(shedder.is_shedding());
+    }
+}
@@ -1,4 +1,5 @@
 use dashmap::DashMap;
+use futures::FutureExt;
 use serde_json::{json, Value};
 use std::collections::HashSet;
 use std::net::SocketAddr;
@@ -7,9 +8,9 @@ use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
-use tokio::time::timeout;
 use tracing::{debug, trace};
 
+use crate::server::socket_options::TcpTuning;
 use crate::telemetry::{TelemetryHistory, TelemetrySeverity};
 
 /// Configuration for connection pool
@@ -59,6 +60,19 @@ impl PooledConnection {
     fn is_expired(&self, idle_timeout: Duration) -> bool {
         self.last_used.elapsed() > idle_timeout
     }
+
+    /// Whether the idle connection can still be handed to a client.
+    ///
+    /// Never blocks: a pending socket error, a FIN or RST from the upstream, or bytes
+    /// the upstream sent while nobody was listening all rule the connection out.
+    fn is_alive(&self) -> bool {
+        if !matches!(self.stream.take_error(), Ok(None)) {
+            return false;
+        }
+        let mut probe = [0u8; 1];
+        // Nothing to read means the connection is open and quiet
+        self.stream.peek(&mut probe).now_or_never().is_none()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -70,6 +84,7 @@ struct PoolMetrics {
     dropped_full: AtomicU64,
     expired: AtomicU64,
     evicted: AtomicU64,
+    validation_failures: AtomicU64,
     connections_in_use: AtomicU64,
     pending_creates: AtomicU64,
     // New: Track total idle connections atomically to avoid linear scan
@@ -85,6 +100,7 @@ struct DestinationMetrics {
     drops: u64,
     evicted: u64,
     expired: u64,
+    validation_failures: u64,
     in_use: u64,
     last_activity: Option<SystemTime>,
     last_miss: Option<SystemTime>,
@@ -103,6 +119,8 @@ pub struct DestinationPoolStats {
     pub drops: u64,
     pub evicted: u64,
     pub expired: u64,
+    /// Idle connections found closed or broken and dropped
+    pub validation_failures: u64,
     pub last_activity: Option<SystemTime>,
     pub last_miss: Option<SystemTime>,
 }
@@ -116,6 +134,22 @@ pub enum ReuseHint {
     Refresh,
 }
 
+/// Holds one count in `pending_creates` until dropped
+struct PendingCreate<'a>(&'a AtomicU64);
+
+impl<'a> PendingCreate<'a> {
+    fn new(counter: &'a AtomicU64) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for PendingCreate<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 /// Connection pool for upstream TCP connections
 ///
 /// Manages a pool of idle TCP connections to upstream servers, enabling connection reuse
@@ -134,6 +168,7 @@ pub struct ConnectionPool {
     metrics: Arc<PoolMetrics>,
     active_counts: Arc<DashMap<SocketAddr, AtomicUsize>>,
     telemetry: Option<Arc<TelemetryHistory>>,
+    tcp: TcpTuning,
 }
 
 impl ConnectionPool {
@@ -164,6 +199,22 @@ impl ConnectionPool {
         });
     }
 
+    fn record_validation_failures(&self, addr: SocketAddr, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        self.metrics
+            .validation_failures
+            .fetch_add(count as u64, Ordering::Relaxed);
+        self.metrics.total_idle.fetch_sub(count, Ordering::Relaxed);
+
+        self.update_destination_metrics(addr, |entry| {
+            entry.validation_failures += count as u64;
+            entry.last_activity = Some(SystemTime::now());
+        });
+    }
+
     fn record_evicted(&self, addr: SocketAddr) {
         self.metrics.evicted.fetch_add(1, Ordering::Relaxed);
 
@@ -295,6 +346,7 @@ impl ConnectionPool {
             metrics,
             active_counts: Arc::new(DashMap::new()),
             telemetry,
+            tcp: TcpTuning::default(),
         };
 
         if enabled {
@@ -304,6 +356,17 @@ impl ConnectionPool {
         pool
     }
 
+    /// Use `tcp` for the upstream connections this pool opens.
+    pub fn with_tcp_tuning(mut self, tcp: TcpTuning) -> Self {
+        self.tcp = tcp;
+        self
+    }
+
+    /// TCP options for client and upstream sockets (`[server.tcp]`).
+    pub fn tcp_tuning(&self) -> &TcpTuning {
+        &self.tcp
+    }
+
     /// Get a connection from the pool or create a new one
     ///
     /// # Arguments
@@ -348,9 +411,7 @@ impl ConnectionPool {
             entry.last_miss = Some(miss_time);
         });
 
-        self.metrics.pending_creates.fetch_add(1, Ordering::Relaxed);
-        let result = self.connect_new(addr).await;
-        self.metrics.pending_creates.fetch_sub(1, Ordering::Relaxed);
+        let result = self.connect_counted(addr).await;
 
         if let Err(ref err) = result {
             let message = format!("Failed to connect to {}: {}", addr, err);
@@ -493,6 +554,7 @@ impl ConnectionPool {
 
         let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
         let mut expired = 0usize;
+        let mut failed = 0usize;
         let mut stream: Option<TcpStream> = None;
 
         while let Some(mut conn) = pool_entry.pop() {
@@ -505,6 +567,15 @@ impl ConnectionPool {
                 expired += 1;
                 continue;
             }
+            if !conn.is_alive() {
+                debug!(
+                    "Discarding pooled connection to {} closed by the upstream (idle: {:?})",
+                    addr,
+                    conn.last_used.elapsed()
+                );
+                failed += 1;
+                continue;
+            }
 
             // Update last_used time
             conn.last_used = Instant::now();
@@ -526,9 +597,8 @@ impl ConnectionPool {
             self.metrics.total_idle.fetch_sub(1, Ordering::Relaxed);
         }
 
-        if expired > 0 {
-            self.record_expired(addr, expired);
-        }
+        self.record_expired(addr, expired);
+        self.record_validation_failures(addr, failed);
 
         stream
     }
@@ -581,9 +651,7 @@ impl ConnectionPool {
 
     /// Establish a fresh upstream connection and add it to the pool.
     async fn refresh_connection(&self, addr: SocketAddr) -> std::io::Result<()> {
-        self.metrics.pending_creates.fetch_add(1, Ordering::Relaxed);
-        let result = self.connect_new(addr).await;
-        self.metrics.pending_creates.fetch_sub(1, Ordering::Relaxed);
+        let result = self.connect_counted(addr).await;
 
         let stream = result?;
 
@@ -624,21 +692,21 @@ impl ConnectionPool {
         Ok(())
     }
 
+    /// Timeout for establishing upstream connections.
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.connect_timeout_ms)
+    }
+
+    /// [`Self::connect_new`], counted in `pending_creates` while it runs. The count is
+    /// taken back on drop too, since happy eyeballs abandons the attempts that lose.
+    async fn connect_counted(&self, addr: SocketAddr) -> std::io::Result<TcpStream> {
+        let _pending = PendingCreate::new(&self.metrics.pending_creates);
+        self.connect_new(addr).await
+    }
+
     /// Create a new TCP connection with timeout
     async fn connect_new(&self, addr: SocketAddr) -> std::io::Result<TcpStream> {
-        let connect_timeout = Duration::from_millis(self.config.connect_timeout_ms);
-
-        match timeout(connect_timeout, TcpStream::connect(addr)).await {
-            Ok(Ok(stream)) => Ok(stream),
-            Ok(Err(e)) => Err(e),
-            Err(_) => Err(std::io::Error::new(
-                std::io::ErrorKind::TimedOut,
-                format!(
-                    "Connection to {} timed out after {:?}",
-                    addr, connect_timeout
-                ),
-            )),
-        }
+        self.tcp.connect(addr, self.connect_timeout()).await
     }
 
     /// Evict the oldest connection from all pools
@@ -676,30 +744,49 @@ impl ConnectionPool {
         oldest_addr
     }
 
-    /// Clean up expired connections (called periodically)
+    /// Drop expired and dead idle connections; the background sweeper runs the same
+    /// pass periodically.
     #[allow(dead_code)]
     fn cleanup_expired(&self) {
-        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
-        let mut total_removed = 0;
-
-        // DashMap retain provides atomic per-key cleanup
-        self.pools.retain(|addr, pool| {
-            let original_len = pool.len();
-            pool.retain(|conn| !conn.is_expired(idle_timeout));
-            let removed = original_len - pool.len();
-
-            if removed > 0 {
-                trace!("Cleaned up {} expired connections to {}", removed, addr);
-                total_removed += removed;
-                self.record_expired(*addr, removed);
-            }
+        sweep_idle(
+            &self.pools,
+            &self.destination_metrics,
+            &self.metrics,
+            Duration::from_secs(self.config.idle_timeout_secs),
+        );
+    }
 
-            !pool.is_empty()
-        });
+    /// Drop every idle connection to `addr` immediately, e.g. when the destination is
+    /// known to be unhealthy. Connections currently in use are not affected.
+    ///
+    /// Returns the number of idle connections torn down.
+    pub fn purge_destination(&self, addr: SocketAddr) -> usize {
+        let removed = self
+            .pools
+            .remove(&addr)
+            .map(|(_, connections)| connections.len())
+            .unwrap_or(0);
 
-        if total_removed > 0 {
-            debug!("Cleanup removed {} expired connections", total_removed);
+        for _ in 0..removed {
+            self.record_evicted(addr);
         }
+        if removed > 0 {
+            debug!("Purged {} idle connections to {}", removed, addr);
+        }
+
+        removed
+    }
+
+    /// Drop every idle connection, e.g. to give descriptors back under resource pressure.
+    /// Connections currently in use are not affected.
+    ///
+    /// Returns the number of idle connections torn down.
+    pub fn purge_idle(&self) -> usize {
+        let destinations: Vec<SocketAddr> = self.pools.iter().map(|entry| *entry.key()).collect();
+        destinations
+            .into_iter()
+            .map(|addr| self.purge_destination(addr))
+            .sum()
     }
 
     /// Get pool statistics
@@ -740,6 +827,7 @@ impl ConnectionPool {
                 drops: entry.drops,
                 evicted: entry.evicted,
                 expired: entry.expired,
+                validation_failures: entry.validation_failures,
                 last_activity: entry.last_activity,
                 last_miss: entry.last_miss,
             });
@@ -772,6 +860,7 @@ impl ConnectionPool {
             dropped_full: self.metrics.dropped_full.load(Ordering::Relaxed),
             expired: self.metrics.expired.load(Ordering::Relaxed),
             evicted: self.metrics.evicted.load(Ordering::Relaxed),
+            validation_failures: self.metrics.validation_failures.load(Ordering::Relaxed),
             connections_in_use,
             pending_creates: self.metrics.pending_creates.load(Ordering::Relaxed),
             per_destination,
@@ -794,45 +883,77 @@ impl ConnectionPool {
 
             loop {
                 interval.tick().await;
+                sweep_idle(
+                    &pools,
+                    &destination_metrics,
+                    &metrics,
+                    Duration::from_secs(idle_timeout_secs),
+                );
+            }
+        });
+    }
+}
 
-                let idle_timeout = Duration::from_secs(idle_timeout_secs);
-                let mut total_removed = 0;
-
-                // DashMap retain provides atomic per-key cleanup
-                pools.retain(|addr, pool| {
-                    let original_len = pool.len();
-                    pool.retain(|conn| !conn.is_expired(idle_timeout));
-                    let removed = original_len - pool.len();
-
-                    if removed > 0 {
-                        trace!(
-                            "Cleanup: removed {} expired connections to {}",
-                            removed,
-                            addr
-                        );
-                        total_removed += removed;
-
-                        // Update metrics atomically
-                        metrics.expired.fetch_add(removed as u64, Ordering::Relaxed);
-                        metrics.total_idle.fetch_sub(removed, Ordering::Relaxed);
+/// One sweep over the idle pools: drop connections idle past `idle_timeout` and those
+/// the upstream has closed in the meantime, so they are gone before a client asks.
+fn sweep_idle(
+    pools: &DashMap<SocketAddr, Vec<PooledConnection>>,
+    destination_metrics: &DashMap<SocketAddr, DestinationMetrics>,
+    metrics: &PoolMetrics,
+    idle_timeout: Duration,
+) {
+    let mut total_expired = 0;
+    let mut total_failed = 0;
+
+    // DashMap retain provides atomic per-key cleanup
+    pools.retain(|addr, pool| {
+        let (mut expired, mut failed) = (0usize, 0usize);
+        pool.retain(|conn| {
+            if conn.is_expired(idle_timeout) {
+                expired += 1;
+                false
+            } else if !conn.is_alive() {
+                failed += 1;
+                false
+            } else {
+                true
+            }
+        });
 
-                        // Update destination metrics
-                        let mut entry = destination_metrics.entry(*addr).or_default();
-                        entry.expired += removed as u64;
-                        entry.last_activity = Some(SystemTime::now());
-                    }
+        if expired + failed > 0 {
+            trace!(
+                "Cleanup: removed {} expired and {} dead connections to {}",
+                expired,
+                failed,
+                addr
+            );
+            total_expired += expired;
+            total_failed += failed;
+
+            // Update metrics atomically
+            metrics.expired.fetch_add(expired as u64, Ordering::Relaxed);
+            metrics
+                .validation_failures
+                .fetch_add(failed as u64, Ordering::Relaxed);
+            metrics
+                .total_idle
+                .fetch_sub(expired + failed, Ordering::Relaxed);
+
+            // Update destination metrics
+            let mut entry = destination_metrics.entry(*addr).or_default();
+            entry.expired += expired as u64;
+            entry.validation_failures += failed as u64;
+            entry.last_activity = Some(SystemTime::now());
+        }
 
-                    !pool.is_empty()
-                });
+        !pool.is_empty()
+    });
 
-                if total_removed > 0 {
-                    debug!(
-                        "Periodic cleanup removed {} expired connections",
-                        total_removed
-                    );
-                }
-            }
-        });
+    if total_expired + total_failed > 0 {
+        debug!(
+            "Periodic cleanup removed {} expired and {} dead connections",
+            total_expired, total_failed
+        );
     }
 }
 
@@ -861,6 +982,8 @@ pub struct PoolStats {
     pub expired: u64,
     /// Connections evicted due to global cap
     pub evicted: u64,
+    /// Idle connections dropped because the upstream had closed them
+    pub validation_failures: u64,
     /// Connections currently being created
     pub pending_creates: u64,
     /// Detailed stats per destination
@@ -904,6 +1027,32 @@ mod tests {
         assert!(stream.peer_addr().is_ok());
     }
 
+    #[tokio::test]
+    async fn purge_destination_drops_idle_connections() {
+        let config = PoolConfig {
+            enabled: true,
+            max_idle_per_dest: 2,
+            ..Default::default()
+        };
+        let pool = Arc::new(ConnectionPool::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let pool_clone = Arc::clone(&pool);
+        let conn_task = tokio::spawn(async move { pool_clone.get(addr).await });
+        let (_server_stream, _) = listener.accept().await.unwrap();
+        let client_stream = conn_task.await.unwrap().unwrap();
+        pool.put(addr, client_stream, ReuseHint::Reuse).await;
+        assert_eq!(pool.stats().total_idle, 1);
+
+        assert_eq!(pool.purge_destination(addr), 1);
+        let stats = pool.stats();
+        assert_eq!(stats.total_idle, 0);
+        assert_eq!(stats.evicted, 1);
+        assert_eq!(pool.purge_destination(addr), 0);
+    }
+
     #[tokio::test]
     async fn pool_reuses_connections() {
         let config = PoolConfig {
@@ -1039,4 +1188,32 @@ mod tests {
             );
         }
     }
+
+    #[tokio::test]
+    async fn cleanup_drops_connections_closed_by_upstream() {
+        let pool = ConnectionPool::new(PoolConfig {
+            enabled: true,
+            ..Default::default()
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut server_streams = Vec::new();
+        for _ in 0..2 {
+            let connect_task = tokio::spawn(async move { TcpStream::connect(addr).await });
+            let (server_stream, _) = listener.accept().await.unwrap();
+            let client_stream = connect_task.await.unwrap().unwrap();
+            pool.insert_stream(addr, client_stream);
+            server_streams.push(server_stream);
+        }
+
+        drop(server_streams.pop());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        pool.cleanup_expired();
+
+        let stats = pool.stats();
+        assert_eq!(stats.total_idle, 1);
+        assert_eq!(stats.validation_failures, 1);
+        assert_eq!(stats.expired, 0);
+    }
 }
@@ -0,0 +1,310 @@
+//! Proactive upstream connectivity probes (`[[diagnostics.probes]]`).
+//!
+//! Each probe opens a connection to its destination every `interval_secs` through the
+//! same special-name, ACL, resolve and connect path a CONNECT takes, as the synthetic
+//! `diagnostics.probe_user`, so "the proxy cannot reach X" shows up before a user
+//! reports it. The most recent results of every probe are kept in a ring buffer for
+//! `GET /api/diagnostics/probes`, and the latest one is exported as
+//! `rustsocks_probe_up{probe}` and `rustsocks_probe_latency_seconds{probe}`.
+
+use crate::config::DiagnosticsSettings;
+use crate::protocol::Address;
+use crate::server::handler::{probe_connect, ClientHandlerContext};
+use crate::session::FailureCategory;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+/// Result of one probe connection, before it is timed and recorded
+pub struct ProbeAttempt {
+    /// Address connected to; the parent proxy when chained
+    pub outcome: std::result::Result<SocketAddr, ProbeError>,
+    /// ACL rule that decided, if an ACL is enabled
+    pub acl_rule: Option<String>,
+    pub chained: bool,
+}
+
+pub struct ProbeError {
+    pub category: FailureCategory,
+    pub message: String,
+}
+
+impl ProbeAttempt {
+    pub(crate) fn failed(
+        category: FailureCategory,
+        message: String,
+        acl_rule: Option<String>,
+    ) -> Self {
+        Self {
+            outcome: Err(ProbeError { category, message }),
+            acl_rule,
+            chained: false,
+        }
+    }
+}
+
+/// One check of a probe
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub timestamp: DateTime<Utc>,
+    pub success: bool,
+    /// Time from the start of the ACL check to the connect completing or failing
+    pub latency_ms: u64,
+    /// Why the probe failed; `None` on success
+    pub category: Option<FailureCategory>,
+    pub error: Option<String>,
+    pub acl_rule: Option<String>,
+    /// Address connected to; the parent proxy when chained
+    pub connected_addr: Option<SocketAddr>,
+    pub chained: bool,
+}
+
+/// A probe's configuration, current state and recent results, newest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeStatus {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub interval_secs: u64,
+    /// Outcome of the latest check; `None` until the first one finishes
+    pub up: Option<bool>,
+    pub consecutive_failures: u64,
+    pub checks_total: u64,
+    pub failures_total: u64,
+    pub history: Vec<ProbeResult>,
+}
+
+#[derive(Debug, Default)]
+struct ProbeCounters {
+    history: VecDeque<ProbeResult>,
+    consecutive_failures: u64,
+    checks_total: u64,
+    failures_total: u64,
+}
+
+#[derive(Debug)]
+struct Probe {
+    name: String,
+    host: String,
+    port: u16,
+    interval: Duration,
+    counters: Mutex<ProbeCounters>,
+}
+
+/// The configured probes and their recent results.
+///
+/// Built with the API before the SOCKS listener exists; [`Self::spawn`] starts the
+/// checks once [`SocksServer::run`](crate::server::SocksServer::run) has the handler
+/// context they connect through.
+#[derive(Debug)]
+pub struct ProbeMonitor {
+    user: Arc<str>,
+    groups: Vec<String>,
+    history: usize,
+    probes: Vec<Probe>,
+}
+
+impl ProbeMonitor {
+    pub fn from_settings(settings: &DiagnosticsSettings) -> Self {
+        Self {
+            user: Arc::from(settings.probe_user.as_str()),
+            groups: settings.probe_groups.clone(),
+            history: settings.probe_history.max(1),
+            probes: settings
+                .probes
+                .iter()
+                .map(|target| Probe {
+                    name: target.display_name(),
+                    host: target.host.clone(),
+                    port: target.port,
+                    interval: Duration::from_secs(target.interval_secs.max(1)),
+                    counters: Mutex::new(ProbeCounters::default()),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probes.is_empty()
+    }
+
+    /// User the probes connect as
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn statuses(&self) -> Vec<ProbeStatus> {
+        self.probes
+            .iter()
+            .map(|probe| {
+                let counters = probe.counters.lock().unwrap_or_else(|e| e.into_inner());
+                ProbeStatus {
+                    name: probe.name.clone(),
+                    host: probe.host.clone(),
+                    port: probe.port,
+                    interval_secs: probe.interval.as_secs(),
+                    up: counters.history.front().map(|result| result.success),
+                    consecutive_failures: counters.consecutive_failures,
+                    checks_total: counters.checks_total,
+                    failures_total: counters.failures_total,
+                    history: counters.history.iter().cloned().collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Start checking every probe on its interval, the first check right away.
+    ///
+    /// Dropping the returned set stops the checks.
+    pub fn spawn(self: &Arc<Self>, ctx: Arc<ClientHandlerContext>) -> JoinSet<()> {
+        let mut tasks = JoinSet::new();
+        if self.is_empty() {
+            return tasks;
+        }
+        info!(
+            probes = self.probes.len(),
+            user = %self.user,
+            "Connectivity probes started"
+        );
+        for index in 0..self.probes.len() {
+            let monitor = self.clone();
+            let ctx = ctx.clone();
+            tasks.spawn(async move {
+                let mut ticker = tokio::time::interval(monitor.probes[index].interval);
+                ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    ticker.tick().await;
+                    monitor.check(index, &ctx).await;
+                }
+            });
+        }
+        tasks
+    }
+
+    /// Run probe `index` once and record the result.
+    pub async fn check(&self, index: usize, ctx: &ClientHandlerContext) -> ProbeResult {
+        let probe = &self.probes[index];
+        let dest = match probe.host.parse::<IpAddr>() {
+            Ok(ip) => Address::bound(ip),
+            Err(_) => Address::Domain(probe.host.as_str().into()),
+        };
+
+        let started = Instant::now();
+        let attempt = probe_connect(ctx, &self.user, &self.groups, &dest, probe.port).await;
+        let latency = started.elapsed();
+
+        let (success, connected_addr, category, error) = match attempt.outcome {
+            Ok(addr) => (true, Some(addr), None, None),
+            Err(e) => (false, None, Some(e.category), Some(e.message)),
+        };
+        let result = ProbeResult {
+            timestamp: Utc::now(),
+            success,
+            latency_ms: latency.as_millis() as u64,
+            category,
+            error,
+            acl_rule: attempt.acl_rule,
+            connected_addr,
+            chained: attempt.chained,
+        };
+        self.record(probe, &result);
+
+        #[cfg(feature = "metrics")]
+        crate::session::SessionMetrics::record_probe(&probe.name, success, latency);
+
+        result
+    }
+
+    fn record(&self, probe: &Probe, result: &ProbeResult) {
+        let mut counters = probe.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters.checks_total += 1;
+        if result.success {
+            if counters.consecutive_failures > 0 {
+                info!(
+                    probe = %probe.name,
+                    failures = counters.consecutive_failures,
+                    "Connectivity probe recovered"
+                );
+            }
+            counters.consecutive_failures = 0;
+            debug!(
+                probe = %probe.name,
+                latency_ms = result.latency_ms,
+                "Connectivity probe succeeded"
+            );
+        } else {
+            counters.failures_total += 1;
+            counters.consecutive_failures += 1;
+            // Logged when the probe goes down, not on every failed check
+            if counters.consecutive_failures == 1 {
+                warn!(
+                    probe = %probe.name,
+                    category = result.category.map(FailureCategory::as_str).unwrap_or("-"),
+                    error = result.error.as_deref().unwrap_or("-"),
+                    "Connectivity probe failed"
+                );
+            }
+        }
+        counters.history.push_front(result.clone());
+        counters.history.truncate(self.history);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeTarget;
+
+    fn result(success: bool) -> ProbeResult {
+        ProbeResult {
+            timestamp: Utc::now(),
+            success,
+            latency_ms: 3,
+            category: (!success).then_some(FailureCategory::ConnectRefused),
+            error: None,
+            acl_rule: None,
+            connected_addr: None,
+            chained: false,
+        }
+    }
+
+    #[test]
+    fn history_is_bounded_and_newest_first() {
+        let monitor = ProbeMonitor::from_settings(&DiagnosticsSettings {
+            probe_history: 2,
+            probes: vec![ProbeTarget {
+                name: None,
+                host: "example.com".to_string(),
+                port: 443,
+                interval_secs: 30,
+            }],
+            ..Default::default()
+        });
+        assert_eq!(monitor.statuses()[0].up, None);
+
+        let probe = &monitor.probes[0];
+        monitor.record(probe, &result(true));
+        monitor.record(probe, &result(false));
+        monitor.record(probe, &result(false));
+
+        let status = &monitor.statuses()[0];
+        assert_eq!(status.name, "example.com:443");
+        assert_eq!(status.up, Some(false));
+        assert_eq!(status.history.len(), 2);
+        assert_eq!(status.checks_total, 3);
+        assert_eq!(status.failures_total, 2);
+        assert_eq!(status.consecutive_failures, 2);
+
+        monitor.record(probe, &result(true));
+        let status = &monitor.statuses()[0];
+        assert_eq!(status.up, Some(true));
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(status.history[0].success);
+        assert!(!status.history[1].success);
+    }
+}
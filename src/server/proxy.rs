@@ -1,37 +1,51 @@
 use crate::qos::{QosEngine, QosMetrics};
+use crate::server::buffer_pool::RelayBufferPool;
+use crate::server::keepalive::TunnelActivity;
 use crate::server::pool::ReuseHint;
 use crate::session::SessionManager;
 use crate::utils::error::{Result, RustSocksError};
 use std::io;
 use std::io::ErrorKind;
+use std::net::IpAddr;
 use std::num::NonZeroU64;
 use std::sync::Arc;
-use tokio::io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf, ReuniteError};
+use std::time::Duration;
+use tokio::io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, WriteHalf};
+use tokio::net::tcp::ReuniteError;
 use tokio::net::TcpStream;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, instrument, trace};
+use tracing::{debug, error, info, instrument, trace};
 use uuid::Uuid;
 
-// Increased from 16KB to 32KB for better throughput on large transfers
-// Reduces syscalls by 50% for large file transfers
-const BUFFER_SIZE: usize = 32 * 1024;
-
 #[derive(Debug, Clone, Copy)]
 pub struct TrafficUpdateConfig {
     packet_interval: NonZeroU64,
+    idle_timeout: Option<Duration>,
 }
 
 impl TrafficUpdateConfig {
     pub fn new(packet_interval: u64) -> Self {
         let fallback = NonZeroU64::new(1).expect("1 is non-zero");
         let packet_interval = NonZeroU64::new(packet_interval).unwrap_or(fallback);
-        Self { packet_interval }
+        Self {
+            packet_interval,
+            idle_timeout: None,
+        }
+    }
+
+    /// Close the relay after `secs` without traffic in either direction (0 = never).
+    pub fn with_idle_timeout(mut self, secs: u64) -> Self {
+        self.idle_timeout = (secs > 0).then(|| Duration::from_secs(secs));
+        self
     }
 
     pub fn packet_interval(&self) -> NonZeroU64 {
         self.packet_interval
     }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
 }
 
 impl Default for TrafficUpdateConfig {
@@ -64,15 +78,15 @@ struct TrafficTotals {
     packets: u64,
 }
 
-struct UploadResult {
+struct UploadResult<U> {
     totals: TrafficTotals,
-    write_half: OwnedWriteHalf,
+    write_half: U,
     client_closed: bool,
 }
 
-struct DownloadResult<W> {
+struct DownloadResult<D, W> {
     totals: TrafficTotals,
-    read_half: OwnedReadHalf,
+    read_half: D,
     remote_closed: bool,
     _writer: W,
 }
@@ -84,6 +98,10 @@ pub struct UpstreamReuse {
 }
 
 /// Proxy data bidirectionally between client and upstream server while tracking traffic.
+///
+/// With an idle timeout configured, a relay that moves no bytes in either direction for
+/// that long is torn down and reported as `RustSocksError::IdleTimeout`; both sockets
+/// are closed as the halves are dropped.
 #[allow(clippy::too_many_arguments)]
 #[instrument(
     level = "debug",
@@ -99,85 +117,196 @@ pub async fn proxy_data<S>(
     update_config: TrafficUpdateConfig,
     qos_engine: QosEngine,
     user: Arc<str>,
+    source_ip: IpAddr,
 ) -> Result<Option<UpstreamReuse>>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    let (client_read, client_write) = split(client);
     let (upstream_read, upstream_write) = upstream.into_split();
-
-    let upload_handle = tokio::spawn(proxy_upload(
-        client_read,
+    let (up, down) = relay(
+        client,
+        upstream_read,
         upstream_write,
-        session_manager.clone(),
+        session_manager,
         session_id,
-        cancel_token.clone(),
+        cancel_token,
         update_config,
-        qos_engine.clone(),
-        Arc::clone(&user),
-    ));
+        qos_engine,
+        user,
+        source_ip,
+    )
+    .await?;
+    let UploadResult {
+        totals: up_totals,
+        write_half,
+        client_closed,
+    } = up;
+    let DownloadResult {
+        totals: down_totals,
+        read_half,
+        remote_closed,
+        ..
+    } = down;
+
+    match read_half.reunite(write_half) {
+        Ok(mut stream) => {
+            let hint = if client_closed
+                && !remote_closed
+                && up_totals.bytes == 0
+                && down_totals.bytes == 0
+            {
+                ReuseHint::Reuse
+            } else {
+                ReuseHint::Refresh
+            };
+
+            if matches!(hint, ReuseHint::Refresh) {
+                if let Err(e) = stream.shutdown().await {
+                    trace!("Failed to shutdown upstream stream before refresh: {}", e);
+                }
+            }
 
-    let download_handle = tokio::spawn(proxy_download(
+            Ok(Some(UpstreamReuse { stream, hint }))
+        }
+        Err(ReuniteError(read, write)) => {
+            drop(read);
+            drop(write);
+            Ok(None)
+        }
+    }
+}
+
+/// Proxy data between client and an upstream stream other than a plain `TcpStream`,
+/// such as a TLS session to the destination (`[server.upstream_tls]`).
+///
+/// Traffic is tracked and limited as in [`proxy_data`], but the upstream stream is shut
+/// down at the end instead of being offered back to the pool.
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    level = "debug",
+    skip(client, upstream, session_manager, cancel_token, qos_engine, user),
+    fields(session = %session_id, user = %user)
+)]
+pub async fn proxy_stream<S, U>(
+    client: S,
+    upstream: U,
+    session_manager: Arc<SessionManager>,
+    session_id: Uuid,
+    cancel_token: CancellationToken,
+    update_config: TrafficUpdateConfig,
+    qos_engine: QosEngine,
+    user: Arc<str>,
+    source_ip: IpAddr,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    U: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (upstream_read, upstream_write) = split(upstream);
+    let (up, _down) = relay(
+        client,
         upstream_read,
-        client_write,
+        upstream_write,
         session_manager,
         session_id,
         cancel_token,
         update_config,
         qos_engine,
         user,
-    ));
+        source_ip,
+    )
+    .await?;
+
+    // A TLS destination sees close_notify rather than a truncated stream
+    let mut write_half = up.write_half;
+    if let Err(e) = write_half.shutdown().await {
+        trace!("Failed to shutdown upstream stream: {}", e);
+    }
+    Ok(())
+}
 
-    let (upload_result, download_result) = tokio::join!(upload_handle, download_handle);
+/// Run both directions of a tunnel until they end, the relay idles out or the session
+/// is terminated; the upstream halves come back on a clean finish.
+#[allow(clippy::too_many_arguments)]
+async fn relay<S, D, U>(
+    client: S,
+    upstream_read: D,
+    upstream_write: U,
+    session_manager: Arc<SessionManager>,
+    session_id: Uuid,
+    cancel_token: CancellationToken,
+    update_config: TrafficUpdateConfig,
+    qos_engine: QosEngine,
+    user: Arc<str>,
+    source_ip: IpAddr,
+) -> Result<(UploadResult<U>, DownloadResult<D, WriteHalf<S>>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    D: AsyncRead + Unpin + Send,
+    U: AsyncWrite + Unpin + Send,
+{
+    let (client_read, client_write) = split(client);
+    let activity = update_config.idle_timeout().map(|_| TunnelActivity::new());
+    // The directions stop each other through a child token, so a half that ends only
+    // interrupts the other between writes; cancelling the session's own token (an API
+    // termination) also cuts a pending write or bandwidth allocation short.
+    let relay_token = cancel_token.child_token();
+
+    // Both directions run inside the connection's own task (like `copy_bidirectional`),
+    // so proxying a session does not spawn or allocate two extra tasks
+    let (upload, download, idle_expired) = tokio::join!(
+        proxy_upload(
+            client_read,
+            upstream_write,
+            session_manager.clone(),
+            session_id,
+            relay_token.clone(),
+            &cancel_token,
+            update_config,
+            qos_engine.clone(),
+            Arc::clone(&user),
+            source_ip,
+            activity.as_ref(),
+        ),
+        proxy_download(
+            upstream_read,
+            client_write,
+            session_manager,
+            session_id,
+            relay_token.clone(),
+            &cancel_token,
+            update_config,
+            qos_engine,
+            user,
+            source_ip,
+            activity.as_ref(),
+        ),
+        watch_idle(
+            activity.as_ref(),
+            update_config.idle_timeout(),
+            &relay_token
+        )
+    );
 
-    let upload = upload_result.map_err(join_error_to_rustsocks)?;
-    let download = download_result.map_err(join_error_to_rustsocks)?;
+    if idle_expired {
+        info!(
+            idle_timeout_secs = update_config
+                .idle_timeout()
+                .map_or(0, |limit| limit.as_secs()),
+            "Closing idle tunnel"
+        );
+        #[cfg(feature = "metrics")]
+        crate::session::SessionMetrics::record_idle_timeout();
+        return Err(RustSocksError::IdleTimeout);
+    }
 
     match (upload, download) {
         (Ok(up), Ok(down)) => {
-            let UploadResult {
-                totals: up_totals,
-                write_half,
-                client_closed,
-            } = up;
-            let DownloadResult {
-                totals: down_totals,
-                read_half,
-                remote_closed,
-                ..
-            } = down;
-
             debug!(
                 "Proxy completed: {} bytes ↑ ({} packets), {} bytes ↓ ({} packets)",
-                up_totals.bytes, up_totals.packets, down_totals.bytes, down_totals.packets
+                up.totals.bytes, up.totals.packets, down.totals.bytes, down.totals.packets
             );
-
-            match read_half.reunite(write_half) {
-                Ok(mut stream) => {
-                    let hint = if client_closed
-                        && !remote_closed
-                        && up_totals.bytes == 0
-                        && down_totals.bytes == 0
-                    {
-                        ReuseHint::Reuse
-                    } else {
-                        ReuseHint::Refresh
-                    };
-
-                    if matches!(hint, ReuseHint::Refresh) {
-                        if let Err(e) = stream.shutdown().await {
-                            trace!("Failed to shutdown upstream stream before refresh: {}", e);
-                        }
-                    }
-
-                    Ok(Some(UpstreamReuse { stream, hint }))
-                }
-                Err(ReuniteError(read, write)) => {
-                    drop(read);
-                    drop(write);
-                    Ok(None)
-                }
-            }
+            Ok((up, down))
         }
         (Err(err), Ok(_down)) => {
             if matches!(err, RustSocksError::ConnectionClosed) {
@@ -207,8 +336,31 @@ where
     }
 }
 
-fn join_error_to_rustsocks(err: tokio::task::JoinError) -> RustSocksError {
-    RustSocksError::Io(io::Error::other(format!("proxy task join error: {}", err)))
+/// Cancel the relay once neither direction has moved a byte for `limit`.
+///
+/// Returns true only when the timeout fired; a relay that ends on its own cancels the
+/// token and stops the watch.
+async fn watch_idle(
+    activity: Option<&TunnelActivity>,
+    limit: Option<Duration>,
+    cancel_token: &CancellationToken,
+) -> bool {
+    let (Some(activity), Some(limit)) = (activity, limit) else {
+        return false;
+    };
+
+    loop {
+        let idle = activity.idle_for();
+        if idle >= limit {
+            cancel_token.cancel();
+            return true;
+        }
+
+        tokio::select! {
+            _ = cancel_token.cancelled() => return false,
+            _ = tokio::time::sleep(limit - idle) => {}
+        }
+    }
 }
 
 fn is_connection_closed_error(err: &io::Error) -> bool {
@@ -230,37 +382,43 @@ fn is_connection_closed_error(err: &io::Error) -> bool {
         upstream_write,
         session_manager,
         cancel_token,
+        terminated,
         qos_engine,
-        user
+        user,
+        activity
     )
 )]
-async fn proxy_upload<R>(
+async fn proxy_upload<R, U>(
     mut reader: R,
-    mut upstream_write: OwnedWriteHalf,
+    mut upstream_write: U,
     session_manager: Arc<SessionManager>,
     session_id: Uuid,
     cancel_token: CancellationToken,
+    terminated: &CancellationToken,
     update_config: TrafficUpdateConfig,
     qos_engine: QosEngine,
     user: Arc<str>,
-) -> Result<UploadResult>
+    source_ip: IpAddr,
+    activity: Option<&TunnelActivity>,
+) -> Result<UploadResult<U>>
 where
     R: AsyncRead + Unpin + Send + 'static,
+    U: AsyncWrite + Unpin + Send,
 {
-    let mut buffer = [0u8; BUFFER_SIZE];
+    let mut buffer = RelayBufferPool::global().checkout();
     let mut totals = TrafficTotals::default();
-    let mut pending_bytes = 0u64;
-    let mut pending_packets = 0u64;
+    let mut pending = PendingTraffic::new(TrafficDirection::Upload);
     let packet_interval = update_config.packet_interval().get();
     let mut cancelled = false;
     let mut client_closed = false;
 
-    loop {
+    // Every exit breaks out with its outcome, so the final flush below covers errors too
+    let outcome: Result<()> = loop {
         let read_result = tokio::select! {
             _ = cancel_token.cancelled() => {
                 trace!("Direction {:?} cancelled", TrafficDirection::Upload);
                 cancelled = true;
-                break;
+                break Ok(());
             }
             result = reader.read(&mut buffer) => result,
         };
@@ -269,9 +427,14 @@ where
             Ok(0) => {
                 trace!("Direction {:?} reached EOF", TrafficDirection::Upload);
                 client_closed = true;
-                break;
+                break Ok(());
+            }
+            Ok(n) => {
+                if let Some(activity) = activity {
+                    activity.touch();
+                }
+                n
             }
-            Ok(n) => n,
             Err(e) => {
                 if is_connection_closed_error(&e) {
                     trace!(
@@ -279,27 +442,34 @@ where
                         e.kind()
                     );
                     client_closed = true;
-                    break;
+                    break Ok(());
                 } else {
                     error!("Read error on {:?}: {}", TrafficDirection::Upload, e);
-                    if pending_packets > 0 {
-                        flush_pending_now(
-                            &session_manager,
-                            &session_id,
-                            TrafficDirection::Upload,
-                            &mut pending_bytes,
-                            &mut pending_packets,
-                        )
-                        .await;
-                    }
-                    return Err(RustSocksError::Io(e));
+                    break Err(RustSocksError::Io(e));
                 }
             }
         };
 
-        qos_engine
-            .allocate_bandwidth_arc(&user, bytes_read as u64)
-            .await?;
+        // Throttling and a peer that stopped reading both wait here, so a terminated
+        // session is watched for as well
+        let allocation = tokio::select! {
+            _ = terminated.cancelled() => {
+                trace!("Direction {:?} cancelled", TrafficDirection::Upload);
+                cancelled = true;
+                break Ok(());
+            }
+            result = qos_engine
+                .allocate_bandwidth_from(&user, source_ip, bytes_read as u64) => result,
+        };
+        match allocation {
+            Ok(Some(dimension)) => {
+                session_manager
+                    .record_qos_throttle(&session_id, dimension, false)
+                    .await;
+            }
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
         if bytes_read > 0 {
             QosMetrics::record_allocation(
                 user.as_ref(),
@@ -308,59 +478,38 @@ where
             );
         }
 
-        if let Err(e) = upstream_write.write_all(&buffer[..bytes_read]).await {
+        let written = tokio::select! {
+            _ = terminated.cancelled() => {
+                trace!("Direction {:?} cancelled", TrafficDirection::Upload);
+                cancelled = true;
+                break Ok(());
+            }
+            result = upstream_write.write_all(&buffer[..bytes_read]) => result,
+        };
+        if let Err(e) = written {
             if is_connection_closed_error(&e) {
                 trace!(
                     "Upload write closed with error {:?}, treating as EOF",
                     e.kind()
                 );
                 client_closed = true;
-                break;
+                break Ok(());
             } else {
                 error!("Write error on {:?}: {}", TrafficDirection::Upload, e);
-                if pending_packets > 0 {
-                    flush_pending_now(
-                        &session_manager,
-                        &session_id,
-                        TrafficDirection::Upload,
-                        &mut pending_bytes,
-                        &mut pending_packets,
-                    )
-                    .await;
-                }
-                return Err(RustSocksError::Io(e));
+                break Err(RustSocksError::Io(e));
             }
         }
 
         totals.bytes = totals.bytes.saturating_add(bytes_read as u64);
         totals.packets = totals.packets.saturating_add(1);
-        pending_bytes = pending_bytes.saturating_add(bytes_read as u64);
-        pending_packets = pending_packets.saturating_add(1);
-
-        if pending_packets >= packet_interval {
-            enqueue_pending_update(
-                &session_manager,
-                &session_id,
-                TrafficDirection::Upload,
-                &mut pending_bytes,
-                &mut pending_packets,
-            )
-            .await;
+        if pending.record(bytes_read, packet_interval) {
+            pending.flush(&session_manager, &session_id).await;
         }
-    }
-
-    if pending_packets > 0 {
-        flush_pending_now(
-            &session_manager,
-            &session_id,
-            TrafficDirection::Upload,
-            &mut pending_bytes,
-            &mut pending_packets,
-        )
-        .await;
-    }
+    };
 
+    pending.flush(&session_manager, &session_id).await;
     cancel_token.cancel();
+    outcome?;
 
     if cancelled {
         Err(RustSocksError::ConnectionClosed)
@@ -376,35 +525,48 @@ where
 #[allow(clippy::too_many_arguments)]
 #[instrument(
     level = "trace",
-    skip(upstream_read, writer, session_manager, cancel_token, qos_engine, user)
+    skip(
+        upstream_read,
+        writer,
+        session_manager,
+        cancel_token,
+        terminated,
+        qos_engine,
+        user,
+        activity
+    )
 )]
-async fn proxy_download<W>(
-    mut upstream_read: OwnedReadHalf,
+async fn proxy_download<D, W>(
+    mut upstream_read: D,
     mut writer: W,
     session_manager: Arc<SessionManager>,
     session_id: Uuid,
     cancel_token: CancellationToken,
+    terminated: &CancellationToken,
     update_config: TrafficUpdateConfig,
     qos_engine: QosEngine,
     user: Arc<str>,
-) -> Result<DownloadResult<W>>
+    source_ip: IpAddr,
+    activity: Option<&TunnelActivity>,
+) -> Result<DownloadResult<D, W>>
 where
+    D: AsyncRead + Unpin + Send,
     W: AsyncWrite + Unpin + Send + 'static,
 {
-    let mut buffer = [0u8; BUFFER_SIZE];
+    let mut buffer = RelayBufferPool::global().checkout();
     let mut totals = TrafficTotals::default();
-    let mut pending_bytes = 0u64;
-    let mut pending_packets = 0u64;
+    let mut pending = PendingTraffic::new(TrafficDirection::Download);
     let packet_interval = update_config.packet_interval().get();
     let mut cancelled = false;
     let mut remote_closed = false;
 
-    loop {
+    // Every exit breaks out with its outcome, so the final flush below covers errors too
+    let outcome: Result<()> = loop {
         let read_result = tokio::select! {
             _ = cancel_token.cancelled() => {
                 trace!("Direction {:?} cancelled", TrafficDirection::Download);
                 cancelled = true;
-                break;
+                break Ok(());
             }
             result = upstream_read.read(&mut buffer) => result,
         };
@@ -413,9 +575,14 @@ where
             Ok(0) => {
                 trace!("Direction {:?} reached EOF", TrafficDirection::Download);
                 remote_closed = true;
-                break;
+                break Ok(());
+            }
+            Ok(n) => {
+                if let Some(activity) = activity {
+                    activity.touch();
+                }
+                n
             }
-            Ok(n) => n,
             Err(e) => {
                 if is_connection_closed_error(&e) {
                     trace!(
@@ -423,27 +590,34 @@ where
                         e.kind()
                     );
                     remote_closed = true;
-                    break;
+                    break Ok(());
                 } else {
                     error!("Read error on {:?}: {}", TrafficDirection::Download, e);
-                    if pending_packets > 0 {
-                        flush_pending_now(
-                            &session_manager,
-                            &session_id,
-                            TrafficDirection::Download,
-                            &mut pending_bytes,
-                            &mut pending_packets,
-                        )
-                        .await;
-                    }
-                    return Err(RustSocksError::Io(e));
+                    break Err(RustSocksError::Io(e));
                 }
             }
         };
 
-        qos_engine
-            .allocate_bandwidth_arc(&user, bytes_read as u64)
-            .await?;
+        // Throttling and a peer that stopped reading both wait here, so a terminated
+        // session is watched for as well
+        let allocation = tokio::select! {
+            _ = terminated.cancelled() => {
+                trace!("Direction {:?} cancelled", TrafficDirection::Download);
+                cancelled = true;
+                break Ok(());
+            }
+            result = qos_engine
+                .allocate_bandwidth_from(&user, source_ip, bytes_read as u64) => result,
+        };
+        match allocation {
+            Ok(Some(dimension)) => {
+                session_manager
+                    .record_qos_throttle(&session_id, dimension, false)
+                    .await;
+            }
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
         if bytes_read > 0 {
             QosMetrics::record_allocation(
                 user.as_ref(),
@@ -452,59 +626,38 @@ where
             );
         }
 
-        if let Err(e) = writer.write_all(&buffer[..bytes_read]).await {
+        let written = tokio::select! {
+            _ = terminated.cancelled() => {
+                trace!("Direction {:?} cancelled", TrafficDirection::Download);
+                cancelled = true;
+                break Ok(());
+            }
+            result = writer.write_all(&buffer[..bytes_read]) => result,
+        };
+        if let Err(e) = written {
             if is_connection_closed_error(&e) {
                 trace!(
                     "Download write closed with error {:?}, treating as EOF",
                     e.kind()
                 );
                 remote_closed = true;
-                break;
+                break Ok(());
             } else {
                 error!("Write error on {:?}: {}", TrafficDirection::Download, e);
-                if pending_packets > 0 {
-                    flush_pending_now(
-                        &session_manager,
-                        &session_id,
-                        TrafficDirection::Download,
-                        &mut pending_bytes,
-                        &mut pending_packets,
-                    )
-                    .await;
-                }
-                return Err(RustSocksError::Io(e));
+                break Err(RustSocksError::Io(e));
             }
         }
 
         totals.bytes = totals.bytes.saturating_add(bytes_read as u64);
         totals.packets = totals.packets.saturating_add(1);
-        pending_bytes = pending_bytes.saturating_add(bytes_read as u64);
-        pending_packets = pending_packets.saturating_add(1);
-
-        if pending_packets >= packet_interval {
-            enqueue_pending_update(
-                &session_manager,
-                &session_id,
-                TrafficDirection::Download,
-                &mut pending_bytes,
-                &mut pending_packets,
-            )
-            .await;
+        if pending.record(bytes_read, packet_interval) {
+            pending.flush(&session_manager, &session_id).await;
         }
-    }
-
-    if pending_packets > 0 {
-        flush_pending_now(
-            &session_manager,
-            &session_id,
-            TrafficDirection::Download,
-            &mut pending_bytes,
-            &mut pending_packets,
-        )
-        .await;
-    }
+    };
 
+    pending.flush(&session_manager, &session_id).await;
     cancel_token.cancel();
+    outcome?;
 
     if cancelled {
         Err(RustSocksError::ConnectionClosed)
@@ -518,74 +671,58 @@ where
     }
 }
 
-async fn enqueue_pending_update(
-    session_manager: &Arc<SessionManager>,
-    session_id: &Uuid,
+/// Traffic relayed in one direction since the session manager was last updated
+struct PendingTraffic {
     direction: TrafficDirection,
-    bytes: &mut u64,
-    packets: &mut u64,
-) {
-    if *packets == 0 {
-        return;
-    }
+    bytes: u64,
+    packets: u64,
+}
 
-    match direction {
-        TrafficDirection::Upload => {
-            session_manager
-                .update_traffic(session_id, *bytes, 0, *packets, 0)
-                .await;
-        }
-        TrafficDirection::Download => {
-            session_manager
-                .update_traffic(session_id, 0, *bytes, 0, *packets)
-                .await;
+impl PendingTraffic {
+    fn new(direction: TrafficDirection) -> Self {
+        Self {
+            direction,
+            bytes: 0,
+            packets: 0,
         }
     }
 
-    trace!(
-        "Flushed {:?} traffic update: {} bytes / {} packets",
-        direction,
-        *bytes,
-        *packets
-    );
-
-    *bytes = 0;
-    *packets = 0;
-}
-
-async fn flush_pending_now(
-    session_manager: &Arc<SessionManager>,
-    session_id: &Uuid,
-    direction: TrafficDirection,
-    bytes: &mut u64,
-    packets: &mut u64,
-) {
-    if *packets == 0 {
-        return;
+    /// Count one relayed read; true once `interval` reads are waiting to be reported
+    fn record(&mut self, bytes: usize, interval: u64) -> bool {
+        self.bytes = self.bytes.saturating_add(bytes as u64);
+        self.packets = self.packets.saturating_add(1);
+        self.packets >= interval
     }
 
-    match direction {
-        TrafficDirection::Upload => {
-            session_manager
-                .update_traffic(session_id, *bytes, 0, *packets, 0)
-                .await;
+    /// Report the pending counts to the session manager. They are taken before the
+    /// update is applied, so the periodic and the final flush never report a read twice.
+    async fn flush(&mut self, session_manager: &SessionManager, session_id: &Uuid) {
+        if self.packets == 0 {
+            return;
         }
-        TrafficDirection::Download => {
-            session_manager
-                .update_traffic(session_id, 0, *bytes, 0, *packets)
-                .await;
-        }
-    }
+        let bytes = std::mem::take(&mut self.bytes);
+        let packets = std::mem::take(&mut self.packets);
 
-    trace!(
-        "Synchronously flushed {:?} traffic update: {} bytes / {} packets",
-        direction,
-        *bytes,
-        *packets
-    );
+        match self.direction {
+            TrafficDirection::Upload => {
+                session_manager
+                    .update_traffic(session_id, bytes, 0, packets, 0)
+                    .await;
+            }
+            TrafficDirection::Download => {
+                session_manager
+                    .update_traffic(session_id, 0, bytes, 0, packets)
+                    .await;
+            }
+        }
 
-    *bytes = 0;
-    *packets = 0;
+        trace!(
+            "Flushed {:?} traffic update: {} bytes / {} packets",
+            self.direction,
+            bytes,
+            packets
+        );
+    }
 }
 
 #[cfg(test)]
@@ -597,4 +734,65 @@ mod tests {
         let config = TrafficUpdateConfig::new(0);
         assert_eq!(config.packet_interval().get(), 1);
     }
+
+    #[test]
+    fn zero_idle_timeout_disables_the_watch() {
+        assert_eq!(TrafficUpdateConfig::default().idle_timeout(), None);
+        assert_eq!(
+            TrafficUpdateConfig::default()
+                .with_idle_timeout(0)
+                .idle_timeout(),
+            None
+        );
+        assert_eq!(
+            TrafficUpdateConfig::new(10)
+                .with_idle_timeout(30)
+                .idle_timeout(),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn pending_traffic_is_due_at_the_interval() {
+        let mut pending = PendingTraffic::new(TrafficDirection::Upload);
+        assert!(!pending.record(100, 3));
+        assert!(!pending.record(100, 3));
+        assert!(pending.record(100, 3));
+        assert_eq!((pending.bytes, pending.packets), (300, 3));
+    }
+
+    #[tokio::test]
+    async fn flush_reports_each_read_once() {
+        let manager = SessionManager::new();
+        let connection = crate::session::ConnectionInfo {
+            source_ip: IpAddr::from([127, 0, 0, 1]),
+            source_port: 40000,
+            dest_ip: "example.com".into(),
+            dest_port: 443,
+            protocol: crate::session::SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
+        };
+        let (session_id, _) = manager
+            .new_session_with_control("alice", connection, "allow", None, None)
+            .await;
+
+        let mut pending = PendingTraffic::new(TrafficDirection::Download);
+        pending.record(1000, 10);
+        pending.flush(&manager, &session_id).await;
+        pending.flush(&manager, &session_id).await;
+        pending.record(24, 10);
+        pending.flush(&manager, &session_id).await;
+
+        let session = manager.get_session(&session_id).unwrap();
+        let session = session.read().await;
+        assert_eq!(
+            (session.bytes_received, session.packets_received),
+            (1024, 2)
+        );
+        assert_eq!(session.bytes_sent, 0);
+    }
 }
@@ -0,0 +1,289 @@
+//! Per-client-IP rate limit on new connections (`server.rate_limit`).
+//!
+//! The accept loop asks [`ConnectionRateLimiter::admit`] before TLS or any SOCKS byte,
+//! so a client over its limit costs one accept and one close. This is separate from
+//! the per-user connection cap in QoS, which only applies once a client has
+//! authenticated.
+//!
+//! Each IP keeps a sliding-window counter: the count for the current minute plus the
+//! previous minute's count weighted by how much of it still overlaps the window. That
+//! is two integers per IP whatever the connection rate. IPs idle for two windows
+//! carry no weight and are pruned, so the map only holds recently active clients.
+//!
+//! Drops are warned about at most once per `log_interval_secs` across all clients, or
+//! with `warn_per_client` once per client per window, so each offending IP is named.
+
+use crate::config::RateLimitSettings;
+use dashmap::DashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// The limit is configured per minute
+const WINDOW: Duration = Duration::from_secs(60);
+
+struct ClientWindow {
+    window_start: Instant,
+    current: u32,
+    previous: u32,
+    /// A drop in the current window has been warned about
+    warned: bool,
+}
+
+impl ClientWindow {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            current: 0,
+            previous: 0,
+            warned: false,
+        }
+    }
+
+    /// Move to the window containing `now`
+    fn roll(&mut self, now: Instant, window: Duration) {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < window {
+            return;
+        }
+        if elapsed < window * 2 {
+            self.previous = self.current;
+            self.window_start += window;
+        } else {
+            // Both windows are over; nothing carries forward
+            self.previous = 0;
+            self.window_start = now;
+        }
+        self.current = 0;
+        self.warned = false;
+    }
+
+    /// True for the first drop in the current window
+    fn claim_warning(&mut self) -> bool {
+        !std::mem::replace(&mut self.warned, true)
+    }
+
+    /// Connections counted in the sliding window ending at `now`
+    fn estimate(&self, now: Instant, window: Duration) -> f64 {
+        let into_current = now
+            .saturating_duration_since(self.window_start)
+            .as_secs_f64()
+            / window.as_secs_f64();
+        f64::from(self.previous) * (1.0 - into_current).max(0.0) + f64::from(self.current)
+    }
+
+    fn is_idle(&self, now: Instant, window: Duration) -> bool {
+        now.saturating_duration_since(self.window_start) >= window * 2
+    }
+}
+
+pub struct ConnectionRateLimiter {
+    limit: u32,
+    window: Duration,
+    log_interval: Option<Duration>,
+    /// Warn on each client's first drop per window instead of per `log_interval`
+    warn_per_client: bool,
+    clients: DashMap<IpAddr, ClientWindow>,
+    next_prune: Mutex<Instant>,
+    last_warning: Mutex<Option<Instant>>,
+    /// Rejections not logged since the last warning
+    suppressed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl ConnectionRateLimiter {
+    /// `None` when `connections_per_ip_per_minute` is 0
+    pub fn new(settings: &RateLimitSettings) -> Option<Self> {
+        (settings.connections_per_ip_per_minute > 0).then(|| Self {
+            warn_per_client: settings.warn_per_client,
+            ..Self::with_window(
+                settings.connections_per_ip_per_minute,
+                WINDOW,
+                (settings.log_interval_secs > 0)
+                    .then(|| Duration::from_secs(settings.log_interval_secs)),
+            )
+        })
+    }
+
+    fn with_window(limit: u32, window: Duration, log_interval: Option<Duration>) -> Self {
+        Self {
+            limit,
+            window,
+            log_interval,
+            warn_per_client: false,
+            clients: DashMap::new(),
+            next_prune: Mutex::new(Instant::now() + window),
+            last_warning: Mutex::new(None),
+            suppressed: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Decide whether a new connection from `ip` may proceed. Rejected attempts are
+    /// not counted, so a client that backs off gets through again once its earlier
+    /// connections leave the window.
+    pub fn admit(&self, ip: IpAddr) -> bool {
+        self.admit_at(ip, Instant::now())
+    }
+
+    fn admit_at(&self, ip: IpAddr, now: Instant) -> bool {
+        self.prune_if_due(now);
+
+        let (admitted, first_drop) = {
+            let mut client = self
+                .clients
+                .entry(ip)
+                .or_insert_with(|| ClientWindow::new(now));
+            client.roll(now, self.window);
+            if client.estimate(now, self.window) < f64::from(self.limit) {
+                client.current = client.current.saturating_add(1);
+                (true, false)
+            } else {
+                (false, client.claim_warning())
+            }
+        };
+
+        if !admitted {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            crate::session::SessionMetrics::record_rate_limited_connection();
+            if !self.warn_per_client {
+                self.log_rejection(ip, now);
+            } else if first_drop {
+                warn!(
+                    client = %ip,
+                    limit_per_minute = self.limit,
+                    "Connection rate limit exceeded, closing new connections from client \
+                     for the rest of the window"
+                );
+            }
+        }
+        admitted
+    }
+
+    /// Warn at most once per `log_interval`, with the count of rejections since
+    fn log_rejection(&self, ip: IpAddr, now: Instant) {
+        let Some(interval) = self.log_interval else {
+            return;
+        };
+        let Ok(mut last) = self.last_warning.try_lock() else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        if last.is_some_and(|at| now.saturating_duration_since(at) < interval) {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        *last = Some(now);
+        let suppressed = self.suppressed.swap(0, Ordering::Relaxed);
+        warn!(
+            client = %ip,
+            limit_per_minute = self.limit,
+            suppressed,
+            "Connection rate limit exceeded, closing new connections from client"
+        );
+    }
+
+    /// Drop IPs whose windows no longer count, at most once per window
+    fn prune_if_due(&self, now: Instant) {
+        let Ok(mut next_prune) = self.next_prune.try_lock() else {
+            return;
+        };
+        if now < *next_prune {
+            return;
+        }
+        *next_prune = now + self.window;
+        drop(next_prune);
+        self.clients
+            .retain(|_, client| !client.is_idle(now, self.window));
+    }
+
+    /// Client IPs currently tracked
+    pub fn tracked_clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Connections closed by the limit since startup
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 0, 2, last])
+    }
+
+    #[test]
+    fn limit_applies_per_client() {
+        let limiter = ConnectionRateLimiter::with_window(3, MINUTE, None);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.admit_at(ip(1), now));
+        }
+        assert!(!limiter.admit_at(ip(1), now));
+        assert!(limiter.admit_at(ip(2), now));
+        assert_eq!(limiter.rejected_connections(), 1);
+    }
+
+    #[test]
+    fn previous_window_weighs_in_while_it_overlaps() {
+        let limiter = ConnectionRateLimiter::with_window(4, MINUTE, None);
+        let start = Instant::now();
+        for _ in 0..4 {
+            assert!(limiter.admit_at(ip(1), start));
+        }
+
+        // A quarter into the next minute, three quarters of the last one still count
+        let later = start + MINUTE + MINUTE / 4;
+        assert!(limiter.admit_at(ip(1), later));
+        assert!(!limiter.admit_at(ip(1), later));
+
+        // Once the full minute has slid out, only the later connection still weighs in
+        let much_later = start + MINUTE * 2 + MINUTE / 2;
+        assert!(limiter.admit_at(ip(1), much_later));
+    }
+
+    #[test]
+    fn idle_clients_are_pruned() {
+        let limiter = ConnectionRateLimiter::with_window(10, MINUTE, None);
+        let start = Instant::now();
+        for last in 0..100 {
+            limiter.admit_at(ip(last), start);
+        }
+        assert_eq!(limiter.tracked_clients(), 100);
+
+        limiter.admit_at(ip(200), start + MINUTE * 3);
+        assert_eq!(limiter.tracked_clients(), 1);
+    }
+
+    #[test]
+    fn each_client_is_warned_once_per_window() {
+        let start = Instant::now();
+        let mut client = ClientWindow::new(start);
+        assert!(client.claim_warning());
+        assert!(!client.claim_warning());
+
+        client.roll(start + MINUTE / 2, MINUTE);
+        assert!(!client.claim_warning());
+        client.roll(start + MINUTE, MINUTE);
+        assert!(client.claim_warning());
+    }
+
+    #[test]
+    fn zero_disables_the_limit() {
+        assert!(ConnectionRateLimiter::new(&RateLimitSettings::default()).is_none());
+        let limiter = ConnectionRateLimiter::new(&RateLimitSettings {
+            connections_per_ip_per_minute: 30,
+            ..RateLimitSettings::default()
+        });
+        assert!(limiter.is_some());
+    }
+}
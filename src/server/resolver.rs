@@ -1,26 +1,460 @@
+use crate::config::DnsSettings;
 use crate::protocol::types::Address;
 use crate::utils::error::{Result, RustSocksError};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
-use tracing::instrument;
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use smallvec::SmallVec;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument};
+
+/// Destination lookup used by the connection handler and the UDP relay.
+pub trait DestinationResolver: Send + Sync + 'static {
+    fn resolve<'a>(
+        &'a self,
+        address: &'a Address,
+        port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>>;
+}
+
+/// Resolver backed by the system (`getaddrinfo`) via [`resolve_address`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemResolver;
+
+impl DestinationResolver for SystemResolver {
+    fn resolve<'a>(
+        &'a self,
+        address: &'a Address,
+        port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(resolve_address(address, port))
+    }
+}
+
+/// Cached outcome of one domain lookup
+#[derive(Debug, Clone)]
+enum CachedLookup {
+    /// Every address returned, IPv6 first, without the port
+    Found(Vec<IpAddr>),
+    /// The name does not exist; kept for `negative_ttl`
+    NotFound {
+        kind: io::ErrorKind,
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    lookup: CachedLookup,
+    expires_at: Instant,
+}
+
+/// Domain lookups remembered for `server.dns.cache_ttl_secs` (`[server.dns]`).
+///
+/// Entries are keyed on the lowercased name and hold all of its addresses, so a
+/// cached answer is expanded to the same v6-then-v4 list a fresh lookup gives.
+/// Names that do not exist are cached for the shorter `negative_ttl_secs`; lookups
+/// that failed for any other reason (timeouts, `EAI_AGAIN`) are not cached at all.
+#[derive(Debug)]
+pub struct DnsCache {
+    entries: DashMap<String, CacheEntry>,
+    ttl: Duration,
+    negative_ttl: Duration,
+    max_entries: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Counters of a [`DnsCache`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct DnsCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl DnsCache {
+    pub fn new(ttl: Duration, negative_ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            entries: DashMap::new(),
+            ttl,
+            negative_ttl,
+            max_entries,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// `None` when `cache_ttl_secs` is 0
+    pub fn from_settings(settings: &DnsSettings) -> Option<Self> {
+        (settings.cache_ttl_secs > 0).then(|| {
+            Self::new(
+                Duration::from_secs(settings.cache_ttl_secs),
+                Duration::from_secs(settings.negative_ttl_secs),
+                settings.cache_max_entries,
+            )
+        })
+    }
+
+    /// Drop every entry; returns how many there were
+    pub fn flush(&self) -> usize {
+        let flushed = self.entries.len();
+        self.entries.clear();
+        flushed
+    }
+
+    pub fn stats(&self) -> DnsCacheStats {
+        DnsCacheStats {
+            entries: self.entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn get(&self, domain: &str) -> Option<CachedLookup> {
+        let lookup = self
+            .entries
+            .get(domain)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.lookup.clone());
+
+        let counter = if lookup.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        crate::session::SessionMetrics::record_dns_cache_lookup(lookup.is_some());
+        lookup
+    }
+
+    fn insert(&self, domain: String, lookup: CachedLookup) {
+        let ttl = match lookup {
+            CachedLookup::Found(_) => self.ttl,
+            CachedLookup::NotFound { .. } => self.negative_ttl,
+        };
+        if ttl.is_zero() {
+            return;
+        }
+
+        if self.entries.len() >= self.max_entries {
+            let now = Instant::now();
+            self.entries.retain(|_, entry| entry.expires_at > now);
+        }
+        if self.entries.len() >= self.max_entries {
+            // Still full of live entries: make room by dropping the oldest tenth
+            let mut by_expiry: Vec<(Instant, String)> = self
+                .entries
+                .iter()
+                .map(|entry| (entry.expires_at, entry.key().clone()))
+                .collect();
+            by_expiry.sort_unstable();
+            let excess = self.entries.len() + 1 - self.max_entries;
+            for (_, key) in by_expiry
+                .into_iter()
+                .take(excess.max(self.max_entries / 10))
+            {
+                self.entries.remove(&key);
+            }
+        }
+
+        self.entries.insert(
+            domain,
+            CacheEntry {
+                lookup,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+    }
+}
+
+/// [`DestinationResolver`] answering repeated domain lookups from a [`DnsCache`]
+pub struct CachingResolver {
+    inner: Arc<dyn DestinationResolver>,
+    cache: Arc<DnsCache>,
+}
+
+impl CachingResolver {
+    pub fn new(inner: Arc<dyn DestinationResolver>, cache: Arc<DnsCache>) -> Self {
+        Self { inner, cache }
+    }
+
+    async fn resolve_domain(&self, address: &Address, port: u16) -> Result<Vec<SocketAddr>> {
+        let Address::Domain(domain) = address else {
+            return self.inner.resolve(address, port).await;
+        };
+        let key = domain.to_ascii_lowercase();
+
+        match self.cache.get(&key) {
+            Some(CachedLookup::Found(ips)) => {
+                return Ok(ips
+                    .into_iter()
+                    .map(|ip| SocketAddr::new(ip, port))
+                    .collect())
+            }
+            Some(CachedLookup::NotFound { kind, message }) => {
+                return Err(RustSocksError::Io(io::Error::new(kind, message)))
+            }
+            None => {}
+        }
+
+        match self.inner.resolve(address, port).await {
+            Ok(targets) => {
+                let ips = targets.iter().map(SocketAddr::ip).collect();
+                self.cache.insert(key, CachedLookup::Found(ips));
+                Ok(targets)
+            }
+            Err(RustSocksError::Io(err)) if is_name_not_found(&err) => {
+                debug!(domain = %key, error = %err, "Caching failed lookup");
+                self.cache.insert(
+                    key,
+                    CachedLookup::NotFound {
+                        kind: err.kind(),
+                        message: err.to_string(),
+                    },
+                );
+                Err(RustSocksError::Io(err))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl DestinationResolver for CachingResolver {
+    fn resolve<'a>(
+        &'a self,
+        address: &'a Address,
+        port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(self.resolve_domain(address, port))
+    }
+}
+
+/// Whether a lookup error says the name does not exist (NXDOMAIN or no records), as
+/// opposed to the resolver being unreachable.
+///
+/// `getaddrinfo` failures reach us as uncategorized I/O errors carrying the
+/// `gai_strerror` text, so the message is all there is to go on.
+fn is_name_not_found(err: &io::Error) -> bool {
+    if matches!(
+        err.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::AddrNotAvailable
+    ) {
+        return true;
+    }
+    let message = err.to_string();
+    [
+        "Name or service not known",
+        "No address associated with hostname",
+        "nodename nor servname provided",
+        "No such host is known",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
+
+/// Socket address for IP-literal destinations, which never need to reach a resolver.
+///
+/// Callers use this to skip [`DestinationResolver::resolve`] (and its boxed future) for the
+/// common CONNECT-by-IP case; only domain names are handed to the resolver. IP literals
+/// sent in domain form (`[2001:db8::1]`, `fe80::1%eth0`) count as IPs.
+pub fn literal_target(address: &Address, port: u16) -> Option<SocketAddr> {
+    address.literal_socket_addr(port)
+}
+
+/// Order in which address families are connected (`server.dns.strategy`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DnsStrategy {
+    PreferIpv4,
+    /// Every IPv6 address, then every IPv4 one (the order [`resolve_address`] returns)
+    #[default]
+    PreferIpv6,
+    Ipv4Only,
+    Ipv6Only,
+    /// RFC 8305: families alternate starting with IPv6, and an attempt that has not
+    /// connected within the attempt delay gets the next address raced against it
+    HappyEyeballs,
+}
+
+impl DnsStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DnsStrategy::PreferIpv4 => "prefer_ipv4",
+            DnsStrategy::PreferIpv6 => "prefer_ipv6",
+            DnsStrategy::Ipv4Only => "ipv4_only",
+            DnsStrategy::Ipv6Only => "ipv6_only",
+            DnsStrategy::HappyEyeballs => "happy_eyeballs",
+        }
+    }
+}
+
+impl std::str::FromStr for DnsStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "prefer_ipv4" => Ok(DnsStrategy::PreferIpv4),
+            "prefer_ipv6" => Ok(DnsStrategy::PreferIpv6),
+            "ipv4_only" => Ok(DnsStrategy::Ipv4Only),
+            "ipv6_only" => Ok(DnsStrategy::Ipv6Only),
+            "happy_eyeballs" => Ok(DnsStrategy::HappyEyeballs),
+            _ => Err(format!("Invalid DNS strategy: {}", value)),
+        }
+    }
+}
+
+/// How a CONNECT picks among the addresses of its destination, from `[server.dns]`.
+///
+/// The resolver always returns every address; the strategy decides the order they are
+/// tried in and drops the family an `*_only` mode excludes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressSelection {
+    pub strategy: DnsStrategy,
+    /// With [`DnsStrategy::HappyEyeballs`], how long one attempt runs alone
+    pub attempt_delay: Duration,
+}
+
+impl Default for AddressSelection {
+    fn default() -> Self {
+        Self {
+            strategy: DnsStrategy::default(),
+            attempt_delay: Duration::from_millis(DEFAULT_HAPPY_EYEBALLS_DELAY_MS),
+        }
+    }
+}
+
+/// RFC 8305's recommended Connection Attempt Delay
+pub const DEFAULT_HAPPY_EYEBALLS_DELAY_MS: u64 = 250;
+
+impl AddressSelection {
+    pub fn from_settings(settings: &DnsSettings) -> Result<Self> {
+        let strategy = settings.strategy.parse().map_err(|_| {
+            RustSocksError::Config(format!(
+                "Invalid server.dns.strategy: {}. Supported: prefer_ipv4, prefer_ipv6, \
+                 ipv4_only, ipv6_only, happy_eyeballs",
+                settings.strategy
+            ))
+        })?;
+        Ok(Self {
+            strategy,
+            attempt_delay: Duration::from_millis(settings.happy_eyeballs_delay_ms),
+        })
+    }
+
+    /// `targets` in the order they should be tried, without the excluded family.
+    /// Order within a family is kept.
+    pub fn order<I>(&self, targets: I) -> SmallVec<[SocketAddr; 2]>
+    where
+        I: IntoIterator<Item = SocketAddr>,
+    {
+        let (v6, v4): (SmallVec<[SocketAddr; 2]>, SmallVec<[SocketAddr; 2]>) =
+            targets.into_iter().partition(SocketAddr::is_ipv6);
+
+        match self.strategy {
+            DnsStrategy::PreferIpv4 => v4.into_iter().chain(v6).collect(),
+            DnsStrategy::PreferIpv6 => v6.into_iter().chain(v4).collect(),
+            DnsStrategy::Ipv4Only => v4,
+            DnsStrategy::Ipv6Only => v6,
+            DnsStrategy::HappyEyeballs => {
+                let mut ordered = SmallVec::with_capacity(v6.len() + v4.len());
+                let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+                loop {
+                    match (v6.next(), v4.next()) {
+                        (None, None) => break,
+                        (first, second) => ordered.extend(first.into_iter().chain(second)),
+                    }
+                }
+                ordered
+            }
+        }
+    }
+
+    /// Connect to the first of `targets` that answers, trying them in order.
+    ///
+    /// With happy eyeballs the next attempt starts as soon as the previous one fails or
+    /// has been pending for `attempt_delay`, and the first connection wins; the attempts
+    /// still pending are dropped. Otherwise each attempt runs to completion before the
+    /// next. On failure, returns the last attempt's error.
+    pub async fn connect<T, F, Fut>(
+        &self,
+        targets: &[SocketAddr],
+        connect: F,
+    ) -> io::Result<(T, SocketAddr)>
+    where
+        F: Fn(SocketAddr) -> Fut,
+        Fut: Future<Output = io::Result<T>>,
+    {
+        let mut last_err = None;
+        let unreachable = |last_err: Option<io::Error>| {
+            last_err.unwrap_or_else(|| io::Error::other("no reachable upstream addresses"))
+        };
+
+        if self.strategy != DnsStrategy::HappyEyeballs {
+            for &target in targets {
+                debug!("Attempting upstream connection to {}", target);
+                match connect(target).await {
+                    Ok(stream) => return Ok((stream, target)),
+                    Err(e) => last_err = Some(e),
+                }
+            }
+            return Err(unreachable(last_err));
+        }
+
+        let attempt = |target: SocketAddr| {
+            debug!("Attempting upstream connection to {}", target);
+            let pending = connect(target);
+            async move { (target, pending.await) }
+        };
+        let mut remaining = targets.iter().copied();
+        let mut attempts = FuturesUnordered::new();
+        loop {
+            if attempts.is_empty() {
+                let Some(target) = remaining.next() else {
+                    return Err(unreachable(last_err));
+                };
+                attempts.push(attempt(target));
+            }
+
+            let more = remaining.len() > 0;
+            tokio::select! {
+                Some((target, result)) = attempts.next() => match result {
+                    Ok(stream) => return Ok((stream, target)),
+                    Err(e) => {
+                        debug!("Upstream connection to {} failed: {}", target, e);
+                        last_err = Some(e);
+                        // A failed attempt hands over to the next address at once
+                        if let Some(target) = remaining.next() {
+                            attempts.push(attempt(target));
+                        }
+                    }
+                },
+                _ = tokio::time::sleep(self.attempt_delay), if more => {
+                    if let Some(target) = remaining.next() {
+                        attempts.push(attempt(target));
+                    }
+                }
+            }
+        }
+    }
+}
 
 /// Resolve a SOCKS5 address into a list of socket addresses, preferring IPv6 entries first.
 #[instrument(level = "debug", fields(port = port, address = ?address))]
 pub async fn resolve_address(address: &Address, port: u16) -> Result<Vec<SocketAddr>> {
-    let mut targets = match address {
-        Address::IPv4(octets) => {
-            let ip = IpAddr::V4(Ipv4Addr::from(*octets));
-            vec![SocketAddr::new(ip, port)]
-        }
-        Address::IPv6(octets) => {
-            let ip = IpAddr::V6(Ipv6Addr::from(*octets));
-            vec![SocketAddr::new(ip, port)]
-        }
-        Address::Domain(domain) => {
+    let mut targets: Vec<SocketAddr> = match (address, literal_target(address, port)) {
+        (Address::Domain(domain), None) => {
             let lookup = tokio::net::lookup_host((domain.as_str(), port))
                 .await
                 .map_err(RustSocksError::Io)?;
             lookup.collect()
         }
+        (_, target) => target.into_iter().collect(),
     };
 
     // Prefer IPv6, then IPv4, while preserving order inside each category.
@@ -42,6 +476,30 @@ pub async fn resolve_address(address: &Address, port: u16) -> Result<Vec<SocketA
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn literal_target_skips_domains() {
+        assert_eq!(
+            literal_target(&Address::IPv4([10, 0, 0, 1]), 443),
+            Some(SocketAddr::from(([10, 0, 0, 1], 443)))
+        );
+        assert_eq!(
+            literal_target(
+                &Address::IPv6([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+                80
+            ),
+            Some(SocketAddr::from((Ipv6Addr::LOCALHOST, 80)))
+        );
+        assert_eq!(
+            literal_target(&Address::Domain("example.com".into()), 80),
+            None
+        );
+        assert_eq!(
+            literal_target(&Address::Domain("[::1]".into()), 80),
+            Some(SocketAddr::from((Ipv6Addr::LOCALHOST, 80)))
+        );
+    }
 
     #[tokio::test]
     async fn resolves_ipv4_literal() {
@@ -62,9 +520,39 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn resolves_bracketed_and_zoned_ipv6_without_dns() {
+        let bracketed = Address::Domain("[2001:db8::1]".into());
+        assert_eq!(
+            resolve_address(&bracketed, 443).await.unwrap(),
+            vec![SocketAddr::from((
+                "2001:db8::1".parse::<Ipv6Addr>().unwrap(),
+                443
+            ))]
+        );
+
+        // Numeric zones are the scope id itself, in plain and URI-escaped form
+        let expected = vec![SocketAddr::V6(std::net::SocketAddrV6::new(
+            "fe80::1".parse().unwrap(),
+            22,
+            0,
+            7,
+        ))];
+        for zoned in ["fe80::1%7", "[fe80::1%257]"] {
+            let address = Address::Domain(zoned.into());
+            assert_eq!(resolve_address(&address, 22).await.unwrap(), expected);
+        }
+
+        // Malformed literals are not IPs and go to the resolver like any name
+        for malformed in ["[fe80::1", "[192.0.2.1]", "fe80::1%"] {
+            let address = Address::Domain(malformed.into());
+            assert_eq!(literal_target(&address, 80), None);
+        }
+    }
+
     #[tokio::test]
     async fn resolves_domain_prefers_ipv6() {
-        let addr = Address::Domain("localhost".to_string());
+        let addr = Address::Domain("localhost".into());
         let resolved = resolve_address(&addr, 8080).await.unwrap();
         assert!(!resolved.is_empty());
         // first entry should be IPv6 when available
@@ -75,4 +563,277 @@ mod tests {
             assert!(matches!(resolved[0].ip(), IpAddr::V6(_)));
         }
     }
+
+    /// Resolver counting its calls; `nxdomain` names fail with NotFound, `flaky` ones
+    /// with a timeout.
+    #[derive(Default)]
+    struct CountingResolver {
+        calls: AtomicU64,
+    }
+
+    impl DestinationResolver for CountingResolver {
+        fn resolve<'a>(
+            &'a self,
+            address: &'a Address,
+            port: u16,
+        ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            Box::pin(async move {
+                match address {
+                    Address::Domain(name) if name.starts_with("nxdomain") => Err(
+                        RustSocksError::Io(io::Error::new(io::ErrorKind::NotFound, "no such name")),
+                    ),
+                    Address::Domain(name) if name.starts_with("flaky") => Err(RustSocksError::Io(
+                        io::Error::new(io::ErrorKind::TimedOut, "timed out"),
+                    )),
+                    _ => Ok(vec![SocketAddr::from(([192, 0, 2, 7], port))]),
+                }
+            })
+        }
+    }
+
+    fn caching(ttl: Duration) -> (Arc<CountingResolver>, Arc<DnsCache>, CachingResolver) {
+        let inner = Arc::new(CountingResolver::default());
+        let cache = Arc::new(DnsCache::new(ttl, ttl, 100));
+        let resolver = CachingResolver::new(inner.clone(), cache.clone());
+        (inner, cache, resolver)
+    }
+
+    #[tokio::test]
+    async fn second_lookup_within_ttl_skips_the_resolver() {
+        let (inner, cache, resolver) = caching(Duration::from_secs(60));
+        let name = Address::Domain("Example.COM".into());
+
+        let first = resolver.resolve(&name, 80).await.unwrap();
+        let second = resolver
+            .resolve(&Address::Domain("example.com".into()), 443)
+            .await
+            .unwrap();
+
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 1);
+        assert_eq!(first, vec![SocketAddr::from(([192, 0, 2, 7], 80))]);
+        // The cached answer carries the port of the new request
+        assert_eq!(second, vec![SocketAddr::from(([192, 0, 2, 7], 443))]);
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_resolved_again() {
+        let (inner, _cache, resolver) = caching(Duration::from_millis(20));
+        let name = Address::Domain("example.com".into());
+
+        resolver.resolve(&name, 80).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        resolver.resolve(&name, 80).await.unwrap();
+
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn missing_names_are_cached_but_transient_failures_are_not() {
+        let (inner, _cache, resolver) = caching(Duration::from_secs(60));
+
+        let missing = Address::Domain("nxdomain.example".into());
+        for _ in 0..2 {
+            let err = resolver.resolve(&missing, 80).await.unwrap_err();
+            assert!(
+                matches!(err, RustSocksError::Io(ref e) if e.kind() == io::ErrorKind::NotFound)
+            );
+        }
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 1);
+
+        let flaky = Address::Domain("flaky.example".into());
+        for _ in 0..2 {
+            assert!(resolver.resolve(&flaky, 80).await.is_err());
+        }
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn flush_drops_cached_lookups() {
+        let (inner, cache, resolver) = caching(Duration::from_secs(60));
+        let name = Address::Domain("example.com".into());
+
+        resolver.resolve(&name, 80).await.unwrap();
+        assert_eq!(cache.flush(), 1);
+        assert_eq!(cache.stats().entries, 0);
+        resolver.resolve(&name, 80).await.unwrap();
+
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn full_cache_evicts_the_oldest_entries() {
+        let cache = DnsCache::new(Duration::from_secs(60), Duration::from_secs(5), 10);
+        for i in 0..15 {
+            cache.insert(
+                format!("host{i}.example"),
+                CachedLookup::Found(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]),
+            );
+        }
+        assert!(cache.stats().entries <= 10);
+        assert!(cache.get("host14.example").is_some());
+    }
+
+    #[test]
+    fn getaddrinfo_messages_count_as_missing_names() {
+        let gai =
+            io::Error::other("failed to lookup address information: Name or service not known");
+        assert!(is_name_not_found(&gai));
+        assert!(!is_name_not_found(&io::Error::new(
+            io::ErrorKind::TimedOut,
+            "timed out"
+        )));
+    }
+
+    fn selection(strategy: DnsStrategy) -> AddressSelection {
+        AddressSelection {
+            strategy,
+            attempt_delay: Duration::from_millis(20),
+        }
+    }
+
+    fn mixed_targets() -> Vec<SocketAddr> {
+        [
+            "[2001:db8::1]:80",
+            "192.0.2.1:80",
+            "[2001:db8::2]:80",
+            "192.0.2.2:80",
+            "192.0.2.3:80",
+        ]
+        .iter()
+        .map(|addr| addr.parse().unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn strategies_order_the_families() {
+        let order = |strategy| -> Vec<String> {
+            selection(strategy)
+                .order(mixed_targets())
+                .iter()
+                .map(|addr| addr.ip().to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            order(DnsStrategy::PreferIpv6),
+            [
+                "2001:db8::1",
+                "2001:db8::2",
+                "192.0.2.1",
+                "192.0.2.2",
+                "192.0.2.3"
+            ]
+        );
+        assert_eq!(
+            order(DnsStrategy::PreferIpv4),
+            [
+                "192.0.2.1",
+                "192.0.2.2",
+                "192.0.2.3",
+                "2001:db8::1",
+                "2001:db8::2"
+            ]
+        );
+        assert_eq!(
+            order(DnsStrategy::HappyEyeballs),
+            [
+                "2001:db8::1",
+                "192.0.2.1",
+                "2001:db8::2",
+                "192.0.2.2",
+                "192.0.2.3"
+            ]
+        );
+    }
+
+    #[test]
+    fn only_modes_drop_the_other_family() {
+        let v4 = selection(DnsStrategy::Ipv4Only).order(mixed_targets());
+        assert_eq!(v4.len(), 3);
+        assert!(v4.iter().all(SocketAddr::is_ipv4));
+
+        let v6 = selection(DnsStrategy::Ipv6Only).order(mixed_targets());
+        assert_eq!(v6.len(), 2);
+        assert!(v6.iter().all(SocketAddr::is_ipv6));
+
+        let v4_only_host = [SocketAddr::from(([192, 0, 2, 1], 80))];
+        assert!(selection(DnsStrategy::Ipv6Only)
+            .order(v4_only_host)
+            .is_empty());
+    }
+
+    #[test]
+    fn strategy_names_round_trip() {
+        for strategy in [
+            DnsStrategy::PreferIpv4,
+            DnsStrategy::PreferIpv6,
+            DnsStrategy::Ipv4Only,
+            DnsStrategy::Ipv6Only,
+            DnsStrategy::HappyEyeballs,
+        ] {
+            assert_eq!(strategy.as_str().parse(), Ok(strategy));
+        }
+        assert!("ipv4".parse::<DnsStrategy>().is_err());
+    }
+
+    /// Connect stand-in: IPv6 attempts hang like an unroutable address, IPv4 ones
+    /// succeed, and 192.0.2.1 is refused
+    async fn fake_connect(target: SocketAddr) -> io::Result<SocketAddr> {
+        match target {
+            SocketAddr::V6(_) => std::future::pending().await,
+            target if target.ip() == IpAddr::from([192, 0, 2, 1]) => {
+                Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+            }
+            target => Ok(target),
+        }
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_races_past_a_stalled_address() {
+        let targets = selection(DnsStrategy::HappyEyeballs).order(mixed_targets());
+        let (connected, addr) = tokio::time::timeout(
+            Duration::from_secs(1),
+            selection(DnsStrategy::HappyEyeballs).connect(&targets, fake_connect),
+        )
+        .await
+        .expect("an IPv4 address answers")
+        .unwrap();
+
+        // 2001:db8::1 stalls, 192.0.2.1 is refused, 2001:db8::2 stalls
+        assert_eq!(connected, addr);
+        assert_eq!(addr, SocketAddr::from(([192, 0, 2, 2], 80)));
+
+        // Trying one address at a time waits on the first IPv6 address for good
+        let targets = selection(DnsStrategy::PreferIpv6).order(mixed_targets());
+        assert!(tokio::time::timeout(
+            Duration::from_millis(200),
+            selection(DnsStrategy::PreferIpv6).connect(&targets, fake_connect),
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn a_failed_attempt_starts_the_next_one_at_once() {
+        let slow = AddressSelection {
+            strategy: DnsStrategy::HappyEyeballs,
+            attempt_delay: Duration::from_secs(30),
+        };
+        let targets = [
+            SocketAddr::from(([192, 0, 2, 1], 80)),
+            SocketAddr::from(([192, 0, 2, 9], 80)),
+        ];
+        let (_, addr) =
+            tokio::time::timeout(Duration::from_secs(1), slow.connect(&targets, fake_connect))
+                .await
+                .expect("refusal hands over without waiting for the delay")
+                .unwrap();
+        assert_eq!(addr, targets[1]);
+
+        let err = slow.connect(&targets[..1], fake_connect).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
 }
@@ -0,0 +1,209 @@
+//! Retrying upstream connects that fail transiently (`server.connect_retries`).
+//!
+//! Only a refused or timed-out connect is retried: the destination is briefly down or
+//! restarting. Every other failure, and anything decided before the connect (ACL
+//! blocks, resolution errors), is final. The session record is only created once the
+//! connect succeeds, so a CONNECT gets one session however many attempts it took.
+
+use crate::config::ServerConfig;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Default wait before the first retry (`server.connect_retry_backoff_ms`)
+pub const DEFAULT_CONNECT_RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Time a CONNECT may spend on its attempts before it is answered with a failure.
+///
+/// Clients wait for the SOCKS reply with their own timeout; past this point a retry
+/// would more likely reach a client that already gave up.
+pub const CONNECT_RETRY_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Retry policy for upstream connects, from `[server]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectRetry {
+    /// Attempts after the first; 0 disables retrying
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each one after it
+    pub backoff: Duration,
+    /// Limit on the time from the first attempt to the last one ending
+    pub deadline: Duration,
+}
+
+impl Default for ConnectRetry {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: DEFAULT_CONNECT_RETRY_BACKOFF,
+            deadline: CONNECT_RETRY_DEADLINE,
+        }
+    }
+}
+
+impl From<&ServerConfig> for ConnectRetry {
+    fn from(server: &ServerConfig) -> Self {
+        Self {
+            retries: server.connect_retries,
+            backoff: Duration::from_millis(server.connect_retry_backoff_ms),
+            deadline: CONNECT_RETRY_DEADLINE,
+        }
+    }
+}
+
+impl ConnectRetry {
+    /// Metric label of a failure worth retrying: the connect was refused or timed out
+    pub fn transient_reason(err: &io::Error) -> Option<&'static str> {
+        match err.kind() {
+            io::ErrorKind::ConnectionRefused => Some("refused"),
+            io::ErrorKind::TimedOut => Some("timeout"),
+            _ => None,
+        }
+    }
+
+    /// Wait before retry number `retry` (1 for the first)
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1u32 << retry.saturating_sub(1).min(16))
+    }
+
+    /// Run `connect` until it succeeds, fails for good or runs out of retries.
+    ///
+    /// `attempt_timeout` is how long one attempt may take; a retry only starts when it
+    /// can end before the deadline, and is cut off there in any case. On failure,
+    /// returns the last attempt's error.
+    pub async fn run<T, F, Fut>(&self, attempt_timeout: Duration, mut connect: F) -> io::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = io::Result<T>>,
+    {
+        let started = Instant::now();
+        let mut result = connect().await;
+
+        for retry in 1..=self.retries {
+            let err = match result {
+                Ok(stream) => return Ok(stream),
+                Err(err) => err,
+            };
+            let Some(reason) = Self::transient_reason(&err) else {
+                return Err(err);
+            };
+
+            let delay = self.delay(retry);
+            let deadline = started + self.deadline;
+            if Instant::now() + delay + attempt_timeout > deadline {
+                debug!(
+                    retry,
+                    error = %err,
+                    "Upstream connect retry would pass the {:?} deadline, giving up",
+                    self.deadline
+                );
+                return Err(err);
+            }
+
+            debug!(
+                retry,
+                retries = self.retries,
+                reason,
+                delay_ms = delay.as_millis() as u64,
+                error = %err,
+                "Retrying upstream connect"
+            );
+            #[cfg(feature = "metrics")]
+            crate::session::SessionMetrics::record_connect_retry(reason);
+            tokio::time::sleep(delay).await;
+
+            result = match tokio::time::timeout_at(deadline, connect()).await {
+                Ok(result) => result,
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("upstream connect retries exceeded {:?}", self.deadline),
+                )),
+            };
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy(retries: u32) -> ConnectRetry {
+        ConnectRetry {
+            retries,
+            backoff: Duration::from_millis(10),
+            deadline: Duration::from_millis(300),
+        }
+    }
+
+    fn failure(kind: io::ErrorKind) -> io::Error {
+        io::Error::new(kind, "test failure")
+    }
+
+    #[test]
+    fn backoff_doubles_per_retry() {
+        let retry = policy(3);
+        assert_eq!(retry.delay(1), Duration::from_millis(10));
+        assert_eq!(retry.delay(2), Duration::from_millis(20));
+        assert_eq!(retry.delay(3), Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn refused_connects_are_retried_until_one_succeeds() {
+        let attempts = AtomicU32::new(0);
+        let result = policy(3)
+            .run(Duration::from_millis(50), || async {
+                match attempts.fetch_add(1, Ordering::Relaxed) {
+                    0 | 1 => Err(failure(io::ErrorKind::ConnectionRefused)),
+                    _ => Ok("connected"),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), "connected");
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn other_failures_and_disabled_policy_fail_at_once() {
+        let attempts = AtomicU32::new(0);
+        let result: io::Result<()> = policy(3)
+            .run(Duration::from_millis(50), || async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(failure(io::ErrorKind::PermissionDenied))
+            })
+            .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+
+        let attempts = AtomicU32::new(0);
+        let result: io::Result<()> = policy(0)
+            .run(Duration::from_millis(50), || async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(failure(io::ErrorKind::ConnectionRefused))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_deadline() {
+        let attempts = AtomicU32::new(0);
+        let started = Instant::now();
+        let result: io::Result<()> = policy(10)
+            .run(Duration::from_millis(100), || async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Err(failure(io::ErrorKind::TimedOut))
+            })
+            .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        // 100 ms per attempt and a growing backoff leave room for one retry in 300 ms
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+        assert!(started.elapsed() < Duration::from_millis(300));
+    }
+}
@@ -0,0 +1,546 @@
+//! `rustsocks check`: validate a configuration bundle without starting any listener.
+//!
+//! Everything an enabled feature depends on is loaded the way the server would load it:
+//! the main file, the ACL file, the users file, the TLS certificates and keys, the
+//! session database and the PAM service files. Each check ends `ok`, `warning`,
+//! `error` or `skipped` (its feature is off); the bundle passes when no check ended in
+//! an error. Warnings flag what works today but needs attention, such as a certificate
+//! expiring within [`CERT_EXPIRY_WARNING_DAYS`] days.
+
+use crate::acl::lint::lint;
+use crate::acl::{load_acl_config_sync, AclEngine, LintSettings, LintSeverity};
+use crate::auth::{certificate_not_after, load_users_file};
+use crate::config::{Config, TlsSettings, UpstreamTlsSettings};
+use crate::server::listener::{create_tls_acceptor, load_certificates, load_private_key};
+use crate::server::upstream_tls::UpstreamTls;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use rustls::pki_types::CertificateDer;
+use rustls::sign::CertifiedKey;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+/// Certificates expiring within this many days are reported as warnings
+pub const CERT_EXPIRY_WARNING_DAYS: i64 = 14;
+
+/// How long the session database gets to accept a connection
+pub const DATABASE_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+    /// The feature the check belongs to is not enabled
+    Skipped,
+}
+
+impl CheckStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warning => "warning",
+            CheckStatus::Error => "error",
+            CheckStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// Outcome of one check, e.g. `tls.server.certificate`
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    /// `--config` file, `None` when the defaults were checked
+    pub config_path: Option<String>,
+    /// No check ended in an error
+    pub passed: bool,
+    pub errors: usize,
+    pub warnings: usize,
+    pub checks: Vec<SelfTestCheck>,
+}
+
+impl SelfTestReport {
+    /// One line per check and a summary, for a terminal
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        for check in &self.checks {
+            let _ = writeln!(
+                out,
+                "{:<9} {}: {}",
+                format!("[{}]", check.status.as_str()),
+                check.name,
+                check.message
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}: {} error(s), {} warning(s)",
+            if self.passed { "PASSED" } else { "FAILED" },
+            self.errors,
+            self.warnings
+        );
+        out
+    }
+}
+
+/// Collects checks while the run goes
+#[derive(Default)]
+struct Checks(Vec<SelfTestCheck>);
+
+impl Checks {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, message: impl Into<String>) {
+        self.0.push(SelfTestCheck {
+            name: name.into(),
+            status,
+            message: message.into(),
+        });
+    }
+
+    /// Whether a check pushed after the first `start` ones failed
+    fn failed_since(&self, start: usize) -> bool {
+        self.0[start..]
+            .iter()
+            .any(|check| check.status == CheckStatus::Error)
+    }
+
+    fn finish(self, config_path: Option<&Path>) -> SelfTestReport {
+        let count = |status| self.0.iter().filter(|c| c.status == status).count();
+        let errors = count(CheckStatus::Error);
+        let warnings = count(CheckStatus::Warning);
+        SelfTestReport {
+            config_path: config_path.map(|path| path.display().to_string()),
+            passed: errors == 0,
+            errors,
+            warnings,
+            checks: self.0,
+        }
+    }
+}
+
+/// Check the bundle around `config_path` (the defaults when `None`).
+///
+/// When the main file does not load, nothing else is checked.
+pub async fn run_self_test(config_path: Option<&Path>) -> SelfTestReport {
+    let mut checks = Checks::default();
+
+    let config = match config_path {
+        Some(path) => match Config::from_file(path) {
+            Ok(config) => {
+                checks.push(
+                    "config",
+                    CheckStatus::Ok,
+                    format!("{} loaded and validated", path.display()),
+                );
+                config
+            }
+            Err(e) => {
+                checks.push("config", CheckStatus::Error, e.to_string());
+                return checks.finish(config_path);
+            }
+        },
+        None => {
+            checks.push(
+                "config",
+                CheckStatus::Ok,
+                "no --config given, checking the built-in defaults",
+            );
+            Config::default()
+        }
+    };
+
+    let now = Utc::now();
+    check_acl(&config, &mut checks);
+    check_users_file(&config, &mut checks);
+    check_server_tls(&config.server.tls, now, &mut checks);
+    check_upstream_tls(&config.server.upstream_tls, now, &mut checks);
+    check_database(&config, &mut checks).await;
+    check_pam(&config, &mut checks);
+
+    checks.finish(config_path)
+}
+
+fn check_acl(config: &Config, checks: &mut Checks) {
+    if !config.acl.enabled {
+        checks.push("acl", CheckStatus::Skipped, "ACL disabled");
+        return;
+    }
+    let path = config
+        .acl
+        .config_file
+        .as_deref()
+        .expect("validated: config_file must be provided when ACL is enabled");
+    let acl = match load_acl_config_sync(path) {
+        Ok(acl) => acl,
+        Err(e) => {
+            checks.push("acl", CheckStatus::Error, e);
+            return;
+        }
+    };
+
+    let lint_settings = LintSettings::from(config);
+    let findings = lint(&acl, &lint_settings);
+    for finding in &findings {
+        let status = match finding.severity {
+            LintSeverity::Warning => CheckStatus::Warning,
+            LintSeverity::Error => CheckStatus::Error,
+        };
+        checks.push(
+            format!("acl.lint.{}", finding.kind.as_str()),
+            status,
+            format!("{}: {}", finding.scope, finding.message),
+        );
+    }
+
+    let rules: usize = acl.users.iter().map(|user| user.rules.len()).sum::<usize>()
+        + acl
+            .groups
+            .iter()
+            .map(|group| group.rules.len())
+            .sum::<usize>();
+    let summary = format!(
+        "{}: {} user(s), {} group(s), {} rule(s), {} lint finding(s)",
+        path,
+        acl.users.len(),
+        acl.groups.len(),
+        rules,
+        findings.len()
+    );
+    match AclEngine::with_lint_settings(acl, lint_settings) {
+        Ok(_) => checks.push("acl", CheckStatus::Ok, summary),
+        Err(e) => checks.push("acl", CheckStatus::Error, format!("{}: {}", path, e)),
+    }
+}
+
+fn check_users_file(config: &Config, checks: &mut Checks) {
+    let Some(path) = config.auth.users_file.as_deref() else {
+        checks.push(
+            "users_file",
+            CheckStatus::Skipped,
+            "auth.users_file not set",
+        );
+        return;
+    };
+    match load_users_file(path) {
+        Ok(users) => checks.push(
+            "users_file",
+            CheckStatus::Ok,
+            format!("{}: {} user(s)", path, users.len()),
+        ),
+        Err(e) => checks.push("users_file", CheckStatus::Error, e.to_string()),
+    }
+}
+
+fn check_server_tls(tls: &TlsSettings, now: DateTime<Utc>, checks: &mut Checks) {
+    if !tls.enabled {
+        checks.push("tls.server", CheckStatus::Skipped, "server.tls disabled");
+        return;
+    }
+    let cert_path = tls
+        .certificate_path
+        .as_deref()
+        .expect("validated: certificate_path must be set when TLS is enabled");
+    let key_path = tls
+        .private_key_path
+        .as_deref()
+        .expect("validated: private_key_path must be set when TLS is enabled");
+    let start = checks.0.len();
+    check_certificate_and_key("tls.server", cert_path, key_path, now, checks);
+    if let Some(ca_path) = tls.client_ca_path.as_deref() {
+        check_ca_bundle("tls.server.client_ca", ca_path, now, checks);
+    }
+
+    // Everything else the acceptor needs: protocol versions, key_password, client auth
+    if checks.failed_since(start) {
+        return;
+    }
+    match create_tls_acceptor(tls) {
+        Ok(_) => checks.push("tls.server", CheckStatus::Ok, "TLS acceptor builds"),
+        Err(e) => checks.push("tls.server", CheckStatus::Error, e.to_string()),
+    }
+}
+
+fn check_upstream_tls(settings: &UpstreamTlsSettings, now: DateTime<Utc>, checks: &mut Checks) {
+    if !settings.enabled {
+        checks.push(
+            "tls.upstream",
+            CheckStatus::Skipped,
+            "server.upstream_tls disabled",
+        );
+        return;
+    }
+    let start = checks.0.len();
+    if let Some(ca_path) = settings.ca_path.as_deref() {
+        check_ca_bundle("tls.upstream.ca", ca_path, now, checks);
+    }
+    if let (Some(cert_path), Some(key_path)) = (
+        settings.client_certificate_path.as_deref(),
+        settings.client_key_path.as_deref(),
+    ) {
+        check_certificate_and_key("tls.upstream.client", cert_path, key_path, now, checks);
+    }
+
+    if checks.failed_since(start) {
+        return;
+    }
+    match UpstreamTls::from_settings(settings) {
+        Ok(_) => checks.push("tls.upstream", CheckStatus::Ok, "TLS connector builds"),
+        Err(e) => checks.push("tls.upstream", CheckStatus::Error, e.to_string()),
+    }
+}
+
+/// `<prefix>.certificate` (parses, expiry of the leaf) and `<prefix>.key` (parses,
+/// belongs to the leaf)
+fn check_certificate_and_key(
+    prefix: &str,
+    cert_path: &str,
+    key_path: &str,
+    now: DateTime<Utc>,
+    checks: &mut Checks,
+) {
+    let certificate_check = format!("{}.certificate", prefix);
+    let key_check = format!("{}.key", prefix);
+
+    let certs = match load_certificates(cert_path) {
+        Ok(certs) => {
+            let (status, expiry) = expiry_status(&certs[0], now);
+            checks.push(
+                &certificate_check,
+                status,
+                format!("{}: {}", cert_path, expiry),
+            );
+            Some(certs)
+        }
+        Err(e) => {
+            checks.push(&certificate_check, CheckStatus::Error, e.to_string());
+            None
+        }
+    };
+    let key = match load_private_key(key_path) {
+        Ok(key) => key,
+        Err(e) => {
+            checks.push(&key_check, CheckStatus::Error, e.to_string());
+            return;
+        }
+    };
+    let Some(certs) = certs else {
+        checks.push(
+            &key_check,
+            CheckStatus::Skipped,
+            format!("{} parsed; no certificate to match it against", key_path),
+        );
+        return;
+    };
+
+    let signing_key = match rustls::crypto::ring::sign::any_supported_type(&key) {
+        Ok(signing_key) => signing_key,
+        Err(e) => {
+            checks.push(
+                &key_check,
+                CheckStatus::Error,
+                format!("{}: unsupported private key: {}", key_path, e),
+            );
+            return;
+        }
+    };
+    match CertifiedKey::new(certs, signing_key).keys_match() {
+        Ok(()) => checks.push(
+            &key_check,
+            CheckStatus::Ok,
+            format!("{} matches the certificate", key_path),
+        ),
+        Err(rustls::Error::InconsistentKeys(rustls::InconsistentKeys::KeyMismatch)) => checks.push(
+            &key_check,
+            CheckStatus::Error,
+            format!("{} does not belong to {}", key_path, cert_path),
+        ),
+        Err(e) => checks.push(
+            &key_check,
+            CheckStatus::Warning,
+            format!(
+                "{}: could not compare with the certificate: {}",
+                key_path, e
+            ),
+        ),
+    }
+}
+
+/// A CA bundle passes while one of its certificates is still valid; expired or soon
+/// expiring members are warnings.
+fn check_ca_bundle(name: &str, path: &str, now: DateTime<Utc>, checks: &mut Checks) {
+    let certs = match load_certificates(path) {
+        Ok(certs) => certs,
+        Err(e) => {
+            checks.push(name, CheckStatus::Error, e.to_string());
+            return;
+        }
+    };
+
+    let statuses: Vec<_> = certs.iter().map(|cert| expiry_status(cert, now)).collect();
+    if statuses
+        .iter()
+        .all(|(status, _)| *status == CheckStatus::Error)
+    {
+        checks.push(
+            name,
+            CheckStatus::Error,
+            format!("{}: every certificate has expired", path),
+        );
+        return;
+    }
+    for (index, (status, expiry)) in statuses.iter().enumerate() {
+        if *status != CheckStatus::Ok {
+            checks.push(
+                name,
+                CheckStatus::Warning,
+                format!("{}: certificate #{} {}", path, index + 1, expiry),
+            );
+        }
+    }
+    checks.push(
+        name,
+        CheckStatus::Ok,
+        format!("{}: {} certificate(s)", path, certs.len()),
+    );
+}
+
+/// Error once expired, warning within [`CERT_EXPIRY_WARNING_DAYS`]
+fn expiry_status(cert: &CertificateDer<'_>, now: DateTime<Utc>) -> (CheckStatus, String) {
+    let Some(not_after) = certificate_not_after(cert) else {
+        return (
+            CheckStatus::Warning,
+            "expiry date could not be read".to_string(),
+        );
+    };
+    let expiry = not_after.to_rfc3339();
+    if not_after <= now {
+        (CheckStatus::Error, format!("expired on {}", expiry))
+    } else if not_after - now <= ChronoDuration::days(CERT_EXPIRY_WARNING_DAYS) {
+        (
+            CheckStatus::Warning,
+            format!(
+                "expires in {} day(s), on {}",
+                (not_after - now).num_days(),
+                expiry
+            ),
+        )
+    } else {
+        (CheckStatus::Ok, format!("valid until {}", expiry))
+    }
+}
+
+async fn check_database(config: &Config, checks: &mut Checks) {
+    if !config.sessions.uses_database() {
+        checks.push(
+            "database",
+            CheckStatus::Skipped,
+            format!("sessions.storage = \"{}\"", config.sessions.storage),
+        );
+        return;
+    }
+    let url = config
+        .sessions
+        .database_url
+        .as_deref()
+        .expect("validated: database_url present when SQL-backed storage enabled");
+
+    #[cfg(feature = "database")]
+    {
+        use crate::session::SessionStore;
+
+        match tokio::time::timeout(
+            DATABASE_CHECK_TIMEOUT,
+            SessionStore::check_connectivity(url),
+        )
+        .await
+        {
+            Ok(Ok(())) => checks.push("database", CheckStatus::Ok, "session database reachable"),
+            Ok(Err(e)) => checks.push("database", CheckStatus::Error, e.to_string()),
+            Err(_) => checks.push(
+                "database",
+                CheckStatus::Error,
+                format!("no connection within {}s", DATABASE_CHECK_TIMEOUT.as_secs()),
+            ),
+        }
+    }
+    #[cfg(not(feature = "database"))]
+    {
+        let _ = url;
+        checks.push(
+            "database",
+            CheckStatus::Error,
+            format!(
+                "sessions.storage = \"{}\" needs a build with the database feature",
+                config.sessions.storage
+            ),
+        );
+    }
+}
+
+/// `/etc/pam.d/<service>` of every PAM method in use
+fn check_pam(config: &Config, checks: &mut Checks) {
+    let auth = &config.auth;
+    let mut services = Vec::new();
+    if auth.client_method == "pam.address" || auth.uses_socks_method("pam.address") {
+        services.push(("address_service", &auth.pam.address_service));
+    }
+    if auth.uses_socks_method("pam.username") {
+        services.push(("username_service", &auth.pam.username_service));
+    }
+    if services.is_empty() {
+        checks.push("pam", CheckStatus::Skipped, "no PAM method configured");
+        return;
+    }
+
+    for (key, service) in services {
+        let path = Path::new("/etc/pam.d").join(service);
+        if path.is_file() {
+            checks.push(
+                format!("pam.{}", key),
+                CheckStatus::Ok,
+                format!("{} exists", path.display()),
+            );
+        } else {
+            checks.push(
+                format!("pam.{}", key),
+                CheckStatus::Error,
+                format!(
+                    "{} not found (auth.pam.{} = \"{}\")",
+                    path.display(),
+                    key,
+                    service
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_do_not_fail_the_report() {
+        let mut checks = Checks::default();
+        checks.push("config", CheckStatus::Ok, "loaded");
+        checks.push("tls.server.certificate", CheckStatus::Warning, "expires");
+        checks.push("database", CheckStatus::Skipped, "off");
+        let report = checks.finish(None);
+        assert!(report.passed);
+        assert_eq!((report.errors, report.warnings), (0, 1));
+        assert!(report
+            .render_text()
+            .ends_with("PASSED: 0 error(s), 1 warning(s)\n"));
+
+        let mut checks = Checks::default();
+        checks.push("pam.address_service", CheckStatus::Error, "missing");
+        assert!(!checks.finish(None).passed);
+    }
+}
@@ -0,0 +1,404 @@
+//! TLS ClientHello inspection for SNI-based classification.
+//!
+//! Clients frequently CONNECT to an IP literal of a shared frontend and only name the
+//! real virtual host in the TLS SNI extension. With `acl.classify_by_sni` enabled the
+//! handler peeks at the first client bytes sent to one of `acl.sni_ports`, extracts that
+//! name and uses it as the session's logical destination. The dialled IP never changes.
+
+use crate::config::AclSettings;
+use std::borrow::Cow;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::time::Instant;
+
+/// TLS record header: content type, legacy version, length.
+const RECORD_HEADER_LEN: usize = 5;
+/// Handshake message header: type and 24-bit length.
+const HANDSHAKE_HEADER_LEN: usize = 4;
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const NAME_TYPE_HOST_NAME: u8 = 0x00;
+/// Upper bound on bytes buffered while a ClientHello is reassembled (two full records).
+pub const MAX_PEEK_BYTES: usize = 2 * (RECORD_HEADER_LEN + 16 * 1024);
+
+/// Outcome of parsing the bytes peeked so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SniParse {
+    /// ClientHello carried a host_name entry
+    Found(String),
+    /// Complete ClientHello without SNI
+    Missing,
+    /// Not a TLS handshake, or a malformed ClientHello
+    Invalid,
+    /// Looks like a ClientHello but more bytes are needed
+    Incomplete,
+}
+
+/// What happens to a TLS-port session whose ClientHello cannot be read in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniFailMode {
+    /// Drop the connection; the name-based ACL stage cannot be bypassed by stalling
+    Block,
+    /// Keep the IP-stage decision
+    Allow,
+}
+
+/// Runtime settings for SNI classification, derived from `[acl]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SniRouting {
+    pub enabled: bool,
+    pub peek_timeout: Duration,
+    /// Destination ports whose traffic is expected to start with a ClientHello
+    pub ports: Vec<u16>,
+    pub fail_mode: SniFailMode,
+}
+
+impl SniRouting {
+    /// Whether a CONNECT to `port` is peeked.
+    pub fn applies_to_port(&self, port: u16) -> bool {
+        self.enabled && self.ports.contains(&port)
+    }
+}
+
+impl Default for SniRouting {
+    fn default() -> Self {
+        Self::from(&AclSettings::default())
+    }
+}
+
+impl From<&AclSettings> for SniRouting {
+    fn from(settings: &AclSettings) -> Self {
+        let fail_mode = match settings.sni_fail_mode.as_str() {
+            "allow" => SniFailMode::Allow,
+            _ => SniFailMode::Block,
+        };
+
+        Self {
+            enabled: settings.classify_by_sni,
+            peek_timeout: Duration::from_millis(settings.sni_peek_timeout_ms),
+            ports: settings.sni_ports.clone(),
+            fail_mode,
+        }
+    }
+}
+
+/// Extract the SNI host name from the start of a client byte stream.
+///
+/// A ClientHello fragmented across several handshake records is reassembled before it
+/// is parsed.
+pub fn parse_sni(data: &[u8]) -> SniParse {
+    let mut handshake: Cow<'_, [u8]> = Cow::Borrowed(&[]);
+    let mut rest = data;
+
+    loop {
+        if rest.is_empty() {
+            return SniParse::Incomplete;
+        }
+        if rest[0] != CONTENT_TYPE_HANDSHAKE {
+            return SniParse::Invalid;
+        }
+        if rest.len() < RECORD_HEADER_LEN {
+            return SniParse::Incomplete;
+        }
+        if rest[1] != 0x03 {
+            return SniParse::Invalid;
+        }
+
+        let record_len = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        if record_len == 0 {
+            return SniParse::Invalid;
+        }
+        let Some(fragment) = rest.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + record_len) else {
+            return SniParse::Incomplete;
+        };
+        rest = &rest[RECORD_HEADER_LEN + record_len..];
+
+        // Only a ClientHello split across records needs a copy
+        if handshake.is_empty() {
+            handshake = Cow::Borrowed(fragment);
+        } else {
+            handshake.to_mut().extend_from_slice(fragment);
+        }
+
+        if handshake.len() < HANDSHAKE_HEADER_LEN {
+            continue;
+        }
+        if handshake[0] != HANDSHAKE_CLIENT_HELLO {
+            return SniParse::Invalid;
+        }
+        let message_len = HANDSHAKE_HEADER_LEN
+            + (((handshake[1] as usize) << 16)
+                | ((handshake[2] as usize) << 8)
+                | handshake[3] as usize);
+        if message_len > MAX_PEEK_BYTES {
+            return SniParse::Invalid;
+        }
+        if handshake.len() < message_len {
+            continue;
+        }
+
+        return match parse_client_hello(&handshake[..message_len]) {
+            Some(Some(name)) => SniParse::Found(name),
+            Some(None) => SniParse::Missing,
+            None => SniParse::Invalid,
+        };
+    }
+}
+
+/// Returns `None` for malformed input, `Some(None)` for a ClientHello without SNI.
+fn parse_client_hello(record: &[u8]) -> Option<Option<String>> {
+    let mut reader = Reader::new(record);
+
+    if reader.u8()? != HANDSHAKE_CLIENT_HELLO {
+        return None;
+    }
+    let body_len = reader.u24()?;
+    let mut hello = Reader::new(reader.bytes(body_len)?);
+
+    hello.skip(2 + 32)?; // legacy_version, random
+    let session_id_len = hello.u8()? as usize;
+    hello.skip(session_id_len)?;
+    let cipher_suites_len = hello.u16()? as usize;
+    hello.skip(cipher_suites_len)?;
+    let compression_len = hello.u8()? as usize;
+    hello.skip(compression_len)?;
+
+    if hello.is_empty() {
+        return Some(None);
+    }
+
+    let extensions_len = hello.u16()? as usize;
+    let mut extensions = Reader::new(hello.bytes(extensions_len)?);
+    while !extensions.is_empty() {
+        let ext_type = extensions.u16()?;
+        let ext_len = extensions.u16()? as usize;
+        let ext = extensions.bytes(ext_len)?;
+        if ext_type == EXTENSION_SERVER_NAME {
+            return parse_server_name(ext).map(Some);
+        }
+    }
+
+    Some(None)
+}
+
+fn parse_server_name(ext: &[u8]) -> Option<String> {
+    let mut reader = Reader::new(ext);
+    let list_len = reader.u16()? as usize;
+    let mut list = Reader::new(reader.bytes(list_len)?);
+
+    while !list.is_empty() {
+        let name_type = list.u8()?;
+        let name_len = list.u16()? as usize;
+        let name = list.bytes(name_len)?;
+        if name_type == NAME_TYPE_HOST_NAME {
+            let name = std::str::from_utf8(name).ok()?;
+            if name.is_empty() || !name.is_ascii() {
+                return None;
+            }
+            return Some(name.trim_end_matches('.').to_ascii_lowercase());
+        }
+    }
+
+    None
+}
+
+/// Read client bytes until the SNI can be decided or `timeout` elapses.
+///
+/// Returns every byte read (they must be forwarded upstream unchanged) and the parse
+/// outcome. EOF or a timeout before a full ClientHello yields [`SniParse::Incomplete`].
+pub async fn peek_sni<R>(reader: &mut R, timeout: Duration) -> std::io::Result<(Vec<u8>, SniParse)>
+where
+    R: AsyncRead + Unpin,
+{
+    let deadline = Instant::now() + timeout;
+    let mut buffer = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        let read = match tokio::time::timeout_at(deadline, reader.read(&mut chunk)).await {
+            Ok(result) => result?,
+            Err(_) => return Ok((buffer, SniParse::Incomplete)),
+        };
+        if read == 0 {
+            return Ok((buffer, SniParse::Incomplete));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        match parse_sni(&buffer) {
+            SniParse::Incomplete if buffer.len() >= MAX_PEEK_BYTES => {
+                return Ok((buffer, SniParse::Invalid))
+            }
+            SniParse::Incomplete => {}
+            outcome => return Ok((buffer, outcome)),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Some(head)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.bytes(3)
+            .map(|b| ((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+}
+
+/// Build a minimal TLS 1.2 ClientHello record carrying `server_name`.
+#[cfg(test)]
+fn client_hello_with_sni(server_name: &str) -> Vec<u8> {
+    let name = server_name.as_bytes();
+
+    let mut sni_ext = Vec::with_capacity(name.len() + 9);
+    sni_ext.extend_from_slice(&EXTENSION_SERVER_NAME.to_be_bytes());
+    sni_ext.extend_from_slice(&((name.len() + 5) as u16).to_be_bytes());
+    sni_ext.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+    sni_ext.push(NAME_TYPE_HOST_NAME);
+    sni_ext.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    sni_ext.extend_from_slice(name);
+
+    let mut hello = Vec::with_capacity(sni_ext.len() + 48);
+    hello.extend_from_slice(&[0x03, 0x03]);
+    hello.extend_from_slice(&[0u8; 32]);
+    hello.push(0); // session id
+    hello.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // one cipher suite
+    hello.extend_from_slice(&[0x01, 0x00]); // null compression
+    hello.extend_from_slice(&(sni_ext.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&sni_ext);
+
+    let mut handshake = Vec::with_capacity(hello.len() + 4);
+    handshake.push(HANDSHAKE_CLIENT_HELLO);
+    handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&hello);
+
+    let mut record = Vec::with_capacity(handshake.len() + RECORD_HEADER_LEN);
+    record.extend_from_slice(&[CONTENT_TYPE_HANDSHAKE, 0x03, 0x01]);
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_host_name() {
+        let record = client_hello_with_sni("Blocked-Site.com.");
+        assert_eq!(
+            parse_sni(&record),
+            SniParse::Found("blocked-site.com".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_incomplete_records() {
+        let record = client_hello_with_sni("example.com");
+        assert_eq!(parse_sni(&record[..3]), SniParse::Incomplete);
+        assert_eq!(parse_sni(&record[..record.len() - 1]), SniParse::Incomplete);
+    }
+
+    #[test]
+    fn reassembles_hello_split_across_records() {
+        let record = client_hello_with_sni("split.example.com");
+        let handshake = &record[RECORD_HEADER_LEN..];
+        let (first, second) = handshake.split_at(20);
+
+        let mut split = Vec::new();
+        for fragment in [first, second] {
+            split.extend_from_slice(&[CONTENT_TYPE_HANDSHAKE, 0x03, 0x01]);
+            split.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            split.extend_from_slice(fragment);
+        }
+
+        let first_record_len = RECORD_HEADER_LEN + first.len();
+        assert_eq!(parse_sni(&split[..first_record_len]), SniParse::Incomplete);
+        assert_eq!(
+            parse_sni(&split),
+            SniParse::Found("split.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_non_tls_traffic() {
+        assert_eq!(parse_sni(b"GET / HTTP/1.1\r\n"), SniParse::Invalid);
+        assert_eq!(
+            parse_sni(&[0x16, 0x03, 0x01, 0x00, 0x04, 0x02, 0x00, 0x00, 0x00]),
+            SniParse::Invalid
+        );
+    }
+
+    #[test]
+    fn routing_settings_follow_acl_config() {
+        let routing = SniRouting::from(&AclSettings {
+            classify_by_sni: true,
+            sni_ports: vec![443, 8443],
+            sni_fail_mode: "allow".to_string(),
+            ..AclSettings::default()
+        });
+        assert!(routing.applies_to_port(8443));
+        assert!(!routing.applies_to_port(80));
+        assert_eq!(routing.fail_mode, SniFailMode::Allow);
+
+        let defaults = SniRouting::default();
+        assert!(!defaults.applies_to_port(443));
+        assert_eq!(defaults.ports, vec![443]);
+        assert_eq!(defaults.fail_mode, SniFailMode::Block);
+    }
+
+    #[tokio::test]
+    async fn peek_returns_all_bytes_read() {
+        let mut data = client_hello_with_sni("example.com");
+        data.extend_from_slice(b"trailing");
+        let mut reader = data.as_slice();
+
+        let (peeked, sni) = peek_sni(&mut reader, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert_eq!(sni, SniParse::Found("example.com".to_string()));
+        assert_eq!(peeked, data);
+    }
+
+    #[tokio::test]
+    async fn peek_reports_truncated_hello_as_incomplete() {
+        let data = client_hello_with_sni("example.com");
+        let mut reader = &data[..10];
+
+        let (peeked, sni) = peek_sni(&mut reader, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert_eq!(sni, SniParse::Incomplete);
+        assert_eq!(peeked, &data[..10]);
+    }
+}
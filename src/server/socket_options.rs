@@ -0,0 +1,547 @@
+//! Per-destination socket options for upstream connections
+//! (`[[server.upstream_socket_options]]`).
+//!
+//! TCP MD5 signatures (RFC 2385) have to be in place before the SYN goes out and are
+//! keyed by the peer address, so options are applied to a fresh socket after the
+//! destination is resolved and before it connects. For that reason tunnels with options
+//! never use pooled upstream connections. DSCP / IP_TOS marking rides along on the same
+//! socket, so the SYN is classified like the rest of the flow.
+//!
+//! The same fresh socket carries a user's egress address (`[server.egress]`), bound
+//! before it connects.
+//!
+//! TCP-AO (RFC 5925) is reserved in the configuration but rejected by validation until
+//! kernel support is broadly available.
+//!
+//! [`TcpTuning`] holds the options every TCP socket gets (`[server.tcp]`): accepted
+//! client sockets right after accept, upstream sockets before they connect, so the
+//! receive buffer can still size the TCP window scale.
+
+use crate::acl::matcher::{CompiledDestinationMatcher, CompiledPortMatcher};
+use crate::config::{ServerConfig, TcpSocketSettings};
+use crate::protocol::Address;
+use crate::server::egress::EgressAddresses;
+use socket2::{SockRef, Socket};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::time::timeout;
+use tracing::debug;
+use zeroize::Zeroizing;
+
+/// Longest key TCP_MD5SIG accepts.
+pub const TCP_MD5_MAX_KEY_LEN: usize = 80;
+
+/// TCP options for client and upstream sockets, from `[server.tcp]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpTuning {
+    pub nodelay: bool,
+    /// SO_KEEPALIVE idle time and probe interval; `None` leaves keepalive off
+    pub keepalive: Option<Duration>,
+    /// SO_SNDBUF; `None` keeps the kernel default
+    pub send_buffer_bytes: Option<usize>,
+    /// SO_RCVBUF; `None` keeps the kernel default
+    pub recv_buffer_bytes: Option<usize>,
+}
+
+impl From<&TcpSocketSettings> for TcpTuning {
+    fn from(settings: &TcpSocketSettings) -> Self {
+        let bytes = |bytes: u32| (bytes > 0).then_some(bytes as usize);
+        Self {
+            nodelay: settings.nodelay,
+            keepalive: (settings.keepalive_secs > 0)
+                .then(|| Duration::from_secs(settings.keepalive_secs)),
+            send_buffer_bytes: bytes(settings.send_buffer_bytes),
+            recv_buffer_bytes: bytes(settings.recv_buffer_bytes),
+        }
+    }
+}
+
+impl Default for TcpTuning {
+    fn default() -> Self {
+        Self::from(&TcpSocketSettings::default())
+    }
+}
+
+impl TcpTuning {
+    /// Set every option on `socket`. `side` ("client" or "upstream") and `peer` only
+    /// label the debug line logged when the options differ from the defaults.
+    pub fn apply(&self, socket: &Socket, side: &str, peer: SocketAddr) -> io::Result<()> {
+        socket.set_nodelay(self.nodelay)?;
+        if let Some(interval) = self.keepalive {
+            socket.set_tcp_keepalive(
+                &socket2::TcpKeepalive::new()
+                    .with_time(interval)
+                    .with_interval(interval),
+            )?;
+        }
+        if let Some(bytes) = self.send_buffer_bytes {
+            socket.set_send_buffer_size(bytes)?;
+        }
+        if let Some(bytes) = self.recv_buffer_bytes {
+            socket.set_recv_buffer_size(bytes)?;
+        }
+
+        if *self != Self::default() {
+            debug!(
+                side,
+                peer = %peer,
+                nodelay = self.nodelay,
+                keepalive_secs = self.keepalive.map_or(0, |interval| interval.as_secs()),
+                send_buffer_bytes = self.send_buffer_bytes.unwrap_or(0),
+                recv_buffer_bytes = self.recv_buffer_bytes.unwrap_or(0),
+                "Applied TCP socket options"
+            );
+        }
+        Ok(())
+    }
+
+    /// A fresh socket of `peer`'s family with the options set, ready to connect.
+    pub fn socket_for(&self, peer: SocketAddr) -> io::Result<TcpSocket> {
+        let socket = match peer {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        self.apply(&SockRef::from(&socket), "upstream", peer)?;
+        Ok(socket)
+    }
+
+    /// Open a new connection to `peer` with the options set before the SYN.
+    pub async fn connect(&self, peer: SocketAddr, limit: Duration) -> io::Result<TcpStream> {
+        connect_socket(self.socket_for(peer)?, peer, limit).await
+    }
+}
+
+/// Options for one upstream socket.
+#[derive(Clone, Default)]
+pub struct SocketOptionPlan {
+    /// TCP MD5 signature key for the peer
+    pub tcp_md5_key: Option<Arc<Zeroizing<Vec<u8>>>>,
+    /// Byte written to IP_TOS (IPv4) or IPV6_TCLASS (IPv6)
+    pub traffic_class: Option<u8>,
+    /// Source addresses of the user's egress rule
+    pub egress: Option<EgressAddresses>,
+}
+
+impl fmt::Debug for SocketOptionPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SocketOptionPlan")
+            .field("tcp_md5", &self.tcp_md5_key.is_some())
+            .field("traffic_class", &self.traffic_class)
+            .field("egress", &self.egress)
+            .finish()
+    }
+}
+
+/// The socket calls a [`SocketOptionPlan`] needs, so plans can be checked against a
+/// recording double where the kernel offers no getsockopt (TCP_MD5SIG is write-only).
+pub trait UpstreamSocketControl {
+    fn set_tcp_md5_key(&self, peer: SocketAddr, key: &[u8]) -> io::Result<()>;
+    fn set_traffic_class(&self, peer: SocketAddr, class: u8) -> io::Result<()>;
+}
+
+impl UpstreamSocketControl for Socket {
+    fn set_tcp_md5_key(&self, peer: SocketAddr, key: &[u8]) -> io::Result<()> {
+        set_tcp_md5_key(self, peer, key)
+    }
+
+    fn set_traffic_class(&self, peer: SocketAddr, class: u8) -> io::Result<()> {
+        match peer {
+            SocketAddr::V4(_) => self.set_tos(class as u32),
+            SocketAddr::V6(_) => set_tclass_v6(self, class),
+        }
+    }
+}
+
+impl SocketOptionPlan {
+    /// Apply every option to `socket`, which is about to connect to `peer`.
+    pub fn apply<S>(&self, socket: &S, peer: SocketAddr) -> io::Result<()>
+    where
+        S: UpstreamSocketControl + ?Sized,
+    {
+        if let Some(key) = &self.tcp_md5_key {
+            socket.set_tcp_md5_key(peer, key)?;
+        }
+        if let Some(class) = self.traffic_class {
+            socket.set_traffic_class(peer, class)?;
+        }
+        Ok(())
+    }
+
+    /// Open a new connection to `peer` with these options and `tcp` set before the SYN.
+    pub async fn connect(
+        &self,
+        peer: SocketAddr,
+        tcp: &TcpTuning,
+        limit: Duration,
+    ) -> io::Result<TcpStream> {
+        let socket = tcp.socket_for(peer)?;
+        self.apply(&*SockRef::from(&socket), peer)?;
+        if let Some(egress) = &self.egress {
+            egress.bind(&socket, peer)?;
+        }
+        connect_socket(socket, peer, limit).await
+    }
+}
+
+async fn connect_socket(
+    socket: TcpSocket,
+    peer: SocketAddr,
+    limit: Duration,
+) -> io::Result<TcpStream> {
+    match timeout(limit, socket.connect(peer)).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("Connection to {} timed out after {:?}", peer, limit),
+        )),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SocketOptionRule {
+    destinations: Vec<CompiledDestinationMatcher>,
+    ports: Vec<CompiledPortMatcher>,
+    plan: SocketOptionPlan,
+}
+
+/// Upstream socket options by destination, derived from `[server]`.
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamSocketOptions {
+    rules: Vec<SocketOptionRule>,
+}
+
+impl From<&ServerConfig> for UpstreamSocketOptions {
+    fn from(settings: &ServerConfig) -> Self {
+        let rules = settings
+            .upstream_socket_options
+            .iter()
+            .map(|entry| SocketOptionRule {
+                // Patterns were checked by Config::validate
+                destinations: entry
+                    .destinations
+                    .iter()
+                    .filter_map(|pattern| CompiledDestinationMatcher::compile(pattern).ok())
+                    .collect(),
+                ports: entry
+                    .ports
+                    .iter()
+                    .filter_map(|pattern| CompiledPortMatcher::compile(pattern).ok())
+                    .collect(),
+                plan: SocketOptionPlan {
+                    tcp_md5_key: entry
+                        .tcp_md5_password
+                        .as_ref()
+                        .map(|password| Arc::new(Zeroizing::new(password.as_bytes().to_vec()))),
+                    traffic_class: entry.ip_tos.or(entry.dscp.map(|dscp| dscp << 2)),
+                    egress: None,
+                },
+            })
+            .collect();
+
+        Self { rules }
+    }
+}
+
+impl UpstreamSocketOptions {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Options for a tunnel to `destination:port`: the first matching entry, if any.
+    pub fn plan_for(&self, destination: &Address, port: u16) -> Option<SocketOptionPlan> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.ports.iter().any(|matcher| matcher.matches(port))
+                    && rule
+                        .destinations
+                        .iter()
+                        .any(|matcher| matcher.matches(destination))
+            })
+            .map(|rule| rule.plan.clone())
+    }
+}
+
+/// Install an RFC 2385 signature key for `peer` on `socket` (TCP_MD5SIG).
+///
+/// Works on listening sockets too, where it makes the kernel require signed segments
+/// from `peer`.
+#[cfg(target_os = "linux")]
+pub fn set_tcp_md5_key(socket: &Socket, peer: SocketAddr, key: &[u8]) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    use zeroize::Zeroize;
+
+    /// `struct tcp_md5sig` from linux/tcp.h
+    #[repr(C)]
+    struct TcpMd5Sig {
+        addr: libc::sockaddr_storage,
+        flags: u8,
+        prefixlen: u8,
+        keylen: u16,
+        ifindex: libc::c_int,
+        key: [u8; TCP_MD5_MAX_KEY_LEN],
+    }
+
+    if key.len() > TCP_MD5_MAX_KEY_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "TCP MD5 key longer than 80 bytes",
+        ));
+    }
+
+    // SAFETY: tcp_md5sig is plain data, and all zeroes is a valid value
+    let mut signature: TcpMd5Sig = unsafe { std::mem::zeroed() };
+    signature.addr = socket2::SockAddr::from(peer).as_storage();
+    signature.keylen = key.len() as u16;
+    signature.key[..key.len()].copy_from_slice(key);
+
+    // SAFETY: the option value is a fully initialised tcp_md5sig of the size given
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_MD5SIG,
+            (&signature as *const TcpMd5Sig).cast(),
+            std::mem::size_of::<TcpMd5Sig>() as libc::socklen_t,
+        )
+    };
+    signature.key.zeroize();
+
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// TCP MD5 signatures need Linux; Config::validate rejects them elsewhere.
+#[cfg(not(target_os = "linux"))]
+pub fn set_tcp_md5_key(_socket: &Socket, _peer: SocketAddr, _key: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TCP MD5 signatures are only supported on Linux",
+    ))
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+fn set_tclass_v6(socket: &Socket, class: u8) -> io::Result<()> {
+    socket.set_tclass_v6(class as u32)
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd",
+)))]
+fn set_tclass_v6(_socket: &Socket, _class: u8) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "IPV6_TCLASS is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UpstreamSocketOptionSettings;
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+
+    #[derive(Default)]
+    struct RecordingSocket {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl UpstreamSocketControl for RecordingSocket {
+        fn set_tcp_md5_key(&self, peer: SocketAddr, key: &[u8]) -> io::Result<()> {
+            self.calls.lock().unwrap().push(format!(
+                "md5 {} {}",
+                peer,
+                String::from_utf8_lossy(key)
+            ));
+            Ok(())
+        }
+
+        fn set_traffic_class(&self, peer: SocketAddr, class: u8) -> io::Result<()> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("tclass {} {:#04x}", peer, class));
+            Ok(())
+        }
+    }
+
+    fn entry(destination: &str, ports: &[&str]) -> UpstreamSocketOptionSettings {
+        UpstreamSocketOptionSettings {
+            destinations: vec![destination.to_string()],
+            ports: ports.iter().map(|port| port.to_string()).collect(),
+            tcp_md5_password: None,
+            tcp_ao_password: None,
+            dscp: None,
+            ip_tos: None,
+        }
+    }
+
+    fn options() -> UpstreamSocketOptions {
+        UpstreamSocketOptions::from(&ServerConfig {
+            upstream_socket_options: vec![
+                UpstreamSocketOptionSettings {
+                    tcp_md5_password: Some("peer-secret".to_string().into()),
+                    dscp: Some(48),
+                    ..entry("192.0.2.0/24", &["179"])
+                },
+                UpstreamSocketOptionSettings {
+                    ip_tos: Some(0x10),
+                    ..entry("*.noc.example", &["22", "830"])
+                },
+                UpstreamSocketOptionSettings {
+                    dscp: Some(10),
+                    ..entry("192.0.2.1", &["*"])
+                },
+            ],
+            ..ServerConfig::default()
+        })
+    }
+
+    #[test]
+    fn plans_follow_destination_and_port() {
+        let options = options();
+        let router = Address::IPv4([192, 0, 2, 1]);
+
+        let bgp = options.plan_for(&router, 179).unwrap();
+        assert!(bgp.tcp_md5_key.is_some());
+        assert_eq!(bgp.traffic_class, Some(48 << 2));
+
+        // The first entry only covers port 179
+        let other_port = options.plan_for(&router, 443).unwrap();
+        assert!(other_port.tcp_md5_key.is_none());
+        assert_eq!(other_port.traffic_class, Some(10 << 2));
+
+        let ssh = options
+            .plan_for(&Address::Domain("core1.noc.example".into()), 22)
+            .unwrap();
+        assert_eq!(ssh.traffic_class, Some(0x10));
+
+        assert!(options
+            .plan_for(&Address::Domain("core1.noc.example".into()), 443)
+            .is_none());
+        assert!(options
+            .plan_for(&Address::IPv4([198, 51, 100, 1]), 179)
+            .is_none());
+        assert!(UpstreamSocketOptions::default()
+            .plan_for(&router, 179)
+            .is_none());
+    }
+
+    #[test]
+    fn plan_sets_key_for_the_resolved_peer() {
+        let plan = options()
+            .plan_for(&Address::IPv4([192, 0, 2, 1]), 179)
+            .unwrap();
+        let socket = RecordingSocket::default();
+        plan.apply(&socket, "192.0.2.1:179".parse().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            *socket.calls.lock().unwrap(),
+            vec!["md5 192.0.2.1:179 peer-secret", "tclass 192.0.2.1:179 0xc0"]
+        );
+        assert!(!format!("{:?}", plan).contains("peer-secret"));
+    }
+
+    #[tokio::test]
+    async fn traffic_class_is_set_before_connecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = listener.local_addr().unwrap();
+
+        let plan = SocketOptionPlan {
+            tcp_md5_key: None,
+            traffic_class: Some(46 << 2),
+            egress: None,
+        };
+        let marked = plan
+            .connect(peer, &TcpTuning::default(), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(SockRef::from(&marked).tos().unwrap(), 46 << 2);
+
+        let unmarked = TcpStream::connect(peer).await.unwrap();
+        assert_eq!(SockRef::from(&unmarked).tos().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn tcp_tuning_is_set_before_connecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = listener.local_addr().unwrap();
+
+        let tcp = TcpTuning::from(&TcpSocketSettings {
+            nodelay: false,
+            keepalive_secs: 45,
+            send_buffer_bytes: 0,
+            recv_buffer_bytes: 64 * 1024,
+        });
+        let stream = tcp.connect(peer, Duration::from_secs(1)).await.unwrap();
+        let socket = SockRef::from(&stream);
+        assert!(!socket.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(45));
+        // Linux doubles the requested size for bookkeeping
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+
+        let defaults = TcpTuning::default()
+            .connect(peer, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let socket = SockRef::from(&defaults);
+        assert!(socket.nodelay().unwrap());
+        assert!(!socket.keepalive().unwrap());
+    }
+
+    #[tokio::test]
+    async fn egress_address_is_bound_before_connecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = listener.local_addr().unwrap();
+
+        let plan = SocketOptionPlan {
+            egress: Some(EgressAddresses {
+                v4: Some(std::net::Ipv4Addr::LOCALHOST),
+                v6: None,
+            }),
+            ..Default::default()
+        };
+        let stream = plan
+            .connect(peer, &TcpTuning::default(), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.local_addr().unwrap().ip(),
+            std::net::Ipv4Addr::LOCALHOST
+        );
+
+        // No IPv6 address in the rule, so IPv6 peers are not tried
+        let err = plan
+            .connect(
+                "[::1]:9".parse().unwrap(),
+                &TcpTuning::default(),
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrNotAvailable);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::config::SpecialNamesSettings;
+use crate::protocol::types::{Address, IpLiteral, ReplyCode};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Category of a special-use destination name (RFC 6761 and friends).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpecialNameCategory {
+    /// `localhost`, `*.localhost` and loopback IP literals
+    Localhost,
+    /// `*.local` multicast DNS names
+    Local,
+    /// `*.onion` Tor hidden services
+    Onion,
+    /// `*.i2p` I2P eepsites
+    I2p,
+}
+
+impl SpecialNameCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpecialNameCategory::Localhost => "localhost",
+            SpecialNameCategory::Local => "local",
+            SpecialNameCategory::Onion => "onion",
+            SpecialNameCategory::I2p => "i2p",
+        }
+    }
+
+    /// SOCKS reply sent to the client when a name of this category is blocked.
+    pub fn reply_code(&self) -> ReplyCode {
+        match self {
+            SpecialNameCategory::Localhost => ReplyCode::ConnectionNotAllowed,
+            SpecialNameCategory::Local => ReplyCode::HostUnreachable,
+            // Overlay networks get their own reply so clients can tell "we are not a Tor/I2P
+            // gateway" apart from policy blocks.
+            SpecialNameCategory::Onion | SpecialNameCategory::I2p => ReplyCode::NetworkUnreachable,
+        }
+    }
+}
+
+impl fmt::Display for SpecialNameCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Policy applied to `localhost` destinations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalhostPolicy {
+    Block,
+    Allow,
+}
+
+/// Policy applied to `.local` (mDNS) destinations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalDomainPolicy {
+    Block,
+    Resolve,
+}
+
+/// Outcome of the special-name check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialNameDecision {
+    /// Not a special name, or a special name the policy lets through.
+    Allow,
+    /// Blocked before any resolution is attempted.
+    Block(SpecialNameCategory),
+}
+
+/// Compiled special-use name policy evaluated before destination resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpecialNamesPolicy {
+    pub localhost: LocalhostPolicy,
+    pub local: LocalDomainPolicy,
+}
+
+impl Default for SpecialNamesPolicy {
+    fn default() -> Self {
+        Self::from(&SpecialNamesSettings::default())
+    }
+}
+
+impl From<&SpecialNamesSettings> for SpecialNamesPolicy {
+    fn from(settings: &SpecialNamesSettings) -> Self {
+        let localhost = match settings.localhost.as_str() {
+            "allow" => LocalhostPolicy::Allow,
+            _ => LocalhostPolicy::Block,
+        };
+        let local = match settings.local.as_str() {
+            "resolve" => LocalDomainPolicy::Resolve,
+            _ => LocalDomainPolicy::Block,
+        };
+
+        Self { localhost, local }
+    }
+}
+
+impl SpecialNamesPolicy {
+    /// Default policy with loopback destinations let through, for services on the proxy host.
+    pub fn localhost_allowed() -> Self {
+        Self {
+            localhost: LocalhostPolicy::Allow,
+            ..Self::default()
+        }
+    }
+
+    /// Decide whether the destination may proceed to resolution.
+    pub fn check(&self, address: &Address) -> SpecialNameDecision {
+        match classify(address) {
+            Some(SpecialNameCategory::Localhost) if self.localhost == LocalhostPolicy::Allow => {
+                SpecialNameDecision::Allow
+            }
+            Some(SpecialNameCategory::Local) if self.local == LocalDomainPolicy::Resolve => {
+                SpecialNameDecision::Allow
+            }
+            Some(category) => SpecialNameDecision::Block(category),
+            None => SpecialNameDecision::Allow,
+        }
+    }
+}
+
+/// Classify a destination address into a special-use category, if any.
+///
+/// IP literals (including domains that are really IP literals, e.g. `"127.0.0.1"` or
+/// `"[::1]"`) are classified the same way as the names they stand for.
+pub fn classify(address: &Address) -> Option<SpecialNameCategory> {
+    match address {
+        Address::IPv4(octets) => classify_ip(IpAddr::V4(Ipv4Addr::from(*octets))),
+        Address::IPv6(octets) => classify_ip(IpAddr::V6(Ipv6Addr::from(*octets))),
+        Address::Domain(domain) => classify_domain(domain),
+    }
+}
+
+fn classify_ip(ip: IpAddr) -> Option<SpecialNameCategory> {
+    let loopback = match ip {
+        IpAddr::V4(v4) => v4.is_loopback(),
+        IpAddr::V6(v6) => {
+            v6.is_loopback() || v6.to_ipv4_mapped().is_some_and(|v4| v4.is_loopback())
+        }
+    };
+
+    loopback.then_some(SpecialNameCategory::Localhost)
+}
+
+fn classify_domain(domain: &str) -> Option<SpecialNameCategory> {
+    let trimmed = domain.trim().trim_end_matches('.');
+    if let Some(literal) = IpLiteral::parse(trimmed) {
+        return classify_ip(literal.ip);
+    }
+
+    // Runs for every domain request, so compare case-insensitively instead of lowercasing
+    let tld = trimmed.rsplit('.').next().unwrap_or("");
+
+    [
+        ("localhost", SpecialNameCategory::Localhost),
+        ("local", SpecialNameCategory::Local),
+        ("onion", SpecialNameCategory::Onion),
+        ("i2p", SpecialNameCategory::I2p),
+    ]
+    .into_iter()
+    .find(|(suffix, _)| tld.eq_ignore_ascii_case(suffix))
+    .map(|(_, category)| category)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain(name: &str) -> Address {
+        Address::Domain(name.into())
+    }
+
+    #[test]
+    fn classifies_name_classes() {
+        assert_eq!(
+            classify(&domain("localhost")),
+            Some(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            classify(&domain("api.LOCALHOST.")),
+            Some(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            classify(&domain("printer.local")),
+            Some(SpecialNameCategory::Local)
+        );
+        assert_eq!(
+            classify(&domain("abcdef.onion")),
+            Some(SpecialNameCategory::Onion)
+        );
+        assert_eq!(
+            classify(&domain("site.i2p")),
+            Some(SpecialNameCategory::I2p)
+        );
+        assert_eq!(classify(&domain("example.com")), None);
+        assert_eq!(classify(&domain("localhost.example.com")), None);
+    }
+
+    #[test]
+    fn classifies_ip_literals_consistently() {
+        assert_eq!(
+            classify(&Address::IPv4([127, 0, 0, 1])),
+            Some(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            classify(&Address::IPv6(Ipv6Addr::LOCALHOST.octets())),
+            Some(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            classify(&domain("127.8.9.10")),
+            Some(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            classify(&domain("[::1]")),
+            Some(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            classify(&domain("::ffff:127.0.0.1")),
+            Some(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(classify(&Address::IPv4([8, 8, 8, 8])), None);
+    }
+
+    #[test]
+    fn default_policy_decisions() {
+        let policy = SpecialNamesPolicy::default();
+        assert_eq!(
+            policy.check(&domain("localhost")),
+            SpecialNameDecision::Block(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            policy.check(&Address::IPv4([127, 0, 0, 1])),
+            SpecialNameDecision::Block(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            policy.check(&domain("nas.local")),
+            SpecialNameDecision::Block(SpecialNameCategory::Local)
+        );
+        assert_eq!(
+            policy.check(&domain("x.onion")),
+            SpecialNameDecision::Block(SpecialNameCategory::Onion)
+        );
+        assert_eq!(
+            policy.check(&domain("x.i2p")),
+            SpecialNameDecision::Block(SpecialNameCategory::I2p)
+        );
+        assert_eq!(
+            policy.check(&domain("example.com")),
+            SpecialNameDecision::Allow
+        );
+    }
+
+    #[test]
+    fn configured_policy_decisions() {
+        let policy = SpecialNamesPolicy::from(&SpecialNamesSettings {
+            localhost: "block".to_string(),
+            local: "resolve".to_string(),
+        });
+
+        assert_eq!(
+            policy.check(&domain("localhost")),
+            SpecialNameDecision::Block(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            policy.check(&Address::IPv4([127, 0, 0, 1])),
+            SpecialNameDecision::Block(SpecialNameCategory::Localhost)
+        );
+        assert_eq!(
+            policy.check(&domain("nas.local")),
+            SpecialNameDecision::Allow
+        );
+        // Overlay networks are never allowed, regardless of configuration
+        assert_eq!(
+            policy.check(&domain("x.onion")),
+            SpecialNameDecision::Block(SpecialNameCategory::Onion)
+        );
+    }
+
+    #[test]
+    fn reply_codes_per_category() {
+        assert_eq!(
+            SpecialNameCategory::Localhost.reply_code(),
+            ReplyCode::ConnectionNotAllowed
+        );
+        assert_eq!(
+            SpecialNameCategory::Local.reply_code(),
+            ReplyCode::HostUnreachable
+        );
+        assert_eq!(
+            SpecialNameCategory::Onion.reply_code(),
+            ReplyCode::NetworkUnreachable
+        );
+        assert_eq!(
+            SpecialNameCategory::I2p.reply_code(),
+            ReplyCode::NetworkUnreachable
+        );
+    }
+}
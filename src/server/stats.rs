@@ -63,6 +63,10 @@ struct SessionStatsDto {
     total_sessions: usize,
     /// Total bytes transferred in the time window
     total_bytes: u64,
+    /// Sessions of the window were evicted from memory, so the counts are incomplete
+    truncated: bool,
+    /// Finished sessions evicted from memory since startup
+    evicted_sessions: u64,
     /// Top users by session count
     top_users: Vec<UserSessionStatDto>,
     /// Top destinations by connection count
@@ -194,6 +198,8 @@ async fn get_session_stats(
         active_sessions: stats.active_sessions,
         total_sessions: stats.total_sessions,
         total_bytes: stats.total_bytes,
+        truncated: stats.truncated,
+        evicted_sessions: stats.evicted_sessions,
         top_users: stats
             .top_users
             .iter()
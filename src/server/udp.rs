@@ -1,15 +1,22 @@
-use crate::protocol::{parse_udp_packet, serialize_udp_packet, Address, UdpHeader, UdpPacket};
-use crate::server::resolver::resolve_address;
-use crate::session::{SessionManager, SessionStatus};
+use crate::config::UdpSettings;
+use crate::protocol::{
+    encapsulate_udp_in_place, parse_udp_header, udp_header_len, Address, UDP_IP_HEADER_MAX,
+};
+use crate::qos::{QosEngine, QosMetrics};
+use crate::server::egress::EgressAddresses;
+use crate::server::resolver::{literal_target, AddressSelection, DestinationResolver};
+use crate::server::special_names::{SpecialNameDecision, SpecialNamesPolicy};
+use crate::server::udp_fragments::{FragmentLimits, Reassembler};
+use crate::session::{SessionManager, SessionStatus, UdpAssociationMode};
 use crate::utils::error::{Result, RustSocksError};
-use bytes::{Bytes, BytesMut};
 use dashmap::DashMap;
-use std::net::SocketAddr;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
 use tokio::sync::broadcast;
-use tokio::time::timeout;
+use tokio::task::JoinHandle;
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
@@ -51,31 +58,337 @@ impl UdpSessionMap {
     }
 }
 
+/// Default of `server.udp.max_datagram_size`: the largest UDP payload over IPv4
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// How long a resolved domain target is reused before it is resolved again.
+/// IP-literal targets never go stale.
+const DOMAIN_TARGET_TTL: Duration = Duration::from_secs(30);
+
+/// The last client target seen on an association.
+///
+/// Clients usually send a stream of datagrams to one destination, so the raw header
+/// (ATYP through DST.PORT) of the previous datagram is kept and compared byte-for-byte.
+/// A match skips address parsing, the special-name check, resolution and the session
+/// map update, which keeps the common case free of allocations.
+struct TargetCache {
+    client: Option<SocketAddr>,
+    header: Vec<u8>,
+    dest: SocketAddr,
+    expires: Option<Instant>,
+}
+
+impl TargetCache {
+    fn new() -> Self {
+        Self {
+            client: None,
+            // ATYP + length + 255-byte domain + port
+            header: Vec::with_capacity(259),
+            dest: SocketAddr::from(([0, 0, 0, 0], 0)),
+            expires: None,
+        }
+    }
+
+    #[inline]
+    fn lookup(&self, client: SocketAddr, header: &[u8]) -> Option<SocketAddr> {
+        if self.client != Some(client) || self.header != header {
+            return None;
+        }
+        match self.expires {
+            Some(expires) if Instant::now() >= expires => None,
+            _ => Some(self.dest),
+        }
+    }
+
+    fn store(&mut self, client: SocketAddr, header: &[u8], dest: SocketAddr, is_domain: bool) {
+        self.client = Some(client);
+        self.header.clear();
+        self.header.extend_from_slice(header);
+        self.dest = dest;
+        self.expires = is_domain.then(|| Instant::now() + DOMAIN_TARGET_TTL);
+    }
+}
+
+/// The source endpoint a UDP association accepts client datagrams from.
+///
+/// Depending on the [`UdpAssociationMode`] the endpoint is known when the association
+/// is set up, or locked in by the first datagram from the expected IP. Datagrams from
+/// any other endpoint are replies from a destination the client sent to, or rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientEndpoint {
+    mode: UdpAssociationMode,
+    /// IP the first datagram must come from while the port is still unknown
+    ip: IpAddr,
+    locked: Option<SocketAddr>,
+}
+
+/// How a datagram's source relates to the association's client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceMatch {
+    /// The locked-in client endpoint
+    Client,
+    /// The first datagram from the expected IP; its endpoint is now locked in
+    Learned,
+    /// Anyone else
+    Other,
+}
+
+impl ClientEndpoint {
+    /// Endpoint expected for an ASSOCIATE request naming `requested`:`requested_port`,
+    /// received on a control connection from `control`.
+    pub fn new(
+        mode: UdpAssociationMode,
+        control: SocketAddr,
+        requested: &Address,
+        requested_port: u16,
+    ) -> Self {
+        let control = canonical(control);
+        let stated_ip = match requested {
+            Address::IPv4(octets) => Some(IpAddr::from(*octets)),
+            Address::IPv6(octets) => Some(IpAddr::from(*octets)),
+            Address::Domain(_) => None,
+        }
+        .map(|ip| ip.to_canonical())
+        .filter(|ip| !ip.is_unspecified());
+        let stated_port = (requested_port != 0).then_some(requested_port);
+
+        match mode {
+            UdpAssociationMode::Strict => {
+                let locked = match stated_port {
+                    Some(port) => SocketAddr::new(stated_ip.unwrap_or(control.ip()), port),
+                    None => control,
+                };
+                Self {
+                    mode,
+                    ip: locked.ip(),
+                    locked: Some(locked),
+                }
+            }
+            UdpAssociationMode::IpOnly => {
+                let ip = stated_ip.unwrap_or(control.ip());
+                Self {
+                    mode,
+                    ip,
+                    locked: stated_port.map(|port| SocketAddr::new(ip, port)),
+                }
+            }
+            UdpAssociationMode::Learned => Self {
+                mode,
+                ip: control.ip(),
+                locked: None,
+            },
+        }
+    }
+
+    pub fn mode(&self) -> UdpAssociationMode {
+        self.mode
+    }
+
+    /// The client endpoint, once known
+    pub fn endpoint(&self) -> Option<SocketAddr> {
+        self.locked
+    }
+
+    /// Match a datagram's source, locking in the endpoint on the first client datagram.
+    pub fn classify(&mut self, source: SocketAddr) -> SourceMatch {
+        let source = canonical(source);
+        match self.locked {
+            Some(locked) if locked == source => SourceMatch::Client,
+            Some(_) => SourceMatch::Other,
+            None if source.ip() == self.ip => {
+                self.locked = Some(source);
+                SourceMatch::Learned
+            }
+            None => SourceMatch::Other,
+        }
+    }
+}
+
+impl fmt::Display for ClientEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.locked, self.ip) {
+            (Some(locked), _) => write!(f, "{}", locked),
+            (None, IpAddr::V4(ip)) => write!(f, "{}:*", ip),
+            (None, IpAddr::V6(ip)) => write!(f, "[{}]:*", ip),
+        }
+    }
+}
+
+/// IPv4-mapped IPv6 addresses compare equal to the IPv4 sources the relay socket sees.
+fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+/// Default of `server.udp.association_idle_timeout_secs`
+pub const DEFAULT_ASSOCIATION_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Datagram size, fragment handling and idle timeout of every association
+/// (`[server.udp]`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpDatagramLimits {
+    /// Largest client datagram or destination reply relayed; sizes the receive buffer
+    pub max_datagram_size: usize,
+    /// Reassembly of fragmented client datagrams; `None` drops them
+    pub fragments: Option<FragmentLimits>,
+    /// An association without datagrams from its client or destinations for this long
+    /// is closed, its control connection included
+    pub idle_timeout: Duration,
+}
+
+impl Default for UdpDatagramLimits {
+    fn default() -> Self {
+        Self {
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            fragments: None,
+            idle_timeout: DEFAULT_ASSOCIATION_IDLE_TIMEOUT,
+        }
+    }
+}
+
+impl From<&UdpSettings> for UdpDatagramLimits {
+    fn from(udp: &UdpSettings) -> Self {
+        Self {
+            max_datagram_size: udp.max_datagram_size,
+            fragments: udp.enable_fragments.then(|| FragmentLimits {
+                max_queues: udp.max_reassembly_queues,
+                timeout: Duration::from_millis(udp.reassembly_timeout_ms),
+            }),
+            idle_timeout: Duration::from_secs(udp.association_idle_timeout_secs),
+        }
+    }
+}
+
+/// Log and count a datagram the relay does not forward
+fn record_dropped_datagram(reason: &'static str, source: SocketAddr, len: usize) {
+    debug!(
+        reason,
+        bytes = len,
+        "UDP ASSOCIATE: dropped datagram from {}",
+        source
+    );
+    #[cfg(feature = "metrics")]
+    crate::session::SessionMetrics::record_udp_dropped_datagram(reason);
+}
+
+/// Destination checks applied to every client datagram before it is resolved, and
+/// where datagrams leave from.
+#[derive(Clone)]
+pub struct UdpDestinations {
+    pub special_names: SpecialNamesPolicy,
+    /// Datagrams to a domain name are dropped when false (`server.dns.allow_domain_requests`)
+    pub allow_domain_names: bool,
+    pub resolver: Arc<dyn DestinationResolver>,
+    /// Datagrams go to the first address `server.dns.strategy` allows
+    pub address_selection: AddressSelection,
+    /// The relay socket binds to the IPv4 address of the user's rule (`server.egress`)
+    pub egress: Option<EgressAddresses>,
+}
+
+/// QoS for the datagrams of one association.
+///
+/// Every datagram takes tokens from the user's (and, with `qos.per_ip`, the client
+/// address's) buckets like TCP traffic does, but never waits for them: a datagram the
+/// buckets cannot cover is dropped and counted instead of being queued.
+#[derive(Clone)]
+pub struct UdpShaping {
+    pub qos_engine: QosEngine,
+    pub user: Arc<str>,
+    pub source_ip: IpAddr,
+}
+
+impl UdpShaping {
+    /// Take tokens for a `bytes` datagram; `false` when it has to be dropped
+    async fn admit(
+        &self,
+        bytes: usize,
+        direction: &'static str,
+        session_manager: &SessionManager,
+        session_id: &Uuid,
+    ) -> bool {
+        match self
+            .qos_engine
+            .try_allocate_bandwidth_from(&self.user, self.source_ip, bytes as u64)
+            .await
+        {
+            Ok(()) => {
+                QosMetrics::record_allocation(&self.user, direction, bytes as u64);
+                true
+            }
+            Err(dimension) => {
+                debug!(
+                    user = %self.user,
+                    bytes,
+                    direction,
+                    limit = dimension,
+                    "UDP ASSOCIATE: dropped datagram over the bandwidth limit"
+                );
+                QosMetrics::record_drop(dimension);
+                session_manager
+                    .record_udp_throttled_datagram(session_id)
+                    .await;
+                session_manager
+                    .record_qos_throttle(session_id, dimension, true)
+                    .await;
+                false
+            }
+        }
+    }
+}
+
 /// Handle UDP ASSOCIATE command
-/// Returns the local address/port where the UDP relay is listening
+///
+/// Returns the local address/port where the UDP relay is listening and the relay task,
+/// which ends when the association does: on `shutdown_rx`, after
+/// [`UdpDatagramLimits::idle_timeout`] without traffic, or on a socket error. The relay
+/// socket is closed by then.
 pub async fn handle_udp_associate(
-    client_addr: SocketAddr,
+    endpoint: ClientEndpoint,
     session_manager: Arc<SessionManager>,
     session_id: Uuid,
     shutdown_rx: broadcast::Receiver<()>,
-) -> Result<SocketAddr> {
-    // Bind UDP socket on any available port
-    let udp_socket = UdpSocket::bind("0.0.0.0:0").await?;
+    destinations: UdpDestinations,
+    shaping: UdpShaping,
+    limits: UdpDatagramLimits,
+) -> Result<(SocketAddr, JoinHandle<()>)> {
+    // Bind UDP socket on any available port, on the user's egress address if it has one
+    let bind_ip = destinations
+        .egress
+        .and_then(|egress| egress.v4)
+        .unwrap_or(Ipv4Addr::UNSPECIFIED);
+    let udp_socket = UdpSocket::bind((bind_ip, 0)).await.map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("cannot bind UDP relay to {}: {}", bind_ip, e),
+        )
+    })?;
     let local_addr = udp_socket.local_addr()?;
 
     info!(
-        "UDP ASSOCIATE: bound relay socket on {} for client {}",
-        local_addr, client_addr
+        "UDP ASSOCIATE: bound relay socket on {} for client {} ({} matching)",
+        local_addr,
+        endpoint,
+        endpoint.mode()
     );
+    session_manager
+        .set_udp_association(&session_id, endpoint.mode(), endpoint.endpoint())
+        .await;
+    if !bind_ip.is_unspecified() {
+        session_manager
+            .set_egress_ip(&session_id, IpAddr::V4(bind_ip))
+            .await;
+    }
 
     // Spawn UDP relay task
-    tokio::spawn(async move {
+    let relay = tokio::spawn(async move {
         if let Err(e) = run_udp_relay(
             udp_socket,
-            client_addr,
+            endpoint,
             session_manager.clone(),
             session_id,
             shutdown_rx,
+            destinations,
+            shaping,
+            limits,
         )
         .await
         {
@@ -90,90 +403,154 @@ pub async fn handle_udp_associate(
         }
     });
 
-    Ok(local_addr)
+    Ok((local_addr, relay))
 }
 
 /// Run the UDP relay loop
+#[allow(clippy::too_many_arguments)]
 async fn run_udp_relay(
     socket: UdpSocket,
-    client_addr: SocketAddr,
+    mut endpoint: ClientEndpoint,
     session_manager: Arc<SessionManager>,
     session_id: Uuid,
     mut shutdown_rx: broadcast::Receiver<()>,
+    destinations: UdpDestinations,
+    shaping: UdpShaping,
+    limits: UdpDatagramLimits,
 ) -> Result<()> {
     let socket = Arc::new(socket);
     let session_map = Arc::new(UdpSessionMap::new());
-
-    const MAX_DATAGRAM: usize = 65_535;
-    let mut buf = BytesMut::with_capacity(MAX_DATAGRAM);
-    let udp_timeout = Duration::from_secs(120); // 2 minutes idle timeout
+    let mut target_cache = TargetCache::new();
+    let mut reassembler = limits
+        .fragments
+        .map(|fragments| Reassembler::new(fragments, limits.max_datagram_size));
+
+    // One buffer per association, reused for every datagram in both directions.
+    // Datagrams land after UDP_IP_HEADER_MAX bytes of headroom so that replies can be
+    // wrapped in their SOCKS5 header without moving the payload. The extra byte tells
+    // a datagram over the limit, which the kernel truncates, from one that fits.
+    let mut buf = vec![0u8; UDP_IP_HEADER_MAX + limits.max_datagram_size + 1];
+    // Only datagrams from the client or its destinations keep the association alive
+    let mut idle_deadline = tokio::time::Instant::now() + limits.idle_timeout;
 
     loop {
-        if buf.capacity() < MAX_DATAGRAM {
-            buf.reserve(MAX_DATAGRAM - buf.capacity());
-        }
-        buf.clear();
-
         // Wait for packet or shutdown signal
         tokio::select! {
-            result = timeout(udp_timeout, socket.recv_buf_from(&mut buf)) => {
+            result = socket.recv_from(&mut buf[UDP_IP_HEADER_MAX..]) => {
                 match result {
-                    Ok(Ok((len, peer_addr))) => {
+                    Ok((len, peer_addr)) => {
                         if len == 0 {
                             continue;
                         }
+                        if len > limits.max_datagram_size {
+                            record_dropped_datagram("oversized", peer_addr, len);
+                            continue;
+                        }
+
+                        let source = endpoint.classify(peer_addr);
+                        if source == SourceMatch::Learned {
+                            info!(
+                                "UDP ASSOCIATE: learned client endpoint {} ({} matching)",
+                                peer_addr,
+                                endpoint.mode()
+                            );
+                            session_manager
+                                .set_udp_association(
+                                    &session_id,
+                                    endpoint.mode(),
+                                    endpoint.endpoint(),
+                                )
+                                .await;
+                        }
 
-                        let packet_data = buf.split().freeze();
+                        if source != SourceMatch::Other {
+                            idle_deadline = tokio::time::Instant::now() + limits.idle_timeout;
+                            let datagram = &buf[UDP_IP_HEADER_MAX..UDP_IP_HEADER_MAX + len];
+                            let reassembled;
+                            let datagram = match datagram.get(2) {
+                                Some(&frag) if frag != 0 => {
+                                    let Some(reassembler) = reassembler.as_mut() else {
+                                        // RFC 1928: without fragmentation support, drop
+                                        record_dropped_datagram("fragmented", peer_addr, len);
+                                        continue;
+                                    };
+                                    match reassembler.push(datagram, Instant::now(), |reason| {
+                                        record_dropped_datagram(reason, peer_addr, len)
+                                    }) {
+                                        Ok(Some(whole)) => {
+                                            reassembled = whole;
+                                            &reassembled[..]
+                                        }
+                                        Ok(None) => continue,
+                                        Err(e) => {
+                                            warn!("Error handling client UDP packet: {}", e);
+                                            continue;
+                                        }
+                                    }
+                                }
+                                _ => datagram,
+                            };
 
-                        // Determine if this is from client or from destination
-                        if peer_addr.ip() == client_addr.ip() {
                             // Packet from client to destination
                             if let Err(e) = handle_client_packet(
                                 &socket,
-                                packet_data,
+                                datagram,
                                 peer_addr,
                                 &session_map,
+                                &mut target_cache,
                                 &session_manager,
                                 &session_id,
+                                &destinations,
+                                &shaping,
                             )
                             .await
                             {
                                 warn!("Error handling client UDP packet: {}", e);
                             }
-                        } else {
+                        } else if let Some(client_addr) = session_map.get_client(&peer_addr) {
+                            idle_deadline = tokio::time::Instant::now() + limits.idle_timeout;
                             // Packet from destination back to client
                             if let Err(e) = handle_destination_packet(
                                 &socket,
-                                packet_data,
+                                &mut buf,
+                                len,
                                 peer_addr,
-                                &session_map,
+                                client_addr,
                                 &session_manager,
                                 &session_id,
+                                &shaping,
                             )
                             .await
                             {
                                 warn!("Error handling destination UDP packet: {}", e);
                             }
+                        } else {
+                            record_dropped_datagram("unknown_source", peer_addr, len);
+                            session_manager
+                                .record_udp_rejected_datagram(&session_id)
+                                .await;
                         }
                     }
-                    Ok(Err(e)) => {
+                    Err(e) => {
                         warn!("UDP socket error: {}", e);
                         return Err(RustSocksError::Io(e));
                     }
-                    Err(_) => {
-                        // Timeout - close session
-                        info!("UDP session timeout after {} seconds", udp_timeout.as_secs());
-                        session_manager
-                            .close_session(
-                                &session_id,
-                                Some("UDP session timeout".to_string()),
-                                SessionStatus::Closed,
-                            )
-                            .await;
-                        return Ok(());
-                    }
                 }
             }
+            _ = tokio::time::sleep_until(idle_deadline) => {
+                info!(
+                    "UDP session timeout after {} seconds",
+                    limits.idle_timeout.as_secs()
+                );
+                session_manager
+                    .close_session(
+                        &session_id,
+                        Some("UDP session timeout".to_string()),
+                        SessionStatus::Closed,
+                    )
+                    .await;
+                return Ok(());
+            }
             _ = shutdown_rx.recv() => {
                 info!("UDP relay shutting down");
                 session_manager
@@ -190,38 +567,52 @@ async fn run_udp_relay(
 }
 
 /// Handle packet from client (forward to destination)
+#[allow(clippy::too_many_arguments)]
 async fn handle_client_packet(
     socket: &Arc<UdpSocket>,
-    packet_data: Bytes,
+    datagram: &[u8],
     client_addr: SocketAddr,
     session_map: &Arc<UdpSessionMap>,
+    target_cache: &mut TargetCache,
     session_manager: &Arc<SessionManager>,
     session_id: &Uuid,
+    destinations: &UdpDestinations,
+    shaping: &UdpShaping,
 ) -> Result<()> {
-    // Parse SOCKS5 UDP packet
-    let packet = parse_udp_packet(packet_data)?;
+    // Fragments were reassembled or dropped by the relay loop; this is the FRAG=0 layout
+    let header_len = udp_header_len(datagram)?;
+    let target = &datagram[3..header_len];
+    let payload = &datagram[header_len..];
+
+    let dest_addr = match target_cache.lookup(client_addr, target) {
+        Some(dest_addr) => dest_addr,
+        None => {
+            let dest_addr = resolve_client_target(datagram, destinations).await?;
+            let is_domain = datagram[3] == 0x03;
+            target_cache.store(client_addr, target, dest_addr, is_domain);
+
+            // Store session mapping
+            session_map.insert(client_addr, dest_addr, *session_id);
+            dest_addr
+        }
+    };
 
     debug!(
-        "UDP client packet: {} -> {}:{} ({} bytes)",
+        "UDP client packet: {} -> {} ({} bytes)",
         client_addr,
-        packet.header.address,
-        packet.header.port,
-        packet.data.len()
+        dest_addr,
+        payload.len()
     );
 
-    // Resolve destination address
-    let dest_candidates = resolve_address(&packet.header.address, packet.header.port).await?;
-
-    // Try to connect to first available destination
-    let dest_addr = dest_candidates
-        .first()
-        .ok_or_else(|| RustSocksError::Protocol("No destination address resolved".to_string()))?;
-
-    // Store session mapping
-    session_map.insert(client_addr, *dest_addr, *session_id);
+    if !shaping
+        .admit(payload.len(), "upload", session_manager, session_id)
+        .await
+    {
+        return Ok(());
+    }
 
     // Forward raw data to destination (without SOCKS5 header)
-    let sent = socket.send_to(packet.data.as_ref(), dest_addr).await?;
+    let sent = socket.send_to(payload, dest_addr).await?;
 
     session_manager.queue_traffic_update(session_id, sent as u64, 0, 1, 0);
 
@@ -233,44 +624,79 @@ async fn handle_client_packet(
     Ok(())
 }
 
+/// Slow path for a target not seen on the previous datagram: parse the address, apply
+/// special-name policy and resolve it.
+async fn resolve_client_target(
+    datagram: &[u8],
+    destinations: &UdpDestinations,
+) -> Result<SocketAddr> {
+    let (header, _) = parse_udp_header(datagram)?;
+
+    // Special-use names are dropped before resolution, exactly like CONNECT
+    if let SpecialNameDecision::Block(category) = destinations.special_names.check(&header.address)
+    {
+        return Err(RustSocksError::Protocol(format!(
+            "Dropped datagram to {}:{} (special-name:{})",
+            header.address, header.port, category
+        )));
+    }
+
+    // Resolve destination address (IP literals never reach the resolver)
+    let resolved = match literal_target(&header.address, header.port) {
+        Some(target) => vec![target],
+        None if !destinations.allow_domain_names => {
+            return Err(RustSocksError::Protocol(format!(
+                "Dropped datagram to {}:{} (domain-requests-disabled)",
+                header.address, header.port
+            )));
+        }
+        None => {
+            destinations
+                .resolver
+                .resolve(&header.address, header.port)
+                .await?
+        }
+    };
+    destinations
+        .address_selection
+        .order(resolved)
+        .first()
+        .copied()
+        .ok_or_else(|| RustSocksError::Protocol("No destination address resolved".to_string()))
+}
+
 /// Handle packet from destination (forward back to client)
+///
+/// `buf` is the relay buffer: the payload sits after [`UDP_IP_HEADER_MAX`] bytes of
+/// headroom, into which the SOCKS5 header is written.
+#[allow(clippy::too_many_arguments)]
 async fn handle_destination_packet(
     socket: &Arc<UdpSocket>,
-    packet_data: Bytes,
+    buf: &mut [u8],
+    packet_len: usize,
     dest_addr: SocketAddr,
-    session_map: &Arc<UdpSessionMap>,
+    client_addr: SocketAddr,
     session_manager: &Arc<SessionManager>,
     session_id: &Uuid,
+    shaping: &UdpShaping,
 ) -> Result<()> {
-    // Find client address from reverse mapping
-    let client_addr = session_map.get_client(&dest_addr).ok_or_else(|| {
-        RustSocksError::Protocol(format!("No client mapping for destination {}", dest_addr))
-    })?;
-
-    let packet_len = packet_data.len();
-
     debug!(
         "UDP destination packet: {} -> {} ({} bytes)",
         dest_addr, client_addr, packet_len
     );
 
-    // Wrap response in SOCKS5 UDP header
-    let response_packet = UdpPacket {
-        header: UdpHeader {
-            frag: 0,
-            address: match dest_addr {
-                SocketAddr::V4(addr) => Address::IPv4(addr.ip().octets()),
-                SocketAddr::V6(addr) => Address::IPv6(addr.ip().octets()),
-            },
-            port: dest_addr.port(),
-        },
-        data: packet_data.clone(),
-    };
+    if !shaping
+        .admit(packet_len, "download", session_manager, session_id)
+        .await
+    {
+        return Ok(());
+    }
 
-    let response_bytes = serialize_udp_packet(&response_packet);
+    // Wrap response in SOCKS5 UDP header
+    let response = encapsulate_udp_in_place(buf, packet_len, dest_addr);
 
     // Send to client
-    let sent = socket.send_to(&response_bytes, client_addr).await?;
+    let sent = socket.send_to(response, client_addr).await?;
 
     session_manager.queue_traffic_update(session_id, 0, packet_len as u64, 0, 1);
 
@@ -302,4 +728,83 @@ mod tests {
         assert!(map.get_destination(&client).is_none());
         assert!(map.get_client(&dest).is_none());
     }
+
+    #[test]
+    fn test_client_endpoint_modes() {
+        let control: SocketAddr = "10.0.0.5:40000".parse().unwrap();
+        let unspecified = Address::IPv4([0, 0, 0, 0]);
+        let other_port: SocketAddr = "10.0.0.5:50000".parse().unwrap();
+        let spoofed: SocketAddr = "10.0.0.5:50001".parse().unwrap();
+
+        // strict: nothing stated means the control connection's endpoint
+        let mut strict = ClientEndpoint::new(UdpAssociationMode::Strict, control, &unspecified, 0);
+        assert_eq!(strict.endpoint(), Some(control));
+        assert_eq!(strict.classify(other_port), SourceMatch::Other);
+        assert_eq!(strict.classify(control), SourceMatch::Client);
+
+        // strict: a stated port on an unspecified address keeps the control IP
+        let mut strict =
+            ClientEndpoint::new(UdpAssociationMode::Strict, control, &unspecified, 50000);
+        assert_eq!(strict.classify(other_port), SourceMatch::Client);
+
+        let mut ip_only = ClientEndpoint::new(UdpAssociationMode::IpOnly, control, &unspecified, 0);
+        assert_eq!(ip_only.endpoint(), None);
+        assert_eq!(
+            ip_only.classify("10.0.0.6:50000".parse().unwrap()),
+            SourceMatch::Other
+        );
+        assert_eq!(ip_only.classify(other_port), SourceMatch::Learned);
+        assert_eq!(ip_only.endpoint(), Some(other_port));
+        assert_eq!(ip_only.classify(other_port), SourceMatch::Client);
+        assert_eq!(ip_only.classify(spoofed), SourceMatch::Other);
+
+        // learned ignores whatever the request stated
+        let mut learned = ClientEndpoint::new(
+            UdpAssociationMode::Learned,
+            control,
+            &Address::IPv4([192, 0, 2, 1]),
+            9999,
+        );
+        assert_eq!(learned.to_string(), "10.0.0.5:*");
+        assert_eq!(learned.classify(other_port), SourceMatch::Learned);
+        assert_eq!(learned.classify(spoofed), SourceMatch::Other);
+    }
+
+    #[test]
+    fn test_client_endpoint_ipv4_mapped_control() {
+        let control: SocketAddr = "[::ffff:127.0.0.1]:40000".parse().unwrap();
+        let mut endpoint = ClientEndpoint::new(
+            UdpAssociationMode::IpOnly,
+            control,
+            &Address::IPv4([0, 0, 0, 0]),
+            0,
+        );
+        assert_eq!(
+            endpoint.classify("127.0.0.1:50000".parse().unwrap()),
+            SourceMatch::Learned
+        );
+    }
+
+    #[test]
+    fn test_target_cache() {
+        let client: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let dest: SocketAddr = "8.8.8.8:53".parse().unwrap();
+        let ip_target = [0x01, 8, 8, 8, 8, 0x00, 0x35];
+        let mut cache = TargetCache::new();
+        assert_eq!(cache.lookup(client, &ip_target), None);
+
+        cache.store(client, &ip_target, dest, false);
+        assert_eq!(cache.lookup(client, &ip_target), Some(dest));
+        assert_eq!(cache.lookup(client, &[0x01, 8, 8, 4, 4, 0x00, 0x35]), None);
+        assert_eq!(
+            cache.lookup("127.0.0.1:1235".parse().unwrap(), &ip_target),
+            None
+        );
+
+        let domain_target = b"\x03\x0bexample.com\x00\x35";
+        cache.store(client, domain_target, dest, true);
+        assert_eq!(cache.lookup(client, domain_target), Some(dest));
+        cache.expires = Some(Instant::now());
+        assert_eq!(cache.lookup(client, domain_target), None);
+    }
 }
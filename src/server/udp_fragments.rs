@@ -0,0 +1,267 @@
+//! Reassembly of fragmented SOCKS5 UDP datagrams (`server.udp.enable_fragments`).
+//!
+//! RFC 1928 section 7: a non-zero FRAG field holds the fragment's position (1-127),
+//! with the high bit set on the last one. Each association keeps a few reassembly
+//! queues, one per destination header. A queue is abandoned when a fragment arrives
+//! out of order, when its timer runs out, or when the datagram would grow past
+//! `server.udp.max_datagram_size`; reassembled datagrams are relayed like ones sent whole.
+
+use crate::protocol::udp_fragment_header_len;
+use crate::utils::error::Result;
+use std::time::{Duration, Instant};
+
+/// FRAG bit marking the last fragment of a datagram
+const END_OF_FRAGMENTS: u8 = 0x80;
+
+/// Reassembly limits of one association
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentLimits {
+    /// Datagrams reassembled at once
+    pub max_queues: usize,
+    /// Time from the first fragment until the last must have arrived
+    pub timeout: Duration,
+}
+
+/// One datagram being reassembled
+struct ReassemblyQueue {
+    /// The datagram so far: RSV, FRAG=0 and the destination header, then the payloads
+    datagram: Vec<u8>,
+    header_len: usize,
+    /// Position of the last fragment queued
+    position: u8,
+    expires: Instant,
+}
+
+impl ReassemblyQueue {
+    fn target(&self) -> &[u8] {
+        &self.datagram[3..self.header_len]
+    }
+}
+
+/// Reassembly queues of one association.
+pub struct Reassembler {
+    limits: FragmentLimits,
+    max_datagram_size: usize,
+    queues: Vec<ReassemblyQueue>,
+}
+
+impl Reassembler {
+    pub fn new(limits: FragmentLimits, max_datagram_size: usize) -> Self {
+        Self {
+            limits,
+            max_datagram_size,
+            queues: Vec::with_capacity(limits.max_queues),
+        }
+    }
+
+    /// Queue a fragment (FRAG other than 0) received at `now`.
+    ///
+    /// Returns the whole datagram, with FRAG 0, once its last fragment arrives.
+    /// Fragments and partial datagrams that are given up are passed to `on_drop` with
+    /// the reason.
+    pub fn push(
+        &mut self,
+        fragment: &[u8],
+        now: Instant,
+        mut on_drop: impl FnMut(&'static str),
+    ) -> Result<Option<Vec<u8>>> {
+        let header_len = udp_fragment_header_len(fragment)?;
+        let position = fragment[2] & !END_OF_FRAGMENTS;
+        let last = fragment[2] & END_OF_FRAGMENTS != 0;
+        let target = &fragment[3..header_len];
+        let payload = &fragment[header_len..];
+
+        self.queues.retain(|queue| {
+            let live = queue.expires > now;
+            if !live {
+                on_drop("reassembly_timeout");
+            }
+            live
+        });
+
+        let index = match self
+            .queues
+            .iter()
+            .position(|queue| queue.target() == target)
+        {
+            Some(index) if position == self.queues[index].position.wrapping_add(1) => index,
+            Some(index) => {
+                // Out of order; a new first fragment starts the datagram over
+                self.queues.swap_remove(index);
+                on_drop("fragment_order");
+                if position != 1 {
+                    return Ok(None);
+                }
+                let Some(index) = self.start(fragment, header_len, now, &mut on_drop) else {
+                    return Ok(None);
+                };
+                index
+            }
+            None if position == 1 => {
+                let Some(index) = self.start(fragment, header_len, now, &mut on_drop) else {
+                    return Ok(None);
+                };
+                index
+            }
+            None => {
+                on_drop("fragment_order");
+                return Ok(None);
+            }
+        };
+
+        let queue = &mut self.queues[index];
+        if queue.datagram.len() + payload.len() > self.max_datagram_size {
+            self.queues.swap_remove(index);
+            on_drop("oversized");
+            return Ok(None);
+        }
+        queue.datagram.extend_from_slice(payload);
+        queue.position = position;
+
+        if last {
+            return Ok(Some(self.queues.swap_remove(index).datagram));
+        }
+        Ok(None)
+    }
+
+    /// Open a queue for the datagram `fragment` starts; `None` when all are in use
+    fn start(
+        &mut self,
+        fragment: &[u8],
+        header_len: usize,
+        now: Instant,
+        on_drop: &mut impl FnMut(&'static str),
+    ) -> Option<usize> {
+        if self.queues.len() >= self.limits.max_queues {
+            on_drop("reassembly_queues");
+            return None;
+        }
+        let mut datagram = Vec::with_capacity(fragment.len());
+        datagram.extend_from_slice(&[0x00, 0x00, 0x00]);
+        datagram.extend_from_slice(&fragment[3..header_len]);
+        self.queues.push(ReassemblyQueue {
+            datagram,
+            header_len,
+            position: 0,
+            expires: now + self.limits.timeout,
+        });
+        Some(self.queues.len() - 1)
+    }
+
+    /// Datagrams being reassembled
+    pub fn pending(&self) -> usize {
+        self.queues.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(frag: u8, port: u8, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = vec![0x00, 0x00, frag, 0x01, 127, 0, 0, 1, 0x00, port];
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    fn reassembler(max_queues: usize, max_datagram_size: usize) -> Reassembler {
+        Reassembler::new(
+            FragmentLimits {
+                max_queues,
+                timeout: Duration::from_secs(5),
+            },
+            max_datagram_size,
+        )
+    }
+
+    #[test]
+    fn fragments_are_joined_in_order() {
+        let mut reassembler = reassembler(2, 1500);
+        let mut dropped = Vec::new();
+        let now = Instant::now();
+
+        for (frag, payload) in [(1, &b"he"[..]), (2, &b"ll"[..])] {
+            let out = reassembler
+                .push(&fragment(frag, 53, payload), now, |r| dropped.push(r))
+                .unwrap();
+            assert!(out.is_none());
+        }
+        // Another destination is reassembled alongside
+        reassembler
+            .push(&fragment(1, 54, b"x"), now, |r| dropped.push(r))
+            .unwrap();
+        assert_eq!(reassembler.pending(), 2);
+
+        let datagram = reassembler
+            .push(&fragment(0x83, 53, b"o"), now, |r| dropped.push(r))
+            .unwrap()
+            .expect("complete datagram");
+        assert_eq!(datagram, fragment(0, 53, b"hello"));
+        assert_eq!(reassembler.pending(), 1);
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn broken_sequences_are_dropped() {
+        let mut reassembler = reassembler(1, 1500);
+        let mut dropped = Vec::new();
+        let now = Instant::now();
+        let mut push = |reassembler: &mut Reassembler, datagram: Vec<u8>, at: Instant| {
+            reassembler
+                .push(&datagram, at, |r| dropped.push(r))
+                .unwrap()
+        };
+
+        // No first fragment, then a gap
+        assert!(push(&mut reassembler, fragment(2, 53, b"b"), now).is_none());
+        push(&mut reassembler, fragment(1, 53, b"a"), now);
+        assert!(push(&mut reassembler, fragment(0x83, 53, b"c"), now).is_none());
+        assert_eq!(reassembler.pending(), 0);
+
+        // The only queue is taken by another destination
+        push(&mut reassembler, fragment(1, 53, b"a"), now);
+        assert!(push(&mut reassembler, fragment(1, 54, b"a"), now).is_none());
+
+        // A repeated first fragment starts over
+        let restarted = now + Duration::from_secs(1);
+        push(&mut reassembler, fragment(1, 53, b"x"), restarted);
+        assert_eq!(
+            push(&mut reassembler, fragment(0x82, 53, b"y"), restarted),
+            Some(fragment(0, 53, b"xy"))
+        );
+
+        // The timer runs out
+        push(&mut reassembler, fragment(1, 53, b"a"), now);
+        let late = now + Duration::from_secs(6);
+        assert!(push(&mut reassembler, fragment(0x82, 53, b"b"), late).is_none());
+
+        assert_eq!(
+            dropped,
+            [
+                "fragment_order",
+                "fragment_order",
+                "reassembly_queues",
+                "fragment_order",
+                "reassembly_timeout",
+                "fragment_order",
+            ]
+        );
+    }
+
+    #[test]
+    fn reassembled_datagrams_stay_under_the_size_limit() {
+        let mut reassembler = reassembler(1, 20);
+        let mut dropped = Vec::new();
+        let now = Instant::now();
+
+        reassembler
+            .push(&fragment(1, 53, b"12345"), now, |r| dropped.push(r))
+            .unwrap();
+        let out = reassembler
+            .push(&fragment(0x82, 53, b"678901"), now, |r| dropped.push(r))
+            .unwrap();
+        assert!(out.is_none());
+        assert_eq!(reassembler.pending(), 0);
+        assert_eq!(dropped, ["oversized"]);
+    }
+}
@@ -0,0 +1,696 @@
+//! Chaining CONNECTs through a parent SOCKS5 proxy (`[server.upstream]`).
+//!
+//! Destinations matching `server.upstream.destinations` are not resolved or dialled
+//! locally: the tunnel opens a connection to the parent, runs the client side of the
+//! SOCKS5 handshake (RFC 1928, with RFC 1929 username/password when configured) and asks
+//! the parent to CONNECT by name, so DNS happens at the parent. Once the parent reports
+//! success the stream is relayed like any direct upstream connection.
+//!
+//! Every `health_check_interval_secs` the parent is sent a SOCKS5 greeting; after
+//! `unhealthy_threshold` failures in a row it counts as unhealthy and `on_unhealthy`
+//! decides what happens to the traffic already going through it. One successful check
+//! makes it healthy again.
+
+use crate::acl::matcher::CompiledDestinationMatcher;
+use crate::config::UpstreamProxySettings;
+use crate::protocol::{Address, AuthMethod, ReplyCode, SOCKS_VERSION};
+use crate::server::pool::ConnectionPool;
+use crate::session::SessionManager;
+use crate::utils::error::{Result, RustSocksError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+use zeroize::Zeroizing;
+
+/// RFC 1929 subnegotiation version
+const USERPASS_VERSION: u8 = 0x01;
+
+/// Limit on the TCP connect and the greeting of one health check
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Close reason of chained sessions drained off an unhealthy parent
+pub const UPSTREAM_UNHEALTHY_CLOSE_REASON: &str = "upstream unhealthy";
+
+/// What happens to traffic through the parent once it is unhealthy
+/// (`server.upstream.on_unhealthy`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnhealthyPolicy {
+    /// Chained sessions run on until they fail by themselves
+    #[default]
+    Keep,
+    /// Chained sessions are closed after `drain_grace_secs` so clients reconnect
+    Drain,
+    /// Idle pooled connections to the parent are torn down; sessions are left alone
+    Migrate,
+}
+
+impl UnhealthyPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnhealthyPolicy::Keep => "keep",
+            UnhealthyPolicy::Drain => "drain",
+            UnhealthyPolicy::Migrate => "migrate",
+        }
+    }
+}
+
+impl std::str::FromStr for UnhealthyPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "keep" => Ok(UnhealthyPolicy::Keep),
+            "drain" => Ok(UnhealthyPolicy::Drain),
+            "migrate" => Ok(UnhealthyPolicy::Migrate),
+            _ => Err(format!("Invalid upstream on_unhealthy policy: {}", value)),
+        }
+    }
+}
+
+/// Health of the parent and what is pinned to it (`GET /api/upstream`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamStatus {
+    /// `host:port` of the parent
+    pub endpoint: String,
+    pub healthy: bool,
+    /// `keep`, `drain` or `migrate`
+    pub on_unhealthy: String,
+    /// 0 when health checks are off
+    pub health_check_interval_secs: u64,
+    pub consecutive_failures: u32,
+    pub checks_total: u64,
+    pub failures_total: u64,
+    pub last_check: Option<DateTime<Utc>>,
+    /// Why the latest failed check failed, or what marked the parent unhealthy
+    pub last_error: Option<String>,
+    /// Active sessions tunnelled through the parent
+    pub active_sessions: usize,
+    /// Sessions waiting for their drain deadline
+    pub pending_drain: usize,
+}
+
+#[derive(Debug)]
+struct HealthState {
+    healthy: bool,
+    consecutive_failures: u32,
+    checks_total: u64,
+    failures_total: u64,
+    last_check: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+impl Default for HealthState {
+    fn default() -> Self {
+        Self {
+            healthy: true,
+            consecutive_failures: 0,
+            checks_total: 0,
+            failures_total: 0,
+            last_check: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Parent proxy and the destinations routed through it, derived from `[server.upstream]`.
+pub struct UpstreamProxy {
+    address: String,
+    port: u16,
+    credentials: Option<(String, Zeroizing<String>)>,
+    destinations: Vec<CompiledDestinationMatcher>,
+    /// `None` when health checks are off
+    health_check_interval: Option<Duration>,
+    unhealthy_threshold: u32,
+    on_unhealthy: UnhealthyPolicy,
+    drain_grace: Duration,
+    health: Mutex<HealthState>,
+}
+
+impl fmt::Debug for UpstreamProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpstreamProxy")
+            .field("address", &self.address)
+            .field("port", &self.port)
+            .field("authenticated", &self.credentials.is_some())
+            .field("on_unhealthy", &self.on_unhealthy)
+            .field("healthy", &self.is_healthy())
+            .finish()
+    }
+}
+
+impl UpstreamProxy {
+    /// `None` unless `server.upstream.enabled` is set.
+    pub fn from_settings(settings: &UpstreamProxySettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+
+        Some(Self {
+            address: settings.address.trim().to_string(),
+            port: settings.port,
+            credentials: settings.username.clone().zip(settings.password.clone()),
+            // Patterns were checked by Config::validate
+            destinations: settings
+                .destinations
+                .iter()
+                .filter_map(|pattern| CompiledDestinationMatcher::compile(pattern).ok())
+                .collect(),
+            health_check_interval: (settings.health_check_interval_secs > 0)
+                .then_some(Duration::from_secs(settings.health_check_interval_secs)),
+            unhealthy_threshold: settings.unhealthy_threshold.max(1),
+            on_unhealthy: settings.on_unhealthy.parse().unwrap_or_default(),
+            drain_grace: Duration::from_secs(settings.drain_grace_secs),
+            health: Mutex::new(HealthState::default()),
+        })
+    }
+
+    /// Whether a CONNECT to `destination` goes through the parent.
+    pub fn routes(&self, destination: &Address) -> bool {
+        self.destinations
+            .iter()
+            .any(|matcher| matcher.matches(destination))
+    }
+
+    /// `host:port` of the parent, for logs
+    pub fn endpoint(&self) -> String {
+        format!("{}:{}", self.address, self.port)
+    }
+
+    /// Connect to the parent and have it CONNECT to `destination:port`.
+    ///
+    /// `limit` bounds the TCP connect and the handshake separately. On success the
+    /// returned stream is already tunnelled to the destination.
+    pub async fn connect(
+        &self,
+        destination: &Address,
+        port: u16,
+        limit: Duration,
+    ) -> Result<TcpStream> {
+        let mut stream = match timeout(
+            limit,
+            TcpStream::connect((self.address.as_str(), self.port)),
+        )
+        .await
+        {
+            Ok(result) => result?,
+            Err(_) => {
+                return Err(RustSocksError::Io(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!(
+                        "Connection to upstream proxy {} timed out after {:?}",
+                        self.endpoint(),
+                        limit
+                    ),
+                )))
+            }
+        };
+
+        let credentials = self
+            .credentials
+            .as_ref()
+            .map(|(username, password)| (username.as_str(), password.as_str()));
+        match timeout(
+            limit,
+            socks5_connect(&mut stream, destination, port, credentials),
+        )
+        .await
+        {
+            Ok(result) => result?,
+            Err(_) => {
+                return Err(RustSocksError::Protocol(format!(
+                    "Upstream proxy {} handshake timed out after {:?}",
+                    self.endpoint(),
+                    limit
+                )))
+            }
+        }
+
+        Ok(stream)
+    }
+
+    /// Whether the parent passed its latest health checks; always true without checks
+    pub fn is_healthy(&self) -> bool {
+        self.health
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .healthy
+    }
+
+    pub fn on_unhealthy(&self) -> UnhealthyPolicy {
+        self.on_unhealthy
+    }
+
+    /// Open a connection to the parent and check it answers a SOCKS5 greeting with a
+    /// method it accepts.
+    pub async fn check_health(&self) -> Result<()> {
+        let mut stream = timeout(
+            HEALTH_CHECK_TIMEOUT,
+            TcpStream::connect((self.address.as_str(), self.port)),
+        )
+        .await
+        .map_err(|_| RustSocksError::Protocol("Health check connect timed out".to_string()))??;
+
+        let greeting: &[u8] = match self.credentials {
+            Some(_) => &[
+                SOCKS_VERSION,
+                2,
+                AuthMethod::NoAuth as u8,
+                AuthMethod::UserPass as u8,
+            ],
+            None => &[SOCKS_VERSION, 1, AuthMethod::NoAuth as u8],
+        };
+        let mut choice = [0u8; 2];
+        timeout(HEALTH_CHECK_TIMEOUT, async {
+            stream.write_all(greeting).await?;
+            stream.read_exact(&mut choice).await
+        })
+        .await
+        .map_err(|_| RustSocksError::Protocol("Health check greeting timed out".to_string()))??;
+
+        if choice[0] != SOCKS_VERSION {
+            return Err(RustSocksError::Protocol(format!(
+                "Upstream proxy answered with SOCKS version 0x{:02x}",
+                choice[0]
+            )));
+        }
+        if AuthMethod::from(choice[1]) == AuthMethod::NoAcceptable {
+            return Err(RustSocksError::Protocol(
+                "Upstream proxy accepted none of the offered methods".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Record the outcome of one health check. Returns true when this check made the
+    /// parent unhealthy.
+    fn record_check(&self, outcome: std::result::Result<(), String>) -> bool {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.checks_total += 1;
+        health.last_check = Some(Utc::now());
+        match outcome {
+            Ok(()) => {
+                if !health.healthy {
+                    info!(
+                        upstream = %self.endpoint(),
+                        failures = health.consecutive_failures,
+                        "Upstream proxy healthy again"
+                    );
+                }
+                health.healthy = true;
+                health.consecutive_failures = 0;
+                health.last_error = None;
+                false
+            }
+            Err(error) => {
+                health.failures_total += 1;
+                health.consecutive_failures += 1;
+                debug!(upstream = %self.endpoint(), error = %error, "Upstream health check failed");
+                health.last_error = Some(error);
+                if health.healthy && health.consecutive_failures >= self.unhealthy_threshold {
+                    health.healthy = false;
+                    warn!(
+                        upstream = %self.endpoint(),
+                        failures = health.consecutive_failures,
+                        error = health.last_error.as_deref().unwrap_or("-"),
+                        "Upstream proxy unhealthy"
+                    );
+                    return true;
+                }
+                false
+            }
+        }
+    }
+
+    /// Mark the parent unhealthy without waiting for the health checks; the next
+    /// successful check makes it healthy again. Returns true when it was healthy until
+    /// now, in which case the caller applies [`Self::apply_unhealthy_policy`].
+    pub fn mark_unhealthy(&self, reason: &str) -> bool {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.last_error = Some(reason.to_string());
+        std::mem::replace(&mut health.healthy, false)
+    }
+
+    /// Apply `on_unhealthy` to the traffic through the parent. Returns the number of
+    /// sessions scheduled to drain or idle pooled connections torn down.
+    pub async fn apply_unhealthy_policy(
+        &self,
+        session_manager: &Arc<SessionManager>,
+        connection_pool: &ConnectionPool,
+    ) -> usize {
+        match self.on_unhealthy {
+            UnhealthyPolicy::Keep => 0,
+            UnhealthyPolicy::Drain => {
+                let chained = session_manager.chained_session_ids().await;
+                session_manager.drain_sessions(
+                    &chained,
+                    self.drain_grace,
+                    UPSTREAM_UNHEALTHY_CLOSE_REASON,
+                )
+            }
+            UnhealthyPolicy::Migrate => {
+                // Chained tunnels never go back to the pool, so this only catches
+                // connections made to the parent's address directly
+                let endpoint = (self.address.as_str(), self.port);
+                let addrs = match tokio::net::lookup_host(endpoint).await {
+                    Ok(addrs) => addrs.collect::<Vec<_>>(),
+                    Err(e) => {
+                        warn!(
+                            upstream = %self.endpoint(),
+                            error = %e,
+                            "Failed to resolve upstream proxy"
+                        );
+                        return 0;
+                    }
+                };
+                addrs
+                    .into_iter()
+                    .map(|addr| connection_pool.purge_destination(addr))
+                    .sum()
+            }
+        }
+    }
+
+    /// Health and the sessions pinned to the parent
+    pub async fn status(&self, session_manager: &SessionManager) -> UpstreamStatus {
+        let active_sessions = session_manager.chained_session_ids().await.len();
+        let health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        UpstreamStatus {
+            endpoint: self.endpoint(),
+            healthy: health.healthy,
+            on_unhealthy: self.on_unhealthy.as_str().to_string(),
+            health_check_interval_secs: self
+                .health_check_interval
+                .map(|interval| interval.as_secs())
+                .unwrap_or(0),
+            consecutive_failures: health.consecutive_failures,
+            checks_total: health.checks_total,
+            failures_total: health.failures_total,
+            last_check: health.last_check,
+            last_error: health.last_error.clone(),
+            active_sessions,
+            pending_drain: session_manager.pending_drain_count(),
+        }
+    }
+
+    /// Start checking the parent every `health_check_interval_secs`, the first check
+    /// right away, applying `on_unhealthy` each time it turns unhealthy.
+    ///
+    /// Dropping the returned set stops the checks.
+    pub fn spawn_health_checks(
+        self: &Arc<Self>,
+        session_manager: Arc<SessionManager>,
+        connection_pool: Arc<ConnectionPool>,
+    ) -> JoinSet<()> {
+        let mut tasks = JoinSet::new();
+        let Some(interval) = self.health_check_interval else {
+            return tasks;
+        };
+        info!(
+            upstream = %self.endpoint(),
+            interval_secs = interval.as_secs(),
+            on_unhealthy = self.on_unhealthy.as_str(),
+            "Upstream proxy health checks started"
+        );
+        let proxy = self.clone();
+        tasks.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let outcome = proxy.check_health().await.map_err(|e| e.to_string());
+                if proxy.record_check(outcome) {
+                    proxy
+                        .apply_unhealthy_policy(&session_manager, &connection_pool)
+                        .await;
+                }
+            }
+        });
+        tasks
+    }
+}
+
+/// Client side of a SOCKS5 CONNECT over `stream`.
+///
+/// Offers no-auth, plus username/password when `credentials` are given, and consumes
+/// the parent's reply including its bound address. Any reply other than success is an
+/// error naming the parent's reply code.
+pub async fn socks5_connect<S>(
+    stream: &mut S,
+    destination: &Address,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Method negotiation
+    let greeting: &[u8] = match credentials {
+        Some(_) => &[
+            SOCKS_VERSION,
+            2,
+            AuthMethod::NoAuth as u8,
+            AuthMethod::UserPass as u8,
+        ],
+        None => &[SOCKS_VERSION, 1, AuthMethod::NoAuth as u8],
+    };
+    stream.write_all(greeting).await?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != SOCKS_VERSION {
+        return Err(RustSocksError::Protocol(format!(
+            "Upstream proxy answered with SOCKS version 0x{:02x}",
+            choice[0]
+        )));
+    }
+
+    match (AuthMethod::from(choice[1]), credentials) {
+        (AuthMethod::NoAuth, _) => {}
+        (AuthMethod::UserPass, Some((username, password))) => {
+            let mut auth = Vec::with_capacity(3 + username.len() + password.len());
+            auth.push(USERPASS_VERSION);
+            auth.push(username.len() as u8);
+            auth.extend_from_slice(username.as_bytes());
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password.as_bytes());
+            let auth = Zeroizing::new(auth);
+            stream.write_all(&auth).await?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0x00 {
+                return Err(RustSocksError::AuthFailed(
+                    "Upstream proxy rejected the configured credentials".to_string(),
+                ));
+            }
+        }
+        _ => {
+            return Err(RustSocksError::Protocol(format!(
+                "Upstream proxy offered no usable authentication method (0x{:02x})",
+                choice[1]
+            )));
+        }
+    }
+
+    // CONNECT request; names are passed through so the parent resolves them
+    let mut request = Vec::with_capacity(7 + 255);
+    request.extend_from_slice(&[SOCKS_VERSION, 0x01, 0x00]);
+    match destination {
+        Address::IPv4(octets) => {
+            request.push(0x01);
+            request.extend_from_slice(octets);
+        }
+        Address::IPv6(octets) => {
+            request.push(0x04);
+            request.extend_from_slice(octets);
+        }
+        Address::Domain(domain) => {
+            if domain.len() > 255 {
+                return Err(RustSocksError::Protocol(format!(
+                    "Domain name too long: {} octets (max 255)",
+                    domain.len()
+                )));
+            }
+            request.push(0x03);
+            request.push(domain.len() as u8);
+            request.extend_from_slice(domain.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+
+    // Reply: VER REP RSV ATYP BND.ADDR BND.PORT
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    if header[0] != SOCKS_VERSION {
+        return Err(RustSocksError::Protocol(format!(
+            "Upstream proxy answered with SOCKS version 0x{:02x}",
+            header[0]
+        )));
+    }
+    let bound_len = match header[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        other => return Err(RustSocksError::UnsupportedAddressType(other)),
+    };
+    let mut bound = [0u8; 255 + 2];
+    stream.read_exact(&mut bound[..bound_len + 2]).await?;
+
+    if header[1] != ReplyCode::Succeeded as u8 {
+        return Err(RustSocksError::Protocol(format!(
+            "Upstream proxy refused CONNECT to {}:{} (reply 0x{:02x})",
+            destination, port, header[1]
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    fn settings(destinations: &[&str]) -> UpstreamProxySettings {
+        UpstreamProxySettings {
+            enabled: true,
+            address: "parent.corp.example".to_string(),
+            destinations: destinations.iter().map(|d| d.to_string()).collect(),
+            ..UpstreamProxySettings::default()
+        }
+    }
+
+    #[test]
+    fn disabled_settings_build_no_proxy() {
+        assert!(UpstreamProxy::from_settings(&UpstreamProxySettings::default()).is_none());
+    }
+
+    #[test]
+    fn only_matching_destinations_are_chained() {
+        let proxy =
+            UpstreamProxy::from_settings(&settings(&["*.corp.example", "10.0.0.0/8"])).unwrap();
+
+        assert!(proxy.routes(&Address::Domain("intranet.corp.example".into())));
+        assert!(proxy.routes(&Address::IPv4([10, 1, 2, 3])));
+        assert!(!proxy.routes(&Address::Domain("example.com".into())));
+        assert!(!proxy.routes(&Address::IPv4([192, 168, 1, 1])));
+        assert_eq!(proxy.endpoint(), "parent.corp.example:1080");
+    }
+
+    #[test]
+    fn parent_turns_unhealthy_after_threshold_failures() {
+        let proxy = UpstreamProxy::from_settings(&UpstreamProxySettings {
+            unhealthy_threshold: 2,
+            on_unhealthy: "drain".to_string(),
+            ..settings(&["*"])
+        })
+        .unwrap();
+        assert!(proxy.is_healthy());
+        assert_eq!(proxy.on_unhealthy(), UnhealthyPolicy::Drain);
+
+        assert!(!proxy.record_check(Err("connection refused".to_string())));
+        assert!(proxy.is_healthy());
+        assert!(proxy.record_check(Err("connection refused".to_string())));
+        assert!(!proxy.is_healthy());
+        // Only the transition reports
+        assert!(!proxy.record_check(Err("connection refused".to_string())));
+
+        assert!(!proxy.record_check(Ok(())));
+        assert!(proxy.is_healthy());
+        assert!(proxy.mark_unhealthy("reported down"));
+        assert!(!proxy.mark_unhealthy("reported down"));
+    }
+
+    #[tokio::test]
+    async fn health_check_sends_a_greeting() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let proxy = UpstreamProxy::from_settings(&UpstreamProxySettings {
+            address: "127.0.0.1".to_string(),
+            port,
+            ..settings(&["*"])
+        })
+        .unwrap();
+
+        let parent = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [0x05, 0x01, 0x00]);
+            stream.write_all(&[0x05, 0xFF]).await.unwrap();
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.read_exact(&mut greeting).await.unwrap();
+            stream.write_all(&[0x05, 0x00]).await.unwrap();
+        });
+
+        assert!(proxy.check_health().await.is_err());
+        proxy.check_health().await.unwrap();
+        parent.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn handshake_authenticates_and_connects_by_name() {
+        let (mut client, mut parent) = duplex(1024);
+        let parent_side = tokio::spawn(async move {
+            let mut greeting = [0u8; 4];
+            parent.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [0x05, 0x02, 0x00, 0x02]);
+            parent.write_all(&[0x05, 0x02]).await.unwrap();
+
+            let mut auth = [0u8; 12];
+            parent.read_exact(&mut auth).await.unwrap();
+            assert_eq!(&auth, b"\x01\x05alice\x04pass");
+            parent.write_all(&[0x01, 0x00]).await.unwrap();
+
+            let mut request = [0u8; 4 + 1 + 11 + 2];
+            parent.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request[..5], &[0x05, 0x01, 0x00, 0x03, 11]);
+            assert_eq!(&request[5..16], b"example.com");
+            assert_eq!(&request[16..], &443u16.to_be_bytes());
+            parent
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 10, 0, 0, 1, 0x1F, 0x90])
+                .await
+                .unwrap();
+        });
+
+        socks5_connect(
+            &mut client,
+            &Address::Domain("example.com".into()),
+            443,
+            Some(("alice", "pass")),
+        )
+        .await
+        .expect("handshake");
+        parent_side.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refused_connect_is_an_error() {
+        let (mut client, mut parent) = duplex(1024);
+        tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            parent.read_exact(&mut greeting).await.unwrap();
+            parent.write_all(&[0x05, 0x00]).await.unwrap();
+            let mut request = [0u8; 10];
+            parent.read_exact(&mut request).await.unwrap();
+            parent
+                .write_all(&[0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+        });
+
+        let err = socks5_connect(&mut client, &Address::IPv4([192, 0, 2, 1]), 80, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("reply 0x05"), "{}", err);
+    }
+}
@@ -0,0 +1,292 @@
+//! TLS originated by the proxy to matching destinations (`[server.upstream_tls]`).
+//!
+//! Clients that cannot speak TLS themselves (or should not hold the client certificate)
+//! reach a TLS-only destination through a plain CONNECT: once the upstream TCP
+//! connection is open, and before the client gets its reply, the proxy runs the client
+//! side of a TLS handshake with the destination. The server name sent as SNI and checked
+//! against the certificate is the requested host, or the IP for IP destinations, unless
+//! `server_name` overrides it. A handshake that fails, an untrusted certificate included,
+//! answers the client with a general failure. Other destinations keep plain TCP.
+//!
+//! Wrapped connections never go back to the connection pool, since the TLS session
+//! cannot be handed to the next tunnel.
+
+use crate::acl::matcher::{CompiledDestinationMatcher, CompiledPortMatcher};
+use crate::config::UpstreamTlsSettings;
+use crate::protocol::Address;
+use crate::server::listener::{load_certificates, load_private_key};
+use crate::utils::error::{Result, RustSocksError};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, RootCertStore};
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::{rustls, TlsConnector};
+
+/// Client TLS configuration and the destinations it applies to, from
+/// `[server.upstream_tls]`.
+pub struct UpstreamTls {
+    connector: TlsConnector,
+    server_name: Option<String>,
+    client_auth: bool,
+    destinations: Vec<CompiledDestinationMatcher>,
+    ports: Vec<CompiledPortMatcher>,
+}
+
+impl fmt::Debug for UpstreamTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpstreamTls")
+            .field("server_name", &self.server_name)
+            .field("client_auth", &self.client_auth)
+            .field("destinations", &self.destinations.len())
+            .finish()
+    }
+}
+
+impl UpstreamTls {
+    /// `None` unless `server.upstream_tls.enabled` is set; fails when the CA bundle or
+    /// the client certificate cannot be loaded.
+    pub fn from_settings(settings: &UpstreamTlsSettings) -> Result<Option<Self>> {
+        if !settings.enabled {
+            return Ok(None);
+        }
+
+        let ca_path = settings
+            .ca_path
+            .as_deref()
+            .expect("validated: ca_path must be set when upstream TLS is enabled");
+        let mut roots = RootCertStore::empty();
+        let (added, _) = roots.add_parsable_certificates(load_certificates(ca_path)?);
+        if added == 0 {
+            return Err(RustSocksError::Config(format!(
+                "No valid CA certificates could be loaded from '{}'",
+                ca_path
+            )));
+        }
+
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let config = match (
+            settings.client_certificate_path.as_deref(),
+            settings.client_key_path.as_deref(),
+        ) {
+            (Some(cert_path), Some(key_path)) => builder
+                .with_client_auth_cert(load_certificates(cert_path)?, load_private_key(key_path)?)
+                .map_err(|e| {
+                    RustSocksError::Config(format!(
+                        "Failed to configure the upstream TLS client certificate: {}",
+                        e
+                    ))
+                })?,
+            _ => builder.with_no_client_auth(),
+        };
+
+        Ok(Some(Self {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name: settings.server_name.clone(),
+            client_auth: settings.client_certificate_path.is_some(),
+            // Patterns were checked by Config::validate
+            destinations: settings
+                .destinations
+                .iter()
+                .filter_map(|pattern| CompiledDestinationMatcher::compile(pattern).ok())
+                .collect(),
+            ports: settings
+                .ports
+                .iter()
+                .filter_map(|pattern| CompiledPortMatcher::compile(pattern).ok())
+                .collect(),
+        }))
+    }
+
+    /// Whether the upstream connection to `destination:port` is wrapped in TLS.
+    pub fn applies(&self, destination: &Address, port: u16) -> bool {
+        self.ports.iter().any(|matcher| matcher.matches(port))
+            && self
+                .destinations
+                .iter()
+                .any(|matcher| matcher.matches(destination))
+    }
+
+    /// Name the handshake with `destination` sends and verifies, for logs
+    pub fn server_name(&self, destination: &Address) -> String {
+        match &self.server_name {
+            Some(name) => name.clone(),
+            None => destination.to_string(),
+        }
+    }
+
+    /// Run the TLS handshake with the destination over `stream`.
+    ///
+    /// `limit` bounds the handshake; an untrusted certificate fails it like any other
+    /// handshake error (see [`failure_outcome`]).
+    pub async fn connect(
+        &self,
+        stream: TcpStream,
+        destination: &Address,
+        limit: Duration,
+    ) -> io::Result<TlsStream<TcpStream>> {
+        let name = self.server_name_for(destination)?;
+        match timeout(limit, self.connector.connect(name, stream)).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("TLS handshake did not complete within {:?}", limit),
+            )),
+        }
+    }
+
+    fn server_name_for(&self, destination: &Address) -> io::Result<ServerName<'static>> {
+        if let Some(name) = &self.server_name {
+            return ServerName::try_from(name.clone())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e));
+        }
+        match destination.literal_ip() {
+            Some(ip) => Ok(ServerName::IpAddress(ip.into())),
+            None => ServerName::try_from(destination.to_string()).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("'{}' is not a valid TLS server name: {}", destination, e),
+                )
+            }),
+        }
+    }
+}
+
+/// Short reason for a failed upstream handshake, as written to the access log and the
+/// `rustsocks_upstream_tls_handshakes_total` metric.
+pub fn failure_outcome(error: &io::Error) -> &'static str {
+    if error.kind() == io::ErrorKind::TimedOut {
+        return "timeout";
+    }
+    match error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+    {
+        Some(rustls::Error::InvalidCertificate(_)) => "certificate_rejected",
+        _ => "handshake_failed",
+    }
+}
+
+/// Upstream side of a CONNECT tunnel: the TCP connection itself, or a TLS session the
+/// proxy runs over it.
+pub enum UpstreamStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl UpstreamStream {
+    /// The TCP connection underneath, for socket options and addresses
+    pub fn tcp(&self) -> &TcpStream {
+        match self {
+            UpstreamStream::Plain(stream) => stream,
+            UpstreamStream::Tls(stream) => stream.get_ref().0,
+        }
+    }
+
+    pub fn is_tls(&self) -> bool {
+        matches!(self, UpstreamStream::Tls(_))
+    }
+}
+
+impl AsyncRead for UpstreamStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            UpstreamStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            UpstreamStream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for UpstreamStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            UpstreamStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            UpstreamStream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            UpstreamStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            UpstreamStream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            UpstreamStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            UpstreamStream::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream_tls(server_name: Option<&str>) -> UpstreamTls {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        UpstreamTls {
+            connector: TlsConnector::from(Arc::new(
+                ClientConfig::builder()
+                    .with_root_certificates(RootCertStore::empty())
+                    .with_no_client_auth(),
+            )),
+            server_name: server_name.map(str::to_string),
+            client_auth: false,
+            destinations: vec![CompiledDestinationMatcher::compile("*.internal.example").unwrap()],
+            ports: vec![CompiledPortMatcher::compile("443").unwrap()],
+        }
+    }
+
+    #[test]
+    fn applies_to_matching_destinations_and_ports() {
+        let tls = upstream_tls(None);
+        let api = Address::Domain("api.internal.example".into());
+        assert!(tls.applies(&api, 443));
+        assert!(!tls.applies(&api, 80));
+        assert!(!tls.applies(&Address::Domain("example.com".into()), 443));
+    }
+
+    #[test]
+    fn server_name_defaults_to_the_requested_host() {
+        let tls = upstream_tls(None);
+        let api = Address::Domain("api.internal.example".into());
+        assert_eq!(tls.server_name(&api), "api.internal.example");
+        assert!(matches!(
+            tls.server_name_for(&Address::IPv4([192, 0, 2, 10])),
+            Ok(ServerName::IpAddress(_))
+        ));
+
+        let tls = upstream_tls(Some("gateway.internal.example"));
+        assert_eq!(tls.server_name(&api), "gateway.internal.example");
+    }
+
+    #[test]
+    fn certificate_errors_have_their_own_outcome() {
+        let untrusted = io::Error::new(
+            io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(rustls::CertificateError::UnknownIssuer),
+        );
+        assert_eq!(failure_outcome(&untrusted), "certificate_rejected");
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        assert_eq!(failure_outcome(&reset), "handshake_failed");
+        let slow = io::Error::from(io::ErrorKind::TimedOut);
+        assert_eq!(failure_outcome(&slow), "timeout");
+    }
+}
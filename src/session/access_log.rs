@@ -0,0 +1,289 @@
+//! Access-log records of ACL decisions.
+//!
+//! Every connection that reaches the ACL stage produces at most one record on the
+//! [`ACCESS_LOG_TARGET`] target with `stage = "acl"`. How much an allowed connection
+//! writes is decided by the `log` setting of the rule that allowed it
+//! ([`RuleLogLevel`]); blocked connections are always written in full so a noisy
+//! `silent` allow rule can never hide a denial.
+//!
+//! `minimal` connections are folded into one record per user and destination, written
+//! by [`AccessLog::flush_minimal`] on a timer ([`AccessLog::spawn_flusher`]).
+//!
+//! Requests refused before the ACL is consulted get a `stage = "request"` record
+//! instead ([`AccessLog::record_rejected_request`]), and allowed CONNECTs whose upstream
+//! TLS handshake fails a `stage = "upstream_tls"` one
+//! ([`AccessLog::record_upstream_tls_failure`]).
+
+use super::admission::ACCESS_LOG_TARGET;
+use super::types::ConnectionInfo;
+use crate::acl::{AclDecision, AclVerdict, RuleLogLevel};
+use crate::protocol::Address;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::info;
+
+/// A connection as seen by the ACL stage, plus the negotiation that led to it.
+#[derive(Debug, Clone, Copy)]
+pub struct AclAccess<'a> {
+    /// Effective user the ACL was evaluated for
+    pub user: &'a str,
+    /// Principal that authenticated, when it differs from `user` or is known
+    pub authenticated_user: Option<&'a str>,
+    /// Client-supplied correlation ID, when any
+    pub correlation_id: Option<&'a str>,
+    pub client_addr: SocketAddr,
+    pub destination: &'a Address,
+    pub port: u16,
+    /// `tcp` or `udp`
+    pub protocol: &'a str,
+    /// 4 or 5
+    pub socks_version: u8,
+    /// `connect`, `bind` or `udp_associate`
+    pub command: &'a str,
+    /// Negotiated authentication method, e.g. `none` or `userpass`
+    pub auth_method: &'a str,
+    /// Groups the user brought to ACL evaluation
+    pub group_count: usize,
+}
+
+/// What happened to access-log output since startup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessLogStats {
+    /// Records written, aggregated ones included
+    pub written: u64,
+    /// Allowed connections of `silent` rules, which write nothing
+    pub suppressed: u64,
+    /// Allowed connections of `minimal` rules folded into aggregated records
+    pub aggregated: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct MinimalKey {
+    user: String,
+    destination: String,
+}
+
+#[derive(Debug)]
+struct MinimalEntry {
+    rule: String,
+    connections: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    minimal: Arc<DashMap<MinimalKey, MinimalEntry>>,
+    written: Arc<AtomicU64>,
+    suppressed: AtomicU64,
+    aggregated: AtomicU64,
+}
+
+impl AccessLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the ACL decision for `access` according to the matched rule's log level.
+    pub fn record_acl(&self, verdict: &AclVerdict, access: &AclAccess<'_>) {
+        let rule = verdict.matched_rule.as_deref().unwrap_or("-");
+        let matched_on = verdict.matched_on.as_str();
+
+        if verdict.decision == AclDecision::Block {
+            self.write(
+                access,
+                "blocked",
+                rule,
+                matched_on,
+                verdict.log == RuleLogLevel::Verbose,
+            );
+            return;
+        }
+
+        match verdict.log {
+            RuleLogLevel::Default => self.write(access, "allowed", rule, matched_on, false),
+            RuleLogLevel::Verbose => self.write(access, "allowed", rule, matched_on, true),
+            RuleLogLevel::Silent => {
+                self.suppressed.fetch_add(1, Ordering::Relaxed);
+            }
+            RuleLogLevel::Minimal => {
+                self.aggregated.fetch_add(1, Ordering::Relaxed);
+                // The key is only ever written out, so skip building it when nobody would
+                // see the aggregated record
+                if !tracing::enabled!(target: ACCESS_LOG_TARGET, tracing::Level::INFO) {
+                    return;
+                }
+                let key = MinimalKey {
+                    user: access.user.to_string(),
+                    destination: format!("{}:{}", access.destination, access.port),
+                };
+                self.minimal
+                    .entry(key)
+                    .and_modify(|entry| entry.connections += 1)
+                    .or_insert_with(|| MinimalEntry {
+                        rule: rule.to_string(),
+                        connections: 1,
+                    });
+            }
+        }
+    }
+
+    /// Write one aggregated record per user and destination seen since the last flush.
+    ///
+    /// Returns the number of records written.
+    pub fn flush_minimal(&self) -> usize {
+        flush_minimal(&self.minimal, &self.written)
+    }
+
+    /// Flush `minimal` aggregates every `period` until the task is aborted.
+    pub fn spawn_flusher(&self, period: Duration) -> JoinHandle<()> {
+        let minimal = Arc::clone(&self.minimal);
+        let written = Arc::clone(&self.written);
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                flush_minimal(&minimal, &written);
+            }
+        })
+    }
+
+    /// Record a request refused before the ACL stage, e.g. a domain name with
+    /// `server.dns.allow_domain_requests = false`; written with `stage = "request"`.
+    pub fn record_rejected_request(&self, access: &AclAccess<'_>, reason: &str) {
+        self.written.fetch_add(1, Ordering::Relaxed);
+        info!(
+            target: ACCESS_LOG_TARGET,
+            stage = "request",
+            outcome = "rejected",
+            reason,
+            user = access.user,
+            source_ip = %access.client_addr.ip(),
+            source_port = access.client_addr.port(),
+            destination = %access.destination,
+            port = access.port,
+            protocol = access.protocol,
+            correlation_id = access.correlation_id.unwrap_or("-"),
+            socks_version = access.socks_version,
+            command = access.command,
+            "Request rejected"
+        );
+    }
+
+    /// Record a CONNECT whose TLS handshake with the destination failed
+    /// (`[server.upstream_tls]`); written with `stage = "upstream_tls"` and the reason,
+    /// e.g. `certificate_rejected`, as the outcome.
+    pub fn record_upstream_tls_failure(
+        &self,
+        user: &str,
+        connection: &ConnectionInfo,
+        server_name: &str,
+        outcome: &str,
+        error: &str,
+    ) {
+        self.written.fetch_add(1, Ordering::Relaxed);
+        info!(
+            target: ACCESS_LOG_TARGET,
+            stage = "upstream_tls",
+            outcome,
+            user,
+            source_ip = %connection.source_ip,
+            source_port = connection.source_port,
+            destination = %connection.dest_ip,
+            port = connection.dest_port,
+            server_name,
+            error,
+            correlation_id = connection.correlation_id.as_deref().unwrap_or("-"),
+            socks_version = connection.socks_version,
+            "Upstream TLS handshake failed"
+        );
+    }
+
+    pub fn stats(&self) -> AccessLogStats {
+        AccessLogStats {
+            written: self.written.load(Ordering::Relaxed),
+            suppressed: self.suppressed.load(Ordering::Relaxed),
+            aggregated: self.aggregated.load(Ordering::Relaxed),
+        }
+    }
+
+    fn write(
+        &self,
+        access: &AclAccess<'_>,
+        outcome: &str,
+        rule: &str,
+        matched_on: &str,
+        verbose: bool,
+    ) {
+        self.written.fetch_add(1, Ordering::Relaxed);
+        if verbose {
+            info!(
+                target: ACCESS_LOG_TARGET,
+                stage = "acl",
+                outcome,
+                user = access.user,
+                source_ip = %access.client_addr.ip(),
+                source_port = access.client_addr.port(),
+                destination = %access.destination,
+                port = access.port,
+                protocol = access.protocol,
+                rule,
+                matched_on,
+                authenticated_user = access.authenticated_user.unwrap_or("-"),
+                correlation_id = access.correlation_id.unwrap_or("-"),
+                socks_version = access.socks_version,
+                command = access.command,
+                auth_method = access.auth_method,
+                group_count = access.group_count,
+                "ACL decision"
+            );
+        } else {
+            info!(
+                target: ACCESS_LOG_TARGET,
+                stage = "acl",
+                outcome,
+                user = access.user,
+                source_ip = %access.client_addr.ip(),
+                source_port = access.client_addr.port(),
+                destination = %access.destination,
+                port = access.port,
+                protocol = access.protocol,
+                rule,
+                matched_on,
+                correlation_id = access.correlation_id.unwrap_or("-"),
+                "ACL decision"
+            );
+        }
+    }
+}
+
+fn flush_minimal(minimal: &DashMap<MinimalKey, MinimalEntry>, written: &AtomicU64) -> usize {
+    // Take the keys first so connections recorded during the flush land in the next one
+    let keys: Vec<MinimalKey> = minimal.iter().map(|entry| entry.key().clone()).collect();
+    let mut flushed = 0;
+    for key in keys {
+        let Some((key, entry)) = minimal.remove(&key) else {
+            continue;
+        };
+        info!(
+            target: ACCESS_LOG_TARGET,
+            stage = "acl",
+            outcome = "allowed",
+            aggregated = true,
+            user = %key.user,
+            destination = %key.destination,
+            rule = %entry.rule,
+            connections = entry.connections,
+            "ACL decisions aggregated"
+        );
+        flushed += 1;
+    }
+    written.fetch_add(flushed as u64, Ordering::Relaxed);
+    flushed
+}
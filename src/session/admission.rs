@@ -0,0 +1,271 @@
+//! Connection-limit rejections at admission time.
+//!
+//! A client turned away by `qos.connection_limits` never gets a session, so it would
+//! otherwise leave nothing behind but a warning line. [`AdmissionLog`] keeps the most
+//! recent rejections in a bounded ring buffer for `GET /api/admission/rejections`,
+//! fans each one out to live subscribers, counts them for the stats endpoints, and
+//! writes an access-log record with `stage = "admission"`.
+
+use crate::qos::{ConnectionLimitExceeded, LimitType};
+use crate::telemetry::{TelemetryHistory, TelemetrySeverity};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::broadcast;
+use tracing::info;
+
+/// Rejections kept for the recent-rejections endpoint.
+pub const ADMISSION_LOG_CAPACITY: usize = 1000;
+
+/// Rejections buffered per live subscriber before it starts lagging.
+const FEED_CAPACITY: usize = 256;
+
+/// Target of the access-log records, so they can be routed separately from server logs.
+pub const ACCESS_LOG_TARGET: &str = "rustsocks::access";
+
+/// One client refused by a connection limit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdmissionRejection {
+    pub timestamp: DateTime<Utc>,
+    /// Always `"admission"`; matches the access-log `stage` field
+    pub stage: String,
+    pub limit_type: LimitType,
+    pub user: String,
+    pub source_ip: String,
+    pub source_port: u16,
+    /// Connections counted against the limit when the check ran
+    pub current: usize,
+    pub limit: usize,
+    /// `host:port` when the request was already parsed (SOCKS4); `None` for SOCKS5,
+    /// where limits are checked before the request is read
+    pub destination: Option<String>,
+    /// Client-supplied correlation ID (`auth.allow_correlation_suffix`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+impl AdmissionRejection {
+    pub fn new(
+        exceeded: &ConnectionLimitExceeded,
+        client_addr: SocketAddr,
+        destination: Option<String>,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            stage: "admission".to_string(),
+            limit_type: exceeded.limit_type,
+            user: exceeded.user.clone(),
+            source_ip: client_addr.ip().to_string(),
+            source_port: client_addr.port(),
+            current: exceeded.current,
+            limit: exceeded.limit,
+            destination,
+            correlation_id: None,
+        }
+    }
+}
+
+/// Rejection counters, in the shape of [`super::AclDecisionStats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdmissionRejectionStats {
+    pub total: u64,
+    pub global_connections: u64,
+    pub user_connections: u64,
+}
+
+#[derive(Debug)]
+pub struct AdmissionLog {
+    recent: Mutex<VecDeque<AdmissionRejection>>,
+    capacity: usize,
+    feed: broadcast::Sender<AdmissionRejection>,
+    global_connections: AtomicU64,
+    user_connections: AtomicU64,
+    per_user: DashMap<String, u64>,
+    telemetry: OnceLock<Arc<TelemetryHistory>>,
+}
+
+impl AdmissionLog {
+    pub fn new(capacity: usize) -> Self {
+        let (feed, _) = broadcast::channel(FEED_CAPACITY);
+        Self {
+            recent: Mutex::new(VecDeque::with_capacity(
+                capacity.min(ADMISSION_LOG_CAPACITY),
+            )),
+            capacity,
+            feed,
+            global_connections: AtomicU64::new(0),
+            user_connections: AtomicU64::new(0),
+            per_user: DashMap::new(),
+            telemetry: OnceLock::new(),
+        }
+    }
+
+    /// Also publish rejections as `admission` telemetry events.
+    pub fn set_telemetry(&self, telemetry: Arc<TelemetryHistory>) {
+        let _ = self.telemetry.set(telemetry);
+    }
+
+    pub async fn record(&self, rejection: AdmissionRejection) {
+        match rejection.limit_type {
+            LimitType::GlobalConnections => &self.global_connections,
+            LimitType::UserConnections => &self.user_connections,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+        *self.per_user.entry(rejection.user.clone()).or_insert(0) += 1;
+
+        info!(
+            target: ACCESS_LOG_TARGET,
+            stage = %rejection.stage,
+            outcome = "rejected",
+            limit_type = rejection.limit_type.as_str(),
+            user = %rejection.user,
+            source_ip = %rejection.source_ip,
+            source_port = rejection.source_port,
+            current = rejection.current,
+            limit = rejection.limit,
+            destination = rejection.destination.as_deref().unwrap_or("-"),
+            correlation_id = rejection.correlation_id.as_deref().unwrap_or("-"),
+            "Connection rejected at admission"
+        );
+
+        {
+            let mut recent = self.recent.lock().expect("admission log lock poisoned");
+            if recent.len() >= self.capacity {
+                recent.pop_front();
+            }
+            if self.capacity > 0 {
+                recent.push_back(rejection.clone());
+            }
+        }
+
+        if let Some(telemetry) = self.telemetry.get() {
+            telemetry
+                .record_event(
+                    TelemetrySeverity::Warning,
+                    "admission",
+                    format!(
+                        "Connection from {} rejected: {} limit {}/{}",
+                        rejection.source_ip,
+                        rejection.limit_type.as_str(),
+                        rejection.current,
+                        rejection.limit
+                    ),
+                    serde_json::to_value(&rejection).ok(),
+                )
+                .await;
+        }
+
+        // No subscribers is the common case and not an error
+        let _ = self.feed.send(rejection);
+    }
+
+    /// Live feed of rejections recorded from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<AdmissionRejection> {
+        self.feed.subscribe()
+    }
+
+    /// Most recent rejections first, optionally for one user.
+    pub fn recent(&self, user: Option<&str>, limit: usize) -> Vec<AdmissionRejection> {
+        let recent = self.recent.lock().expect("admission log lock poisoned");
+        recent
+            .iter()
+            .rev()
+            .filter(|rejection| user.is_none_or(|user| rejection.user == user))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn stats(&self) -> AdmissionRejectionStats {
+        let global_connections = self.global_connections.load(Ordering::Relaxed);
+        let user_connections = self.user_connections.load(Ordering::Relaxed);
+        AdmissionRejectionStats {
+            total: global_connections + user_connections,
+            global_connections,
+            user_connections,
+        }
+    }
+
+    /// Rejections counted for `user` since startup.
+    pub fn user_rejections(&self, user: &str) -> u64 {
+        self.per_user.get(user).map(|count| *count).unwrap_or(0)
+    }
+}
+
+impl Default for AdmissionLog {
+    fn default() -> Self {
+        Self::new(ADMISSION_LOG_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(user: &str, limit_type: LimitType) -> AdmissionRejection {
+        AdmissionRejection::new(
+            &ConnectionLimitExceeded {
+                limit_type,
+                user: user.to_string(),
+                current: 2,
+                limit: 2,
+            },
+            "10.0.0.1:40000".parse().unwrap(),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn ring_buffer_keeps_the_newest_rejections() {
+        let log = AdmissionLog::new(3);
+        for user in ["a", "b", "a", "c"] {
+            log.record(rejection(user, LimitType::UserConnections))
+                .await;
+        }
+        log.record(rejection("a", LimitType::GlobalConnections))
+            .await;
+
+        let users: Vec<_> = log
+            .recent(None, 10)
+            .into_iter()
+            .map(|rejection| rejection.user)
+            .collect();
+        assert_eq!(users, ["a", "c", "a"]);
+        assert_eq!(log.recent(Some("a"), 10).len(), 2);
+        assert_eq!(log.recent(None, 1).len(), 1);
+
+        // Counters are not bounded by the buffer
+        let stats = log.stats();
+        assert_eq!(stats.total, 5);
+        assert_eq!(stats.user_connections, 4);
+        assert_eq!(stats.global_connections, 1);
+        assert_eq!(log.user_rejections("a"), 3);
+    }
+
+    #[tokio::test]
+    async fn rejections_reach_subscribers_and_telemetry() {
+        let log = AdmissionLog::default();
+        let telemetry = Arc::new(TelemetryHistory::new(10, 1));
+        log.set_telemetry(telemetry.clone());
+        let mut feed = log.subscribe();
+
+        log.record(rejection("alice", LimitType::UserConnections))
+            .await;
+
+        let event = feed.try_recv().expect("rejection on the feed");
+        assert_eq!(event.user, "alice");
+        assert_eq!(event.stage, "admission");
+
+        let events = telemetry.get_events().await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].category, "admission");
+        assert_eq!(
+            events[0].details.as_ref().unwrap()["limit_type"],
+            "user_connections"
+        );
+    }
+}
@@ -1,14 +1,33 @@
 use super::store::SessionStore;
-use super::types::Session;
+use super::types::{BatchOverflowPolicy, Session};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::{Mutex, Notify};
-use tokio::time::{interval, Duration, MissedTickBehavior};
-use tracing::{debug, error, info};
+use std::time::Instant;
+use tokio::sync::{Mutex, MutexGuard, Notify};
+use tokio::time::{sleep, Duration};
+use tracing::{debug, error, info, warn};
+
+/// Number of recent flushes used to estimate the p95 flush latency.
+const LATENCY_WINDOW: usize = 32;
 
 #[derive(Debug, Clone)]
 pub struct BatchConfig {
+    /// Starting batch size; also the fixed size when adaptive sizing is disabled.
     pub batch_size: usize,
+    /// Longest time a session may wait in the queue before being flushed.
     pub batch_interval: Duration,
+    pub adaptive: bool,
+    pub min_batch_size: usize,
+    pub max_batch_size: usize,
+    /// Shortest flush interval the adaptive controller may tighten to.
+    pub min_interval: Duration,
+    /// Target p95 flush latency.
+    pub target_flush_latency: Duration,
+    /// Sessions the queue holds before `overflow_policy` applies.
+    pub queue_max: usize,
+    pub overflow_policy: BatchOverflowPolicy,
 }
 
 impl BatchConfig {
@@ -16,7 +35,43 @@ impl BatchConfig {
         Self {
             batch_size,
             batch_interval: Duration::from_millis(batch_interval_ms),
+            min_interval: Duration::from_millis((batch_interval_ms / 10).max(10)),
+            ..Self::default()
+        }
+        .normalized()
+    }
+
+    /// Build the writer configuration from `[sessions]` settings.
+    pub fn from_session_settings(settings: &crate::config::SessionSettings) -> Self {
+        Self {
+            adaptive: settings.batch_adaptive,
+            min_batch_size: settings.batch_min_size,
+            max_batch_size: settings.batch_max_size,
+            target_flush_latency: Duration::from_millis(settings.batch_target_flush_ms),
+            queue_max: settings.batch_queue_max,
+            // Validated with the rest of the config
+            overflow_policy: settings.batch_overflow_policy.parse().unwrap_or_default(),
+            ..Self::from_settings(settings.batch_size, settings.batch_interval_ms)
         }
+        .normalized()
+    }
+
+    /// Ensure the static batch size always lies within the adaptive bounds, so configs that
+    /// only set `batch_size` keep their behaviour as the starting point. Zero sizes and
+    /// intervals are clamped to the smallest usable value rather than rejected.
+    fn normalized(mut self) -> Self {
+        self.batch_size = self.batch_size.max(1);
+        self.batch_interval = self.batch_interval.max(Duration::from_millis(1));
+        self.min_batch_size = self.min_batch_size.clamp(1, self.batch_size);
+        self.max_batch_size = self.max_batch_size.max(self.batch_size);
+        self.min_interval = self.min_interval.min(self.batch_interval);
+        self.queue_max = self.queue_max.max(1);
+        self
+    }
+
+    /// Queue depth from which batches grow regardless of flush latency.
+    fn deep_queue(&self) -> usize {
+        (self.queue_max / 2).max(1)
     }
 }
 
@@ -25,36 +80,162 @@ impl Default for BatchConfig {
         Self {
             batch_size: 100,
             batch_interval: Duration::from_secs(1),
+            adaptive: true,
+            min_batch_size: 10,
+            max_batch_size: 1000,
+            min_interval: Duration::from_millis(100),
+            target_flush_latency: Duration::from_millis(50),
+            queue_max: 100_000,
+            overflow_policy: BatchOverflowPolicy::Block,
         }
     }
 }
 
+/// Destination for flushed session batches.
+pub trait BatchSink: Send + Sync + 'static {
+    fn save_batch(
+        &self,
+        sessions: Vec<Session>,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+}
+
+impl BatchSink for SessionStore {
+    fn save_batch(
+        &self,
+        sessions: Vec<Session>,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send {
+        SessionStore::save_batch(self, sessions)
+    }
+}
+
+/// Point-in-time view of the writer's adaptive state.
+#[derive(Debug, Clone)]
+pub struct BatchWriterStats {
+    pub adaptive: bool,
+    pub effective_batch_size: usize,
+    pub effective_interval_ms: u64,
+    pub min_batch_size: usize,
+    pub max_batch_size: usize,
+    pub target_flush_ms: u64,
+    pub queue_depth: usize,
+    pub queue_max: usize,
+    pub overflow_policy: BatchOverflowPolicy,
+    /// Sessions discarded by `drop_oldest` or `drop_newest` since startup
+    pub dropped_sessions: u64,
+    /// Enqueues that had to wait for a flush under `block` since startup
+    pub blocked_enqueues: u64,
+    pub flush_count: u64,
+    pub last_flush_ms: Option<f64>,
+    pub p95_flush_ms: Option<f64>,
+}
+
 #[derive(Debug)]
-pub struct BatchWriter {
-    store: Arc<SessionStore>,
-    config: BatchConfig,
-    queue: Mutex<Vec<Session>>,
+pub struct BatchWriter<S: BatchSink = SessionStore> {
+    store: Arc<S>,
+    /// Replaced by [`BatchWriter::reconfigure`] on a config reload
+    config: std::sync::RwLock<BatchConfig>,
+    queue: Mutex<VecDeque<Session>>,
+    effective_size: AtomicUsize,
+    effective_interval_ms: AtomicU64,
+    flush_count: AtomicU64,
+    dropped_sessions: AtomicU64,
+    blocked_enqueues: AtomicU64,
+    latencies: std::sync::Mutex<VecDeque<Duration>>,
     flush_notify: Notify,
+    /// Woken after every flush, for producers waiting on a full queue
+    space_notify: Notify,
     shutdown_notify: Notify,
 }
 
-impl BatchWriter {
-    pub fn new(store: Arc<SessionStore>, config: BatchConfig) -> Arc<Self> {
+impl<S: BatchSink> BatchWriter<S> {
+    pub fn new(store: Arc<S>, config: BatchConfig) -> Arc<Self> {
         let capacity = config.batch_size;
         Arc::new(Self {
-            queue: Mutex::new(Vec::with_capacity(capacity)),
+            queue: Mutex::new(VecDeque::with_capacity(capacity)),
+            effective_size: AtomicUsize::new(config.batch_size),
+            effective_interval_ms: AtomicU64::new(config.batch_interval.as_millis() as u64),
+            flush_count: AtomicU64::new(0),
+            dropped_sessions: AtomicU64::new(0),
+            blocked_enqueues: AtomicU64::new(0),
+            latencies: std::sync::Mutex::new(VecDeque::with_capacity(LATENCY_WINDOW)),
             flush_notify: Notify::new(),
+            space_notify: Notify::new(),
             shutdown_notify: Notify::new(),
             store,
-            config,
+            config: std::sync::RwLock::new(config),
         })
     }
 
+    /// Settings currently driving the writer.
+    pub fn config(&self) -> BatchConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Switch to new settings, restarting the adaptive controller from the new
+    /// starting size and interval. Queued sessions stay queued.
+    pub fn reconfigure(&self, config: BatchConfig) {
+        info!(
+            batch_size = config.batch_size,
+            interval_ms = config.batch_interval.as_millis(),
+            adaptive = config.adaptive,
+            "Session batch writer reconfigured"
+        );
+        let (size, interval) = (config.batch_size, config.batch_interval);
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+        self.effective_size.store(size, Ordering::Relaxed);
+        self.effective_interval_ms
+            .store(interval.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Batch size currently used to trigger size-based flushes.
+    pub fn effective_batch_size(&self) -> usize {
+        self.effective_size.load(Ordering::Relaxed)
+    }
+
+    /// Interval currently used for time-based flushes.
+    pub fn effective_interval(&self) -> Duration {
+        Duration::from_millis(self.effective_interval_ms.load(Ordering::Relaxed))
+    }
+
+    /// Number of non-empty flushes performed so far.
+    pub fn flush_count(&self) -> u64 {
+        self.flush_count.load(Ordering::Relaxed)
+    }
+
+    /// Queue `session` for the next flush. A full queue (`queue_max`) is handled by
+    /// `overflow_policy`: wait for the flush, or discard the oldest or this session.
     pub async fn enqueue(&self, session: Session) {
+        let config = self.config();
         let mut queue = self.queue.lock().await;
-        queue.push(session);
 
-        if queue.len() >= self.config.batch_size {
+        if queue.len() >= config.queue_max {
+            match config.overflow_policy {
+                BatchOverflowPolicy::Block => {
+                    self.blocked_enqueues.fetch_add(1, Ordering::Relaxed);
+                    #[cfg(feature = "metrics")]
+                    super::metrics::SessionMetrics::record_batch_queue_overflow("blocked");
+                    queue = self.wait_for_space(queue).await;
+                }
+                BatchOverflowPolicy::DropOldest => {
+                    queue.pop_front();
+                    self.record_drop(config.overflow_policy);
+                }
+                BatchOverflowPolicy::DropNewest => {
+                    self.record_drop(config.overflow_policy);
+                    self.flush_notify.notify_one();
+                    return;
+                }
+            }
+        }
+
+        queue.push_back(session);
+        #[cfg(feature = "metrics")]
+        super::metrics::SessionMetrics::set_batch_queue_depth(queue.len());
+
+        if queue.len() >= self.effective_batch_size() {
             debug!(
                 len = queue.len(),
                 "Batch size threshold reached, triggering flush"
@@ -63,6 +244,44 @@ impl BatchWriter {
         }
     }
 
+    /// Release `queue` until a flush leaves room in it.
+    async fn wait_for_space<'a>(
+        &'a self,
+        mut queue: MutexGuard<'a, VecDeque<Session>>,
+    ) -> MutexGuard<'a, VecDeque<Session>> {
+        loop {
+            // Registered before the lock is released, so the flush cannot be missed
+            let space = self.space_notify.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+            self.flush_notify.notify_one();
+            drop(queue);
+
+            space.await;
+            queue = self.queue.lock().await;
+            if queue.len() < self.config().queue_max {
+                return queue;
+            }
+        }
+    }
+
+    fn record_drop(&self, policy: BatchOverflowPolicy) {
+        let dropped = self.dropped_sessions.fetch_add(1, Ordering::Relaxed) + 1;
+        // One warning per thousand drops keeps a stalled store from flooding the log
+        if dropped % 1000 == 1 {
+            warn!(
+                dropped,
+                policy = policy.as_str(),
+                "Session batch queue full, discarding sessions"
+            );
+        }
+        #[cfg(feature = "metrics")]
+        super::metrics::SessionMetrics::record_batch_queue_overflow(match policy {
+            BatchOverflowPolicy::DropNewest => "dropped_newest",
+            _ => "dropped_oldest",
+        });
+    }
+
     pub async fn flush(&self) {
         let mut queue = self.queue.lock().await;
 
@@ -70,31 +289,127 @@ impl BatchWriter {
             return;
         }
 
-        let mut batch = Vec::with_capacity(queue.len());
-        std::mem::swap(&mut *queue, &mut batch);
+        let batch = Vec::from(std::mem::take(&mut *queue));
         drop(queue);
+        self.space_notify.notify_waiters();
+        #[cfg(feature = "metrics")]
+        super::metrics::SessionMetrics::set_batch_queue_depth(0);
 
         let count = batch.len();
         debug!(count, "Flushing session batch to store");
 
+        let started = Instant::now();
         if let Err(e) = self.store.save_batch(batch).await {
             error!(error = %e, "Failed to persist session batch");
         } else {
             debug!(count, "Session batch persisted successfully");
         }
+
+        self.flush_count.fetch_add(1, Ordering::Relaxed);
+        self.record_flush(count, started.elapsed());
+    }
+
+    /// Feed a flush observation into the adaptive controller.
+    fn record_flush(&self, count: usize, latency: Duration) {
+        let p95 = {
+            let mut latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+            if latencies.len() == LATENCY_WINDOW {
+                latencies.pop_front();
+            }
+            latencies.push_back(latency);
+            percentile(&latencies, 0.95)
+        };
+
+        #[cfg(feature = "metrics")]
+        super::metrics::SessionMetrics::observe_batch_flush(latency.as_secs_f64());
+
+        if self.config().adaptive {
+            self.adjust(count, p95.unwrap_or(latency));
+        }
+
+        #[cfg(feature = "metrics")]
+        super::metrics::SessionMetrics::set_batch_effective(
+            self.effective_batch_size(),
+            self.effective_interval().as_millis() as u64,
+        );
+    }
+
+    /// Shrink batches and tighten the interval when flushes are slow; grow batches and relax
+    /// the interval when flushes are fast and the queue keeps filling up. A flush that
+    /// drained a queue at least half full grows batches whatever the latency: the store
+    /// is falling behind, and smaller transactions would only cost it more.
+    fn adjust(&self, count: usize, p95: Duration) {
+        let size = self.effective_batch_size();
+        let interval = self.effective_interval();
+        let config = self.config();
+        let target = config.target_flush_latency;
+
+        let (new_size, new_interval) = if count >= config.deep_queue() {
+            (size.saturating_mul(2).min(config.max_batch_size), interval)
+        } else if p95 > target {
+            (
+                (size / 2).max(config.min_batch_size),
+                (interval / 2).max(config.min_interval),
+            )
+        } else if p95 <= target / 2 && count >= size {
+            (
+                size.saturating_mul(2).min(config.max_batch_size),
+                interval.saturating_mul(2).min(config.batch_interval),
+            )
+        } else {
+            return;
+        };
+
+        if new_size != size || new_interval != interval {
+            debug!(
+                old_size = size,
+                new_size,
+                interval_ms = new_interval.as_millis() as u64,
+                p95_ms = p95.as_secs_f64() * 1000.0,
+                "Adjusted session batch parameters"
+            );
+        }
+
+        self.effective_size.store(new_size, Ordering::Relaxed);
+        self.effective_interval_ms
+            .store(new_interval.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub async fn stats(&self) -> BatchWriterStats {
+        let queue_depth = self.queue.lock().await.len();
+        let (last, p95) = {
+            let latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+            (latencies.back().copied(), percentile(&latencies, 0.95))
+        };
+
+        let config = self.config();
+        BatchWriterStats {
+            adaptive: config.adaptive,
+            effective_batch_size: self.effective_batch_size(),
+            effective_interval_ms: self.effective_interval().as_millis() as u64,
+            min_batch_size: config.min_batch_size,
+            max_batch_size: config.max_batch_size,
+            target_flush_ms: config.target_flush_latency.as_millis() as u64,
+            queue_depth,
+            queue_max: config.queue_max,
+            overflow_policy: config.overflow_policy,
+            dropped_sessions: self.dropped_sessions.load(Ordering::Relaxed),
+            blocked_enqueues: self.blocked_enqueues.load(Ordering::Relaxed),
+            flush_count: self.flush_count(),
+            last_flush_ms: last.map(|d| d.as_secs_f64() * 1000.0),
+            p95_flush_ms: p95.map(|d| d.as_secs_f64() * 1000.0),
+        }
     }
 
     pub fn start(self: &Arc<Self>) {
-        let interval_duration = self.config.batch_interval;
         let writer = Arc::clone(self);
 
         tokio::spawn(async move {
-            let mut ticker = interval(interval_duration);
-            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
-
             loop {
+                // Re-read the interval every round so adaptive changes apply immediately
+                let wait = writer.effective_interval();
                 tokio::select! {
-                    _ = ticker.tick() => {
+                    _ = sleep(wait) => {
                         writer.flush().await;
                     }
                     _ = writer.flush_notify.notified() => {
@@ -108,9 +423,13 @@ impl BatchWriter {
             }
         });
 
+        let config = self.config();
         info!(
-            batch_size = self.config.batch_size,
-            interval_ms = self.config.batch_interval.as_millis(),
+            batch_size = config.batch_size,
+            interval_ms = config.batch_interval.as_millis(),
+            adaptive = config.adaptive,
+            queue_max = config.queue_max,
+            overflow_policy = config.overflow_policy.as_str(),
             "Session batch writer started"
         );
     }
@@ -120,3 +439,291 @@ impl BatchWriter {
         self.flush().await;
     }
 }
+
+fn percentile(samples: &VecDeque<Duration>, quantile: f64) -> Option<Duration> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    let rank = ((sorted.len() as f64) * quantile).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::types::{ConnectionInfo, Protocol};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    /// Fake store that records batch sizes and sleeps for an injected latency.
+    #[derive(Debug, Default)]
+    struct InstrumentedStore {
+        latency_ms: AtomicU64,
+        batches: std::sync::Mutex<Vec<usize>>,
+    }
+
+    impl BatchSink for InstrumentedStore {
+        async fn save_batch(&self, sessions: Vec<Session>) -> Result<(), sqlx::Error> {
+            let latency = self.latency_ms.load(Ordering::Relaxed);
+            if latency > 0 {
+                sleep(Duration::from_millis(latency)).await;
+            }
+            self.batches.lock().unwrap().push(sessions.len());
+            Ok(())
+        }
+    }
+
+    fn session() -> Session {
+        Session::new(
+            "alice",
+            ConnectionInfo {
+                source_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                source_port: 40000,
+                dest_ip: "example.com".into(),
+                dest_port: 443,
+                protocol: Protocol::Tcp,
+                authenticated_user: None,
+                correlation_id: None,
+                socks_version: 5,
+                chained: false,
+                groups: Vec::new(),
+            },
+            "allow",
+            None,
+        )
+    }
+
+    fn config() -> BatchConfig {
+        BatchConfig {
+            batch_size: 50,
+            batch_interval: Duration::from_millis(400),
+            adaptive: true,
+            min_batch_size: 5,
+            max_batch_size: 800,
+            min_interval: Duration::from_millis(20),
+            target_flush_latency: Duration::from_millis(30),
+            queue_max: 10_000,
+            overflow_policy: BatchOverflowPolicy::Block,
+        }
+    }
+
+    async fn push(writer: &BatchWriter<InstrumentedStore>, count: usize) {
+        for _ in 0..count {
+            writer.enqueue(session()).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_store_shrinks_batches_and_tightens_interval() {
+        let store = Arc::new(InstrumentedStore::default());
+        store.latency_ms.store(80, Ordering::Relaxed);
+        let writer = BatchWriter::new(store.clone(), config());
+
+        for _ in 0..4 {
+            push(&writer, writer.effective_batch_size()).await;
+            writer.flush().await;
+        }
+
+        assert_eq!(writer.effective_batch_size(), 5);
+        assert_eq!(writer.effective_interval(), Duration::from_millis(25));
+
+        // Visibility lag stays bounded: a single queued session is flushed within
+        // one tightened interval plus the store latency.
+        writer.start();
+        let started = Instant::now();
+        writer.enqueue(session()).await;
+        while store.batches.lock().unwrap().len() < 5 {
+            assert!(
+                started.elapsed() < Duration::from_millis(400),
+                "queued session should be persisted quickly"
+            );
+            sleep(Duration::from_millis(5)).await;
+        }
+        writer.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn fast_store_grows_batches_and_reduces_flushes() {
+        let store = Arc::new(InstrumentedStore::default());
+        let writer = BatchWriter::new(store.clone(), config());
+
+        for _ in 0..4 {
+            push(&writer, writer.effective_batch_size()).await;
+            writer.flush().await;
+        }
+
+        assert_eq!(writer.effective_batch_size(), 800);
+        assert_eq!(writer.effective_interval(), Duration::from_millis(400));
+
+        // The same volume now needs far fewer flushes than with the static size
+        let before = writer.flush_count();
+        push(&writer, 1600).await;
+        while writer.queue.lock().await.len() >= writer.effective_batch_size() {
+            writer.flush().await;
+        }
+        writer.flush().await;
+        assert!(writer.flush_count() - before <= 3);
+        assert!(store.batches.lock().unwrap().iter().any(|&n| n >= 800));
+    }
+
+    #[tokio::test]
+    async fn static_configuration_is_respected_when_adaptive_disabled() {
+        let store = Arc::new(InstrumentedStore::default());
+        store.latency_ms.store(60, Ordering::Relaxed);
+        let writer = BatchWriter::new(
+            store,
+            BatchConfig {
+                adaptive: false,
+                ..config()
+            },
+        );
+
+        push(&writer, 50).await;
+        writer.flush().await;
+
+        let stats = writer.stats().await;
+        assert_eq!(stats.effective_batch_size, 50);
+        assert_eq!(stats.effective_interval_ms, 400);
+        assert_eq!(stats.flush_count, 1);
+        assert!(stats.p95_flush_ms.unwrap() >= 60.0);
+    }
+
+    #[tokio::test]
+    async fn reconfigure_restarts_from_the_new_settings() {
+        let store = Arc::new(InstrumentedStore::default());
+        store.latency_ms.store(80, Ordering::Relaxed);
+        let writer = BatchWriter::new(store, config());
+
+        push(&writer, writer.effective_batch_size()).await;
+        writer.flush().await;
+        assert!(writer.effective_batch_size() < 50);
+
+        writer.reconfigure(BatchConfig {
+            batch_size: 200,
+            batch_interval: Duration::from_millis(800),
+            adaptive: false,
+            ..config()
+        });
+
+        push(&writer, 10).await;
+        writer.flush().await;
+        let stats = writer.stats().await;
+        assert!(!stats.adaptive);
+        assert_eq!(stats.effective_batch_size, 200);
+        assert_eq!(stats.effective_interval_ms, 800);
+    }
+
+    #[tokio::test]
+    async fn full_queue_drops_by_policy() {
+        for policy in [
+            BatchOverflowPolicy::DropOldest,
+            BatchOverflowPolicy::DropNewest,
+        ] {
+            let writer = BatchWriter::new(
+                Arc::new(InstrumentedStore::default()),
+                BatchConfig {
+                    queue_max: 3,
+                    overflow_policy: policy,
+                    ..config()
+                },
+            );
+            let sessions: Vec<Session> = (0..5).map(|_| session()).collect();
+            for session in &sessions {
+                writer.enqueue(session.clone()).await;
+            }
+
+            let queued: Vec<_> = writer
+                .queue
+                .lock()
+                .await
+                .iter()
+                .map(|session| session.session_id)
+                .collect();
+            let kept = match policy {
+                BatchOverflowPolicy::DropOldest => &sessions[2..],
+                _ => &sessions[..3],
+            };
+            let kept: Vec<_> = kept.iter().map(|session| session.session_id).collect();
+            assert_eq!(queued, kept, "{}", policy);
+
+            let stats = writer.stats().await;
+            assert_eq!(stats.queue_depth, 3);
+            assert_eq!(stats.dropped_sessions, 2);
+            assert_eq!(stats.blocked_enqueues, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn full_queue_blocks_until_flush() {
+        let store = Arc::new(InstrumentedStore::default());
+        let writer = BatchWriter::new(
+            store.clone(),
+            BatchConfig {
+                queue_max: 2,
+                ..config()
+            },
+        );
+        push(&writer, 2).await;
+
+        let blocked = tokio::spawn({
+            let writer = Arc::clone(&writer);
+            async move { writer.enqueue(session()).await }
+        });
+        sleep(Duration::from_millis(50)).await;
+        assert!(!blocked.is_finished());
+
+        writer.flush().await;
+        tokio::time::timeout(Duration::from_secs(1), blocked)
+            .await
+            .expect("enqueue resumes after the flush")
+            .unwrap();
+
+        assert_eq!(*store.batches.lock().unwrap(), vec![2]);
+        let stats = writer.stats().await;
+        assert_eq!(stats.queue_depth, 1);
+        assert_eq!(stats.blocked_enqueues, 1);
+        assert_eq!(stats.dropped_sessions, 0);
+    }
+
+    #[tokio::test]
+    async fn deep_queue_grows_batches_despite_slow_flushes() {
+        let store = Arc::new(InstrumentedStore::default());
+        store.latency_ms.store(80, Ordering::Relaxed);
+        let writer = BatchWriter::new(
+            store,
+            BatchConfig {
+                queue_max: 100,
+                ..config()
+            },
+        );
+
+        push(&writer, 60).await;
+        writer.flush().await;
+        assert_eq!(writer.effective_batch_size(), 100);
+        assert_eq!(writer.effective_interval(), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn normalized_bounds_contain_static_size() {
+        let config = BatchConfig {
+            batch_size: 5000,
+            min_batch_size: 10,
+            max_batch_size: 1000,
+            ..BatchConfig::default()
+        }
+        .normalized();
+        assert_eq!(config.max_batch_size, 5000);
+
+        let config = BatchConfig::from_settings(4, 1000);
+        assert_eq!(config.min_batch_size, 4);
+        assert_eq!(config.min_interval, Duration::from_millis(100));
+
+        let config = BatchConfig::from_settings(0, 0);
+        assert_eq!(config.batch_size, 1);
+        assert_eq!(config.min_batch_size, 1);
+        assert_eq!(config.batch_interval, Duration::from_millis(1));
+        assert_eq!(config.min_interval, Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,319 @@
+//! Live session events for `GET /api/ws`.
+//!
+//! [`SessionManager`](super::SessionManager) publishes an event when a session starts or
+//! closes, when its traffic counters move, when an ACL blocks it and when QoS makes it
+//! wait or drops its datagrams, and when a connection limit turns a client away before
+//! it gets a session. The feed is a broadcast channel: publishing never
+//! waits, and a subscriber that falls behind skips the events it missed instead of
+//! slowing the relays down. Traffic and throttle events come at most once per session
+//! per `sessions.live_traffic_interval_secs`, so a busy tunnel cannot flood the feed.
+
+use super::admission::AdmissionRejection;
+use super::types::{DestHostPattern, Session, SessionStatus};
+use crate::qos::LimitType;
+use chrono::{DateTime, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Events buffered per subscriber before it starts lagging.
+const FEED_CAPACITY: usize = 1024;
+
+/// Default of `sessions.live_traffic_interval_secs`
+pub const DEFAULT_LIVE_TRAFFIC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One change to a session, or a client refused at admission, as sent over `/api/ws`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionEvent {
+    pub timestamp: DateTime<Utc>,
+    /// Nil for `admission_rejected`, which never gets a session
+    pub session_id: Uuid,
+    pub user: String,
+    pub source_ip: String,
+    pub source_port: u16,
+    /// Requested destination, see [`Session::dest_host`]
+    pub dest_host: String,
+    pub dest_port: u16,
+    #[serde(flatten)]
+    pub kind: SessionEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEventKind {
+    SessionStarted {
+        protocol: String,
+        acl_rule: Option<String>,
+    },
+    SessionClosed {
+        status: SessionStatus,
+        close_reason: Option<String>,
+        bytes_sent: u64,
+        bytes_received: u64,
+        duration_secs: Option<u64>,
+    },
+    /// Totals so far, not the change since the previous update
+    TrafficUpdate {
+        bytes_sent: u64,
+        bytes_received: u64,
+        packets_sent: u64,
+        packets_received: u64,
+    },
+    AclBlocked {
+        acl_rule: Option<String>,
+        /// SOCKS reply sent to the client; 0 when the block came after the connect
+        /// succeeded (an SNI rule or an ACL reload)
+        reply_code: Option<u8>,
+    },
+    QosThrottled {
+        /// Bucket that ran out: "global", "ip" or "user"
+        dimension: String,
+        /// A UDP datagram was dropped rather than delayed
+        dropped: bool,
+    },
+    /// A connection limit refused the client, see [`AdmissionRejection`]
+    AdmissionRejected {
+        limit_type: LimitType,
+        current: usize,
+        limit: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        correlation_id: Option<String>,
+    },
+}
+
+impl SessionEvent {
+    pub fn new(session: &Session, kind: SessionEventKind) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            session_id: session.session_id,
+            user: session.user.to_string(),
+            source_ip: session.source_ip.to_string(),
+            source_port: session.source_port,
+            dest_host: session.dest_host.to_string(),
+            dest_port: session.dest_port,
+            kind,
+        }
+    }
+
+    pub fn started(session: &Session) -> Self {
+        Self::new(
+            session,
+            SessionEventKind::SessionStarted {
+                protocol: session.protocol.to_string(),
+                acl_rule: session.acl_rule_matched.as_deref().map(str::to_string),
+            },
+        )
+    }
+
+    pub fn closed(session: &Session) -> Self {
+        Self::new(
+            session,
+            SessionEventKind::SessionClosed {
+                status: session.status.clone(),
+                close_reason: session.close_reason.clone(),
+                bytes_sent: session.bytes_sent,
+                bytes_received: session.bytes_received,
+                duration_secs: session.duration_secs,
+            },
+        )
+    }
+
+    pub fn traffic(session: &Session) -> Self {
+        Self::new(
+            session,
+            SessionEventKind::TrafficUpdate {
+                bytes_sent: session.bytes_sent,
+                bytes_received: session.bytes_received,
+                packets_sent: session.packets_sent,
+                packets_received: session.packets_received,
+            },
+        )
+    }
+
+    /// The destination is only known for SOCKS4, whose request is read before the
+    /// limits are checked; SOCKS5 rejections have an empty `dest_host` and port 0
+    pub fn admission_rejected(rejection: &AdmissionRejection) -> Self {
+        let (dest_host, dest_port) = rejection
+            .destination
+            .as_deref()
+            .and_then(|destination| destination.rsplit_once(':'))
+            .and_then(|(host, port)| Some((host.to_string(), port.parse().ok()?)))
+            .unwrap_or_default();
+        Self {
+            timestamp: rejection.timestamp,
+            session_id: Uuid::nil(),
+            user: rejection.user.clone(),
+            source_ip: rejection.source_ip.clone(),
+            source_port: rejection.source_port,
+            dest_host,
+            dest_port,
+            kind: SessionEventKind::AdmissionRejected {
+                limit_type: rejection.limit_type,
+                current: rejection.current,
+                limit: rejection.limit,
+                correlation_id: rejection.correlation_id.clone(),
+            },
+        }
+    }
+
+    pub fn acl_blocked(session: &Session) -> Self {
+        Self::new(
+            session,
+            SessionEventKind::AclBlocked {
+                acl_rule: session.acl_rule_matched.as_deref().map(str::to_string),
+                reply_code: session.reply_code,
+            },
+        )
+    }
+}
+
+/// Which events a `/api/ws` subscriber wants; empty matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct SessionEventFilter {
+    pub user: Option<String>,
+    pub destination: Option<DestHostPattern>,
+}
+
+impl SessionEventFilter {
+    pub fn matches(&self, event: &SessionEvent) -> bool {
+        self.user.as_deref().is_none_or(|user| event.user == user)
+            && self
+                .destination
+                .as_ref()
+                .is_none_or(|pattern| pattern.matches(&event.dest_host))
+    }
+}
+
+/// Events published at most once per session per interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Paced {
+    Traffic,
+    QosThrottled,
+}
+
+/// Broadcast feed of [`SessionEvent`]s
+#[derive(Debug)]
+pub struct SessionEvents {
+    feed: broadcast::Sender<SessionEvent>,
+    interval_ms: AtomicU64,
+    last_sent: DashMap<(Uuid, Paced), Instant>,
+}
+
+impl SessionEvents {
+    pub fn new(interval: Duration) -> Self {
+        let (feed, _) = broadcast::channel(FEED_CAPACITY);
+        Self {
+            feed,
+            interval_ms: AtomicU64::new(interval.as_millis() as u64),
+            last_sent: DashMap::new(),
+        }
+    }
+
+    /// Pace traffic and throttle events to one per session per `interval`.
+    pub fn set_interval(&self, interval: Duration) {
+        self.interval_ms
+            .store(interval.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Live feed of events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.feed.subscribe()
+    }
+
+    /// Checked before building an event, which is skipped while nobody listens
+    pub fn has_subscribers(&self) -> bool {
+        self.feed.receiver_count() > 0
+    }
+
+    pub fn publish(&self, event: SessionEvent) {
+        // Subscribers may all have gone since the check
+        let _ = self.feed.send(event);
+    }
+
+    /// Whether a traffic update for `session_id` is due
+    pub fn traffic_due(&self, session_id: Uuid) -> bool {
+        self.due(session_id, Paced::Traffic)
+    }
+
+    /// Whether a throttle event for `session_id` is due
+    pub fn throttle_due(&self, session_id: Uuid) -> bool {
+        self.due(session_id, Paced::QosThrottled)
+    }
+
+    fn due(&self, session_id: Uuid, paced: Paced) -> bool {
+        let now = Instant::now();
+        let interval = Duration::from_millis(self.interval_ms.load(Ordering::Relaxed));
+        match self.last_sent.entry((session_id, paced)) {
+            Entry::Occupied(mut last) => {
+                if now.duration_since(*last.get()) < interval {
+                    return false;
+                }
+                last.insert(now);
+            }
+            Entry::Vacant(last) => {
+                last.insert(now);
+            }
+        }
+        true
+    }
+
+    /// Drop the pacing state of a closed session.
+    pub fn forget(&self, session_id: Uuid) {
+        self.last_sent.remove(&(session_id, Paced::Traffic));
+        self.last_sent.remove(&(session_id, Paced::QosThrottled));
+    }
+}
+
+impl Default for SessionEvents {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIVE_TRAFFIC_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paced_events_wait_for_the_interval() {
+        let events = SessionEvents::new(Duration::from_secs(60));
+        let session_id = Uuid::new_v4();
+
+        assert!(events.traffic_due(session_id));
+        assert!(!events.traffic_due(session_id));
+        // Each kind is paced on its own
+        assert!(events.throttle_due(session_id));
+
+        events.forget(session_id);
+        assert!(events.traffic_due(session_id));
+    }
+
+    #[test]
+    fn filter_matches_user_and_destination() {
+        let filter = SessionEventFilter {
+            user: Some("alice".to_string()),
+            destination: Some(DestHostPattern::parse("*.example.com").unwrap()),
+        };
+        let event = |user: &str, dest_host: &str| SessionEvent {
+            timestamp: Utc::now(),
+            session_id: Uuid::new_v4(),
+            user: user.to_string(),
+            source_ip: "10.0.0.5".to_string(),
+            source_port: 50000,
+            dest_host: dest_host.to_string(),
+            dest_port: 443,
+            kind: SessionEventKind::QosThrottled {
+                dimension: "user".to_string(),
+                dropped: false,
+            },
+        };
+
+        assert!(filter.matches(&event("alice", "api.example.com")));
+        assert!(!filter.matches(&event("bob", "api.example.com")));
+        assert!(!filter.matches(&event("alice", "example.org")));
+        assert!(SessionEventFilter::default().matches(&event("bob", "example.org")));
+    }
+}
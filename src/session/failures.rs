@@ -0,0 +1,324 @@
+//! Failed requests, for telling "the proxy is broken" apart by cause.
+//!
+//! Every request that ends without a tunnel is recorded here with a [`FailureCategory`]
+//! and the SOCKS reply the client got: logins that fail, ACL blocks, destinations that
+//! do not resolve and upstream connects that time out or are refused. Requests that got
+//! as far as a destination also leave a session record carrying the same category, but
+//! a failed login happens before the request is read, so [`FailureLog`] is the one
+//! place that sees all of them. It keeps the most recent failures in a bounded ring
+//! buffer for `GET /api/stats/failures`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Failures kept for the failure statistics.
+pub const FAILURE_LOG_CAPACITY: usize = 10_000;
+
+/// Why a request failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCategory {
+    /// The destination name did not resolve to a usable address
+    DnsError,
+    /// The upstream connect did not complete within `server.pool.connect_timeout_ms`
+    ConnectTimeout,
+    /// The destination refused the connection
+    ConnectRefused,
+    /// Any other upstream connect error (unreachable network, parent proxy refusal, ...)
+    ConnectError,
+    /// Blocked by an ACL rule, the default policy or the special-use names policy
+    AclBlock,
+    /// Credentials, method negotiation or a ban refused the login
+    AuthFail,
+    /// The TLS handshake with a `server.upstream_tls` destination failed, e.g. on an
+    /// untrusted certificate
+    UpstreamTls,
+}
+
+impl FailureCategory {
+    pub const ALL: [FailureCategory; 7] = [
+        FailureCategory::DnsError,
+        FailureCategory::ConnectTimeout,
+        FailureCategory::ConnectRefused,
+        FailureCategory::ConnectError,
+        FailureCategory::AclBlock,
+        FailureCategory::AuthFail,
+        FailureCategory::UpstreamTls,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailureCategory::DnsError => "dns_error",
+            FailureCategory::ConnectTimeout => "connect_timeout",
+            FailureCategory::ConnectRefused => "connect_refused",
+            FailureCategory::ConnectError => "connect_error",
+            FailureCategory::AclBlock => "acl_block",
+            FailureCategory::AuthFail => "auth_fail",
+            FailureCategory::UpstreamTls => "upstream_tls",
+        }
+    }
+
+    /// Category of an upstream connect that failed with `error`
+    pub fn for_connect_error(error: &std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::TimedOut => FailureCategory::ConnectTimeout,
+            std::io::ErrorKind::ConnectionRefused => FailureCategory::ConnectRefused,
+            _ => FailureCategory::ConnectError,
+        }
+    }
+}
+
+impl std::str::FromStr for FailureCategory {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        FailureCategory::ALL
+            .into_iter()
+            .find(|category| category.as_str() == value)
+            .ok_or_else(|| format!("Invalid failure category: {}", value))
+    }
+}
+
+/// One failed request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailureRecord {
+    pub timestamp: DateTime<Utc>,
+    pub category: FailureCategory,
+    /// SOCKS reply sent to the client; `None` when it never got one (a failed login is
+    /// answered in the method or auth sub-negotiation)
+    pub reply_code: Option<u8>,
+    /// `None` when the login itself failed
+    pub user: Option<String>,
+    pub source_ip: String,
+    /// `host:port` of the request; `None` when it failed before the request was read
+    pub destination: Option<String>,
+    /// Session record of the request, when one was created
+    pub session_id: Option<Uuid>,
+}
+
+impl FailureRecord {
+    /// A login refused before the client could send a request
+    pub fn auth_failure(source_ip: IpAddr, reply_code: Option<u8>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            category: FailureCategory::AuthFail,
+            reply_code,
+            user: None,
+            source_ip: source_ip.to_string(),
+            destination: None,
+            session_id: None,
+        }
+    }
+}
+
+/// Failures of one destination within the window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailingDestination {
+    pub destination: String,
+    pub failures: u64,
+    pub by_category: BTreeMap<FailureCategory, u64>,
+}
+
+/// Failure counts over a window, see [`FailureLog::summary`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailureSummary {
+    pub since: DateTime<Utc>,
+    /// Oldest failure still held; a window reaching further back than this may have
+    /// lost failures to the ring buffer
+    pub oldest_kept: Option<DateTime<Utc>>,
+    pub total: u64,
+    /// Every category, zeros included
+    pub by_category: BTreeMap<FailureCategory, u64>,
+    /// Most failing destinations first
+    pub top_destinations: Vec<FailingDestination>,
+}
+
+#[derive(Debug)]
+pub struct FailureLog {
+    recent: Mutex<VecDeque<FailureRecord>>,
+    capacity: usize,
+    /// Failures per category since startup, in [`FailureCategory::ALL`] order
+    totals: [AtomicU64; 7],
+}
+
+impl FailureLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            recent: Mutex::new(VecDeque::with_capacity(capacity.min(FAILURE_LOG_CAPACITY))),
+            capacity,
+            totals: Default::default(),
+        }
+    }
+
+    pub fn record(&self, record: FailureRecord) {
+        self.totals[record.category as usize].fetch_add(1, Ordering::Relaxed);
+        if self.capacity == 0 {
+            return;
+        }
+        let mut recent = self.recent();
+        if recent.len() >= self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(record);
+    }
+
+    /// Failures per category since startup
+    pub fn totals(&self) -> BTreeMap<FailureCategory, u64> {
+        FailureCategory::ALL
+            .into_iter()
+            .map(|category| {
+                (
+                    category,
+                    self.totals[category as usize].load(Ordering::Relaxed),
+                )
+            })
+            .collect()
+    }
+
+    /// Failures at or after `since`, by category and for the `top` most failing
+    /// destinations
+    pub fn summary(&self, since: DateTime<Utc>, top: usize) -> FailureSummary {
+        let mut by_category: BTreeMap<FailureCategory, u64> = FailureCategory::ALL
+            .into_iter()
+            .map(|category| (category, 0))
+            .collect();
+        let mut destinations: HashMap<&str, FailingDestination> = HashMap::new();
+        let mut total = 0;
+
+        let recent = self.recent();
+        for record in recent.iter().filter(|record| record.timestamp >= since) {
+            total += 1;
+            *by_category.entry(record.category).or_insert(0) += 1;
+            if let Some(destination) = record.destination.as_deref() {
+                let entry = destinations
+                    .entry(destination)
+                    .or_insert_with(|| FailingDestination {
+                        destination: destination.to_string(),
+                        failures: 0,
+                        by_category: BTreeMap::new(),
+                    });
+                entry.failures += 1;
+                *entry.by_category.entry(record.category).or_insert(0) += 1;
+            }
+        }
+
+        let mut top_destinations: Vec<FailingDestination> = destinations.into_values().collect();
+        top_destinations.sort_by(|a, b| {
+            b.failures
+                .cmp(&a.failures)
+                .then_with(|| a.destination.cmp(&b.destination))
+        });
+        top_destinations.truncate(top);
+
+        FailureSummary {
+            since,
+            oldest_kept: recent.front().map(|record| record.timestamp),
+            total,
+            by_category,
+            top_destinations,
+        }
+    }
+
+    fn recent(&self) -> std::sync::MutexGuard<'_, VecDeque<FailureRecord>> {
+        self.recent.lock().expect("failure log lock poisoned")
+    }
+}
+
+impl Default for FailureLog {
+    fn default() -> Self {
+        Self::new(FAILURE_LOG_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(category: FailureCategory, destination: Option<&str>) -> FailureRecord {
+        FailureRecord {
+            timestamp: Utc::now(),
+            category,
+            reply_code: Some(0x04),
+            user: Some("alice".to_string()),
+            source_ip: "10.0.0.5".to_string(),
+            destination: destination.map(str::to_string),
+            session_id: None,
+        }
+    }
+
+    #[test]
+    fn summary_groups_by_category_and_destination() {
+        let log = FailureLog::new(100);
+        let since = Utc::now() - chrono::Duration::minutes(5);
+
+        let mut old = failure(FailureCategory::DnsError, Some("gone.example.com:443"));
+        old.timestamp = since - chrono::Duration::minutes(1);
+        log.record(old);
+        for _ in 0..3 {
+            log.record(failure(
+                FailureCategory::ConnectTimeout,
+                Some("slow.example.com:443"),
+            ));
+        }
+        log.record(failure(
+            FailureCategory::ConnectRefused,
+            Some("slow.example.com:443"),
+        ));
+        log.record(failure(
+            FailureCategory::AclBlock,
+            Some("ads.example.com:80"),
+        ));
+        log.record(FailureRecord::auth_failure(
+            "10.0.0.5".parse().unwrap(),
+            None,
+        ));
+
+        let summary = log.summary(since, 1);
+        assert_eq!(summary.total, 6);
+        assert_eq!(summary.by_category[&FailureCategory::ConnectTimeout], 3);
+        assert_eq!(summary.by_category[&FailureCategory::DnsError], 0);
+        assert_eq!(summary.by_category.len(), FailureCategory::ALL.len());
+        assert_eq!(summary.top_destinations.len(), 1);
+        assert_eq!(
+            summary.top_destinations[0].destination,
+            "slow.example.com:443"
+        );
+        assert_eq!(summary.top_destinations[0].failures, 4);
+        assert!(summary.oldest_kept.unwrap() < since);
+
+        // Startup totals include what fell out of the window
+        assert_eq!(log.totals()[&FailureCategory::DnsError], 1);
+    }
+
+    #[test]
+    fn ring_keeps_the_latest_failures() {
+        let log = FailureLog::new(2);
+        for category in [
+            FailureCategory::DnsError,
+            FailureCategory::AclBlock,
+            FailureCategory::AuthFail,
+        ] {
+            log.record(failure(category, None));
+        }
+        let summary = log.summary(DateTime::<Utc>::MIN_UTC, 10);
+        assert_eq!(summary.total, 2);
+        assert_eq!(summary.by_category[&FailureCategory::DnsError], 0);
+        assert_eq!(log.totals()[&FailureCategory::DnsError], 1);
+    }
+
+    #[test]
+    fn categories_round_trip_through_their_names() {
+        for category in FailureCategory::ALL {
+            assert_eq!(category.as_str().parse::<FailureCategory>(), Ok(category));
+            assert_eq!(
+                serde_json::to_value(category).unwrap(),
+                serde_json::Value::String(category.as_str().to_string())
+            );
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use chrono::{DateTime, Duration as ChronoDuration, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration as StdDuration;
 use tokio::sync::RwLock;
@@ -11,7 +12,13 @@ use tracing::warn;
 
 use super::SessionManager;
 #[cfg(feature = "database")]
-use super::SessionStore;
+use super::{MetricsPageCursor, SessionStore};
+
+/// Samples fetched from storage per step when streaming history.
+pub const METRICS_CHUNK_SIZE: usize = 512;
+
+/// Samples a decimation bucket can emit (min/max of active sessions and bandwidth).
+const POINTS_PER_BUCKET: usize = 4;
 
 /// Single metrics snapshot at a point in time
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,14 +27,237 @@ pub struct MetricsSnapshot {
     pub active_sessions: u64,
     pub total_sessions: u64,
     pub bandwidth: u64, // total bytes sent + received
+    /// Spread of the bucket when this point is a rollup; the series above then hold
+    /// the bucket averages and `timestamp` its start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollup: Option<MetricsRollup>,
+}
+
+impl MetricsSnapshot {
+    /// Point for the rollup bucket starting at `bucket_start`
+    pub fn rolled_up(bucket_start: DateTime<Utc>, rollup: MetricsRollup) -> Self {
+        Self {
+            timestamp: bucket_start,
+            active_sessions: rollup.active_sessions.avg.round() as u64,
+            total_sessions: rollup.total_sessions.avg.round() as u64,
+            bandwidth: rollup.bandwidth.avg.round() as u64,
+            rollup: Some(rollup),
+        }
+    }
+
+    /// Fold a raw sample or another bucket into this rollup point
+    fn absorb(&mut self, other: &MetricsSnapshot) {
+        let mut rollup = MetricsRollup::of(self);
+        rollup.merge(&MetricsRollup::of(other));
+        *self = Self::rolled_up(self.timestamp, rollup);
+    }
+}
+
+/// Aggregates of one rollup bucket
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MetricsRollup {
+    /// Raw samples folded into the bucket
+    pub samples: u64,
+    pub active_sessions: MetricAggregate,
+    pub total_sessions: MetricAggregate,
+    pub bandwidth: MetricAggregate,
+}
+
+impl MetricsRollup {
+    /// Aggregates of a point: its own for a rollup, a one-sample bucket for a raw sample
+    pub fn of(point: &MetricsSnapshot) -> Self {
+        point.rollup.unwrap_or_else(|| Self {
+            samples: 1,
+            active_sessions: MetricAggregate::single(point.active_sessions),
+            total_sessions: MetricAggregate::single(point.total_sessions),
+            bandwidth: MetricAggregate::single(point.bandwidth),
+        })
+    }
+
+    pub fn merge(&mut self, other: &MetricsRollup) {
+        self.samples = self.samples.saturating_add(other.samples);
+        for (mine, theirs) in [
+            (&mut self.active_sessions, &other.active_sessions),
+            (&mut self.total_sessions, &other.total_sessions),
+            (&mut self.bandwidth, &other.bandwidth),
+        ] {
+            mine.merge(theirs, self.samples);
+        }
+    }
+}
+
+/// Minimum, maximum, average and sum of one series within a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MetricAggregate {
+    pub min: u64,
+    pub max: u64,
+    pub avg: f64,
+    pub sum: u64,
+}
+
+impl MetricAggregate {
+    pub fn single(value: u64) -> Self {
+        Self {
+            min: value,
+            max: value,
+            avg: value as f64,
+            sum: value,
+        }
+    }
+
+    /// Aggregate over `samples` samples, `sum / samples` being the average
+    pub fn from_parts(min: u64, max: u64, sum: u64, samples: u64) -> Self {
+        Self {
+            min,
+            max,
+            avg: sum as f64 / samples.max(1) as f64,
+            sum,
+        }
+    }
+
+    fn merge(&mut self, other: &MetricAggregate, samples: u64) {
+        *self = Self::from_parts(
+            self.min.min(other.min),
+            self.max.max(other.max),
+            self.sum.saturating_add(other.sum),
+            samples,
+        );
+    }
+}
+
+/// Tier of the metrics history a range is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetricsResolution {
+    /// Samples as collected, kept for `metrics.retention_hours`
+    Raw,
+    /// 1-minute rollups, kept for `metrics.rollup_retention_days`
+    OneMinute,
+    /// 15-minute rollups, kept for `metrics.rollup_retention_days`
+    FifteenMinutes,
+}
+
+impl MetricsResolution {
+    /// Rollup tiers, finest first
+    pub const ROLLUPS: [MetricsResolution; 2] = [Self::OneMinute, Self::FifteenMinutes];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::OneMinute => "1m",
+            Self::FifteenMinutes => "15m",
+        }
+    }
+
+    /// Bucket width in seconds; `None` for raw samples
+    pub fn bucket_secs(self) -> Option<i64> {
+        match self {
+            Self::Raw => None,
+            Self::OneMinute => Some(60),
+            Self::FifteenMinutes => Some(15 * 60),
+        }
+    }
+
+    /// Start of the bucket holding `timestamp`; raw samples are their own bucket
+    pub fn bucket_start(self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let Some(secs) = self.bucket_secs() else {
+            return timestamp;
+        };
+        let start = timestamp.timestamp().div_euclid(secs) * secs;
+        DateTime::from_timestamp(start, 0).unwrap_or(timestamp)
+    }
+
+    /// Tier for a range when the client leaves the choice to the server.
+    ///
+    /// Picks the coarsest tier whose buckets are still narrower than one of the
+    /// `max_points` returned points. Rollups replace raw samples when raw samples are
+    /// not `raw_available` for the whole range (aged out, or wider than a raw scan may
+    /// go); without `rollups` everything is read raw.
+    pub fn for_range(
+        span: ChronoDuration,
+        max_points: usize,
+        raw_available: bool,
+        rollups: bool,
+    ) -> Self {
+        if !rollups {
+            return Self::Raw;
+        }
+        let point_secs = span.num_seconds() / max_points.max(1) as i64;
+        let coarsest = Self::ROLLUPS
+            .into_iter()
+            .rev()
+            .find(|tier| tier.bucket_secs().is_some_and(|secs| secs <= point_secs));
+        match coarsest {
+            Some(tier) => tier,
+            None if raw_available => Self::Raw,
+            None => Self::OneMinute,
+        }
+    }
+
+    fn tier_index(self) -> Option<usize> {
+        Self::ROLLUPS.iter().position(|tier| *tier == self)
+    }
+}
+
+impl FromStr for MetricsResolution {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "raw" => Ok(Self::Raw),
+            "1m" => Ok(Self::OneMinute),
+            "15m" => Ok(Self::FifteenMinutes),
+            other => Err(format!(
+                "unknown resolution '{}', expected raw, 1m, 15m or auto",
+                other
+            )),
+        }
+    }
+}
+
+/// Folds a time-ordered stream of points into buckets of one resolution
+#[derive(Debug)]
+pub struct RollupFolder {
+    resolution: MetricsResolution,
+    open: Option<MetricsSnapshot>,
+}
+
+impl RollupFolder {
+    pub fn new(resolution: MetricsResolution) -> Self {
+        Self {
+            resolution,
+            open: None,
+        }
+    }
+
+    /// Add the next point; returns the bucket it closed, if any
+    pub fn push(&mut self, point: &MetricsSnapshot) -> Option<MetricsSnapshot> {
+        let bucket = self.resolution.bucket_start(point.timestamp);
+        match &mut self.open {
+            // A point behind the open bucket (clock stepped back) joins it
+            Some(open) if bucket <= open.timestamp => {
+                open.absorb(point);
+                None
+            }
+            open => open.replace(MetricsSnapshot::rolled_up(bucket, MetricsRollup::of(point))),
+        }
+    }
+
+    /// The bucket still being filled
+    pub fn finish(&mut self) -> Option<MetricsSnapshot> {
+        self.open.take()
+    }
 }
 
 /// Thread-safe storage for metrics history
 #[derive(Debug, Clone)]
 pub struct MetricsHistory {
     snapshots: Arc<RwLock<VecDeque<MetricsSnapshot>>>,
+    /// 1-minute and 15-minute rollups, the last bucket of each still filling
+    rollups: Arc<RwLock<[VecDeque<MetricsSnapshot>; 2]>>,
     max_snapshots: usize,
     max_age: ChronoDuration,
+    /// How long rollups are kept; zero keeps none
+    rollup_max_age: ChronoDuration,
 }
 
 impl MetricsHistory {
@@ -39,13 +269,48 @@ impl MetricsHistory {
     pub fn new(max_snapshots: usize, max_age_hours: i64) -> Self {
         Self {
             snapshots: Arc::new(RwLock::new(VecDeque::with_capacity(max_snapshots))),
+            rollups: Arc::default(),
             max_snapshots,
             max_age: ChronoDuration::hours(max_age_hours),
+            rollup_max_age: ChronoDuration::zero(),
         }
     }
 
+    /// Also keep 1-minute and 15-minute rollups of every snapshot for `retention_days`
+    pub fn with_rollups(mut self, retention_days: u64) -> Self {
+        self.rollup_max_age = i64::try_from(retention_days)
+            .ok()
+            .and_then(ChronoDuration::try_days)
+            .unwrap_or(ChronoDuration::MAX);
+        self
+    }
+
+    pub fn keeps_rollups(&self) -> bool {
+        self.rollup_max_age > ChronoDuration::zero()
+    }
+
     /// Add a new snapshot
     pub async fn add_snapshot(&self, snapshot: MetricsSnapshot) {
+        if self.keeps_rollups() {
+            let mut tiers = self.rollups.write().await;
+            let cutoff = Utc::now()
+                .checked_sub_signed(self.rollup_max_age)
+                .unwrap_or(DateTime::<Utc>::MIN_UTC);
+            for (tier, resolution) in tiers.iter_mut().zip(MetricsResolution::ROLLUPS) {
+                let bucket = resolution.bucket_start(snapshot.timestamp);
+                match tier.back_mut() {
+                    Some(last) if bucket <= last.timestamp => last.absorb(&snapshot),
+                    _ => tier.push_back(MetricsSnapshot::rolled_up(
+                        bucket,
+                        MetricsRollup::of(&snapshot),
+                    )),
+                }
+                while tier.front().is_some_and(|front| front.timestamp < cutoff) {
+                    tier.pop_front();
+                }
+            }
+        }
+
         let mut snapshots = self.snapshots.write().await;
 
         // Remove old snapshots beyond max_age
@@ -84,6 +349,357 @@ impl MetricsHistory {
             .cloned()
             .collect()
     }
+
+    /// Bytes per second between the two most recent snapshots.
+    ///
+    /// `bandwidth` covers sessions in the stats window, so it can drop when old sessions
+    /// age out; such intervals report zero.
+    pub async fn recent_throughput(&self) -> Option<f64> {
+        let snapshots = self.snapshots.read().await;
+        let mut recent = snapshots.iter().rev();
+        let (latest, previous) = (recent.next()?, recent.next()?);
+        let elapsed_ms = (latest.timestamp - previous.timestamp).num_milliseconds();
+        if elapsed_ms <= 0 {
+            return None;
+        }
+        let bytes = latest.bandwidth.saturating_sub(previous.bandwidth);
+        Some(bytes as f64 * 1000.0 / elapsed_ms as f64)
+    }
+
+    /// Up to `limit` snapshots in `[start, end]`, oldest first, newer than `after`.
+    pub async fn chunk(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        after: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Vec<MetricsSnapshot> {
+        let snapshots = self.snapshots.read().await;
+        Self::range(&snapshots, start, end, after, limit)
+    }
+
+    /// Like [`chunk`](Self::chunk), from the kept rollups of `resolution`
+    pub async fn chunk_at(
+        &self,
+        resolution: MetricsResolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        after: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Vec<MetricsSnapshot> {
+        match resolution.tier_index() {
+            Some(index) => {
+                let tiers = self.rollups.read().await;
+                Self::range(&tiers[index], start, end, after, limit)
+            }
+            None => self.chunk(start, end, after, limit).await,
+        }
+    }
+
+    fn range(
+        snapshots: &VecDeque<MetricsSnapshot>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        after: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Vec<MetricsSnapshot> {
+        let first = match after {
+            Some(after) => snapshots.partition_point(|s| s.timestamp <= after),
+            None => snapshots.partition_point(|s| s.timestamp < start),
+        };
+
+        snapshots
+            .range(first..)
+            .take_while(|s| s.timestamp <= end)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Reads a time range of snapshots in bounded chunks, oldest first.
+pub struct MetricsCursor {
+    source: CursorSource,
+    /// Tier read from the source
+    resolution: MetricsResolution,
+    /// Folds the raw samples read into buckets on the way
+    folder: Option<RollupFolder>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    chunk_size: usize,
+    done: bool,
+    /// Continues the range once this cursor is exhausted
+    then: Option<Box<MetricsCursor>>,
+}
+
+enum CursorSource {
+    Memory {
+        history: Arc<MetricsHistory>,
+        after: Option<DateTime<Utc>>,
+    },
+    #[cfg(feature = "database")]
+    Store {
+        store: Arc<SessionStore>,
+        after: Option<MetricsPageCursor>,
+    },
+}
+
+impl MetricsCursor {
+    pub fn memory(history: Arc<MetricsHistory>, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self::with_source(
+            CursorSource::Memory {
+                history,
+                after: None,
+            },
+            start,
+            end,
+        )
+    }
+
+    #[cfg(feature = "database")]
+    pub fn store(store: Arc<SessionStore>, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self::with_source(CursorSource::Store { store, after: None }, start, end)
+    }
+
+    /// History at `resolution`. Tiers the history does not keep are folded from its
+    /// raw samples.
+    pub fn memory_at(
+        history: Arc<MetricsHistory>,
+        resolution: MetricsResolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Self {
+        if resolution == MetricsResolution::Raw || !history.keeps_rollups() {
+            return Self::memory(history, start, end).folded(resolution);
+        }
+        let mut cursor = Self::memory(history, resolution.bucket_start(start), end);
+        cursor.resolution = resolution;
+        cursor
+    }
+
+    /// Stored rollups of `resolution`, followed by the raw samples newer than the last
+    /// stored bucket folded to the same resolution, so buckets the cleanup task has not
+    /// rolled up yet still show.
+    #[cfg(feature = "database")]
+    pub async fn store_at(
+        store: Arc<SessionStore>,
+        resolution: MetricsResolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> std::io::Result<Self> {
+        if resolution == MetricsResolution::Raw {
+            return Ok(Self::store(store, start, end));
+        }
+        let rolled_until = store
+            .metrics_rolled_until(resolution)
+            .await
+            .map_err(std::io::Error::other)?;
+        let tail_start = rolled_until.map_or(start, |until| until.max(start));
+        let tail = Self::store(store.clone(), tail_start, end).folded(resolution);
+
+        match rolled_until {
+            Some(until) if until > start => {
+                // Bucket starts are whole seconds, so this stops short of the tail
+                let stored_end = end.min(until - ChronoDuration::seconds(1));
+                let mut cursor = Self::store(store, resolution.bucket_start(start), stored_end);
+                cursor.resolution = resolution;
+                cursor.then = Some(Box::new(tail));
+                Ok(cursor)
+            }
+            _ => Ok(tail),
+        }
+    }
+
+    fn with_source(source: CursorSource, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self {
+            source,
+            resolution: MetricsResolution::Raw,
+            folder: None,
+            start,
+            end,
+            chunk_size: METRICS_CHUNK_SIZE,
+            done: false,
+            then: None,
+        }
+    }
+
+    fn folded(mut self, resolution: MetricsResolution) -> Self {
+        if resolution != MetricsResolution::Raw {
+            self.folder = Some(RollupFolder::new(resolution));
+        }
+        self
+    }
+
+    /// Override the number of samples fetched per step.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Next chunk of at most `chunk_size` samples; empty once the range is exhausted.
+    pub async fn next_chunk(&mut self) -> std::io::Result<Vec<MetricsSnapshot>> {
+        loop {
+            if self.done {
+                match self.then.take() {
+                    Some(next) => *self = *next,
+                    None => return Ok(Vec::new()),
+                }
+            }
+
+            let chunk = self.read_source().await?;
+            let chunk = match &mut self.folder {
+                Some(folder) => {
+                    let mut buckets: Vec<MetricsSnapshot> = chunk
+                        .iter()
+                        .filter_map(|sample| folder.push(sample))
+                        .collect();
+                    if self.done {
+                        buckets.extend(folder.finish());
+                    }
+                    buckets
+                }
+                None => chunk,
+            };
+            // Folding may close no bucket yet; keep reading rather than signal the end
+            if !chunk.is_empty() {
+                return Ok(chunk);
+            }
+        }
+    }
+
+    async fn read_source(&mut self) -> std::io::Result<Vec<MetricsSnapshot>> {
+        let chunk = match &mut self.source {
+            CursorSource::Memory { history, after } => {
+                let chunk = history
+                    .chunk_at(
+                        self.resolution,
+                        self.start,
+                        self.end,
+                        *after,
+                        self.chunk_size,
+                    )
+                    .await;
+                if let Some(last) = chunk.last() {
+                    *after = Some(last.timestamp);
+                }
+                chunk
+            }
+            #[cfg(feature = "database")]
+            CursorSource::Store { store, after } => {
+                let (chunk, next) = store
+                    .query_metrics_page_at(
+                        self.resolution,
+                        &self.start,
+                        &self.end,
+                        after.as_ref(),
+                        self.chunk_size,
+                    )
+                    .await
+                    .map_err(std::io::Error::other)?;
+                *after = next;
+                chunk
+            }
+        };
+
+        if chunk.len() < self.chunk_size {
+            self.done = true;
+        }
+        Ok(chunk)
+    }
+}
+
+/// Reduces a time-ordered sample stream to a bounded number of points.
+///
+/// The range is split into equal time buckets and each bucket keeps only the
+/// samples holding its minimum and maximum active sessions and bandwidth, so
+/// short spikes survive while memory stays constant.
+#[derive(Debug)]
+pub struct MinMaxDecimator {
+    start_ms: i64,
+    bucket_ms: i64,
+    bucket: Option<i64>,
+    extremes: [Option<(u64, MetricsSnapshot)>; POINTS_PER_BUCKET],
+    seq: u64,
+}
+
+impl MinMaxDecimator {
+    /// `max_points` below the per-bucket point count is raised to it.
+    pub fn new(start: DateTime<Utc>, end: DateTime<Utc>, max_points: usize) -> Self {
+        let buckets = (max_points / POINTS_PER_BUCKET).max(1) as i64;
+        let span_ms = (end - start).num_milliseconds().max(1);
+        Self {
+            start_ms: start.timestamp_millis(),
+            bucket_ms: ((span_ms + buckets - 1) / buckets).max(1),
+            bucket: None,
+            extremes: Default::default(),
+            seq: 0,
+        }
+    }
+
+    /// Feed the next sample; completed buckets are appended to `out` in time order.
+    pub fn push(&mut self, sample: MetricsSnapshot, out: &mut Vec<MetricsSnapshot>) {
+        let bucket =
+            (sample.timestamp.timestamp_millis() - self.start_ms).div_euclid(self.bucket_ms);
+        if self.bucket != Some(bucket) {
+            self.flush(out);
+            self.bucket = Some(bucket);
+        }
+
+        let seq = self.seq;
+        self.seq += 1;
+        // Slots: max active, min active, max bandwidth, min bandwidth. Rollup points
+        // compete with their bucket extremes rather than the averages.
+        for (index, slot) in self.extremes.iter_mut().enumerate() {
+            let value = |s: &MetricsSnapshot| {
+                let rollup = MetricsRollup::of(s);
+                let series = if index < 2 {
+                    rollup.active_sessions
+                } else {
+                    rollup.bandwidth
+                };
+                if index % 2 == 0 {
+                    series.max
+                } else {
+                    series.min
+                }
+            };
+            let replace = match slot {
+                Some((_, current)) if index % 2 == 0 => value(&sample) > value(current),
+                Some((_, current)) => value(&sample) < value(current),
+                None => true,
+            };
+            if replace {
+                *slot = Some((seq, sample.clone()));
+            }
+        }
+    }
+
+    /// Emit the bucket still being filled.
+    pub fn finish(&mut self, out: &mut Vec<MetricsSnapshot>) {
+        self.flush(out);
+        self.bucket = None;
+    }
+
+    /// Samples currently held back for the open bucket.
+    pub fn pending(&self) -> usize {
+        let mut seqs: Vec<u64> = self
+            .extremes
+            .iter()
+            .flatten()
+            .map(|(seq, _)| *seq)
+            .collect();
+        seqs.sort_unstable();
+        seqs.dedup();
+        seqs.len()
+    }
+
+    fn flush(&mut self, out: &mut Vec<MetricsSnapshot>) {
+        let mut kept: Vec<(u64, MetricsSnapshot)> =
+            self.extremes.iter_mut().filter_map(Option::take).collect();
+        kept.sort_by_key(|(seq, _)| *seq);
+        kept.dedup_by_key(|(seq, _)| *seq);
+        out.extend(kept.into_iter().map(|(_, sample)| sample));
+    }
 }
 
 /// Background task that collects metrics periodically
@@ -110,6 +726,7 @@ pub async fn start_metrics_collector(
             active_sessions: stats.active_sessions as u64,
             total_sessions: stats.total_sessions as u64,
             bandwidth: stats.total_bytes,
+            rollup: None,
         };
 
         // Add to in-memory history
@@ -132,3 +749,230 @@ pub async fn start_metrics_collector(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(offset_ms: i64, active: u64, bandwidth: u64) -> MetricsSnapshot {
+        MetricsSnapshot {
+            timestamp: DateTime::<Utc>::UNIX_EPOCH + ChronoDuration::milliseconds(offset_ms),
+            active_sessions: active,
+            total_sessions: 0,
+            bandwidth,
+            rollup: None,
+        }
+    }
+
+    #[test]
+    fn decimator_emits_each_extreme_once_in_time_order() {
+        let start = DateTime::<Utc>::UNIX_EPOCH;
+        let mut decimator = MinMaxDecimator::new(start, start + ChronoDuration::seconds(10), 4);
+        let mut out = Vec::new();
+        for (i, (active, bandwidth)) in [(5, 50), (9, 40), (1, 60), (6, 10), (5, 90), (5, 50)]
+            .into_iter()
+            .enumerate()
+        {
+            decimator.push(sample(i as i64 * 1000, active, bandwidth), &mut out);
+        }
+        assert!(out.is_empty());
+        assert_eq!(decimator.pending(), 4);
+
+        decimator.finish(&mut out);
+        let kept: Vec<(u64, u64)> = out
+            .iter()
+            .map(|s| (s.active_sessions, s.bandwidth))
+            .collect();
+        assert_eq!(kept, vec![(9, 40), (1, 60), (6, 10), (5, 90)]);
+    }
+
+    #[test]
+    fn decimator_flushes_when_a_new_bucket_starts() {
+        let start = DateTime::<Utc>::UNIX_EPOCH;
+        // Two buckets of 5s each
+        let mut decimator = MinMaxDecimator::new(start, start + ChronoDuration::seconds(10), 8);
+        let mut out = Vec::new();
+        decimator.push(sample(0, 1, 1), &mut out);
+        decimator.push(sample(4_999, 2, 2), &mut out);
+        assert!(out.is_empty());
+
+        decimator.push(sample(5_000, 3, 3), &mut out);
+        assert_eq!(out.len(), 2);
+        assert_eq!(decimator.pending(), 1);
+    }
+
+    #[test]
+    fn folder_aggregates_each_bucket() {
+        let mut folder = RollupFolder::new(MetricsResolution::OneMinute);
+        assert!(folder.push(&sample(0, 4, 100)).is_none());
+        assert!(folder.push(&sample(20_000, 1, 300)).is_none());
+        assert!(folder.push(&sample(40_000, 7, 200)).is_none());
+
+        let bucket = folder
+            .push(&sample(60_000, 2, 50))
+            .expect("first minute closed");
+        assert_eq!(bucket.timestamp, DateTime::<Utc>::UNIX_EPOCH);
+        let rollup = bucket.rollup.expect("rollup point");
+        assert_eq!(rollup.samples, 3);
+        assert_eq!(
+            (
+                rollup.active_sessions.min,
+                rollup.active_sessions.max,
+                rollup.active_sessions.sum
+            ),
+            (1, 7, 12)
+        );
+        assert_eq!(rollup.bandwidth.avg, 200.0);
+        assert_eq!((bucket.active_sessions, bucket.bandwidth), (4, 200));
+
+        // Buckets fold into coarser ones without losing the extremes
+        let mut coarse = RollupFolder::new(MetricsResolution::FifteenMinutes);
+        assert!(coarse.push(&bucket).is_none());
+        assert!(coarse.push(&folder.finish().unwrap()).is_none());
+        let merged = coarse.finish().unwrap().rollup.unwrap();
+        assert_eq!(merged.samples, 4);
+        assert_eq!((merged.bandwidth.min, merged.bandwidth.max), (50, 300));
+        assert_eq!(merged.bandwidth.avg, 162.5);
+    }
+
+    #[test]
+    fn auto_resolution_follows_the_range() {
+        let pick = |hours, raw_available, rollups| {
+            MetricsResolution::for_range(ChronoDuration::hours(hours), 1440, raw_available, rollups)
+        };
+        assert_eq!(pick(2, true, true), MetricsResolution::Raw);
+        assert_eq!(pick(24, true, true), MetricsResolution::OneMinute);
+        assert_eq!(
+            pick(30 * 24, false, true),
+            MetricsResolution::FifteenMinutes
+        );
+        // Raw samples no longer reach back to the start
+        assert_eq!(pick(2, false, true), MetricsResolution::OneMinute);
+        assert_eq!(pick(30 * 24, false, false), MetricsResolution::Raw);
+
+        assert_eq!("15m".parse(), Ok(MetricsResolution::FifteenMinutes));
+        assert!("5m".parse::<MetricsResolution>().is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_rollups_outlive_raw_samples() {
+        let history = Arc::new(MetricsHistory::new(100, 1).with_rollups(30));
+        let start =
+            MetricsResolution::OneMinute.bucket_start(Utc::now() - ChronoDuration::hours(3));
+        for i in 0..4 {
+            history
+                .add_snapshot(MetricsSnapshot {
+                    timestamp: start + ChronoDuration::seconds(30 * i),
+                    active_sessions: i as u64,
+                    total_sessions: 0,
+                    bandwidth: 0,
+                    rollup: None,
+                })
+                .await;
+        }
+        history
+            .add_snapshot(MetricsSnapshot {
+                timestamp: Utc::now(),
+                active_sessions: 9,
+                total_sessions: 0,
+                bandwidth: 0,
+                rollup: None,
+            })
+            .await;
+        let end = start + ChronoDuration::minutes(10);
+        assert!(history.chunk(start, end, None, 10).await.is_empty());
+
+        let mut cursor =
+            MetricsCursor::memory_at(history.clone(), MetricsResolution::OneMinute, start, end);
+        let buckets = cursor.next_chunk().await.unwrap();
+        let samples: Vec<u64> = buckets.iter().map(|b| b.rollup.unwrap().samples).collect();
+        assert_eq!(samples, vec![2, 2]);
+        assert!(cursor.next_chunk().await.unwrap().is_empty());
+
+        // A history without rollups folds its raw samples instead
+        let plain = Arc::new(MetricsHistory::new(100, 24));
+        for i in 0..4 {
+            plain
+                .add_snapshot(MetricsSnapshot {
+                    timestamp: start + ChronoDuration::hours(2) + ChronoDuration::seconds(30 * i),
+                    active_sessions: i as u64,
+                    total_sessions: 0,
+                    bandwidth: 0,
+                    rollup: None,
+                })
+                .await;
+        }
+        let mut folded = MetricsCursor::memory_at(
+            plain,
+            MetricsResolution::OneMinute,
+            start,
+            start + ChronoDuration::hours(3),
+        );
+        let buckets = folded.next_chunk().await.unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[1].rollup.unwrap().active_sessions.max, 3);
+    }
+
+    #[tokio::test]
+    async fn recent_throughput_uses_the_last_two_snapshots() {
+        let history = MetricsHistory::new(100, 24);
+        assert_eq!(history.recent_throughput().await, None);
+
+        let now = Utc::now();
+        for (offset, bandwidth) in [(0, 1_000), (5, 2_000), (10, 12_000)] {
+            history
+                .add_snapshot(MetricsSnapshot {
+                    timestamp: now + ChronoDuration::seconds(offset),
+                    active_sessions: 0,
+                    total_sessions: 0,
+                    bandwidth,
+                    rollup: None,
+                })
+                .await;
+        }
+        assert_eq!(history.recent_throughput().await, Some(2_000.0));
+
+        // Sessions leaving the stats window shrink the total
+        history
+            .add_snapshot(MetricsSnapshot {
+                timestamp: now + ChronoDuration::seconds(15),
+                active_sessions: 0,
+                total_sessions: 0,
+                bandwidth: 500,
+                rollup: None,
+            })
+            .await;
+        assert_eq!(history.recent_throughput().await, Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn memory_chunks_resume_after_the_last_sample() {
+        let history = MetricsHistory::new(100, 24);
+        let now = Utc::now();
+        for i in 0..10 {
+            history
+                .add_snapshot(MetricsSnapshot {
+                    timestamp: now + ChronoDuration::seconds(i),
+                    active_sessions: i as u64,
+                    total_sessions: 0,
+                    bandwidth: 0,
+                    rollup: None,
+                })
+                .await;
+        }
+
+        let end = now + ChronoDuration::seconds(7);
+        let first = history
+            .chunk(now + ChronoDuration::seconds(2), end, None, 3)
+            .await;
+        assert_eq!(
+            first.iter().map(|s| s.active_sessions).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        let rest = history.chunk(now, end, Some(first[2].timestamp), 10).await;
+        assert_eq!(
+            rest.iter().map(|s| s.active_sessions).collect::<Vec<_>>(),
+            vec![5, 6, 7]
+        );
+    }
+}
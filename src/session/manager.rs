@@ -1,23 +1,29 @@
+use super::access_log::AccessLog;
+use super::admission::{AdmissionLog, AdmissionRejection};
 #[cfg(feature = "database")]
-use super::batch::{BatchConfig, BatchWriter};
+use super::batch::{BatchConfig, BatchWriter, BatchWriterStats};
+use super::events::{SessionEvent, SessionEventKind, SessionEvents};
+use super::failures::{FailureCategory, FailureLog, FailureRecord};
 #[cfg(feature = "metrics")]
 use super::metrics::SessionMetrics;
 #[cfg(feature = "database")]
 use super::store::SessionStore;
 use super::types::{
-    AclDecisionStats, ConnectionInfo, DestinationStat, Session, SessionStats, SessionStatus,
+    AclDecisionStats, ConnectionInfo, DestinationStat, HostSource, Session, SessionCommand,
+    SessionEviction, SessionResolution, SessionStats, SessionStatus, UdpAssociationMode,
     UserSessionStat,
 };
-use crate::acl::{AclDecision, AclEngine, Protocol as AclProtocol};
+use crate::acl::{AclDecision, AclEngine, AclMode, Protocol as AclProtocol};
 use crate::protocol::Address;
 use chrono::{Duration as ChronoDuration, Utc};
 use dashmap::DashMap;
-use std::collections::HashMap;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 #[cfg(feature = "database")]
 use std::sync::OnceLock;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::{
     broadcast,
     mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
@@ -27,17 +33,42 @@ use tokio_util::sync::CancellationToken;
 use tracing::{info, warn};
 use uuid::Uuid;
 
+/// Pre-sized so the first sessions landing in each shard do not trigger a rehash.
+const INITIAL_SESSION_CAPACITY: usize = 1024;
+
+/// Close reason of sessions still open when the shutdown grace period runs out
+pub const SHUTDOWN_CLOSE_REASON: &str = "server shutdown";
+
+/// How often [`SessionManager::wait_for_idle`] checks the active session count
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// In-memory session tracker built on top of DashMap.
 ///
 /// Optimizations:
 /// - Uses RwLock instead of Mutex for closed/rejected sessions (allows concurrent reads in get_stats)
 /// - DashMap for active sessions (lock-free concurrent access)
+///
+/// Closed and rejected sessions are each capped at `memory_max_sessions`, evicting the
+/// oldest first; active sessions are never evicted.
 #[derive(Debug)]
 pub struct SessionManager {
     active_sessions: DashMap<Uuid, Arc<RwLock<Session>>>,
-    closed_sessions: RwLock<Vec<Session>>,
-    rejected_sessions: RwLock<Vec<Session>>,
+    closed_sessions: RwLock<RetainedSessions>,
+    rejected_sessions: RwLock<RetainedSessions>,
+    /// Cap of each finished-session list (0 = unbounded)
+    memory_max_sessions: usize,
     session_controls: DashMap<Uuid, SessionControl>,
+    /// Sessions scheduled for a graceful drain (see [`SessionManager::drain_sessions`])
+    draining: DashMap<Uuid, ()>,
+    /// Set once the server stopped accepting clients and waits for sessions to finish
+    shutting_down: AtomicBool,
+    /// Clients refused by connection limits before a session existed
+    admission: AdmissionLog,
+    /// Requests that ended without a tunnel, failed logins included
+    failures: FailureLog,
+    /// Live feed for `GET /api/ws`
+    events: Arc<SessionEvents>,
+    access_log: AccessLog,
     #[cfg(feature = "database")]
     store: Option<Arc<SessionStore>>,
     #[cfg(feature = "database")]
@@ -45,12 +76,60 @@ pub struct SessionManager {
     traffic_tx: UnboundedSender<TrafficUpdate>,
 }
 
+/// Finished sessions kept in memory, in the order they ended
+#[derive(Debug, Default)]
+struct RetainedSessions {
+    sessions: VecDeque<Session>,
+    eviction: SessionEviction,
+}
+
+impl RetainedSessions {
+    /// Append `session`, evicting the oldest beyond `max` (0 = unbounded)
+    fn push(&mut self, session: Session, max: usize) {
+        self.sessions.push_back(session);
+        if max == 0 {
+            return;
+        }
+        while self.sessions.len() > max {
+            let Some(oldest) = self.sessions.pop_front() else {
+                break;
+            };
+            self.eviction.record(oldest.start_time);
+        }
+    }
+
+    fn snapshot(&self) -> Vec<Session> {
+        self.sessions.iter().cloned().collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 struct SessionControl {
     cancel_token: CancellationToken,
     udp_shutdown: Option<broadcast::Sender<()>>,
 }
 
+impl SessionControl {
+    /// Stop the session's relay; dropping its streams closes both sockets
+    fn cancel(&self) {
+        self.cancel_token.cancel();
+        if let Some(tx) = &self.udp_shutdown {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// What [`SessionManager::terminate_session`] found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminateOutcome {
+    /// The session was active; its relay is cancelled and its closure recorded
+    Terminated,
+    /// The session had already ended
+    AlreadyClosed,
+    /// No session with that ID is known
+    NotFound,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct TrafficUpdate {
     session_id: Uuid,
@@ -65,10 +144,17 @@ impl SessionManager {
     pub fn new() -> Self {
         let (traffic_tx, traffic_rx) = unbounded_channel();
         let manager = Self {
-            active_sessions: DashMap::new(),
-            closed_sessions: RwLock::new(Vec::new()),
-            rejected_sessions: RwLock::new(Vec::new()),
-            session_controls: DashMap::new(),
+            active_sessions: DashMap::with_capacity(INITIAL_SESSION_CAPACITY),
+            closed_sessions: RwLock::new(RetainedSessions::default()),
+            rejected_sessions: RwLock::new(RetainedSessions::default()),
+            memory_max_sessions: 0,
+            session_controls: DashMap::with_capacity(INITIAL_SESSION_CAPACITY),
+            draining: DashMap::new(),
+            shutting_down: AtomicBool::new(false),
+            admission: AdmissionLog::default(),
+            failures: FailureLog::default(),
+            events: Arc::new(SessionEvents::default()),
+            access_log: AccessLog::new(),
             #[cfg(feature = "database")]
             store: None,
             #[cfg(feature = "database")]
@@ -84,6 +170,7 @@ impl SessionManager {
         let active_sessions = self.active_sessions.clone();
         #[cfg(feature = "database")]
         let batch_writer = self.batch_writer.clone();
+        let events = self.events.clone();
 
         tokio::spawn(async move {
             while let Some(update) = rx.recv().await {
@@ -91,6 +178,7 @@ impl SessionManager {
                     &active_sessions,
                     #[cfg(feature = "database")]
                     &batch_writer,
+                    &events,
                     update,
                 )
                 .await;
@@ -115,12 +203,24 @@ impl SessionManager {
         let _ = self.batch_writer.set(writer);
     }
 
+    /// Cap closed and rejected sessions kept in memory at `max` each (0 = unbounded).
+    pub fn set_memory_max_sessions(&mut self, max: usize) {
+        self.memory_max_sessions = max;
+    }
+
+    /// Finished sessions evicted from memory so far, closed and rejected together.
+    pub async fn eviction(&self) -> SessionEviction {
+        let mut eviction = self.closed_sessions.read().await.eviction;
+        eviction.merge(&self.rejected_sessions.read().await.eviction);
+        eviction
+    }
+
     /// Start tracking a freshly accepted session.
     pub async fn new_session(
         &self,
         user: &str,
         connection: ConnectionInfo,
-        acl_decision: impl Into<String>,
+        acl_decision: impl AsRef<str>,
         acl_rule_matched: Option<String>,
     ) -> Uuid {
         self.new_session_with_control(user, connection, acl_decision, acl_rule_matched, None)
@@ -129,16 +229,17 @@ impl SessionManager {
     }
 
     /// Start tracking a session and return its cancellation token so callers can react to shutdown.
+    ///
+    /// Passing the connection's `Arc<str>` username shares it instead of copying it.
     pub async fn new_session_with_control(
         &self,
-        user: &str,
+        user: impl Into<Arc<str>>,
         connection: ConnectionInfo,
-        acl_decision: impl Into<String>,
+        acl_decision: impl AsRef<str>,
         acl_rule_matched: Option<String>,
         udp_shutdown: Option<broadcast::Sender<()>>,
     ) -> (Uuid, CancellationToken) {
-        let mut session =
-            Session::new(user.to_string(), connection, acl_decision, acl_rule_matched);
+        let mut session = Session::new(user, connection, acl_decision, acl_rule_matched);
         session.status = SessionStatus::Active;
 
         let session_id = session.session_id;
@@ -153,22 +254,30 @@ impl SessionManager {
         );
 
         #[cfg(feature = "metrics")]
-        SessionMetrics::record_session_start(&session.user);
+        {
+            SessionMetrics::record_session_start(&session.user);
+            SessionMetrics::record_socks_version(session.socks_version);
+        }
 
         #[cfg(feature = "database")]
         if let Some(writer) = self.current_batch_writer() {
             writer.enqueue(session.clone()).await;
         }
 
+        if self.events.has_subscribers() {
+            self.events.publish(SessionEvent::started(&session));
+        }
+
         self.active_sessions
             .insert(session_id, Arc::new(RwLock::new(session)));
 
         (session_id, cancel_token)
     }
 
-    #[cfg(feature = "database")]
+    /// Close whatever is still active with [`SHUTDOWN_CLOSE_REASON`] and flush the
+    /// session store.
     pub async fn shutdown(&self) {
-        self.close_all_active("Server shutdown", SessionStatus::Failed)
+        self.close_all_active(SHUTDOWN_CLOSE_REASON, SessionStatus::Failed)
             .await;
 
         #[cfg(feature = "database")]
@@ -177,6 +286,27 @@ impl SessionManager {
         }
     }
 
+    /// Adaptive state of the session batch writer, when a persistent store is attached.
+    #[cfg(feature = "database")]
+    pub async fn batch_writer_stats(&self) -> Option<BatchWriterStats> {
+        match self.current_batch_writer() {
+            Some(writer) => Some(writer.stats().await),
+            None => None,
+        }
+    }
+
+    /// Apply reloaded `[sessions]` batch settings; `false` when no store is attached.
+    #[cfg(feature = "database")]
+    pub fn reconfigure_batch_writer(&self, config: BatchConfig) -> bool {
+        match self.current_batch_writer() {
+            Some(writer) => {
+                writer.reconfigure(config);
+                true
+            }
+            None => false,
+        }
+    }
+
     #[cfg(feature = "database")]
     fn current_batch_writer(&self) -> Option<Arc<BatchWriter>> {
         self.batch_writer.get().cloned()
@@ -219,12 +349,14 @@ impl SessionManager {
 
         // Helper closure to aggregate a single session (avoids code duplication)
         let mut aggregate_session = |user: &str,
-                                     dest_ip: &str,
+                                     destination: &str,
                                      bytes_sent: u64,
                                      bytes_received: u64,
                                      acl_decision: &str| {
             *user_counts.entry(user.to_string()).or_insert(0) += 1;
-            *destination_counts.entry(dest_ip.to_string()).or_insert(0) += 1;
+            *destination_counts
+                .entry(destination.to_string())
+                .or_insert(0) += 1;
             total_bytes = total_bytes.saturating_add(bytes_sent.saturating_add(bytes_received));
             total_sessions += 1;
 
@@ -248,7 +380,7 @@ impl SessionManager {
             if session.start_time >= cutoff {
                 aggregate_session(
                     &session.user,
-                    &session.dest_ip,
+                    session.logical_destination(),
                     session.bytes_sent,
                     session.bytes_received,
                     &session.acl_decision,
@@ -260,10 +392,10 @@ impl SessionManager {
         // Using RwLock.read() allows concurrent reads without blocking
         {
             let closed = self.closed_sessions.read().await;
-            for session in closed.iter().filter(|s| s.start_time >= cutoff) {
+            for session in closed.sessions.iter().filter(|s| s.start_time >= cutoff) {
                 aggregate_session(
                     &session.user,
-                    &session.dest_ip,
+                    session.logical_destination(),
                     session.bytes_sent,
                     session.bytes_received,
                     &session.acl_decision,
@@ -275,10 +407,10 @@ impl SessionManager {
         // Using RwLock.read() allows concurrent reads without blocking
         {
             let rejected = self.rejected_sessions.read().await;
-            for session in rejected.iter().filter(|s| s.start_time >= cutoff) {
+            for session in rejected.sessions.iter().filter(|s| s.start_time >= cutoff) {
                 aggregate_session(
                     &session.user,
-                    &session.dest_ip,
+                    session.logical_destination(),
                     session.bytes_sent,
                     session.bytes_received,
                     &session.acl_decision,
@@ -313,17 +445,22 @@ impl SessionManager {
         });
         top_destinations.truncate(TOP_LIMIT);
 
+        let eviction = self.eviction().await;
+
         SessionStats {
             generated_at: now,
             active_sessions: active_count,
             total_sessions,
             total_bytes,
+            truncated: eviction.truncates(cutoff),
+            evicted_sessions: eviction.evicted,
             top_users,
             top_destinations,
             acl: AclDecisionStats {
                 allowed: acl_allowed,
                 blocked: acl_blocked,
             },
+            admission: self.admission.stats(),
         }
     }
 
@@ -340,6 +477,7 @@ impl SessionManager {
             &self.active_sessions,
             #[cfg(feature = "database")]
             &self.batch_writer,
+            &self.events,
             TrafficUpdate {
                 session_id: *session_id,
                 bytes_sent,
@@ -379,6 +517,7 @@ impl SessionManager {
     async fn apply_traffic_update(
         active_sessions: &DashMap<Uuid, Arc<RwLock<Session>>>,
         #[cfg(feature = "database")] batch_writer: &OnceLock<Arc<BatchWriter>>,
+        events: &SessionEvents,
         update: TrafficUpdate,
     ) {
         if let Some(entry) = active_sessions.get(&update.session_id) {
@@ -398,10 +537,19 @@ impl SessionManager {
             session_guard.packets_received = session_guard
                 .packets_received
                 .saturating_add(update.packets_received);
+            session_guard.transfer_rate.record(
+                Instant::now(),
+                update.bytes_sent,
+                update.bytes_received,
+            );
 
             #[cfg(feature = "metrics")]
             SessionMetrics::record_traffic(&user_label, update.bytes_sent, update.bytes_received);
 
+            if events.has_subscribers() && events.traffic_due(update.session_id) {
+                events.publish(SessionEvent::traffic(&session_guard));
+            }
+
             #[cfg(feature = "database")]
             if let Some(writer) = Self::clone_batch_writer_handle(batch_writer) {
                 let snapshot = session_guard.clone();
@@ -414,6 +562,133 @@ impl SessionManager {
         }
     }
 
+    /// Attach the TLS server name observed on an active session.
+    pub async fn set_sni_host(&self, session_id: &Uuid, sni_host: &str) {
+        if let Some(handle) = self.get_session(session_id) {
+            let mut session = handle.write().await;
+            session.sni_host = Some(Arc::from(sni_host));
+            session.offer_requested_host(sni_host, HostSource::Sni);
+        }
+    }
+
+    /// Count a keepalive probe armed on an idle tunnel.
+    pub async fn record_keepalive_probe(&self, session_id: &Uuid) {
+        if let Some(handle) = self.get_session(session_id) {
+            let mut session = handle.write().await;
+            session.keepalive_probes = session.keepalive_probes.saturating_add(1);
+        }
+    }
+
+    /// Record how a UDP association matches client datagrams, and the client endpoint
+    /// once it is known.
+    pub async fn set_udp_association(
+        &self,
+        session_id: &Uuid,
+        mode: UdpAssociationMode,
+        endpoint: Option<SocketAddr>,
+    ) {
+        if let Some(handle) = self.get_session(session_id) {
+            let mut session = handle.write().await;
+            session.udp_association_mode = Some(mode);
+            session.udp_client_endpoint = endpoint;
+        }
+    }
+
+    /// Record the local address a session's upstream traffic leaves from.
+    pub async fn set_egress_ip(&self, session_id: &Uuid, ip: IpAddr) {
+        if let Some(handle) = self.get_session(session_id) {
+            handle.write().await.egress_ip = Some(ip);
+        }
+    }
+
+    /// Record the addresses an active session's destination resolved to and connected
+    /// to, and what the ACL matched on.
+    pub async fn set_resolution(&self, session_id: &Uuid, resolution: SessionResolution) {
+        if let Some(handle) = self.get_session(session_id) {
+            resolution.apply(&mut *handle.write().await);
+        }
+    }
+
+    /// Record the SOCKS command of a session whose transport alone does not tell it.
+    pub async fn set_command(&self, session_id: &Uuid, command: SessionCommand) {
+        if let Some(handle) = self.get_session(session_id) {
+            handle.write().await.command = command;
+        }
+    }
+
+    /// Count a datagram a UDP association dropped for coming from an unknown source.
+    pub async fn record_udp_rejected_datagram(&self, session_id: &Uuid) {
+        if let Some(handle) = self.get_session(session_id) {
+            let mut session = handle.write().await;
+            session.udp_rejected_datagrams = session.udp_rejected_datagrams.saturating_add(1);
+        }
+    }
+
+    /// Count a datagram a UDP association dropped for exceeding the QoS bandwidth limits.
+    pub async fn record_udp_throttled_datagram(&self, session_id: &Uuid) {
+        if let Some(handle) = self.get_session(session_id) {
+            let mut session = handle.write().await;
+            session.udp_throttled_datagrams = session.udp_throttled_datagrams.saturating_add(1);
+        }
+    }
+
+    /// Offer a requested hostname for an active session; ignored when a more
+    /// authoritative source already supplied one.
+    pub async fn set_requested_host(
+        &self,
+        session_id: &Uuid,
+        host: &str,
+        source: HostSource,
+    ) -> bool {
+        match self.get_session(session_id) {
+            Some(handle) => handle.write().await.offer_requested_host(host, source),
+            None => false,
+        }
+    }
+
+    /// Mark an active session as relayed only because the ACL runs in monitor mode.
+    pub async fn mark_would_block(&self, session_id: &Uuid) {
+        if let Some(handle) = self.get_session(session_id) {
+            handle.write().await.would_block = true;
+        }
+    }
+
+    /// Mark an active session whose upstream connection the proxy wrapped in TLS.
+    pub async fn mark_upstream_tls(&self, session_id: &Uuid) {
+        if let Some(handle) = self.get_session(session_id) {
+            handle.write().await.upstream_tls = true;
+        }
+    }
+
+    /// Close an active session that an ACL stage blocked after it was established.
+    pub async fn reject_active_session(
+        &self,
+        session_id: &Uuid,
+        acl_rule: Option<String>,
+        reason: impl Into<String>,
+    ) {
+        if let Some(handle) = self.get_session(session_id) {
+            let mut session = handle.write().await;
+            session.acl_decision = Arc::from("block");
+            session.acl_rule_matched = acl_rule.map(Arc::from);
+            // The client was already told the connect succeeded, so the reply stays 0
+            session.failure = Some(FailureCategory::AclBlock);
+            self.record_session_failure(&session);
+            if self.events.has_subscribers() {
+                self.events.publish(SessionEvent::acl_blocked(&session));
+            }
+            #[cfg(feature = "metrics")]
+            SessionMetrics::record_rejected_session(&session.user);
+        }
+
+        self.close_session(
+            session_id,
+            Some(reason.into()),
+            SessionStatus::RejectedByAcl,
+        )
+        .await;
+    }
+
     /// Close an active session and record it in the closed list.
     pub async fn close_session(
         &self,
@@ -421,61 +696,146 @@ impl SessionManager {
         reason: Option<String>,
         status: SessionStatus,
     ) {
+        self.close_active(session_id, reason, status).await;
+    }
+
+    /// [`Self::close_session`], returning false when the session was not active
+    async fn close_active(
+        &self,
+        session_id: &Uuid,
+        reason: Option<String>,
+        status: SessionStatus,
+    ) -> bool {
         self.session_controls.remove(session_id);
+        self.draining.remove(session_id);
 
         if let Some((_, session_arc)) = self.active_sessions.remove(session_id) {
-            let mut session = session_arc.write().await;
-            session.close(reason, status);
+            // The map held the only long-lived handle, so the session can usually be moved
+            // out instead of cloned; the traffic worker may still hold a transient one.
+            let snapshot = match Arc::try_unwrap(session_arc) {
+                Ok(lock) => {
+                    let mut session = lock.into_inner();
+                    session.close(reason, status);
+                    session
+                }
+                Err(session_arc) => {
+                    let mut session = session_arc.write().await;
+                    session.close(reason, status);
+                    session.clone()
+                }
+            };
             #[cfg(feature = "metrics")]
-            SessionMetrics::record_session_close(session.duration_secs);
-            let snapshot = session.clone();
-            drop(session);
+            SessionMetrics::record_session_close(snapshot.duration_secs);
 
-            // Use write lock for appending to closed sessions
-            // RwLock reduces contention compared to Mutex for read-heavy workloads
-            self.closed_sessions.write().await.push(snapshot.clone());
+            self.events.forget(*session_id);
+            if self.events.has_subscribers() {
+                self.events.publish(SessionEvent::closed(&snapshot));
+            }
 
             #[cfg(feature = "database")]
             if let Some(writer) = self.current_batch_writer() {
-                writer.enqueue(snapshot).await;
+                writer.enqueue(snapshot.clone()).await;
             }
+
+            // Use write lock for appending to closed sessions
+            // RwLock reduces contention compared to Mutex for read-heavy workloads
+            self.closed_sessions
+                .write()
+                .await
+                .push(snapshot, self.memory_max_sessions);
+            true
+        } else {
+            false
         }
     }
 
     /// Terminate an active session by cancelling underlying IO and recording closure.
+    ///
+    /// The closure is recorded before the relay is cancelled, so the reason given here
+    /// is the one kept rather than whatever the relay reports as it unwinds.
     pub async fn terminate_session(
         &self,
         session_id: &Uuid,
         reason: impl Into<String>,
         status: SessionStatus,
-    ) {
-        if let Some(control) = self.session_controls.get(session_id) {
-            control.cancel_token.cancel();
-            if let Some(tx) = &control.udp_shutdown {
-                let _ = tx.send(());
-            }
+    ) -> TerminateOutcome {
+        let control = self
+            .session_controls
+            .get(session_id)
+            .map(|control| control.clone());
+
+        if !self
+            .close_active(session_id, Some(reason.into()), status)
+            .await
+        {
+            let closed = self
+                .closed_sessions
+                .read()
+                .await
+                .sessions
+                .iter()
+                .any(|session| session.session_id == *session_id);
+            return if closed {
+                TerminateOutcome::AlreadyClosed
+            } else {
+                TerminateOutcome::NotFound
+            };
         }
 
-        self.close_session(session_id, Some(reason.into()), status)
-            .await;
+        if let Some(control) = control {
+            control.cancel();
+        }
+        TerminateOutcome::Terminated
     }
 
-    /// Record a connection rejected before session creation (e.g., ACL block).
+    /// Record a connection rejected before session creation (e.g., ACL block), answered
+    /// with the SOCKS5 `reply_code`.
     pub async fn track_rejected_session(
         &self,
         user: &str,
         conn: ConnectionInfo,
         acl_rule: Option<String>,
+        reply_code: u8,
+    ) -> Uuid {
+        self.track_rejected_session_with(
+            user,
+            conn,
+            acl_rule,
+            reply_code,
+            SessionResolution::default(),
+        )
+        .await
+    }
+
+    /// [`Self::track_rejected_session`] recording what the ACL matched on and, for a
+    /// block on a resolved address, what the destination resolved to.
+    pub async fn track_rejected_session_with(
+        &self,
+        user: &str,
+        conn: ConnectionInfo,
+        acl_rule: Option<String>,
+        reply_code: u8,
+        resolution: SessionResolution,
     ) -> Uuid {
-        let mut session = Session::new(user.to_string(), conn, "block", acl_rule);
+        let mut session = Session::new(user, conn, "block", acl_rule);
+        resolution.apply(&mut session);
+        session.reply_code = Some(reply_code);
+        session.failure = Some(FailureCategory::AclBlock);
 
         session.close(
             Some("Rejected by ACL".to_string()),
             SessionStatus::RejectedByAcl,
         );
+        self.record_session_failure(&session);
+        if self.events.has_subscribers() {
+            self.events.publish(SessionEvent::acl_blocked(&session));
+        }
 
         #[cfg(feature = "metrics")]
-        SessionMetrics::record_rejected_session(user);
+        {
+            SessionMetrics::record_rejected_session(user);
+            SessionMetrics::record_socks_version(session.socks_version);
+        }
 
         let session_id = session.session_id;
 
@@ -485,19 +845,124 @@ impl SessionManager {
         }
 
         // Use write lock for appending to rejected sessions
-        self.rejected_sessions.write().await.push(session);
+        self.rejected_sessions
+            .write()
+            .await
+            .push(session, self.memory_max_sessions);
 
         session_id
     }
 
+    /// Record a request the ACL allowed but that failed before the relay started (the
+    /// destination did not resolve or the upstream connect failed).
+    #[allow(clippy::too_many_arguments)]
+    pub async fn track_failed_session(
+        &self,
+        user: &str,
+        conn: ConnectionInfo,
+        acl_decision: &str,
+        acl_rule: Option<String>,
+        failure: FailureCategory,
+        reply_code: u8,
+        reason: String,
+        resolution: SessionResolution,
+    ) -> Uuid {
+        let mut session = Session::new(user, conn, acl_decision, acl_rule);
+        resolution.apply(&mut session);
+        session.reply_code = Some(reply_code);
+        session.failure = Some(failure);
+        session.close(Some(reason), SessionStatus::Failed);
+        self.record_session_failure(&session);
+
+        #[cfg(feature = "metrics")]
+        SessionMetrics::record_socks_version(session.socks_version);
+
+        let session_id = session.session_id;
+
+        #[cfg(feature = "database")]
+        if let Some(writer) = self.current_batch_writer() {
+            writer.enqueue(session.clone()).await;
+        }
+
+        self.closed_sessions
+            .write()
+            .await
+            .push(session, self.memory_max_sessions);
+
+        session_id
+    }
+
+    fn record_session_failure(&self, session: &Session) {
+        if let Some(category) = session.failure {
+            self.failures.record(FailureRecord {
+                timestamp: session.end_time.unwrap_or(session.start_time),
+                category,
+                reply_code: session.reply_code,
+                user: Some(session.user.to_string()),
+                source_ip: session.source_ip.to_string(),
+                destination: Some(format!("{}:{}", session.dest_ip, session.dest_port)),
+                session_id: Some(session.session_id),
+            });
+        }
+    }
+
+    /// Report that QoS made an active session wait for bandwidth in `dimension`
+    /// ("global", "ip" or "user"), or dropped one of its datagrams. Published at most
+    /// once per session per live traffic interval.
+    pub async fn record_qos_throttle(&self, session_id: &Uuid, dimension: &str, dropped: bool) {
+        if !self.events.has_subscribers() || !self.events.throttle_due(*session_id) {
+            return;
+        }
+        if let Some(handle) = self.get_session(session_id) {
+            let session = handle.read().await;
+            self.events.publish(SessionEvent::new(
+                &session,
+                SessionEventKind::QosThrottled {
+                    dimension: dimension.to_string(),
+                    dropped,
+                },
+            ));
+        }
+    }
+
+    /// Record a client turned away by a connection limit (see [`AdmissionLog`]) and
+    /// publish it on the live session events.
+    pub async fn record_admission_rejection(&self, rejection: AdmissionRejection) {
+        if self.events.has_subscribers() {
+            self.events
+                .publish(SessionEvent::admission_rejected(&rejection));
+        }
+        self.admission.record(rejection).await;
+    }
+
+    /// Live session events (see [`SessionEvents`]).
+    pub fn events(&self) -> &SessionEvents {
+        &self.events
+    }
+
+    /// Requests that failed, by category (see [`FailureLog`]).
+    pub fn failures(&self) -> &FailureLog {
+        &self.failures
+    }
+
+    /// Connection-limit rejections (recent log, live feed and counters).
+    pub fn admission(&self) -> &AdmissionLog {
+        &self.admission
+    }
+
+    /// Access-log writer for ACL decisions.
+    pub fn access_log(&self) -> &AccessLog {
+        &self.access_log
+    }
+
     /// Snapshot of all rejected sessions (testing/diagnostics).
     pub async fn rejected_snapshot(&self) -> Vec<Session> {
-        self.rejected_sessions.read().await.clone()
+        self.rejected_sessions.read().await.snapshot()
     }
 
     /// Snapshot of closed sessions (testing/diagnostics).
     pub async fn closed_snapshot(&self) -> Vec<Session> {
-        self.closed_sessions.read().await.clone()
+        self.closed_sessions.read().await.snapshot()
     }
 
     /// Close all active sessions with a common reason/status (e.g., server shutdown).
@@ -515,6 +980,108 @@ impl SessionManager {
         }
     }
 
+    /// Terminate every active session whose user or authenticating principal is `user`.
+    ///
+    /// Returns the IDs of the sessions closed.
+    pub async fn terminate_user_sessions(&self, user: &str, reason: &str) -> Vec<Uuid> {
+        let mut session_ids = Vec::new();
+        for entry in self.active_sessions.iter() {
+            let session = entry.value().read().await;
+            if &*session.user == user || session.authenticated_user.as_deref() == Some(user) {
+                session_ids.push(session.session_id);
+            }
+        }
+
+        let mut terminated = Vec::with_capacity(session_ids.len());
+        for session_id in session_ids {
+            let outcome = self
+                .terminate_session(&session_id, reason, SessionStatus::Closed)
+                .await;
+            if outcome == TerminateOutcome::Terminated {
+                terminated.push(session_id);
+            }
+        }
+        terminated
+    }
+
+    /// Schedule active sessions for a graceful drain.
+    ///
+    /// The sessions keep relaying for `grace` so clients can finish in-flight work, then
+    /// the ones still open are terminated with `reason` (e.g. "upstream unhealthy") and
+    /// status `Closed`, prompting clients to reconnect. Returns how many sessions were
+    /// newly scheduled; unknown or already draining sessions are skipped.
+    pub fn drain_sessions(
+        self: &Arc<Self>,
+        session_ids: &[Uuid],
+        grace: Duration,
+        reason: &str,
+    ) -> usize {
+        let scheduled: Vec<Uuid> = session_ids
+            .iter()
+            .filter(|id| self.active_sessions.contains_key(id))
+            .filter(|id| self.draining.insert(**id, ()).is_none())
+            .copied()
+            .collect();
+
+        if scheduled.is_empty() {
+            return 0;
+        }
+
+        info!(
+            count = scheduled.len(),
+            grace_ms = grace.as_millis() as u64,
+            reason,
+            "Draining sessions"
+        );
+
+        let count = scheduled.len();
+        let manager = Arc::clone(self);
+        let reason = reason.to_string();
+        tokio::spawn(async move {
+            tokio::time::sleep(grace).await;
+            for session_id in scheduled {
+                // Sessions that ended on their own during the grace period are gone already
+                if manager.draining.remove(&session_id).is_some() {
+                    manager
+                        .terminate_session(&session_id, reason.clone(), SessionStatus::Closed)
+                        .await;
+                }
+            }
+        });
+
+        count
+    }
+
+    /// Mark the server as draining for shutdown; reported by the health endpoint.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the server stopped accepting clients and is waiting for sessions to end.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// Wait until no session is active or `deadline` passes; `true` when all ended.
+    pub async fn wait_for_idle(&self, deadline: Duration) -> bool {
+        let wait = async {
+            while !self.active_sessions.is_empty() {
+                tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(deadline, wait).await.is_ok()
+    }
+
+    /// Whether the session is waiting for its drain deadline.
+    pub fn is_draining(&self, session_id: &Uuid) -> bool {
+        self.draining.contains_key(session_id)
+    }
+
+    /// Number of active sessions waiting for their drain deadline.
+    pub fn pending_drain_count(&self) -> usize {
+        self.draining.len()
+    }
+
     /// Re-evaluate all active sessions against the provided ACL engine and terminate those that are no longer allowed.
     pub async fn enforce_acl(&self, acl_engine: Arc<AclEngine>) {
         let mut to_terminate = Vec::new();
@@ -529,7 +1096,7 @@ impl SessionManager {
             } else if let Ok(ipv6) = session.dest_ip.parse::<Ipv6Addr>() {
                 Address::IPv6(ipv6.octets())
             } else {
-                Address::Domain(session.dest_ip.to_string())
+                Address::Domain(session.dest_ip.as_ref().into())
             };
 
             let acl_protocol = match session.protocol {
@@ -537,10 +1104,31 @@ impl SessionManager {
                 super::types::Protocol::Udp => AclProtocol::Udp,
             };
 
-            let (decision, matched_rule) = acl_engine
-                .evaluate(&session.user, &address, session.dest_port, &acl_protocol)
+            let (mut decision, mut matched_rule) = acl_engine
+                .evaluate(
+                    &session.user,
+                    &address,
+                    session.dest_port,
+                    &acl_protocol,
+                    Some(session.source_ip),
+                )
                 .await;
 
+            // SNI-classified sessions must also pass the name-based stage (strictest wins)
+            if decision == AclDecision::Allow {
+                if let Some(sni_host) = session.sni_host.as_deref() {
+                    (decision, matched_rule) = acl_engine
+                        .evaluate(
+                            &session.user,
+                            &Address::Domain(sni_host.into()),
+                            session.dest_port,
+                            &acl_protocol,
+                            Some(session.source_ip),
+                        )
+                        .await;
+                }
+            }
+
             if decision == AclDecision::Block {
                 let rule_desc = matched_rule.unwrap_or_else(|| "Default policy".to_string());
                 let reason = format!("Terminated by ACL update ({})", rule_desc);
@@ -554,6 +1142,26 @@ impl SessionManager {
             }
         }
 
+        if acl_engine.mode() == AclMode::Monitor {
+            if !to_terminate.is_empty() {
+                info!(
+                    count = to_terminate.len(),
+                    "ACL update would revoke sessions (monitor mode)"
+                );
+            }
+            for (session_id, _, user, dest, port) in to_terminate {
+                warn!(
+                    %session_id,
+                    user = %user,
+                    dest = %dest,
+                    port,
+                    "ACL update would close session (monitor mode)"
+                );
+                self.mark_would_block(&session_id).await;
+            }
+            return;
+        }
+
         if !to_terminate.is_empty() {
             info!(
                 count = to_terminate.len(),
@@ -585,10 +1193,10 @@ impl SessionManager {
         }
 
         // Add closed sessions
-        all.extend(self.closed_sessions.read().await.clone());
+        all.extend(self.closed_sessions.read().await.snapshot());
 
         // Add rejected sessions
-        all.extend(self.rejected_sessions.read().await.clone());
+        all.extend(self.rejected_sessions.read().await.snapshot());
 
         all
     }
@@ -603,9 +1211,20 @@ impl SessionManager {
         sessions
     }
 
+    /// IDs of the active sessions tunnelled through the parent proxy (`server.upstream`)
+    pub async fn chained_session_ids(&self) -> Vec<Uuid> {
+        let mut ids = Vec::new();
+        for entry in self.active_sessions.iter() {
+            if entry.value().read().await.chained {
+                ids.push(*entry.key());
+            }
+        }
+        ids
+    }
+
     /// Get closed sessions only
     pub async fn get_closed_sessions(&self) -> Vec<Session> {
-        self.closed_sessions.read().await.clone()
+        self.closed_sessions.read().await.snapshot()
     }
 
     #[cfg(feature = "database")]
@@ -624,9 +1243,10 @@ impl Default for SessionManager {
 mod tests {
     use super::*;
     use crate::acl::types::{
-        AclConfig, AclRule, Action, GlobalAclConfig, Protocol as AclAclProtocol, UserAcl,
+        AclConfig, AclRule, Action, GlobalAclConfig, Protocol as AclAclProtocol, RuleLogLevel,
+        UserAcl,
     };
-    use crate::acl::AclEngine;
+    use crate::acl::{AclEngine, AclMatchedOn};
     use crate::session::types::Protocol;
     use std::collections::HashMap;
     use std::net::{IpAddr, Ipv4Addr};
@@ -649,9 +1269,14 @@ mod tests {
         ConnectionInfo {
             source_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
             source_port: 50000,
-            dest_ip: "example.com".to_string(),
+            dest_ip: "example.com".into(),
             dest_port: 443,
             protocol: Protocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         }
     }
 
@@ -693,9 +1318,14 @@ mod tests {
             dest_ip: "blocked.example.com".into(),
             dest_port: 80,
             protocol: Protocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
         let session_id = manager
-            .track_rejected_session("bob", conn, Some("Block admin".into()))
+            .track_rejected_session("bob", conn, Some("Block admin".into()), 0x02)
             .await;
 
         assert_ne!(session_id, Uuid::nil());
@@ -705,6 +1335,123 @@ mod tests {
         assert_eq!(rejected[0].status, SessionStatus::RejectedByAcl);
         assert_eq!(rejected[0].acl_decision.as_ref(), "block");
         assert_eq!(rejected[0].acl_rule_matched.as_deref(), Some("Block admin"));
+        assert_eq!(rejected[0].reply_code, Some(0x02));
+        assert_eq!(rejected[0].failure, Some(FailureCategory::AclBlock));
+
+        let summary = manager
+            .failures()
+            .summary(chrono::Utc::now() - chrono::Duration::minutes(1), 10);
+        assert_eq!(summary.by_category[&FailureCategory::AclBlock], 1);
+        assert_eq!(
+            summary.top_destinations[0].destination,
+            "blocked.example.com:80"
+        );
+    }
+
+    #[tokio::test]
+    async fn track_failed_session() {
+        let manager = SessionManager::new();
+
+        let session_id = manager
+            .track_failed_session(
+                "alice",
+                sample_connection(),
+                "allow",
+                None,
+                FailureCategory::ConnectRefused,
+                0x04,
+                "Connection refused".to_string(),
+                SessionResolution {
+                    resolved_ips: vec!["192.0.2.7".parse().unwrap()],
+                    connected_ip: None,
+                    acl_matched_on: Some(AclMatchedOn::DefaultPolicy),
+                },
+            )
+            .await;
+
+        assert_eq!(manager.active_session_count(), 0);
+        let closed = manager.closed_snapshot().await;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].session_id, session_id);
+        assert_eq!(closed[0].status, SessionStatus::Failed);
+        assert_eq!(closed[0].reply_code, Some(0x04));
+        assert_eq!(closed[0].failure, Some(FailureCategory::ConnectRefused));
+        assert_eq!(
+            closed[0].resolved_ips,
+            ["192.0.2.7".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(closed[0].acl_matched_on, Some(AclMatchedOn::DefaultPolicy));
+        assert_eq!(
+            manager.failures().totals()[&FailureCategory::ConnectRefused],
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn closed_sessions_are_capped_and_active_ones_survive() {
+        let mut manager = SessionManager::new();
+        manager.set_memory_max_sessions(10);
+
+        let mut long_lived = Vec::new();
+        for _ in 0..5 {
+            long_lived.push(
+                manager
+                    .new_session("alice", sample_connection(), "allow", None)
+                    .await,
+            );
+        }
+
+        let mut closed_ids = Vec::new();
+        for _ in 0..25 {
+            let id = manager
+                .new_session("bob", sample_connection(), "allow", None)
+                .await;
+            manager
+                .close_session(&id, None, SessionStatus::Closed)
+                .await;
+            closed_ids.push(id);
+        }
+
+        // The 10 most recently closed are kept, oldest first
+        let closed = manager.closed_snapshot().await;
+        assert_eq!(closed.len(), 10);
+        let kept: Vec<Uuid> = closed.iter().map(|s| s.session_id).collect();
+        assert_eq!(kept, closed_ids[15..]);
+
+        assert_eq!(manager.active_session_count(), 5);
+        for id in &long_lived {
+            assert!(manager.get_session(id).is_some());
+        }
+        assert_eq!(manager.get_all_sessions().await.len(), 15);
+
+        let eviction = manager.eviction().await;
+        assert_eq!(eviction.evicted, 15);
+        let stats = manager.get_stats(Duration::from_secs(3600)).await;
+        assert!(stats.truncated);
+        assert_eq!(stats.evicted_sessions, 15);
+        assert_eq!(stats.total_sessions, 15);
+
+        // Evictions older than the window leave it complete
+        let later = eviction.newest_evicted_start.unwrap() + ChronoDuration::seconds(1);
+        assert!(!eviction.truncates(later));
+
+        // Without a cap nothing is evicted
+        let unbounded = SessionManager::new();
+        for _ in 0..25 {
+            let id = unbounded
+                .new_session("bob", sample_connection(), "allow", None)
+                .await;
+            unbounded
+                .close_session(&id, None, SessionStatus::Closed)
+                .await;
+        }
+        assert_eq!(unbounded.closed_snapshot().await.len(), 25);
+        assert!(
+            !unbounded
+                .get_stats(Duration::from_secs(3600))
+                .await
+                .truncated
+        );
     }
 
     #[tokio::test]
@@ -736,9 +1483,14 @@ mod tests {
             dest_ip: "blocked.internal".into(),
             dest_port: 8080,
             protocol: Protocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
         manager
-            .track_rejected_session("carol", conn_carol, Some("Block admin".into()))
+            .track_rejected_session("carol", conn_carol, Some("Block admin".into()), 0x02)
             .await;
 
         let stats = manager.get_stats(Duration::from_secs(24 * 3600)).await;
@@ -795,6 +1547,39 @@ mod tests {
         assert!(closed[0].duration_secs.is_some());
     }
 
+    #[tokio::test]
+    async fn terminate_user_sessions_cancels_only_that_users_sessions() {
+        let manager = SessionManager::new();
+        let (first, first_token) = manager
+            .new_session_with_control("alice", sample_connection(), "allow", None, None)
+            .await;
+        let mut impersonating = sample_connection();
+        impersonating.authenticated_user = Some("alice".into());
+        let (second, _token) = manager
+            .new_session_with_control("svc", impersonating, "allow", None, None)
+            .await;
+        let (other, other_token) = manager
+            .new_session_with_control("bob", sample_connection(), "allow", None, None)
+            .await;
+
+        let mut terminated = manager
+            .terminate_user_sessions("alice", "Terminated by admin")
+            .await;
+        terminated.sort();
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_eq!(terminated, expected);
+
+        assert!(first_token.is_cancelled());
+        assert!(!other_token.is_cancelled());
+        assert_eq!(manager.active_session_count(), 1);
+        assert!(manager.get_session(&other).is_some());
+        assert!(manager
+            .terminate_user_sessions("alice", "Terminated by admin")
+            .await
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn enforce_acl_revokes_blocked_session() {
         let manager = SessionManager::new();
@@ -814,8 +1599,13 @@ mod tests {
                     description: "Allow all".into(),
                     destinations: vec!["0.0.0.0/0".into()],
                     ports: vec!["*".into()],
+                    sources: vec![],
                     protocols: vec![AclAclProtocol::Tcp],
                     priority: 10,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
             groups: vec![],
@@ -841,8 +1631,13 @@ mod tests {
                     description: "Block test dest".into(),
                     destinations: vec!["10.42.0.10".into()],
                     ports: vec!["443".into()],
+                    sources: vec![],
                     protocols: vec![AclAclProtocol::Tcp],
                     priority: 500,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
             groups: vec![],
@@ -862,6 +1657,255 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn enforce_acl_in_monitor_mode_marks_instead_of_revoking() {
+        let manager = SessionManager::new();
+        let block_all = AclConfig {
+            global: GlobalAclConfig {
+                default_policy: Action::Block,
+            },
+            users: vec![],
+            groups: vec![],
+        };
+        let engine = Arc::new(
+            AclEngine::new(block_all)
+                .expect("engine")
+                .with_mode(AclMode::Monitor),
+        );
+
+        let (session_id, _token) = manager
+            .new_session_with_control("alice", sample_connection(), "allow", None, None)
+            .await;
+        manager.enforce_acl(engine).await;
+
+        assert_eq!(manager.active_session_count(), 1);
+        let session = manager.get_session(&session_id).unwrap();
+        assert!(session.read().await.would_block);
+    }
+
+    #[tokio::test]
+    async fn enforce_acl_rechecks_sni_host() {
+        let manager = SessionManager::new();
+        let mut conn = sample_connection();
+        conn.dest_ip = "10.42.0.20".into();
+        conn.dest_port = 443;
+
+        let (classified_id, _token) = manager
+            .new_session_with_control("alice", conn.clone(), "allow", None, None)
+            .await;
+        manager
+            .set_sni_host(&classified_id, "blocked-site.com")
+            .await;
+        let (plain_id, _token) = manager
+            .new_session_with_control("alice", conn, "allow", None, None)
+            .await;
+
+        // The dialled IP stays allowed; only the SNI name matches the new block rule
+        let block_sni = AclConfig {
+            global: GlobalAclConfig {
+                default_policy: Action::Allow,
+            },
+            users: vec![UserAcl {
+                username: "alice".into(),
+                groups: vec![],
+                rules: vec![AclRule {
+                    action: Action::Block,
+                    description: "Block by SNI".into(),
+                    destinations: vec!["blocked-site.com".into()],
+                    ports: vec!["*".into()],
+                    sources: vec![],
+                    protocols: vec![AclAclProtocol::Tcp],
+                    priority: 500,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
+                }],
+            }],
+            groups: vec![],
+        };
+        let engine = Arc::new(AclEngine::new(block_sni).expect("engine"));
+
+        manager.enforce_acl(engine).await;
+
+        assert_eq!(manager.active_session_count(), 1);
+        assert!(manager.get_session(&plain_id).is_some());
+        let closed = manager.closed_snapshot().await;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].session_id, classified_id);
+        assert_eq!(closed[0].sni_host.as_deref(), Some("blocked-site.com"));
+        assert_eq!(
+            closed[0].close_reason.as_deref(),
+            Some("Terminated by ACL update (Block by SNI)"),
+        );
+    }
+
+    #[tokio::test]
+    async fn requested_host_keeps_the_most_authoritative_source() {
+        let manager = SessionManager::new();
+
+        // Domain CONNECTs are named by the client itself; SNI cannot override that
+        let (domain_id, _token) = manager
+            .new_session_with_control("alice", sample_connection(), "allow", None, None)
+            .await;
+        manager.set_sni_host(&domain_id, "cdn.example.net").await;
+        let session = manager.get_session(&domain_id).unwrap();
+        let session = session.read().await;
+        assert_eq!(session.requested_host.as_deref(), Some("example.com"));
+        assert_eq!(
+            session.requested_host_source,
+            Some(HostSource::SocksRequest)
+        );
+        assert_eq!(session.sni_host.as_deref(), Some("cdn.example.net"));
+        drop(session);
+
+        let mut conn = sample_connection();
+        conn.dest_ip = "192.0.2.10".into();
+        let (ip_id, _token) = manager
+            .new_session_with_control("alice", conn, "allow", None, None)
+            .await;
+        assert_eq!(
+            manager
+                .get_session(&ip_id)
+                .unwrap()
+                .read()
+                .await
+                .requested_host,
+            None
+        );
+
+        assert!(
+            manager
+                .set_requested_host(&ip_id, "mapped.example", HostSource::ReverseMap)
+                .await
+        );
+        assert!(
+            manager
+                .set_requested_host(&ip_id, "resolved.example", HostSource::ResolveExtension)
+                .await
+        );
+        manager.set_sni_host(&ip_id, "tls.example").await;
+        assert!(
+            !manager
+                .set_requested_host(&ip_id, "late.example", HostSource::ReverseMap)
+                .await
+        );
+
+        let session = manager.get_session(&ip_id).unwrap();
+        let session = session.read().await;
+        assert_eq!(session.requested_host.as_deref(), Some("tls.example"));
+        assert_eq!(session.requested_host_source, Some(HostSource::Sni));
+        assert_eq!(session.logical_destination(), "tls.example");
+    }
+
+    #[tokio::test]
+    async fn drain_closes_sessions_after_grace_period() {
+        let manager = Arc::new(SessionManager::new());
+        let (draining_id, token) = manager
+            .new_session_with_control("alice", sample_connection(), "allow", None, None)
+            .await;
+        let (healthy_id, _healthy_token) = manager
+            .new_session_with_control("alice", sample_connection(), "allow", None, None)
+            .await;
+
+        let scheduled = manager.drain_sessions(
+            &[draining_id, draining_id, Uuid::new_v4()],
+            Duration::from_millis(50),
+            "upstream unhealthy",
+        );
+        assert_eq!(scheduled, 1);
+        assert_eq!(manager.pending_drain_count(), 1);
+        assert!(manager.is_draining(&draining_id));
+
+        // Still relaying during the grace period
+        assert!(manager.get_session(&draining_id).is_some());
+        assert!(!token.is_cancelled());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert!(token.is_cancelled());
+        assert_eq!(manager.pending_drain_count(), 0);
+        assert!(manager.get_session(&healthy_id).is_some());
+        let closed = manager.closed_snapshot().await;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].session_id, draining_id);
+        assert_eq!(closed[0].status, SessionStatus::Closed);
+        assert_eq!(
+            closed[0].close_reason.as_deref(),
+            Some("upstream unhealthy")
+        );
+    }
+
+    #[tokio::test]
+    async fn drain_skips_sessions_that_close_during_grace() {
+        let manager = Arc::new(SessionManager::new());
+        let (session_id, _token) = manager
+            .new_session_with_control("alice", sample_connection(), "allow", None, None)
+            .await;
+
+        manager.drain_sessions(
+            &[session_id],
+            Duration::from_millis(50),
+            "upstream unhealthy",
+        );
+        manager
+            .close_session(
+                &session_id,
+                Some("Connection closed normally".into()),
+                SessionStatus::Closed,
+            )
+            .await;
+        assert_eq!(manager.pending_drain_count(), 0);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let closed = manager.closed_snapshot().await;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(
+            closed[0].close_reason.as_deref(),
+            Some("Connection closed normally")
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_sessions_then_closes_the_rest() {
+        let manager = Arc::new(SessionManager::new());
+        let (finishing_id, _) = manager
+            .new_session_with_control("alice", sample_connection(), "allow", None, None)
+            .await;
+        let (stuck_id, stuck_token) = manager
+            .new_session_with_control("bob", sample_connection(), "allow", None, None)
+            .await;
+
+        manager.begin_shutdown();
+        assert!(manager.is_shutting_down());
+
+        let finisher = manager.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            finisher
+                .close_session(
+                    &finishing_id,
+                    Some("Connection closed normally".into()),
+                    SessionStatus::Closed,
+                )
+                .await;
+        });
+
+        assert!(!manager.wait_for_idle(Duration::from_millis(300)).await);
+        assert_eq!(manager.active_session_count(), 1);
+
+        manager.shutdown().await;
+        assert!(stuck_token.is_cancelled());
+        let closed = manager.closed_snapshot().await;
+        let stuck = closed
+            .iter()
+            .find(|session| session.session_id == stuck_id)
+            .unwrap();
+        assert_eq!(stuck.close_reason.as_deref(), Some(SHUTDOWN_CLOSE_REASON));
+        assert!(manager.wait_for_idle(Duration::from_millis(10)).await);
+    }
+
     #[cfg(feature = "metrics")]
     #[tokio::test]
     async fn session_metrics_update_counters() {
@@ -946,9 +1990,14 @@ mod tests {
             dest_ip: "blocked.metrics.example".into(),
             dest_port: 1080,
             protocol: Protocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
         manager
-            .track_rejected_session("bob", conn_rejected, None)
+            .track_rejected_session("bob", conn_rejected, None, 0x02)
             .await;
 
         assert!(
@@ -1,7 +1,8 @@
 use lazy_static::lazy_static;
 use prometheus::{
-    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
-    Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge,
+    register_gauge, register_gauge_vec, register_histogram, register_int_counter,
+    register_int_counter_vec, register_int_gauge, register_int_gauge_vec, Gauge, GaugeVec,
+    Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 
 lazy_static! {
@@ -20,6 +21,11 @@ lazy_static! {
         "Total number of rejected SOCKS5 sessions (e.g. ACL)"
     )
     .expect("register rustsocks_sessions_rejected_total counter");
+    pub static ref IDLE_TIMEOUT_SESSIONS: IntCounter = register_int_counter!(
+        "rustsocks_sessions_idle_timeout_total",
+        "Relayed TCP sessions closed by server.idle_timeout_secs"
+    )
+    .expect("register rustsocks_sessions_idle_timeout_total counter");
     pub static ref SESSION_DURATION: Histogram = register_histogram!(HistogramOpts::new(
         "rustsocks_session_duration_seconds",
         "Observed SOCKS5 session duration in seconds"
@@ -50,6 +56,174 @@ lazy_static! {
         &["user", "direction"]
     )
     .expect("register rustsocks_user_bandwidth_bytes_total counter_vec");
+    pub static ref SOCKS_VERSION_SESSIONS: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_sessions_by_socks_version_total",
+        "Sessions opened (including rejected ones) per SOCKS version the client spoke",
+        &["version"]
+    )
+    .expect("register rustsocks_sessions_by_socks_version_total counter_vec");
+    pub static ref SPECIAL_NAME_BLOCKS: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_special_name_blocks_total",
+        "Destinations rejected by the special-use name policy, per category",
+        &["category"]
+    )
+    .expect("register rustsocks_special_name_blocks_total counter_vec");
+    pub static ref DOMAIN_REQUESTS_REJECTED: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_domain_requests_rejected_total",
+        "Requests for a domain name refused by server.dns.allow_domain_requests = false, per command",
+        &["command"]
+    )
+    .expect("register rustsocks_domain_requests_rejected_total counter_vec");
+    pub static ref UPSTREAM_TLS_HANDSHAKES: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_upstream_tls_handshakes_total",
+        "TLS handshakes the proxy ran with server.upstream_tls destinations, per outcome",
+        &["outcome"]
+    )
+    .expect("register rustsocks_upstream_tls_handshakes_total counter_vec");
+    pub static ref BATCH_FLUSH_LATENCY: Histogram = register_histogram!(HistogramOpts::new(
+        "rustsocks_session_batch_flush_seconds",
+        "Observed latency of session batch flushes to the persistent store"
+    )
+    .buckets(vec![
+        0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0
+    ]))
+    .expect("register rustsocks_session_batch_flush_seconds histogram");
+    pub static ref BATCH_EFFECTIVE_SIZE: IntGauge = register_int_gauge!(
+        "rustsocks_session_batch_effective_size",
+        "Batch size currently chosen by the adaptive session batch writer"
+    )
+    .expect("register rustsocks_session_batch_effective_size gauge");
+    pub static ref BATCH_EFFECTIVE_INTERVAL_MS: IntGauge = register_int_gauge!(
+        "rustsocks_session_batch_effective_interval_ms",
+        "Flush interval currently chosen by the adaptive session batch writer"
+    )
+    .expect("register rustsocks_session_batch_effective_interval_ms gauge");
+    pub static ref BATCH_QUEUE_DEPTH: IntGauge = register_int_gauge!(
+        "rustsocks_session_batch_queue_depth",
+        "Sessions waiting in the batch writer queue for the persistent store"
+    )
+    .expect("register rustsocks_session_batch_queue_depth gauge");
+    pub static ref BATCH_QUEUE_OVERFLOWS: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_session_batch_queue_overflows_total",
+        "Sessions that found the batch writer queue full (sessions.batch_queue_max): blocked until a flush, dropped_oldest or dropped_newest",
+        &["action"]
+    )
+    .expect("register rustsocks_session_batch_queue_overflows_total counter_vec");
+    pub static ref METRICS_HISTORY_REQUEST_DURATION: Histogram =
+        register_histogram!(HistogramOpts::new(
+            "rustsocks_api_metrics_history_duration_seconds",
+            "Time to stream one /api/metrics/history response, including decimation"
+        )
+        .buckets(vec![
+            0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0
+        ]))
+        .expect("register rustsocks_api_metrics_history_duration_seconds histogram");
+    pub static ref METRICS_HISTORY_REJECTED: IntCounter = register_int_counter!(
+        "rustsocks_api_metrics_history_rejected_total",
+        "Metrics history requests refused for exceeding the scan range cap"
+    )
+    .expect("register rustsocks_api_metrics_history_rejected_total counter");
+    pub static ref OVERLOAD_SHEDDING: IntGauge = register_int_gauge!(
+        "rustsocks_overload_shedding",
+        "Whether new connections are being shed (1) or not (0)"
+    )
+    .expect("register rustsocks_overload_shedding gauge");
+    pub static ref OVERLOAD_SIGNAL: Gauge = register_gauge!(
+        "rustsocks_overload_signal",
+        "Last sampled value of the configured overload signal"
+    )
+    .expect("register rustsocks_overload_signal gauge");
+    pub static ref OVERLOAD_REJECTED: IntCounter = register_int_counter!(
+        "rustsocks_overload_rejected_connections_total",
+        "New connections closed right after accept by load shedding"
+    )
+    .expect("register rustsocks_overload_rejected_connections_total counter");
+    pub static ref RATE_LIMITED_CONNECTIONS: IntCounter = register_int_counter!(
+        "rustsocks_rate_limited_connections_total",
+        "New connections closed right after accept by the per-client-IP rate limit"
+    )
+    .expect("register rustsocks_rate_limited_connections_total counter");
+    pub static ref RESOURCE_GUARD_LEVEL: IntGauge = register_int_gauge!(
+        "rustsocks_resource_guard_level",
+        "Resource guardrail level (0 = normal, 1 = soft watermark, 2 = hard watermark)"
+    )
+    .expect("register rustsocks_resource_guard_level gauge");
+    pub static ref OPEN_FDS: IntGauge = register_int_gauge!(
+        "rustsocks_open_fds",
+        "File descriptors held by the process at the last guardrail sample"
+    )
+    .expect("register rustsocks_open_fds gauge");
+    pub static ref FD_SOFT_WATERMARK: IntGauge = register_int_gauge!(
+        "rustsocks_fd_soft_watermark",
+        "Open descriptor count at which new connections are rejected"
+    )
+    .expect("register rustsocks_fd_soft_watermark gauge");
+    pub static ref FD_HARD_WATERMARK: IntGauge = register_int_gauge!(
+        "rustsocks_fd_hard_watermark",
+        "Open descriptor count at which idle connections are reclaimed"
+    )
+    .expect("register rustsocks_fd_hard_watermark gauge");
+    pub static ref RESIDENT_MEMORY: IntGauge = register_int_gauge!(
+        "rustsocks_resident_memory_bytes",
+        "Resident memory of the process at the last guardrail sample"
+    )
+    .expect("register rustsocks_resident_memory_bytes gauge");
+    pub static ref RESOURCE_GUARD_REJECTED: IntCounter = register_int_counter!(
+        "rustsocks_resource_guard_rejected_connections_total",
+        "Connections rejected because the process reached a resource watermark"
+    )
+    .expect("register rustsocks_resource_guard_rejected_connections_total counter");
+    pub static ref RESOURCE_GUARD_RECLAIMED: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_resource_guard_reclaimed_total",
+        "Idle connections closed at the resource hard watermark",
+        &["kind"]
+    )
+    .expect("register rustsocks_resource_guard_reclaimed_total counter_vec");
+    pub static ref DNS_CACHE_HITS: IntCounter = register_int_counter!(
+        "rustsocks_dns_cache_hits_total",
+        "Domain lookups answered from the server.dns cache (negative entries included)"
+    )
+    .expect("register rustsocks_dns_cache_hits_total counter");
+    pub static ref DNS_CACHE_MISSES: IntCounter = register_int_counter!(
+        "rustsocks_dns_cache_misses_total",
+        "Domain lookups the server.dns cache passed on to the resolver"
+    )
+    .expect("register rustsocks_dns_cache_misses_total counter");
+    pub static ref UPSTREAM_CONNECT_RETRIES: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_upstream_connect_retries_total",
+        "Upstream connects retried after a refused or timed-out attempt (server.connect_retries)",
+        &["reason"]
+    )
+    .expect("register rustsocks_upstream_connect_retries_total counter_vec");
+    pub static ref HANDSHAKE_TIMEOUTS: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_handshake_timeouts_total",
+        "Connections closed for not finishing the handshake within server.handshake_timeout_secs",
+        &["phase"]
+    )
+    .expect("register rustsocks_handshake_timeouts_total counter_vec");
+    pub static ref HANDSHAKES_REJECTED: IntCounter = register_int_counter!(
+        "rustsocks_handshakes_rejected_total",
+        "Connections closed on accept because server.max_concurrent_handshakes were in progress"
+    )
+    .expect("register rustsocks_handshakes_rejected_total counter");
+    pub static ref UDP_DROPPED_DATAGRAMS: IntCounterVec = register_int_counter_vec!(
+        "rustsocks_udp_dropped_datagrams_total",
+        "UDP ASSOCIATE datagrams not relayed: oversized, fragmented with server.udp.enable_fragments off, given up during reassembly, or from an unknown source",
+        &["reason"]
+    )
+    .expect("register rustsocks_udp_dropped_datagrams_total counter_vec");
+    pub static ref PROBE_UP: IntGaugeVec = register_int_gauge_vec!(
+        "rustsocks_probe_up",
+        "1 when the latest diagnostics.probes check of the destination connected, 0 when it failed",
+        &["probe"]
+    )
+    .expect("register rustsocks_probe_up gauge_vec");
+    pub static ref PROBE_LATENCY: GaugeVec = register_gauge_vec!(
+        "rustsocks_probe_latency_seconds",
+        "Duration of the latest diagnostics.probes check, successful or not",
+        &["probe"]
+    )
+    .expect("register rustsocks_probe_latency_seconds gauge_vec");
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -77,6 +251,137 @@ impl SessionMetrics {
         USER_SESSIONS.with_label_values(&[user]).inc();
     }
 
+    #[inline]
+    pub fn record_idle_timeout() {
+        IDLE_TIMEOUT_SESSIONS.inc();
+    }
+
+    #[inline]
+    pub fn record_socks_version(version: u8) {
+        let label = match version {
+            4 => "4",
+            _ => "5",
+        };
+        SOCKS_VERSION_SESSIONS.with_label_values(&[label]).inc();
+    }
+
+    #[inline]
+    pub fn record_special_name_block(category: &str) {
+        SPECIAL_NAME_BLOCKS.with_label_values(&[category]).inc();
+    }
+
+    #[inline]
+    pub fn record_domain_request_rejected(command: &str) {
+        DOMAIN_REQUESTS_REJECTED.with_label_values(&[command]).inc();
+    }
+
+    /// `outcome` is `ok` or a [`failure_outcome`](crate::server::upstream_tls::failure_outcome)
+    #[inline]
+    pub fn record_upstream_tls_handshake(outcome: &str) {
+        UPSTREAM_TLS_HANDSHAKES.with_label_values(&[outcome]).inc();
+    }
+
+    #[inline]
+    pub fn observe_batch_flush(duration_secs: f64) {
+        BATCH_FLUSH_LATENCY.observe(duration_secs);
+    }
+
+    #[inline]
+    pub fn set_batch_effective(batch_size: usize, interval_ms: u64) {
+        BATCH_EFFECTIVE_SIZE.set(batch_size as i64);
+        BATCH_EFFECTIVE_INTERVAL_MS.set(interval_ms as i64);
+    }
+
+    #[inline]
+    pub fn set_batch_queue_depth(depth: usize) {
+        BATCH_QUEUE_DEPTH.set(depth as i64);
+    }
+
+    #[inline]
+    pub fn record_batch_queue_overflow(action: &str) {
+        BATCH_QUEUE_OVERFLOWS.with_label_values(&[action]).inc();
+    }
+
+    #[inline]
+    pub fn set_overload_state(shedding: bool, signal: f64) {
+        OVERLOAD_SHEDDING.set(shedding as i64);
+        OVERLOAD_SIGNAL.set(signal);
+    }
+
+    #[inline]
+    pub fn record_overload_rejection() {
+        OVERLOAD_REJECTED.inc();
+    }
+
+    #[inline]
+    pub fn record_rate_limited_connection() {
+        RATE_LIMITED_CONNECTIONS.inc();
+    }
+
+    #[inline]
+    pub fn set_resource_watermarks(fd_soft: Option<u64>, fd_hard: Option<u64>) {
+        FD_SOFT_WATERMARK.set(fd_soft.map_or(-1, |value| value as i64));
+        FD_HARD_WATERMARK.set(fd_hard.map_or(-1, |value| value as i64));
+    }
+
+    #[inline]
+    pub fn set_resource_usage(level: u8, open_fds: Option<u64>, resident_memory: Option<u64>) {
+        RESOURCE_GUARD_LEVEL.set(level as i64);
+        OPEN_FDS.set(open_fds.map_or(-1, |value| value as i64));
+        RESIDENT_MEMORY.set(resident_memory.map_or(-1, |value| value as i64));
+    }
+
+    #[inline]
+    pub fn record_resource_rejection() {
+        RESOURCE_GUARD_REJECTED.inc();
+    }
+
+    #[inline]
+    pub fn record_resource_reclaimed(kind: &str, count: u64) {
+        if count > 0 {
+            RESOURCE_GUARD_RECLAIMED
+                .with_label_values(&[kind])
+                .inc_by(count);
+        }
+    }
+
+    #[inline]
+    pub fn record_dns_cache_lookup(hit: bool) {
+        if hit {
+            DNS_CACHE_HITS.inc();
+        } else {
+            DNS_CACHE_MISSES.inc();
+        }
+    }
+
+    #[inline]
+    pub fn record_connect_retry(reason: &str) {
+        UPSTREAM_CONNECT_RETRIES.with_label_values(&[reason]).inc();
+    }
+
+    #[inline]
+    pub fn record_handshake_timeout(phase: &str) {
+        HANDSHAKE_TIMEOUTS.with_label_values(&[phase]).inc();
+    }
+
+    #[inline]
+    pub fn record_handshake_rejected() {
+        HANDSHAKES_REJECTED.inc();
+    }
+
+    #[inline]
+    pub fn record_udp_dropped_datagram(reason: &str) {
+        UDP_DROPPED_DATAGRAMS.with_label_values(&[reason]).inc();
+    }
+
+    #[inline]
+    pub fn record_probe(probe: &str, up: bool, latency: std::time::Duration) {
+        PROBE_UP.with_label_values(&[probe]).set(up as i64);
+        PROBE_LATENCY
+            .with_label_values(&[probe])
+            .set(latency.as_secs_f64());
+    }
+
     #[inline]
     pub fn record_traffic(user: &str, bytes_sent: u64, bytes_received: u64) {
         if bytes_sent > 0 {
@@ -1,5 +1,9 @@
+pub mod access_log;
+pub mod admission;
 #[cfg(feature = "database")]
 pub mod batch;
+pub mod events;
+pub mod failures;
 pub mod history;
 pub mod manager;
 #[cfg(feature = "metrics")]
@@ -8,15 +12,33 @@ pub mod metrics;
 pub mod store;
 pub mod types;
 
+pub use access_log::{AccessLog, AccessLogStats, AclAccess};
+pub use admission::{AdmissionLog, AdmissionRejection, AdmissionRejectionStats};
 #[cfg(feature = "database")]
-pub use batch::{BatchConfig, BatchWriter};
-pub use history::{start_metrics_collector, MetricsHistory, MetricsSnapshot};
-pub use manager::SessionManager;
+pub use batch::{BatchConfig, BatchSink, BatchWriter, BatchWriterStats};
+pub use events::{
+    SessionEvent, SessionEventFilter, SessionEventKind, SessionEvents,
+    DEFAULT_LIVE_TRAFFIC_INTERVAL,
+};
+pub use failures::{
+    FailingDestination, FailureCategory, FailureLog, FailureRecord, FailureSummary,
+    FAILURE_LOG_CAPACITY,
+};
+pub use history::{
+    start_metrics_collector, MetricAggregate, MetricsCursor, MetricsHistory, MetricsResolution,
+    MetricsRollup, MetricsSnapshot, MinMaxDecimator, RollupFolder, METRICS_CHUNK_SIZE,
+};
+pub use manager::{SessionManager, TerminateOutcome, SHUTDOWN_CLOSE_REASON};
 #[cfg(feature = "metrics")]
 pub use metrics::SessionMetrics;
 #[cfg(feature = "database")]
-pub use store::SessionStore;
+pub use store::{
+    InstanceLock, InstanceLockError, InstanceLockHolder, MetricsPageCursor, SessionStore,
+};
 pub use types::{
-    AclDecisionStats, ConnectionInfo, DestinationStat, Protocol as SessionProtocol, Session,
-    SessionFilter, SessionStats, SessionStatus, UserSessionStat,
+    dest_host_key, instance_id, new_session_id, AclDecisionStats, BatchOverflowPolicy,
+    ConnectionInfo, DestHostPattern, DestinationBucket, DestinationStat, HostSource,
+    Protocol as SessionProtocol, Session, SessionCommand, SessionEviction, SessionFilter,
+    SessionResolution, SessionStats, SessionStatus, TransferRate, TransferRates,
+    UdpAssociationMode, UserSessionStat,
 };
@@ -1,24 +1,120 @@
-use super::types::{Protocol as SessionProtocol, Session, SessionFilter, SessionStatus};
+use super::failures::FailureCategory;
+use super::types::{
+    dest_host_key, DestHostPattern, DestinationBucket, HostSource, Protocol as SessionProtocol,
+    Session, SessionCommand, SessionFilter, SessionStatus, UdpAssociationMode,
+};
+use crate::acl::{AclMatchedOn, Action as AclAction, RuleStatsRecord};
 use chrono::{DateTime, Duration as ChronoDuration, NaiveDateTime, Utc};
-use sqlx::any::{install_default_drivers, AnyPoolOptions};
+use sqlx::any::{install_default_drivers, AnyArguments, AnyPoolOptions};
 use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::{Any, AnyPool, FromRow, QueryBuilder};
+use sqlx::{Any, AnyConnection, AnyPool, Connection, Execute, FromRow, QueryBuilder};
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::Read;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
-use tokio::time::{interval, Duration, MissedTickBehavior};
-use tracing::{debug, info, warn};
+use std::sync::{Arc, OnceLock};
+use tokio::time::{interval, sleep, Duration, Instant, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+/// Retention batches, oldest first: closed sessions expire by end time, sessions never
+/// closed by start time. Each select and its order column are served by
+/// `idx_sessions_end_start` (migration 030) without a sort.
+const RETENTION_SELECTS: [(&str, &str); 2] = [
+    (
+        "SELECT session_id FROM sessions WHERE end_time < ?",
+        "end_time",
+    ),
+    (
+        "SELECT session_id FROM sessions WHERE end_time IS NULL AND start_time < ?",
+        "start_time",
+    ),
+];
+/// Least time the cleanup leaves the database to the batch writer between two batches
+const CLEANUP_BATCH_PAUSE: Duration = Duration::from_millis(50);
+/// Groups only change with a new session, so rows that exist are kept as they are
+const INSERT_SESSION_GROUP: &str = "INSERT INTO session_groups (session_id, group_name) \
+     VALUES (?, ?) ON CONFLICT(session_id, group_name) DO NOTHING";
+
 /// Persistent storage for session history.
 #[derive(Debug)]
 pub struct SessionStore {
     pool: AnyPool,
     flavor: DatabaseFlavor,
+    /// Set once this process holds its row in `store_instances`
+    lock: OnceLock<InstanceLock>,
+}
+
+/// This process's claim on the session database (`store_instances`, migration 017).
+#[derive(Debug, Clone)]
+pub struct InstanceLock {
+    /// Boot ID written on this process's sessions (see [`crate::session::instance_id`])
+    pub instance_id: Uuid,
+    /// `sessions.allow_shared_database`: other live instances may write the same file
+    pub shared: bool,
+    /// Heartbeat age after which another instance's row is taken over
+    pub stale_after: Duration,
+}
+
+impl InstanceLock {
+    fn stale_threshold(&self) -> ChronoDuration {
+        ChronoDuration::from_std(self.stale_after).unwrap_or(ChronoDuration::MAX)
+    }
+}
+
+/// Another live instance holds the session database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceLockHolder {
+    pub instance_id: String,
+    pub hostname: String,
+    pub pid: i64,
+    pub shared: bool,
+    pub heartbeat_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum InstanceLockError {
+    /// A live instance holds the store and the two cannot share it
+    Held {
+        holder: InstanceLockHolder,
+        stale_after: Duration,
+    },
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for InstanceLockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstanceLockError::Held {
+                holder,
+                stale_after,
+            } => write!(
+                f,
+                "session database is in use by instance {} (host {}, pid {}{}), last heartbeat {}; \
+                 its lock is taken over once the heartbeat is {}s old. To run several instances \
+                 on one SQLite file set sessions.allow_shared_database = true on all of them, \
+                 or use a MariaDB or PostgreSQL server",
+                holder.instance_id,
+                holder.hostname,
+                holder.pid,
+                if holder.shared { ", shared" } else { "" },
+                holder.heartbeat_at.to_rfc3339(),
+                stale_after.as_secs()
+            ),
+            InstanceLockError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for InstanceLockError {}
+
+impl From<sqlx::Error> for InstanceLockError {
+    fn from(e: sqlx::Error) -> Self {
+        InstanceLockError::Database(e)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -29,6 +125,7 @@ enum DatabaseFlavor {
         connect_url: String,
     },
     MariaDb,
+    Postgres,
 }
 
 impl SessionStore {
@@ -47,6 +144,22 @@ impl SessionStore {
         }
     }
 
+    /// Open one connection to `database_url` and run `SELECT 1`, without the migrations
+    /// and SQLite file housekeeping of [`Self::connect`] (`rustsocks check`).
+    ///
+    /// A SQLite file that does not exist yet passes: the server creates it on start.
+    pub async fn check_connectivity(database_url: &str) -> Result<(), sqlx::Error> {
+        let flavor = DatabaseFlavor::from_url(database_url)?;
+        if flavor.sqlite_path().is_some_and(|path| !path.exists()) {
+            return Ok(());
+        }
+
+        install_default_drivers();
+        let mut connection = AnyConnection::connect(flavor.connection_url(database_url)).await?;
+        sqlx::query("SELECT 1").execute(&mut connection).await?;
+        connection.close().await
+    }
+
     async fn connect_attempt(
         database_url: &str,
         allow_reset: bool,
@@ -77,8 +190,13 @@ impl SessionStore {
             .connect(connect_url)
             .await?;
 
-        // Apply migrations shipped in the `migrations/` directory.
-        if let Err(e) = sqlx::migrate!("./migrations").run(&pool).await {
+        // Apply migrations shipped in the `migrations/` directory; PostgreSQL has its
+        // own set in `migrations/postgres/`.
+        let migrated = match flavor {
+            DatabaseFlavor::Postgres => sqlx::migrate!("./migrations/postgres").run(&pool).await,
+            _ => sqlx::migrate!("./migrations").run(&pool).await,
+        };
+        if let Err(e) = migrated {
             return Err(sqlx::Error::Migrate(Box::new(e)));
         }
 
@@ -128,7 +246,11 @@ impl SessionStore {
             }
         }
 
-        Ok(Some(Self { pool, flavor }))
+        Ok(Some(Self {
+            pool,
+            flavor,
+            lock: OnceLock::new(),
+        }))
     }
 
     fn is_in_memory_database(filename: &Path, url: &str) -> bool {
@@ -146,22 +268,32 @@ impl SessionStore {
     }
 
     /// Mark all active sessions as closed (called on server startup to clean up stale sessions).
+    ///
+    /// In shared mode only sessions of instances without a live lock are closed; the
+    /// active sessions of the other running instances are left alone.
     pub async fn close_all_active_sessions(&self) -> Result<u64, sqlx::Error> {
         let now = Utc::now();
-        let result = sqlx::query(
+        let peers = self.live_peer_instances().await?;
+        let statement = format!(
             r#"
             UPDATE sessions
             SET status = 'closed',
                 close_reason = 'Server restart',
                 end_time = ?,
-                duration_secs = CAST((julianday(?) - julianday(start_time)) * 86400 AS INTEGER)
-            WHERE status = 'active'
+                duration_secs = {}
+            WHERE status = 'active'{}
             "#,
-        )
-        .bind(now.to_rfc3339())
-        .bind(now.to_rfc3339())
-        .execute(&self.pool)
-        .await?;
+            self.flavor.seconds_since("start_time"),
+            exclude_instances(peers.len())
+        );
+        let statement = self.flavor.sql(&statement);
+        let mut query = sqlx::query(&statement)
+            .bind(now.to_rfc3339())
+            .bind(now.to_rfc3339());
+        for peer in &peers {
+            query = query.bind(peer.clone());
+        }
+        let result = query.execute(&self.pool).await?;
 
         let rows_affected = result.rows_affected();
         if rows_affected > 0 {
@@ -173,6 +305,134 @@ impl SessionStore {
         Ok(rows_affected)
     }
 
+    /// Register this instance in `store_instances` and switch the store into the mode
+    /// the lock allows.
+    ///
+    /// Refuses while another instance with a fresh heartbeat holds an SQLite file,
+    /// unless both run in shared mode; rows without a heartbeat for `stale_after`
+    /// belong to dead instances and are taken over. MariaDB and PostgreSQL are built
+    /// for many writers and always run shared. In-memory databases are private to the process and skip
+    /// the lock.
+    pub async fn acquire_instance_lock(&self, lock: InstanceLock) -> Result<(), InstanceLockError> {
+        if self.flavor.is_memory() {
+            return Ok(());
+        }
+
+        let lock = InstanceLock {
+            shared: lock.shared || !self.flavor.is_sqlite(),
+            ..lock
+        };
+        let own_id = lock.instance_id.to_string();
+        let now = Utc::now();
+
+        // Register first and look second: two instances starting at the same moment
+        // both see each other and both refuse, instead of both going ahead.
+        self.register_instance(&own_id, lock.shared, now).await?;
+
+        let stale_after = lock.stale_threshold();
+        let mut holder = None;
+        for row in self.registered_instances().await? {
+            if row.instance_id == own_id {
+                continue;
+            }
+            let other = row.into_holder()?;
+            if now - other.heartbeat_at > stale_after {
+                warn!(
+                    instance_id = %other.instance_id,
+                    hostname = %other.hostname,
+                    pid = other.pid,
+                    last_heartbeat = %other.heartbeat_at.to_rfc3339(),
+                    "Taking over stale session store lock"
+                );
+                self.delete_instance(&other.instance_id).await?;
+            } else if !(lock.shared && other.shared) && holder.is_none() {
+                holder = Some(other);
+            }
+        }
+
+        if let Some(holder) = holder {
+            self.delete_instance(&own_id).await?;
+            return Err(InstanceLockError::Held {
+                holder,
+                stale_after: lock.stale_after,
+            });
+        }
+
+        info!(
+            instance_id = %own_id,
+            shared = lock.shared,
+            "Session store lock acquired"
+        );
+        let _ = self.lock.set(lock);
+        Ok(())
+    }
+
+    /// Refresh this instance's heartbeat every `every` until the process exits.
+    pub fn spawn_instance_heartbeat(self: &Arc<Self>, every: Duration) {
+        let Some(lock) = self.lock.get() else {
+            return;
+        };
+        let own_id = lock.instance_id.to_string();
+        let shared = lock.shared;
+        let store = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut ticker = interval(every);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick fires immediately and the row was just written
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+
+                let now = Utc::now();
+                match sqlx::query(
+                    &store
+                        .flavor
+                        .sql("UPDATE store_instances SET heartbeat_at = ? WHERE instance_id = ?"),
+                )
+                .bind(now.to_rfc3339())
+                .bind(own_id.clone())
+                .execute(&store.pool)
+                .await
+                {
+                    Ok(result) if result.rows_affected() == 0 => {
+                        // Heartbeats stalled long enough for another instance to take
+                        // over; put the row back so shared peers keep our sessions open
+                        error!(
+                            instance_id = %own_id,
+                            "Session store lock was taken over by another instance"
+                        );
+                        if let Err(e) = store.register_instance(&own_id, shared, now).await {
+                            warn!(error = %e, "Failed to re-register session store lock");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!(error = %e, "Session store heartbeat failed");
+                    }
+                }
+            }
+        });
+    }
+
+    /// Drop this instance's lock row (graceful shutdown), so the next start does not
+    /// have to wait for it to go stale.
+    pub async fn release_instance_lock(&self) -> Result<(), sqlx::Error> {
+        match self.lock.get() {
+            Some(lock) => self.delete_instance(&lock.instance_id.to_string()).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Cheapest possible round trip to the database, for the readiness check
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+    }
+
     /// Access underlying connection pool.
     pub fn pool(&self) -> &AnyPool {
         &self.pool
@@ -193,7 +453,83 @@ impl SessionStore {
         &self,
         filter: &SessionFilter,
     ) -> Result<Vec<Session>, sqlx::Error> {
+        let mut builder = QueryBuilder::<Any>::new("");
+        Self::push_session_query(&mut builder, filter);
+
+        let (statement, arguments) = self.flavor.finish(&mut builder)?;
+        let rows = sqlx::query_as_with::<_, SessionRow, _>(&statement, arguments)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut sessions = rows
+            .into_iter()
+            .map(SessionRow::into_session)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.load_groups(&mut sessions).await?;
+        Ok(sessions)
+    }
+
+    /// Fill in [`Session::groups`] from `session_groups`.
+    async fn load_groups(&self, sessions: &mut [Session]) -> Result<(), sqlx::Error> {
+        if sessions.is_empty() {
+            return Ok(());
+        }
+
         let mut builder = QueryBuilder::<Any>::new(
+            "SELECT session_id, group_name FROM session_groups WHERE session_id IN (",
+        );
+        {
+            let mut separated = builder.separated(", ");
+            for session in sessions.iter() {
+                separated.push_bind(session.session_id.to_string());
+            }
+        }
+        builder.push(") ORDER BY session_id, group_name");
+
+        let (statement, arguments) = self.flavor.finish(&mut builder)?;
+        let rows = sqlx::query_as_with::<_, SessionGroupRow, _>(&statement, arguments)
+            .fetch_all(&self.pool)
+            .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut groups: HashMap<String, Vec<Arc<str>>> = HashMap::new();
+        for row in rows {
+            groups
+                .entry(row.session_id)
+                .or_default()
+                .push(Arc::from(row.group_name));
+        }
+        for session in sessions {
+            if let Some(list) = groups.remove(&session.session_id.to_string()) {
+                session.groups = list;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the groups of `session`; rows that already exist are left alone.
+    async fn insert_groups(
+        &self,
+        conn: &mut AnyConnection,
+        session_id: &str,
+        groups: &[Arc<str>],
+    ) -> Result<(), sqlx::Error> {
+        let statement = self.flavor.sql(INSERT_SESSION_GROUP);
+        for group in groups {
+            sqlx::query(&statement)
+                .bind(session_id.to_string())
+                .bind(group.to_string())
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// SELECT statement behind `query_sessions`, appended to `builder`.
+    fn push_session_query(builder: &mut QueryBuilder<'_, Any>, filter: &SessionFilter) {
+        builder.push(
             r#"
             SELECT
                 session_id,
@@ -213,13 +549,36 @@ impl SessionStore {
                 status,
                 close_reason,
                 acl_rule_matched,
-                acl_decision
+                acl_decision,
+                sni_host,
+                requested_host,
+                requested_host_source,
+                authenticated_user,
+                instance_id,
+                keepalive_probes,
+                udp_association_mode,
+                udp_client_endpoint,
+                udp_rejected_datagrams,
+                udp_throttled_datagrams,
+                correlation_id,
+                command,
+                socks_version,
+                chained,
+                dest_host,
+                egress_ip,
+                would_block,
+                reply_code,
+                failure,
+                resolved_ips,
+                connected_ip,
+                acl_matched_on,
+                upstream_tls
             FROM sessions
             WHERE 1=1
             "#,
         );
 
-        Self::push_filters(&mut builder, filter);
+        Self::push_filters(builder, filter);
 
         // Apply sorting (with validation to prevent SQL injection)
         let sort_column = filter.sort_by.as_deref().unwrap_or("start_time");
@@ -227,6 +586,7 @@ impl SessionStore {
             "user" => "user",
             "source_ip" => "source_ip",
             "dest_ip" => "dest_ip",
+            "dest_host" => "dest_host",
             "protocol" => "protocol",
             "status" => "status",
             "acl_decision" => "acl_decision",
@@ -253,17 +613,17 @@ impl SessionStore {
         if let Some(offset) = filter.offset {
             builder.push(" OFFSET ").push_bind(offset as i64);
         }
-
-        let query = builder.build_query_as::<SessionRow>();
-        let rows = query.fetch_all(&self.pool).await?;
-
-        rows.into_iter().map(SessionRow::into_session).collect()
     }
 
     pub async fn count_sessions(&self, filter: &SessionFilter) -> Result<u64, sqlx::Error> {
         // If filter is mostly empty and table is large, use approximate count
         let is_simple_filter = filter.user.is_none()
+            && filter.authenticated_user.is_none()
+            && filter.correlation_id.is_none()
             && filter.dest_ip.is_none()
+            && filter.dest_host.is_none()
+            && filter.requested_host.is_none()
+            && filter.requested_host_source.is_none()
             && filter.status.is_none()
             && filter.start_after.is_none();
 
@@ -281,8 +641,10 @@ impl SessionStore {
         );
 
         Self::push_filters(&mut builder, filter);
-        let query = builder.build_query_scalar();
-        let count: i64 = query.fetch_one(&self.pool).await?;
+        let (statement, arguments) = self.flavor.finish(&mut builder)?;
+        let count: i64 = sqlx::query_scalar_with(&statement, arguments)
+            .fetch_one(&self.pool)
+            .await?;
         Ok(count as u64)
     }
 
@@ -352,8 +714,8 @@ impl SessionStore {
 
         builder.push(")");
 
-        let rows = builder
-            .build_query_as::<SessionIdRow>()
+        let (statement, arguments) = self.flavor.finish(&mut builder)?;
+        let rows = sqlx::query_as_with::<_, SessionIdRow, _>(&statement, arguments)
             .fetch_all(&self.pool)
             .await?;
 
@@ -388,29 +750,104 @@ impl SessionStore {
                 status,
                 close_reason,
                 acl_rule_matched,
-                acl_decision
+                acl_decision,
+                sni_host,
+                requested_host,
+                requested_host_source,
+                authenticated_user,
+                instance_id,
+                keepalive_probes,
+                udp_association_mode,
+                udp_client_endpoint,
+                udp_rejected_datagrams,
+                udp_throttled_datagrams,
+                correlation_id,
+                command,
+                socks_version,
+                chained,
+                dest_host,
+                egress_ip,
+                would_block,
+                reply_code,
+                failure,
+                resolved_ips,
+                connected_ip,
+                acl_matched_on,
+                upstream_tls
             FROM sessions
             WHERE session_id = 
             "#,
         );
         builder.push_bind(session_id.to_string());
-        let query = builder.build_query_as::<SessionRow>();
+        let (statement, arguments) = self.flavor.finish(&mut builder)?;
 
-        let row = query.fetch_optional(&self.pool).await?;
-        row.map(SessionRow::into_session).transpose()
+        let row = sqlx::query_as_with::<_, SessionRow, _>(&statement, arguments)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(session) = row.map(SessionRow::into_session).transpose()? else {
+            return Ok(None);
+        };
+        let mut sessions = [session];
+        self.load_groups(&mut sessions).await?;
+        let [session] = sessions;
+        Ok(Some(session))
     }
 
+    /// Equality filters come first and the `start_time` range after them, in the
+    /// column order of the `(column, start_time)` indexes (migrations 001, 002, 015).
     fn push_filters(builder: &mut QueryBuilder<'_, Any>, filter: &SessionFilter) {
         if let Some(user) = &filter.user {
             builder.push(" AND user = ").push_bind(user.clone());
         }
 
+        if let Some(dest_ip) = &filter.dest_ip {
+            builder.push(" AND dest_ip = ").push_bind(dest_ip.clone());
+        }
+
+        match &filter.dest_host {
+            Some(DestHostPattern::Exact(host)) => {
+                builder.push(" AND dest_host = ").push_bind(host.clone());
+            }
+            // A leading wildcard cannot use the index, but the other filters still can
+            Some(DestHostPattern::Suffix(suffix)) => {
+                builder
+                    .push(" AND dest_host LIKE ")
+                    .push_bind(format!("_%{}", escape_like(suffix)))
+                    .push(" ESCAPE '!'");
+            }
+            None => {}
+        }
+
         if let Some(status) = &filter.status {
             builder
                 .push(" AND status = ")
                 .push_bind(status.as_str().to_string());
         }
 
+        if let Some(authenticated_user) = &filter.authenticated_user {
+            builder
+                .push(" AND authenticated_user = ")
+                .push_bind(authenticated_user.clone());
+        }
+
+        if let Some(correlation_id) = &filter.correlation_id {
+            builder
+                .push(" AND correlation_id = ")
+                .push_bind(correlation_id.clone());
+        }
+
+        if let Some(requested_host) = &filter.requested_host {
+            builder
+                .push(" AND requested_host = ")
+                .push_bind(requested_host.clone());
+        }
+
+        if let Some(source) = filter.requested_host_source {
+            builder
+                .push(" AND requested_host_source = ")
+                .push_bind(source.as_str());
+        }
+
         if let Some(start_after) = filter.start_after {
             // Direct comparison works with RFC3339 format (sortable strings)
             builder
@@ -425,10 +862,6 @@ impl SessionStore {
                 .push_bind(start_before.to_rfc3339());
         }
 
-        if let Some(dest_ip) = &filter.dest_ip {
-            builder.push(" AND dest_ip = ").push_bind(dest_ip.clone());
-        }
-
         if let Some(min_duration) = filter.min_duration_secs {
             builder
                 .push(" AND duration_secs IS NOT NULL AND duration_secs >= ")
@@ -441,10 +874,14 @@ impl SessionStore {
         }
     }
 
+    /// Insert a session or update the row it already owns.
+    ///
+    /// A row with the same `session_id` but a different instance or start time belongs
+    /// to another session; it is left untouched and the write fails.
     async fn upsert_session(&self, session: &Session) -> Result<(), sqlx::Error> {
         let params = SessionParams::from(session);
 
-        sqlx::query(
+        let result = sqlx::query(&self.flavor.sql(
             r#"
             INSERT INTO sessions (
                 session_id,
@@ -464,10 +901,33 @@ impl SessionStore {
                 status,
                 close_reason,
                 acl_rule_matched,
-                acl_decision
+                acl_decision,
+                sni_host,
+                requested_host,
+                requested_host_source,
+                authenticated_user,
+                instance_id,
+                keepalive_probes,
+                udp_association_mode,
+                udp_client_endpoint,
+                udp_rejected_datagrams,
+                udp_throttled_datagrams,
+                correlation_id,
+                command,
+                socks_version,
+                chained,
+                dest_host,
+                egress_ip,
+                would_block,
+                reply_code,
+                failure,
+                resolved_ips,
+                connected_ip,
+                acl_matched_on,
+                upstream_tls
             )
             VALUES (
-                ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
+                ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
             )
             ON CONFLICT(session_id) DO UPDATE SET
                 user = excluded.user,
@@ -486,9 +946,34 @@ impl SessionStore {
                 status = excluded.status,
                 close_reason = excluded.close_reason,
                 acl_rule_matched = excluded.acl_rule_matched,
-                acl_decision = excluded.acl_decision
+                acl_decision = excluded.acl_decision,
+                sni_host = excluded.sni_host,
+                requested_host = excluded.requested_host,
+                requested_host_source = excluded.requested_host_source,
+                authenticated_user = excluded.authenticated_user,
+                keepalive_probes = excluded.keepalive_probes,
+                udp_association_mode = excluded.udp_association_mode,
+                udp_client_endpoint = excluded.udp_client_endpoint,
+                udp_rejected_datagrams = excluded.udp_rejected_datagrams,
+                udp_throttled_datagrams = excluded.udp_throttled_datagrams,
+                correlation_id = excluded.correlation_id,
+                command = excluded.command,
+                socks_version = excluded.socks_version,
+                chained = excluded.chained,
+                dest_host = excluded.dest_host,
+                egress_ip = excluded.egress_ip,
+                would_block = excluded.would_block,
+                reply_code = excluded.reply_code,
+                failure = excluded.failure,
+                resolved_ips = excluded.resolved_ips,
+                connected_ip = excluded.connected_ip,
+                acl_matched_on = excluded.acl_matched_on,
+                upstream_tls = excluded.upstream_tls
+            -- Only the session that owns the row may update it; see upsert_session
+            WHERE sessions.instance_id = excluded.instance_id
+                AND sessions.start_time = excluded.start_time
             "#,
-        )
+        ))
         .bind(params.session_id.as_ref())
         .bind(params.user.as_ref())
         .bind(&params.start_time)
@@ -507,9 +992,40 @@ impl SessionStore {
         .bind(&params.close_reason)
         .bind(&params.acl_rule_matched)
         .bind(params.acl_decision.as_ref())
+        .bind(params.sni_host.as_deref())
+        .bind(params.requested_host.as_deref())
+        .bind(params.requested_host_source)
+        .bind(params.authenticated_user.as_deref())
+        .bind(params.instance_id.as_ref())
+        .bind(params.keepalive_probes)
+        .bind(params.udp_association_mode)
+        .bind(params.udp_client_endpoint.as_deref())
+        .bind(params.udp_rejected_datagrams)
+        .bind(params.udp_throttled_datagrams)
+        .bind(params.correlation_id.as_deref())
+        .bind(params.command)
+        .bind(params.socks_version)
+        .bind(params.chained)
+        .bind(params.dest_host.as_ref())
+        .bind(params.egress_ip.as_deref())
+        .bind(params.would_block)
+        .bind(params.reply_code)
+        .bind(params.failure)
+        .bind(params.resolved_ips.as_deref())
+        .bind(params.connected_ip.as_deref())
+        .bind(params.acl_matched_on)
+        .bind(params.upstream_tls)
         .execute(&self.pool)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(duplicate_session_id(&params.session_id));
+        }
+        if !session.groups.is_empty() {
+            let mut conn = self.pool.acquire().await?;
+            self.insert_groups(&mut conn, &params.session_id, &session.groups)
+                .await?;
+        }
         Ok(())
     }
 
@@ -518,7 +1034,7 @@ impl SessionStore {
 
         for session in sessions {
             let params = SessionParams::from(&session);
-            sqlx::query(
+            let result = sqlx::query(&self.flavor.sql(
                 r#"
                 INSERT INTO sessions (
                     session_id,
@@ -538,9 +1054,34 @@ impl SessionStore {
                     status,
                     close_reason,
                     acl_rule_matched,
-                    acl_decision
+                    acl_decision,
+                    sni_host,
+                    requested_host,
+                    requested_host_source,
+                    authenticated_user,
+                    instance_id,
+                    keepalive_probes,
+                    udp_association_mode,
+                    udp_client_endpoint,
+                    udp_rejected_datagrams,
+                    udp_throttled_datagrams,
+                    correlation_id,
+                    command,
+                    socks_version,
+                    chained,
+                    dest_host,
+                    egress_ip,
+                    would_block,
+                    reply_code,
+                    failure,
+                    resolved_ips,
+                    connected_ip,
+                    acl_matched_on,
+                    upstream_tls
+                )
+                VALUES (
+                    ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
                 )
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                 ON CONFLICT(session_id) DO UPDATE SET
                     user = excluded.user,
                     start_time = excluded.start_time,
@@ -558,9 +1099,34 @@ impl SessionStore {
                     status = excluded.status,
                     close_reason = excluded.close_reason,
                     acl_rule_matched = excluded.acl_rule_matched,
-                    acl_decision = excluded.acl_decision
+                    acl_decision = excluded.acl_decision,
+                    sni_host = excluded.sni_host,
+                    requested_host = excluded.requested_host,
+                    requested_host_source = excluded.requested_host_source,
+                    authenticated_user = excluded.authenticated_user,
+                    keepalive_probes = excluded.keepalive_probes,
+                    udp_association_mode = excluded.udp_association_mode,
+                    udp_client_endpoint = excluded.udp_client_endpoint,
+                    udp_rejected_datagrams = excluded.udp_rejected_datagrams,
+                    udp_throttled_datagrams = excluded.udp_throttled_datagrams,
+                    correlation_id = excluded.correlation_id,
+                    command = excluded.command,
+                    socks_version = excluded.socks_version,
+                    chained = excluded.chained,
+                    dest_host = excluded.dest_host,
+                    egress_ip = excluded.egress_ip,
+                    would_block = excluded.would_block,
+                    reply_code = excluded.reply_code,
+                    failure = excluded.failure,
+                    resolved_ips = excluded.resolved_ips,
+                    connected_ip = excluded.connected_ip,
+                    acl_matched_on = excluded.acl_matched_on,
+                    upstream_tls = excluded.upstream_tls
+                -- Only the session that owns the row may update it; see upsert_session
+                WHERE sessions.instance_id = excluded.instance_id
+                    AND sessions.start_time = excluded.start_time
                 "#,
-            )
+            ))
             .bind(params.session_id.as_ref())
             .bind(params.user.as_ref())
             .bind(&params.start_time)
@@ -579,35 +1145,138 @@ impl SessionStore {
             .bind(&params.close_reason)
             .bind(&params.acl_rule_matched)
             .bind(params.acl_decision.as_ref())
+            .bind(params.sni_host.as_deref())
+            .bind(params.requested_host.as_deref())
+            .bind(params.requested_host_source)
+            .bind(params.authenticated_user.as_deref())
+            .bind(params.instance_id.as_ref())
+            .bind(params.keepalive_probes)
+            .bind(params.udp_association_mode)
+            .bind(params.udp_client_endpoint.as_deref())
+            .bind(params.udp_rejected_datagrams)
+            .bind(params.udp_throttled_datagrams)
+            .bind(params.correlation_id.as_deref())
+            .bind(params.command)
+            .bind(params.socks_version)
+            .bind(params.chained)
+            .bind(params.dest_host.as_ref())
+            .bind(params.egress_ip.as_deref())
+            .bind(params.would_block)
+            .bind(params.reply_code)
+            .bind(params.failure)
+            .bind(params.resolved_ips.as_deref())
+            .bind(params.connected_ip.as_deref())
+            .bind(params.acl_matched_on)
+            .bind(params.upstream_tls)
             .execute(&mut *tx)
             .await?;
+
+            // Skip rather than fail: one colliding ID must not drop the rest of the batch
+            if result.rows_affected() == 0 {
+                warn!(
+                    session_id = %params.session_id,
+                    "{}",
+                    duplicate_session_id(&params.session_id)
+                );
+            } else {
+                self.insert_groups(&mut tx, &params.session_id, &session.groups)
+                    .await?;
+            }
         }
 
         tx.commit().await
     }
 
-    pub async fn cleanup_older_than(&self, retention_days: u64) -> Result<u64, sqlx::Error> {
+    /// Delete sessions past the retention period, at most `batch_size` per transaction.
+    /// In shared mode the rows of other live instances are left to those instances.
+    ///
+    /// Between two batches the cleanup sleeps at least as long as the batch took, so
+    /// the batch writer's inserts get the database (and SQLite's write lock) for most
+    /// of the run. Every batch commits on its own: a run cut short by a restart leaves
+    /// only rows the next run selects again.
+    pub async fn cleanup_older_than(
+        &self,
+        retention_days: u64,
+        batch_size: usize,
+    ) -> Result<u64, sqlx::Error> {
         if retention_days == 0 {
             return Ok(0);
         }
 
         let cutoff = Utc::now() - ChronoDuration::days(retention_days as i64);
+        let cutoff = cutoff.to_rfc3339();
+        let batch_size = batch_size.max(1);
+
+        let peers = self.live_peer_instances().await?;
+        let mut affected = 0;
+        for (select, order_by) in RETENTION_SELECTS {
+            let statement = format!(
+                "{}{} ORDER BY {} LIMIT {}",
+                select,
+                exclude_instances(peers.len()),
+                order_by,
+                batch_size
+            );
+            let statement = self.flavor.sql(&statement);
+            loop {
+                let started = Instant::now();
+                let mut query = sqlx::query_scalar::<_, String>(&statement).bind(cutoff.clone());
+                for peer in &peers {
+                    query = query.bind(peer.clone());
+                }
+                let ids = query.fetch_all(&self.pool).await?;
+                if ids.is_empty() {
+                    break;
+                }
 
-        let affected = sqlx::query(
-            r#"
-            DELETE FROM sessions
-            WHERE start_time < ?;
-            "#,
-        )
-        .bind(cutoff.to_rfc3339())
-        .execute(&self.pool)
-        .await?
-        .rows_affected();
+                let deleted = self.delete_sessions(&ids).await?;
+                affected += deleted;
+                debug!(
+                    deleted,
+                    total = affected,
+                    by = order_by,
+                    "Session cleanup batch removed expired sessions"
+                );
+                if ids.len() < batch_size {
+                    break;
+                }
+                sleep(started.elapsed().max(CLEANUP_BATCH_PAUSE)).await;
+            }
+        }
 
         Ok(affected)
     }
 
-    pub fn spawn_cleanup(self: &Arc<Self>, retention_days: u64, interval_hours: u64) {
+    /// Delete the sessions `ids` and their group rows in one transaction
+    async fn delete_sessions(&self, ids: &[String]) -> Result<u64, sqlx::Error> {
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let mut tx = self.pool.begin().await?;
+
+        // Sessions go last; theirs is the count returned
+        let mut deleted = 0;
+        for table in ["session_groups", "sessions"] {
+            let statement = format!(
+                "DELETE FROM {} WHERE session_id IN ({})",
+                table, placeholders
+            );
+            let statement = self.flavor.sql(&statement);
+            let mut query = sqlx::query(&statement);
+            for id in ids {
+                query = query.bind(id.as_str());
+            }
+            deleted = query.execute(&mut *tx).await?.rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    pub fn spawn_cleanup(
+        self: &Arc<Self>,
+        retention_days: u64,
+        interval_hours: u64,
+        batch_size: usize,
+    ) {
         if retention_days == 0 {
             info!("Session cleanup disabled (retention_days = 0)");
             return;
@@ -623,10 +1292,12 @@ impl SessionStore {
             loop {
                 ticker.tick().await;
 
-                match store.cleanup_older_than(retention_days).await {
+                // The first tick fires at once, so a run a restart cut short resumes
+                // at startup
+                match store.cleanup_older_than(retention_days, batch_size).await {
                     Ok(affected) => {
                         if affected > 0 {
-                            debug!(affected, "Session cleanup removed old records");
+                            info!(affected, "Session cleanup removed old records");
                         }
                     }
                     Err(e) => {
@@ -638,8 +1309,62 @@ impl SessionStore {
 
         info!(
             retention_days,
-            interval_hours, "Session cleanup task started"
+            interval_hours, batch_size, "Session cleanup task started"
+        );
+    }
+
+    /// Per-destination traffic of sessions started since `since`, bucketed by start time.
+    ///
+    /// Only the `top` destinations (`dest_ip:dest_port`) with the most bytes over the
+    /// whole window are included. Buckets are `bucket_secs` wide and aligned to the Unix
+    /// epoch; rows come back ordered by bucket, then by destination.
+    pub async fn destination_stats(
+        &self,
+        since: &DateTime<Utc>,
+        bucket_secs: u32,
+        top: usize,
+    ) -> Result<Vec<DestinationBucket>, sqlx::Error> {
+        let bucket_secs = bucket_secs.max(1);
+        let statement = format!(
+            r#"
+            WITH top_destinations AS (
+                SELECT dest_ip, dest_port
+                FROM sessions
+                WHERE start_time >= ?
+                GROUP BY dest_ip, dest_port
+                ORDER BY SUM(COALESCE(bytes_sent, 0) + COALESCE(bytes_received, 0)) DESC,
+                         dest_ip, dest_port
+                LIMIT ?
+            )
+            SELECT
+                {bucket} AS bucket_start,
+                s.dest_ip AS dest_ip,
+                s.dest_port AS dest_port,
+                COUNT(*) AS connections,
+                CAST(SUM(COALESCE(s.bytes_sent, 0)) AS {int}) AS bytes_sent,
+                CAST(SUM(COALESCE(s.bytes_received, 0)) AS {int}) AS bytes_received
+            FROM sessions s
+            JOIN top_destinations t ON s.dest_ip = t.dest_ip AND s.dest_port = t.dest_port
+            WHERE s.start_time >= ?
+            GROUP BY bucket_start, s.dest_ip, s.dest_port
+            ORDER BY bucket_start, s.dest_ip, s.dest_port
+            "#,
+            bucket = self.flavor.epoch_bucket("s.start_time", bucket_secs),
+            int = self.flavor.integer_type(),
         );
+
+        let statement = self.flavor.sql(&statement);
+        let since = since.to_rfc3339();
+        let rows: Vec<DestinationBucketRow> = sqlx::query_as(&statement)
+            .bind(since.clone())
+            .bind(top as i64)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(DestinationBucketRow::into_bucket)
+            .collect()
     }
 
     /// Insert a metrics snapshot.
@@ -650,12 +1375,12 @@ impl SessionStore {
         total_sessions: u64,
         bandwidth: u64,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query(
+        sqlx::query(&self.flavor.sql(
             r#"
             INSERT INTO metrics_snapshots (timestamp, active_sessions, total_sessions, bandwidth)
             VALUES (?, ?, ?, ?)
             "#,
-        )
+        ))
         .bind(timestamp.to_rfc3339())
         .bind(active_sessions as i64)
         .bind(total_sessions as i64)
@@ -690,6 +1415,7 @@ impl SessionStore {
             query.push_str(&format!(" LIMIT {}", limit_val));
         }
 
+        let query = self.flavor.sql(&query);
         let mut q = sqlx::query_as::<_, MetricSnapshotRow>(&query);
 
         if let Some(start_time) = start {
@@ -703,45 +1429,355 @@ impl SessionStore {
             .collect::<Result<Vec<_>, _>>()
     }
 
-    /// Cleanup old metrics snapshots.
-    pub async fn cleanup_old_metrics(&self, retention_hours: u64) -> Result<u64, sqlx::Error> {
-        if retention_hours == 0 {
-            return Ok(0);
+    /// One page of metrics snapshots in `[start, end]`, oldest first.
+    ///
+    /// Pages are keyed on `(timestamp, id)` so samples sharing a timestamp are
+    /// neither skipped nor repeated across pages. Returns the cursor for the
+    /// next page, or `None` when this page was short.
+    pub async fn query_metrics_page(
+        &self,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        after: Option<&MetricsPageCursor>,
+        limit: usize,
+    ) -> Result<(Vec<MetricsSnapshot>, Option<MetricsPageCursor>), sqlx::Error> {
+        let mut builder: QueryBuilder<Any> = QueryBuilder::new(
+            "SELECT id, timestamp, active_sessions, total_sessions, bandwidth \
+             FROM metrics_snapshots WHERE timestamp <= ",
+        );
+        builder.push_bind(end.to_rfc3339());
+        match after {
+            Some(cursor) => {
+                builder.push(" AND (timestamp > ");
+                builder.push_bind(cursor.timestamp.clone());
+                builder.push(" OR (timestamp = ");
+                builder.push_bind(cursor.timestamp.clone());
+                builder.push(" AND id > ");
+                builder.push_bind(cursor.id);
+                builder.push("))");
+            }
+            None => {
+                builder.push(" AND timestamp >= ");
+                builder.push_bind(start.to_rfc3339());
+            }
         }
+        builder.push(" ORDER BY timestamp ASC, id ASC LIMIT ");
+        builder.push_bind(limit as i64);
 
-        let cutoff = Utc::now() - ChronoDuration::hours(retention_hours as i64);
-
-        let affected = sqlx::query(
-            r#"
-            DELETE FROM metrics_snapshots
-            WHERE timestamp < ?;
-            "#,
-        )
-        .bind(cutoff.to_rfc3339())
-        .execute(&self.pool)
-        .await?
-        .rows_affected();
+        let (statement, arguments) = self.flavor.finish(&mut builder)?;
+        let rows = sqlx::query_as_with::<_, MetricSnapshotPageRow, _>(&statement, arguments)
+            .fetch_all(&self.pool)
+            .await?;
 
-        Ok(affected)
+        let next = match rows.last() {
+            Some(last) if rows.len() == limit => Some(MetricsPageCursor {
+                timestamp: last.timestamp.clone(),
+                id: last.id,
+            }),
+            _ => None,
+        };
+        let snapshots = rows
+            .into_iter()
+            .map(|row| {
+                MetricSnapshotRow {
+                    timestamp: row.timestamp,
+                    active_sessions: row.active_sessions,
+                    total_sessions: row.total_sessions,
+                    bandwidth: row.bandwidth,
+                }
+                .into_metric()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((snapshots, next))
     }
 
-    /// Spawn background task to cleanup old metrics.
-    pub fn spawn_metrics_cleanup(self: &Arc<Self>, retention_hours: u64, interval_hours: u64) {
-        if retention_hours == 0 {
-            info!("Metrics cleanup disabled (retention_hours = 0)");
-            return;
+    /// [`query_metrics_page`](Self::query_metrics_page) for raw samples, stored rollup
+    /// buckets of `resolution` otherwise.
+    pub async fn query_metrics_page_at(
+        &self,
+        resolution: MetricsResolution,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        after: Option<&MetricsPageCursor>,
+        limit: usize,
+    ) -> Result<(Vec<MetricsSnapshot>, Option<MetricsPageCursor>), sqlx::Error> {
+        let Some(resolution_secs) = resolution.bucket_secs() else {
+            return self.query_metrics_page(start, end, after, limit).await;
+        };
+
+        let mut builder: QueryBuilder<Any> = QueryBuilder::new(
+            "SELECT id, bucket_start, samples, \
+             active_sessions_min, active_sessions_max, active_sessions_sum, \
+             total_sessions_min, total_sessions_max, total_sessions_sum, \
+             bandwidth_min, bandwidth_max, bandwidth_sum \
+             FROM metrics_rollups WHERE resolution_secs = ",
+        );
+        builder.push_bind(resolution_secs);
+        builder.push(" AND bucket_start <= ");
+        builder.push_bind(end.to_rfc3339());
+        builder.push(" AND bucket_start ");
+        // Bucket starts are unique per resolution, so the timestamp alone is the key
+        match after {
+            Some(cursor) => {
+                builder.push("> ");
+                builder.push_bind(cursor.timestamp.clone());
+            }
+            None => {
+                builder.push(">= ");
+                builder.push_bind(start.to_rfc3339());
+            }
         }
+        builder.push(" ORDER BY bucket_start ASC LIMIT ");
+        builder.push_bind(limit as i64);
 
-        let interval_secs = interval_hours.max(1) * 3600;
-        let store = Arc::clone(self);
+        let (statement, arguments) = self.flavor.finish(&mut builder)?;
+        let rows = sqlx::query_as_with::<_, MetricRollupRow, _>(&statement, arguments)
+            .fetch_all(&self.pool)
+            .await?;
 
-        tokio::spawn(async move {
-            let mut ticker = interval(Duration::from_secs(interval_secs));
-            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let next = match rows.last() {
+            Some(last) if rows.len() == limit => Some(MetricsPageCursor {
+                timestamp: last.bucket_start.clone(),
+                id: last.id,
+            }),
+            _ => None,
+        };
+        let buckets = rows
+            .into_iter()
+            .map(MetricRollupRow::into_metric)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((buckets, next))
+    }
+
+    /// End of the last stored rollup bucket of `resolution`; `None` before the first
+    /// rollup.
+    pub async fn metrics_rolled_until(
+        &self,
+        resolution: MetricsResolution,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let Some(resolution_secs) = resolution.bucket_secs() else {
+            return Ok(None);
+        };
+        let last: Option<String> = sqlx::query_scalar(
+            &self
+                .flavor
+                .sql("SELECT MAX(bucket_start) FROM metrics_rollups WHERE resolution_secs = ?"),
+        )
+        .bind(resolution_secs)
+        .fetch_one(&self.pool)
+        .await?;
+
+        last.map(|start| {
+            parse_datetime("bucket_start", &start)
+                .map(|start| start + ChronoDuration::seconds(resolution_secs))
+        })
+        .transpose()
+    }
+
+    /// Roll raw snapshots up into 1-minute buckets, and those into 15-minute buckets,
+    /// up to the last bucket completed before `now`. Buckets already stored are not
+    /// read again. Returns the number of buckets written.
+    pub async fn roll_up_metrics(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let mut written = 0;
+        let mut source = MetricsResolution::Raw;
+        let mut complete_until = now;
+
+        for resolution in MetricsResolution::ROLLUPS {
+            let until = resolution.bucket_start(complete_until);
+            let from = self
+                .metrics_rolled_until(resolution)
+                .await?
+                .unwrap_or(DateTime::UNIX_EPOCH);
+            if from < until {
+                written += self.roll_up_range(source, resolution, from, until).await?;
+            }
+            // Coarser tiers only fold buckets this tier has completed
+            source = resolution;
+            complete_until = until;
+        }
+
+        Ok(written)
+    }
+
+    /// Fold `source` points in `[from, until)` into `resolution` buckets.
+    async fn roll_up_range(
+        &self,
+        source: MetricsResolution,
+        resolution: MetricsResolution,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let mut folder = RollupFolder::new(resolution);
+        let mut after = None;
+        let mut written = 0;
+
+        loop {
+            let (page, next) = self
+                .query_metrics_page_at(source, &from, &until, after.as_ref(), METRICS_CHUNK_SIZE)
+                .await?;
+            for point in page.iter().filter(|point| point.timestamp < until) {
+                if let Some(bucket) = folder.push(point) {
+                    self.upsert_metric_rollup(resolution, &bucket).await?;
+                    written += 1;
+                }
+            }
+            match next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        if let Some(bucket) = folder.finish() {
+            self.upsert_metric_rollup(resolution, &bucket).await?;
+            written += 1;
+        }
+
+        Ok(written)
+    }
+
+    async fn upsert_metric_rollup(
+        &self,
+        resolution: MetricsResolution,
+        bucket: &MetricsSnapshot,
+    ) -> Result<(), sqlx::Error> {
+        let rollup = MetricsRollup::of(bucket);
+        let clamp = |value: u64| i64::try_from(value).unwrap_or(i64::MAX);
+
+        sqlx::query(&self.flavor.sql(
+            r#"
+            INSERT INTO metrics_rollups (
+                resolution_secs, bucket_start, samples,
+                active_sessions_min, active_sessions_max, active_sessions_sum,
+                total_sessions_min, total_sessions_max, total_sessions_sum,
+                bandwidth_min, bandwidth_max, bandwidth_sum
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(resolution_secs, bucket_start) DO UPDATE SET
+                samples = excluded.samples,
+                active_sessions_min = excluded.active_sessions_min,
+                active_sessions_max = excluded.active_sessions_max,
+                active_sessions_sum = excluded.active_sessions_sum,
+                total_sessions_min = excluded.total_sessions_min,
+                total_sessions_max = excluded.total_sessions_max,
+                total_sessions_sum = excluded.total_sessions_sum,
+                bandwidth_min = excluded.bandwidth_min,
+                bandwidth_max = excluded.bandwidth_max,
+                bandwidth_sum = excluded.bandwidth_sum
+            "#,
+        ))
+        .bind(resolution.bucket_secs().unwrap_or_default())
+        .bind(bucket.timestamp.to_rfc3339())
+        .bind(clamp(rollup.samples))
+        .bind(clamp(rollup.active_sessions.min))
+        .bind(clamp(rollup.active_sessions.max))
+        .bind(clamp(rollup.active_sessions.sum))
+        .bind(clamp(rollup.total_sessions.min))
+        .bind(clamp(rollup.total_sessions.max))
+        .bind(clamp(rollup.total_sessions.sum))
+        .bind(clamp(rollup.bandwidth.min))
+        .bind(clamp(rollup.bandwidth.max))
+        .bind(clamp(rollup.bandwidth.sum))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete rollup buckets older than `retention_days`.
+    pub async fn cleanup_old_metric_rollups(
+        &self,
+        retention_days: u64,
+    ) -> Result<u64, sqlx::Error> {
+        if retention_days == 0 {
+            return Ok(0);
+        }
+
+        let cutoff = Utc::now() - ChronoDuration::days(retention_days as i64);
+        let affected = sqlx::query(
+            &self
+                .flavor
+                .sql("DELETE FROM metrics_rollups WHERE bucket_start < ?"),
+        )
+        .bind(cutoff.to_rfc3339())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(affected)
+    }
+
+    /// Cleanup old metrics snapshots.
+    pub async fn cleanup_old_metrics(&self, retention_hours: u64) -> Result<u64, sqlx::Error> {
+        if retention_hours == 0 {
+            return Ok(0);
+        }
+
+        let cutoff = Utc::now() - ChronoDuration::hours(retention_hours as i64);
+
+        let affected = sqlx::query(&self.flavor.sql(
+            r#"
+            DELETE FROM metrics_snapshots
+            WHERE timestamp < ?;
+            "#,
+        ))
+        .bind(cutoff.to_rfc3339())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(affected)
+    }
+
+    /// Spawn background task to roll up and cleanup old metrics.
+    ///
+    /// Each run rolls raw snapshots up before deleting any, and skips the delete when
+    /// the rollup fails, so the raw and rollup tiers always meet.
+    pub fn spawn_metrics_cleanup(
+        self: &Arc<Self>,
+        retention_hours: u64,
+        rollup_retention_days: u64,
+        interval_hours: u64,
+    ) {
+        if retention_hours == 0 && rollup_retention_days == 0 {
+            info!("Metrics cleanup disabled (retention_hours = 0, rollup_retention_days = 0)");
+            return;
+        }
+
+        let interval_secs = interval_hours.max(1) * 3600;
+        let store = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(interval_secs));
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
             loop {
                 ticker.tick().await;
 
+                if rollup_retention_days > 0 {
+                    match store.roll_up_metrics(Utc::now()).await {
+                        Ok(written) => {
+                            if written > 0 {
+                                debug!(written, "Metrics rollup stored buckets");
+                            }
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "Metrics rollup failed, keeping raw metrics");
+                            continue;
+                        }
+                    }
+                    match store
+                        .cleanup_old_metric_rollups(rollup_retention_days)
+                        .await
+                    {
+                        Ok(affected) => {
+                            if affected > 0 {
+                                debug!(affected, "Metrics cleanup removed old rollups");
+                            }
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "Metrics rollup cleanup failed");
+                        }
+                    }
+                }
+
                 match store.cleanup_old_metrics(retention_hours).await {
                     Ok(affected) => {
                         if affected > 0 {
@@ -757,12 +1793,131 @@ impl SessionStore {
 
         info!(
             retention_hours,
-            interval_hours, "Metrics cleanup task started"
+            rollup_retention_days, interval_hours, "Metrics cleanup task started"
         );
     }
+
+    /// Load persisted ACL rule hit counters.
+    pub async fn load_acl_rule_stats(&self) -> Result<Vec<RuleStatsRecord>, sqlx::Error> {
+        let rows: Vec<AclRuleStatsRow> = sqlx::query_as(
+            r#"
+            SELECT rule_id, scope, action, hits, last_matched_at, tracked_since
+            FROM acl_rule_stats
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(AclRuleStatsRow::into_record).collect()
+    }
+
+    /// Upsert ACL rule hit counters; rows of rules not in `records` are kept.
+    pub async fn save_acl_rule_stats(
+        &self,
+        records: &[RuleStatsRecord],
+    ) -> Result<(), sqlx::Error> {
+        let updated_at = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+
+        for record in records {
+            sqlx::query(&self.flavor.sql(
+                r#"
+                INSERT INTO acl_rule_stats (
+                    rule_id, scope, action, hits, last_matched_at, tracked_since, updated_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(rule_id) DO UPDATE SET
+                    scope = excluded.scope,
+                    action = excluded.action,
+                    hits = excluded.hits,
+                    last_matched_at = excluded.last_matched_at,
+                    tracked_since = excluded.tracked_since,
+                    updated_at = excluded.updated_at
+                "#,
+            ))
+            .bind(&record.rule_id)
+            .bind(&record.scope)
+            .bind(acl_action_str(&record.action))
+            .bind(record.hits.min(i64::MAX as u64) as i64)
+            .bind(record.last_matched_at.map(|at| at.to_rfc3339()))
+            .bind(record.tracked_since.to_rfc3339())
+            .bind(&updated_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
 }
 
 impl SessionStore {
+    async fn register_instance(
+        &self,
+        instance_id: &str,
+        shared: bool,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(&self.flavor.sql(
+            r#"
+            INSERT INTO store_instances (
+                instance_id, hostname, pid, shared, started_at, heartbeat_at
+            ) VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        ))
+        .bind(instance_id.to_string())
+        .bind(local_hostname())
+        .bind(i64::from(std::process::id()))
+        .bind(i64::from(shared))
+        .bind(now.to_rfc3339())
+        .bind(now.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn registered_instances(&self) -> Result<Vec<StoreInstanceRow>, sqlx::Error> {
+        sqlx::query_as::<_, StoreInstanceRow>(
+            "SELECT instance_id, hostname, pid, shared, heartbeat_at FROM store_instances",
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn delete_instance(&self, instance_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            &self
+                .flavor
+                .sql("DELETE FROM store_instances WHERE instance_id = ?"),
+        )
+        .bind(instance_id.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Other instances with a fresh heartbeat whose sessions this one must not touch;
+    /// always empty unless the store runs shared.
+    async fn live_peer_instances(&self) -> Result<Vec<String>, sqlx::Error> {
+        let Some(lock) = self.lock.get().filter(|lock| lock.shared) else {
+            return Ok(Vec::new());
+        };
+        let own_id = lock.instance_id.to_string();
+        let stale_after = lock.stale_threshold();
+        let now = Utc::now();
+
+        let mut peers = Vec::new();
+        for row in self.registered_instances().await? {
+            if row.instance_id == own_id {
+                continue;
+            }
+            let peer = row.into_holder()?;
+            if now - peer.heartbeat_at <= stale_after {
+                peers.push(peer.instance_id);
+            }
+        }
+        Ok(peers)
+    }
+
     async fn configure_journal_mode(pool: &AnyPool) -> Result<(), sqlx::Error> {
         let wal_enabled = match sqlx::query_scalar::<_, String>("PRAGMA journal_mode = WAL")
             .fetch_one(pool)
@@ -962,10 +2117,12 @@ impl DatabaseFlavor {
             })
         } else if lower.starts_with("mysql://") || lower.starts_with("mariadb://") {
             Ok(Self::MariaDb)
+        } else if lower.starts_with("postgres://") || lower.starts_with("postgresql://") {
+            Ok(Self::Postgres)
         } else {
             Err(sqlx::Error::Configuration(
                 format!(
-                    "Unsupported session database URL: {}. Supported schemes: sqlite://..., mysql://..., mariadb://...",
+                    "Unsupported session database URL: {}. Supported schemes: sqlite://..., mysql://..., mariadb://..., postgres://...",
                     normalized
                 )
                 .into(),
@@ -997,12 +2154,207 @@ impl DatabaseFlavor {
         )
     }
 
+    /// SQL for the start of the `bucket_secs` bucket holding an RFC3339 UTC `column`,
+    /// in seconds since the Unix epoch.
+    fn epoch_bucket(&self, column: &str, bucket_secs: u32) -> String {
+        match self {
+            DatabaseFlavor::Sqlite { .. } => format!(
+                "(CAST(strftime('%s', {column}) AS INTEGER) / {bucket_secs}) * {bucket_secs}"
+            ),
+            // Parsing only the first 19 characters drops the offset, which is always
+            // +00:00 here, and accepts legacy timestamps with a space separator.
+            // TIMESTAMPDIFF against the epoch avoids UNIX_TIMESTAMP's session time zone.
+            DatabaseFlavor::MariaDb => format!(
+                "(TIMESTAMPDIFF(SECOND, '1970-01-01 00:00:00', \
+                 STR_TO_DATE(REPLACE(LEFT({column}, 19), 'T', ' '), '%Y-%m-%d %H:%i:%s')) \
+                 DIV {bucket_secs}) * {bucket_secs}"
+            ),
+            DatabaseFlavor::Postgres => format!(
+                "(CAST(EXTRACT(EPOCH FROM CAST({column} AS TIMESTAMPTZ)) AS BIGINT) \
+                 / {bucket_secs}) * {bucket_secs}"
+            ),
+        }
+    }
+
+    /// SQL for the whole seconds from an RFC3339 `column` to a bound RFC3339 timestamp
+    fn seconds_since(&self, column: &str) -> String {
+        match self {
+            DatabaseFlavor::Sqlite { .. } => {
+                format!("CAST((julianday(?) - julianday({column})) * 86400 AS INTEGER)")
+            }
+            DatabaseFlavor::MariaDb => format!(
+                "TIMESTAMPDIFF(SECOND, \
+                 STR_TO_DATE(REPLACE(LEFT({column}, 19), 'T', ' '), '%Y-%m-%d %H:%i:%s'), \
+                 STR_TO_DATE(REPLACE(LEFT(?, 19), 'T', ' '), '%Y-%m-%d %H:%i:%s'))"
+            ),
+            DatabaseFlavor::Postgres => format!(
+                "CAST(EXTRACT(EPOCH FROM CAST(? AS TIMESTAMPTZ) - CAST({column} AS TIMESTAMPTZ)) \
+                 AS BIGINT)"
+            ),
+        }
+    }
+
+    /// Type name for casting aggregates back to 64-bit integers
+    fn integer_type(&self) -> &'static str {
+        match self {
+            DatabaseFlavor::Sqlite { .. } => "INTEGER",
+            DatabaseFlavor::MariaDb => "SIGNED",
+            DatabaseFlavor::Postgres => "BIGINT",
+        }
+    }
+
     fn connection_url<'a>(&'a self, original: &'a str) -> &'a str {
         match self {
             DatabaseFlavor::Sqlite { connect_url, .. } => connect_url,
-            DatabaseFlavor::MariaDb => original,
+            DatabaseFlavor::MariaDb | DatabaseFlavor::Postgres => original,
+        }
+    }
+
+    /// Statement text for this database.
+    ///
+    /// The store writes `?` placeholders, which the Any driver passes through as is.
+    /// PostgreSQL numbers them instead (`$1`, `$2`, ...) and reserves `user`, the
+    /// column holding the session's user, so both are rewritten there.
+    fn sql<'q>(&self, statement: &'q str) -> Cow<'q, str> {
+        match self {
+            DatabaseFlavor::Postgres => Cow::Owned(postgres_statement(statement)),
+            _ => Cow::Borrowed(statement),
+        }
+    }
+
+    /// Statement and arguments of a finished `builder`, the statement passed
+    /// through [`DatabaseFlavor::sql`].
+    fn finish<'b>(
+        &self,
+        builder: &'b mut QueryBuilder<'_, Any>,
+    ) -> Result<(String, AnyArguments<'b>), sqlx::Error> {
+        let statement = self.sql(builder.sql()).into_owned();
+        let arguments = builder
+            .build()
+            .take_arguments()
+            .map_err(sqlx::Error::Encode)?
+            .unwrap_or_default();
+        Ok((statement, arguments))
+    }
+}
+
+/// Number `?` placeholders and quote the `user` identifier, leaving string literals,
+/// quoted identifiers and `--` comments alone.
+fn postgres_statement(statement: &str) -> String {
+    let mut out = String::with_capacity(statement.len() + 16);
+    let mut placeholders = 0;
+    let mut chars = statement.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                out.push(c);
+                for inner in chars.by_ref() {
+                    out.push(inner);
+                    if inner == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                out.push(c);
+                for inner in chars.by_ref() {
+                    out.push(inner);
+                    if inner == '\n' {
+                        break;
+                    }
+                }
+            }
+            '?' => {
+                placeholders += 1;
+                out.push('$');
+                out.push_str(&placeholders.to_string());
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_ascii_alphanumeric() || next == '_') {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                if word == "user" {
+                    out.push_str("\"user\"");
+                } else {
+                    out.push_str(&word);
+                }
+            }
+            _ => out.push(c),
         }
     }
+    out
+}
+
+#[derive(Debug, FromRow)]
+struct StoreInstanceRow {
+    instance_id: String,
+    hostname: String,
+    pid: i64,
+    shared: i64,
+    heartbeat_at: String,
+}
+
+impl StoreInstanceRow {
+    fn into_holder(self) -> Result<InstanceLockHolder, sqlx::Error> {
+        Ok(InstanceLockHolder {
+            heartbeat_at: parse_datetime("heartbeat_at", &self.heartbeat_at)?,
+            instance_id: self.instance_id,
+            hostname: self.hostname,
+            pid: self.pid,
+            shared: self.shared != 0,
+        })
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct AclRuleStatsRow {
+    rule_id: String,
+    scope: String,
+    action: String,
+    hits: i64,
+    last_matched_at: Option<String>,
+    tracked_since: String,
+}
+
+impl AclRuleStatsRow {
+    fn into_record(self) -> Result<RuleStatsRecord, sqlx::Error> {
+        let action = match self.action.as_str() {
+            "allow" => AclAction::Allow,
+            "block" => AclAction::Block,
+            other => {
+                return Err(decode_error(
+                    "action",
+                    format!("unknown action '{}'", other),
+                ))
+            }
+        };
+
+        Ok(RuleStatsRecord {
+            rule_id: self.rule_id,
+            scope: self.scope,
+            action,
+            hits: self.hits.max(0) as u64,
+            last_matched_at: self
+                .last_matched_at
+                .as_deref()
+                .map(|value| parse_datetime("last_matched_at", value))
+                .transpose()?,
+            tracked_since: parse_datetime("tracked_since", &self.tracked_since)?,
+        })
+    }
+}
+
+fn acl_action_str(action: &AclAction) -> &'static str {
+    match action {
+        AclAction::Allow => "allow",
+        AclAction::Block => "block",
+    }
 }
 
 #[derive(Debug, FromRow)]
@@ -1022,11 +2374,103 @@ impl MetricSnapshotRow {
             active_sessions: self.active_sessions as u64,
             total_sessions: self.total_sessions as u64,
             bandwidth: self.bandwidth as u64,
+            rollup: None,
+        })
+    }
+}
+
+use super::history::{
+    MetricAggregate, MetricsResolution, MetricsRollup, MetricsSnapshot, RollupFolder,
+    METRICS_CHUNK_SIZE,
+};
+
+#[derive(Debug, FromRow)]
+struct MetricSnapshotPageRow {
+    id: i64,
+    timestamp: String,
+    active_sessions: i64,
+    total_sessions: i64,
+    bandwidth: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct MetricRollupRow {
+    id: i64,
+    bucket_start: String,
+    samples: i64,
+    active_sessions_min: i64,
+    active_sessions_max: i64,
+    active_sessions_sum: i64,
+    total_sessions_min: i64,
+    total_sessions_max: i64,
+    total_sessions_sum: i64,
+    bandwidth_min: i64,
+    bandwidth_max: i64,
+    bandwidth_sum: i64,
+}
+
+impl MetricRollupRow {
+    fn into_metric(self) -> Result<MetricsSnapshot, sqlx::Error> {
+        let samples = self.samples.max(0) as u64;
+        let aggregate = |min: i64, max: i64, sum: i64| {
+            MetricAggregate::from_parts(
+                min.max(0) as u64,
+                max.max(0) as u64,
+                sum.max(0) as u64,
+                samples,
+            )
+        };
+        Ok(MetricsSnapshot::rolled_up(
+            parse_datetime("bucket_start", &self.bucket_start)?,
+            MetricsRollup {
+                samples,
+                active_sessions: aggregate(
+                    self.active_sessions_min,
+                    self.active_sessions_max,
+                    self.active_sessions_sum,
+                ),
+                total_sessions: aggregate(
+                    self.total_sessions_min,
+                    self.total_sessions_max,
+                    self.total_sessions_sum,
+                ),
+                bandwidth: aggregate(self.bandwidth_min, self.bandwidth_max, self.bandwidth_sum),
+            },
+        ))
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct DestinationBucketRow {
+    bucket_start: i64,
+    dest_ip: String,
+    dest_port: i64,
+    connections: i64,
+    bytes_sent: i64,
+    bytes_received: i64,
+}
+
+impl DestinationBucketRow {
+    fn into_bucket(self) -> Result<DestinationBucket, sqlx::Error> {
+        let bucket_start = DateTime::from_timestamp(self.bucket_start, 0).ok_or_else(|| {
+            sqlx::Error::Decode(format!("invalid bucket_start {}", self.bucket_start).into())
+        })?;
+        Ok(DestinationBucket {
+            bucket_start,
+            dest: format!("{}:{}", self.dest_ip, self.dest_port),
+            connections: self.connections as u64,
+            bytes_sent: self.bytes_sent.max(0) as u64,
+            bytes_received: self.bytes_received.max(0) as u64,
         })
     }
 }
 
-use super::history::MetricsSnapshot;
+/// Position after the last row of a metrics page.
+#[derive(Debug, Clone)]
+pub struct MetricsPageCursor {
+    timestamp: String,
+    id: i64,
+}
 
 #[derive(Debug, FromRow)]
 struct SessionRow {
@@ -1048,6 +2492,34 @@ struct SessionRow {
     close_reason: Option<String>,
     acl_rule_matched: Option<String>,
     acl_decision: String,
+    sni_host: Option<String>,
+    requested_host: Option<String>,
+    requested_host_source: Option<String>,
+    authenticated_user: Option<String>,
+    /// NULL only for rows written before migration 011 and not backfilled
+    instance_id: Option<String>,
+    keepalive_probes: i64,
+    udp_association_mode: Option<String>,
+    udp_client_endpoint: Option<String>,
+    udp_rejected_datagrams: i64,
+    udp_throttled_datagrams: i64,
+    correlation_id: Option<String>,
+    /// NULL for rows written before migration 018
+    command: Option<String>,
+    socks_version: i64,
+    chained: i64,
+    /// NULL only for rows written before migration 023 and not backfilled
+    dest_host: Option<String>,
+    egress_ip: Option<String>,
+    would_block: i64,
+    /// NULL for rows written before migration 027
+    reply_code: Option<i64>,
+    failure: Option<String>,
+    /// NULL for rows written before migration 028; comma-separated otherwise
+    resolved_ips: Option<String>,
+    connected_ip: Option<String>,
+    acl_matched_on: Option<String>,
+    upstream_tls: i64,
 }
 
 #[derive(Debug, FromRow)]
@@ -1055,10 +2527,20 @@ struct SessionIdRow {
     session_id: String,
 }
 
+#[derive(Debug, FromRow)]
+struct SessionGroupRow {
+    session_id: String,
+    group_name: String,
+}
+
 impl SessionRow {
     fn into_session(self) -> Result<Session, sqlx::Error> {
         let session_id =
             Uuid::parse_str(&self.session_id).map_err(|e| decode_error("session_id", e))?;
+        let instance_id = match self.instance_id.as_deref() {
+            Some(id) => Uuid::parse_str(id).map_err(|e| decode_error("instance_id", e))?,
+            None => Uuid::nil(),
+        };
         let start_time = parse_datetime("start_time", &self.start_time)?;
         let end_time = match self.end_time {
             Some(ref ts) => Some(parse_datetime("end_time", ts)?),
@@ -1080,25 +2562,125 @@ impl SessionRow {
             .parse()
             .map_err(|e| decode_error("source_ip", e))?;
 
+        let requested_host_source = self
+            .requested_host_source
+            .as_deref()
+            .map(str::parse::<HostSource>)
+            .transpose()
+            .map_err(|e| decode_error("requested_host_source", e))?;
+
+        let udp_association_mode = self
+            .udp_association_mode
+            .as_deref()
+            .map(str::parse::<UdpAssociationMode>)
+            .transpose()
+            .map_err(|e| decode_error("udp_association_mode", e))?;
+
+        let udp_client_endpoint = self
+            .udp_client_endpoint
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e| decode_error("udp_client_endpoint", e))?;
+
+        let egress_ip = self
+            .egress_ip
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e| decode_error("egress_ip", e))?;
+
+        let command = match self.command.as_deref() {
+            Some(command) => command
+                .parse::<SessionCommand>()
+                .map_err(|e| decode_error("command", e))?,
+            None => SessionCommand::for_protocol(protocol),
+        };
+
+        let failure = self
+            .failure
+            .as_deref()
+            .map(str::parse::<FailureCategory>)
+            .transpose()
+            .map_err(|e| decode_error("failure", e))?;
+
+        let resolved_ips = self
+            .resolved_ips
+            .as_deref()
+            .filter(|list| !list.is_empty())
+            .map(|list| {
+                list.split(',')
+                    .map(str::parse)
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()
+            .map_err(|e| decode_error("resolved_ips", e))?
+            .unwrap_or_default();
+
+        let connected_ip = self
+            .connected_ip
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e| decode_error("connected_ip", e))?;
+
+        let acl_matched_on = self
+            .acl_matched_on
+            .as_deref()
+            .map(str::parse::<AclMatchedOn>)
+            .transpose()
+            .map_err(|e| decode_error("acl_matched_on", e))?;
+
+        let dest_host = match self.dest_host {
+            Some(host) => Arc::from(host),
+            None => dest_host_key(&self.dest_ip),
+        };
+
         Ok(Session {
             session_id,
+            instance_id,
             user: self.user.into(),
+            authenticated_user: self.authenticated_user.map(Arc::from),
+            correlation_id: self.correlation_id.map(Arc::from),
+            // Kept in `session_groups`, see `SessionStore::load_groups`
+            groups: Vec::new(),
             start_time,
             end_time,
             duration_secs: sanitize_duration(self.duration_secs),
             source_ip,
             source_port: self.source_port as u16,
             dest_ip: self.dest_ip.into(),
+            dest_host,
             dest_port: self.dest_port as u16,
             protocol,
+            command,
+            socks_version: self.socks_version as u8,
+            chained: self.chained != 0,
+            egress_ip,
+            resolved_ips,
+            connected_ip,
+            sni_host: self.sni_host.map(Arc::from),
+            requested_host: self.requested_host.map(Arc::from),
+            requested_host_source,
             bytes_sent: self.bytes_sent as u64,
             bytes_received: self.bytes_received as u64,
             packets_sent: self.packets_sent as u64,
             packets_received: self.packets_received as u64,
+            transfer_rate: Default::default(),
+            keepalive_probes: self.keepalive_probes as u64,
+            udp_association_mode,
+            udp_client_endpoint,
+            udp_rejected_datagrams: self.udp_rejected_datagrams as u64,
+            udp_throttled_datagrams: self.udp_throttled_datagrams as u64,
             status,
             close_reason: self.close_reason,
             acl_rule_matched: self.acl_rule_matched.map(Arc::from),
             acl_decision: self.acl_decision.into(),
+            would_block: self.would_block != 0,
+            acl_matched_on,
+            upstream_tls: self.upstream_tls != 0,
+            reply_code: self.reply_code.map(|code| code as u8),
+            failure,
         })
     }
 }
@@ -1122,6 +2704,29 @@ struct SessionParams<'a> {
     close_reason: Option<String>,
     acl_rule_matched: Option<String>,
     acl_decision: Cow<'a, str>,
+    sni_host: Option<Cow<'a, str>>,
+    requested_host: Option<Cow<'a, str>>,
+    requested_host_source: Option<&'static str>,
+    authenticated_user: Option<Cow<'a, str>>,
+    instance_id: Cow<'a, str>,
+    keepalive_probes: i64,
+    udp_association_mode: Option<&'static str>,
+    udp_client_endpoint: Option<String>,
+    udp_rejected_datagrams: i64,
+    udp_throttled_datagrams: i64,
+    correlation_id: Option<Cow<'a, str>>,
+    command: &'static str,
+    socks_version: i64,
+    chained: i64,
+    dest_host: Cow<'a, str>,
+    egress_ip: Option<String>,
+    would_block: i64,
+    reply_code: Option<i64>,
+    failure: Option<&'static str>,
+    resolved_ips: Option<String>,
+    connected_ip: Option<String>,
+    acl_matched_on: Option<&'static str>,
+    upstream_tls: i64,
 }
 
 impl<'a> From<&'a Session> for SessionParams<'a> {
@@ -1145,6 +2750,36 @@ impl<'a> From<&'a Session> for SessionParams<'a> {
             close_reason: session.close_reason.clone(),
             acl_rule_matched: session.acl_rule_matched.as_ref().map(|s| s.to_string()),
             acl_decision: Cow::Borrowed(session.acl_decision.as_ref()),
+            sni_host: session.sni_host.as_deref().map(Cow::Borrowed),
+            requested_host: session.requested_host.as_deref().map(Cow::Borrowed),
+            requested_host_source: session.requested_host_source.map(|s| s.as_str()),
+            authenticated_user: session.authenticated_user.as_deref().map(Cow::Borrowed),
+            instance_id: Cow::Owned(session.instance_id.to_string()),
+            keepalive_probes: session.keepalive_probes as i64,
+            udp_association_mode: session.udp_association_mode.map(|mode| mode.as_str()),
+            udp_client_endpoint: session.udp_client_endpoint.map(|addr| addr.to_string()),
+            udp_rejected_datagrams: session.udp_rejected_datagrams as i64,
+            udp_throttled_datagrams: session.udp_throttled_datagrams as i64,
+            correlation_id: session.correlation_id.as_deref().map(Cow::Borrowed),
+            command: session.command.as_str(),
+            socks_version: session.socks_version as i64,
+            chained: session.chained as i64,
+            dest_host: Cow::Borrowed(session.dest_host.as_ref()),
+            egress_ip: session.egress_ip.map(|ip| ip.to_string()),
+            would_block: session.would_block as i64,
+            reply_code: session.reply_code.map(i64::from),
+            failure: session.failure.map(FailureCategory::as_str),
+            resolved_ips: (!session.resolved_ips.is_empty()).then(|| {
+                session
+                    .resolved_ips
+                    .iter()
+                    .map(IpAddr::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }),
+            connected_ip: session.connected_ip.map(|ip| ip.to_string()),
+            acl_matched_on: session.acl_matched_on.map(|on| on.as_str()),
+            upstream_tls: session.upstream_tls as i64,
         }
     }
 }
@@ -1164,10 +2799,59 @@ fn parse_legacy_timestamp(value: &str) -> Result<DateTime<Utc>, chrono::format::
         .map(|naive| naive.and_utc())
 }
 
+/// Quote `%`, `_` and the escape character itself for `LIKE ... ESCAPE '!'`
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '!') {
+            escaped.push('!');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 fn sanitize_duration(value: Option<i64>) -> Option<u64> {
     value.and_then(|v| if v >= 0 { Some(v as u64) } else { None })
 }
 
+fn duplicate_session_id(session_id: &str) -> sqlx::Error {
+    sqlx::Error::Protocol(format!(
+        "session_id {} already belongs to another session",
+        session_id
+    ))
+}
+
+/// `AND` clause keeping `count` bound instance IDs out of a session statement; rows
+/// without an instance predate migration 011 and belong to nobody.
+fn exclude_instances(count: usize) -> String {
+    if count == 0 {
+        return String::new();
+    }
+    format!(
+        " AND (instance_id IS NULL OR instance_id NOT IN ({}))",
+        vec!["?"; count].join(", ")
+    )
+}
+
+/// Name of this host for the lock table, so a refused start can say where the other
+/// instance runs.
+fn local_hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        // SAFETY: gethostname writes at most buf.len() bytes into the buffer
+        let rc = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+        if rc == 0 {
+            let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+    }
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
 fn decode_error(
     field: &str,
     err: impl Into<Box<dyn std::error::Error + Send + Sync>>,
@@ -1181,19 +2865,27 @@ fn decode_error(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::session::{ConnectionInfo, SessionProtocol};
+    use crate::session::{ConnectionInfo, MetricsCursor, SessionProtocol};
     use chrono::SecondsFormat;
     use std::net::{IpAddr, Ipv4Addr};
 
-    fn test_session() -> Session {
-        let conn = ConnectionInfo {
+    fn test_session_connection() -> ConnectionInfo {
+        ConnectionInfo {
             source_ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)),
             source_port: 5000,
-            dest_ip: "example.com".to_string(),
+            dest_ip: "example.com".into(),
             dest_port: 443,
             protocol: SessionProtocol::Tcp,
-        };
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
+        }
+    }
 
+    fn test_session() -> Session {
+        let conn = test_session_connection();
         let mut session = Session::new("alice", conn, "allow", Some("Allow HTTPS".into()));
         session.bytes_sent = 2048;
         session.bytes_received = 1024;
@@ -1236,6 +2928,1005 @@ mod tests {
         assert!(results[0].end_time.is_some());
     }
 
+    #[tokio::test]
+    async fn sni_host_round_trips() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut classified = test_session();
+        classified.sni_host = Some(Arc::from("api.example.com"));
+        let plain = test_session();
+
+        store.insert_session(&classified).await.unwrap();
+        store.save_batch(vec![plain.clone()]).await.unwrap();
+
+        let loaded = store
+            .get_session(&classified.session_id)
+            .await
+            .unwrap()
+            .expect("classified session");
+        assert_eq!(loaded.sni_host.as_deref(), Some("api.example.com"));
+
+        let loaded = store
+            .get_session(&plain.session_id)
+            .await
+            .unwrap()
+            .expect("plain session");
+        assert_eq!(loaded.sni_host, None);
+
+        // Upserts keep the name, and queries return it too
+        classified.close(Some("Finished".into()), SessionStatus::Closed);
+        store.save_batch(vec![classified.clone()]).await.unwrap();
+        let results = store
+            .query_sessions(&SessionFilter {
+                status: Some(SessionStatus::Closed),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].sni_host.as_deref(), Some("api.example.com"));
+    }
+
+    #[tokio::test]
+    async fn metrics_pages_cover_duplicate_timestamps_in_order() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        let base = Utc::now() - ChronoDuration::hours(1);
+        // Offsets in seconds; three samples share t=2 so one page boundary splits them
+        let offsets = [0, 1, 2, 2, 2, 3, 4, 10];
+        for (i, offset) in offsets.iter().enumerate() {
+            store
+                .insert_metric(&(base + ChronoDuration::seconds(*offset)), i as u64, 0, 0)
+                .await
+                .unwrap();
+        }
+
+        let start = base + ChronoDuration::seconds(1);
+        let end = base + ChronoDuration::seconds(4);
+        let mut seen = Vec::new();
+        let mut after = None;
+        loop {
+            let (page, next) = store
+                .query_metrics_page(&start, &end, after.as_ref(), 2)
+                .await
+                .unwrap();
+            assert!(page.len() <= 2);
+            seen.extend(page.iter().map(|s| s.active_sessions));
+            match next {
+                Some(cursor) => after = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn dest_host_filters_exact_and_by_suffix() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        let session = |dest: &str| {
+            let mut conn = test_session_connection();
+            conn.dest_ip = dest.into();
+            Session::new("alice", conn, "allow", None)
+        };
+        let sessions = [
+            session("WWW.Example.com"),
+            session("a.b.example.com"),
+            session("example.com"),
+            session("badexample.com"),
+            session("192.0.2.10"),
+        ];
+        store.save_batch(sessions.to_vec()).await.unwrap();
+
+        let hosts = |pattern: &str| {
+            let filter = SessionFilter {
+                dest_host: Some(DestHostPattern::parse(pattern).unwrap()),
+                sort_by: Some("dest_host".to_string()),
+                sort_dir: Some("asc".to_string()),
+                ..Default::default()
+            };
+            let store = &store;
+            async move {
+                let found = store.query_sessions(&filter).await.unwrap();
+                assert_eq!(
+                    store.count_sessions(&filter).await.unwrap(),
+                    found.len() as u64
+                );
+                found
+                    .into_iter()
+                    .map(|s| s.dest_host.to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(
+            hosts("*.example.com").await,
+            vec!["a.b.example.com", "www.example.com"]
+        );
+        assert_eq!(hosts("www.EXAMPLE.com").await, vec!["www.example.com"]);
+        assert_eq!(hosts("192.0.2.10").await, vec!["192.0.2.10"]);
+        // LIKE wildcards in the pattern are literal
+        assert!(hosts("*._xample.com").await.is_empty());
+
+        // Rows written before migration 023 fall back to dest_ip
+        sqlx::query("UPDATE sessions SET dest_host = NULL")
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        let loaded = store
+            .get_session(&sessions[0].session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.dest_ip.as_ref(), "WWW.Example.com");
+        assert_eq!(loaded.dest_host.as_ref(), "www.example.com");
+    }
+
+    #[tokio::test]
+    async fn requested_host_round_trips_and_filters() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut mapped = test_session();
+        mapped.dest_ip = "192.0.2.10".into();
+        mapped.requested_host = Some(Arc::from("app.example"));
+        mapped.requested_host_source = Some(HostSource::ReverseMap);
+        let named = test_session();
+
+        store.insert_session(&mapped).await.unwrap();
+        store.save_batch(vec![named.clone()]).await.unwrap();
+
+        let loaded = store
+            .get_session(&mapped.session_id)
+            .await
+            .unwrap()
+            .expect("mapped session");
+        assert_eq!(loaded.requested_host.as_deref(), Some("app.example"));
+        assert_eq!(loaded.requested_host_source, Some(HostSource::ReverseMap));
+
+        let loaded = store
+            .get_session(&named.session_id)
+            .await
+            .unwrap()
+            .expect("named session");
+        assert_eq!(loaded.requested_host.as_deref(), Some("example.com"));
+        assert_eq!(loaded.requested_host_source, Some(HostSource::SocksRequest));
+
+        let filter = SessionFilter {
+            requested_host_source: Some(HostSource::ReverseMap),
+            ..Default::default()
+        };
+        let results = store.query_sessions(&filter).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].session_id, mapped.session_id);
+        assert_eq!(store.count_sessions(&filter).await.unwrap(), 1);
+
+        let results = store
+            .query_sessions(&SessionFilter {
+                requested_host: Some("example.com".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].session_id, named.session_id);
+    }
+
+    #[tokio::test]
+    async fn authenticated_user_round_trips_and_filters() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut impersonated = test_session();
+        impersonated.authenticated_user = Some(Arc::from("svc-report"));
+        let mut direct = test_session();
+        direct.authenticated_user = Some(Arc::from("alice"));
+
+        store.insert_session(&impersonated).await.unwrap();
+        store.save_batch(vec![direct.clone()]).await.unwrap();
+
+        let loaded = store
+            .get_session(&impersonated.session_id)
+            .await
+            .unwrap()
+            .expect("impersonated session");
+        assert_eq!(loaded.user.as_ref(), "alice");
+        assert_eq!(loaded.authenticated_user.as_deref(), Some("svc-report"));
+
+        let filter = SessionFilter {
+            user: Some("alice".into()),
+            authenticated_user: Some("svc-report".into()),
+            ..Default::default()
+        };
+        let results = store.query_sessions(&filter).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].session_id, impersonated.session_id);
+        assert_eq!(store.count_sessions(&filter).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn correlation_id_round_trips_and_filters() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut traced = test_session();
+        traced.correlation_id = Some(Arc::from("wf-12345"));
+        let untraced = test_session();
+
+        store.insert_session(&traced).await.unwrap();
+        store.save_batch(vec![untraced.clone()]).await.unwrap();
+
+        let loaded = store
+            .get_session(&traced.session_id)
+            .await
+            .unwrap()
+            .expect("traced session");
+        assert_eq!(loaded.correlation_id.as_deref(), Some("wf-12345"));
+        let loaded = store
+            .get_session(&untraced.session_id)
+            .await
+            .unwrap()
+            .expect("untraced session");
+        assert!(loaded.correlation_id.is_none());
+
+        let filter = SessionFilter {
+            correlation_id: Some("wf-12345".into()),
+            ..Default::default()
+        };
+        let results = store.query_sessions(&filter).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].session_id, traced.session_id);
+        assert_eq!(store.count_sessions(&filter).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn session_command_round_trips() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut bind = test_session();
+        bind.command = SessionCommand::Bind;
+        let connect = test_session();
+        store.insert_session(&bind).await.unwrap();
+        store.save_batch(vec![connect.clone()]).await.unwrap();
+
+        let loaded = store.get_session(&bind.session_id).await.unwrap().unwrap();
+        assert_eq!(loaded.command, SessionCommand::Bind);
+        let loaded = store
+            .get_session(&connect.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.command, SessionCommand::Connect);
+
+        // Rows written before migration 018 fall back to the protocol's command
+        sqlx::query("UPDATE sessions SET command = NULL")
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        let loaded = store.get_session(&bind.session_id).await.unwrap().unwrap();
+        assert_eq!(loaded.command, SessionCommand::Connect);
+    }
+
+    #[tokio::test]
+    async fn egress_ip_round_trips() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut bound = test_session();
+        bound.egress_ip = Some("2001:db8::20".parse().unwrap());
+        let unbound = test_session();
+        store.insert_session(&bound).await.unwrap();
+        store.save_batch(vec![unbound.clone()]).await.unwrap();
+
+        let loaded = store.get_session(&bound.session_id).await.unwrap().unwrap();
+        assert_eq!(loaded.egress_ip, bound.egress_ip);
+        let loaded = store
+            .get_session(&unbound.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.egress_ip, None);
+    }
+
+    #[tokio::test]
+    async fn would_block_round_trips() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut monitored = test_session();
+        monitored.would_block = true;
+        let enforced = test_session();
+        store.insert_session(&monitored).await.unwrap();
+        store.save_batch(vec![enforced.clone()]).await.unwrap();
+
+        let loaded = store
+            .get_session(&monitored.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(loaded.would_block);
+        let loaded = store
+            .get_session(&enforced.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!loaded.would_block);
+    }
+
+    #[tokio::test]
+    async fn upstream_tls_round_trips() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut wrapped = test_session();
+        wrapped.upstream_tls = true;
+        let plain = test_session();
+        store.insert_session(&wrapped).await.unwrap();
+        store.save_batch(vec![plain.clone()]).await.unwrap();
+
+        let loaded = store
+            .get_session(&wrapped.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(loaded.upstream_tls);
+        let loaded = store.get_session(&plain.session_id).await.unwrap().unwrap();
+        assert!(!loaded.upstream_tls);
+    }
+
+    #[tokio::test]
+    async fn destination_resolution_round_trips() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut resolved = test_session();
+        resolved.resolved_ips = vec![
+            "192.0.2.10".parse().unwrap(),
+            "2001:db8::10".parse().unwrap(),
+        ];
+        resolved.connected_ip = Some("2001:db8::10".parse().unwrap());
+        resolved.acl_matched_on = Some(AclMatchedOn::ResolvedIp);
+        let literal = test_session();
+        store.insert_session(&resolved).await.unwrap();
+        store.save_batch(vec![literal.clone()]).await.unwrap();
+
+        let loaded = store
+            .get_session(&resolved.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.resolved_ips, resolved.resolved_ips);
+        assert_eq!(loaded.connected_ip, resolved.connected_ip);
+        assert_eq!(loaded.acl_matched_on, Some(AclMatchedOn::ResolvedIp));
+        let loaded = store
+            .get_session(&literal.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(loaded.resolved_ips.is_empty());
+        assert_eq!(loaded.connected_ip, None);
+        assert_eq!(loaded.acl_matched_on, None);
+    }
+
+    #[tokio::test]
+    async fn reply_code_and_failure_round_trip() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let granted = test_session();
+        let mut failed = test_session();
+        failed.reply_code = Some(0x04);
+        failed.failure = Some(FailureCategory::ConnectTimeout);
+        store.insert_session(&granted).await.unwrap();
+        store.save_batch(vec![failed.clone()]).await.unwrap();
+
+        let loaded = store
+            .get_session(&granted.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.reply_code, Some(0));
+        assert_eq!(loaded.failure, None);
+        let loaded = store
+            .get_session(&failed.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.reply_code, Some(0x04));
+        assert_eq!(loaded.failure, Some(FailureCategory::ConnectTimeout));
+    }
+
+    #[tokio::test]
+    async fn groups_round_trip_and_expire_with_their_session() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut grouped = test_session();
+        grouped.groups = vec![Arc::from("engineering"), Arc::from("vpn-users")];
+        let mut batched = test_session();
+        batched.groups = vec![Arc::from("sales")];
+        let ungrouped = test_session();
+        store.insert_session(&grouped).await.unwrap();
+        // Updates write the same rows again
+        store.update_session(&grouped).await.unwrap();
+        store
+            .save_batch(vec![batched.clone(), ungrouped.clone()])
+            .await
+            .unwrap();
+
+        let loaded = store
+            .get_session(&grouped.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.groups, grouped.groups);
+        let mut all = store
+            .query_sessions(&SessionFilter::default())
+            .await
+            .unwrap();
+        all.sort_by_key(|session| session.session_id);
+        let groups: Vec<_> = all.iter().map(|session| session.groups.clone()).collect();
+        assert_eq!(groups, vec![grouped.groups, batched.groups, Vec::new()]);
+
+        let long_ago = Utc::now() - ChronoDuration::days(40);
+        let mut expired = test_session();
+        expired.groups = vec![Arc::from("engineering")];
+        expired.start_time = long_ago;
+        expired.close(None, SessionStatus::Closed);
+        expired.end_time = Some(long_ago);
+        store.insert_session(&expired).await.unwrap();
+        assert_eq!(store.cleanup_older_than(30, 5000).await.unwrap(), 1);
+
+        let (rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM session_groups")
+            .fetch_one(store.pool())
+            .await
+            .unwrap();
+        assert_eq!(rows, 3);
+    }
+
+    #[tokio::test]
+    async fn duplicated_session_id_is_rejected() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+
+        let mut original = test_session();
+        store.insert_session(&original).await.unwrap();
+
+        // Same session again (normal progress updates) is fine
+        original.bytes_sent += 1;
+        store.update_session(&original).await.unwrap();
+
+        // Another boot reusing the ID must not overwrite the row
+        let mut impostor = test_session();
+        impostor.session_id = original.session_id;
+        impostor.instance_id = Uuid::new_v4();
+        impostor.user = Arc::from("mallory");
+        let err = store.insert_session(&impostor).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("already belongs to another session"));
+
+        // Batches skip the collision and keep the rest
+        let other = test_session();
+        store
+            .save_batch(vec![impostor.clone(), other.clone()])
+            .await
+            .unwrap();
+
+        let loaded = store
+            .get_session(&original.session_id)
+            .await
+            .unwrap()
+            .expect("original session");
+        assert_eq!(loaded.user.as_ref(), "alice");
+        assert_eq!(loaded.instance_id, original.instance_id);
+        assert_eq!(loaded.bytes_sent, original.bytes_sent);
+        assert!(store
+            .get_session(&other.session_id)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn rows_without_instance_id_load_as_nil() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        let session = test_session();
+        store.insert_session(&session).await.unwrap();
+        sqlx::query("UPDATE sessions SET instance_id = NULL")
+            .execute(store.pool())
+            .await
+            .unwrap();
+
+        let loaded = store
+            .get_session(&session.session_id)
+            .await
+            .unwrap()
+            .expect("legacy session");
+        assert!(loaded.instance_id.is_nil());
+    }
+
+    /// Detail lines of an `EXPLAIN QUERY PLAN` statement.
+    async fn query_plan<'q>(
+        store: &SessionStore,
+        query: sqlx::query::Query<'q, Any, sqlx::any::AnyArguments<'q>>,
+    ) -> Vec<String> {
+        use sqlx::Row;
+
+        query
+            .fetch_all(&store.pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.try_get::<String, _>("detail").unwrap())
+            .collect()
+    }
+
+    fn assert_no_table_scan(what: &str, plan: &[String]) {
+        assert!(
+            plan.iter().all(|step| !step.starts_with("SCAN")),
+            "{} scans sessions: {:?}",
+            what,
+            plan
+        );
+    }
+
+    #[tokio::test]
+    async fn history_filters_use_an_index() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        let week_ago = Utc::now() - ChronoDuration::days(7);
+        let range = SessionFilter {
+            start_after: Some(week_ago),
+            start_before: Some(Utc::now()),
+            ..Default::default()
+        };
+
+        let cases = [
+            (
+                "user",
+                SessionFilter {
+                    user: Some("alice".into()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "user + time range",
+                SessionFilter {
+                    user: Some("alice".into()),
+                    ..range.clone()
+                },
+            ),
+            (
+                "dest_ip",
+                SessionFilter {
+                    dest_ip: Some("10.0.0.1".into()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "dest_ip + time range",
+                SessionFilter {
+                    dest_ip: Some("10.0.0.1".into()),
+                    ..range.clone()
+                },
+            ),
+            (
+                "dest_host + time range",
+                SessionFilter {
+                    dest_host: Some(DestHostPattern::Exact("example.com".into())),
+                    ..range.clone()
+                },
+            ),
+            (
+                "status",
+                SessionFilter {
+                    status: Some(SessionStatus::Closed),
+                    ..Default::default()
+                },
+            ),
+            (
+                "status + time range",
+                SessionFilter {
+                    status: Some(SessionStatus::Closed),
+                    ..range.clone()
+                },
+            ),
+            (
+                "user + status + time range",
+                SessionFilter {
+                    user: Some("alice".into()),
+                    status: Some(SessionStatus::Closed),
+                    ..range.clone()
+                },
+            ),
+            (
+                "authenticated_user",
+                SessionFilter {
+                    authenticated_user: Some("admin".into()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "requested_host",
+                SessionFilter {
+                    requested_host: Some("example.com".into()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "correlation_id + time range",
+                SessionFilter {
+                    correlation_id: Some("wf-12345".into()),
+                    ..range.clone()
+                },
+            ),
+            ("time range", range.clone()),
+        ];
+
+        for (what, filter) in cases {
+            let paged = SessionFilter {
+                limit: Some(50),
+                offset: Some(100),
+                ..filter
+            };
+            let unpaged = SessionFilter {
+                limit: None,
+                offset: None,
+                ..paged.clone()
+            };
+            for query in [&paged, &unpaged] {
+                let mut builder = QueryBuilder::<Any>::new("EXPLAIN QUERY PLAN ");
+                SessionStore::push_session_query(&mut builder, query);
+                let plan = query_plan(&store, builder.build()).await;
+                assert_no_table_scan(what, &plan);
+            }
+
+            // count_sessions
+            let mut builder = QueryBuilder::<Any>::new("EXPLAIN QUERY PLAN ");
+            builder.push("SELECT COUNT(*) as count FROM sessions WHERE 1=1");
+            SessionStore::push_filters(&mut builder, &paged);
+            let plan = query_plan(&store, builder.build()).await;
+            assert_no_table_scan(what, &plan);
+        }
+
+        let mut builder = QueryBuilder::<Any>::new("EXPLAIN QUERY PLAN ");
+        SessionStore::push_session_query(
+            &mut builder,
+            &SessionFilter {
+                dest_ip: Some("10.0.0.1".into()),
+                ..range
+            },
+        );
+        let plan = query_plan(&store, builder.build()).await;
+        assert!(
+            plan.iter()
+                .any(|step| step.contains("idx_sessions_dest_start")),
+            "{:?}",
+            plan
+        );
+
+        // Retention batches walk the index in order instead of sorting the expired rows
+        for (select, order_by) in RETENTION_SELECTS {
+            let explain = format!(
+                "EXPLAIN QUERY PLAN {} ORDER BY {} LIMIT 5000",
+                select, order_by
+            );
+            let query = sqlx::query(&explain).bind(week_ago.to_rfc3339());
+            let plan = query_plan(&store, query).await;
+            assert_no_table_scan(select, &plan);
+            assert!(
+                plan.iter()
+                    .any(|step| step.contains("idx_sessions_end_start"))
+                    && plan.iter().all(|step| !step.contains("TEMP B-TREE")),
+                "{:?}",
+                plan
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn retention_expires_closed_sessions_by_end_time() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        let long_ago = Utc::now() - ChronoDuration::days(40);
+
+        // Opened long ago but closed recently: kept
+        let mut long_running = test_session();
+        long_running.start_time = long_ago;
+        long_running.close(None, SessionStatus::Closed);
+        store.insert_session(&long_running).await.unwrap();
+
+        // Closed long ago, and never closed at all: both expire
+        let mut finished = test_session();
+        finished.start_time = long_ago;
+        finished.close(None, SessionStatus::Closed);
+        finished.end_time = Some(long_ago + ChronoDuration::hours(1));
+        store.insert_session(&finished).await.unwrap();
+
+        let mut abandoned = test_session();
+        abandoned.start_time = long_ago;
+        store.insert_session(&abandoned).await.unwrap();
+
+        assert_eq!(store.cleanup_older_than(30, 5000).await.unwrap(), 2);
+        let remaining = store
+            .query_sessions(&SessionFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].session_id, long_running.session_id);
+    }
+
+    #[tokio::test]
+    async fn retention_deletes_in_batches_with_the_groups() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        let long_ago = Utc::now() - ChronoDuration::days(40);
+
+        let mut kept = test_session();
+        kept.groups = vec![Arc::from("engineering")];
+        store.insert_session(&kept).await.unwrap();
+        for hour in 0..5 {
+            let mut expired = test_session();
+            expired.groups = vec![Arc::from("engineering")];
+            expired.start_time = long_ago;
+            expired.close(None, SessionStatus::Closed);
+            expired.end_time = Some(long_ago + ChronoDuration::hours(hour));
+            store.insert_session(&expired).await.unwrap();
+        }
+
+        // Three batches of at most two
+        assert_eq!(store.cleanup_older_than(30, 2).await.unwrap(), 5);
+        assert_eq!(store.cleanup_older_than(30, 2).await.unwrap(), 0);
+        let remaining = store
+            .query_sessions(&SessionFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].session_id, kept.session_id);
+
+        let groups: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session_groups")
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_eq!(groups, 1);
+    }
+
+    #[tokio::test]
+    async fn destination_stats_bucket_the_top_destinations() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        let hour = DateTime::parse_from_rfc3339("2026-03-02T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        for (dest, minutes, bytes) in [
+            ("example.com", 5, 100),
+            ("example.com", 50, 300),
+            ("example.com", 70, 1_000),
+            ("big.example", 61, 10_000),
+            ("small.example", 10, 1),
+        ] {
+            let mut session = test_session();
+            session.dest_ip = dest.into();
+            session.start_time = hour + ChronoDuration::minutes(minutes);
+            session.bytes_sent = bytes;
+            session.bytes_received = 2 * bytes;
+            store.insert_session(&session).await.unwrap();
+        }
+        // Before the window
+        let mut old = test_session();
+        old.start_time = hour - ChronoDuration::hours(5);
+        store.insert_session(&old).await.unwrap();
+
+        let since = hour - ChronoDuration::hours(1);
+        let buckets = store.destination_stats(&since, 3600, 2).await.unwrap();
+        let summary: Vec<(i64, &str, u64, u64, u64)> = buckets
+            .iter()
+            .map(|b| {
+                (
+                    (b.bucket_start - hour).num_hours(),
+                    b.dest.as_str(),
+                    b.connections,
+                    b.bytes_sent,
+                    b.bytes_received,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, "example.com:443", 2, 400, 800),
+                (1, "big.example:443", 1, 10_000, 20_000),
+                (1, "example.com:443", 1, 1_000, 2_000),
+            ]
+        );
+    }
+
+    /// Two stores on one SQLite file, as two server processes would open it
+    async fn two_stores(dir: &tempfile::TempDir) -> (SessionStore, SessionStore) {
+        let url = format!("sqlite://{}", dir.path().join("sessions.db").display());
+        let first = SessionStore::connect(&url).await.unwrap();
+        let second = SessionStore::connect(&url).await.unwrap();
+        (first, second)
+    }
+
+    fn instance_lock(shared: bool) -> InstanceLock {
+        InstanceLock {
+            instance_id: Uuid::new_v4(),
+            shared,
+            stale_after: Duration::from_secs(60),
+        }
+    }
+
+    fn session_of(instance: Uuid) -> Session {
+        let mut session = test_session();
+        session.instance_id = instance;
+        session
+    }
+
+    #[tokio::test]
+    async fn second_instance_is_refused_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = two_stores(&dir).await;
+        let first_lock = instance_lock(false);
+        first
+            .acquire_instance_lock(first_lock.clone())
+            .await
+            .unwrap();
+
+        for shared in [false, true] {
+            let err = second
+                .acquire_instance_lock(instance_lock(shared))
+                .await
+                .unwrap_err();
+            let InstanceLockError::Held { holder, .. } = &err else {
+                panic!("unexpected error: {}", err);
+            };
+            assert_eq!(holder.instance_id, first_lock.instance_id.to_string());
+            assert!(!holder.shared);
+            let message = err.to_string();
+            assert!(message.contains(&first_lock.instance_id.to_string()));
+            assert!(message.contains("last heartbeat"));
+            assert!(message.contains("sessions.allow_shared_database"));
+        }
+
+        // The refused instance leaves no row behind
+        assert_eq!(second.registered_instances().await.unwrap().len(), 1);
+
+        // A graceful shutdown frees the store at once
+        first.release_instance_lock().await.unwrap();
+        second
+            .acquire_instance_lock(instance_lock(false))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn shared_instances_scope_startup_close_and_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = two_stores(&dir).await;
+        let first_lock = instance_lock(true);
+        let second_lock = instance_lock(true);
+        first
+            .acquire_instance_lock(first_lock.clone())
+            .await
+            .unwrap();
+        second
+            .acquire_instance_lock(second_lock.clone())
+            .await
+            .unwrap();
+
+        // Active sessions of the running first instance, of a crashed earlier boot and
+        // of rows from before migration 011
+        let running = session_of(first_lock.instance_id);
+        let crashed = session_of(Uuid::new_v4());
+        let legacy = session_of(Uuid::nil());
+        for session in [&running, &crashed, &legacy] {
+            first.insert_session(session).await.unwrap();
+        }
+
+        assert_eq!(second.close_all_active_sessions().await.unwrap(), 2);
+        let still_active = second
+            .query_sessions(&SessionFilter {
+                status: Some(SessionStatus::Active),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(still_active.len(), 1);
+        assert_eq!(still_active[0].session_id, running.session_id);
+
+        // Expired history: each instance deletes its own rows and those of instances
+        // that are gone, never a live peer's
+        let long_ago = Utc::now() - ChronoDuration::days(40);
+        let mut expired = Vec::new();
+        for instance in [
+            first_lock.instance_id,
+            second_lock.instance_id,
+            Uuid::new_v4(),
+        ] {
+            let mut session = session_of(instance);
+            session.start_time = long_ago;
+            session.close(None, SessionStatus::Closed);
+            session.end_time = Some(long_ago);
+            first.insert_session(&session).await.unwrap();
+            expired.push(session);
+        }
+
+        assert_eq!(second.cleanup_older_than(30, 5000).await.unwrap(), 2);
+        assert!(first
+            .get_session(&expired[0].session_id)
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(first.cleanup_older_than(30, 5000).await.unwrap(), 1);
+        assert!(first
+            .get_session(&expired[0].session_id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn stale_lock_is_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = two_stores(&dir).await;
+        let first_lock = instance_lock(false);
+        first
+            .acquire_instance_lock(first_lock.clone())
+            .await
+            .unwrap();
+
+        let long_ago = Utc::now() - ChronoDuration::seconds(120);
+        sqlx::query("UPDATE store_instances SET heartbeat_at = ?")
+            .bind(long_ago.to_rfc3339())
+            .execute(first.pool())
+            .await
+            .unwrap();
+
+        let second_lock = instance_lock(false);
+        second
+            .acquire_instance_lock(second_lock.clone())
+            .await
+            .unwrap();
+        let registered = second.registered_instances().await.unwrap();
+        assert_eq!(registered.len(), 1);
+        assert_eq!(
+            registered[0].instance_id,
+            second_lock.instance_id.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn in_memory_store_skips_the_lock() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        store
+            .acquire_instance_lock(instance_lock(false))
+            .await
+            .unwrap();
+        assert!(store.registered_instances().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn acl_rule_stats_upsert_and_reload() {
+        let store = SessionStore::connect("sqlite::memory:").await.unwrap();
+        let tracked_since = Utc::now() - ChronoDuration::days(3);
+        let mut record = RuleStatsRecord {
+            rule_id: "0123456789abcdef".to_string(),
+            scope: "user:alice".to_string(),
+            action: AclAction::Block,
+            hits: 5,
+            last_matched_at: None,
+            tracked_since,
+        };
+        store
+            .save_acl_rule_stats(std::slice::from_ref(&record))
+            .await
+            .unwrap();
+
+        record.hits = 9;
+        record.last_matched_at = Some(Utc::now());
+        store
+            .save_acl_rule_stats(std::slice::from_ref(&record))
+            .await
+            .unwrap();
+
+        let loaded = store.load_acl_rule_stats().await.unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].hits, 9);
+        assert_eq!(loaded[0].action, AclAction::Block);
+        assert_eq!(loaded[0].scope, "user:alice");
+        assert!(loaded[0].last_matched_at.is_some());
+        assert_eq!(
+            loaded[0].tracked_since.timestamp_millis(),
+            tracked_since.timestamp_millis()
+        );
+    }
+
     #[test]
     fn parse_datetime_handles_rfc3339_with_timezone() {
         let ts = "2025-10-09T11:22:49.421595Z";
@@ -1256,6 +3947,111 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn rollups_keep_history_after_raw_cleanup() {
+        let store = Arc::new(SessionStore::connect("sqlite::memory:").await.unwrap());
+        let base =
+            MetricsResolution::FifteenMinutes.bucket_start(Utc::now() - ChronoDuration::hours(2));
+        // Half an hour of samples every 10s
+        for i in 0..180u64 {
+            let timestamp = base + ChronoDuration::seconds(10 * i as i64);
+            store
+                .insert_metric(&timestamp, i % 7, i, 1_000)
+                .await
+                .unwrap();
+        }
+
+        let rolled_at = base + ChronoDuration::minutes(31);
+        // 30 one-minute buckets, folded into two 15-minute ones
+        assert_eq!(store.roll_up_metrics(rolled_at).await.unwrap(), 32);
+        assert_eq!(store.roll_up_metrics(rolled_at).await.unwrap(), 0);
+        assert_eq!(
+            store
+                .metrics_rolled_until(MetricsResolution::OneMinute)
+                .await
+                .unwrap(),
+            Some(base + ChronoDuration::minutes(30))
+        );
+
+        let (quarters, _) = store
+            .query_metrics_page_at(
+                MetricsResolution::FifteenMinutes,
+                &base,
+                &rolled_at,
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(quarters.len(), 2);
+        let first = quarters[0].rollup.unwrap();
+        assert_eq!(first.samples, 90);
+        assert_eq!(
+            (first.active_sessions.min, first.active_sessions.max),
+            (0, 6)
+        );
+        assert_eq!(first.total_sessions.sum, (0..90).sum::<u64>());
+        assert_eq!(first.bandwidth.avg, 1_000.0);
+
+        // Raw rows go, the rollups still answer; a sample newer than the last stored
+        // bucket is folded in on the way
+        let recent = Utc::now() - ChronoDuration::minutes(1);
+        store.insert_metric(&recent, 3, 0, 0).await.unwrap();
+        assert_eq!(store.cleanup_old_metrics(1).await.unwrap(), 180);
+
+        let mut cursor = MetricsCursor::store_at(
+            store.clone(),
+            MetricsResolution::OneMinute,
+            base,
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+        let mut buckets = Vec::new();
+        loop {
+            let chunk = cursor.next_chunk().await.unwrap();
+            if chunk.is_empty() {
+                break;
+            }
+            buckets.extend(chunk);
+        }
+        assert_eq!(buckets.len(), 31);
+        assert!(buckets.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+        assert_eq!(
+            buckets[30].timestamp,
+            MetricsResolution::OneMinute.bucket_start(recent)
+        );
+    }
+
+    #[test]
+    fn postgres_urls_select_the_postgres_flavor() {
+        for url in [
+            "postgres://proxy@db/rustsocks",
+            "postgresql://db:5432/rustsocks",
+        ] {
+            let flavor = DatabaseFlavor::from_url(url).expect("postgres url");
+            assert!(matches!(flavor, DatabaseFlavor::Postgres));
+            assert_eq!(flavor.connection_url(url), url);
+        }
+        assert!(DatabaseFlavor::from_url("oracle://db/rustsocks").is_err());
+    }
+
+    #[test]
+    fn postgres_statements_number_placeholders_and_quote_user() {
+        let postgres = DatabaseFlavor::Postgres;
+        assert_eq!(
+            postgres.sql("SELECT user FROM sessions WHERE user = ? AND authenticated_user = ?"),
+            r#"SELECT "user" FROM sessions WHERE "user" = $1 AND authenticated_user = $2"#
+        );
+        assert_eq!(
+            postgres.sql("UPDATE sessions SET user = excluded.user -- user?\nWHERE x = '?'"),
+            "UPDATE sessions SET \"user\" = excluded.\"user\" -- user?\nWHERE x = '?'"
+        );
+
+        let sqlite = DatabaseFlavor::from_url("sqlite::memory:").unwrap();
+        assert!(matches!(sqlite.sql("user = ?"), Cow::Borrowed("user = ?")));
+    }
+
     #[test]
     fn sanitize_duration_drops_negative_values() {
         assert_eq!(sanitize_duration(Some(-1)), None);
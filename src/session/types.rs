@@ -1,8 +1,13 @@
+use super::admission::AdmissionRejectionStats;
+use super::failures::FailureCategory;
+use crate::acl::AclMatchedOn;
+use crate::utils::log_sampling;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::net::IpAddr;
-use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
 use uuid::Uuid;
 
 /// Transport protocol associated with a session.
@@ -80,6 +85,220 @@ impl std::str::FromStr for SessionStatus {
     }
 }
 
+/// Where a session's `requested_host` came from, most authoritative first.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HostSource {
+    /// Domain-type CONNECT: the client named the host itself
+    SocksRequest,
+    /// TLS SNI seen on a CONNECT-by-IP session
+    Sni,
+    /// The same client resolved the name through the proxy shortly before
+    ResolveExtension,
+    /// The proxy recently resolved the name to this IP for the same client
+    ReverseMap,
+}
+
+impl HostSource {
+    #[inline(always)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HostSource::SocksRequest => "socks_request",
+            HostSource::Sni => "sni",
+            HostSource::ResolveExtension => "resolve_extension",
+            HostSource::ReverseMap => "reverse_map",
+        }
+    }
+
+    /// Higher ranks win; a source never replaces one with a higher rank.
+    #[inline(always)]
+    pub fn rank(&self) -> u8 {
+        match self {
+            HostSource::SocksRequest => 4,
+            HostSource::Sni => 3,
+            HostSource::ResolveExtension => 2,
+            HostSource::ReverseMap => 1,
+        }
+    }
+}
+
+impl fmt::Display for HostSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for HostSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "socks_request" => Ok(HostSource::SocksRequest),
+            "sni" => Ok(HostSource::Sni),
+            "resolve_extension" => Ok(HostSource::ResolveExtension),
+            "reverse_map" => Ok(HostSource::ReverseMap),
+            _ => Err(format!("Invalid requested host source: {}", value)),
+        }
+    }
+}
+
+/// How a UDP association tells its client's datagrams apart from everyone else's.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UdpAssociationMode {
+    /// Only the endpoint stated in the ASSOCIATE request, or the control connection's
+    /// endpoint when the request states none
+    #[default]
+    Strict,
+    /// Any port on the stated (or control connection's) IP until the first datagram,
+    /// whose endpoint is then locked in
+    IpOnly,
+    /// The first datagram from the control connection's IP locks in the endpoint,
+    /// whatever the request stated
+    Learned,
+}
+
+impl UdpAssociationMode {
+    #[inline(always)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UdpAssociationMode::Strict => "strict",
+            UdpAssociationMode::IpOnly => "ip-only",
+            UdpAssociationMode::Learned => "learned",
+        }
+    }
+}
+
+impl fmt::Display for UdpAssociationMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for UdpAssociationMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "strict" => Ok(UdpAssociationMode::Strict),
+            "ip-only" => Ok(UdpAssociationMode::IpOnly),
+            "learned" => Ok(UdpAssociationMode::Learned),
+            _ => Err(format!("Invalid UDP association mode: {}", value)),
+        }
+    }
+}
+
+/// What the session batch writer does with a session that finds its queue full
+/// (`sessions.batch_overflow_policy`).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchOverflowPolicy {
+    /// The producer waits until a flush makes room; nothing is lost, but closing
+    /// sessions slows down to the pace of the store
+    #[default]
+    Block,
+    /// The oldest queued session is discarded to make room
+    DropOldest,
+    /// The session being queued is discarded
+    DropNewest,
+}
+
+impl BatchOverflowPolicy {
+    #[inline(always)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchOverflowPolicy::Block => "block",
+            BatchOverflowPolicy::DropOldest => "drop_oldest",
+            BatchOverflowPolicy::DropNewest => "drop_newest",
+        }
+    }
+}
+
+impl fmt::Display for BatchOverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for BatchOverflowPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "block" => Ok(BatchOverflowPolicy::Block),
+            "drop_oldest" => Ok(BatchOverflowPolicy::DropOldest),
+            "drop_newest" => Ok(BatchOverflowPolicy::DropNewest),
+            _ => Err(format!("Invalid batch overflow policy: {}", value)),
+        }
+    }
+}
+
+/// SOCKS command that opened a session.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionCommand {
+    #[default]
+    Connect,
+    /// Inbound connection accepted on behalf of the client (RFC 1928 BIND)
+    Bind,
+    UdpAssociate,
+}
+
+impl SessionCommand {
+    #[inline(always)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionCommand::Connect => "connect",
+            SessionCommand::Bind => "bind",
+            SessionCommand::UdpAssociate => "udp_associate",
+        }
+    }
+
+    /// Command implied by the transport, for sessions recorded before the
+    /// command was stored.
+    #[inline(always)]
+    pub fn for_protocol(protocol: Protocol) -> Self {
+        match protocol {
+            Protocol::Tcp => SessionCommand::Connect,
+            Protocol::Udp => SessionCommand::UdpAssociate,
+        }
+    }
+}
+
+impl fmt::Display for SessionCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for SessionCommand {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "connect" => Ok(SessionCommand::Connect),
+            "bind" => Ok(SessionCommand::Bind),
+            "udp_associate" => Ok(SessionCommand::UdpAssociate),
+            _ => Err(format!("Invalid session command: {}", value)),
+        }
+    }
+}
+
+static INSTANCE_ID: LazyLock<Uuid> = LazyLock::new(Uuid::new_v4);
+
+/// Identifier of the running server instance, random per boot.
+///
+/// Recorded on every session so deployments with several proxies (or a proxy that
+/// restarted) can tell apart sessions that share a store.
+pub fn instance_id() -> Uuid {
+    *INSTANCE_ID
+}
+
+/// Generate a session ID: UUIDv7, time-ordered and unique across restarts.
+pub fn new_session_id() -> Uuid {
+    Uuid::now_v7()
+}
+
 /// Core representation of a SOCKS session.
 ///
 /// Performance optimization: Uses Arc<str> for user, dest_ip, acl_decision, and acl_rule_matched
@@ -88,11 +307,38 @@ impl std::str::FromStr for SessionStatus {
 pub struct Session {
     // Identity
     pub session_id: Uuid,
+    /// Server boot that created the session (see [`instance_id`]); nil for sessions
+    /// persisted before instance IDs were recorded
+    #[serde(default)]
+    pub instance_id: Uuid,
     #[serde(
         serialize_with = "serialize_arc_str",
         deserialize_with = "deserialize_arc_str"
     )]
     pub user: Arc<str>,
+    /// Principal that passed SOCKS authentication; differs from `user` when it
+    /// acts on behalf of another identity. `None` for unauthenticated sessions.
+    #[serde(
+        default,
+        serialize_with = "serialize_option_arc_str",
+        deserialize_with = "deserialize_option_arc_str"
+    )]
+    pub authenticated_user: Option<Arc<str>>,
+    /// Client-supplied ID for cross-system tracing (`auth.allow_correlation_suffix`)
+    #[serde(
+        default,
+        serialize_with = "serialize_option_arc_str",
+        deserialize_with = "deserialize_option_arc_str"
+    )]
+    pub correlation_id: Option<Arc<str>>,
+    /// Groups of the user (LDAP or system groups, as the ACL saw them); empty when the
+    /// user has none or did not authenticate
+    #[serde(
+        default,
+        serialize_with = "serialize_arc_str_list",
+        deserialize_with = "deserialize_arc_str_list"
+    )]
+    pub groups: Vec<Arc<str>>,
 
     // Timing
     pub start_time: DateTime<Utc>,
@@ -107,14 +353,80 @@ pub struct Session {
         deserialize_with = "deserialize_arc_str"
     )]
     pub dest_ip: Arc<str>,
+    /// Requested destination as searched by `dest_host`: the domain or IP literal from
+    /// the SOCKS request, lowercased (see [`dest_host_key`])
+    #[serde(
+        default,
+        serialize_with = "serialize_arc_str",
+        deserialize_with = "deserialize_arc_str"
+    )]
+    pub dest_host: Arc<str>,
     pub dest_port: u16,
     pub protocol: Protocol,
+    /// SOCKS command; BIND sessions have `dest_ip`/`dest_port` set to the expected peer
+    #[serde(default)]
+    pub command: SessionCommand,
+    /// SOCKS version the client spoke: 5, or 4 for SOCKS4/4a (`server.enable_socks4`)
+    #[serde(default = "default_socks_version")]
+    pub socks_version: u8,
+    /// CONNECT tunnelled through the parent proxy (`server.upstream`) rather than direct
+    #[serde(default)]
+    pub chained: bool,
+    /// Upstream connection wrapped in TLS by the proxy (`server.upstream_tls`)
+    #[serde(default)]
+    pub upstream_tls: bool,
+    /// Local address the upstream socket was bound to, so the source address chosen by
+    /// `[server.egress]` can be audited
+    #[serde(default)]
+    pub egress_ip: Option<IpAddr>,
+    /// Addresses the requested domain resolved to; empty for IP literals and chained
+    /// sessions, whose parent proxy resolves the name
+    #[serde(default)]
+    pub resolved_ips: Vec<IpAddr>,
+    /// Address of the destination the upstream connection went to
+    #[serde(default)]
+    pub connected_ip: Option<IpAddr>,
+    /// TLS server name seen on a CONNECT-by-IP session (`acl.classify_by_sni`)
+    #[serde(
+        default,
+        serialize_with = "serialize_option_arc_str",
+        deserialize_with = "deserialize_option_arc_str"
+    )]
+    pub sni_host: Option<Arc<str>>,
+    /// Hostname the client asked for, even when it dialled an IP
+    #[serde(
+        default,
+        serialize_with = "serialize_option_arc_str",
+        deserialize_with = "deserialize_option_arc_str"
+    )]
+    pub requested_host: Option<Arc<str>>,
+    #[serde(default)]
+    pub requested_host_source: Option<HostSource>,
 
     // Traffic stats
     pub bytes_sent: u64,
     pub bytes_received: u64,
     pub packets_sent: u64,
     pub packets_received: u64,
+    /// Recent throughput, fed by the traffic updates; only kept in memory
+    #[serde(skip)]
+    pub transfer_rate: TransferRate,
+    /// Keepalive probes armed while the tunnel was idle (`mode = "probe"`)
+    #[serde(default)]
+    pub keepalive_probes: u64,
+
+    // UDP ASSOCIATE
+    #[serde(default)]
+    pub udp_association_mode: Option<UdpAssociationMode>,
+    /// Client endpoint the association accepts datagrams from, once known
+    #[serde(default)]
+    pub udp_client_endpoint: Option<SocketAddr>,
+    /// Datagrams dropped because they came from neither the client nor a destination
+    #[serde(default)]
+    pub udp_rejected_datagrams: u64,
+    /// Datagrams dropped because the QoS bandwidth limits had no tokens left
+    #[serde(default)]
+    pub udp_throttled_datagrams: u64,
 
     // Status
     pub status: SessionStatus,
@@ -131,6 +443,26 @@ pub struct Session {
         deserialize_with = "deserialize_arc_str"
     )]
     pub acl_decision: Arc<str>,
+    /// Relayed although the ACL blocks it, because `acl.mode` is `monitor`
+    #[serde(default)]
+    pub would_block: bool,
+    /// What the ACL decision was made on: the requested domain, an IP literal, an
+    /// address the domain resolved to or, with no rule matching, the default policy
+    #[serde(default)]
+    pub acl_matched_on: Option<AclMatchedOn>,
+
+    // Outcome
+    /// SOCKS5 reply code (RFC 1928) the request was answered with; SOCKS4 clients get
+    /// "granted" for 0 and "rejected" for anything else
+    #[serde(default)]
+    pub reply_code: Option<u8>,
+    /// Why the request failed, for sessions that never reached the relay
+    #[serde(default)]
+    pub failure: Option<FailureCategory>,
+}
+
+fn default_socks_version() -> u8 {
+    5
 }
 
 // Serde helpers for Arc<str> serialization
@@ -167,37 +499,109 @@ where
     Ok(opt.map(Arc::from))
 }
 
+fn serialize_arc_str_list<S>(list: &[Arc<str>], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(list.iter().map(|item| &**item))
+}
+
+fn deserialize_arc_str_list<'de, D>(deserializer: D) -> Result<Vec<Arc<str>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let list = Vec::<String>::deserialize(deserializer)?;
+    Ok(list.into_iter().map(Arc::from).collect())
+}
+
 impl Session {
     /// Create a new active session from connection info.
     #[inline(always)]
     pub fn new(
-        user: impl Into<String>,
+        user: impl Into<Arc<str>>,
         connection: ConnectionInfo,
-        acl_decision: impl Into<String>,
+        acl_decision: impl AsRef<str>,
         acl_rule_matched: Option<String>,
     ) -> Self {
+        // Anything that is not an IP literal was sent as a domain by the client
+        let requested_host = connection
+            .dest_ip
+            .parse::<IpAddr>()
+            .is_err()
+            .then(|| Arc::clone(&connection.dest_ip));
+        let requested_host_source = requested_host.as_ref().map(|_| HostSource::SocksRequest);
+
         Self {
-            session_id: Uuid::new_v4(),
-            user: Arc::from(user.into()),
+            // The connection's own ID, which its log sampling was decided from
+            session_id: log_sampling::take_session_id().unwrap_or_else(new_session_id),
+            instance_id: instance_id(),
+            user: user.into(),
+            authenticated_user: connection.authenticated_user,
+            correlation_id: connection.correlation_id,
+            groups: connection.groups,
             start_time: Utc::now(),
             end_time: None,
             duration_secs: None,
             source_ip: connection.source_ip,
             source_port: connection.source_port,
-            dest_ip: Arc::from(connection.dest_ip),
+            dest_host: dest_host_key(&connection.dest_ip),
+            dest_ip: connection.dest_ip,
             dest_port: connection.dest_port,
             protocol: connection.protocol,
+            command: SessionCommand::for_protocol(connection.protocol),
+            socks_version: connection.socks_version,
+            chained: connection.chained,
+            upstream_tls: false,
+            egress_ip: None,
+            resolved_ips: Vec::new(),
+            connected_ip: None,
+            sni_host: None,
+            requested_host,
+            requested_host_source,
             bytes_sent: 0,
             bytes_received: 0,
             packets_sent: 0,
             packets_received: 0,
+            transfer_rate: TransferRate::default(),
+            keepalive_probes: 0,
+            udp_association_mode: None,
+            udp_client_endpoint: None,
+            udp_rejected_datagrams: 0,
+            udp_throttled_datagrams: 0,
             status: SessionStatus::Active,
             close_reason: None,
             acl_rule_matched: acl_rule_matched.map(Arc::from),
-            acl_decision: Arc::from(acl_decision.into()),
+            acl_decision: acl_decision_arc(acl_decision.as_ref()),
+            would_block: false,
+            acl_matched_on: None,
+            // Sessions open once the request was granted
+            reply_code: Some(0),
+            failure: None,
         }
     }
 
+    /// Logical destination: the requested hostname when known, the dialled address otherwise.
+    #[inline(always)]
+    pub fn logical_destination(&self) -> &str {
+        self.requested_host
+            .as_deref()
+            .or(self.sni_host.as_deref())
+            .unwrap_or(&self.dest_ip)
+    }
+
+    /// Record the requested hostname unless a more authoritative source already supplied one.
+    /// Returns whether the value was stored.
+    pub fn offer_requested_host(&mut self, host: &str, source: HostSource) -> bool {
+        if let Some(current) = self.requested_host_source {
+            if current.rank() >= source.rank() {
+                return false;
+            }
+        }
+        self.requested_host = Some(Arc::from(host));
+        self.requested_host_source = Some(source);
+        true
+    }
+
     /// Mark the session as closed and compute duration.
     #[inline(always)]
     pub fn close(&mut self, reason: Option<String>, status: SessionStatus) {
@@ -210,24 +614,124 @@ impl Session {
     }
 }
 
+/// Every session carries one of a handful of decisions; share them instead of allocating.
+fn acl_decision_arc(decision: &str) -> Arc<str> {
+    static ALLOW: LazyLock<Arc<str>> = LazyLock::new(|| Arc::from("allow"));
+    static BLOCK: LazyLock<Arc<str>> = LazyLock::new(|| Arc::from("block"));
+
+    match decision {
+        "allow" => Arc::clone(&ALLOW),
+        "block" => Arc::clone(&BLOCK),
+        other => Arc::from(other),
+    }
+}
+
+/// [`Session::dest_host`] of a requested destination. Host names are case-insensitive,
+/// so they are stored and compared lowercased.
+pub fn dest_host_key(dest: &str) -> Arc<str> {
+    if dest.bytes().any(|b| b.is_ascii_uppercase()) {
+        Arc::from(dest.to_ascii_lowercase())
+    } else {
+        Arc::from(dest)
+    }
+}
+
+/// `dest_host` query: one host, or `*.example.com` for every name below example.com
+/// (not example.com itself).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DestHostPattern {
+    Exact(String),
+    /// Suffix to match, with its leading dot
+    Suffix(String),
+}
+
+impl DestHostPattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = dest_host_key(pattern.trim());
+        let domain = pattern.strip_prefix("*.").unwrap_or(&pattern);
+        if domain.is_empty() {
+            return Err("dest_host cannot be empty".to_string());
+        }
+        if domain.contains('*') {
+            return Err("dest_host allows only a leading \"*.\" wildcard".to_string());
+        }
+        Ok(if domain.len() < pattern.len() {
+            Self::Suffix(format!(".{}", domain))
+        } else {
+            Self::Exact(domain.to_string())
+        })
+    }
+
+    /// Whether a [`Session::dest_host`] matches
+    pub fn matches(&self, dest_host: &str) -> bool {
+        match self {
+            Self::Exact(host) => dest_host == host,
+            Self::Suffix(suffix) => dest_host.len() > suffix.len() && dest_host.ends_with(suffix),
+        }
+    }
+}
+
 /// Immutable connection metadata collected at session start.
 #[derive(Debug, Clone)]
 pub struct ConnectionInfo {
     pub source_ip: IpAddr,
     pub source_port: u16,
-    pub dest_ip: String,
+    pub dest_ip: Arc<str>,
     pub dest_port: u16,
     pub protocol: Protocol,
+    /// Principal that passed SOCKS authentication (see [`Session::authenticated_user`])
+    pub authenticated_user: Option<Arc<str>>,
+    /// See [`Session::correlation_id`]
+    pub correlation_id: Option<Arc<str>>,
+    /// See [`Session::socks_version`]
+    pub socks_version: u8,
+    /// See [`Session::chained`]
+    pub chained: bool,
+    /// See [`Session::groups`]
+    pub groups: Vec<Arc<str>>,
+}
+
+/// How a session's destination was resolved and what the ACL decided on, recorded once
+/// the upstream connect is settled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionResolution {
+    /// See [`Session::resolved_ips`]
+    pub resolved_ips: Vec<IpAddr>,
+    /// See [`Session::connected_ip`]
+    pub connected_ip: Option<IpAddr>,
+    /// See [`Session::acl_matched_on`]
+    pub acl_matched_on: Option<AclMatchedOn>,
+}
+
+impl SessionResolution {
+    pub fn apply(self, session: &mut Session) {
+        session.resolved_ips = self.resolved_ips;
+        session.connected_ip = self.connected_ip;
+        if self.acl_matched_on.is_some() {
+            session.acl_matched_on = self.acl_matched_on;
+        }
+    }
 }
 
 /// User-provided filters for querying session history.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionFilter {
     pub user: Option<String>,
+    #[serde(default)]
+    pub authenticated_user: Option<String>,
+    #[serde(default)]
+    pub correlation_id: Option<String>,
     pub status: Option<SessionStatus>,
     pub start_after: Option<DateTime<Utc>>,
     pub start_before: Option<DateTime<Utc>>,
     pub dest_ip: Option<String>,
+    #[serde(default)]
+    pub dest_host: Option<DestHostPattern>,
+    #[serde(default)]
+    pub requested_host: Option<String>,
+    #[serde(default)]
+    pub requested_host_source: Option<HostSource>,
     pub min_duration_secs: Option<u64>,
     pub min_bytes: Option<u64>,
     pub limit: Option<u64>,
@@ -240,10 +744,15 @@ impl Default for SessionFilter {
     fn default() -> Self {
         Self {
             user: None,
+            authenticated_user: None,
+            correlation_id: None,
             status: None,
             start_after: None,
             start_before: None,
             dest_ip: None,
+            dest_host: None,
+            requested_host: None,
+            requested_host_source: None,
             min_duration_secs: None,
             min_bytes: None,
             limit: Some(100),
@@ -254,6 +763,131 @@ impl Default for SessionFilter {
     }
 }
 
+/// Finished sessions dropped from memory by `sessions.memory_max_sessions`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionEviction {
+    pub evicted: u64,
+    /// Start time of the newest evicted session
+    pub newest_evicted_start: Option<DateTime<Utc>>,
+}
+
+impl SessionEviction {
+    pub fn record(&mut self, start_time: DateTime<Utc>) {
+        self.evicted += 1;
+        self.newest_evicted_start = Some(
+            self.newest_evicted_start
+                .map_or(start_time, |newest| newest.max(start_time)),
+        );
+    }
+
+    pub fn merge(&mut self, other: &SessionEviction) {
+        self.evicted += other.evicted;
+        self.newest_evicted_start = self.newest_evicted_start.max(other.newest_evicted_start);
+    }
+
+    /// Whether sessions that started at or after `since` were evicted
+    pub fn truncates(&self, since: DateTime<Utc>) -> bool {
+        self.newest_evicted_start
+            .is_some_and(|newest| newest >= since)
+    }
+}
+
+/// Short window of [`TransferRate`]
+pub const RATE_WINDOW_SHORT_MS: u64 = 5_000;
+/// Long window of [`TransferRate`]
+pub const RATE_WINDOW_LONG_MS: u64 = 60_000;
+
+/// Time base of the rate windows
+static RATE_EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Rolling transfer rate of a session over the last 5 and 60 seconds.
+///
+/// Each window keeps two slots, the bytes of the current and of the previous window
+/// period; the previous slot counts for the part of it still inside the window. The
+/// traffic update records into it under the session lock it already holds.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransferRate {
+    short: RateWindow,
+    long: RateWindow,
+}
+
+/// Bytes per second sent and received, over both windows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferRates {
+    pub sent_bps: u64,
+    pub received_bps: u64,
+    pub sent_bps_60s: u64,
+    pub received_bps_60s: u64,
+}
+
+impl TransferRate {
+    pub fn record(&mut self, now: Instant, bytes_sent: u64, bytes_received: u64) {
+        let now_ms = rate_clock_ms(now);
+        let bytes = [bytes_sent, bytes_received];
+        self.short.record(RATE_WINDOW_SHORT_MS, now_ms, bytes);
+        self.long.record(RATE_WINDOW_LONG_MS, now_ms, bytes);
+    }
+
+    pub fn rates(&self, now: Instant) -> TransferRates {
+        let now_ms = rate_clock_ms(now);
+        let [sent_bps, received_bps] = self.short.rate(RATE_WINDOW_SHORT_MS, now_ms);
+        let [sent_bps_60s, received_bps_60s] = self.long.rate(RATE_WINDOW_LONG_MS, now_ms);
+        TransferRates {
+            sent_bps,
+            received_bps,
+            sent_bps_60s,
+            received_bps_60s,
+        }
+    }
+}
+
+fn rate_clock_ms(now: Instant) -> u64 {
+    now.saturating_duration_since(*RATE_EPOCH).as_millis() as u64
+}
+
+/// Sent and received bytes of the current and the previous period of one window
+#[derive(Debug, Clone, Copy, Default)]
+struct RateWindow {
+    period: u64,
+    current: [u64; 2],
+    previous: [u64; 2],
+}
+
+impl RateWindow {
+    fn record(&mut self, window_ms: u64, now_ms: u64, bytes: [u64; 2]) {
+        let period = now_ms / window_ms;
+        let (previous, current) = self.slots_at(period);
+        self.period = self.period.max(period);
+        self.previous = previous;
+        self.current = [
+            current[0].saturating_add(bytes[0]),
+            current[1].saturating_add(bytes[1]),
+        ];
+    }
+
+    fn rate(&self, window_ms: u64, now_ms: u64) -> [u64; 2] {
+        let (previous, current) = self.slots_at(now_ms / window_ms);
+        // Share of the previous period still inside the window ending now
+        let overlap_ms = window_ms - now_ms % window_ms;
+        [0, 1].map(|i| {
+            let bytes =
+                previous[i] as u128 * overlap_ms as u128 / window_ms as u128 + current[i] as u128;
+            (bytes * 1000 / window_ms as u128) as u64
+        })
+    }
+
+    /// The two slots as they stand in `period`
+    fn slots_at(&self, period: u64) -> ([u64; 2], [u64; 2]) {
+        if period <= self.period {
+            (self.previous, self.current)
+        } else if period == self.period + 1 {
+            (self.current, [0; 2])
+        } else {
+            ([0; 2], [0; 2])
+        }
+    }
+}
+
 /// Aggregated statistics returned by `SessionManager::get_stats`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionStats {
@@ -261,9 +895,14 @@ pub struct SessionStats {
     pub active_sessions: usize,
     pub total_sessions: usize,
     pub total_bytes: u64,
+    /// Sessions of the window were evicted from memory, so the counts are incomplete
+    pub truncated: bool,
+    /// Finished sessions evicted from memory since startup
+    pub evicted_sessions: u64,
     pub top_users: Vec<UserSessionStat>,
     pub top_destinations: Vec<DestinationStat>,
     pub acl: AclDecisionStats,
+    pub admission: AdmissionRejectionStats,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -278,6 +917,17 @@ pub struct DestinationStat {
     pub connections: u64,
 }
 
+/// Traffic to one destination within one time bucket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DestinationBucket {
+    pub bucket_start: DateTime<Utc>,
+    /// `dest_ip:dest_port`
+    pub dest: String,
+    pub connections: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AclDecisionStats {
     pub allowed: u64,
@@ -290,6 +940,56 @@ mod tests {
     use serde_json::{json, Value};
     use std::net::{IpAddr, Ipv4Addr};
 
+    #[test]
+    fn session_ids_are_time_ordered_and_unique_across_restarts() {
+        use std::collections::HashSet;
+        use uuid::{ContextV7, Timestamp};
+
+        // One process: every ID sorts after the previous one
+        let ids: Vec<Uuid> = (0..2000).map(|_| new_session_id()).collect();
+        assert!(ids.iter().all(|id| id.get_version_num() == 7));
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Simulated restarts: each boot starts from a fresh generator state
+        let mut across_boots: Vec<Uuid> = Vec::new();
+        for _boot in 0..3 {
+            let context = ContextV7::new();
+            let boot: Vec<Uuid> = (0..500)
+                .map(|_| Uuid::new_v7(Timestamp::now(&context)))
+                .collect();
+            if let (Some(last), Some(first)) = (across_boots.last(), boot.first()) {
+                assert!(last < first, "a later boot must sort after an earlier one");
+            }
+            across_boots.extend(boot);
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        across_boots.extend(ids);
+        let unique: HashSet<_> = across_boots.iter().collect();
+        assert_eq!(unique.len(), across_boots.len());
+    }
+
+    #[test]
+    fn sessions_record_the_boot_instance() {
+        let conn = ConnectionInfo {
+            source_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            source_port: 1,
+            dest_ip: "example.com".into(),
+            dest_port: 80,
+            protocol: Protocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
+        };
+        let first = Session::new("alice", conn.clone(), "allow", None);
+        let second = Session::new("alice", conn, "allow", None);
+        assert_eq!(first.instance_id, instance_id());
+        assert_eq!(second.instance_id, instance_id());
+        assert!(!instance_id().is_nil());
+        assert!(first.session_id < second.session_id);
+    }
+
     #[test]
     fn protocol_display_matches_lowercase() {
         assert_eq!(Protocol::Tcp.to_string(), "tcp");
@@ -304,6 +1004,25 @@ mod tests {
         assert!(filter.status.is_none());
     }
 
+    #[test]
+    fn dest_host_patterns_match_exactly_or_below_a_domain() {
+        let exact = DestHostPattern::parse("Example.COM").unwrap();
+        assert_eq!(exact, DestHostPattern::Exact("example.com".to_string()));
+        assert!(exact.matches("example.com"));
+        assert!(!exact.matches("www.example.com"));
+
+        let suffix = DestHostPattern::parse("*.example.com").unwrap();
+        assert!(suffix.matches("www.example.com"));
+        assert!(suffix.matches("a.b.example.com"));
+        assert!(!suffix.matches("example.com"));
+        assert!(!suffix.matches("badexample.com"));
+
+        for invalid in ["", "*.", "*example.com", "www.*.com"] {
+            assert!(DestHostPattern::parse(invalid).is_err(), "{invalid}");
+        }
+        assert_eq!(&*dest_host_key("WWW.Example.com"), "www.example.com");
+    }
+
     #[test]
     fn session_status_serializes_to_snake_case() {
         let value = serde_json::to_string(&SessionStatus::RejectedByAcl).unwrap();
@@ -315,9 +1034,14 @@ mod tests {
         let connection = ConnectionInfo {
             source_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
             source_port: 12345,
-            dest_ip: "example.com".to_string(),
+            dest_ip: "example.com".into(),
             dest_port: 443,
             protocol: Protocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
 
         let session = Session::new("alice", connection, "allow", Some("Allow HTTPS".into()));
@@ -326,4 +1050,28 @@ mod tests {
         assert_eq!(json_value["acl_decision"], json!("allow"));
         assert!(json_value["acl_rule_matched"].is_string());
     }
+
+    #[test]
+    fn transfer_rate_covers_recent_traffic_only() {
+        use std::time::Duration;
+
+        let start = Instant::now();
+        let mut rate = TransferRate::default();
+        rate.record(start, 10_000, 5_000);
+        rate.record(start, 0, 5_000);
+
+        let now = rate.rates(start);
+        assert_eq!((now.sent_bps, now.received_bps), (2_000, 2_000));
+        assert_eq!((now.sent_bps_60s, now.received_bps_60s), (166, 166));
+
+        // Past the short window, still inside the long one
+        let later = rate.rates(start + Duration::from_secs(11));
+        assert_eq!((later.sent_bps, later.received_bps), (0, 0));
+        assert!(later.sent_bps_60s > 0 && later.sent_bps_60s <= 166);
+
+        assert_eq!(
+            rate.rates(start + Duration::from_secs(121)),
+            TransferRates::default()
+        );
+    }
 }
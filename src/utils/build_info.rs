@@ -0,0 +1,127 @@
+//! What is running: the build embedded by `build.rs` and the subsystems the
+//! configuration turns on, for `GET /api/version` and the startup log.
+
+use crate::config::Config;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::info;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Full commit hash, "unknown" for builds outside a git checkout
+pub const GIT_HASH: &str = env!("RUSTSOCKS_GIT_HASH");
+
+/// `rustc --version` of the compiler that built this binary
+pub const RUSTC_VERSION: &str = env!("RUSTSOCKS_RUSTC_VERSION");
+
+const BUILD_TIMESTAMP: &str = env!("RUSTSOCKS_BUILD_TIMESTAMP");
+
+/// When the binary was built (`SOURCE_DATE_EPOCH` when that was set)
+pub fn build_time() -> Option<DateTime<Utc>> {
+    BUILD_TIMESTAMP
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+}
+
+/// Cargo features compiled in
+pub fn enabled_features() -> Vec<&'static str> {
+    [
+        ("database", cfg!(feature = "database")),
+        ("metrics", cfg!(feature = "metrics")),
+        ("fast-allocator", cfg!(feature = "fast-allocator")),
+        ("gssapi", cfg!(feature = "gssapi")),
+        ("ldap", cfg!(feature = "ldap")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+/// Optional subsystems a configuration enables
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subsystems {
+    pub acl: bool,
+    pub qos: bool,
+    /// TLS on the SOCKS listener
+    pub tls: bool,
+    /// Where session history is kept: `memory`, the database URL scheme (`sqlite`,
+    /// `mysql`, `postgres`) or `disabled`
+    pub session_storage: String,
+}
+
+impl Subsystems {
+    pub fn from_config(config: &Config) -> Self {
+        let session_storage = if !config.sessions.enabled {
+            "disabled".to_string()
+        } else if config.sessions.uses_database() {
+            config
+                .sessions
+                .database_url
+                .as_deref()
+                .and_then(|url| url.split_once(':'))
+                .map(|(scheme, _)| scheme.to_ascii_lowercase())
+                .unwrap_or_else(|| "database".to_string())
+        } else {
+            "memory".to_string()
+        };
+
+        Self {
+            acl: config.acl.enabled,
+            qos: config.qos.enabled,
+            tls: config.server.tls.enabled,
+            session_storage,
+        }
+    }
+}
+
+/// Log the build and the enabled subsystems, once at startup
+pub fn log_startup(config: &Config, config_path: Option<&Path>) {
+    let subsystems = Subsystems::from_config(config);
+    info!(
+        version = VERSION,
+        git_hash = GIT_HASH,
+        built_at = %build_time().map(|at| at.to_rfc3339()).unwrap_or_default(),
+        rustc = RUSTC_VERSION,
+        features = %enabled_features().join(","),
+        config = %config_path.map(|path| path.display().to_string()).unwrap_or_default(),
+        acl = subsystems.acl,
+        qos = subsystems.qos,
+        tls = subsystems.tls,
+        session_storage = %subsystems.session_storage,
+        "Build and runtime information"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_info_is_embedded() {
+        assert!(!GIT_HASH.is_empty());
+        assert!(RUSTC_VERSION.starts_with("rustc ") || RUSTC_VERSION == "unknown");
+        assert!(build_time().is_some());
+        assert_eq!(
+            enabled_features().contains(&"database"),
+            cfg!(feature = "database")
+        );
+    }
+
+    #[test]
+    fn session_storage_names_the_backend() {
+        let mut config = Config::default();
+        assert_eq!(Subsystems::from_config(&config).session_storage, "disabled");
+
+        config.sessions.enabled = true;
+        assert_eq!(Subsystems::from_config(&config).session_storage, "memory");
+
+        config.sessions.storage = "database".to_string();
+        config.sessions.database_url = Some("postgres://rustsocks@db/sessions".to_string());
+        assert_eq!(Subsystems::from_config(&config).session_storage, "postgres");
+
+        config.sessions.enabled = false;
+        assert_eq!(Subsystems::from_config(&config).session_storage, "disabled");
+    }
+}
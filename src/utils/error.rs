@@ -1,3 +1,4 @@
+use crate::qos::ConnectionLimitExceeded;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -11,12 +12,25 @@ pub enum RustSocksError {
     #[error("Authentication failed: {0}")]
     AuthFailed(String),
 
+    #[error("Impersonation denied: {0}")]
+    ImpersonationDenied(String),
+
     #[error("Configuration error: {0}")]
     Config(String),
 
+    #[error("Connection limit exceeded: {0}")]
+    ConnectionLimit(ConnectionLimitExceeded),
+
     #[error("Connection closed")]
     ConnectionClosed,
 
+    #[error("Idle timeout")]
+    IdleTimeout,
+
+    /// The client did not finish the SOCKS handshake in time; names the phase it stalled in
+    #[error("Handshake timed out during {0}")]
+    HandshakeTimeout(&'static str),
+
     #[error("Unsupported command: {0}")]
     UnsupportedCommand(u8),
 
@@ -0,0 +1,164 @@
+//! Debounced watching of a configuration file that is reloaded while running (the ACL
+//! file, the userpass users file).
+//!
+//! Filesystem events and a polling fallback feed one task. Changes are coalesced until
+//! the file has been quiet for [`RELOAD_DEBOUNCE`]; then the file is read, and the
+//! contents handed to the owner, which parses, validates and swaps. A file that is
+//! empty or changes while it is read is left for the next round, and contents that were
+//! handed over once are not handed over again until the file changes.
+
+use notify::{
+    Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Result as NotifyResult, Watcher,
+};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, timeout, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+/// Quiet time after the last change before the file is read, so the several events of
+/// one save (write to a temp file, rename over the original) cause a single reload
+pub const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How often the file is checked in case filesystem events are missed
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileFingerprint {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileFingerprint {
+    fn capture(path: &Path) -> Result<Self, String> {
+        let metadata =
+            std::fs::metadata(path).map_err(|e| format!("Failed to access metadata: {}", e))?;
+        Ok(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// A running watch; dropping it stops the watch.
+pub struct FileWatch {
+    _watcher: RecommendedWatcher,
+    handle: JoinHandle<()>,
+}
+
+impl FileWatch {
+    /// Watch `path` and call `on_change` with its contents each time it settles on new
+    /// contents. `label` names the file in logs.
+    ///
+    /// The file as it is now counts as loaded, so only later changes are reported.
+    pub fn start<F, Fut>(path: PathBuf, label: &'static str, on_change: F) -> Result<Self, String>
+    where
+        F: FnMut(String) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        // Events only wake the reload task, so a full channel loses nothing
+        let (tx, rx) = mpsc::channel(1);
+        let mut watcher = RecommendedWatcher::new(
+            move |res: NotifyResult<Event>| {
+                if let Ok(event) = res {
+                    if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+                        let _ = tx.try_send(());
+                    }
+                }
+            },
+            Config::default()
+                .with_poll_interval(POLL_INTERVAL)
+                .with_compare_contents(true), // Only trigger on actual content changes
+        )
+        .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+        watcher
+            .watch(&path, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {}: {}", label, e))?;
+
+        let loaded = FileFingerprint::capture(&path).ok();
+        info!(
+            path = ?path,
+            debounce_ms = RELOAD_DEBOUNCE.as_millis() as u64,
+            "Watching {} for changes", label
+        );
+
+        Ok(Self {
+            _watcher: watcher,
+            handle: tokio::spawn(run(path, label, rx, loaded, on_change)),
+        })
+    }
+}
+
+impl Drop for FileWatch {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Wait for changes, let them settle, and hand over the new contents
+async fn run<F, Fut>(
+    path: PathBuf,
+    label: &'static str,
+    mut events: mpsc::Receiver<()>,
+    mut loaded: Option<FileFingerprint>,
+    mut on_change: F,
+) where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    // Polling fallback for environments where filesystem events are unreliable, and for
+    // editors that replace the file so the watch stays on the old one
+    let mut ticker = interval(POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                if event.is_none() {
+                    return;
+                }
+            }
+            _ = ticker.tick() => {}
+        }
+
+        if FileFingerprint::capture(&path).ok() == loaded {
+            continue;
+        }
+        settle(&mut events).await;
+
+        match read_settled(&path) {
+            Ok(Some((content, fingerprint))) => {
+                if loaded.as_ref() == Some(&fingerprint) {
+                    continue;
+                }
+                info!(path = ?path, "{} changed, reloading", label);
+                on_change(content).await;
+                // Not handed over again until the file changes
+                loaded = Some(fingerprint);
+            }
+            Ok(None) => debug!(path = ?path, "{} not settled, retrying", label),
+            Err(e) => warn!(path = ?path, error = %e, "Failed to read {}", label),
+        }
+    }
+}
+
+/// Return once no event has arrived for [`RELOAD_DEBOUNCE`]
+async fn settle(events: &mut mpsc::Receiver<()>) {
+    while let Ok(Some(())) = timeout(RELOAD_DEBOUNCE, events.recv()).await {}
+}
+
+/// Contents of the file with the fingerprint they were read at; `None` while the file
+/// is empty or changed during the read
+fn read_settled(path: &Path) -> Result<Option<(String, FileFingerprint)>, String> {
+    let before = FileFingerprint::capture(path)?;
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let after = FileFingerprint::capture(path)?;
+
+    // An empty file is what an editor leaves between truncating and writing
+    if before != after || content.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((content, after)))
+}
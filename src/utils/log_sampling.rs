@@ -0,0 +1,183 @@
+//! Sampling of per-connection log events (`[logging.sampling]`).
+//!
+//! At high connection rates the info-level events every connection writes (new
+//! connection, login, request, tunnel established) swamp a log pipeline. Each accepted
+//! connection is served inside [`connection_scope`], which draws the ID of the session
+//! the connection will carry and decides from it once whether the connection is logged,
+//! so a sampled connection keeps all of its events, those of its session included, and
+//! a stored session ID tells whether its events were kept. [`SamplingLayer`] drops the info, debug and trace events
+//! of the other connections. Warnings and errors, the access log and everything logged
+//! outside a connection are never sampled, and a login by one of `always_log_users`
+//! turns the rest of its connection back on.
+
+use crate::config::LogSamplingSettings;
+use crate::session::admission::ACCESS_LOG_TARGET;
+use crate::session::types::new_session_id;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use uuid::Uuid;
+
+#[derive(Debug)]
+struct Sampling {
+    ratio: f64,
+    always_log_users: HashSet<String>,
+}
+
+/// `None` while every connection is logged
+static SAMPLING: RwLock<Option<Arc<Sampling>>> = RwLock::new(None);
+
+tokio::task_local! {
+    static CONNECTION: ConnectionLog;
+}
+
+struct ConnectionLog {
+    logged: Cell<bool>,
+    /// ID for the connection's session, until the session takes it
+    session_id: Cell<Option<Uuid>>,
+}
+
+/// Apply `logging.sampling`; connections already running keep their decision.
+pub fn configure(settings: &LogSamplingSettings) {
+    let sampling = (settings.connection_events_ratio < 1.0).then(|| {
+        Arc::new(Sampling {
+            ratio: settings.connection_events_ratio,
+            always_log_users: settings.always_log_users.iter().cloned().collect(),
+        })
+    });
+    *SAMPLING.write().unwrap_or_else(|e| e.into_inner()) = sampling;
+}
+
+fn current() -> Option<Arc<Sampling>> {
+    SAMPLING.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Run one client connection with its sampling decision.
+pub fn connection_scope<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let session_id = new_session_id();
+    let logged = current().is_none_or(|sampling| is_sampled(session_id, sampling.ratio));
+    CONNECTION.scope(
+        ConnectionLog {
+            logged: Cell::new(logged),
+            session_id: Cell::new(Some(session_id)),
+        },
+        future,
+    )
+}
+
+/// The session ID the sampling decision of the current connection was made from;
+/// `Some` once per connection, `None` afterwards and outside a connection.
+pub fn take_session_id() -> Option<Uuid> {
+    CONNECTION
+        .try_with(|log| log.session_id.take())
+        .ok()
+        .flatten()
+}
+
+/// Log the rest of the current connection in full when `user` is one of
+/// `logging.sampling.always_log_users`. Call it before logging the login itself.
+pub fn note_user(user: &str) {
+    let Some(sampling) = current() else {
+        return;
+    };
+    if sampling.always_log_users.contains(user) {
+        let _ = CONNECTION.try_with(|log| log.logged.set(true));
+    }
+}
+
+/// Whether the connection carrying session `session_id` is logged. The ID's timestamp
+/// bits are mixed with its random ones, so any run of sessions keeps close to `ratio`.
+fn is_sampled(session_id: Uuid, ratio: f64) -> bool {
+    let (high, low) = session_id.as_u64_pair();
+    // splitmix64 finalizer
+    let mut z = (high ^ low).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    ((z >> 11) as f64 / (1u64 << 53) as f64) < ratio
+}
+
+/// Drops the info and more verbose events of connections that were not sampled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SamplingLayer;
+
+impl<S: Subscriber> Layer<S> for SamplingLayer {
+    fn event_enabled(&self, event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        let metadata = event.metadata();
+        if *metadata.level() <= Level::WARN || metadata.target() == ACCESS_LOG_TARGET {
+            return true;
+        }
+        CONNECTION.try_with(|log| log.logged.get()).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logged() -> bool {
+        CONNECTION.try_with(|log| log.logged.get()).unwrap_or(true)
+    }
+
+    #[test]
+    fn ratio_bounds_and_spread() {
+        let ids: Vec<Uuid> = (0..10_000).map(|_| new_session_id()).collect();
+        assert!(ids.iter().all(|id| !is_sampled(*id, 0.0)));
+        assert!(ids.iter().all(|id| is_sampled(*id, 1.0)));
+
+        let kept = ids.iter().filter(|id| is_sampled(**id, 0.1)).count();
+        assert!((850..1150).contains(&kept), "kept {} of 10000", kept);
+        // The same session always gets the same answer
+        assert!(ids
+            .iter()
+            .all(|id| is_sampled(*id, 0.1) == is_sampled(*id, 0.1)));
+    }
+
+    #[tokio::test]
+    async fn the_decision_follows_the_session_id() {
+        configure(&LogSamplingSettings {
+            connection_events_ratio: 0.5,
+            always_log_users: Vec::new(),
+        });
+
+        let outcomes = futures::future::join_all((0..64).map(|_| {
+            connection_scope(async {
+                let session_id = take_session_id().expect("reserved for the connection");
+                assert_eq!(take_session_id(), None);
+                (session_id, logged())
+            })
+        }))
+        .await;
+        configure(&LogSamplingSettings::default());
+
+        assert!(outcomes
+            .iter()
+            .all(|(session_id, logged)| is_sampled(*session_id, 0.5) == *logged));
+        assert_eq!(take_session_id(), None);
+    }
+
+    #[tokio::test]
+    async fn always_logged_users_turn_their_connection_back_on() {
+        configure(&LogSamplingSettings {
+            connection_events_ratio: 0.0,
+            always_log_users: vec!["alice".to_string()],
+        });
+
+        let outcome = connection_scope(async {
+            let before = logged();
+            note_user("bob");
+            let after_bob = logged();
+            note_user("alice");
+            (before, after_bob, logged())
+        })
+        .await;
+        configure(&LogSamplingSettings::default());
+
+        assert_eq!(outcome, (false, false, true));
+        // Outside a connection nothing is sampled
+        assert!(logged());
+    }
+}
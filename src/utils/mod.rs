@@ -1,2 +1,5 @@
+pub mod build_info;
 pub mod error;
+pub mod file_watch;
+pub mod log_sampling;
 pub mod system;
@@ -2,7 +2,7 @@
 ///
 /// These tests verify that the REST API endpoints for ACL management work correctly,
 /// including CRUD operations for groups, users, and global settings.
-use rustsocks::acl::types::{AclConfig, Action, GlobalAclConfig, GroupAcl};
+use rustsocks::acl::types::{AclConfig, Action, GlobalAclConfig, GroupAcl, RuleLogLevel};
 use rustsocks::acl::{load_config, save_config};
 use tempfile::TempDir;
 
@@ -14,6 +14,7 @@ fn create_test_config() -> AclConfig {
         },
         groups: vec![GroupAcl {
             name: "developers".to_string(),
+            parents: vec![],
             rules: vec![],
         }],
         users: vec![],
@@ -36,8 +37,13 @@ async fn test_add_group_rule() {
         description: "Test rule".to_string(),
         destinations: vec!["*.example.com".to_string()],
         ports: vec!["443".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
 
     rustsocks::acl::crud::add_group_rule(&mut config, "developers", rule.clone()).unwrap();
@@ -59,8 +65,13 @@ async fn test_update_group_rule() {
         description: "Original rule".to_string(),
         destinations: vec!["*.example.com".to_string()],
         ports: vec!["443".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
     rustsocks::acl::crud::add_group_rule(&mut config, "developers", rule1).unwrap();
     save_config(&config, &config_path).await.unwrap();
@@ -77,8 +88,13 @@ async fn test_update_group_rule() {
         description: "Updated rule".to_string(),
         destinations: vec!["*.example.com".to_string()],
         ports: vec!["443".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 500,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
 
     let old_rule = rustsocks::acl::crud::update_group_rule(
@@ -109,8 +125,13 @@ async fn test_delete_group_rule() {
         description: "Test rule".to_string(),
         destinations: vec!["*.example.com".to_string()],
         ports: vec!["443".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
     rustsocks::acl::crud::add_group_rule(&mut config, "developers", rule).unwrap();
     save_config(&config, &config_path).await.unwrap();
@@ -140,8 +161,13 @@ async fn test_add_duplicate_rule_fails() {
         description: "Test rule".to_string(),
         destinations: vec!["*.example.com".to_string()],
         ports: vec!["443".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
 
     // Add first time - should succeed
@@ -165,8 +191,13 @@ async fn test_update_nonexistent_rule_fails() {
         description: "New rule".to_string(),
         destinations: vec!["*.nonexistent.com".to_string()],
         ports: vec!["443".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
 
     let result =
@@ -184,8 +215,13 @@ async fn test_search_rules() {
         description: "SSH to prod".to_string(),
         destinations: vec!["*.prod.com".to_string()],
         ports: vec!["22".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
 
     let rule2 = rustsocks::acl::types::AclRule {
@@ -193,8 +229,13 @@ async fn test_search_rules() {
         description: "HTTPS to prod".to_string(),
         destinations: vec!["*.prod.com".to_string()],
         ports: vec!["443".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 200,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
 
     rustsocks::acl::crud::add_group_rule(&mut config, "developers", rule1).unwrap();
@@ -205,6 +246,7 @@ async fn test_search_rules() {
         destination: Some("prod.com".to_string()),
         port: None,
         action: None,
+        log: None,
     };
     let results = rustsocks::acl::crud::search_rules(&config, &criteria);
     assert_eq!(results.len(), 2);
@@ -214,6 +256,7 @@ async fn test_search_rules() {
         destination: None,
         port: None,
         action: Some("block".to_string()),
+        log: None,
     };
     let results = rustsocks::acl::crud::search_rules(&config, &criteria);
     assert_eq!(results.len(), 1);
@@ -231,6 +274,7 @@ async fn test_create_and_delete_group() {
     // Add new group
     config.groups.push(GroupAcl {
         name: "admins".to_string(),
+        parents: vec![],
         rules: vec![],
     });
 
@@ -238,7 +282,7 @@ async fn test_create_and_delete_group() {
     assert_eq!(config.groups[1].name, "admins");
 
     // Delete group
-    let deleted = rustsocks::acl::crud::delete_group(&mut config, "admins").unwrap();
+    let deleted = rustsocks::acl::crud::delete_group(&mut config, "admins", false).unwrap();
     assert_eq!(deleted.name, "admins");
     assert_eq!(config.groups.len(), 1);
 }
@@ -257,8 +301,13 @@ async fn test_add_user_rule() {
         description: "Alice blocked from admin".to_string(),
         destinations: vec!["admin.example.com".to_string()],
         ports: vec!["*".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 1000,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
 
     rustsocks::acl::crud::add_user_rule(&mut config, "alice", rule.clone()).unwrap();
@@ -304,8 +353,13 @@ async fn test_rule_identifier_matching() {
         description: "Test".to_string(),
         destinations: vec!["*.example.com".to_string()],
         ports: vec!["443".to_string()],
+        sources: vec![],
         protocols: vec![rustsocks::acl::Protocol::Tcp],
         priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
     };
 
     // Match with ports
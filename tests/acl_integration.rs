@@ -1,9 +1,9 @@
-use rustsocks::acl::types::{AclRule, GlobalAclConfig, UserAcl};
-use rustsocks::acl::{AclConfig, AclEngine, AclStats, Action, Protocol};
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, AclMode, AclStats, Action, Protocol};
 use rustsocks::auth::AuthManager;
 use rustsocks::config::{AuthConfig, PamSettings};
 use rustsocks::protocol::ReplyCode;
-use rustsocks::qos::{ConnectionLimits, QosEngine};
+use rustsocks::qos::QosEngine;
 use rustsocks::server::proxy::TrafficUpdateConfig;
 use rustsocks::server::{handle_client, ClientHandlerContext, ConnectionPool, PoolConfig};
 use rustsocks::session::{SessionManager, SessionStatus};
@@ -26,8 +26,13 @@ fn blocking_acl_config() -> AclConfig {
                 description: "Block blocked.example.com".to_string(),
                 destinations: vec!["blocked.example.com".to_string()],
                 ports: vec!["*".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Tcp],
                 priority: 1000,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             }],
         }],
         groups: vec![],
@@ -51,16 +56,26 @@ async fn acl_blocks_connection_and_tracks_stats() {
         AuthManager::new(&AuthConfig {
             client_method: "none".into(),
             socks_method: "none".into(),
+            socks_method_preference: Vec::new(),
             users: Vec::new(),
+            users_file: None,
             pam: PamSettings::default(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         })
         .expect("auth manager"),
     );
 
     let acl_engine = Arc::new(AclEngine::new(blocking_acl_config()).expect("acl engine"));
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new(String::from("anonymous"));
+    let anonymous_user = Arc::<str>::from("anonymous");
 
     let listener = TcpListener::bind("127.0.0.1:0")
         .await
@@ -77,8 +92,25 @@ async fn acl_blocks_connection_and_tracks_stats() {
             session_manager: session_manager.clone(),
             traffic_config: TrafficUpdateConfig::default(),
             qos_engine: QosEngine::None,
-            connection_limits: ConnectionLimits::default(),
+            connection_limits: Default::default(),
             connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+            special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+            sni_routing: rustsocks::server::SniRouting::default(),
+            resolver: Arc::new(rustsocks::server::SystemResolver),
+            host_hints: None,
+            tunnel_keepalive: Default::default(),
+            upstream_socket_options: Default::default(),
+            egress: Default::default(),
+            upstream_proxy: None,
+            upstream_tls: None,
+            udp_association: Default::default(),
+            udp_datagrams: Default::default(),
+            bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+            enable_socks4: false,
+            address_selection: Default::default(),
+            connect_retry: Default::default(),
+            handshake: Default::default(),
+            allow_domain_requests: true,
         });
 
         tokio::spawn(async move {
@@ -151,6 +183,7 @@ struct AllowEnv {
     server_addr: SocketAddr,
     upstream_addr: SocketAddr,
     session_manager: Arc<SessionManager>,
+    acl_stats: Arc<AclStats>,
     server_task: tokio::task::JoinHandle<()>,
     upstream_task: tokio::task::JoinHandle<()>,
 }
@@ -163,20 +196,34 @@ impl AllowEnv {
 }
 
 async fn spawn_allow_env(expected: usize) -> AllowEnv {
+    let acl_engine = Arc::new(AclEngine::new(allowing_acl_config()).expect("acl engine"));
+    spawn_env(acl_engine, expected).await
+}
+
+async fn spawn_env(acl_engine: Arc<AclEngine>, expected: usize) -> AllowEnv {
     let auth_manager = Arc::new(
         AuthManager::new(&AuthConfig {
             client_method: "none".into(),
             socks_method: "none".into(),
+            socks_method_preference: Vec::new(),
             users: Vec::new(),
+            users_file: None,
             pam: PamSettings::default(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         })
         .expect("auth manager"),
     );
 
-    let acl_engine = Arc::new(AclEngine::new(allowing_acl_config()).expect("acl engine"));
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new(String::from("anonymous"));
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let upstream_listener = TcpListener::bind("127.0.0.1:0")
@@ -207,8 +254,25 @@ async fn spawn_allow_env(expected: usize) -> AllowEnv {
             session_manager: session_manager.clone(),
             traffic_config: TrafficUpdateConfig::default(),
             qos_engine: QosEngine::None,
-            connection_limits: ConnectionLimits::default(),
+            connection_limits: Default::default(),
             connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+            special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+            sni_routing: rustsocks::server::SniRouting::default(),
+            resolver: Arc::new(rustsocks::server::SystemResolver),
+            host_hints: None,
+            tunnel_keepalive: Default::default(),
+            upstream_socket_options: Default::default(),
+            egress: Default::default(),
+            upstream_proxy: None,
+            upstream_tls: None,
+            udp_association: Default::default(),
+            udp_datagrams: Default::default(),
+            bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+            enable_socks4: false,
+            address_selection: Default::default(),
+            connect_retry: Default::default(),
+            handshake: Default::default(),
+            allow_domain_requests: true,
         });
 
         tokio::spawn(async move {
@@ -236,6 +300,7 @@ async fn spawn_allow_env(expected: usize) -> AllowEnv {
         server_addr,
         upstream_addr,
         session_manager,
+        acl_stats,
         server_task,
         upstream_task,
     }
@@ -307,6 +372,45 @@ async fn acl_allows_connection_and_creates_session() {
     assert_eq!(closed[0].status, SessionStatus::Closed);
 }
 
+#[tokio::test]
+async fn monitor_mode_relays_blocked_connection_and_marks_session() {
+    let block_all = AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![],
+        groups: vec![],
+    };
+    let acl_engine = Arc::new(
+        AclEngine::new(block_all)
+            .expect("acl engine")
+            .with_mode(AclMode::Monitor),
+    );
+    let env = spawn_env(acl_engine, 1).await;
+    let session_manager = env.session_manager.clone();
+    let acl_stats = env.acl_stats.clone();
+
+    perform_handshake(env.server_addr, env.upstream_addr)
+        .await
+        .expect("monitor mode relays the connection");
+    env.wait().await;
+
+    // Counted as the block it would have been
+    let totals = acl_stats.snapshot();
+    assert_eq!(totals.blocked, 1);
+    assert_eq!(totals.allowed, 0);
+
+    assert!(session_manager.rejected_snapshot().await.is_empty());
+    let closed = session_manager.closed_snapshot().await;
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].status, SessionStatus::Closed);
+    assert!(closed[0].would_block);
+    assert_eq!(
+        closed[0].acl_rule_matched.as_deref(),
+        Some("Default policy (no matching groups)")
+    );
+}
+
 #[tokio::test]
 #[ignore = "Timing-sensitive benchmark"]
 async fn acl_performance_under_seven_ms() {
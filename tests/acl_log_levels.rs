@@ -0,0 +1,204 @@
+/// Integration tests for per-rule access-log verbosity
+///
+/// Decisions are recorded into an `AccessLog` while a JSON subscriber captures everything
+/// written to the access-log target.
+use rustsocks::acl::types::{AclConfig, AclRule, Action, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclEngine, Protocol};
+use rustsocks::protocol::Address;
+use rustsocks::session::{AccessLog, AclAccess};
+use serde_json::Value;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    /// Fields of every captured ACL record
+    fn acl_records(&self) -> Vec<Value> {
+        let bytes = self.0.lock().unwrap();
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .filter(|record| record["target"] == "rustsocks::access")
+            .map(|record| record["fields"].clone())
+            .filter(|fields| fields["stage"] == "acl")
+            .collect()
+    }
+}
+
+fn capture() -> (Captured, tracing::subscriber::DefaultGuard) {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_max_level(tracing::Level::INFO)
+        .with_writer(move || writer.clone())
+        .finish();
+    (captured, tracing::subscriber::set_default(subscriber))
+}
+
+fn rule(destination: &str, log: RuleLogLevel) -> AclRule {
+    AclRule {
+        action: Action::Allow,
+        description: format!("{} ({})", destination, log.as_str()),
+        destinations: vec![destination.to_string()],
+        ports: vec!["443".to_string()],
+        sources: vec![],
+        protocols: vec![Protocol::Tcp],
+        priority: 100,
+        log,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
+    }
+}
+
+fn engine() -> AclEngine {
+    AclEngine::new(AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![UserAcl {
+            username: "alice".to_string(),
+            groups: vec![],
+            rules: vec![
+                rule("*.cdn.example.com", RuleLogLevel::Silent),
+                rule("*.example.net", RuleLogLevel::Minimal),
+                rule("audit.example.org", RuleLogLevel::Verbose),
+                rule("intranet.example.org", RuleLogLevel::Default),
+            ],
+        }],
+        groups: vec![],
+    })
+    .unwrap()
+}
+
+async fn connect(engine: &AclEngine, log: &AccessLog, host: &str, port: u16) {
+    let destination = Address::Domain(host.into());
+    let verdict = engine
+        .verdict_with_groups("alice", &[], &destination, port, &Protocol::Tcp, None)
+        .await;
+    let client_addr: SocketAddr = "192.0.2.10:50000".parse().unwrap();
+    log.record_acl(
+        &verdict,
+        &AclAccess {
+            user: "alice",
+            authenticated_user: Some("alice"),
+            correlation_id: None,
+            client_addr,
+            destination: &destination,
+            port,
+            protocol: "tcp",
+            socks_version: 5,
+            command: "connect",
+            auth_method: "userpass",
+            group_count: 0,
+        },
+    );
+}
+
+#[tokio::test]
+async fn silent_rule_counts_hits_without_logging() {
+    let (captured, _guard) = capture();
+    let engine = engine();
+    let log = AccessLog::new();
+
+    for _ in 0..3 {
+        connect(&engine, &log, "img.cdn.example.com", 443).await;
+    }
+
+    assert!(captured.acl_records().is_empty());
+    assert_eq!(log.stats().suppressed, 3);
+    assert_eq!(log.stats().written, 0);
+    let usage = engine.rule_usage().await;
+    let silent = usage
+        .iter()
+        .find(|rule| rule.description.starts_with("*.cdn.example.com"))
+        .unwrap();
+    assert_eq!(silent.hits, 3);
+}
+
+#[tokio::test]
+async fn minimal_rule_writes_aggregated_records() {
+    let (captured, _guard) = capture();
+    let engine = engine();
+    let log = AccessLog::new();
+
+    for _ in 0..4 {
+        connect(&engine, &log, "a.example.net", 443).await;
+    }
+    for _ in 0..2 {
+        connect(&engine, &log, "b.example.net", 443).await;
+    }
+    assert!(captured.acl_records().is_empty());
+
+    assert_eq!(log.flush_minimal(), 2);
+    let records = captured.acl_records();
+    assert_eq!(records.len(), 2);
+    for (destination, connections) in [("a.example.net:443", 4), ("b.example.net:443", 2)] {
+        let record = records
+            .iter()
+            .find(|record| record["destination"] == destination)
+            .unwrap();
+        assert_eq!(record["aggregated"], true);
+        assert_eq!(record["user"], "alice");
+        assert_eq!(record["connections"], connections);
+    }
+
+    // Nothing left to aggregate until new connections arrive
+    assert_eq!(log.flush_minimal(), 0);
+    assert_eq!(log.stats().aggregated, 6);
+    assert_eq!(log.stats().written, 2);
+}
+
+#[tokio::test]
+async fn blocked_connections_are_always_logged() {
+    let (captured, _guard) = capture();
+    let engine = engine();
+    let log = AccessLog::new();
+
+    // The silent rule only covers port 443; port 80 falls through to the default policy
+    connect(&engine, &log, "img.cdn.example.com", 80).await;
+
+    let records = captured.acl_records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["outcome"], "blocked");
+    assert_eq!(records[0]["destination"], "img.cdn.example.com");
+    assert_eq!(records[0]["port"], 80);
+    assert_eq!(log.stats().suppressed, 0);
+}
+
+#[tokio::test]
+async fn verbose_rule_adds_negotiation_details() {
+    let (captured, _guard) = capture();
+    let engine = engine();
+    let log = AccessLog::new();
+
+    connect(&engine, &log, "audit.example.org", 443).await;
+    connect(&engine, &log, "intranet.example.org", 443).await;
+
+    let records = captured.acl_records();
+    assert_eq!(records.len(), 2);
+    let verbose = &records[0];
+    assert_eq!(verbose["outcome"], "allowed");
+    assert_eq!(verbose["auth_method"], "userpass");
+    assert_eq!(verbose["command"], "connect");
+    assert_eq!(verbose["socks_version"], 5);
+
+    let default = &records[1];
+    assert_eq!(default["destination"], "intranet.example.org");
+    assert!(default.get("auth_method").is_none());
+}
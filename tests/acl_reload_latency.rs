@@ -0,0 +1,157 @@
+/// Integration test for ACL reloads under continuous evaluation
+///
+/// Runs on a single-threaded runtime so any reload work done on the runtime shows up
+/// directly as a gap between consecutive evaluations.
+use rustsocks::acl::types::{
+    AclConfig, AclRule, Action, GlobalAclConfig, GroupAcl, RuleLogLevel, UserAcl,
+};
+use rustsocks::acl::{AclDecision, AclEngine, Protocol};
+use rustsocks::protocol::Address;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Rules per generated configuration
+const RULES: usize = 40_000;
+const RULES_PER_USER: usize = 20;
+
+/// Extra evaluation latency tolerated while a reload is in progress
+const MAX_EXTRA_LATENCY: Duration = Duration::from_millis(5);
+
+fn rule(action: Action, destination: String, port: &str) -> AclRule {
+    AclRule {
+        action,
+        description: format!("{} {}", destination, port),
+        destinations: vec![destination],
+        ports: vec![port.to_string()],
+        sources: vec![],
+        protocols: vec![Protocol::Tcp],
+        priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
+    }
+}
+
+/// A large configuration; `generation` changes one of alice's rules.
+fn large_config(generation: usize) -> AclConfig {
+    let users = (0..RULES / RULES_PER_USER)
+        .map(|user| UserAcl {
+            username: format!("user{}", user),
+            groups: Vec::new(),
+            rules: (0..RULES_PER_USER)
+                .map(|n| {
+                    let i = user * RULES_PER_USER + n;
+                    rule(
+                        Action::Allow,
+                        format!("10.{}.{}.0/24", i / 256 % 256, i % 256),
+                        "443",
+                    )
+                })
+                .collect(),
+        })
+        .chain(std::iter::once(UserAcl {
+            username: "alice".to_string(),
+            groups: vec!["developers".to_string()],
+            rules: vec![rule(
+                Action::Allow,
+                format!("gen{}.example.com", generation),
+                "443",
+            )],
+        }))
+        .collect();
+
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users,
+        groups: vec![GroupAcl {
+            name: "developers".to_string(),
+            parents: vec![],
+            rules: vec![rule(Action::Block, "*.internal".to_string(), "*")],
+        }],
+    }
+}
+
+/// Evaluate for alice until `stop` is set; returns the longest time one iteration took.
+async fn evaluate_until(engine: Arc<AclEngine>, stop: Arc<AtomicBool>) -> Duration {
+    let groups = vec!["developers".to_string()];
+    let dest = Address::Domain("api.example.com".into());
+    let mut slowest = Duration::ZERO;
+    while !stop.load(Ordering::Relaxed) {
+        let started = Instant::now();
+        engine
+            .evaluate_with_groups("alice", &groups, &dest, 443, &Protocol::Tcp, None)
+            .await;
+        tokio::task::yield_now().await;
+        slowest = slowest.max(started.elapsed());
+    }
+    slowest
+}
+
+async fn allowed(engine: &AclEngine, host: &str) -> bool {
+    let (decision, _) = engine
+        .evaluate_with_groups(
+            "alice",
+            &["developers".to_string()],
+            &Address::Domain(host.into()),
+            443,
+            &Protocol::Tcp,
+            None,
+        )
+        .await;
+    decision == AclDecision::Allow
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn evaluations_are_not_stalled_by_reloads() {
+    let engine = Arc::new(AclEngine::new(large_config(0)).unwrap());
+    let reloads: Vec<AclConfig> = (1..=3).map(large_config).collect();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let baseline = tokio::spawn(evaluate_until(engine.clone(), stop.clone()));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    stop.store(true, Ordering::Relaxed);
+    let baseline = baseline.await.unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let during_reload = tokio::spawn(evaluate_until(engine.clone(), stop.clone()));
+    for config in reloads {
+        engine.reload(config).await.unwrap();
+    }
+    stop.store(true, Ordering::Relaxed);
+    let during_reload = during_reload.await.unwrap();
+
+    assert!(
+        during_reload <= baseline + MAX_EXTRA_LATENCY,
+        "slowest evaluation {:?} during reloads, {:?} before",
+        during_reload,
+        baseline
+    );
+
+    assert!(allowed(&engine, "gen3.example.com").await);
+    assert!(!allowed(&engine, "gen0.example.com").await);
+    assert_eq!(engine.get_user_count().await, RULES / RULES_PER_USER + 1);
+}
+
+#[tokio::test]
+async fn failed_reload_keeps_current_rules() {
+    let engine = AclEngine::new(large_config(0)).unwrap();
+
+    // alice's new rule compiles before the broken one fails the build
+    let mut invalid = large_config(1);
+    let alice = invalid.users.last_mut().unwrap();
+    alice.rules.push(rule(
+        Action::Allow,
+        "broken.example.com".to_string(),
+        "99999",
+    ));
+    assert!(engine.reload(invalid).await.is_err());
+
+    assert!(allowed(&engine, "gen0.example.com").await);
+    assert!(!allowed(&engine, "gen1.example.com").await);
+    assert_eq!(engine.rule_usage().await.len(), RULES + 2);
+    assert_eq!(engine.rule_stats().snapshot().len(), RULES + 2);
+}
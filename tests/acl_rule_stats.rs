@@ -0,0 +1,185 @@
+/// Integration tests for persisted ACL rule hit counters
+///
+/// Each test simulates a restart by flushing one engine's counters and restoring them
+/// into a fresh engine built from the (possibly edited) ACL configuration.
+use rustsocks::acl::rule_stats::{flush, restore, sidecar_path};
+use rustsocks::acl::types::{
+    AclConfig, AclRule, Action, GlobalAclConfig, GroupAcl, RuleLogLevel, UserAcl,
+};
+use rustsocks::acl::{AclEngine, Protocol, RuleStatsPersistence, RuleUsage};
+use rustsocks::protocol::Address;
+use tempfile::TempDir;
+
+fn rule(action: Action, destination: &str, port: &str) -> AclRule {
+    AclRule {
+        action,
+        description: format!("{} {}", destination, port),
+        destinations: vec![destination.to_string()],
+        ports: vec![port.to_string()],
+        sources: vec![],
+        protocols: vec![Protocol::Tcp],
+        priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
+    }
+}
+
+fn config() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![UserAcl {
+            username: "alice".to_string(),
+            groups: vec!["developers".to_string()],
+            rules: vec![
+                rule(Action::Allow, "*.example.com", "443"),
+                rule(Action::Block, "10.0.0.0/8", "*"),
+            ],
+        }],
+        groups: vec![GroupAcl {
+            name: "developers".to_string(),
+            parents: vec![],
+            rules: vec![rule(Action::Allow, "git.internal", "22")],
+        }],
+    }
+}
+
+async fn connect(engine: &AclEngine, dest: Address, port: u16, times: usize) {
+    for _ in 0..times {
+        engine
+            .evaluate_with_groups(
+                "alice",
+                &["developers".to_string()],
+                &dest,
+                port,
+                &Protocol::Tcp,
+                None,
+            )
+            .await;
+    }
+}
+
+fn usage_of<'a>(usage: &'a [RuleUsage], description: &str) -> &'a RuleUsage {
+    usage
+        .iter()
+        .find(|rule| rule.description == description)
+        .unwrap_or_else(|| panic!("rule '{}' not found", description))
+}
+
+async fn seed_traffic(engine: &AclEngine) {
+    connect(engine, Address::Domain("api.example.com".into()), 443, 3).await;
+    connect(engine, Address::IPv4([10, 1, 2, 3]), 80, 2).await;
+    connect(engine, Address::Domain("git.internal".into()), 22, 1).await;
+}
+
+#[tokio::test]
+async fn counters_resume_after_restart() {
+    let dir = TempDir::new().unwrap();
+    let persistence = RuleStatsPersistence::Sidecar(sidecar_path(&dir.path().join("acl.toml")));
+
+    let engine = AclEngine::new(config()).unwrap();
+    seed_traffic(&engine).await;
+    flush(&engine.rule_stats(), &persistence).await;
+
+    // Restart with the rules listed in a different order
+    let mut reordered = config();
+    reordered.users[0].rules.reverse();
+    let engine = AclEngine::new(reordered).unwrap();
+    restore(&engine.rule_stats(), &persistence).await;
+    connect(&engine, Address::Domain("api.example.com".into()), 443, 1).await;
+
+    let usage = engine.rule_usage().await;
+    assert_eq!(usage.len(), 3);
+    assert_eq!(usage_of(&usage, "*.example.com 443").hits, 4);
+    assert_eq!(usage_of(&usage, "10.0.0.0/8 *").hits, 2);
+    assert_eq!(usage_of(&usage, "git.internal 22").hits, 1);
+    assert!(usage_of(&usage, "git.internal 22")
+        .last_matched_at
+        .is_some());
+}
+
+#[tokio::test]
+async fn edited_rule_starts_over_while_others_persist() {
+    let dir = TempDir::new().unwrap();
+    let persistence = RuleStatsPersistence::Sidecar(sidecar_path(&dir.path().join("acl.toml")));
+
+    let engine = AclEngine::new(config()).unwrap();
+    seed_traffic(&engine).await;
+    flush(&engine.rule_stats(), &persistence).await;
+    let before = engine.rule_usage().await;
+
+    // Widen the port range of the domain rule; rewording a description keeps its id
+    let mut edited = config();
+    edited.users[0].rules[0].ports = vec!["443-8443".to_string()];
+    edited.users[0].rules[1].description = "Internal networks".to_string();
+    edited.users[0].rules.swap(0, 1);
+    let engine = AclEngine::new(edited).unwrap();
+    restore(&engine.rule_stats(), &persistence).await;
+
+    let usage = engine.rule_usage().await;
+    let widened = usage_of(&usage, "*.example.com 443");
+    assert_eq!(widened.hits, 0);
+    assert!(widened.last_matched_at.is_none());
+    assert_ne!(
+        widened.rule_id,
+        usage_of(&before, "*.example.com 443").rule_id
+    );
+
+    let renamed = usage_of(&usage, "Internal networks");
+    assert_eq!(renamed.hits, 2);
+    assert_eq!(renamed.rule_id, usage_of(&before, "10.0.0.0/8 *").rule_id);
+    assert_eq!(usage_of(&usage, "git.internal 22").hits, 1);
+}
+
+#[tokio::test]
+async fn dry_runs_are_not_counted() {
+    let engine = AclEngine::new(config()).unwrap();
+    engine
+        .dry_run_with_groups(
+            "alice",
+            &["developers".to_string()],
+            &Address::Domain("api.example.com".into()),
+            443,
+            &Protocol::Tcp,
+            None,
+        )
+        .await;
+
+    let usage = engine.rule_usage().await;
+    assert!(usage.iter().all(|rule| rule.hits == 0));
+}
+
+#[tokio::test]
+async fn reload_keeps_counters_until_reset() {
+    let engine = AclEngine::new(config()).unwrap();
+    seed_traffic(&engine).await;
+    for _ in 0..2 {
+        engine
+            .evaluate_with_groups(
+                "bob",
+                &["developers".to_string()],
+                &Address::Domain("git.internal".into()),
+                22,
+                &Protocol::Tcp,
+                None,
+            )
+            .await;
+    }
+
+    engine.reload(config()).await.unwrap();
+    let usage = engine.rule_usage().await;
+    assert_eq!(usage_of(&usage, "*.example.com 443").hits, 3);
+    assert_eq!(
+        usage_of(&usage, "git.internal 22").users,
+        vec![("bob".to_string(), 2), ("alice".to_string(), 1)]
+    );
+
+    assert_eq!(engine.rule_stats().reset(), 3);
+    let usage = engine.rule_usage().await;
+    assert!(usage
+        .iter()
+        .all(|rule| rule.hits == 0 && rule.users.is_empty() && rule.last_matched_at.is_none()));
+}
@@ -10,9 +10,10 @@
 use rustsocks::acl::engine::AclEngine;
 use rustsocks::acl::stats::AclStats;
 use rustsocks::acl::types::{
-    AclConfig, AclDecision, AclRule, Action, GlobalAclConfig, GroupAcl, Protocol, UserAcl,
+    AclConfig, AclDecision, AclRule, Action, BlockReplyCode, GlobalAclConfig, GroupAcl, Protocol,
+    ResolveMode, RuleLogLevel, UserAcl,
 };
-use rustsocks::protocol::Address;
+use rustsocks::protocol::{Address, ReplyCode};
 use std::sync::Arc;
 
 // ============================================================================
@@ -29,8 +30,13 @@ mod ip_matcher_tests {
             description: "Allow specific IPv4".to_string(),
             destinations: vec!["192.168.1.100".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -43,6 +49,7 @@ mod ip_matcher_tests {
                 &Address::IPv4([192, 168, 1, 100]),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -54,6 +61,7 @@ mod ip_matcher_tests {
                 &Address::IPv4([192, 168, 1, 101]),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block); // default policy
@@ -66,8 +74,13 @@ mod ip_matcher_tests {
             description: "Allow specific IPv6".to_string(),
             destinations: vec!["2001:db8::1".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -82,6 +95,7 @@ mod ip_matcher_tests {
                 ]),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -95,6 +109,7 @@ mod ip_matcher_tests {
                 ]),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -107,8 +122,13 @@ mod ip_matcher_tests {
             description: "Allow IPv4".to_string(),
             destinations: vec!["192.168.1.1".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -123,6 +143,7 @@ mod ip_matcher_tests {
                 ]),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -143,8 +164,13 @@ mod cidr_matcher_tests {
             description: "Allow 10.0.0.0/8".to_string(),
             destinations: vec!["10.0.0.0/8".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -162,7 +188,7 @@ mod cidr_matcher_tests {
 
         for (ip, should_match) in test_cases {
             let (decision, _) = engine
-                .evaluate("alice", &Address::IPv4(ip), 80, &Protocol::Tcp)
+                .evaluate("alice", &Address::IPv4(ip), 80, &Protocol::Tcp, None)
                 .await;
             assert_eq!(
                 decision == AclDecision::Allow,
@@ -180,8 +206,13 @@ mod cidr_matcher_tests {
             description: "Block 192.168.0.0/16".to_string(),
             destinations: vec!["192.168.0.0/16".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Allow);
@@ -199,7 +230,7 @@ mod cidr_matcher_tests {
 
         for (ip, should_block) in test_cases {
             let (decision, _) = engine
-                .evaluate("alice", &Address::IPv4(ip), 80, &Protocol::Tcp)
+                .evaluate("alice", &Address::IPv4(ip), 80, &Protocol::Tcp, None)
                 .await;
             assert_eq!(
                 decision == AclDecision::Block,
@@ -217,8 +248,13 @@ mod cidr_matcher_tests {
             description: "Allow 172.16.50.0/24".to_string(),
             destinations: vec!["172.16.50.0/24".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -236,7 +272,7 @@ mod cidr_matcher_tests {
 
         for (ip, should_match) in test_cases {
             let (decision, _) = engine
-                .evaluate("alice", &Address::IPv4(ip), 80, &Protocol::Tcp)
+                .evaluate("alice", &Address::IPv4(ip), 80, &Protocol::Tcp, None)
                 .await;
             assert_eq!(
                 decision == AclDecision::Allow,
@@ -254,8 +290,13 @@ mod cidr_matcher_tests {
             description: "Allow 10.0.0.1/32".to_string(),
             destinations: vec!["10.0.0.1/32".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -263,12 +304,24 @@ mod cidr_matcher_tests {
 
         // /32 should match only one specific IP
         let (decision, _) = engine
-            .evaluate("alice", &Address::IPv4([10, 0, 0, 1]), 80, &Protocol::Tcp)
+            .evaluate(
+                "alice",
+                &Address::IPv4([10, 0, 0, 1]),
+                80,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Allow);
 
         let (decision, _) = engine
-            .evaluate("alice", &Address::IPv4([10, 0, 0, 2]), 80, &Protocol::Tcp)
+            .evaluate(
+                "alice",
+                &Address::IPv4([10, 0, 0, 2]),
+                80,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Block);
     }
@@ -280,8 +333,13 @@ mod cidr_matcher_tests {
             description: "Allow 2001:db8::/32".to_string(),
             destinations: vec!["2001:db8::/32".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -296,6 +354,7 @@ mod cidr_matcher_tests {
                 ]),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -309,6 +368,7 @@ mod cidr_matcher_tests {
                 ]),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -322,6 +382,7 @@ mod cidr_matcher_tests {
                 ]),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -338,8 +399,13 @@ mod cidr_matcher_tests {
                 "192.168.0.0/16".to_string(),
             ],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -357,7 +423,7 @@ mod cidr_matcher_tests {
 
         for (ip, should_match) in test_cases {
             let (decision, _) = engine
-                .evaluate("alice", &Address::IPv4(ip), 80, &Protocol::Tcp)
+                .evaluate("alice", &Address::IPv4(ip), 80, &Protocol::Tcp, None)
                 .await;
             assert_eq!(
                 decision == AclDecision::Allow,
@@ -383,8 +449,13 @@ mod domain_matcher_tests {
             description: "Allow example.com".to_string(),
             destinations: vec!["example.com".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -394,9 +465,10 @@ mod domain_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -404,9 +476,10 @@ mod domain_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("EXAMPLE.COM".to_string()),
+                &Address::Domain("EXAMPLE.COM".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -415,9 +488,10 @@ mod domain_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("www.example.com".to_string()),
+                &Address::Domain("www.example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -426,9 +500,10 @@ mod domain_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("test.com".to_string()),
+                &Address::Domain("test.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -441,8 +516,13 @@ mod domain_matcher_tests {
             description: "Block *.malware.com".to_string(),
             destinations: vec!["*.malware.com".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Allow);
@@ -464,9 +544,10 @@ mod domain_matcher_tests {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain(domain.to_string()),
+                    &Address::Domain(domain.into()),
                     80,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(
@@ -485,8 +566,13 @@ mod domain_matcher_tests {
             description: "Allow api.*.company.com".to_string(),
             destinations: vec!["api.*.company.com".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -506,9 +592,10 @@ mod domain_matcher_tests {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain(domain.to_string()),
+                    &Address::Domain(domain.into()),
                     80,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(
@@ -527,8 +614,13 @@ mod domain_matcher_tests {
             description: "Allow *.*.example.com".to_string(),
             destinations: vec!["*.*.example.com".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -538,9 +630,10 @@ mod domain_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("api.v1.example.com".to_string()),
+                &Address::Domain("api.v1.example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -548,9 +641,10 @@ mod domain_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("cdn.prod.example.com".to_string()),
+                &Address::Domain("cdn.prod.example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -559,9 +653,10 @@ mod domain_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("api.example.com".to_string()),
+                &Address::Domain("api.example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -578,8 +673,13 @@ mod domain_matcher_tests {
                 "my-app-v2.example.org".to_string(),
             ],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -599,9 +699,10 @@ mod domain_matcher_tests {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain(domain.to_string()),
+                    &Address::Domain(domain.into()),
                     80,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(
@@ -628,8 +729,13 @@ mod port_matcher_tests {
             description: "Allow HTTPS only".to_string(),
             destinations: vec!["*".to_string()], // Empty = match all
             ports: vec!["443".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -638,9 +744,10 @@ mod port_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -648,9 +755,10 @@ mod port_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -663,8 +771,13 @@ mod port_matcher_tests {
             description: "Block high ports".to_string(),
             destinations: vec!["*".to_string()], // Empty = match all
             ports: vec!["49152-65535".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Allow);
@@ -684,9 +797,10 @@ mod port_matcher_tests {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain("example.com".to_string()),
+                    &Address::Domain("example.com".into()),
                     port,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(
@@ -705,8 +819,13 @@ mod port_matcher_tests {
             description: "Allow common web ports".to_string(),
             destinations: vec!["*".to_string()], // Empty = match all
             ports: vec!["80,443,8080,8443".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -727,9 +846,10 @@ mod port_matcher_tests {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain("example.com".to_string()),
+                    &Address::Domain("example.com".into()),
                     port,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(
@@ -748,8 +868,13 @@ mod port_matcher_tests {
             description: "Allow all ports".to_string(),
             destinations: vec!["example.com".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -760,9 +885,10 @@ mod port_matcher_tests {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain("example.com".to_string()),
+                    &Address::Domain("example.com".into()),
                     port,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(decision, AclDecision::Allow, "Port {} should match", port);
@@ -777,16 +903,26 @@ mod port_matcher_tests {
                 description: "Block SSH".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["22".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 200,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Allow,
                 description: "Allow web ports".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["80,443".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
         ];
 
@@ -796,9 +932,10 @@ mod port_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 22,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -806,9 +943,10 @@ mod port_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -829,8 +967,13 @@ mod protocol_matcher_tests {
             description: "TCP only".to_string(),
             destinations: vec!["*".to_string()], // Empty = match all
             ports: vec!["*".to_string()],        // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Tcp],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -839,9 +982,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -849,9 +993,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 53,
                 &Protocol::Udp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -864,8 +1009,13 @@ mod protocol_matcher_tests {
             description: "UDP only".to_string(),
             destinations: vec!["*".to_string()], // Empty = match all
             ports: vec!["*".to_string()],        // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Udp],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -874,9 +1024,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 53,
                 &Protocol::Udp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -884,9 +1035,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -899,8 +1051,13 @@ mod protocol_matcher_tests {
             description: "Both protocols".to_string(),
             destinations: vec!["*".to_string()], // Empty = match all
             ports: vec!["*".to_string()],        // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -909,9 +1066,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -919,9 +1077,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 53,
                 &Protocol::Udp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -935,8 +1094,13 @@ mod protocol_matcher_tests {
             description: "Star protocol".to_string(),
             destinations: vec!["*".to_string()],
             ports: vec!["*".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Both], // "*" is alias for "both"
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -946,9 +1110,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -957,9 +1122,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 53,
                 &Protocol::Udp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -972,8 +1138,13 @@ mod protocol_matcher_tests {
             description: "Empty protocols".to_string(),
             destinations: vec!["*".to_string()],
             ports: vec!["*".to_string()],
+            sources: vec![],
             protocols: vec![], // Empty = match nothing
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Block);
@@ -983,9 +1154,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -994,9 +1166,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 53,
                 &Protocol::Udp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1010,16 +1183,26 @@ mod protocol_matcher_tests {
                 description: "Block UDP DNS".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["53".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Udp],
                 priority: 200,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Allow,
                 description: "Allow all TCP".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["*".to_string()],        // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Tcp],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
         ];
 
@@ -1030,9 +1213,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("8.8.8.8".to_string()),
+                &Address::Domain("8.8.8.8".into()),
                 53,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1041,9 +1225,10 @@ mod protocol_matcher_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("8.8.8.8".to_string()),
+                &Address::Domain("8.8.8.8".into()),
                 53,
                 &Protocol::Udp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1065,16 +1250,26 @@ mod priority_tests {
                 description: "High priority block".to_string(),
                 destinations: vec!["evil.com".to_string()],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 1000,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Allow,
                 description: "Low priority allow".to_string(),
                 destinations: vec!["*.com".to_string()],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
         ];
 
@@ -1085,9 +1280,10 @@ mod priority_tests {
         let (decision, desc) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("evil.com".to_string()),
+                &Address::Domain("evil.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1102,16 +1298,26 @@ mod priority_tests {
                 description: "Allow all".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["*".to_string()],        // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Block,
                 description: "Block specific".to_string(),
                 destinations: vec!["blocked.com".to_string()],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
         ];
 
@@ -1122,9 +1328,10 @@ mod priority_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("blocked.com".to_string()),
+                &Address::Domain("blocked.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1140,16 +1347,26 @@ mod priority_tests {
                 description: "High priority allow".to_string(),
                 destinations: vec!["example.com".to_string()],
                 ports: vec!["80".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Tcp],
                 priority: 200,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Block,
                 description: "Lower priority block (but wins due to BLOCK-first)".to_string(),
                 destinations: vec!["example.com".to_string()],
                 ports: vec!["80".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Tcp],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
         ];
 
@@ -1159,9 +1376,10 @@ mod priority_tests {
         let (decision, desc) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         // BLOCK wins because BLOCK rules are always checked first
@@ -1177,24 +1395,39 @@ mod priority_tests {
                 description: "Priority 50".to_string(),
                 destinations: vec!["low.example.com".to_string()],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 50,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Block,
                 description: "Priority 500".to_string(),
                 destinations: vec!["high.example.com".to_string()],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 500,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Allow,
                 description: "Priority 100".to_string(),
                 destinations: vec!["mid.example.com".to_string()],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
         ];
 
@@ -1205,9 +1438,10 @@ mod priority_tests {
         let (decision, desc) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("high.example.com".to_string()),
+                &Address::Domain("high.example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1235,13 +1469,19 @@ mod group_inheritance_tests {
             }],
             groups: vec![GroupAcl {
                 name: "developers".to_string(),
+                parents: vec![],
                 rules: vec![AclRule {
                     action: Action::Allow,
                     description: "Devs can access dev servers".to_string(),
                     destinations: vec!["*.dev.company.com".to_string()],
                     ports: vec!["*".to_string()], // Empty = match all
+                    sources: vec![],
                     protocols: vec![Protocol::Both],
                     priority: 100,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
         };
@@ -1251,9 +1491,10 @@ mod group_inheritance_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("api.dev.company.com".to_string()),
+                &Address::Domain("api.dev.company.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1273,19 +1514,30 @@ mod group_inheritance_tests {
                     description: "Alice blocks social media".to_string(),
                     destinations: vec!["*.facebook.com".to_string(), "*.twitter.com".to_string()],
                     ports: vec!["*".to_string()], // Empty = match all
+                    sources: vec![],
                     protocols: vec![Protocol::Both],
                     priority: 500,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
             groups: vec![GroupAcl {
                 name: "developers".to_string(),
+                parents: vec![],
                 rules: vec![AclRule {
                     action: Action::Allow,
                     description: "Allow all internet".to_string(),
                     destinations: vec!["*".to_string()], // Empty = match all
                     ports: vec!["*".to_string()],        // Empty = match all
+                    sources: vec![],
                     protocols: vec![Protocol::Both],
                     priority: 100,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
         };
@@ -1296,9 +1548,10 @@ mod group_inheritance_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("www.facebook.com".to_string()),
+                &Address::Domain("www.facebook.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1307,9 +1560,10 @@ mod group_inheritance_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("github.com".to_string()),
+                &Address::Domain("github.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1329,24 +1583,36 @@ mod group_inheritance_tests {
             groups: vec![
                 GroupAcl {
                     name: "developers".to_string(),
+                    parents: vec![],
                     rules: vec![AclRule {
                         action: Action::Allow,
                         description: "Dev access".to_string(),
                         destinations: vec!["*.dev.company.com".to_string()],
                         ports: vec!["*".to_string()], // Empty = match all
+                        sources: vec![],
                         protocols: vec![Protocol::Both],
                         priority: 100,
+                        log: RuleLogLevel::Default,
+                        reply_code: None,
+                        resolve: Default::default(),
+                        schedule: None,
                     }],
                 },
                 GroupAcl {
                     name: "admins".to_string(),
+                    parents: vec![],
                     rules: vec![AclRule {
                         action: Action::Allow,
                         description: "Admin access".to_string(),
                         destinations: vec!["*.prod.company.com".to_string()],
                         ports: vec!["*".to_string()], // Empty = match all
+                        sources: vec![],
                         protocols: vec![Protocol::Both],
                         priority: 100,
+                        log: RuleLogLevel::Default,
+                        reply_code: None,
+                        resolve: Default::default(),
+                        schedule: None,
                     }],
                 },
             ],
@@ -1358,9 +1624,10 @@ mod group_inheritance_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("api.dev.company.com".to_string()),
+                &Address::Domain("api.dev.company.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1369,9 +1636,10 @@ mod group_inheritance_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("db.prod.company.com".to_string()),
+                &Address::Domain("db.prod.company.com".into()),
                 5432,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1405,9 +1673,10 @@ mod default_policy_tests {
         let (decision, desc) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("anything.com".to_string()),
+                &Address::Domain("anything.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1434,9 +1703,10 @@ mod default_policy_tests {
         let (decision, desc) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("anything.com".to_string()),
+                &Address::Domain("anything.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1457,8 +1727,13 @@ mod default_policy_tests {
                     description: "Alice can access".to_string(),
                     destinations: vec!["*.com".to_string()],
                     ports: vec!["*".to_string()], // Empty = match all
+                    sources: vec![],
                     protocols: vec![Protocol::Both],
                     priority: 100,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
             groups: vec![],
@@ -1470,9 +1745,10 @@ mod default_policy_tests {
         let (decision, desc) = engine
             .evaluate(
                 "unknown_user",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1493,8 +1769,13 @@ mod default_policy_tests {
                     description: "Only example.com".to_string(),
                     destinations: vec!["example.com".to_string()],
                     ports: vec!["443".to_string()],
+                    sources: vec![],
                     protocols: vec![Protocol::Tcp],
                     priority: 100,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             }],
             groups: vec![],
@@ -1506,9 +1787,10 @@ mod default_policy_tests {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1517,9 +1799,10 @@ mod default_policy_tests {
         let (decision, desc) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1529,9 +1812,10 @@ mod default_policy_tests {
         let (decision, desc) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("other.com".to_string()),
+                &Address::Domain("other.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1662,8 +1946,13 @@ mod complex_scenarios {
                         description: "Devs cannot access production DB".to_string(),
                         destinations: vec!["prod-db.company.com".to_string()],
                         ports: vec!["5432".to_string()],
+                        sources: vec![],
                         protocols: vec![Protocol::Tcp],
                         priority: 1000,
+                        log: RuleLogLevel::Default,
+                        reply_code: None,
+                        resolve: Default::default(),
+                        schedule: None,
                     }],
                 },
                 UserAcl {
@@ -1675,14 +1964,20 @@ mod complex_scenarios {
             groups: vec![
                 GroupAcl {
                     name: "engineering".to_string(),
+                    parents: vec![],
                     rules: vec![
                         AclRule {
                             action: Action::Allow,
                             description: "Access dev environment".to_string(),
                             destinations: vec!["*.dev.company.com".to_string()],
                             ports: vec!["*".to_string()], // Empty = match all
+                            sources: vec![],
                             protocols: vec![Protocol::Both],
                             priority: 100,
+                            log: RuleLogLevel::Default,
+                            reply_code: None,
+                            resolve: Default::default(),
+                            schedule: None,
                         },
                         AclRule {
                             action: Action::Allow,
@@ -1692,13 +1987,19 @@ mod complex_scenarios {
                                 "*.github.com".to_string(),
                             ],
                             ports: vec!["443".to_string()],
+                            sources: vec![],
                             protocols: vec![Protocol::Tcp],
                             priority: 100,
+                            log: RuleLogLevel::Default,
+                            reply_code: None,
+                            resolve: Default::default(),
+                            schedule: None,
                         },
                     ],
                 },
                 GroupAcl {
                     name: "ops".to_string(),
+                    parents: vec![],
                     rules: vec![AclRule {
                         action: Action::Allow,
                         description: "Full production access".to_string(),
@@ -1707,8 +2008,13 @@ mod complex_scenarios {
                             "prod-db.company.com".to_string(), // Exact match for prod DB
                         ],
                         ports: vec!["*".to_string()], // Empty = match all
+                        sources: vec![],
                         protocols: vec![Protocol::Both],
                         priority: 200,
+                        log: RuleLogLevel::Default,
+                        reply_code: None,
+                        resolve: Default::default(),
+                        schedule: None,
                     }],
                 },
             ],
@@ -1720,9 +2026,10 @@ mod complex_scenarios {
         let (decision, _) = engine
             .evaluate(
                 "developer",
-                &Address::Domain("api.dev.company.com".to_string()),
+                &Address::Domain("api.dev.company.com".into()),
                 8080,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1731,9 +2038,10 @@ mod complex_scenarios {
         let (decision, _) = engine
             .evaluate(
                 "developer",
-                &Address::Domain("prod-db.company.com".to_string()),
+                &Address::Domain("prod-db.company.com".into()),
                 5432,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1742,9 +2050,10 @@ mod complex_scenarios {
         let (decision, _) = engine
             .evaluate(
                 "admin",
-                &Address::Domain("prod-db.company.com".to_string()),
+                &Address::Domain("prod-db.company.com".into()),
                 5432,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1753,9 +2062,10 @@ mod complex_scenarios {
         let (decision, _) = engine
             .evaluate(
                 "developer",
-                &Address::Domain("github.com".to_string()),
+                &Address::Domain("github.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1775,8 +2085,13 @@ mod complex_scenarios {
                     "*.tiktok.com".to_string(),
                 ],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 900,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             // Block torrent ports
             AclRule {
@@ -1784,8 +2099,13 @@ mod complex_scenarios {
                 description: "Block torrents".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["6881-6889".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 800,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             // Allow HTTPS to anywhere
             AclRule {
@@ -1793,8 +2113,13 @@ mod complex_scenarios {
                 description: "Allow HTTPS".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["443".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Tcp],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             // Allow HTTP
             AclRule {
@@ -1802,8 +2127,13 @@ mod complex_scenarios {
                 description: "Allow HTTP".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["80".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Tcp],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
         ];
 
@@ -1814,9 +2144,10 @@ mod complex_scenarios {
         let (decision, _) = engine
             .evaluate(
                 "user",
-                &Address::Domain("www.facebook.com".to_string()),
+                &Address::Domain("www.facebook.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1825,9 +2156,10 @@ mod complex_scenarios {
         let (decision, _) = engine
             .evaluate(
                 "user",
-                &Address::Domain("tracker.example.com".to_string()),
+                &Address::Domain("tracker.example.com".into()),
                 6881,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -1836,9 +2168,10 @@ mod complex_scenarios {
         let (decision, _) = engine
             .evaluate(
                 "user",
-                &Address::Domain("google.com".to_string()),
+                &Address::Domain("google.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -1852,24 +2185,39 @@ mod complex_scenarios {
                 description: "Block China IP ranges".to_string(),
                 destinations: vec!["1.0.1.0/24".to_string(), "1.0.2.0/23".to_string()],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 500,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Block,
                 description: "Block Russia IP ranges".to_string(),
                 destinations: vec!["5.8.0.0/16".to_string()],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 500,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
             AclRule {
                 action: Action::Allow,
                 description: "Allow all other IPs".to_string(),
                 destinations: vec!["*".to_string()], // Empty = match all
                 ports: vec!["*".to_string()],        // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             },
         ];
 
@@ -1878,19 +2226,37 @@ mod complex_scenarios {
 
         // Should block China IPs
         let (decision, _) = engine
-            .evaluate("user", &Address::IPv4([1, 0, 1, 100]), 80, &Protocol::Tcp)
+            .evaluate(
+                "user",
+                &Address::IPv4([1, 0, 1, 100]),
+                80,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Block);
 
         // Should block Russia IPs
         let (decision, _) = engine
-            .evaluate("user", &Address::IPv4([5, 8, 0, 1]), 80, &Protocol::Tcp)
+            .evaluate(
+                "user",
+                &Address::IPv4([5, 8, 0, 1]),
+                80,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Block);
 
         // Should allow other IPs
         let (decision, _) = engine
-            .evaluate("user", &Address::IPv4([8, 8, 8, 8]), 80, &Protocol::Tcp)
+            .evaluate(
+                "user",
+                &Address::IPv4([8, 8, 8, 8]),
+                80,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Allow);
     }
@@ -1910,8 +2276,13 @@ mod edge_cases {
             description: "Star matches all".to_string(),
             destinations: vec!["*".to_string()], // "*" = match all
             ports: vec!["443".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -1921,15 +2292,22 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("anything.com".to_string()),
+                &Address::Domain("anything.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
 
         let (decision, _) = engine
-            .evaluate("alice", &Address::IPv4([1, 2, 3, 4]), 443, &Protocol::Tcp)
+            .evaluate(
+                "alice",
+                &Address::IPv4([1, 2, 3, 4]),
+                443,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Allow);
     }
@@ -1941,8 +2319,13 @@ mod edge_cases {
             description: "Empty destinations".to_string(),
             destinations: vec![], // Empty = match nothing
             ports: vec!["443".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Block);
@@ -1952,15 +2335,22 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("anything.com".to_string()),
+                &Address::Domain("anything.com".into()),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block); // Falls back to default policy
 
         let (decision, _) = engine
-            .evaluate("alice", &Address::IPv4([1, 2, 3, 4]), 443, &Protocol::Tcp)
+            .evaluate(
+                "alice",
+                &Address::IPv4([1, 2, 3, 4]),
+                443,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Block); // Falls back to default policy
     }
@@ -1972,8 +2362,13 @@ mod edge_cases {
             description: "Star ports".to_string(),
             destinations: vec!["blocked.com".to_string()],
             ports: vec!["*".to_string()], // "*" = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -1984,9 +2379,10 @@ mod edge_cases {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain("blocked.com".to_string()),
+                    &Address::Domain("blocked.com".into()),
                     port,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(decision, AclDecision::Block, "Port {} should match", port);
@@ -2000,8 +2396,13 @@ mod edge_cases {
             description: "Empty ports".to_string(),
             destinations: vec!["blocked.com".to_string()],
             ports: vec![], // Empty = match nothing
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Allow);
@@ -2012,9 +2413,10 @@ mod edge_cases {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain("blocked.com".to_string()),
+                    &Address::Domain("blocked.com".into()),
                     port,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(
@@ -2033,8 +2435,13 @@ mod edge_cases {
             description: "Domain".to_string(),
             destinations: vec!["ExAmPlE.cOm".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -2046,9 +2453,10 @@ mod edge_cases {
             let (decision, _) = engine
                 .evaluate(
                     "alice",
-                    &Address::Domain(domain.to_string()),
+                    &Address::Domain(domain.into()),
                     80,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
             assert_eq!(
@@ -2067,8 +2475,13 @@ mod edge_cases {
             description: "Wildcard".to_string(),
             destinations: vec!["*.example.com".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -2078,9 +2491,10 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -2089,9 +2503,10 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("www.example.com".to_string()),
+                &Address::Domain("www.example.com".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -2104,8 +2519,13 @@ mod edge_cases {
             description: "Any port".to_string(),
             destinations: vec!["example.com".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -2115,9 +2535,10 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 0,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -2130,8 +2551,13 @@ mod edge_cases {
             description: "Block max port".to_string(),
             destinations: vec!["*".to_string()], // Empty = match all
             ports: vec!["65535".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Allow);
@@ -2140,9 +2566,10 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 65535,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -2150,9 +2577,10 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 65534,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow); // Default allow
@@ -2165,8 +2593,13 @@ mod edge_cases {
             description: "Block private IPs".to_string(),
             destinations: vec!["192.168.0.0/16".to_string()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
@@ -2176,9 +2609,10 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("192.168.1.1".to_string()),
+                &Address::Domain("192.168.1.1".into()),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Block);
@@ -2192,15 +2626,26 @@ mod edge_cases {
             description: "Long domain".to_string(),
             destinations: vec![long_domain.clone()],
             ports: vec!["*".to_string()], // Empty = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config("alice", vec![rule]);
         let engine = AclEngine::new(config).unwrap();
 
         let (decision, _) = engine
-            .evaluate("alice", &Address::Domain(long_domain), 80, &Protocol::Tcp)
+            .evaluate(
+                "alice",
+                &Address::Domain(long_domain.into()),
+                80,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Allow);
     }
@@ -2219,8 +2664,13 @@ mod edge_cases {
                 description: format!("Rule {}", i),
                 destinations: vec![format!("domain{}.com", i)],
                 ports: vec!["*".to_string()], // Empty = match all
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: i as u32,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             });
         }
 
@@ -2235,9 +2685,10 @@ mod edge_cases {
             engine
                 .evaluate(
                     "alice",
-                    &Address::Domain(format!("domain{}.com", i)),
+                    &Address::Domain(format!("domain{}.com", i).into()),
                     80,
                     &Protocol::Tcp,
+                    None,
                 )
                 .await;
         }
@@ -2258,8 +2709,13 @@ mod edge_cases {
             description: "Allow all destinations and ports".to_string(),
             destinations: vec!["*".to_string()], // "*" = match all
             ports: vec!["*".to_string()],        // "*" = match all
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Block);
@@ -2273,6 +2729,7 @@ mod edge_cases {
                 &Address::IPv4([192, 168, 55, 220]),
                 22,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(
@@ -2284,7 +2741,13 @@ mod edge_cases {
 
         // Test more IPv4 addresses
         let (decision, _) = engine
-            .evaluate("alice", &Address::IPv4([10, 0, 0, 1]), 80, &Protocol::Tcp)
+            .evaluate(
+                "alice",
+                &Address::IPv4([10, 0, 0, 1]),
+                80,
+                &Protocol::Tcp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Allow);
 
@@ -2294,6 +2757,7 @@ mod edge_cases {
                 &Address::IPv4([172, 16, 0, 1]),
                 443,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -2308,6 +2772,7 @@ mod edge_cases {
                 ]),
                 22,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -2316,16 +2781,23 @@ mod edge_cases {
         let (decision, _) = engine
             .evaluate(
                 "alice",
-                &Address::Domain("example.com".to_string()),
+                &Address::Domain("example.com".into()),
                 8080,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
 
         // Test UDP protocol
         let (decision, _) = engine
-            .evaluate("alice", &Address::IPv4([8, 8, 8, 8]), 53, &Protocol::Udp)
+            .evaluate(
+                "alice",
+                &Address::IPv4([8, 8, 8, 8]),
+                53,
+                &Protocol::Udp,
+                None,
+            )
             .await;
         assert_eq!(decision, AclDecision::Allow);
     }
@@ -2373,6 +2845,7 @@ groups = []
                 &Address::IPv4([192, 168, 55, 220]),
                 22,
                 &Protocol::Tcp,
+                None,
             )
             .await;
 
@@ -2427,6 +2900,7 @@ groups = []
                 &Address::IPv4([192, 168, 55, 220]),
                 22,
                 &Protocol::Tcp,
+                None,
             )
             .await;
 
@@ -2481,6 +2955,7 @@ groups = []
                 &Address::IPv4([192, 168, 1, 1]),
                 80,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -2492,6 +2967,7 @@ groups = []
                 &Address::IPv4([192, 168, 1, 1]),
                 53,
                 &Protocol::Udp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -2505,8 +2981,13 @@ groups = []
             description: "Allow all via CIDR".to_string(),
             destinations: vec!["0.0.0.0/0".to_string(), "::/0".to_string()],
             ports: vec!["*".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Both],
             priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         };
 
         let config = create_test_config_with_policy("alice", vec![rule], Action::Block);
@@ -2519,6 +3000,7 @@ groups = []
                 &Address::IPv4([192, 168, 55, 220]),
                 22,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
@@ -2532,12 +3014,438 @@ groups = []
                 ]),
                 22,
                 &Protocol::Tcp,
+                None,
             )
             .await;
         assert_eq!(decision, AclDecision::Allow);
     }
 }
 
+// ============================================================================
+// Block Reply Code Tests
+// ============================================================================
+
+mod reply_code_tests {
+    use super::*;
+
+    fn rule(action: Action, destination: &str, reply_code: Option<BlockReplyCode>) -> AclRule {
+        AclRule {
+            description: format!("{:?} {}", action, destination),
+            action,
+            destinations: vec![destination.to_string()],
+            ports: vec!["*".to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Both],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code,
+            resolve: Default::default(),
+            schedule: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn block_rules_carry_their_reply_code() {
+        let config = create_test_config(
+            "alice",
+            vec![
+                rule(
+                    Action::Block,
+                    "gone.example.com",
+                    Some(BlockReplyCode::HostUnreachable),
+                ),
+                rule(Action::Block, "denied.example.com", None),
+                rule(Action::Allow, "ok.example.com", None),
+            ],
+        );
+        let engine = AclEngine::new(config).unwrap();
+        let verdict = |host: &'static str| {
+            let engine = &engine;
+            async move {
+                engine
+                    .verdict(
+                        "alice",
+                        &Address::Domain(host.into()),
+                        443,
+                        &Protocol::Tcp,
+                        None,
+                    )
+                    .await
+            }
+        };
+
+        let gone = verdict("gone.example.com").await;
+        assert_eq!(gone.decision, AclDecision::Block);
+        assert_eq!(gone.reply_code, Some(BlockReplyCode::HostUnreachable));
+        assert_eq!(gone.block_reply(), ReplyCode::HostUnreachable);
+
+        let denied = verdict("denied.example.com").await;
+        assert_eq!(
+            denied.reply_code,
+            Some(BlockReplyCode::ConnectionNotAllowed)
+        );
+        assert_eq!(denied.block_reply(), ReplyCode::ConnectionNotAllowed);
+
+        // Default policy blocks always answer connection_not_allowed
+        let fallback = verdict("other.example.com").await;
+        assert_eq!(fallback.decision, AclDecision::Block);
+        assert_eq!(
+            fallback.reply_code,
+            Some(BlockReplyCode::ConnectionNotAllowed)
+        );
+
+        let allowed = verdict("ok.example.com").await;
+        assert_eq!(allowed.decision, AclDecision::Allow);
+        assert_eq!(allowed.reply_code, None);
+
+        let explanation = engine
+            .explain(
+                "alice",
+                &Address::Domain("gone.example.com".into()),
+                443,
+                &Protocol::Tcp,
+                None,
+            )
+            .await;
+        assert_eq!(
+            explanation.reply_code,
+            Some(BlockReplyCode::HostUnreachable)
+        );
+    }
+
+    #[test]
+    fn reply_code_on_allow_rule_is_rejected() {
+        let config = create_test_config(
+            "alice",
+            vec![rule(
+                Action::Allow,
+                "ok.example.com",
+                Some(BlockReplyCode::HostUnreachable),
+            )],
+        );
+        let err = AclEngine::new(config)
+            .err()
+            .expect("allow rule with reply_code");
+        assert!(err.contains("reply_code"), "{}", err);
+    }
+
+    #[test]
+    fn reply_code_parses_from_toml() {
+        let config: AclConfig = toml::from_str(
+            r#"
+            [global]
+            default_policy = "allow"
+
+            [[users]]
+            username = "alice"
+
+              [[users.rules]]
+              action = "block"
+              description = "Decommissioned"
+              destinations = ["legacy.example.com"]
+              ports = ["*"]
+              reply_code = "network_unreachable"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.users[0].rules[0].reply_code,
+            Some(BlockReplyCode::NetworkUnreachable)
+        );
+
+        let invalid = toml::from_str::<AclConfig>(
+            r#"
+            [[users]]
+            username = "alice"
+
+              [[users.rules]]
+              action = "block"
+              destinations = ["legacy.example.com"]
+              reply_code = "succeeded"
+            "#,
+        );
+        assert!(invalid.is_err());
+    }
+}
+
+// ============================================================================
+// Resolve Mode Tests
+// ============================================================================
+
+mod resolve_mode_tests {
+    use super::*;
+
+    fn rule(action: Action, destination: &str, resolve: ResolveMode) -> AclRule {
+        AclRule {
+            description: format!("{:?} {}", action, destination),
+            action,
+            destinations: vec![destination.to_string()],
+            ports: vec!["*".to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Both],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve,
+            schedule: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn allow_rules_carry_their_resolve_mode() {
+        let mut config = create_test_config(
+            "alice",
+            vec![
+                rule(Action::Allow, "*.onion", ResolveMode::Remote),
+                rule(Action::Allow, "ok.example.com", ResolveMode::Local),
+            ],
+        );
+        config.global.default_policy = Action::Allow;
+        let engine = AclEngine::new(config).unwrap();
+        let verdict = |host: &'static str| {
+            let engine = &engine;
+            async move {
+                engine
+                    .verdict(
+                        "alice",
+                        &Address::Domain(host.into()),
+                        443,
+                        &Protocol::Tcp,
+                        None,
+                    )
+                    .await
+            }
+        };
+
+        assert_eq!(verdict("hidden.onion").await.resolve, ResolveMode::Remote);
+        assert_eq!(verdict("ok.example.com").await.resolve, ResolveMode::Local);
+        // The default policy resolves locally
+        assert_eq!(
+            verdict("other.example.com").await.resolve,
+            ResolveMode::Local
+        );
+
+        let explanation = engine
+            .explain(
+                "alice",
+                &Address::Domain("hidden.onion".into()),
+                443,
+                &Protocol::Tcp,
+                None,
+            )
+            .await;
+        assert_eq!(explanation.resolve, ResolveMode::Remote);
+    }
+
+    #[test]
+    fn resolve_on_block_rule_is_rejected() {
+        let config = create_test_config(
+            "alice",
+            vec![rule(Action::Block, "*.onion", ResolveMode::Remote)],
+        );
+        let err = AclEngine::new(config)
+            .err()
+            .expect("block rule with resolve");
+        assert!(err.contains("resolve"), "{}", err);
+    }
+
+    #[test]
+    fn resolve_parses_from_toml() {
+        let config: AclConfig = toml::from_str(
+            r#"
+            [global]
+            default_policy = "block"
+
+            [[users]]
+            username = "alice"
+
+              [[users.rules]]
+              action = "allow"
+              description = "Hidden services"
+              destinations = ["*.onion"]
+              ports = ["*"]
+              resolve = "remote"
+
+              [[users.rules]]
+              action = "allow"
+              destinations = ["example.com"]
+              ports = ["*"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.users[0].rules[0].resolve, ResolveMode::Remote);
+        assert_eq!(config.users[0].rules[1].resolve, ResolveMode::Local);
+
+        let invalid = toml::from_str::<AclConfig>(
+            r#"
+            [[users]]
+            username = "alice"
+
+              [[users.rules]]
+              action = "allow"
+              destinations = ["example.com"]
+              resolve = "upstream"
+            "#,
+        );
+        assert!(invalid.is_err());
+    }
+}
+
+// ============================================================================
+// Decision Cache Tests
+// ============================================================================
+
+mod decision_cache_tests {
+    use super::*;
+    use rustsocks::config::AclCacheSettings;
+
+    fn allow_rule(destination: &str) -> AclRule {
+        AclRule {
+            description: format!("Allow {}", destination),
+            action: Action::Allow,
+            destinations: vec![destination.to_string()],
+            ports: vec!["443".to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Tcp],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
+        }
+    }
+
+    fn cached_engine(config: AclConfig, enabled: bool) -> (AclEngine, Arc<AclStats>) {
+        let stats = Arc::new(AclStats::new());
+        let settings = AclCacheSettings {
+            enabled,
+            ..AclCacheSettings::default()
+        };
+        let engine = AclEngine::new(config)
+            .unwrap()
+            .with_decision_cache(&settings, stats.clone());
+        (engine, stats)
+    }
+
+    async fn connect(engine: &AclEngine, dest: &Address) -> AclDecision {
+        engine
+            .verdict_with_groups("alice", &[], dest, 443, &Protocol::Tcp, None)
+            .await
+            .decision
+    }
+
+    #[tokio::test]
+    async fn repeated_connections_are_served_from_the_cache() {
+        let config = create_test_config("alice", vec![allow_rule("example.com")]);
+        let (engine, stats) = cached_engine(config, true);
+        let dest = Address::Domain("example.com".into());
+
+        assert_eq!(connect(&engine, &dest).await, AclDecision::Allow);
+        assert_eq!(connect(&engine, &dest).await, AclDecision::Allow);
+        assert_eq!(connect(&engine, &dest).await, AclDecision::Allow);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.cache_misses, 1);
+        assert_eq!(snapshot.cache_hits, 2);
+
+        // Cached decisions still count as hits of the rule that made them
+        let usage = engine.rule_usage().await;
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].hits, 3);
+    }
+
+    #[tokio::test]
+    async fn domains_are_cached_by_name_not_address() {
+        let config = create_test_config("alice", vec![allow_rule("example.com")]);
+        let (engine, stats) = cached_engine(config, true);
+
+        let by_name = Address::Domain("example.com".into());
+        let by_address = Address::IPv4([93, 184, 216, 34]);
+        assert_eq!(connect(&engine, &by_name).await, AclDecision::Allow);
+        assert_eq!(connect(&engine, &by_address).await, AclDecision::Block);
+        assert_eq!(connect(&engine, &by_name).await, AclDecision::Allow);
+        assert_eq!(connect(&engine, &by_address).await, AclDecision::Block);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.cache_misses, 2);
+        assert_eq!(snapshot.cache_hits, 2);
+    }
+
+    #[tokio::test]
+    async fn reload_drops_cached_decisions() {
+        let config = create_test_config("alice", vec![allow_rule("example.com")]);
+        let (engine, stats) = cached_engine(config, true);
+        let dest = Address::Domain("example.com".into());
+
+        assert_eq!(connect(&engine, &dest).await, AclDecision::Allow);
+        assert_eq!(connect(&engine, &dest).await, AclDecision::Allow);
+
+        engine
+            .reload(create_test_config("alice", vec![]))
+            .await
+            .unwrap();
+        assert_eq!(connect(&engine, &dest).await, AclDecision::Block);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.cache_misses, 2);
+        assert_eq!(snapshot.cache_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn groups_and_previews_bypass_stale_entries() {
+        let config = AclConfig {
+            global: GlobalAclConfig {
+                default_policy: Action::Block,
+            },
+            users: vec![],
+            groups: vec![GroupAcl {
+                name: "developers".to_string(),
+                parents: vec![],
+                rules: vec![allow_rule("example.com")],
+            }],
+        };
+        let (engine, stats) = cached_engine(config, true);
+        let dest = Address::Domain("example.com".into());
+        let verdict = |groups: &'static [&'static str]| {
+            let engine = &engine;
+            let dest = &dest;
+            async move {
+                let groups: Vec<String> = groups.iter().map(|g| g.to_string()).collect();
+                engine
+                    .verdict_with_groups("alice", &groups, dest, 443, &Protocol::Tcp, None)
+                    .await
+                    .decision
+            }
+        };
+
+        assert_eq!(verdict(&["Developers", "hr"]).await, AclDecision::Allow);
+        // Same ACL groups in another case and order share the entry
+        assert_eq!(verdict(&["staff", "developers"]).await, AclDecision::Allow);
+        // Losing the group is a different key
+        assert_eq!(verdict(&["hr"]).await, AclDecision::Block);
+
+        let (decision, _) = engine
+            .dry_run_with_groups("alice", &[], &dest, 443, &Protocol::Tcp, None)
+            .await;
+        assert_eq!(decision, AclDecision::Block);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.cache_misses, 2);
+        assert_eq!(snapshot.cache_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn disabled_cache_counts_nothing() {
+        let config = create_test_config("alice", vec![allow_rule("example.com")]);
+        let (engine, stats) = cached_engine(config, false);
+        let dest = Address::Domain("example.com".into());
+
+        assert_eq!(connect(&engine, &dest).await, AclDecision::Allow);
+        assert_eq!(connect(&engine, &dest).await, AclDecision::Allow);
+        assert_eq!(stats.snapshot(), Default::default());
+    }
+}
+
 // ============================================================================
 // Helper Functions
 // ============================================================================
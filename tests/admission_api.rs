@@ -0,0 +1,377 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, GroupAcl, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::api::handlers::test_admission;
+use rustsocks::config::Config;
+use rustsocks::qos::{ConnectionLimits, QosConfig, QosEngine};
+use rustsocks::server::pool::{ConnectionPool, PoolConfig};
+use rustsocks::session::SessionManager;
+use serde_json::Value;
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+fn create_api_state(
+    session_manager: Arc<SessionManager>,
+    acl_engine: Option<Arc<AclEngine>>,
+    qos_engine: QosEngine,
+    config: Config,
+) -> ApiState {
+    ApiState {
+        session_manager,
+        acl_engine,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(qos_engine),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(config),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    }
+}
+
+fn acl_config() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Allow,
+        },
+        users: vec![UserAcl {
+            username: "alice".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Block,
+                description: "Block blocked.example.com".to_string(),
+                destinations: vec!["blocked.example.com".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 1000,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+        groups: vec![GroupAcl {
+            name: "contractors".to_string(),
+            parents: vec![],
+            rules: vec![AclRule {
+                action: Action::Block,
+                description: "Contractors cannot reach internal".to_string(),
+                destinations: vec!["*.internal.example.com".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 500,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+    }
+}
+
+async fn post_admission(state: ApiState, body: Value) -> (StatusCode, Value) {
+    let app = Router::new()
+        .route("/api/admission/test", post(test_admission))
+        .with_state(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/admission/test")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn gate<'a>(verdict: &'a Value, name: &str) -> &'a Value {
+    verdict["gates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|gate| gate["gate"] == name)
+        .unwrap_or_else(|| panic!("gate {} missing", name))
+}
+
+#[tokio::test]
+async fn admits_when_no_gate_is_configured() {
+    let state = create_api_state(
+        Arc::new(SessionManager::new()),
+        None,
+        QosEngine::None,
+        Config::default(),
+    );
+
+    let (status, verdict) = post_admission(
+        state,
+        serde_json::json!({
+            "user": "alice",
+            "destination": "example.com",
+            "port": 443
+        }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(verdict["admitted"], true);
+    assert_eq!(verdict["reply_code"], 0);
+    assert_eq!(verdict["reply"], "succeeded");
+    assert_eq!(verdict["protocol"], "tcp");
+    assert_eq!(verdict["command"], "connect");
+    assert!(verdict.get("failed_gate").is_none());
+    assert_eq!(
+        gate(&verdict, "connection_limits")["status"],
+        "not_configured"
+    );
+    assert_eq!(gate(&verdict, "special_names")["status"], "pass");
+    assert_eq!(gate(&verdict, "acl")["status"], "not_configured");
+    assert_eq!(
+        verdict["not_evaluated"],
+        serde_json::json!([
+            "maintenance_mode",
+            "allowed_commands",
+            "rate_limiter",
+            "quota",
+            "fan_out"
+        ])
+    );
+}
+
+#[tokio::test]
+async fn connection_limit_gate_reports_without_reserving() {
+    let limits = ConnectionLimits {
+        max_connections_per_user: 1,
+        max_connections_global: 100,
+    };
+    let qos_engine = QosEngine::from_config(QosConfig {
+        enabled: true,
+        connection_limits: limits.clone(),
+        ..QosConfig::default()
+    })
+    .await
+    .expect("qos engine");
+
+    let mut config = Config::default();
+    config.qos.connection_limits = limits.clone();
+    let state = create_api_state(
+        Arc::new(SessionManager::new()),
+        None,
+        qos_engine.clone(),
+        config,
+    );
+
+    let body = serde_json::json!({
+        "user": "alice",
+        "destination": "example.com",
+        "port": 443
+    });
+
+    // Free slot: passes and leaves the counter untouched
+    let (_, verdict) = post_admission(state.clone(), body.clone()).await;
+    assert_eq!(verdict["admitted"], true);
+    assert_eq!(gate(&verdict, "connection_limits")["status"], "pass");
+    assert_eq!(qos_engine.get_user_connections("alice"), 0);
+
+    // Occupy the only slot, the dry-run must now fail with the handler's reply
+    qos_engine
+        .check_and_inc_connection("alice", &limits)
+        .expect("reserve slot");
+    let (_, verdict) = post_admission(state, body).await;
+    assert_eq!(verdict["admitted"], false);
+    assert_eq!(verdict["failed_gate"], "connection_limits");
+    assert_eq!(verdict["reply_code"], 2);
+    assert_eq!(verdict["reply"], "connection_not_allowed");
+    let limits_gate = gate(&verdict, "connection_limits");
+    assert_eq!(limits_gate["status"], "fail");
+    assert_eq!(limits_gate["details"]["user_connections"], 1);
+    assert_eq!(qos_engine.get_user_connections("alice"), 1);
+
+    qos_engine.dec_user_connection("alice");
+}
+
+#[tokio::test]
+async fn special_names_gate_uses_category_reply() {
+    let state = create_api_state(
+        Arc::new(SessionManager::new()),
+        None,
+        QosEngine::None,
+        Config::default(),
+    );
+
+    let (_, verdict) = post_admission(
+        state.clone(),
+        serde_json::json!({
+            "user": "alice",
+            "destination": "printer.local",
+            "port": 631
+        }),
+    )
+    .await;
+
+    assert_eq!(verdict["admitted"], false);
+    assert_eq!(verdict["failed_gate"], "special_names");
+    assert_eq!(verdict["reply_code"], 4);
+    assert_eq!(
+        gate(&verdict, "special_names")["reason"],
+        "special-name:local"
+    );
+    assert_eq!(
+        gate(&verdict, "special_names")["details"]["category"],
+        "local"
+    );
+
+    // The association itself is admitted; the relay drops such datagrams later
+    let (_, verdict) = post_admission(
+        state,
+        serde_json::json!({
+            "user": "alice",
+            "destination": "printer.local",
+            "port": 631,
+            "command": "udp_associate"
+        }),
+    )
+    .await;
+
+    assert_eq!(verdict["admitted"], true);
+    assert_eq!(verdict["protocol"], "udp");
+    assert_eq!(gate(&verdict, "special_names")["status"], "not_configured");
+    assert_eq!(
+        gate(&verdict, "special_names")["reason"],
+        "Checked per datagram by the UDP relay"
+    );
+}
+
+#[tokio::test]
+async fn acl_gate_evaluates_user_and_group_rules() {
+    let session_manager = Arc::new(SessionManager::new());
+    let acl_engine = Arc::new(AclEngine::new(acl_config()).expect("acl engine"));
+    let state = create_api_state(
+        session_manager.clone(),
+        Some(acl_engine),
+        QosEngine::None,
+        Config::default(),
+    );
+
+    let (_, verdict) = post_admission(
+        state.clone(),
+        serde_json::json!({
+            "user": "alice",
+            "destination": "blocked.example.com",
+            "port": 443
+        }),
+    )
+    .await;
+    assert_eq!(verdict["admitted"], false);
+    assert_eq!(verdict["failed_gate"], "acl");
+    assert_eq!(verdict["reply_code"], 2);
+    assert_eq!(gate(&verdict, "acl")["status"], "fail");
+    assert!(gate(&verdict, "acl")["reason"]
+        .as_str()
+        .unwrap()
+        .contains("blocked.example.com"));
+
+    let (_, verdict) = post_admission(
+        state.clone(),
+        serde_json::json!({
+            "user": "bob",
+            "groups": ["contractors"],
+            "destination": "wiki.internal.example.com",
+            "port": 443
+        }),
+    )
+    .await;
+    assert_eq!(verdict["failed_gate"], "acl");
+    assert_eq!(verdict["groups"], serde_json::json!(["contractors"]));
+
+    // Groups are caller-supplied: without them the group rule does not apply
+    let (_, verdict) = post_admission(
+        state,
+        serde_json::json!({
+            "user": "bob",
+            "destination": "wiki.internal.example.com",
+            "port": 443
+        }),
+    )
+    .await;
+    assert_eq!(verdict["admitted"], true);
+    assert_eq!(gate(&verdict, "acl")["status"], "pass");
+
+    // Dry-runs never show up as rejected sessions
+    assert!(session_manager.rejected_snapshot().await.is_empty());
+}
+
+#[tokio::test]
+async fn reports_first_failing_gate_but_evaluates_all() {
+    let acl_engine = Arc::new(AclEngine::new(acl_config()).expect("acl engine"));
+    let state = create_api_state(
+        Arc::new(SessionManager::new()),
+        Some(acl_engine),
+        QosEngine::None,
+        Config::default(),
+    );
+
+    let (_, verdict) = post_admission(
+        state,
+        serde_json::json!({
+            "user": "bob",
+            "groups": ["contractors"],
+            "destination": "x.internal.example.com.onion",
+            "port": 80
+        }),
+    )
+    .await;
+
+    assert_eq!(verdict["failed_gate"], "special_names");
+    assert_eq!(verdict["reply_code"], 3);
+    assert_eq!(gate(&verdict, "acl")["status"], "pass");
+}
+
+#[tokio::test]
+async fn rejects_invalid_requests() {
+    let state = create_api_state(
+        Arc::new(SessionManager::new()),
+        None,
+        QosEngine::None,
+        Config::default(),
+    );
+
+    for body in [
+        serde_json::json!({"user": "alice", "destination": "a.com", "port": 1, "command": "ping"}),
+        serde_json::json!({"user": "alice", "destination": "a.com", "port": 1, "protocol": "sctp"}),
+    ] {
+        let (status, verdict) = post_admission(state.clone(), body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(verdict["admitted"], false);
+        assert_eq!(verdict["failed_gate"], "request");
+    }
+}
@@ -0,0 +1,245 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use futures::StreamExt;
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::api::handlers::{
+    get_admission_rejections, get_session_stats, get_telemetry_events, stream_admission_rejections,
+};
+use rustsocks::config::Config;
+use rustsocks::qos::{ConnectionLimits, HtbConfig, PerIpConfig, QosConfig, QosEngine};
+use rustsocks::server::{
+    accept_loop, AcceptOptions, ClientHandlerContext, ConnectionPool, PoolConfig,
+};
+use rustsocks::session::SessionManager;
+use rustsocks::telemetry::TelemetryHistory;
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+use tower::util::ServiceExt;
+
+const LIMITS: ConnectionLimits = ConnectionLimits {
+    max_connections_per_user: 1,
+    max_connections_global: 100,
+};
+
+async fn qos_engine() -> QosEngine {
+    QosEngine::from_config(QosConfig {
+        enabled: true,
+        algorithm: "htb".to_string(),
+        htb: HtbConfig::default(),
+        connection_limits: LIMITS,
+        per_ip: PerIpConfig::default(),
+    })
+    .await
+    .expect("create QoS engine")
+}
+
+async fn spawn_proxy(session_manager: Arc<SessionManager>, qos_engine: QosEngine) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let addr = listener.local_addr().expect("proxy addr");
+    let ctx = Arc::new(ClientHandlerContext {
+        qos_engine,
+        connection_limits: LIMITS.into(),
+        enable_socks4: true,
+        ..common::handler_context(session_manager)
+    });
+    tokio::spawn(accept_loop(listener, ctx, AcceptOptions::default()));
+    addr
+}
+
+/// Upstream that accepts and holds connections open.
+async fn spawn_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+    addr
+}
+
+/// SOCKS5 no-auth CONNECT; returns the stream and the reply code.
+async fn socks5_connect(proxy: SocketAddr, upstream: SocketAddr) -> (TcpStream, u8) {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    (stream, reply[1])
+}
+
+fn api_state(session_manager: Arc<SessionManager>, telemetry: Arc<TelemetryHistory>) -> ApiState {
+    ApiState {
+        session_manager,
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: Some(telemetry),
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    }
+}
+
+async fn get_json(app: &Router, uri: &str) -> Value {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn per_user_limit_rejection_is_streamed_listed_and_counted() {
+    let session_manager = Arc::new(SessionManager::new());
+    let telemetry = Arc::new(TelemetryHistory::new(100, 1));
+    session_manager.admission().set_telemetry(telemetry.clone());
+
+    let app = Router::new()
+        .route("/api/admission/rejections", get(get_admission_rejections))
+        .route(
+            "/api/admission/rejections/stream",
+            get(stream_admission_rejections),
+        )
+        .route("/api/sessions/stats", get(get_session_stats))
+        .route("/api/telemetry/events", get(get_telemetry_events))
+        .with_state(api_state(session_manager.clone(), telemetry));
+
+    // Subscribe before the rejection happens
+    let feed = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/admission/rejections/stream?user=anonymous")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(feed.status(), StatusCode::OK);
+    assert_eq!(
+        feed.headers()["content-type"].to_str().unwrap(),
+        "text/event-stream"
+    );
+    let mut feed = feed.into_body().into_data_stream();
+
+    let proxy = spawn_proxy(session_manager.clone(), qos_engine().await).await;
+    let upstream = spawn_upstream().await;
+
+    let (_first, code) = socks5_connect(proxy, upstream).await;
+    assert_eq!(code, 0x00, "first connection is within the limit");
+    let (_second, code) = socks5_connect(proxy, upstream).await;
+    assert_eq!(code, 0x02, "second connection exceeds the per-user limit");
+
+    // Live feed
+    let chunk = timeout(Duration::from_secs(5), feed.next())
+        .await
+        .expect("event in time")
+        .expect("feed open")
+        .unwrap();
+    let text = String::from_utf8(chunk.to_vec()).unwrap();
+    assert!(text.starts_with("event: admission_rejection\n"), "{}", text);
+    let data = text
+        .lines()
+        .find_map(|line| line.strip_prefix("data: "))
+        .expect("data line");
+    let event: Value = serde_json::from_str(data).unwrap();
+    assert_eq!(event["stage"], "admission");
+    assert_eq!(event["limit_type"], "user_connections");
+    assert_eq!(event["user"], "anonymous");
+    assert_eq!(event["source_ip"], "127.0.0.1");
+    assert_eq!(event["current"], 1);
+    assert_eq!(event["limit"], 1);
+    assert!(event["destination"].is_null());
+
+    // Recent-rejections endpoint, with per-user filtering
+    let listed = get_json(&app, "/api/admission/rejections?user=anonymous").await;
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0], event);
+    let other = get_json(&app, "/api/admission/rejections?user=bob").await;
+    assert!(other.as_array().unwrap().is_empty());
+
+    // Aggregates
+    let stats = get_json(&app, "/api/sessions/stats").await;
+    assert_eq!(stats["admission_rejections"]["total"], 1);
+    assert_eq!(stats["admission_rejections"]["user_connections"], 1);
+    assert_eq!(stats["admission_rejections"]["global_connections"], 0);
+    let manager_stats = session_manager.get_stats(Duration::from_secs(3600)).await;
+    assert_eq!(manager_stats.admission.user_connections, 1);
+
+    // Telemetry events carry the same record
+    let events = get_json(&app, "/api/telemetry/events?category=admission").await;
+    assert_eq!(events.as_array().unwrap().len(), 1);
+    assert_eq!(events[0]["details"], event);
+}
+
+#[tokio::test]
+async fn socks4_rejection_records_the_destination() {
+    let session_manager = Arc::new(SessionManager::new());
+    let proxy = spawn_proxy(session_manager.clone(), qos_engine().await).await;
+    let upstream = spawn_upstream().await;
+
+    let (_held, code) = socks5_connect(proxy, upstream).await;
+    assert_eq!(code, 0x00);
+
+    // SOCKS4 CONNECT with an empty user id maps to the same anonymous user
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    let mut request = vec![0x04, 0x01];
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    request.extend_from_slice(&[127, 0, 0, 1, 0x00]);
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 8];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    assert_ne!(reply[1], 0x5A, "SOCKS4 request must be rejected");
+
+    let rejections = session_manager.admission().recent(None, 10);
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(
+        rejections[0].destination.as_deref(),
+        Some(format!("127.0.0.1:{}", upstream.port()).as_str())
+    );
+}
@@ -0,0 +1,261 @@
+//! API keys for the stats API (`[sessions.api_auth]`)
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use rustsocks::api::auth::{api_key_auth, required_access, ApiAccess, ApiAuthState, AuthState};
+use rustsocks::config::{ApiAuthSettings, ApiKey, DashboardAuthSettings, User};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+const READ_TOKEN: &str = "read-token-0123456789";
+const ADMIN_TOKEN: &str = "admin-token-0123456789";
+
+fn key(token: &str, role: &str) -> ApiKey {
+    ApiKey {
+        token: token.to_string().into(),
+        role: role.to_string(),
+        name: Some(role.to_string()),
+    }
+}
+
+fn app(dashboard: Arc<AuthState>) -> Router {
+    let state = Arc::new(ApiAuthState {
+        settings: ApiAuthSettings {
+            enabled: true,
+            keys: vec![key(READ_TOKEN, "read"), key(ADMIN_TOKEN, "admin")],
+        },
+        dashboard,
+    });
+    Router::new()
+        .route("/health", get(|| async { "ok" }))
+        .route("/health/ready", get(|| async { "ok" }))
+        .route("/metrics", get(|| async { "ok" }))
+        .route("/status.json", get(|| async { "ok" }))
+        .route("/api/sessions/active", get(|| async { "ok" }))
+        .route("/api/sessions/{id}/terminate", post(|| async { "ok" }))
+        .route("/api/acl/test", post(|| async { "ok" }))
+        .route("/api/acl/groups", post(|| async { "ok" }))
+        .route("/api/admin/reload-acl", post(|| async { "ok" }))
+        .layer(middleware::from_fn_with_state(state, api_key_auth))
+}
+
+fn no_dashboard() -> Arc<AuthState> {
+    Arc::new(AuthState::new(DashboardAuthSettings::default()))
+}
+
+async fn call(app: &Router, method: Method, uri: &str, header: Option<(&str, &str)>) -> StatusCode {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some((name, value)) = header {
+        request = request.header(name, value);
+    }
+    app.clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+fn bearer(token: &str) -> String {
+    format!("Bearer {}", token)
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_key_get_401_with_a_json_body() {
+    let app = app(no_dashboard());
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/sessions/active")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["error"], "unauthorized");
+    assert_eq!(error["status_code"], 401);
+
+    let wrong = bearer("not-a-configured-token");
+    for (method, uri) in [
+        (Method::GET, "/metrics"),
+        (Method::GET, "/api/sessions/active"),
+        (Method::POST, "/api/admin/reload-acl"),
+    ] {
+        assert_eq!(
+            call(&app, method.clone(), uri, Some(("authorization", &wrong))).await,
+            StatusCode::UNAUTHORIZED,
+            "{} {}",
+            method,
+            uri
+        );
+    }
+
+    // The public status page and the health probes stay open
+    for uri in ["/status.json", "/health", "/health/ready"] {
+        assert_eq!(
+            call(&app, Method::GET, uri, None).await,
+            StatusCode::OK,
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn read_keys_reach_reads_but_not_changes() {
+    let app = app(no_dashboard());
+    let read = bearer(READ_TOKEN);
+    let read = Some(("authorization", read.as_str()));
+
+    assert_eq!(
+        call(&app, Method::GET, "/metrics", read).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        call(&app, Method::GET, "/api/sessions/active", read).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        call(&app, Method::POST, "/api/acl/test", read).await,
+        StatusCode::OK
+    );
+    // X-API-Key works as well
+    assert_eq!(
+        call(
+            &app,
+            Method::GET,
+            "/api/sessions/active",
+            Some(("x-api-key", READ_TOKEN))
+        )
+        .await,
+        StatusCode::OK
+    );
+
+    for uri in [
+        "/api/acl/groups",
+        "/api/admin/reload-acl",
+        "/api/sessions/7f9c0c8e-8f3a-4b8e-9a50-2b0c0a4e6b11/terminate",
+    ] {
+        assert_eq!(
+            call(&app, Method::POST, uri, read).await,
+            StatusCode::FORBIDDEN,
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn admin_keys_reach_everything() {
+    let app = app(no_dashboard());
+    let admin = bearer(ADMIN_TOKEN);
+    let admin = Some(("authorization", admin.as_str()));
+
+    assert_eq!(
+        call(&app, Method::GET, "/api/sessions/active", admin).await,
+        StatusCode::OK
+    );
+    for uri in [
+        "/api/acl/groups",
+        "/api/admin/reload-acl",
+        "/api/sessions/7f9c0c8e-8f3a-4b8e-9a50-2b0c0a4e6b11/terminate",
+    ] {
+        assert_eq!(
+            call(&app, Method::POST, uri, admin).await,
+            StatusCode::OK,
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn dashboard_login_counts_as_admin() {
+    let dashboard = Arc::new(AuthState::new(DashboardAuthSettings {
+        enabled: true,
+        users: vec![User {
+            username: "admin".to_string(),
+            password: "secret".to_string().into(),
+            password_hash: None,
+        }],
+        ..DashboardAuthSettings::default()
+    }));
+    let session = dashboard.create_session("admin".to_string());
+    let app = app(dashboard);
+    let cookie = format!("rustsocks_session={}", session);
+
+    assert_eq!(
+        call(
+            &app,
+            Method::POST,
+            "/api/admin/reload-acl",
+            Some(("cookie", &cookie))
+        )
+        .await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        call(
+            &app,
+            Method::POST,
+            "/api/admin/reload-acl",
+            Some(("cookie", "rustsocks_session=expired"))
+        )
+        .await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[test]
+fn access_levels_follow_method_and_path() {
+    assert_eq!(
+        required_access(&Method::POST, "/api/auth/login"),
+        ApiAccess::Public
+    );
+    assert_eq!(
+        required_access(&Method::GET, "/swagger-ui/"),
+        ApiAccess::Public
+    );
+    assert_eq!(
+        required_access(&Method::GET, "/assets/index.js"),
+        ApiAccess::Public
+    );
+    for probe in ["/health", "/health/live", "/health/ready/"] {
+        assert_eq!(required_access(&Method::GET, probe), ApiAccess::Public);
+    }
+    assert_eq!(required_access(&Method::GET, "/metrics"), ApiAccess::Read);
+    assert_eq!(
+        required_access(&Method::POST, "/api/acl/search"),
+        ApiAccess::Read
+    );
+    assert_eq!(
+        required_access(&Method::GET, "/api/admin/runtime-config"),
+        ApiAccess::Admin
+    );
+    assert_eq!(
+        required_access(&Method::POST, "/api/auth/address-cache/invalidate"),
+        ApiAccess::Admin
+    );
+    assert_eq!(
+        required_access(&Method::DELETE, "/api/acl/users/alice"),
+        ApiAccess::Admin
+    );
+    assert_eq!(
+        required_access(&Method::PUT, "/api/qos/users/alice/limit"),
+        ApiAccess::Admin
+    );
+    assert_eq!(
+        required_access(&Method::GET, "/api/qos/config"),
+        ApiAccess::Read
+    );
+}
@@ -0,0 +1,159 @@
+//! Compression and ETags on the stats API (`[sessions.api_compression]`)
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    middleware,
+    routing::get,
+    Json, Router,
+};
+use rustsocks::api::encoding::{
+    compression_layer, conditional_get, skip_compression, CompressionExclusions,
+};
+use rustsocks::config::ApiCompressionSettings;
+use serde_json::{json, Value};
+use tower::util::ServiceExt;
+
+/// A sessions list the size the dashboard sees on a busy proxy
+fn sessions() -> Value {
+    let sessions: Vec<Value> = (0..2000)
+        .map(|i| {
+            json!({
+                "session_id": format!("00000000-0000-0000-0000-{:012}", i),
+                "user": format!("user{}", i % 50),
+                "source_ip": "10.0.0.1",
+                "dest_ip": "93.184.216.34",
+                "dest_port": 443,
+                "protocol": "tcp",
+                "status": "active",
+                "bytes_sent": i * 1000,
+                "bytes_received": i * 4000,
+            })
+        })
+        .collect();
+    json!({ "sessions": sessions })
+}
+
+fn app(settings: &ApiCompressionSettings) -> Router {
+    let app = Router::new()
+        .route(
+            "/api/sessions/active",
+            get(|| async { Json(sessions()) }).layer(middleware::from_fn(conditional_get)),
+        )
+        .route(
+            "/metrics",
+            get(|| async { "rustsocks_active_sessions 1\n".repeat(200) }),
+        );
+    app.layer(compression_layer(settings).unwrap())
+        .layer(middleware::from_fn_with_state(
+            CompressionExclusions::new(settings, ""),
+            skip_compression,
+        ))
+}
+
+async fn get_with(
+    app: &Router,
+    uri: &str,
+    headers: &[(header::HeaderName, &str)],
+) -> axum::response::Response {
+    let mut request = Request::builder().uri(uri);
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    app.clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+async fn body_len(response: axum::response::Response) -> usize {
+    axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap()
+        .len()
+}
+
+#[tokio::test]
+async fn large_sessions_response_is_compressed() {
+    let app = app(&ApiCompressionSettings::default());
+    let plain_len = serde_json::to_vec(&sessions()).unwrap().len();
+
+    let response = get_with(
+        &app,
+        "/api/sessions/active",
+        &[(header::ACCEPT_ENCODING, "gzip")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    assert!(response.headers().contains_key(header::ETAG));
+    let compressed_len = body_len(response).await;
+    assert!(
+        compressed_len * 5 < plain_len,
+        "{compressed_len} of {plain_len} bytes"
+    );
+
+    let response = get_with(
+        &app,
+        "/api/sessions/active",
+        &[(header::ACCEPT_ENCODING, "deflate")],
+    )
+    .await;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "deflate");
+
+    // Without Accept-Encoding the JSON goes out as is
+    let response = get_with(&app, "/api/sessions/active", &[]).await;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    assert_eq!(body_len(response).await, plain_len);
+}
+
+#[tokio::test]
+async fn unchanged_sessions_response_is_not_modified() {
+    let app = app(&ApiCompressionSettings::default());
+
+    let first = get_with(&app, "/api/sessions/active", &[]).await;
+    let etag = first.headers()[header::ETAG].to_str().unwrap().to_string();
+    assert!(etag.starts_with("W/\""), "{etag}");
+
+    // The tag is the same whatever the encoding
+    let response = get_with(
+        &app,
+        "/api/sessions/active",
+        &[
+            (header::ACCEPT_ENCODING, "gzip"),
+            (header::IF_NONE_MATCH, etag.as_str()),
+        ],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    assert_eq!(body_len(response).await, 0);
+
+    let response = get_with(
+        &app,
+        "/api/sessions/active",
+        &[(header::IF_NONE_MATCH, "W/\"stale\"")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_len(response).await > 0);
+}
+
+#[tokio::test]
+async fn excluded_paths_stay_uncompressed() {
+    let default = app(&ApiCompressionSettings::default());
+    let response = get_with(&default, "/metrics", &[(header::ACCEPT_ENCODING, "gzip")]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+
+    let everything = app(&ApiCompressionSettings {
+        exclude_paths: Vec::new(),
+        ..ApiCompressionSettings::default()
+    });
+    let response = get_with(
+        &everything,
+        "/metrics",
+        &[(header::ACCEPT_ENCODING, "gzip")],
+    )
+    .await;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+}
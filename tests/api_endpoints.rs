@@ -1,18 +1,29 @@
 use axum::{
     body::Body,
+    extract::ConnectInfo,
     http::{Request, StatusCode},
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
+use rustsocks::api::auth::ApiCaller;
 use rustsocks::api::handlers::sessions::ApiState;
 use rustsocks::api::handlers::{
-    get_acl_rules, get_active_sessions, get_metrics, get_session_history, get_session_stats,
-    get_user_sessions, health_check, test_acl_decision,
+    delete_qos_user_limit, delete_user_ban, export_acl_config, get_acl_example, get_acl_rule_stats,
+    get_acl_rules, get_active_sessions, get_banned_users, get_destination_stats, get_failure_stats,
+    get_metrics, get_qos_allocations, get_qos_config, get_session_detail, get_session_history,
+    get_session_stats, get_session_writer_status, get_user_sessions, get_version, health_check,
+    import_acl_config, readiness_check, reset_acl_rule_stats, set_qos_user_limit,
+    terminate_session, terminate_user_sessions, test_acl_decision, update_global_settings,
 };
+use rustsocks::auth::UserBans;
 use rustsocks::config::Config;
+use rustsocks::qos::{QosConfig, QosEngine};
 use rustsocks::server::pool::{ConnectionPool, PoolConfig};
-use rustsocks::session::{ConnectionInfo, SessionManager, SessionProtocol, SessionStatus};
-use std::net::IpAddr;
+use rustsocks::session::{
+    ConnectionInfo, FailureCategory, FailureRecord, SessionManager, SessionProtocol,
+    SessionResolution, SessionStatus,
+};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use tower::util::ServiceExt;
 
@@ -23,6 +34,7 @@ fn create_api_state(session_manager: Arc<SessionManager>) -> ApiState {
         acl_engine: None,
         acl_config_path: None,
         connection_pool,
+        qos_engine: Arc::new(QosEngine::None),
         start_time: std::time::Instant::now(),
         #[cfg(feature = "database")]
         session_store: None,
@@ -31,6 +43,15 @@ fn create_api_state(session_manager: Arc<SessionManager>) -> ApiState {
         config_path: None,
         config_snapshot: Arc::new(Config::default()),
         original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
     }
 }
 
@@ -62,6 +83,451 @@ async fn test_health_endpoint() {
 
     assert_eq!(health["status"], "healthy");
     assert!(health["version"].is_string());
+    assert_eq!(
+        health["instance_id"],
+        rustsocks::session::instance_id().to_string()
+    );
+    assert!(health.get("remaining_sessions").is_none());
+}
+
+#[tokio::test]
+async fn test_version_endpoint() {
+    let session_manager = Arc::new(SessionManager::new());
+    let app = Router::new()
+        .route("/api/version", get(get_version))
+        .with_state(create_api_state(session_manager));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/version")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let version: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!version["git_hash"].as_str().unwrap().is_empty());
+    assert!(version["build_timestamp"].is_string());
+    assert!(version["features"].is_array());
+    assert_eq!(version["active_sessions"], 0);
+    assert!(version["config_path"].is_null());
+    assert_eq!(version["subsystems"]["session_storage"], "disabled");
+    assert_eq!(version["subsystems"]["acl"], false);
+}
+
+#[tokio::test]
+async fn test_health_stays_live_while_draining() {
+    let session_manager = Arc::new(SessionManager::new());
+    let conn_info = ConnectionInfo {
+        source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+        source_port: 10000,
+        dest_ip: "8.8.8.8".into(),
+        dest_port: 80,
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+    session_manager
+        .new_session("alice", conn_info, "allow", None)
+        .await;
+    session_manager.begin_shutdown();
+
+    let app = Router::new()
+        .route("/health", get(health_check))
+        .route("/health/ready", get(readiness_check))
+        .with_state(create_api_state(session_manager.clone()));
+
+    // Liveness stays green so the process is not killed mid-drain
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/health")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(health["status"], "healthy");
+    assert_eq!(health["remaining_sessions"], 1);
+
+    // Readiness takes the node out of rotation
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/health/ready")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let ready: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(ready["status"], "draining");
+}
+
+#[tokio::test]
+async fn test_readiness_waits_for_listener_and_acl() {
+    use rustsocks::acl::types::GlobalAclConfig;
+    use rustsocks::acl::{AclConfig, AclEngine, Action};
+
+    let bound = Arc::new(rustsocks::server::BoundAddresses::default());
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.bound_addresses = Some(bound.clone());
+    let mut config = Config::default();
+    config.acl.enabled = true;
+    config.acl.config_file = Some("config/acl.toml".to_string());
+    state.config_snapshot = Arc::new(config);
+
+    let ready = |state: ApiState| async move {
+        let app = Router::new()
+            .route("/health/ready", get(readiness_check))
+            .route("/health/live", get(health_check))
+            .with_state(state);
+        let live = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/health/live")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(live.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/health/ready")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+
+    // Listener not bound yet, ACL enabled but not loaded
+    let (status, body) = ready(state.clone()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["components"]["socks_listener"]["status"], "down");
+    assert_eq!(body["components"]["acl"]["status"], "down");
+    assert_eq!(body["components"]["session_store"]["status"], "disabled");
+    assert_eq!(body["components"]["qos"]["status"], "disabled");
+
+    bound.set(vec!["127.0.0.1:1080".parse().unwrap()]);
+    state.acl_engine = Some(Arc::new(
+        AclEngine::new(AclConfig {
+            global: GlobalAclConfig {
+                default_policy: Action::Allow,
+            },
+            users: vec![],
+            groups: vec![],
+        })
+        .unwrap(),
+    ));
+    let (status, body) = ready(state.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(
+        body["components"]["socks_listener"]["detail"],
+        "127.0.0.1:1080"
+    );
+    assert_eq!(body["components"]["acl"]["status"], "up");
+
+    state.session_manager.begin_shutdown();
+    let (status, body) = ready(state).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "draining");
+}
+
+#[tokio::test]
+async fn test_session_detail_accepts_every_uuid_form() {
+    let session_manager = Arc::new(SessionManager::new());
+    let conn_info = ConnectionInfo {
+        source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+        source_port: 10000,
+        dest_ip: "8.8.8.8".into(),
+        dest_port: 80,
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+    let session_id = session_manager
+        .new_session("alice", conn_info, "allow", None)
+        .await;
+    assert_eq!(session_id.get_version_num(), 7);
+
+    let app = Router::new()
+        .route("/api/sessions/{id}", get(get_session_detail))
+        .with_state(create_api_state(session_manager.clone()));
+
+    let forms = [
+        session_id.hyphenated().to_string(),
+        session_id.simple().to_string(),
+        session_id.hyphenated().to_string().to_uppercase(),
+        session_id.urn().to_string(),
+    ];
+    for form in forms {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/sessions/{}", form))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "form {}", form);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let session: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(session["id"], session_id.to_string());
+        assert_eq!(
+            session["instance_id"],
+            rustsocks::session::instance_id().to_string()
+        );
+    }
+
+    // Old v4 IDs still parse; they just are not known here
+    for (uri, status) in [
+        (
+            format!("/api/sessions/{}", uuid::Uuid::new_v4()),
+            StatusCode::NOT_FOUND,
+        ),
+        (
+            "/api/sessions/not-a-session".to_string(),
+            StatusCode::BAD_REQUEST,
+        ),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status);
+    }
+}
+
+#[tokio::test]
+async fn test_terminate_session_outcomes() {
+    let session_manager = Arc::new(SessionManager::new());
+    let conn_info = ConnectionInfo {
+        source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+        source_port: 10000,
+        dest_ip: "8.8.8.8".into(),
+        dest_port: 80,
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+    let (session_id, cancel_token) = session_manager
+        .new_session_with_control("alice", conn_info, "allow", None, None)
+        .await;
+
+    let app = Router::new()
+        .route("/api/sessions/{id}/terminate", post(terminate_session))
+        .with_state(create_api_state(session_manager.clone()));
+    let terminate = |id: String| {
+        // What `api_key_auth` and the connect-info service add to a real request
+        Request::builder()
+            .method("POST")
+            .uri(format!("/api/sessions/{}/terminate", id))
+            .extension(ApiCaller("key ops".to_string()))
+            .extension(ConnectInfo("10.0.0.5:40000".parse::<SocketAddr>().unwrap()))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(terminate(session_id.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["status"], "terminated");
+    assert!(cancel_token.is_cancelled());
+
+    let closed = session_manager.get_closed_sessions().await;
+    assert_eq!(
+        closed[0].close_reason.as_deref(),
+        Some("Terminated by admin (key ops) from 10.0.0.5")
+    );
+
+    for (id, status, label) in [
+        (
+            session_id.to_string(),
+            StatusCode::CONFLICT,
+            "already_closed",
+        ),
+        (
+            uuid::Uuid::new_v4().to_string(),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        ),
+    ] {
+        let response = app.clone().oneshot(terminate(id)).await.unwrap();
+        assert_eq!(response.status(), status);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], label);
+    }
+}
+
+#[tokio::test]
+async fn test_terminate_user_sessions_and_ban() {
+    let session_manager = Arc::new(SessionManager::new());
+    let conn_info = ConnectionInfo {
+        source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+        source_port: 10000,
+        dest_ip: "8.8.8.8".into(),
+        dest_port: 80,
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+    let mut tokens = Vec::new();
+    for user in ["alice", "alice", "bob"] {
+        let (_, token) = session_manager
+            .new_session_with_control(user, conn_info.clone(), "allow", None, None)
+            .await;
+        tokens.push(token);
+    }
+
+    let bans = Arc::new(UserBans::new());
+    let mut state = create_api_state(session_manager.clone());
+    state.user_bans = Some(bans.clone());
+    let app = Router::new()
+        .route(
+            "/api/users/{user}/terminate-sessions",
+            post(terminate_user_sessions),
+        )
+        .route("/api/users/banned", get(get_banned_users))
+        .route(
+            "/api/users/banned/{user}",
+            axum::routing::delete(delete_user_ban),
+        )
+        .with_state(state);
+    let terminate = |body: Option<&str>| {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/users/alice/terminate-sessions")
+            .extension(ApiCaller("key ops".to_string()));
+        match body {
+            Some(body) => request
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap()
+    };
+    let send = |request: Request<Body>| {
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default(),
+            )
+        }
+    };
+
+    let (status, json) = send(terminate(Some(r#"{"ban_minutes": 30}"#))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["terminated"].as_array().unwrap().len(), 2);
+    assert_eq!(json["ban"]["user"], "alice");
+    assert_eq!(json["ban"]["banned_by"], "key ops");
+    assert!(tokens[0].is_cancelled() && tokens[1].is_cancelled());
+    assert!(!tokens[2].is_cancelled());
+    assert_eq!(session_manager.active_session_count(), 1);
+    assert!(bans.get("alice").is_some());
+
+    let list = || {
+        Request::builder()
+            .uri("/api/users/banned")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let (_, json) = send(list()).await;
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["user"], "alice");
+
+    // Without a body nothing is banned, and nothing is left to close
+    let (status, json) = send(terminate(None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(json["terminated"].as_array().unwrap().is_empty());
+    assert!(json.get("ban").is_none());
+
+    let (status, _) = send(terminate(Some(r#"{"ban_minutes": 0}"#))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let lift = || {
+        Request::builder()
+            .method("DELETE")
+            .uri("/api/users/banned/alice")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let (status, _) = send(lift()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(lift()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, json) = send(list()).await;
+    assert!(json.as_array().unwrap().is_empty());
 }
 
 #[tokio::test]
@@ -72,9 +538,14 @@ async fn test_metrics_endpoint() {
     let conn_info = ConnectionInfo {
         source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
         source_port: 12345,
-        dest_ip: "8.8.8.8".to_string(),
+        dest_ip: "8.8.8.8".into(),
         dest_port: 80,
         protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
     };
 
     session_manager
@@ -119,9 +590,14 @@ async fn test_get_active_sessions() {
         let conn_info = ConnectionInfo {
             source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
             source_port: 10000 + i,
-            dest_ip: format!("8.8.8.{}", i),
+            dest_ip: format!("8.8.8.{}", i).into(),
             dest_port: 80,
             protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
 
         session_manager
@@ -163,6 +639,148 @@ async fn test_get_active_sessions() {
     assert!(users.contains(&"user2".to_string()));
 }
 
+#[tokio::test]
+async fn test_session_lists_negotiate_msgpack() {
+    let session_manager = Arc::new(SessionManager::new());
+    for i in 0..2u16 {
+        let conn_info = ConnectionInfo {
+            source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+            source_port: 10000 + i,
+            dest_ip: format!("8.8.8.{}", i).into(),
+            dest_port: 443,
+            protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: vec!["staff".into()],
+        };
+        let id = session_manager
+            .new_session(&format!("user{}", i), conn_info, "allow", None)
+            .await;
+        if i == 1 {
+            session_manager
+                .close_session(&id, Some("done".to_string()), SessionStatus::Closed)
+                .await;
+        }
+    }
+
+    let app = Router::new()
+        .route("/api/sessions/active", get(get_active_sessions))
+        .route("/api/sessions/history", get(get_session_history))
+        .with_state(create_api_state(session_manager));
+    let fetch = |uri: &'static str, accept: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .header("accept", accept)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let content_type = response.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .to_string();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let value: serde_json::Value = if content_type == "application/msgpack" {
+                rmp_serde::from_slice(&body).unwrap()
+            } else {
+                serde_json::from_slice(&body).unwrap()
+            };
+            (content_type, value)
+        }
+    };
+
+    for uri in ["/api/sessions/active", "/api/sessions/history"] {
+        let (content_type, json) = fetch(uri, "application/json").await;
+        assert_eq!(content_type, "application/json");
+        let (content_type, msgpack) = fetch(uri, "application/msgpack").await;
+        assert_eq!(content_type, "application/msgpack");
+        assert_eq!(msgpack, json, "{uri}");
+
+        let (content_type, fallback) = fetch(uri, "text/x-unknown").await;
+        assert_eq!(content_type, "application/json");
+        assert_eq!(fallback, json, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn test_get_active_sessions_sorted_by_rate() {
+    let session_manager = Arc::new(SessionManager::new());
+
+    for (i, bytes) in [1_000u64, 50_000, 5_000].into_iter().enumerate() {
+        let conn_info = ConnectionInfo {
+            source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+            source_port: 10000 + i as u16,
+            dest_ip: format!("8.8.8.{}", i).into(),
+            dest_port: 80,
+            protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
+        };
+        let session_id = session_manager
+            .new_session(&format!("user{}", i), conn_info, "allow", None)
+            .await;
+        session_manager
+            .update_traffic(&session_id, bytes, bytes / 2, 1, 1)
+            .await;
+    }
+
+    let app = Router::new()
+        .route("/api/sessions/active", get(get_active_sessions))
+        .with_state(create_api_state(session_manager.clone()));
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/sessions/active?sort=rate&limit=2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let sessions: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+
+    let users: Vec<&str> = sessions
+        .iter()
+        .map(|s| s["user"].as_str().unwrap())
+        .collect();
+    assert_eq!(users, ["user1", "user2"]);
+    // 50 000 bytes within the 5-second window (a little less if it just rolled over)
+    let sent = sessions[0]["rate_bps_sent"].as_u64().unwrap();
+    let received = sessions[0]["rate_bps_received"].as_u64().unwrap();
+    assert!((9_900..=10_000).contains(&sent), "{sent}");
+    assert!((4_950..=5_000).contains(&received), "{received}");
+    assert!(sessions[0]["rate_bps_sent_60s"].as_u64().unwrap() > 0);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/sessions/active?sort=bytes")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
 #[tokio::test]
 async fn test_get_session_stats() {
     let session_manager = Arc::new(SessionManager::new());
@@ -172,9 +790,14 @@ async fn test_get_session_stats() {
         let conn_info = ConnectionInfo {
             source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
             source_port: 10000 + i,
-            dest_ip: format!("8.8.8.{}", i % 2),
+            dest_ip: format!("8.8.8.{}", i % 2).into(),
             dest_port: 80,
             protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
 
         session_manager
@@ -182,12 +805,249 @@ async fn test_get_session_stats() {
             .await;
     }
 
-    let state = create_api_state(session_manager.clone());
-
+    let state = create_api_state(session_manager.clone());
+
+    let app = Router::new()
+        .route("/api/sessions/stats", get(get_session_stats))
+        .with_state(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/sessions/stats")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(stats["total_sessions"], 5);
+    assert_eq!(stats["active_sessions"], 5);
+    assert!(stats["top_users"].is_array());
+    assert!(stats["top_destinations"].is_array());
+    assert!(stats.get("groups").is_none());
+}
+
+#[tokio::test]
+async fn test_session_stats_by_group() {
+    let session_manager = Arc::new(SessionManager::new());
+    let conn_info = |groups: &[&str]| ConnectionInfo {
+        source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+        source_port: 10000,
+        dest_ip: "8.8.8.8".into(),
+        dest_port: 443,
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: groups.iter().map(|group| Arc::from(*group)).collect(),
+    };
+
+    for (user, groups) in [
+        ("alice", &["engineering", "vpn"][..]),
+        ("alice", &["engineering", "vpn"][..]),
+        ("bob", &["engineering"][..]),
+        ("anonymous", &[][..]),
+    ] {
+        let session_id = session_manager
+            .new_session(user, conn_info(groups), "allow", None)
+            .await;
+        session_manager
+            .update_traffic(&session_id, 100, 1_000, 1, 1)
+            .await;
+    }
+    session_manager
+        .track_rejected_session("carol", conn_info(&["sales"]), None, 0x02)
+        .await;
+
+    let app = Router::new()
+        .route("/api/sessions/stats", get(get_session_stats))
+        .with_state(create_api_state(session_manager));
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/sessions/stats?group_by=group")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(stats["total_sessions"], 5);
+
+    let groups = stats["groups"].as_array().unwrap();
+    let names: Vec<&str> = groups
+        .iter()
+        .map(|group| group["group"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["engineering", "vpn", "sales", "ungrouped"]);
+
+    let engineering = &groups[0];
+    assert_eq!(engineering["session_count"], 3);
+    assert_eq!(engineering["bytes_sent"], 300);
+    assert_eq!(engineering["bytes_received"], 3_000);
+    assert_eq!(engineering["unique_users"], 2);
+    assert_eq!(engineering["acl"]["allowed"], 3);
+    assert_eq!(engineering["acl"]["blocked"], 0);
+    assert_eq!(groups[1]["unique_users"], 1);
+    assert_eq!(groups[2]["acl"]["blocked"], 1);
+    assert_eq!(groups[2]["acl"]["allowed"], 0);
+    assert_eq!(groups[3]["session_count"], 1);
+}
+
+#[tokio::test]
+async fn test_failure_stats_by_category_and_destination() {
+    let session_manager = Arc::new(SessionManager::new());
+    let conn_info = |dest: &str| ConnectionInfo {
+        source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+        source_port: 10000,
+        dest_ip: dest.into(),
+        dest_port: 443,
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+
+    for _ in 0..2 {
+        session_manager
+            .track_failed_session(
+                "alice",
+                conn_info("slow.example.com"),
+                "allow",
+                None,
+                FailureCategory::ConnectTimeout,
+                0x04,
+                "Connect failed: timed out".to_string(),
+                SessionResolution::default(),
+            )
+            .await;
+    }
+    session_manager
+        .track_failed_session(
+            "alice",
+            conn_info("nxdomain.example.com"),
+            "allow",
+            None,
+            FailureCategory::DnsError,
+            0x04,
+            "Connect failed: no such host".to_string(),
+            SessionResolution::default(),
+        )
+        .await;
+    session_manager
+        .track_rejected_session("bob", conn_info("ads.example.com"), None, 0x02)
+        .await;
+    // A failed login never becomes a session but still counts
+    session_manager
+        .failures()
+        .record(FailureRecord::auth_failure(
+            "127.0.0.1".parse().unwrap(),
+            None,
+        ));
+
+    let app = Router::new()
+        .route("/api/stats/failures", get(get_failure_stats))
+        .with_state(create_api_state(session_manager.clone()));
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/stats/failures?window_minutes=5&top=2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(stats["window_minutes"], 5);
+    assert_eq!(stats["total"], 5);
+    assert_eq!(stats["by_category"]["connect_timeout"], 2);
+    assert_eq!(stats["by_category"]["dns_error"], 1);
+    assert_eq!(stats["by_category"]["acl_block"], 1);
+    assert_eq!(stats["by_category"]["auth_fail"], 1);
+    assert_eq!(stats["by_category"]["connect_refused"], 0);
+    assert_eq!(stats["totals_since_start"]["connect_timeout"], 2);
+
+    let top = stats["top_destinations"].as_array().unwrap();
+    assert_eq!(top.len(), 2);
+    assert_eq!(top[0]["destination"], "slow.example.com:443");
+    assert_eq!(top[0]["failures"], 2);
+    assert_eq!(top[0]["by_category"]["connect_timeout"], 2);
+
+    // The failed sessions carry the reply and category too
+    let failed = session_manager.closed_snapshot().await;
+    assert_eq!(failed.len(), 3);
+    assert!(
+        failed
+            .iter()
+            .all(|session| session.status == SessionStatus::Failed
+                && session.reply_code == Some(0x04))
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/stats/failures?window_minutes=0")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_session_stats_would_block() {
+    let session_manager = Arc::new(SessionManager::new());
+    let conn_info = ConnectionInfo {
+        source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+        source_port: 10000,
+        dest_ip: "8.8.8.8".into(),
+        dest_port: 443,
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+
+    for would_block in [true, true, false] {
+        let session_id = session_manager
+            .new_session("alice", conn_info.clone(), "allow", None)
+            .await;
+        session_manager
+            .update_traffic(&session_id, 100, 1_000, 1, 1)
+            .await;
+        if would_block {
+            session_manager.mark_would_block(&session_id).await;
+        }
+    }
+
     let app = Router::new()
         .route("/api/sessions/stats", get(get_session_stats))
-        .with_state(state);
-
+        .with_state(create_api_state(session_manager));
     let response = app
         .oneshot(
             Request::builder()
@@ -197,18 +1057,155 @@ async fn test_get_session_stats() {
         )
         .await
         .unwrap();
-
     assert_eq!(response.status(), StatusCode::OK);
 
     let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
         .unwrap();
     let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(stats["total_sessions"], 3);
+    assert_eq!(stats["would_block"]["session_count"], 2);
+    assert_eq!(stats["would_block"]["active_sessions"], 2);
+    assert_eq!(stats["would_block"]["bytes_sent"], 200);
+    assert_eq!(stats["would_block"]["bytes_received"], 2_000);
+}
 
-    assert_eq!(stats["total_sessions"], 5);
-    assert_eq!(stats["active_sessions"], 5);
-    assert!(stats["top_users"].is_array());
-    assert!(stats["top_destinations"].is_array());
+#[tokio::test]
+async fn test_destination_stats_from_the_session_store() {
+    #[allow(unused_mut)]
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+
+    #[cfg(feature = "database")]
+    {
+        let store = Arc::new(
+            rustsocks::session::SessionStore::connect("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        for (dest, bytes) in [("8.8.8.8", 500), ("8.8.8.8", 700), ("8.8.4.4", 10)] {
+            let conn_info = ConnectionInfo {
+                source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+                source_port: 10000,
+                dest_ip: dest.into(),
+                dest_port: 53,
+                protocol: SessionProtocol::Tcp,
+                authenticated_user: None,
+                correlation_id: None,
+                socks_version: 5,
+                chained: false,
+                groups: Vec::new(),
+            };
+            let mut session = rustsocks::session::Session::new("alice", conn_info, "allow", None);
+            session.bytes_sent = bytes;
+            store.insert_session(&session).await.unwrap();
+        }
+        state.session_store = Some(store);
+    }
+
+    let app = Router::new()
+        .route("/api/stats/destinations", get(get_destination_stats))
+        .with_state(state);
+    let get = |uri: &'static str| {
+        app.clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+    };
+
+    for uri in [
+        "/api/stats/destinations?bucket_minutes=0",
+        "/api/stats/destinations?window_hours=720&bucket_minutes=1",
+        "/api/stats/destinations?top=1000",
+    ] {
+        assert_eq!(get(uri).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+
+    let response = get("/api/stats/destinations?window_hours=1&bucket_minutes=60&top=1")
+        .await
+        .unwrap();
+    if cfg!(not(feature = "database")) {
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        return;
+    }
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(stats["bucket_minutes"], 60);
+    // Only the busiest destination; the sessions may straddle an hour boundary
+    let buckets = stats["buckets"].as_array().unwrap();
+    assert!(!buckets.is_empty());
+    assert!(buckets.iter().all(|bucket| bucket["dest"] == "8.8.8.8:53"));
+    let connections: u64 = buckets
+        .iter()
+        .map(|bucket| bucket["connections"].as_u64().unwrap())
+        .sum();
+    let bytes_sent: u64 = buckets
+        .iter()
+        .map(|bucket| bucket["bytes_sent"].as_u64().unwrap())
+        .sum();
+    assert_eq!((connections, bytes_sent), (2, 1200));
+}
+
+#[tokio::test]
+async fn test_session_writer_status() {
+    let app = Router::new()
+        .route(
+            "/api/sessions/writer-status",
+            get(get_session_writer_status),
+        )
+        .with_state(create_api_state(Arc::new(SessionManager::new())));
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/sessions/writer-status")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    // Memory-only sessions have no writer
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    #[cfg(feature = "database")]
+    {
+        let store = Arc::new(
+            rustsocks::session::SessionStore::connect("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let manager = SessionManager::with_store(
+            store,
+            rustsocks::session::BatchConfig {
+                queue_max: 5000,
+                overflow_policy: rustsocks::session::BatchOverflowPolicy::DropNewest,
+                ..Default::default()
+            },
+        );
+        let app = Router::new()
+            .route(
+                "/api/sessions/writer-status",
+                get(get_session_writer_status),
+            )
+            .with_state(create_api_state(Arc::new(manager)));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/sessions/writer-status")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status["queue_max"], 5000);
+        assert_eq!(status["overflow_policy"], "drop_newest");
+        assert_eq!(status["queue_depth"], 0);
+        assert_eq!(status["dropped_sessions"], 0);
+    }
 }
 
 #[tokio::test]
@@ -220,9 +1217,14 @@ async fn test_get_user_sessions() {
         let conn_info = ConnectionInfo {
             source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
             source_port: 10000 + i,
-            dest_ip: "8.8.8.8".to_string(),
+            dest_ip: "8.8.8.8".into(),
             dest_port: 80,
             protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
 
         session_manager
@@ -234,9 +1236,14 @@ async fn test_get_user_sessions() {
         let conn_info = ConnectionInfo {
             source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
             source_port: 20000 + i,
-            dest_ip: "8.8.4.4".to_string(),
+            dest_ip: "8.8.4.4".into(),
             dest_port: 443,
             protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
 
         session_manager
@@ -282,9 +1289,14 @@ async fn test_session_history_with_filters() {
         let conn_info = ConnectionInfo {
             source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
             source_port: 10000 + i,
-            dest_ip: format!("8.8.8.{}", i),
+            dest_ip: format!("8.8.8.{}", i).into(),
             dest_port: 80,
             protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
 
         let session_id = session_manager
@@ -345,9 +1357,14 @@ async fn test_session_history_pagination() {
         let conn_info = ConnectionInfo {
             source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
             source_port: 10000 + i,
-            dest_ip: "8.8.8.8".to_string(),
+            dest_ip: "8.8.8.8".into(),
             dest_port: 80,
             protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
 
         let session_id = session_manager
@@ -537,3 +1554,878 @@ async fn test_test_acl_decision_valid_request() {
     // ACL not enabled returns error
     assert_eq!(result["matched_rule"], "ACL is not enabled");
 }
+
+#[tokio::test]
+async fn test_test_acl_decision_with_source() {
+    use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+    use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
+
+    let acl = AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![UserAcl {
+            username: "alice".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Allow,
+                description: "Office network".to_string(),
+                destinations: vec!["*".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec!["10.1.0.0/16".to_string()],
+                protocols: vec![Protocol::Tcp],
+                priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+        groups: vec![],
+    };
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.acl_engine = Some(Arc::new(AclEngine::new(acl).unwrap()));
+    let app = Router::new()
+        .route("/api/acl/test", post(test_acl_decision))
+        .with_state(state);
+
+    let test = |source: Option<&str>| {
+        let app = app.clone();
+        let mut request_body = serde_json::json!({
+            "user": "alice",
+            "destination": "example.com",
+            "port": 443,
+            "protocol": "tcp",
+            "explain": true
+        });
+        if let Some(source) = source {
+            request_body["source"] = source.into();
+        }
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/acl/test")
+                        .header("content-type", "application/json")
+                        .body(Body::from(request_body.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            (status, result)
+        }
+    };
+
+    let (status, result) = test(Some("10.1.2.3")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["decision"], "allow");
+    assert_eq!(result["source"], "10.1.2.3");
+    assert_eq!(result["explanation"]["rules"][0]["source_matched"], true);
+
+    let (_, result) = test(Some("192.0.2.1")).await;
+    assert_eq!(result["decision"], "block");
+    assert_eq!(result["explanation"]["rules"][0]["source_matched"], false);
+    assert_eq!(
+        result["explanation"]["rules"][0]["destination_matched"],
+        true
+    );
+
+    // Without a source, rules limited to client addresses cannot match
+    let (_, result) = test(None).await;
+    assert_eq!(result["decision"], "block");
+    assert!(result.get("source").is_none());
+
+    let (status, result) = test(Some("office")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(result["matched_rule"], "Invalid source (use an IP address)");
+}
+
+#[tokio::test]
+async fn test_test_acl_decision_at_a_given_time() {
+    use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, RuleSchedule, UserAcl};
+    use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
+
+    let acl = AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![UserAcl {
+            username: "bob".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Allow,
+                description: "Build servers in business hours".to_string(),
+                destinations: vec!["build.internal".to_string()],
+                ports: vec!["22".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: Some(RuleSchedule {
+                    days: vec!["mon-fri".to_string()],
+                    times: vec!["08:00-18:00".to_string()],
+                    timezone: Some("UTC".to_string()),
+                }),
+            }],
+        }],
+        groups: vec![],
+    };
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.acl_engine = Some(Arc::new(AclEngine::new(acl).unwrap()));
+    let app = Router::new()
+        .route("/api/acl/test", post(test_acl_decision))
+        .with_state(state);
+
+    let test = |at: &str| {
+        let app = app.clone();
+        let request_body = serde_json::json!({
+            "user": "bob",
+            "destination": "build.internal",
+            "port": 22,
+            "protocol": "tcp",
+            "explain": true,
+            "at": at
+        });
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/acl/test")
+                        .header("content-type", "application/json")
+                        .body(Body::from(request_body.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, body)
+        }
+    };
+
+    // Monday morning
+    let (status, body) = test("2026-10-19T09:00:00Z").await;
+    assert_eq!(status, StatusCode::OK);
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["decision"], "allow");
+    assert_eq!(result["at"], "2026-10-19T09:00:00Z");
+    assert_eq!(result["explanation"]["rules"][0]["schedule_matched"], true);
+
+    // Saturday
+    let (_, body) = test("2026-10-17T09:00:00Z").await;
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["decision"], "block");
+    assert_eq!(result["explanation"]["rules"][0]["schedule_matched"], false);
+    assert_eq!(
+        result["explanation"]["rules"][0]["destination_matched"],
+        true
+    );
+
+    let (status, _) = test("next monday").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_acl_mode_switch() {
+    use rustsocks::acl::types::GlobalAclConfig;
+    use rustsocks::acl::{AclConfig, AclEngine, AclMode, Action};
+
+    let acl = AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![],
+        groups: vec![],
+    };
+    let engine = Arc::new(AclEngine::new(acl).unwrap());
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.acl_engine = Some(engine.clone());
+    let app = Router::new()
+        .route("/api/acl/global", put(update_global_settings))
+        .with_state(state);
+
+    let update = |body: serde_json::Value| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("PUT")
+                        .uri("/api/acl/global")
+                        .header("content-type", "application/json")
+                        .body(Body::from(body.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            (status, result)
+        }
+    };
+
+    let (status, result) = update(serde_json::json!({ "mode": "monitor" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["old_mode"], "enforce");
+    assert_eq!(result["new_mode"], "monitor");
+    // The default policy is untouched, so no ACL file is needed
+    assert_eq!(result["old_policy"], "block");
+    assert_eq!(result["new_policy"], "block");
+    assert_eq!(engine.mode(), AclMode::Monitor);
+
+    let (status, _) = update(serde_json::json!({ "mode": "shadow" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = update(serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(engine.mode(), AclMode::Monitor);
+
+    let (status, result) = update(serde_json::json!({ "mode": "enforce" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["old_mode"], "monitor");
+    assert_eq!(engine.mode(), AclMode::Enforce);
+}
+
+#[tokio::test]
+async fn test_acl_policy_and_mode_flip_together() {
+    use rustsocks::acl::types::GlobalAclConfig;
+    use rustsocks::acl::{AclConfig, AclEngine, AclMode, Action};
+
+    let acl = AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![],
+        groups: vec![],
+    };
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let acl_path = temp_dir.path().join("acl.toml");
+    rustsocks::acl::save_config(&acl, &acl_path).await.unwrap();
+
+    let engine = Arc::new(AclEngine::new(acl).unwrap());
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.acl_engine = Some(engine.clone());
+    state.acl_config_path = Some(acl_path.to_string_lossy().into_owned());
+    let app = Router::new()
+        .route("/api/acl/global", put(update_global_settings))
+        .with_state(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/api/acl/global")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({ "default_policy": "allow", "mode": "monitor" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["message"], "Global policy and ACL mode updated");
+    assert_eq!(result["old_policy"], "block");
+    assert_eq!(result["new_policy"], "allow");
+    assert_eq!(result["new_mode"], "monitor");
+    assert_eq!(engine.mode(), AclMode::Monitor);
+
+    // Persisted to the ACL file
+    let saved = rustsocks::acl::load_config(&acl_path).await.unwrap();
+    assert!(matches!(saved.global.default_policy, Action::Allow));
+}
+
+#[tokio::test]
+async fn test_acl_rule_stats_sorted_and_reset() {
+    use rustsocks::acl::types::RuleLogLevel;
+    use rustsocks::acl::types::{AclRule, GlobalAclConfig, UserAcl};
+    use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
+    use rustsocks::protocol::Address;
+
+    let rule = |destination: &str| AclRule {
+        action: Action::Allow,
+        description: destination.to_string(),
+        destinations: vec![destination.to_string()],
+        ports: vec!["*".to_string()],
+        sources: vec![],
+        protocols: vec![Protocol::Tcp],
+        priority: 100,
+        log: RuleLogLevel::Default,
+        reply_code: None,
+        resolve: Default::default(),
+        schedule: None,
+    };
+    let acl = AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![UserAcl {
+            username: "alice".to_string(),
+            groups: vec![],
+            rules: vec![
+                rule("a.example.com"),
+                rule("b.example.com"),
+                rule("c.example.com"),
+            ],
+        }],
+        groups: vec![],
+    };
+    let engine = Arc::new(AclEngine::new(acl).unwrap());
+    for (destination, times) in [("b.example.com", 3), ("c.example.com", 1)] {
+        for _ in 0..times {
+            engine
+                .evaluate_with_groups(
+                    "alice",
+                    &[],
+                    &Address::Domain(destination.into()),
+                    443,
+                    &Protocol::Tcp,
+                    None,
+                )
+                .await;
+        }
+    }
+
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.acl_engine = Some(engine.clone());
+    let app = Router::new()
+        .route("/api/acl/rules/stats", get(get_acl_rule_stats))
+        .route("/api/acl/rules/stats/reset", post(reset_acl_rule_stats))
+        .with_state(state);
+    let send = |method: &'static str, uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            (status, json)
+        }
+    };
+
+    let (status, json) = send("GET", "/api/acl/rules/stats").await;
+    assert_eq!(status, StatusCode::OK);
+    let rules = json["rules"].as_array().unwrap();
+    let order: Vec<&str> = rules
+        .iter()
+        .map(|rule| rule["description"].as_str().unwrap())
+        .collect();
+    assert_eq!(order, ["b.example.com", "c.example.com", "a.example.com"]);
+    assert_eq!(rules[0]["hits"], 3);
+    assert_eq!(rules[0]["users"][0]["user"], "alice");
+    assert_eq!(rules[0]["users"][0]["hits"], 3);
+    // Never matched, but listed
+    assert_eq!(rules[2]["hits"], 0);
+    assert!(rules[2]["last_matched_at"].is_null());
+
+    let (status, json) = send("POST", "/api/acl/rules/stats/reset").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["reset"], 3);
+    let (_, json) = send("GET", "/api/acl/rules/stats").await;
+    assert!(json["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|rule| rule["hits"] == 0 && rule["users"].as_array().unwrap().is_empty()));
+}
+
+#[tokio::test]
+async fn test_acl_example_variants() {
+    let session_manager = Arc::new(SessionManager::new());
+    let app = Router::new()
+        .route("/api/acl/example", get(get_acl_example))
+        .with_state(create_api_state(session_manager.clone()));
+
+    let get_example = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, body)
+        }
+    };
+
+    let (status, body) = get_example("/api/acl/example").await;
+    assert_eq!(status, StatusCode::OK);
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["variant"], "minimal");
+    let acl: rustsocks::acl::AclConfig =
+        toml::from_str(result["content"].as_str().unwrap()).unwrap();
+    assert_eq!(acl.groups.len(), 1);
+
+    let (status, _) = get_example("/api/acl/example?variant=everything").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Nothing to learn from until a session was allowed
+    let (status, _) = get_example("/api/acl/example?variant=from-learning").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    for (user, decision) in [("alice", "allow"), ("mallory", "block")] {
+        let conn_info = ConnectionInfo {
+            source_ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
+            source_port: 12345,
+            dest_ip: "93.184.216.34".into(),
+            dest_port: 443,
+            protocol: SessionProtocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
+        };
+        session_manager
+            .new_session(user, conn_info, decision, None)
+            .await;
+    }
+
+    let (status, body) = get_example("/api/acl/example?variant=from-learning").await;
+    assert_eq!(status, StatusCode::OK);
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let acl: rustsocks::acl::AclConfig =
+        toml::from_str(result["content"].as_str().unwrap()).unwrap();
+    assert_eq!(acl.users.len(), 1);
+    assert_eq!(acl.users[0].username, "alice");
+    assert_eq!(acl.users[0].rules[0].destinations, vec!["93.184.216.34"]);
+    assert_eq!(acl.users[0].rules[0].ports, vec!["443"]);
+}
+
+#[tokio::test]
+async fn test_qos_allocations_report_readable_rates() {
+    let session_manager = Arc::new(SessionManager::new());
+    let get_allocations = |state: ApiState| async move {
+        let app = Router::new()
+            .route("/api/qos/allocations", get(get_qos_allocations))
+            .with_state(state);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/qos/allocations")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+
+    let result = get_allocations(create_api_state(session_manager.clone())).await;
+    assert_eq!(result["enabled"], false);
+    assert!(result["limits"].is_null());
+    assert_eq!(result["users"].as_array().unwrap().len(), 0);
+
+    let config: QosConfig = toml::from_str(
+        r#"
+        enabled = true
+        [htb]
+        global_bandwidth_bytes_per_sec = "1Gbit"
+        guaranteed_bandwidth_bytes_per_sec = "1mbps"
+        max_bandwidth_bytes_per_sec = "100Mbit"
+        "#,
+    )
+    .unwrap();
+    let engine = QosEngine::from_config(config).await.unwrap();
+    engine.allocate_bandwidth("bob", 1024).await.unwrap();
+    engine.allocate_bandwidth("alice", 1024).await.unwrap();
+
+    let mut state = create_api_state(session_manager);
+    state.qos_engine = Arc::new(engine);
+    let result = get_allocations(state).await;
+
+    assert_eq!(result["enabled"], true);
+    assert_eq!(result["limits"]["global"]["bytes_per_sec"], 125_000_000);
+    assert_eq!(
+        result["limits"]["global"]["rate_human"],
+        "1 Gbps (125 MB/s)"
+    );
+    assert_eq!(
+        result["limits"]["max_per_user"]["rate_human"],
+        "100 Mbps (12.5 MB/s)"
+    );
+    let users = result["users"].as_array().unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0]["user"], "alice");
+    assert_eq!(users[0]["guaranteed"]["bytes_per_sec"], 125_000);
+    assert!(users[0]["allocated"]["rate_human"]
+        .as_str()
+        .unwrap()
+        .ends_with("B/s)"));
+    assert!(users[0]["borrowed"]["bytes_per_sec"].is_u64());
+    assert_eq!(users[0]["max_overridden"], false);
+    assert_eq!(users[0]["is_throttled"], false);
+}
+
+#[tokio::test]
+async fn test_qos_user_limit_override() {
+    let config: QosConfig = toml::from_str(
+        r#"
+        enabled = true
+        [htb]
+        guaranteed_bandwidth_bytes_per_sec = "1mbps"
+        max_bandwidth_bytes_per_sec = "100Mbit"
+        "#,
+    )
+    .unwrap();
+    let engine = QosEngine::from_config(config).await.unwrap();
+    engine.allocate_bandwidth("alice", 1024).await.unwrap();
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.qos_engine = Arc::new(engine);
+    let app = Router::new()
+        .route("/api/qos/allocations", get(get_qos_allocations))
+        .route("/api/qos/config", get(get_qos_config))
+        .route(
+            "/api/qos/users/{user}/limit",
+            put(set_qos_user_limit).delete(delete_qos_user_limit),
+        )
+        .with_state(state);
+    let send = |method: &str, uri: &str, body: &str| {
+        let app = app.clone();
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json = serde_json::from_slice::<serde_json::Value>(&body)
+                .unwrap_or(serde_json::Value::Null);
+            (status, json)
+        }
+    };
+
+    let (status, result) = send(
+        "PUT",
+        "/api/qos/users/alice/limit",
+        r#"{"max_bandwidth": "10Mbit"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["max"]["bytes_per_sec"], 1_250_000);
+    let (status, _) = send(
+        "PUT",
+        "/api/qos/users/alice/limit",
+        r#"{"max_bandwidth": 0}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, allocations) = send("GET", "/api/qos/allocations", "").await;
+    let alice = &allocations["users"][0];
+    assert_eq!(alice["max"]["bytes_per_sec"], 1_250_000);
+    assert_eq!(alice["max_overridden"], true);
+
+    let (status, config) = send("GET", "/api/qos/config", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(config["enabled"], true);
+    assert_eq!(config["htb"]["max_per_user"]["bytes_per_sec"], 12_500_000);
+    assert_eq!(config["user_limits"][0]["user"], "alice");
+    assert_eq!(config["user_limits"][0]["max"]["bytes_per_sec"], 1_250_000);
+
+    let (status, _) = send("DELETE", "/api/qos/users/alice/limit", "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send("DELETE", "/api/qos/users/alice/limit", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, allocations) = send("GET", "/api/qos/allocations", "").await;
+    assert_eq!(allocations["users"][0]["max"]["bytes_per_sec"], 12_500_000);
+    assert_eq!(allocations["users"][0]["max_overridden"], false);
+}
+
+#[tokio::test]
+async fn test_qos_user_limit_needs_qos_enabled() {
+    let app = Router::new()
+        .route("/api/qos/config", get(get_qos_config))
+        .route("/api/qos/users/{user}/limit", put(set_qos_user_limit))
+        .with_state(create_api_state(Arc::new(SessionManager::new())));
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/api/qos/users/alice/limit")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"max_bandwidth": "10Mbit"}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/qos/config")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let config: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(config["enabled"], false);
+    assert!(config["htb"].is_null());
+    assert_eq!(config["user_limits"].as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_qos_allocations_list_heaviest_source_ips() {
+    let config: QosConfig = toml::from_str(
+        r#"
+        enabled = true
+        [per_ip]
+        enabled = true
+        max_bytes_per_sec = "10mbit"
+        exempt = ["10.0.0.0/8"]
+        "#,
+    )
+    .unwrap();
+    let engine = QosEngine::from_config(config).await.unwrap();
+    let user: Arc<str> = Arc::from("guest");
+    for (ip, bytes) in [
+        ("192.168.1.10", 1_000),
+        ("192.168.1.11", 3_000),
+        ("192.168.1.12", 2_000),
+        ("10.0.0.5", 9_000),
+    ] {
+        engine
+            .allocate_bandwidth_from(&user, ip.parse().unwrap(), bytes)
+            .await
+            .unwrap();
+    }
+
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.qos_engine = Arc::new(engine);
+    let app = Router::new()
+        .route("/api/qos/allocations", get(get_qos_allocations))
+        .with_state(state);
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/qos/allocations?ip_limit=2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    let per_ip = &result["per_ip"];
+    assert_eq!(per_ip["max_per_ip"]["bytes_per_sec"], 1_250_000);
+    assert_eq!(per_ip["exempt"][0], "10.0.0.0/8");
+    assert_eq!(per_ip["tracked_ips"], 3);
+    assert_eq!(per_ip["truncated"], true);
+    let ips = per_ip["ips"].as_array().unwrap();
+    assert_eq!(ips.len(), 2);
+    assert_eq!(ips[0]["ip"], "192.168.1.11");
+    assert_eq!(ips[0]["total_bytes"], 3_000);
+    assert_eq!(ips[1]["ip"], "192.168.1.12");
+}
+
+#[tokio::test]
+async fn test_acl_import_dry_run_then_apply() {
+    use rustsocks::acl::{AclConfig, AclEngine};
+
+    let current: AclConfig = toml::from_str(
+        r#"
+        [global]
+        default_policy = "block"
+
+        [[groups]]
+        name = "developers"
+
+        [[groups.rules]]
+        action = "allow"
+        description = "GitHub"
+        destinations = ["*.github.com"]
+        ports = ["443"]
+        "#,
+    )
+    .unwrap();
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let acl_path = temp_dir.path().join("acl.toml");
+    rustsocks::acl::save_config(&current, &acl_path)
+        .await
+        .unwrap();
+
+    let engine = Arc::new(AclEngine::new(current).unwrap());
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.acl_engine = Some(engine.clone());
+    state.acl_config_path = Some(acl_path.to_string_lossy().into_owned());
+    let app = Router::new()
+        .route("/api/acl/import", post(import_acl_config))
+        .route("/api/acl/export", get(export_acl_config))
+        .with_state(state);
+
+    let imported = r#"
+        [global]
+        default_policy = "block"
+
+        [[groups]]
+        name = "developers"
+
+        [[groups.rules]]
+        action = "allow"
+        description = "GitHub"
+        destinations = ["*.github.com"]
+        ports = ["443"]
+
+        [[groups.rules]]
+        action = "allow"
+        description = "GitLab"
+        destinations = ["*.gitlab.com"]
+        ports = ["443"]
+
+        [[users]]
+        username = "alice"
+        groups = ["developers"]
+    "#;
+    let import = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(uri)
+                        .header("content-type", "application/toml")
+                        .body(Body::from(imported))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            (status, result)
+        }
+    };
+
+    let file_before = std::fs::read_to_string(&acl_path).unwrap();
+    let (status, result) = import("/api/acl/import?dry_run=true").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["applied"], false);
+    assert_eq!(result["diff"]["rules_added"], 1);
+    assert_eq!(result["diff"]["rules_removed"], 0);
+    assert_eq!(result["diff"]["scopes"][0]["scope"], "group:developers");
+    assert_eq!(result["diff"]["scopes"][1]["scope"], "user:alice");
+    assert_eq!(result["diff"]["scopes"][1]["change"], "added");
+    assert_eq!(std::fs::read_to_string(&acl_path).unwrap(), file_before);
+    assert_eq!(engine.current_config().users.len(), 0);
+
+    let (status, result) = import("/api/acl/import").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["applied"], true);
+    assert_eq!(engine.current_config().users.len(), 1);
+    let saved = rustsocks::acl::load_config(&acl_path).await.unwrap();
+    assert_eq!(saved.groups[0].rules.len(), 2);
+
+    // The export is the live config and imports back with no changes
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/acl/export")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/toml");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let exported: AclConfig = toml::from_str(std::str::from_utf8(&body).unwrap()).unwrap();
+    assert!(rustsocks::acl::diff::diff(&engine.current_config(), &exported).is_empty());
+}
+
+#[tokio::test]
+async fn test_acl_import_rejects_invalid_config() {
+    use rustsocks::acl::{AclConfig, AclEngine};
+
+    let mut state = create_api_state(Arc::new(SessionManager::new()));
+    state.acl_engine = Some(Arc::new(AclEngine::new(AclConfig::default()).unwrap()));
+    let app = Router::new()
+        .route("/api/acl/import", post(import_acl_config))
+        .with_state(state);
+
+    let duplicate_users = serde_json::json!({
+        "global": {"default_policy": "block"},
+        "users": [
+            {"username": "alice", "groups": [], "rules": []},
+            {"username": "alice", "groups": [], "rules": []}
+        ],
+        "groups": []
+    });
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/acl/import?dry_run=true")
+                .header("content-type", "application/json")
+                .body(Body::from(duplicate_users.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["success"], false);
+    assert!(result["message"]
+        .as_str()
+        .unwrap()
+        .contains("Duplicate user: alice"));
+}
@@ -0,0 +1,92 @@
+//! Management API on a Unix domain socket (`sessions.stats_api_bind = "unix:<path>"`)
+#![cfg(unix)]
+
+use rustsocks::api::{start_api_server, ApiConfig};
+use rustsocks::config::Config;
+use rustsocks::qos::QosEngine;
+use rustsocks::server::pool::{ConnectionPool, PoolConfig};
+use rustsocks::session::SessionManager;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::task::JoinHandle;
+
+async fn start(path: &Path) -> rustsocks::utils::error::Result<JoinHandle<()>> {
+    let config = ApiConfig {
+        enable_api: true,
+        unix_socket: Some(path.to_path_buf()),
+        unix_socket_mode: 0o600,
+        ..ApiConfig::default()
+    };
+    start_api_server(
+        config,
+        Arc::new(SessionManager::new()),
+        None,
+        None,
+        Arc::new(ConnectionPool::new(PoolConfig::default())),
+        Arc::new(QosEngine::None),
+        None,
+        None,
+        Arc::new(Config::default()),
+        None,
+        Arc::new(Vec::new()),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await
+}
+
+/// `GET /health` over the socket; the raw HTTP/1.1 response.
+async fn get_health(path: &Path) -> String {
+    let mut stream = UnixStream::connect(path).await.expect("connect API socket");
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn serves_the_api_over_a_unix_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("api.sock");
+
+    let server = start(&path).await.expect("start API on the socket");
+
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let response = get_health(&path).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\"status\":\"healthy\""), "{}", response);
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn stale_socket_is_replaced_and_live_one_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("api.sock");
+
+    // Left behind by a crashed run: the file exists, nothing listens
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let server = start(&path).await.expect("stale socket is replaced");
+    assert!(get_health(&path).await.starts_with("HTTP/1.1 200"));
+
+    // A second instance must not take the socket away from the first
+    assert!(start(&path).await.is_err());
+    assert!(get_health(&path).await.starts_with("HTTP/1.1 200"));
+
+    server.abort();
+}
@@ -0,0 +1,82 @@
+//! SOCKS5 method negotiation against `auth.socks_method_preference`
+//!
+//! The server answers with the first method of its preference list that the client
+//! offered, whatever order the client listed its methods in, and with 0xFF only when
+//! nothing overlaps.
+
+mod common;
+
+use rustsocks::auth::AuthManager;
+use rustsocks::config::{AuthConfig, User};
+use rustsocks::server::{handle_client, ClientHandlerContext};
+use rustsocks::session::SessionManager;
+use std::sync::Arc;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+use tokio::time::{timeout, Duration};
+
+fn auth_config(preference: &[&str]) -> AuthConfig {
+    AuthConfig {
+        socks_method: "userpass".to_string(),
+        socks_method_preference: preference.iter().map(|m| m.to_string()).collect(),
+        users: vec![User {
+            username: "alice".to_string(),
+            password: "secret123".to_string().into(),
+            password_hash: None,
+        }],
+        ..AuthConfig::default()
+    }
+}
+
+fn handler_context(auth: &AuthConfig) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(AuthManager::new(auth).expect("auth manager")),
+        ..common::handler_context(Arc::new(SessionManager::new()))
+    })
+}
+
+/// Send a greeting offering `methods` and return the method byte the server chose.
+async fn negotiate(ctx: Arc<ClientHandlerContext>, methods: &[u8]) -> u8 {
+    let (mut client, server) = duplex(1024);
+    tokio::spawn(async move {
+        let _ = handle_client(server, ctx, "127.0.0.1:40000".parse().unwrap()).await;
+    });
+
+    let mut greeting = vec![0x05, methods.len() as u8];
+    greeting.extend_from_slice(methods);
+    client.write_all(&greeting).await.unwrap();
+
+    let mut choice = [0u8; 2];
+    timeout(Duration::from_secs(5), client.read_exact(&mut choice))
+        .await
+        .expect("choice in time")
+        .unwrap();
+    assert_eq!(choice[0], 0x05);
+    choice[1]
+}
+
+#[tokio::test]
+async fn preferred_method_wins_regardless_of_client_order() {
+    let ctx = handler_context(&auth_config(&["userpass", "none"]));
+
+    assert_eq!(negotiate(ctx.clone(), &[0x00, 0x02]).await, 0x02);
+    assert_eq!(negotiate(ctx.clone(), &[0x02, 0x00]).await, 0x02);
+    assert_eq!(negotiate(ctx.clone(), &[0x00, 0x01, 0x02]).await, 0x02);
+    // Falls back to the next preference the client offered
+    assert_eq!(negotiate(ctx.clone(), &[0x01, 0x00]).await, 0x00);
+
+    let ctx = handler_context(&auth_config(&["none", "userpass"]));
+    assert_eq!(negotiate(ctx.clone(), &[0x02, 0x00]).await, 0x00);
+    assert_eq!(negotiate(ctx.clone(), &[0x02]).await, 0x02);
+}
+
+#[tokio::test]
+async fn no_overlap_is_answered_with_no_acceptable_methods() {
+    let ctx = handler_context(&auth_config(&["userpass", "none"]));
+    assert_eq!(negotiate(ctx.clone(), &[0x01]).await, 0xFF);
+    assert_eq!(negotiate(ctx.clone(), &[0x03, 0x80, 0xFE]).await, 0xFF);
+
+    // Without a preference list only socks_method is accepted
+    let ctx = handler_context(&auth_config(&[]));
+    assert_eq!(negotiate(ctx.clone(), &[0x00]).await, 0xFF);
+    assert_eq!(negotiate(ctx.clone(), &[0x00, 0x02]).await, 0x02);
+}
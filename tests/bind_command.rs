@@ -1,12 +1,13 @@
 use rustsocks::acl::{load_acl_config_sync, AclEngine, AclStats};
 use rustsocks::auth::AuthManager;
 use rustsocks::config::{AuthConfig, PamSettings};
-use rustsocks::qos::{ConnectionLimits, QosEngine};
+use rustsocks::qos::QosEngine;
 use rustsocks::server::{
     handle_client, ClientHandlerContext, ConnectionPool, PoolConfig, TrafficUpdateConfig,
 };
-use rustsocks::session::SessionManager;
+use rustsocks::session::{SessionCommand, SessionManager, SessionStatus};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 
@@ -16,13 +17,23 @@ async fn bind_basic_handshake() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: PamSettings::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let ctx = Arc::new(ClientHandlerContext {
@@ -33,8 +44,25 @@ async fn bind_basic_handshake() {
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     // Start SOCKS5 server
@@ -101,13 +129,23 @@ async fn bind_with_incoming_connection() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: PamSettings::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let ctx = Arc::new(ClientHandlerContext {
@@ -118,8 +156,25 @@ async fn bind_with_incoming_connection() {
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     // Start SOCKS5 server
@@ -226,13 +281,23 @@ username = "anonymous"
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: PamSettings::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let ctx = Arc::new(ClientHandlerContext {
@@ -243,8 +308,25 @@ username = "anonymous"
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     // Start SOCKS5 server
@@ -312,13 +394,23 @@ username = "anonymous"
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: PamSettings::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let ctx = Arc::new(ClientHandlerContext {
@@ -329,8 +421,25 @@ username = "anonymous"
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     // Start SOCKS5 server
@@ -368,3 +477,147 @@ username = "anonymous"
 
     println!("BIND with ACL block test completed");
 }
+
+/// Serve one SOCKS5 client with no auth and no ACL, BIND waiting `accept_timeout`.
+async fn spawn_bind_server(
+    accept_timeout: Duration,
+) -> (std::net::SocketAddr, Arc<SessionManager>) {
+    let auth_config = AuthConfig {
+        client_method: "none".to_string(),
+        socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
+        users: vec![],
+        users_file: None,
+        pam: PamSettings::default(),
+        gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
+    };
+    let session_manager = Arc::new(SessionManager::new());
+
+    let ctx = Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(AuthManager::new(&auth_config).unwrap()),
+        acl_engine: None,
+        acl_stats: Arc::new(AclStats::new()),
+        anonymous_user: Arc::<str>::from("anonymous"),
+        session_manager: session_manager.clone(),
+        traffic_config: TrafficUpdateConfig::default(),
+        qos_engine: QosEngine::None,
+        connection_limits: Default::default(),
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: accept_timeout,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        handle_client(stream, ctx, client_addr).await.ok();
+    });
+
+    (server_addr, session_manager)
+}
+
+/// Greet, send BIND for `expected_peer` and return the port of the first reply.
+async fn request_bind(client: &mut TcpStream, expected_peer: [u8; 4]) -> u16 {
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    client.read_exact(&mut choice).await.unwrap();
+    assert_eq!(choice, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x02, 0x00, 0x01];
+    request.extend_from_slice(&expected_peer);
+    request.extend_from_slice(&20u16.to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut first = [0u8; 10];
+    client.read_exact(&mut first).await.unwrap();
+    assert_eq!(first[1], 0x00, "first BIND reply must succeed");
+    u16::from_be_bytes([first[8], first[9]])
+}
+
+#[tokio::test]
+async fn bind_times_out_with_failure_reply() {
+    let (server_addr, session_manager) = spawn_bind_server(Duration::from_secs(1)).await;
+    let mut client = TcpStream::connect(server_addr).await.unwrap();
+    let bind_port = request_bind(&mut client, [0, 0, 0, 0]).await;
+
+    let mut second = [0u8; 10];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut second))
+        .await
+        .expect("failure reply after the accept timeout")
+        .unwrap();
+    assert_eq!(second[0], 0x05);
+    assert_eq!(second[1], 0x01); // General failure
+
+    // The listener is gone and the session is recorded as a failed BIND
+    assert!(TcpStream::connect(("127.0.0.1", bind_port)).await.is_err());
+    let mut closed = session_manager.get_closed_sessions().await;
+    for _ in 0..50 {
+        if !closed.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        closed = session_manager.get_closed_sessions().await;
+    }
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].command, SessionCommand::Bind);
+    assert_eq!(closed[0].status, SessionStatus::Failed);
+}
+
+#[tokio::test]
+async fn bind_drops_connections_from_unexpected_peers() {
+    let (server_addr, session_manager) = spawn_bind_server(Duration::from_secs(2)).await;
+    let mut client = TcpStream::connect(server_addr).await.unwrap();
+    // Expect a TEST-NET-1 host; the connection below comes from loopback
+    let bind_port = request_bind(&mut client, [192, 0, 2, 1]).await;
+
+    let mut active = session_manager.get_active_sessions().await;
+    for _ in 0..50 {
+        if !active.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        active = session_manager.get_active_sessions().await;
+    }
+    assert_eq!(active.len(), 1);
+    assert_eq!(active[0].command, SessionCommand::Bind);
+    assert_eq!(active[0].dest_ip.as_ref(), "192.0.2.1");
+
+    let mut intruder = TcpStream::connect(("127.0.0.1", bind_port)).await.unwrap();
+    let mut probe = [0u8; 1];
+    let dropped = tokio::time::timeout(Duration::from_secs(1), intruder.read(&mut probe))
+        .await
+        .expect("unexpected peer should be disconnected");
+    assert!(matches!(dropped, Ok(0) | Err(_)));
+
+    // No second success reply: the BIND keeps waiting and then fails
+    let mut second = [0u8; 10];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut second))
+        .await
+        .expect("failure reply after the accept timeout")
+        .unwrap();
+    assert_eq!(second[1], 0x01);
+}
@@ -0,0 +1,54 @@
+//! Fixtures shared by the integration tests
+#![allow(dead_code)]
+
+use rustsocks::acl::AclStats;
+use rustsocks::auth::AuthManager;
+use rustsocks::config::AuthConfig;
+use rustsocks::qos::QosEngine;
+use rustsocks::server::proxy::TrafficUpdateConfig;
+use rustsocks::server::{
+    ClientHandlerContext, ConnectionPool, PoolConfig, SniRouting, SpecialNamesPolicy,
+    SystemResolver, DEFAULT_BIND_ACCEPT_TIMEOUT,
+};
+use rustsocks::session::SessionManager;
+use std::sync::Arc;
+
+/// Handler context of a proxy with no authentication, ACL or QoS, that may connect to
+/// localhost; tests set the fields they exercise with struct update syntax:
+///
+/// ```ignore
+/// ClientHandlerContext {
+///     enable_socks4: true,
+///     ..common::handler_context(session_manager)
+/// }
+/// ```
+pub fn handler_context(session_manager: Arc<SessionManager>) -> ClientHandlerContext {
+    ClientHandlerContext {
+        auth_manager: Arc::new(AuthManager::new(&AuthConfig::default()).expect("auth manager")),
+        acl_engine: None,
+        acl_stats: Arc::new(AclStats::new()),
+        anonymous_user: Arc::<str>::from("anonymous"),
+        session_manager,
+        traffic_config: TrafficUpdateConfig::default(),
+        qos_engine: QosEngine::None,
+        connection_limits: Default::default(),
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: SniRouting::default(),
+        resolver: Arc::new(SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
+    }
+}
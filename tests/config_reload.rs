@@ -0,0 +1,218 @@
+/// Integration tests for reloading the main config file (SIGHUP and
+/// POST /api/admin/reload-config)
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use rustsocks::api::handlers::reload_config;
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::auth::AuthManager;
+use rustsocks::config::Config;
+use rustsocks::qos::{QosEngine, SharedConnectionLimits};
+use rustsocks::server::pool::{ConnectionPool, PoolConfig};
+use rustsocks::server::ConfigReloader;
+use rustsocks::session::SessionManager;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tower::util::ServiceExt;
+
+fn base_config() -> Config {
+    let mut config = Config::default();
+    config.qos.enabled = true;
+    config.qos.connection_limits.max_connections_per_user = 1;
+    config
+}
+
+fn write_config(path: &Path, config: &Config) {
+    config.write_to_file(path).expect("write config");
+}
+
+struct Fixture {
+    _dir: TempDir,
+    path: std::path::PathBuf,
+    reloader: Arc<ConfigReloader>,
+    qos_engine: QosEngine,
+    limits: SharedConnectionLimits,
+}
+
+async fn fixture(config: Config) -> Fixture {
+    let dir = TempDir::new().expect("temp dir");
+    let path = dir.path().join("rustsocks.toml");
+    write_config(&path, &config);
+
+    let qos_engine = QosEngine::from_config(config.qos.clone())
+        .await
+        .expect("qos engine");
+    let limits = SharedConnectionLimits::new(config.qos.connection_limits.clone());
+    let reloader = Arc::new(ConfigReloader::new(
+        Arc::new(config.clone()),
+        Some(path.clone()),
+        Arc::new(AuthManager::new(&config.auth).expect("auth manager")),
+        qos_engine.clone(),
+        limits.clone(),
+        Arc::new(SessionManager::new()),
+    ));
+
+    Fixture {
+        _dir: dir,
+        path,
+        reloader,
+        qos_engine,
+        limits,
+    }
+}
+
+#[tokio::test]
+async fn reload_applies_hot_settings_and_reports_the_rest() {
+    let fixture = fixture(base_config()).await;
+    let levels = Arc::new(Mutex::new(Vec::new()));
+    let seen = levels.clone();
+    fixture
+        .reloader
+        .set_log_level_hook(Box::new(move |level: &str| -> Result<(), String> {
+            seen.lock().unwrap().push(level.to_string());
+            Ok(())
+        }));
+
+    let mut edited = base_config();
+    edited.qos.connection_limits.max_connections_per_user = 5;
+    edited.qos.htb.max_bandwidth_bytes_per_sec = 2_500_000;
+    edited.logging.level = "debug".to_string();
+    edited.server.bind_port += 1;
+    write_config(&fixture.path, &edited);
+
+    let report = fixture.reloader.reload().await.expect("reload");
+    assert_eq!(
+        report.applied,
+        vec![
+            "logging.level",
+            "qos.connection_limits.max_connections_per_user",
+            "qos.htb.max_bandwidth_bytes_per_sec",
+        ]
+    );
+    assert_eq!(report.requires_restart, vec!["server.bind_port"]);
+
+    assert_eq!(fixture.limits.get().max_connections_per_user, 5);
+    assert_eq!(
+        fixture
+            .qos_engine
+            .htb_config()
+            .unwrap()
+            .max_bandwidth_bytes_per_sec,
+        2_500_000
+    );
+    assert_eq!(*levels.lock().unwrap(), vec!["debug".to_string()]);
+
+    let running = fixture.reloader.running();
+    assert_eq!(running.logging.level, "debug");
+    assert_eq!(running.server.bind_port, base_config().server.bind_port);
+
+    // Nothing new to apply the second time; the port still waits for a restart
+    let report = fixture.reloader.reload().await.expect("reload again");
+    assert!(report.applied.is_empty());
+    assert_eq!(report.requires_restart, vec!["server.bind_port"]);
+}
+
+#[tokio::test]
+async fn invalid_file_leaves_the_running_config_untouched() {
+    let fixture = fixture(base_config()).await;
+
+    std::fs::write(&fixture.path, "[qos\nenabled = true").unwrap();
+    assert!(fixture.reloader.reload().await.is_err());
+
+    let mut invalid = base_config();
+    invalid.qos.connection_limits.max_connections_per_user = 5;
+    invalid.auth.socks_method = "userpass".to_string();
+    write_config(&fixture.path, &invalid);
+    assert!(fixture.reloader.reload().await.is_err());
+
+    assert_eq!(fixture.limits.get().max_connections_per_user, 1);
+    assert_eq!(
+        fixture
+            .reloader
+            .running()
+            .qos
+            .connection_limits
+            .max_connections_per_user,
+        1
+    );
+}
+
+fn api_state(reloader: Option<Arc<ConfigReloader>>) -> ApiState {
+    ApiState {
+        session_manager: Arc::new(SessionManager::new()),
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: reloader,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    }
+}
+
+async fn post_reload(state: ApiState) -> (StatusCode, Value) {
+    let app = Router::new()
+        .route("/api/admin/reload-config", post(reload_config))
+        .with_state(state);
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/admin/reload-config")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn reload_config_endpoint_returns_the_report() {
+    let fixture = fixture(base_config()).await;
+    let mut edited = base_config();
+    edited.sessions.batch_size = 250;
+    edited.server.tls.certificate_path = Some("/etc/rustsocks/cert.pem".to_string());
+    write_config(&fixture.path, &edited);
+
+    let (status, body) = post_reload(api_state(Some(fixture.reloader.clone()))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["success"], true);
+    assert_eq!(body["applied"], serde_json::json!(["sessions.batch_size"]));
+    assert_eq!(
+        body["requires_restart"],
+        serde_json::json!(["server.tls.certificate_path"])
+    );
+
+    std::fs::write(&fixture.path, "not toml at all [").unwrap();
+    let (status, body) = post_reload(api_state(Some(fixture.reloader.clone()))).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["success"], false);
+
+    let (status, body) = post_reload(api_state(None)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["success"], false);
+}
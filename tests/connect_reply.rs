@@ -0,0 +1,120 @@
+//! BND.ADDR/BND.PORT of CONNECT replies
+//!
+//! The reply carries the local address of the proxy's upstream socket, which is the
+//! peer address the upstream server sees. Strict clients check the address type.
+mod common;
+
+use rustsocks::server::{handle_client, ClientHandlerContext};
+use rustsocks::session::SessionManager;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+
+fn context() -> Arc<ClientHandlerContext> {
+    Arc::new(common::handler_context(Arc::new(SessionManager::new())))
+}
+
+async fn spawn_proxy() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let ctx = context();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        handle_client(stream, ctx, client_addr).await.ok();
+    });
+    addr
+}
+
+/// Upstream that reports the peer address of its one connection
+async fn spawn_upstream(listener: TcpListener) -> oneshot::Receiver<SocketAddr> {
+    let (tx, rx) = oneshot::channel();
+    tokio::spawn(async move {
+        if let Ok((_stream, peer)) = listener.accept().await {
+            let _ = tx.send(peer);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    });
+    rx
+}
+
+/// CONNECT to `target`, returning the full reply
+async fn connect(proxy: SocketAddr, target: SocketAddr) -> (TcpStream, Vec<u8>) {
+    let mut client = TcpStream::connect(proxy).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    client.read_exact(&mut choice).await.unwrap();
+    assert_eq!(choice, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    match target.ip() {
+        IpAddr::V4(ip) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut head = [0u8; 4];
+    client.read_exact(&mut head).await.unwrap();
+    let addr_len = match head[3] {
+        0x01 => 4,
+        0x04 => 16,
+        other => panic!("unexpected ATYP {:#04x}", other),
+    };
+    let mut rest = vec![0u8; addr_len + 2];
+    client.read_exact(&mut rest).await.unwrap();
+
+    let mut reply = head.to_vec();
+    reply.extend_from_slice(&rest);
+    (client, reply)
+}
+
+#[tokio::test]
+async fn ipv4_upstream_reply_carries_the_bound_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target = listener.local_addr().unwrap();
+    let peer = spawn_upstream(listener).await;
+    let proxy = spawn_proxy().await;
+
+    let (_client, reply) = connect(proxy, target).await;
+    let bound = timeout(Duration::from_secs(2), peer)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut expected = vec![0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1];
+    expected.extend_from_slice(&bound.port().to_be_bytes());
+    assert_eq!(reply, expected);
+    assert_ne!(bound.port(), 0);
+}
+
+#[tokio::test]
+async fn ipv6_upstream_reply_carries_the_bound_address() {
+    let Ok(listener) = TcpListener::bind("[::1]:0").await else {
+        // No IPv6 loopback in this environment
+        return;
+    };
+    let target = listener.local_addr().unwrap();
+    let peer = spawn_upstream(listener).await;
+    let proxy = spawn_proxy().await;
+
+    let (_client, reply) = connect(proxy, target).await;
+    let bound = timeout(Duration::from_secs(2), peer)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut expected = vec![0x05, 0x00, 0x00, 0x04];
+    expected.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+    expected.extend_from_slice(&bound.port().to_be_bytes());
+    assert_eq!(reply, expected);
+}
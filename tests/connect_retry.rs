@@ -0,0 +1,116 @@
+//! Retrying refused upstream connects (`server.connect_retries`)
+//!
+//! The destination starts listening only after the first attempts were refused, as a
+//! service does while it restarts.
+
+mod common;
+
+use rustsocks::server::{
+    handle_client, ClientHandlerContext, ConnectRetry, ConnectionPool, PoolConfig,
+    CONNECT_RETRY_DEADLINE,
+};
+use rustsocks::session::{FailureCategory, SessionManager, SessionStatus};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout, Duration};
+
+fn handler_context(
+    session_manager: Arc<SessionManager>,
+    connect_retry: ConnectRetry,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig {
+            connect_timeout_ms: 500,
+            ..PoolConfig::default()
+        })),
+        connect_retry,
+        ..common::handler_context(session_manager)
+    })
+}
+
+/// A loopback port nothing listens on (yet)
+async fn free_port() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind probe");
+    listener.local_addr().expect("probe addr")
+}
+
+/// CONNECT to `target` through a fresh handler; returns the reply code and the stream.
+async fn connect(ctx: Arc<ClientHandlerContext>, target: SocketAddr) -> (u8, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x01];
+    request.extend_from_slice(&[127, 0, 0, 1]);
+    request.extend_from_slice(&target.port().to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    timeout(
+        CONNECT_RETRY_DEADLINE + Duration::from_secs(1),
+        client.read_exact(&mut reply),
+    )
+    .await
+    .expect("reply in time")
+    .unwrap();
+    (reply[1], client)
+}
+
+#[tokio::test]
+async fn refused_connect_succeeds_once_the_destination_is_back() {
+    let target = free_port().await;
+    tokio::spawn(async move {
+        sleep(Duration::from_millis(300)).await;
+        let listener = TcpListener::bind(target).await.expect("bind upstream");
+        let (mut stream, _) = listener.accept().await.expect("accept upstream");
+        let (mut reader, mut writer) = stream.split();
+        let _ = tokio::io::copy(&mut reader, &mut writer).await;
+    });
+
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(
+        session_manager.clone(),
+        ConnectRetry {
+            retries: 6,
+            backoff: Duration::from_millis(100),
+            ..ConnectRetry::default()
+        },
+    );
+    let (reply, mut client) = connect(ctx, target).await;
+    assert_eq!(reply, 0x00, "retried connect should succeed");
+
+    client.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    client.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo, b"ping");
+
+    // One session for the request, however many attempts it took
+    assert_eq!(session_manager.get_all_sessions().await.len(), 1);
+}
+
+#[tokio::test]
+async fn refused_connect_fails_at_once_without_retries() {
+    let target = free_port().await;
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(session_manager.clone(), ConnectRetry::default());
+
+    let started = std::time::Instant::now();
+    let (reply, _client) = connect(ctx, target).await;
+    assert_eq!(reply, 0x04, "refused connect answers host unreachable");
+    assert!(started.elapsed() < Duration::from_millis(500));
+    // Recorded as one failed session, never as an active one
+    let sessions = session_manager.get_all_sessions().await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].status, SessionStatus::Failed);
+    assert_eq!(sessions[0].failure, Some(FailureCategory::ConnectRefused));
+}
@@ -4,7 +4,7 @@
 use rustsocks::acl::AclStats;
 use rustsocks::auth::AuthManager;
 use rustsocks::config::AuthConfig;
-use rustsocks::qos::{ConnectionLimits, QosEngine};
+use rustsocks::qos::QosEngine;
 use rustsocks::server::{
     handle_client, ClientHandlerContext, ConnectionPool, PoolConfig, TrafficUpdateConfig,
 };
@@ -19,13 +19,23 @@ async fn connection_pool_reuses_upstream_connections() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     // Enable connection pooling
@@ -46,8 +56,25 @@ async fn connection_pool_reuses_upstream_connections() {
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: connection_pool.clone(),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     // Start SOCKS5 server
@@ -0,0 +1,62 @@
+//! Per-client-IP connection rate limit (`server.rate_limit`)
+mod common;
+
+use rustsocks::config::RateLimitSettings;
+use rustsocks::server::{accept_loop, AcceptOptions, ConnectionRateLimiter};
+use rustsocks::session::SessionManager;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+
+async fn spawn_proxy(limiter: Arc<ConnectionRateLimiter>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let addr = listener.local_addr().expect("proxy addr");
+    let ctx = Arc::new(common::handler_context(Arc::new(SessionManager::new())));
+    tokio::spawn(accept_loop(
+        listener,
+        ctx,
+        AcceptOptions {
+            rate_limiter: Some(limiter),
+            ..AcceptOptions::default()
+        },
+    ));
+    addr
+}
+
+/// Send a SOCKS5 greeting; `Some(method)` when the server answered, `None` when it
+/// closed the connection instead.
+async fn greet(proxy: SocketAddr) -> Option<u8> {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    // The server may already have closed the socket
+    let _ = stream.write_all(&[0x05, 0x01, 0x00]).await;
+    let mut choice = [0u8; 2];
+    match timeout(Duration::from_secs(5), stream.read_exact(&mut choice))
+        .await
+        .expect("answer or close in time")
+    {
+        Ok(_) => Some(choice[1]),
+        Err(_) => None,
+    }
+}
+
+#[tokio::test]
+async fn connections_over_the_limit_are_closed_before_the_handshake() {
+    let limiter = Arc::new(
+        ConnectionRateLimiter::new(&RateLimitSettings {
+            connections_per_ip_per_minute: 2,
+            ..RateLimitSettings::default()
+        })
+        .expect("limit configured"),
+    );
+    let proxy = spawn_proxy(limiter.clone()).await;
+
+    assert_eq!(greet(proxy).await, Some(0x00));
+    assert_eq!(greet(proxy).await, Some(0x00));
+    assert_eq!(greet(proxy).await, None);
+    assert_eq!(greet(proxy).await, None);
+
+    assert_eq!(limiter.rejected_connections(), 2);
+    assert_eq!(limiter.tracked_clients(), 1);
+}
@@ -0,0 +1,184 @@
+//! Connectivity probes (`[[diagnostics.probes]]`) and `GET /api/diagnostics/probes`
+mod common;
+
+use axum::{body::Body, http::Request, http::StatusCode, routing::get, Router};
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
+use rustsocks::api::handlers::get_probes;
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::config::{Config, DiagnosticsSettings, ProbeTarget};
+use rustsocks::qos::QosEngine;
+use rustsocks::server::{ClientHandlerContext, ConnectionPool, PoolConfig, ProbeMonitor};
+use rustsocks::session::{FailureCategory, SessionManager};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tower::util::ServiceExt;
+
+/// Default allow; the probe user may not reach blocked.example
+fn acl_config() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Allow,
+        },
+        users: vec![UserAcl {
+            username: "probe".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Block,
+                description: "No probes to blocked.example".to_string(),
+                destinations: vec!["blocked.example".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 1000,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+        groups: vec![],
+    }
+}
+
+fn handler_context(session_manager: Arc<SessionManager>) -> ClientHandlerContext {
+    ClientHandlerContext {
+        acl_engine: Some(Arc::new(AclEngine::new(acl_config()).expect("acl engine"))),
+        ..common::handler_context(session_manager)
+    }
+}
+
+fn target(name: &str, host: &str, port: u16) -> ProbeTarget {
+    ProbeTarget {
+        name: Some(name.to_string()),
+        host: host.to_string(),
+        port,
+        interval_secs: 30,
+    }
+}
+
+fn create_api_state(
+    session_manager: Arc<SessionManager>,
+    probe_monitor: Arc<ProbeMonitor>,
+) -> ApiState {
+    ApiState {
+        session_manager,
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: Some(probe_monitor),
+        upstream_proxy: None,
+    }
+}
+
+#[tokio::test]
+async fn probes_record_success_refusal_and_acl_block() {
+    let reachable = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let reachable_port = reachable.local_addr().unwrap().port();
+    let closed_port = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    };
+
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(session_manager.clone());
+    let monitor = Arc::new(ProbeMonitor::from_settings(&DiagnosticsSettings {
+        probes: vec![
+            target("up", "127.0.0.1", reachable_port),
+            target("refused", "127.0.0.1", closed_port),
+            target("blocked", "blocked.example", 443),
+        ],
+        ..Default::default()
+    }));
+
+    let up = monitor.check(0, &ctx).await;
+    assert!(up.success, "{:?}", up.error);
+    assert_eq!(
+        up.connected_addr.map(|addr| addr.port()),
+        Some(reachable_port)
+    );
+    let refused = monitor.check(1, &ctx).await;
+    assert!(!refused.success);
+    assert_eq!(refused.category, Some(FailureCategory::ConnectRefused));
+    let blocked = monitor.check(2, &ctx).await;
+    assert!(!blocked.success);
+    assert_eq!(blocked.category, Some(FailureCategory::AclBlock));
+    assert!(blocked.acl_rule.is_some());
+
+    // Probes leave no sessions behind; the ACL counts them under the probe user
+    assert!(session_manager.get_active_sessions().await.is_empty());
+    assert!(session_manager.get_closed_sessions().await.is_empty());
+    let acl = ctx
+        .acl_stats
+        .user_snapshot("probe")
+        .expect("probe user stats");
+    assert_eq!((acl.allowed, acl.blocked), (2, 1));
+
+    let app = Router::new()
+        .route("/api/diagnostics/probes", get(get_probes))
+        .with_state(create_api_state(session_manager, monitor));
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/diagnostics/probes")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(json["user"], "probe");
+    let probes = json["probes"].as_array().unwrap();
+    assert_eq!(probes.len(), 3);
+    assert_eq!(probes[0]["name"], "up");
+    assert_eq!(probes[0]["up"], true);
+    assert_eq!(probes[1]["up"], false);
+    assert_eq!(probes[1]["consecutive_failures"], 1);
+    assert_eq!(probes[1]["history"][0]["category"], "connect_refused");
+    assert_eq!(probes[2]["history"][0]["category"], "acl_block");
+}
+
+#[tokio::test]
+async fn probes_endpoint_needs_a_running_server() {
+    let session_manager = Arc::new(SessionManager::new());
+    let mut state = create_api_state(
+        session_manager,
+        Arc::new(ProbeMonitor::from_settings(&DiagnosticsSettings::default())),
+    );
+    state.probe_monitor = None;
+    let app = Router::new()
+        .route("/api/diagnostics/probes", get(get_probes))
+        .with_state(state);
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/diagnostics/probes")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,270 @@
+/// Integration tests for correlation IDs carried in the login name
+/// (`auth.allow_correlation_suffix`)
+///
+/// A JSON subscriber captures the server's log output, so the tests can check that the
+/// ID reaches the request log and the access log as well as the session.
+mod common;
+
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
+use rustsocks::auth::{AuthManager, MAX_CORRELATION_ID_LEN};
+use rustsocks::config::{AuthConfig, ImpersonationSettings, User};
+use rustsocks::protocol::ReplyCode;
+use rustsocks::server::{handle_client, ClientHandlerContext};
+use rustsocks::session::{Session, SessionManager};
+use serde_json::Value;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Duration;
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    /// Fields of every captured record whose message is `message`
+    fn records(&self, message: &str) -> Vec<Value> {
+        let bytes = self.0.lock().unwrap();
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .map(|record| record["fields"].clone())
+            .filter(|fields| fields["message"] == message)
+            .collect()
+    }
+}
+
+fn capture() -> (Captured, tracing::subscriber::DefaultGuard) {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_max_level(tracing::Level::INFO)
+        .with_writer(move || writer.clone())
+        .finish();
+    (captured, tracing::subscriber::set_default(subscriber))
+}
+
+fn user(username: &str, password: &str) -> User {
+    User {
+        username: username.to_string(),
+        password: password.to_string().into(),
+        password_hash: None,
+    }
+}
+
+fn auth_config(allow_correlation_suffix: bool) -> AuthConfig {
+    AuthConfig {
+        socks_method: "userpass".to_string(),
+        socks_method_preference: Vec::new(),
+        users: vec![
+            user("alice", "alice-secret"),
+            user("svc-report", "svc-secret"),
+        ],
+        impersonation: ImpersonationSettings {
+            allowed_principals: vec!["svc-report".to_string()],
+            allowed_target_pattern: "*".to_string(),
+            separator: ":".to_string(),
+        },
+        allow_correlation_suffix,
+        correlation_separator: "#".to_string(),
+        ..AuthConfig::default()
+    }
+}
+
+/// Only alice may reach the loopback upstream, so a successful CONNECT proves the ACL
+/// saw the stripped name.
+fn acl_config() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![UserAcl {
+            username: "alice".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Allow,
+                description: "alice may reach loopback".to_string(),
+                destinations: vec!["127.0.0.1".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+        groups: vec![],
+    }
+}
+
+fn handler_context(
+    session_manager: Arc<SessionManager>,
+    allow_correlation_suffix: bool,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(
+            AuthManager::new(&auth_config(allow_correlation_suffix)).expect("auth manager"),
+        ),
+        acl_engine: Some(Arc::new(AclEngine::new(acl_config()).expect("acl engine"))),
+        ..common::handler_context(session_manager)
+    })
+}
+
+async fn spawn_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            drop(stream);
+        }
+    });
+    addr
+}
+
+/// Log in with `login`/`password` and CONNECT to a loopback upstream; returns the
+/// SOCKS reply code, or `None` when authentication was rejected.
+async fn login_and_connect(
+    ctx: Arc<ClientHandlerContext>,
+    login: &str,
+    password: &str,
+) -> Option<u8> {
+    let upstream = spawn_upstream().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+    let server_task = tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        handle_client(stream, ctx, client_addr).await
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x02]);
+
+    let mut auth = vec![0x01, login.len() as u8];
+    auth.extend_from_slice(login.as_bytes());
+    auth.push(password.len() as u8);
+    auth.extend_from_slice(password.as_bytes());
+    client.write_all(&auth).await.unwrap();
+    let mut status = [0u8; 2];
+    client.read_exact(&mut status).await.unwrap();
+
+    let reply = if status[1] != 0x00 {
+        None
+    } else {
+        let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+        request.extend_from_slice(&upstream.port().to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        Some(reply[1])
+    };
+    drop(client);
+
+    let _ = tokio::time::timeout(Duration::from_secs(5), server_task)
+        .await
+        .expect("handler finished");
+    reply
+}
+
+async fn closed_session(session_manager: &SessionManager) -> Session {
+    session_manager
+        .closed_snapshot()
+        .await
+        .pop()
+        .expect("closed session")
+}
+
+#[tokio::test]
+async fn suffix_is_stripped_and_recorded() {
+    let (captured, _guard) = capture();
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(session_manager.clone(), true);
+
+    let reply = login_and_connect(ctx, "alice#wf-12345", "alice-secret").await;
+    assert_eq!(reply, Some(ReplyCode::Succeeded as u8));
+
+    let session = closed_session(&session_manager).await;
+    assert_eq!(session.user.as_ref(), "alice");
+    assert_eq!(session.authenticated_user.as_deref(), Some("alice"));
+    assert_eq!(session.correlation_id.as_deref(), Some("wf-12345"));
+
+    let requests = captured.records("SOCKS5 request");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["user"], "alice");
+    assert_eq!(requests[0]["correlation_id"], "wf-12345");
+
+    let decisions = captured.records("ACL decision");
+    assert_eq!(decisions.len(), 1);
+    assert_eq!(decisions[0]["outcome"], "allowed");
+    assert_eq!(decisions[0]["correlation_id"], "wf-12345");
+}
+
+#[tokio::test]
+async fn suffix_composes_with_impersonation() {
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(session_manager.clone(), true);
+
+    let reply = login_and_connect(ctx, "svc-report:alice#wf-7", "svc-secret").await;
+    assert_eq!(reply, Some(ReplyCode::Succeeded as u8));
+
+    let session = closed_session(&session_manager).await;
+    assert_eq!(session.user.as_ref(), "alice");
+    assert_eq!(session.authenticated_user.as_deref(), Some("svc-report"));
+    assert_eq!(session.correlation_id.as_deref(), Some("wf-7"));
+}
+
+#[tokio::test]
+async fn invalid_ids_are_dropped_without_failing_authentication() {
+    let (captured, _guard) = capture();
+    let overlong = format!("alice#{}", "a".repeat(MAX_CORRELATION_ID_LEN + 1));
+
+    for login in ["alice#wf 1", "alice#wf/1", "alice#", overlong.as_str()] {
+        let session_manager = Arc::new(SessionManager::new());
+        let ctx = handler_context(session_manager.clone(), true);
+
+        let reply = login_and_connect(ctx, login, "alice-secret").await;
+        assert_eq!(reply, Some(ReplyCode::Succeeded as u8), "{}", login);
+
+        let session = closed_session(&session_manager).await;
+        assert_eq!(session.user.as_ref(), "alice");
+        assert!(session.correlation_id.is_none(), "{}", login);
+    }
+
+    let dropped = captured.records("Ignoring invalid correlation ID");
+    assert_eq!(dropped.len(), 4);
+    assert!(dropped.iter().all(|fields| fields["user"] == "alice"));
+    assert!(captured
+        .records("SOCKS5 request")
+        .iter()
+        .all(|fields| fields["correlation_id"] == "-"));
+}
+
+#[tokio::test]
+async fn suffix_is_part_of_the_name_when_disabled() {
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(session_manager.clone(), false);
+
+    let reply = login_and_connect(ctx, "alice#wf-12345", "alice-secret").await;
+    assert_eq!(reply, None);
+    assert!(session_manager.closed_snapshot().await.is_empty());
+}
@@ -0,0 +1,145 @@
+//! `server.dns.allow_domain_requests = false`: requests for names are refused with
+//! reply 0x08 before anything is resolved, IP requests are served as before
+mod common;
+
+use futures::future::BoxFuture;
+use rustsocks::protocol::{Address, ReplyCode};
+use rustsocks::server::{handle_client, ClientHandlerContext, DestinationResolver};
+use rustsocks::session::SessionManager;
+use rustsocks::utils::error::Result;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Resolver that records every lookup; none is expected to happen.
+#[derive(Default)]
+struct RecordingResolver {
+    lookups: Mutex<Vec<String>>,
+}
+
+impl DestinationResolver for RecordingResolver {
+    fn resolve<'a>(
+        &'a self,
+        address: &'a Address,
+        port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        self.lookups.lock().unwrap().push(address.to_string());
+        Box::pin(async move { Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))]) })
+    }
+}
+
+fn handler_context(
+    resolver: Arc<RecordingResolver>,
+    session_manager: Arc<SessionManager>,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        resolver,
+        allow_domain_requests: false,
+        ..common::handler_context(session_manager)
+    })
+}
+
+/// Send `request` (after a no-auth greeting) to a fresh handler; the client stream and
+/// the reply code.
+async fn send_request(ctx: Arc<ClientHandlerContext>, request: &[u8]) -> (TcpStream, u8) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    client.read_exact(&mut choice).await.unwrap();
+    assert_eq!(choice, [0x05, 0x00]);
+
+    client.write_all(request).await.unwrap();
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[0], 0x05);
+    (client, reply[1])
+}
+
+fn domain_request(command: u8, domain: &str, port: u16) -> Vec<u8> {
+    let mut request = vec![0x05, command, 0x00, 0x03, domain.len() as u8];
+    request.extend_from_slice(domain.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    request
+}
+
+async fn echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 64];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+async fn assert_echoes(client: &mut TcpStream) {
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn domain_requests_are_refused_before_resolution() {
+    let resolver = Arc::new(RecordingResolver::default());
+    let session_manager = Arc::new(SessionManager::new());
+
+    // CONNECT 0x01, UDP ASSOCIATE 0x03
+    for command in [0x01, 0x03] {
+        let ctx = handler_context(resolver.clone(), session_manager.clone());
+        let (_client, reply) =
+            send_request(ctx, &domain_request(command, "example.com", 443)).await;
+        assert_eq!(reply, ReplyCode::AddressTypeNotSupported as u8);
+    }
+
+    assert!(resolver.lookups.lock().unwrap().is_empty());
+    let rejected = session_manager.rejected_snapshot().await;
+    assert_eq!(rejected.len(), 2);
+    assert!(rejected.iter().all(|session| {
+        session.dest_ip.as_ref() == "example.com"
+            && session.acl_rule_matched.as_deref() == Some("domain-requests-disabled")
+    }));
+}
+
+#[tokio::test]
+async fn ip_requests_are_served() {
+    let echo = echo_server().await;
+    let resolver = Arc::new(RecordingResolver::default());
+    let session_manager = Arc::new(SessionManager::new());
+    let port = echo.port().to_be_bytes();
+
+    let ctx = handler_context(resolver.clone(), session_manager.clone());
+    let (mut client, reply) = send_request(
+        ctx,
+        &[0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1, port[0], port[1]],
+    )
+    .await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    assert_echoes(&mut client).await;
+
+    // An IP literal sent as a name needs no lookup either
+    let ctx = handler_context(resolver.clone(), session_manager.clone());
+    let (mut client, reply) =
+        send_request(ctx, &domain_request(0x01, "127.0.0.1", echo.port())).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    assert_echoes(&mut client).await;
+
+    assert!(resolver.lookups.lock().unwrap().is_empty());
+    assert!(session_manager.rejected_snapshot().await.is_empty());
+}
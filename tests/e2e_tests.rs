@@ -9,12 +9,12 @@
 /// 6. BIND Command - Reverse connections
 ///
 /// These tests ensure that all components work together correctly in real-world scenarios.
-use rustsocks::acl::types::{AclRule, GlobalAclConfig, UserAcl};
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
 use rustsocks::acl::{AclConfig, AclEngine, AclStats, Action, Protocol};
 use rustsocks::auth::AuthManager;
 use rustsocks::config::{AuthConfig, User};
 use rustsocks::protocol::ReplyCode;
-use rustsocks::qos::{ConnectionLimits, QosEngine};
+use rustsocks::qos::QosEngine;
 use rustsocks::server::proxy::TrafficUpdateConfig;
 use rustsocks::server::{handle_client, ClientHandlerContext, ConnectionPool, PoolConfig};
 use rustsocks::session::{SessionManager, SessionStatus};
@@ -35,7 +35,7 @@ async fn create_basic_server_context(
 ) -> (Arc<ClientHandlerContext>, Arc<SessionManager>) {
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let acl_engine = acl_config.map(|config| Arc::new(AclEngine::new(config).unwrap()));
@@ -51,8 +51,25 @@ async fn create_basic_server_context(
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: connection_pool.clone(),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     (ctx, session_manager)
@@ -203,9 +220,19 @@ async fn e2e_basic_connect() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let (ctx, session_manager) = create_basic_server_context(auth_config, None).await;
@@ -248,9 +275,19 @@ async fn e2e_auth_noauth() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let (ctx, _) = create_basic_server_context(auth_config, None).await;
@@ -276,12 +313,23 @@ async fn e2e_auth_userpass() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "userpass".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![User {
             username: "alice".to_string(),
-            password: "secret123".to_string(),
+            password: "secret123".to_string().into(),
+            password_hash: None,
         }],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let (ctx, _) = create_basic_server_context(auth_config, None).await;
@@ -307,12 +355,23 @@ async fn e2e_auth_userpass_invalid() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "userpass".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![User {
             username: "alice".to_string(),
-            password: "secret123".to_string(),
+            password: "secret123".to_string().into(),
+            password_hash: None,
         }],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let (ctx, _) = create_basic_server_context(auth_config, None).await;
@@ -337,9 +396,19 @@ async fn e2e_acl_allow() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     // ACL config that allows all
@@ -373,9 +442,19 @@ async fn e2e_acl_block() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     // ACL config that blocks the echo server
@@ -391,8 +470,13 @@ async fn e2e_acl_block() {
                 description: "Block test server".to_string(),
                 destinations: vec![echo_addr.ip().to_string()],
                 ports: vec![echo_addr.port().to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Tcp],
                 priority: 1000,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             }],
         }],
         groups: vec![],
@@ -429,9 +513,19 @@ async fn e2e_session_tracking() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let (ctx, session_manager) = create_basic_server_context(auth_config, None).await;
@@ -494,9 +588,19 @@ async fn e2e_udp_associate() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let (ctx, session_manager) = create_basic_server_context(auth_config, None).await;
@@ -549,9 +653,19 @@ async fn e2e_bind_command() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let (ctx, _session_manager) = create_basic_server_context(auth_config, None).await;
@@ -609,12 +723,23 @@ async fn e2e_complete_flow() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "userpass".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![User {
             username: "testuser".to_string(),
-            password: "testpass".to_string(),
+            password: "testpass".to_string().into(),
+            password_hash: None,
         }],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let acl_config = AclConfig {
@@ -629,8 +754,13 @@ async fn e2e_complete_flow() {
                 description: "Allow all for testuser".to_string(),
                 destinations: vec!["*".to_string()],
                 ports: vec!["*".to_string()],
+                sources: vec![],
                 protocols: vec![Protocol::Both],
                 priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
             }],
         }],
         groups: vec![],
@@ -0,0 +1,153 @@
+//! Source addresses by user (`[server.egress]`)
+//!
+//! Everything runs on loopback: the proxy binds 127.0.0.2 for users with a rule, and
+//! the upstream servers on 127.0.0.1 see which address each connection came from.
+mod common;
+
+use rustsocks::config::{EgressRuleSettings, EgressSettings};
+use rustsocks::server::{handle_client, ClientHandlerContext, EgressMap};
+use rustsocks::session::SessionManager;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+const EGRESS: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+
+fn context(user: &str) -> Arc<ClientHandlerContext> {
+    let egress = EgressMap::from_settings(&EgressSettings {
+        rules: vec![EgressRuleSettings {
+            users: vec!["anonymous".to_string()],
+            groups: Vec::new(),
+            addresses: vec![EGRESS.to_string()],
+        }],
+    })
+    .expect("loopback addresses are local");
+
+    Arc::new(ClientHandlerContext {
+        anonymous_user: Arc::<str>::from(user),
+        egress: Arc::new(egress),
+        ..common::handler_context(Arc::new(SessionManager::new()))
+    })
+}
+
+/// Proxy serving one client with `ctx`
+async fn spawn_proxy(ctx: Arc<ClientHandlerContext>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        handle_client(stream, ctx, client_addr).await.ok();
+    });
+    addr
+}
+
+/// Upstream that reports the source address of every connection
+async fn spawn_upstream() -> (SocketAddr, mpsc::UnboundedReceiver<IpAddr>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((_stream, peer)) = listener.accept().await {
+            let _ = tx.send(peer.ip());
+        }
+    });
+    (addr, rx)
+}
+
+async fn socks5_connect(proxy: SocketAddr, target: SocketAddr) -> (TcpStream, [u8; 10]) {
+    let mut client = TcpStream::connect(proxy).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    client.read_exact(&mut choice).await.unwrap();
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.port().to_be_bytes());
+    client.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    (client, reply)
+}
+
+#[tokio::test]
+async fn connect_leaves_from_the_users_egress_address() {
+    let (upstream, mut peers) = spawn_upstream().await;
+    let ctx = context("anonymous");
+    let proxy = spawn_proxy(ctx.clone()).await;
+
+    let (_client, reply) = socks5_connect(proxy, upstream).await;
+    assert_eq!(reply[1], 0x00);
+    // BND.ADDR is the bound egress address
+    assert_eq!(&reply[4..8], &EGRESS.octets());
+
+    let peer = timeout(Duration::from_secs(2), peers.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(peer, IpAddr::V4(EGRESS));
+
+    let sessions = ctx.session_manager.get_active_sessions().await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].egress_ip, Some(IpAddr::V4(EGRESS)));
+}
+
+#[tokio::test]
+async fn users_without_a_rule_keep_the_default_address() {
+    let (upstream, mut peers) = spawn_upstream().await;
+    let ctx = context("guest");
+    let proxy = spawn_proxy(ctx.clone()).await;
+
+    let (_client, reply) = socks5_connect(proxy, upstream).await;
+    assert_eq!(reply[1], 0x00);
+
+    let peer = timeout(Duration::from_secs(2), peers.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(peer, IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    let sessions = ctx.session_manager.get_active_sessions().await;
+    assert_eq!(sessions[0].egress_ip, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+}
+
+#[tokio::test]
+async fn udp_relay_binds_the_egress_address() {
+    let echo = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let echo_addr = echo.local_addr().unwrap();
+    let ctx = context("anonymous");
+    let proxy = spawn_proxy(ctx.clone()).await;
+
+    // The association accepts datagrams from the endpoint the request names
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut control = TcpStream::connect(proxy).await.unwrap();
+    control.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    control.read_exact(&mut choice).await.unwrap();
+    let mut request = vec![0x05, 0x03, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&client.local_addr().unwrap().port().to_be_bytes());
+    control.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    control.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    assert_eq!(&reply[4..8], &EGRESS.octets());
+    let relay = SocketAddr::from((EGRESS, u16::from_be_bytes([reply[8], reply[9]])));
+
+    let mut datagram = vec![0x00, 0x00, 0x00, 0x01, 127, 0, 0, 1];
+    datagram.extend_from_slice(&echo_addr.port().to_be_bytes());
+    datagram.extend_from_slice(b"ping");
+    client.send_to(&datagram, relay).await.unwrap();
+
+    let mut buf = [0u8; 64];
+    let (len, peer) = timeout(Duration::from_secs(2), echo.recv_from(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&buf[..len], b"ping");
+    assert_eq!(peer.ip(), IpAddr::V4(EGRESS));
+
+    let sessions = ctx.session_manager.get_active_sessions().await;
+    assert_eq!(sessions[0].egress_ip, Some(IpAddr::V4(EGRESS)));
+}
@@ -0,0 +1,400 @@
+//! Allocation budget for the SOCKS negotiation path.
+//!
+//! A counting global allocator records allocations made by the current thread while a
+//! measurement is active. Measured futures are polled exactly once with `now_or_never`, so
+//! the runtime never gets a chance to run (and allocate) in between.
+
+mod common;
+
+use futures::FutureExt;
+use rustsocks::auth::AuthManager;
+use rustsocks::config::{AuthConfig, PamSettings};
+use rustsocks::protocol::{
+    parse_socks5_client_greeting, parse_socks5_request, parse_userpass_auth, send_server_choice,
+    send_socks5_response, Address, AuthMethod, ReplyCode,
+};
+use rustsocks::server::{handle_client, ClientHandlerContext, SpecialNamesPolicy};
+use rustsocks::session::SessionManager;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::future::Future;
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, BufReader, ReadBuf};
+use tokio::runtime::Runtime;
+
+/// Greeting, method choice, request parsing and reply for an IP-literal CONNECT.
+const NEGOTIATION_BUDGET_IP: usize = 1;
+/// Same as above plus the owned domain name.
+const NEGOTIATION_BUDGET_DOMAIN: usize = 2;
+/// Username and password strings handed to the authenticator.
+const USERPASS_BUDGET: usize = 2;
+/// Full handler run up to a pre-resolution rejection, including the rejected-session record,
+/// its failure category and the boxed authenticator futures.
+const REJECTED_HANDSHAKE_BUDGET: usize = 14;
+/// Full handler run for a successful CONNECT: negotiation, boxed authenticator futures,
+/// upstream connect, session tracking, relay of a short payload and session close, including
+/// runtime bookkeeping.
+const SUCCESSFUL_CONNECT_BUDGET: usize = 15;
+
+struct CountingAllocator;
+
+thread_local! {
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_allocation() {
+    let tracking = TRACKING.try_with(Cell::get).unwrap_or(false);
+    if tracking {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Run `future` to completion in a single poll and return its output with the number of
+/// allocations it made on this thread.
+fn measure<F: Future>(future: F) -> (F::Output, usize) {
+    ALLOCATIONS.with(|count| count.set(0));
+    TRACKING.with(|tracking| tracking.set(true));
+    let output = future.now_or_never();
+    TRACKING.with(|tracking| tracking.set(false));
+
+    let output = output.expect("handshake must complete without waiting on the runtime");
+    (output, ALLOCATIONS.with(Cell::get))
+}
+
+/// Like [`measure`], but drives `future` on a current-thread runtime so it may wait on real
+/// sockets. Tasks it spawns run on this thread too and are counted.
+fn measure_on_runtime<F: Future>(runtime: &Runtime, future: F) -> (F::Output, usize) {
+    ALLOCATIONS.with(|count| count.set(0));
+    TRACKING.with(|tracking| tracking.set(true));
+    let output = runtime.block_on(future);
+    TRACKING.with(|tracking| tracking.set(false));
+
+    (output, ALLOCATIONS.with(Cell::get))
+}
+
+/// In-memory client: serves a fixed script and captures what the server writes back.
+struct ScriptedStream {
+    input: &'static [u8],
+    position: usize,
+    /// Offsets a single read never crosses, so each protocol step arrives separately
+    segment_ends: &'static [usize],
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl ScriptedStream {
+    fn new(input: &'static [u8]) -> (Self, Arc<Mutex<Vec<u8>>>) {
+        Self::segmented(input, &[])
+    }
+
+    fn segmented(
+        input: &'static [u8],
+        segment_ends: &'static [usize],
+    ) -> (Self, Arc<Mutex<Vec<u8>>>) {
+        let output = Arc::new(Mutex::new(Vec::with_capacity(256)));
+        let stream = Self {
+            input,
+            position: 0,
+            segment_ends,
+            output: output.clone(),
+        };
+        (stream, output)
+    }
+}
+
+impl AsyncRead for ScriptedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let end = self
+            .segment_ends
+            .iter()
+            .copied()
+            .find(|&end| end > self.position)
+            .unwrap_or(self.input.len());
+        let remaining = &self.input[self.position..end];
+        let len = remaining.len().min(buf.remaining());
+        buf.put_slice(&remaining[..len]);
+        self.position += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ScriptedStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut output = self.output.lock().unwrap();
+        // Never grow the capture buffer while a measurement may be running
+        let len = buf.len().min(output.capacity() - output.len());
+        output.extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Mirror of the handler's pre-relay protocol steps.
+async fn negotiate(stream: ScriptedStream) -> Address {
+    let mut buffered = BufReader::with_capacity(4096, stream);
+    let version = {
+        let mut byte = [0u8; 1];
+        tokio::io::AsyncReadExt::read_exact(&mut buffered, &mut byte)
+            .await
+            .unwrap();
+        byte[0]
+    };
+    let greeting = parse_socks5_client_greeting(&mut buffered, version)
+        .await
+        .unwrap();
+    assert!(greeting.methods.contains(&AuthMethod::NoAuth));
+    send_server_choice(buffered.get_mut(), AuthMethod::NoAuth)
+        .await
+        .unwrap();
+
+    let request = parse_socks5_request(&mut buffered).await.unwrap();
+    send_socks5_response(
+        buffered.get_mut(),
+        ReplyCode::Succeeded,
+        Address::IPv4([10, 0, 0, 1]),
+        40000,
+    )
+    .await
+    .unwrap();
+
+    request.address
+}
+
+const IP_CONNECT: &[u8] = &[
+    0x05, 0x02, 0x00, 0x02, // greeting: no-auth, userpass
+    0x05, 0x01, 0x00, 0x01, 93, 184, 216, 34, 0x01, 0xBB, // CONNECT 93.184.216.34:443
+];
+
+const DOMAIN_CONNECT: &[u8] = &[
+    0x05, 0x01, 0x00, // greeting: no-auth
+    0x05, 0x01, 0x00, 0x03, 11, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+    0x01, 0xBB, // CONNECT example.com:443
+];
+
+const ONION_CONNECT: &[u8] = &[
+    0x05, 0x01, 0x00, // greeting: no-auth
+    0x05, 0x01, 0x00, 0x03, 12, b'h', b'i', b'd', b'd', b'e', b'n', b'.', b'o', b'n', b'i', b'o',
+    b'n', 0x00, 0x50, // CONNECT hidden.onion:80
+];
+
+#[test]
+fn socks5_negotiation_stays_within_budget() {
+    // Warm up lazily initialised statics (tracing callsites and the like)
+    let (warmup, _) = ScriptedStream::new(DOMAIN_CONNECT);
+    measure(negotiate(warmup));
+
+    let (stream, output) = ScriptedStream::new(IP_CONNECT);
+    let (address, allocations) = measure(negotiate(stream));
+    assert_eq!(address, Address::IPv4([93, 184, 216, 34]));
+    assert_eq!(&output.lock().unwrap()[..2], &[0x05, 0x00]);
+    assert!(
+        allocations <= NEGOTIATION_BUDGET_IP,
+        "IP CONNECT negotiation made {} allocations (budget {})",
+        allocations,
+        NEGOTIATION_BUDGET_IP
+    );
+
+    let (stream, _) = ScriptedStream::new(DOMAIN_CONNECT);
+    let (address, allocations) = measure(negotiate(stream));
+    assert_eq!(address, Address::Domain("example.com".into()));
+    assert!(
+        allocations <= NEGOTIATION_BUDGET_DOMAIN,
+        "domain CONNECT negotiation made {} allocations (budget {})",
+        allocations,
+        NEGOTIATION_BUDGET_DOMAIN
+    );
+}
+
+#[test]
+fn userpass_parsing_stays_within_budget() {
+    const USERPASS: &[u8] = &[
+        0x01, 5, b'a', b'l', b'i', b'c', b'e', 6, b's', b'e', b'c', b'r', b'e', b't',
+    ];
+
+    let (warmup, _) = ScriptedStream::new(USERPASS);
+    measure(async move {
+        let mut stream = warmup;
+        parse_userpass_auth(&mut stream).await.unwrap()
+    });
+
+    let (stream, _) = ScriptedStream::new(USERPASS);
+    let ((username, password), allocations) = measure(async move {
+        let mut stream = stream;
+        parse_userpass_auth(&mut stream).await.unwrap()
+    });
+    assert_eq!(username, "alice");
+    assert_eq!(password.as_str(), "secret");
+    assert!(
+        allocations <= USERPASS_BUDGET,
+        "userpass parsing made {} allocations (budget {})",
+        allocations,
+        USERPASS_BUDGET
+    );
+}
+
+fn handler_context(
+    session_manager: Arc<SessionManager>,
+    special_names: SpecialNamesPolicy,
+) -> Arc<ClientHandlerContext> {
+    let auth_manager = Arc::new(
+        AuthManager::new(&AuthConfig {
+            client_method: "none".into(),
+            socks_method: "none".into(),
+            socks_method_preference: Vec::new(),
+            users: Vec::new(),
+            users_file: None,
+            pam: PamSettings::default(),
+            gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
+        })
+        .expect("auth manager"),
+    );
+    Arc::new(ClientHandlerContext {
+        auth_manager,
+        special_names,
+        ..common::handler_context(session_manager)
+    })
+}
+
+#[tokio::test]
+async fn rejected_handshake_stays_within_budget() {
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(session_manager.clone(), SpecialNamesPolicy::default());
+    let client_addr = "127.0.0.1:40000".parse().unwrap();
+
+    let (warmup, _) = ScriptedStream::new(ONION_CONNECT);
+    let (result, _) = measure(handle_client(warmup, ctx.clone(), client_addr));
+    result.expect("warm-up handshake");
+
+    let (stream, output) = ScriptedStream::new(ONION_CONNECT);
+    let (result, allocations) = measure(handle_client(stream, ctx.clone(), client_addr));
+    result.expect("rejected handshake");
+
+    {
+        let output = output.lock().unwrap();
+        assert_eq!(&output[..2], &[0x05, 0x00], "method selection");
+        assert_eq!(output[3], ReplyCode::NetworkUnreachable as u8);
+    }
+    assert_eq!(session_manager.rejected_snapshot().await.len(), 2);
+    assert!(
+        allocations <= REJECTED_HANDSHAKE_BUDGET,
+        "rejected handshake made {} allocations (budget {})",
+        allocations,
+        REJECTED_HANDSHAKE_BUDGET
+    );
+}
+
+#[test]
+fn successful_connect_stays_within_budget() {
+    let upstream = std::net::TcpListener::bind("127.0.0.1:0").expect("bind upstream");
+    let upstream_port = upstream.local_addr().unwrap().port();
+    // Upstream runs on its own thread so its allocations are not counted
+    let upstream_thread = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) = upstream.accept().expect("accept proxy");
+            let mut payload = Vec::new();
+            stream.read_to_end(&mut payload).expect("read payload");
+            received.push(payload);
+        }
+        received
+    });
+
+    let mut script = vec![
+        0x05, 0x01, 0x00, // greeting: no-auth
+        0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1, // CONNECT 127.0.0.1
+    ];
+    script.extend_from_slice(&upstream_port.to_be_bytes());
+    script.extend_from_slice(b"ping");
+    let script: &'static [u8] = Box::leak(script.into_boxed_slice());
+    // The handler hands its raw stream to the relay, so the payload must not be read
+    // together with the request
+    const SEGMENTS: &[usize] = &[3, 13];
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("runtime");
+    // SessionManager spawns its traffic worker on construction
+    let _runtime_guard = runtime.enter();
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(
+        session_manager.clone(),
+        SpecialNamesPolicy::localhost_allowed(),
+    );
+    let client_addr = "127.0.0.1:40000".parse().unwrap();
+
+    let (warmup, _) = ScriptedStream::segmented(script, SEGMENTS);
+    let (result, _) = measure_on_runtime(&runtime, handle_client(warmup, ctx.clone(), client_addr));
+    result.expect("warm-up connect");
+
+    let (stream, output) = ScriptedStream::segmented(script, SEGMENTS);
+    let (result, allocations) =
+        measure_on_runtime(&runtime, handle_client(stream, ctx.clone(), client_addr));
+    result.expect("successful connect");
+
+    {
+        let output = output.lock().unwrap();
+        assert_eq!(&output[..2], &[0x05, 0x00], "method selection");
+        assert_eq!(output[3], ReplyCode::Succeeded as u8);
+    }
+    let received = upstream_thread.join().expect("upstream thread");
+    assert_eq!(received, vec![b"ping".to_vec(), b"ping".to_vec()]);
+    let closed = runtime.block_on(session_manager.closed_snapshot());
+    assert_eq!(closed.len(), 2);
+    assert!(
+        allocations <= SUCCESSFUL_CONNECT_BUDGET,
+        "successful CONNECT made {} allocations (budget {})",
+        allocations,
+        SUCCESSFUL_CONNECT_BUDGET
+    );
+}
@@ -0,0 +1,110 @@
+//! Handshake deadline and concurrency limit (`server.handshake_timeout_secs`,
+//! `server.max_concurrent_handshakes`)
+
+mod common;
+
+use rustsocks::server::{accept_loop, AcceptOptions, ClientHandlerContext, HandshakeLimits};
+use rustsocks::session::SessionManager;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::{timeout, Duration, Instant};
+
+async fn spawn_proxy(handshake: HandshakeLimits) -> SocketAddr {
+    let ctx = Arc::new(ClientHandlerContext {
+        handshake,
+        ..common::handler_context(Arc::new(SessionManager::new()))
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let addr = listener.local_addr().expect("proxy addr");
+    tokio::spawn(accept_loop(listener, ctx, AcceptOptions::default()));
+    addr
+}
+
+async fn spawn_echo_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr
+}
+
+/// `true` once the proxy has closed `stream`
+async fn closed_within(stream: &mut TcpStream, wait: Duration) -> bool {
+    let mut byte = [0u8; 1];
+    matches!(
+        timeout(wait, stream.read(&mut byte)).await,
+        Ok(Ok(0)) | Ok(Err(_))
+    )
+}
+
+#[tokio::test]
+async fn stalled_handshakes_are_closed_at_the_deadline() {
+    let proxy = spawn_proxy(HandshakeLimits {
+        timeout: Duration::from_millis(300),
+        slots: None,
+    })
+    .await;
+
+    // Nothing after connecting
+    let started = Instant::now();
+    let mut silent = TcpStream::connect(proxy).await.expect("connect proxy");
+    assert!(closed_within(&mut silent, Duration::from_secs(2)).await);
+    assert!(started.elapsed() >= Duration::from_millis(250));
+
+    // Greeting answered, request never sent
+    let mut stalled = TcpStream::connect(proxy).await.expect("connect proxy");
+    stalled.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stalled.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+    assert!(closed_within(&mut stalled, Duration::from_secs(2)).await);
+}
+
+#[tokio::test]
+async fn handshake_slots_do_not_count_established_relays() {
+    let upstream = spawn_echo_upstream().await;
+    let proxy = spawn_proxy(HandshakeLimits {
+        timeout: Duration::from_secs(5),
+        slots: Some(Arc::new(Semaphore::new(1))),
+    })
+    .await;
+
+    // The only slot is held by a client that is still negotiating
+    let mut first = TcpStream::connect(proxy).await.expect("connect proxy");
+    first.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    first.read_exact(&mut method).await.unwrap();
+
+    let mut turned_away = TcpStream::connect(proxy).await.expect("connect proxy");
+    assert!(closed_within(&mut turned_away, Duration::from_secs(1)).await);
+
+    // Once its relay starts, the slot is free for the next client
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    first.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    first.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00, "CONNECT should succeed");
+
+    let mut next = TcpStream::connect(proxy).await.expect("connect proxy");
+    next.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    next.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    first.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    first.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo, b"ping");
+}
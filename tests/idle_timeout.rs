@@ -0,0 +1,137 @@
+//! Relayed TCP sessions idle in both directions (`server.idle_timeout_secs`)
+//!
+//! A tunnel that moves nothing for the timeout is shut down and lands in the session
+//! history with close reason "idle timeout"; traffic in either direction restarts the
+//! clock.
+
+mod common;
+
+use rustsocks::protocol::ReplyCode;
+use rustsocks::server::proxy::TrafficUpdateConfig;
+use rustsocks::server::{handle_client, ClientHandlerContext};
+use rustsocks::session::{Session, SessionManager, SessionStatus};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+async fn spawn_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind echo");
+    let addr = listener.local_addr().expect("echo addr");
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr
+}
+
+fn handler_context(idle_timeout_secs: u64) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        traffic_config: TrafficUpdateConfig::default().with_idle_timeout(idle_timeout_secs),
+        ..common::handler_context(Arc::new(SessionManager::new()))
+    })
+}
+
+/// CONNECT to `target` through a fresh handler and return the tunnelled stream.
+async fn connect(ctx: Arc<ClientHandlerContext>, target: SocketAddr) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+
+    let SocketAddr::V4(target) = target else {
+        panic!("echo server listens on IPv4");
+    };
+    let mut request = vec![0x05, 0x01, 0x00, 0x01];
+    request.extend_from_slice(&target.ip().octets());
+    request.extend_from_slice(&target.port().to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(5), client.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    assert_eq!(reply[1], ReplyCode::Succeeded as u8);
+    client
+}
+
+async fn echo(stream: &mut TcpStream) {
+    stream.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(2), stream.read_exact(&mut echo))
+        .await
+        .expect("echo in time")
+        .unwrap();
+    assert_eq!(&echo, b"ping");
+}
+
+async fn wait_for_closed(session_manager: &SessionManager) -> Option<Session> {
+    for _ in 0..100 {
+        if let Some(session) = session_manager.get_closed_sessions().await.pop() {
+            return Some(session);
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    None
+}
+
+#[tokio::test]
+async fn idle_tunnel_is_closed_with_idle_timeout_reason() {
+    let echo_addr = spawn_echo().await;
+    let ctx = handler_context(1);
+    let mut client = connect(ctx.clone(), echo_addr).await;
+    echo(&mut client).await;
+
+    let started = Instant::now();
+    let mut buf = [0u8; 1];
+    let read = timeout(Duration::from_secs(5), client.read(&mut buf))
+        .await
+        .expect("tunnel closed in time");
+    assert!(matches!(read, Ok(0) | Err(_)), "client socket shut down");
+    assert!(started.elapsed() >= Duration::from_millis(900));
+
+    let session = wait_for_closed(&ctx.session_manager)
+        .await
+        .expect("session in history");
+    assert_eq!(session.close_reason.as_deref(), Some("idle timeout"));
+    assert_eq!(session.status, SessionStatus::Closed);
+    assert_eq!(session.bytes_sent, 4);
+}
+
+#[tokio::test]
+async fn traffic_resets_the_idle_timer() {
+    let echo_addr = spawn_echo().await;
+    let ctx = handler_context(1);
+    let mut client = connect(ctx.clone(), echo_addr).await;
+
+    // Well past the timeout in total, but never idle for a full second
+    for _ in 0..5 {
+        sleep(Duration::from_millis(400)).await;
+        echo(&mut client).await;
+    }
+    assert!(ctx.session_manager.get_closed_sessions().await.is_empty());
+}
+
+#[tokio::test]
+async fn zero_disables_the_idle_timeout() {
+    let echo_addr = spawn_echo().await;
+    let ctx = handler_context(0);
+    let mut client = connect(ctx.clone(), echo_addr).await;
+
+    sleep(Duration::from_millis(1500)).await;
+    echo(&mut client).await;
+    assert!(ctx.session_manager.get_closed_sessions().await.is_empty());
+}
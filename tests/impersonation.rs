@@ -0,0 +1,230 @@
+mod common;
+
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
+use rustsocks::auth::AuthManager;
+use rustsocks::config::{AuthConfig, ImpersonationSettings, User};
+use rustsocks::protocol::ReplyCode;
+use rustsocks::server::{handle_client, ClientHandlerContext};
+use rustsocks::session::{SessionManager, SessionStatus};
+use rustsocks::utils::error::RustSocksError;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Duration;
+
+fn user(username: &str, password: &str) -> User {
+    User {
+        username: username.to_string(),
+        password: password.to_string().into(),
+        password_hash: None,
+    }
+}
+
+fn auth_config() -> AuthConfig {
+    AuthConfig {
+        socks_method: "userpass".to_string(),
+        socks_method_preference: Vec::new(),
+        users: vec![
+            user("svc-report", "svc-secret"),
+            user("intruder", "intruder-secret"),
+            user("alice", "alice-secret"),
+        ],
+        impersonation: ImpersonationSettings {
+            allowed_principals: vec!["svc-report".to_string()],
+            allowed_target_pattern: "*".to_string(),
+            separator: ":".to_string(),
+        },
+        ..AuthConfig::default()
+    }
+}
+
+/// Only alice may reach the loopback upstream; everyone else is blocked by default.
+fn acl_config() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![UserAcl {
+            username: "alice".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Allow,
+                description: "alice may reach loopback".to_string(),
+                destinations: vec!["127.0.0.1".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 100,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+        groups: vec![],
+    }
+}
+
+fn handler_context(session_manager: Arc<SessionManager>) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(AuthManager::new(&auth_config()).expect("auth manager")),
+        acl_engine: Some(Arc::new(AclEngine::new(acl_config()).expect("acl engine"))),
+        ..common::handler_context(session_manager)
+    })
+}
+
+async fn spawn_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            drop(stream);
+        }
+    });
+    addr
+}
+
+enum Outcome {
+    AuthRejected,
+    Reply(u8),
+}
+
+/// Log in with `login`/`password` and CONNECT to `upstream`; returns the handler result too.
+async fn login_and_connect(
+    ctx: Arc<ClientHandlerContext>,
+    login: &str,
+    password: &str,
+    upstream: SocketAddr,
+) -> (Outcome, rustsocks::Result<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+    let server_task = tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        handle_client(stream, ctx, client_addr).await
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x02]);
+
+    let mut auth = vec![0x01, login.len() as u8];
+    auth.extend_from_slice(login.as_bytes());
+    auth.push(password.len() as u8);
+    auth.extend_from_slice(password.as_bytes());
+    client.write_all(&auth).await.unwrap();
+    let mut status = [0u8; 2];
+    client.read_exact(&mut status).await.unwrap();
+
+    let outcome = if status[1] != 0x00 {
+        Outcome::AuthRejected
+    } else {
+        let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+        request.extend_from_slice(&upstream.port().to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        Outcome::Reply(reply[1])
+    };
+    drop(client);
+
+    let result = tokio::time::timeout(Duration::from_secs(5), server_task)
+        .await
+        .expect("handler finished")
+        .unwrap();
+    (outcome, result)
+}
+
+#[tokio::test]
+async fn allowed_service_account_acts_as_target_user() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let ctx = handler_context(session_manager.clone());
+
+    let (outcome, result) =
+        login_and_connect(ctx, "svc-report:alice", "svc-secret", upstream).await;
+    assert!(matches!(outcome, Outcome::Reply(code) if code == ReplyCode::Succeeded as u8));
+    result.expect("handler succeeds");
+
+    let session = session_manager
+        .closed_snapshot()
+        .await
+        .pop()
+        .expect("closed session");
+    assert_eq!(session.user.as_ref(), "alice");
+    assert_eq!(session.authenticated_user.as_deref(), Some("svc-report"));
+    assert_eq!(session.acl_decision.as_ref(), "allow");
+}
+
+#[tokio::test]
+async fn service_account_without_target_keeps_its_own_acl() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let ctx = handler_context(session_manager.clone());
+
+    let (outcome, _) = login_and_connect(ctx, "svc-report", "svc-secret", upstream).await;
+    assert!(
+        matches!(outcome, Outcome::Reply(code) if code == ReplyCode::ConnectionNotAllowed as u8)
+    );
+
+    let rejected = session_manager.rejected_snapshot().await;
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].user.as_ref(), "svc-report");
+    assert_eq!(
+        rejected[0].authenticated_user.as_deref(),
+        Some("svc-report")
+    );
+    assert_eq!(rejected[0].status, SessionStatus::RejectedByAcl);
+}
+
+#[tokio::test]
+async fn disallowed_principal_is_rejected_at_authentication() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let ctx = handler_context(session_manager.clone());
+
+    let (outcome, result) =
+        login_and_connect(ctx, "intruder:alice", "intruder-secret", upstream).await;
+    assert!(matches!(outcome, Outcome::AuthRejected));
+    assert!(matches!(
+        result,
+        Err(RustSocksError::ImpersonationDenied(ref msg)) if msg.contains("intruder")
+    ));
+    assert!(session_manager.closed_snapshot().await.is_empty());
+    assert!(session_manager.rejected_snapshot().await.is_empty());
+}
+
+#[tokio::test]
+async fn wrong_password_fails_before_impersonation_is_considered() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let ctx = handler_context(session_manager.clone());
+
+    let (outcome, result) =
+        login_and_connect(ctx, "svc-report:alice", "alice-secret", upstream).await;
+    assert!(matches!(outcome, Outcome::AuthRejected));
+    assert!(matches!(result, Err(RustSocksError::AuthFailed(_))));
+}
+
+#[tokio::test]
+async fn plain_login_records_the_same_identity_twice() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let ctx = handler_context(session_manager.clone());
+
+    let (outcome, _) = login_and_connect(ctx, "alice", "alice-secret", upstream).await;
+    assert!(matches!(outcome, Outcome::Reply(code) if code == ReplyCode::Succeeded as u8));
+
+    let session = session_manager
+        .closed_snapshot()
+        .await
+        .pop()
+        .expect("closed session");
+    assert_eq!(session.user.as_ref(), "alice");
+    assert_eq!(session.authenticated_user.as_deref(), Some("alice"));
+}
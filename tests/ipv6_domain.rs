@@ -14,7 +14,7 @@ async fn domain_resolution_reaches_ipv6_localhost() {
     };
 
     let port = listener.local_addr().unwrap().port();
-    let addr = Address::Domain("localhost".to_string());
+    let addr = Address::Domain("localhost".into());
     let resolved = resolve_address(&addr, port).await.unwrap();
     if !resolved
         .iter()
@@ -8,7 +8,7 @@
 ///
 /// Note: These tests use mock LDAP groups (simulated arrays of strings).
 /// Real LDAP integration would require NSS/SSSD configuration.
-use rustsocks::acl::types::{AclRule, GlobalAclConfig, GroupAcl};
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, GroupAcl, RuleLogLevel};
 use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
 use rustsocks::protocol::Address;
 
@@ -23,25 +23,37 @@ fn create_test_acl_config() -> AclConfig {
             // Developers group - allow access to internal dev servers
             GroupAcl {
                 name: "developers".to_string(),
+                parents: vec![],
                 rules: vec![AclRule {
                     action: Action::Allow,
                     description: "Developers internal access".to_string(),
                     destinations: vec!["10.0.0.0/8".to_string()],
                     ports: vec!["*".to_string()],
+                    sources: vec![],
                     protocols: vec![Protocol::Tcp],
                     priority: 100,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             },
             // Admins group - full access
             GroupAcl {
                 name: "admins".to_string(),
+                parents: vec![],
                 rules: vec![AclRule {
                     action: Action::Allow,
                     description: "Admins full access".to_string(),
                     destinations: vec!["*".to_string()],
                     ports: vec!["*".to_string()],
+                    sources: vec![],
                     protocols: vec![Protocol::Tcp, Protocol::Udp],
                     priority: 200,
+                    log: RuleLogLevel::Default,
+                    reply_code: None,
+                    resolve: Default::default(),
+                    schedule: None,
                 }],
             },
         ],
@@ -67,7 +79,7 @@ async fn test_ldap_groups_only_defined_groups_are_checked() {
     // Try to connect to 10.1.2.3 (in developers' allowed range)
     let dest = Address::IPv4([10, 1, 2, 3]);
     let (decision, matched_rule) = engine
-        .evaluate_with_groups("alice", &ldap_groups, &dest, 80, &Protocol::Tcp)
+        .evaluate_with_groups("alice", &ldap_groups, &dest, 80, &Protocol::Tcp, None)
         .await;
 
     // Should ALLOW because "developers" group matches and allows 10.0.0.0/8
@@ -92,7 +104,7 @@ async fn test_ldap_groups_no_matching_groups_uses_default_policy() {
     // Try to connect to 10.1.2.3
     let dest = Address::IPv4([10, 1, 2, 3]);
     let (decision, matched_rule) = engine
-        .evaluate_with_groups("bob", &ldap_groups, &dest, 80, &Protocol::Tcp)
+        .evaluate_with_groups("bob", &ldap_groups, &dest, 80, &Protocol::Tcp, None)
         .await;
 
     // Should BLOCK because no groups match and default_policy = Block
@@ -116,7 +128,7 @@ async fn test_ldap_groups_case_insensitive_matching() {
     // Try to connect to 10.1.2.3
     let dest = Address::IPv4([10, 1, 2, 3]);
     let (decision, matched_rule) = engine
-        .evaluate_with_groups("alice", &ldap_groups, &dest, 80, &Protocol::Tcp)
+        .evaluate_with_groups("alice", &ldap_groups, &dest, 80, &Protocol::Tcp, None)
         .await;
 
     // Should ALLOW because case-insensitive matching: "Developers" = "developers"
@@ -140,7 +152,7 @@ async fn test_ldap_groups_multiple_matching_groups() {
     // Try to connect to 192.168.1.1 (NOT in developers range, but admins allow *)
     let dest = Address::IPv4([192, 168, 1, 1]);
     let (decision, matched_rule) = engine
-        .evaluate_with_groups("charlie", &ldap_groups, &dest, 80, &Protocol::Tcp)
+        .evaluate_with_groups("charlie", &ldap_groups, &dest, 80, &Protocol::Tcp, None)
         .await;
 
     // Should ALLOW via "admins" group (higher priority)
@@ -165,8 +177,13 @@ async fn test_ldap_groups_with_per_user_override() {
             description: "Alice blocked from 10.1.2.3".to_string(),
             destinations: vec!["10.1.2.3".to_string()], // Specific IP
             ports: vec!["*".to_string()],
+            sources: vec![],
             protocols: vec![Protocol::Tcp],
-            priority: 1000, // Higher than group rules
+            priority: 1000, // Higher than group rules,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
         }],
     }];
 
@@ -175,7 +192,7 @@ async fn test_ldap_groups_with_per_user_override() {
     // Try to connect to 10.1.2.3
     let dest = Address::IPv4([10, 1, 2, 3]);
     let (decision, matched_rule) = engine
-        .evaluate_with_groups("alice", &ldap_groups, &dest, 80, &Protocol::Tcp)
+        .evaluate_with_groups("alice", &ldap_groups, &dest, 80, &Protocol::Tcp, None)
         .await;
 
     // Should BLOCK because per-user rule has higher priority
@@ -186,7 +203,7 @@ async fn test_ldap_groups_with_per_user_override() {
     // Try to connect to 10.1.2.4 (different IP, not blocked)
     let dest2 = Address::IPv4([10, 1, 2, 4]);
     let (decision2, matched_rule2) = engine
-        .evaluate_with_groups("alice", &ldap_groups, &dest2, 80, &Protocol::Tcp)
+        .evaluate_with_groups("alice", &ldap_groups, &dest2, 80, &Protocol::Tcp, None)
         .await;
 
     // Should ALLOW via "developers" group rule
@@ -208,7 +225,7 @@ async fn test_ldap_groups_empty_groups_list() {
     // Try to connect
     let dest = Address::IPv4([10, 1, 2, 3]);
     let (decision, matched_rule) = engine
-        .evaluate_with_groups("alice", &ldap_groups, &dest, 80, &Protocol::Tcp)
+        .evaluate_with_groups("alice", &ldap_groups, &dest, 80, &Protocol::Tcp, None)
         .await;
 
     // Should BLOCK (default policy, no groups)
@@ -243,7 +260,7 @@ async fn test_ldap_groups_mixed_case_variations() {
         };
 
         let (decision, _) = engine
-            .evaluate_with_groups("testuser", &ldap_groups, &dest, 80, &Protocol::Tcp)
+            .evaluate_with_groups("testuser", &ldap_groups, &dest, 80, &Protocol::Tcp, None)
             .await;
 
         assert_eq!(
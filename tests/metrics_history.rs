@@ -0,0 +1,340 @@
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use rustsocks::api::handlers::sessions::{get_metrics_history, ApiState};
+use rustsocks::config::Config;
+use rustsocks::qos::QosEngine;
+use rustsocks::server::{ConnectionPool, PoolConfig};
+use rustsocks::session::{
+    MetricsCursor, MetricsHistory, MetricsSnapshot, MinMaxDecimator, SessionManager,
+};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+const SAMPLES: usize = 100_000;
+const ACTIVE_SPIKE_AT: usize = 31_337;
+const BANDWIDTH_SPIKE_AT: usize = 77_777;
+const DIP_AT: usize = 50_001;
+
+/// One sample every 500ms, ending a minute ago, with three planted outliers.
+async fn seeded_history() -> (Arc<MetricsHistory>, DateTime<Utc>, DateTime<Utc>) {
+    let history = Arc::new(MetricsHistory::new(SAMPLES, 24));
+    let first = Utc::now()
+        - ChronoDuration::minutes(1)
+        - ChronoDuration::milliseconds(500 * SAMPLES as i64);
+    for i in 0..SAMPLES {
+        let mut active = 100 + (i % 7) as u64;
+        let mut bandwidth = 1_000_000 + (i % 13) as u64 * 1_000;
+        match i {
+            ACTIVE_SPIKE_AT => active = 9_000,
+            BANDWIDTH_SPIKE_AT => bandwidth = 900_000_000,
+            DIP_AT => active = 0,
+            _ => {}
+        }
+        history
+            .add_snapshot(MetricsSnapshot {
+                timestamp: first + ChronoDuration::milliseconds(500 * i as i64),
+                active_sessions: active,
+                total_sessions: i as u64,
+                bandwidth,
+                rollup: None,
+            })
+            .await;
+    }
+    let last = first + ChronoDuration::milliseconds(500 * (SAMPLES as i64 - 1));
+    (history, first, last)
+}
+
+fn app(history: Option<Arc<MetricsHistory>>, config: Config) -> Router {
+    let state = ApiState {
+        session_manager: Arc::new(SessionManager::new()),
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: history,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(config),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    };
+    Router::new()
+        .route("/api/metrics/history", get(get_metrics_history))
+        .with_state(state)
+}
+
+async fn fetch(app: Router, uri: String, accept: Option<&str>) -> (StatusCode, String, String) {
+    let mut request = Request::builder().uri(uri);
+    if let Some(accept) = accept {
+        request = request.header(header::ACCEPT, accept);
+    }
+    let response = app
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        content_type,
+        String::from_utf8(body.to_vec()).unwrap(),
+    )
+}
+
+fn range_uri(start: DateTime<Utc>, end: DateTime<Utc>, extra: &str) -> String {
+    format!(
+        "/api/metrics/history?start={}&end={}{}",
+        urlencode(&start.to_rfc3339()),
+        urlencode(&end.to_rfc3339()),
+        extra
+    )
+}
+
+fn urlencode(value: &str) -> String {
+    value.replace('+', "%2B").replace(':', "%3A")
+}
+
+#[tokio::test]
+async fn decimated_history_keeps_spikes_and_dips() {
+    let (history, first, last) = seeded_history().await;
+    let (status, content_type, body) = fetch(
+        app(Some(history), Config::default()),
+        range_uri(first, last, "&max_points=40"),
+        None,
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/json");
+    let points: Vec<MetricsSnapshot> = serde_json::from_str(&body).unwrap();
+    assert!(points.len() <= 40, "got {} points", points.len());
+    assert!(points.len() >= 10);
+    assert!(points.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+    assert!(points.iter().any(|p| p.active_sessions == 9_000));
+    assert!(points.iter().any(|p| p.bandwidth == 900_000_000));
+    assert!(points.iter().any(|p| p.active_sessions == 0));
+    assert_eq!(points[0].total_sessions, 0);
+}
+
+#[tokio::test]
+async fn ndjson_is_negotiated_from_accept_header() {
+    let (history, first, last) = seeded_history().await;
+    let router = app(Some(history), Config::default());
+
+    let (status, content_type, body) = fetch(
+        router.clone(),
+        range_uri(first, last, "&max_points=100"),
+        Some("application/x-ndjson"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/x-ndjson");
+    let lines: Vec<MetricsSnapshot> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(!lines.is_empty() && lines.len() <= 100);
+
+    let (_, _, json) = fetch(router, range_uri(first, last, "&max_points=100"), None).await;
+    let array: Vec<MetricsSnapshot> = serde_json::from_str(&json).unwrap();
+    assert_eq!(array.len(), lines.len());
+}
+
+#[tokio::test]
+async fn msgpack_is_negotiated_from_accept_header() {
+    let (history, first, last) = seeded_history().await;
+    let router = app(Some(history), Config::default());
+    let uri = range_uri(first, last, "&max_points=100");
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(uri.clone())
+                .header(header::ACCEPT, "application/msgpack")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/msgpack"
+    );
+    assert!(response.headers().contains_key("x-metrics-resolution"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let from_msgpack: serde_json::Value = rmp_serde::from_slice(&body).unwrap();
+
+    let (_, content_type, json) = fetch(router.clone(), uri.clone(), None).await;
+    assert_eq!(content_type, "application/json");
+    let from_json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert!(!from_json.as_array().unwrap().is_empty());
+    assert_eq!(from_msgpack, from_json);
+
+    // Media types the API does not speak still get JSON
+    let (status, content_type, body) = fetch(router, uri, Some("application/cbor")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/json");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        from_json
+    );
+}
+
+#[tokio::test]
+async fn small_ranges_are_returned_undecimated() {
+    let (history, first, _) = seeded_history().await;
+    let end = first + ChronoDuration::milliseconds(500 * 9);
+    let (_, _, body) = fetch(
+        app(Some(history), Config::default()),
+        range_uri(first, end, ""),
+        None,
+    )
+    .await;
+    let points: Vec<MetricsSnapshot> = serde_json::from_str(&body).unwrap();
+    let totals: Vec<u64> = points.iter().map(|p| p.total_sessions).collect();
+    assert_eq!(totals, (0..10).collect::<Vec<u64>>());
+}
+
+#[tokio::test]
+async fn range_wider_than_cap_is_rejected() {
+    let mut config = Config::default();
+    config.metrics.history_max_range_hours = 1;
+    let router = app(None, config);
+
+    let (status, _, body) = fetch(
+        router.clone(),
+        "/api/metrics/history?minutes=120".to_string(),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(body.contains("history_max_range_hours"));
+
+    let (status, _, body) = fetch(
+        router.clone(),
+        "/api/metrics/history?minutes=30".to_string(),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "[]");
+
+    let (status, _, _) = fetch(router, "/api/metrics/history?format=xml".to_string(), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn cursor_and_decimator_hold_bounded_sample_counts() {
+    let (history, first, last) = seeded_history().await;
+    let mut cursor = MetricsCursor::memory(history, first, last).with_chunk_size(1_000);
+    let mut decimator = MinMaxDecimator::new(first, last, 40);
+    let mut out = Vec::new();
+    let mut scanned = 0;
+    let mut chunks = 0;
+
+    loop {
+        let chunk = cursor.next_chunk().await.unwrap();
+        if chunk.is_empty() {
+            break;
+        }
+        assert!(chunk.len() <= 1_000);
+        scanned += chunk.len();
+        chunks += 1;
+        for sample in chunk {
+            decimator.push(sample, &mut out);
+            assert!(decimator.pending() <= 4);
+        }
+    }
+    decimator.finish(&mut out);
+
+    assert_eq!(scanned, SAMPLES);
+    assert_eq!(chunks, SAMPLES / 1_000);
+    assert!(out.len() <= 40);
+    assert_eq!(decimator.pending(), 0);
+}
+
+#[tokio::test]
+async fn rollups_answer_ranges_past_raw_retention() {
+    let history = Arc::new(MetricsHistory::new(10_000, 24).with_rollups(30));
+    let now = Utc::now();
+    // Two days of one sample a minute; raw samples only keep the last day
+    for i in (0..2 * 24 * 60).rev() {
+        history
+            .add_snapshot(MetricsSnapshot {
+                timestamp: now - ChronoDuration::minutes(i),
+                active_sessions: if i == 40 * 60 { 5_000 } else { 10 },
+                total_sessions: 0,
+                bandwidth: 0,
+                rollup: None,
+            })
+            .await;
+    }
+    let router = app(Some(history), Config::default());
+
+    let request = |query: &str| {
+        Request::builder()
+            .uri(format!("/api/metrics/history?minutes=2880{}", query))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = router.clone().oneshot(request("")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-metrics-resolution"], "1m");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let points: Vec<MetricsSnapshot> = serde_json::from_slice(&body).unwrap();
+    assert!(points.len() <= 1440);
+    assert!(points[0].timestamp < now - ChronoDuration::hours(47));
+    // The spike survives as the maximum of its bucket
+    assert!(points
+        .iter()
+        .any(|p| p.rollup.is_some_and(|r| r.active_sessions.max == 5_000)));
+
+    let response = router
+        .clone()
+        .oneshot(request("&resolution=15m"))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-metrics-resolution"], "15m");
+
+    // Raw samples over two days would scan past the cap
+    let response = router
+        .clone()
+        .oneshot(request("&resolution=raw"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let response = router.oneshot(request("&resolution=5m")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,81 @@
+//! SOCKS listeners on several addresses (`server.bind_addresses`)
+use rustsocks::server::{bind_listeners, BoundAddresses};
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+
+/// A port free on the IPv4 loopback at the time of the call.
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn ipv4_and_ipv6_listeners_share_a_port() {
+    let port = free_port();
+    let addresses: Vec<SocketAddr> = vec![
+        format!("127.0.0.1:{port}").parse().unwrap(),
+        format!("[::1]:{port}").parse().unwrap(),
+    ];
+
+    // Hosts without IPv6 still get the IPv4 listener
+    let listeners = bind_listeners(&addresses, false).unwrap();
+    let bound: Vec<SocketAddr> = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap())
+        .collect();
+    assert_eq!(bound[0], addresses[0]);
+
+    for (listener, address) in listeners.iter().zip(&bound) {
+        let (client, accepted) = tokio::join!(TcpStream::connect(address), listener.accept());
+        client.unwrap();
+        accepted.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn unavailable_addresses_do_not_stop_the_others() {
+    let addresses: Vec<SocketAddr> = vec![
+        // TEST-NET-1, never assigned to a local interface
+        "192.0.2.1:0".parse().unwrap(),
+        "127.0.0.1:0".parse().unwrap(),
+    ];
+    let listeners = bind_listeners(&addresses, false).unwrap();
+    assert_eq!(listeners.len(), 1);
+    assert!(listeners[0].local_addr().unwrap().ip().is_loopback());
+}
+
+#[tokio::test]
+async fn binding_fails_only_when_every_address_fails() {
+    let addresses: Vec<SocketAddr> = vec!["192.0.2.1:0".parse().unwrap()];
+    assert!(bind_listeners(&addresses, false).is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn reuse_port_lets_two_listeners_bind_the_same_port() {
+    let port = free_port();
+    let addresses: Vec<SocketAddr> = vec![format!("127.0.0.1:{port}").parse().unwrap()];
+
+    // The second bind stands in for the next version starting during an upgrade
+    let current = bind_listeners(&addresses, true).unwrap();
+    let next = bind_listeners(&addresses, true).unwrap();
+    assert_eq!(
+        current[0].local_addr().unwrap(),
+        next[0].local_addr().unwrap()
+    );
+
+    // Without it the port stays taken
+    assert!(bind_listeners(&addresses, false).is_err());
+}
+
+#[test]
+fn bound_addresses_start_empty() {
+    let bound = BoundAddresses::default();
+    assert!(bound.get().is_empty());
+    let address: SocketAddr = "127.0.0.1:1080".parse().unwrap();
+    bound.set(vec![address]);
+    assert_eq!(bound.get(), vec![address]);
+}
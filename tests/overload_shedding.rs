@@ -0,0 +1,287 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::{get, put},
+    Router,
+};
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::api::handlers::{health_check, set_overload_mode};
+use rustsocks::config::{Config, OverloadSettings};
+use rustsocks::qos::QosEngine;
+use rustsocks::server::{
+    accept_loop, AcceptOptions, ClientHandlerContext, ConnectionPool, LoadShedder, PoolConfig,
+    ShedMode,
+};
+use rustsocks::session::SessionManager;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration, Instant};
+use tower::util::ServiceExt;
+
+/// Engage at 80, release below 60; the reject ratio ramps 0.2 -> 0.8 between 80 and 100.
+fn shedder() -> Arc<LoadShedder> {
+    Arc::new(LoadShedder::new(&OverloadSettings {
+        enabled: true,
+        engage_threshold: 80.0,
+        release_threshold: 60.0,
+        full_threshold: 100.0,
+        min_reject_ratio: 0.2,
+        max_reject_ratio: 0.8,
+        ..OverloadSettings::default()
+    }))
+}
+
+fn handler_context(session_manager: Arc<SessionManager>) -> Arc<ClientHandlerContext> {
+    Arc::new(common::handler_context(session_manager))
+}
+
+async fn spawn_proxy(shedder: Arc<LoadShedder>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let addr = listener.local_addr().expect("proxy addr");
+    let ctx = handler_context(Arc::new(SessionManager::new()));
+    tokio::spawn(accept_loop(
+        listener,
+        ctx,
+        AcceptOptions {
+            overload: Some(shedder),
+            ..AcceptOptions::default()
+        },
+    ));
+    addr
+}
+
+async fn spawn_echo_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr
+}
+
+/// Open a connection and send a SOCKS5 greeting; `true` when the proxy answered.
+async fn greeting_answered(proxy: SocketAddr) -> bool {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    if stream.write_all(&[0x05, 0x01, 0x00]).await.is_err() {
+        return false;
+    }
+    let mut reply = [0u8; 2];
+    matches!(
+        timeout(Duration::from_secs(2), stream.read_exact(&mut reply)).await,
+        Ok(Ok(_)) if reply == [0x05, 0x00]
+    )
+}
+
+async fn rejected_out_of(proxy: SocketAddr, attempts: usize) -> usize {
+    let mut rejected = 0;
+    for _ in 0..attempts {
+        if !greeting_answered(proxy).await {
+            rejected += 1;
+        }
+    }
+    rejected
+}
+
+async fn open_tunnel(proxy: SocketAddr, upstream: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00, "CONNECT should succeed");
+    stream
+}
+
+/// Push `total` bytes through the tunnel and read the echo back.
+async fn echo_round_trip(stream: &mut TcpStream, total: usize) {
+    let chunk = vec![0xAB; 64 * 1024];
+    let (mut reader, mut writer) = stream.split();
+    let write = async {
+        let mut sent = 0;
+        while sent < total {
+            writer.write_all(&chunk).await.unwrap();
+            sent += chunk.len();
+        }
+    };
+    let read = async {
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut received = 0;
+        while received < total {
+            let n = reader.read(&mut buf).await.unwrap();
+            assert!(n > 0, "tunnel closed mid-transfer");
+            assert!(buf[..n].iter().all(|b| *b == 0xAB));
+            received += n;
+        }
+    };
+    timeout(Duration::from_secs(10), async { tokio::join!(write, read) })
+        .await
+        .expect("transfer finished");
+}
+
+#[tokio::test]
+async fn new_connections_are_shed_at_the_configured_rate() {
+    let shedder = shedder();
+    let proxy = spawn_proxy(shedder.clone()).await;
+
+    assert_eq!(rejected_out_of(proxy, 20).await, 0);
+
+    // Halfway between engage and full: 0.2 + 0.6 * 0.5 = 0.5
+    shedder.observe(90.0);
+    let rejected = rejected_out_of(proxy, 40).await;
+    assert!((16..=24).contains(&rejected), "rejected {} of 40", rejected);
+
+    // Full severity: 0.8
+    shedder.observe(100.0);
+    let rejected = rejected_out_of(proxy, 40).await;
+    assert!((28..=36).contains(&rejected), "rejected {} of 40", rejected);
+
+    let status = shedder.status();
+    assert!(status.shedding);
+    assert_eq!(status.engagements, 1);
+    assert!(status.rejected_connections >= 44);
+}
+
+#[tokio::test]
+async fn established_transfer_is_not_throttled_while_shedding() {
+    let shedder = shedder();
+    let proxy = spawn_proxy(shedder.clone()).await;
+    let upstream = spawn_echo_upstream().await;
+
+    let mut tunnel = open_tunnel(proxy, upstream).await;
+    let started = Instant::now();
+    echo_round_trip(&mut tunnel, 8 * 1024 * 1024).await;
+    let baseline = started.elapsed();
+
+    // Most new connections are refused, but the tunnel keeps relaying
+    shedder.observe(100.0);
+    shedder.set_mode(ShedMode::ForceOn);
+    assert!(shedder.is_shedding());
+
+    let transfer = tokio::spawn(async move {
+        let started = Instant::now();
+        echo_round_trip(&mut tunnel, 8 * 1024 * 1024).await;
+        started.elapsed()
+    });
+    let rejected = rejected_out_of(proxy, 10).await;
+    assert!(rejected >= 7, "rejected {} of 10", rejected);
+    let while_shedding = transfer.await.expect("transfer task");
+
+    // Generous bound: the relay path has no shedding hook, this only guards against one
+    assert!(
+        while_shedding <= baseline * 4 + Duration::from_secs(1),
+        "baseline {:?}, while shedding {:?}",
+        baseline,
+        while_shedding
+    );
+}
+
+#[tokio::test]
+async fn recovery_needs_the_release_threshold() {
+    let shedder = shedder();
+    let proxy = spawn_proxy(shedder.clone()).await;
+
+    shedder.observe(85.0);
+    assert!(shedder.is_shedding());
+
+    // Back under the engage threshold, but not under release: still shedding
+    shedder.observe(70.0);
+    assert!(shedder.is_shedding());
+    assert!(rejected_out_of(proxy, 20).await >= 3);
+
+    shedder.observe(55.0);
+    assert!(!shedder.is_shedding());
+    assert_eq!(rejected_out_of(proxy, 20).await, 0);
+
+    // Rising again stays clear until engage is reached
+    shedder.observe(79.0);
+    assert!(!shedder.is_shedding());
+    assert_eq!(rejected_out_of(proxy, 20).await, 0);
+}
+
+#[tokio::test]
+async fn admin_override_and_health_report_shed_state() {
+    let shedder = shedder();
+    let state = ApiState {
+        session_manager: Arc::new(SessionManager::new()),
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: Some(shedder.clone()),
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    };
+    let app = Router::new()
+        .route("/health", get(health_check))
+        .route("/api/admin/overload", put(set_overload_mode))
+        .with_state(state);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/api/admin/overload")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"mode":"force_on"}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(shedder.is_shedding());
+
+    // Forcing on does not need the signal, and force_off ignores a hot signal
+    shedder.observe(100.0);
+    shedder.set_mode(ShedMode::ForceOff);
+    assert!(!shedder.is_shedding());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/health")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(health["overload"]["mode"], "force_off");
+    assert_eq!(health["overload"]["shedding"], false);
+    assert_eq!(health["overload"]["signal"], 100.0);
+}
@@ -0,0 +1,305 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use futures::future::BoxFuture;
+use rustsocks::api::handlers::invalidate_address_cache;
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::auth::{AddressAuthError, AddressAuthenticator, AuthManager};
+use rustsocks::config::{AuthConfig, Config};
+use rustsocks::qos::QosEngine;
+use rustsocks::server::pool::{ConnectionPool, PoolConfig};
+use rustsocks::session::SessionManager;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+/// Stands in for the PAM stack: accepts everything except `denied`, counting calls.
+struct MockPam {
+    calls: AtomicUsize,
+    denied: Vec<IpAddr>,
+    unavailable: bool,
+}
+
+impl MockPam {
+    fn new(denied: &[&str]) -> Arc<Self> {
+        Arc::new(Self {
+            calls: AtomicUsize::new(0),
+            denied: denied.iter().map(|ip| ip.parse().unwrap()).collect(),
+            unavailable: false,
+        })
+    }
+
+    fn unavailable() -> Arc<Self> {
+        Arc::new(Self {
+            calls: AtomicUsize::new(0),
+            denied: Vec::new(),
+            unavailable: true,
+        })
+    }
+
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+impl AddressAuthenticator for MockPam {
+    fn authenticate<'a>(
+        &'a self,
+        client_ip: IpAddr,
+    ) -> BoxFuture<'a, Result<(), AddressAuthError>> {
+        Box::pin(async move {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.unavailable {
+                Err(AddressAuthError::Unavailable(
+                    "LDAP unreachable".to_string(),
+                ))
+            } else if self.denied.contains(&client_ip) {
+                Err(AddressAuthError::Denied(format!("{} rejected", client_ip)))
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+fn address_config(positive_secs: u64, negative_secs: u64) -> AuthConfig {
+    let mut config = AuthConfig {
+        client_method: "pam.address".to_string(),
+        ..AuthConfig::default()
+    };
+    config.pam.address_cache_secs = positive_secs;
+    config.pam.negative_cache_secs = negative_secs;
+    config
+}
+
+fn ip(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+#[tokio::test]
+async fn without_cache_settings_every_connection_reaches_pam() {
+    let pam = MockPam::new(&[]);
+    let manager =
+        AuthManager::with_address_authenticator(&address_config(0, 0), pam.clone()).unwrap();
+
+    for _ in 0..3 {
+        manager.authenticate_client(ip("10.0.0.1")).await.unwrap();
+    }
+
+    assert_eq!(pam.calls(), 3);
+    assert_eq!(manager.address_gate().unwrap().stats().cached_entries, 0);
+}
+
+#[tokio::test]
+async fn repeated_client_hits_pam_once_per_ttl() {
+    let pam = MockPam::new(&[]);
+    let manager =
+        AuthManager::with_address_authenticator(&address_config(60, 0), pam.clone()).unwrap();
+
+    for _ in 0..5 {
+        manager.authenticate_client(ip("10.0.0.1")).await.unwrap();
+    }
+    manager.authenticate_client(ip("10.0.0.2")).await.unwrap();
+
+    assert_eq!(pam.calls(), 2);
+    let stats = manager.address_gate().unwrap().stats();
+    assert_eq!(stats.cache_hits, 4);
+    assert_eq!(stats.pam_invocations, 2);
+    assert_eq!(stats.cached_entries, 2);
+}
+
+#[tokio::test]
+async fn expired_verdict_is_re_evaluated() {
+    let pam = MockPam::new(&[]);
+    let manager =
+        AuthManager::with_address_authenticator(&address_config(1, 0), pam.clone()).unwrap();
+
+    manager.authenticate_client(ip("10.0.0.1")).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    manager.authenticate_client(ip("10.0.0.1")).await.unwrap();
+
+    assert_eq!(pam.calls(), 2);
+}
+
+#[tokio::test]
+async fn rejections_use_the_negative_ttl() {
+    let pam = MockPam::new(&["10.0.0.9"]);
+
+    let uncached =
+        AuthManager::with_address_authenticator(&address_config(60, 0), pam.clone()).unwrap();
+    assert!(uncached.authenticate_client(ip("10.0.0.9")).await.is_err());
+    assert!(uncached.authenticate_client(ip("10.0.0.9")).await.is_err());
+    assert_eq!(pam.calls(), 2);
+
+    let cached =
+        AuthManager::with_address_authenticator(&address_config(0, 60), pam.clone()).unwrap();
+    assert!(cached.authenticate_client(ip("10.0.0.9")).await.is_err());
+    assert!(cached.authenticate_client(ip("10.0.0.9")).await.is_err());
+    assert_eq!(pam.calls(), 3);
+}
+
+#[tokio::test]
+async fn unavailable_backend_is_never_cached() {
+    let pam = MockPam::unavailable();
+    let manager =
+        AuthManager::with_address_authenticator(&address_config(60, 60), pam.clone()).unwrap();
+
+    assert!(manager.authenticate_client(ip("10.0.0.1")).await.is_err());
+    assert!(manager.authenticate_client(ip("10.0.0.1")).await.is_err());
+
+    assert_eq!(pam.calls(), 2);
+}
+
+#[tokio::test]
+async fn allow_and_deny_lists_short_circuit_pam() {
+    let pam = MockPam::new(&[]);
+    let mut config = address_config(0, 0);
+    config.client_allow = vec!["192.168.0.0/16".to_string()];
+    config.client_deny = vec!["192.168.66.0/24".to_string(), "10.9.9.9".to_string()];
+    let manager = AuthManager::with_address_authenticator(&config, pam.clone()).unwrap();
+
+    manager
+        .authenticate_client(ip("192.168.1.10"))
+        .await
+        .unwrap();
+    // Deny wins over a broader allow entry
+    assert!(manager
+        .authenticate_client(ip("192.168.66.1"))
+        .await
+        .is_err());
+    assert!(manager.authenticate_client(ip("10.9.9.9")).await.is_err());
+    assert_eq!(pam.calls(), 0);
+
+    manager.authenticate_client(ip("172.16.0.1")).await.unwrap();
+    assert_eq!(pam.calls(), 1);
+
+    let stats = manager.address_gate().unwrap().stats();
+    assert_eq!(stats.allow_list_hits, 1);
+    assert_eq!(stats.deny_list_hits, 2);
+}
+
+#[tokio::test]
+async fn socks_stage_shares_the_client_stage_cache() {
+    let pam = MockPam::new(&[]);
+    let mut config = address_config(60, 0);
+    config.socks_method = "pam.address".to_string();
+    let manager = AuthManager::with_address_authenticator(&config, pam.clone()).unwrap();
+
+    manager.authenticate_client(ip("10.0.0.1")).await.unwrap();
+    let (mut stream, _peer) = tokio::io::duplex(64);
+    manager
+        .authenticate(
+            &mut stream,
+            rustsocks::protocol::AuthMethod::NoAuth,
+            ip("10.0.0.1"),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(pam.calls(), 1);
+}
+
+fn api_state(manager: &AuthManager) -> ApiState {
+    ApiState {
+        session_manager: Arc::new(SessionManager::new()),
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: manager.address_gate(),
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    }
+}
+
+async fn post_invalidate(
+    state: ApiState,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, Vec<u8>) {
+    let app = Router::new()
+        .route(
+            "/api/auth/address-cache/invalidate",
+            post(invalidate_address_cache),
+        )
+        .with_state(state);
+
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/api/auth/address-cache/invalidate");
+    let body = match body {
+        Some(body) => {
+            request = request.header("content-type", "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+
+    let response = app.oneshot(request.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, body.to_vec())
+}
+
+#[tokio::test]
+async fn invalidate_endpoint_forces_re_evaluation() {
+    let pam = MockPam::new(&[]);
+    let manager =
+        AuthManager::with_address_authenticator(&address_config(60, 0), pam.clone()).unwrap();
+    manager.authenticate_client(ip("10.0.0.1")).await.unwrap();
+    manager.authenticate_client(ip("10.0.0.2")).await.unwrap();
+
+    let (status, body) = post_invalidate(
+        api_state(&manager),
+        Some(serde_json::json!({ "ip": "10.0.0.1" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["removed"], 1);
+    assert_eq!(result["stats"]["cached_entries"], 1);
+
+    manager.authenticate_client(ip("10.0.0.1")).await.unwrap();
+    manager.authenticate_client(ip("10.0.0.2")).await.unwrap();
+    assert_eq!(pam.calls(), 3);
+
+    let (status, body) = post_invalidate(api_state(&manager), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["removed"], 2);
+}
+
+#[tokio::test]
+async fn invalidate_endpoint_rejects_bad_requests() {
+    let pam = MockPam::new(&[]);
+    let manager = AuthManager::with_address_authenticator(&address_config(60, 0), pam).unwrap();
+    let (status, _) = post_invalidate(
+        api_state(&manager),
+        Some(serde_json::json!({ "ip": "not-an-ip" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let without_pam = AuthManager::new(&AuthConfig::default()).unwrap();
+    let (status, _) = post_invalidate(api_state(&without_pam), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -24,6 +24,8 @@ fn pam_settings() -> PamSettings {
         default_ruser: "rhostusr".to_string(),
         verbose: true,
         verify_service: false, // Don't verify in tests
+        address_cache_secs: 0,
+        negative_cache_secs: 0,
     }
 }
 
@@ -37,9 +39,19 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "none".to_string(),
             socks_method: "pam.username".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let result = AuthManager::new(&config);
@@ -55,9 +67,19 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let result = AuthManager::new(&config);
@@ -73,9 +95,19 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let auth_manager = AuthManager::new(&config).expect("Failed to create auth manager");
@@ -99,12 +131,22 @@ mod unix_tests {
         let mut config = AuthConfig {
             client_method: "none".to_string(),
             socks_method: "pam.username".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: PamSettings {
                 username_service: "".to_string(), // Empty!
                 ..pam_settings()
             },
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let result = AuthManager::new(&config);
@@ -124,9 +166,19 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "pam.username".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let result = AuthManager::new(&config);
@@ -141,12 +193,23 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "userpass".to_string(), // Invalid for client_method
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![User {
                 username: "test".to_string(),
-                password: "test".to_string(),
+                password: "test".to_string().into(),
+                password_hash: None,
             }],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         // This should fail during config validation
@@ -164,9 +227,19 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let auth_manager = AuthManager::new(&config).expect("Failed to create auth manager");
@@ -185,9 +258,19 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let auth_manager = AuthManager::new(&config).expect("Failed to create auth manager");
@@ -218,9 +301,19 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let auth_manager =
@@ -257,7 +350,9 @@ mod unix_tests {
         let mut config = AuthConfig {
             client_method: "none".to_string(),
             socks_method: "pam.username".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: PamSettings {
                 username_service: "".to_string(), // Empty
                 address_service: "rustsocks-client-test".to_string(),
@@ -265,8 +360,18 @@ mod unix_tests {
                 default_ruser: "rhostusr".to_string(),
                 verbose: false,
                 verify_service: false,
+                address_cache_secs: 0,
+                negative_cache_secs: 0,
             },
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         // Empty username_service should fail
@@ -288,7 +393,9 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: PamSettings {
                 username_service: "rustsocks-test".to_string(),
                 address_service: "".to_string(), // Empty
@@ -296,8 +403,18 @@ mod unix_tests {
                 default_ruser: "rhostusr".to_string(),
                 verbose: false,
                 verify_service: false,
+                address_cache_secs: 0,
+                negative_cache_secs: 0,
             },
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         // Empty address_service should fail
@@ -312,7 +429,9 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "none".to_string(),
             socks_method: "pam.username".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: PamSettings {
                 username_service: "rustsocks-test".to_string(),
                 address_service: "rustsocks-client-test".to_string(),
@@ -320,8 +439,18 @@ mod unix_tests {
                 default_ruser: "rhostusr".to_string(),
                 verbose: true, // Enable verbose
                 verify_service: false,
+                address_cache_secs: 0,
+                negative_cache_secs: 0,
             },
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         // Should succeed with verbose enabled
@@ -335,7 +464,9 @@ mod unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: PamSettings {
                 username_service: "rustsocks-test".to_string(),
                 address_service: "rustsocks-client-test".to_string(),
@@ -343,8 +474,18 @@ mod unix_tests {
                 default_ruser: "customruser".to_string(),
                 verbose: false,
                 verify_service: false,
+                address_cache_secs: 0,
+                negative_cache_secs: 0,
             },
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let result = AuthManager::new(&config);
@@ -361,9 +502,19 @@ mod non_unix_tests {
         let config = AuthConfig {
             client_method: "none".to_string(),
             socks_method: "pam.username".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let result = AuthManager::new(&config);
@@ -384,9 +535,19 @@ mod non_unix_tests {
         let config = AuthConfig {
             client_method: "pam.address".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: pam_settings(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         };
 
         let result = AuthManager::new(&config);
@@ -416,12 +577,23 @@ async fn test_non_pam_methods_still_work() {
     let config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "userpass".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![User {
             username: "alice".to_string(),
-            password: "secret123".to_string(),
+            password: "secret123".to_string().into(),
+            password_hash: None,
         }],
+        users_file: None,
         pam: PamSettings::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let result = AuthManager::new(&config);
@@ -436,9 +608,19 @@ async fn test_none_auth_works() {
     let config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: PamSettings::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
 
     let auth_manager = AuthManager::new(&config).expect("None auth should always work");
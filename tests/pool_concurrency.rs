@@ -184,9 +184,11 @@ async fn pool_mutex_contention_benchmark() {
     let server_addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -87,9 +87,11 @@ async fn pool_evicts_expired_connections() {
 
     // Accept connections in background
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -129,9 +131,11 @@ async fn pool_enforces_per_destination_limit_strictly() {
     let addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -172,9 +176,11 @@ async fn pool_enforces_global_limit_with_multiple_destinations() {
         let addr = listener.local_addr().unwrap();
 
         tokio::spawn(async move {
+            // Keep the upstream side open, or pooled connections fail the liveness check
+            let mut open = Vec::new();
             loop {
                 if let Ok((stream, _)) = listener.accept().await {
-                    drop(stream);
+                    open.push(stream);
                 }
             }
         });
@@ -221,9 +227,11 @@ async fn pool_stats_accurate_after_operations() {
     let addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -279,9 +287,11 @@ async fn pool_disabled_never_stores_connections() {
     let addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -314,9 +324,11 @@ async fn pool_handles_simultaneous_put_operations() {
     let addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -359,9 +371,11 @@ async fn pool_reuses_most_recent_connection() {
     let addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -402,9 +416,11 @@ async fn pool_creates_new_connection_on_empty_pool() {
     let addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -435,9 +451,11 @@ async fn pool_handles_multiple_destinations_correctly() {
         let addr = listener.local_addr().unwrap();
 
         tokio::spawn(async move {
+            // Keep the upstream side open, or pooled connections fail the liveness check
+            let mut open = Vec::new();
             loop {
                 if let Ok((stream, _)) = listener.accept().await {
-                    drop(stream);
+                    open.push(stream);
                 }
             }
         });
@@ -488,9 +506,11 @@ async fn pool_cleanup_task_runs_periodically() {
     let addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
+        // Keep the upstream side open, or pooled connections fail the liveness check
+        let mut open = Vec::new();
         loop {
             if let Ok((stream, _)) = listener.accept().await {
-                drop(stream);
+                open.push(stream);
             }
         }
     });
@@ -686,3 +706,49 @@ async fn pool_refresh_hint_replenishes_idle_connection() {
     drop(pooled);
     drop(refreshed);
 }
+
+#[tokio::test]
+async fn pool_skips_connections_closed_by_upstream() {
+    let pool_config = PoolConfig {
+        enabled: true,
+        max_idle_per_dest: 5,
+        max_total_idle: 100,
+        idle_timeout_secs: 90,
+        connect_timeout_ms: 5000,
+    };
+    let pool = Arc::new(ConnectionPool::new(pool_config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            if tx.send(stream).is_err() {
+                break;
+            }
+        }
+    });
+
+    let stream = pool.get(addr).await.unwrap();
+    let server_side = rx.recv().await.expect("connection accepted");
+    pool.put(addr, stream, ReuseHint::Reuse).await;
+    assert_eq!(pool.stats().total_idle, 1);
+
+    // The upstream closes the idle connection
+    drop(server_side);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut stream = pool.get(addr).await.unwrap();
+    let mut server_side = rx.recv().await.expect("fresh connection dialed");
+    stream.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    server_side.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+
+    let stats = pool.stats();
+    assert_eq!(stats.validation_failures, 1);
+    assert_eq!(stats.pool_hits, 0);
+    assert_eq!(stats.total_created, 2);
+    assert_eq!(stats.total_idle, 0);
+    assert_eq!(stats.per_destination[0].validation_failures, 1);
+}
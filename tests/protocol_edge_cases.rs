@@ -129,7 +129,7 @@ async fn test_userpass_auth_max_username_length() {
     assert!(result.is_ok());
     let (username, password) = result.unwrap();
     assert_eq!(username.len(), 255);
-    assert_eq!(password, "password");
+    assert_eq!(password.as_str(), "password");
 }
 
 #[tokio::test]
@@ -161,10 +161,12 @@ async fn test_userpass_auth_zero_length_username() {
     let mut stream = MockStream::new(data);
     let result = parse_userpass_auth(&mut stream).await;
 
-    assert!(result.is_ok());
-    let (username, password) = result.unwrap();
-    assert_eq!(username, "");
-    assert_eq!(password, "password");
+    // RFC 1929 requires ULEN >= 1; the client gets the failure status
+    match result.unwrap_err() {
+        RustSocksError::Protocol(msg) => assert!(msg.contains("Empty username"), "{}", msg),
+        other => panic!("Expected Protocol error, got {:?}", other),
+    }
+    assert_eq!(stream.write_buf, [0x01, 0x01]);
 }
 
 #[tokio::test]
@@ -178,10 +180,12 @@ async fn test_userpass_auth_zero_length_password() {
     let mut stream = MockStream::new(data);
     let result = parse_userpass_auth(&mut stream).await;
 
-    assert!(result.is_ok());
-    let (username, password) = result.unwrap();
-    assert_eq!(username, "alice");
-    assert_eq!(password, "");
+    // RFC 1929 requires PLEN >= 1; the client gets the failure status
+    match result.unwrap_err() {
+        RustSocksError::Protocol(msg) => assert!(msg.contains("Empty password"), "{}", msg),
+        other => panic!("Expected Protocol error, got {:?}", other),
+    }
+    assert_eq!(stream.write_buf, [0x01, 0x01]);
 }
 
 #[tokio::test]
@@ -422,7 +426,7 @@ async fn test_udp_packet_roundtrip() {
     let original_packet = UdpPacket {
         header: UdpHeader {
             frag: 0,
-            address: Address::Domain("example.com".to_string()),
+            address: Address::Domain("example.com".into()),
             port: 8080,
         },
         data: Bytes::from_static(b"Hello, UDP!"),
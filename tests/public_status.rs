@@ -0,0 +1,138 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::api::handlers::{get_public_status, get_public_status_page};
+use rustsocks::config::Config;
+use rustsocks::qos::QosEngine;
+use rustsocks::server::pool::{ConnectionPool, PoolConfig};
+use rustsocks::session::{ConnectionInfo, SessionManager, SessionProtocol};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+const USERNAME: &str = "status-page-alice";
+const DESTINATION: &str = "secret-intranet.example.org";
+
+fn create_api_state(session_manager: Arc<SessionManager>, config: Config) -> ApiState {
+    ApiState {
+        session_manager,
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(config),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    }
+}
+
+async fn router_with_sessions(config: Config) -> Router {
+    let session_manager = Arc::new(SessionManager::new());
+    for port in 0..12 {
+        let conn_info = ConnectionInfo {
+            source_ip: "10.20.30.40".parse::<IpAddr>().unwrap(),
+            source_port: 40000 + port,
+            dest_ip: DESTINATION.into(),
+            dest_port: 443,
+            protocol: SessionProtocol::Tcp,
+            authenticated_user: Some(USERNAME.into()),
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
+        };
+        session_manager
+            .new_session(USERNAME, conn_info, "allow", None)
+            .await;
+    }
+
+    Router::new()
+        .route("/status", get(get_public_status_page))
+        .route("/status.json", get(get_public_status))
+        .with_state(create_api_state(session_manager, config))
+}
+
+async fn fetch(app: &Router, uri: &str) -> (StatusCode, String) {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn status_page_exposes_only_sanitized_data() {
+    let mut config = Config::default();
+    config.sessions.public_status_enabled = true;
+    let app = router_with_sessions(config).await;
+
+    let (status, body) = fetch(&app, "/status.json").await;
+    assert_eq!(status, StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["state"], "up");
+    assert_eq!(json["active_sessions"], "11-100");
+    assert!(json["throughput_bytes_per_sec"].is_null());
+    assert!(json["throughput_human"].is_null());
+    assert!(json["maintenance_message"].is_null());
+
+    let (status, page) = fetch(&app, "/status").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("Operational"));
+    assert!(page.contains("11-100"));
+
+    for body in [&body, &page] {
+        for secret in [USERNAME, DESTINATION, "10.20.30.40"] {
+            assert!(!body.contains(secret), "status leaks {}: {}", secret, body);
+        }
+    }
+}
+
+#[tokio::test]
+async fn maintenance_message_sets_the_state() {
+    let mut config = Config::default();
+    config.sessions.public_status_enabled = true;
+    config.sessions.maintenance_message = Some("Upgrade <tonight>".to_string());
+    let app = router_with_sessions(config).await;
+
+    let (_, body) = fetch(&app, "/status.json").await;
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["state"], "maintenance");
+    assert_eq!(json["maintenance_message"], "Upgrade <tonight>");
+
+    let (_, page) = fetch(&app, "/status").await;
+    assert!(page.contains("Upgrade &lt;tonight&gt;"));
+}
+
+#[tokio::test]
+async fn status_page_is_not_found_when_disabled() {
+    let app = router_with_sessions(Config::default()).await;
+
+    for uri in ["/status", "/status.json"] {
+        let (status, body) = fetch(&app, uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+        assert!(body.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+//! Per-user connection slots are released on every exit from the handshake, so
+//! refused connections never lock a user out of their connection limit
+mod common;
+
+use rustsocks::acl::types::GlobalAclConfig;
+use rustsocks::acl::{AclConfig, AclEngine, Action};
+use rustsocks::qos::{ConnectionLimits, HtbConfig, PerIpConfig, QosConfig, QosEngine};
+use rustsocks::server::{accept_loop, AcceptOptions, ClientHandlerContext};
+use rustsocks::session::SessionManager;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+const LIMITS: ConnectionLimits = ConnectionLimits {
+    max_connections_per_user: 1,
+    max_connections_global: 100,
+};
+
+async fn qos_engine() -> QosEngine {
+    QosEngine::from_config(QosConfig {
+        enabled: true,
+        algorithm: "htb".to_string(),
+        htb: HtbConfig::default(),
+        connection_limits: LIMITS,
+        per_ip: PerIpConfig::default(),
+    })
+    .await
+    .expect("create QoS engine")
+}
+
+/// Proxy whose ACL blocks every destination
+async fn spawn_proxy(session_manager: Arc<SessionManager>, qos_engine: QosEngine) -> SocketAddr {
+    let block_all = AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![],
+        groups: vec![],
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let addr = listener.local_addr().expect("proxy addr");
+    let ctx = Arc::new(ClientHandlerContext {
+        acl_engine: Some(Arc::new(AclEngine::new(block_all).expect("acl engine"))),
+        qos_engine,
+        connection_limits: LIMITS.into(),
+        enable_socks4: true,
+        ..common::handler_context(session_manager)
+    });
+    tokio::spawn(accept_loop(listener, ctx, AcceptOptions::default()));
+    addr
+}
+
+/// SOCKS5 no-auth CONNECT to 127.0.0.1:9; returns the reply code
+async fn socks5_connect(proxy: SocketAddr) -> u8 {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    stream
+        .write_all(&[0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1, 0, 9])
+        .await
+        .unwrap();
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    reply[1]
+}
+
+/// SOCKS4 CONNECT to 127.0.0.1:9; returns the reply code
+async fn socks4_connect(proxy: SocketAddr) -> u8 {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    stream
+        .write_all(&[0x04, 0x01, 0, 9, 127, 0, 0, 1, 0x00])
+        .await
+        .unwrap();
+    let mut reply = [0u8; 8];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    reply[1]
+}
+
+/// The handler may still be unwinding after the reply was read
+async fn wait_for_no_connections(qos_engine: &QosEngine) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while qos_engine.get_total_connections() > 0 && Instant::now() < deadline {
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn acl_blocked_connections_release_their_slots() {
+    let session_manager = Arc::new(SessionManager::new());
+    let qos_engine = qos_engine().await;
+    let proxy = spawn_proxy(session_manager.clone(), qos_engine.clone()).await;
+
+    for _ in 0..1000 {
+        assert_eq!(socks5_connect(proxy).await, 0x02, "blocked by ACL");
+    }
+    for _ in 0..10 {
+        assert_eq!(socks4_connect(proxy).await, 0x5B, "blocked by ACL");
+    }
+    wait_for_no_connections(&qos_engine).await;
+
+    assert_eq!(qos_engine.get_user_connections("anonymous"), 0);
+    assert_eq!(qos_engine.get_total_connections(), 0);
+    // With a limit of one, a leaked slot would have turned the ACL blocks into
+    // connection-limit rejections
+    let stats = session_manager.get_stats(Duration::from_secs(3600)).await;
+    assert_eq!(stats.admission.user_connections, 0);
+}
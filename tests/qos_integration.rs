@@ -1,6 +1,7 @@
-use rustsocks::qos::{ConnectionLimits, HtbConfig, QosConfig, QosEngine};
+use rustsocks::qos::{ConnectionLimits, HtbConfig, PerIpConfig, QosConfig, QosEngine};
 use rustsocks::server::proxy::{proxy_data, TrafficUpdateConfig};
 use rustsocks::session::{ConnectionInfo, SessionManager, SessionProtocol, SessionStatus};
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
@@ -39,9 +40,14 @@ async fn bandwidth_throttling_enforced_by_proxy() {
     let connection_info = ConnectionInfo {
         source_ip: server_client_stream.peer_addr().unwrap().ip(),
         source_port: server_client_stream.peer_addr().unwrap().port(),
-        dest_ip: upstream_addr.ip().to_string(),
+        dest_ip: upstream_addr.ip().to_string().into(),
         dest_port: upstream_addr.port(),
         protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
     };
 
     let (session_id, cancel_token) = session_manager
@@ -84,6 +90,7 @@ async fn bandwidth_throttling_enforced_by_proxy() {
         TrafficUpdateConfig::new(10),
         qos_clone,
         Arc::<str>::from("throttle-user"),
+        IpAddr::from([127, 0, 0, 1]),
     ));
 
     let chunk = vec![0xAB; 65_536];
@@ -190,3 +197,81 @@ async fn fair_sharing_allocations_even_between_users() {
         qos_engine.dec_user_connection(user);
     }
 }
+
+#[tokio::test]
+async fn idle_ip_buckets_are_evicted_after_a_relay_closes() {
+    let session_manager = Arc::new(SessionManager::new());
+    let qos_engine = QosEngine::from_config(QosConfig {
+        enabled: true,
+        htb: HtbConfig {
+            idle_timeout_secs: 1,
+            ..HtbConfig::default()
+        },
+        per_ip: PerIpConfig {
+            enabled: true,
+            ..PerIpConfig::default()
+        },
+        ..QosConfig::default()
+    })
+    .await
+    .expect("create QoS engine");
+
+    let client_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let (client_peer, client_side) = tokio::join!(
+        TcpStream::connect(client_listener.local_addr().unwrap()),
+        client_listener.accept()
+    );
+    let mut client_peer = client_peer.unwrap();
+    let (client_side, _) = client_side.unwrap();
+    let upstream_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let (upstream_side, upstream_peer) = tokio::join!(
+        TcpStream::connect(upstream_listener.local_addr().unwrap()),
+        upstream_listener.accept()
+    );
+    let (mut upstream_peer, _) = upstream_peer.unwrap();
+
+    let connection_info = ConnectionInfo {
+        source_ip: IpAddr::from([127, 0, 0, 1]),
+        source_port: client_side.peer_addr().unwrap().port(),
+        dest_ip: "127.0.0.1".into(),
+        dest_port: upstream_listener.local_addr().unwrap().port(),
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+    let (session_id, cancel_token) = session_manager
+        .new_session_with_control("guest", connection_info, "allow", None, None)
+        .await;
+
+    // The relay holds its own clone of the engine and drops it when it ends
+    let relay = tokio::spawn(proxy_data(
+        client_side,
+        upstream_side.unwrap(),
+        session_manager.clone(),
+        session_id,
+        cancel_token,
+        TrafficUpdateConfig::new(10),
+        qos_engine.clone(),
+        Arc::<str>::from("guest"),
+        IpAddr::from([127, 0, 0, 1]),
+    ));
+    client_peer.write_all(b"hello").await.unwrap();
+    let mut received = [0u8; 5];
+    upstream_peer.read_exact(&mut received).await.unwrap();
+    client_peer.shutdown().await.unwrap();
+    upstream_peer.shutdown().await.unwrap();
+    let _ = relay.await.expect("join relay");
+    assert_eq!(qos_engine.get_ip_allocations().await.len(), 1);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !qos_engine.get_ip_allocations().await.is_empty() {
+        assert!(
+            Instant::now() < deadline,
+            "idle per-IP bucket was not evicted after the relay closed"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
@@ -7,7 +7,7 @@
 
 #![allow(unexpected_cfgs)]
 
-use rustsocks::qos::{ConnectionLimits, HtbConfig, QosConfig, QosEngine};
+use rustsocks::qos::{ConnectionLimits, HtbConfig, PerIpConfig, QosConfig, QosEngine};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::{sleep, Instant};
@@ -65,6 +65,7 @@ mod token_bucket_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -100,6 +101,7 @@ mod token_bucket_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -143,6 +145,7 @@ mod token_bucket_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -184,6 +187,7 @@ mod token_bucket_tests {
                 algorithm: "htb".to_string(),
                 htb: config.clone(),
                 connection_limits: ConnectionLimits::default(),
+                per_ip: PerIpConfig::default(),
             })
             .await
             .expect("create QoS engine"),
@@ -220,6 +224,7 @@ mod token_bucket_tests {
             algorithm: "htb".to_string(),
             htb: config,
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -250,6 +255,7 @@ mod connection_counting_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -265,6 +271,7 @@ mod connection_counting_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -288,6 +295,7 @@ mod connection_counting_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -315,6 +323,7 @@ mod connection_counting_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -335,6 +344,7 @@ mod connection_counting_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: limits.clone(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -362,6 +372,7 @@ mod connection_counting_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: limits.clone(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -378,6 +389,40 @@ mod connection_counting_tests {
             .contains("Global connection limit"));
     }
 
+    #[tokio::test]
+    async fn connection_slot_is_released_on_drop() {
+        let limits = ConnectionLimits {
+            max_connections_per_user: 1,
+            max_connections_global: 100,
+        };
+
+        let qos = QosEngine::from_config(QosConfig {
+            enabled: true,
+            algorithm: "htb".to_string(),
+            htb: HtbConfig::default(),
+            connection_limits: limits.clone(),
+            per_ip: PerIpConfig::default(),
+        })
+        .await
+        .expect("create QoS engine");
+        let alice = Arc::<str>::from("alice");
+
+        let slot = qos.acquire_connection(&alice, &limits).unwrap();
+        assert_eq!(qos.get_user_connections("alice"), 1);
+        assert!(qos.acquire_connection(&alice, &limits).is_err());
+        assert_eq!(
+            qos.get_user_connections("alice"),
+            1,
+            "a refused slot is not counted"
+        );
+
+        drop(slot);
+        assert_eq!(qos.get_user_connections("alice"), 0);
+        assert_eq!(qos.get_total_connections(), 0);
+        drop(qos.acquire_connection(&alice, &limits).unwrap());
+        assert_eq!(qos.get_user_connections("alice"), 0);
+    }
+
     #[tokio::test]
     async fn concurrent_increment_operations_safe() {
         let qos = Arc::new(
@@ -386,6 +431,7 @@ mod connection_counting_tests {
                 algorithm: "htb".to_string(),
                 htb: HtbConfig::default(),
                 connection_limits: ConnectionLimits::default(),
+                per_ip: PerIpConfig::default(),
             })
             .await
             .expect("create QoS engine"),
@@ -438,6 +484,7 @@ mod bandwidth_allocation_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -471,6 +518,7 @@ mod bandwidth_allocation_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -486,11 +534,7 @@ mod bandwidth_allocation_tests {
         qos.allocate_bandwidth("user1", 100_000).await.unwrap();
         let elapsed = start.elapsed();
 
-        assert_fast(
-            elapsed,
-            20,
-            "borrowing should use max bucket burst"
-        );
+        assert_fast(elapsed, 20, "borrowing should use max bucket burst");
     }
 
     #[tokio::test]
@@ -512,6 +556,7 @@ mod bandwidth_allocation_tests {
                 algorithm: "htb".to_string(),
                 htb: config.clone(),
                 connection_limits: ConnectionLimits::default(),
+                per_ip: PerIpConfig::default(),
             })
             .await
             .expect("create QoS engine"),
@@ -556,6 +601,7 @@ mod bandwidth_allocation_tests {
                 algorithm: "htb".to_string(),
                 htb: config.clone(),
                 connection_limits: ConnectionLimits::default(),
+                per_ip: PerIpConfig::default(),
             })
             .await
             .expect("create QoS engine"),
@@ -617,6 +663,7 @@ mod fair_sharing_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -674,6 +721,7 @@ mod fair_sharing_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -730,6 +778,7 @@ mod fair_sharing_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -774,6 +823,7 @@ mod fair_sharing_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -815,6 +865,7 @@ mod qos_engine_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -836,6 +887,7 @@ mod qos_engine_tests {
             algorithm: "unknown-algo".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await;
 
@@ -852,6 +904,7 @@ mod qos_engine_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -900,6 +953,7 @@ mod qos_engine_tests {
             algorithm: "htb".to_string(),
             htb: custom_htb.clone(),
             connection_limits: custom_limits.clone(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -919,6 +973,78 @@ mod qos_engine_tests {
     }
 }
 
+// ============================================================================
+// Rate Units in Configuration
+// ============================================================================
+
+mod rate_unit_tests {
+    use super::*;
+
+    #[test]
+    fn htb_config_accepts_units_and_plain_bytes() {
+        let config: HtbConfig = toml::from_str(
+            r#"
+            global_bandwidth_bytes_per_sec = "1gbit"
+            guaranteed_bandwidth_bytes_per_sec = "12.5MBps"
+            max_bandwidth_bytes_per_sec = 50000000
+            burst_size_bytes = "2MiB"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.global_bandwidth_bytes_per_sec, 125_000_000);
+        assert_eq!(config.guaranteed_bandwidth_bytes_per_sec, 12_500_000);
+        assert_eq!(config.max_bandwidth_bytes_per_sec, 50_000_000);
+        assert_eq!(config.burst_size_bytes, 2 * 1_048_576);
+    }
+
+    #[test]
+    fn ambiguous_units_fail_to_parse() {
+        let err = toml::from_str::<HtbConfig>(r#"max_bandwidth_bytes_per_sec = "100m""#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("ambiguous"), "{}", err);
+
+        assert!(toml::from_str::<HtbConfig>("burst_size_bytes = -1").is_err());
+        assert!(toml::from_str::<HtbConfig>("global_bandwidth_bytes_per_sec = 1.5").is_err());
+    }
+
+    #[test]
+    fn defaults_are_written_with_units() {
+        let rendered = toml::to_string(&HtbConfig::default()).unwrap();
+        assert!(rendered.contains("global_bandwidth_bytes_per_sec = \"1Gbit\"\n"));
+        assert!(rendered.contains("guaranteed_bandwidth_bytes_per_sec = \"1Mibit\"\n"));
+        assert!(rendered.contains("max_bandwidth_bytes_per_sec = \"100Mbit\"\n"));
+        assert!(rendered.contains("burst_size_bytes = \"1MiB\"\n"));
+
+        let parsed: HtbConfig = toml::from_str(&rendered).unwrap();
+        assert_eq!(parsed.guaranteed_bandwidth_bytes_per_sec, 131_072);
+    }
+
+    #[test]
+    fn guaranteed_rate_in_bits_is_flagged() {
+        assert!(HtbConfig::default().warnings().is_empty());
+
+        // Meant 1 Mbps, written as kilobits
+        let config = HtbConfig {
+            guaranteed_bandwidth_bytes_per_sec: 1_000,
+            global_bandwidth_bytes_per_sec: 1_250_000_000,
+            ..HtbConfig::default()
+        };
+        let warnings = config.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("8 kbps (1 kB/s)"), "{}", warnings[0]);
+
+        // A low guaranteed rate is fine on a small link
+        let config = HtbConfig {
+            guaranteed_bandwidth_bytes_per_sec: 1_000,
+            global_bandwidth_bytes_per_sec: 1_000_000,
+            ..HtbConfig::default()
+        };
+        assert!(config.warnings().is_empty());
+    }
+}
+
 // ============================================================================
 // User Allocation Information Tests
 // ============================================================================
@@ -933,6 +1059,7 @@ mod user_allocation_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -976,6 +1103,7 @@ mod user_allocation_tests {
             algorithm: "htb".to_string(),
             htb: config.clone(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -1002,6 +1130,7 @@ mod user_allocation_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -1017,6 +1146,7 @@ mod user_allocation_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -1035,6 +1165,183 @@ mod user_allocation_tests {
     }
 }
 
+// ============================================================================
+// Per-Source-IP Shaping Tests
+// ============================================================================
+
+mod per_ip_tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    const SHARED_USER: &str = "guest-wifi";
+
+    async fn engine(htb: HtbConfig, per_ip: PerIpConfig) -> Arc<QosEngine> {
+        Arc::new(
+            QosEngine::from_config(QosConfig {
+                enabled: true,
+                algorithm: "htb".to_string(),
+                htb,
+                connection_limits: ConnectionLimits::default(),
+                per_ip,
+            })
+            .await
+            .expect("create QoS engine"),
+        )
+    }
+
+    fn per_ip(exempt: &[&str]) -> PerIpConfig {
+        PerIpConfig {
+            enabled: true,
+            max_bytes_per_sec: 100_000,
+            burst: 10_000,
+            exempt: exempt.iter().map(|net| net.to_string()).collect(),
+        }
+    }
+
+    /// Relay `bytes` in 5 KB chunks for each source IP at once, all as one user;
+    /// returns how long each source took
+    async fn relay_from(qos: &Arc<QosEngine>, ips: &[IpAddr], bytes: u64) -> Vec<Duration> {
+        let user: Arc<str> = Arc::from(SHARED_USER);
+        let tasks: Vec<_> = ips
+            .iter()
+            .map(|ip| {
+                let qos = qos.clone();
+                let user = user.clone();
+                let ip = *ip;
+                tokio::spawn(async move {
+                    let start = Instant::now();
+                    for _ in 0..bytes / 5_000 {
+                        qos.allocate_bandwidth_from(&user, ip, 5_000)
+                            .await
+                            .expect("allocate bandwidth");
+                    }
+                    start.elapsed()
+                })
+            })
+            .collect();
+
+        let mut elapsed = Vec::new();
+        for task in tasks {
+            elapsed.push(task.await.expect("relay task"));
+        }
+        elapsed
+    }
+
+    fn unlimited_htb() -> HtbConfig {
+        HtbConfig {
+            global_bandwidth_bytes_per_sec: 100_000_000,
+            guaranteed_bandwidth_bytes_per_sec: 50_000_000,
+            max_bandwidth_bytes_per_sec: 50_000_000,
+            burst_size_bytes: 50_000_000,
+            fair_sharing_enabled: false,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn each_source_ip_is_capped_at_the_per_ip_rate() {
+        let qos = engine(unlimited_htb(), per_ip(&["10.0.0.0/8"])).await;
+        let first: IpAddr = "192.168.1.10".parse().unwrap();
+        let second: IpAddr = "192.168.1.11".parse().unwrap();
+
+        // 60 KB at 100 KB/s after a 10 KB burst: at least 500 ms per address, however
+        // little the shared user has used
+        let elapsed = relay_from(&qos, &[first, second], 60_000).await;
+        for took in &elapsed {
+            assert!(
+                *took >= Duration::from_millis(400),
+                "expected per-IP throttling, got {:?}",
+                took
+            );
+        }
+
+        let allocations = qos.get_ip_allocations().await;
+        assert_eq!(allocations.len(), 2);
+        assert!(allocations.iter().all(|a| a.total_bytes == 60_000));
+        assert!(allocations.iter().all(|a| a.max_bandwidth == 100_000));
+
+        // Exempt addresses only answer to the user and global limits
+        let exempt: IpAddr = "10.1.2.3".parse().unwrap();
+        let elapsed = relay_from(&qos, &[exempt], 60_000).await;
+        assert_fast(elapsed[0], 100, "exempt source IP");
+        assert_eq!(qos.get_ip_allocations().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn shared_user_and_global_limits_still_bound_the_sum() {
+        let ips: [IpAddr; 2] = [
+            "192.168.1.10".parse().unwrap(),
+            "192.168.1.11".parse().unwrap(),
+        ];
+
+        // User limit: 80 KB through a guaranteed and a borrowed bucket of 20 KB/s each,
+        // both starting with a 10 KB burst, takes at least 1.5 s although each address
+        // alone would be done in 300 ms
+        let user_capped = HtbConfig {
+            guaranteed_bandwidth_bytes_per_sec: 20_000,
+            max_bandwidth_bytes_per_sec: 20_000,
+            burst_size_bytes: 10_000,
+            ..unlimited_htb()
+        };
+        let qos = engine(user_capped, per_ip(&[])).await;
+        let elapsed = relay_from(&qos, &ips, 40_000).await;
+        let slowest = elapsed.iter().max().unwrap();
+        assert!(
+            *slowest >= Duration::from_millis(1_200),
+            "expected the user limit to hold, got {:?}",
+            slowest
+        );
+
+        // Global limit: 80 KB at 40 KB/s after a 10 KB burst, at least 1.75 s
+        let global_capped = HtbConfig {
+            global_bandwidth_bytes_per_sec: 40_000,
+            burst_size_bytes: 10_000,
+            ..unlimited_htb()
+        };
+        let qos = engine(global_capped, per_ip(&[])).await;
+        let elapsed = relay_from(&qos, &ips, 40_000).await;
+        let slowest = elapsed.iter().max().unwrap();
+        assert!(
+            *slowest >= Duration::from_millis(1_400),
+            "expected the global limit to hold, got {:?}",
+            slowest
+        );
+    }
+
+    #[test]
+    fn per_ip_section_parses_with_units() {
+        let config: QosConfig = toml::from_str(
+            r#"
+            enabled = true
+
+            [per_ip]
+            enabled = true
+            max_bytes_per_sec = "20mbit"
+            burst = "256KiB"
+            exempt = ["10.0.0.0/8", "192.0.2.1"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.per_ip.enabled);
+        assert_eq!(config.per_ip.max_bytes_per_sec, 2_500_000);
+        assert_eq!(config.per_ip.burst, 256 * 1024);
+        assert_eq!(config.per_ip.exempt.len(), 2);
+        assert!(!QosConfig::default().per_ip.enabled);
+    }
+
+    #[tokio::test]
+    async fn invalid_exemption_is_rejected() {
+        let result = QosEngine::from_config(QosConfig {
+            enabled: true,
+            per_ip: per_ip(&["10.0.0.0/33"]),
+            ..QosConfig::default()
+        })
+        .await;
+        assert!(result.is_err());
+    }
+}
+
 // ============================================================================
 // Edge Cases and Error Handling
 // ============================================================================
@@ -1060,6 +1367,7 @@ mod edge_cases_tests {
             algorithm: "htb".to_string(),
             htb: config,
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -1089,6 +1397,7 @@ mod edge_cases_tests {
             algorithm: "htb".to_string(),
             htb: config,
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -1113,6 +1422,7 @@ mod edge_cases_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -1135,6 +1445,7 @@ mod edge_cases_tests {
             algorithm: "htb".to_string(),
             htb: HtbConfig::default(),
             connection_limits: ConnectionLimits::default(),
+            per_ip: PerIpConfig::default(),
         })
         .await
         .expect("create QoS engine");
@@ -1173,6 +1484,7 @@ mod edge_cases_tests {
                     max_connections_per_user: 100,
                     max_connections_global: 10000,
                 },
+                per_ip: PerIpConfig::default(),
             })
             .await
             .expect("create QoS engine"),
@@ -0,0 +1,356 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use futures::future::BoxFuture;
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, AclMatchedOn, AclStats, Action, Protocol};
+use rustsocks::api::handlers::sessions::{get_session_history, get_session_stats, ApiState};
+use rustsocks::config::Config;
+use rustsocks::protocol::{Address, ReplyCode};
+use rustsocks::qos::QosEngine;
+use rustsocks::server::{
+    handle_client, ClientHandlerContext, ConnectionPool, DestinationResolver, HostHints, PoolConfig,
+};
+use rustsocks::session::{HostSource, Session, SessionManager};
+use rustsocks::Result;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Duration;
+use tower::util::ServiceExt;
+
+/// Resolves every name to the local upstream.
+struct FixedResolver {
+    target: SocketAddr,
+}
+
+impl DestinationResolver for FixedResolver {
+    fn resolve<'a>(
+        &'a self,
+        _address: &'a Address,
+        _port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(async move { Ok(vec![self.target]) })
+    }
+}
+
+/// Upstream that accepts any number of connections and closes each immediately.
+async fn spawn_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            drop(stream);
+        }
+    });
+    addr
+}
+
+fn handler_context(
+    session_manager: Arc<SessionManager>,
+    upstream: SocketAddr,
+    host_hints: Option<Arc<HostHints>>,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        resolver: Arc::new(FixedResolver { target: upstream }),
+        host_hints,
+        ..common::handler_context(session_manager)
+    })
+}
+
+/// Run one CONNECT through the handler and wait for the session to close.
+async fn run_connect(ctx: Arc<ClientHandlerContext>, address: &[u8], port: u16) {
+    assert_eq!(
+        connect_reply(ctx, address, port).await,
+        ReplyCode::Succeeded as u8
+    );
+}
+
+/// Run one CONNECT through the handler; returns the reply code once the handler is done.
+async fn connect_reply(ctx: Arc<ClientHandlerContext>, address: &[u8], port: u16) -> u8 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+    let server_task = tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    request.extend_from_slice(address);
+    request.extend_from_slice(&port.to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    drop(client);
+
+    tokio::time::timeout(Duration::from_secs(5), server_task)
+        .await
+        .expect("handler finished")
+        .unwrap();
+    reply[1]
+}
+
+fn domain(name: &str) -> Vec<u8> {
+    let mut address = vec![0x03, name.len() as u8];
+    address.extend_from_slice(name.as_bytes());
+    address
+}
+
+fn loopback() -> Vec<u8> {
+    vec![0x01, 127, 0, 0, 1]
+}
+
+async fn last_closed(session_manager: &SessionManager) -> Session {
+    session_manager
+        .closed_snapshot()
+        .await
+        .last()
+        .cloned()
+        .expect("closed session")
+}
+
+#[tokio::test]
+async fn domain_connect_records_socks_request() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let ctx = handler_context(session_manager.clone(), upstream, None);
+
+    run_connect(ctx, &domain("app.example"), upstream.port()).await;
+
+    let session = last_closed(&session_manager).await;
+    assert_eq!(session.dest_ip.as_ref(), "app.example");
+    assert_eq!(session.requested_host.as_deref(), Some("app.example"));
+    assert_eq!(
+        session.requested_host_source,
+        Some(HostSource::SocksRequest)
+    );
+}
+
+#[tokio::test]
+async fn ip_connect_after_domain_connect_uses_reverse_map() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let hints = Arc::new(HostHints::new(Duration::from_secs(60)));
+    let ctx = handler_context(session_manager.clone(), upstream, Some(hints));
+
+    run_connect(ctx.clone(), &domain("app.example"), upstream.port()).await;
+    run_connect(ctx, &loopback(), upstream.port()).await;
+
+    let session = last_closed(&session_manager).await;
+    assert_eq!(session.dest_ip.as_ref(), "127.0.0.1");
+    assert_eq!(session.requested_host.as_deref(), Some("app.example"));
+    assert_eq!(session.requested_host_source, Some(HostSource::ReverseMap));
+    assert_eq!(session.logical_destination(), "app.example");
+}
+
+#[tokio::test]
+async fn ip_connect_after_resolve_is_tagged_resolve_extension() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let hints = Arc::new(HostHints::new(Duration::from_secs(60)));
+    let ctx = handler_context(session_manager.clone(), upstream, Some(hints.clone()));
+
+    let client: IpAddr = "127.0.0.1".parse().unwrap();
+    hints.record(
+        client,
+        &Arc::from("resolved.example"),
+        [upstream.ip()],
+        HostSource::ResolveExtension,
+    );
+    // A later domain CONNECT to another name must not demote the resolver hint
+    run_connect(ctx.clone(), &domain("other.example"), upstream.port()).await;
+    run_connect(ctx, &loopback(), upstream.port()).await;
+
+    let session = last_closed(&session_manager).await;
+    assert_eq!(session.requested_host.as_deref(), Some("resolved.example"));
+    assert_eq!(
+        session.requested_host_source,
+        Some(HostSource::ResolveExtension)
+    );
+}
+
+#[tokio::test]
+async fn ip_connect_without_hints_has_no_requested_host() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let ctx = handler_context(session_manager.clone(), upstream, None);
+
+    run_connect(ctx.clone(), &domain("app.example"), upstream.port()).await;
+    run_connect(ctx, &loopback(), upstream.port()).await;
+
+    let session = last_closed(&session_manager).await;
+    assert_eq!(session.requested_host, None);
+    assert_eq!(session.requested_host_source, None);
+    assert_eq!(session.logical_destination(), "127.0.0.1");
+}
+
+#[tokio::test]
+async fn history_and_stats_expose_requested_host() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let hints = Arc::new(HostHints::new(Duration::from_secs(60)));
+    let ctx = handler_context(session_manager.clone(), upstream, Some(hints));
+
+    run_connect(ctx.clone(), &domain("app.example"), upstream.port()).await;
+    run_connect(ctx, &loopback(), upstream.port()).await;
+
+    let state = ApiState {
+        session_manager: session_manager.clone(),
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    };
+    let app = Router::new()
+        .route("/api/sessions/history", get(get_session_history))
+        .route("/api/sessions/stats", get(get_session_stats))
+        .with_state(state);
+
+    let fetch = |uri: String| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    let history = fetch(
+        "/api/sessions/history?requested_host=app.example&requested_host_source=reverse_map"
+            .to_string(),
+    )
+    .await;
+    assert_eq!(history["total"], 1);
+    let session = &history["data"][0];
+    assert_eq!(session["dest_ip"], "127.0.0.1");
+    assert_eq!(session["requested_host"], "app.example");
+    assert_eq!(session["requested_host_source"], "reverse_map");
+
+    let by_host = fetch("/api/sessions/history?requested_host=app.example".to_string()).await;
+    assert_eq!(by_host["total"], 2);
+
+    let unknown = fetch("/api/sessions/history?requested_host_source=guess".to_string()).await;
+    assert_eq!(unknown["total"], 0);
+
+    let stats = fetch("/api/sessions/stats?group_by=requested_host".to_string()).await;
+    let top = &stats["top_destinations"][0];
+    assert_eq!(
+        top["destination"],
+        format!("app.example:{}", upstream.port())
+    );
+    assert_eq!(top["session_count"], 2);
+}
+
+/// Allows every name, blocks loopback addresses
+fn loopback_blocked_acl() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Allow,
+        },
+        users: vec![UserAcl {
+            username: "anonymous".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Block,
+                description: "Block loopback".to_string(),
+                destinations: vec!["127.0.0.0/8".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 1000,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+        groups: vec![],
+    }
+}
+
+#[tokio::test]
+async fn domain_connect_records_resolved_and_connected_addresses() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let ctx = handler_context(session_manager.clone(), upstream, None);
+
+    run_connect(ctx, &domain("app.example"), upstream.port()).await;
+
+    let session = last_closed(&session_manager).await;
+    assert_eq!(session.resolved_ips, [upstream.ip()]);
+    assert_eq!(session.connected_ip, Some(upstream.ip()));
+    // No ACL, nothing matched
+    assert_eq!(session.acl_matched_on, None);
+}
+
+#[tokio::test]
+async fn acl_blocks_a_name_that_resolves_to_a_blocked_address() {
+    let session_manager = Arc::new(SessionManager::new());
+    let upstream = spawn_upstream().await;
+    let acl_stats = Arc::new(AclStats::new());
+    let mut ctx = Arc::into_inner(handler_context(session_manager.clone(), upstream, None))
+        .expect("sole owner");
+    ctx.acl_engine = Some(Arc::new(
+        AclEngine::new(loopback_blocked_acl()).expect("acl engine"),
+    ));
+    ctx.acl_stats = acl_stats.clone();
+
+    // No rule names it, so only the address it resolves to can block it
+    let reply = connect_reply(Arc::new(ctx), &domain("rebound.example"), upstream.port()).await;
+    assert_eq!(reply, ReplyCode::ConnectionNotAllowed as u8);
+
+    let rejected = session_manager.rejected_snapshot().await;
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(
+        rejected[0].requested_host.as_deref(),
+        Some("rebound.example")
+    );
+    assert_eq!(rejected[0].resolved_ips, [upstream.ip()]);
+    assert_eq!(rejected[0].connected_ip, None);
+    assert_eq!(rejected[0].acl_matched_on, Some(AclMatchedOn::ResolvedIp));
+    assert_eq!(
+        rejected[0].acl_rule_matched.as_deref(),
+        Some("Block loopback")
+    );
+    assert!(session_manager.closed_snapshot().await.is_empty());
+
+    let stats = acl_stats.snapshot();
+    assert_eq!((stats.allowed, stats.blocked), (0, 1));
+}
@@ -65,8 +65,7 @@ async fn test_resolve_ipv6_all_ones() {
 #[tokio::test]
 async fn test_resolve_nonexistent_domain() {
     // Use a domain that should not exist
-    let addr =
-        Address::Domain("this-domain-definitely-does-not-exist-12345678990.invalid".to_string());
+    let addr = Address::Domain("this-domain-definitely-does-not-exist-12345678990.invalid".into());
     let result = resolve_address(&addr, 80).await;
 
     // Should return an error because the domain doesn't exist
@@ -76,7 +75,7 @@ async fn test_resolve_nonexistent_domain() {
 #[tokio::test]
 async fn test_resolve_invalid_tld() {
     // Use an invalid TLD that should fail DNS resolution
-    let addr = Address::Domain("example.invalidtld99999".to_string());
+    let addr = Address::Domain("example.invalidtld99999".into());
     let result = resolve_address(&addr, 80).await;
 
     // Should return an error
@@ -85,7 +84,7 @@ async fn test_resolve_invalid_tld() {
 
 #[tokio::test]
 async fn test_resolve_localhost() {
-    let addr = Address::Domain("localhost".to_string());
+    let addr = Address::Domain("localhost".into());
     let result = resolve_address(&addr, 8080).await;
 
     assert!(result.is_ok());
@@ -143,7 +142,7 @@ async fn test_resolve_domain_case_insensitivity() {
 
     let mut results = Vec::new();
     for domain in domains {
-        let addr = Address::Domain(domain.to_string());
+        let addr = Address::Domain(domain.into());
         let result = resolve_address(&addr, 8080).await;
         assert!(result.is_ok(), "Failed to resolve: {}", domain);
         results.push(result.unwrap());
@@ -163,7 +162,7 @@ async fn test_resolve_very_long_domain() {
     let label = "a".repeat(63); // Max label length
     let domain = format!("{}.{}.{}.{}", label, label, label, label); // ~255 chars - will be too long
 
-    let addr = Address::Domain(domain.clone());
+    let addr = Address::Domain(domain.clone().into());
     let result = resolve_address(&addr, 80).await;
 
     // This should fail because the domain is too long
@@ -173,7 +172,7 @@ async fn test_resolve_very_long_domain() {
 #[tokio::test]
 async fn test_resolve_domain_with_hyphens() {
     // Test valid domain with hyphens
-    let addr = Address::Domain("my-test-domain.example.com".to_string());
+    let addr = Address::Domain("my-test-domain.example.com".into());
     let result = resolve_address(&addr, 80).await;
 
     // This will fail because the domain doesn't exist, but it should be parsed correctly
@@ -184,7 +183,7 @@ async fn test_resolve_domain_with_hyphens() {
 #[tokio::test]
 async fn test_resolve_domain_with_numbers() {
     // Test domain with numbers (e.g., "example123.com")
-    let addr = Address::Domain("test123.example456.com".to_string());
+    let addr = Address::Domain("test123.example456.com".into());
     let result = resolve_address(&addr, 80).await;
 
     // Should fail due to non-existent domain, not parsing issues
@@ -194,7 +193,7 @@ async fn test_resolve_domain_with_numbers() {
 #[tokio::test]
 async fn test_resolve_ipv6_prefers_over_ipv4() {
     // When both IPv4 and IPv6 are available, IPv6 should come first
-    let addr = Address::Domain("localhost".to_string());
+    let addr = Address::Domain("localhost".into());
     let result = resolve_address(&addr, 8080).await;
 
     assert!(result.is_ok());
@@ -237,7 +236,7 @@ async fn test_resolve_concurrent_operations() {
         Address::IPv4([127, 0, 0, 1]),
         Address::IPv4([8, 8, 8, 8]),
         Address::IPv6([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
-        Address::Domain("localhost".to_string()),
+        Address::Domain("localhost".into()),
     ];
 
     // Spawn 50 concurrent resolution tasks
@@ -263,7 +262,7 @@ async fn test_resolve_with_timeout() {
     // Test that resolution doesn't hang indefinitely
     use tokio::time::{timeout, Duration};
 
-    let addr = Address::Domain("example.com".to_string());
+    let addr = Address::Domain("example.com".into());
 
     // Should complete within 5 seconds
     let result = timeout(Duration::from_secs(5), resolve_address(&addr, 80)).await;
@@ -325,7 +324,7 @@ async fn test_resolve_empty_results_handling() {
     // While it's hard to trigger with real DNS, we test the code path exists
 
     // Use an invalid domain that should fail
-    let addr = Address::Domain("".to_string());
+    let addr = Address::Domain("".into());
     let result = resolve_address(&addr, 80).await;
 
     // Empty domain should fail during resolution
@@ -0,0 +1,215 @@
+//! Descriptor guardrails (`server.guardrails`) under a connection storm
+//!
+//! The soft watermark is set a few dozen descriptors above what the test process
+//! already holds, so opening tunnels reaches it quickly.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use rustsocks::api::handlers::get_system_resources;
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::config::{Config, GuardrailSettings};
+use rustsocks::qos::QosEngine;
+use rustsocks::server::guardrails::open_fd_count;
+use rustsocks::server::{
+    accept_loop, spawn_resource_monitor, AcceptOptions, ClientHandlerContext, ConnectionPool,
+    GuardLevel, PoolConfig, ResourceGuard,
+};
+use rustsocks::session::SessionManager;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout, Duration};
+use tower::util::ServiceExt;
+
+/// Descriptors between the baseline and the soft watermark
+const HEADROOM: u64 = 40;
+const MAX_TUNNELS: usize = 100;
+
+fn handler_context(
+    session_manager: Arc<SessionManager>,
+    connection_pool: Arc<ConnectionPool>,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        connection_pool,
+        ..common::handler_context(session_manager)
+    })
+}
+
+fn api_state(
+    session_manager: Arc<SessionManager>,
+    connection_pool: Arc<ConnectionPool>,
+    guard: Arc<ResourceGuard>,
+) -> ApiState {
+    ApiState {
+        session_manager,
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool,
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: Some(guard),
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    }
+}
+
+async fn spawn_echo_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr
+}
+
+/// Open a CONNECT tunnel; `None` when the proxy dropped the connection instead.
+async fn try_open_tunnel(proxy: SocketAddr, upstream: SocketAddr) -> Option<TcpStream> {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    stream.write_all(&[0x05, 0x01, 0x00]).await.ok()?;
+    let mut method = [0u8; 2];
+    timeout(Duration::from_secs(2), stream.read_exact(&mut method))
+        .await
+        .ok()?
+        .ok()?;
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    stream.write_all(&request).await.ok()?;
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.ok()?;
+    assert_eq!(reply[1], 0x00, "CONNECT should succeed");
+    Some(stream)
+}
+
+async fn echoes(stream: &mut TcpStream) -> bool {
+    if stream.write_all(b"still here").await.is_err() {
+        return false;
+    }
+    let mut echo = [0u8; 10];
+    matches!(
+        timeout(Duration::from_secs(2), stream.read_exact(&mut echo)).await,
+        Ok(Ok(_)) if &echo == b"still here"
+    )
+}
+
+#[tokio::test]
+async fn soft_watermark_rejects_new_connections_only() {
+    let session_manager = Arc::new(SessionManager::new());
+    let connection_pool = Arc::new(ConnectionPool::new(PoolConfig::default()));
+    let upstream = spawn_echo_upstream().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy = listener.local_addr().expect("proxy addr");
+
+    let baseline = open_fd_count().expect("open descriptors");
+    let guard = Arc::new(ResourceGuard::new(&GuardrailSettings {
+        enabled: true,
+        fd_soft_limit: Some(baseline + HEADROOM),
+        fd_hard_limit: Some(baseline + HEADROOM * 100),
+        ..GuardrailSettings::default()
+    }));
+    let monitor = spawn_resource_monitor(
+        guard.clone(),
+        connection_pool.clone(),
+        session_manager.clone(),
+        Duration::from_millis(20),
+    );
+    tokio::spawn(accept_loop(
+        listener,
+        handler_context(session_manager.clone(), connection_pool.clone()),
+        AcceptOptions {
+            resource_guard: Some(guard.clone()),
+            ..AcceptOptions::default()
+        },
+    ));
+
+    let mut tunnels = Vec::new();
+    let mut rejected = false;
+    for _ in 0..MAX_TUNNELS {
+        match try_open_tunnel(proxy, upstream).await {
+            Some(tunnel) => tunnels.push(tunnel),
+            None => {
+                rejected = true;
+                break;
+            }
+        }
+        sleep(Duration::from_millis(30)).await;
+    }
+
+    assert!(rejected, "no rejection after {} tunnels", tunnels.len());
+    assert!(!tunnels.is_empty(), "rejected before any tunnel opened");
+    assert_eq!(guard.level(), GuardLevel::Soft);
+    assert!(guard.status().rejected_connections >= 1);
+
+    // Running transfers are untouched, and further attempts keep being turned away
+    for tunnel in &mut tunnels {
+        assert!(echoes(tunnel).await, "existing tunnel stopped relaying");
+    }
+    assert!(try_open_tunnel(proxy, upstream).await.is_none());
+
+    let app = Router::new()
+        .route("/api/system/resources", get(get_system_resources))
+        .with_state(api_state(session_manager, connection_pool, guard.clone()));
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/system/resources")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let resources: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(resources["process_open_fds"].as_u64().unwrap() >= baseline + HEADROOM);
+    assert_eq!(resources["guardrails"]["level"], "soft");
+    assert_eq!(
+        resources["guardrails"]["fd_soft_watermark"],
+        baseline + HEADROOM
+    );
+    assert!(
+        resources["guardrails"]["rejected_connections"]
+            .as_u64()
+            .unwrap()
+            >= 2
+    );
+
+    // Closing the tunnels brings the process back under the watermark
+    drop(tunnels);
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(guard.level(), GuardLevel::Normal);
+    assert!(try_open_tunnel(proxy, upstream).await.is_some());
+
+    monitor.abort();
+}
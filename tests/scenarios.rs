@@ -0,0 +1,839 @@
+/// Scenario tests: documented setups run as executable scripts
+///
+/// Every directory under `tests/scenarios/` is one scenario:
+///
+/// - `config.toml`: the server configuration, copied from the documentation.
+///   `${SCENARIO_DIR}` and `${WORK_DIR}` (a fresh temporary directory) are substituted
+///   before parsing. The work directory also holds a self-signed `server.crt` and
+///   `server.key` for `localhost`.
+/// - `acl.toml` (optional): the ACL file, referenced as `${SCENARIO_DIR}/acl.toml`.
+/// - `script.toml`: what the client does and what it must observe.
+///
+/// The harness boots `SocksServer` with exactly that configuration, except that the
+/// SOCKS listener and the API are moved to free loopback ports, and runs the steps in
+/// order. A key the parser would silently ignore fails the scenario, so a documented
+/// setting that no longer exists is caught as well.
+///
+/// Run a single scenario with `SCENARIO=<name> cargo test --test scenarios`.
+///
+/// `script.toml` format:
+///
+/// ```toml
+/// description = "What the scenario documents"
+/// requires = ["database"]   # Cargo features; the scenario is skipped without them
+/// config = "generated"      # Use the --generate-config output instead of config.toml
+///
+/// [[step]]
+/// action = "connect"        # connect, udp, api, release or sleep
+/// user = "alice"            # Username/password auth; no-auth when absent
+/// password = "secret"
+/// tls = true                # SOCKS over TLS, trusting the work directory certificate
+/// target = "echo"           # echo (default), closed, or host:port
+/// expect = "allow"          # allow, deny or auth_fail
+/// reply = 2                 # Exact reply code for deny
+/// send_bytes = 65536        # Echoed through the tunnel, or sent in one datagram (udp)
+/// min_secs = 0.5            # Bounds on the transfer time
+/// max_secs = 5.0
+/// hold = true               # Keep the connection open until `release`
+/// retry_secs = 2.0          # Repeat until the step passes, for state settled later
+///
+/// [[step]]
+/// action = "api"
+/// path = "/api/sessions/stats"
+/// status = 200
+/// json = { "/active_sessions" = 0 }      # JSON pointer = expected value
+/// json_at_least = { "/total_sessions" = 1 }
+/// ```
+use rcgen::generate_simple_self_signed;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use rustsocks::config::fields::{describe, feature_enabled};
+use rustsocks::config::Config;
+use rustsocks::server::SocksServer;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+/// How long the server may take to start listening
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a UDP echo may take to come back through the relay
+const UDP_ECHO_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Script {
+    description: String,
+    #[serde(default)]
+    requires: Vec<String>,
+    #[serde(default)]
+    config: ConfigSource,
+    #[serde(rename = "step")]
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConfigSource {
+    /// `config.toml` in the scenario directory
+    #[default]
+    File,
+    /// The file written by `--generate-config`
+    Generated,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    Connect,
+    Udp,
+    Api,
+    Release,
+    Sleep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Expect {
+    Allow,
+    Deny,
+    AuthFail,
+}
+
+impl Expect {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Expect::Allow => "allow",
+            Expect::Deny => "deny",
+            Expect::AuthFail => "auth_fail",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    action: Action,
+    user: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    tls: bool,
+    target: Option<String>,
+    expect: Option<Expect>,
+    reply: Option<u8>,
+    send_bytes: Option<usize>,
+    min_secs: Option<f64>,
+    max_secs: Option<f64>,
+    #[serde(default)]
+    hold: bool,
+    path: Option<String>,
+    status: Option<u16>,
+    #[serde(default)]
+    json: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    json_at_least: BTreeMap<String, f64>,
+    retry_secs: Option<f64>,
+    secs: Option<f64>,
+}
+
+impl Step {
+    /// One-line summary for failure messages
+    fn summary(&self) -> String {
+        let who = match &self.user {
+            Some(user) => format!("as {}", user),
+            None => "without auth".to_string(),
+        };
+        let over = if self.tls { " over TLS" } else { "" };
+        let target = self.target.as_deref().unwrap_or("echo");
+        let expect = self
+            .expect
+            .map(|expect| format!(", expecting {}", expect.as_str()))
+            .unwrap_or_default();
+        match self.action {
+            Action::Connect => format!("connect {} to {}{}{}", who, target, over, expect),
+            Action::Udp => format!("udp associate {} to {}{}{}", who, target, over, expect),
+            Action::Api => format!("GET {}", self.path.as_deref().unwrap_or("?")),
+            Action::Release => "release held connections".to_string(),
+            Action::Sleep => format!("sleep {}s", self.secs.unwrap_or(0.0)),
+        }
+    }
+}
+
+/// Destination of a CONNECT or a datagram
+enum Target {
+    Ip(SocketAddr),
+    Domain(String, u16),
+}
+
+impl Target {
+    /// SOCKS5 ATYP, address and port
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let port = match self {
+            Target::Ip(SocketAddr::V4(addr)) => {
+                out.push(0x01);
+                out.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            Target::Ip(SocketAddr::V6(addr)) => {
+                out.push(0x04);
+                out.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            Target::Domain(host, port) => {
+                out.push(0x03);
+                out.push(host.len() as u8);
+                out.extend_from_slice(host.as_bytes());
+                *port
+            }
+        };
+        out.extend_from_slice(&port.to_be_bytes());
+        out
+    }
+}
+
+trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ClientStream for T {}
+
+enum Outcome {
+    Passed,
+    Skipped(String),
+}
+
+/// Everything a scenario's steps talk to
+struct Environment {
+    socks: SocketAddr,
+    api: SocketAddr,
+    tcp_echo: SocketAddr,
+    udp_echo: SocketAddr,
+    /// A loopback port nothing listens on
+    closed: SocketAddr,
+    tls: TlsConnector,
+    held: Vec<Box<dyn ClientStream>>,
+}
+
+#[tokio::test]
+async fn documented_scenarios() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let only = std::env::var("SCENARIO").ok();
+
+    let mut passed = 0;
+    let mut failures = Vec::new();
+    for dir in scenario_dirs() {
+        let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+        if only.as_deref().is_some_and(|only| only != name) {
+            continue;
+        }
+
+        let started = Instant::now();
+        match run_scenario(&dir).await {
+            Ok(Outcome::Passed) => {
+                passed += 1;
+                eprintln!("scenario {}: ok ({:.1?})", name, started.elapsed());
+            }
+            Ok(Outcome::Skipped(reason)) => eprintln!("scenario {}: skipped, {}", name, reason),
+            Err(e) => failures.push(format!("scenario {} ({}):\n  {}", name, dir.display(), e)),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} scenario(s) failed:\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+    assert!(passed > 0, "no scenario ran (SCENARIO={:?})", only);
+}
+
+fn scenario_dirs() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios");
+    let mut dirs: Vec<PathBuf> = std::fs::read_dir(&root)
+        .unwrap_or_else(|e| panic!("read {}: {}", root.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+async fn run_scenario(dir: &Path) -> Result<Outcome, String> {
+    let script = std::fs::read_to_string(dir.join("script.toml"))
+        .map_err(|e| format!("script.toml: {}", e))?;
+    let script: Script = toml::from_str(&script).map_err(|e| format!("script.toml: {}", e))?;
+    if let Some(feature) = script.requires.iter().find(|f| !feature_enabled(f)) {
+        return Ok(Outcome::Skipped(format!(
+            "needs the \"{}\" feature",
+            feature
+        )));
+    }
+
+    let work_dir = tempfile::tempdir().map_err(|e| format!("work directory: {}", e))?;
+    let certificate = write_certificate(work_dir.path());
+
+    let (source, text) = match script.config {
+        ConfigSource::File => (
+            "config.toml",
+            std::fs::read_to_string(dir.join("config.toml"))
+                .map_err(|e| format!("config.toml: {}", e))?,
+        ),
+        ConfigSource::Generated => {
+            let path = work_dir.path().join("rustsocks.toml");
+            Config::create_example(&path).map_err(|e| format!("--generate-config: {}", e))?;
+            (
+                "generated config",
+                std::fs::read_to_string(&path).map_err(|e| format!("generated config: {}", e))?,
+            )
+        }
+    };
+    let text = text
+        .replace("${SCENARIO_DIR}", &dir.display().to_string())
+        .replace("${WORK_DIR}", &work_dir.path().display().to_string());
+
+    let unknown = unknown_keys(&text).map_err(|e| format!("{}: {}", source, e))?;
+    if !unknown.is_empty() {
+        return Err(format!(
+            "{}: not settings, the parser would ignore them: {}",
+            source,
+            unknown.join(", ")
+        ));
+    }
+    let mut config = Config::from_toml_str(&text).map_err(|e| format!("{}: {}", source, e))?;
+
+    let mut env = Environment {
+        socks: free_loopback_addr(),
+        api: free_loopback_addr(),
+        tcp_echo: spawn_tcp_echo().await,
+        udp_echo: spawn_udp_echo().await,
+        closed: free_loopback_addr(),
+        tls: tls_connector(certificate),
+        held: Vec::new(),
+    };
+    config.server.bind_address = env.socks.ip().to_string();
+    config.server.bind_port = env.socks.port();
+    config.sessions.stats_api_bind_address = env.api.ip().to_string();
+    config.sessions.stats_api_port = env.api.port();
+
+    let server = SocksServer::new(config, None, Arc::new(Vec::new()))
+        .await
+        .map_err(|e| format!("server failed to start: {}", e))?;
+
+    let result = tokio::select! {
+        result = server.run() => Err(match result {
+            Ok(()) => "server stopped during the script".to_string(),
+            Err(e) => format!("server stopped during the script: {}", e),
+        }),
+        result = env.run(&script.steps) => result,
+    };
+    server.shutdown().await;
+
+    result
+        .map(|()| Outcome::Passed)
+        .map_err(|e| format!("{}\n  ({})", e, script.description))
+}
+
+/// Dotted paths in `text` that are not in the settings registry. Arrays of tables are
+/// described as a whole, so their entries are not descended into.
+fn unknown_keys(text: &str) -> Result<Vec<String>, String> {
+    fn walk(table: &toml::Table, prefix: &str, unknown: &mut Vec<String>) {
+        for (key, value) in table {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            if describe(&path).is_none() {
+                unknown.push(path);
+            } else if let toml::Value::Table(inner) = value {
+                walk(inner, &path, unknown);
+            }
+        }
+    }
+
+    let table: toml::Table = text.parse().map_err(|e| format!("{}", e))?;
+    let mut unknown = Vec::new();
+    walk(&table, "", &mut unknown);
+    Ok(unknown)
+}
+
+fn write_certificate(dir: &Path) -> CertificateDer<'static> {
+    let cert = generate_simple_self_signed(["localhost".into()]).unwrap();
+    std::fs::write(dir.join("server.crt"), cert.cert.pem()).unwrap();
+    std::fs::write(dir.join("server.key"), cert.signing_key.serialize_pem()).unwrap();
+    CertificateDer::from(cert.cert.der().to_vec())
+}
+
+fn tls_connector(certificate: CertificateDer<'static>) -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add(certificate).unwrap();
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+/// A loopback address that was free a moment ago
+fn free_loopback_addr() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+async fn spawn_tcp_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr
+}
+
+async fn spawn_udp_echo() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 65536];
+        while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+            let _ = socket.send_to(&buf[..len], peer).await;
+        }
+    });
+    addr
+}
+
+async fn wait_for_listener(addr: SocketAddr) -> Result<(), String> {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    while TcpStream::connect(addr).await.is_err() {
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "nothing listening on {} after {:?}",
+                addr, STARTUP_TIMEOUT
+            ));
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    Ok(())
+}
+
+fn io(what: &'static str) -> impl Fn(std::io::Error) -> String {
+    move |e| format!("{}: {}", what, e)
+}
+
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn reply_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "succeeded",
+        0x01 => "general failure",
+        0x02 => "not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unassigned",
+    }
+}
+
+/// Compare a SOCKS reply with the expectation of `step`
+fn check_reply(step: &Step, expect: Expect, reply: u8) -> Result<(), String> {
+    match expect {
+        Expect::Allow if reply == 0x00 => Ok(()),
+        Expect::Allow => Err(format!(
+            "reply {:#04x} ({}), expected success",
+            reply,
+            reply_name(reply)
+        )),
+        Expect::Deny if reply == 0x00 => {
+            Err("request succeeded, expected it to be refused".to_string())
+        }
+        Expect::Deny => match step.reply {
+            Some(code) if code != reply => Err(format!(
+                "reply {:#04x} ({}), expected {:#04x} ({})",
+                reply,
+                reply_name(reply),
+                code,
+                reply_name(code)
+            )),
+            _ => Ok(()),
+        },
+        Expect::AuthFail => Err("authentication succeeded, expected it to be rejected".to_string()),
+    }
+}
+
+/// Method negotiation and RFC 1929 login; `false` when the server rejected the client
+async fn authenticate(stream: &mut Box<dyn ClientStream>, step: &Step) -> Result<bool, String> {
+    let method = if step.user.is_some() { 0x02 } else { 0x00 };
+    stream
+        .write_all(&[0x05, 0x01, method])
+        .await
+        .map_err(io("send greeting"))?;
+    let mut choice = [0u8; 2];
+    stream
+        .read_exact(&mut choice)
+        .await
+        .map_err(io("read method choice"))?;
+    if choice[0] != 0x05 {
+        return Err(format!("method choice {:02x?} is not SOCKS5", choice));
+    }
+    if choice[1] == 0xFF {
+        return Ok(false);
+    }
+    if choice[1] != method {
+        return Err(format!(
+            "server chose method {:#04x}, only {:#04x} was offered",
+            choice[1], method
+        ));
+    }
+
+    if let Some(user) = &step.user {
+        let password = step.password.as_deref().unwrap_or_default();
+        let mut login = vec![0x01, user.len() as u8];
+        login.extend_from_slice(user.as_bytes());
+        login.push(password.len() as u8);
+        login.extend_from_slice(password.as_bytes());
+        stream.write_all(&login).await.map_err(io("send login"))?;
+
+        let mut status = [0u8; 2];
+        stream
+            .read_exact(&mut status)
+            .await
+            .map_err(io("read login status"))?;
+        return Ok(status[1] == 0x00);
+    }
+    Ok(true)
+}
+
+fn auth_rejected(expect: Expect) -> Result<(), String> {
+    match expect {
+        Expect::AuthFail => Ok(()),
+        _ => Err("authentication was rejected".to_string()),
+    }
+}
+
+/// Reply code and bound address of a CONNECT or UDP ASSOCIATE reply
+async fn read_reply(stream: &mut Box<dyn ClientStream>) -> Result<(u8, SocketAddr), String> {
+    let mut head = [0u8; 4];
+    stream
+        .read_exact(&mut head)
+        .await
+        .map_err(io("read reply"))?;
+    if head[0] != 0x05 {
+        return Err(format!("reply {:02x?} is not SOCKS5", head));
+    }
+
+    let bound = match head[3] {
+        0x01 => {
+            let mut addr = [0u8; 6];
+            stream
+                .read_exact(&mut addr)
+                .await
+                .map_err(io("read bound address"))?;
+            let ip = Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]);
+            SocketAddr::from((ip, u16::from_be_bytes([addr[4], addr[5]])))
+        }
+        0x04 => {
+            let mut addr = [0u8; 18];
+            stream
+                .read_exact(&mut addr)
+                .await
+                .map_err(io("read bound address"))?;
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&addr[..16]);
+            SocketAddr::from((Ipv6Addr::from(ip), u16::from_be_bytes([addr[16], addr[17]])))
+        }
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream
+                .read_exact(&mut len)
+                .await
+                .map_err(io("read bound address"))?;
+            let mut addr = vec![0u8; len[0] as usize + 2];
+            stream
+                .read_exact(&mut addr)
+                .await
+                .map_err(io("read bound address"))?;
+            let port = u16::from_be_bytes([addr[addr.len() - 2], addr[addr.len() - 1]]);
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))
+        }
+        atyp => return Err(format!("reply has unknown address type {:#04x}", atyp)),
+    };
+    Ok((head[1], bound))
+}
+
+/// Status code and body of an HTTP/1.0 GET
+async fn http_get(addr: SocketAddr, path: &str) -> Result<(u16, String), String> {
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .map_err(io("connect to the API"))?;
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, addr);
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(io("send request"))?;
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .await
+        .map_err(io("read response"))?;
+
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| format!("malformed response: {:?}", response))?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("malformed status line: {:?}", head.lines().next()))?;
+    Ok((status, body.to_string()))
+}
+
+impl Environment {
+    async fn run(&mut self, steps: &[Step]) -> Result<(), String> {
+        wait_for_listener(self.socks).await?;
+        for (index, step) in steps.iter().enumerate() {
+            self.run_step(step)
+                .await
+                .map_err(|e| format!("step {} ({}): {}", index + 1, step.summary(), e))?;
+        }
+        Ok(())
+    }
+
+    async fn run_step(&mut self, step: &Step) -> Result<(), String> {
+        let deadline = Instant::now() + Duration::from_secs_f64(step.retry_secs.unwrap_or(0.0));
+        loop {
+            let result = match step.action {
+                Action::Connect => self.connect(step).await,
+                Action::Udp => self.udp(step).await,
+                Action::Api => self.api(step).await,
+                Action::Release => {
+                    self.held.clear();
+                    Ok(())
+                }
+                Action::Sleep => {
+                    sleep(Duration::from_secs_f64(step.secs.unwrap_or(0.0))).await;
+                    Ok(())
+                }
+            };
+            match result {
+                Err(_) if Instant::now() < deadline => sleep(Duration::from_millis(100)).await,
+                result => return result,
+            }
+        }
+    }
+
+    fn target(&self, step: &Step, echo: SocketAddr) -> Result<Target, String> {
+        match step.target.as_deref().unwrap_or("echo") {
+            "echo" => Ok(Target::Ip(echo)),
+            "closed" => Ok(Target::Ip(self.closed)),
+            other => {
+                if let Ok(addr) = other.parse() {
+                    return Ok(Target::Ip(addr));
+                }
+                other
+                    .rsplit_once(':')
+                    .and_then(|(host, port)| {
+                        Some(Target::Domain(host.to_string(), port.parse().ok()?))
+                    })
+                    .ok_or_else(|| format!("target {:?} is not echo, closed or host:port", other))
+            }
+        }
+    }
+
+    async fn open(&self, tls: bool) -> Result<Box<dyn ClientStream>, String> {
+        let tcp = TcpStream::connect(self.socks)
+            .await
+            .map_err(io("connect to the proxy"))?;
+        if !tls {
+            return Ok(Box::new(tcp));
+        }
+        let name = ServerName::try_from("localhost").unwrap();
+        let stream = self
+            .tls
+            .connect(name, tcp)
+            .await
+            .map_err(io("TLS handshake"))?;
+        Ok(Box::new(stream))
+    }
+
+    async fn connect(&mut self, step: &Step) -> Result<(), String> {
+        let expect = step.expect.ok_or("connect needs `expect`")?;
+        let target = self.target(step, self.tcp_echo)?;
+        let mut stream = self.open(step.tls).await?;
+        if !authenticate(&mut stream, step).await? {
+            return auth_rejected(expect);
+        }
+
+        let mut request = vec![0x05, 0x01, 0x00];
+        request.extend(target.encode());
+        // Connection limits are answered before the request is read, so the reply can be
+        // waiting even when sending the request failed
+        let sent = stream.write_all(&request).await;
+        let (reply, _) = match read_reply(&mut stream).await {
+            Ok(reply) => reply,
+            Err(e) => return Err(sent.err().map_or(e, io("send CONNECT"))),
+        };
+        check_reply(step, expect, reply)?;
+        if expect != Expect::Allow {
+            return Ok(());
+        }
+
+        if let Some(len) = step.send_bytes {
+            let sent = payload(len);
+            let mut echoed = vec![0u8; len];
+            let started = Instant::now();
+            let (mut reader, mut writer) = tokio::io::split(&mut stream);
+            let (written, read) =
+                tokio::join!(writer.write_all(&sent), reader.read_exact(&mut echoed));
+            written.map_err(io("send through the tunnel"))?;
+            read.map_err(io("read the echo"))?;
+            let elapsed = started.elapsed().as_secs_f64();
+
+            if echoed != sent {
+                return Err(format!(
+                    "the {} bytes echoed back differ from those sent",
+                    len
+                ));
+            }
+            if let Some(min) = step.min_secs.filter(|min| elapsed < *min) {
+                return Err(format!(
+                    "{} bytes echoed in {:.2}s, expected at least {}s",
+                    len, elapsed, min
+                ));
+            }
+            if let Some(max) = step.max_secs.filter(|max| elapsed > *max) {
+                return Err(format!(
+                    "{} bytes echoed in {:.2}s, expected at most {}s",
+                    len, elapsed, max
+                ));
+            }
+        }
+
+        if step.hold {
+            self.held.push(stream);
+        }
+        Ok(())
+    }
+
+    async fn udp(&mut self, step: &Step) -> Result<(), String> {
+        let expect = step.expect.ok_or("udp needs `expect`")?;
+        let target = self.target(step, self.udp_echo)?;
+        let socket = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(io("bind the client UDP socket"))?;
+        let local = socket.local_addr().map_err(io("client UDP address"))?;
+
+        let mut control = self.open(step.tls).await?;
+        if !authenticate(&mut control, step).await? {
+            return auth_rejected(expect);
+        }
+
+        // State the client socket, so the strict association mode accepts it
+        let mut request = vec![0x05, 0x03, 0x00];
+        request.extend(Target::Ip(local).encode());
+        control
+            .write_all(&request)
+            .await
+            .map_err(io("send UDP ASSOCIATE"))?;
+        let (reply, relay) = read_reply(&mut control).await?;
+        if reply != 0x00 || expect == Expect::AuthFail {
+            return check_reply(step, expect, reply);
+        }
+        let relay = if relay.ip().is_unspecified() {
+            SocketAddr::new(self.socks.ip(), relay.port())
+        } else {
+            relay
+        };
+
+        let sent = payload(step.send_bytes.unwrap_or(512));
+        let mut datagram = vec![0x00, 0x00, 0x00];
+        datagram.extend(target.encode());
+        datagram.extend_from_slice(&sent);
+        socket
+            .send_to(&datagram, relay)
+            .await
+            .map_err(io("send datagram"))?;
+
+        let mut buf = vec![0u8; 65536];
+        let echoed = match timeout(UDP_ECHO_TIMEOUT, socket.recv_from(&mut buf)).await {
+            Ok(Ok((len, _))) => buf[..len].ends_with(&sent),
+            _ => false,
+        };
+        match (expect, echoed) {
+            (Expect::Allow, false) => Err(format!(
+                "datagram was not relayed back within {:?}",
+                UDP_ECHO_TIMEOUT
+            )),
+            (Expect::Deny, true) => Err("datagram was relayed, expected it to be dropped".into()),
+            _ => {
+                if step.hold {
+                    self.held.push(control);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn api(&self, step: &Step) -> Result<(), String> {
+        let path = step.path.as_deref().ok_or("api needs `path`")?;
+        wait_for_listener(self.api).await?;
+        let (status, body) = http_get(self.api, path).await?;
+
+        let expected = step.status.unwrap_or(200);
+        if status != expected {
+            return Err(format!(
+                "status {}, expected {}; body: {}",
+                status, expected, body
+            ));
+        }
+        if step.json.is_empty() && step.json_at_least.is_empty() {
+            return Ok(());
+        }
+
+        let value: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|e| format!("body is not JSON ({}): {}", e, body))?;
+        for (pointer, expected) in &step.json {
+            let expected = serde_json::to_value(expected).map_err(|e| e.to_string())?;
+            match value.pointer(pointer) {
+                Some(actual) if *actual == expected => {}
+                Some(actual) => {
+                    return Err(format!("{} is {}, expected {}", pointer, actual, expected))
+                }
+                None => return Err(format!("{} is missing from {}", pointer, value)),
+            }
+        }
+        for (pointer, minimum) in &step.json_at_least {
+            match value.pointer(pointer).and_then(serde_json::Value::as_f64) {
+                Some(actual) if actual >= *minimum => {}
+                Some(actual) => {
+                    return Err(format!(
+                        "{} is {}, expected at least {}",
+                        pointer, actual, minimum
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "{} is missing or not a number in {}",
+                        pointer, value
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+//! `rustsocks check`: a config bundle is validated without starting listeners, and
+//! warnings (a certificate about to expire) are told apart from errors
+use chrono::{Datelike, Utc};
+use rcgen::{CertificateParams, KeyPair};
+use rustsocks::server::{run_self_test, CheckStatus, SelfTestReport};
+use std::path::Path;
+
+/// Self-signed `localhost` certificate expiring at the start of the day `days` from
+/// now, and its key
+fn certificate(days: i64) -> (String, KeyPair) {
+    let expiry = Utc::now() + chrono::Duration::days(days);
+    let mut params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+    params.not_after =
+        rcgen::date_time_ymd(expiry.year(), expiry.month() as u8, expiry.day() as u8);
+    let key = KeyPair::generate().unwrap();
+    (params.self_signed(&key).unwrap().pem(), key)
+}
+
+/// Main file, ACL file and users file in `dir`, TLS with `cert_pem` and `key_pem`
+fn write_bundle(dir: &Path, cert_pem: &str, key_pem: &str) -> std::path::PathBuf {
+    let file = |name: &str, contents: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    };
+    let cert = file("server.crt", cert_pem);
+    let key = file("server.key", key_pem);
+    let acl = file(
+        "acl.toml",
+        r#"
+[global]
+default_policy = "block"
+
+[[users]]
+username = "alice"
+groups = ["developers"]
+
+[[users.rules]]
+action = "allow"
+description = "HTTPS"
+destinations = ["*"]
+ports = ["443"]
+protocols = ["tcp"]
+priority = 100
+
+[[groups]]
+name = "developers"
+
+[[groups.rules]]
+action = "allow"
+description = "SSH"
+destinations = ["*"]
+ports = ["22"]
+protocols = ["tcp"]
+priority = 50
+"#,
+    );
+    let users = file(
+        "users.toml",
+        r#"
+[[users]]
+username = "alice"
+password = "secret123"
+"#,
+    );
+
+    let config = dir.join("rustsocks.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+[server]
+bind_address = "127.0.0.1"
+bind_port = 1080
+
+[server.tls]
+enabled = true
+certificate_path = "{cert}"
+private_key_path = "{key}"
+
+[auth]
+socks_method = "userpass"
+users_file = "{users}"
+
+[acl]
+enabled = true
+config_file = "{acl}"
+"#
+        ),
+    )
+    .unwrap();
+    config
+}
+
+fn status(report: &SelfTestReport, name: &str) -> CheckStatus {
+    report
+        .checks
+        .iter()
+        .find(|check| check.name == name)
+        .unwrap_or_else(|| panic!("no {} check in {:#?}", name, report.checks))
+        .status
+}
+
+#[tokio::test]
+async fn valid_bundle_passes_with_rule_counts() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let dir = tempfile::tempdir().unwrap();
+    let (cert, key) = certificate(365);
+    let config = write_bundle(dir.path(), &cert, &key.serialize_pem());
+
+    let report = run_self_test(Some(&config)).await;
+    assert!(report.passed, "{}", report.render_text());
+    assert_eq!(report.warnings, 0, "{}", report.render_text());
+    assert_eq!(status(&report, "tls.server.key"), CheckStatus::Ok);
+    assert_eq!(status(&report, "users_file"), CheckStatus::Ok);
+    assert_eq!(status(&report, "database"), CheckStatus::Skipped);
+    let acl = report.checks.iter().find(|c| c.name == "acl").unwrap();
+    assert!(
+        acl.message.contains("1 user(s), 1 group(s), 2 rule(s)"),
+        "{}",
+        acl.message
+    );
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["passed"], true);
+    assert_eq!(json["checks"][0]["status"], "ok");
+}
+
+#[tokio::test]
+async fn expiring_certificate_is_only_a_warning() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let dir = tempfile::tempdir().unwrap();
+    let (cert, key) = certificate(5);
+    let config = write_bundle(dir.path(), &cert, &key.serialize_pem());
+
+    let report = run_self_test(Some(&config)).await;
+    assert!(report.passed, "{}", report.render_text());
+    assert_eq!(
+        status(&report, "tls.server.certificate"),
+        CheckStatus::Warning
+    );
+    assert_eq!(report.warnings, 1);
+}
+
+#[tokio::test]
+async fn mismatched_key_and_broken_files_fail() {
+    let dir = tempfile::tempdir().unwrap();
+    let (cert, _) = certificate(365);
+    let other_key = KeyPair::generate().unwrap();
+    let config = write_bundle(dir.path(), &cert, &other_key.serialize_pem());
+    std::fs::write(dir.path().join("users.toml"), "users = []").unwrap();
+
+    let report = run_self_test(Some(&config)).await;
+    assert!(!report.passed);
+    assert_eq!(status(&report, "tls.server.key"), CheckStatus::Error);
+    assert_eq!(status(&report, "users_file"), CheckStatus::Error);
+    assert_eq!(status(&report, "acl"), CheckStatus::Ok);
+
+    // Nothing else is checked when the main file does not load
+    let report = run_self_test(Some(&dir.path().join("missing.toml"))).await;
+    assert!(!report.passed);
+    assert_eq!(report.checks.len(), 1);
+    assert_eq!(status(&report, "config"), CheckStatus::Error);
+}
@@ -0,0 +1,277 @@
+//! Live session events over `GET /api/ws`
+mod common;
+
+use axum::{routing::get, Router};
+use futures::{SinkExt, StreamExt};
+use rustsocks::api::handlers::session_events_ws;
+use rustsocks::api::handlers::sessions::ApiState;
+use rustsocks::config::Config;
+use rustsocks::qos::{ConnectionLimits, HtbConfig, PerIpConfig, QosConfig, QosEngine};
+use rustsocks::server::pool::{ConnectionPool, PoolConfig};
+use rustsocks::server::{accept_loop, AcceptOptions, ClientHandlerContext};
+use rustsocks::session::{ConnectionInfo, SessionManager, SessionProtocol, SessionStatus};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+fn create_api_state(session_manager: Arc<SessionManager>) -> ApiState {
+    ApiState {
+        session_manager,
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    }
+}
+
+async fn spawn_api(session_manager: Arc<SessionManager>) -> SocketAddr {
+    let app = Router::new()
+        .route("/api/ws", get(session_events_ws))
+        .with_state(create_api_state(session_manager));
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind API");
+    let addr = listener.local_addr().expect("API addr");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.expect("serve API");
+    });
+    addr
+}
+
+async fn subscribe(addr: SocketAddr, subscription: Value) -> Client {
+    let (mut client, _) = connect_async(format!("ws://{}/api/ws", addr))
+        .await
+        .expect("WebSocket upgrade");
+    client
+        .send(Message::Text(subscription.to_string().into()))
+        .await
+        .expect("send subscription");
+    let confirmation = next_json(&mut client).await;
+    assert_eq!(confirmation["type"], "subscribed");
+    client
+}
+
+async fn next_json(client: &mut Client) -> Value {
+    loop {
+        let message = timeout(Duration::from_secs(5), client.next())
+            .await
+            .expect("message within 5s")
+            .expect("socket open")
+            .expect("valid message");
+        if let Message::Text(text) = message {
+            return serde_json::from_str(text.as_str()).expect("JSON message");
+        }
+    }
+}
+
+fn connection(port: u16, dest: &str) -> ConnectionInfo {
+    ConnectionInfo {
+        source_ip: "10.0.0.5".parse().unwrap(),
+        source_port: port,
+        dest_ip: Arc::from(dest),
+        dest_port: 443,
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn filtered_subscriber_gets_the_events_of_its_user() {
+    let session_manager = Arc::new(SessionManager::new());
+    let addr = spawn_api(session_manager.clone()).await;
+    let mut client = subscribe(addr, serde_json::json!({"user": "alice"})).await;
+
+    // bob's session is filtered out
+    let other = session_manager
+        .new_session("bob", connection(50001, "example.org"), "allow", None)
+        .await;
+    let session_id = session_manager
+        .new_session("alice", connection(50000, "example.com"), "allow", None)
+        .await;
+    session_manager
+        .update_traffic(&session_id, 1200, 3400, 1, 2)
+        .await;
+    session_manager
+        .close_session(&session_id, None, SessionStatus::Closed)
+        .await;
+    session_manager
+        .close_session(&other, None, SessionStatus::Closed)
+        .await;
+    session_manager
+        .track_rejected_session("alice", connection(50002, "ads.example.com"), None, 0x02)
+        .await;
+
+    let started = next_json(&mut client).await;
+    assert_eq!(started["type"], "session_started");
+    assert_eq!(started["session_id"], session_id.to_string());
+    assert_eq!(started["user"], "alice");
+    assert_eq!(started["dest_host"], "example.com");
+
+    let traffic = next_json(&mut client).await;
+    assert_eq!(traffic["type"], "traffic_update");
+    assert_eq!(traffic["bytes_sent"], 1200);
+    assert_eq!(traffic["bytes_received"], 3400);
+
+    let closed = next_json(&mut client).await;
+    assert_eq!(closed["type"], "session_closed");
+    assert_eq!(closed["session_id"], session_id.to_string());
+    assert_eq!(closed["status"], "closed");
+
+    let blocked = next_json(&mut client).await;
+    assert_eq!(blocked["type"], "acl_blocked");
+    assert_eq!(blocked["dest_host"], "ads.example.com");
+    assert_eq!(blocked["reply_code"], 2);
+}
+
+#[tokio::test]
+async fn traffic_updates_are_paced_per_session() {
+    let session_manager = Arc::new(SessionManager::new());
+    session_manager
+        .events()
+        .set_interval(Duration::from_secs(60));
+    let addr = spawn_api(session_manager.clone()).await;
+    let mut client = subscribe(addr, serde_json::json!({"destination": "*.example.com"})).await;
+
+    let session_id = session_manager
+        .new_session("alice", connection(50000, "api.example.com"), "allow", None)
+        .await;
+    for _ in 0..5 {
+        session_manager
+            .update_traffic(&session_id, 100, 100, 1, 1)
+            .await;
+    }
+    session_manager
+        .close_session(&session_id, None, SessionStatus::Closed)
+        .await;
+
+    let kinds: Vec<String> = [
+        next_json(&mut client).await,
+        next_json(&mut client).await,
+        next_json(&mut client).await,
+    ]
+    .iter()
+    .map(|event| event["type"].as_str().unwrap().to_string())
+    .collect();
+    assert_eq!(
+        kinds,
+        ["session_started", "traffic_update", "session_closed"]
+    );
+}
+
+#[tokio::test]
+async fn invalid_subscription_is_refused() {
+    let session_manager = Arc::new(SessionManager::new());
+    let addr = spawn_api(session_manager).await;
+    let (mut client, _) = connect_async(format!("ws://{}/api/ws", addr))
+        .await
+        .expect("WebSocket upgrade");
+    client
+        .send(Message::Text(r#"{"destination": "*"}"#.into()))
+        .await
+        .expect("send subscription");
+
+    let error = next_json(&mut client).await;
+    assert_eq!(error["type"], "error");
+    let closing = timeout(Duration::from_secs(5), client.next())
+        .await
+        .expect("close within 5s");
+    assert!(matches!(closing, Some(Ok(Message::Close(_))) | None));
+}
+
+/// SOCKS5 no-auth CONNECT to `upstream`; returns the stream and the reply code
+async fn socks5_connect(proxy: SocketAddr, upstream: SocketAddr) -> (TcpStream, u8) {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    (stream, reply[1])
+}
+
+#[tokio::test]
+async fn admission_rejections_reach_the_websocket() {
+    let limits = ConnectionLimits {
+        max_connections_per_user: 1,
+        max_connections_global: 100,
+    };
+    let qos_engine = QosEngine::from_config(QosConfig {
+        enabled: true,
+        algorithm: "htb".to_string(),
+        htb: HtbConfig::default(),
+        connection_limits: limits.clone(),
+        per_ip: PerIpConfig::default(),
+    })
+    .await
+    .expect("create QoS engine");
+    let session_manager = Arc::new(SessionManager::new());
+    let addr = spawn_api(session_manager.clone()).await;
+    let mut client = subscribe(addr, serde_json::json!({"user": "anonymous"})).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy = listener.local_addr().expect("proxy addr");
+    let ctx = Arc::new(ClientHandlerContext {
+        qos_engine,
+        connection_limits: limits.into(),
+        ..common::handler_context(session_manager.clone())
+    });
+    tokio::spawn(accept_loop(listener, ctx, AcceptOptions::default()));
+    let upstream = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let upstream_addr = upstream.local_addr().expect("upstream addr");
+
+    let (_held, code) = socks5_connect(proxy, upstream_addr).await;
+    assert_eq!(code, 0x00);
+    let (_refused, code) = socks5_connect(proxy, upstream_addr).await;
+    assert_eq!(code, 0x02, "second connection exceeds the per-user limit");
+
+    // The held connection's session_started may come first
+    let rejected = loop {
+        let event = next_json(&mut client).await;
+        if event["type"] == "admission_rejected" {
+            break event;
+        }
+        assert_eq!(event["type"], "session_started");
+    };
+    assert_eq!(rejected["session_id"], uuid::Uuid::nil().to_string());
+    assert_eq!(rejected["user"], "anonymous");
+    assert_eq!(rejected["source_ip"], "127.0.0.1");
+    assert_eq!(rejected["limit_type"], "user_connections");
+    assert_eq!(rejected["current"], 1);
+    assert_eq!(rejected["limit"], 1);
+    assert_eq!(rejected["dest_port"], 0);
+}
@@ -12,9 +12,14 @@ fn create_test_connection(source_port: u16, dest_port: u16) -> ConnectionInfo {
     ConnectionInfo {
         source_ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100)),
         source_port,
-        dest_ip: "93.184.216.34".to_string(), // example.com
+        dest_ip: "93.184.216.34".into(), // example.com
         dest_port,
         protocol: rustsocks::session::types::Protocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
     }
 }
 
@@ -201,6 +206,7 @@ async fn test_rejected_sessions_tracking() {
                 &format!("user{}", i % 5),
                 conn,
                 Some(format!("rule_{}", i % 3)),
+                0x02,
             )
             .await;
     }
@@ -235,6 +241,7 @@ async fn test_concurrent_rejected_and_accepted_sessions() {
                     &format!("user{}", i),
                     conn,
                     Some("test_rule".to_string()),
+                    0x02,
                 )
                 .await
             }
@@ -451,9 +458,14 @@ async fn test_mixed_protocol_sessions() {
         let conn = ConnectionInfo {
             source_ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
             source_port: 15000 + i,
-            dest_ip: "93.184.216.34".to_string(),
+            dest_ip: "93.184.216.34".into(),
             dest_port: 80,
             protocol: rustsocks::session::types::Protocol::Tcp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
         manager.new_session("alice", conn, "allow", None).await;
     }
@@ -463,9 +475,14 @@ async fn test_mixed_protocol_sessions() {
         let conn = ConnectionInfo {
             source_ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
             source_port: 16000 + i,
-            dest_ip: "93.184.216.34".to_string(),
+            dest_ip: "93.184.216.34".into(),
             dest_port: 53,
             protocol: rustsocks::session::types::Protocol::Udp,
+            authenticated_user: None,
+            correlation_id: None,
+            socks_version: 5,
+            chained: false,
+            groups: Vec::new(),
         };
         manager.new_session("bob", conn, "allow", None).await;
     }
@@ -517,9 +534,14 @@ async fn test_ipv6_sessions() {
     let conn = ConnectionInfo {
         source_ip: IpAddr::V6(std::net::Ipv6Addr::new(0x2001, 0x0db8, 0, 0, 0, 0, 0, 1)),
         source_port: 30000,
-        dest_ip: "2001:db8::2".to_string(),
+        dest_ip: "2001:db8::2".into(),
         dest_port: 443,
         protocol: rustsocks::session::types::Protocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
     };
 
     let session_id = manager.new_session("alice", conn, "allow", None).await;
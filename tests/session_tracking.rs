@@ -1,6 +1,10 @@
 use rustsocks::qos::QosEngine;
 use rustsocks::server::proxy::{proxy_data, TrafficUpdateConfig};
-use rustsocks::session::{ConnectionInfo, SessionManager, SessionProtocol, SessionStatus};
+use rustsocks::session::{
+    ConnectionInfo, SessionManager, SessionProtocol, SessionStatus, TerminateOutcome,
+};
+use rustsocks::utils::error::RustSocksError;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
@@ -39,9 +43,14 @@ async fn proxy_updates_session_traffic_on_shutdown_flush() {
     let connection_info = ConnectionInfo {
         source_ip: source_addr.ip(),
         source_port: source_addr.port(),
-        dest_ip: dest_addr.ip().to_string(),
+        dest_ip: dest_addr.ip().to_string().into(),
         dest_port: dest_addr.port(),
         protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
     };
 
     let (session_id, cancel_token) = session_manager
@@ -57,6 +66,7 @@ async fn proxy_updates_session_traffic_on_shutdown_flush() {
         TrafficUpdateConfig::new(10),
         QosEngine::None,
         Arc::<str>::from("integration-user"),
+        IpAddr::from([127, 0, 0, 1]),
     ));
 
     // Client -> Upstream payload (forces flush on close, not threshold)
@@ -118,3 +128,206 @@ async fn proxy_updates_session_traffic_on_shutdown_flush() {
         )
         .await;
 }
+
+#[tokio::test]
+async fn proxy_reports_exact_totals_when_client_resets() {
+    let session_manager = Arc::new(SessionManager::new());
+
+    let client_listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind client listener");
+    let (client_peer, server_side_client) = tokio::join!(
+        TcpStream::connect(client_listener.local_addr().unwrap()),
+        client_listener.accept()
+    );
+    let mut client_peer = client_peer.expect("client connect");
+    let (server_client_stream, source_addr) = server_side_client.expect("server accept client");
+
+    let upstream_listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream listener");
+    let dest_addr = upstream_listener.local_addr().unwrap();
+    let (upstream_stream, upstream_peer) =
+        tokio::join!(TcpStream::connect(dest_addr), upstream_listener.accept());
+    let upstream_stream = upstream_stream.expect("proxy connect upstream");
+    let (mut upstream_peer, _) = upstream_peer.expect("upstream accept proxy");
+
+    let connection_info = ConnectionInfo {
+        source_ip: source_addr.ip(),
+        source_port: source_addr.port(),
+        dest_ip: dest_addr.ip().to_string().into(),
+        dest_port: dest_addr.port(),
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+    let (session_id, cancel_token) = session_manager
+        .new_session_with_control("integration-user", connection_info, "allow", None, None)
+        .await;
+
+    // An interval no transfer below reaches, so every byte rides on the final flush
+    let proxy_task = tokio::spawn(proxy_data(
+        server_client_stream,
+        upstream_stream,
+        session_manager.clone(),
+        session_id,
+        cancel_token,
+        TrafficUpdateConfig::new(1_000_000),
+        QosEngine::None,
+        Arc::<str>::from("integration-user"),
+        IpAddr::from([127, 0, 0, 1]),
+    ));
+
+    const UPLOAD: usize = 300_001;
+    const DOWNLOAD: usize = 150_007;
+
+    let upload = vec![0x5a; UPLOAD];
+    client_peer.write_all(&upload).await.expect("client write");
+    let mut received = vec![0u8; UPLOAD];
+    upstream_peer
+        .read_exact(&mut received)
+        .await
+        .expect("upstream read upload");
+
+    let download = vec![0xa5; DOWNLOAD];
+    upstream_peer
+        .write_all(&download)
+        .await
+        .expect("upstream write");
+    let mut received = vec![0u8; DOWNLOAD];
+    client_peer
+        .read_exact(&mut received)
+        .await
+        .expect("client read download");
+
+    // Abort the client: with a zero linger the close sends RST instead of FIN
+    client_peer
+        .set_linger(Some(std::time::Duration::ZERO))
+        .expect("set linger");
+    drop(client_peer);
+
+    let _ = proxy_task.await.expect("proxy task join");
+    session_manager
+        .close_session(&session_id, None, SessionStatus::Closed)
+        .await;
+
+    let closed = session_manager.get_closed_sessions().await;
+    let session = closed
+        .iter()
+        .find(|session| session.session_id == session_id)
+        .expect("closed session recorded");
+    assert_eq!(session.bytes_sent, UPLOAD as u64);
+    assert_eq!(session.bytes_received, DOWNLOAD as u64);
+}
+
+#[tokio::test]
+async fn terminate_severs_a_relay_blocked_on_a_slow_reader() {
+    let session_manager = Arc::new(SessionManager::new());
+
+    let client_listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind client listener");
+    let (client_peer, server_side_client) = tokio::join!(
+        TcpStream::connect(client_listener.local_addr().unwrap()),
+        client_listener.accept()
+    );
+    let mut client_peer = client_peer.expect("client connect");
+    let (server_client_stream, source_addr) = server_side_client.expect("server accept client");
+
+    let upstream_listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream listener");
+    let dest_addr = upstream_listener.local_addr().unwrap();
+    let (upstream_stream, upstream_peer) =
+        tokio::join!(TcpStream::connect(dest_addr), upstream_listener.accept());
+    let upstream_stream = upstream_stream.expect("proxy connect upstream");
+    let (upstream_peer, _) = upstream_peer.expect("upstream accept proxy");
+    let (mut upstream_read, mut upstream_write) = upstream_peer.into_split();
+
+    let connection_info = ConnectionInfo {
+        source_ip: source_addr.ip(),
+        source_port: source_addr.port(),
+        dest_ip: dest_addr.ip().to_string().into(),
+        dest_port: dest_addr.port(),
+        protocol: SessionProtocol::Tcp,
+        authenticated_user: None,
+        correlation_id: None,
+        socks_version: 5,
+        chained: false,
+        groups: Vec::new(),
+    };
+    let (session_id, cancel_token) = session_manager
+        .new_session_with_control("integration-user", connection_info, "allow", None, None)
+        .await;
+
+    let proxy_task = tokio::spawn(proxy_data(
+        server_client_stream,
+        upstream_stream,
+        session_manager.clone(),
+        session_id,
+        cancel_token,
+        TrafficUpdateConfig::new(10),
+        QosEngine::None,
+        Arc::<str>::from("integration-user"),
+        IpAddr::from([127, 0, 0, 1]),
+    ));
+
+    // The client never reads, so once the socket buffers fill the relay waits in a write
+    tokio::spawn(async move {
+        let chunk = vec![0x42; 64 * 1024];
+        while upstream_write.write_all(&chunk).await.is_ok() {}
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+
+    assert_eq!(
+        session_manager
+            .terminate_session(&session_id, "Terminated by test", SessionStatus::Closed)
+            .await,
+        TerminateOutcome::Terminated
+    );
+    let result = tokio::time::timeout(std::time::Duration::from_secs(1), proxy_task)
+        .await
+        .expect("relay stops within a second")
+        .expect("proxy task join");
+    assert!(matches!(result, Err(RustSocksError::ConnectionClosed)));
+
+    // Both sockets are closed. The proxy's upstream socket still held unread data, so
+    // upstream may see a reset instead of EOF.
+    let mut sink = Vec::new();
+    let _ = tokio::time::timeout(
+        std::time::Duration::from_secs(1),
+        upstream_read.read_to_end(&mut sink),
+    )
+    .await
+    .expect("upstream socket closed");
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        client_peer.read_to_end(&mut sink),
+    )
+    .await
+    .expect("client socket closed")
+    .expect("client reads to EOF");
+
+    let closed = session_manager.get_closed_sessions().await;
+    let session = closed
+        .iter()
+        .find(|session| session.session_id == session_id)
+        .expect("closed session recorded");
+    assert_eq!(session.close_reason.as_deref(), Some("Terminated by test"));
+
+    assert_eq!(
+        session_manager
+            .terminate_session(&session_id, "again", SessionStatus::Closed)
+            .await,
+        TerminateOutcome::AlreadyClosed
+    );
+    assert_eq!(
+        session_manager
+            .terminate_session(&uuid::Uuid::new_v4(), "unknown", SessionStatus::Closed)
+            .await,
+        TerminateOutcome::NotFound
+    );
+}
@@ -0,0 +1,493 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, AclStats, Action, Protocol};
+use rustsocks::api::handlers::sessions::{get_session_stats, ApiState};
+use rustsocks::auth::AuthManager;
+use rustsocks::config::{AuthConfig, Config, PamSettings};
+use rustsocks::protocol::ReplyCode;
+use rustsocks::qos::QosEngine;
+use rustsocks::server::{
+    handle_client, ClientHandlerContext, ConnectionPool, PoolConfig, SniFailMode, SniRouting,
+};
+use rustsocks::session::{HostSource, SessionManager, SessionStatus};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tower::util::ServiceExt;
+
+const UPSTREAM_REPLY: &[u8] = b"upstream-server-hello";
+
+/// Minimal TLS 1.2 ClientHello carrying `server_name`, as one handshake record.
+fn client_hello_with_sni(server_name: &str) -> Vec<u8> {
+    let name = server_name.as_bytes();
+
+    let mut sni_ext = Vec::new();
+    sni_ext.extend_from_slice(&[0x00, 0x00]); // server_name
+    sni_ext.extend_from_slice(&((name.len() + 5) as u16).to_be_bytes());
+    sni_ext.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+    sni_ext.push(0x00); // host_name
+    sni_ext.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    sni_ext.extend_from_slice(name);
+
+    let mut hello = Vec::new();
+    hello.extend_from_slice(&[0x03, 0x03]);
+    hello.extend_from_slice(&[0u8; 32]);
+    hello.push(0); // session id
+    hello.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // one cipher suite
+    hello.extend_from_slice(&[0x01, 0x00]); // null compression
+    hello.extend_from_slice(&(sni_ext.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&sni_ext);
+
+    let mut handshake = vec![0x01]; // ClientHello
+    handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&hello);
+
+    tls_records(&[&handshake])
+}
+
+/// Wrap handshake fragments into consecutive TLS handshake records.
+fn tls_records(fragments: &[&[u8]]) -> Vec<u8> {
+    let mut records = Vec::new();
+    for fragment in fragments {
+        records.extend_from_slice(&[0x16, 0x03, 0x01]);
+        records.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        records.extend_from_slice(fragment);
+    }
+    records
+}
+
+fn sni_acl_config() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Allow,
+        },
+        users: vec![UserAcl {
+            username: "anonymous".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Block,
+                description: "Block blocked-site.com".to_string(),
+                destinations: vec!["blocked-site.com".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 1000,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+        groups: vec![],
+    }
+}
+
+fn handler_context(
+    session_manager: Arc<SessionManager>,
+    acl_stats: Arc<AclStats>,
+    sni_port: u16,
+    fail_mode: SniFailMode,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(
+            AuthManager::new(&AuthConfig {
+                client_method: "none".into(),
+                socks_method: "none".into(),
+                socks_method_preference: Vec::new(),
+                users: Vec::new(),
+                users_file: None,
+                pam: PamSettings::default(),
+                gssapi: Default::default(),
+                ldap: Default::default(),
+                client_allow: Vec::new(),
+                client_deny: Vec::new(),
+                impersonation: Default::default(),
+                password_hashing: Default::default(),
+                allow_correlation_suffix: false,
+                correlation_separator: "#".to_string(),
+                tls_cert_identity: "cn".to_string(),
+            })
+            .expect("auth manager"),
+        ),
+        acl_engine: Some(Arc::new(
+            AclEngine::new(sni_acl_config()).expect("acl engine"),
+        )),
+        acl_stats,
+        sni_routing: SniRouting {
+            enabled: true,
+            peek_timeout: Duration::from_millis(500),
+            ports: vec![sni_port],
+            fail_mode,
+        },
+        ..common::handler_context(session_manager)
+    })
+}
+
+/// Upstream that records whatever it receives and answers the first bytes with a fixed reply.
+async fn spawn_upstream() -> (SocketAddr, JoinHandle<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+
+    let task = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.expect("accept proxy");
+        let mut received = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if received.is_empty() {
+                        stream.write_all(UPSTREAM_REPLY).await.expect("reply");
+                    }
+                    received.extend_from_slice(&buf[..n]);
+                }
+            }
+        }
+        received
+    });
+
+    (addr, task)
+}
+
+/// Run one client through the handler up to a successful CONNECT by IP literal.
+async fn connect_by_ip(
+    ctx: Arc<ClientHandlerContext>,
+    upstream: SocketAddr,
+) -> (TcpStream, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+
+    let server_task = tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    let SocketAddr::V4(upstream) = upstream else {
+        panic!("upstream must be IPv4");
+    };
+    let mut request = vec![0x05, 0x01, 0x00, 0x01];
+    request.extend_from_slice(&upstream.ip().octets());
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], ReplyCode::Succeeded as u8);
+
+    (client, server_task)
+}
+
+/// CONNECT by IP literal, then send a ClientHello.
+async fn connect_with_sni(
+    ctx: Arc<ClientHandlerContext>,
+    upstream: SocketAddr,
+    sni: &str,
+) -> (TcpStream, JoinHandle<()>) {
+    let (mut client, server_task) = connect_by_ip(ctx, upstream).await;
+    client
+        .write_all(&client_hello_with_sni(sni))
+        .await
+        .expect("send client hello");
+
+    (client, server_task)
+}
+
+/// Wait for the proxy to drop the client connection.
+async fn expect_closed(client: &mut TcpStream) {
+    let mut buf = [0u8; 64];
+    let read = tokio::time::timeout(Duration::from_secs(2), client.read(&mut buf))
+        .await
+        .expect("proxy should close the connection");
+    assert!(matches!(read, Ok(0) | Err(_)));
+}
+
+#[tokio::test]
+async fn blocks_ip_connect_when_sni_matches_domain_rule() {
+    let session_manager = Arc::new(SessionManager::new());
+    let acl_stats = Arc::new(AclStats::new());
+    let (upstream, upstream_task) = spawn_upstream().await;
+    let ctx = handler_context(
+        session_manager.clone(),
+        acl_stats.clone(),
+        upstream.port(),
+        SniFailMode::Block,
+    );
+
+    let (mut client, server_task) = connect_with_sni(ctx, upstream, "blocked-site.com").await;
+
+    // The proxy drops the connection instead of relaying the ClientHello
+    expect_closed(&mut client).await;
+
+    server_task.await.unwrap();
+    let received = upstream_task.await.unwrap();
+    assert!(received.is_empty(), "upstream must not see blocked traffic");
+
+    let closed = session_manager.closed_snapshot().await;
+    assert_eq!(closed.len(), 1);
+    let session = &closed[0];
+    assert_eq!(session.status, SessionStatus::RejectedByAcl);
+    assert_eq!(session.dest_ip.as_ref(), "127.0.0.1");
+    assert_eq!(session.sni_host.as_deref(), Some("blocked-site.com"));
+    assert_eq!(session.acl_decision.as_ref(), "block");
+    assert_eq!(
+        session.acl_rule_matched.as_deref(),
+        Some("Block blocked-site.com")
+    );
+
+    // IP stage allowed, SNI stage blocked: counted once, as the final outcome
+    let totals = acl_stats.snapshot();
+    assert_eq!(totals.allowed, 0);
+    assert_eq!(totals.blocked, 1);
+}
+
+#[tokio::test]
+async fn stats_attribute_bytes_to_sni_name() {
+    let session_manager = Arc::new(SessionManager::new());
+    let acl_stats = Arc::new(AclStats::new());
+    let (upstream, upstream_task) = spawn_upstream().await;
+    let ctx = handler_context(
+        session_manager.clone(),
+        acl_stats.clone(),
+        upstream.port(),
+        SniFailMode::Block,
+    );
+
+    let (mut client, server_task) = connect_with_sni(ctx, upstream, "Allowed-Site.com").await;
+
+    let mut reply = vec![0u8; UPSTREAM_REPLY.len()];
+    client.read_exact(&mut reply).await.expect("upstream reply");
+    assert_eq!(reply, UPSTREAM_REPLY);
+    drop(client);
+
+    server_task.await.unwrap();
+    let received = upstream_task.await.unwrap();
+    let hello = client_hello_with_sni("Allowed-Site.com");
+    assert_eq!(
+        received, hello,
+        "peeked bytes must reach upstream unchanged"
+    );
+
+    let closed = session_manager.closed_snapshot().await;
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].status, SessionStatus::Closed);
+    assert_eq!(closed[0].sni_host.as_deref(), Some("allowed-site.com"));
+    assert_eq!(
+        closed[0].requested_host.as_deref(),
+        Some("allowed-site.com")
+    );
+    assert_eq!(closed[0].requested_host_source, Some(HostSource::Sni));
+    assert_eq!(closed[0].bytes_sent, hello.len() as u64);
+    assert_eq!(acl_stats.snapshot().allowed, 1);
+
+    let state = ApiState {
+        session_manager: session_manager.clone(),
+        acl_engine: None,
+        acl_config_path: None,
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        qos_engine: Arc::new(QosEngine::None),
+        start_time: std::time::Instant::now(),
+        #[cfg(feature = "database")]
+        session_store: None,
+        metrics_history: None,
+        telemetry_history: None,
+        config_path: None,
+        config_snapshot: Arc::new(Config::default()),
+        original_args: Arc::new(Vec::new()),
+        address_gate: None,
+        overload: None,
+        resource_guard: None,
+        config_reloader: None,
+        dns_cache: None,
+        bound_addresses: None,
+        user_bans: None,
+        probe_monitor: None,
+        upstream_proxy: None,
+    };
+    let app = Router::new()
+        .route("/api/sessions/stats", get(get_session_stats))
+        .with_state(state);
+
+    let fetch = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    let by_sni = fetch("/api/sessions/stats?group_by=sni").await;
+    let destination = &by_sni["top_destinations"][0];
+    assert_eq!(
+        destination["destination"],
+        format!("allowed-site.com:{}", upstream.port())
+    );
+    assert_eq!(destination["bytes_sent"], hello.len() as u64);
+
+    // Default grouping keeps the dialled address
+    let by_ip = fetch("/api/sessions/stats").await;
+    assert_eq!(
+        by_ip["top_destinations"][0]["destination"],
+        format!("127.0.0.1:{}", upstream.port())
+    );
+
+    let invalid = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/sessions/stats?group_by=user")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+
+    let stats = session_manager.get_stats(Duration::from_secs(3600)).await;
+    assert_eq!(stats.top_destinations[0].dest_ip, "allowed-site.com");
+}
+
+#[tokio::test]
+async fn blocks_delayed_client_hello_split_across_records() {
+    let session_manager = Arc::new(SessionManager::new());
+    let acl_stats = Arc::new(AclStats::new());
+    let (upstream, upstream_task) = spawn_upstream().await;
+    let ctx = handler_context(
+        session_manager.clone(),
+        acl_stats.clone(),
+        upstream.port(),
+        SniFailMode::Allow,
+    );
+
+    let (mut client, server_task) = connect_by_ip(ctx, upstream).await;
+
+    // Re-frame the handshake into two records and send them with a pause in between
+    let hello = client_hello_with_sni("blocked-site.com");
+    let (first, second) = hello[5..].split_at(24);
+    let records = tls_records(&[first, second]);
+    let split_at = 5 + first.len();
+    client.write_all(&records[..split_at]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client.write_all(&records[split_at..]).await.unwrap();
+
+    expect_closed(&mut client).await;
+    server_task.await.unwrap();
+    assert!(upstream_task.await.unwrap().is_empty());
+
+    let closed = session_manager.closed_snapshot().await;
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].status, SessionStatus::RejectedByAcl);
+    assert_eq!(closed[0].sni_host.as_deref(), Some("blocked-site.com"));
+    assert_eq!(acl_stats.snapshot().blocked, 1);
+}
+
+#[tokio::test]
+async fn stalled_client_hello_is_blocked_in_block_mode() {
+    let session_manager = Arc::new(SessionManager::new());
+    let acl_stats = Arc::new(AclStats::new());
+    let (upstream, upstream_task) = spawn_upstream().await;
+    let ctx = handler_context(
+        session_manager.clone(),
+        acl_stats.clone(),
+        upstream.port(),
+        SniFailMode::Block,
+    );
+
+    let (mut client, server_task) = connect_by_ip(ctx, upstream).await;
+    // Only part of the record header arrives before the peek deadline
+    client.write_all(&[0x16, 0x03]).await.unwrap();
+
+    expect_closed(&mut client).await;
+    server_task.await.unwrap();
+    assert!(upstream_task.await.unwrap().is_empty());
+
+    let closed = session_manager.closed_snapshot().await;
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].status, SessionStatus::RejectedByAcl);
+    assert_eq!(closed[0].sni_host, None);
+    let totals = acl_stats.snapshot();
+    assert_eq!((totals.allowed, totals.blocked), (0, 1));
+}
+
+#[tokio::test]
+async fn unreadable_client_hello_keeps_ip_decision_in_allow_mode() {
+    let session_manager = Arc::new(SessionManager::new());
+    let acl_stats = Arc::new(AclStats::new());
+    let (upstream, upstream_task) = spawn_upstream().await;
+    let ctx = handler_context(
+        session_manager.clone(),
+        acl_stats.clone(),
+        upstream.port(),
+        SniFailMode::Allow,
+    );
+
+    let (mut client, server_task) = connect_by_ip(ctx, upstream).await;
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+
+    let mut reply = vec![0u8; UPSTREAM_REPLY.len()];
+    client.read_exact(&mut reply).await.expect("upstream reply");
+    drop(client);
+
+    server_task.await.unwrap();
+    assert_eq!(upstream_task.await.unwrap(), b"GET / HTTP/1.1\r\n\r\n");
+
+    let closed = session_manager.closed_snapshot().await;
+    assert_eq!(closed[0].status, SessionStatus::Closed);
+    assert_eq!(closed[0].sni_host, None);
+    let totals = acl_stats.snapshot();
+    assert_eq!((totals.allowed, totals.blocked), (1, 0));
+}
+
+#[tokio::test]
+async fn connects_to_other_ports_are_not_peeked() {
+    let session_manager = Arc::new(SessionManager::new());
+    let acl_stats = Arc::new(AclStats::new());
+    let (upstream, upstream_task) = spawn_upstream().await;
+    let ctx = handler_context(
+        session_manager.clone(),
+        acl_stats.clone(),
+        443,
+        SniFailMode::Block,
+    );
+
+    let (mut client, server_task) = connect_with_sni(ctx, upstream, "blocked-site.com").await;
+
+    let mut reply = vec![0u8; UPSTREAM_REPLY.len()];
+    client.read_exact(&mut reply).await.expect("upstream reply");
+    drop(client);
+
+    server_task.await.unwrap();
+    assert_eq!(
+        upstream_task.await.unwrap(),
+        client_hello_with_sni("blocked-site.com")
+    );
+
+    let closed = session_manager.closed_snapshot().await;
+    assert_eq!(closed[0].status, SessionStatus::Closed);
+    assert_eq!(closed[0].sni_host, None);
+    assert_eq!(acl_stats.snapshot().allowed, 1);
+}
@@ -0,0 +1,202 @@
+/// Integration tests for SOCKS4/SOCKS4a clients on the SOCKS5 listener
+/// (`server.enable_socks4`)
+mod common;
+
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, Action, Protocol};
+use rustsocks::server::{accept_loop, AcceptOptions, ClientHandlerContext};
+use rustsocks::session::{Session, SessionManager, SessionStatus};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout, Duration};
+
+const SOCKS4_GRANTED: u8 = 0x5A;
+const SOCKS4_REJECTED: u8 = 0x5B;
+
+/// `legacy` may reach loopback; `blocked` may not.
+fn acl_config() -> AclConfig {
+    let user = |username: &str, action: Action| UserAcl {
+        username: username.to_string(),
+        groups: vec![],
+        rules: vec![AclRule {
+            action,
+            description: format!("{} loopback", username),
+            destinations: vec!["127.0.0.1".to_string(), "localhost".to_string()],
+            ports: vec!["*".to_string()],
+            sources: vec![],
+            protocols: vec![Protocol::Tcp],
+            priority: 100,
+            log: RuleLogLevel::Default,
+            reply_code: None,
+            resolve: Default::default(),
+            schedule: None,
+        }],
+    };
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Block,
+        },
+        users: vec![
+            user("legacy", Action::Allow),
+            user("blocked", Action::Block),
+            user("anonymous", Action::Allow),
+        ],
+        groups: vec![],
+    }
+}
+
+async fn spawn_proxy(session_manager: Arc<SessionManager>, enable_socks4: bool) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let addr = listener.local_addr().expect("proxy addr");
+    let ctx = Arc::new(ClientHandlerContext {
+        acl_engine: Some(Arc::new(AclEngine::new(acl_config()).expect("acl engine"))),
+        enable_socks4,
+        ..common::handler_context(session_manager)
+    });
+    tokio::spawn(accept_loop(listener, ctx, AcceptOptions::default()));
+    addr
+}
+
+/// Upstream that echoes whatever it receives.
+async fn spawn_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr
+}
+
+/// Send a SOCKS4 CONNECT (SOCKS4a when `domain` is set) and return the reply code.
+async fn socks4_connect(
+    proxy: SocketAddr,
+    upstream: SocketAddr,
+    user_id: &str,
+    domain: Option<&str>,
+) -> (TcpStream, u8) {
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    let mut request = vec![0x04, 0x01];
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    match domain {
+        Some(_) => request.extend_from_slice(&[0, 0, 0, 1]),
+        None => request.extend_from_slice(&[127, 0, 0, 1]),
+    }
+    request.extend_from_slice(user_id.as_bytes());
+    request.push(0x00);
+    if let Some(domain) = domain {
+        request.extend_from_slice(domain.as_bytes());
+        request.push(0x00);
+    }
+    stream.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 8];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    assert_eq!(reply[0], 0x00, "SOCKS4 replies carry version 0");
+    (stream, reply[1])
+}
+
+async fn wait_for_sessions(session_manager: &SessionManager, count: usize) -> Vec<Session> {
+    for _ in 0..50 {
+        let sessions = session_manager.get_all_sessions().await;
+        if sessions.len() >= count {
+            return sessions;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    session_manager.get_all_sessions().await
+}
+
+#[tokio::test]
+async fn socks4_is_refused_unless_enabled() {
+    let session_manager = Arc::new(SessionManager::new());
+    let proxy = spawn_proxy(session_manager.clone(), false).await;
+    let upstream = spawn_echo().await;
+
+    let (_stream, code) = socks4_connect(proxy, upstream, "legacy", None).await;
+    assert_eq!(code, SOCKS4_REJECTED);
+    sleep(Duration::from_millis(100)).await;
+    assert!(session_manager.get_all_sessions().await.is_empty());
+}
+
+#[tokio::test]
+async fn socks4_connect_uses_the_user_id_and_records_the_version() {
+    let session_manager = Arc::new(SessionManager::new());
+    let proxy = spawn_proxy(session_manager.clone(), true).await;
+    let upstream = spawn_echo().await;
+
+    let (mut stream, code) = socks4_connect(proxy, upstream, "legacy", None).await;
+    assert_eq!(code, SOCKS4_GRANTED);
+    stream.write_all(b"ping").await.unwrap();
+    let mut echoed = [0u8; 4];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"ping");
+
+    let sessions = wait_for_sessions(&session_manager, 1).await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].user.as_ref(), "legacy");
+    assert_eq!(sessions[0].socks_version, 4);
+}
+
+#[tokio::test]
+async fn socks4a_resolves_the_domain_on_the_proxy() {
+    let session_manager = Arc::new(SessionManager::new());
+    let proxy = spawn_proxy(session_manager.clone(), true).await;
+    let upstream = spawn_echo().await;
+
+    let (_stream, code) = socks4_connect(proxy, upstream, "legacy", Some("localhost")).await;
+    assert_eq!(code, SOCKS4_GRANTED);
+
+    let sessions = wait_for_sessions(&session_manager, 1).await;
+    assert_eq!(sessions[0].socks_version, 4);
+    assert_eq!(sessions[0].requested_host.as_deref(), Some("localhost"));
+}
+
+#[tokio::test]
+async fn socks4_user_id_is_subject_to_the_acl() {
+    let session_manager = Arc::new(SessionManager::new());
+    let proxy = spawn_proxy(session_manager.clone(), true).await;
+    let upstream = spawn_echo().await;
+
+    let (_stream, code) = socks4_connect(proxy, upstream, "blocked", None).await;
+    assert_eq!(code, SOCKS4_REJECTED);
+
+    let sessions = wait_for_sessions(&session_manager, 1).await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].status, SessionStatus::RejectedByAcl);
+    assert_eq!(sessions[0].user.as_ref(), "blocked");
+    assert_eq!(sessions[0].socks_version, 4);
+}
+
+#[tokio::test]
+async fn socks5_sessions_record_version_5() {
+    let session_manager = Arc::new(SessionManager::new());
+    let proxy = spawn_proxy(session_manager.clone(), true).await;
+    let upstream = spawn_echo().await;
+
+    let mut stream = TcpStream::connect(proxy).await.expect("connect proxy");
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await.unwrap();
+    assert_eq!(choice, [0x05, 0x00]);
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&upstream.port().to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+
+    let sessions = wait_for_sessions(&session_manager, 1).await;
+    assert_eq!(sessions[0].socks_version, 5);
+}
@@ -0,0 +1,293 @@
+mod common;
+
+use futures::future::BoxFuture;
+use rustsocks::auth::AuthManager;
+use rustsocks::config::{AuthConfig, PamSettings, SpecialNamesSettings};
+use rustsocks::protocol::{Address, ReplyCode};
+use rustsocks::server::{
+    handle_client, ClientHandlerContext, DestinationResolver, SpecialNamesPolicy,
+};
+use rustsocks::session::SessionManager;
+use rustsocks::utils::error::Result;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::Duration;
+
+/// Resolver that records every lookup and answers all of them with `target`,
+/// whatever port was requested.
+struct RecordingResolver {
+    target: SocketAddr,
+    lookups: Mutex<Vec<String>>,
+}
+
+impl RecordingResolver {
+    fn new(target: SocketAddr) -> Arc<Self> {
+        Arc::new(Self {
+            target,
+            lookups: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn lookups(&self) -> Vec<String> {
+        self.lookups.lock().unwrap().clone()
+    }
+}
+
+impl DestinationResolver for RecordingResolver {
+    fn resolve<'a>(
+        &'a self,
+        address: &'a Address,
+        _port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        self.lookups.lock().unwrap().push(address.to_string());
+        Box::pin(async move { Ok(vec![self.target]) })
+    }
+}
+
+/// Port that refuses connections, so allowed CONNECTs fail fast after the lookup.
+async fn closed_port() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind probe");
+    let addr = listener.local_addr().expect("probe addr");
+    drop(listener);
+    addr
+}
+
+fn handler_context(
+    policy: SpecialNamesPolicy,
+    resolver: Arc<RecordingResolver>,
+    session_manager: Arc<SessionManager>,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(
+            AuthManager::new(&AuthConfig {
+                client_method: "none".into(),
+                socks_method: "none".into(),
+                socks_method_preference: Vec::new(),
+                users: Vec::new(),
+                users_file: None,
+                pam: PamSettings::default(),
+                gssapi: Default::default(),
+                ldap: Default::default(),
+                client_allow: Vec::new(),
+                client_deny: Vec::new(),
+                impersonation: Default::default(),
+                password_hashing: Default::default(),
+                allow_correlation_suffix: false,
+                correlation_separator: "#".to_string(),
+                tls_cert_identity: "cn".to_string(),
+            })
+            .expect("auth manager"),
+        ),
+        special_names: policy,
+        resolver,
+        ..common::handler_context(session_manager)
+    })
+}
+
+/// Send a SOCKS5 CONNECT for `domain` through a fresh handler and return the reply code,
+/// the session manager and the resolver used by the handler.
+async fn connect_domain(
+    policy: SpecialNamesPolicy,
+    domain: &str,
+) -> (u8, Arc<SessionManager>, Arc<RecordingResolver>) {
+    let resolver = RecordingResolver::new(closed_port().await);
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(policy, resolver.clone(), session_manager.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind test listener");
+    let addr = listener.local_addr().expect("listener addr");
+
+    let server_task = tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept test client");
+        // Allowed names end in a failed upstream connect, which the handler reports as Err
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(addr).await.expect("connect to handler");
+    client
+        .write_all(&[0x05, 0x01, 0x00])
+        .await
+        .expect("send greeting");
+    let mut response = [0u8; 2];
+    client
+        .read_exact(&mut response)
+        .await
+        .expect("read method selection");
+    assert_eq!(response, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, domain.len() as u8];
+    request.extend_from_slice(domain.as_bytes());
+    request.extend_from_slice(&80u16.to_be_bytes());
+    client
+        .write_all(&request)
+        .await
+        .expect("send connect request");
+
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.expect("read reply");
+    assert_eq!(reply[0], 0x05);
+
+    drop(client);
+    server_task.await.expect("handler finished");
+
+    (reply[1], session_manager, resolver)
+}
+
+async fn assert_blocked_without_lookup(
+    policy: SpecialNamesPolicy,
+    domain: &str,
+    expected_reply: ReplyCode,
+    category: &str,
+) {
+    let (reply, session_manager, resolver) = connect_domain(policy, domain).await;
+
+    assert_eq!(reply, expected_reply as u8, "reply for {}", domain);
+    assert!(
+        resolver.lookups().is_empty(),
+        "{} must not be resolved",
+        domain
+    );
+
+    let rejected = session_manager.rejected_snapshot().await;
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].dest_ip.as_ref(), domain);
+    assert_eq!(
+        rejected[0].acl_rule_matched.as_deref(),
+        Some(format!("special-name:{}", category).as_str())
+    );
+}
+
+#[tokio::test]
+async fn localhost_blocked_by_default() {
+    for domain in ["localhost", "app.localhost", "127.0.0.1"] {
+        assert_blocked_without_lookup(
+            SpecialNamesPolicy::default(),
+            domain,
+            ReplyCode::ConnectionNotAllowed,
+            "localhost",
+        )
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn allowed_names_reach_the_resolver() {
+    let (reply, _, resolver) =
+        connect_domain(SpecialNamesPolicy::localhost_allowed(), "localhost").await;
+    assert_eq!(reply, ReplyCode::HostUnreachable as u8);
+    assert_eq!(resolver.lookups(), vec!["localhost".to_string()]);
+
+    let (_, _, resolver) = connect_domain(SpecialNamesPolicy::default(), "example.com").await;
+    assert_eq!(resolver.lookups(), vec!["example.com".to_string()]);
+}
+
+#[tokio::test]
+async fn mdns_names_blocked_by_default() {
+    assert_blocked_without_lookup(
+        SpecialNamesPolicy::default(),
+        "printer.local",
+        ReplyCode::HostUnreachable,
+        "local",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn onion_and_i2p_always_blocked() {
+    let permissive = SpecialNamesPolicy::from(&SpecialNamesSettings {
+        localhost: "allow".to_string(),
+        local: "resolve".to_string(),
+    });
+
+    assert_blocked_without_lookup(
+        permissive,
+        "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion",
+        ReplyCode::NetworkUnreachable,
+        "onion",
+    )
+    .await;
+    assert_blocked_without_lookup(
+        permissive,
+        "stats.i2p",
+        ReplyCode::NetworkUnreachable,
+        "i2p",
+    )
+    .await;
+}
+
+fn udp_datagram(domain: &str, port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x00, 0x00, 0x00, 0x03, domain.len() as u8];
+    packet.extend_from_slice(domain.as_bytes());
+    packet.extend_from_slice(&port.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+#[tokio::test]
+async fn udp_datagrams_to_special_names_are_dropped_before_resolution() {
+    let sink = UdpSocket::bind("127.0.0.1:0").await.expect("bind sink");
+    let sink_addr = sink.local_addr().expect("sink addr");
+    let resolver = RecordingResolver::new(sink_addr);
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = handler_context(
+        SpecialNamesPolicy::default(),
+        resolver.clone(),
+        session_manager,
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind test listener");
+    let addr = listener.local_addr().expect("listener addr");
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept test client");
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut control = TcpStream::connect(addr).await.expect("connect to handler");
+    control.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    control.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.expect("bind client");
+    let mut associate = vec![0x05, 0x03, 0x00, 0x01, 127, 0, 0, 1];
+    associate.extend_from_slice(&client.local_addr().unwrap().port().to_be_bytes());
+    control.write_all(&associate).await.unwrap();
+    let mut reply = [0u8; 10];
+    control.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], ReplyCode::Succeeded as u8);
+    let relay_port = u16::from_be_bytes([reply[8], reply[9]]);
+
+    client.connect(("127.0.0.1", relay_port)).await.unwrap();
+    let port = sink_addr.port();
+    for domain in [
+        "localhost",
+        "printer.local",
+        "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion",
+    ] {
+        client
+            .send(&udp_datagram(domain, port, b"blocked"))
+            .await
+            .unwrap();
+    }
+    client
+        .send(&udp_datagram("example.com", port, b"allowed"))
+        .await
+        .unwrap();
+
+    // Datagrams are handled in order, so the first one to arrive is the allowed one
+    let mut buf = [0u8; 64];
+    let (len, _) = tokio::time::timeout(Duration::from_secs(2), sink.recv_from(&mut buf))
+        .await
+        .expect("allowed datagram should be relayed")
+        .unwrap();
+    assert_eq!(&buf[..len], b"allowed");
+    assert_eq!(resolver.lookups(), vec!["example.com".to_string()]);
+
+    drop(control);
+}
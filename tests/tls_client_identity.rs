@@ -0,0 +1,312 @@
+//! Session users taken from TLS client certificates (`auth.client_method = "tls.cert"`)
+mod common;
+
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+    KeyUsagePurpose, SanType,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use rustsocks::acl::types::{AclRule, GlobalAclConfig, RuleLogLevel, UserAcl};
+use rustsocks::acl::{AclConfig, AclEngine, AclStats, Action, Protocol};
+use rustsocks::auth::{certificate_identity, AuthManager, CertIdentityField};
+use rustsocks::config::{AuthConfig, TlsSettings};
+use rustsocks::protocol::ReplyCode;
+use rustsocks::server::{create_tls_acceptor, handle_tls_client, ClientHandlerContext};
+use rustsocks::session::SessionManager;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+
+/// Test CA issuing the server certificate and the client certificates.
+struct TestCa {
+    params: CertificateParams,
+    key: KeyPair,
+    der: Vec<u8>,
+    pem: String,
+}
+
+impl TestCa {
+    fn new() -> Self {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "RustSocks Test CA");
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        Self {
+            der: cert.der().to_vec(),
+            pem: cert.pem(),
+            params,
+            key,
+        }
+    }
+
+    /// Issue a certificate; returns the certificate and its private key as DER and PEM.
+    fn issue(&self, params: CertificateParams) -> (Vec<u8>, Vec<u8>, String, String) {
+        let key = KeyPair::generate().unwrap();
+        let issuer = Issuer::from_params(&self.params, &self.key);
+        let cert = params.signed_by(&key, &issuer).unwrap();
+        (
+            cert.der().to_vec(),
+            key.serialize_der(),
+            cert.pem(),
+            key.serialize_pem(),
+        )
+    }
+
+    fn client(&self, common_name: &str) -> (Vec<u8>, Vec<u8>) {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let (der, key_der, _, _) = self.issue(params);
+        (der, key_der)
+    }
+}
+
+/// Alice may reach everything; bob is blocked from the loopback address.
+fn acl_config() -> AclConfig {
+    AclConfig {
+        global: GlobalAclConfig {
+            default_policy: Action::Allow,
+        },
+        users: vec![UserAcl {
+            username: "bob".to_string(),
+            groups: vec![],
+            rules: vec![AclRule {
+                action: Action::Block,
+                description: "Block loopback for bob".to_string(),
+                destinations: vec!["127.0.0.1".to_string()],
+                ports: vec!["*".to_string()],
+                sources: vec![],
+                protocols: vec![Protocol::Tcp],
+                priority: 1000,
+                log: RuleLogLevel::Default,
+                reply_code: None,
+                resolve: Default::default(),
+                schedule: None,
+            }],
+        }],
+        groups: vec![],
+    }
+}
+
+struct Proxy {
+    addr: SocketAddr,
+    ca_der: Vec<u8>,
+    acl_stats: Arc<AclStats>,
+    session_manager: Arc<SessionManager>,
+    handler: tokio::task::JoinHandle<()>,
+}
+
+/// Start a mutual-TLS SOCKS5 handler with `tls.cert` client auth for a single client.
+async fn start_proxy(ca: &TestCa) -> Proxy {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let mut server_params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+    server_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    let (_, _, server_pem, server_key_pem) = ca.issue(server_params);
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let server_cert_path = temp_dir.path().join("server.crt");
+    let server_key_path = temp_dir.path().join("server.key");
+    let client_ca_path = temp_dir.path().join("clients-ca.crt");
+    std::fs::write(&server_cert_path, server_pem).unwrap();
+    std::fs::write(&server_key_path, server_key_pem).unwrap();
+    std::fs::write(&client_ca_path, &ca.pem).unwrap();
+
+    let acceptor = create_tls_acceptor(&TlsSettings {
+        enabled: true,
+        certificate_path: Some(server_cert_path.to_string_lossy().into_owned()),
+        private_key_path: Some(server_key_path.to_string_lossy().into_owned()),
+        client_ca_path: Some(client_ca_path.to_string_lossy().into_owned()),
+        require_client_auth: true,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let auth_manager = AuthManager::new(&AuthConfig {
+        client_method: "tls.cert".to_string(),
+        ..AuthConfig::default()
+    })
+    .unwrap();
+    assert!(auth_manager.uses_client_certificates());
+
+    let acl_stats = Arc::new(AclStats::new());
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(auth_manager),
+        acl_engine: Some(Arc::new(AclEngine::new(acl_config()).unwrap())),
+        acl_stats: acl_stats.clone(),
+        ..common::handler_context(session_manager.clone())
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handler = tokio::spawn(async move {
+        let _temp_dir = temp_dir;
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        let tls_stream = acceptor.accept(stream).await.unwrap();
+        handle_tls_client(tls_stream, ctx, client_addr).await.ok();
+    });
+
+    Proxy {
+        addr,
+        ca_der: ca.der.clone(),
+        acl_stats,
+        session_manager,
+        handler,
+    }
+}
+
+/// Connect through the proxy with a client certificate and return the CONNECT reply code.
+async fn connect_as(proxy: &Proxy, cert: (Vec<u8>, Vec<u8>), target: SocketAddr) -> u8 {
+    let mut root_store = RootCertStore::empty();
+    root_store
+        .add(CertificateDer::from(proxy.ca_der.clone()))
+        .unwrap();
+    let client_config = ClientConfig::builder()
+        .with_root_certificates(root_store)
+        .with_client_auth_cert(
+            vec![CertificateDer::from(cert.0)],
+            PrivateKeyDer::try_from(cert.1).unwrap(),
+        )
+        .unwrap();
+    let tcp = TcpStream::connect(proxy.addr).await.unwrap();
+    let mut client = TlsConnector::from(Arc::new(client_config))
+        .connect(ServerName::try_from("localhost").unwrap(), tcp)
+        .await
+        .unwrap();
+
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    client.read_exact(&mut choice).await.unwrap();
+    assert_eq!(choice, [0x05, 0x00]);
+
+    let port = target.port().to_be_bytes();
+    client
+        .write_all(&[0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1, port[0], port[1]])
+        .await
+        .unwrap();
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+
+    if reply[1] == ReplyCode::Succeeded as u8 {
+        client.write_all(b"ping").await.unwrap();
+        let mut pong = [0u8; 4];
+        client.read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"pong");
+    }
+    reply[1]
+}
+
+async fn spawn_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut inbound, _)) = listener.accept().await {
+            let mut buf = [0u8; 4];
+            if inbound.read_exact(&mut buf).await.is_ok() {
+                let _ = inbound.write_all(b"pong").await;
+            }
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn certificate_common_name_is_the_session_user() {
+    let ca = TestCa::new();
+    let target = spawn_echo().await;
+
+    let proxy = start_proxy(&ca).await;
+    let reply = connect_as(&proxy, ca.client("alice"), target).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    proxy.handler.await.unwrap();
+
+    let sessions = proxy.session_manager.get_all_sessions().await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].user.as_ref(), "alice");
+    assert_eq!(sessions[0].authenticated_user.as_deref(), Some("alice"));
+    assert_eq!(proxy.acl_stats.user_snapshot("alice").unwrap().allowed, 1);
+}
+
+#[tokio::test]
+async fn certificate_users_get_their_own_acl_rules() {
+    let ca = TestCa::new();
+    let target = spawn_echo().await;
+
+    let proxy = start_proxy(&ca).await;
+    let reply = connect_as(&proxy, ca.client("bob"), target).await;
+    assert_eq!(reply, ReplyCode::ConnectionNotAllowed as u8);
+    proxy.handler.await.unwrap();
+
+    let stats = proxy.acl_stats.user_snapshot("bob").unwrap();
+    assert_eq!((stats.allowed, stats.blocked), (0, 1));
+    assert!(proxy.acl_stats.user_snapshot("anonymous").is_none());
+}
+
+#[test]
+fn identity_fields_are_read_from_the_certificate() {
+    let ca = TestCa::new();
+    let mut params = CertificateParams::new(vec!["alice.example.com".into()]).unwrap();
+    params
+        .distinguished_name
+        .push(DnType::OrganizationName, "Example");
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "Alice Example");
+    params
+        .subject_alt_names
+        .push(SanType::Rfc822Name("alice@example.com".try_into().unwrap()));
+    params.subject_alt_names.push(SanType::URI(
+        "spiffe://example.com/alice".try_into().unwrap(),
+    ));
+    let (der, _, _, _) = ca.issue(params);
+
+    assert_eq!(
+        certificate_identity(&der, CertIdentityField::CommonName).as_deref(),
+        Some("Alice Example")
+    );
+    assert_eq!(
+        certificate_identity(&der, CertIdentityField::SanDns).as_deref(),
+        Some("alice.example.com")
+    );
+    assert_eq!(
+        certificate_identity(&der, CertIdentityField::SanEmail).as_deref(),
+        Some("alice@example.com")
+    );
+    assert_eq!(
+        certificate_identity(&der, CertIdentityField::SanUri).as_deref(),
+        Some("spiffe://example.com/alice")
+    );
+
+    // The CA has a common name but no subjectAltName
+    assert_eq!(
+        certificate_identity(&ca.der, CertIdentityField::SanEmail),
+        None
+    );
+}
+
+#[test]
+fn missing_certificates_fail_authentication() {
+    let auth_manager = AuthManager::new(&AuthConfig {
+        client_method: "tls.cert".to_string(),
+        tls_cert_identity: "san.email".to_string(),
+        ..AuthConfig::default()
+    })
+    .unwrap();
+    assert!(auth_manager.authenticate_certificate(None).is_err());
+
+    // alice's certificate has no email address
+    let ca = TestCa::new();
+    let (der, _) = ca.client("alice");
+    assert!(auth_manager.authenticate_certificate(Some(&der)).is_err());
+}
@@ -7,7 +7,7 @@ use rustls::{ClientConfig, RootCertStore};
 use rustsocks::acl::AclStats;
 use rustsocks::auth::AuthManager;
 use rustsocks::config::{AuthConfig, TlsSettings};
-use rustsocks::qos::{ConnectionLimits, QosEngine};
+use rustsocks::qos::QosEngine;
 use rustsocks::server::{
     create_tls_acceptor, handle_client, ClientHandlerContext, ConnectionPool, PoolConfig,
     TrafficUpdateConfig,
@@ -51,13 +51,23 @@ async fn socks5_connect_over_tls() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let ctx = Arc::new(ClientHandlerContext {
@@ -68,8 +78,25 @@ async fn socks5_connect_over_tls() {
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     let socks_listener = bind_nonblocking("127.0.0.1:0");
@@ -206,9 +233,19 @@ async fn socks5_connect_with_mutual_tls() {
         AuthManager::new(&AuthConfig {
             client_method: "none".to_string(),
             socks_method: "none".to_string(),
+            socks_method_preference: Vec::new(),
             users: vec![],
+            users_file: None,
             pam: Default::default(),
             gssapi: Default::default(),
+            ldap: Default::default(),
+            client_allow: Vec::new(),
+            client_deny: Vec::new(),
+            impersonation: Default::default(),
+            password_hashing: Default::default(),
+            allow_correlation_suffix: false,
+            correlation_separator: "#".to_string(),
+            tls_cert_identity: "cn".to_string(),
         })
         .unwrap(),
     );
@@ -217,12 +254,29 @@ async fn socks5_connect_with_mutual_tls() {
         auth_manager: auth_manager.clone(),
         acl_engine: None,
         acl_stats: Arc::new(AclStats::new()),
-        anonymous_user: Arc::new("anonymous".to_string()),
+        anonymous_user: Arc::<str>::from("anonymous"),
         session_manager: Arc::new(SessionManager::new()),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     let socks_listener = bind_nonblocking("127.0.0.1:0");
@@ -0,0 +1,203 @@
+//! Per-destination keepalive of idle tunnels (`[[server.tunnel_keepalive]]`)
+//!
+//! The upstream plays a middlebox that drops connections after a quiet period. It
+//! watches the kernel's received-segment counter rather than payload, so keepalive
+//! probes (which carry no bytes) count as activity, just as they would on the wire.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use futures::future::BoxFuture;
+use rustsocks::config::{ServerConfig, TunnelKeepaliveSettings};
+use rustsocks::protocol::{Address, ReplyCode};
+use rustsocks::server::{
+    handle_client, ClientHandlerContext, DestinationResolver, TunnelKeepalive,
+};
+use rustsocks::session::SessionManager;
+use rustsocks::Result;
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+/// Quiet period after which the upstream drops a connection
+const UPSTREAM_IDLE_TIMEOUT: Duration = Duration::from_millis(2500);
+
+/// Offset of `tcpi_segs_in` in the kernel's `struct tcp_info`
+const TCPI_SEGS_IN_OFFSET: usize = 140;
+
+/// Resolves every name to the local upstream.
+struct FixedResolver {
+    target: SocketAddr,
+}
+
+impl DestinationResolver for FixedResolver {
+    fn resolve<'a>(
+        &'a self,
+        _address: &'a Address,
+        _port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(async move { Ok(vec![self.target]) })
+    }
+}
+
+/// Segments received on `stream`, keepalive probes included.
+fn segments_in(stream: &TcpStream) -> u32 {
+    let mut info = [0u8; 232];
+    let mut len = info.len() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            info.as_mut_ptr().cast(),
+            &mut len,
+        )
+    };
+    assert_eq!(rc, 0, "TCP_INFO");
+    assert!(
+        len as usize >= TCPI_SEGS_IN_OFFSET + 4,
+        "tcp_info too short"
+    );
+    u32::from_ne_bytes(
+        info[TCPI_SEGS_IN_OFFSET..TCPI_SEGS_IN_OFFSET + 4]
+            .try_into()
+            .unwrap(),
+    )
+}
+
+/// Echo upstream that closes connections which receive nothing for `UPSTREAM_IDLE_TIMEOUT`.
+async fn spawn_idle_enforcing_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 64];
+                let mut seen = segments_in(&stream);
+                let mut last_activity = Instant::now();
+                loop {
+                    tokio::select! {
+                        read = stream.read(&mut buffer) => match read {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => {
+                                if stream.write_all(&buffer[..n]).await.is_err() {
+                                    return;
+                                }
+                            }
+                        },
+                        _ = sleep(Duration::from_millis(100)) => {}
+                    }
+
+                    let segments = segments_in(&stream);
+                    if segments != seen {
+                        seen = segments;
+                        last_activity = Instant::now();
+                    } else if last_activity.elapsed() >= UPSTREAM_IDLE_TIMEOUT {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+fn handler_context(
+    session_manager: Arc<SessionManager>,
+    upstream: SocketAddr,
+    server: &ServerConfig,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        resolver: Arc::new(FixedResolver { target: upstream }),
+        tunnel_keepalive: Arc::new(TunnelKeepalive::from(server)),
+        ..common::handler_context(session_manager)
+    })
+}
+
+/// Open a CONNECT tunnel to `host` through a fresh handler.
+async fn open_tunnel(ctx: Arc<ClientHandlerContext>, host: &str) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&443u16.to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], ReplyCode::Succeeded as u8);
+    client
+}
+
+/// Whether the tunnel still relays bytes end to end.
+async fn echoes(client: &mut TcpStream) -> bool {
+    if client.write_all(b"ping").await.is_err() {
+        return false;
+    }
+    let mut echo = [0u8; 4];
+    matches!(
+        timeout(Duration::from_secs(2), client.read_exact(&mut echo)).await,
+        Ok(Ok(_)) if &echo == b"ping"
+    )
+}
+
+fn keepalive_entry(destination: &str, mode: &str) -> TunnelKeepaliveSettings {
+    TunnelKeepaliveSettings {
+        destinations: vec![destination.to_string()],
+        interval_secs: 1,
+        mode: mode.to_string(),
+        user_timeout_secs: Some(10),
+    }
+}
+
+#[tokio::test]
+async fn matching_tunnels_survive_upstream_idle_timeout() {
+    let upstream = spawn_idle_enforcing_upstream().await;
+    let session_manager = Arc::new(SessionManager::new());
+    let server = ServerConfig {
+        tunnel_keepalive: vec![
+            keepalive_entry("probe.legacy.example", "probe"),
+            keepalive_entry("tcp.legacy.example", "tcp"),
+        ],
+        ..ServerConfig::default()
+    };
+    let ctx = handler_context(session_manager.clone(), upstream, &server);
+
+    let mut probed = open_tunnel(ctx.clone(), "probe.legacy.example").await;
+    let mut tcp = open_tunnel(ctx.clone(), "tcp.legacy.example").await;
+    let mut unmatched = open_tunnel(ctx, "other.example").await;
+
+    // Twice the upstream's idle timeout without a single relayed byte
+    sleep(UPSTREAM_IDLE_TIMEOUT * 2).await;
+
+    assert!(echoes(&mut probed).await, "probe tunnel was dropped");
+    assert!(echoes(&mut tcp).await, "tcp tunnel was dropped");
+    assert!(!echoes(&mut unmatched).await, "unmatched tunnel survived");
+
+    let sessions = session_manager.get_active_sessions().await;
+    let probe_session = sessions
+        .iter()
+        .find(|session| session.dest_ip.as_ref() == "probe.legacy.example")
+        .expect("probe session");
+    assert!(probe_session.keepalive_probes > 0);
+    let tcp_session = sessions
+        .iter()
+        .find(|session| session.dest_ip.as_ref() == "tcp.legacy.example")
+        .expect("tcp session");
+    assert_eq!(tcp_session.keepalive_probes, 0);
+}
@@ -0,0 +1,127 @@
+//! Allocation budget for the UDP relay data path.
+//!
+//! A counting global allocator records allocations made by the current thread while a
+//! measurement is active. The relay decapsulates client datagrams with `udp_header_len`
+//! and wraps replies with `encapsulate_udp_in_place`; both must stay allocation-free.
+
+use rustsocks::protocol::{
+    encapsulate_udp_in_place, serialize_udp_packet, udp_header_len, Address, UdpHeader, UdpPacket,
+    UDP_IP_HEADER_MAX,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::hint::black_box;
+use std::net::SocketAddr;
+
+/// Decapsulating a FRAG=0 datagram from the client.
+const DECAPSULATE_BUDGET: usize = 0;
+/// Wrapping a destination reply in its SOCKS5 header.
+const ENCAPSULATE_BUDGET: usize = 0;
+
+const DATAGRAMS: usize = 1_000;
+
+struct CountingAllocator;
+
+thread_local! {
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_allocation() {
+    let tracking = TRACKING.try_with(Cell::get).unwrap_or(false);
+    if tracking {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Run `f` and return its output with the number of allocations it made on this thread.
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    ALLOCATIONS.with(|count| count.set(0));
+    TRACKING.with(|tracking| tracking.set(true));
+    let output = f();
+    TRACKING.with(|tracking| tracking.set(false));
+
+    (output, ALLOCATIONS.with(Cell::get))
+}
+
+fn client_datagram(address: Address) -> Vec<u8> {
+    serialize_udp_packet(&UdpPacket {
+        header: UdpHeader {
+            frag: 0,
+            address,
+            port: 53,
+        },
+        data: vec![0xAB; 512].into(),
+    })
+}
+
+#[test]
+fn decapsulation_stays_within_budget() {
+    for datagram in [
+        client_datagram(Address::IPv4([192, 0, 2, 1])),
+        client_datagram(Address::IPv6([
+            0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ])),
+        client_datagram(Address::Domain("resolver.example.com".into())),
+    ] {
+        let (payload_len, allocations) = measure(|| {
+            let mut payload_len = 0;
+            for _ in 0..DATAGRAMS {
+                let header_len = udp_header_len(black_box(&datagram)).unwrap();
+                payload_len = datagram[header_len..].len();
+            }
+            payload_len
+        });
+        assert_eq!(payload_len, 512);
+        assert_eq!(
+            allocations, DECAPSULATE_BUDGET,
+            "decapsulating {} datagrams made {} allocations (budget {})",
+            DATAGRAMS, allocations, DECAPSULATE_BUDGET
+        );
+    }
+}
+
+#[test]
+fn encapsulation_stays_within_budget() {
+    let mut buf = vec![0u8; UDP_IP_HEADER_MAX + 65_535];
+    for source in ["192.0.2.1:53", "[2001:db8::1]:53"] {
+        let source: SocketAddr = source.parse().unwrap();
+        let (total, allocations) = measure(|| {
+            let mut total = 0;
+            for _ in 0..DATAGRAMS {
+                total += encapsulate_udp_in_place(&mut buf, 512, black_box(source)).len();
+            }
+            total
+        });
+        assert!(total > DATAGRAMS * 512);
+        assert_eq!(
+            allocations, ENCAPSULATE_BUDGET,
+            "encapsulating {} datagrams made {} allocations (budget {})",
+            DATAGRAMS, allocations, ENCAPSULATE_BUDGET
+        );
+    }
+}
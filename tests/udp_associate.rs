@@ -1,14 +1,18 @@
+use bytes::Bytes;
 use rustsocks::acl::{load_acl_config_sync, AclEngine, AclStats};
 use rustsocks::auth::AuthManager;
 use rustsocks::config::AuthConfig;
-use rustsocks::qos::{ConnectionLimits, QosEngine};
+use rustsocks::protocol::{serialize_udp_packet, Address, UdpHeader, UdpPacket};
+use rustsocks::qos::QosEngine;
 use rustsocks::server::{
     handle_client, ClientHandlerContext, ConnectionPool, PoolConfig, TrafficUpdateConfig,
 };
 use rustsocks::session::SessionManager;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::timeout;
 
 #[tokio::test]
 async fn udp_associate_basic_flow() {
@@ -16,13 +20,23 @@ async fn udp_associate_basic_flow() {
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let pool_config = PoolConfig::default();
@@ -36,8 +50,25 @@ async fn udp_associate_basic_flow() {
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: connection_pool.clone(),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     // Start SOCKS5 server
@@ -127,13 +158,23 @@ username = "anonymous"
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let pool_config = PoolConfig::default();
@@ -147,8 +188,25 @@ username = "anonymous"
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: connection_pool.clone(),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     // Start SOCKS5 server
@@ -214,13 +272,23 @@ username = "anonymous"
     let auth_config = AuthConfig {
         client_method: "none".to_string(),
         socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
         users: vec![],
+        users_file: None,
         pam: Default::default(),
         gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
     };
     let auth_manager = Arc::new(AuthManager::new(&auth_config).unwrap());
     let acl_stats = Arc::new(AclStats::new());
-    let anonymous_user = Arc::new("anonymous".to_string());
+    let anonymous_user = Arc::<str>::from("anonymous");
     let session_manager = Arc::new(SessionManager::new());
 
     let pool_config = PoolConfig::default();
@@ -234,8 +302,25 @@ username = "anonymous"
         session_manager: session_manager.clone(),
         traffic_config: TrafficUpdateConfig::default(),
         qos_engine: QosEngine::None,
-        connection_limits: ConnectionLimits::default(),
+        connection_limits: Default::default(),
         connection_pool: connection_pool.clone(),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
     });
 
     // Start SOCKS5 server
@@ -271,3 +356,132 @@ username = "anonymous"
     let user_stats = acl_stats.user_snapshot("anonymous").unwrap();
     assert_eq!(user_stats.blocked, 1);
 }
+
+#[tokio::test]
+async fn udp_associate_relays_datagrams_byte_for_byte() {
+    let auth_config = AuthConfig {
+        client_method: "none".to_string(),
+        socks_method: "none".to_string(),
+        socks_method_preference: Vec::new(),
+        users: vec![],
+        users_file: None,
+        pam: Default::default(),
+        gssapi: Default::default(),
+        ldap: Default::default(),
+        client_allow: Vec::new(),
+        client_deny: Vec::new(),
+        impersonation: Default::default(),
+        password_hashing: Default::default(),
+        allow_correlation_suffix: false,
+        correlation_separator: "#".to_string(),
+        tls_cert_identity: "cn".to_string(),
+    };
+    let ctx = Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(AuthManager::new(&auth_config).unwrap()),
+        acl_engine: None,
+        acl_stats: Arc::new(AclStats::new()),
+        anonymous_user: Arc::<str>::from("anonymous"),
+        session_manager: Arc::new(SessionManager::new()),
+        traffic_config: TrafficUpdateConfig::default(),
+        qos_engine: QosEngine::None,
+        connection_limits: Default::default(),
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+        special_names: rustsocks::server::SpecialNamesPolicy::localhost_allowed(),
+        sni_routing: rustsocks::server::SniRouting::default(),
+        resolver: Arc::new(rustsocks::server::SystemResolver),
+        host_hints: None,
+        tunnel_keepalive: Default::default(),
+        upstream_socket_options: Default::default(),
+        egress: Default::default(),
+        upstream_proxy: None,
+        upstream_tls: None,
+        udp_association: Default::default(),
+        udp_datagrams: Default::default(),
+        bind_accept_timeout: rustsocks::server::DEFAULT_BIND_ACCEPT_TIMEOUT,
+        enable_socks4: false,
+        address_selection: Default::default(),
+        connect_retry: Default::default(),
+        handshake: Default::default(),
+        allow_domain_requests: true,
+    });
+
+    // The echo server lives on a different loopback address than the client, so its
+    // replies can never be mistaken for client datagrams
+    let echo = UdpSocket::bind("127.0.0.2:0").await.unwrap();
+    let echo_addr = echo.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((len, peer)) = echo.recv_from(&mut buf).await {
+            let _ = echo.send_to(&buf[..len], peer).await;
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        handle_client(stream, ctx, client_addr).await.ok();
+    });
+
+    let mut client = TcpStream::connect(server_addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    client.read_exact(&mut choice).await.unwrap();
+
+    // State the endpoint datagrams will come from, as strict matching requires
+    let udp_client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut associate = vec![0x05, 0x03, 0x00, 0x01, 127, 0, 0, 1];
+    associate.extend_from_slice(&udp_client.local_addr().unwrap().port().to_be_bytes());
+    client.write_all(&associate).await.unwrap();
+    let mut response = [0u8; 10];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(response[1], 0x00);
+    let relay_port = u16::from_be_bytes([response[8], response[9]]);
+
+    udp_client.connect(("127.0.0.1", relay_port)).await.unwrap();
+
+    let target = UdpHeader {
+        frag: 0,
+        address: Address::IPv4([127, 0, 0, 2]),
+        port: echo_addr.port(),
+    };
+    let fragment = UdpPacket {
+        header: UdpHeader {
+            frag: 1,
+            ..target.clone()
+        },
+        data: Bytes::from_static(b"fragment"),
+    };
+
+    // The second datagram to the same target takes the cached path; the fragment in
+    // between is dropped without disturbing it
+    for (i, payload) in [&b"first"[..], &b"second"[..]].into_iter().enumerate() {
+        if i == 1 {
+            udp_client
+                .send(&serialize_udp_packet(&fragment))
+                .await
+                .unwrap();
+        }
+        let request = UdpPacket {
+            header: target.clone(),
+            data: Bytes::copy_from_slice(payload),
+        };
+        udp_client
+            .send(&serialize_udp_packet(&request))
+            .await
+            .unwrap();
+
+        let mut reply = [0u8; 2048];
+        let len = timeout(Duration::from_secs(2), udp_client.recv(&mut reply))
+            .await
+            .expect("relay reply")
+            .unwrap();
+        let expected = UdpPacket {
+            header: target.clone(),
+            data: Bytes::copy_from_slice(payload),
+        };
+        assert_eq!(&reply[..len], serialize_udp_packet(&expected).as_slice());
+    }
+
+    drop(client);
+}
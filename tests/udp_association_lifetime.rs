@@ -0,0 +1,183 @@
+//! UDP ASSOCIATE lifetime and source restriction
+//!
+//! An association lives as long as its TCP control connection, ends after
+//! `server.udp.association_idle_timeout_secs` without traffic, and only relays the
+//! client endpoint the request declared.
+mod common;
+
+use bytes::Bytes;
+use rustsocks::protocol::{serialize_udp_packet, Address, UdpHeader, UdpPacket};
+use rustsocks::server::{handle_client, ClientHandlerContext, UdpDatagramLimits};
+use rustsocks::session::{Session, SessionManager, SessionStatus, UdpAssociationMode};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::{sleep, timeout};
+
+struct Association {
+    control: TcpStream,
+    relay: SocketAddr,
+    session_manager: Arc<SessionManager>,
+}
+
+/// Echo server on a different loopback address than the client.
+async fn spawn_echo() -> SocketAddr {
+    let echo = UdpSocket::bind("127.0.0.2:0").await.unwrap();
+    let addr = echo.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((len, peer)) = echo.recv_from(&mut buf).await {
+            let _ = echo.send_to(&buf[..len], peer).await;
+        }
+    });
+    addr
+}
+
+/// Associate in strict mode, declaring `declared_port` on 127.0.0.1.
+async fn associate(idle_timeout: Duration, declared_port: u16) -> Association {
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = Arc::new(ClientHandlerContext {
+        udp_association: UdpAssociationMode::Strict,
+        udp_datagrams: UdpDatagramLimits {
+            idle_timeout,
+            ..Default::default()
+        },
+        ..common::handler_context(session_manager.clone())
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        handle_client(stream, ctx, client_addr).await.ok();
+    });
+
+    let mut control = TcpStream::connect(server_addr).await.unwrap();
+    control.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    control.read_exact(&mut choice).await.unwrap();
+    let port = declared_port.to_be_bytes();
+    control
+        .write_all(&[0x05, 0x03, 0x00, 0x01, 127, 0, 0, 1, port[0], port[1]])
+        .await
+        .unwrap();
+    let mut response = [0u8; 10];
+    control.read_exact(&mut response).await.unwrap();
+    assert_eq!(response[1], 0x00);
+    let relay_port = u16::from_be_bytes([response[8], response[9]]);
+
+    Association {
+        control,
+        relay: SocketAddr::from(([127, 0, 0, 1], relay_port)),
+        session_manager,
+    }
+}
+
+/// A UDP socket on the client's address, connected to `relay`.
+async fn client_socket(relay: SocketAddr) -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(relay).await.unwrap();
+    socket
+}
+
+/// Send `payload` to `echo`; whether it came back through the relay.
+async fn echoes(socket: &UdpSocket, echo: SocketAddr, payload: &'static [u8]) -> bool {
+    let packet = UdpPacket {
+        header: UdpHeader {
+            frag: 0,
+            address: Address::IPv4([127, 0, 0, 2]),
+            port: echo.port(),
+        },
+        data: Bytes::from_static(payload),
+    };
+    socket.send(&serialize_udp_packet(&packet)).await.unwrap();
+
+    let mut reply = [0u8; 2048];
+    match timeout(Duration::from_millis(500), socket.recv(&mut reply)).await {
+        Ok(Ok(len)) => reply[..len].ends_with(payload),
+        _ => false,
+    }
+}
+
+async fn closed_session(session_manager: &SessionManager) -> Session {
+    for _ in 0..50 {
+        if let Some(session) = session_manager
+            .get_closed_sessions()
+            .await
+            .into_iter()
+            .next()
+        {
+            return session;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    panic!("UDP session was not closed");
+}
+
+#[tokio::test]
+async fn spoofed_source_is_dropped_and_counted() {
+    // Bound first so its port can be declared in the request
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let declared_port = client.local_addr().unwrap().port();
+    let association = associate(Duration::from_secs(120), declared_port).await;
+    client.connect(association.relay).await.unwrap();
+    let echo = spawn_echo().await;
+
+    // Same IP, another port than the one declared
+    let spoofed = client_socket(association.relay).await;
+    assert!(!echoes(&spoofed, echo, b"spoofed").await);
+    assert!(!echoes(&spoofed, echo, b"spoofed again").await);
+    assert!(echoes(&client, echo, b"declared").await);
+
+    let session = association
+        .session_manager
+        .get_active_sessions()
+        .await
+        .into_iter()
+        .next()
+        .expect("UDP session");
+    assert_eq!(session.udp_client_endpoint, client.local_addr().ok());
+    assert_eq!(session.udp_rejected_datagrams, 2);
+}
+
+#[tokio::test]
+async fn closing_control_connection_tears_down_relay() {
+    let association = associate(Duration::from_secs(120), 0).await;
+    let relay = association.relay;
+    let session_manager = association.session_manager.clone();
+
+    drop(association.control);
+
+    let session = closed_session(&session_manager).await;
+    assert_eq!(session.status, SessionStatus::Closed);
+    assert_eq!(
+        session.close_reason.as_deref(),
+        Some("TCP control connection closed")
+    );
+    // The relay socket is released, not left waiting for the idle timeout
+    let mut rebound = false;
+    for _ in 0..50 {
+        if UdpSocket::bind(relay).await.is_ok() {
+            rebound = true;
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert!(rebound, "relay port {} still bound", relay);
+}
+
+#[tokio::test]
+async fn idle_timeout_closes_control_connection() {
+    let mut association = associate(Duration::from_millis(300), 0).await;
+
+    let mut buf = [0u8; 16];
+    let read = timeout(Duration::from_secs(5), association.control.read(&mut buf))
+        .await
+        .expect("control connection closed within 5s");
+    assert!(matches!(read, Ok(0) | Err(_)));
+
+    let session = closed_session(&association.session_manager).await;
+    assert_eq!(session.close_reason.as_deref(), Some("UDP session timeout"));
+}
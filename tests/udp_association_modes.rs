@@ -0,0 +1,148 @@
+//! UDP ASSOCIATE source matching (`server.udp_association_mode`)
+//!
+//! Every test associates with 0.0.0.0:0, as many clients do, and then sends from a
+//! UDP socket of its own choosing.
+mod common;
+
+use bytes::Bytes;
+use rustsocks::protocol::{serialize_udp_packet, Address, UdpHeader, UdpPacket};
+use rustsocks::server::{handle_client, ClientHandlerContext};
+use rustsocks::session::{Session, SessionManager, UdpAssociationMode};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::{sleep, timeout};
+
+struct Association {
+    /// Keeps the association open
+    _control: TcpStream,
+    relay: SocketAddr,
+    session_manager: Arc<SessionManager>,
+    echo: SocketAddr,
+}
+
+/// Echo server on a different loopback address than the client.
+async fn spawn_echo() -> SocketAddr {
+    let echo = UdpSocket::bind("127.0.0.2:0").await.unwrap();
+    let addr = echo.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((len, peer)) = echo.recv_from(&mut buf).await {
+            let _ = echo.send_to(&buf[..len], peer).await;
+        }
+    });
+    addr
+}
+
+async fn associate(mode: UdpAssociationMode) -> Association {
+    let session_manager = Arc::new(SessionManager::new());
+    let ctx = Arc::new(ClientHandlerContext {
+        udp_association: mode,
+        ..common::handler_context(session_manager.clone())
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        handle_client(stream, ctx, client_addr).await.ok();
+    });
+
+    let mut control = TcpStream::connect(server_addr).await.unwrap();
+    control.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    control.read_exact(&mut choice).await.unwrap();
+    control
+        .write_all(&[0x05, 0x03, 0x00, 0x01, 0, 0, 0, 0, 0x00, 0x00])
+        .await
+        .unwrap();
+    let mut response = [0u8; 10];
+    control.read_exact(&mut response).await.unwrap();
+    assert_eq!(response[1], 0x00);
+    let relay_port = u16::from_be_bytes([response[8], response[9]]);
+
+    Association {
+        _control: control,
+        relay: SocketAddr::from(([127, 0, 0, 1], relay_port)),
+        session_manager,
+        echo: spawn_echo().await,
+    }
+}
+
+impl Association {
+    /// A UDP socket on the client's address, connected to the relay.
+    async fn socket(&self) -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(self.relay).await.unwrap();
+        socket
+    }
+
+    /// Send `payload` to the echo server; whether it came back through the relay.
+    async fn echoes(&self, socket: &UdpSocket, payload: &'static [u8]) -> bool {
+        let packet = UdpPacket {
+            header: UdpHeader {
+                frag: 0,
+                address: Address::IPv4([127, 0, 0, 2]),
+                port: self.echo.port(),
+            },
+            data: Bytes::from_static(payload),
+        };
+        socket.send(&serialize_udp_packet(&packet)).await.unwrap();
+
+        let mut reply = [0u8; 2048];
+        match timeout(Duration::from_millis(500), socket.recv(&mut reply)).await {
+            Ok(Ok(len)) => reply[..len].ends_with(payload),
+            _ => false,
+        }
+    }
+
+    async fn session(&self) -> Session {
+        self.session_manager
+            .get_active_sessions()
+            .await
+            .into_iter()
+            .find(|session| session.udp_association_mode.is_some())
+            .expect("UDP session")
+    }
+}
+
+#[tokio::test]
+async fn strict_rejects_undeclared_source_port() {
+    let association = associate(UdpAssociationMode::Strict).await;
+    let socket = association.socket().await;
+
+    assert!(!association.echoes(&socket, b"strict").await);
+
+    let session = association.session().await;
+    assert_eq!(
+        session.udp_association_mode,
+        Some(UdpAssociationMode::Strict)
+    );
+    // Nothing was stated, so only the control connection's endpoint would be accepted
+    assert_ne!(session.udp_client_endpoint, socket.local_addr().ok());
+    assert_eq!(session.udp_rejected_datagrams, 1);
+}
+
+#[tokio::test]
+async fn ip_only_and_learned_lock_onto_first_source() {
+    for mode in [UdpAssociationMode::IpOnly, UdpAssociationMode::Learned] {
+        let association = associate(mode).await;
+        let socket = association.socket().await;
+
+        assert!(association.echoes(&socket, b"first").await, "{}", mode);
+        assert!(association.echoes(&socket, b"second").await, "{}", mode);
+
+        // Same IP, different port: not the learned endpoint any more
+        let spoofed = association.socket().await;
+        assert!(!association.echoes(&spoofed, b"spoofed").await, "{}", mode);
+        assert!(association.echoes(&socket, b"third").await, "{}", mode);
+
+        sleep(Duration::from_millis(50)).await;
+        let session = association.session().await;
+        assert_eq!(session.udp_association_mode, Some(mode));
+        assert_eq!(session.udp_client_endpoint, socket.local_addr().ok());
+        assert_eq!(session.udp_rejected_datagrams, 1, "{}", mode);
+    }
+}
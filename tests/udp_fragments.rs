@@ -0,0 +1,155 @@
+//! Fragmented and oversized UDP ASSOCIATE datagrams (`[server.udp]`)
+mod common;
+
+use rustsocks::server::{handle_client, ClientHandlerContext, FragmentLimits, UdpDatagramLimits};
+use rustsocks::session::{SessionManager, UdpAssociationMode};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+struct Association {
+    /// Keeps the association open
+    _control: TcpStream,
+    client: UdpSocket,
+    echo: SocketAddr,
+}
+
+/// Echo server on a different loopback address than the client.
+async fn spawn_echo() -> SocketAddr {
+    let echo = UdpSocket::bind("127.0.0.2:0").await.unwrap();
+    let addr = echo.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((len, peer)) = echo.recv_from(&mut buf).await {
+            let _ = echo.send_to(&buf[..len], peer).await;
+        }
+    });
+    addr
+}
+
+async fn associate(limits: UdpDatagramLimits) -> Association {
+    let ctx = Arc::new(ClientHandlerContext {
+        udp_association: UdpAssociationMode::IpOnly,
+        udp_datagrams: limits,
+        ..common::handler_context(Arc::new(SessionManager::new()))
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        handle_client(stream, ctx, client_addr).await.ok();
+    });
+
+    let mut control = TcpStream::connect(server_addr).await.unwrap();
+    control.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    control.read_exact(&mut choice).await.unwrap();
+    control
+        .write_all(&[0x05, 0x03, 0x00, 0x01, 0, 0, 0, 0, 0x00, 0x00])
+        .await
+        .unwrap();
+    let mut response = [0u8; 10];
+    control.read_exact(&mut response).await.unwrap();
+    assert_eq!(response[1], 0x00);
+    let relay_port = u16::from_be_bytes([response[8], response[9]]);
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client
+        .connect(SocketAddr::from(([127, 0, 0, 1], relay_port)))
+        .await
+        .unwrap();
+    Association {
+        _control: control,
+        client,
+        echo: spawn_echo().await,
+    }
+}
+
+impl Association {
+    /// Send one SOCKS5 UDP datagram to the echo server with the given FRAG field
+    async fn send(&self, frag: u8, payload: &[u8]) {
+        let mut datagram = vec![0x00, 0x00, frag, 0x01, 127, 0, 0, 2];
+        datagram.extend_from_slice(&self.echo.port().to_be_bytes());
+        datagram.extend_from_slice(payload);
+        self.client.send(&datagram).await.unwrap();
+    }
+
+    /// Payload of the next reply relayed back, if one arrives
+    async fn reply(&self) -> Option<Vec<u8>> {
+        let mut buf = [0u8; 2048];
+        let len = timeout(Duration::from_millis(300), self.client.recv(&mut buf))
+            .await
+            .ok()?
+            .unwrap();
+        assert_eq!(buf[2], 0x00, "replies are never fragmented");
+        Some(buf[10..len].to_vec())
+    }
+}
+
+fn with_fragments() -> UdpDatagramLimits {
+    UdpDatagramLimits {
+        fragments: Some(FragmentLimits {
+            max_queues: 2,
+            timeout: Duration::from_secs(5),
+        }),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn fragments_are_dropped_when_reassembly_is_off() {
+    let association = associate(UdpDatagramLimits::default()).await;
+
+    association.send(0x01, b"hello ").await;
+    association.send(0x82, b"world").await;
+    assert_eq!(association.reply().await, None);
+
+    // Whole datagrams still go through
+    association.send(0x00, b"ping").await;
+    assert_eq!(association.reply().await.as_deref(), Some(&b"ping"[..]));
+}
+
+#[tokio::test]
+async fn fragments_are_reassembled_in_order() {
+    let association = associate(with_fragments()).await;
+
+    association.send(0x01, b"hello ").await;
+    association.send(0x02, b"fragmented ").await;
+    association.send(0x83, b"world").await;
+    assert_eq!(
+        association.reply().await.as_deref(),
+        Some(&b"hello fragmented world"[..])
+    );
+
+    // A missing fragment drops the whole datagram
+    association.send(0x01, b"lost ").await;
+    association.send(0x83, b"gap").await;
+    assert_eq!(association.reply().await, None);
+
+    association.send(0x00, b"ping").await;
+    assert_eq!(association.reply().await.as_deref(), Some(&b"ping"[..]));
+}
+
+#[tokio::test]
+async fn oversized_datagrams_are_dropped() {
+    let association = associate(UdpDatagramLimits {
+        max_datagram_size: 64,
+        ..with_fragments()
+    })
+    .await;
+
+    association.send(0x00, &[0xAB; 100]).await;
+    assert_eq!(association.reply().await, None);
+
+    // Fragments that fit one by one but not together
+    association.send(0x01, &[0xAB; 40]).await;
+    association.send(0x82, &[0xAB; 40]).await;
+    assert_eq!(association.reply().await, None);
+
+    association.send(0x00, &[0xAB; 54]).await;
+    assert_eq!(association.reply().await, Some(vec![0xAB; 54]));
+}
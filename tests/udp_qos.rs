@@ -0,0 +1,174 @@
+//! QoS on UDP ASSOCIATE relays
+//!
+//! Datagrams take tokens from the user's buckets like TCP traffic, but one that the
+//! buckets cannot cover is dropped rather than queued. A client sending far above its
+//! limit therefore sees its throughput settle near the configured rate, with the
+//! surplus counted in the session's `udp_throttled_datagrams`.
+mod common;
+
+use bytes::Bytes;
+use rustsocks::protocol::{serialize_udp_packet, Address, UdpHeader, UdpPacket};
+use rustsocks::qos::{HtbConfig, QosConfig, QosEngine};
+use rustsocks::server::{handle_client, ClientHandlerContext};
+use rustsocks::session::SessionManager;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::{sleep, Instant};
+
+const GUARANTEED_RATE: u64 = 1_000;
+const MAX_RATE: u64 = 20_000;
+const BURST: u64 = 2_000;
+const DATAGRAM: usize = 500;
+
+/// UDP sink counting the payload bytes it receives.
+async fn spawn_sink() -> (SocketAddr, Arc<AtomicU64>) {
+    let sink = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = sink.local_addr().unwrap();
+    let received = Arc::new(AtomicU64::new(0));
+    let counter = received.clone();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok(len) = sink.recv(&mut buf).await {
+            counter.fetch_add(len as u64, Ordering::Relaxed);
+        }
+    });
+    (addr, received)
+}
+
+async fn shaped_engine() -> QosEngine {
+    QosEngine::from_config(QosConfig {
+        enabled: true,
+        htb: HtbConfig {
+            global_bandwidth_bytes_per_sec: 10_000_000,
+            guaranteed_bandwidth_bytes_per_sec: GUARANTEED_RATE,
+            max_bandwidth_bytes_per_sec: MAX_RATE,
+            burst_size_bytes: BURST,
+            fair_sharing_enabled: false,
+            ..HtbConfig::default()
+        },
+        ..QosConfig::default()
+    })
+    .await
+    .unwrap()
+}
+
+/// Open an association for datagrams from `client` through a handler using
+/// `qos_engine`; returns the control connection (which keeps the association open) and
+/// the relay address.
+async fn associate(
+    qos_engine: QosEngine,
+    session_manager: Arc<SessionManager>,
+    client: SocketAddr,
+) -> (TcpStream, SocketAddr) {
+    let ctx = Arc::new(ClientHandlerContext {
+        qos_engine,
+        ..common::handler_context(session_manager)
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        handle_client(stream, ctx, client_addr).await.ok();
+    });
+
+    let mut control = TcpStream::connect(server_addr).await.unwrap();
+    control.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    control.read_exact(&mut choice).await.unwrap();
+    let mut request = vec![0x05, 0x03, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&client.port().to_be_bytes());
+    control.write_all(&request).await.unwrap();
+    let mut response = [0u8; 10];
+    control.read_exact(&mut response).await.unwrap();
+    assert_eq!(response[1], 0x00);
+    let relay_port = u16::from_be_bytes([response[8], response[9]]);
+
+    (control, SocketAddr::from(([127, 0, 0, 1], relay_port)))
+}
+
+#[tokio::test]
+async fn udp_throughput_converges_near_the_user_limit() {
+    let (sink, received) = spawn_sink().await;
+    let session_manager = Arc::new(SessionManager::new());
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let (_control, relay) = associate(
+        shaped_engine().await,
+        session_manager.clone(),
+        client.local_addr().unwrap(),
+    )
+    .await;
+    client.connect(relay).await.unwrap();
+    let packet = serialize_udp_packet(&UdpPacket {
+        header: UdpHeader {
+            frag: 0,
+            address: Address::IPv4([127, 0, 0, 1]),
+            port: sink.port(),
+        },
+        data: Bytes::from(vec![0x5a; DATAGRAM]),
+    });
+
+    // About 250 kB/s offered, more than ten times what the user may send
+    let window = Duration::from_millis(1500);
+    let started = Instant::now();
+    let mut offered = 0u64;
+    while started.elapsed() < window {
+        client.send(&packet).await.unwrap();
+        offered += DATAGRAM as u64;
+        sleep(Duration::from_millis(2)).await;
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    sleep(Duration::from_millis(100)).await;
+
+    let received = received.load(Ordering::Relaxed);
+    // Both user buckets refill, and each starts with a full burst
+    let expected = (GUARANTEED_RATE + MAX_RATE) as f64 * elapsed + (2 * BURST) as f64;
+    assert!(
+        (received as f64) < expected * 1.3,
+        "received {received} bytes, expected about {expected:.0}"
+    );
+    assert!(
+        (received as f64) > expected * 0.5,
+        "received {received} bytes, expected about {expected:.0}"
+    );
+    assert!(received < offered / 4, "most datagrams are dropped");
+
+    let session = session_manager
+        .get_active_sessions()
+        .await
+        .into_iter()
+        .next()
+        .expect("UDP session");
+    let dropped = session.udp_throttled_datagrams * DATAGRAM as u64;
+    assert!(
+        dropped >= offered - received - offered / 20,
+        "{dropped} of {offered} bytes counted as dropped, {received} delivered"
+    );
+}
+
+#[tokio::test]
+async fn datagram_over_the_limit_is_refused_without_waiting() {
+    let engine = shaped_engine().await;
+    let user: Arc<str> = Arc::from("alice");
+    let ip = "127.0.0.1".parse().unwrap();
+
+    // The guaranteed bucket covers the first burst, the max bucket the second. The
+    // global bucket holds one burst too, so it gets a moment to refill in between.
+    for _ in 0..2 {
+        assert_eq!(
+            engine.try_allocate_bandwidth_from(&user, ip, BURST).await,
+            Ok(())
+        );
+        sleep(Duration::from_millis(1)).await;
+    }
+    let started = Instant::now();
+    assert_eq!(
+        engine.try_allocate_bandwidth_from(&user, ip, BURST).await,
+        Err("user")
+    );
+    assert!(started.elapsed() < Duration::from_millis(50));
+}
@@ -0,0 +1,326 @@
+//! Chaining CONNECTs through a parent SOCKS5 proxy (`[server.upstream]`)
+//!
+//! A minimal parent proxy records the CONNECT it is asked for and then echoes, so the
+//! tests can tell chained tunnels (name handed to the parent, no local lookup) from
+//! direct ones (resolved locally, straight to the echo server) and check that chained
+//! sessions are drained once the parent is unhealthy.
+
+mod common;
+
+use futures::future::BoxFuture;
+use rustsocks::config::UpstreamProxySettings;
+use rustsocks::protocol::{Address, ReplyCode};
+use rustsocks::server::upstream_proxy::UPSTREAM_UNHEALTHY_CLOSE_REASON;
+use rustsocks::server::{
+    handle_client, ClientHandlerContext, ConnectionPool, DestinationResolver, PoolConfig,
+    UpstreamProxy,
+};
+use rustsocks::session::{Session, SessionManager, SessionStatus};
+use rustsocks::Result;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+
+/// Resolves every name to the echo server and remembers what it was asked.
+struct RecordingResolver {
+    target: SocketAddr,
+    lookups: Mutex<Vec<String>>,
+}
+
+impl DestinationResolver for RecordingResolver {
+    fn resolve<'a>(
+        &'a self,
+        address: &'a Address,
+        _port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        self.lookups.lock().unwrap().push(address.to_string());
+        Box::pin(async move { Ok(vec![self.target]) })
+    }
+}
+
+async fn spawn_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind echo");
+    let addr = listener.local_addr().expect("echo addr");
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr
+}
+
+/// Parent proxy requiring `gateway`/`secret`; answers every CONNECT with `reply` and
+/// records the requested `host:port`.
+async fn spawn_parent(reply: u8) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind parent");
+    let addr = listener.local_addr().expect("parent addr");
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let seen = seen.clone();
+            tokio::spawn(async move {
+                let mut header = [0u8; 2];
+                stream.read_exact(&mut header).await.unwrap();
+                let mut methods = vec![0u8; header[1] as usize];
+                stream.read_exact(&mut methods).await.unwrap();
+                assert!(methods.contains(&0x02), "credentials offered");
+                stream.write_all(&[0x05, 0x02]).await.unwrap();
+
+                let mut version_ulen = [0u8; 2];
+                stream.read_exact(&mut version_ulen).await.unwrap();
+                let mut username = vec![0u8; version_ulen[1] as usize];
+                stream.read_exact(&mut username).await.unwrap();
+                let mut password = vec![0u8; stream.read_u8().await.unwrap() as usize];
+                stream.read_exact(&mut password).await.unwrap();
+                let accepted = username == b"gateway" && password == b"secret";
+                stream
+                    .write_all(&[0x01, if accepted { 0x00 } else { 0x01 }])
+                    .await
+                    .unwrap();
+                if !accepted {
+                    return;
+                }
+
+                let mut request = [0u8; 4];
+                stream.read_exact(&mut request).await.unwrap();
+                assert_eq!(request[3], 0x03, "names are passed to the parent");
+                let mut host = vec![0u8; stream.read_u8().await.unwrap() as usize];
+                stream.read_exact(&mut host).await.unwrap();
+                let port = stream.read_u16().await.unwrap();
+                seen.lock()
+                    .unwrap()
+                    .push(format!("{}:{}", String::from_utf8(host).unwrap(), port));
+
+                stream
+                    .write_all(&[0x05, reply, 0x00, 0x01, 10, 0, 0, 1, 0x04, 0x38])
+                    .await
+                    .unwrap();
+                if reply == 0x00 {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                }
+            });
+        }
+    });
+    (addr, requests)
+}
+
+fn upstream_settings(parent: SocketAddr) -> UpstreamProxySettings {
+    UpstreamProxySettings {
+        enabled: true,
+        address: parent.ip().to_string(),
+        port: parent.port(),
+        username: Some("gateway".to_string()),
+        password: Some("secret".to_string().into()),
+        destinations: vec!["*.corp.example".to_string()],
+        // Health is driven by hand in these tests
+        health_check_interval_secs: 0,
+        ..UpstreamProxySettings::default()
+    }
+}
+
+fn handler_context(
+    resolver: Arc<RecordingResolver>,
+    settings: &UpstreamProxySettings,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig {
+            connect_timeout_ms: 500,
+            ..PoolConfig::default()
+        })),
+        resolver,
+        upstream_proxy: UpstreamProxy::from_settings(settings).map(Arc::new),
+        ..common::handler_context(Arc::new(SessionManager::new()))
+    })
+}
+
+/// CONNECT to `host:port` through a fresh handler; returns the reply code and the stream.
+async fn connect(ctx: Arc<ClientHandlerContext>, host: &str, port: u16) -> (u8, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(5), client.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    (reply[1], client)
+}
+
+async fn assert_echoes(stream: &mut TcpStream) {
+    stream.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(2), stream.read_exact(&mut echo))
+        .await
+        .expect("echo in time")
+        .unwrap();
+    assert_eq!(&echo, b"ping");
+}
+
+async fn wait_for_sessions(session_manager: &SessionManager, count: usize) -> Vec<Session> {
+    for _ in 0..50 {
+        let sessions = session_manager.get_all_sessions().await;
+        if sessions.len() >= count {
+            return sessions;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    session_manager.get_all_sessions().await
+}
+
+#[tokio::test]
+async fn matching_destinations_are_chained_and_resolved_by_the_parent() {
+    let echo = spawn_echo().await;
+    let (parent, requests) = spawn_parent(0x00).await;
+    let resolver = Arc::new(RecordingResolver {
+        target: echo,
+        lookups: Mutex::new(Vec::new()),
+    });
+    let ctx = handler_context(resolver.clone(), &upstream_settings(parent));
+
+    let (reply, mut chained) = connect(ctx.clone(), "intranet.corp.example", 443).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    assert_echoes(&mut chained).await;
+    assert_eq!(
+        *requests.lock().unwrap(),
+        vec!["intranet.corp.example:443".to_string()]
+    );
+
+    let (reply, mut direct) = connect(ctx.clone(), "example.com", 80).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    assert_echoes(&mut direct).await;
+
+    // Only the direct destination was looked up locally
+    assert_eq!(
+        *resolver.lookups.lock().unwrap(),
+        vec!["example.com".to_string()]
+    );
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    let sessions = wait_for_sessions(&ctx.session_manager, 2).await;
+    let chained_flag = |dest: &str| {
+        sessions
+            .iter()
+            .find(|session| session.dest_ip.as_ref() == dest)
+            .map(|session| session.chained)
+    };
+    assert_eq!(chained_flag("intranet.corp.example"), Some(true));
+    assert_eq!(chained_flag("example.com"), Some(false));
+}
+
+#[tokio::test]
+async fn parent_failures_reply_host_unreachable() {
+    let echo = spawn_echo().await;
+    let resolver = Arc::new(RecordingResolver {
+        target: echo,
+        lookups: Mutex::new(Vec::new()),
+    });
+
+    // Parent refuses the CONNECT
+    let (parent, _) = spawn_parent(0x05).await;
+    let ctx = handler_context(resolver.clone(), &upstream_settings(parent));
+    let (reply, _) = connect(ctx, "intranet.corp.example", 443).await;
+    assert_eq!(reply, ReplyCode::HostUnreachable as u8);
+
+    // Parent rejects the credentials
+    let mut settings = upstream_settings(parent);
+    settings.password = Some("wrong".to_string().into());
+    let ctx = handler_context(resolver.clone(), &settings);
+    let (reply, _) = connect(ctx, "intranet.corp.example", 443).await;
+    assert_eq!(reply, ReplyCode::HostUnreachable as u8);
+
+    // Parent is down
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let down = closed.local_addr().unwrap();
+    drop(closed);
+    let ctx = handler_context(resolver.clone(), &upstream_settings(down));
+    let (reply, _) = connect(ctx.clone(), "intranet.corp.example", 443).await;
+    assert_eq!(reply, ReplyCode::HostUnreachable as u8);
+
+    // Only a failed session was recorded and nothing fell back to a local lookup
+    let sessions = ctx.session_manager.get_all_sessions().await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].status, SessionStatus::Failed);
+    assert!(resolver.lookups.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn unhealthy_parent_drains_its_sessions_after_the_grace_period() {
+    let echo = spawn_echo().await;
+    let (parent, _) = spawn_parent(0x00).await;
+    let resolver = Arc::new(RecordingResolver {
+        target: echo,
+        lookups: Mutex::new(Vec::new()),
+    });
+    let mut settings = upstream_settings(parent);
+    settings.on_unhealthy = "drain".to_string();
+    settings.drain_grace_secs = 1;
+    let ctx = handler_context(resolver, &settings);
+    let proxy = ctx.upstream_proxy.clone().expect("parent configured");
+
+    let (reply, mut chained) = connect(ctx.clone(), "intranet.corp.example", 443).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    let (reply, mut direct) = connect(ctx.clone(), "example.com", 80).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    wait_for_sessions(&ctx.session_manager, 2).await;
+
+    assert!(proxy.mark_unhealthy("stub parent marked down"));
+    let drained = proxy
+        .apply_unhealthy_policy(&ctx.session_manager, &ctx.connection_pool)
+        .await;
+    assert_eq!(drained, 1);
+
+    let status = proxy.status(&ctx.session_manager).await;
+    assert!(!status.healthy);
+    assert_eq!(status.on_unhealthy, "drain");
+    assert_eq!(status.active_sessions, 1);
+    assert_eq!(status.pending_drain, 1);
+
+    // Both keep relaying during the grace period
+    assert_echoes(&mut chained).await;
+    assert_echoes(&mut direct).await;
+
+    let mut buf = [0u8; 1];
+    let read = timeout(Duration::from_secs(3), chained.read(&mut buf))
+        .await
+        .expect("chained tunnel closed after the grace period");
+    assert!(matches!(read, Ok(0) | Err(_)));
+
+    let sessions = ctx.session_manager.get_all_sessions().await;
+    let drained = sessions
+        .iter()
+        .find(|session| session.chained)
+        .expect("chained session");
+    assert_eq!(drained.status, SessionStatus::Closed);
+    assert_eq!(
+        drained.close_reason.as_deref(),
+        Some(UPSTREAM_UNHEALTHY_CLOSE_REASON)
+    );
+    let direct_session = sessions
+        .iter()
+        .find(|session| !session.chained)
+        .expect("direct session");
+    assert_eq!(direct_session.status, SessionStatus::Active);
+    assert_echoes(&mut direct).await;
+    assert_eq!(ctx.session_manager.pending_drain_count(), 0);
+}
@@ -0,0 +1,149 @@
+//! Per-destination upstream socket options (`[[server.upstream_socket_options]]`)
+//!
+//! The upstream listener holds a TCP MD5 key for loopback, so the kernel drops every
+//! SYN from 127.0.0.1 that is not signed with it. Only tunnels whose destination
+//! matches an entry can get through.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use futures::future::BoxFuture;
+use rustsocks::config::{ServerConfig, UpstreamSocketOptionSettings};
+use rustsocks::protocol::{Address, ReplyCode};
+use rustsocks::server::socket_options::set_tcp_md5_key;
+use rustsocks::server::{
+    handle_client, ClientHandlerContext, ConnectionPool, DestinationResolver, PoolConfig,
+    UpstreamSocketOptions,
+};
+use rustsocks::session::SessionManager;
+use rustsocks::Result;
+use socket2::SockRef;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+
+const PEER_KEY: &str = "bgp-peer-secret";
+
+/// Resolves every name to the local upstream.
+struct FixedResolver {
+    target: SocketAddr,
+}
+
+impl DestinationResolver for FixedResolver {
+    fn resolve<'a>(
+        &'a self,
+        _address: &'a Address,
+        _port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(async move { Ok(vec![self.target]) })
+    }
+}
+
+/// Echo upstream that only accepts segments signed with `PEER_KEY`; `None` when the
+/// kernel was built without TCP MD5 support.
+async fn spawn_signed_upstream() -> Option<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind upstream");
+    let loopback: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    if let Err(e) = set_tcp_md5_key(&SockRef::from(&listener), loopback, PEER_KEY.as_bytes()) {
+        eprintln!("skipping: TCP_MD5SIG unavailable ({})", e);
+        return None;
+    }
+
+    let addr = listener.local_addr().expect("upstream addr");
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    Some(addr)
+}
+
+fn handler_context(upstream: SocketAddr, server: &ServerConfig) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        connection_pool: Arc::new(ConnectionPool::new(PoolConfig {
+            enabled: true,
+            connect_timeout_ms: 500,
+            ..PoolConfig::default()
+        })),
+        resolver: Arc::new(FixedResolver { target: upstream }),
+        upstream_socket_options: Arc::new(UpstreamSocketOptions::from(server)),
+        ..common::handler_context(Arc::new(SessionManager::new()))
+    })
+}
+
+/// CONNECT to `host:port` through a fresh handler; returns the reply code and the stream.
+async fn connect(ctx: Arc<ClientHandlerContext>, host: &str, port: u16) -> (u8, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind proxy");
+    let proxy_addr = listener.local_addr().expect("proxy addr");
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.expect("accept client");
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.expect("connect proxy");
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(5), client.read_exact(&mut reply))
+        .await
+        .expect("reply in time")
+        .unwrap();
+    (reply[1], client)
+}
+
+#[tokio::test]
+async fn md5_signed_tunnels_reach_the_peer_and_others_are_untouched() {
+    let Some(upstream) = spawn_signed_upstream().await else {
+        return;
+    };
+    let server = ServerConfig {
+        upstream_socket_options: vec![UpstreamSocketOptionSettings {
+            destinations: vec!["*.peers.example".to_string()],
+            ports: vec!["179".to_string()],
+            tcp_md5_password: Some(PEER_KEY.to_string().into()),
+            tcp_ao_password: None,
+            dscp: Some(48),
+            ip_tos: None,
+        }],
+        ..ServerConfig::default()
+    };
+    let ctx = handler_context(upstream, &server);
+
+    let (reply, mut signed) = connect(ctx.clone(), "edge1.peers.example", 179).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    signed.write_all(b"OPEN").await.unwrap();
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(2), signed.read_exact(&mut echo))
+        .await
+        .expect("echo in time")
+        .unwrap();
+    assert_eq!(&echo, b"OPEN");
+
+    // Same upstream, but the port or the name does not match: no key, so no handshake
+    let (reply, _) = connect(ctx.clone(), "edge1.peers.example", 22).await;
+    assert_eq!(reply, ReplyCode::HostUnreachable as u8);
+    let (reply, _) = connect(ctx.clone(), "edge1.other.example", 179).await;
+    assert_eq!(reply, ReplyCode::HostUnreachable as u8);
+
+    // The signed connection was opened outside the pool and never handed to it
+    drop(signed);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let stats = ctx.connection_pool.stats();
+    assert_eq!(stats.total_created, 0);
+    assert_eq!(stats.total_idle, 0);
+    assert_eq!(stats.connections_in_use, 0);
+}
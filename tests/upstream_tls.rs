@@ -0,0 +1,259 @@
+//! `[server.upstream_tls]`: plain CONNECTs to matching destinations are relayed over a
+//! TLS session the proxy opens, verified against the configured CA
+mod common;
+
+use futures::future::BoxFuture;
+use rcgen::{
+    generate_simple_self_signed, BasicConstraints, CertificateParams, DnType,
+    ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair, KeyUsagePurpose,
+};
+use rustsocks::config::{TlsSettings, UpstreamTlsSettings};
+use rustsocks::protocol::{Address, ReplyCode};
+use rustsocks::server::{
+    create_tls_acceptor, handle_client, ClientHandlerContext, DestinationResolver, UpstreamTls,
+};
+use rustsocks::session::{FailureCategory, SessionManager};
+use rustsocks::utils::error::Result;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+
+/// Every name is this host
+struct LoopbackResolver;
+
+impl DestinationResolver for LoopbackResolver {
+    fn resolve<'a>(
+        &'a self,
+        _address: &'a Address,
+        port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(async move { Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))]) })
+    }
+}
+
+/// A CA, a `localhost` server certificate it signed and a client certificate for the
+/// proxy, written as PEM files into `dir`; the upstream TLS settings that use them.
+fn issue_certificates(dir: &Path) -> (TlsSettings, UpstreamTlsSettings) {
+    let mut ca_params = CertificateParams::new(vec![]).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "Upstream Test CA");
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    let ca_key = KeyPair::generate().unwrap();
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+    let ca_issuer = Issuer::from_params(&ca_params, &ca_key);
+
+    let mut server_params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+    server_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    let server_key = KeyPair::generate().unwrap();
+    let server_cert = server_params.signed_by(&server_key, &ca_issuer).unwrap();
+
+    let mut client_params = CertificateParams::new(vec![]).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "rustsocks");
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_key = KeyPair::generate().unwrap();
+    let client_cert = client_params.signed_by(&client_key, &ca_issuer).unwrap();
+
+    let path = |name: &str, contents: String| {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        Some(path.to_string_lossy().into_owned())
+    };
+    let ca_path = path("ca.pem", ca_cert.pem());
+    let server = TlsSettings {
+        enabled: true,
+        certificate_path: path("server.crt", server_cert.pem()),
+        private_key_path: path("server.key", server_key.serialize_pem()),
+        // The destination only talks to holders of a client certificate from the CA
+        client_ca_path: ca_path.clone(),
+        require_client_auth: true,
+        ..Default::default()
+    };
+    let upstream = UpstreamTlsSettings {
+        enabled: true,
+        destinations: vec!["localhost".to_string()],
+        ca_path,
+        client_certificate_path: path("client.crt", client_cert.pem()),
+        client_key_path: path("client.key", client_key.serialize_pem()),
+        ..Default::default()
+    };
+    (server, upstream)
+}
+
+fn handler_context(
+    upstream_tls: &UpstreamTlsSettings,
+    session_manager: Arc<SessionManager>,
+) -> Arc<ClientHandlerContext> {
+    Arc::new(ClientHandlerContext {
+        resolver: Arc::new(LoopbackResolver),
+        upstream_tls: UpstreamTls::from_settings(upstream_tls)
+            .expect("load upstream TLS")
+            .map(Arc::new),
+        ..common::handler_context(session_manager)
+    })
+}
+
+async fn echo<S>(mut stream: S)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = [0u8; 64];
+    while let Ok(n) = stream.read(&mut buf).await {
+        if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Echo server behind TLS; a plain one without an acceptor.
+async fn echo_server(acceptor: Option<TlsAcceptor>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                match acceptor {
+                    Some(acceptor) => {
+                        if let Ok(stream) = acceptor.accept(stream).await {
+                            echo(stream).await;
+                        }
+                    }
+                    None => echo(stream).await,
+                }
+            });
+        }
+    });
+    port
+}
+
+/// CONNECT to `address:port` through a fresh handler; the client stream and reply code.
+async fn connect(ctx: Arc<ClientHandlerContext>, address: &Address, port: u16) -> (TcpStream, u8) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, client_addr) = listener.accept().await.unwrap();
+        let _ = handle_client(stream, ctx, client_addr).await;
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut choice = [0u8; 2];
+    client.read_exact(&mut choice).await.unwrap();
+    assert_eq!(choice, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    match address {
+        Address::IPv4(octets) => {
+            request.push(0x01);
+            request.extend_from_slice(octets);
+        }
+        Address::Domain(name) => {
+            request.extend_from_slice(&[0x03, name.len() as u8]);
+            request.extend_from_slice(name.as_bytes());
+        }
+        Address::IPv6(_) => unreachable!("not used here"),
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[0], 0x05);
+    (client, reply[1])
+}
+
+async fn assert_echoes(client: &mut TcpStream) {
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn matching_destination_is_relayed_over_tls() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let dir = tempfile::tempdir().unwrap();
+    let (server, upstream) = issue_certificates(dir.path());
+    let tls_port = echo_server(Some(create_tls_acceptor(&server).unwrap())).await;
+    let session_manager = Arc::new(SessionManager::new());
+
+    let ctx = handler_context(&upstream, session_manager.clone());
+    let (mut client, reply) = connect(ctx, &Address::Domain("localhost".into()), tls_port).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    // The destination only echoes what it decrypted, under a handshake that needed the
+    // proxy's client certificate and sent SNI "localhost"
+    assert_echoes(&mut client).await;
+
+    let sessions = session_manager.get_active_sessions().await;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].upstream_tls);
+}
+
+#[tokio::test]
+async fn untrusted_certificate_is_a_general_failure() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let dir = tempfile::tempdir().unwrap();
+    let (_, upstream) = issue_certificates(dir.path());
+
+    // Valid for localhost, but not issued by the configured CA
+    let self_signed = generate_simple_self_signed(["localhost".into()]).unwrap();
+    let untrusted = TlsSettings {
+        enabled: true,
+        certificate_path: Some(dir.path().join("untrusted.crt").to_string_lossy().into()),
+        private_key_path: Some(dir.path().join("untrusted.key").to_string_lossy().into()),
+        ..Default::default()
+    };
+    std::fs::write(dir.path().join("untrusted.crt"), self_signed.cert.pem()).unwrap();
+    std::fs::write(
+        dir.path().join("untrusted.key"),
+        self_signed.signing_key.serialize_pem(),
+    )
+    .unwrap();
+    let port = echo_server(Some(create_tls_acceptor(&untrusted).unwrap())).await;
+    let session_manager = Arc::new(SessionManager::new());
+
+    let ctx = handler_context(&upstream, session_manager.clone());
+    let (_client, reply) = connect(ctx, &Address::Domain("localhost".into()), port).await;
+    assert_eq!(reply, ReplyCode::GeneralFailure as u8);
+
+    assert!(session_manager.get_active_sessions().await.is_empty());
+    let closed = session_manager.get_closed_sessions().await;
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].failure, Some(FailureCategory::UpstreamTls));
+    assert_eq!(closed[0].reply_code, Some(ReplyCode::GeneralFailure as u8));
+    assert!(closed[0]
+        .close_reason
+        .as_deref()
+        .is_some_and(|reason| reason.contains("certificate")));
+    assert_eq!(
+        session_manager.access_log().stats().written,
+        1,
+        "the failed handshake is audited"
+    );
+}
+
+#[tokio::test]
+async fn other_destinations_keep_plain_tcp() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let dir = tempfile::tempdir().unwrap();
+    let (_, upstream) = issue_certificates(dir.path());
+    let plain_port = echo_server(None).await;
+    let session_manager = Arc::new(SessionManager::new());
+
+    // 127.0.0.1 is not the "localhost" pattern
+    let ctx = handler_context(&upstream, session_manager.clone());
+    let (mut client, reply) = connect(ctx, &Address::IPv4([127, 0, 0, 1]), plain_port).await;
+    assert_eq!(reply, ReplyCode::Succeeded as u8);
+    assert_echoes(&mut client).await;
+
+    let sessions = session_manager.get_active_sessions().await;
+    assert_eq!(sessions.len(), 1);
+    assert!(!sessions[0].upstream_tls);
+}
@@ -0,0 +1,138 @@
+//! Malformed RFC 1929 username/password sub-negotiations
+//!
+//! Whatever the client sends after the server picked userpass, it gets the failure
+//! status `[0x01, 0x01]` and the connection is closed: empty fields, wrong versions,
+//! declared lengths beyond what arrives, and clients that stall mid-request.
+
+mod common;
+
+use rustsocks::auth::AuthManager;
+use rustsocks::config::{AuthConfig, PasswordHashSettings, User};
+use rustsocks::server::{handle_client, ClientHandlerContext, HandshakeLimits};
+use rustsocks::session::SessionManager;
+use std::sync::Arc;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::time::{timeout, Duration};
+
+const FAILURE: [u8; 2] = [0x01, 0x01];
+
+fn handler_context(handshake_timeout: Duration) -> Arc<ClientHandlerContext> {
+    let auth = AuthConfig {
+        socks_method: "userpass".to_string(),
+        users: vec![User {
+            username: "alice".to_string(),
+            password: "secret123".to_string().into(),
+            password_hash: None,
+        }],
+        password_hashing: PasswordHashSettings {
+            memory_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        },
+        ..AuthConfig::default()
+    };
+    Arc::new(ClientHandlerContext {
+        auth_manager: Arc::new(AuthManager::new(&auth).expect("auth manager")),
+        handshake: HandshakeLimits {
+            timeout: handshake_timeout,
+            slots: None,
+        },
+        ..common::handler_context(Arc::new(SessionManager::new()))
+    })
+}
+
+/// Client whose greeting selected userpass, with `request` sent as the sub-negotiation
+async fn send_subnegotiation(ctx: Arc<ClientHandlerContext>, request: &[u8]) -> DuplexStream {
+    let (mut client, server) = duplex(1024);
+    tokio::spawn(async move {
+        let _ = handle_client(server, ctx, "127.0.0.1:40000".parse().unwrap()).await;
+    });
+
+    client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+    let mut choice = [0u8; 2];
+    client.read_exact(&mut choice).await.unwrap();
+    assert_eq!(choice, [0x05, 0x02]);
+
+    client.write_all(request).await.unwrap();
+    client
+}
+
+/// Everything the server sends until it closes the connection
+async fn reply_until_close(client: &mut DuplexStream) -> Vec<u8> {
+    let mut reply = Vec::new();
+    timeout(Duration::from_secs(5), client.read_to_end(&mut reply))
+        .await
+        .expect("server closes the connection")
+        .unwrap();
+    reply
+}
+
+/// Request, then end of input
+async fn reply_to(ctx: &Arc<ClientHandlerContext>, request: &[u8]) -> Vec<u8> {
+    let mut client = send_subnegotiation(ctx.clone(), request).await;
+    client.shutdown().await.unwrap();
+    reply_until_close(&mut client).await
+}
+
+#[tokio::test]
+async fn malformed_requests_get_a_failure_status() {
+    let ctx = handler_context(Duration::from_secs(10));
+    let cases: &[&[u8]] = &[
+        // Empty username, empty password
+        b"\x01\x00",
+        b"\x01\x00\x09secret123",
+        b"\x01\x05alice\x00",
+        // Wrong sub-negotiation version
+        b"\x05\x05alice\x09secret123",
+        // Declared lengths beyond what was sent
+        b"",
+        b"\x01",
+        b"\x01\xffab",
+        b"\x01\x05alice",
+        b"\x01\x05alice\x09sec",
+        b"\x01\x05alice\xff",
+        // Not UTF-8
+        b"\x01\x02\xff\xfe\x01x",
+        // Well-formed, wrong password
+        b"\x01\x05alice\x05wrong",
+    ];
+    for request in cases {
+        assert_eq!(reply_to(&ctx, request).await, FAILURE, "{request:?}");
+    }
+}
+
+#[tokio::test]
+async fn random_requests_always_get_an_answer() {
+    let ctx = handler_context(Duration::from_secs(10));
+    // Fixed-seed xorshift, so a failure reproduces
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    for _ in 0..200 {
+        let len = (next() % 24) as usize;
+        let mut request: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+        // Mostly the right version, with small lengths so fields are often complete
+        if let Some(version) = request.first_mut() {
+            if next() % 4 != 0 {
+                *version = 0x01;
+            }
+        }
+        if request.len() > 1 && next() % 2 == 0 {
+            request[1] %= 8;
+        }
+        assert_eq!(reply_to(&ctx, &request).await, FAILURE, "{request:?}");
+    }
+}
+
+#[tokio::test]
+async fn stalled_request_is_answered_at_the_handshake_deadline() {
+    let ctx = handler_context(Duration::from_millis(300));
+    // Promises 255 bytes of username and stops after two, without closing
+    let mut client = send_subnegotiation(ctx, b"\x01\xffab").await;
+    assert_eq!(reply_until_close(&mut client).await, FAILURE);
+}